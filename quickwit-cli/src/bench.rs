@@ -0,0 +1,557 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Reproducible indexing and search benchmarks.
+//!
+//! Documents are generated from the schema of the index with a seeded random generator,
+//! so that two runs with the same seed index exactly the same documents.
+
+use std::fs::File;
+use std::io::{stdout, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use byte_unit::Byte;
+use chrono::{TimeZone, Utc};
+use futures::stream::{self, StreamExt};
+use quickwit_actors::{ActorExitStatus, Universe};
+use quickwit_index_config::{IndexConfig, SOURCE_FIELD_NAME, TAGS_FIELD_NAME};
+use quickwit_indexing::actors::{
+    IndexerParams, IndexingPipelineParams, IndexingPipelineSupervisor,
+};
+use quickwit_indexing::models::{
+    CommitPolicy, DiskGuardrails, DocSizeLimit, IndexingStatistics, ScratchDirectory,
+};
+use quickwit_indexing::source::{FileSourceParams, SourceConfig};
+use quickwit_metastore::MetastoreUriResolver;
+use quickwit_storage::quickwit_storage_uri_resolver;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use tantivy::schema::FieldType;
+use tracing::debug;
+
+use crate::OutputFormat;
+
+/// Words the text fields of the generated documents are made of.
+const WORDS: [&str; 16] = [
+    "quickwit",
+    "search",
+    "engine",
+    "object",
+    "storage",
+    "split",
+    "index",
+    "query",
+    "log",
+    "trace",
+    "cloud",
+    "native",
+    "cost",
+    "efficient",
+    "rust",
+    "tantivy",
+];
+
+/// Timestamp of the first generated document: 2021-01-01T00:00:00Z.
+const FIRST_TIMESTAMP: i64 = 1_609_459_200;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct GenerateDocsArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    pub num_docs: usize,
+    pub seed: u64,
+    pub output_path: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct BenchIndexArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    pub num_docs: usize,
+    pub seed: u64,
+    pub parallelism: usize,
+    pub heap_size: Byte,
+    pub report_path: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct BenchSearchArgs {
+    pub endpoint: String,
+    pub index_id: String,
+    pub query_file_path: PathBuf,
+    pub concurrency: usize,
+    pub num_iterations: usize,
+    pub max_hits: usize,
+    pub cache_policy: String,
+    pub report_path: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BenchArgs {
+    Generate(GenerateDocsArgs),
+    Index(BenchIndexArgs),
+    Search(BenchSearchArgs),
+}
+
+/// Report of an indexing benchmark.
+#[derive(Debug, Serialize)]
+pub struct IndexingBenchReport {
+    pub num_docs: u64,
+    pub num_invalid_docs: u64,
+    pub num_bytes: u64,
+    pub num_published_splits: u64,
+    pub parallelism: usize,
+    pub elapsed_secs: f64,
+    pub docs_per_sec: f64,
+    pub megabytes_per_sec: f64,
+}
+
+/// Latency percentiles of a search benchmark, in milliseconds.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct LatencyReport {
+    pub min: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+/// Report of a search benchmark.
+#[derive(Debug, Serialize)]
+pub struct SearchBenchReport {
+    pub num_queries: usize,
+    pub num_errors: usize,
+    pub concurrency: usize,
+    pub elapsed_secs: f64,
+    pub queries_per_sec: f64,
+    /// Latencies of the successful queries.
+    pub latency_ms: Option<LatencyReport>,
+}
+
+/// Generates documents matching the schema of an index.
+pub struct DocGenerator {
+    fields: Vec<(Vec<String>, FieldType)>,
+    timestamp_field_name_opt: Option<String>,
+    num_generated_docs: i64,
+    rng: StdRng,
+}
+
+impl DocGenerator {
+    /// Creates a generator whose output only depends on the index config and the seed.
+    pub fn new(index_config: &dyn IndexConfig, seed: u64) -> Self {
+        let schema = index_config.schema();
+        let fields = schema
+            .fields()
+            .map(|(_, field_entry)| field_entry)
+            .filter(|field_entry| {
+                field_entry.name() != SOURCE_FIELD_NAME && field_entry.name() != TAGS_FIELD_NAME
+            })
+            .map(|field_entry| {
+                let field_path: Vec<String> =
+                    field_entry.name().split('.').map(str::to_string).collect();
+                (field_path, field_entry.field_type().clone())
+            })
+            .collect();
+        DocGenerator {
+            fields,
+            timestamp_field_name_opt: index_config.timestamp_field_name(),
+            num_generated_docs: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Generates the next document.
+    ///
+    /// The timestamp field, if any, increases by one second with every document.
+    pub fn next_doc(&mut self) -> JsonValue {
+        let timestamp = FIRST_TIMESTAMP + self.num_generated_docs;
+        self.num_generated_docs += 1;
+        let mut doc = JsonMap::new();
+        for (field_path, field_type) in &self.fields {
+            let field_name = field_path.join(".");
+            let value = if self.timestamp_field_name_opt.as_ref() == Some(&field_name) {
+                JsonValue::from(timestamp)
+            } else if let Some(value) = generate_value(&mut self.rng, field_type, timestamp) {
+                value
+            } else {
+                continue;
+            };
+            insert_at_path(&mut doc, field_path, value);
+        }
+        JsonValue::Object(doc)
+    }
+}
+
+fn generate_value(rng: &mut StdRng, field_type: &FieldType, timestamp: i64) -> Option<JsonValue> {
+    let value = match field_type {
+        FieldType::Str(_) => {
+            let num_words = rng.gen_range(1..=8);
+            let text: Vec<&str> = (0..num_words)
+                .map(|_| WORDS[rng.gen_range(0..WORDS.len())])
+                .collect();
+            JsonValue::from(text.join(" "))
+        }
+        FieldType::U64(_) => JsonValue::from(rng.gen_range(0..1_000_000u64)),
+        FieldType::I64(_) => JsonValue::from(rng.gen_range(-1_000_000..1_000_000i64)),
+        FieldType::F64(_) => JsonValue::from(rng.gen_range(0.0..1_000.0f64)),
+        FieldType::Date(_) => JsonValue::from(Utc.timestamp(timestamp, 0).to_rfc3339()),
+        FieldType::HierarchicalFacet(_) | FieldType::Bytes(_) => return None,
+    };
+    Some(value)
+}
+
+fn insert_at_path(doc: &mut JsonMap<String, JsonValue>, field_path: &[String], value: JsonValue) {
+    if let [field_name] = field_path {
+        doc.insert(field_name.clone(), value);
+        return;
+    }
+    let child = doc
+        .entry(field_path[0].clone())
+        .or_insert_with(|| JsonValue::Object(JsonMap::new()));
+    if let JsonValue::Object(child_doc) = child {
+        insert_at_path(child_doc, &field_path[1..], value);
+    }
+}
+
+/// Computes the latency percentiles of a non-empty list of latencies.
+pub fn latency_report(latencies: &mut [Duration]) -> Option<LatencyReport> {
+    if latencies.is_empty() {
+        return None;
+    }
+    latencies.sort_unstable();
+    let to_millis = |latency: Duration| latency.as_micros() as f64 / 1_000.0;
+    let percentile = |percentile: f64| {
+        let rank = ((latencies.len() - 1) as f64 * percentile).round() as usize;
+        to_millis(latencies[rank])
+    };
+    let total_millis: f64 = latencies.iter().cloned().map(to_millis).sum();
+    Some(LatencyReport {
+        min: to_millis(latencies[0]),
+        p50: percentile(0.5),
+        p90: percentile(0.9),
+        p99: percentile(0.99),
+        max: to_millis(latencies[latencies.len() - 1]),
+        mean: total_millis / latencies.len() as f64,
+    })
+}
+
+/// Writes the report to `report_path_opt`, or prints it on stdout: pretty-printed in text mode,
+/// on a single line in JSON mode.
+fn write_report<T: Serialize>(
+    report: &T,
+    report_path_opt: Option<&PathBuf>,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    if let Some(report_path) = report_path_opt {
+        let report_json = serde_json::to_string_pretty(report)?;
+        std::fs::write(report_path, report_json)
+            .with_context(|| format!("Failed to write report to `{}`.", report_path.display()))?;
+        output_format.print_message(&format!("Wrote the report to `{}`.", report_path.display()));
+        return Ok(());
+    }
+    match output_format {
+        OutputFormat::Text => println!("{}", serde_json::to_string_pretty(report)?),
+        OutputFormat::Json => output_format.print_json_result(report)?,
+    }
+    Ok(())
+}
+
+async fn load_index_config(
+    metastore_uri: &str,
+    index_id: &str,
+) -> anyhow::Result<Arc<dyn IndexConfig>> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    let index_metadata = metastore.index_metadata(index_id).await?;
+    Ok(index_metadata.index_config)
+}
+
+fn write_docs(
+    doc_generator: &mut DocGenerator,
+    num_docs: usize,
+    writer: &mut dyn Write,
+) -> anyhow::Result<()> {
+    for _ in 0..num_docs {
+        serde_json::to_writer(&mut *writer, &doc_generator.next_doc())?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub async fn bench_cli(args: BenchArgs, output_format: OutputFormat) -> anyhow::Result<()> {
+    match args {
+        BenchArgs::Generate(args) => generate_docs_cli(args).await,
+        BenchArgs::Index(args) => bench_index_cli(args, output_format).await,
+        BenchArgs::Search(args) => bench_search_cli(args, output_format).await,
+    }
+}
+
+pub async fn generate_docs_cli(args: GenerateDocsArgs) -> anyhow::Result<()> {
+    debug!(args = ?args, "bench-generate");
+    let index_config = load_index_config(&args.metastore_uri, &args.index_id).await?;
+    let mut doc_generator = DocGenerator::new(index_config.as_ref(), args.seed);
+    if let Some(output_path) = args.output_path.as_ref() {
+        let mut writer = BufWriter::new(File::create(output_path).with_context(|| {
+            format!("Failed to create output file `{}`.", output_path.display())
+        })?);
+        write_docs(&mut doc_generator, args.num_docs, &mut writer)
+    } else {
+        let stdout = stdout();
+        let mut writer = BufWriter::new(stdout.lock());
+        write_docs(&mut doc_generator, args.num_docs, &mut writer)
+    }
+}
+
+/// Generates the documents and indexes them with `parallelism` concurrent indexing pipelines.
+///
+/// The documents are generated before the clock starts, so that only indexing is measured.
+pub async fn bench_index_cli(
+    args: BenchIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "bench-index");
+    if args.parallelism == 0 {
+        bail!("'parallelism' should be strictly positive.");
+    }
+    let index_config = load_index_config(&args.metastore_uri, &args.index_id).await?;
+    let docs_dir = tempfile::tempdir()?;
+    let mut doc_generator = DocGenerator::new(index_config.as_ref(), args.seed);
+    let mut docs_file_paths = Vec::with_capacity(args.parallelism);
+    for pipeline_ord in 0..args.parallelism {
+        let num_docs = args.num_docs / args.parallelism
+            + if pipeline_ord < args.num_docs % args.parallelism {
+                1
+            } else {
+                0
+            };
+        let docs_file_path = docs_dir.path().join(format!("docs-{}.json", pipeline_ord));
+        let mut writer = BufWriter::new(File::create(&docs_file_path)?);
+        write_docs(&mut doc_generator, num_docs, &mut writer)?;
+        docs_file_paths.push(docs_file_path);
+    }
+
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let metastore = MetastoreUriResolver::default()
+        .resolve(&args.metastore_uri)
+        .await?;
+    let universe = Universe::new();
+    let start_time = Instant::now();
+    let mut supervisor_handlers = Vec::with_capacity(args.parallelism);
+    for (pipeline_ord, docs_file_path) in docs_file_paths.iter().enumerate() {
+        let source_config = SourceConfig {
+            source_id: format!("bench-source-{}", pipeline_ord),
+            source_type: "file".to_string(),
+            params: serde_json::to_value(FileSourceParams {
+                filepath: Some(docs_file_path.clone()),
+                max_line_num_bytes: None,
+            })?,
+        };
+        let indexer_params = IndexerParams {
+            scratch_directory: ScratchDirectory::try_new_temp()
+                .with_context(|| "Failed to create a tempdir for the indexer")?,
+            heap_size: args.heap_size,
+            commit_policy: CommitPolicy::default(),
+            disk_guardrails: DiskGuardrails::default(),
+            doc_size_limit: DocSizeLimit::default(),
+            realtime_opt: None,
+        };
+        let indexing_pipeline_params = IndexingPipelineParams {
+            index_id: args.index_id.clone(),
+            source_config,
+            indexer_params,
+            metastore: metastore.clone(),
+            storage_uri_resolver: storage_uri_resolver.clone(),
+            routing_rules: Vec::new(),
+            source_sharding_opt: None,
+        };
+        let indexing_supervisor = IndexingPipelineSupervisor::new(indexing_pipeline_params);
+        let (_supervisor_mailbox, supervisor_handler) =
+            universe.spawn_actor(indexing_supervisor).spawn_async();
+        supervisor_handlers.push(supervisor_handler);
+    }
+    let mut statistics = IndexingStatistics::default();
+    for supervisor_handler in supervisor_handlers {
+        let (supervisor_exit_status, pipeline_statistics) = supervisor_handler.join().await;
+        match supervisor_exit_status {
+            ActorExitStatus::Success => {}
+            ActorExitStatus::Failure(err) => bail!(err),
+            _ => bail!(supervisor_exit_status),
+        }
+        statistics.num_docs += pipeline_statistics.num_docs;
+        statistics.num_invalid_docs += pipeline_statistics.num_invalid_docs;
+        statistics.total_bytes_processed += pipeline_statistics.total_bytes_processed;
+        statistics.num_published_splits += pipeline_statistics.num_published_splits;
+    }
+    let elapsed_secs = start_time.elapsed().as_secs_f64();
+    let report = IndexingBenchReport {
+        num_docs: statistics.num_docs,
+        num_invalid_docs: statistics.num_invalid_docs,
+        num_bytes: statistics.total_bytes_processed,
+        num_published_splits: statistics.num_published_splits,
+        parallelism: args.parallelism,
+        elapsed_secs,
+        docs_per_sec: statistics.num_docs as f64 / elapsed_secs,
+        megabytes_per_sec: statistics.total_bytes_processed as f64 / 1_000_000.0 / elapsed_secs,
+    };
+    write_report(&report, args.report_path.as_ref(), output_format)
+}
+
+fn read_queries(query_file_path: &Path) -> anyhow::Result<Vec<String>> {
+    let query_file = File::open(query_file_path)
+        .with_context(|| format!("Failed to open query file `{}`.", query_file_path.display()))?;
+    let mut queries = Vec::new();
+    for line in BufReader::new(query_file).lines() {
+        let query = line?;
+        if !query.trim().is_empty() {
+            queries.push(query);
+        }
+    }
+    if queries.is_empty() {
+        bail!(
+            "Query file `{}` does not contain any query.",
+            query_file_path.display()
+        );
+    }
+    Ok(queries)
+}
+
+/// Replays the queries of the query file, one per line, against the REST API of a
+/// serving cluster.
+pub async fn bench_search_cli(
+    args: BenchSearchArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "bench-search");
+    if args.concurrency == 0 {
+        bail!("'concurrency' should be strictly positive.");
+    }
+    let queries = read_queries(&args.query_file_path)?;
+    let search_url = format!(
+        "{}/api/v1/{}/search",
+        args.endpoint.trim_end_matches('/'),
+        args.index_id
+    );
+    let client = reqwest::Client::new();
+    let max_hits = args.max_hits.to_string();
+    let query_futures = queries
+        .iter()
+        .cycle()
+        .take(queries.len() * args.num_iterations)
+        .map(|query| {
+            let request = client.get(&search_url).query(&[
+                ("query", query.as_str()),
+                ("maxHits", max_hits.as_str()),
+                ("cache", args.cache_policy.as_str()),
+            ]);
+            async move {
+                let query_start_time = Instant::now();
+                let response_result = request
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                (response_result.is_ok(), query_start_time.elapsed())
+            }
+        });
+    let start_time = Instant::now();
+    let query_results: Vec<(bool, Duration)> = stream::iter(query_futures)
+        .buffer_unordered(args.concurrency)
+        .collect()
+        .await;
+    let elapsed_secs = start_time.elapsed().as_secs_f64();
+
+    let num_errors = query_results.iter().filter(|(is_ok, _)| !is_ok).count();
+    let mut latencies: Vec<Duration> = query_results
+        .iter()
+        .filter(|(is_ok, _)| *is_ok)
+        .map(|(_, latency)| *latency)
+        .collect();
+    let report = SearchBenchReport {
+        num_queries: query_results.len(),
+        num_errors,
+        concurrency: args.concurrency,
+        elapsed_secs,
+        queries_per_sec: query_results.len() as f64 / elapsed_secs,
+        latency_ms: latency_report(&mut latencies),
+    };
+    write_report(&report, args.report_path.as_ref(), output_format)
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_index_config::DefaultIndexConfigBuilder;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_doc_generator_is_reproducible() -> anyhow::Result<()> {
+        let index_config_json = r#"{
+            "default_search_fields": ["body"],
+            "timestamp_field": "ts",
+            "field_mappings": [
+                {"name": "body", "type": "text"},
+                {"name": "ts", "type": "i64", "fast": true},
+                {"name": "severity", "type": "u64"},
+                {
+                    "name": "attributes",
+                    "type": "object",
+                    "field_mappings": [{"name": "duration", "type": "f64"}]
+                }
+            ]
+        }"#;
+        let index_config =
+            serde_json::from_str::<DefaultIndexConfigBuilder>(index_config_json)?.build()?;
+        let mut doc_generator = DocGenerator::new(&index_config, 42);
+        let docs: Vec<JsonValue> = (0..10).map(|_| doc_generator.next_doc()).collect();
+        assert_eq!(docs[0]["ts"], json!(FIRST_TIMESTAMP));
+        assert_eq!(docs[9]["ts"], json!(FIRST_TIMESTAMP + 9));
+        assert!(docs[0]["body"].is_string());
+        assert!(docs[0]["severity"].is_u64());
+        assert!(docs[0]["attributes"]["duration"].is_f64());
+        for doc in &docs {
+            index_config.doc_from_json(&doc.to_string())?;
+        }
+
+        let mut other_doc_generator = DocGenerator::new(&index_config, 42);
+        let other_docs: Vec<JsonValue> = (0..10).map(|_| other_doc_generator.next_doc()).collect();
+        assert_eq!(docs, other_docs);
+        Ok(())
+    }
+
+    #[test]
+    fn test_latency_report() {
+        assert_eq!(latency_report(&mut []), None);
+        let mut latencies: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+        let report = latency_report(&mut latencies).unwrap();
+        assert_eq!(report.min, 1.0);
+        assert_eq!(report.p50, 51.0);
+        assert_eq!(report.p90, 90.0);
+        assert_eq!(report.p99, 99.0);
+        assert_eq!(report.max, 100.0);
+        assert_eq!(report.mean, 50.5);
+    }
+}
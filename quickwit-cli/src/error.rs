@@ -0,0 +1,242 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::io;
+
+use quickwit_actors::ActorExitStatus;
+use quickwit_metastore::{
+    CompanionFileError, MetastoreError, MetastoreErrorKind, MetastoreResolverError,
+};
+use quickwit_search::SearchError;
+use quickwit_storage::{StorageError, StorageErrorKind, StorageResolverError};
+
+/// The classes of errors a command can fail with.
+///
+/// Each class exits with its own code, so that scripts can tell a command that should be fixed
+/// from a command that may succeed if retried later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The command itself is wrong, e.g. invalid arguments or a missing index.
+    User,
+    /// A resource the command relies on, e.g. the storage or the metastore, is temporarily
+    /// unavailable.
+    Transient,
+    /// The command failed unexpectedly, e.g. it panicked.
+    Internal,
+}
+
+impl ErrorClass {
+    /// Returns the exit code of the commands failing with an error of this class.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorClass::User => 2,
+            ErrorClass::Transient => 3,
+            ErrorClass::Internal => 4,
+        }
+    }
+
+    /// Classifies `error` after the first error of its chain with a known class. Errors
+    /// without a known class are internal.
+    pub fn of_error(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(error_class)
+            .unwrap_or(ErrorClass::Internal)
+    }
+}
+
+fn error_class(error: &(dyn std::error::Error + 'static)) -> Option<ErrorClass> {
+    if let Some(metastore_error) = error.downcast_ref::<MetastoreError>() {
+        return Some(metastore_error_class(metastore_error));
+    }
+    if let Some(metastore_resolver_error) = error.downcast_ref::<MetastoreResolverError>() {
+        return Some(match metastore_resolver_error {
+            MetastoreResolverError::InvalidUri(_)
+            | MetastoreResolverError::ProtocolUnsupported(_) => ErrorClass::User,
+            MetastoreResolverError::FailedToOpenMetastore(metastore_error) => {
+                metastore_error_class(metastore_error)
+            }
+        });
+    }
+    if let Some(storage_error) = error.downcast_ref::<StorageError>() {
+        return Some(storage_error_kind_class(storage_error.kind()));
+    }
+    if let Some(storage_resolver_error) = error.downcast_ref::<StorageResolverError>() {
+        return Some(storage_resolver_error_class(storage_resolver_error));
+    }
+    if let Some(companion_file_error) = error.downcast_ref::<CompanionFileError>() {
+        return Some(match companion_file_error {
+            CompanionFileError::InvalidName(_)
+            | CompanionFileError::FileDoesNotExist { .. }
+            | CompanionFileError::VersionDoesNotExist { .. } => ErrorClass::User,
+            CompanionFileError::InvalidManifest(_) => ErrorClass::Internal,
+            CompanionFileError::StorageError(storage_error) => {
+                storage_error_kind_class(storage_error.kind())
+            }
+        });
+    }
+    if let Some(search_error) = error.downcast_ref::<SearchError>() {
+        return Some(match search_error {
+            SearchError::IndexDoesNotExist { .. }
+            | SearchError::InvalidQuery(_)
+            | SearchError::DocDoesNotExist { .. } => ErrorClass::User,
+            SearchError::StorageResolverError(storage_resolver_error) => {
+                storage_resolver_error_class(storage_resolver_error)
+            }
+            SearchError::Unavailable(_) => ErrorClass::Transient,
+            SearchError::InternalError(_) => ErrorClass::Internal,
+        });
+    }
+    if let Some(actor_exit_status) = error.downcast_ref::<ActorExitStatus>() {
+        return Some(match actor_exit_status {
+            ActorExitStatus::Failure(cause) => ErrorClass::of_error(cause),
+            _ => ErrorClass::Internal,
+        });
+    }
+    if let Some(io_error) = error.downcast_ref::<io::Error>() {
+        return Some(io_error_class(io_error));
+    }
+    if error.is::<serde_json::Error>() || error.is::<clap::Error>() {
+        return Some(ErrorClass::User);
+    }
+    None
+}
+
+fn metastore_error_class(metastore_error: &MetastoreError) -> ErrorClass {
+    match metastore_error.kind() {
+        MetastoreErrorKind::NotFound
+        | MetastoreErrorKind::Conflict
+        | MetastoreErrorKind::Forbidden => ErrorClass::User,
+        MetastoreErrorKind::Unavailable => ErrorClass::Transient,
+        MetastoreErrorKind::Corrupted | MetastoreErrorKind::Internal => ErrorClass::Internal,
+    }
+}
+
+fn storage_error_kind_class(storage_error_kind: StorageErrorKind) -> ErrorClass {
+    match storage_error_kind {
+        StorageErrorKind::DoesNotExist | StorageErrorKind::Unauthorized => ErrorClass::User,
+        StorageErrorKind::Service | StorageErrorKind::Io | StorageErrorKind::Timeout => {
+            ErrorClass::Transient
+        }
+        StorageErrorKind::InternalError => ErrorClass::Internal,
+    }
+}
+
+fn storage_resolver_error_class(storage_resolver_error: &StorageResolverError) -> ErrorClass {
+    match storage_resolver_error {
+        StorageResolverError::InvalidUri { .. }
+        | StorageResolverError::ProtocolUnsupported { .. } => ErrorClass::User,
+        StorageResolverError::FailedToOpenStorage { kind, .. } => storage_error_kind_class(*kind),
+    }
+}
+
+fn io_error_class(io_error: &io::Error) -> ErrorClass {
+    match io_error.kind() {
+        io::ErrorKind::NotFound
+        | io::ErrorKind::PermissionDenied
+        | io::ErrorKind::AlreadyExists
+        | io::ErrorKind::InvalidInput
+        | io::ErrorKind::InvalidData => ErrorClass::User,
+        io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::NotConnected
+        | io::ErrorKind::AddrInUse
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::TimedOut
+        | io::ErrorKind::Interrupted
+        | io::ErrorKind::WouldBlock => ErrorClass::Transient,
+        _ => ErrorClass::Internal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn test_error_class_of_error() {
+        let index_does_not_exist = anyhow::Error::from(MetastoreError::IndexDoesNotExist {
+            index_id: "my-index".to_string(),
+        });
+        assert_eq!(
+            ErrorClass::of_error(&index_does_not_exist),
+            ErrorClass::User
+        );
+        let storage_unavailable: anyhow::Result<()> =
+            Err(StorageErrorKind::Service.with_error(anyhow::anyhow!("Slow down.")));
+        let storage_unavailable = storage_unavailable
+            .context("Failed to upload split.")
+            .unwrap_err();
+        assert_eq!(
+            ErrorClass::of_error(&storage_unavailable),
+            ErrorClass::Transient
+        );
+        let connection_refused =
+            anyhow::Error::from(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert_eq!(
+            ErrorClass::of_error(&connection_refused),
+            ErrorClass::Transient
+        );
+        let failure = anyhow::Error::from(ActorExitStatus::from(anyhow::Error::from(
+            SearchError::InvalidQuery("foo:".to_string()),
+        )));
+        assert_eq!(ErrorClass::of_error(&failure), ErrorClass::User);
+        let metastore_unavailable = anyhow::Error::from(MetastoreError::ConnectionError {
+            message: "Connection refused.".to_string(),
+        });
+        assert_eq!(
+            ErrorClass::of_error(&metastore_unavailable),
+            ErrorClass::Transient
+        );
+        let search_unavailable =
+            anyhow::Error::from(SearchError::Unavailable("Connection refused.".to_string()));
+        assert_eq!(
+            ErrorClass::of_error(&search_unavailable),
+            ErrorClass::Transient
+        );
+        let invalid_uri = anyhow::Error::from(MetastoreResolverError::InvalidUri(
+            "Invalid URI `s3:bucket`.".to_string(),
+        ));
+        assert_eq!(ErrorClass::of_error(&invalid_uri), ErrorClass::User);
+        let companion_file_does_not_exist =
+            anyhow::Error::from(CompanionFileError::FileDoesNotExist {
+                name: "synonyms.txt".to_string(),
+            });
+        assert_eq!(
+            ErrorClass::of_error(&companion_file_does_not_exist),
+            ErrorClass::User
+        );
+        let panicked = anyhow::Error::from(ActorExitStatus::Panicked);
+        assert_eq!(ErrorClass::of_error(&panicked), ErrorClass::Internal);
+        assert_eq!(
+            ErrorClass::of_error(&anyhow::anyhow!("Unexpected.")),
+            ErrorClass::Internal
+        );
+    }
+
+    #[test]
+    fn test_error_class_exit_code() {
+        assert_eq!(ErrorClass::User.exit_code(), 2);
+        assert_eq!(ErrorClass::Transient.exit_code(), 3);
+        assert_eq!(ErrorClass::Internal.exit_code(), 4);
+    }
+}
@@ -17,44 +17,230 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use std::collections::VecDeque;
+pub mod bench;
+mod error;
+mod progress;
+pub mod tail;
+
+use std::collections::{BTreeMap, VecDeque};
 use std::convert::TryFrom;
 use std::env;
 use std::fs::File;
-use std::io::{stdout, Stdout, Write};
-use std::path::PathBuf;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use anyhow::{bail, Context};
 use byte_unit::Byte;
-use crossterm::style::{Print, PrintStyledContent, Stylize};
-use crossterm::QueueableCommand;
+use chrono::Utc;
+use crossterm::style::Stylize;
 use humansize::{file_size_opts, FileSize};
 use json_comments::StripComments;
 use quickwit_actors::{ActorExitStatus, ActorHandle, ObservationType, Universe};
-use quickwit_common::extract_index_id_from_index_uri;
-use quickwit_core::{create_index, delete_index, garbage_collect_index, reset_index};
+use quickwit_common::{extract_index_id_from_index_uri, QuickwitUri, RealtimeSplits};
+use quickwit_core::{
+    attach_index, clone_index, create_index, delete_companion_file, delete_index,
+    delete_quarantined_splits, delete_splits_in_time_range, describe_index, garbage_collect_index,
+    get_companion_file, index_checkpoint, list_companion_files, mirror_index, put_companion_file,
+    reset_index, set_checkpoint_positions, update_index_config, update_index_labels,
+    verify_index_splits, IndexDescription,
+};
 use quickwit_directories::{
-    get_hotcache_from_split, read_split_footer, BundleDirectory, HotDirectory,
+    get_hotcache_from_split, read_split_footer, BundleDirectory, HotDirectory, SplitStats,
 };
 use quickwit_index_config::{DefaultIndexConfigBuilder, IndexConfig};
 use quickwit_indexing::actors::{
-    IndexerParams, IndexingPipelineParams, IndexingPipelineSupervisor,
+    IndexerParams, IndexingPipelineParams, IndexingPipelineSupervisor, SourceShardingParams,
+};
+use quickwit_indexing::models::{
+    AdaptiveCommitPolicy, CommitPolicy, DiskGuardrails, DocSizeLimit, IndexingStatistics,
+    RealtimeParams, RoutingRule, ScratchDirectory,
 };
-use quickwit_indexing::models::{CommitPolicy, IndexingStatistics, ScratchDirectory};
 use quickwit_indexing::source::{FileSourceParams, SourceConfig};
+use quickwit_indexing::FileEntry;
 use quickwit_metastore::checkpoint::Checkpoint;
 use quickwit_metastore::{IndexMetadata, MetastoreUriResolver};
-use quickwit_proto::{SearchRequest, SearchResponse};
-use quickwit_search::{single_node_search, SearchResponseRest};
-use quickwit_storage::quickwit_storage_uri_resolver;
+use quickwit_proto::{CachePolicy, SearchRequest, SearchResponse};
+use quickwit_search::{single_node_search, SearchResponseRest, SqlQuery, SqlResponse};
+use quickwit_serve::start_realtime_grpc_service;
+use quickwit_storage::{
+    multipart_file_storage, quickwit_storage_uri_resolver, StorageProgress, StorageProgressSnapshot,
+};
 use quickwit_telemetry::payload::TelemetryEvent;
-use tracing::debug;
+use serde::Serialize;
+use tracing::{debug, error};
+
+pub use crate::error::ErrorClass;
+use crate::progress::{
+    display_deletion_progress, estimate_remaining_time, format_duration, format_eta,
+    upload_progress_line, ProgressDisplay,
+};
 
 /// Throughput calculation window size.
 const THROUGHPUT_WINDOW_SIZE: usize = 5;
 
+/// Format of the results the commands print on stdout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Text meant for humans.
+    Text,
+    /// A single line JSON object meant for scripts. The text meant for humans, e.g. prompts
+    /// or progress reports, is printed on stderr instead.
+    Json,
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        OutputFormat::Text
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(output_format_str: &str) -> anyhow::Result<Self> {
+        match output_format_str {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => bail!(
+                "Unknown output format `{}`. Expected `text` or `json`.",
+                output_format_str
+            ),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Prints a message meant for humans: on stdout in text mode, on stderr in JSON mode.
+    fn print_message(&self, message: &str) {
+        match self {
+            OutputFormat::Text => println!("{}", message),
+            OutputFormat::Json => eprintln!("{}", message),
+        }
+    }
+
+    /// Prints the result of a command on stdout in JSON mode. Does nothing in text mode.
+    fn print_json_result<T: Serialize>(&self, result: &T) -> anyhow::Result<()> {
+        if *self == OutputFormat::Json {
+            println!("{}", serde_json::to_string(result)?);
+        }
+        Ok(())
+    }
+}
+
+/// Result of the `new` command in JSON mode.
+#[derive(Debug, Serialize)]
+struct CreateIndexOutput<'a> {
+    index_id: &'a str,
+    index_uri: &'a str,
+    mirror_index_uri: Option<&'a str>,
+}
+
+/// Result of the `index` command in JSON mode.
+#[derive(Debug, Serialize)]
+struct IndexDataOutput<'a> {
+    index_id: &'a str,
+    num_docs: u64,
+    num_invalid_docs: u64,
+    num_published_splits: u64,
+    total_bytes_processed: u64,
+    total_size_splits: u64,
+    elapsed_secs: u64,
+}
+
+/// Result of the `delete` and `gc` commands in JSON mode.
+#[derive(Debug, Serialize)]
+struct RemovedFilesOutput<'a> {
+    index_id: &'a str,
+    dry_run: bool,
+    num_files: usize,
+    num_bytes: u64,
+    files: &'a [FileEntry],
+}
+
+impl<'a> RemovedFilesOutput<'a> {
+    fn new(index_id: &'a str, dry_run: bool, files: &'a [FileEntry]) -> Self {
+        RemovedFilesOutput {
+            index_id,
+            dry_run,
+            num_files: files.len(),
+            num_bytes: files.iter().map(|entry| entry.file_size_in_bytes).sum(),
+            files,
+        }
+    }
+}
+
+/// Files copied or attached by the `mirror`, `clone` and `attach` commands, in JSON mode.
+#[derive(Debug, Serialize)]
+struct FilesOutput<'a> {
+    num_files: usize,
+    num_bytes: u64,
+    files: &'a [FileEntry],
+}
+
+impl<'a> FilesOutput<'a> {
+    fn new(files: &'a [FileEntry]) -> Self {
+        FilesOutput {
+            num_files: files.len(),
+            num_bytes: files.iter().map(|entry| entry.file_size_in_bytes).sum(),
+            files,
+        }
+    }
+}
+
+/// Result of the `mirror` command in JSON mode.
+#[derive(Debug, Serialize)]
+struct MirrorIndexOutput<'a> {
+    index_id: &'a str,
+    dry_run: bool,
+    copied: FilesOutput<'a>,
+    deleted: FilesOutput<'a>,
+}
+
+/// Result of the `clone` command in JSON mode.
+#[derive(Debug, Serialize)]
+struct CloneIndexOutput<'a> {
+    index_id: &'a str,
+    target_index_id: &'a str,
+    copied: FilesOutput<'a>,
+}
+
+/// Result of the `attach` command in JSON mode.
+#[derive(Debug, Serialize)]
+struct AttachIndexOutput<'a> {
+    index_id: &'a str,
+    attached: FilesOutput<'a>,
+}
+
+/// Size of a file of a split or of its hotcache, in the JSON result of the `inspect` command.
+#[derive(Debug, Serialize)]
+struct InspectSplitFileOutput {
+    path: PathBuf,
+    num_bytes: usize,
+}
+
+/// Result of the `inspect` command in JSON mode.
+#[derive(Debug, Serialize)]
+struct InspectSplitOutput<'a> {
+    index_id: &'a str,
+    split_id: &'a str,
+    files: Vec<InspectSplitFileOutput>,
+    split_stats: Option<SplitStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hotcache_files: Option<Vec<InspectSplitFileOutput>>,
+}
+
+fn inspect_split_file_outputs(stats: Vec<(PathBuf, usize)>) -> Vec<InspectSplitFileOutput> {
+    stats
+        .into_iter()
+        .map(|(path, num_bytes)| InspectSplitFileOutput { path, num_bytes })
+        .collect()
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct InspectSplitArgs {
     metastore_uri: String,
@@ -83,14 +269,21 @@ impl InspectSplitArgs {
 pub struct CreateIndexArgs {
     metastore_uri: String,
     index_uri: String,
+    mirror_index_uri: Option<String>,
     index_config: Arc<dyn IndexConfig>,
+    description: Option<String>,
+    labels: BTreeMap<String, String>,
     overwrite: bool,
 }
 impl PartialEq for CreateIndexArgs {
     // index_config is opaque and not compared currently, need to change the trait to enable
     // IndexConfig comparison
     fn eq(&self, other: &Self) -> bool {
-        self.index_uri == other.index_uri && self.overwrite == other.overwrite
+        self.index_uri == other.index_uri
+            && self.mirror_index_uri == other.mirror_index_uri
+            && self.description == other.description
+            && self.labels == other.labels
+            && self.overwrite == other.overwrite
     }
 }
 
@@ -98,34 +291,44 @@ impl CreateIndexArgs {
     pub fn new(
         metastore_uri: String,
         index_uri: String,
+        mirror_index_uri: Option<String>,
         index_config_path: PathBuf,
+        description: Option<String>,
+        labels: BTreeMap<String, String>,
         overwrite: bool,
     ) -> anyhow::Result<Self> {
-        let json_file = std::fs::File::open(index_config_path.clone())
-            .with_context(|| format!("Cannot open index-config-path {:?}", index_config_path))?;
-        let reader = std::io::BufReader::new(json_file);
-        let strip_comment_reader = StripComments::new(reader);
-        let builder: DefaultIndexConfigBuilder = serde_json::from_reader(strip_comment_reader)
-            .with_context(|| {
-                format!(
-                    "index-config-path {:?} is not a valid JSON file",
-                    index_config_path
-                )
-            })?;
-        let default_index_config = builder.build().with_context(|| {
-            format!("index-config-path file {:?} is invalid", index_config_path)
-        })?;
-        let index_config = Arc::new(default_index_config) as Arc<dyn IndexConfig>;
-
+        let index_config = load_index_config(&index_config_path)?;
         Ok(Self {
             metastore_uri,
             index_uri,
+            mirror_index_uri,
             index_config,
+            description,
+            labels,
             overwrite,
         })
     }
 }
 
+/// Loads the index config file at `index_config_path`.
+fn load_index_config(index_config_path: &Path) -> anyhow::Result<Arc<dyn IndexConfig>> {
+    let json_file = std::fs::File::open(index_config_path)
+        .with_context(|| format!("Cannot open index-config-path {:?}", index_config_path))?;
+    let reader = std::io::BufReader::new(json_file);
+    let strip_comment_reader = StripComments::new(reader);
+    let builder: DefaultIndexConfigBuilder = serde_json::from_reader(strip_comment_reader)
+        .with_context(|| {
+            format!(
+                "index-config-path {:?} is not a valid JSON file",
+                index_config_path
+            )
+        })?;
+    let default_index_config = builder
+        .build()
+        .with_context(|| format!("index-config-path file {:?} is invalid", index_config_path))?;
+    Ok(Arc::new(default_index_config))
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct IndexDataArgs {
     pub metastore_uri: String,
@@ -134,7 +337,23 @@ pub struct IndexDataArgs {
     pub source_config_path: Option<PathBuf>,
     pub temp_dir: Option<PathBuf>,
     pub heap_size: Byte,
+    pub disk_guardrails: DiskGuardrails,
+    pub doc_size_limit: DocSizeLimit,
     pub overwrite: bool,
+    pub no_progress: bool,
+    /// If set, the documents of the split being indexed are searchable through the gRPC
+    /// service started on this address, before the split is published.
+    pub realtime_grpc_addr: Option<SocketAddr>,
+    /// Minimum interval between two commits of the split being indexed, which makes the
+    /// documents received in the meantime searchable.
+    pub realtime_refresh_interval: Duration,
+    /// If set, the commit timeout adapts to the ingest rate to produce splits of this number
+    /// of documents.
+    pub target_split_num_docs: Option<u64>,
+    /// Rules sending some of the documents to other indexes than `index_id`.
+    pub routing_rules: Vec<RoutingRule>,
+    /// If set, the source is shared by this number of indexers, each reading a shard of it.
+    pub source_shards: Option<usize>,
 }
 
 #[derive(Debug, PartialEq, Eq, Default)]
@@ -150,6 +369,12 @@ pub struct SearchIndexArgs {
     pub tags: Option<Vec<String>>,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct QueryIndexArgs {
+    pub metastore_uri: String,
+    pub sql: String,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct DeleteIndexArgs {
     pub metastore_uri: String,
@@ -157,34 +382,192 @@ pub struct DeleteIndexArgs {
     pub dry_run: bool,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct DeleteSplitsArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    /// The splits to delete are the ones within the time range, or the quarantined splits if
+    /// `None`.
+    pub time_range: Option<Range<i64>>,
+    pub dry_run: bool,
+    pub gc: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifySplitsArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    pub max_num_splits: usize,
+    pub quarantined: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MirrorIndexArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CloneIndexArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    pub target_metastore_uri: String,
+    pub target_index_id: String,
+    pub target_index_uri: String,
+    pub start_timestamp: Option<i64>,
+    pub end_timestamp: Option<i64>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AttachIndexArgs {
+    pub metastore_uri: String,
+    pub index_uri: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PutCompanionFileArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    pub name: String,
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct GetCompanionFileArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    pub name: String,
+    pub version: Option<u64>,
+    pub output_path: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ListCompanionFilesArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DeleteCompanionFileArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DescribeIndexArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct UpdateIndexArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    /// The new description, if it changes. An empty description removes it.
+    pub description: Option<String>,
+    /// The `(key, value)` labels to add or replace.
+    pub labels: Vec<(String, String)>,
+    /// The keys of the labels to remove.
+    pub remove_labels: Vec<String>,
+    /// The path of the new index config, if it changes.
+    pub index_config_path: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ShowCheckpointArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SetCheckpointArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    /// The new `(partition, position)` positions.
+    pub positions: Vec<(String, String)>,
+    /// Moves the checkpoint without asking for confirmation.
+    pub yes: bool,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct GarbageCollectIndexArgs {
     pub metastore_uri: String,
     pub index_id: String,
     pub grace_period: Duration,
     pub dry_run: bool,
+    pub no_progress: bool,
 }
 
-pub async fn inspect_split_cli(args: InspectSplitArgs) -> anyhow::Result<()> {
+pub async fn inspect_split_cli(
+    args: InspectSplitArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
     debug!(args = ?args, "inspect-split");
 
     let storage_uri_resolver = quickwit_storage_uri_resolver();
     let metastore_uri_resolver = MetastoreUriResolver::default();
     let metastore = metastore_uri_resolver.resolve(&args.metastore_uri).await?;
     let index_metadata = metastore.index_metadata(&args.index_id).await?;
-    let index_storage = storage_uri_resolver.resolve(&index_metadata.index_uri)?;
-
-    let split_file = PathBuf::from(format!("{}.split", args.split_id));
-    let bundle = read_split_footer(index_storage, &split_file).await?;
+    let index_storage = storage_uri_resolver.resolve_with_mirror(
+        &index_metadata.index_uri,
+        index_metadata.mirror_index_uri.as_deref(),
+    )?;
+
+    let split_opt = metastore
+        .list_all_splits(&args.index_id)
+        .await?
+        .into_iter()
+        .find(|split| split.split_metadata.split_id == args.split_id);
+    let split_file = split_opt
+        .as_ref()
+        .map(|split| PathBuf::from(split.split_metadata.split_file()))
+        .unwrap_or_else(|| PathBuf::from(quickwit_common::split_file(&args.split_id)));
+    let index_storage = multipart_file_storage(
+        index_storage,
+        split_opt
+            .as_ref()
+            .and_then(|split| split.split_metadata.split_part_num_bytes),
+    );
+    let bundle = read_split_footer(index_storage.clone(), &split_file).await?;
 
     let stats = BundleDirectory::get_stats_split(bundle.clone())?;
-    let hotcache_bytes = get_hotcache_from_split(bundle)?;
+    let hotcache_bytes = get_hotcache_from_split(bundle.clone())?;
+    let split_stats_opt = SplitStats::read_from_split(&*index_storage, &split_file, bundle).await?;
+
+    if output_format == OutputFormat::Json {
+        let hotcache_files = if args.verbose {
+            let hotcache_stats = HotDirectory::get_stats_per_file(hotcache_bytes.into())?;
+            Some(inspect_split_file_outputs(hotcache_stats))
+        } else {
+            None
+        };
+        return output_format.print_json_result(&InspectSplitOutput {
+            index_id: &args.index_id,
+            split_id: &args.split_id,
+            files: inspect_split_file_outputs(stats),
+            split_stats: split_stats_opt,
+            hotcache_files,
+        });
+    }
 
     for (path, size) in stats {
         let readable_size = size.file_size(file_size_opts::DECIMAL).unwrap();
         println!("{:?} {}", path, readable_size);
     }
 
+    if let Some(split_stats) = split_stats_opt {
+        println!("Num docs {}", split_stats.num_docs);
+        for (field_name, field_stats) in &split_stats.fields {
+            println!(
+                "Field {:?} {}",
+                field_name,
+                serde_json::to_string(field_stats)?
+            );
+        }
+    }
+
     if args.verbose {
         let hotcache_stats = HotDirectory::get_stats_per_file(hotcache_bytes.into())?;
         for (path, size) in hotcache_stats {
@@ -195,7 +578,10 @@ pub async fn inspect_split_cli(args: InspectSplitArgs) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub async fn create_index_cli(args: CreateIndexArgs) -> anyhow::Result<()> {
+pub async fn create_index_cli(
+    args: CreateIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
     debug!(args = ?args, "create-index");
     quickwit_telemetry::send_telemetry_event(TelemetryEvent::Create).await;
     let index_id = extract_index_id_from_index_uri(&args.index_uri)?;
@@ -206,29 +592,57 @@ pub async fn create_index_cli(args: CreateIndexArgs) -> anyhow::Result<()> {
 
     let index_metadata = IndexMetadata {
         index_id: index_id.to_string(),
-        index_uri: args.index_uri.to_string(),
+        index_uri: QuickwitUri::from_str(&args.index_uri)?,
         index_config: args.index_config,
         checkpoint: Checkpoint::default(),
+        index_config_history: Vec::new(),
+        mirror_index_uri: args
+            .mirror_index_uri
+            .as_deref()
+            .map(QuickwitUri::from_str)
+            .transpose()?,
+        read_only: false,
+        description: args.description,
+        labels: args.labels,
+        create_timestamp: Utc::now().timestamp(),
     };
     create_index(&args.metastore_uri, index_metadata).await?;
-    Ok(())
+    output_format.print_json_result(&CreateIndexOutput {
+        index_id,
+        index_uri: &args.index_uri,
+        mirror_index_uri: args.mirror_index_uri.as_deref(),
+    })
 }
 
-pub async fn index_data_cli(args: IndexDataArgs) -> anyhow::Result<()> {
+pub async fn index_data_cli(
+    args: IndexDataArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
     debug!(args = ?args, "index-data");
     quickwit_telemetry::send_telemetry_event(TelemetryEvent::IndexStart).await;
 
     let source_config_path_opt = args.source_config_path.as_ref();
     let input_path_opt = args.input_path.as_ref();
-    let source_config =
-        create_source_config_from_args(source_config_path_opt, input_path_opt).await?;
+    let source_config = create_source_config_from_args(
+        source_config_path_opt,
+        input_path_opt,
+        args.doc_size_limit.max_read_num_bytes(),
+    )
+    .await?;
     let scratch_directory = if let Some(scratch_root_path) = args.temp_dir.as_ref() {
-        ScratchDirectory::new_in_path(scratch_root_path.clone())
+        ScratchDirectory::for_indexing_pipeline(
+            scratch_root_path,
+            &args.index_id,
+            &source_config.source_id,
+        )
+        .with_context(|| "Failed to create the scratch directory for the indexer")?
     } else {
         ScratchDirectory::try_new_temp()
             .with_context(|| "Failed to create a tempdir for the indexer")?
     };
-    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let storage_progress = Arc::new(StorageProgress::default());
+    let storage_uri_resolver =
+        quickwit_storage_uri_resolver().with_progress(storage_progress.clone());
     let metastore_uri_resolver = MetastoreUriResolver::default();
     let metastore = metastore_uri_resolver.resolve(&args.metastore_uri).await?;
 
@@ -241,10 +655,35 @@ pub async fn index_data_cli(args: IndexDataArgs) -> anyhow::Result<()> {
         .await?;
     }
 
+    let realtime_opt = if let Some(realtime_grpc_addr) = args.realtime_grpc_addr {
+        let realtime_splits = RealtimeSplits::default();
+        let realtime_grpc_service = start_realtime_grpc_service(
+            realtime_grpc_addr,
+            metastore.clone(),
+            realtime_splits.clone(),
+        );
+        tokio::spawn(async move {
+            if let Err(error) = realtime_grpc_service.await {
+                error!(error=?error, "The realtime gRPC service failed.");
+            }
+        });
+        Some(RealtimeParams {
+            splits: realtime_splits,
+            refresh_interval: args.realtime_refresh_interval,
+        })
+    } else {
+        None
+    };
     let indexer_params = IndexerParams {
         scratch_directory,
         heap_size: args.heap_size,
-        commit_policy: CommitPolicy::default(), //< TODO make the commit policy configurable
+        commit_policy: CommitPolicy {
+            adaptive_opt: args.target_split_num_docs.map(AdaptiveCommitPolicy::new),
+            ..Default::default()
+        },
+        disk_guardrails: args.disk_guardrails,
+        doc_size_limit: args.doc_size_limit,
+        realtime_opt,
     };
 
     let indexing_pipeline_params = IndexingPipelineParams {
@@ -253,6 +692,8 @@ pub async fn index_data_cli(args: IndexDataArgs) -> anyhow::Result<()> {
         indexer_params,
         metastore,
         storage_uri_resolver: storage_uri_resolver.clone(),
+        routing_rules: args.routing_rules.clone(),
+        source_sharding_opt: args.source_shards.map(SourceShardingParams::new),
     };
 
     let indexing_supervisor = IndexingPipelineSupervisor::new(indexing_pipeline_params);
@@ -267,31 +708,48 @@ pub async fn index_data_cli(args: IndexDataArgs) -> anyhow::Result<()> {
             "windows" => "CTRL+Z",
             _ => "CTRL+D",
         };
-        println!(
+        output_format.print_message(&format!(
             "Please enter your new line delimited json documents one line at a time.\nEnd your \
              input using {}.",
             eof_shortcut
-        );
+        ));
     }
-    let statistics =
-        start_statistics_reporting_loop(supervisor_handler, args.input_path.clone()).await?;
+    let start_time = Instant::now();
+    let statistics = start_statistics_reporting_loop(
+        supervisor_handler,
+        args.input_path.clone(),
+        storage_progress,
+        !args.no_progress,
+        output_format,
+    )
+    .await?;
 
     if statistics.num_published_splits > 0 {
-        println!(
+        output_format.print_message(&format!(
             "You can now query your index with `quickwit search --index-id {} --metastore-uri {} \
              --query \"barack obama\"`",
             args.index_id, args.metastore_uri
-        );
+        ));
     }
-    Ok(())
+    output_format.print_json_result(&IndexDataOutput {
+        index_id: &args.index_id,
+        num_docs: statistics.num_docs,
+        num_invalid_docs: statistics.num_invalid_docs,
+        num_published_splits: statistics.num_published_splits,
+        total_bytes_processed: statistics.total_bytes_processed,
+        total_size_splits: statistics.total_size_splits,
+        elapsed_secs: start_time.elapsed().as_secs(),
+    })
 }
 
 /// Inspects the CLI arguments and creates the appropriate [`SourceConfig`]. When a source config
 /// path is provided, the source config is loaded from file. Otherwise, a source config for a
-/// [`quickwit_indexing::source::FileSource`] is returned.
+/// [`quickwit_indexing::source::FileSource`] skipping the lines longer than
+/// `max_line_num_bytes_opt` is returned.
 async fn create_source_config_from_args(
     source_config_path_opt: Option<&PathBuf>,
     input_path_opt: Option<&PathBuf>,
+    max_line_num_bytes_opt: Option<u64>,
 ) -> anyhow::Result<SourceConfig> {
     if source_config_path_opt.is_some() && input_path_opt.is_some() {
         bail!(
@@ -321,6 +779,7 @@ async fn create_source_config_from_args(
         .to_string();
     let file_source_params = serde_json::to_value(FileSourceParams {
         filepath: input_path_opt.cloned(),
+        max_line_num_bytes: max_line_num_bytes_opt,
     })?;
     let source_config = SourceConfig {
         source_id,
@@ -344,25 +803,86 @@ pub async fn search_index(args: SearchIndexArgs) -> anyhow::Result<SearchRespons
         max_hits: args.max_hits as u64,
         start_offset: args.start_offset as u64,
         tags: args.tags.unwrap_or_default(),
+        distinct_count_fields: Vec::new(),
+        percentile_fields: Vec::new(),
+        percentiles: Vec::new(),
+        terms_aggregation: None,
+        date_histogram: None,
+        time_zone: String::new(),
+        geo_filter: None,
+        mandatory_filter: None,
+        flatten_hits: false,
+        sort_by_field: None,
+        sort_ascending: false,
+        disable_synonyms: false,
+        session_token: None,
+        min_publish_timestamp: None,
+        cache_policy: CachePolicy::Use as i32,
+        max_num_splits: None,
+        hit_sampling: None,
     };
     let search_response: SearchResponse =
         single_node_search(&search_request, &*metastore, storage_uri_resolver.clone()).await?;
     Ok(search_response)
 }
 
-pub async fn search_index_cli(args: SearchIndexArgs) -> anyhow::Result<()> {
+pub async fn search_index_cli(
+    args: SearchIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
     let search_response: SearchResponse = search_index(args).await?;
     let search_response_rest = SearchResponseRest::try_from(search_response)?;
-    let search_response_rest_json = serde_json::to_string_pretty(&search_response_rest)?;
+    let search_response_rest_json = match output_format {
+        OutputFormat::Text => serde_json::to_string_pretty(&search_response_rest)?,
+        OutputFormat::Json => serde_json::to_string(&search_response_rest)?,
+    };
     println!("{}", search_response_rest_json);
     Ok(())
 }
 
-pub async fn delete_index_cli(args: DeleteIndexArgs) -> anyhow::Result<()> {
+pub async fn query_index(args: QueryIndexArgs) -> anyhow::Result<SqlResponse> {
+    debug!(args = ?args, "query-index");
+    let sql_query = SqlQuery::parse(&args.sql)?;
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let metastore_uri_resolver = MetastoreUriResolver::default();
+    let metastore = metastore_uri_resolver.resolve(&args.metastore_uri).await?;
+    let search_response = single_node_search(
+        &sql_query.search_request(),
+        &*metastore,
+        storage_uri_resolver.clone(),
+    )
+    .await?;
+    Ok(sql_query.build_response(search_response)?)
+}
+
+pub async fn query_index_cli(
+    args: QueryIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    let sql_response = query_index(args).await?;
+    let sql_response_json = match output_format {
+        OutputFormat::Text => serde_json::to_string_pretty(&sql_response)?,
+        OutputFormat::Json => serde_json::to_string(&sql_response)?,
+    };
+    println!("{}", sql_response_json);
+    Ok(())
+}
+
+pub async fn delete_index_cli(
+    args: DeleteIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
     debug!(args = ?args, "delete-index");
     quickwit_telemetry::send_telemetry_event(TelemetryEvent::Delete).await;
 
     let affected_files = delete_index(&args.metastore_uri, &args.index_id, args.dry_run).await?;
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&RemovedFilesOutput::new(
+            &args.index_id,
+            args.dry_run,
+            &affected_files,
+        ));
+    }
     if args.dry_run {
         if affected_files.is_empty() {
             println!("Only the index will be deleted since it does not contains any data file.");
@@ -383,17 +903,576 @@ pub async fn delete_index_cli(args: DeleteIndexArgs) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub async fn garbage_collect_index_cli(args: GarbageCollectIndexArgs) -> anyhow::Result<()> {
+pub async fn delete_splits_cli(
+    args: DeleteSplitsArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "delete-splits");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::DeleteSplits).await;
+
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let split_files = if let Some(time_range) = args.time_range.clone() {
+        delete_splits_in_time_range(
+            &args.metastore_uri,
+            &args.index_id,
+            time_range,
+            args.dry_run,
+            args.gc,
+            storage_uri_resolver,
+        )
+        .await?
+    } else {
+        delete_quarantined_splits(
+            &args.metastore_uri,
+            &args.index_id,
+            args.dry_run,
+            args.gc,
+            storage_uri_resolver,
+        )
+        .await?
+    };
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&RemovedFilesOutput::new(
+            &args.index_id,
+            args.dry_run,
+            &split_files,
+        ));
+    }
+    if split_files.is_empty() {
+        if args.time_range.is_some() {
+            println!(
+                "No split of index `{}` is within the time range.",
+                args.index_id
+            );
+        } else {
+            println!("No split of index `{}` is quarantined.", args.index_id);
+        }
+        return Ok(());
+    }
+    if args.dry_run {
+        println!(
+            "The following split files of index `{}` will be removed",
+            args.index_id
+        );
+        for file_entry in split_files {
+            println!(" - {}", file_entry.file_name);
+        }
+        return Ok(());
+    }
+    if args.gc {
+        println!(
+            "{} split(s) of index `{}` successfully deleted.",
+            split_files.len(),
+            args.index_id
+        );
+    } else {
+        println!(
+            "{} split(s) of index `{}` marked for deletion, their files will be removed by the \
+             next garbage collection.",
+            split_files.len(),
+            args.index_id
+        );
+    }
+    Ok(())
+}
+
+pub async fn verify_splits_cli(
+    args: VerifySplitsArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "verify-splits");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::VerifySplits).await;
+
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let stats = verify_index_splits(
+        &args.metastore_uri,
+        &args.index_id,
+        args.max_num_splits,
+        args.quarantined,
+        storage_uri_resolver,
+    )
+    .await?;
+    if output_format == OutputFormat::Json {
+        output_format.print_json_result(&stats)?;
+    } else {
+        println!(
+            "{} split(s) of index `{}` verified.",
+            stats.num_verified_splits, args.index_id
+        );
+        if stats.num_failed_splits > 0 {
+            println!(
+                "{} split(s) could not be verified, see the logs for details.",
+                stats.num_failed_splits
+            );
+        }
+        for split_id in &stats.corrupted_split_ids {
+            println!(" - {} is corrupted", split_id);
+        }
+        for split_id in &stats.restored_split_ids {
+            println!(" - {} is intact and published again", split_id);
+        }
+    }
+    // Fail so that the scripts running the command periodically can alert on corruption.
+    if !stats.corrupted_split_ids.is_empty() {
+        bail!(
+            "{} corrupted split(s) found in index `{}`.",
+            stats.corrupted_split_ids.len(),
+            args.index_id
+        );
+    }
+    Ok(())
+}
+
+pub async fn mirror_index_cli(
+    args: MirrorIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "mirror-index");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::Mirror).await;
+
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let reconciliation = mirror_index(
+        &args.metastore_uri,
+        &args.index_id,
+        args.dry_run,
+        storage_uri_resolver,
+    )
+    .await?;
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&MirrorIndexOutput {
+            index_id: &args.index_id,
+            dry_run: args.dry_run,
+            copied: FilesOutput::new(&reconciliation.copied_files),
+            deleted: FilesOutput::new(&reconciliation.deleted_files),
+        });
+    }
+    if reconciliation.copied_files.is_empty() && reconciliation.deleted_files.is_empty() {
+        println!("The mirror of the index `{}` is up to date.", args.index_id);
+        return Ok(());
+    }
+
+    if args.dry_run {
+        if !reconciliation.copied_files.is_empty() {
+            println!("The following files will be copied to the mirror.");
+            for file_entry in &reconciliation.copied_files {
+                println!(" - {}", file_entry.file_name);
+            }
+        }
+        if !reconciliation.deleted_files.is_empty() {
+            println!("The following files will be deleted from the mirror.");
+            for file_entry in &reconciliation.deleted_files {
+                println!(" - {}", file_entry.file_name);
+            }
+        }
+        return Ok(());
+    }
+
+    println!(
+        "{} files copied to and {} files deleted from the mirror of the index `{}`.",
+        reconciliation.copied_files.len(),
+        reconciliation.deleted_files.len(),
+        args.index_id
+    );
+    Ok(())
+}
+
+pub async fn clone_index_cli(
+    args: CloneIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "clone-index");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::Clone).await;
+
+    let time_range_opt = if args.start_timestamp.is_some() || args.end_timestamp.is_some() {
+        Some(args.start_timestamp.unwrap_or(i64::MIN)..args.end_timestamp.unwrap_or(i64::MAX))
+    } else {
+        None
+    };
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let copied_files = clone_index(
+        &args.metastore_uri,
+        &args.index_id,
+        &args.target_metastore_uri,
+        &args.target_index_id,
+        &args.target_index_uri,
+        time_range_opt,
+        storage_uri_resolver,
+    )
+    .await?;
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&CloneIndexOutput {
+            index_id: &args.index_id,
+            target_index_id: &args.target_index_id,
+            copied: FilesOutput::new(&copied_files),
+        });
+    }
+    println!(
+        "Index `{}` successfully cloned into `{}`, {} split files copied.",
+        args.index_id,
+        args.target_index_id,
+        copied_files.len()
+    );
+    Ok(())
+}
+
+pub async fn attach_index_cli(
+    args: AttachIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "attach-index");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::Attach).await;
+
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let (index_id, attached_files) =
+        attach_index(&args.metastore_uri, &args.index_uri, storage_uri_resolver).await?;
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&AttachIndexOutput {
+            index_id: &index_id,
+            attached: FilesOutput::new(&attached_files),
+        });
+    }
+    println!(
+        "Index `{}` successfully attached in read-only mode, {} split files can be searched.",
+        index_id,
+        attached_files.len()
+    );
+    Ok(())
+}
+
+pub async fn put_companion_file_cli(
+    args: PutCompanionFileArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "put-companion-file");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::Companion).await;
+
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let file_version = put_companion_file(
+        &args.metastore_uri,
+        &args.index_id,
+        &args.name,
+        &args.file_path,
+        storage_uri_resolver,
+    )
+    .await?;
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&file_version);
+    }
+    let readable_size = file_version
+        .num_bytes
+        .file_size(file_size_opts::DECIMAL)
+        .unwrap();
+    println!(
+        "Version {} of companion file `{}` of index `{}` written ({}).",
+        file_version.version, args.name, args.index_id, readable_size
+    );
+    Ok(())
+}
+
+pub async fn get_companion_file_cli(
+    args: GetCompanionFileArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "get-companion-file");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::Companion).await;
+
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let (file_version, content) = get_companion_file(
+        &args.metastore_uri,
+        &args.index_id,
+        &args.name,
+        args.version,
+        storage_uri_resolver,
+    )
+    .await?;
+    if let Some(output_path) = &args.output_path {
+        std::fs::write(output_path, &content)
+            .with_context(|| format!("Failed to write `{}`.", output_path.display()))?;
+        output_format.print_message(&format!(
+            "Version {} of companion file `{}` written to `{}`.",
+            file_version.version,
+            args.name,
+            output_path.display()
+        ));
+        return Ok(());
+    }
+    // The content is printed as is, whatever the output format.
+    std::io::stdout().write_all(&content)?;
+    Ok(())
+}
+
+pub async fn list_companion_files_cli(
+    args: ListCompanionFilesArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "list-companion-files");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::Companion).await;
+
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let companion_files =
+        list_companion_files(&args.metastore_uri, &args.index_id, storage_uri_resolver).await?;
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&companion_files);
+    }
+    if companion_files.is_empty() {
+        println!("The index `{}` has no companion files.", args.index_id);
+        return Ok(());
+    }
+    for companion_file in companion_files {
+        if let Some(latest_version) = companion_file.latest_version() {
+            println!(
+                " - {} (latest version: {}, {} version(s))",
+                companion_file.name,
+                latest_version.version,
+                companion_file.versions.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+pub async fn delete_companion_file_cli(
+    args: DeleteCompanionFileArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "delete-companion-file");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::Companion).await;
+
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let deleted_file = delete_companion_file(
+        &args.metastore_uri,
+        &args.index_id,
+        &args.name,
+        storage_uri_resolver,
+    )
+    .await?;
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&deleted_file);
+    }
+    println!(
+        "Companion file `{}` of index `{}` deleted, {} version(s) removed.",
+        args.name,
+        args.index_id,
+        deleted_file.versions.len()
+    );
+    Ok(())
+}
+
+pub async fn describe_index_cli(
+    args: DescribeIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "describe-index");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::Describe).await;
+
+    let index_description = describe_index(&args.metastore_uri, &args.index_id).await?;
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&index_description);
+    }
+    print_index_description(&index_description);
+    Ok(())
+}
+
+pub async fn update_index_cli(
+    args: UpdateIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "update-index");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::Update).await;
+
+    let mut index_description_opt = None;
+    if let Some(index_config_path) = &args.index_config_path {
+        let index_config = load_index_config(index_config_path)?;
+        index_description_opt =
+            Some(update_index_config(&args.metastore_uri, &args.index_id, index_config).await?);
+    }
+    if args.description.is_some() || !args.labels.is_empty() || !args.remove_labels.is_empty() {
+        index_description_opt = Some(
+            update_index_labels(
+                &args.metastore_uri,
+                &args.index_id,
+                args.description.as_deref(),
+                &args.labels,
+                &args.remove_labels,
+            )
+            .await?,
+        );
+    }
+    let index_description = index_description_opt.context("Nothing to update for the index.")?;
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&index_description);
+    }
+    println!("Index `{}` successfully updated.", args.index_id);
+    print_index_description(&index_description);
+    Ok(())
+}
+
+fn print_index_description(index_description: &IndexDescription) {
+    println!("Index `{}`:", index_description.index_id);
+    println!(" - URI: {}", index_description.index_uri);
+    if let Some(mirror_index_uri) = &index_description.mirror_index_uri {
+        println!(" - mirror URI: {}", mirror_index_uri);
+    }
+    if index_description.read_only {
+        println!(" - read-only: attached from the storage of another cluster");
+    }
+    if let Some(description) = &index_description.description {
+        println!(" - description: {}", description);
+    }
+    if !index_description.labels.is_empty() {
+        let labels: Vec<String> = index_description
+            .labels
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        println!(" - labels: {}", labels.join(", "));
+    }
+    println!(
+        " - index config version: {}",
+        index_description.index_config_version
+    );
+    println!(
+        " - published splits: {} ({} docs, {})",
+        index_description.num_published_splits,
+        index_description.num_published_docs,
+        index_description
+            .published_splits_size_in_bytes
+            .file_size(file_size_opts::DECIMAL)
+            .unwrap()
+    );
+}
+
+pub async fn show_checkpoint_cli(
+    args: ShowCheckpointArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "show-checkpoint");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::Checkpoint).await;
+
+    let checkpoint = index_checkpoint(&args.metastore_uri, &args.index_id).await?;
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&checkpoint);
+    }
+    if checkpoint.is_empty() {
+        println!(
+            "The checkpoint of index `{}` is empty: nothing was indexed from its source yet.",
+            args.index_id
+        );
+        return Ok(());
+    }
+    println!("Checkpoint of index `{}`:", args.index_id);
+    for (partition_id, position) in checkpoint.iter() {
+        let watermark = checkpoint
+            .watermark_for_partition(&partition_id)
+            .map(|watermark| format!(" (watermark: {})", watermark))
+            .unwrap_or_default();
+        println!(
+            " - partition `{}`: position `{}`{}",
+            partition_id.0,
+            position.as_str(),
+            watermark
+        );
+    }
+    Ok(())
+}
+
+pub async fn set_checkpoint_cli(
+    args: SetCheckpointArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "set-checkpoint");
+    quickwit_telemetry::send_telemetry_event(TelemetryEvent::Checkpoint).await;
+
+    let changes =
+        set_checkpoint_positions(&args.metastore_uri, &args.index_id, &args.positions, true)
+            .await?;
+    output_format.print_message(&format!(
+        "The checkpoint of index `{}` will be moved:",
+        args.index_id
+    ));
+    for change in &changes {
+        output_format.print_message(&format!(
+            " - partition `{}`: position `{}` -> `{}`",
+            change.partition_id,
+            change.from.as_deref().unwrap_or(""),
+            change.to
+        ));
+    }
+    if !args.yes {
+        if !atty::is(atty::Stream::Stdin) {
+            bail!("Moving the checkpoint requires a confirmation, pass `--yes` to confirm it.");
+        }
+        eprint!("The indexing should be stopped meanwhile. Continue? [y/N] ");
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            output_format.print_message("Aborted, the checkpoint was not moved.");
+            return Ok(());
+        }
+    }
+    let changes =
+        set_checkpoint_positions(&args.metastore_uri, &args.index_id, &args.positions, false)
+            .await?;
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&changes);
+    }
+    println!(
+        "Checkpoint of index `{}` moved, the indexing resumes from the new positions.",
+        args.index_id
+    );
+    Ok(())
+}
+
+pub async fn garbage_collect_index_cli(
+    args: GarbageCollectIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
     debug!(args = ?args, "garbage-collect-index");
     quickwit_telemetry::send_telemetry_event(TelemetryEvent::GarbageCollect).await;
 
-    let deleted_files = garbage_collect_index(
+    let storage_progress = Arc::new(StorageProgress::default());
+    let storage_uri_resolver =
+        quickwit_storage_uri_resolver().with_progress(storage_progress.clone());
+    let garbage_collect_future = garbage_collect_index(
         &args.metastore_uri,
         &args.index_id,
         args.grace_period,
         args.dry_run,
-    )
-    .await?;
+        &storage_uri_resolver,
+    );
+    let deleted_files = if args.dry_run || args.no_progress {
+        garbage_collect_future.await?
+    } else {
+        // A dry run lists the files to delete without touching the storage.
+        let num_files_to_delete = garbage_collect_index(
+            &args.metastore_uri,
+            &args.index_id,
+            args.grace_period,
+            true,
+            &storage_uri_resolver,
+        )
+        .await?
+        .len() as u64;
+        if num_files_to_delete == 0 {
+            garbage_collect_future.await?
+        } else {
+            display_deletion_progress(
+                garbage_collect_future,
+                &storage_progress,
+                num_files_to_delete,
+                output_format,
+            )
+            .await??
+        }
+    };
+    if output_format == OutputFormat::Json {
+        return output_format.print_json_result(&RemovedFilesOutput::new(
+            &args.index_id,
+            args.dry_run,
+            &deleted_files,
+        ));
+    }
     if deleted_files.is_empty() {
         println!("No dangling files to garbage collect.");
         return Ok(());
@@ -421,14 +1500,24 @@ pub async fn garbage_collect_index_cli(args: GarbageCollectIndexArgs) -> anyhow:
 
 /// Starts a tokio task that displays the indexing statistics
 /// every once in awhile.
+///
+/// The progress of the split uploads is read from `storage_progress`. Unless `show_progress`
+/// is false, the statistics are displayed every second, on stderr in JSON mode.
 pub async fn start_statistics_reporting_loop(
     pipeline_handler: ActorHandle<IndexingPipelineSupervisor>,
     input_path_opt: Option<PathBuf>,
+    storage_progress: Arc<StorageProgress>,
+    show_progress: bool,
+    output_format: OutputFormat,
 ) -> anyhow::Result<IndexingStatistics> {
-    let mut stdout_handle = stdout();
+    let mut progress_display = ProgressDisplay::new(output_format);
     let start_time = Instant::now();
-    let is_tty = atty::is(atty::Stream::Stdout);
     let mut throughput_calculator = ThroughputCalculator::new(start_time);
+    // The time remaining can only be estimated when the size of the input is known.
+    let input_num_bytes_opt = input_path_opt
+        .as_ref()
+        .and_then(|input_path| std::fs::metadata(input_path).ok())
+        .map(|metadata| metadata.len());
     let mut report_interval = tokio::time::interval(Duration::from_secs(1));
 
     loop {
@@ -441,12 +1530,13 @@ pub async fn start_statistics_reporting_loop(
         let observation = pipeline_handler.observe().await;
 
         // Let's not display live statistics to allow screen to scroll.
-        if observation.state.num_docs > 0 {
+        if show_progress && observation.state.num_docs > 0 {
             display_statistics(
-                &mut stdout_handle,
+                &mut progress_display,
                 &mut throughput_calculator,
                 &observation.state,
-                is_tty,
+                input_num_bytes_opt,
+                &storage_progress.snapshot(),
             )?;
         }
 
@@ -466,7 +1556,12 @@ pub async fn start_statistics_reporting_loop(
             bail!(supervisor_exit_status)
         }
         ActorExitStatus::Failure(err) => {
-            bail!(err);
+            // Returning the error itself rather than its message preserves its chain of
+            // causes, from which the class of the error is inferred.
+            match Arc::try_unwrap(err) {
+                Ok(err) => return Err(err),
+                Err(err) => bail!(err),
+            }
         }
     }
 
@@ -476,80 +1571,100 @@ pub async fn start_statistics_reporting_loop(
         return Ok(statistics);
     }
 
-    if input_path_opt.is_none() {
+    if show_progress && input_path_opt.is_none() {
         display_statistics(
-            &mut stdout_handle,
+            &mut progress_display,
             &mut throughput_calculator,
             &statistics,
-            is_tty,
+            input_num_bytes_opt,
+            &storage_progress.snapshot(),
         )?;
     }
     // display end of task report
-    println!();
+    output_format.print_message("");
     let elapsed_secs = start_time.elapsed().as_secs();
     if elapsed_secs >= 60 {
-        println!(
+        output_format.print_message(&format!(
             "Indexed {} documents in {:.2$}min",
             statistics.num_docs,
             elapsed_secs.max(1) as f64 / 60f64,
             2
-        );
+        ));
     } else {
-        println!(
+        output_format.print_message(&format!(
             "Indexed {} documents in {}s",
             statistics.num_docs,
             elapsed_secs.max(1)
-        );
+        ));
     }
 
     Ok(statistics)
 }
 
 fn display_statistics(
-    stdout_handle: &mut Stdout,
+    progress_display: &mut ProgressDisplay,
     throughput_calculator: &mut ThroughputCalculator,
     statistics: &IndexingStatistics,
-    is_tty: bool,
+    input_num_bytes_opt: Option<u64>,
+    storage_progress: &StorageProgressSnapshot,
 ) -> anyhow::Result<()> {
-    let elapsed_duration = chrono::Duration::from_std(throughput_calculator.elapsed_time())?;
-    let elapsed_time = format!(
-        "{:02}:{:02}:{:02}",
-        elapsed_duration.num_hours(),
-        elapsed_duration.num_minutes() % 60,
-        elapsed_duration.num_seconds() % 60
-    );
+    let elapsed_time = format_duration(throughput_calculator.elapsed_time());
     let throughput_mb_s = throughput_calculator.calculate(statistics.total_bytes_processed);
-    if is_tty {
-        stdout_handle.queue(PrintStyledContent("Num docs: ".blue()))?;
-        stdout_handle.queue(Print(format!("{:>7}", statistics.num_docs)))?;
-        stdout_handle.queue(PrintStyledContent(" Parse errs: ".blue()))?;
-        stdout_handle.queue(Print(format!("{:>5}", statistics.num_invalid_docs)))?;
-        stdout_handle.queue(PrintStyledContent(" Staged splits: ".blue()))?;
-        stdout_handle.queue(Print(format!("{:>3}", statistics.num_staged_splits)))?;
-        stdout_handle.queue(PrintStyledContent(" Input size: ".blue()))?;
-        stdout_handle.queue(Print(format!(
-            "{:>5}MB",
-            statistics.total_bytes_processed / 1_000_000
-        )))?;
-        stdout_handle.queue(PrintStyledContent(" Thrghput: ".blue()))?;
-        stdout_handle.queue(Print(format!("{:>5.2}MB/s", throughput_mb_s)))?;
-        stdout_handle.queue(PrintStyledContent(" Time: ".blue()))?;
-        stdout_handle.queue(Print(format!("{}\n", elapsed_time)))?;
-    } else {
-        let report_line = format!(
-            "Num docs: {:>7} Parse errs: {:>5} Staged splits: {:>3} Input size: {:>5}MB Thrghput: \
-             {:>5.2}MB/s Time: {}\n",
-            statistics.num_docs,
-            statistics.num_invalid_docs,
-            statistics.num_staged_splits,
+    let is_tty = progress_display.is_tty();
+    let label = |label: &str| {
+        if is_tty {
+            label.blue().to_string()
+        } else {
+            label.to_string()
+        }
+    };
+    let input_size = match input_num_bytes_opt {
+        Some(input_num_bytes) => format!(
+            "{:>5}MB/{}MB",
             statistics.total_bytes_processed / 1_000_000,
-            throughput_mb_s,
-            elapsed_time,
+            input_num_bytes / 1_000_000
+        ),
+        None => format!("{:>5}MB", statistics.total_bytes_processed / 1_000_000),
+    };
+    let mut report_line = format!(
+        "{}{:>7}{}{:>5}{}{:>3}{}{}{}{:>5.2}MB/s{}{}",
+        label("Num docs: "),
+        statistics.num_docs,
+        label(" Parse errs: "),
+        statistics.num_invalid_docs,
+        label(" Staged splits: "),
+        statistics.num_staged_splits,
+        label(" Input size: "),
+        input_size,
+        label(" Thrghput: "),
+        throughput_mb_s,
+        label(" Time: "),
+        elapsed_time,
+    );
+    if let Some(input_num_bytes) = input_num_bytes_opt {
+        let remaining_time_opt = estimate_remaining_time(
+            input_num_bytes.saturating_sub(statistics.total_bytes_processed),
+            throughput_mb_s * 1_000_000f64,
         );
-        stdout_handle.write_all(report_line.as_bytes())?;
+        report_line.push(' ');
+        report_line.push_str(&format_eta(remaining_time_opt));
     }
-    stdout_handle.flush()?;
-    Ok(())
+    let mut lines = vec![report_line];
+    lines.extend(
+        storage_progress
+            .ongoing_uploads
+            .iter()
+            .map(upload_progress_line),
+    );
+    if storage_progress.num_uploaded_files > 0 {
+        lines.push(format!(
+            "{}{} ({}MB)",
+            label("Uploaded splits: "),
+            storage_progress.num_uploaded_files,
+            storage_progress.num_uploaded_bytes / 1_000_000
+        ));
+    }
+    progress_display.draw(&lines)
 }
 
 /// ThroughputCalculator is used to calculate throughput.
@@ -600,23 +1715,29 @@ mod tests {
     #[tokio::test]
     async fn test_create_source_config_from_input_path() -> anyhow::Result<()> {
         {
-            let source_config = create_source_config_from_args(None, None).await?;
+            let source_config = create_source_config_from_args(None, None, None).await?;
             assert_eq!(source_config.source_id, "stdin-source");
             assert_eq!(source_config.source_type, "file");
             assert_eq!(
                 source_config.params.get("filepath"),
                 Some(&json!(None::<&str>))
             );
+            assert!(source_config.params.get("max_line_num_bytes").is_none());
         }
         {
             let input_path = PathBuf::from("path/to/file");
-            let source_config = create_source_config_from_args(None, Some(&input_path)).await?;
+            let source_config =
+                create_source_config_from_args(None, Some(&input_path), Some(1_000)).await?;
             assert_eq!(source_config.source_id, "file-source");
             assert_eq!(source_config.source_type, "file");
             assert_eq!(
                 source_config.params.get("filepath"),
                 Some(&json!("path/to/file"))
             );
+            assert_eq!(
+                source_config.params.get("max_line_num_bytes"),
+                Some(&json!(1_000))
+            );
         }
         Ok(())
     }
@@ -633,7 +1754,8 @@ mod tests {
             },
         });
         serde_json::to_writer(source_config_file.as_file(), &source_config_json)?;
-        let source_config = create_source_config_from_args(Some(&source_config_path), None).await?;
+        let source_config =
+            create_source_config_from_args(Some(&source_config_path), None, None).await?;
         assert_eq!(source_config.source_id, "foo-source");
         assert_eq!(source_config.source_type, "foo");
         assert_eq!(source_config.params.get("foo"), Some(&json!("bar")));
@@ -17,33 +17,74 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
 use std::env;
 use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::time::Duration;
 
 use anyhow::{bail, Context};
 use byte_unit::Byte;
 use clap::{load_yaml, value_t, App, AppSettings, ArgMatches};
+use futures::FutureExt;
 use opentelemetry::global;
 use opentelemetry::sdk::propagation::TraceContextPropagator;
+use quickwit_cli::bench::{
+    bench_cli, BenchArgs, BenchIndexArgs, BenchSearchArgs, GenerateDocsArgs,
+};
+use quickwit_cli::tail::{tail_index_cli, TailIndexArgs};
 use quickwit_cli::*;
 use quickwit_common::net::socket_addr_from_str;
-use quickwit_serve::{serve_cli, ServeArgs};
+use quickwit_common::QuickwitUri;
+use quickwit_indexing::models::{DiskGuardrails, DocSizeLimit, OversizedDocPolicy, RoutingRule};
+use quickwit_search::{
+    parse_node_attribute, FastFieldPinningParams, HedgingParams, NodeAttributes, PlacementRule,
+    PlacementRules, SearchThreadPoolParams,
+};
+use quickwit_serve::{
+    serve_cli, ApiKey, ApiKeySplitLimit, AuditLogConfig, ClickHouseSinkConfig, QuotaConfig,
+    ServeArgs,
+};
+use quickwit_storage::{S3HttpConfig, StorageTimeoutPolicy};
 use quickwit_telemetry::payload::TelemetryEvent;
 use tracing::Level;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::EnvFilter;
 
+/// Delay before the first retry of a command failing with a transient error. The delay doubles
+/// with each retry.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Maximum delay between two retries of a command.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
 #[derive(Debug, PartialEq)]
 enum CliCommand {
     InspectSplit(InspectSplitArgs),
     New(CreateIndexArgs),
     Index(IndexDataArgs),
     Search(SearchIndexArgs),
+    Tail(TailIndexArgs),
+    Query(QueryIndexArgs),
     Serve(ServeArgs),
     GarbageCollect(GarbageCollectIndexArgs),
     Delete(DeleteIndexArgs),
+    Describe(DescribeIndexArgs),
+    Update(UpdateIndexArgs),
+    DeleteSplits(DeleteSplitsArgs),
+    VerifySplits(VerifySplitsArgs),
+    Mirror(MirrorIndexArgs),
+    Clone(CloneIndexArgs),
+    Attach(AttachIndexArgs),
+    PutCompanionFile(PutCompanionFileArgs),
+    GetCompanionFile(GetCompanionFileArgs),
+    ListCompanionFiles(ListCompanionFilesArgs),
+    DeleteCompanionFile(DeleteCompanionFileArgs),
+    ShowCheckpoint(ShowCheckpointArgs),
+    SetCheckpoint(SetCheckpointArgs),
+    Bench(BenchArgs),
 }
 
 impl CliCommand {
@@ -53,9 +94,37 @@ impl CliCommand {
             CliCommand::New(_) => Level::WARN,
             CliCommand::Index(_) => Level::WARN,
             CliCommand::Search(_) => Level::WARN,
+            CliCommand::Tail(_) => Level::WARN,
+            CliCommand::Query(_) => Level::WARN,
             CliCommand::Serve(_) => Level::INFO,
             CliCommand::GarbageCollect(_) => Level::WARN,
             CliCommand::Delete(_) => Level::WARN,
+            CliCommand::Describe(_) => Level::WARN,
+            CliCommand::Update(_) => Level::WARN,
+            CliCommand::DeleteSplits(_) => Level::WARN,
+            CliCommand::VerifySplits(_) => Level::WARN,
+            CliCommand::Mirror(_) => Level::WARN,
+            CliCommand::Clone(_) => Level::WARN,
+            CliCommand::Attach(_) => Level::WARN,
+            CliCommand::PutCompanionFile(_) => Level::WARN,
+            CliCommand::GetCompanionFile(_) => Level::WARN,
+            CliCommand::ListCompanionFiles(_) => Level::WARN,
+            CliCommand::DeleteCompanionFile(_) => Level::WARN,
+            CliCommand::ShowCheckpoint(_) => Level::WARN,
+            CliCommand::SetCheckpoint(_) => Level::WARN,
+            CliCommand::Bench(_) => Level::WARN,
+        }
+    }
+
+    /// Returns true if running the command again after a transient error makes sense.
+    fn is_retryable(&self) -> bool {
+        match self {
+            // The documents read from stdin before the failure cannot be read again.
+            CliCommand::Index(args) => {
+                args.input_path.is_some() || args.source_config_path.is_some()
+            }
+            CliCommand::Serve(_) => false,
+            _ => true,
         }
     }
 
@@ -68,10 +137,21 @@ impl CliCommand {
             "new" => Self::parse_new_args(submatches),
             "index" => Self::parse_index_args(submatches),
             "search" => Self::parse_search_args(submatches),
+            "tail" => Self::parse_tail_args(submatches),
+            "query" => Self::parse_query_args(submatches),
             "serve" => Self::parse_serve_args(submatches),
             "gc" => Self::parse_garbage_collect_args(submatches),
             "delete" => Self::parse_delete_args(submatches),
+            "describe" => Self::parse_describe_args(submatches),
+            "update" => Self::parse_update_args(submatches),
+            "split" => Self::parse_split_args(submatches),
+            "mirror" => Self::parse_mirror_args(submatches),
+            "clone" => Self::parse_clone_args(submatches),
+            "attach" => Self::parse_attach_args(submatches),
+            "companion" => Self::parse_companion_args(submatches),
+            "checkpoint" => Self::parse_checkpoint_args(submatches),
             "inspect-split" => Self::parse_inspect_split_args(submatches),
+            "bench" => Self::parse_bench_args(submatches),
             _ => bail!("Subcommand '{}' is not implemented", subcommand),
         }
     }
@@ -79,22 +159,39 @@ impl CliCommand {
     fn parse_new_args(matches: &ArgMatches) -> anyhow::Result<Self> {
         let index_uri = matches
             .value_of("index-uri")
-            .context("'index-uri' is a required arg")?
-            .to_string();
+            .map(parse_uri)
+            .context("'index-uri' is a required arg")??;
+        let mirror_index_uri = matches
+            .value_of("mirror-index-uri")
+            .map(parse_uri)
+            .transpose()?;
         let index_config_path = matches
             .value_of("index-config-path")
             .map(PathBuf::from)
             .context("'index-config-path' is a required arg")?;
         let metastore_uri = matches
             .value_of("metastore-uri")
-            .map(|metastore_uri_str| metastore_uri_str.to_string())
-            .context("'metastore-uri' is a required arg")?;
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let description = matches.value_of("description").map(str::to_string);
+        let labels = matches
+            .values_of("label")
+            .map(|values| {
+                values
+                    .map(parse_label)
+                    .collect::<anyhow::Result<BTreeMap<_, _>>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
         let overwrite = matches.is_present("overwrite");
 
         Ok(CliCommand::New(CreateIndexArgs::new(
             metastore_uri,
             index_uri,
+            mirror_index_uri,
             index_config_path,
+            description,
+            labels,
             overwrite,
         )?))
     }
@@ -110,8 +207,8 @@ impl CliCommand {
             .to_string();
         let metastore_uri = matches
             .value_of("metastore-uri")
-            .map(|metastore_uri_str| metastore_uri_str.to_string())
-            .context("'metastore-uri' is a required arg")?;
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
 
         let verbose = matches.is_present("verbose");
 
@@ -126,8 +223,8 @@ impl CliCommand {
     fn parse_index_args(matches: &ArgMatches) -> anyhow::Result<Self> {
         let metastore_uri = matches
             .value_of("metastore-uri")
-            .map(|metastore_uri_str| metastore_uri_str.to_string())
-            .expect("`metastore-uri` is a required arg.");
+            .map(parse_uri)
+            .expect("`metastore-uri` is a required arg.")?;
         let index_id = matches
             .value_of("index-id")
             .expect("`index-id` is a required arg.")
@@ -140,7 +237,59 @@ impl CliCommand {
             .value_of("heap-size")
             .expect("`heap-size` has a default value.");
         let heap_size = Byte::from_str(heap_size_str)?;
+        let max_disk_usage = matches
+            .value_of("max-disk-usage")
+            .map(Byte::from_str)
+            .transpose()?;
+        let min_free_disk_space = matches
+            .value_of("min-free-disk-space")
+            .map(Byte::from_str)
+            .transpose()?;
+        let max_doc_size = matches
+            .value_of("max-doc-size")
+            .map(Byte::from_str)
+            .transpose()?;
+        let oversized_doc_policy = matches
+            .value_of("oversized-doc-policy")
+            .map(OversizedDocPolicy::from_str)
+            .expect("`oversized-doc-policy` has a default value.")?;
         let overwrite = matches.is_present("overwrite");
+        let no_progress = matches.is_present("no-progress");
+        let realtime_grpc_addr = matches
+            .value_of("realtime-grpc-listen-address")
+            .map(socket_addr_from_str)
+            .transpose()?;
+        let realtime_refresh_interval = matches
+            .value_of("realtime-refresh-interval")
+            .map(parse_duration_with_unit)
+            .context("'realtime-refresh-interval' should have default")??;
+        let target_split_num_docs = if matches.is_present("target-split-num-docs") {
+            Some(value_t!(matches, "target-split-num-docs", u64)?)
+        } else {
+            None
+        };
+        let routing_rules = matches
+            .values_of("route")
+            .map(|values| {
+                values
+                    .map(RoutingRule::from_str)
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let source_shards = if matches.is_present("source-shards") {
+            let source_shards = value_t!(matches, "source-shards", usize)?;
+            if source_shards == 0 {
+                bail!("The number of source shards must be positive.");
+            }
+            // The file-backed metastores cannot share the leases of the shards between processes.
+            if QuickwitUri::from_well_formed(&metastore_uri).protocol() != "postgres" {
+                bail!("Sharding a source with `--source-shards` requires a PostgreSQL metastore.");
+            }
+            Some(source_shards)
+        } else {
+            None
+        };
 
         Ok(CliCommand::Index(IndexDataArgs {
             index_id,
@@ -148,16 +297,30 @@ impl CliCommand {
             source_config_path,
             temp_dir,
             heap_size,
+            disk_guardrails: DiskGuardrails {
+                max_disk_usage,
+                min_free_disk_space,
+            },
+            doc_size_limit: DocSizeLimit {
+                max_doc_size,
+                oversized_doc_policy,
+            },
             metastore_uri,
             overwrite,
+            no_progress,
+            realtime_grpc_addr,
+            realtime_refresh_interval,
+            target_split_num_docs,
+            routing_rules,
+            source_shards,
         }))
     }
 
     fn parse_search_args(matches: &ArgMatches) -> anyhow::Result<Self> {
         let metastore_uri = matches
             .value_of("metastore-uri")
-            .map(|metastore_uri_str| metastore_uri_str.to_string())
-            .context("'metastore-uri' is a required arg")?;
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
         let index_id = matches
             .value_of("index-id")
             .context("'index-id' is a required arg")?
@@ -198,11 +361,59 @@ impl CliCommand {
         }))
     }
 
+    fn parse_tail_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let metastore_uri = matches
+            .value_of("metastore-uri")
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let index_id = matches
+            .value_of("index-id")
+            .context("'index-id' is a required arg")?
+            .to_string();
+        let query = matches
+            .value_of("query")
+            .context("'query' should have a default value")?
+            .to_string();
+        let search_fields = matches
+            .values_of("search-fields")
+            .map(|values| values.map(|value| value.to_string()).collect());
+        let poll_interval = matches
+            .value_of("poll-interval")
+            .map(parse_duration_with_unit)
+            .context("'poll-interval' should have a default value")??;
+        if poll_interval.as_secs() == 0 {
+            bail!("'poll-interval' should be at least 1 second");
+        }
+        let max_hits = value_t!(matches, "max-hits", usize)?;
+
+        Ok(CliCommand::Tail(TailIndexArgs {
+            metastore_uri,
+            index_id,
+            query,
+            search_fields,
+            poll_interval,
+            max_hits,
+        }))
+    }
+
+    fn parse_query_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let metastore_uri = matches
+            .value_of("metastore-uri")
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let sql = matches
+            .value_of("sql")
+            .context("'sql' is a required arg")?
+            .to_string();
+
+        Ok(CliCommand::Query(QueryIndexArgs { metastore_uri, sql }))
+    }
+
     fn parse_serve_args(matches: &ArgMatches) -> anyhow::Result<Self> {
         let metastore_uri = matches
             .value_of("metastore-uri")
-            .map(|metastore_uri_str| metastore_uri_str.to_string())
-            .context("'metastore-uri' is a required arg")?;
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
         let host = matches
             .value_of("host")
             .context("'host' has a default value")?
@@ -225,20 +436,248 @@ impl CliCommand {
                 }
             }
         }
+        let gc_index_ids = matches
+            .values_of("gc-index-id")
+            .map(|values| values.map(|value| value.to_string()).collect())
+            .unwrap_or_default();
+        let gc_interval = matches
+            .value_of("gc-interval")
+            .map(parse_duration_with_unit)
+            .context("'gc-interval' should have default")??;
+        let gc_grace_period = matches
+            .value_of("gc-grace-period")
+            .map(parse_duration_with_unit)
+            .context("'gc-grace-period' should have default")??;
+        let gc_retention_period = matches
+            .value_of("gc-retention-period")
+            .map(parse_duration_with_unit)
+            .transpose()?;
+        let verify_index_ids = matches
+            .values_of("verify-index-id")
+            .map(|values| values.map(|value| value.to_string()).collect())
+            .unwrap_or_default();
+        let verify_interval = matches
+            .value_of("verify-interval")
+            .map(parse_duration_with_unit)
+            .context("'verify-interval' should have default")??;
+        let verify_num_splits = value_t!(matches, "verify-num-splits", usize)?;
+        let max_qps = if matches.is_present("quota-max-qps") {
+            Some(value_t!(matches, "quota-max-qps", u32)?)
+        } else {
+            None
+        };
+        let max_concurrent_searches = if matches.is_present("quota-max-concurrent-searches") {
+            Some(value_t!(matches, "quota-max-concurrent-searches", u32)?)
+        } else {
+            None
+        };
+        let quota_config = QuotaConfig {
+            max_qps,
+            max_concurrent_searches,
+        };
+        let tree_aggregation_min_nodes = value_t!(matches, "tree-aggregation-min-nodes", usize)?;
+        let split_quarantine_threshold = value_t!(matches, "split-quarantine-threshold", usize)?;
+        let leaf_search_hedging = if matches.is_present("hedge-leaf-search-percentile") {
+            let percentile = value_t!(matches, "hedge-leaf-search-percentile", f64)?;
+            if !(0.0..=100.0).contains(&percentile) {
+                bail!(
+                    "'hedge-leaf-search-percentile' should be between 0 and 100, got `{}`.",
+                    percentile
+                );
+            }
+            Some(HedgingParams {
+                percentile: percentile / 100.0,
+                ..Default::default()
+            })
+        } else {
+            None
+        };
+        let node_attributes = matches
+            .values_of("node-attribute")
+            .map(|values| {
+                values
+                    .map(parse_node_attribute)
+                    .collect::<anyhow::Result<NodeAttributes>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let placement_rules = matches
+            .values_of("placement-rule")
+            .map(|values| {
+                values
+                    .map(str::parse::<PlacementRule>)
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .transpose()?
+            .map(PlacementRules::new)
+            .unwrap_or_default();
+        let fast_field_pinning =
+            if let Some(time_window_str) = matches.value_of("pin-fast-fields-time-window") {
+                let time_window = parse_duration_with_unit(time_window_str)?;
+                let budget_in_bytes = matches
+                    .value_of("pin-fast-fields-budget")
+                    .map(Byte::from_str)
+                    .context("'pin-fast-fields-budget' should have default")??
+                    .get_bytes() as usize;
+                Some(FastFieldPinningParams {
+                    time_window,
+                    budget_in_bytes,
+                })
+            } else {
+                None
+            };
+        let audit_log = if let Some(index_uri_str) = matches.value_of("audit-log-index-uri") {
+            let index_uri = parse_uri(index_uri_str)?;
+            let flush_interval = matches
+                .value_of("audit-log-flush-interval")
+                .map(parse_duration_with_unit)
+                .context("'audit-log-flush-interval' should have default")??;
+            Some(AuditLogConfig {
+                index_uri,
+                flush_interval,
+            })
+        } else {
+            None
+        };
+        let mut search_thread_pool = SearchThreadPoolParams::default();
+        if matches.is_present("search-threads") {
+            search_thread_pool.num_threads = value_t!(matches, "search-threads", usize)?;
+            search_thread_pool.max_threads_per_query = (search_thread_pool.num_threads + 1) / 2;
+        }
+        if matches.is_present("search-threads-per-query") {
+            search_thread_pool.max_threads_per_query =
+                value_t!(matches, "search-threads-per-query", usize)?;
+        }
+        let whole_split_download_max_num_bytes = matches
+            .value_of("whole-split-download-max-size")
+            .map(Byte::from_str)
+            .transpose()?
+            .map(|max_size| max_size.get_bytes() as u64);
+        let storage_timeout_policy = StorageTimeoutPolicy {
+            min_timeout: matches
+                .value_of("storage-timeout")
+                .map(parse_duration_with_unit)
+                .context("'storage-timeout' should have default")??,
+            min_throughput_bytes_per_sec: matches
+                .value_of("storage-min-throughput")
+                .map(Byte::from_str)
+                .context("'storage-min-throughput' should have default")??
+                .get_bytes() as u64,
+        };
+        let s3_http_config = S3HttpConfig {
+            max_idle_connections_per_host: value_t!(
+                matches,
+                "s3-max-idle-connections-per-host",
+                usize
+            )?,
+            pool_idle_timeout: matches
+                .value_of("s3-pool-idle-timeout")
+                .map(parse_duration_with_unit)
+                .context("'s3-pool-idle-timeout' should have default")??,
+            connect_timeout: matches
+                .value_of("s3-connect-timeout")
+                .map(parse_duration_with_unit)
+                .context("'s3-connect-timeout' should have default")??,
+            read_timeout_opt: matches
+                .value_of("s3-read-timeout")
+                .map(parse_duration_with_unit)
+                .transpose()?,
+            http2: matches.is_present("s3-http2"),
+        };
+        let standby = matches.is_present("standby");
+        let api_keys = matches
+            .values_of("api-key")
+            .map(|values| {
+                values
+                    .map(str::parse::<ApiKey>)
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let api_key_split_limits = matches
+            .values_of("api-key-split-limit")
+            .map(|values| {
+                values
+                    .map(str::parse::<ApiKeySplitLimit>)
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        for api_key_split_limit in &api_key_split_limits {
+            if !api_keys
+                .iter()
+                .any(|api_key| api_key.token == api_key_split_limit.token)
+            {
+                bail!(
+                    "The split limit of the API key `{}` does not match any `--api-key`.",
+                    api_key_split_limit.token
+                );
+            }
+        }
+        let realtime_indexer_addrs = matches
+            .values_of("realtime-indexer")
+            .map(|values| {
+                values
+                    .map(socket_addr_from_str)
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let click_house_sinks = matches
+            .values_of("click-house-sink")
+            .map(|values| {
+                values
+                    .map(str::parse::<ClickHouseSinkConfig>)
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        for (sink_ord, click_house_sink) in click_house_sinks.iter().enumerate() {
+            if click_house_sinks[..sink_ord]
+                .iter()
+                .any(|other_sink| other_sink.name == click_house_sink.name)
+            {
+                bail!("Duplicated ClickHouse sink `{}`.", click_house_sink.name);
+            }
+        }
 
         Ok(CliCommand::Serve(ServeArgs {
             rest_socket_addr,
             host_key_path,
             peer_socket_addrs,
             metastore_uri,
+            gc_index_ids,
+            gc_interval,
+            gc_grace_period,
+            gc_retention_period,
+            verify_index_ids,
+            verify_interval,
+            verify_num_splits,
+            quota_config,
+            leaf_search_hedging,
+            tree_aggregation_min_nodes,
+            split_quarantine_threshold,
+            node_attributes,
+            placement_rules,
+            fast_field_pinning,
+            audit_log,
+            search_thread_pool,
+            whole_split_download_max_num_bytes,
+            storage_timeout_policy,
+            s3_http_config,
+            standby,
+            api_keys,
+            api_key_split_limits,
+            realtime_indexer_addrs,
+            click_house_sinks,
         }))
     }
 
     fn parse_delete_args(matches: &ArgMatches) -> anyhow::Result<Self> {
         let metastore_uri = matches
             .value_of("metastore-uri")
-            .map(|metastore_uri_str| metastore_uri_str.to_string())
-            .context("'metastore-uri' is a required arg")?;
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
         let index_id = matches
             .value_of("index-id")
             .context("'index-id' is a required arg")?
@@ -252,11 +691,322 @@ impl CliCommand {
         }))
     }
 
+    fn parse_describe_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let metastore_uri = matches
+            .value_of("metastore-uri")
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let index_id = matches
+            .value_of("index-id")
+            .context("'index-id' is a required arg")?
+            .to_string();
+
+        Ok(CliCommand::Describe(DescribeIndexArgs {
+            metastore_uri,
+            index_id,
+        }))
+    }
+
+    fn parse_update_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let metastore_uri = matches
+            .value_of("metastore-uri")
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let index_id = matches
+            .value_of("index-id")
+            .context("'index-id' is a required arg")?
+            .to_string();
+        let description = matches.value_of("description").map(str::to_string);
+        let labels = matches
+            .values_of("label")
+            .map(|values| values.map(parse_label).collect::<anyhow::Result<Vec<_>>>())
+            .transpose()?
+            .unwrap_or_default();
+        let remove_labels: Vec<String> = matches
+            .values_of("remove-label")
+            .map(|values| values.map(str::to_string).collect())
+            .unwrap_or_default();
+        let index_config_path = matches.value_of("index-config-path").map(PathBuf::from);
+        if description.is_none()
+            && labels.is_empty()
+            && remove_labels.is_empty()
+            && index_config_path.is_none()
+        {
+            bail!(
+                "Nothing to update, pass `--description`, `--label`, `--remove-label` or \
+                 `--index-config-path`."
+            );
+        }
+
+        Ok(CliCommand::Update(UpdateIndexArgs {
+            metastore_uri,
+            index_id,
+            description,
+            labels,
+            remove_labels,
+            index_config_path,
+        }))
+    }
+
+    fn parse_split_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let (subcommand, submatches_opt) = matches.subcommand();
+        let submatches =
+            submatches_opt.ok_or_else(|| anyhow::anyhow!("Failed to parse sub-matches."))?;
+
+        match subcommand {
+            "delete" => Self::parse_delete_splits_args(submatches),
+            "verify" => Self::parse_verify_splits_args(submatches),
+            _ => bail!("Subcommand 'split {}' is not implemented", subcommand),
+        }
+    }
+
+    fn parse_delete_splits_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let metastore_uri = matches
+            .value_of("metastore-uri")
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let index_id = matches
+            .value_of("index-id")
+            .context("'index-id' is a required arg")?
+            .to_string();
+        let time_range = if matches.is_present("quarantined") {
+            None
+        } else {
+            let start_timestamp = value_t!(matches, "from", i64)?;
+            let end_timestamp = value_t!(matches, "to", i64)?;
+            if start_timestamp >= end_timestamp {
+                bail!(
+                    "'from' ({}) must be before 'to' ({}).",
+                    start_timestamp,
+                    end_timestamp
+                );
+            }
+            Some(start_timestamp..end_timestamp)
+        };
+        let dry_run = matches.is_present("dry-run");
+        let gc = matches.is_present("gc");
+
+        Ok(CliCommand::DeleteSplits(DeleteSplitsArgs {
+            metastore_uri,
+            index_id,
+            time_range,
+            dry_run,
+            gc,
+        }))
+    }
+
+    fn parse_verify_splits_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let metastore_uri = matches
+            .value_of("metastore-uri")
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let index_id = matches
+            .value_of("index-id")
+            .context("'index-id' is a required arg")?
+            .to_string();
+        let max_num_splits = value_t!(matches, "max-num-splits", usize)?;
+        let quarantined = matches.is_present("quarantined");
+
+        Ok(CliCommand::VerifySplits(VerifySplitsArgs {
+            metastore_uri,
+            index_id,
+            max_num_splits,
+            quarantined,
+        }))
+    }
+
+    fn parse_mirror_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let metastore_uri = matches
+            .value_of("metastore-uri")
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let index_id = matches
+            .value_of("index-id")
+            .context("'index-id' is a required arg")?
+            .to_string();
+        let dry_run = matches.is_present("dry-run");
+
+        Ok(CliCommand::Mirror(MirrorIndexArgs {
+            metastore_uri,
+            index_id,
+            dry_run,
+        }))
+    }
+
+    fn parse_clone_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let metastore_uri = matches
+            .value_of("metastore-uri")
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let index_id = matches
+            .value_of("index-id")
+            .context("'index-id' is a required arg")?
+            .to_string();
+        let target_index_id = matches
+            .value_of("target-index-id")
+            .context("'target-index-id' is a required arg")?
+            .to_string();
+        let target_index_uri = matches
+            .value_of("target-uri")
+            .map(parse_uri)
+            .context("'target-uri' is a required arg")??;
+        let target_metastore_uri = matches
+            .value_of("target-metastore-uri")
+            .map(parse_uri)
+            .transpose()?
+            .unwrap_or_else(|| metastore_uri.clone());
+        let start_timestamp = if matches.is_present("from") {
+            Some(value_t!(matches, "from", i64)?)
+        } else {
+            None
+        };
+        let end_timestamp = if matches.is_present("to") {
+            Some(value_t!(matches, "to", i64)?)
+        } else {
+            None
+        };
+        if let (Some(start_timestamp), Some(end_timestamp)) = (start_timestamp, end_timestamp) {
+            if start_timestamp >= end_timestamp {
+                bail!(
+                    "'from' ({}) must be before 'to' ({}).",
+                    start_timestamp,
+                    end_timestamp
+                );
+            }
+        }
+        if target_metastore_uri == metastore_uri && target_index_id == index_id {
+            bail!(
+                "The clone must have another id than the cloned index `{}`.",
+                index_id
+            );
+        }
+
+        Ok(CliCommand::Clone(CloneIndexArgs {
+            metastore_uri,
+            index_id,
+            target_metastore_uri,
+            target_index_id,
+            target_index_uri,
+            start_timestamp,
+            end_timestamp,
+        }))
+    }
+
+    fn parse_attach_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let metastore_uri = matches
+            .value_of("metastore-uri")
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let index_uri = matches
+            .value_of("index-uri")
+            .map(parse_uri)
+            .context("'index-uri' is a required arg")??;
+
+        Ok(CliCommand::Attach(AttachIndexArgs {
+            metastore_uri,
+            index_uri,
+        }))
+    }
+
+    fn parse_companion_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let (subcommand, submatches_opt) = matches.subcommand();
+        let submatches =
+            submatches_opt.ok_or_else(|| anyhow::anyhow!("Failed to parse sub-matches."))?;
+        let metastore_uri = submatches
+            .value_of("metastore-uri")
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let index_id = submatches
+            .value_of("index-id")
+            .context("'index-id' is a required arg")?
+            .to_string();
+        if subcommand == "list" {
+            return Ok(CliCommand::ListCompanionFiles(ListCompanionFilesArgs {
+                metastore_uri,
+                index_id,
+            }));
+        }
+        let name = submatches
+            .value_of("name")
+            .context("'name' is a required arg")?
+            .to_string();
+
+        match subcommand {
+            "put" => {
+                let file_path = submatches
+                    .value_of("file-path")
+                    .map(PathBuf::from)
+                    .context("'file-path' is a required arg")?;
+                Ok(CliCommand::PutCompanionFile(PutCompanionFileArgs {
+                    metastore_uri,
+                    index_id,
+                    name,
+                    file_path,
+                }))
+            }
+            "get" => {
+                let version = if submatches.is_present("version") {
+                    Some(value_t!(submatches, "version", u64)?)
+                } else {
+                    None
+                };
+                Ok(CliCommand::GetCompanionFile(GetCompanionFileArgs {
+                    metastore_uri,
+                    index_id,
+                    name,
+                    version,
+                    output_path: submatches.value_of("output-path").map(PathBuf::from),
+                }))
+            }
+            "delete" => Ok(CliCommand::DeleteCompanionFile(DeleteCompanionFileArgs {
+                metastore_uri,
+                index_id,
+                name,
+            })),
+            _ => bail!("Subcommand 'companion {}' is not implemented", subcommand),
+        }
+    }
+
+    fn parse_checkpoint_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let (subcommand, submatches_opt) = matches.subcommand();
+        let submatches =
+            submatches_opt.ok_or_else(|| anyhow::anyhow!("Failed to parse sub-matches."))?;
+        let metastore_uri = submatches
+            .value_of("metastore-uri")
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
+        let index_id = submatches
+            .value_of("index-id")
+            .context("'index-id' is a required arg")?
+            .to_string();
+
+        match subcommand {
+            "show" => Ok(CliCommand::ShowCheckpoint(ShowCheckpointArgs {
+                metastore_uri,
+                index_id,
+            })),
+            "set" => {
+                let positions = submatches
+                    .values_of("position")
+                    .context("'position' is a required arg")?
+                    .map(parse_checkpoint_position)
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                Ok(CliCommand::SetCheckpoint(SetCheckpointArgs {
+                    metastore_uri,
+                    index_id,
+                    positions,
+                    yes: submatches.is_present("yes"),
+                }))
+            }
+            _ => bail!("Subcommand 'checkpoint {}' is not implemented", subcommand),
+        }
+    }
+
     fn parse_garbage_collect_args(matches: &ArgMatches) -> anyhow::Result<Self> {
         let metastore_uri = matches
             .value_of("metastore-uri")
-            .map(|metastore_uri_str| metastore_uri_str.to_string())
-            .context("'metastore-uri' is a required arg")?;
+            .map(parse_uri)
+            .context("'metastore-uri' is a required arg")??;
         let index_id = matches
             .value_of("index-id")
             .context("'index-id' is a required arg")?
@@ -266,14 +1016,82 @@ impl CliCommand {
             .map(parse_duration_with_unit)
             .context("'grace-period' should have default")??;
         let dry_run = matches.is_present("dry-run");
+        let no_progress = matches.is_present("no-progress");
 
         Ok(CliCommand::GarbageCollect(GarbageCollectIndexArgs {
             index_id,
             grace_period,
             metastore_uri,
             dry_run,
+            no_progress,
         }))
     }
+
+    fn parse_bench_args(matches: &ArgMatches) -> anyhow::Result<Self> {
+        let (subcommand, submatches_opt) = matches.subcommand();
+        let submatches =
+            submatches_opt.ok_or_else(|| anyhow::anyhow!("Failed to parse sub-matches."))?;
+
+        let bench_args = match subcommand {
+            "generate" => BenchArgs::Generate(GenerateDocsArgs {
+                metastore_uri: submatches
+                    .value_of("metastore-uri")
+                    .map(parse_uri)
+                    .context("'metastore-uri' is a required arg")??,
+                index_id: submatches
+                    .value_of("index-id")
+                    .context("'index-id' is a required arg")?
+                    .to_string(),
+                num_docs: value_t!(submatches, "num-docs", usize)?,
+                seed: value_t!(submatches, "seed", u64)?,
+                output_path: submatches.value_of("output-path").map(PathBuf::from),
+            }),
+            "index" => {
+                let heap_size_str = submatches
+                    .value_of("heap-size")
+                    .context("'heap-size' should have default")?;
+                BenchArgs::Index(BenchIndexArgs {
+                    metastore_uri: submatches
+                        .value_of("metastore-uri")
+                        .map(parse_uri)
+                        .context("'metastore-uri' is a required arg")??,
+                    index_id: submatches
+                        .value_of("index-id")
+                        .context("'index-id' is a required arg")?
+                        .to_string(),
+                    num_docs: value_t!(submatches, "num-docs", usize)?,
+                    seed: value_t!(submatches, "seed", u64)?,
+                    parallelism: value_t!(submatches, "parallelism", usize)?,
+                    heap_size: Byte::from_str(heap_size_str)?,
+                    report_path: submatches.value_of("report-path").map(PathBuf::from),
+                })
+            }
+            "search" => BenchArgs::Search(BenchSearchArgs {
+                endpoint: submatches
+                    .value_of("endpoint")
+                    .context("'endpoint' is a required arg")?
+                    .to_string(),
+                index_id: submatches
+                    .value_of("index-id")
+                    .context("'index-id' is a required arg")?
+                    .to_string(),
+                query_file_path: submatches
+                    .value_of("query-file")
+                    .map(PathBuf::from)
+                    .context("'query-file' is a required arg")?,
+                concurrency: value_t!(submatches, "concurrency", usize)?,
+                num_iterations: value_t!(submatches, "num-iterations", usize)?,
+                max_hits: value_t!(submatches, "max-hits", usize)?,
+                cache_policy: submatches
+                    .value_of("cache")
+                    .expect("`cache` has a default value.")
+                    .to_string(),
+                report_path: submatches.value_of("report-path").map(PathBuf::from),
+            }),
+            _ => bail!("Subcommand 'bench {}' is not implemented", subcommand),
+        };
+        Ok(CliCommand::Bench(bench_args))
+    }
 }
 
 fn setup_logging_and_tracing(level: Level) -> anyhow::Result<()> {
@@ -309,31 +1127,50 @@ async fn main() -> anyhow::Result<()> {
         .setting(AppSettings::ArgRequiredElseHelp)
         .version(env!("CARGO_PKG_VERSION"))
         .about(about_text.as_str());
-    let matches = app.get_matches();
+    let matches = match app.get_matches_safe() {
+        Ok(matches) => matches,
+        // The help and the version are not errors.
+        Err(err) if !err.use_stderr() => err.exit(),
+        Err(err) => {
+            eprintln!("{}", err.message);
+            std::process::exit(ErrorClass::User.exit_code());
+        }
+    };
 
-    let command = match CliCommand::parse_cli_args(&matches) {
-        Ok(command) => command,
+    let (command, output_format, num_retries) = match parse_command_and_global_args(&matches) {
+        Ok(command_and_global_args) => command_and_global_args,
         Err(err) => {
             eprintln!("Failed to parse command arguments: {:?}", err);
-            std::process::exit(1);
+            std::process::exit(ErrorClass::User.exit_code());
         }
     };
 
     setup_logging_and_tracing(command.default_log_level())?;
 
-    let command_res = match command {
-        CliCommand::InspectSplit(args) => inspect_split_cli(args).await,
-        CliCommand::New(args) => create_index_cli(args).await,
-        CliCommand::Index(args) => index_data_cli(args).await,
-        CliCommand::Search(args) => search_index_cli(args).await,
-        CliCommand::Serve(args) => serve_cli(args).await,
-        CliCommand::GarbageCollect(args) => garbage_collect_index_cli(args).await,
-        CliCommand::Delete(args) => delete_index_cli(args).await,
-    };
+    let is_retryable = command.is_retryable();
+    let mut command_res = run_command(command, output_format).await;
+    let mut retry_delay = INITIAL_RETRY_DELAY;
+    for _ in 0..num_retries {
+        match &command_res {
+            Err(err) if is_retryable && ErrorClass::of_error(err) == ErrorClass::Transient => {
+                eprintln!(
+                    "Command failed with a transient error, retrying in {}s: {:?}",
+                    retry_delay.as_secs(),
+                    err
+                );
+            }
+            _ => break,
+        }
+        tokio::time::sleep(retry_delay).await;
+        retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+        // The arguments were successfully parsed once already.
+        let command = CliCommand::parse_cli_args(&matches)?;
+        command_res = run_command(command, output_format).await;
+    }
 
     let return_code: i32 = if let Err(err) = command_res {
         eprintln!("Command failed: {:?}", err);
-        1
+        ErrorClass::of_error(&err).exit_code()
     } else {
         0
     };
@@ -346,6 +1183,56 @@ async fn main() -> anyhow::Result<()> {
     std::process::exit(return_code);
 }
 
+fn parse_command_and_global_args(
+    matches: &ArgMatches,
+) -> anyhow::Result<(CliCommand, OutputFormat, usize)> {
+    let command = CliCommand::parse_cli_args(matches)?;
+    let output_format = parse_output_format(matches)?;
+    let num_retries = parse_num_retries(matches)?;
+    Ok((command, output_format, num_retries))
+}
+
+/// Runs `command`. A panic is returned as an internal error.
+async fn run_command(command: CliCommand, output_format: OutputFormat) -> anyhow::Result<()> {
+    let command_future = async move {
+        match command {
+            CliCommand::InspectSplit(args) => inspect_split_cli(args, output_format).await,
+            CliCommand::New(args) => create_index_cli(args, output_format).await,
+            CliCommand::Index(args) => index_data_cli(args, output_format).await,
+            CliCommand::Search(args) => search_index_cli(args, output_format).await,
+            CliCommand::Tail(args) => tail_index_cli(args, output_format).await,
+            CliCommand::Query(args) => query_index_cli(args, output_format).await,
+            CliCommand::Serve(args) => serve_cli(args).await,
+            CliCommand::GarbageCollect(args) => {
+                garbage_collect_index_cli(args, output_format).await
+            }
+            CliCommand::Delete(args) => delete_index_cli(args, output_format).await,
+            CliCommand::Describe(args) => describe_index_cli(args, output_format).await,
+            CliCommand::Update(args) => update_index_cli(args, output_format).await,
+            CliCommand::DeleteSplits(args) => delete_splits_cli(args, output_format).await,
+            CliCommand::VerifySplits(args) => verify_splits_cli(args, output_format).await,
+            CliCommand::Mirror(args) => mirror_index_cli(args, output_format).await,
+            CliCommand::Clone(args) => clone_index_cli(args, output_format).await,
+            CliCommand::Attach(args) => attach_index_cli(args, output_format).await,
+            CliCommand::PutCompanionFile(args) => put_companion_file_cli(args, output_format).await,
+            CliCommand::GetCompanionFile(args) => get_companion_file_cli(args, output_format).await,
+            CliCommand::ListCompanionFiles(args) => {
+                list_companion_files_cli(args, output_format).await
+            }
+            CliCommand::DeleteCompanionFile(args) => {
+                delete_companion_file_cli(args, output_format).await
+            }
+            CliCommand::ShowCheckpoint(args) => show_checkpoint_cli(args, output_format).await,
+            CliCommand::SetCheckpoint(args) => set_checkpoint_cli(args, output_format).await,
+            CliCommand::Bench(args) => bench_cli(args, output_format).await,
+        }
+    };
+    AssertUnwindSafe(command_future)
+        .catch_unwind()
+        .await
+        .unwrap_or_else(|_| bail!("The command panicked."))
+}
+
 /// Return the about text with telemetry info.
 fn about_text() -> String {
     let mut about_text = format!(
@@ -359,18 +1246,76 @@ fn about_text() -> String {
     about_text
 }
 
-/// Parse duration with unit.
-/// examples: 1s 2m 3h 5d
-pub fn parse_duration_with_unit(duration: &str) -> anyhow::Result<Duration> {
-    let mut value = "".to_string();
-    let mut unit = "".to_string();
-    for character in duration.chars() {
-        if character.is_numeric() {
-            value.push(character);
-        } else {
-            unit.push(character);
-        }
-    }
+/// Returns the value of a global arg, which can be passed before or after the subcommand.
+fn global_value_of<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    matches
+        .subcommand()
+        .1
+        .and_then(|submatches| submatches.value_of(name))
+        .or_else(|| matches.value_of(name))
+}
+
+fn parse_output_format(matches: &ArgMatches) -> anyhow::Result<OutputFormat> {
+    global_value_of(matches, "output")
+        .map(OutputFormat::from_str)
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+fn parse_num_retries(matches: &ArgMatches) -> anyhow::Result<usize> {
+    global_value_of(matches, "retry")
+        .map(|num_retries_str| {
+            num_retries_str
+                .parse::<usize>()
+                .with_context(|| format!("Invalid number of retries `{}`.", num_retries_str))
+        })
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+/// Parses and normalizes a storage, metastore or index URI.
+///
+/// URIs without a protocol, like `./indexes`, are rejected.
+pub fn parse_uri(uri: &str) -> anyhow::Result<String> {
+    let uri = QuickwitUri::from_str(uri)?;
+    Ok(uri.to_string())
+}
+
+/// Parses a checkpoint position of the form `<partition>=<position>`.
+fn parse_checkpoint_position(position: &str) -> anyhow::Result<(String, String)> {
+    match position.rsplit_once('=') {
+        Some((partition_id, position)) if !partition_id.is_empty() => {
+            Ok((partition_id.to_string(), position.to_string()))
+        }
+        _ => bail!(
+            "Invalid checkpoint position `{}`, expected `<partition>=<position>`.",
+            position
+        ),
+    }
+}
+
+/// Parses an index label of the form `<key>=<value>`.
+fn parse_label(label: &str) -> anyhow::Result<(String, String)> {
+    match label.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => bail!("Invalid label `{}`, expected `<key>=<value>`.", label),
+    }
+}
+
+/// Parse duration with unit.
+/// examples: 1s 2m 3h 5d
+pub fn parse_duration_with_unit(duration: &str) -> anyhow::Result<Duration> {
+    let mut value = "".to_string();
+    let mut unit = "".to_string();
+    for character in duration.chars() {
+        if character.is_numeric() {
+            value.push(character);
+        } else {
+            unit.push(character);
+        }
+    }
 
     match value.parse::<u64>() {
         Ok(value) => match unit.as_str() {
@@ -396,8 +1341,9 @@ mod tests {
 
     use super::*;
     use crate::{
-        parse_duration_with_unit, CliCommand, CreateIndexArgs, DeleteIndexArgs,
-        GarbageCollectIndexArgs, IndexDataArgs, SearchIndexArgs,
+        parse_duration_with_unit, parse_num_retries, parse_output_format, AttachIndexArgs,
+        CliCommand, CloneIndexArgs, CreateIndexArgs, DeleteIndexArgs, GarbageCollectIndexArgs,
+        IndexDataArgs, MirrorIndexArgs, OutputFormat, SearchIndexArgs,
     };
 
     #[test]
@@ -440,7 +1386,10 @@ mod tests {
             CreateIndexArgs::new(
                 "file:///indexes".to_string(),
                 "file:///indexes/wikipedia".to_string(),
+                None,
                 path.to_path_buf(),
+                None,
+                BTreeMap::new(),
                 false,
             )
             .unwrap(),
@@ -463,13 +1412,74 @@ mod tests {
             CreateIndexArgs::new(
                 "file:///indexes".to_string(),
                 "file:///indexes/wikipedia".to_string(),
+                None,
                 path.to_path_buf(),
+                None,
+                BTreeMap::new(),
                 true,
             )
             .unwrap(),
         );
         assert_eq!(command.unwrap(), expected_cmd);
 
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "new",
+            "--index-uri",
+            "file:///indexes/wikipedia",
+            "--index-config-path",
+            &path_str,
+            "--metastore-uri",
+            "file:///indexes",
+            "--description",
+            "English Wikipedia",
+            "--label",
+            "team=search",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        let expected_cmd = CliCommand::New(
+            CreateIndexArgs::new(
+                "file:///indexes".to_string(),
+                "file:///indexes/wikipedia".to_string(),
+                None,
+                path.to_path_buf(),
+                Some("English Wikipedia".to_string()),
+                vec![("team".to_string(), "search".to_string())]
+                    .into_iter()
+                    .collect(),
+                false,
+            )
+            .unwrap(),
+        );
+        assert_eq!(command.unwrap(), expected_cmd);
+
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "new",
+            "--index-uri",
+            "s3://quickwit-indexes/wikipedia",
+            "--mirror-index-uri",
+            "s3://quickwit-indexes-mirror/wikipedia/",
+            "--index-config-path",
+            &path_str,
+            "--metastore-uri",
+            "file:///indexes",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        let expected_cmd = CliCommand::New(
+            CreateIndexArgs::new(
+                "file:///indexes".to_string(),
+                "s3://quickwit-indexes/wikipedia".to_string(),
+                Some("s3://quickwit-indexes-mirror/wikipedia".to_string()),
+                path.to_path_buf(),
+                None,
+                BTreeMap::new(),
+                false,
+            )
+            .unwrap(),
+        );
+        assert_eq!(command.unwrap(), expected_cmd);
+
         Ok(())
     }
 
@@ -493,11 +1503,27 @@ mod tests {
                 source_config_path: None,
                 temp_dir: None,
                 heap_size,
+                disk_guardrails: DiskGuardrails {
+                    max_disk_usage: None,
+                    min_free_disk_space: None,
+                },
+                doc_size_limit: DocSizeLimit {
+                    max_doc_size: None,
+                    oversized_doc_policy: OversizedDocPolicy::Reject,
+                },
                 metastore_uri,
                 overwrite: false,
+                no_progress: false,
+                realtime_grpc_addr: None,
+                realtime_refresh_interval,
+                target_split_num_docs: None,
+                routing_rules,
+                source_shards: None,
             })) if &index_id == "wikipedia"
                     && &metastore_uri == "file:///indexes"
                     && heap_size.get_bytes() == 2_000_000_000
+                    && realtime_refresh_interval == Duration::from_secs(1)
+                    && routing_rules.is_empty()
         ));
 
         let yaml = load_yaml!("cli.yaml");
@@ -512,9 +1538,30 @@ mod tests {
             "./tmp",
             "--heap-size",
             "4gib",
+            "--max-disk-usage",
+            "100GB",
+            "--min-free-disk-space",
+            "5GB",
+            "--max-doc-size",
+            "1MB",
+            "--oversized-doc-policy",
+            "truncate",
             "--metastore-uri",
-            "file:///indexes",
+            "postgres://quickwit@db/quickwit",
             "--overwrite",
+            "--no-progress",
+            "--realtime-grpc-listen-address",
+            "127.0.0.1:7290",
+            "--realtime-refresh-interval",
+            "2s",
+            "--target-split-num-docs",
+            "5000000",
+            "--route",
+            "service=api:api-logs",
+            "--route",
+            "level=error:errors",
+            "--source-shards",
+            "4",
         ])?;
         let command = CliCommand::parse_cli_args(&matches);
         assert!(matches!(
@@ -525,18 +1572,61 @@ mod tests {
                 source_config_path: Some(source_config_path),
                 temp_dir,
                 heap_size,
+                disk_guardrails: DiskGuardrails {
+                    max_disk_usage: Some(max_disk_usage),
+                    min_free_disk_space: Some(min_free_disk_space),
+                },
+                doc_size_limit: DocSizeLimit {
+                    max_doc_size: Some(max_doc_size),
+                    oversized_doc_policy: OversizedDocPolicy::Truncate,
+                },
                 metastore_uri,
                 overwrite: true,
+                no_progress: true,
+                realtime_grpc_addr: Some(realtime_grpc_addr),
+                realtime_refresh_interval,
+                target_split_num_docs: Some(5_000_000),
+                routing_rules,
+                source_shards: Some(4),
             })) if &index_id == "wikipedia"
                     && source_config_path == Path::new("/conf/source_config.json")
                     && temp_dir == Some(PathBuf::from("./tmp"))
-                    && &metastore_uri == "file:///indexes"
+                    && &metastore_uri == "postgres://quickwit@db/quickwit"
                     && heap_size.get_bytes() == 4_294_967_296
+                    && max_disk_usage.get_bytes() == 100_000_000_000
+                    && min_free_disk_space.get_bytes() == 5_000_000_000
+                    && max_doc_size.get_bytes() == 1_000_000
+                    && realtime_grpc_addr == socket_addr_from_str("127.0.0.1:7290").unwrap()
+                    && realtime_refresh_interval == Duration::from_secs(2)
+                    && routing_rules == vec![
+                        RoutingRule::from_str("service=api:api-logs").unwrap(),
+                        RoutingRule::from_str("level=error:errors").unwrap(),
+                    ]
         ));
 
         Ok(())
     }
 
+    #[test]
+    fn test_parse_index_args_source_shards_require_postgresql_metastore() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "index",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "s3://quickwit-indexes",
+            "--source-config-path",
+            "/conf/source_config.json",
+            "--source-shards",
+            "2",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        assert!(command.is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_source_config_path_and_input_path_args_are_mutually_exclusive() -> anyhow::Result<()> {
         let yaml = load_yaml!("cli.yaml");
@@ -632,143 +1722,1060 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_delete_args() -> anyhow::Result<()> {
+    fn test_parse_tail_args() -> anyhow::Result<()> {
         let yaml = load_yaml!("cli.yaml");
         let app = App::from(yaml).setting(AppSettings::NoBinaryName);
         let matches = app.get_matches_from_safe(vec![
-            "delete",
+            "tail",
             "--index-id",
             "wikipedia",
             "--metastore-uri",
             "file:///indexes",
         ])?;
-        let command = CliCommand::parse_cli_args(&matches);
-        assert!(matches!(
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
             command,
-            Ok(CliCommand::Delete(DeleteIndexArgs {
-                index_id,
-                metastore_uri,
-                dry_run: false
-            })) if &index_id == "wikipedia" && &metastore_uri == "file:///indexes"
-        ));
+            CliCommand::Tail(TailIndexArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+                query: "*".to_string(),
+                search_fields: None,
+                poll_interval: Duration::from_secs(2),
+                max_hits: 1000,
+            })
+        );
 
         let yaml = load_yaml!("cli.yaml");
         let app = App::from(yaml).setting(AppSettings::NoBinaryName);
         let matches = app.get_matches_from_safe(vec![
-            "delete",
+            "tail",
             "--index-id",
             "wikipedia",
             "--metastore-uri",
             "file:///indexes",
-            "--dry-run",
+            "--query",
+            "level:error",
+            "--search-fields",
+            "body",
+            "--poll-interval",
+            "1m",
+            "--max-hits",
+            "10",
         ])?;
-        let command = CliCommand::parse_cli_args(&matches);
-        assert!(matches!(
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
             command,
-            Ok(CliCommand::Delete(DeleteIndexArgs {
-                index_id,
-                metastore_uri,
-                dry_run: true
-            })) if &index_id == "wikipedia" && &metastore_uri == "file:///indexes"
-        ));
+            CliCommand::Tail(TailIndexArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+                query: "level:error".to_string(),
+                search_fields: Some(vec!["body".to_string()]),
+                poll_interval: Duration::from_secs(60),
+                max_hits: 10,
+            })
+        );
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "tail",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "file:///indexes",
+            "--poll-interval",
+            "0s",
+        ])?;
+        assert!(CliCommand::parse_cli_args(&matches).is_err());
         Ok(())
     }
 
     #[test]
-    fn test_parse_garbage_collect_args() -> anyhow::Result<()> {
+    fn test_parse_delete_splits_args() -> anyhow::Result<()> {
         let yaml = load_yaml!("cli.yaml");
         let app = App::from(yaml).setting(AppSettings::NoBinaryName);
         let matches = app.get_matches_from_safe(vec![
-            "gc",
+            "split",
+            "delete",
+            "--metastore-uri",
+            "file:///indexes",
             "--index-id",
             "wikipedia",
+            "--from",
+            "0",
+            "--to",
+            "86400",
+            "--gc",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
+            command,
+            CliCommand::DeleteSplits(DeleteSplitsArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+                time_range: Some(0..86400),
+                dry_run: false,
+                gc: true,
+            })
+        );
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "split",
+            "delete",
             "--metastore-uri",
             "file:///indexes",
+            "--index-id",
+            "wikipedia",
+            "--quarantined",
         ])?;
-        let command = CliCommand::parse_cli_args(&matches);
+        let command = CliCommand::parse_cli_args(&matches)?;
         assert!(matches!(
             command,
-            Ok(CliCommand::GarbageCollect(GarbageCollectIndexArgs {
-                index_id,
-                grace_period,
-                metastore_uri,
-                dry_run: false
-            })) if &index_id == "wikipedia" && grace_period == Duration::from_secs(60 * 60) && &metastore_uri == "file:///indexes"
+            CliCommand::DeleteSplits(DeleteSplitsArgs {
+                time_range: None,
+                ..
+            })
         ));
 
         let yaml = load_yaml!("cli.yaml");
         let app = App::from(yaml).setting(AppSettings::NoBinaryName);
         let matches = app.get_matches_from_safe(vec![
-            "gc",
+            "split",
+            "delete",
+            "--metastore-uri",
+            "file:///indexes",
             "--index-id",
             "wikipedia",
-            "--grace-period",
-            "5m",
+            "--from",
+            "10",
+            "--to",
+            "10",
+        ])?;
+        assert!(CliCommand::parse_cli_args(&matches).is_err());
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        assert!(app
+            .get_matches_from_safe(vec![
+                "split",
+                "delete",
+                "--metastore-uri",
+                "file:///indexes",
+                "--index-id",
+                "wikipedia",
+                "--from",
+                "0",
+            ])
+            .is_err());
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        assert!(app
+            .get_matches_from_safe(vec![
+                "split",
+                "delete",
+                "--metastore-uri",
+                "file:///indexes",
+                "--index-id",
+                "wikipedia",
+                "--from",
+                "0",
+                "--quarantined",
+            ])
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_verify_splits_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "split",
+            "verify",
             "--metastore-uri",
             "file:///indexes",
-            "--dry-run",
+            "--index-id",
+            "wikipedia",
         ])?;
-        let command = CliCommand::parse_cli_args(&matches);
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
+            command,
+            CliCommand::VerifySplits(VerifySplitsArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+                max_num_splits: 10,
+                quarantined: false,
+            })
+        );
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "split",
+            "verify",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+            "--max-num-splits",
+            "100",
+            "--quarantined",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
         assert!(matches!(
             command,
-            Ok(CliCommand::GarbageCollect(GarbageCollectIndexArgs {
-                index_id,
-                grace_period,
-                metastore_uri,
-                dry_run: true
-            })) if &index_id == "wikipedia" && grace_period == Duration::from_secs(5 * 60) && &metastore_uri == "file:///indexes"
+            CliCommand::VerifySplits(VerifySplitsArgs {
+                max_num_splits: 100,
+                quarantined: true,
+                ..
+            })
         ));
         Ok(())
     }
 
     #[test]
-    fn test_parse_serve_args() -> anyhow::Result<()> {
+    fn test_parse_companion_args() -> anyhow::Result<()> {
         let yaml = load_yaml!("cli.yaml");
         let app = App::from(yaml).setting(AppSettings::NoBinaryName);
         let matches = app.get_matches_from_safe(vec![
-            "serve",
+            "companion",
+            "put",
             "--metastore-uri",
             "file:///indexes",
-            "--host",
-            "127.0.0.1",
-            "--port",
-            "9090",
-            "--host-key-path-prefix",
-            "/etc/quickwit-host-key",
-            "--peer-seed",
-            "192.168.1.13:9090",
+            "--index-id",
+            "wikipedia",
+            "--name",
+            "synonyms.txt",
+            "--file-path",
+            "/tmp/synonyms.txt",
         ])?;
-        let command = CliCommand::parse_cli_args(&matches);
-        assert!(matches!(
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
             command,
-            Ok(CliCommand::Serve(ServeArgs {
-                rest_socket_addr, host_key_path, peer_socket_addrs, metastore_uri,
-            })) if rest_socket_addr == socket_addr_from_str("127.0.0.1:9090").unwrap() && host_key_path == Path::new("/etc/quickwit-host-key-127.0.0.1-9090").to_path_buf() && peer_socket_addrs == vec![socket_addr_from_str("192.168.1.13:9090").unwrap()] && &metastore_uri == "file:///indexes"
-        ));
+            CliCommand::PutCompanionFile(PutCompanionFileArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+                name: "synonyms.txt".to_string(),
+                file_path: PathBuf::from("/tmp/synonyms.txt"),
+            })
+        );
 
         let yaml = load_yaml!("cli.yaml");
         let app = App::from(yaml).setting(AppSettings::NoBinaryName);
         let matches = app.get_matches_from_safe(vec![
-            "serve",
+            "companion",
+            "get",
             "--metastore-uri",
             "file:///indexes",
-            "--host",
-            "127.0.0.1",
-            "--port",
-            "9090",
-            "--host-key-path-prefix",
-            "/etc/quickwit-host-key",
-            "--peer-seed",
-            "192.168.1.13:9090,192.168.1.14:9090",
+            "--index-id",
+            "wikipedia",
+            "--name",
+            "synonyms.txt",
+            "--version",
+            "2",
         ])?;
-        let command = CliCommand::parse_cli_args(&matches);
-        assert!(matches!(
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
             command,
-            Ok(CliCommand::Serve(ServeArgs {
-                rest_socket_addr, host_key_path, peer_socket_addrs, metastore_uri,
-            })) if rest_socket_addr == socket_addr_from_str("127.0.0.1:9090").unwrap() && host_key_path == Path::new("/etc/quickwit-host-key-127.0.0.1-9090").to_path_buf() && peer_socket_addrs == vec![socket_addr_from_str("192.168.1.13:9090").unwrap(), socket_addr_from_str("192.168.1.14:9090").unwrap()] && &metastore_uri == "file:///indexes"
-        ));
+            CliCommand::GetCompanionFile(GetCompanionFileArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+                name: "synonyms.txt".to_string(),
+                version: Some(2),
+                output_path: None,
+            })
+        );
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "companion",
+            "list",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
+            command,
+            CliCommand::ListCompanionFiles(ListCompanionFilesArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+            })
+        );
 
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        assert!(app
+            .get_matches_from_safe(vec![
+                "companion",
+                "delete",
+                "--metastore-uri",
+                "file:///indexes",
+                "--index-id",
+                "wikipedia",
+            ])
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_checkpoint_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "checkpoint",
+            "show",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
+            command,
+            CliCommand::ShowCheckpoint(ShowCheckpointArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+            })
+        );
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "checkpoint",
+            "set",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+            "--position",
+            "0=1234",
+            "--position",
+            "1=",
+            "--yes",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
+            command,
+            CliCommand::SetCheckpoint(SetCheckpointArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+                positions: vec![
+                    ("0".to_string(), "1234".to_string()),
+                    ("1".to_string(), "".to_string()),
+                ],
+                yes: true,
+            })
+        );
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "checkpoint",
+            "set",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+            "--position",
+            "1234",
+        ])?;
+        assert!(CliCommand::parse_cli_args(&matches).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_describe_and_update_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "describe",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
+            command,
+            CliCommand::Describe(DescribeIndexArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+            })
+        );
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "update",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+            "--description",
+            "English Wikipedia",
+            "--label",
+            "team=search",
+            "--label",
+            "env = prod",
+            "--remove-label",
+            "owner",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
+            command,
+            CliCommand::Update(UpdateIndexArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+                description: Some("English Wikipedia".to_string()),
+                labels: vec![
+                    ("team".to_string(), "search".to_string()),
+                    ("env".to_string(), "prod".to_string()),
+                ],
+                remove_labels: vec!["owner".to_string()],
+                index_config_path: None,
+            })
+        );
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "update",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+            "--index-config-path",
+            "/config.json",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
+            command,
+            CliCommand::Update(UpdateIndexArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+                description: None,
+                labels: Vec::new(),
+                remove_labels: Vec::new(),
+                index_config_path: Some(PathBuf::from("/config.json")),
+            })
+        );
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "update",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+        ])?;
+        assert!(CliCommand::parse_cli_args(&matches).is_err());
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "update",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+            "--label",
+            "=search",
+        ])?;
+        assert!(CliCommand::parse_cli_args(&matches).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_query_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "query",
+            "--metastore-uri",
+            "file:///indexes",
+            "--sql",
+            "SELECT COUNT(*) FROM wikipedia",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
+            command,
+            CliCommand::Query(QueryIndexArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                sql: "SELECT COUNT(*) FROM wikipedia".to_string(),
+            })
+        );
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        assert!(app
+            .get_matches_from_safe(vec!["query", "--metastore-uri", "file:///indexes"])
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_delete_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "delete",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "file:///indexes",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        assert!(matches!(
+            command,
+            Ok(CliCommand::Delete(DeleteIndexArgs {
+                index_id,
+                metastore_uri,
+                dry_run: false
+            })) if &index_id == "wikipedia" && &metastore_uri == "file:///indexes"
+        ));
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "delete",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "file:///indexes",
+            "--dry-run",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        assert!(matches!(
+            command,
+            Ok(CliCommand::Delete(DeleteIndexArgs {
+                index_id,
+                metastore_uri,
+                dry_run: true
+            })) if &index_id == "wikipedia" && &metastore_uri == "file:///indexes"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mirror_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "mirror",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "file:///indexes",
+            "--dry-run",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        assert!(matches!(
+            command,
+            Ok(CliCommand::Mirror(MirrorIndexArgs {
+                index_id,
+                metastore_uri,
+                dry_run: true
+            })) if &index_id == "wikipedia" && &metastore_uri == "file:///indexes"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_clone_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "clone",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+            "--target-index-id",
+            "wikipedia-2021",
+            "--target-uri",
+            "file:///shared/wikipedia-2021",
+            "--from",
+            "1609459200",
+            "--to",
+            "1640995200",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
+            command,
+            CliCommand::Clone(CloneIndexArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_id: "wikipedia".to_string(),
+                target_metastore_uri: "file:///indexes".to_string(),
+                target_index_id: "wikipedia-2021".to_string(),
+                target_index_uri: "file:///shared/wikipedia-2021".to_string(),
+                start_timestamp: Some(1_609_459_200),
+                end_timestamp: Some(1_640_995_200),
+            })
+        );
+
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "clone",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-id",
+            "wikipedia",
+            "--target-index-id",
+            "wikipedia",
+            "--target-uri",
+            "file:///shared/wikipedia",
+            "--target-metastore-uri",
+            "file:///shared",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert!(matches!(
+            command,
+            CliCommand::Clone(CloneIndexArgs {
+                target_metastore_uri,
+                start_timestamp: None,
+                end_timestamp: None,
+                ..
+            }) if target_metastore_uri == "file:///shared"
+        ));
+
+        for invalid_args in &[
+            vec!["--target-index-id", "wikipedia"],
+            vec![
+                "--target-index-id",
+                "wikipedia-2021",
+                "--from",
+                "10",
+                "--to",
+                "10",
+            ],
+        ] {
+            let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+            let mut args = vec![
+                "clone",
+                "--metastore-uri",
+                "file:///indexes",
+                "--index-id",
+                "wikipedia",
+                "--target-uri",
+                "file:///shared/wikipedia",
+            ];
+            args.extend(invalid_args.iter().copied());
+            let matches = app.get_matches_from_safe(args)?;
+            assert!(CliCommand::parse_cli_args(&matches).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_attach_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches_result =
+            app.get_matches_from_safe(vec!["attach", "--metastore-uri", "file:///indexes"]);
+        assert!(matches!(matches_result, Err(_)));
+
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "attach",
+            "--metastore-uri",
+            "file:///indexes",
+            "--index-uri",
+            "s3://other-bucket/wikipedia",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches)?;
+        assert_eq!(
+            command,
+            CliCommand::Attach(AttachIndexArgs {
+                metastore_uri: "file:///indexes".to_string(),
+                index_uri: "s3://other-bucket/wikipedia".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_uri_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "delete",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "S3://bucket/indexes/",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        assert!(matches!(
+            command,
+            Ok(CliCommand::Delete(DeleteIndexArgs {
+                metastore_uri,
+                ..
+            })) if &metastore_uri == "s3://bucket/indexes"
+        ));
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "delete",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "s3:/bucket/indexes",
+        ])?;
+        let error = CliCommand::parse_cli_args(&matches).unwrap_err();
+        assert!(error.to_string().contains("expected `://`"));
+
+        assert_eq!(parse_uri("file:///indexes/")?, "file:///indexes");
+        assert!(parse_uri("my-bucket/indexes").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_bench_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "bench",
+            "index",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "file:///indexes",
+            "--num-docs",
+            "1000",
+            "--parallelism",
+            "4",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        assert!(matches!(
+            command,
+            Ok(CliCommand::Bench(BenchArgs::Index(BenchIndexArgs {
+                index_id,
+                metastore_uri,
+                num_docs: 1000,
+                seed: 0,
+                parallelism: 4,
+                heap_size,
+                report_path: None,
+            }))) if &index_id == "wikipedia" && &metastore_uri == "file:///indexes" && heap_size.get_bytes() == 2_000_000_000
+        ));
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "bench",
+            "search",
+            "--endpoint",
+            "http://127.0.0.1:8080",
+            "--index-id",
+            "wikipedia",
+            "--query-file",
+            "/tmp/queries.txt",
+            "--concurrency",
+            "8",
+            "--cache",
+            "bypass",
+            "--report-path",
+            "/tmp/report.json",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        assert!(matches!(
+            command,
+            Ok(CliCommand::Bench(BenchArgs::Search(BenchSearchArgs {
+                endpoint,
+                index_id,
+                query_file_path,
+                concurrency: 8,
+                num_iterations: 1,
+                max_hits: 20,
+                cache_policy,
+                report_path: Some(report_path),
+            }))) if &endpoint == "http://127.0.0.1:8080" && &index_id == "wikipedia" && query_file_path == Path::new("/tmp/queries.txt") && &cache_policy == "bypass" && report_path == Path::new("/tmp/report.json")
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_garbage_collect_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "gc",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "file:///indexes",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        assert!(matches!(
+            command,
+            Ok(CliCommand::GarbageCollect(GarbageCollectIndexArgs {
+                index_id,
+                grace_period,
+                metastore_uri,
+                dry_run: false,
+                no_progress: false,
+            })) if &index_id == "wikipedia" && grace_period == Duration::from_secs(60 * 60) && &metastore_uri == "file:///indexes"
+        ));
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "gc",
+            "--index-id",
+            "wikipedia",
+            "--grace-period",
+            "5m",
+            "--metastore-uri",
+            "file:///indexes",
+            "--dry-run",
+            "--no-progress",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        assert!(matches!(
+            command,
+            Ok(CliCommand::GarbageCollect(GarbageCollectIndexArgs {
+                index_id,
+                grace_period,
+                metastore_uri,
+                dry_run: true,
+                no_progress: true,
+            })) if &index_id == "wikipedia" && grace_period == Duration::from_secs(5 * 60) && &metastore_uri == "file:///indexes"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_serve_args() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "serve",
+            "--metastore-uri",
+            "file:///indexes",
+            "--host",
+            "127.0.0.1",
+            "--port",
+            "9090",
+            "--host-key-path-prefix",
+            "/etc/quickwit-host-key",
+            "--peer-seed",
+            "192.168.1.13:9090",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        assert!(matches!(
+            command,
+            Ok(CliCommand::Serve(ServeArgs {
+                rest_socket_addr, host_key_path, peer_socket_addrs, metastore_uri, gc_index_ids, gc_interval, gc_grace_period, gc_retention_period: None, verify_index_ids, verify_interval, verify_num_splits: 10, quota_config, leaf_search_hedging: None, tree_aggregation_min_nodes: 50, split_quarantine_threshold: 3, node_attributes, placement_rules, fast_field_pinning: None, audit_log: None, search_thread_pool, whole_split_download_max_num_bytes: None, storage_timeout_policy, s3_http_config, standby: false, api_keys, api_key_split_limits, realtime_indexer_addrs, click_house_sinks,
+            })) if rest_socket_addr == socket_addr_from_str("127.0.0.1:9090").unwrap() && host_key_path == Path::new("/etc/quickwit-host-key-127.0.0.1-9090").to_path_buf() && peer_socket_addrs == vec![socket_addr_from_str("192.168.1.13:9090").unwrap()] && &metastore_uri == "file:///indexes"
+                && gc_index_ids.is_empty() && gc_interval == Duration::from_secs(10 * 60) && gc_grace_period == Duration::from_secs(60 * 60)
+                && verify_index_ids.is_empty() && verify_interval == Duration::from_secs(60 * 60)
+                && quota_config == QuotaConfig::default() && node_attributes.is_empty() && placement_rules == PlacementRules::default() && search_thread_pool == SearchThreadPoolParams::default() && storage_timeout_policy == StorageTimeoutPolicy::default() && s3_http_config == S3HttpConfig::default() && api_keys.is_empty() && api_key_split_limits.is_empty() && realtime_indexer_addrs.is_empty() && click_house_sinks.is_empty()
+        ));
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "serve",
+            "--metastore-uri",
+            "file:///indexes",
+            "--host",
+            "127.0.0.1",
+            "--port",
+            "9090",
+            "--host-key-path-prefix",
+            "/etc/quickwit-host-key",
+            "--peer-seed",
+            "192.168.1.13:9090,192.168.1.14:9090",
+            "--gc-index-id",
+            "wikipedia,hdfs-logs",
+            "--gc-interval",
+            "5m",
+            "--gc-grace-period",
+            "2h",
+            "--gc-retention-period",
+            "30d",
+            "--verify-index-id",
+            "wikipedia",
+            "--verify-interval",
+            "30m",
+            "--verify-num-splits",
+            "5",
+            "--quota-max-qps",
+            "10",
+            "--quota-max-concurrent-searches",
+            "4",
+            "--hedge-leaf-search-percentile",
+            "90",
+            "--tree-aggregation-min-nodes",
+            "20",
+            "--split-quarantine-threshold",
+            "0",
+            "--node-attribute",
+            "tier=hot,region=eu",
+            "--placement-rule",
+            "logs-*:tier=hot",
+            "--pin-fast-fields-time-window",
+            "1d",
+            "--pin-fast-fields-budget",
+            "2GB",
+            "--audit-log-index-uri",
+            "s3://my-bucket/indexes/audit-log",
+            "--audit-log-flush-interval",
+            "30s",
+            "--search-threads",
+            "8",
+            "--search-threads-per-query",
+            "2",
+            "--whole-split-download-max-size",
+            "4MB",
+            "--storage-timeout",
+            "30s",
+            "--storage-min-throughput",
+            "500KB",
+            "--s3-max-idle-connections-per-host",
+            "512",
+            "--s3-connect-timeout",
+            "2s",
+            "--s3-read-timeout",
+            "20s",
+            "--s3-http2",
+            "--standby",
+            "--api-key",
+            "admin",
+            "--api-key",
+            "acme=tenant_id:acme AND env:prod",
+            "--api-key-split-limit",
+            "acme=100",
+            "--realtime-indexer",
+            "10.0.0.2:7290",
+            "--click-house-sink",
+            "events=tcp://default@clickhouse:9000/logs#events",
+        ])?;
+        let command = CliCommand::parse_cli_args(&matches);
+        assert!(matches!(
+            command,
+            Ok(CliCommand::Serve(ServeArgs {
+                rest_socket_addr, host_key_path, peer_socket_addrs, metastore_uri, gc_index_ids, gc_interval, gc_grace_period, gc_retention_period, verify_index_ids, verify_interval, verify_num_splits: 5, quota_config, leaf_search_hedging, tree_aggregation_min_nodes: 20, split_quarantine_threshold: 0, node_attributes, placement_rules, fast_field_pinning, audit_log, search_thread_pool, whole_split_download_max_num_bytes, storage_timeout_policy, s3_http_config, standby: true, api_keys, api_key_split_limits, realtime_indexer_addrs, click_house_sinks,
+            })) if rest_socket_addr == socket_addr_from_str("127.0.0.1:9090").unwrap() && host_key_path == Path::new("/etc/quickwit-host-key-127.0.0.1-9090").to_path_buf() && peer_socket_addrs == vec![socket_addr_from_str("192.168.1.13:9090").unwrap(), socket_addr_from_str("192.168.1.14:9090").unwrap()] && &metastore_uri == "file:///indexes"
+                && gc_index_ids == vec!["wikipedia".to_string(), "hdfs-logs".to_string()] && gc_interval == Duration::from_secs(5 * 60) && gc_grace_period == Duration::from_secs(2 * 60 * 60) && gc_retention_period == Some(Duration::from_secs(30 * 24 * 60 * 60))
+                && verify_index_ids == vec!["wikipedia".to_string()] && verify_interval == Duration::from_secs(30 * 60)
+                && quota_config == QuotaConfig { max_qps: Some(10), max_concurrent_searches: Some(4) }
+                && leaf_search_hedging == Some(HedgingParams { percentile: 0.9, ..Default::default() })
+                && node_attributes == vec![("tier".to_string(), "hot".to_string()), ("region".to_string(), "eu".to_string())].into_iter().collect::<NodeAttributes>()
+                && placement_rules == PlacementRules::new(vec![PlacementRule { index_id_pattern: "logs-*".to_string(), attribute_key: "tier".to_string(), attribute_value: "hot".to_string() }])
+                && fast_field_pinning == Some(FastFieldPinningParams { time_window: Duration::from_secs(24 * 60 * 60), budget_in_bytes: 2_000_000_000 })
+                && audit_log == Some(AuditLogConfig { index_uri: "s3://my-bucket/indexes/audit-log".to_string(), flush_interval: Duration::from_secs(30) })
+                && search_thread_pool == SearchThreadPoolParams { num_threads: 8, max_threads_per_query: 2 }
+                && whole_split_download_max_num_bytes == Some(4_000_000)
+                && storage_timeout_policy == StorageTimeoutPolicy { min_timeout: Duration::from_secs(30), min_throughput_bytes_per_sec: 500_000 }
+                && s3_http_config == S3HttpConfig { max_idle_connections_per_host: 512, pool_idle_timeout: Duration::from_secs(10), connect_timeout: Duration::from_secs(2), read_timeout_opt: Some(Duration::from_secs(20)), http2: true }
+                && api_keys == vec![ApiKey { token: "admin".to_string(), filter_opt: None }, ApiKey { token: "acme".to_string(), filter_opt: Some("tenant_id:acme AND env:prod".to_string()) }]
+                && api_key_split_limits == vec![ApiKeySplitLimit { token: "acme".to_string(), max_num_splits: 100 }]
+                && realtime_indexer_addrs == vec![socket_addr_from_str("10.0.0.2:7290").unwrap()]
+                && click_house_sinks == vec![ClickHouseSinkConfig { name: "events".to_string(), dsn: "tcp://default@clickhouse:9000/logs".to_string(), table: "events".to_string() }]
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_output_format() -> anyhow::Result<()> {
+        let parse_output_format_from = |args: Vec<&str>| -> anyhow::Result<OutputFormat> {
+            let yaml = load_yaml!("cli.yaml");
+            let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+            let matches = app.get_matches_from_safe(args)?;
+            parse_output_format(&matches)
+        };
+        let delete_args = vec![
+            "delete",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "file:///indexes",
+        ];
+        assert_eq!(
+            parse_output_format_from(delete_args.clone())?,
+            OutputFormat::Text
+        );
+        assert_eq!(
+            parse_output_format_from([&["--output", "json"][..], &delete_args[..]].concat())?,
+            OutputFormat::Json
+        );
+        assert_eq!(
+            parse_output_format_from([&delete_args[..], &["--output", "json"][..]].concat())?,
+            OutputFormat::Json
+        );
+        assert!(
+            parse_output_format_from([&delete_args[..], &["--output", "xml"][..]].concat())
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_num_retries_and_is_retryable() -> anyhow::Result<()> {
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "index",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "file:///indexes",
+            "--retry",
+            "3",
+        ])?;
+        assert_eq!(parse_num_retries(&matches)?, 3);
+        // The documents are read from stdin.
+        assert!(!CliCommand::parse_cli_args(&matches)?.is_retryable());
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "--retry",
+            "2",
+            "index",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "file:///indexes",
+            "--input-path",
+            "/data/wikipedia.json",
+        ])?;
+        assert_eq!(parse_num_retries(&matches)?, 2);
+        assert!(CliCommand::parse_cli_args(&matches)?.is_retryable());
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "delete",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "file:///indexes",
+        ])?;
+        assert_eq!(parse_num_retries(&matches)?, 0);
+
+        let yaml = load_yaml!("cli.yaml");
+        let app = App::from(yaml).setting(AppSettings::NoBinaryName);
+        let matches = app.get_matches_from_safe(vec![
+            "delete",
+            "--index-id",
+            "wikipedia",
+            "--metastore-uri",
+            "file:///indexes",
+            "--retry",
+            "many",
+        ])?;
+        assert!(parse_num_retries(&matches).is_err());
         Ok(())
     }
 
@@ -0,0 +1,242 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::future::Future;
+use std::io::{stderr, stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::MoveUp;
+use crossterm::style::Print;
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::QueueableCommand;
+use quickwit_storage::{OngoingUpload, StorageProgress};
+
+use crate::OutputFormat;
+
+/// Width of the progress bars, in characters.
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// Displays a block of progress lines.
+///
+/// On a terminal, each draw replaces the previously drawn lines. Otherwise, e.g. when the
+/// output is piped into a file, the lines are appended. The lines are drawn on stdout in
+/// text mode and on stderr in JSON mode, leaving stdout to the result of the command.
+pub(crate) struct ProgressDisplay {
+    output_handle: Box<dyn Write>,
+    is_tty: bool,
+    num_drawn_lines: usize,
+}
+
+impl ProgressDisplay {
+    pub fn new(output_format: OutputFormat) -> Self {
+        let (output_handle, is_tty): (Box<dyn Write>, bool) = match output_format {
+            OutputFormat::Text => (Box::new(stdout()), atty::is(atty::Stream::Stdout)),
+            OutputFormat::Json => (Box::new(stderr()), atty::is(atty::Stream::Stderr)),
+        };
+        ProgressDisplay {
+            output_handle,
+            is_tty,
+            num_drawn_lines: 0,
+        }
+    }
+
+    pub fn is_tty(&self) -> bool {
+        self.is_tty
+    }
+
+    pub fn draw(&mut self, lines: &[String]) -> anyhow::Result<()> {
+        if self.num_drawn_lines > 0 {
+            self.output_handle
+                .queue(MoveUp(self.num_drawn_lines as u16))?;
+            self.output_handle.queue(Clear(ClearType::FromCursorDown))?;
+        }
+        for line in lines {
+            self.output_handle.queue(Print(format!("{}\n", line)))?;
+        }
+        self.output_handle.flush()?;
+        if self.is_tty {
+            self.num_drawn_lines = lines.len();
+        }
+        Ok(())
+    }
+}
+
+/// Renders a progress bar followed by its percentage, e.g. `[#######       ]  50%`.
+pub(crate) fn progress_bar(num_done: u64, num_total: u64) -> String {
+    let ratio = if num_total == 0 {
+        1f64
+    } else {
+        (num_done as f64 / num_total as f64).min(1f64)
+    };
+    let num_filled = (ratio * PROGRESS_BAR_WIDTH as f64).round() as usize;
+    format!(
+        "[{}{}] {:>3}%",
+        "#".repeat(num_filled),
+        " ".repeat(PROGRESS_BAR_WIDTH - num_filled),
+        (ratio * 100f64).floor() as u64
+    )
+}
+
+/// Formats a duration as `HH:MM:SS`.
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let num_secs = duration.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        num_secs / 3_600,
+        (num_secs / 60) % 60,
+        num_secs % 60
+    )
+}
+
+/// Returns the time left to process `num_remaining` units at `num_per_sec` units per second.
+pub(crate) fn estimate_remaining_time(num_remaining: u64, num_per_sec: f64) -> Option<Duration> {
+    if num_per_sec <= 0f64 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(num_remaining as f64 / num_per_sec))
+}
+
+/// Formats an estimated time remaining as `ETA: HH:MM:SS`, or `ETA: --:--:--` when unknown.
+pub(crate) fn format_eta(remaining_time_opt: Option<Duration>) -> String {
+    match remaining_time_opt {
+        Some(remaining_time) => format!("ETA: {}", format_duration(remaining_time)),
+        None => "ETA: --:--:--".to_string(),
+    }
+}
+
+/// Returns the line displaying the progress of an ongoing split upload.
+pub(crate) fn upload_progress_line(upload: &OngoingUpload) -> String {
+    format!(
+        "Uploading {} {} of {}MB",
+        upload.path.display(),
+        progress_bar(upload.num_uploaded_bytes, upload.num_bytes),
+        upload.num_bytes / 1_000_000
+    )
+}
+
+fn deletion_progress_line(
+    num_deleted_files: u64,
+    num_files_to_delete: u64,
+    elapsed_time: Duration,
+) -> String {
+    let num_files_per_sec = num_deleted_files as f64 / elapsed_time.as_secs_f64().max(1f64);
+    let remaining_time_opt = estimate_remaining_time(
+        num_files_to_delete.saturating_sub(num_deleted_files),
+        num_files_per_sec,
+    );
+    format!(
+        "Deleted files: {}/{} {} {:.0} files/s {}",
+        num_deleted_files,
+        num_files_to_delete,
+        progress_bar(num_deleted_files, num_files_to_delete),
+        num_files_per_sec,
+        format_eta(remaining_time_opt)
+    )
+}
+
+/// Awaits `future`, displaying every second how many of the `num_files_to_delete` files
+/// were deleted through the storages recording their progress in `storage_progress`.
+pub(crate) async fn display_deletion_progress<F: Future>(
+    future: F,
+    storage_progress: &StorageProgress,
+    num_files_to_delete: u64,
+    output_format: OutputFormat,
+) -> anyhow::Result<F::Output> {
+    tokio::pin!(future);
+    let start_time = Instant::now();
+    let mut progress_display = ProgressDisplay::new(output_format);
+    let mut report_interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            output = &mut future => {
+                let num_deleted_files = storage_progress.snapshot().num_deleted_files;
+                progress_display.draw(&[deletion_progress_line(
+                    num_deleted_files,
+                    num_files_to_delete,
+                    start_time.elapsed(),
+                )])?;
+                return Ok(output);
+            }
+            _ = report_interval.tick() => {
+                let num_deleted_files = storage_progress.snapshot().num_deleted_files;
+                progress_display.draw(&[deletion_progress_line(
+                    num_deleted_files,
+                    num_files_to_delete,
+                    start_time.elapsed(),
+                )])?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_progress_bar() {
+        assert_eq!(
+            progress_bar(0, 10),
+            format!("[{}]   0%", " ".repeat(PROGRESS_BAR_WIDTH))
+        );
+        assert_eq!(
+            progress_bar(5, 10),
+            format!("[{}{}]  50%", "#".repeat(15), " ".repeat(15))
+        );
+        assert_eq!(
+            progress_bar(12, 10),
+            format!("[{}] 100%", "#".repeat(PROGRESS_BAR_WIDTH))
+        );
+        assert_eq!(progress_bar(0, 0), progress_bar(10, 10));
+    }
+
+    #[test]
+    fn test_format_duration_and_eta() {
+        assert_eq!(format_duration(Duration::from_secs(3_723)), "01:02:03");
+        assert_eq!(estimate_remaining_time(100, 0f64), None);
+        assert_eq!(
+            estimate_remaining_time(100, 4f64),
+            Some(Duration::from_secs(25))
+        );
+        assert_eq!(format_eta(Some(Duration::from_secs(25))), "ETA: 00:00:25");
+        assert_eq!(format_eta(None), "ETA: --:--:--");
+    }
+
+    #[test]
+    fn test_progress_lines() {
+        let upload = OngoingUpload {
+            path: PathBuf::from("01FH.split"),
+            num_bytes: 120_000_000,
+            num_uploaded_bytes: 30_000_000,
+        };
+        assert_eq!(
+            upload_progress_line(&upload),
+            format!("Uploading 01FH.split {} of 120MB", progress_bar(1, 4))
+        );
+        assert_eq!(
+            deletion_progress_line(200, 1_000, Duration::from_secs(10)),
+            format!(
+                "Deleted files: 200/1000 {} 20 files/s ETA: 00:00:40",
+                progress_bar(1, 5)
+            )
+        );
+    }
+}
@@ -0,0 +1,279 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Near-real-time following of the documents of an index, like `tail -f`.
+//!
+//! The metastore is polled for the splits published by the indexers since the previous poll,
+//! and the documents of these splits matching the query are printed in time order.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use quickwit_metastore::{
+    Metastore, MetastoreUriResolver, SplitMetadataAndFooterOffsets, SplitState,
+};
+use quickwit_proto::{CachePolicy, SearchRequest};
+use quickwit_search::single_node_search_with_split_filter;
+use quickwit_storage::quickwit_storage_uri_resolver;
+use serde_json::Value as JsonValue;
+use tracing::{debug, error, warn};
+
+use crate::OutputFormat;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TailIndexArgs {
+    pub metastore_uri: String,
+    pub index_id: String,
+    pub query: String,
+    pub search_fields: Option<Vec<String>>,
+    pub poll_interval: Duration,
+    pub max_hits: usize,
+}
+
+/// Returns the IDs of the `published_splits` produced by an indexer that are not part of
+/// `known_split_ids`.
+///
+/// The splits produced by merges are left out: their documents were already published in the
+/// splits they replace. They are told apart by the reception times of their documents, which
+/// only the splits produced by an indexer record.
+fn new_indexed_split_ids(
+    published_splits: &[SplitMetadataAndFooterOffsets],
+    known_split_ids: &HashSet<String>,
+) -> HashSet<String> {
+    published_splits
+        .iter()
+        .map(|split| &split.split_metadata)
+        .filter(|split_metadata| {
+            split_metadata.ingest_timestamps.is_some()
+                && !known_split_ids.contains(&split_metadata.split_id)
+        })
+        .map(|split_metadata| split_metadata.split_id.clone())
+        .collect()
+}
+
+/// Returns the value of the timestamp field of the document `doc_json`, as returned in a hit.
+fn doc_timestamp(doc_json: &str, timestamp_field_name: &str) -> Option<i64> {
+    let doc: JsonValue = serde_json::from_str(doc_json).ok()?;
+    match doc.get(timestamp_field_name)? {
+        JsonValue::Array(values) => values.first()?.as_i64(),
+        value => value.as_i64(),
+    }
+}
+
+/// Sorts the documents by ascending timestamp. The documents without a timestamp come first.
+fn sort_docs_by_timestamp(docs: &mut Vec<String>, timestamp_field_name: &str) {
+    docs.sort_by_cached_key(|doc_json| doc_timestamp(doc_json, timestamp_field_name));
+}
+
+async fn published_splits(
+    metastore: &dyn Metastore,
+    index_id: &str,
+) -> anyhow::Result<Vec<SplitMetadataAndFooterOffsets>> {
+    let splits = metastore
+        .list_splits(index_id, SplitState::Published, None, &[])
+        .await?;
+    Ok(splits)
+}
+
+/// Formats a document for printing: pretty-printed in text mode, on a single line in JSON mode.
+fn format_doc(doc_json: &str, output_format: OutputFormat) -> anyhow::Result<String> {
+    let doc: JsonValue = serde_json::from_str(doc_json)?;
+    let formatted_doc = match output_format {
+        OutputFormat::Text => serde_json::to_string_pretty(&doc)?,
+        OutputFormat::Json => serde_json::to_string(&doc)?,
+    };
+    Ok(formatted_doc)
+}
+
+pub async fn tail_index_cli(
+    args: TailIndexArgs,
+    output_format: OutputFormat,
+) -> anyhow::Result<()> {
+    debug!(args = ?args, "tail-index");
+    let storage_uri_resolver = quickwit_storage_uri_resolver();
+    let metastore_uri_resolver = MetastoreUriResolver::default();
+    let metastore = metastore_uri_resolver.resolve(&args.metastore_uri).await?;
+    let index_metadata = metastore.index_metadata(&args.index_id).await?;
+    let timestamp_field_name_opt = index_metadata.index_config.timestamp_field_name();
+    // The documents published before the command started are not printed.
+    let mut known_split_ids: HashSet<String> = published_splits(&*metastore, &args.index_id)
+        .await?
+        .into_iter()
+        .map(|split| split.split_metadata.split_id)
+        .collect();
+    let search_request = SearchRequest {
+        index_id: args.index_id.clone(),
+        query: args.query.clone(),
+        search_fields: args.search_fields.clone().unwrap_or_default(),
+        start_timestamp: None,
+        end_timestamp: None,
+        max_hits: args.max_hits as u64,
+        start_offset: 0,
+        tags: Vec::new(),
+        distinct_count_fields: Vec::new(),
+        percentile_fields: Vec::new(),
+        percentiles: Vec::new(),
+        terms_aggregation: None,
+        date_histogram: None,
+        time_zone: String::new(),
+        geo_filter: None,
+        mandatory_filter: None,
+        flatten_hits: false,
+        sort_by_field: None,
+        sort_ascending: false,
+        disable_synonyms: false,
+        session_token: None,
+        min_publish_timestamp: None,
+        cache_policy: CachePolicy::Use as i32,
+        max_num_splits: None,
+        hit_sampling: None,
+    };
+    let mut poll_interval = tokio::time::interval(args.poll_interval);
+    loop {
+        poll_interval.tick().await;
+        // A failed poll is retried with the next one: the splits it missed are still new then.
+        let published_splits = match published_splits(&*metastore, &args.index_id).await {
+            Ok(published_splits) => published_splits,
+            Err(error) => {
+                error!(
+                    index_id = %args.index_id,
+                    error = ?error,
+                    "Failed to list the published splits."
+                );
+                continue;
+            }
+        };
+        let new_split_ids = new_indexed_split_ids(&published_splits, &known_split_ids);
+        if !new_split_ids.is_empty() {
+            let search_response_res = single_node_search_with_split_filter(
+                &search_request,
+                &*metastore,
+                storage_uri_resolver.clone(),
+                |split_metadata| new_split_ids.contains(&split_metadata.split_id),
+            )
+            .await;
+            let search_response = match search_response_res {
+                Ok(search_response) => search_response,
+                Err(error) => {
+                    error!(
+                        index_id = %args.index_id,
+                        error = ?error,
+                        "Failed to search the new splits."
+                    );
+                    continue;
+                }
+            };
+            if search_response.num_hits > search_response.hits.len() as u64 {
+                warn!(
+                    num_hits = search_response.num_hits,
+                    max_hits = args.max_hits,
+                    "More documents than the maximum number of hits were published since the last \
+                     poll, only the first ones are printed."
+                );
+            }
+            let mut docs: Vec<String> = search_response
+                .hits
+                .into_iter()
+                .map(|hit| hit.json)
+                .collect();
+            if let Some(timestamp_field_name) = &timestamp_field_name_opt {
+                sort_docs_by_timestamp(&mut docs, timestamp_field_name);
+            }
+            for doc_json in docs {
+                println!("{}", format_doc(&doc_json, output_format)?);
+            }
+        }
+        known_split_ids = published_splits
+            .into_iter()
+            .map(|split| split.split_metadata.split_id)
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use quickwit_metastore::{IngestTimestamps, SplitMetadata};
+
+    use super::*;
+
+    fn published_split(split_id: &str, is_merged: bool) -> SplitMetadataAndFooterOffsets {
+        let ingest_timestamps = if is_merged {
+            None
+        } else {
+            let num_docs_per_timestamp: BTreeMap<i64, u64> =
+                vec![(1_628_837_062, 1)].into_iter().collect();
+            IngestTimestamps::from_num_docs_per_timestamp(&num_docs_per_timestamp)
+        };
+        SplitMetadataAndFooterOffsets {
+            split_metadata: SplitMetadata {
+                split_id: split_id.to_string(),
+                split_state: SplitState::Published,
+                ingest_timestamps,
+                ..Default::default()
+            },
+            footer_offsets: 0..1,
+        }
+    }
+
+    #[test]
+    fn test_new_indexed_split_ids() {
+        let published_splits = vec![
+            published_split("split1", false),
+            published_split("split2", false),
+            published_split("merged-split", true),
+        ];
+        let known_split_ids: HashSet<String> = vec!["split1".to_string()].into_iter().collect();
+        let new_split_ids = new_indexed_split_ids(&published_splits, &known_split_ids);
+        assert_eq!(new_split_ids.len(), 1);
+        assert!(new_split_ids.contains("split2"));
+    }
+
+    #[test]
+    fn test_sort_docs_by_timestamp() {
+        let mut docs = vec![
+            r#"{"body": ["b"], "ts": [1628837063]}"#.to_string(),
+            r#"{"body": ["c"]}"#.to_string(),
+            r#"{"body": ["a"], "ts": [1628837062]}"#.to_string(),
+        ];
+        sort_docs_by_timestamp(&mut docs, "ts");
+        assert_eq!(
+            docs,
+            vec![
+                r#"{"body": ["c"]}"#.to_string(),
+                r#"{"body": ["a"], "ts": [1628837062]}"#.to_string(),
+                r#"{"body": ["b"], "ts": [1628837063]}"#.to_string(),
+            ]
+        );
+        assert_eq!(doc_timestamp(r#"{"ts": 12}"#, "ts"), Some(12));
+        assert_eq!(doc_timestamp("not json", "ts"), None);
+    }
+
+    #[test]
+    fn test_format_doc() -> anyhow::Result<()> {
+        let doc_json = r#"{"body": ["a"], "ts": [1628837062]}"#;
+        assert_eq!(
+            format_doc(doc_json, OutputFormat::Json)?,
+            r#"{"body":["a"],"ts":[1628837062]}"#
+        );
+        assert!(format_doc(doc_json, OutputFormat::Text)?.contains('\n'));
+        Ok(())
+    }
+}
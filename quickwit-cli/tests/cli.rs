@@ -21,13 +21,14 @@
 
 mod helpers;
 
+use std::collections::BTreeMap;
 use std::io::Read;
 use std::path::Path;
 
 use anyhow::Result;
 use helpers::{TestEnv, TestStorageType};
 use predicates::prelude::*;
-use quickwit_cli::{create_index_cli, CreateIndexArgs};
+use quickwit_cli::{create_index_cli, CreateIndexArgs, OutputFormat};
 use quickwit_metastore::{Metastore, MetastoreUriResolver, SplitState};
 use serde_json::{Number, Value};
 use serial_test::serial;
@@ -112,6 +113,7 @@ fn test_cmd_new_on_existing_index() -> Result<()> {
     )
     .assert()
     .failure()
+    .code(2)
     .stderr(predicate::str::contains("already exists"));
 
     Ok(())
@@ -294,6 +296,138 @@ fn test_cmd_delete_index_dry_run() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_cmd_json_output() -> Result<()> {
+    let test_env = create_test_env(TestStorageType::LocalFileSystem)?;
+    let index_id = "my-index";
+    create_logs_index(&test_env, index_id);
+
+    let output = make_command(
+        format!(
+            "index --index-id {} --metastore-uri {} --input-path {} --output json",
+            index_id,
+            test_env.metastore_uri,
+            test_env.resource_files["logs"].display()
+        )
+        .as_str(),
+    )
+    .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Indexed"));
+    let index_output: Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(index_output["index_id"], index_id);
+    assert!(index_output["num_published_splits"].as_u64().unwrap() > 0);
+
+    let output = make_command(
+        format!(
+            "delete --index-id {} --metastore-uri {} --dry-run --output json",
+            index_id, test_env.metastore_uri
+        )
+        .as_str(),
+    )
+    .output()?;
+    assert!(output.status.success());
+    let delete_output: Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(delete_output["dry_run"], true);
+    assert_eq!(
+        delete_output["num_files"],
+        index_output["num_published_splits"]
+    );
+    assert!(delete_output["files"][0]["file_name"]
+        .as_str()
+        .unwrap()
+        .ends_with(".split"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cmd_update_index_config() -> Result<()> {
+    let test_env = create_test_env(TestStorageType::LocalFileSystem)?;
+    let index_id = "my-index";
+    create_logs_index(&test_env, index_id);
+    index_data(
+        index_id,
+        test_env.resource_files["logs"].as_path(),
+        &test_env.metastore_uri,
+    );
+
+    // The new config stores the `level` field.
+    let index_config = std::fs::read_to_string(&test_env.resource_files["config"])?.replace(
+        r#""stored": false  /* Field not stored.*/"#,
+        r#""stored": true"#,
+    );
+    let new_config_path = test_env.local_directory.path().join("new-config.json");
+    std::fs::write(&new_config_path, index_config)?;
+
+    for expected_index_config_version in 1..=2 {
+        let output = make_command(
+            format!(
+                "update --index-id {} --metastore-uri {} --index-config-path {} --output json",
+                index_id,
+                test_env.metastore_uri,
+                new_config_path.display()
+            )
+            .as_str(),
+        )
+        .output()?;
+        assert!(output.status.success());
+        let update_output: Value = serde_json::from_slice(&output.stdout)?;
+        assert_eq!(
+            update_output["index_config_version"],
+            expected_index_config_version
+        );
+    }
+
+    let metastore = test_env.metastore();
+    let index_metadata = metastore.index_metadata(index_id).await?;
+    assert_eq!(index_metadata.index_config_version(), 2);
+    assert!(index_metadata.index_config_for_version(0).is_some());
+    assert!(index_metadata.index_config_for_version(1).is_some());
+    let splits = metastore
+        .list_splits(index_id, SplitState::Published, None, &[])
+        .await?;
+    assert!(!splits.is_empty());
+    for split in &splits {
+        assert_eq!(split.split_metadata.index_config_version, 0);
+        assert_eq!(
+            format!(
+                "{:?}",
+                index_metadata
+                    .index_config_for_split(&split.split_metadata)
+                    .unwrap()
+            ),
+            format!("{:?}", index_metadata.index_config_for_version(0).unwrap())
+        );
+    }
+
+    // The splits built with the former config are still searchable.
+    make_command(
+        format!(
+            "search --metastore-uri {} --index-id {} --query level:info",
+            test_env.metastore_uri, index_id,
+        )
+        .as_str(),
+    )
+    .assert()
+    .success()
+    .stdout(predicate::function(|output: &[u8]| {
+        let result: Value = serde_json::from_slice(output).unwrap();
+        result["numHits"] == Value::Number(Number::from(2i64))
+    }));
+
+    // Nothing to update.
+    make_command(
+        format!(
+            "update --index-id {} --metastore-uri {}",
+            index_id, test_env.metastore_uri
+        )
+        .as_str(),
+    )
+    .assert()
+    .failure();
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_cmd_delete() -> Result<()> {
     let test_env = create_test_env(TestStorageType::LocalFileSystem)?;
@@ -780,10 +914,13 @@ async fn test_all_with_s3_localstack_internal_api() -> Result<()> {
     let args = CreateIndexArgs::new(
         test_env.metastore_uri.clone(),
         index_uri.clone(),
+        None,
         test_env.resource_files["config"].to_path_buf(),
+        None,
+        BTreeMap::new(),
         false,
     )?;
-    create_index_cli(args).await?;
+    create_index_cli(args, OutputFormat::Text).await?;
     let index_metadata = test_env.metastore().index_metadata(index_id).await;
     assert_eq!(index_metadata.is_ok(), true);
 
@@ -19,10 +19,16 @@
 
 mod coolid;
 pub mod metrics;
+mod realtime_splits;
+mod split_lease;
+mod uri;
 
 pub use coolid::new_coolid;
 use once_cell::sync::Lazy;
+pub use realtime_splits::RealtimeSplits;
 use regex::Regex;
+pub use split_lease::{global_split_leases, SplitLease, SplitLeases, DEFAULT_SPLIT_LEASE_TTL};
+pub use uri::QuickwitUri;
 
 /// Filenames used for hotcache files.
 pub const HOTCACHE_FILENAME: &str = "hotcache";
@@ -0,0 +1,128 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use tantivy::Searcher;
+
+/// Searchers over the splits being indexed, which are not published yet.
+///
+/// The indexer commits the documents of its current split on a regular basis and places
+/// a searcher over them here. The publisher removes the split once it is published, from
+/// then on it is searched like any other split.
+#[derive(Clone, Default)]
+pub struct RealtimeSplits {
+    searchers: Arc<RwLock<HashMap<String, BTreeMap<String, Searcher>>>>,
+}
+
+impl fmt::Debug for RealtimeSplits {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let searchers = self.searchers.read().unwrap();
+        let split_ids: BTreeMap<&String, Vec<&String>> = searchers
+            .iter()
+            .map(|(index_id, split_searchers)| (index_id, split_searchers.keys().collect()))
+            .collect();
+        formatter
+            .debug_struct("RealtimeSplits")
+            .field("split_ids", &split_ids)
+            .finish()
+    }
+}
+
+impl RealtimeSplits {
+    /// Replaces the searcher of the split `split_id`.
+    pub fn update(&self, index_id: &str, split_id: &str, searcher: Searcher) {
+        self.searchers
+            .write()
+            .unwrap()
+            .entry(index_id.to_string())
+            .or_default()
+            .insert(split_id.to_string(), searcher);
+    }
+
+    /// Removes the searchers of the splits `split_ids`.
+    pub fn remove(&self, index_id: &str, split_ids: &[&str]) {
+        let mut searchers = self.searchers.write().unwrap();
+        if let Some(split_searchers) = searchers.get_mut(index_id) {
+            for split_id in split_ids {
+                split_searchers.remove(*split_id);
+            }
+            if split_searchers.is_empty() {
+                searchers.remove(index_id);
+            }
+        }
+    }
+
+    /// Returns the split IDs and searchers of the splits of the index being indexed.
+    pub fn searchers(&self, index_id: &str) -> Vec<(String, Searcher)> {
+        self.searchers
+            .read()
+            .unwrap()
+            .get(index_id)
+            .map(|split_searchers| {
+                split_searchers
+                    .iter()
+                    .map(|(split_id, searcher)| (split_id.clone(), searcher.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::{Schema, TEXT};
+    use tantivy::{doc, Index};
+
+    use super::*;
+
+    #[test]
+    fn test_realtime_splits() -> anyhow::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let body = schema_builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer = index.writer_with_num_threads(1, 3_000_000)?;
+        index_writer.add_document(doc!(body => "hello"));
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let realtime_splits = RealtimeSplits::default();
+        assert!(realtime_splits.searchers("index").is_empty());
+        realtime_splits.update("index", "split1", searcher.clone());
+        realtime_splits.update("index", "split2", searcher);
+        let searchers = realtime_splits.searchers("index");
+        assert_eq!(searchers.len(), 2);
+        assert_eq!(searchers[0].0, "split1");
+        assert_eq!(searchers[0].1.num_docs(), 1);
+        assert!(realtime_splits.searchers("other-index").is_empty());
+
+        realtime_splits.remove("index", &["split1", "split3"]);
+        let split_ids: Vec<String> = realtime_splits
+            .searchers("index")
+            .into_iter()
+            .map(|(split_id, _)| split_id)
+            .collect();
+        assert_eq!(split_ids, vec!["split2".to_string()]);
+        realtime_splits.remove("index", &["split2"]);
+        assert!(realtime_splits.searchers("index").is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+
+/// Default lifetime of a split lease.
+///
+/// A lease is released when it is dropped. The TTL only protects against
+/// queries that hang forever, so that they do not block garbage collection indefinitely.
+pub const DEFAULT_SPLIT_LEASE_TTL: Duration = Duration::from_secs(30 * 60);
+
+type LeaseId = u64;
+
+/// Registry of the splits currently being read by the searchers of this process.
+///
+/// Searchers acquire a lease on the splits they are about to read, and
+/// the split deletion logic skips the splits that are leased.
+#[derive(Clone, Default)]
+pub struct SplitLeases {
+    leases: Arc<Mutex<HashMap<String, HashMap<LeaseId, Instant>>>>,
+    lease_id_counter: Arc<AtomicU64>,
+}
+
+impl SplitLeases {
+    /// Leases the given splits for at most `ttl`.
+    pub fn acquire<I, S>(&self, split_ids: I, ttl: Duration) -> SplitLease
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let lease_id = self.lease_id_counter.fetch_add(1, Ordering::Relaxed);
+        let expiration = Instant::now() + ttl;
+        let split_ids: Vec<String> = split_ids.into_iter().map(Into::into).collect();
+        let mut leases = self.leases.lock().unwrap();
+        for split_id in split_ids.iter() {
+            leases
+                .entry(split_id.clone())
+                .or_default()
+                .insert(lease_id, expiration);
+        }
+        SplitLease {
+            lease_id,
+            split_ids,
+            split_leases: self.clone(),
+        }
+    }
+
+    /// Returns true if the split is covered by at least one lease that has not expired.
+    pub fn is_leased(&self, split_id: &str) -> bool {
+        let now = Instant::now();
+        let mut leases = self.leases.lock().unwrap();
+        let split_leases = if let Some(split_leases) = leases.get_mut(split_id) {
+            split_leases
+        } else {
+            return false;
+        };
+        split_leases.retain(|_, expiration| *expiration > now);
+        if split_leases.is_empty() {
+            leases.remove(split_id);
+            return false;
+        }
+        true
+    }
+
+    fn release(&self, lease_id: LeaseId, split_ids: &[String]) {
+        let mut leases = self.leases.lock().unwrap();
+        for split_id in split_ids {
+            if let Some(split_leases) = leases.get_mut(split_id) {
+                split_leases.remove(&lease_id);
+                if split_leases.is_empty() {
+                    leases.remove(split_id);
+                }
+            }
+        }
+    }
+}
+
+/// A lease over a set of splits. The lease is released on drop.
+pub struct SplitLease {
+    lease_id: LeaseId,
+    split_ids: Vec<String>,
+    split_leases: SplitLeases,
+}
+
+impl Drop for SplitLease {
+    fn drop(&mut self) {
+        self.split_leases.release(self.lease_id, &self.split_ids);
+    }
+}
+
+/// Returns the split lease registry shared by the searchers and the garbage collector
+/// running in this process.
+pub fn global_split_leases() -> &'static SplitLeases {
+    static INSTANCE: Lazy<SplitLeases> = Lazy::new(SplitLeases::default);
+    &INSTANCE
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SplitLeases;
+
+    #[test]
+    fn test_split_leases_are_released_on_drop() {
+        let split_leases = SplitLeases::default();
+        assert!(!split_leases.is_leased("split-1"));
+        let lease = split_leases.acquire(vec!["split-1", "split-2"], Duration::from_secs(60));
+        let other_lease = split_leases.acquire(vec!["split-2"], Duration::from_secs(60));
+        assert!(split_leases.is_leased("split-1"));
+        assert!(split_leases.is_leased("split-2"));
+        assert!(!split_leases.is_leased("split-3"));
+        drop(lease);
+        assert!(!split_leases.is_leased("split-1"));
+        assert!(split_leases.is_leased("split-2"));
+        drop(other_lease);
+        assert!(!split_leases.is_leased("split-2"));
+    }
+
+    #[test]
+    fn test_split_leases_expire() {
+        let split_leases = SplitLeases::default();
+        let _lease = split_leases.acquire(vec!["split-1"], Duration::from_millis(0));
+        assert!(!split_leases.is_leased("split-1"));
+    }
+}
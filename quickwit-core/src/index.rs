@@ -17,13 +17,36 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
-use quickwit_indexing::{delete_splits_with_files, run_garbage_collect, FileEntry};
-use quickwit_metastore::{IndexMetadata, Metastore, MetastoreUriResolver, SplitState};
-use quickwit_storage::{quickwit_storage_uri_resolver, StorageUriResolver};
-use tracing::warn;
+use anyhow::{bail, Context};
+use bytes::Bytes;
+use quickwit_common::QuickwitUri;
+use quickwit_index_config::IndexConfig;
+use quickwit_indexing::{
+    delete_splits_with_files, new_split_id, run_garbage_collect, run_split_verification,
+    verify_quarantined_splits, FileEntry, SplitVerificationStats,
+};
+use quickwit_metastore::checkpoint::{Checkpoint, CheckpointDelta, PartitionId, Position};
+use quickwit_metastore::{
+    retry_on_transient_error, CompanionFileMetadata, CompanionFileVersion, CompanionFiles,
+    IndexMetadata, MetadataSet, Metastore, MetastoreUriResolver, SplitMetadataAndFooterOffsets,
+    SplitState,
+};
+use quickwit_storage::{
+    multipart_file_storage, quickwit_storage_uri_resolver, PutPayload, Storage, StorageErrorKind,
+    StorageUriResolver,
+};
+use serde::Serialize;
+use tracing::{info, warn};
+
+/// Name of the file holding the metadata of an index at the root of its storage.
+const INDEX_METADATA_FILENAME: &str = "quickwit.json";
 
 /// Creates an index at `index-path` extracted from `metastore_uri`. The command fails if an index
 /// already exists at `index-path`.
@@ -45,6 +68,9 @@ pub async fn create_index(
 /// This is equivalent to running `rm -rf <index path>` for a local index or
 /// `aws s3 rm --recursive <index path>` for a remote Amazon S3 index.
 ///
+/// The companion files of the index are deleted along with its split files. The files of a
+/// read-only index are left untouched: only its metastore records are deleted.
+///
 /// * `metastore_uri` - The metastore URI for accessing the metastore.
 /// * `index_id` - The target index Id.
 /// * `dry_run` - Should this only return a list of affected files without performing deletion.
@@ -57,11 +83,20 @@ pub async fn delete_index(
         .resolve(metastore_uri)
         .await?;
     let storage_resolver = quickwit_storage_uri_resolver();
-    let index_uri = metastore.index_metadata(index_id).await?.index_uri;
-    let storage = storage_resolver.resolve(&index_uri)?;
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    if index_metadata.read_only {
+        if !dry_run {
+            metastore.delete_index(index_id).await?;
+        }
+        return Ok(Vec::new());
+    }
+    let storage = storage_resolver.resolve_with_mirror(
+        &index_metadata.index_uri,
+        index_metadata.mirror_index_uri.as_deref(),
+    )?;
 
     if dry_run {
-        let all_splits = metastore.list_all_splits(index_id).await?;
+        let all_splits = retry_on_transient_error(|| metastore.list_all_splits(index_id)).await?;
 
         let file_entries_to_delete: Vec<FileEntry> =
             all_splits.iter().map(FileEntry::from).collect();
@@ -69,12 +104,13 @@ pub async fn delete_index(
     }
 
     // Schedule staged and published splits for deletion.
-    let staged_splits = metastore
-        .list_splits(index_id, SplitState::Staged, None, &[])
-        .await?;
-    let published_splits = metastore
-        .list_splits(index_id, SplitState::Published, None, &[])
-        .await?;
+    let staged_splits =
+        retry_on_transient_error(|| metastore.list_splits(index_id, SplitState::Staged, None, &[]))
+            .await?;
+    let published_splits = retry_on_transient_error(|| {
+        metastore.list_splits(index_id, SplitState::Published, None, &[])
+    })
+    .await?;
     let split_ids = staged_splits
         .iter()
         .chain(published_splits.iter())
@@ -85,11 +121,26 @@ pub async fn delete_index(
         .await?;
 
     // Select split to delete
-    let splits_to_delete = metastore
-        .list_splits(index_id, SplitState::ScheduledForDeletion, None, &[])
-        .await?;
-    let deletion_stats =
-        delete_splits_with_files(index_id, storage, metastore.clone(), splits_to_delete).await?;
+    let splits_to_delete = retry_on_transient_error(|| {
+        metastore.list_splits(index_id, SplitState::ScheduledForDeletion, None, &[])
+    })
+    .await?;
+    let deletion_stats = delete_splits_with_files(
+        index_id,
+        storage.clone(),
+        metastore.clone(),
+        splits_to_delete,
+    )
+    .await?;
+    if !deletion_stats.leased_entries.is_empty() {
+        bail!(
+            "{} split(s) of index `{}` are currently being searched. Retry once the searches are \
+             over.",
+            deletion_stats.leased_entries.len(),
+            index_id
+        );
+    }
+    CompanionFiles::new(storage).delete_all().await?;
     metastore.delete_index(index_id).await?;
     Ok(deletion_stats.deleted_entries)
 }
@@ -100,19 +151,24 @@ pub async fn delete_index(
 /// * `index_id` - The target index Id.
 /// * `grace_period` -  Threshold period after which a staged split can be garbage collected.
 /// * `dry_run` - Should this only return a list of affected files without performing deletion.
+/// * `storage_resolver` - A storage resolver object to access the storage.
 pub async fn garbage_collect_index(
     metastore_uri: &str,
     index_id: &str,
     grace_period: Duration,
     dry_run: bool,
+    storage_resolver: &StorageUriResolver,
 ) -> anyhow::Result<Vec<FileEntry>> {
     let metastore = MetastoreUriResolver::default()
         .resolve(metastore_uri)
         .await?;
-    let storage_resolver = quickwit_storage_uri_resolver();
 
-    let index_uri = metastore.index_metadata(index_id).await?.index_uri;
-    let storage = storage_resolver.resolve(&index_uri)?;
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    ensure_not_read_only(&index_metadata, "garbage collected")?;
+    let storage = storage_resolver.resolve_with_mirror(
+        &index_metadata.index_uri,
+        index_metadata.mirror_index_uri.as_deref(),
+    )?;
 
     let deletion_stats =
         run_garbage_collect(index_id, storage, metastore, grace_period, dry_run).await?;
@@ -123,6 +179,203 @@ pub async fn garbage_collect_index(
     }
 }
 
+/// Verifies the integrity of the files of up to `max_num_splits` published splits of an index,
+/// starting with the splits never verified or verified the longest time ago, and records the
+/// outcome of each verification in the metastore. The corrupted splits are quarantined.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+/// * `max_num_splits` - The maximum number of splits to verify.
+/// * `quarantined` - Should the quarantined splits be verified instead, publishing again the
+/// intact ones.
+/// * `storage_resolver` - A storage resolver object to access the storage.
+pub async fn verify_index_splits(
+    metastore_uri: &str,
+    index_id: &str,
+    max_num_splits: usize,
+    quarantined: bool,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<SplitVerificationStats> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    let storage = storage_resolver.resolve_with_mirror(
+        &index_metadata.index_uri,
+        index_metadata.mirror_index_uri.as_deref(),
+    )?;
+    if quarantined {
+        verify_quarantined_splits(index_id, storage, metastore, max_num_splits).await
+    } else {
+        run_split_verification(index_id, storage, metastore, max_num_splits).await
+    }
+}
+
+/// Schedules for deletion the published splits whose documents all have a timestamp within
+/// `time_range`, and returns their files. The splits overlapping the bounds of the range are
+/// kept.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+/// * `time_range` - The time range containing the splits to delete.
+/// * `dry_run` - Should this only return a list of affected files without performing deletion.
+/// * `delete_files` - Should the split files be deleted right away, instead of by the next
+/// garbage collection.
+/// * `storage_resolver` - A storage resolver object to access the storage.
+pub async fn delete_splits_in_time_range(
+    metastore_uri: &str,
+    index_id: &str,
+    time_range: Range<i64>,
+    dry_run: bool,
+    delete_files: bool,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<Vec<FileEntry>> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    mark_splits_in_time_range_for_deletion(
+        metastore,
+        index_id,
+        time_range,
+        dry_run,
+        delete_files,
+        storage_resolver,
+    )
+    .await
+}
+
+async fn mark_splits_in_time_range_for_deletion(
+    metastore: Arc<dyn Metastore>,
+    index_id: &str,
+    time_range: Range<i64>,
+    dry_run: bool,
+    delete_files: bool,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<Vec<FileEntry>> {
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    ensure_not_read_only(&index_metadata, "modified")?;
+    if index_metadata.index_config.timestamp_field_name().is_none() {
+        bail!(
+            "The index `{}` does not have a timestamp field, its splits cannot be filtered by \
+             time range.",
+            index_id
+        );
+    }
+    let splits: Vec<SplitMetadataAndFooterOffsets> = metastore
+        .list_splits(
+            index_id,
+            SplitState::Published,
+            Some(time_range.clone()),
+            &[],
+        )
+        .await?
+        .into_iter()
+        .filter(|split| split.split_metadata.is_contained_in(&time_range))
+        .collect();
+    schedule_splits_for_deletion(
+        metastore,
+        &index_metadata,
+        splits,
+        dry_run,
+        delete_files,
+        storage_resolver,
+    )
+    .await
+}
+
+/// Schedules for deletion the quarantined splits of an index, and returns their files.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+/// * `dry_run` - Should this only return a list of affected files without performing deletion.
+/// * `delete_files` - Should the split files be deleted right away, instead of by the next
+/// garbage collection.
+/// * `storage_resolver` - A storage resolver object to access the storage.
+pub async fn delete_quarantined_splits(
+    metastore_uri: &str,
+    index_id: &str,
+    dry_run: bool,
+    delete_files: bool,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<Vec<FileEntry>> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    mark_quarantined_splits_for_deletion(
+        metastore,
+        index_id,
+        dry_run,
+        delete_files,
+        storage_resolver,
+    )
+    .await
+}
+
+async fn mark_quarantined_splits_for_deletion(
+    metastore: Arc<dyn Metastore>,
+    index_id: &str,
+    dry_run: bool,
+    delete_files: bool,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<Vec<FileEntry>> {
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    ensure_not_read_only(&index_metadata, "modified")?;
+    let splits = retry_on_transient_error(|| {
+        metastore.list_splits(index_id, SplitState::Quarantined, None, &[])
+    })
+    .await?;
+    schedule_splits_for_deletion(
+        metastore,
+        &index_metadata,
+        splits,
+        dry_run,
+        delete_files,
+        storage_resolver,
+    )
+    .await
+}
+
+/// Schedules `splits` for deletion, deleting their files right away if `delete_files` is set,
+/// and returns their files.
+async fn schedule_splits_for_deletion(
+    metastore: Arc<dyn Metastore>,
+    index_metadata: &IndexMetadata,
+    splits: Vec<SplitMetadataAndFooterOffsets>,
+    dry_run: bool,
+    delete_files: bool,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<Vec<FileEntry>> {
+    let index_id = &index_metadata.index_id;
+    let file_entries: Vec<FileEntry> = splits.iter().map(FileEntry::from).collect();
+    if dry_run || splits.is_empty() {
+        return Ok(file_entries);
+    }
+    let split_ids: Vec<&str> = splits
+        .iter()
+        .map(|split| split.split_metadata.split_id.as_str())
+        .collect();
+    metastore
+        .mark_splits_for_deletion(index_id, &split_ids)
+        .await?;
+    info!(index_id = %index_id, splits = ?split_ids, "Marked splits for deletion.");
+    if delete_files {
+        let storage = storage_resolver.resolve_with_mirror(
+            &index_metadata.index_uri,
+            index_metadata.mirror_index_uri.as_deref(),
+        )?;
+        let deletion_stats =
+            delete_splits_with_files(index_id, storage, metastore.clone(), splits).await?;
+        if !deletion_stats.leased_entries.is_empty() {
+            warn!(
+                index_id = %index_id,
+                num_splits = deletion_stats.leased_entries.len(),
+                "Splits currently being searched will be deleted by the next garbage collection."
+            );
+        }
+    }
+    Ok(file_entries)
+}
+
 /// Clears the index by applying the following actions:
 /// - mark all splits for deletion in the metastore.
 /// - delete the files of all splits marked for deletion using garbage collection.
@@ -136,10 +389,14 @@ pub async fn reset_index(
     index_id: &str,
     storage_resolver: StorageUriResolver,
 ) -> anyhow::Result<()> {
-    let index_uri = metastore.index_metadata(index_id).await?.index_uri;
-    let storage = storage_resolver.resolve(&index_uri)?;
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    ensure_not_read_only(&index_metadata, "reset")?;
+    let storage = storage_resolver.resolve_with_mirror(
+        &index_metadata.index_uri,
+        index_metadata.mirror_index_uri.as_deref(),
+    )?;
 
-    let splits = metastore.list_all_splits(index_id).await?;
+    let splits = retry_on_transient_error(|| metastore.list_all_splits(index_id)).await?;
     let split_ids = splits
         .iter()
         .map(|meta| meta.split_metadata.split_id.as_str())
@@ -155,3 +412,1435 @@ pub async fn reset_index(
     }
     Ok(())
 }
+
+/// Files copied to and deleted from the mirror of an index by [`mirror_index`].
+#[derive(Debug, Default)]
+pub struct MirrorReconciliation {
+    /// Files of the published splits that were missing from the mirror.
+    pub copied_files: Vec<FileEntry>,
+    /// Files of the splits scheduled for deletion that were still on the mirror.
+    pub deleted_files: Vec<FileEntry>,
+}
+
+/// Reconciles the mirror storage of an index with its published splits.
+///
+/// The writes to the mirror storage are allowed to fail, so this should be run regularly to
+/// catch up with the splits uploaded while the mirror was unavailable: the published splits
+/// missing from the mirror are copied there, and the files of the splits scheduled for
+/// deletion are deleted from the mirror. Returns the files that were copied and deleted (or,
+/// with `dry_run`, that would be).
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+/// * `dry_run` - Should this only return the lists of files without copying or deleting them.
+/// * `storage_resolver` - A storage resolver object to access the storage.
+pub async fn mirror_index(
+    metastore_uri: &str,
+    index_id: &str,
+    dry_run: bool,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<MirrorReconciliation> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    reconcile_mirror(&*metastore, index_id, dry_run, storage_resolver).await
+}
+
+async fn reconcile_mirror(
+    metastore: &dyn Metastore,
+    index_id: &str,
+    dry_run: bool,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<MirrorReconciliation> {
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    ensure_not_read_only(&index_metadata, "mirrored")?;
+    let mirror_index_uri = index_metadata
+        .mirror_index_uri
+        .as_ref()
+        .with_context(|| format!("The index `{}` does not have a mirror.", index_id))?;
+    let storage = storage_resolver.resolve(&index_metadata.index_uri)?;
+    let mirror_storage = storage_resolver.resolve(mirror_index_uri)?;
+
+    let published_splits = retry_on_transient_error(|| {
+        metastore.list_splits(index_id, SplitState::Published, None, &[])
+    })
+    .await?;
+    let mut missing_file_entries = Vec::new();
+    for split in &published_splits {
+        let file_entry = FileEntry::from(split);
+        let split_path = Path::new(&file_entry.file_name);
+        let split_mirror_storage =
+            multipart_file_storage(mirror_storage.clone(), file_entry.split_part_num_bytes);
+        match split_mirror_storage.file_num_bytes(split_path).await {
+            // A file of the wrong size is a leftover of an interrupted upload.
+            Ok(num_bytes) if num_bytes == file_entry.file_size_in_bytes => continue,
+            Ok(_) => {}
+            Err(error) if error.kind() == StorageErrorKind::DoesNotExist => {}
+            Err(error) => return Err(error.into()),
+        }
+        missing_file_entries.push(file_entry);
+    }
+    let deleted_file_entries =
+        delete_removed_splits_from_mirror(metastore, index_id, &mirror_storage, dry_run).await?;
+    if dry_run || missing_file_entries.is_empty() {
+        return Ok(MirrorReconciliation {
+            copied_files: missing_file_entries,
+            deleted_files: deleted_file_entries,
+        });
+    }
+    let scratch_dir = tempfile::tempdir()?;
+    for file_entry in &missing_file_entries {
+        let split_path = Path::new(&file_entry.file_name);
+        // Split files may be nested in directories, the scratch copy is not.
+        let local_split_path = scratch_dir
+            .path()
+            .join(split_path.file_name().unwrap_or_default());
+        multipart_file_storage(storage.clone(), file_entry.split_part_num_bytes)
+            .copy_to_file(split_path, &local_split_path)
+            .await?;
+        multipart_file_storage(mirror_storage.clone(), file_entry.split_part_num_bytes)
+            .put(split_path, PutPayload::from(local_split_path.clone()))
+            .await?;
+        tokio::fs::remove_file(&local_split_path).await?;
+        info!(index_id = %index_id, split_file = %file_entry.file_name, mirror_index_uri = %mirror_index_uri, "Copied split file to the mirror.");
+    }
+    Ok(MirrorReconciliation {
+        copied_files: missing_file_entries,
+        deleted_files: deleted_file_entries,
+    })
+}
+
+/// Deletes from the mirror the files of the splits scheduled for deletion, which the garbage
+/// collection could not delete there yet. Returns the files that were found on the mirror.
+async fn delete_removed_splits_from_mirror(
+    metastore: &dyn Metastore,
+    index_id: &str,
+    mirror_storage: &Arc<dyn Storage>,
+    dry_run: bool,
+) -> anyhow::Result<Vec<FileEntry>> {
+    let removed_splits = retry_on_transient_error(|| {
+        metastore.list_splits(index_id, SplitState::ScheduledForDeletion, None, &[])
+    })
+    .await?;
+    let mut deleted_file_entries = Vec::new();
+    for split in &removed_splits {
+        let file_entry = FileEntry::from(split);
+        let split_path = Path::new(&file_entry.file_name);
+        let split_mirror_storage =
+            multipart_file_storage(mirror_storage.clone(), file_entry.split_part_num_bytes);
+        match split_mirror_storage.file_num_bytes(split_path).await {
+            Ok(_) => {}
+            Err(error) if error.kind() == StorageErrorKind::DoesNotExist => continue,
+            Err(error) => return Err(error.into()),
+        }
+        if !dry_run {
+            split_mirror_storage.delete(split_path).await?;
+            info!(index_id = %index_id, split_file = %file_entry.file_name, "Deleted split file from the mirror.");
+        }
+        deleted_file_entries.push(file_entry);
+    }
+    Ok(deleted_file_entries)
+}
+
+/// Creates the index `target_index_id` at `target_index_uri`, with a copy of the published
+/// splits of the index `index_id` overlapping `time_range_opt`. Returns the files that were
+/// copied.
+///
+/// The splits are copied as a whole, so they may hold documents outside of the time range.
+/// The clone has the config history of the source index, but no mirror and an empty
+/// checkpoint: it is not kept up to date with its source.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore of the source index.
+/// * `index_id` - The source index Id.
+/// * `target_metastore_uri` - The metastore URI the clone is registered in.
+/// * `target_index_id` - The Id of the clone.
+/// * `target_index_uri` - The location of the split files of the clone.
+/// * `time_range_opt` - If set, only the splits overlapping this time range are copied.
+/// * `storage_resolver` - A storage resolver object to access the storage.
+pub async fn clone_index(
+    metastore_uri: &str,
+    index_id: &str,
+    target_metastore_uri: &str,
+    target_index_id: &str,
+    target_index_uri: &str,
+    time_range_opt: Option<Range<i64>>,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<Vec<FileEntry>> {
+    let metastore_uri_resolver = MetastoreUriResolver::default();
+    let metastore = metastore_uri_resolver.resolve(metastore_uri).await?;
+    let target_metastore = if target_metastore_uri == metastore_uri {
+        metastore.clone()
+    } else {
+        metastore_uri_resolver.resolve(target_metastore_uri).await?
+    };
+    copy_splits_to_clone(
+        &*metastore,
+        index_id,
+        &*target_metastore,
+        target_index_id,
+        target_index_uri,
+        time_range_opt,
+        storage_resolver,
+    )
+    .await
+}
+
+async fn copy_splits_to_clone(
+    metastore: &dyn Metastore,
+    index_id: &str,
+    target_metastore: &dyn Metastore,
+    target_index_id: &str,
+    target_index_uri: &str,
+    time_range_opt: Option<Range<i64>>,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<Vec<FileEntry>> {
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    if time_range_opt.is_some() && index_metadata.index_config.timestamp_field_name().is_none() {
+        bail!(
+            "The index `{}` does not have a timestamp field, its splits cannot be filtered by \
+             time range.",
+            index_id
+        );
+    }
+    let storage = storage_resolver.resolve_with_mirror(
+        &index_metadata.index_uri,
+        index_metadata.mirror_index_uri.as_deref(),
+    )?;
+    let target_storage = storage_resolver.resolve(target_index_uri)?;
+    let splits = retry_on_transient_error(|| {
+        metastore.list_splits(index_id, SplitState::Published, time_range_opt.clone(), &[])
+    })
+    .await?;
+    target_metastore
+        .create_index(IndexMetadata {
+            index_id: target_index_id.to_string(),
+            index_uri: QuickwitUri::from_str(target_index_uri)?,
+            mirror_index_uri: None,
+            index_config: index_metadata.index_config.clone(),
+            index_config_history: index_metadata.index_config_history.clone(),
+            checkpoint: Checkpoint::default(),
+            read_only: false,
+            description: index_metadata.description.clone(),
+            labels: index_metadata.labels.clone(),
+            create_timestamp: target_metastore.now_timestamp().await?,
+        })
+        .await?;
+
+    let scratch_dir = tempfile::tempdir()?;
+    let mut copied_file_entries = Vec::with_capacity(splits.len());
+    let mut target_split_ids = Vec::with_capacity(splits.len());
+    for split in &splits {
+        let file_entry = FileEntry::from(split);
+        // Split IDs are unique across the indexes of a metastore, so the clone of a split
+        // cannot keep the ID of its source. Its file is named after its new ID.
+        let mut target_split = split.clone();
+        target_split.split_metadata.split_id = new_split_id();
+        target_split.split_metadata.split_file_path = None;
+        let target_file_entry = FileEntry::from(&target_split);
+        target_metastore
+            .stage_split(target_index_id, target_split.clone())
+            .await?;
+        let split_path = Path::new(&file_entry.file_name);
+        let local_split_path = scratch_dir
+            .path()
+            .join(&target_split.split_metadata.split_id);
+        // The clone stores the split file in the same parts as its source.
+        multipart_file_storage(storage.clone(), file_entry.split_part_num_bytes)
+            .copy_to_file(split_path, &local_split_path)
+            .await?;
+        multipart_file_storage(target_storage.clone(), file_entry.split_part_num_bytes)
+            .put(
+                Path::new(&target_file_entry.file_name),
+                PutPayload::from(local_split_path.clone()),
+            )
+            .await?;
+        tokio::fs::remove_file(&local_split_path).await?;
+        info!(index_id = %index_id, target_index_id = %target_index_id, split_file = %file_entry.file_name, target_split_file = %target_file_entry.file_name, "Copied split file to the clone.");
+        copied_file_entries.push(target_file_entry);
+        target_split_ids.push(target_split.split_metadata.split_id);
+    }
+    let split_ids: Vec<&str> = target_split_ids.iter().map(String::as_str).collect();
+    if !split_ids.is_empty() {
+        target_metastore
+            .publish_splits(target_index_id, &split_ids, CheckpointDelta::default())
+            .await?;
+    }
+    Ok(copied_file_entries)
+}
+
+/// Registers the index stored at `index_uri` by another cluster in the metastore, along
+/// with its published splits. Returns the ID of the index and its split files.
+///
+/// The index is attached in read-only mode: its split files are searched where they are,
+/// without being copied, and are never modified or deleted by this cluster.
+///
+/// * `metastore_uri` - The metastore URI the index is registered in.
+/// * `index_uri` - The location of the index, holding its `quickwit.json` metadata file.
+/// * `storage_resolver` - A storage resolver object to access the storage.
+pub async fn attach_index(
+    metastore_uri: &str,
+    index_uri: &str,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<(String, Vec<FileEntry>)> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    import_index_metadata(&*metastore, index_uri, storage_resolver).await
+}
+
+async fn import_index_metadata(
+    metastore: &dyn Metastore,
+    index_uri: &str,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<(String, Vec<FileEntry>)> {
+    let storage = storage_resolver.resolve(index_uri)?;
+    let content = storage
+        .get_all(Path::new(INDEX_METADATA_FILENAME))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to read the metadata file `{}` of the index at `{}`.",
+                INDEX_METADATA_FILENAME, index_uri
+            )
+        })?;
+    let metadata_set: MetadataSet = serde_json::from_slice(&content).with_context(|| {
+        format!(
+            "Failed to parse the metadata file `{}` of the index at `{}`.",
+            INDEX_METADATA_FILENAME, index_uri
+        )
+    })?;
+    let index_id = metadata_set.index.index_id.clone();
+    metastore
+        .create_index(IndexMetadata {
+            index_uri: QuickwitUri::from_str(index_uri)?,
+            // The mirror belongs to the other cluster as well.
+            mirror_index_uri: None,
+            read_only: true,
+            ..metadata_set.index
+        })
+        .await?;
+
+    let mut splits: Vec<_> = metadata_set
+        .splits
+        .into_iter()
+        .map(|(_, split)| split)
+        .filter(|split| split.split_metadata.split_state == SplitState::Published)
+        .collect();
+    splits.sort_by(|left, right| {
+        left.split_metadata
+            .split_id
+            .cmp(&right.split_metadata.split_id)
+    });
+    let file_entries: Vec<FileEntry> = splits.iter().map(FileEntry::from).collect();
+    let split_ids: Vec<String> = splits
+        .iter()
+        .map(|split| split.split_metadata.split_id.clone())
+        .collect();
+    for split in splits {
+        metastore.stage_split(&index_id, split).await?;
+    }
+    if !split_ids.is_empty() {
+        let split_ids: Vec<&str> = split_ids.iter().map(String::as_str).collect();
+        metastore
+            .publish_splits(&index_id, &split_ids, CheckpointDelta::default())
+            .await?;
+    }
+    info!(index_id = %index_id, index_uri = %index_uri, num_splits = file_entries.len(), "Attached read-only index.");
+    Ok((index_id, file_entries))
+}
+
+/// Writes the local file `file_path` as a new version of the companion file `name` of an
+/// index, and returns this version.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+/// * `name` - The name of the companion file.
+/// * `file_path` - The local file holding the content of the new version.
+/// * `storage_resolver` - A storage resolver object to access the storage.
+pub async fn put_companion_file(
+    metastore_uri: &str,
+    index_id: &str,
+    name: &str,
+    file_path: &Path,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<CompanionFileVersion> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    ensure_not_read_only(&index_metadata, "modified")?;
+    let companion_files = index_companion_files(&index_metadata, storage_resolver)?;
+    let file_version = companion_files
+        .put(name, PutPayload::from(file_path.to_path_buf()))
+        .await?;
+    info!(index_id = %index_id, name = %name, version = file_version.version, "Wrote companion file.");
+    Ok(file_version)
+}
+
+/// Reads `version_opt`, or the latest version if it is not set, of the companion file `name`
+/// of an index.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+/// * `name` - The name of the companion file.
+/// * `version_opt` - The version to read, the latest one if not set.
+/// * `storage_resolver` - A storage resolver object to access the storage.
+pub async fn get_companion_file(
+    metastore_uri: &str,
+    index_id: &str,
+    name: &str,
+    version_opt: Option<u64>,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<(CompanionFileVersion, Bytes)> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    let companion_files = index_companion_files(&index_metadata, storage_resolver)?;
+    Ok(companion_files.get(name, version_opt).await?)
+}
+
+/// Lists the companion files of an index and their versions.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+/// * `storage_resolver` - A storage resolver object to access the storage.
+pub async fn list_companion_files(
+    metastore_uri: &str,
+    index_id: &str,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<Vec<CompanionFileMetadata>> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    let companion_files = index_companion_files(&index_metadata, storage_resolver)?;
+    Ok(companion_files.list().await?)
+}
+
+/// Deletes all the versions of the companion file `name` of an index, and returns them.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+/// * `name` - The name of the companion file.
+/// * `storage_resolver` - A storage resolver object to access the storage.
+pub async fn delete_companion_file(
+    metastore_uri: &str,
+    index_id: &str,
+    name: &str,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<CompanionFileMetadata> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    ensure_not_read_only(&index_metadata, "modified")?;
+    let companion_files = index_companion_files(&index_metadata, storage_resolver)?;
+    let deleted_file = companion_files.delete(name).await?;
+    info!(index_id = %index_id, name = %name, num_versions = deleted_file.versions.len(), "Deleted companion file.");
+    Ok(deleted_file)
+}
+
+/// Returns the checkpoint of an index: the position reached in each partition of its source.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+pub async fn index_checkpoint(metastore_uri: &str, index_id: &str) -> anyhow::Result<Checkpoint> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    Ok(index_metadata.checkpoint)
+}
+
+/// Change of the checkpoint position of a partition.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CheckpointPositionChange {
+    /// The partition of the source.
+    pub partition_id: String,
+    /// The position before the change, if the partition had one.
+    pub from: Option<String>,
+    /// The new position. The empty string is the beginning of the partition.
+    pub to: String,
+}
+
+/// Rewinds or advances the checkpoint of an index to the given `(partition, position)`
+/// positions, and returns the changes (or, with `dry_run`, the changes that would be made).
+///
+/// The indexing of the index should be stopped while its checkpoint is moved: it resumes from
+/// the new positions once restarted.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+/// * `positions` - The new positions of the partitions.
+/// * `dry_run` - Should this only return the changes without applying them.
+pub async fn set_checkpoint_positions(
+    metastore_uri: &str,
+    index_id: &str,
+    positions: &[(String, String)],
+    dry_run: bool,
+) -> anyhow::Result<Vec<CheckpointPositionChange>> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    set_checkpoint_positions_in_metastore(&*metastore, index_id, positions, dry_run).await
+}
+
+async fn set_checkpoint_positions_in_metastore(
+    metastore: &dyn Metastore,
+    index_id: &str,
+    positions: &[(String, String)],
+    dry_run: bool,
+) -> anyhow::Result<Vec<CheckpointPositionChange>> {
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    ensure_not_read_only(&index_metadata, "repositioned")?;
+    let changes = checkpoint_position_changes(&index_metadata.checkpoint, positions);
+    if dry_run {
+        return Ok(changes);
+    }
+    let new_positions = changes
+        .iter()
+        .map(|change| {
+            (
+                PartitionId::from(change.partition_id.as_str()),
+                Position::from(change.to.as_str()),
+            )
+        })
+        .collect();
+    metastore
+        .set_checkpoint_positions(index_id, new_positions)
+        .await?;
+    info!(index_id = %index_id, changes = ?changes, "Set checkpoint positions.");
+    Ok(changes)
+}
+
+/// Resolves the partitions and positions given by a user against the checkpoint.
+///
+/// The sources pad their numeric partition IDs and offsets with zeros to keep their
+/// lexicographical order, so `0` stands for the partition `0000000000` if the partitions of
+/// the checkpoint have this width, and likewise for the positions.
+fn checkpoint_position_changes(
+    checkpoint: &Checkpoint,
+    positions: &[(String, String)],
+) -> Vec<CheckpointPositionChange> {
+    let reference_partition_opt = checkpoint.iter().next();
+    positions
+        .iter()
+        .map(|(partition_id, position)| {
+            let partition_id = match &reference_partition_opt {
+                Some((reference_partition_id, _)) => {
+                    pad_like(partition_id, &reference_partition_id.0)
+                }
+                None => partition_id.clone(),
+            };
+            let current_position_opt = checkpoint
+                .position_for_partition(&PartitionId::from(partition_id.as_str()))
+                .cloned();
+            let position = match current_position_opt.as_ref().or_else(|| {
+                reference_partition_opt
+                    .as_ref()
+                    .map(|(_, position)| position)
+            }) {
+                Some(reference_position) => pad_like(position, reference_position.as_str()),
+                None => position.clone(),
+            };
+            CheckpointPositionChange {
+                partition_id,
+                from: current_position_opt.map(|position| position.as_str().to_string()),
+                to: position,
+            }
+        })
+        .collect()
+}
+
+/// Left-pads the numeric `value` with zeros to the width of the numeric `reference`.
+fn pad_like(value: &str, reference: &str) -> String {
+    let is_numeric =
+        |text: &str| !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_digit());
+    if is_numeric(value) && is_numeric(reference) && value.len() < reference.len() {
+        return format!("{:0>width$}", value, width = reference.len());
+    }
+    value.to_string()
+}
+
+/// Metadata and published splits of an index, as displayed by the `describe` command.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IndexDescription {
+    /// The index Id.
+    pub index_id: String,
+    /// The URI of the storage of the split files.
+    pub index_uri: String,
+    /// The URI of the mirror of the split files, if any.
+    pub mirror_index_uri: Option<String>,
+    /// True if the index was attached from the storage of another cluster.
+    pub read_only: bool,
+    /// The free-form description of the index.
+    pub description: Option<String>,
+    /// The free-form labels of the index.
+    pub labels: BTreeMap<String, String>,
+    /// The version of the current index config.
+    pub index_config_version: u64,
+    /// The number of published splits.
+    pub num_published_splits: usize,
+    /// The number of documents of the published splits.
+    pub num_published_docs: usize,
+    /// The total size of the published splits.
+    pub published_splits_size_in_bytes: u64,
+}
+
+/// Returns the metadata of an index, along with statistics on its published splits.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+pub async fn describe_index(
+    metastore_uri: &str,
+    index_id: &str,
+) -> anyhow::Result<IndexDescription> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    describe_index_in_metastore(&*metastore, index_id).await
+}
+
+async fn describe_index_in_metastore(
+    metastore: &dyn Metastore,
+    index_id: &str,
+) -> anyhow::Result<IndexDescription> {
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    let published_splits = retry_on_transient_error(|| {
+        metastore.list_splits(index_id, SplitState::Published, None, &[])
+    })
+    .await?;
+    Ok(IndexDescription {
+        index_config_version: index_metadata.index_config_version(),
+        index_id: index_metadata.index_id,
+        index_uri: index_metadata.index_uri.to_string(),
+        mirror_index_uri: index_metadata
+            .mirror_index_uri
+            .map(|mirror_index_uri| mirror_index_uri.to_string()),
+        read_only: index_metadata.read_only,
+        description: index_metadata.description,
+        labels: index_metadata.labels,
+        num_published_splits: published_splits.len(),
+        num_published_docs: published_splits
+            .iter()
+            .map(|split| split.split_metadata.num_records)
+            .sum(),
+        published_splits_size_in_bytes: published_splits
+            .iter()
+            .map(|split| split.split_metadata.size_in_bytes)
+            .sum(),
+    })
+}
+
+/// Updates the description and the labels of an index, and returns its new description.
+///
+/// The labels are only metastore records, so the labels of a read-only index can be updated
+/// as well.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+/// * `description_opt` - The new description, if it changes. An empty description removes it.
+/// * `labels_to_set` - The `(key, value)` labels to add or replace.
+/// * `labels_to_remove` - The keys of the labels to remove.
+pub async fn update_index_labels(
+    metastore_uri: &str,
+    index_id: &str,
+    description_opt: Option<&str>,
+    labels_to_set: &[(String, String)],
+    labels_to_remove: &[String],
+) -> anyhow::Result<IndexDescription> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    update_index_labels_in_metastore(
+        &*metastore,
+        index_id,
+        description_opt,
+        labels_to_set,
+        labels_to_remove,
+    )
+    .await
+}
+
+async fn update_index_labels_in_metastore(
+    metastore: &dyn Metastore,
+    index_id: &str,
+    description_opt: Option<&str>,
+    labels_to_set: &[(String, String)],
+    labels_to_remove: &[String],
+) -> anyhow::Result<IndexDescription> {
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    let description = match description_opt {
+        Some("") => None,
+        Some(description) => Some(description.to_string()),
+        None => index_metadata.description,
+    };
+    let mut labels = index_metadata.labels;
+    labels.extend(labels_to_set.iter().cloned());
+    for key in labels_to_remove {
+        labels.remove(key);
+    }
+    metastore
+        .update_index_labels(index_id, description, labels)
+        .await?;
+    info!(index_id = %index_id, "Updated index labels.");
+    describe_index_in_metastore(metastore, index_id).await
+}
+
+/// Replaces the index config of an index, and returns its new description.
+///
+/// The former configs are kept in the metastore: the existing splits are still read with the
+/// config they were built with, while the splits built from now on use the new one.
+///
+/// * `metastore_uri` - The metastore URI for accessing the metastore.
+/// * `index_id` - The target index Id.
+/// * `index_config` - The new index config.
+pub async fn update_index_config(
+    metastore_uri: &str,
+    index_id: &str,
+    index_config: Arc<dyn IndexConfig>,
+) -> anyhow::Result<IndexDescription> {
+    let metastore = MetastoreUriResolver::default()
+        .resolve(metastore_uri)
+        .await?;
+    update_index_config_in_metastore(&*metastore, index_id, index_config).await
+}
+
+async fn update_index_config_in_metastore(
+    metastore: &dyn Metastore,
+    index_id: &str,
+    index_config: Arc<dyn IndexConfig>,
+) -> anyhow::Result<IndexDescription> {
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    ensure_not_read_only(&index_metadata, "modified")?;
+    let index_config_version = metastore
+        .update_index_config(index_id, index_config)
+        .await?;
+    info!(
+        index_id = %index_id,
+        index_config_version = index_config_version,
+        "Updated index config."
+    );
+    describe_index_in_metastore(metastore, index_id).await
+}
+
+/// Returns the companion files of an index, stored next to its split files.
+fn index_companion_files(
+    index_metadata: &IndexMetadata,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<CompanionFiles> {
+    let storage = storage_resolver.resolve_with_mirror(
+        &index_metadata.index_uri,
+        index_metadata.mirror_index_uri.as_deref(),
+    )?;
+    Ok(CompanionFiles::new(storage))
+}
+
+/// Returns an error if the index is read-only, as the `operation` would modify its files.
+fn ensure_not_read_only(index_metadata: &IndexMetadata, operation: &str) -> anyhow::Result<()> {
+    if index_metadata.read_only {
+        bail!(
+            "The index `{}` is read-only: it was attached from the storage of another cluster and \
+             cannot be {}.",
+            index_metadata.index_id,
+            operation
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_index_config::{DefaultIndexConfigBuilder, WikipediaIndexConfig};
+    use quickwit_metastore::{MockMetastore, SingleFileMetastore, SplitMetadata};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reconcile_mirror() -> anyhow::Result<()> {
+        let mut metastore = MockMetastore::default();
+        metastore.expect_index_metadata().returning(|index_id| {
+            Ok(IndexMetadata {
+                index_id: index_id.to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/test-index"),
+                mirror_index_uri: Some(QuickwitUri::from_well_formed("ram://mirror/test-index")),
+                index_config: Arc::new(WikipediaIndexConfig::new()),
+                index_config_history: Vec::new(),
+                checkpoint: Checkpoint::default(),
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
+            })
+        });
+        metastore
+            .expect_list_splits()
+            .returning(|_, split_state, _, _| {
+                let split_ids: &[&str] = match &split_state {
+                    SplitState::Published => &["split1", "split2"],
+                    SplitState::ScheduledForDeletion => &["split3"],
+                    _ => &[],
+                };
+                Ok(split_ids
+                    .iter()
+                    .map(|split_id| SplitMetadataAndFooterOffsets {
+                        split_metadata: SplitMetadata {
+                            split_id: split_id.to_string(),
+                            split_state: split_state.clone(),
+                            ..Default::default()
+                        },
+                        footer_offsets: 0..6,
+                    })
+                    .collect())
+            });
+        let storage_resolver = StorageUriResolver::for_test();
+        let storage = storage_resolver.resolve("ram://indexes/test-index")?;
+        let mirror_storage = storage_resolver.resolve("ram://mirror/test-index")?;
+        storage
+            .put(Path::new("split1.split"), b"split1".to_vec().into())
+            .await?;
+        storage
+            .put(Path::new("split2.split"), b"split2".to_vec().into())
+            .await?;
+        mirror_storage
+            .put(Path::new("split1.split"), b"split1".to_vec().into())
+            .await?;
+        mirror_storage
+            .put(Path::new("split3.split"), b"split3".to_vec().into())
+            .await?;
+
+        let file_names = |file_entries: Vec<FileEntry>| {
+            file_entries
+                .into_iter()
+                .map(|file_entry| file_entry.file_name)
+                .collect::<Vec<_>>()
+        };
+        let reconciliation =
+            reconcile_mirror(&metastore, "test-index", true, &storage_resolver).await?;
+        assert_eq!(
+            file_names(reconciliation.copied_files),
+            vec!["split2.split"]
+        );
+        assert_eq!(
+            file_names(reconciliation.deleted_files),
+            vec!["split3.split"]
+        );
+        assert!(!mirror_storage.exists(Path::new("split2.split")).await?);
+        assert!(mirror_storage.exists(Path::new("split3.split")).await?);
+
+        let reconciliation =
+            reconcile_mirror(&metastore, "test-index", false, &storage_resolver).await?;
+        assert_eq!(
+            file_names(reconciliation.copied_files),
+            vec!["split2.split"]
+        );
+        assert_eq!(
+            file_names(reconciliation.deleted_files),
+            vec!["split3.split"]
+        );
+        assert_eq!(
+            &mirror_storage.get_all(Path::new("split2.split")).await?[..],
+            b"split2"
+        );
+        assert!(!mirror_storage.exists(Path::new("split3.split")).await?);
+
+        let reconciliation =
+            reconcile_mirror(&metastore, "test-index", false, &storage_resolver).await?;
+        assert!(reconciliation.copied_files.is_empty());
+        assert!(reconciliation.deleted_files.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy_splits_to_clone() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "timestamp_field": "ts",
+            "field_mappings": [
+                {"name": "body", "type": "text"},
+                {"name": "ts", "type": "i64", "fast": true}
+            ]
+        }"#;
+        let index_config =
+            serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?;
+        let metastore = SingleFileMetastore::for_test();
+        metastore
+            .create_index(IndexMetadata {
+                index_id: "test-index".to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/test-index"),
+                mirror_index_uri: None,
+                index_config: Arc::new(index_config),
+                index_config_history: Vec::new(),
+                checkpoint: Checkpoint::default(),
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
+            })
+            .await?;
+        let storage_resolver = StorageUriResolver::for_test();
+        let storage = storage_resolver.resolve("ram://indexes/test-index")?;
+        for (split_id, time_range) in &[("split1", 0..=9), ("split2", 10..=19), ("split3", 20..=29)]
+        {
+            metastore
+                .stage_split(
+                    "test-index",
+                    SplitMetadataAndFooterOffsets {
+                        split_metadata: SplitMetadata {
+                            split_id: split_id.to_string(),
+                            time_range: Some(time_range.clone()),
+                            ..Default::default()
+                        },
+                        footer_offsets: 0..6,
+                    },
+                )
+                .await?;
+            storage
+                .put(
+                    Path::new(&format!("{}.split", split_id)),
+                    split_id.as_bytes().to_vec().into(),
+                )
+                .await?;
+            metastore
+                .publish_splits("test-index", &[*split_id], CheckpointDelta::default())
+                .await?;
+        }
+
+        let copied_file_entries = copy_splits_to_clone(
+            &metastore,
+            "test-index",
+            &metastore,
+            "test-index-clone",
+            "ram://clones/test-index",
+            Some(5..15),
+            &storage_resolver,
+        )
+        .await?;
+        let mut copied_file_names: Vec<String> = copied_file_entries
+            .into_iter()
+            .map(|file_entry| file_entry.file_name)
+            .collect();
+        copied_file_names.sort();
+
+        let clone_metadata = metastore.index_metadata("test-index-clone").await?;
+        assert_eq!(clone_metadata.index_uri, "ram://clones/test-index");
+        assert_eq!(
+            clone_metadata.index_config.timestamp_field_name(),
+            Some("ts".to_string())
+        );
+        let mut clone_splits = metastore
+            .list_splits("test-index-clone", SplitState::Published, None, &[])
+            .await?;
+        assert_eq!(clone_splits.len(), 2);
+        clone_splits
+            .sort_by_key(|split| *split.split_metadata.time_range.as_ref().unwrap().start());
+        let mut clone_file_names: Vec<String> = clone_splits
+            .iter()
+            .map(|split| split.split_metadata.split_file())
+            .collect();
+        clone_file_names.sort();
+        assert_eq!(clone_file_names, copied_file_names);
+        // The clones of the splits have new IDs.
+        let clone_storage = storage_resolver.resolve("ram://clones/test-index")?;
+        for (clone_split, split_id) in clone_splits.iter().zip(&["split1", "split2"]) {
+            assert_ne!(clone_split.split_metadata.split_id, *split_id);
+            assert_eq!(
+                &clone_storage
+                    .get_all(Path::new(&clone_split.split_metadata.split_file()))
+                    .await?[..],
+                split_id.as_bytes()
+            );
+        }
+        assert!(!clone_storage.exists(Path::new("split1.split")).await?);
+        assert!(!clone_storage.exists(Path::new("split3.split")).await?);
+
+        // The clone is a new index.
+        assert!(copy_splits_to_clone(
+            &metastore,
+            "test-index",
+            &metastore,
+            "test-index-clone",
+            "ram://clones/test-index",
+            None,
+            &storage_resolver,
+        )
+        .await
+        .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy_splits_to_clone_without_timestamp_field() -> anyhow::Result<()> {
+        let metastore = SingleFileMetastore::for_test();
+        metastore
+            .create_index(IndexMetadata {
+                index_id: "test-index".to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/test-index"),
+                mirror_index_uri: None,
+                index_config: Arc::new(WikipediaIndexConfig::new()),
+                index_config_history: Vec::new(),
+                checkpoint: Checkpoint::default(),
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
+            })
+            .await?;
+        let storage_resolver = StorageUriResolver::for_test();
+        assert!(copy_splits_to_clone(
+            &metastore,
+            "test-index",
+            &metastore,
+            "test-index-clone",
+            "ram://clones/test-index",
+            Some(5..15),
+            &storage_resolver,
+        )
+        .await
+        .is_err());
+        assert!(metastore.index_metadata("test-index-clone").await.is_err());
+        Ok(())
+    }
+
+    async fn published_split_ids(metastore: &dyn Metastore) -> anyhow::Result<Vec<String>> {
+        let mut split_ids: Vec<String> = metastore
+            .list_splits("test-index", SplitState::Published, None, &[])
+            .await?
+            .into_iter()
+            .map(|split| split.split_metadata.split_id)
+            .collect();
+        split_ids.sort();
+        Ok(split_ids)
+    }
+
+    #[tokio::test]
+    async fn test_mark_splits_in_time_range_for_deletion() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "timestamp_field": "ts",
+            "field_mappings": [
+                {"name": "body", "type": "text"},
+                {"name": "ts", "type": "i64", "fast": true}
+            ]
+        }"#;
+        let index_config =
+            serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?;
+        let metastore = Arc::new(SingleFileMetastore::for_test());
+        metastore
+            .create_index(IndexMetadata {
+                index_id: "test-index".to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/test-index"),
+                mirror_index_uri: None,
+                index_config: Arc::new(index_config),
+                index_config_history: Vec::new(),
+                checkpoint: Checkpoint::default(),
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
+            })
+            .await?;
+        let storage_resolver = StorageUriResolver::for_test();
+        let storage = storage_resolver.resolve("ram://indexes/test-index")?;
+        for (split_id, time_range) in &[("split1", 0..=9), ("split2", 10..=19), ("split3", 20..=29)]
+        {
+            metastore
+                .stage_split(
+                    "test-index",
+                    SplitMetadataAndFooterOffsets {
+                        split_metadata: SplitMetadata {
+                            split_id: split_id.to_string(),
+                            time_range: Some(time_range.clone()),
+                            ..Default::default()
+                        },
+                        footer_offsets: 0..6,
+                    },
+                )
+                .await?;
+            storage
+                .put(
+                    Path::new(&format!("{}.split", split_id)),
+                    split_id.as_bytes().to_vec().into(),
+                )
+                .await?;
+            metastore
+                .publish_splits("test-index", &[*split_id], CheckpointDelta::default())
+                .await?;
+        }
+        let file_names = |file_entries: Vec<FileEntry>| {
+            let mut file_names: Vec<String> = file_entries
+                .into_iter()
+                .map(|file_entry| file_entry.file_name)
+                .collect();
+            file_names.sort();
+            file_names
+        };
+
+        let file_entries = mark_splits_in_time_range_for_deletion(
+            metastore.clone(),
+            "test-index",
+            5..30,
+            true,
+            true,
+            &storage_resolver,
+        )
+        .await?;
+        assert_eq!(
+            file_names(file_entries),
+            vec!["split2.split", "split3.split"]
+        );
+        assert_eq!(
+            published_split_ids(&*metastore).await?,
+            vec!["split1", "split2", "split3"]
+        );
+
+        let file_entries = mark_splits_in_time_range_for_deletion(
+            metastore.clone(),
+            "test-index",
+            10..20,
+            false,
+            false,
+            &storage_resolver,
+        )
+        .await?;
+        assert_eq!(file_names(file_entries), vec!["split2.split"]);
+        assert_eq!(
+            published_split_ids(&*metastore).await?,
+            vec!["split1", "split3"]
+        );
+        assert!(storage.exists(Path::new("split2.split")).await?);
+
+        let file_entries = mark_splits_in_time_range_for_deletion(
+            metastore.clone(),
+            "test-index",
+            20..i64::MAX,
+            false,
+            true,
+            &storage_resolver,
+        )
+        .await?;
+        assert_eq!(file_names(file_entries), vec!["split3.split"]);
+        assert_eq!(published_split_ids(&*metastore).await?, vec!["split1"]);
+        assert!(!storage.exists(Path::new("split3.split")).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mark_quarantined_splits_for_deletion() -> anyhow::Result<()> {
+        let metastore = Arc::new(SingleFileMetastore::for_test());
+        metastore
+            .create_index(IndexMetadata {
+                index_id: "test-index".to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/test-index"),
+                mirror_index_uri: None,
+                index_config: Arc::new(WikipediaIndexConfig::new()),
+                index_config_history: Vec::new(),
+                checkpoint: Checkpoint::default(),
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
+            })
+            .await?;
+        let storage_resolver = StorageUriResolver::for_test();
+        let storage = storage_resolver.resolve("ram://indexes/test-index")?;
+        for split_id in &["split1", "split2"] {
+            metastore
+                .stage_split(
+                    "test-index",
+                    SplitMetadataAndFooterOffsets {
+                        split_metadata: SplitMetadata {
+                            split_id: split_id.to_string(),
+                            ..Default::default()
+                        },
+                        footer_offsets: 0..6,
+                    },
+                )
+                .await?;
+            storage
+                .put(
+                    Path::new(&format!("{}.split", split_id)),
+                    split_id.as_bytes().to_vec().into(),
+                )
+                .await?;
+            metastore
+                .publish_splits("test-index", &[*split_id], CheckpointDelta::default())
+                .await?;
+        }
+        metastore
+            .quarantine_splits("test-index", &["split1"])
+            .await?;
+
+        let file_entries = mark_quarantined_splits_for_deletion(
+            metastore.clone(),
+            "test-index",
+            true,
+            true,
+            &storage_resolver,
+        )
+        .await?;
+        assert_eq!(file_entries.len(), 1);
+        assert_eq!(file_entries[0].file_name, "split1.split");
+        assert!(storage.exists(Path::new("split1.split")).await?);
+
+        let file_entries = mark_quarantined_splits_for_deletion(
+            metastore.clone(),
+            "test-index",
+            false,
+            true,
+            &storage_resolver,
+        )
+        .await?;
+        assert_eq!(file_entries.len(), 1);
+        assert!(!storage.exists(Path::new("split1.split")).await?);
+        assert!(metastore
+            .list_splits("test-index", SplitState::Quarantined, None, &[])
+            .await?
+            .is_empty());
+        assert_eq!(published_split_ids(&*metastore).await?, vec!["split2"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_index_metadata() -> anyhow::Result<()> {
+        let storage_resolver = StorageUriResolver::for_test();
+        let foreign_metastore =
+            SingleFileMetastore::new(storage_resolver.resolve("ram://other-bucket")?);
+        foreign_metastore
+            .create_index(IndexMetadata {
+                index_id: "test-index".to_string(),
+                index_uri: QuickwitUri::from_well_formed("s3://other-bucket/test-index"),
+                mirror_index_uri: Some(QuickwitUri::from_well_formed(
+                    "s3://other-mirror/test-index",
+                )),
+                index_config: Arc::new(WikipediaIndexConfig::new()),
+                index_config_history: Vec::new(),
+                checkpoint: Checkpoint::default(),
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
+            })
+            .await?;
+        for split_id in &["split1", "split2", "split3"] {
+            foreign_metastore
+                .stage_split(
+                    "test-index",
+                    SplitMetadataAndFooterOffsets {
+                        split_metadata: SplitMetadata {
+                            split_id: split_id.to_string(),
+                            ..Default::default()
+                        },
+                        footer_offsets: 0..6,
+                    },
+                )
+                .await?;
+        }
+        foreign_metastore
+            .publish_splits(
+                "test-index",
+                &["split1", "split2"],
+                CheckpointDelta::default(),
+            )
+            .await?;
+
+        let metastore = SingleFileMetastore::for_test();
+        let (index_id, file_entries) = import_index_metadata(
+            &metastore,
+            "ram://other-bucket/test-index",
+            &storage_resolver,
+        )
+        .await?;
+        assert_eq!(index_id, "test-index");
+        let file_names: Vec<&str> = file_entries
+            .iter()
+            .map(|file_entry| file_entry.file_name.as_str())
+            .collect();
+        assert_eq!(file_names, vec!["split1.split", "split2.split"]);
+
+        let index_metadata = metastore.index_metadata("test-index").await?;
+        assert_eq!(index_metadata.index_uri, "ram://other-bucket/test-index");
+        assert!(index_metadata.mirror_index_uri.is_none());
+        assert!(index_metadata.read_only);
+        let mut split_ids: Vec<String> = metastore
+            .list_splits("test-index", SplitState::Published, None, &[])
+            .await?
+            .into_iter()
+            .map(|split| split.split_metadata.split_id)
+            .collect();
+        split_ids.sort();
+        assert_eq!(split_ids, vec!["split1", "split2"]);
+        assert!(ensure_not_read_only(&index_metadata, "reset").is_err());
+
+        // The index is already attached.
+        assert!(import_index_metadata(
+            &metastore,
+            "ram://other-bucket/test-index",
+            &storage_resolver
+        )
+        .await
+        .is_err());
+        assert!(import_index_metadata(
+            &metastore,
+            "ram://other-bucket/no-index",
+            &storage_resolver
+        )
+        .await
+        .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_checkpoint_positions() -> anyhow::Result<()> {
+        let metastore = SingleFileMetastore::for_test();
+        metastore
+            .create_index(IndexMetadata {
+                index_id: "test-index".to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/test-index"),
+                mirror_index_uri: None,
+                index_config: Arc::new(WikipediaIndexConfig::new()),
+                index_config_history: Vec::new(),
+                checkpoint: Checkpoint::default(),
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
+            })
+            .await?;
+        let mut checkpoint_delta = CheckpointDelta::from_partition_delta(
+            PartitionId::from(0i32),
+            Position::Beginning,
+            Position::from(120i64),
+        );
+        checkpoint_delta.record_partition_delta(
+            PartitionId::from(1i32),
+            Position::Beginning,
+            Position::from(80i64),
+        )?;
+        metastore
+            .publish_splits("test-index", &[], checkpoint_delta)
+            .await?;
+
+        let positions = vec![
+            ("0".to_string(), "100".to_string()),
+            ("2".to_string(), "".to_string()),
+        ];
+        let changes =
+            set_checkpoint_positions_in_metastore(&metastore, "test-index", &positions, true)
+                .await?;
+        assert_eq!(
+            changes,
+            vec![
+                CheckpointPositionChange {
+                    partition_id: "0000000000".to_string(),
+                    from: Some("00000000000000000120".to_string()),
+                    to: "00000000000000000100".to_string(),
+                },
+                CheckpointPositionChange {
+                    partition_id: "0000000002".to_string(),
+                    from: None,
+                    to: "".to_string(),
+                },
+            ]
+        );
+        // A dry run leaves the checkpoint untouched.
+        let checkpoint = metastore.index_metadata("test-index").await?.checkpoint;
+        assert_eq!(
+            format!("{:?}", checkpoint),
+            "Ckpt(0000000000:00000000000000000120 0000000001:00000000000000000080)"
+        );
+
+        set_checkpoint_positions_in_metastore(&metastore, "test-index", &positions, false).await?;
+        let checkpoint = metastore.index_metadata("test-index").await?.checkpoint;
+        assert_eq!(
+            format!("{:?}", checkpoint),
+            "Ckpt(0000000000:00000000000000000100 0000000001:00000000000000000080 0000000002:)"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_index_labels() -> anyhow::Result<()> {
+        let metastore = SingleFileMetastore::for_test();
+        metastore
+            .create_index(IndexMetadata {
+                index_id: "test-index".to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/test-index"),
+                mirror_index_uri: None,
+                index_config: Arc::new(WikipediaIndexConfig::new()),
+                index_config_history: Vec::new(),
+                checkpoint: Checkpoint::default(),
+                read_only: false,
+                description: Some("Web server logs".to_string()),
+                labels: vec![
+                    ("env".to_string(), "staging".to_string()),
+                    ("team".to_string(), "search".to_string()),
+                ]
+                .into_iter()
+                .collect(),
+                create_timestamp: 0,
+            })
+            .await?;
+
+        let index_description = update_index_labels_in_metastore(
+            &metastore,
+            "test-index",
+            None,
+            &[
+                ("env".to_string(), "prod".to_string()),
+                ("cost-center".to_string(), "cc-42".to_string()),
+            ],
+            &["team".to_string()],
+        )
+        .await?;
+        assert_eq!(
+            index_description.description.as_deref(),
+            Some("Web server logs")
+        );
+        assert_eq!(
+            index_description.labels.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("cost-center".to_string(), "cc-42".to_string()),
+                ("env".to_string(), "prod".to_string()),
+            ]
+        );
+        assert_eq!(index_description.num_published_splits, 0);
+
+        update_index_labels_in_metastore(&metastore, "test-index", Some(""), &[], &[]).await?;
+        let index_metadata = metastore.index_metadata("test-index").await?;
+        assert_eq!(index_metadata.description, None);
+        assert_eq!(index_metadata.labels.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_index_config_of_read_only_index_fails() -> anyhow::Result<()> {
+        let metastore = SingleFileMetastore::for_test();
+        metastore
+            .create_index(IndexMetadata {
+                index_id: "test-index".to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/test-index"),
+                mirror_index_uri: None,
+                index_config: Arc::new(WikipediaIndexConfig::new()),
+                index_config_history: Vec::new(),
+                checkpoint: Checkpoint::default(),
+                read_only: true,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
+            })
+            .await?;
+        let error = update_index_config_in_metastore(
+            &metastore,
+            "test-index",
+            Arc::new(WikipediaIndexConfig::new()),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("is read-only"));
+        let index_metadata = metastore.index_metadata("test-index").await?;
+        assert_eq!(index_metadata.index_config_version(), 0);
+        Ok(())
+    }
+}
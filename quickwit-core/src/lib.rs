@@ -25,10 +25,27 @@
 //! - `index_data` for indexing new-line delimited json documents
 //! - `search_index` for searching an index
 //! - `delete_index` for deleting an index
+//! - `delete_splits_in_time_range` for deleting the splits of an index within a time range
+//! - `delete_quarantined_splits` for deleting the quarantined splits of an index
+//! - `verify_index_splits` for verifying the integrity of the split files of an index
+//! - `attach_index` for attaching an index of another cluster in read-only mode
+//! - `put_companion_file`, `get_companion_file`, `list_companion_files` and `delete_companion_file`
+//!   for managing the companion files of an index
+//! - `index_checkpoint` and `set_checkpoint_positions` for inspecting and repositioning the
+//!   checkpoint of an index
+//! - `describe_index` and `update_index_labels` for inspecting an index and updating its
+//!   description and labels
+//! - `update_index_config` for replacing the index config of an index
 
 mod index;
 
-pub use index::{create_index, delete_index, garbage_collect_index, reset_index};
+pub use index::{
+    attach_index, clone_index, create_index, delete_companion_file, delete_index,
+    delete_quarantined_splits, delete_splits_in_time_range, describe_index, garbage_collect_index,
+    get_companion_file, index_checkpoint, list_companion_files, mirror_index, put_companion_file,
+    reset_index, set_checkpoint_positions, update_index_config, update_index_labels,
+    verify_index_splits, CheckpointPositionChange, IndexDescription, MirrorReconciliation,
+};
 
 #[cfg(test)]
 mod tests {
@@ -104,6 +104,13 @@ impl BundleDirectory {
         Ok(files_and_size)
     }
 
+    /// Get the offsets of the files of a split, relative to the start of the split file.
+    pub fn get_file_offsets_split(data: Bytes) -> io::Result<BundleStorageFileOffsets> {
+        let split_file = FileSlice::new(Box::new(OwnedBytes::new(BytesWrapper(data))));
+        let (body_and_bundle_metadata, _hot_cache) = split_footer(split_file)?;
+        BundleStorageFileOffsets::open_from_file_slice(body_and_bundle_metadata)
+    }
+
     /// Opens a split file.
     pub fn open_split(split_file: FileSlice) -> io::Result<BundleDirectory> {
         // First we remove the hotcache from our file slice.
@@ -17,13 +17,15 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::HashMap;
 use std::ops::{Deref, Range};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::{fmt, io};
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use futures::future::try_join_all;
 use quickwit_storage::SliceCache;
 use stable_deref_trait::StableDeref;
 use tantivy::directory::error::{DeleteError, OpenReadError, OpenWriteError};
@@ -36,6 +38,7 @@ pub struct CachingDirectory {
     underlying: Arc<dyn Directory>,
     // TODO fixme: that's a pretty ugly cache we have here.
     cache: Arc<SliceCache>,
+    pinned_files: Arc<RwLock<HashMap<PathBuf, Bytes>>>,
 }
 
 impl CachingDirectory {
@@ -57,6 +60,7 @@ impl CachingDirectory {
         CachingDirectory {
             underlying,
             cache: Arc::new(SliceCache::with_capacity_in_bytes(capacity_in_bytes)),
+            pinned_files: Default::default(),
         }
     }
 
@@ -69,8 +73,40 @@ impl CachingDirectory {
         CachingDirectory {
             underlying,
             cache: Arc::new(SliceCache::with_infinite_capacity()),
+            pinned_files: Default::default(),
         }
     }
+
+    /// Pins the entire content of a file in memory.
+    ///
+    /// Reads of a pinned file are served from `file_bytes` and never reach the
+    /// underlying directory. Pinned files do not count in the cache capacity.
+    /// Only the file handles obtained after the call are affected.
+    pub fn pin_file(&self, path: PathBuf, file_bytes: Bytes) {
+        self.pinned_files.write().unwrap().insert(path, file_bytes);
+    }
+
+    /// Returns the number of bytes held by the cache. Pinned files are not counted.
+    pub fn num_bytes(&self) -> usize {
+        self.cache.num_bytes()
+    }
+
+    /// Fetches the given file slices from the underlying directory concurrently and
+    /// stores them in the cache.
+    ///
+    /// It makes it possible to run a synchronous tantivy search over a directory that only
+    /// supports async reads, like the [`StorageDirectory`](crate::StorageDirectory), by
+    /// prefetching the slices the search will read. Afterwards, only the reads of these
+    /// exact byte ranges are served from the cache.
+    pub async fn prefetch(&self, slices: &[(PathBuf, Range<usize>)]) -> anyhow::Result<()> {
+        let prefetch_futures = slices.iter().map(|(path, byte_range)| async move {
+            let file_handle = self.get_file_handle(path)?;
+            file_handle.read_bytes_async(byte_range.clone()).await?;
+            anyhow::Result::<()>::Ok(())
+        });
+        try_join_all(prefetch_futures).await?;
+        Ok(())
+    }
 }
 
 impl fmt::Debug for CachingDirectory {
@@ -82,6 +118,7 @@ impl fmt::Debug for CachingDirectory {
 struct CachingFileHandle {
     path: PathBuf,
     cache: Arc<SliceCache>,
+    pinned_bytes_opt: Option<Bytes>,
     underlying_filehandle: Box<dyn FileHandle>,
 }
 
@@ -109,6 +146,10 @@ impl Deref for BytesWrapper {
 #[async_trait]
 impl FileHandle for CachingFileHandle {
     fn read_bytes(&self, byte_range: Range<usize>) -> io::Result<OwnedBytes> {
+        if let Some(pinned_bytes) = &self.pinned_bytes_opt {
+            let pinned_slice = pinned_bytes.slice(byte_range);
+            return Ok(OwnedBytes::new(BytesWrapper(pinned_slice)));
+        }
         if let Some(bytes) = self.cache.get(&self.path, byte_range.clone()) {
             return Ok(OwnedBytes::new(BytesWrapper(bytes)));
         }
@@ -122,6 +163,10 @@ impl FileHandle for CachingFileHandle {
     }
 
     async fn read_bytes_async(&self, byte_range: Range<usize>) -> AsyncIoResult<OwnedBytes> {
+        if let Some(pinned_bytes) = &self.pinned_bytes_opt {
+            let pinned_slice = pinned_bytes.slice(byte_range);
+            return Ok(OwnedBytes::new(BytesWrapper(pinned_slice)));
+        }
         if let Some(owned_bytes) = self.cache.get(&self.path, byte_range.clone()) {
             return Ok(OwnedBytes::new(BytesWrapper(owned_bytes)));
         }
@@ -157,6 +202,7 @@ impl Directory for CachingDirectory {
         let caching_file_handle = CachingFileHandle {
             path: path.to_path_buf(),
             cache: self.cache.clone(),
+            pinned_bytes_opt: self.pinned_files.read().unwrap().get(path).cloned(),
             underlying_filehandle,
         };
         Ok(Box::new(caching_file_handle))
@@ -197,6 +243,7 @@ mod tests {
     use std::path::Path;
     use std::sync::Arc;
 
+    use bytes::Bytes;
     use tantivy::directory::RamDirectory;
     use tantivy::Directory;
 
@@ -216,4 +263,38 @@ mod tests {
         assert_eq!(debug_proxy_directory.drain_read_operations().count(), 1);
         Ok(())
     }
+
+    #[test]
+    fn test_caching_directory_pinned_file() -> tantivy::Result<()> {
+        let ram_directory = RamDirectory::default();
+        let test_path = Path::new("test");
+        ram_directory.atomic_write(test_path, &b"test"[..])?;
+        let debug_proxy_directory = Arc::new(DebugProxyDirectory::wrap(ram_directory));
+        let caching_directory =
+            CachingDirectory::new_with_capacity_in_bytes(debug_proxy_directory.clone(), 10_000);
+        caching_directory.pin_file(test_path.to_path_buf(), Bytes::from_static(b"test"));
+        let file_handle = caching_directory.get_file_handle(test_path)?;
+        assert_eq!(file_handle.read_bytes(1..3)?.as_slice(), b"es");
+        assert_eq!(caching_directory.atomic_read(test_path)?, b"test");
+        assert_eq!(debug_proxy_directory.drain_read_operations().count(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_caching_directory_prefetch() -> anyhow::Result<()> {
+        let ram_directory = RamDirectory::default();
+        let test_path = Path::new("test");
+        ram_directory.atomic_write(test_path, &b"abcdef"[..])?;
+        let debug_proxy_directory = Arc::new(DebugProxyDirectory::wrap(ram_directory));
+        let caching_directory =
+            CachingDirectory::new_with_unlimited_capacity(debug_proxy_directory.clone());
+        caching_directory
+            .prefetch(&[(test_path.to_path_buf(), 1..3)])
+            .await?;
+        assert_eq!(debug_proxy_directory.drain_read_operations().count(), 1);
+        let file_handle = caching_directory.get_file_handle(test_path)?;
+        assert_eq!(file_handle.read_bytes(1..3)?.as_slice(), b"bc");
+        assert_eq!(debug_proxy_directory.drain_read_operations().count(), 0);
+        Ok(())
+    }
 }
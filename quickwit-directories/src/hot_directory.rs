@@ -31,10 +31,11 @@ use tantivy::directory::{
     DirectoryLock, FileHandle, FileSlice, OwnedBytes, WatchCallback, WatchHandle,
 };
 use tantivy::error::DataCorruption;
+use tantivy::schema::{Cardinality, FieldEntry, FieldType};
 use tantivy::{AsyncIoResult, Directory, HasLen, Index, IndexReader, ReloadPolicy};
 
 use crate::caching_directory::BytesWrapper;
-use crate::{CachingDirectory, DebugProxyDirectory};
+use crate::{CachingDirectory, DebugProxyDirectory, SPLIT_STATS_FILENAME};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct SliceCacheIndexEntry {
@@ -482,12 +483,52 @@ fn list_index_files(index: &Index) -> tantivy::Result<HashSet<PathBuf>> {
     Ok(files)
 }
 
+/// Defines which parts of a split are copied into its hotcache.
+///
+/// The term dictionaries of the indexed fields are always copied, as searches read them
+/// synchronously.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HotcacheOptions {
+    /// Copies the fieldnorms of the indexed fields.
+    pub fieldnorms: bool,
+    /// Names of the fast fields copied in full.
+    ///
+    /// The fields missing from the split schema are ignored.
+    pub fast_field_names: Vec<String>,
+}
+
+impl Default for HotcacheOptions {
+    fn default() -> Self {
+        HotcacheOptions {
+            fieldnorms: false,
+            fast_field_names: Vec::new(),
+        }
+    }
+}
+
+/// Returns the number of files of the composite fast field file used by a fast field.
+fn num_fast_field_parts(field_entry: &FieldEntry) -> usize {
+    match field_entry.field_type() {
+        FieldType::U64(options)
+        | FieldType::I64(options)
+        | FieldType::F64(options)
+        | FieldType::Date(options)
+            if options.get_fastfield_cardinality() == Some(Cardinality::MultiValues) =>
+        {
+            2
+        }
+        FieldType::Bytes(_) => 2,
+        _ => 1,
+    }
+}
+
 /// Given a tantivy directory, automatically identify the parts that should be loaded on startup
 /// and writes a static cache file called hotcache in the `output`.
 ///
 /// See [`HotDirectory`] for more information.
 pub fn write_hotcache<D: Directory>(
     directory: D,
+    options: &HotcacheOptions,
     output: &mut dyn io::Write,
 ) -> tantivy::Result<()> {
     // We use the caching directory here in order to defensively ensure that
@@ -509,8 +550,37 @@ pub fn write_hotcache<D: Directory>(
         }
         for reader in searcher.segment_readers() {
             let _inv_idx = reader.inverted_index(field)?;
+            if options.fieldnorms {
+                let _fieldnorms_reader = reader.get_fieldnorms_reader(field)?;
+            }
         }
     }
+    for fast_field_name in &options.fast_field_names {
+        let field = if let Some(field) = schema.get_field(fast_field_name) {
+            field
+        } else {
+            continue;
+        };
+        let field_entry = schema.get_field_entry(field);
+        if !field_entry.is_fast() {
+            continue;
+        }
+        for reader in searcher.segment_readers() {
+            for idx in 0..num_fast_field_parts(field_entry) {
+                let fast_field_slice = reader.fast_fields().fast_field_data(field, idx)?;
+                fast_field_slice.read_bytes()?;
+            }
+        }
+    }
+    // The split stats are small, and read by the query planner of every search.
+    let split_stats_path = Path::new(SPLIT_STATS_FILENAME);
+    match debug_proxy_directory.open_read(split_stats_path) {
+        Ok(split_stats_slice) => {
+            split_stats_slice.read_bytes()?;
+        }
+        Err(OpenReadError::FileDoesNotExist(_)) => {}
+        Err(error) => return Err(error.into()),
+    }
     let mut cache_builder = StaticDirectoryCacheBuilder::default();
     let read_operations = debug_proxy_directory.drain_read_operations();
     let mut per_file_slices: HashMap<PathBuf, HashSet<Range<usize>>> = HashMap::default();
@@ -520,7 +590,8 @@ pub fn write_hotcache<D: Directory>(
             .or_default()
             .insert(read_operation.offset..read_operation.offset + read_operation.num_bytes);
     }
-    let index_files = list_index_files(&index)?;
+    let mut index_files = list_index_files(&index)?;
+    index_files.insert(split_stats_path.to_path_buf());
     for file_path in index_files {
         let file_slice_res = debug_proxy_directory.open_read(&file_path);
         if let Err(tantivy::directory::error::OpenReadError::FileDoesNotExist(_)) = file_slice_res {
@@ -728,4 +799,85 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_write_hotcache_with_options() -> anyhow::Result<()> {
+        use tantivy::directory::MmapDirectory;
+        use tantivy::doc;
+        use tantivy::schema::{Schema, FAST, TEXT};
+
+        let tempdir = tempfile::tempdir()?;
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let timestamp_field = schema_builder.add_u64_field("timestamp", FAST);
+        let index = Index::create_in_dir(tempdir.path(), schema_builder.build())?;
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        for timestamp in 0..1_000u64 {
+            index_writer.add_document(doc!(
+                text_field => format!("timestamp is {}", timestamp),
+                timestamp_field => timestamp * 1_000_003
+            ));
+        }
+        index_writer.commit()?;
+
+        let hotcache_num_bytes = |options: &HotcacheOptions| -> anyhow::Result<usize> {
+            let mut hotcache = Vec::new();
+            write_hotcache(MmapDirectory::open(tempdir.path())?, options, &mut hotcache)?;
+            Ok(hotcache.len())
+        };
+        let default_num_bytes = hotcache_num_bytes(&HotcacheOptions::default())?;
+        let fieldnorms_num_bytes = hotcache_num_bytes(&HotcacheOptions {
+            fieldnorms: true,
+            ..Default::default()
+        })?;
+        let fast_field_num_bytes = hotcache_num_bytes(&HotcacheOptions {
+            fast_field_names: vec!["timestamp".to_string(), "unknown".to_string()],
+            ..Default::default()
+        })?;
+        assert!(default_num_bytes < fieldnorms_num_bytes);
+        assert!(default_num_bytes + 1_000 < fast_field_num_bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_hotcache_with_split_stats() -> anyhow::Result<()> {
+        use tantivy::directory::{MmapDirectory, RamDirectory};
+        use tantivy::doc;
+        use tantivy::schema::{Schema, TEXT};
+
+        use crate::SplitStats;
+
+        let tempdir = tempfile::tempdir()?;
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_dir(tempdir.path(), schema_builder.build())?;
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        index_writer.add_document(doc!(text_field => "hello happy tax payer"));
+        index_writer.commit()?;
+
+        let mut hotcache = Vec::new();
+        write_hotcache(
+            MmapDirectory::open(tempdir.path())?,
+            &HotcacheOptions::default(),
+            &mut hotcache,
+        )?;
+        let hot_directory = HotDirectory::open(RamDirectory::create(), Bytes::from(hotcache))?;
+        assert_eq!(SplitStats::open(&hot_directory)?, None);
+
+        let split_stats = SplitStats::compute(&index.reader()?.searcher())?;
+        std::fs::write(
+            tempdir.path().join(SPLIT_STATS_FILENAME),
+            serde_json::to_vec(&split_stats)?,
+        )?;
+        let mut hotcache = Vec::new();
+        write_hotcache(
+            MmapDirectory::open(tempdir.path())?,
+            &HotcacheOptions::default(),
+            &mut hotcache,
+        )?;
+        // The stats are read from the hotcache, as the underlying directory is empty.
+        let hot_directory = HotDirectory::open(RamDirectory::create(), Bytes::from(hotcache))?;
+        assert_eq!(SplitStats::open(&hot_directory)?, Some(split_stats));
+        Ok(())
+    }
 }
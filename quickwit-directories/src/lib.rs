@@ -19,25 +19,32 @@
 
 //! This crate contains all of the building pieces that make quickwit's IO possible.
 //!
-//! - The `StorageDirectory` justs wraps a `Storage` trait to make it compatible with tantivy's
-//!   Directory API.
+//! - The `StorageDirectory` wraps a `Storage` trait to make it compatible with tantivy's Directory
+//!   API. Combined with the `CachingDirectory` and its async prefetching, it makes it possible to
+//!   search an index located on an object storage.
 //! - The `BundleDirectory` bundles multiple files into a single file.
 //! - The `HotDirectory` wraps another directory with a static cache.
 //! - The `CachingDirectory` wraps a Directory with a dynamic cache.
 //! - The `DebugDirectory` acts as a proxy to another directory to instrument it and record all of
 //!   its IO.
+//! - The `SplitStats` describe the content of a split, and are written into its bundle.
+//! - `verify_split` checks the integrity of a split file.
 #![warn(missing_docs)]
 
 mod bundle_directory;
 mod caching_directory;
 mod debug_proxy_directory;
 mod hot_directory;
+mod split_stats;
+mod split_verification;
 mod storage_directory;
 mod union_directory;
 
 pub use self::bundle_directory::{get_hotcache_from_split, read_split_footer, BundleDirectory};
 pub use self::caching_directory::CachingDirectory;
 pub use self::debug_proxy_directory::{DebugProxyDirectory, ReadOperation};
-pub use self::hot_directory::{write_hotcache, HotDirectory};
+pub use self::hot_directory::{write_hotcache, HotDirectory, HotcacheOptions};
+pub use self::split_stats::{FastFieldValue, FieldStats, SplitStats, SPLIT_STATS_FILENAME};
+pub use self::split_verification::{verify_split, SplitCorruption};
 pub use self::storage_directory::StorageDirectory;
 pub use self::union_directory::UnionDirectory;
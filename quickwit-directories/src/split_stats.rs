@@ -0,0 +1,288 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use bytes::Bytes;
+use quickwit_storage::Storage;
+use serde::{Deserialize, Serialize};
+use tantivy::directory::Directory;
+use tantivy::fastfield::{FastFieldReader, FastValue};
+use tantivy::schema::{Cardinality, Field, FieldType, IntOptions};
+use tantivy::{Searcher, SegmentReader, TantivyError};
+
+use crate::BundleDirectory;
+
+/// Name of the file of the bundle holding the statistics of a split.
+pub const SPLIT_STATS_FILENAME: &str = "split_stats.json";
+
+/// Statistics about the content of a split, written into its bundle when it is packaged.
+///
+/// They describe the split without opening its index, e.g. to decide in which order the
+/// clauses of a query should be executed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SplitStats {
+    /// Number of documents of the split.
+    pub num_docs: u64,
+    /// Statistics of the indexed and fast fields, by field name.
+    pub fields: BTreeMap<String, FieldStats>,
+}
+
+/// Statistics about the values of a field of a split.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FieldStats {
+    /// Number of documents with at least one token in the field, for the text fields with
+    /// fieldnorms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_docs: Option<u64>,
+    /// Number of distinct terms of the field, for the indexed fields.
+    ///
+    /// This approximates the cardinality of the field: the terms of a split with several
+    /// segments are counted once per segment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_terms: Option<u64>,
+    /// Total number of tokens of the field, for the indexed text fields.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_num_tokens: Option<u64>,
+    /// Largest (approximate) number of tokens of the field in a document, for the text fields
+    /// with fieldnorms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_num_tokens: Option<u32>,
+    /// Smallest value of the field, for the single-valued numeric fast fields.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_value: Option<FastFieldValue>,
+    /// Largest value of the field, for the single-valued numeric fast fields.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_value: Option<FastFieldValue>,
+}
+
+impl FieldStats {
+    /// Returns the average number of tokens of the field in the documents that have it.
+    pub fn avg_num_tokens(&self) -> Option<f64> {
+        let num_docs = self.num_docs.filter(|num_docs| *num_docs > 0)?;
+        Some(self.total_num_tokens? as f64 / num_docs as f64)
+    }
+}
+
+/// Value of a numeric fast field.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FastFieldValue {
+    /// Value of a `u64` field.
+    U64(u64),
+    /// Value of an `i64` or date field.
+    I64(i64),
+    /// Value of an `f64` field.
+    F64(f64),
+}
+
+impl SplitStats {
+    /// Computes the statistics of the split read by `searcher`.
+    pub fn compute(searcher: &Searcher) -> tantivy::Result<SplitStats> {
+        let schema = searcher.schema();
+        let mut fields = BTreeMap::new();
+        for (field, field_entry) in schema.fields() {
+            if !field_entry.is_indexed() && !field_entry.is_fast() {
+                continue;
+            }
+            let mut field_stats = FieldStats::default();
+            for segment_reader in searcher.segment_readers() {
+                if segment_reader.num_docs() == 0 {
+                    continue;
+                }
+                add_segment_field_stats(segment_reader, field, &mut field_stats)?;
+            }
+            fields.insert(field_entry.name().to_string(), field_stats);
+        }
+        Ok(SplitStats {
+            num_docs: searcher.num_docs(),
+            fields,
+        })
+    }
+
+    /// Reads the statistics of the split of `directory`, which are copied into the hotcache
+    /// of the split.
+    ///
+    /// Returns `None` for the splits packaged before the statistics were introduced.
+    pub fn open(directory: &dyn Directory) -> tantivy::Result<Option<SplitStats>> {
+        let stats_path = Path::new(SPLIT_STATS_FILENAME);
+        if !directory.exists(stats_path)? {
+            return Ok(None);
+        }
+        let stats_bytes = directory.atomic_read(stats_path)?;
+        let split_stats = serde_json::from_slice(&stats_bytes).map_err(|error| {
+            TantivyError::InternalError(format!("Failed to parse the split stats: {}", error))
+        })?;
+        Ok(Some(split_stats))
+    }
+
+    /// Reads the statistics of the split file at `split_path`, given its footer as returned
+    /// by `read_split_footer`.
+    ///
+    /// Returns `None` for the splits packaged before the statistics were introduced.
+    pub async fn read_from_split(
+        storage: &dyn Storage,
+        split_path: &Path,
+        split_footer: Bytes,
+    ) -> anyhow::Result<Option<SplitStats>> {
+        let file_offsets = BundleDirectory::get_file_offsets_split(split_footer)?;
+        let stats_range =
+            if let Some(stats_range) = file_offsets.get(Path::new(SPLIT_STATS_FILENAME)) {
+                stats_range
+            } else {
+                return Ok(None);
+            };
+        let stats_bytes = storage.get_slice(split_path, stats_range).await?;
+        Ok(Some(serde_json::from_slice(&stats_bytes)?))
+    }
+}
+
+fn add_segment_field_stats(
+    segment_reader: &SegmentReader,
+    field: Field,
+    field_stats: &mut FieldStats,
+) -> tantivy::Result<()> {
+    let field_entry = segment_reader.schema().get_field_entry(field);
+    if field_entry.is_indexed() {
+        let inverted_index = segment_reader.inverted_index(field)?;
+        *field_stats.num_terms.get_or_insert(0) += inverted_index.terms().num_terms() as u64;
+        if let FieldType::Str(_) = field_entry.field_type() {
+            *field_stats.total_num_tokens.get_or_insert(0) += inverted_index.total_num_tokens();
+            if let Ok(fieldnorm_reader) = segment_reader.get_fieldnorms_reader(field) {
+                for doc in segment_reader.doc_ids_alive() {
+                    let num_tokens = fieldnorm_reader.fieldnorm(doc);
+                    if num_tokens == 0 {
+                        continue;
+                    }
+                    *field_stats.num_docs.get_or_insert(0) += 1;
+                    let max_num_tokens = field_stats.max_num_tokens.get_or_insert(0);
+                    *max_num_tokens = (*max_num_tokens).max(num_tokens);
+                }
+            }
+        }
+    }
+    let fast_fields = segment_reader.fast_fields();
+    let (min_value, max_value) = match field_entry.field_type() {
+        FieldType::U64(options) if is_single_valued_fast(options) => {
+            let reader = fast_fields.u64(field)?;
+            (
+                FastFieldValue::U64(reader.min_value()),
+                FastFieldValue::U64(reader.max_value()),
+            )
+        }
+        FieldType::I64(options) | FieldType::Date(options) if is_single_valued_fast(options) => {
+            // Dates are stored as `i64` timestamps.
+            let reader = fast_fields.u64_lenient(field)?;
+            (
+                FastFieldValue::I64(i64::from_u64(reader.min_value())),
+                FastFieldValue::I64(i64::from_u64(reader.max_value())),
+            )
+        }
+        FieldType::F64(options) if is_single_valued_fast(options) => {
+            let reader = fast_fields.f64(field)?;
+            (
+                FastFieldValue::F64(reader.min_value()),
+                FastFieldValue::F64(reader.max_value()),
+            )
+        }
+        _ => return Ok(()),
+    };
+    if field_stats
+        .min_value
+        .map_or(true, |field_min_value| min_value < field_min_value)
+    {
+        field_stats.min_value = Some(min_value);
+    }
+    if field_stats
+        .max_value
+        .map_or(true, |field_max_value| max_value > field_max_value)
+    {
+        field_stats.max_value = Some(max_value);
+    }
+    Ok(())
+}
+
+fn is_single_valued_fast(options: &IntOptions) -> bool {
+    options.get_fastfield_cardinality() == Some(Cardinality::SingleValue)
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::directory::RamDirectory;
+    use tantivy::schema::{Schema, FAST, INDEXED, TEXT};
+    use tantivy::{doc, Index};
+
+    use super::*;
+
+    #[test]
+    fn test_split_stats() -> anyhow::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let body_field = schema_builder.add_text_field("body", TEXT);
+        let timestamp_field = schema_builder.add_i64_field("timestamp", FAST | INDEXED);
+        let score_field = schema_builder.add_f64_field("score", FAST);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        index_writer.add_document(doc!(
+            body_field => "one two three",
+            timestamp_field => 10i64,
+            score_field => 0.5f64
+        ));
+        index_writer.add_document(doc!(
+            timestamp_field => -5i64,
+            score_field => 2.0f64
+        ));
+        index_writer.add_document(doc!(
+            body_field => "one",
+            timestamp_field => 7i64,
+            score_field => 1.0f64
+        ));
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+        let split_stats = SplitStats::compute(&searcher)?;
+        assert_eq!(split_stats.num_docs, 3);
+
+        let body_stats = &split_stats.fields["body"];
+        assert_eq!(body_stats.num_docs, Some(2));
+        assert_eq!(body_stats.num_terms, Some(3));
+        assert_eq!(body_stats.total_num_tokens, Some(4));
+        assert_eq!(body_stats.max_num_tokens, Some(3));
+        assert_eq!(body_stats.avg_num_tokens(), Some(2.0));
+        assert_eq!(body_stats.min_value, None);
+
+        let timestamp_stats = &split_stats.fields["timestamp"];
+        assert_eq!(timestamp_stats.num_terms, Some(3));
+        assert_eq!(timestamp_stats.min_value, Some(FastFieldValue::I64(-5)));
+        assert_eq!(timestamp_stats.max_value, Some(FastFieldValue::I64(10)));
+
+        let score_stats = &split_stats.fields["score"];
+        assert_eq!(score_stats.num_terms, None);
+        assert_eq!(score_stats.min_value, Some(FastFieldValue::F64(0.5)));
+        assert_eq!(score_stats.max_value, Some(FastFieldValue::F64(2.0)));
+
+        let directory = RamDirectory::create();
+        assert_eq!(SplitStats::open(&directory)?, None);
+        directory.atomic_write(
+            Path::new(SPLIT_STATS_FILENAME),
+            &serde_json::to_vec(&split_stats)?,
+        )?;
+        assert_eq!(SplitStats::open(&directory)?, Some(split_stats));
+        Ok(())
+    }
+}
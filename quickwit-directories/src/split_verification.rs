@@ -0,0 +1,243 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::convert::TryInto;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use quickwit_storage::BundleStorageFileOffsets;
+use tantivy::directory::FileSlice;
+use tantivy::{HasLen, Index};
+use thiserror::Error;
+
+use crate::BundleDirectory;
+
+/// Number of bytes used to encode the length of the footers of a split file.
+const FOOTER_LENGTH_NUM_BYTES: usize = std::mem::size_of::<u64>();
+
+/// Corruption detected while verifying a split file.
+#[derive(Debug, Error)]
+pub enum SplitCorruption {
+    /// The split file is too short to hold the footer lengths it declares.
+    #[error("The split file is truncated: {0}.")]
+    Truncated(String),
+    /// The bundle metadata cannot be read.
+    #[error("The bundle metadata is invalid: {0}.")]
+    InvalidBundleMetadata(io::Error),
+    /// A file of the bundle lies outside of the bundle body.
+    #[error(
+        "The file `{path:?}` has offsets {range:?} out of the bundle body ({body_num_bytes} \
+         bytes)."
+    )]
+    InvalidFileOffsets {
+        /// Path of the file within the bundle.
+        path: PathBuf,
+        /// Offsets of the file within the bundle.
+        range: Range<usize>,
+        /// Length of the bundle body.
+        body_num_bytes: usize,
+    },
+    /// The bundle does not hold a tantivy index.
+    #[error("The index cannot be opened: {0}.")]
+    InvalidIndex(tantivy::TantivyError),
+    /// The checksum or the footer of some tantivy files does not match their content.
+    #[error("The checksum of the files {0:?} does not match their content.")]
+    ChecksumMismatch(Vec<PathBuf>),
+}
+
+/// Splits `file_slice` into its body and its footer, whose length is encoded in the last bytes
+/// of the file slice, after checking that the declared footer length fits in the file slice.
+fn split_footer_checked(
+    file_slice: FileSlice,
+    footer_name: &str,
+) -> Result<(FileSlice, FileSlice), SplitCorruption> {
+    if file_slice.len() < FOOTER_LENGTH_NUM_BYTES {
+        return Err(SplitCorruption::Truncated(format!(
+            "{} bytes cannot hold the length of the {}",
+            file_slice.len(),
+            footer_name
+        )));
+    }
+    let (body_and_footer, footer_len_slice) = file_slice.split_from_end(FOOTER_LENGTH_NUM_BYTES);
+    let footer_len_bytes = footer_len_slice
+        .read_bytes()
+        .map_err(|error| SplitCorruption::Truncated(error.to_string()))?;
+    let footer_len = u64::from_le_bytes(footer_len_bytes.as_slice().try_into().unwrap());
+    if footer_len > body_and_footer.len() as u64 {
+        return Err(SplitCorruption::Truncated(format!(
+            "the {} is declared to be {} bytes long but only {} bytes precede it",
+            footer_name,
+            footer_len,
+            body_and_footer.len()
+        )));
+    }
+    Ok(body_and_footer.split_from_end(footer_len as usize))
+}
+
+/// Verifies the integrity of a split file:
+/// - the hotcache and bundle metadata footers are consistent with the length of the file,
+/// - the bundle metadata can be parsed and the files of the bundle lie within its body,
+/// - the bundle holds a tantivy index, and the footer magic number and checksum of each of its
+///   segment files match their content.
+///
+/// The files that tantivy does not checksum, such as `meta.json`, the split stats and the
+/// hotcache, are only checked for being readable.
+pub fn verify_split(split_file: FileSlice) -> Result<(), SplitCorruption> {
+    let (body_and_bundle_metadata, _hotcache) =
+        split_footer_checked(split_file.clone(), "hotcache")?;
+    let (body, _bundle_metadata) =
+        split_footer_checked(body_and_bundle_metadata.clone(), "bundle metadata")?;
+    let body_num_bytes = body.len();
+    let file_offsets = BundleStorageFileOffsets::open_from_file_slice(body_and_bundle_metadata)
+        .map_err(SplitCorruption::InvalidBundleMetadata)?;
+    for (path, range) in &file_offsets.files {
+        if range.start > range.end || range.end > body_num_bytes {
+            return Err(SplitCorruption::InvalidFileOffsets {
+                path: path.clone(),
+                range: range.clone(),
+                body_num_bytes,
+            });
+        }
+    }
+    let bundle_directory =
+        BundleDirectory::open_split(split_file).map_err(SplitCorruption::InvalidBundleMetadata)?;
+    let index = Index::open(bundle_directory).map_err(SplitCorruption::InvalidIndex)?;
+    let segment_metas = index
+        .searchable_segment_metas()
+        .map_err(SplitCorruption::InvalidIndex)?;
+    let mut corrupted_files = Vec::new();
+    for segment_meta in segment_metas {
+        for path in segment_meta.list_files() {
+            // `list_files` may return files that do not exist.
+            if !file_offsets.exists(&path) {
+                continue;
+            }
+            // A file whose footer cannot be read is reported as corrupted as well.
+            if !index.directory().validate_checksum(&path).unwrap_or(false) {
+                corrupted_files.push(path);
+            }
+        }
+    }
+    if !corrupted_files.is_empty() {
+        corrupted_files.sort();
+        return Err(SplitCorruption::ChecksumMismatch(corrupted_files));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use quickwit_storage::BundleStorageBuilder;
+    use tantivy::doc;
+    use tantivy::schema::{Schema, TEXT};
+
+    use super::*;
+
+    /// Builds a split holding a small index, laid out as the packager does.
+    fn build_split() -> anyhow::Result<Vec<u8>> {
+        let temp_dir = tempfile::tempdir()?;
+        let mut schema_builder = Schema::builder();
+        let text_field = schema_builder.add_text_field("text", TEXT);
+        let index = Index::create_in_dir(temp_dir.path(), schema_builder.build())?;
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        for doc_num in 0..100 {
+            index_writer.add_document(doc!(text_field => format!("document number {}", doc_num)));
+        }
+        index_writer.commit()?;
+
+        let mut split_file = Vec::new();
+        let mut bundle_builder = BundleStorageBuilder::new(&mut split_file)?;
+        for segment_meta in index.searchable_segment_metas()? {
+            for relative_path in segment_meta.list_files() {
+                let path = temp_dir.path().join(relative_path);
+                if path.exists() {
+                    bundle_builder.add_file(&path)?;
+                }
+            }
+        }
+        bundle_builder.add_file(&temp_dir.path().join("meta.json"))?;
+        bundle_builder.finalize()?;
+        let hotcache = [1u8, 2, 3, 4];
+        split_file.write_all(&hotcache)?;
+        split_file.write_all(&(hotcache.len() as u64).to_le_bytes())?;
+        Ok(split_file)
+    }
+
+    #[test]
+    fn test_verify_split() -> anyhow::Result<()> {
+        let split_file = build_split()?;
+        assert!(verify_split(FileSlice::from(split_file)).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_split_detects_bit_flip() -> anyhow::Result<()> {
+        let mut split_file = build_split()?;
+        // The bundle starts with the content of the first segment file.
+        split_file[10] ^= 1;
+        match verify_split(FileSlice::from(split_file)) {
+            Err(SplitCorruption::ChecksumMismatch(corrupted_files)) => {
+                assert_eq!(corrupted_files.len(), 1);
+            }
+            verification_result => panic!("Unexpected result: {:?}.", verification_result),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_split_detects_truncation() -> anyhow::Result<()> {
+        let split_file = build_split()?;
+        assert!(matches!(
+            verify_split(FileSlice::from(split_file[..4].to_vec())),
+            Err(SplitCorruption::Truncated(_))
+        ));
+        let mut split_file_with_invalid_length = split_file.clone();
+        let num_bytes = split_file_with_invalid_length.len();
+        split_file_with_invalid_length[num_bytes - 8..]
+            .copy_from_slice(&(num_bytes as u64).to_le_bytes());
+        assert!(matches!(
+            verify_split(FileSlice::from(split_file_with_invalid_length)),
+            Err(SplitCorruption::Truncated(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_split_detects_invalid_file_offsets() -> anyhow::Result<()> {
+        // The file `f1` extends past the 3 bytes of the bundle body.
+        let file_offsets_json = serde_json::to_vec(&serde_json::json!({
+            "files": {"f1": {"start": 0, "end": 30}}
+        }))?;
+        let mut split_file = vec![1, 2, 3];
+        split_file.extend_from_slice(&file_offsets_json);
+        split_file.extend_from_slice(&(file_offsets_json.len() as u64).to_le_bytes());
+        split_file.extend_from_slice(&0u64.to_le_bytes());
+        assert!(matches!(
+            verify_split(FileSlice::from(split_file)),
+            Err(SplitCorruption::InvalidFileOffsets {
+                body_num_bytes: 3,
+                ..
+            })
+        ));
+        Ok(())
+    }
+}
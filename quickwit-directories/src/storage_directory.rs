@@ -74,15 +74,30 @@ impl FileHandle for StorageDirectoryFileHandle {
     }
 }
 
-/// Directory backed a quickwit `Storage` abstraction.
+/// Directory backed by a quickwit `Storage` abstraction, such as an object storage.
 ///
-/// It should not be used in a context outside quickwit, as it contains
-/// several pitfalls:
-/// Fetching data synchronously panics.
-/// Writing data panics.
+/// This directory fetches slices of data from a possibly distant storage
+/// everytime a file is read. It has several pitfalls:
+/// - it only supports async reads, fetching data synchronously returns an error.
+/// - it is read-only.
+/// - the file handles it returns do not know the length of their file.
 ///
-/// This directory is fetch slices of data to a possibly distant storage
-/// everytime `read_bytes` is called.
+/// Tantivy search being synchronous, the `StorageDirectory` is meant to be wrapped
+/// in a [`CachingDirectory`](crate::CachingDirectory), and for Quickwit splits in a
+/// [`HotDirectory`](crate::HotDirectory) that knows the file lengths. The data required by
+/// a search is first prefetched asynchronously, using
+/// [`CachingDirectory::prefetch`](crate::CachingDirectory::prefetch) or tantivy's async
+/// warm-up methods, and the search then runs over the cached data:
+///
+/// ```ignore
+/// let storage_directory = StorageDirectory::new(storage);
+/// let caching_directory =
+///     CachingDirectory::new_with_unlimited_capacity(Arc::new(storage_directory));
+/// let hot_directory = HotDirectory::open(caching_directory.clone(), hotcache_bytes)?;
+/// let index = Index::open(hot_directory)?;
+/// caching_directory.prefetch(&slices_read_by_the_search).await?;
+/// let top_docs = index.reader()?.searcher().search(&query, &collector)?;
+/// ```
 #[derive(Clone)]
 pub struct StorageDirectory {
     storage: Arc<dyn Storage>,
@@ -0,0 +1,305 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+
+use chrono::{TimeZone as ChronoTimeZone, Utc};
+use quickwit_proto::QueryWarning;
+use tantivy::schema::{FieldType, Schema};
+use tantivy_query_grammar::{UserInputAst, UserInputLeaf, UserInputLiteral};
+
+use crate::default_index_config::BytesFormat;
+use crate::wildcard_query::is_pattern;
+use crate::TimeZone;
+
+/// How the value of a `field:value` clause is interpreted against the type of its field.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Coercion {
+    /// The value matches the type of the field.
+    Unchanged,
+    /// The value unambiguously stands for this value of the type of the field.
+    Coerced(String),
+    /// The value cannot be interpreted as a value of the field, so the clause matches nothing.
+    Mismatch,
+}
+
+fn type_name(field_type: &FieldType) -> &'static str {
+    match field_type {
+        FieldType::I64(_) => "i64",
+        FieldType::U64(_) => "u64",
+        FieldType::F64(_) => "f64",
+        FieldType::Date(_) => "date",
+        FieldType::Bytes(_) => "bytes",
+        _ => "text",
+    }
+}
+
+/// Returns the integer `value` stands for, if it is a float without fractional part.
+fn integral_float(value: &str) -> Option<f64> {
+    let float_value = value.parse::<f64>().ok()?;
+    if float_value.is_finite() && float_value.fract() == 0.0 {
+        Some(float_value)
+    } else {
+        None
+    }
+}
+
+/// Coerces `value` into a value of `field_type`.
+///
+/// Integers given as floats (`200.0`) and dates given to `i64` fields are coerced, as well
+/// as local dates (`2021-06-01`) given to `date` fields, which are interpreted in `time_zone`.
+pub(crate) fn coerce_value(field_type: &FieldType, value: &str, time_zone: &TimeZone) -> Coercion {
+    let trimmed_value = value.trim();
+    let coerced_value_opt = match field_type {
+        FieldType::I64(_) => {
+            if value.parse::<i64>().is_ok() {
+                return Coercion::Unchanged;
+            }
+            integral_float(trimmed_value)
+                .filter(|float_value| {
+                    *float_value >= i64::MIN as f64 && *float_value <= i64::MAX as f64
+                })
+                .map(|float_value| (float_value as i64).to_string())
+                .or_else(|| {
+                    time_zone
+                        .parse_timestamp(trimmed_value)
+                        .ok()
+                        .map(|timestamp| timestamp.to_string())
+                })
+        }
+        FieldType::U64(_) => {
+            if value.parse::<u64>().is_ok() {
+                return Coercion::Unchanged;
+            }
+            integral_float(trimmed_value)
+                .filter(|float_value| *float_value >= 0.0 && *float_value <= u64::MAX as f64)
+                .map(|float_value| (float_value as u64).to_string())
+        }
+        FieldType::F64(_) => {
+            if value.parse::<f64>().is_ok() {
+                return Coercion::Unchanged;
+            }
+            trimmed_value
+                .parse::<f64>()
+                .ok()
+                .map(|float_value| float_value.to_string())
+        }
+        FieldType::Date(_) => {
+            if chrono::DateTime::parse_from_rfc3339(value).is_ok() {
+                return Coercion::Unchanged;
+            }
+            time_zone
+                .parse_timestamp(trimmed_value)
+                .ok()
+                .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single())
+                .map(|date_time| date_time.to_rfc3339())
+        }
+        _ => return Coercion::Unchanged,
+    };
+    match coerced_value_opt {
+        Some(coerced_value) => Coercion::Coerced(coerced_value),
+        None => Coercion::Mismatch,
+    }
+}
+
+/// Returns how the value of the clause `literal` is interpreted, if it targets a field
+/// of `schema`.
+///
+/// Wildcard, regex and fuzzy terms are left out: they are only supported on text fields. The
+/// values of bytes fields are never coerced, they either are valid in the format of their
+/// field in `bytes_formats` or match nothing.
+pub(crate) fn coerce_literal(
+    schema: &Schema,
+    literal: &UserInputLiteral,
+    bytes_formats: &BTreeMap<String, BytesFormat>,
+    time_zone: &TimeZone,
+) -> Coercion {
+    if is_pattern(&literal.phrase) || is_fuzzy(&literal.phrase) {
+        return Coercion::Unchanged;
+    }
+    let (field_name, field) = match literal.field_name.as_ref().and_then(|field_name| {
+        schema
+            .get_field(field_name)
+            .map(|field| (field_name, field))
+    }) {
+        Some(field_name_and_field) => field_name_and_field,
+        None => return Coercion::Unchanged,
+    };
+    let field_type = schema.get_field_entry(field).field_type();
+    if let FieldType::Bytes(_) = field_type {
+        let format = bytes_formats.get(field_name).copied().unwrap_or_default();
+        return match format.decode(&literal.phrase) {
+            Some(_) => Coercion::Unchanged,
+            None => Coercion::Mismatch,
+        };
+    }
+    coerce_value(field_type, &literal.phrase, time_zone)
+}
+
+/// Returns a warning for each clause of the query whose value does not match the type
+/// of its field, either because it was coerced, or because it cannot match any document.
+pub(crate) fn query_warnings(
+    schema: &Schema,
+    user_input_ast: &UserInputAst,
+    bytes_formats: &BTreeMap<String, BytesFormat>,
+    time_zone: &TimeZone,
+) -> Vec<QueryWarning> {
+    let mut warnings = Vec::new();
+    collect_query_warnings(
+        schema,
+        user_input_ast,
+        bytes_formats,
+        time_zone,
+        &mut warnings,
+    );
+    warnings
+}
+
+fn collect_query_warnings(
+    schema: &Schema,
+    user_input_ast: &UserInputAst,
+    bytes_formats: &BTreeMap<String, BytesFormat>,
+    time_zone: &TimeZone,
+    warnings: &mut Vec<QueryWarning>,
+) {
+    let literal = match user_input_ast {
+        UserInputAst::Clause(sub_asts) => {
+            for (_, sub_ast) in sub_asts {
+                collect_query_warnings(schema, sub_ast, bytes_formats, time_zone, warnings);
+            }
+            return;
+        }
+        UserInputAst::Boost(ast, _) => {
+            collect_query_warnings(schema, ast, bytes_formats, time_zone, warnings);
+            return;
+        }
+        UserInputAst::Leaf(leaf) => match leaf.as_ref() {
+            UserInputLeaf::Literal(literal) => literal,
+            _ => return,
+        },
+    };
+    let field_name = literal.field_name.as_deref().unwrap_or_default();
+    let field_type_name = schema
+        .get_field(field_name)
+        .map(|field| type_name(schema.get_field_entry(field).field_type()))
+        .unwrap_or_default();
+    let message = match coerce_literal(schema, literal, bytes_formats, time_zone) {
+        Coercion::Unchanged => return,
+        Coercion::Coerced(coerced_value) => format!(
+            "The value was coerced into `{}` to match the {} field `{}`.",
+            coerced_value, field_type_name, field_name
+        ),
+        Coercion::Mismatch => format!(
+            "The clause cannot match any document: `{}` is not a valid value of the {} field `{}`.",
+            literal.phrase, field_type_name, field_name
+        ),
+    };
+    warnings.push(QueryWarning {
+        clause: format!("{}:{}", field_name, literal.phrase),
+        message,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::{FAST, INDEXED, TEXT};
+
+    use super::*;
+
+    #[test]
+    fn test_coerce_value() {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT);
+        schema_builder.add_i64_field("timestamp", INDEXED);
+        schema_builder.add_u64_field("status", INDEXED);
+        schema_builder.add_f64_field("latency", FAST);
+        schema_builder.add_date_field("date", INDEXED);
+        let schema = schema_builder.build();
+        let field_type = |field_name: &str| {
+            schema
+                .get_field_entry(schema.get_field(field_name).unwrap())
+                .field_type()
+                .clone()
+        };
+        let utc = TimeZone::default();
+        let coerce =
+            |field_name: &str, value: &str| coerce_value(&field_type(field_name), value, &utc);
+        assert_eq!(coerce("title", "200"), Coercion::Unchanged);
+        assert_eq!(coerce("status", "200"), Coercion::Unchanged);
+        assert_eq!(
+            coerce("status", "200.0"),
+            Coercion::Coerced("200".to_string())
+        );
+        assert_eq!(coerce("status", "-1"), Coercion::Mismatch);
+        assert_eq!(coerce("status", "OK"), Coercion::Mismatch);
+        assert_eq!(coerce("timestamp", "-1"), Coercion::Unchanged);
+        assert_eq!(
+            coerce("timestamp", " 12 "),
+            Coercion::Coerced("12".to_string())
+        );
+        assert_eq!(
+            coerce("timestamp", "2021-06-01"),
+            Coercion::Coerced("1622505600".to_string())
+        );
+        assert_eq!(coerce("timestamp", "1.5"), Coercion::Mismatch);
+        assert_eq!(coerce("latency", "1"), Coercion::Unchanged);
+        assert_eq!(coerce("latency", "fast"), Coercion::Mismatch);
+        assert_eq!(coerce("date", "2021-06-01T00:00:00Z"), Coercion::Unchanged);
+        assert_eq!(
+            coerce("date", "2021-06-01"),
+            Coercion::Coerced("2021-06-01T00:00:00+00:00".to_string())
+        );
+        assert_eq!(coerce("date", "yesterday"), Coercion::Mismatch);
+    }
+
+    #[test]
+    fn test_query_warnings() {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT);
+        schema_builder.add_u64_field("status", INDEXED);
+        schema_builder.add_bytes_field("trace_id", INDEXED);
+        let schema = schema_builder.build();
+        let mut bytes_formats = BTreeMap::new();
+        bytes_formats.insert("trace_id".to_string(), BytesFormat::Hex);
+        let user_input_ast = tantivy_query_grammar::parse_query(
+            "title:200 (status:200.0 status:OK) status:2* -status:404 trace_id:0af3 trace_id:xyz",
+        )
+        .unwrap();
+        let warnings = query_warnings(
+            &schema,
+            &user_input_ast,
+            &bytes_formats,
+            &TimeZone::default(),
+        );
+        assert_eq!(warnings.len(), 3);
+        assert_eq!(warnings[0].clause, "status:200.0");
+        assert_eq!(
+            warnings[0].message,
+            "The value was coerced into `200` to match the u64 field `status`."
+        );
+        assert_eq!(warnings[1].clause, "status:OK");
+        assert!(warnings[1].message.contains("cannot match any document"));
+        assert_eq!(warnings[2].clause, "trace_id:xyz");
+        assert_eq!(
+            warnings[2].message,
+            "The clause cannot match any document: `xyz` is not a valid value of the bytes field \
+             `trace_id`."
+        );
+    }
+}
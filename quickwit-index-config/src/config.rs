@@ -17,16 +17,18 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 
 use dyn_clone::{clone_trait_object, DynClone};
-use quickwit_proto::SearchRequest;
+use quickwit_proto::{QueryWarning, SearchRequest};
 use serde::{Deserialize, Serialize};
 use tantivy::query::Query;
 use tantivy::schema::{Field, Schema};
-use tantivy::{Document, Order};
+use tantivy::{Document, Order, Searcher};
 
-use crate::{DocParsingError, QueryParserError, TAGS_FIELD_NAME};
+use crate::query_builder::query_warnings;
+use crate::{DocParsingError, QueryParserError, SplitPathLayout, TAGS_FIELD_NAME};
 
 /// Sorted order (either Ascending or Descending).
 /// To get a regular top-K results search, use `SortOrder::Desc`.
@@ -70,6 +72,162 @@ pub enum SortBy {
     DocId,
 }
 
+/// Defines what gets copied into the hotcache of the splits of an index.
+///
+/// The hotcache is downloaded when a split is opened. A bigger hotcache
+/// makes opening a split more expensive, but saves GET requests at query time.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HotcacheConfig {
+    /// Caches the term dictionaries of the indexed fields.
+    ///
+    /// It cannot be disabled, as searches read the term dictionaries synchronously.
+    pub term_dict_blocks: bool,
+    /// Caches the fieldnorms of the indexed fields, used for scoring.
+    pub fieldnorms: bool,
+    /// Names of the fast fields cached in full.
+    pub fast_fields: Vec<String>,
+}
+
+impl Default for HotcacheConfig {
+    fn default() -> Self {
+        HotcacheConfig {
+            term_dict_blocks: true,
+            fieldnorms: false,
+            fast_fields: Vec::new(),
+        }
+    }
+}
+
+/// Guardrails of the wildcard (`err*r`) and regex (`/err.r/`) queries.
+///
+/// These queries are expanded into the terms of the split they match, which can be
+/// arbitrarily expensive without limits.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WildcardQueryConfig {
+    /// Maximum number of terms a wildcard or regex query can expand to in a split.
+    pub max_expanded_terms: usize,
+    /// Maximum size of the automaton compiled from a wildcard or regex pattern.
+    pub max_automaton_size_in_bytes: usize,
+    /// Accepts the patterns starting with a wildcard, which scan the entire term dictionary.
+    pub allow_leading_wildcard: bool,
+}
+
+impl Default for WildcardQueryConfig {
+    fn default() -> Self {
+        WildcardQueryConfig {
+            max_expanded_terms: 1_000,
+            max_automaton_size_in_bytes: 1_000_000,
+            allow_leading_wildcard: false,
+        }
+    }
+}
+
+/// Guardrails of the fuzzy (`valeu~1`) queries.
+///
+/// These queries are expanded into the terms of the split within their edit distance,
+/// found by scanning the term dictionary.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FuzzyQueryConfig {
+    /// Maximum edit distance a fuzzy term can ask for.
+    pub max_edit_distance: u8,
+    /// Maximum number of terms a fuzzy term expands to in a split. The closest terms are
+    /// kept.
+    pub max_expanded_terms: usize,
+    /// Number of leading characters the expanded terms share with the fuzzy term, which
+    /// bounds the part of the term dictionary that is scanned.
+    pub prefix_length: usize,
+    /// Counts the transposition of two adjacent characters as a single edit.
+    pub transpositions: bool,
+}
+
+impl Default for FuzzyQueryConfig {
+    fn default() -> Self {
+        FuzzyQueryConfig {
+            max_edit_distance: 2,
+            max_expanded_terms: 50,
+            prefix_length: 1,
+            transpositions: true,
+        }
+    }
+}
+
+/// Guardrail on the number of splits a single query searches, e.g. against an accidental `*`
+/// over a year of data.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SplitLimitConfig {
+    /// Maximum number of splits a query searches at once, unlimited if `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_num_splits: Option<usize>,
+    /// What happens to the queries spanning more splits than the limit.
+    pub on_limit_exceeded: SplitLimitPolicy,
+}
+
+/// How the queries spanning more splits than the [`SplitLimitConfig`] allows are handled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitLimitPolicy {
+    /// The query is rejected.
+    Reject,
+    /// The query is run as sequential sub-queries over time-range chunks of at most
+    /// `max_num_splits` splits, the most recent first, whose results are merged.
+    Chunk,
+}
+
+impl Default for SplitLimitPolicy {
+    fn default() -> Self {
+        SplitLimitPolicy::Reject
+    }
+}
+
+/// Joins the fetched documents with a small lookup table, e.g. `ip` → geo or
+/// `service` → team, before they are returned.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnrichmentConfig {
+    /// Stored field of the documents whose values are looked up in the table.
+    pub source_field: String,
+    /// Field added to the documents, holding the entries of the table matching the values.
+    pub target_field: String,
+    /// Path of the lookup table, relative to the index URI, or `companion:<name>` for the
+    /// latest version of the companion file `<name>` of the index.
+    ///
+    /// Tables ending with `.csv` are CSV files with a header row, keyed by their first
+    /// column. Other tables are JSON objects mapping keys to entries.
+    pub lookup_table_path: String,
+}
+
+/// Extracts structured fields from a raw text field at indexing time, e.g. the
+/// program and pid of a syslog line.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtractionConfig {
+    /// Top-level text field of the documents the pattern is applied to.
+    pub source_field: String,
+    /// Grok pattern, i.e. a regex in which `%{NAME}` expands to a built-in pattern and
+    /// `%{NAME:field}` captures the text it matches into `field`.
+    pub pattern: String,
+}
+
+/// Synonyms the terms of the queries are expanded into, e.g. `laptop` → `notebook`.
+///
+/// The terms of a set are equivalent: a query on any of them matches the documents
+/// containing any of the others.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SynonymsConfig {
+    /// Sets of equivalent terms. The terms are single words, matched regardless of case.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sets: Vec<Vec<String>>,
+    /// Name of a companion file of the index holding more synonym sets, one per line as
+    /// comma-separated terms. Its latest version is read at query time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub companion_file: Option<String>,
+}
+
 /// The `IndexConfig` trait defines the way of defining how a (json) document,
 /// and the fields it contains, are stored and indexed.
 ///
@@ -98,6 +256,33 @@ pub trait IndexConfig: Send + Sync + Debug + DynClone + 'static {
         request: &SearchRequest,
     ) -> Result<Box<dyn Query>, QueryParserError>;
 
+    /// Returns the query, for the split `searcher` is reading.
+    ///
+    /// Unlike [`IndexConfig::query`], it accepts wildcard and regex terms, which
+    /// get expanded into the terms of the split they match.
+    fn split_query(
+        &self,
+        searcher: &Searcher,
+        request: &SearchRequest,
+    ) -> Result<Box<dyn Query>, QueryParserError> {
+        self.query(searcher.schema().clone(), request)
+    }
+
+    /// Returns a warning for each clause of the query whose value does not match the type of
+    /// its field: the value is either coerced into the type of the field, or the clause cannot
+    /// match any document.
+    fn query_warnings(
+        &self,
+        request: &SearchRequest,
+    ) -> Result<Vec<QueryWarning>, QueryParserError> {
+        query_warnings(&self.schema(), request, &BTreeMap::new(), &BTreeMap::new())
+    }
+
+    /// Returns the name of the field `field_name` is an alias of, if it is a field alias.
+    fn resolve_field_alias(&self, _field_name: &str) -> Option<&str> {
+        None
+    }
+
     /// Returns the default sort
     fn sort_by(&self) -> SortBy {
         SortBy::DocId
@@ -121,6 +306,53 @@ pub trait IndexConfig: Send + Sync + Debug + DynClone + 'static {
         vec![]
     }
 
+    /// Returns what gets copied into the hotcache of the splits.
+    fn hotcache_config(&self) -> HotcacheConfig {
+        HotcacheConfig::default()
+    }
+
+    /// Returns the lookup tables the fetched documents are joined with.
+    fn enrichments(&self) -> Vec<EnrichmentConfig> {
+        Vec::new()
+    }
+
+    /// Returns the layout of the paths of the split files, or `None` if the split files
+    /// are stored at the root of the index, as `<split ID>.split`.
+    fn split_path_layout(&self) -> Option<SplitPathLayout> {
+        None
+    }
+
+    /// Returns the size of the parts the split files larger than it are stored as, or `None`
+    /// if the split files are always stored as single objects.
+    fn split_part_num_bytes(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns the names of the geo point fields, whose fast field holds encoded
+    /// [`GeoPoint`](crate::GeoPoint)s.
+    fn geo_point_field_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Returns the guardrail on the number of splits a query searches.
+    fn split_limit_config(&self) -> SplitLimitConfig {
+        SplitLimitConfig::default()
+    }
+
+    /// Returns the name of the companion file holding synonym sets of the index, if any.
+    fn synonyms_companion_file(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns a copy of the config, expanding the terms of the queries into the
+    /// synonyms of `synonym_sets` as well.
+    fn with_companion_synonyms(
+        &self,
+        _synonym_sets: Vec<Vec<String>>,
+    ) -> anyhow::Result<Box<dyn IndexConfig>> {
+        anyhow::bail!("This index config does not support synonyms.")
+    }
+
     /// Returns the special tags field if any.
     fn tags_field(&self, split_schema: &Schema) -> Field {
         split_schema
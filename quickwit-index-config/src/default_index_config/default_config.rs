@@ -17,23 +17,31 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::convert::TryFrom;
+use std::path::{Component, Path};
 
 use anyhow::{bail, Context};
-use quickwit_proto::SearchRequest;
+use quickwit_proto::{QueryWarning, SearchRequest};
 use serde::{Deserialize, Serialize};
 use serde_json::{self, Value as JsonValue};
 use tantivy::query::Query;
 use tantivy::schema::{
-    Cardinality, FieldEntry, FieldType, FieldValue, Schema, SchemaBuilder, Value, STORED, STRING,
+    Cardinality, Facet, FieldEntry, FieldType, FieldValue, Schema, SchemaBuilder, Value, INDEXED,
+    STORED, STRING,
 };
-use tantivy::Document;
+use tantivy::{Document, Searcher};
 
 use super::field_mapping_entry::DocParsingError;
-use super::{default_as_true, FieldMappingEntry, FieldMappingType};
-use crate::query_builder::build_query;
-use crate::{IndexConfig, QueryParserError, SortBy, SortOrder, SOURCE_FIELD_NAME, TAGS_FIELD_NAME};
+use super::{default_as_true, BytesFormat, FieldMappingEntry, FieldMappingType};
+use crate::extraction::Extraction;
+use crate::query_builder::{build_query, query_warnings};
+use crate::synonyms::SynonymMap;
+use crate::{
+    EnrichmentConfig, ExtractionConfig, FuzzyQueryConfig, HotcacheConfig, IndexConfig,
+    QueryParserError, SortBy, SortOrder, SplitLimitConfig, SplitPathLayout, SynonymsConfig,
+    WildcardQueryConfig, SOURCE_FIELD_NAME, STRING_FAST_FIELD_PREFIX, TAGS_FIELD_NAME,
+};
 
 /// DefaultIndexConfigBuilder is here
 /// to create a valid IndexConfig.
@@ -49,6 +57,26 @@ pub struct DefaultIndexConfigBuilder {
     field_mappings: Vec<FieldMappingEntry>,
     #[serde(default)]
     tag_fields: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hotcache: Option<HotcacheConfig>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    field_aliases: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wildcard_queries: Option<WildcardQueryConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fuzzy_queries: Option<FuzzyQueryConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    enrichments: Vec<EnrichmentConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    extractions: Vec<ExtractionConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    split_path_layout: Option<SplitPathLayout>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    split_part_num_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    synonyms: Option<SynonymsConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    split_limit: Option<SplitLimitConfig>,
 }
 
 #[derive(Default, Serialize, Deserialize, Clone)]
@@ -100,6 +128,16 @@ impl DefaultIndexConfigBuilder {
             sort_by: None,
             field_mappings: vec![],
             tag_fields: vec![],
+            hotcache: None,
+            field_aliases: BTreeMap::new(),
+            wildcard_queries: None,
+            fuzzy_queries: None,
+            enrichments: Vec::new(),
+            extractions: Vec::new(),
+            split_path_layout: None,
+            split_part_num_bytes: None,
+            synonyms: None,
+            split_limit: None,
         }
     }
 
@@ -107,6 +145,9 @@ impl DefaultIndexConfigBuilder {
     /// This will consume your `DefaultIndexConfigBuilder`.
     pub fn build(self) -> anyhow::Result<DefaultIndexConfig> {
         let schema = self.build_schema()?;
+        if self.split_part_num_bytes == Some(0) {
+            bail!("The split parts must not be empty, `split_part_num_bytes` must be positive.");
+        }
         // Resolve default search fields
         let mut default_search_field_names = Vec::new();
         for field_name in self.default_search_fields.iter() {
@@ -168,9 +209,108 @@ impl DefaultIndexConfigBuilder {
             tag_field_names.push(tag_field_name.clone());
         }
 
+        // Resolve hotcache fast fields
+        let hotcache_config = self.hotcache.unwrap_or_default();
+        // Searches open the term dictionaries synchronously, so they must be in the hotcache.
+        if !hotcache_config.term_dict_blocks {
+            bail!(
+                "Leaving the term dictionaries out of the hotcache is not supported: searches \
+                 read them synchronously. Please remove `term_dict_blocks: false` from the \
+                 hotcache config."
+            )
+        }
+        for fast_field_name in hotcache_config.fast_fields.iter() {
+            let fast_field = schema
+                .get_field(fast_field_name)
+                .with_context(|| format!("Unknown hotcache fast field: `{}`", fast_field_name))?;
+            if !schema.get_field_entry(fast_field).is_fast() {
+                bail!(
+                    "Hotcache fast field must be a fast field, please add the fast property to \
+                     your field `{}`.",
+                    fast_field_name
+                )
+            }
+        }
+
+        // Resolve field aliases
+        for (alias, field_name) in self.field_aliases.iter() {
+            if schema.get_field(alias).is_some() {
+                bail!(
+                    "Field alias `{}` conflicts with the field of the same name.",
+                    alias
+                )
+            }
+            schema.get_field(field_name).with_context(|| {
+                format!("Unknown field `{}` for field alias `{}`", field_name, alias)
+            })?;
+        }
+
+        // Resolve enrichments
+        let mut target_field_names = HashSet::new();
+        for enrichment in self.enrichments.iter() {
+            let source_field = schema
+                .get_field(&enrichment.source_field)
+                .with_context(|| {
+                    format!(
+                        "Unknown enrichment source field: `{}`",
+                        enrichment.source_field
+                    )
+                })?;
+            if !schema.get_field_entry(source_field).is_stored() {
+                bail!(
+                    "Enrichment source field must be a stored field, please add the stored \
+                     property to your field `{}`.",
+                    enrichment.source_field
+                )
+            }
+            if schema.get_field(&enrichment.target_field).is_some()
+                || !target_field_names.insert(enrichment.target_field.as_str())
+            {
+                bail!(
+                    "Enrichment target field `{}` conflicts with another field of the same name.",
+                    enrichment.target_field
+                )
+            }
+            if enrichment.lookup_table_path.is_empty() {
+                bail!(
+                    "Missing lookup table path for enrichment target field `{}`.",
+                    enrichment.target_field
+                )
+            }
+            let is_within_index = Path::new(&enrichment.lookup_table_path)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+            if !is_within_index {
+                bail!(
+                    "Lookup table path `{}` must be relative to the index URI and cannot contain \
+                     `..`.",
+                    enrichment.lookup_table_path
+                )
+            }
+        }
+
+        // Resolve synonyms
+        let synonyms_config = self.synonyms.unwrap_or_default();
+        let synonym_map = SynonymMap::build(&synonyms_config.sets)?;
+
+        let split_limit_config = self.split_limit.unwrap_or_default();
+        if split_limit_config.max_num_splits == Some(0) {
+            bail!("The maximum number of splits of a query must be strictly positive.");
+        }
+
+        // Resolve extractions
+        let extractions = self
+            .extractions
+            .into_iter()
+            .map(|extraction_config| Extraction::new(extraction_config, &schema))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
         // Build the root mapping entry, it has an empty name so that we don't prefix all
         // field name with it.
         let field_mappings = FieldMappingEntry::root(FieldMappingType::Object(self.field_mappings));
+        let bytes_formats = field_mappings.bytes_formats().into_iter().collect();
+        let geo_point_field_names = field_mappings.geo_point_field_names();
+        let string_fast_field_names = field_mappings.string_fast_field_names();
         Ok(DefaultIndexConfig {
             schema,
             store_source: self.store_source,
@@ -179,6 +319,20 @@ impl DefaultIndexConfigBuilder {
             sort_by,
             field_mappings,
             tag_field_names,
+            hotcache_config,
+            field_aliases: self.field_aliases,
+            wildcard_query_config: self.wildcard_queries.unwrap_or_default(),
+            fuzzy_query_config: self.fuzzy_queries.unwrap_or_default(),
+            enrichments: self.enrichments,
+            extractions,
+            bytes_formats,
+            geo_point_field_names,
+            string_fast_field_names,
+            split_path_layout: self.split_path_layout,
+            split_part_num_bytes: self.split_part_num_bytes,
+            synonyms_config,
+            synonym_map,
+            split_limit_config,
         })
     }
 
@@ -197,6 +351,13 @@ impl DefaultIndexConfigBuilder {
                 if field_name == TAGS_FIELD_NAME {
                     bail!("`_tags` is a reserved name, change your field name.");
                 }
+                if field_name.starts_with(STRING_FAST_FIELD_PREFIX) {
+                    bail!(
+                        "`{}` is a reserved prefix, change your field name `{}`.",
+                        STRING_FAST_FIELD_PREFIX,
+                        field_name
+                    );
+                }
                 if unique_field_names.contains(&field_name) {
                     bail!(
                         "Field name must be unique, found duplicates for `{}`",
@@ -206,6 +367,11 @@ impl DefaultIndexConfigBuilder {
                 unique_field_names.insert(field_name.clone());
                 builder.add_field(FieldEntry::new(field_name, field_type));
             }
+            // The facet fields come with a fast field of the ordinals of their values in the
+            // facet dictionary, which is all a dictionary encoded fast field needs.
+            for field_name in field_mapping.string_fast_field_names() {
+                builder.add_facet_field(&crate::string_fast_field_name(&field_name), INDEXED);
+            }
         }
         if self.store_source {
             builder.add_text_field(SOURCE_FIELD_NAME, STORED);
@@ -246,6 +412,26 @@ impl From<DefaultIndexConfig> for DefaultIndexConfigBuilder {
                 .field_mappings()
                 .unwrap_or_else(Vec::new),
             tag_fields: value.tag_field_names,
+            hotcache: Some(value.hotcache_config)
+                .filter(|hotcache_config| *hotcache_config != HotcacheConfig::default()),
+            field_aliases: value.field_aliases,
+            wildcard_queries: Some(value.wildcard_query_config).filter(|wildcard_query_config| {
+                *wildcard_query_config != WildcardQueryConfig::default()
+            }),
+            fuzzy_queries: Some(value.fuzzy_query_config)
+                .filter(|fuzzy_query_config| *fuzzy_query_config != FuzzyQueryConfig::default()),
+            enrichments: value.enrichments,
+            extractions: value
+                .extractions
+                .into_iter()
+                .map(|extraction| extraction.config)
+                .collect(),
+            split_path_layout: value.split_path_layout,
+            split_part_num_bytes: value.split_part_num_bytes,
+            synonyms: Some(value.synonyms_config)
+                .filter(|synonyms_config| *synonyms_config != SynonymsConfig::default()),
+            split_limit: Some(value.split_limit_config)
+                .filter(|split_limit_config| *split_limit_config != SplitLimitConfig::default()),
         }
     }
 }
@@ -276,6 +462,34 @@ pub struct DefaultIndexConfig {
     schema: Schema,
     /// List of field names used for tagging.
     tag_field_names: Vec<String>,
+    /// What gets copied into the hotcache of the splits.
+    hotcache_config: HotcacheConfig,
+    /// Alternative field names accepted in queries, mapped to the field names they stand for.
+    field_aliases: BTreeMap<String, String>,
+    /// Guardrails of the wildcard and regex queries.
+    wildcard_query_config: WildcardQueryConfig,
+    /// Guardrails of the fuzzy queries.
+    fuzzy_query_config: FuzzyQueryConfig,
+    /// Lookup tables the fetched documents are joined with.
+    enrichments: Vec<EnrichmentConfig>,
+    /// Patterns extracting fields from the raw text fields of the documents.
+    extractions: Vec<Extraction>,
+    /// Format of the values of the bytes fields, by field name.
+    bytes_formats: BTreeMap<String, BytesFormat>,
+    /// Names of the geo point fields.
+    geo_point_field_names: Vec<String>,
+    /// Names of the text fast fields, whose values are dictionary encoded.
+    string_fast_field_names: Vec<String>,
+    /// Layout of the paths of the split files.
+    split_path_layout: Option<SplitPathLayout>,
+    /// Size of the parts the split files larger than it are stored as.
+    split_part_num_bytes: Option<u64>,
+    /// Synonym sets of the index, as configured.
+    synonyms_config: SynonymsConfig,
+    /// Synonyms the terms of the queries are expanded into.
+    synonym_map: SynonymMap,
+    /// Guardrail on the number of splits a query searches.
+    split_limit_config: SplitLimitConfig,
 }
 
 impl std::fmt::Debug for DefaultIndexConfig {
@@ -293,6 +507,15 @@ impl std::fmt::Debug for DefaultIndexConfig {
     }
 }
 
+impl DefaultIndexConfig {
+    /// Returns the synonyms the terms of `request` are expanded into, unless the request
+    /// disables them.
+    fn synonym_map_opt(&self, request: &SearchRequest) -> Option<&SynonymMap> {
+        Some(&self.synonym_map)
+            .filter(|synonym_map| !request.disable_synonyms && !synonym_map.is_empty())
+    }
+}
+
 /// Converts a [`tantivy::Value`] to it's [`String`] value.
 fn tantivy_value_to_string(field_value: &Value) -> String {
     match field_value {
@@ -317,7 +540,7 @@ impl IndexConfig for DefaultIndexConfig {
             })?;
             document.add_text(source, doc_json);
         }
-        let json_obj: JsonValue = serde_json::from_str(doc_json).map_err(|_| {
+        let mut json_obj: JsonValue = serde_json::from_str(doc_json).map_err(|_| {
             let doc_json_sample: String = if doc_json.len() < 20 {
                 String::from(doc_json)
             } else {
@@ -325,6 +548,11 @@ impl IndexConfig for DefaultIndexConfig {
             };
             DocParsingError::NotJson(doc_json_sample)
         })?;
+        if let JsonValue::Object(json_map) = &mut json_obj {
+            for extraction in self.extractions.iter() {
+                extraction.apply(json_map)?;
+            }
+        }
         let parsing_result = self.field_mappings.parse(&json_obj)?;
         let tags_field_opt = self.schema.get_field(TAGS_FIELD_NAME);
         for (field_path, field_value) in parsing_result {
@@ -337,9 +565,27 @@ impl IndexConfig for DefaultIndexConfig {
                 let tags_field = tags_field_opt.ok_or_else(|| {
                     DocParsingError::NoSuchFieldInSchema(TAGS_FIELD_NAME.to_string())
                 })?;
-                let tag_value = format!("{}:{}", field_name, tantivy_value_to_string(&field_value));
+                let tag_value = match (&field_value, self.bytes_formats.get(&field_name)) {
+                    (Value::Bytes(bytes), Some(format)) => {
+                        format!("{}:{}", field_name, format.encode(bytes))
+                    }
+                    _ => format!("{}:{}", field_name, tantivy_value_to_string(&field_value)),
+                };
                 document.add(FieldValue::new(tags_field, Value::Str(tag_value)));
             }
+            if let Value::Str(text) = &field_value {
+                if self.string_fast_field_names.contains(&field_name) {
+                    let dictionary_field_name = crate::string_fast_field_name(&field_name);
+                    let dictionary_field = self
+                        .schema
+                        .get_field(&dictionary_field_name)
+                        .ok_or_else(|| {
+                            DocParsingError::NoSuchFieldInSchema(dictionary_field_name.clone())
+                        })?;
+                    let facet = Facet::from_path(vec![text]);
+                    document.add(FieldValue::new(dictionary_field, Value::Facet(facet)));
+                }
+            }
             document.add(FieldValue::new(field, field_value))
         }
         Ok(document)
@@ -350,7 +596,51 @@ impl IndexConfig for DefaultIndexConfig {
         split_schema: Schema,
         request: &SearchRequest,
     ) -> Result<Box<dyn Query>, QueryParserError> {
-        build_query(split_schema, request, &self.default_search_field_names)
+        build_query(
+            split_schema,
+            request,
+            &self.default_search_field_names,
+            &self.field_aliases,
+            &self.bytes_formats,
+            &self.wildcard_query_config,
+            &self.fuzzy_query_config,
+            self.synonym_map_opt(request),
+            None,
+        )
+    }
+
+    fn split_query(
+        &self,
+        searcher: &Searcher,
+        request: &SearchRequest,
+    ) -> Result<Box<dyn Query>, QueryParserError> {
+        build_query(
+            searcher.schema().clone(),
+            request,
+            &self.default_search_field_names,
+            &self.field_aliases,
+            &self.bytes_formats,
+            &self.wildcard_query_config,
+            &self.fuzzy_query_config,
+            self.synonym_map_opt(request),
+            Some(searcher),
+        )
+    }
+
+    fn query_warnings(
+        &self,
+        request: &SearchRequest,
+    ) -> Result<Vec<QueryWarning>, QueryParserError> {
+        query_warnings(
+            &self.schema,
+            request,
+            &self.field_aliases,
+            &self.bytes_formats,
+        )
+    }
+
+    fn resolve_field_alias(&self, field_name: &str) -> Option<&str> {
+        self.field_aliases.get(field_name).map(String::as_str)
     }
 
     fn schema(&self) -> Schema {
@@ -368,6 +658,49 @@ impl IndexConfig for DefaultIndexConfig {
     fn tag_field_names(&self) -> Vec<String> {
         self.tag_field_names.clone()
     }
+
+    fn hotcache_config(&self) -> HotcacheConfig {
+        self.hotcache_config.clone()
+    }
+
+    fn enrichments(&self) -> Vec<EnrichmentConfig> {
+        self.enrichments.clone()
+    }
+
+    fn split_path_layout(&self) -> Option<SplitPathLayout> {
+        self.split_path_layout.clone()
+    }
+
+    fn split_part_num_bytes(&self) -> Option<u64> {
+        self.split_part_num_bytes
+    }
+
+    fn geo_point_field_names(&self) -> Vec<String> {
+        self.geo_point_field_names.clone()
+    }
+
+    fn split_limit_config(&self) -> SplitLimitConfig {
+        self.split_limit_config.clone()
+    }
+
+    fn synonyms_companion_file(&self) -> Option<String> {
+        self.synonyms_config.companion_file.clone()
+    }
+
+    fn with_companion_synonyms(
+        &self,
+        synonym_sets: Vec<Vec<String>>,
+    ) -> anyhow::Result<Box<dyn IndexConfig>> {
+        let mut synonyms_config = self.synonyms_config.clone();
+        synonyms_config.sets.extend(synonym_sets);
+        synonyms_config.companion_file = None;
+        let synonym_map = SynonymMap::build(&synonyms_config.sets)?;
+        Ok(Box::new(DefaultIndexConfig {
+            synonyms_config,
+            synonym_map,
+            ..self.clone()
+        }))
+    }
 }
 
 #[cfg(test)]
@@ -375,12 +708,14 @@ mod tests {
     use std::collections::HashMap;
 
     use anyhow::bail;
+    use quickwit_proto::SearchRequest;
     use serde_json::{self, Value as JsonValue};
+    use tantivy::schema::{Facet, FieldType, Value};
 
     use super::DefaultIndexConfig;
     use crate::{
-        DefaultIndexConfigBuilder, DocParsingError, IndexConfig, SortBy, SortOrder,
-        SOURCE_FIELD_NAME, TAGS_FIELD_NAME,
+        DefaultIndexConfigBuilder, DocParsingError, HotcacheConfig, IndexConfig, SortBy, SortOrder,
+        SplitLimitConfig, SplitLimitPolicy, SynonymsConfig, SOURCE_FIELD_NAME, TAGS_FIELD_NAME,
     };
 
     const JSON_DOC_VALUE: &str = r#"
@@ -451,6 +786,14 @@ mod tests {
             config.sort_by.unwrap(),
             config_after_serialization.sort_by.unwrap()
         );
+        assert_eq!(
+            config.hotcache_config,
+            config_after_serialization.hotcache_config
+        );
+        assert_eq!(
+            config.field_aliases,
+            config_after_serialization.field_aliases
+        );
         Ok(())
     }
 
@@ -604,6 +947,60 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parse_document_with_text_fast_field() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "field_mappings": [
+                {
+                    "name": "level",
+                    "type": "text",
+                    "tokenizer": "raw",
+                    "fast": true
+                }
+            ]
+        }"#;
+
+        let builder = serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?;
+        let index_config = builder.build()?;
+        let schema = index_config.schema();
+        let dictionary_field = schema.get_field("_dict.level").unwrap();
+        assert!(matches!(
+            schema.get_field_entry(dictionary_field).field_type(),
+            FieldType::HierarchicalFacet(_)
+        ));
+        let document = index_config.doc_from_json(r#"{"level": "error"}"#)?;
+        assert_eq!(
+            document.get_first(dictionary_field),
+            Some(&Value::Facet(Facet::from_path(vec!["error"])))
+        );
+        // The `_source` and the text field hold the original value.
+        assert_eq!(document.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fail_with_field_name_with_string_fast_field_prefix() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "field_mappings": [
+                {
+                    "name": "_dict.level",
+                    "type": "text"
+                }
+            ]
+        }"#;
+
+        let builder = serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?;
+        assert_eq!(
+            builder.build().unwrap_err().to_string(),
+            "`_dict.` is a reserved prefix, change your field name `_dict.level`."
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_fail_to_parse_document_with_wrong_base64_value() -> anyhow::Result<()> {
         let index_config = r#"{
@@ -697,6 +1094,47 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parse_document_with_multivalued_tag_fields() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "timestamp_field": null,
+            "tag_fields": ["tags", "ports"],
+            "field_mappings": [
+                {
+                    "name": "tags",
+                    "type": "array<text>",
+                    "tokenizer": "raw"
+                },
+                {
+                    "name": "ports",
+                    "type": "array<u64>",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let builder = serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?;
+        let index_config = builder.build()?;
+        let schema = index_config.schema();
+        let document = index_config.doc_from_json(
+            r#"{
+                "tags": ["a", "b"],
+                "ports": [80, 443]
+            }"#,
+        )?;
+        let tags_field = schema.get_field(TAGS_FIELD_NAME).unwrap();
+        let tags: Vec<&str> = document
+            .get_all(tags_field)
+            .map(|value| value.text().unwrap())
+            .collect();
+        assert_eq!(tags, vec!["tags:a", "tags:b", "ports:80", "ports:443"]);
+        let ports_field = schema.get_field("ports").unwrap();
+        let ports: Vec<&Value> = document.get_all(ports_field).collect();
+        assert_eq!(ports, vec![&Value::U64(80), &Value::U64(443)]);
+        Ok(())
+    }
+
     #[test]
     fn test_fail_to_build_index_config_with_non_fast_sort_by_field() -> anyhow::Result<()> {
         let index_config = r#"{
@@ -722,6 +1160,452 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_build_index_config_with_hotcache_config() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "tag_fields": [],
+            "hotcache": {
+                "fieldnorms": true,
+                "fast_fields": ["timestamp"]
+            },
+            "field_mappings": [
+                {
+                    "name": "timestamp",
+                    "type": "u64",
+                    "fast": true
+                },
+                {
+                    "name": "body",
+                    "type": "text"
+                }
+            ]
+        }"#;
+        let builder = serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?;
+        let index_config = builder.clone().build()?;
+        assert_eq!(
+            index_config.hotcache_config(),
+            HotcacheConfig {
+                term_dict_blocks: true,
+                fieldnorms: true,
+                fast_fields: vec!["timestamp".to_string()],
+            }
+        );
+
+        let mut builder = builder;
+        builder.hotcache = Some(HotcacheConfig {
+            fast_fields: vec!["body".to_string()],
+            ..Default::default()
+        });
+        let expected_msg = "Hotcache fast field must be a fast field, please add the fast \
+                            property to your field `body`."
+            .to_string();
+        assert_eq!(
+            builder.clone().build().unwrap_err().to_string(),
+            expected_msg
+        );
+
+        builder.hotcache = Some(HotcacheConfig {
+            term_dict_blocks: false,
+            ..Default::default()
+        });
+        assert!(builder
+            .build()
+            .unwrap_err()
+            .to_string()
+            .contains("term_dict_blocks"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_index_config_with_split_path_layout() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "tag_fields": [],
+            "split_path_layout": "{{date}}/{{split_id}}.split",
+            "field_mappings": []
+        }"#;
+        let index_config = serde_json::from_str::<DefaultIndexConfig>(index_config)?;
+        let split_path_layout = index_config.split_path_layout().unwrap();
+        assert_eq!(
+            split_path_layout.split_file_path("split1", 0),
+            "1970-01-01/split1.split"
+        );
+        let serialized_index_config = serde_json::to_string(&index_config)?;
+        let deserialized_index_config: DefaultIndexConfig =
+            serde_json::from_str(&serialized_index_config)?;
+        assert_eq!(
+            deserialized_index_config.split_path_layout(),
+            Some(split_path_layout)
+        );
+        assert!(DefaultIndexConfigBuilder::new()
+            .build()?
+            .split_path_layout()
+            .is_none());
+
+        let index_config_without_split_id = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "tag_fields": [],
+            "split_path_layout": "{{date}}.split",
+            "field_mappings": []
+        }"#;
+        assert!(serde_json::from_str::<DefaultIndexConfig>(index_config_without_split_id).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_index_config_with_split_part_num_bytes() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "tag_fields": [],
+            "split_part_num_bytes": 5000000000,
+            "field_mappings": []
+        }"#;
+        let index_config = serde_json::from_str::<DefaultIndexConfig>(index_config)?;
+        assert_eq!(index_config.split_part_num_bytes(), Some(5_000_000_000));
+        let serialized_index_config = serde_json::to_string(&index_config)?;
+        let deserialized_index_config: DefaultIndexConfig =
+            serde_json::from_str(&serialized_index_config)?;
+        assert_eq!(
+            deserialized_index_config.split_part_num_bytes(),
+            Some(5_000_000_000)
+        );
+        assert!(DefaultIndexConfigBuilder::new()
+            .build()?
+            .split_part_num_bytes()
+            .is_none());
+
+        let index_config_with_empty_parts = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "tag_fields": [],
+            "split_part_num_bytes": 0,
+            "field_mappings": []
+        }"#;
+        assert!(serde_json::from_str::<DefaultIndexConfig>(index_config_with_empty_parts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_index_config_with_enrichments() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "tag_fields": [],
+            "enrichments": [
+                {
+                    "source_field": "ip",
+                    "target_field": "geo",
+                    "lookup_table_path": "lookup/ip_geo.csv"
+                }
+            ],
+            "field_mappings": [
+                {
+                    "name": "ip",
+                    "type": "text",
+                    "tokenizer": "raw"
+                },
+                {
+                    "name": "body",
+                    "type": "text",
+                    "stored": false
+                }
+            ]
+        }"#;
+        let builder = serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?;
+        let index_config = builder.clone().build()?;
+        assert_eq!(
+            index_config.enrichments(),
+            vec![EnrichmentConfig {
+                source_field: "ip".to_string(),
+                target_field: "geo".to_string(),
+                lookup_table_path: "lookup/ip_geo.csv".to_string(),
+            }]
+        );
+        let serialized_index_config = serde_json::to_string(&index_config)?;
+        let deserialized_index_config: DefaultIndexConfig =
+            serde_json::from_str(&serialized_index_config)?;
+        assert_eq!(
+            deserialized_index_config.enrichments(),
+            index_config.enrichments()
+        );
+
+        for lookup_table_path in [
+            "../other-index/lookup.csv",
+            "lookup/../../secret.csv",
+            "/etc/passwd",
+        ] {
+            let mut builder_with_traversal = builder.clone();
+            builder_with_traversal.enrichments[0].lookup_table_path = lookup_table_path.to_string();
+            assert_eq!(
+                builder_with_traversal.build().unwrap_err().to_string(),
+                format!(
+                    "Lookup table path `{}` must be relative to the index URI and cannot contain \
+                     `..`.",
+                    lookup_table_path
+                )
+            );
+        }
+
+        let mut builder_with_conflict = builder.clone();
+        builder_with_conflict.enrichments[0].target_field = "body".to_string();
+        assert_eq!(
+            builder_with_conflict.build().unwrap_err().to_string(),
+            "Enrichment target field `body` conflicts with another field of the same name."
+        );
+
+        let mut builder_with_unstored_field = builder;
+        builder_with_unstored_field.enrichments[0].source_field = "body".to_string();
+        let expected_msg = "Enrichment source field must be a stored field, please add the stored \
+                            property to your field `body`."
+            .to_string();
+        assert_eq!(
+            builder_with_unstored_field.build().unwrap_err().to_string(),
+            expected_msg
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_document_with_extractions() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "tag_fields": [],
+            "extractions": [
+                {
+                    "source_field": "message",
+                    "pattern": "^%{SYSLOGTIMESTAMP} %{HOSTNAME:host} %{PROG:program}\\[%{POSINT:pid}\\]"
+                }
+            ],
+            "field_mappings": [
+                {
+                    "name": "message",
+                    "type": "text"
+                },
+                {
+                    "name": "host",
+                    "type": "text",
+                    "tokenizer": "raw"
+                },
+                {
+                    "name": "program",
+                    "type": "text",
+                    "tokenizer": "raw"
+                },
+                {
+                    "name": "pid",
+                    "type": "u64"
+                }
+            ]
+        }"#;
+        let builder = serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?;
+        let index_config = builder.clone().build()?;
+        let schema = index_config.schema();
+        let document = index_config.doc_from_json(
+            r#"{"message": "Oct 14 07:23:06 web-1 sshd[4242]: Accepted publickey", "host": "web-1.local"}"#,
+        )?;
+        let host_field = schema.get_field("host").unwrap();
+        let program_field = schema.get_field("program").unwrap();
+        let pid_field = schema.get_field("pid").unwrap();
+        assert_eq!(document.get_all(host_field).count(), 1);
+        assert_eq!(
+            document.get_first(host_field).unwrap().text(),
+            Some("web-1.local")
+        );
+        assert_eq!(
+            document.get_first(program_field).unwrap().text(),
+            Some("sshd")
+        );
+        assert_eq!(document.get_first(pid_field), Some(&Value::U64(4242)));
+
+        let document = index_config.doc_from_json(r#"{"message": "not a syslog line"}"#)?;
+        assert!(document.get_first(program_field).is_none());
+
+        let serialized_index_config = serde_json::to_string(&index_config)?;
+        let deserialized_index_config: DefaultIndexConfig =
+            serde_json::from_str(&serialized_index_config)?;
+        assert_eq!(deserialized_index_config.extractions.len(), 1);
+
+        let mut builder_with_unknown_field = builder;
+        builder_with_unknown_field.extractions[0].pattern = "%{WORD:service}".to_string();
+        assert_eq!(
+            builder_with_unknown_field.build().unwrap_err().to_string(),
+            "Unknown extraction target field: `service`"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_index_config_with_field_aliases() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "tag_fields": [],
+            "field_aliases": {
+                "message": "body"
+            },
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                }
+            ]
+        }"#;
+        let builder = serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?;
+        let index_config = builder.clone().build()?;
+        assert_eq!(
+            index_config
+                .field_aliases
+                .get("message")
+                .map(String::as_str),
+            Some("body")
+        );
+        assert_eq!(index_config.resolve_field_alias("message"), Some("body"));
+        assert_eq!(index_config.resolve_field_alias("body"), None);
+        let request = SearchRequest {
+            index_id: "test_index".to_string(),
+            query: "message:foo".to_string(),
+            search_fields: vec!["message".to_string()],
+            max_hits: 20,
+            ..Default::default()
+        };
+        assert!(index_config.query(index_config.schema(), &request).is_ok());
+
+        let mut builder_with_conflict = builder.clone();
+        builder_with_conflict
+            .field_aliases
+            .insert("body".to_string(), "body".to_string());
+        assert_eq!(
+            builder_with_conflict.build().unwrap_err().to_string(),
+            "Field alias `body` conflicts with the field of the same name."
+        );
+
+        let mut builder_with_unknown_field = builder;
+        builder_with_unknown_field
+            .field_aliases
+            .insert("text".to_string(), "unknown".to_string());
+        assert_eq!(
+            builder_with_unknown_field.build().unwrap_err().to_string(),
+            "Unknown field `unknown` for field alias `text`"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_index_config_with_split_limit() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "type": "default",
+            "default_search_fields": [],
+            "split_limit": {
+                "max_num_splits": 500,
+                "on_limit_exceeded": "chunk"
+            },
+            "field_mappings": []
+        }"#;
+        let builder = serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?;
+        let index_config = builder.clone().build()?;
+        let expected_split_limit_config = SplitLimitConfig {
+            max_num_splits: Some(500),
+            on_limit_exceeded: SplitLimitPolicy::Chunk,
+        };
+        assert_eq!(
+            index_config.split_limit_config(),
+            expected_split_limit_config
+        );
+        let serialized_index_config = serde_json::to_string(&index_config)?;
+        let deserialized_index_config: DefaultIndexConfig =
+            serde_json::from_str(&serialized_index_config)?;
+        assert_eq!(
+            deserialized_index_config.split_limit_config(),
+            expected_split_limit_config
+        );
+
+        let default_index_config = DefaultIndexConfigBuilder::new().build()?;
+        assert_eq!(
+            default_index_config.split_limit_config(),
+            SplitLimitConfig::default()
+        );
+
+        let mut builder_with_zero_limit = builder;
+        builder_with_zero_limit.split_limit = Some(SplitLimitConfig {
+            max_num_splits: Some(0),
+            on_limit_exceeded: SplitLimitPolicy::Reject,
+        });
+        assert_eq!(
+            builder_with_zero_limit.build().unwrap_err().to_string(),
+            "The maximum number of splits of a query must be strictly positive."
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_index_config_with_synonyms() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "type": "default",
+            "default_search_fields": ["body"],
+            "tag_fields": [],
+            "synonyms": {
+                "sets": [["laptop", "notebook"]],
+                "companion_file": "synonyms.txt"
+            },
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                }
+            ]
+        }"#;
+        let builder = serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?;
+        let index_config = builder.clone().build()?;
+        assert_eq!(
+            index_config.synonyms_companion_file().as_deref(),
+            Some("synonyms.txt")
+        );
+        let mut request = SearchRequest {
+            index_id: "test_index".to_string(),
+            query: "laptop".to_string(),
+            max_hits: 20,
+            ..Default::default()
+        };
+        let query = index_config.query(index_config.schema(), &request)?;
+        assert!(format!("{:?}", query).contains("notebook"));
+        request.disable_synonyms = true;
+        let query = index_config.query(index_config.schema(), &request)?;
+        assert!(!format!("{:?}", query).contains("notebook"));
+
+        request.disable_synonyms = false;
+        request.query = "tv".to_string();
+        let companion_index_config = index_config
+            .with_companion_synonyms(vec![vec!["tv".to_string(), "television".to_string()]])?;
+        assert!(companion_index_config.synonyms_companion_file().is_none());
+        let query = companion_index_config.query(index_config.schema(), &request)?;
+        assert!(format!("{:?}", query).contains("television"));
+
+        let serialized_index_config = serde_json::to_string(&index_config)?;
+        let deserialized_index_config: DefaultIndexConfig =
+            serde_json::from_str(&serialized_index_config)?;
+        assert_eq!(
+            deserialized_index_config.synonyms_config,
+            index_config.synonyms_config
+        );
+
+        let mut builder_with_invalid_set = builder;
+        builder_with_invalid_set.synonyms = Some(SynonymsConfig {
+            sets: vec![vec!["laptop".to_string()]],
+            companion_file: None,
+        });
+        assert_eq!(
+            builder_with_invalid_set.build().unwrap_err().to_string(),
+            "Synonym set `laptop` must contain at least two distinct terms."
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_build_index_config_with_sort_by_field_asc() -> anyhow::Result<()> {
         let index_config = r#"{
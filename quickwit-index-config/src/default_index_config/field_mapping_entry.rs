@@ -30,8 +30,9 @@ use tantivy::schema::{
 };
 use thiserror::Error;
 
-use super::{default_as_true, FieldMappingType};
+use super::{default_as_true, BytesFormat, FieldMappingType};
 use crate::default_index_config::is_valid_field_mapping_name;
+use crate::GeoPoint;
 
 /// A `FieldMappingEntry` defines how a field is indexed, stored,
 /// and mapped from a JSON document to the related index fields.
@@ -66,7 +67,7 @@ impl FieldMappingEntry {
     pub fn field_entries(&self) -> anyhow::Result<Vec<(FieldPath, FieldType)>> {
         let field_path = FieldPath::new(&self.name);
         let results = match &self.mapping_type {
-            FieldMappingType::Text(options, _) => {
+            FieldMappingType::Text(options, _, _) => {
                 vec![(field_path, FieldType::Str(options.clone()))]
             }
             FieldMappingType::I64(options, _) => {
@@ -81,9 +82,12 @@ impl FieldMappingEntry {
             FieldMappingType::Date(options, _) => {
                 vec![(field_path, FieldType::Date(options.clone()))]
             }
-            FieldMappingType::Bytes(options, _) => {
+            FieldMappingType::Bytes(options, _, _) => {
                 vec![(field_path, FieldType::Bytes(options.clone()))]
             }
+            FieldMappingType::GeoPoint(options) => {
+                vec![(field_path, FieldType::U64(options.clone()))]
+            }
             FieldMappingType::Object(field_mappings) => process_results(
                 field_mappings.iter().map(|entry| entry.field_entries()),
                 |iter| {
@@ -96,6 +100,80 @@ impl FieldMappingEntry {
         Ok(results)
     }
 
+    /// Returns true if every value this entry maps to is multivalued, which is
+    /// what allows an array of objects to be flattened into its fields.
+    fn is_multivalued(&self) -> bool {
+        match &self.mapping_type {
+            FieldMappingType::Text(_, _, cardinality)
+            | FieldMappingType::I64(_, cardinality)
+            | FieldMappingType::U64(_, cardinality)
+            | FieldMappingType::F64(_, cardinality)
+            | FieldMappingType::Date(_, cardinality)
+            | FieldMappingType::Bytes(_, _, cardinality) => {
+                *cardinality == Cardinality::MultiValues
+            }
+            FieldMappingType::GeoPoint(_) => false,
+            FieldMappingType::Object(entries) => entries.iter().all(Self::is_multivalued),
+        }
+    }
+
+    /// Returns the format of the bytes fields, by field name.
+    pub fn bytes_formats(&self) -> Vec<(String, BytesFormat)> {
+        match &self.mapping_type {
+            FieldMappingType::Bytes(_, format, _) => vec![(self.name.clone(), *format)],
+            FieldMappingType::Object(entries) => entries
+                .iter()
+                .flat_map(|entry| entry.bytes_formats())
+                .map(|(field_name, format)| {
+                    if self.name.is_empty() {
+                        (field_name, format)
+                    } else {
+                        (format!("{}.{}", self.name, field_name), format)
+                    }
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the names of the text fast fields, whose values are dictionary encoded.
+    pub fn string_fast_field_names(&self) -> Vec<String> {
+        match &self.mapping_type {
+            FieldMappingType::Text(_, true, _) => vec![self.name.clone()],
+            FieldMappingType::Object(entries) => entries
+                .iter()
+                .flat_map(|entry| entry.string_fast_field_names())
+                .map(|field_name| {
+                    if self.name.is_empty() {
+                        field_name
+                    } else {
+                        format!("{}.{}", self.name, field_name)
+                    }
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the names of the geo point fields.
+    pub fn geo_point_field_names(&self) -> Vec<String> {
+        match &self.mapping_type {
+            FieldMappingType::GeoPoint(_) => vec![self.name.clone()],
+            FieldMappingType::Object(entries) => entries
+                .iter()
+                .flat_map(|entry| entry.geo_point_field_names())
+                .map(|field_name| {
+                    if self.name.is_empty() {
+                        field_name
+                    } else {
+                        format!("{}.{}", self.name, field_name)
+                    }
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
     pub fn field_mappings(&self) -> Option<Vec<FieldMappingEntry>> {
         match &self.mapping_type {
             FieldMappingType::Object(entries) => Some(entries.clone()),
@@ -112,7 +190,7 @@ impl FieldMappingEntry {
         json_value: &JsonValue,
     ) -> Result<Vec<(FieldPath, Value)>, DocParsingError> {
         match &self.mapping_type {
-            FieldMappingType::Text(options, cardinality) => {
+            FieldMappingType::Text(options, _, cardinality) => {
                 self.parse_text(json_value, options, cardinality)
             }
             FieldMappingType::I64(options, cardinality) => {
@@ -127,9 +205,10 @@ impl FieldMappingEntry {
             FieldMappingType::Date(options, cardinality) => {
                 self.parse_date(json_value, options, cardinality)
             }
-            FieldMappingType::Bytes(options, cardinality) => {
-                self.parse_bytes(json_value, options, cardinality)
+            FieldMappingType::Bytes(options, format, cardinality) => {
+                self.parse_bytes(json_value, options, format, cardinality)
             }
+            FieldMappingType::GeoPoint(_) => self.parse_geo_point(json_value),
             FieldMappingType::Object(field_mappings) => {
                 self.parse_object(json_value, field_mappings)
             }
@@ -365,6 +444,7 @@ impl FieldMappingEntry {
         &self,
         json_value: &JsonValue,
         options: &BytesOptions,
+        format: &BytesFormat,
         cardinality: &Cardinality,
     ) -> Result<Vec<(FieldPath, Value)>, DocParsingError> {
         let parsed_values = match json_value {
@@ -375,17 +455,22 @@ impl FieldMappingEntry {
                 process_results(
                     array
                         .iter()
-                        .map(|element| self.parse_bytes(element, options, cardinality)),
+                        .map(|element| self.parse_bytes(element, options, format, cardinality)),
                     |iter| iter.flatten().collect(),
                 )?
             }
             JsonValue::String(value_as_str) => {
-                let value = base64::decode(value_as_str)
+                let value = format
+                    .decode(value_as_str)
                     .map(Value::Bytes)
-                    .map_err(|_| {
+                    .ok_or_else(|| {
+                        let format_name = match format {
+                            BytesFormat::Base64 => "Base64",
+                            BytesFormat::Hex => "hexadecimal",
+                        };
                         DocParsingError::ValueError(
                             self.name.clone(),
-                            format!("Expected Base64 string, got '{}'", value_as_str),
+                            format!("Expected {} string, got '{}'", format_name, value_as_str),
                         )
                     })?;
                 vec![(FieldPath::new(&self.name), value)]
@@ -403,16 +488,57 @@ impl FieldMappingEntry {
         Ok(parsed_values)
     }
 
+    fn parse_geo_point(
+        &self,
+        json_value: &JsonValue,
+    ) -> Result<Vec<(FieldPath, Value)>, DocParsingError> {
+        let point_res = match json_value {
+            JsonValue::Array(_) => {
+                return Err(DocParsingError::MultiValuesNotSupported(self.name.clone()));
+            }
+            JsonValue::String(point_str) => point_str.parse::<GeoPoint>(),
+            JsonValue::Object(object) => match (
+                object.get("lat").and_then(JsonValue::as_f64),
+                object.get("lon").and_then(JsonValue::as_f64),
+            ) {
+                (Some(lat), Some(lon)) => GeoPoint::new(lat, lon),
+                _ => Err(anyhow::anyhow!(
+                    "Expected a point with a numeric `lat` and `lon`, got '{}'.",
+                    json_value
+                )),
+            },
+            JsonValue::Null => {
+                return Ok(vec![]);
+            }
+            _ => Err(anyhow::anyhow!(
+                "Expected a point as `{{\"lat\": .., \"lon\": ..}}` or `lat,lon`, got '{}'.",
+                json_value
+            )),
+        };
+        let point = point_res
+            .map_err(|error| DocParsingError::ValueError(self.name.clone(), error.to_string()))?;
+        Ok(vec![(
+            FieldPath::new(&self.name),
+            Value::U64(point.to_u64()),
+        )])
+    }
+
     fn parse_object<'a>(
         &'a self,
         json_value: &JsonValue,
         entries: &'a [FieldMappingEntry],
     ) -> Result<Vec<(FieldPath, Value)>, DocParsingError> {
         let parsed_values = match json_value {
-            JsonValue::Array(_) => {
-                // TODO: we can support it but we need to do some extra validation on
-                // the field mappings as they must be all multivalued.
-                return Err(DocParsingError::MultiValuesNotSupported(self.name.clone()));
+            JsonValue::Array(array) => {
+                if !entries.iter().all(FieldMappingEntry::is_multivalued) {
+                    return Err(DocParsingError::MultiValuesNotSupported(self.name.clone()));
+                }
+                process_results(
+                    array
+                        .iter()
+                        .map(|element| self.parse_object(element, entries)),
+                    |iter| iter.flatten().collect(),
+                )?
             }
             JsonValue::Object(object) => process_results(
                 entries
@@ -490,6 +616,8 @@ struct FieldMappingEntryForSerialization {
     tokenizer: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     record: Option<IndexRecordOption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_format: Option<BytesFormat>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     field_mappings: Vec<FieldMappingEntryForSerialization>,
 }
@@ -498,6 +626,12 @@ impl TryFrom<FieldMappingEntryForSerialization> for FieldMappingEntry {
     type Error = anyhow::Error;
 
     fn try_from(value: FieldMappingEntryForSerialization) -> anyhow::Result<Self> {
+        if value.input_format.is_some() && value.field_type_str() != "bytes" {
+            bail!(
+                "Error when parsing field `{}`: `input_format` is only allowed for bytes fields.",
+                value.name
+            )
+        }
         let field_type = match value.field_type_str() {
             "text" => value.new_text()?,
             "i64" => value.new_i64()?,
@@ -505,6 +639,7 @@ impl TryFrom<FieldMappingEntryForSerialization> for FieldMappingEntry {
             "f64" => value.new_f64()?,
             "date" => value.new_date()?,
             "bytes" => value.new_bytes()?,
+            "geo_point" => value.new_geo_point()?,
             "object" => value.new_object()?,
             type_str => bail!(
                 "Field `{}` has an unknown type: `{}`.",
@@ -533,9 +668,11 @@ impl From<FieldMappingEntry> for FieldMappingEntryForSerialization {
         let mut record = None;
         let mut stored = false;
         let mut tokenizer: Option<String> = None;
+        let mut input_format = None;
         match value.mapping_type {
-            FieldMappingType::Text(text_options, _) => {
+            FieldMappingType::Text(text_options, is_fast, _) => {
                 stored = text_options.is_stored();
+                fast = is_fast;
                 if let Some(indexing_options) = text_options.get_indexing_options() {
                     tokenizer = Some(indexing_options.tokenizer().to_owned());
                     record = Some(indexing_options.index_option());
@@ -551,10 +688,14 @@ impl From<FieldMappingEntry> for FieldMappingEntryForSerialization {
                 indexed = Some(options.is_indexed());
                 fast = options.get_fastfield_cardinality().is_some();
             }
-            FieldMappingType::Bytes(options, _) => {
+            FieldMappingType::Bytes(options, format, _) => {
                 stored = options.is_stored();
                 indexed = Some(options.is_indexed());
                 fast = options.is_fast();
+                input_format = Some(format).filter(|format| *format != BytesFormat::default());
+            }
+            FieldMappingType::GeoPoint(_) => {
+                fast = true;
             }
             _ => (),
         }
@@ -567,6 +708,7 @@ impl From<FieldMappingEntry> for FieldMappingEntryForSerialization {
             record,
             stored,
             tokenizer,
+            input_format,
             field_mappings,
         }
     }
@@ -595,9 +737,10 @@ impl FieldMappingEntryForSerialization {
     }
 
     fn new_text(&self) -> anyhow::Result<FieldMappingType> {
-        if self.fast {
+        // The dictionary encoded fast field holds a single value per document.
+        if self.fast && self.is_array() {
             bail!(
-                "Error when parsing field `{}`: fast=true not yet supported for text field.",
+                "Error when parsing field `{}`: fast=true is not supported for array<text> fields.",
                 self.name
             )
         }
@@ -621,7 +764,11 @@ impl FieldMappingEntryForSerialization {
         if self.stored {
             options = options.set_stored();
         }
-        Ok(FieldMappingType::Text(options, self.cardinality()))
+        Ok(FieldMappingType::Text(
+            options,
+            self.fast,
+            self.cardinality(),
+        ))
     }
 
     fn new_i64(&self) -> anyhow::Result<FieldMappingType> {
@@ -656,7 +803,32 @@ impl FieldMappingEntryForSerialization {
         if self.fast {
             options = options.set_fast();
         }
-        Ok(FieldMappingType::Bytes(options, self.cardinality()))
+        Ok(FieldMappingType::Bytes(
+            options,
+            self.input_format.unwrap_or_default(),
+            self.cardinality(),
+        ))
+    }
+
+    fn new_geo_point(&self) -> anyhow::Result<FieldMappingType> {
+        self.check_no_text_options()?;
+        if self.is_array() {
+            bail!(
+                "Error when parsing field `{}`: array of geo_point is not supported.",
+                self.name
+            )
+        }
+        if self.indexed == Some(true) {
+            bail!(
+                "Error when parsing field `{}`: geo_point fields cannot be indexed, they are \
+                 filtered on their fast field.",
+                self.name
+            )
+        }
+        // Geo points are only kept in a fast field: their encoded value is of no use in
+        // the document store, the `_source` holds the original one.
+        let options = IntOptions::default().set_fast(Cardinality::SingleValue);
+        Ok(FieldMappingType::GeoPoint(options))
     }
 
     fn new_object(&self) -> anyhow::Result<FieldMappingType> {
@@ -753,11 +925,11 @@ mod tests {
     use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
     use matches::matches;
     use serde_json::json;
-    use tantivy::schema::{Cardinality, Value};
+    use tantivy::schema::{Cardinality, FieldType, Value};
 
     use super::FieldMappingEntry;
     use crate::default_index_config::FieldMappingType;
-    use crate::DocParsingError;
+    use crate::{DocParsingError, GeoPoint};
 
     const TEXT_MAPPING_ENTRY_VALUE: &str = r#"
         {
@@ -787,8 +959,9 @@ mod tests {
         let mapping_entry = serde_json::from_str::<FieldMappingEntry>(TEXT_MAPPING_ENTRY_VALUE)?;
         assert_eq!(mapping_entry.name, "my_field_name");
         match mapping_entry.mapping_type {
-            FieldMappingType::Text(options, _) => {
+            FieldMappingType::Text(options, is_fast, _) => {
                 assert_eq!(options.is_stored(), true);
+                assert!(!is_fast);
                 let indexing_options = options
                     .get_indexing_options()
                     .expect("should have indexing option");
@@ -822,6 +995,68 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_deserialize_text_fast_field() -> anyhow::Result<()> {
+        let mapping_entry = serde_json::from_str::<FieldMappingEntry>(
+            r#"
+            {
+                "name": "level",
+                "type": "text",
+                "tokenizer": "raw",
+                "fast": true
+            }
+            "#,
+        )?;
+        assert!(matches!(
+            mapping_entry.mapping_type,
+            FieldMappingType::Text(_, true, Cardinality::SingleValue)
+        ));
+        assert_eq!(mapping_entry.string_fast_field_names(), vec!["level"]);
+        assert_eq!(serde_json::to_value(&mapping_entry)?["fast"], true);
+        // The text field itself is left as is, the dictionary encoded values are held by a
+        // hidden facet field.
+        let field_entries = mapping_entry.field_entries()?;
+        assert_eq!(field_entries.len(), 1);
+        assert!(matches!(&field_entries[0].1, FieldType::Str(_)));
+
+        let error = serde_json::from_str::<FieldMappingEntry>(
+            r#"
+            {
+                "name": "levels",
+                "type": "array<text>",
+                "fast": true
+            }
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Error when parsing field `levels`: fast=true is not supported for array<text> fields."
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_string_fast_field_names() -> anyhow::Result<()> {
+        let mapping_entry = serde_json::from_str::<FieldMappingEntry>(
+            r#"
+            {
+                "name": "service",
+                "type": "object",
+                "field_mappings": [
+                    {"name": "name", "type": "text", "fast": true},
+                    {"name": "version", "type": "text"}
+                ]
+            }
+            "#,
+        )?;
+        assert_eq!(
+            mapping_entry.string_fast_field_names(),
+            vec!["service.name"]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_error_on_unknown_fields() -> anyhow::Result<()> {
         let result = serde_json::from_str::<FieldMappingEntry>(
@@ -874,6 +1109,77 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_array_of_objects() -> anyhow::Result<()> {
+        let entry = serde_json::from_str::<FieldMappingEntry>(
+            r#"
+            {
+                "name": "servers",
+                "type": "object",
+                "field_mappings": [
+                    {
+                        "name": "host",
+                        "type": "array<text>"
+                    },
+                    {
+                        "name": "port",
+                        "type": "array<u64>"
+                    }
+                ]
+            }
+            "#,
+        )?;
+        let parsed_values = entry.parse(&json!([
+            {"host": "alpha", "port": 80},
+            {"host": "beta", "port": [443, 8443]}
+        ]))?;
+        let parsed_values: Vec<(String, Value)> = parsed_values
+            .into_iter()
+            .map(|(path, value)| (path.field_name(), value))
+            .collect();
+        assert_eq!(
+            parsed_values,
+            vec![
+                ("servers.host".to_string(), Value::Str("alpha".to_string())),
+                ("servers.port".to_string(), Value::U64(80)),
+                ("servers.host".to_string(), Value::Str("beta".to_string())),
+                ("servers.port".to_string(), Value::U64(443)),
+                ("servers.port".to_string(), Value::U64(8443)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fail_to_parse_array_of_objects_with_singlevalued_fields() -> anyhow::Result<()> {
+        let entry = serde_json::from_str::<FieldMappingEntry>(
+            r#"
+            {
+                "name": "servers",
+                "type": "object",
+                "field_mappings": [
+                    {
+                        "name": "host",
+                        "type": "array<text>"
+                    },
+                    {
+                        "name": "port",
+                        "type": "u64"
+                    }
+                ]
+            }
+            "#,
+        )?;
+        let parsed_error = entry.parse(&json!([{"host": "alpha", "port": 80}]));
+        assert_eq!(
+            parsed_error.err(),
+            Some(DocParsingError::MultiValuesNotSupported(
+                "servers".to_string()
+            ))
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_deserialize_field_with_unknown_type() {
         let result = serde_json::from_str::<FieldMappingEntry>(
@@ -1431,6 +1737,128 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parse_hex_bytes() -> anyhow::Result<()> {
+        let entry = serde_json::from_str::<FieldMappingEntry>(
+            r#"
+            {
+                "name": "trace_id",
+                "type": "bytes",
+                "input_format": "hex"
+            }
+            "#,
+        )?;
+
+        // Successful parsing
+        let parsed_value = entry.parse(&json!("0aF3"))?;
+        assert_eq!(parsed_value.len(), 1);
+        assert_eq!(parsed_value[0].1, Value::Bytes(vec![10, 243]));
+
+        // Failed parsing
+        let parsed_error = entry.parse(&json!("dGhpcw=="));
+        assert_eq!(
+            parsed_error.err(),
+            Some(DocParsingError::ValueError(
+                "trace_id".to_string(),
+                "Expected hexadecimal string, got 'dGhpcw=='".to_string()
+            ))
+        );
+
+        let mapping_json = serde_json::to_value(&entry)?;
+        assert_eq!(mapping_json["input_format"], "hex");
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_input_format_on_non_bytes_field() {
+        let result = serde_json::from_str::<FieldMappingEntry>(
+            r#"
+            {
+                "name": "my_field_name",
+                "type": "text",
+                "input_format": "hex"
+            }
+            "#,
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error when parsing field `my_field_name`: `input_format` is only allowed for bytes \
+             fields."
+        );
+    }
+
+    #[test]
+    fn test_parse_geo_point() -> anyhow::Result<()> {
+        let entry = serde_json::from_str::<FieldMappingEntry>(
+            r#"
+            {
+                "name": "location",
+                "type": "geo_point"
+            }
+            "#,
+        )?;
+        let expected_value = Value::U64(GeoPoint::new(48.8566, 2.3522)?.to_u64());
+
+        // Successful parsing
+        let parsed_value = entry.parse(&json!({"lat": 48.8566, "lon": 2.3522}))?;
+        assert_eq!(parsed_value.len(), 1);
+        assert_eq!(parsed_value[0].0.field_name(), "location");
+        assert_eq!(parsed_value[0].1, expected_value);
+        let parsed_value = entry.parse(&json!("48.8566,2.3522"))?;
+        assert_eq!(parsed_value[0].1, expected_value);
+        assert!(entry.parse(&json!(null))?.is_empty());
+
+        // Failed parsing
+        assert!(matches!(
+            entry.parse(&json!({"lat": 95.0, "lon": 2.3522})),
+            Err(DocParsingError::ValueError(_, _))
+        ));
+        assert!(matches!(
+            entry.parse(&json!({"lat": 48.8566})),
+            Err(DocParsingError::ValueError(_, _))
+        ));
+        assert!(matches!(
+            entry.parse(&json!(48.8566)),
+            Err(DocParsingError::ValueError(_, _))
+        ));
+        assert!(matches!(
+            entry.parse(&json!([48.8566, 2.3522])),
+            Err(DocParsingError::MultiValuesNotSupported(_))
+        ));
+
+        let field_entries = entry.field_entries()?;
+        assert_eq!(field_entries.len(), 1);
+        assert!(matches!(&field_entries[0].1, FieldType::U64(options) if options.is_fast()));
+        assert_eq!(serde_json::to_value(&entry)?["type"], "geo_point");
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_invalid_geo_point_field() {
+        let result = serde_json::from_str::<FieldMappingEntry>(
+            r#"
+            {
+                "name": "location",
+                "type": "array<geo_point>"
+            }
+            "#,
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error when parsing field `location`: array of geo_point is not supported."
+        );
+        let result = serde_json::from_str::<FieldMappingEntry>(
+            r#"
+            {
+                "name": "location",
+                "type": "geo_point",
+                "indexed": true
+            }
+            "#,
+        );
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_parse_mutivalued_bytes() -> anyhow::Result<()> {
         let entry = serde_json::from_str::<FieldMappingEntry>(
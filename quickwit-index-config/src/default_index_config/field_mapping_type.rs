@@ -17,6 +17,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use serde::{Deserialize, Serialize};
 use tantivy::schema::{BytesOptions, Cardinality, IntOptions, TextOptions};
 
 use super::FieldMappingEntry;
@@ -25,8 +26,9 @@ use super::FieldMappingEntry;
 /// of a mapping field.
 #[derive(Clone, Debug)]
 pub enum FieldMappingType {
-    /// String mapping type configuration.
-    Text(TextOptions, Cardinality),
+    /// String mapping type configuration, and whether its values are dictionary encoded in a
+    /// fast field.
+    Text(TextOptions, bool, Cardinality),
     /// Signed 64-bit integer mapping type configuration.
     I64(IntOptions, Cardinality),
     /// Unsigned 64-bit integer mapping type configuration.
@@ -36,7 +38,9 @@ pub enum FieldMappingType {
     /// RFC 3339 date mapping type configuration.
     Date(IntOptions, Cardinality),
     /// Bytes mapping type configuration.
-    Bytes(BytesOptions, Cardinality),
+    Bytes(BytesOptions, BytesFormat, Cardinality),
+    /// Geo point mapping type configuration.
+    GeoPoint(IntOptions),
     /// Object mapping type configuration.
     Object(Vec<FieldMappingEntry>),
 }
@@ -49,9 +53,11 @@ impl FieldMappingType {
             | FieldMappingType::U64(_, cardinality)
             | FieldMappingType::Date(_, cardinality)
             | FieldMappingType::F64(_, cardinality) => cardinality,
-            FieldMappingType::Text(_, cardinality) => cardinality,
-            FieldMappingType::Bytes(_, cardinality) => cardinality,
-            FieldMappingType::Object(_) => &Cardinality::SingleValue,
+            FieldMappingType::Text(_, _, cardinality) => cardinality,
+            FieldMappingType::Bytes(_, _, cardinality) => cardinality,
+            FieldMappingType::GeoPoint(_) | FieldMappingType::Object(_) => {
+                &Cardinality::SingleValue
+            }
         };
         if cardinality == &Cardinality::MultiValues {
             format!("array<{}>", self.field_type_str())
@@ -68,7 +74,51 @@ impl FieldMappingType {
             FieldMappingType::F64(..) => "f64",
             FieldMappingType::Date(..) => "date",
             FieldMappingType::Bytes(..) => "bytes",
+            FieldMappingType::GeoPoint(..) => "geo_point",
             FieldMappingType::Object(..) => "object",
         }
     }
 }
+
+/// Encoding of the values of a bytes field, in the JSON documents as well as in the queries
+/// and the tags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BytesFormat {
+    /// Base64 with padding, as in `YWJj`.
+    Base64,
+    /// Hexadecimal, upper or lower case, as in `616263`.
+    Hex,
+}
+
+impl Default for BytesFormat {
+    fn default() -> Self {
+        BytesFormat::Base64
+    }
+}
+
+impl BytesFormat {
+    /// Returns the name of the format, as written in the field mappings.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BytesFormat::Base64 => "base64",
+            BytesFormat::Hex => "hex",
+        }
+    }
+
+    /// Decodes `value`, returning `None` if it is not valid in this format.
+    pub fn decode(&self, value: &str) -> Option<Vec<u8>> {
+        match self {
+            BytesFormat::Base64 => base64::decode(value).ok(),
+            BytesFormat::Hex => hex::decode(value).ok(),
+        }
+    }
+
+    /// Encodes `bytes` in this format.
+    pub fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            BytesFormat::Base64 => base64::encode(bytes),
+            BytesFormat::Hex => hex::encode(bytes),
+        }
+    }
+}
@@ -26,7 +26,7 @@ use regex::Regex;
 
 pub use self::default_config::{DefaultIndexConfig, DefaultIndexConfigBuilder};
 pub use self::field_mapping_entry::{DocParsingError, FieldMappingEntry};
-pub use self::field_mapping_type::FieldMappingType;
+pub use self::field_mapping_type::{BytesFormat, FieldMappingType};
 
 /// Regular expression representing the restriction on a valid field name.
 pub const FIELD_MAPPING_NAME_PATTERN: &str = r#"^[_a-zA-Z][_\.\-a-zA-Z0-9]*$"#;
@@ -0,0 +1,432 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use regex::Regex;
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use tantivy::schema::{FieldType, Schema, Type};
+
+use crate::{DocParsingError, ExtractionConfig};
+
+/// Built-in grok patterns, a subset of the Logstash ones.
+const GROK_PATTERNS: &[(&str, &str)] = &[
+    ("WORD", r"\b\w+\b"),
+    ("NOTSPACE", r"\S+"),
+    ("SPACE", r"\s*"),
+    ("DATA", r".*?"),
+    ("GREEDYDATA", r".*"),
+    ("INT", r"[+-]?[0-9]+"),
+    ("POSINT", r"\b[1-9][0-9]*\b"),
+    ("NUMBER", r"[+-]?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+)"),
+    (
+        "IPV4",
+        r"(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)",
+    ),
+    (
+        "IPV6",
+        r"(?:[0-9A-Fa-f]{1,4}:){7}[0-9A-Fa-f]{1,4}|(?:[0-9A-Fa-f]{1,4}:){0,6}[0-9A-Fa-f]{0,4}::(?:[0-9A-Fa-f]{1,4}:){0,6}[0-9A-Fa-f]{0,4}",
+    ),
+    ("IP", r"%{IPV6}|%{IPV4}"),
+    (
+        "HOSTNAME",
+        r"\b[0-9A-Za-z][0-9A-Za-z-]{0,62}(?:\.[0-9A-Za-z][0-9A-Za-z-]{0,62})*\.?\b",
+    ),
+    ("IPORHOST", r"%{IP}|%{HOSTNAME}"),
+    (
+        "LOGLEVEL",
+        r"(?i:trace|debug|info|notice|warn(?:ing)?|err(?:or)?|crit(?:ical)?|fatal|severe|emerg(?:ency)?|alert)",
+    ),
+    (
+        "TIMESTAMP_ISO8601",
+        r"[0-9]{4}-[0-9]{2}-[0-9]{2}[T ][0-9]{2}:[0-9]{2}:[0-9]{2}(?:\.[0-9]+)?(?:Z|[+-][0-9]{2}:?[0-9]{2})?",
+    ),
+    (
+        "SYSLOGTIMESTAMP",
+        r"(?:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) +[0-9]{1,2} [0-9]{2}:[0-9]{2}:[0-9]{2}",
+    ),
+    (
+        "HTTPDATE",
+        r"[0-9]{2}/\w{3}/[0-9]{4}:[0-9]{2}:[0-9]{2}:[0-9]{2} [+-][0-9]{4}",
+    ),
+    ("PROG", r"[\x21-\x5a\x5c\x5e-\x7e]+"),
+    ("QUOTEDSTRING", r#""(?:[^"\\]|\\.)*""#),
+    ("URIPATH", r"/[^\s?#]*"),
+];
+
+/// Returns true if `field_name` can name a capture group.
+fn is_valid_capture_name(field_name: &str) -> bool {
+    let mut chars = field_name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Expands the `%{NAME}` and `%{NAME:field}` references of a grok pattern into a regex.
+fn expand_grok_pattern(pattern: &str) -> anyhow::Result<String> {
+    let mut regex = String::with_capacity(pattern.len());
+    let mut remaining = pattern;
+    while let Some(start) = remaining.find("%{") {
+        regex.push_str(&remaining[..start]);
+        let end = start
+            + remaining[start..]
+                .find('}')
+                .with_context(|| format!("Unclosed grok reference in pattern `{}`.", pattern))?;
+        let reference = &remaining[start + 2..end];
+        let (name, field_name_opt) = match reference.split_once(':') {
+            Some((name, field_name)) => (name, Some(field_name)),
+            None => (reference, None),
+        };
+        let sub_pattern = GROK_PATTERNS
+            .iter()
+            .find(|(grok_name, _)| *grok_name == name)
+            .map(|(_, sub_pattern)| *sub_pattern)
+            .with_context(|| format!("Unknown grok pattern `{}`.", name))?;
+        let sub_regex = expand_grok_pattern(sub_pattern)?;
+        match field_name_opt {
+            Some(field_name) => {
+                if !is_valid_capture_name(field_name) {
+                    bail!(
+                        "Invalid grok field name `{}`, only top-level field names made of \
+                         letters, digits and underscores can be captured.",
+                        field_name
+                    );
+                }
+                regex.push_str(&format!("(?P<{}>{})", field_name, sub_regex));
+            }
+            None => regex.push_str(&format!("(?:{})", sub_regex)),
+        }
+        remaining = &remaining[end + 1..];
+    }
+    regex.push_str(remaining);
+    Ok(regex)
+}
+
+/// An extraction whose pattern is compiled and whose captured fields are resolved
+/// against the schema.
+#[derive(Clone, Debug)]
+pub(crate) struct Extraction {
+    pub config: ExtractionConfig,
+    regex: Regex,
+    /// Captured field names and the type of their values.
+    target_fields: Vec<(String, Type)>,
+}
+
+impl Extraction {
+    pub fn new(config: ExtractionConfig, schema: &Schema) -> anyhow::Result<Self> {
+        let source_field = schema.get_field(&config.source_field).with_context(|| {
+            format!("Unknown extraction source field: `{}`", config.source_field)
+        })?;
+        if !matches!(
+            schema.get_field_entry(source_field).field_type(),
+            FieldType::Str(_)
+        ) {
+            bail!(
+                "Extraction source field must be a text field, please change your field type `{}` \
+                 to text.",
+                config.source_field
+            );
+        }
+        let regex = Regex::new(&expand_grok_pattern(&config.pattern)?).map_err(|error| {
+            anyhow::anyhow!("Invalid extraction pattern `{}`: {}", config.pattern, error)
+        })?;
+        let mut target_fields = Vec::new();
+        for field_name in regex.capture_names().flatten() {
+            if field_name == config.source_field {
+                bail!(
+                    "Extraction pattern `{}` cannot capture its source field `{}`.",
+                    config.pattern,
+                    field_name
+                );
+            }
+            let field = schema
+                .get_field(field_name)
+                .with_context(|| format!("Unknown extraction target field: `{}`", field_name))?;
+            let value_type = match schema.get_field_entry(field).field_type() {
+                FieldType::Str(_) => Type::Str,
+                FieldType::I64(_) => Type::I64,
+                FieldType::U64(_) => Type::U64,
+                FieldType::F64(_) => Type::F64,
+                FieldType::Date(_) => Type::Date,
+                _ => bail!(
+                    "Extraction target field must be of type text, i64, u64, f64 or date, please \
+                     change your field type `{}`.",
+                    field_name
+                ),
+            };
+            target_fields.push((field_name.to_string(), value_type));
+        }
+        if target_fields.is_empty() {
+            bail!(
+                "Extraction pattern `{}` does not capture any field.",
+                config.pattern
+            );
+        }
+        Ok(Extraction {
+            config,
+            regex,
+            target_fields,
+        })
+    }
+
+    /// Adds the fields captured from the source field of the document to `json_obj`.
+    ///
+    /// Documents whose source field does not match the pattern are left untouched, and so
+    /// are the fields the document already sets.
+    pub fn apply(&self, json_obj: &mut JsonMap<String, JsonValue>) -> Result<(), DocParsingError> {
+        let captures = match json_obj
+            .get(&self.config.source_field)
+            .and_then(JsonValue::as_str)
+            .and_then(|text| self.regex.captures(text))
+        {
+            Some(captures) => captures,
+            None => return Ok(()),
+        };
+        let mut captured_values = Vec::with_capacity(self.target_fields.len());
+        for (field_name, value_type) in self.target_fields.iter() {
+            if let Some(capture) = captures.name(field_name) {
+                let value = captured_value(field_name, capture.as_str(), *value_type)?;
+                captured_values.push((field_name.clone(), value));
+            }
+        }
+        for (field_name, value) in captured_values {
+            json_obj.entry(field_name).or_insert(value);
+        }
+        Ok(())
+    }
+}
+
+/// Converts the captured text into the json value expected by the field mapping.
+fn captured_value(
+    field_name: &str,
+    text: &str,
+    value_type: Type,
+) -> Result<JsonValue, DocParsingError> {
+    let value_res = match value_type {
+        Type::I64 => text.parse::<i64>().map(JsonValue::from).ok(),
+        Type::U64 => text.parse::<u64>().map(JsonValue::from).ok(),
+        Type::F64 => text.parse::<f64>().map(JsonValue::from).ok(),
+        Type::Date => {
+            parse_captured_date(text, Utc::now()).map(|date| JsonValue::String(date.to_rfc3339()))
+        }
+        _ => Some(JsonValue::String(text.to_string())),
+    };
+    value_res.ok_or_else(|| {
+        let message = if value_type == Type::Date {
+            format!("Expected date, got '{}'.", text)
+        } else {
+            format!("Expected {:?} number, got '{}'.", value_type, text)
+        };
+        DocParsingError::ValueError(field_name.to_string(), message)
+    })
+}
+
+/// Parses a date captured by the `TIMESTAMP_ISO8601`, `HTTPDATE` or `SYSLOGTIMESTAMP` grok
+/// patterns, or an RFC 3339 date.
+///
+/// The dates without a time zone are in UTC, and the syslog dates, which have no year, are
+/// in the year of `now`.
+fn parse_captured_date(text: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let iso8601_text = text.replacen(' ', "T", 1);
+    if let Ok(date) = DateTime::parse_from_rfc3339(&iso8601_text) {
+        return Some(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = DateTime::parse_from_str(&iso8601_text, "%Y-%m-%dT%H:%M:%S%.f%z") {
+        return Some(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(&iso8601_text, "%Y-%m-%dT%H:%M:%S%.f") {
+        return Some(DateTime::from_utc(date, Utc));
+    }
+    if let Ok(date) = DateTime::parse_from_str(text, "%d/%b/%Y:%H:%M:%S %z") {
+        return Some(date.with_timezone(&Utc));
+    }
+    let syslog_text = format!("{} {}", now.year(), text);
+    NaiveDateTime::parse_from_str(&syslog_text, "%Y %b %e %H:%M:%S")
+        .ok()
+        .map(|date| DateTime::from_utc(date, Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+    use tantivy::schema::{BytesOptions, FAST, STRING, TEXT};
+
+    use super::*;
+
+    fn schema() -> Schema {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("message", TEXT);
+        schema_builder.add_text_field("program", STRING);
+        schema_builder.add_u64_field("pid", FAST);
+        schema_builder.add_bytes_field("payload", BytesOptions::default());
+        schema_builder.build()
+    }
+
+    fn extraction_config(pattern: &str) -> ExtractionConfig {
+        ExtractionConfig {
+            source_field: "message".to_string(),
+            pattern: pattern.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_expand_grok_pattern() -> anyhow::Result<()> {
+        assert_eq!(expand_grok_pattern("^[a-z]+$")?, "^[a-z]+$");
+        assert_eq!(
+            expand_grok_pattern("%{WORD:program}: %{INT}")?,
+            r"(?P<program>\b\w+\b): (?:[+-]?[0-9]+)"
+        );
+        assert!(expand_grok_pattern("%{IPORHOST:host}")?.starts_with("(?P<host>(?:(?:"));
+        for (name, _) in GROK_PATTERNS {
+            Regex::new(&expand_grok_pattern(&format!("%{{{}}}", name))?)?;
+        }
+        assert_eq!(
+            expand_grok_pattern("%{WORD").unwrap_err().to_string(),
+            "Unclosed grok reference in pattern `%{WORD`."
+        );
+        assert_eq!(
+            expand_grok_pattern("%{UNKNOWN:program}")
+                .unwrap_err()
+                .to_string(),
+            "Unknown grok pattern `UNKNOWN`."
+        );
+        assert!(expand_grok_pattern("%{WORD:attributes.program}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_extraction_apply() -> anyhow::Result<()> {
+        let extraction = Extraction::new(
+            extraction_config(r"^%{SYSLOGTIMESTAMP} %{HOSTNAME} %{PROG:program}\[%{POSINT:pid}\]"),
+            &schema(),
+        )?;
+        let mut json_obj = json!({
+            "message": "Oct 14 07:23:06 web-1 sshd[4242]: Accepted publickey for root"
+        });
+        extraction.apply(json_obj.as_object_mut().unwrap())?;
+        assert_eq!(json_obj["program"], "sshd");
+        assert_eq!(json_obj["pid"], 4242);
+
+        let mut json_obj = json!({
+            "message": "Oct 14 07:23:06 web-1 cron[1]: (root) CMD (true)",
+            "program": "crond"
+        });
+        extraction.apply(json_obj.as_object_mut().unwrap())?;
+        assert_eq!(json_obj["program"], "crond");
+        assert_eq!(json_obj["pid"], 1);
+
+        let mut json_obj = json!({ "message": "not a syslog line" });
+        extraction.apply(json_obj.as_object_mut().unwrap())?;
+        assert_eq!(json_obj, json!({ "message": "not a syslog line" }));
+
+        let extraction = Extraction::new(extraction_config(r"pid=(?P<pid>\S+)"), &schema())?;
+        let mut json_obj = json!({ "message": "pid=abc" });
+        assert!(matches!(
+            extraction.apply(json_obj.as_object_mut().unwrap()),
+            Err(DocParsingError::ValueError(field_name, _)) if field_name == "pid"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_extraction_apply_date() -> anyhow::Result<()> {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("message", TEXT);
+        schema_builder.add_date_field("ts", FAST);
+        let schema = schema_builder.build();
+        let extraction = Extraction::new(extraction_config("^%{TIMESTAMP_ISO8601:ts} "), &schema)?;
+        let mut json_obj = json!({ "message": "2021-10-14 07:23:06.5+02:00 started" });
+        extraction.apply(json_obj.as_object_mut().unwrap())?;
+        assert_eq!(json_obj["ts"], "2021-10-14T05:23:06.500+00:00");
+
+        let extraction = Extraction::new(extraction_config(r"\[%{HTTPDATE:ts}\]"), &schema)?;
+        let mut json_obj = json!({ "message": "GET / [14/Oct/2021:07:23:06 -0700]" });
+        extraction.apply(json_obj.as_object_mut().unwrap())?;
+        assert_eq!(json_obj["ts"], "2021-10-14T14:23:06+00:00");
+
+        let extraction = Extraction::new(extraction_config(r"ts=(?P<ts>\S+)"), &schema)?;
+        let mut json_obj = json!({ "message": "ts=yesterday" });
+        assert!(matches!(
+            extraction.apply(json_obj.as_object_mut().unwrap()),
+            Err(DocParsingError::ValueError(field_name, _)) if field_name == "ts"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_captured_date() {
+        let now = Utc.ymd(2021, 10, 14).and_hms(12, 0, 0);
+        let parse = |text: &str| parse_captured_date(text, now).map(|date| date.to_rfc3339());
+        assert_eq!(
+            parse("2021-10-14T07:23:06Z").as_deref(),
+            Some("2021-10-14T07:23:06+00:00")
+        );
+        assert_eq!(
+            parse("2021-10-14T07:23:06+0200").as_deref(),
+            Some("2021-10-14T05:23:06+00:00")
+        );
+        assert_eq!(
+            parse("2021-10-14 07:23:06").as_deref(),
+            Some("2021-10-14T07:23:06+00:00")
+        );
+        assert_eq!(
+            parse("Oct  4 07:23:06").as_deref(),
+            Some("2021-10-04T07:23:06+00:00")
+        );
+        assert_eq!(parse("not a date"), None);
+    }
+
+    #[test]
+    fn test_extraction_validation() {
+        let schema = schema();
+        let error_msg = |source_field: &str, pattern: &str| {
+            let extraction_config = ExtractionConfig {
+                source_field: source_field.to_string(),
+                pattern: pattern.to_string(),
+            };
+            Extraction::new(extraction_config, &schema)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error_msg("unknown", "%{WORD:program}"),
+            "Unknown extraction source field: `unknown`"
+        );
+        assert_eq!(
+            error_msg("pid", "%{WORD:program}"),
+            "Extraction source field must be a text field, please change your field type `pid` to \
+             text."
+        );
+        assert_eq!(
+            error_msg("message", "%{WORD:host}"),
+            "Unknown extraction target field: `host`"
+        );
+        assert_eq!(
+            error_msg("message", "%{WORD:message}"),
+            "Extraction pattern `%{WORD:message}` cannot capture its source field `message`."
+        );
+        assert!(error_msg("message", "%{WORD:payload}")
+            .starts_with("Extraction target field must be of type text"));
+        assert_eq!(
+            error_msg("message", "%{WORD}"),
+            "Extraction pattern `%{WORD}` does not capture any field."
+        );
+        assert!(error_msg("message", "(%{WORD:program}").starts_with("Invalid extraction pattern"));
+    }
+}
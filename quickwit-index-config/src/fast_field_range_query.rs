@@ -0,0 +1,284 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::ops::{Bound, RangeBounds};
+
+use tantivy::fastfield::{
+    DynamicFastFieldReader, FastFieldReader, FastValue, MultiValuedFastFieldReader,
+};
+use tantivy::query::{ConstScorer, Explanation, Query, Scorer, Weight};
+use tantivy::schema::{Cardinality, Field, FieldType};
+use tantivy::{DocId, DocSet, Score, Searcher, SegmentReader, TantivyError, TERMINATED};
+
+/// Query matching the documents whose value of a numeric fast field is within a range.
+///
+/// Unlike a `RangeQuery`, it does not require the field to be indexed: the values of the
+/// fast field are scanned instead of the postings of the terms of the range. The bounds are
+/// in the `u64` representation of the fast field values, which preserves their order.
+#[derive(Clone, Debug)]
+pub struct FastFieldRangeQuery {
+    field: Field,
+    left_bound: Bound<u64>,
+    right_bound: Bound<u64>,
+}
+
+fn map_bound<T: FastValue>(bound: Bound<T>) -> Bound<u64> {
+    match bound {
+        Bound::Included(value) => Bound::Included(value.to_u64()),
+        Bound::Excluded(value) => Bound::Excluded(value.to_u64()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+impl FastFieldRangeQuery {
+    /// Creates a range query on an `i64` or `date` fast field. The bounds of a `date` field
+    /// are timestamps.
+    pub fn new_i64_bounds(field: Field, left_bound: Bound<i64>, right_bound: Bound<i64>) -> Self {
+        FastFieldRangeQuery {
+            field,
+            left_bound: map_bound(left_bound),
+            right_bound: map_bound(right_bound),
+        }
+    }
+
+    /// Creates a range query on a `u64` fast field.
+    pub fn new_u64_bounds(field: Field, left_bound: Bound<u64>, right_bound: Bound<u64>) -> Self {
+        FastFieldRangeQuery {
+            field,
+            left_bound,
+            right_bound,
+        }
+    }
+
+    /// Creates a range query on an `f64` fast field.
+    pub fn new_f64_bounds(field: Field, left_bound: Bound<f64>, right_bound: Bound<f64>) -> Self {
+        FastFieldRangeQuery {
+            field,
+            left_bound: map_bound(left_bound),
+            right_bound: map_bound(right_bound),
+        }
+    }
+
+    /// Returns the field of the range.
+    pub fn field(&self) -> Field {
+        self.field
+    }
+
+    /// Returns the lower bound of the range, in the `u64` representation of the values.
+    pub fn left_bound(&self) -> Bound<u64> {
+        self.left_bound
+    }
+
+    /// Returns the upper bound of the range, in the `u64` representation of the values.
+    pub fn right_bound(&self) -> Bound<u64> {
+        self.right_bound
+    }
+}
+
+impl Query for FastFieldRangeQuery {
+    fn weight(
+        &self,
+        _searcher: &Searcher,
+        _scoring_enabled: bool,
+    ) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(FastFieldRangeWeight {
+            field: self.field,
+            range: (self.left_bound, self.right_bound),
+        }))
+    }
+}
+
+struct FastFieldRangeWeight {
+    field: Field,
+    range: (Bound<u64>, Bound<u64>),
+}
+
+impl Weight for FastFieldRangeWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let field_entry = reader.schema().get_field_entry(self.field);
+        let cardinality_opt = match field_entry.field_type() {
+            FieldType::I64(options)
+            | FieldType::U64(options)
+            | FieldType::F64(options)
+            | FieldType::Date(options) => options.get_fastfield_cardinality(),
+            _ => None,
+        };
+        let values = match cardinality_opt {
+            Some(Cardinality::SingleValue) => {
+                FastFieldValues::SingleValue(reader.fast_fields().u64_lenient(self.field)?)
+            }
+            Some(Cardinality::MultiValues) => FastFieldValues::MultiValues(
+                reader.fast_fields().u64s_lenient(self.field)?,
+                Vec::new(),
+            ),
+            None => {
+                return Err(TantivyError::SchemaError(format!(
+                    "Field `{}` is not a numeric fast field.",
+                    field_entry.name()
+                )))
+            }
+        };
+        let doc_set = FastFieldRangeDocSet::new(values, self.range, reader.max_doc());
+        Ok(Box::new(ConstScorer::new(doc_set, boost)))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Document #({}) does not match",
+                doc
+            )));
+        }
+        Ok(Explanation::new("FastFieldRangeQuery", 1.0))
+    }
+}
+
+enum FastFieldValues {
+    SingleValue(DynamicFastFieldReader<u64>),
+    MultiValues(MultiValuedFastFieldReader<u64>, Vec<u64>),
+}
+
+struct FastFieldRangeDocSet {
+    values: FastFieldValues,
+    range: (Bound<u64>, Bound<u64>),
+    doc: DocId,
+    max_doc: DocId,
+}
+
+impl FastFieldRangeDocSet {
+    fn new(values: FastFieldValues, range: (Bound<u64>, Bound<u64>), max_doc: DocId) -> Self {
+        let mut doc_set = FastFieldRangeDocSet {
+            values,
+            range,
+            doc: 0,
+            max_doc,
+        };
+        doc_set.doc = doc_set.next_match(0);
+        doc_set
+    }
+
+    fn matches(&mut self, doc: DocId) -> bool {
+        match &mut self.values {
+            FastFieldValues::SingleValue(reader) => self.range.contains(&reader.get(doc)),
+            FastFieldValues::MultiValues(reader, vals) => {
+                reader.get_vals(doc, vals);
+                vals.iter().any(|value| self.range.contains(value))
+            }
+        }
+    }
+
+    /// Returns the first matching document from `doc` on.
+    fn next_match(&mut self, doc: DocId) -> DocId {
+        for doc in doc..self.max_doc {
+            if self.matches(doc) {
+                return doc;
+            }
+        }
+        TERMINATED
+    }
+}
+
+impl DocSet for FastFieldRangeDocSet {
+    fn advance(&mut self) -> DocId {
+        self.doc = self.next_match(self.doc.saturating_add(1));
+        self.doc
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        if self.doc < target {
+            self.doc = self.next_match(target);
+        }
+        self.doc
+    }
+
+    fn doc(&self) -> DocId {
+        self.doc
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.max_doc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::collector::Count;
+    use tantivy::schema::{Schema, FAST};
+    use tantivy::{doc, Index};
+
+    use super::*;
+
+    #[test]
+    fn test_fast_field_range_query() -> anyhow::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let latency_field = schema_builder.add_f64_field("latency", FAST);
+        let offset_field = schema_builder.add_i64_field("offset", FAST);
+        let codes_field = schema_builder.add_u64_field(
+            "codes",
+            tantivy::schema::IntOptions::default().set_fast(Cardinality::MultiValues),
+        );
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        index_writer.add_document(doc!(
+            latency_field => 0.5f64,
+            offset_field => -10i64,
+            codes_field => 200u64,
+        ));
+        index_writer.add_document(doc!(
+            latency_field => 1.5f64,
+            offset_field => 0i64,
+            codes_field => 200u64,
+            codes_field => 503u64,
+        ));
+        index_writer.add_document(doc!(
+            latency_field => 3.0f64,
+            offset_field => 10i64,
+            codes_field => 404u64,
+        ));
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let latency_query = FastFieldRangeQuery::new_f64_bounds(
+            latency_field,
+            Bound::Included(1.0),
+            Bound::Excluded(3.0),
+        );
+        assert_eq!(searcher.search(&latency_query, &Count)?, 1);
+
+        let offset_query =
+            FastFieldRangeQuery::new_i64_bounds(offset_field, Bound::Unbounded, Bound::Included(0));
+        assert_eq!(searcher.search(&offset_query, &Count)?, 2);
+
+        let codes_query = FastFieldRangeQuery::new_u64_bounds(
+            codes_field,
+            Bound::Included(500),
+            Bound::Unbounded,
+        );
+        assert_eq!(searcher.search(&codes_query, &Count)?, 1);
+
+        let empty_query = FastFieldRangeQuery::new_u64_bounds(
+            codes_field,
+            Bound::Excluded(503),
+            Bound::Excluded(504),
+        );
+        assert_eq!(searcher.search(&empty_query, &Count)?, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use tantivy::query::{BooleanQuery, Occur, Query, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption};
+use tantivy::{Searcher, Term};
+use tantivy_query_grammar::{UserInputAst, UserInputLeaf};
+
+use crate::wildcard_query::is_pattern;
+use crate::FuzzyQueryConfig;
+
+/// Edit distance of the fuzzy terms that do not specify one, as in `valeu~`.
+const DEFAULT_EDIT_DISTANCE: u8 = 2;
+
+/// Term matching the terms within an edit distance of it, written `valeu~1`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct FuzzyTerm<'a> {
+    pub text: &'a str,
+    pub edit_distance: u8,
+}
+
+/// Returns the fuzzy term of `phrase` if it is a fuzzy term (`valeu~` or `valeu~1`).
+pub(crate) fn fuzzy_term(phrase: &str) -> Option<FuzzyTerm> {
+    let (text, edit_distance_str) = phrase.rsplit_once('~')?;
+    if text.is_empty() || text.contains(char::is_whitespace) || is_pattern(text) {
+        return None;
+    }
+    let edit_distance = if edit_distance_str.is_empty() {
+        DEFAULT_EDIT_DISTANCE
+    } else {
+        edit_distance_str.parse().ok()?
+    };
+    Some(FuzzyTerm {
+        text,
+        edit_distance,
+    })
+}
+
+/// Returns true if `phrase` is a fuzzy term.
+pub(crate) fn is_fuzzy(phrase: &str) -> bool {
+    fuzzy_term(phrase).is_some()
+}
+
+/// Returns true if the query contains a fuzzy term.
+pub(crate) fn has_fuzzy_clause(user_input_ast: &UserInputAst) -> bool {
+    match user_input_ast {
+        UserInputAst::Clause(sub_queries) => sub_queries
+            .iter()
+            .any(|(_, sub_ast)| has_fuzzy_clause(sub_ast)),
+        UserInputAst::Boost(ast, _) => has_fuzzy_clause(ast),
+        UserInputAst::Leaf(leaf) => match leaf.as_ref() {
+            UserInputLeaf::Literal(literal) => is_fuzzy(&literal.phrase),
+            _ => false,
+        },
+    }
+}
+
+/// Builds the query matching the terms of the text `fields` within the edit distance of
+/// `fuzzy_term`, in the split `searcher` reads.
+///
+/// The terms sharing the first `prefix_length` characters of the fuzzy term are scanned,
+/// and the `max_expanded_terms` closest ones are kept.
+pub(crate) fn build_fuzzy_query(
+    searcher: &Searcher,
+    fields: &[Field],
+    fuzzy_term: &FuzzyTerm,
+    config: &FuzzyQueryConfig,
+) -> anyhow::Result<Box<dyn Query>> {
+    if fuzzy_term.edit_distance > config.max_edit_distance {
+        bail!(
+            "The edit distance of the fuzzy term `{}~{}` exceeds the maximum of {}.",
+            fuzzy_term.text,
+            fuzzy_term.edit_distance,
+            config.max_edit_distance
+        );
+    }
+    let fuzzy_chars: Vec<char> = fuzzy_term.text.chars().collect();
+    let prefix: String = fuzzy_chars.iter().take(config.prefix_length).collect();
+    let mut term_distances: BTreeMap<Term, u8> = BTreeMap::new();
+    for &field in fields {
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader.inverted_index(field)?;
+            let mut term_stream = inverted_index
+                .terms()
+                .range()
+                .ge(prefix.as_bytes())
+                .into_stream()?;
+            while let Some((term_bytes, _)) = term_stream.next() {
+                if !term_bytes.starts_with(prefix.as_bytes()) {
+                    break;
+                }
+                let term_text = match std::str::from_utf8(term_bytes) {
+                    Ok(term_text) => term_text,
+                    Err(_) => continue,
+                };
+                let term_chars: Vec<char> = term_text.chars().collect();
+                if let Some(distance) = edit_distance(
+                    &fuzzy_chars,
+                    &term_chars,
+                    fuzzy_term.edit_distance,
+                    config.transpositions,
+                ) {
+                    term_distances.insert(Term::from_field_text(field, term_text), distance);
+                }
+            }
+        }
+    }
+    let mut terms: Vec<(u8, Term)> = term_distances
+        .into_iter()
+        .map(|(term, distance)| (distance, term))
+        .collect();
+    terms.sort();
+    terms.truncate(config.max_expanded_terms);
+    let term_queries: Vec<(Occur, Box<dyn Query>)> = terms
+        .into_iter()
+        .map(|(_, term)| {
+            let term_query: Box<dyn Query> =
+                Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
+            (Occur::Should, term_query)
+        })
+        .collect();
+    Ok(Box::new(BooleanQuery::new(term_queries)))
+}
+
+/// Returns the edit distance between `left` and `right` if it is at most `max_distance`.
+///
+/// The edits are insertions, deletions and substitutions of a character, as well as
+/// transpositions of two adjacent characters if `transpositions` is set.
+fn edit_distance(
+    left: &[char],
+    right: &[char],
+    max_distance: u8,
+    transpositions: bool,
+) -> Option<u8> {
+    let max_distance = max_distance as usize;
+    if left.len().max(right.len()) - left.len().min(right.len()) > max_distance {
+        return None;
+    }
+    // Rows of the distances between the prefixes of `left` and of `right`.
+    let mut previous_previous_row: Vec<usize> = Vec::new();
+    let mut previous_row: Vec<usize> = (0..=right.len()).collect();
+    for i in 1..=left.len() {
+        let mut row = vec![i; right.len() + 1];
+        for j in 1..=right.len() {
+            let substitution_cost = if left[i - 1] == right[j - 1] { 0 } else { 1 };
+            row[j] = (previous_row[j] + 1)
+                .min(row[j - 1] + 1)
+                .min(previous_row[j - 1] + substitution_cost);
+            if transpositions
+                && i > 1
+                && j > 1
+                && left[i - 1] == right[j - 2]
+                && left[i - 2] == right[j - 1]
+            {
+                row[j] = row[j].min(previous_previous_row[j - 2] + 1);
+            }
+        }
+        if row.iter().all(|&distance| distance > max_distance) {
+            return None;
+        }
+        previous_previous_row = std::mem::replace(&mut previous_row, row);
+    }
+    let distance = previous_row[right.len()];
+    if distance > max_distance {
+        return None;
+    }
+    Some(distance as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_term() {
+        assert_eq!(
+            fuzzy_term("valeu~1"),
+            Some(FuzzyTerm {
+                text: "valeu",
+                edit_distance: 1
+            })
+        );
+        assert_eq!(
+            fuzzy_term("valeu~"),
+            Some(FuzzyTerm {
+                text: "valeu",
+                edit_distance: 2
+            })
+        );
+        assert!(fuzzy_term("value").is_none());
+        assert!(fuzzy_term("~1").is_none());
+        assert!(fuzzy_term("valeu~one").is_none());
+        assert!(fuzzy_term("val*u~1").is_none());
+        assert!(fuzzy_term("invalid value~1").is_none());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        let distance = |left: &str, right: &str, max_distance: u8, transpositions: bool| {
+            let left_chars: Vec<char> = left.chars().collect();
+            let right_chars: Vec<char> = right.chars().collect();
+            edit_distance(&left_chars, &right_chars, max_distance, transpositions)
+        };
+        assert_eq!(distance("value", "value", 2, true), Some(0));
+        assert_eq!(distance("valeu", "value", 2, true), Some(1));
+        assert_eq!(distance("valeu", "value", 2, false), Some(2));
+        assert_eq!(distance("valeu", "value", 1, false), None);
+        assert_eq!(distance("value", "values", 1, true), Some(1));
+        assert_eq!(distance("value", "valid", 2, true), Some(2));
+        assert_eq!(distance("value", "v", 2, true), None);
+        assert_eq!(distance("", "ab", 2, true), Some(2));
+        assert_eq!(distance("héllo", "hello", 1, true), Some(1));
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+
+/// Mean radius of the Earth, used to compute the distance between two points.
+const EARTH_RADIUS_IN_METERS: f64 = 6_371_008.8;
+
+/// Number of distinct values of the quantized latitudes and longitudes. The value `0`
+/// is left out, so that documents without a point do not decode into one.
+const NUM_QUANTIZATION_STEPS: f64 = (u32::MAX - 1) as f64;
+
+/// A point on Earth, given by its latitude and longitude in degrees.
+///
+/// A point is indexed as the `u64` value of a fast field, the 32 upper bits holding its
+/// quantized latitude and the 32 lower bits its quantized longitude, with a precision
+/// better than a centimeter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GeoPoint {
+    /// Latitude, between -90 and 90.
+    pub lat: f64,
+    /// Longitude, between -180 and 180.
+    pub lon: f64,
+}
+
+fn quantize(value: f64, min_value: f64, max_value: f64) -> u64 {
+    ((value - min_value) / (max_value - min_value) * NUM_QUANTIZATION_STEPS).round() as u64 + 1
+}
+
+fn dequantize(quantized_value: u64, min_value: f64, max_value: f64) -> f64 {
+    (quantized_value - 1) as f64 / NUM_QUANTIZATION_STEPS * (max_value - min_value) + min_value
+}
+
+impl GeoPoint {
+    /// Returns the point of latitude `lat` and longitude `lon`, or an error if they are out
+    /// of bounds.
+    pub fn new(lat: f64, lon: f64) -> anyhow::Result<GeoPoint> {
+        if !(-90.0..=90.0).contains(&lat) {
+            bail!("Latitude `{}` is not between -90 and 90.", lat);
+        }
+        if !(-180.0..=180.0).contains(&lon) {
+            bail!("Longitude `{}` is not between -180 and 180.", lon);
+        }
+        Ok(GeoPoint { lat, lon })
+    }
+
+    /// Encodes the point into the value of its fast field.
+    pub fn to_u64(&self) -> u64 {
+        (quantize(self.lat, -90.0, 90.0) << 32) | quantize(self.lon, -180.0, 180.0)
+    }
+
+    /// Decodes the value of a geo point fast field, `0` standing for a document without
+    /// a point.
+    pub fn from_u64(value: u64) -> Option<GeoPoint> {
+        let quantized_lat = value >> 32;
+        let quantized_lon = value & u64::from(u32::MAX);
+        if quantized_lat == 0 || quantized_lon == 0 {
+            return None;
+        }
+        Some(GeoPoint {
+            lat: dequantize(quantized_lat, -90.0, 90.0),
+            lon: dequantize(quantized_lon, -180.0, 180.0),
+        })
+    }
+
+    /// Returns the great-circle distance to `other`, in meters.
+    pub fn distance_in_meters(&self, other: &GeoPoint) -> f64 {
+        let delta_lat = (other.lat - self.lat).to_radians();
+        let delta_lon = (other.lon - self.lon).to_radians();
+        let haversine = (delta_lat / 2.0).sin().powi(2)
+            + self.lat.to_radians().cos()
+                * other.lat.to_radians().cos()
+                * (delta_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_IN_METERS * haversine.sqrt().min(1.0).asin()
+    }
+}
+
+impl fmt::Display for GeoPoint {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{},{}", self.lat, self.lon)
+    }
+}
+
+impl FromStr for GeoPoint {
+    type Err = anyhow::Error;
+
+    /// Parses a point written as `lat,lon`.
+    fn from_str(point_str: &str) -> anyhow::Result<GeoPoint> {
+        let (lat_str, lon_str) = point_str
+            .split_once(',')
+            .with_context(|| format!("Expected a point as `lat,lon`, got `{}`.", point_str))?;
+        let lat = lat_str
+            .trim()
+            .parse::<f64>()
+            .with_context(|| format!("Invalid latitude `{}`.", lat_str))?;
+        let lon = lon_str
+            .trim()
+            .parse::<f64>()
+            .with_context(|| format!("Invalid longitude `{}`.", lon_str))?;
+        GeoPoint::new(lat, lon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geo_point_encoding() -> anyhow::Result<()> {
+        for (lat, lon) in &[
+            (0.0, 0.0),
+            (48.8566, 2.3522),
+            (-90.0, -180.0),
+            (90.0, 180.0),
+        ] {
+            let point = GeoPoint::new(*lat, *lon)?;
+            let decoded_point = GeoPoint::from_u64(point.to_u64()).unwrap();
+            assert!((decoded_point.lat - lat).abs() < 1e-7);
+            assert!((decoded_point.lon - lon).abs() < 1e-7);
+        }
+        assert!(GeoPoint::from_u64(0).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_geo_point_from_str() {
+        assert_eq!(
+            "48.8566, 2.3522".parse::<GeoPoint>().unwrap(),
+            GeoPoint {
+                lat: 48.8566,
+                lon: 2.3522
+            }
+        );
+        assert!("48.8566".parse::<GeoPoint>().is_err());
+        assert!("91,0".parse::<GeoPoint>().is_err());
+        assert!("0,west".parse::<GeoPoint>().is_err());
+    }
+
+    #[test]
+    fn test_geo_point_distance() -> anyhow::Result<()> {
+        let paris = GeoPoint::new(48.8566, 2.3522)?;
+        let london = GeoPoint::new(51.5074, -0.1278)?;
+        let distance = paris.distance_in_meters(&london);
+        assert!((distance - 343_500.0).abs() < 1_000.0);
+        assert_eq!(paris.distance_in_meters(&paris), 0.0);
+        Ok(())
+    }
+}
@@ -24,15 +24,33 @@
 //! to convert a json like documents to a document indexable by tantivy
 //! engine, aka tantivy::Document.
 
+mod coercion;
 mod config;
 mod default_index_config;
 mod error;
+mod extraction;
+mod fast_field_range_query;
+mod fuzzy_query;
+mod geo_point;
 mod query_builder;
+mod range_query;
+mod split_path;
+mod synonyms;
+mod time_zone;
 mod wikipedia_config;
+mod wildcard_query;
 
-pub use config::{IndexConfig, SortBy, SortOrder};
+pub use config::{
+    EnrichmentConfig, ExtractionConfig, FuzzyQueryConfig, HotcacheConfig, IndexConfig, SortBy,
+    SortOrder, SplitLimitConfig, SplitLimitPolicy, SynonymsConfig, WildcardQueryConfig,
+};
 pub use default_index_config::{DefaultIndexConfig, DefaultIndexConfigBuilder, DocParsingError};
 pub use error::QueryParserError;
+pub use fast_field_range_query::FastFieldRangeQuery;
+pub use geo_point::GeoPoint;
+pub use split_path::SplitPathLayout;
+pub use synonyms::parse_synonym_sets;
+pub use time_zone::{CalendarUnit, TimeZone};
 pub use wikipedia_config::WikipediaIndexConfig;
 
 /// Field name reserved for storing the source document.
@@ -41,6 +59,16 @@ pub static SOURCE_FIELD_NAME: &str = "_source";
 /// Field name reserved for storing the tags.
 pub static TAGS_FIELD_NAME: &str = "_tags";
 
+/// Field name prefix reserved for the dictionary encoded values of the text fast fields.
+pub static STRING_FAST_FIELD_PREFIX: &str = "_dict.";
+
+/// Returns the name of the hidden facet field holding the dictionary encoded values of the
+/// text fast field `field_name`: its fast field maps each document to the ordinal of its value
+/// in the facet dictionary.
+pub fn string_fast_field_name(field_name: &str) -> String {
+    format!("{}{}", STRING_FAST_FIELD_PREFIX, field_name)
+}
+
 /// Returns a default `DefaultIndexConfig` for unit tests.
 #[cfg(any(test, feature = "testsuite"))]
 pub fn default_config_for_tests() -> DefaultIndexConfig {
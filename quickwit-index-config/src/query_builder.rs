@@ -17,39 +17,215 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use quickwit_proto::SearchRequest;
-use tantivy::query::{Query, QueryParser, QueryParserError as TantivyQueryParserError};
-use tantivy::schema::{Field, Schema};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use quickwit_proto::{QueryWarning, SearchRequest};
+use tantivy::query::{
+    BooleanQuery, Occur, Query, QueryParser, QueryParserError as TantivyQueryParserError,
+};
+use tantivy::schema::{Field, FieldType, Schema};
 use tantivy::tokenizer::TokenizerManager;
+use tantivy::Searcher;
 use tantivy_query_grammar::{UserInputAst, UserInputLeaf};
 
-use crate::QueryParserError;
+use crate::default_index_config::BytesFormat;
+use crate::fuzzy_query::has_fuzzy_clause;
+use crate::synonyms::{expand_synonyms, SynonymMap};
+use crate::wildcard_query::{has_wildcard_clause, UserInputQueryBuilder};
+use crate::{coercion, FuzzyQueryConfig, QueryParserError, TimeZone, WildcardQueryConfig};
 
-/// Build a `Query` with field resolution & forbidding range clauses.
+/// Build a `Query` with field resolution.
+///
+/// The field aliases used in the query and the search fields are replaced by
+/// the field names they stand for.
+///
+/// Wildcard and regex terms are expanded into the terms of the split `searcher_opt`
+/// reads, within the limits of `wildcard_query_config`, and fuzzy terms into the terms
+/// within their edit distance, within the limits of `fuzzy_query_config`. They are
+/// rejected if no searcher is given.
+///
+/// The dates bounding the range clauses are interpreted in the time zone of the request.
+///
+/// The values that do not match the type of their field are coerced into it when this is
+/// unambiguous, and match nothing otherwise. See [`query_warnings`].
+///
+/// The values of the bytes fields are decoded with the format of their field in
+/// `bytes_formats`, Base64 by default, and matched exactly.
+///
+/// The terms on text fields are expanded into their synonyms in `synonym_map_opt`.
+///
+/// The mandatory filter of the request, if any, is parsed on its own and required by a
+/// boolean query along with the query of the request. Concatenating the two strings instead
+/// would let a query like `x) OR (y` escape the filter.
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn build_query(
     schema: Schema,
     request: &SearchRequest,
     default_field_names: &[String],
+    field_aliases: &BTreeMap<String, String>,
+    bytes_formats: &BTreeMap<String, BytesFormat>,
+    wildcard_query_config: &WildcardQueryConfig,
+    fuzzy_query_config: &FuzzyQueryConfig,
+    synonym_map_opt: Option<&SynonymMap>,
+    searcher_opt: Option<&Searcher>,
 ) -> Result<Box<dyn Query>, QueryParserError> {
-    let user_input_ast = tantivy_query_grammar::parse_query(&request.query)
-        .map_err(|_| TantivyQueryParserError::SyntaxError)?;
+    let build = |query: &str| {
+        build_user_query(
+            schema.clone(),
+            query,
+            request,
+            default_field_names,
+            field_aliases,
+            bytes_formats,
+            wildcard_query_config,
+            fuzzy_query_config,
+            synonym_map_opt,
+            searcher_opt,
+        )
+    };
+    let query = build(&request.query)?;
+    let mandatory_filter = match request.mandatory_filter.as_deref() {
+        Some(mandatory_filter) => mandatory_filter,
+        None => return Ok(query),
+    };
+    let filter_query = build(mandatory_filter)?;
+    Ok(Box::new(BooleanQuery::new(vec![
+        (Occur::Must, query),
+        (Occur::Must, filter_query),
+    ])))
+}
 
-    if has_range_clause(user_input_ast) {
-        return Err(anyhow::anyhow!("Range queries are not currently allowed.").into());
-    }
+/// Builds the `Query` of `query`, with the search fields and the time zone of `request`.
+#[allow(clippy::too_many_arguments)]
+fn build_user_query(
+    schema: Schema,
+    query: &str,
+    request: &SearchRequest,
+    default_field_names: &[String],
+    field_aliases: &BTreeMap<String, String>,
+    bytes_formats: &BTreeMap<String, BytesFormat>,
+    wildcard_query_config: &WildcardQueryConfig,
+    fuzzy_query_config: &FuzzyQueryConfig,
+    synonym_map_opt: Option<&SynonymMap>,
+    searcher_opt: Option<&Searcher>,
+) -> Result<Box<dyn Query>, QueryParserError> {
+    let query = resolve_field_aliases(query, field_aliases);
+    let mut user_input_ast = tantivy_query_grammar::parse_query(&query)
+        .map_err(|_| TantivyQueryParserError::SyntaxError)?;
+    let has_synonyms = synonym_map_opt
+        .map(|synonym_map| expand_synonyms(&mut user_input_ast, synonym_map, &schema))
+        .unwrap_or(false);
 
     let search_fields = if request.search_fields.is_empty() {
         resolve_fields(&schema, default_field_names)?
     } else {
-        resolve_fields(&schema, &request.search_fields)?
+        let search_field_names: Vec<String> = request
+            .search_fields
+            .iter()
+            .map(|field_name| {
+                field_aliases
+                    .get(field_name)
+                    .unwrap_or(field_name)
+                    .to_string()
+            })
+            .collect();
+        resolve_fields(&schema, &search_field_names)?
     };
 
-    let query_parser = QueryParser::new(schema, search_fields, TokenizerManager::default());
-    let query = query_parser.parse_query(&request.query)?;
-    Ok(query)
+    let query_parser = QueryParser::new(
+        schema.clone(),
+        search_fields.clone(),
+        TokenizerManager::default(),
+    );
+    let time_zone = TimeZone::from_str(&request.time_zone)?;
+    if !has_synonyms
+        && !has_wildcard_clause(&user_input_ast)
+        && !has_fuzzy_clause(&user_input_ast)
+        && !has_range_clause(&user_input_ast)
+        && !has_bytes_clause(&schema, &user_input_ast)
+        && coercion::query_warnings(&schema, &user_input_ast, bytes_formats, &time_zone).is_empty()
+    {
+        return Ok(query_parser.parse_query(&query)?);
+    }
+    let user_input_query_builder = UserInputQueryBuilder {
+        schema: &schema,
+        search_fields: &search_fields,
+        query_parser: &query_parser,
+        config: wildcard_query_config,
+        fuzzy_config: fuzzy_query_config,
+        searcher_opt,
+        time_zone: &time_zone,
+        bytes_formats,
+    };
+    Ok(user_input_query_builder.build(user_input_ast)?)
+}
+
+/// Returns a warning for each clause of the query whose value does not match the type of
+/// its field, so that queries matching nothing because of a type mismatch are not silent.
+pub(crate) fn query_warnings(
+    schema: &Schema,
+    request: &SearchRequest,
+    field_aliases: &BTreeMap<String, String>,
+    bytes_formats: &BTreeMap<String, BytesFormat>,
+) -> Result<Vec<QueryWarning>, QueryParserError> {
+    let query = resolve_field_aliases(&request.query, field_aliases);
+    let user_input_ast = tantivy_query_grammar::parse_query(&query)
+        .map_err(|_| TantivyQueryParserError::SyntaxError)?;
+    let time_zone = TimeZone::from_str(&request.time_zone)?;
+    Ok(coercion::query_warnings(
+        schema,
+        &user_input_ast,
+        bytes_formats,
+        &time_zone,
+    ))
+}
+
+fn is_field_name_delimiter(c: char) -> bool {
+    c.is_whitespace() || matches!(c, ':' | '(' | ')' | '"' | '^')
 }
 
-fn has_range_clause(user_input_ast: UserInputAst) -> bool {
+/// Replaces the field aliases placed in front of a `:` in the query by the field
+/// names they stand for, leaving phrases untouched.
+fn resolve_field_aliases<'a>(
+    query: &'a str,
+    field_aliases: &BTreeMap<String, String>,
+) -> Cow<'a, str> {
+    if field_aliases.is_empty() {
+        return Cow::Borrowed(query);
+    }
+    let mut resolved_query = String::with_capacity(query.len());
+    let mut remaining = query;
+    let mut in_phrase = false;
+    let mut at_word_start = true;
+    while let Some(c) = remaining.chars().next() {
+        if at_word_start && !in_phrase && !is_field_name_delimiter(c) && !matches!(c, '+' | '-') {
+            let word_len = remaining
+                .find(is_field_name_delimiter)
+                .unwrap_or_else(|| remaining.len());
+            let (word, after_word) = remaining.split_at(word_len);
+            match field_aliases.get(word) {
+                Some(field_name) if after_word.starts_with(':') => {
+                    resolved_query.push_str(field_name)
+                }
+                _ => resolved_query.push_str(word),
+            }
+            remaining = after_word;
+            at_word_start = false;
+            continue;
+        }
+        if c == '"' {
+            in_phrase = !in_phrase;
+        }
+        at_word_start = c.is_whitespace() || matches!(c, '(' | '+' | '-');
+        resolved_query.push(c);
+        remaining = &remaining[c.len_utf8()..];
+    }
+    Cow::Owned(resolved_query)
+}
+
+fn has_range_clause(user_input_ast: &UserInputAst) -> bool {
     match user_input_ast {
         UserInputAst::Clause(sub_queries) => {
             for (_, sub_ast) in sub_queries {
@@ -59,8 +235,31 @@ fn has_range_clause(user_input_ast: UserInputAst) -> bool {
             }
             false
         }
-        UserInputAst::Boost(ast, _) => has_range_clause(*ast),
-        UserInputAst::Leaf(leaf) => matches!(*leaf, UserInputLeaf::Range { .. }),
+        UserInputAst::Boost(ast, _) => has_range_clause(ast),
+        UserInputAst::Leaf(leaf) => matches!(leaf.as_ref(), UserInputLeaf::Range { .. }),
+    }
+}
+
+fn has_bytes_clause(schema: &Schema, user_input_ast: &UserInputAst) -> bool {
+    match user_input_ast {
+        UserInputAst::Clause(sub_queries) => sub_queries
+            .iter()
+            .any(|(_, sub_ast)| has_bytes_clause(schema, sub_ast)),
+        UserInputAst::Boost(ast, _) => has_bytes_clause(schema, ast),
+        UserInputAst::Leaf(leaf) => match leaf.as_ref() {
+            UserInputLeaf::Literal(literal) => literal
+                .field_name
+                .as_ref()
+                .and_then(|field_name| schema.get_field(field_name))
+                .map(|field| {
+                    matches!(
+                        schema.get_field_entry(field).field_type(),
+                        FieldType::Bytes(_)
+                    )
+                })
+                .unwrap_or(false),
+            _ => false,
+        },
     }
 }
 
@@ -77,10 +276,17 @@ fn resolve_fields(schema: &Schema, field_names: &[String]) -> anyhow::Result<Vec
 
 #[cfg(test)]
 mod test {
+    use std::collections::BTreeMap;
+
     use quickwit_proto::SearchRequest;
-    use tantivy::schema::{Schema, TEXT};
+    use tantivy::collector::Count;
+    use tantivy::schema::{Schema, FAST, INDEXED, STORED, TEXT};
+    use tantivy::{doc, Index};
 
-    use super::build_query;
+    use super::{build_query, query_warnings, resolve_field_aliases};
+    use crate::default_index_config::BytesFormat;
+    use crate::synonyms::SynonymMap;
+    use crate::{FuzzyQueryConfig, WildcardQueryConfig};
 
     enum TestExpectation {
         Err(&'static str),
@@ -97,6 +303,13 @@ mod test {
         schema_builder.build()
     }
 
+    fn make_field_aliases() -> BTreeMap<String, String> {
+        let mut field_aliases = BTreeMap::new();
+        field_aliases.insert("message".to_string(), "desc".to_string());
+        field_aliases.insert("host".to_string(), "server.name".to_string());
+        field_aliases
+    }
+
     fn check_build_query(
         query_str: &str,
         search_fields: Vec<String>,
@@ -111,11 +324,39 @@ mod test {
             max_hits: 20,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
 
         let default_field_names = vec!["title".to_string(), "desc".to_string()];
+        let field_aliases = make_field_aliases();
 
-        let query_result = build_query(make_schema(), &request, &default_field_names);
+        let query_result = build_query(
+            make_schema(),
+            &request,
+            &default_field_names,
+            &field_aliases,
+            &BTreeMap::new(),
+            &WildcardQueryConfig::default(),
+            &FuzzyQueryConfig::default(),
+            None,
+            None,
+        );
         match expected {
             TestExpectation::Err(sub_str) => {
                 assert_eq!(format!("{:?}", query_result).contains(sub_str), true);
@@ -136,20 +377,17 @@ mod test {
             vec![],
             TestExpectation::Err("Field does not exists: '\"foo\"'"),
         )?;
-        check_build_query(
-            "title:[a TO b]",
-            vec![],
-            TestExpectation::Err("Range queries are not currently allowed."),
-        )?;
+        check_build_query("title:[a TO b]", vec![], TestExpectation::Ok("RangeQuery"))?;
         check_build_query(
             "title:{a TO b} desc:foo",
             vec![],
-            TestExpectation::Err("Range queries are not currently allowed."),
+            TestExpectation::Ok("RangeQuery"),
         )?;
+        check_build_query("title:>foo", vec![], TestExpectation::Ok("RangeQuery"))?;
         check_build_query(
-            "title:>foo",
+            "server.type:[a TO b]",
             vec![],
-            TestExpectation::Err("Range queries are not currently allowed."),
+            TestExpectation::Err("Field does not exists: '\"server.type\"'"),
         )?;
         check_build_query(
             "title:foo desc:bar _source:baz",
@@ -181,6 +419,457 @@ mod test {
             TestExpectation::Ok("TermQuery"),
         )?;
 
+        check_build_query(
+            "message:foo -host:bar",
+            vec![],
+            TestExpectation::Ok("TermQuery"),
+        )?;
+
+        check_build_query(
+            "foo",
+            vec!["message".to_string()],
+            TestExpectation::Ok("TermQuery"),
+        )?;
+
+        check_build_query(
+            "title:foo*",
+            vec![],
+            TestExpectation::Err("Wildcard and regex queries can only be built for a given split."),
+        )?;
+
+        check_build_query(
+            "title:foo~1",
+            vec![],
+            TestExpectation::Err("Fuzzy queries can only be built for a given split."),
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_wildcard_query() -> anyhow::Result<()> {
+        let schema = make_schema();
+        let title_field = schema.get_field("title").unwrap();
+        let desc_field = schema.get_field("desc").unwrap();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        index_writer.add_document(doc!(title_field => "error", desc_field => "first"));
+        index_writer.add_document(doc!(title_field => "errors", desc_field => "second"));
+        index_writer.add_document(doc!(title_field => "warning", desc_field => "third"));
+        index_writer.add_document(doc!(title_field => "eror", desc_field => "fourth"));
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let count_hits = |query_str: &str,
+                          wildcard_query_config: &WildcardQueryConfig|
+         -> anyhow::Result<usize> {
+            let request = SearchRequest {
+                index_id: "test_index".to_string(),
+                query: query_str.to_string(),
+                max_hits: 20,
+                ..Default::default()
+            };
+            let query = build_query(
+                schema.clone(),
+                &request,
+                &["title".to_string(), "desc".to_string()],
+                &BTreeMap::new(),
+                &BTreeMap::new(),
+                wildcard_query_config,
+                &FuzzyQueryConfig::default(),
+                None,
+                Some(&*searcher),
+            )?;
+            Ok(searcher.search(&query, &Count)?)
+        };
+        let default_config = WildcardQueryConfig::default();
+        assert_eq!(count_hits("title:err*", &default_config)?, 2);
+        assert_eq!(count_hits("title:er?or", &default_config)?, 1);
+        assert_eq!(count_hits("title:/er+ors?/", &default_config)?, 3);
+        assert_eq!(count_hits("err* desc:third", &default_config)?, 3);
+        assert_eq!(count_hits("+title:err* -desc:first", &default_config)?, 1);
+        assert_eq!(count_hits("title:unknown*", &default_config)?, 0);
+        assert!(count_hits("title:*ror", &default_config).is_err());
+        let leading_wildcard_config = WildcardQueryConfig {
+            allow_leading_wildcard: true,
+            ..Default::default()
+        };
+        assert_eq!(count_hits("title:*ror", &leading_wildcard_config)?, 2);
+        let small_expansion_config = WildcardQueryConfig {
+            max_expanded_terms: 1,
+            ..Default::default()
+        };
+        assert!(count_hits("title:err*", &small_expansion_config).is_err());
+        let small_automaton_config = WildcardQueryConfig {
+            max_automaton_size_in_bytes: 10,
+            ..Default::default()
+        };
+        assert!(count_hits("title:err*", &small_automaton_config).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_query_with_mandatory_filter() -> anyhow::Result<()> {
+        let schema = make_schema();
+        let title_field = schema.get_field("title").unwrap();
+        let desc_field = schema.get_field("desc").unwrap();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        index_writer.add_document(doc!(title_field => "acme", desc_field => "first"));
+        index_writer.add_document(doc!(title_field => "acme", desc_field => "second"));
+        index_writer.add_document(doc!(title_field => "other", desc_field => "first"));
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let count_hits = |query_str: &str| -> anyhow::Result<usize> {
+            let request = SearchRequest {
+                index_id: "test_index".to_string(),
+                query: query_str.to_string(),
+                mandatory_filter: Some("title:acme".to_string()),
+                max_hits: 20,
+                ..Default::default()
+            };
+            let query = build_query(
+                schema.clone(),
+                &request,
+                &["title".to_string(), "desc".to_string()],
+                &BTreeMap::new(),
+                &BTreeMap::new(),
+                &WildcardQueryConfig::default(),
+                &FuzzyQueryConfig::default(),
+                None,
+                Some(&*searcher),
+            )?;
+            Ok(searcher.search(&query, &Count)?)
+        };
+        assert_eq!(count_hits("*")?, 2);
+        assert_eq!(count_hits("desc:first")?, 1);
+        assert_eq!(count_hits("desc:first OR desc:second")?, 2);
+        // The query cannot escape the filter: `other` matches `desc:first` but not the filter,
+        // and closing the parentheses of the filter is a syntax error of the query.
+        assert_eq!(count_hits("desc:first OR title:other")?, 1);
+        assert_eq!(count_hits("(desc:first) OR (title:other)")?, 1);
+        assert!(count_hits("desc:second) OR (desc:first").is_err());
+        assert!(count_hits("desc:second) OR (title:other").is_err());
+        assert_eq!(count_hits("title:other")?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_fuzzy_query() -> anyhow::Result<()> {
+        let schema = make_schema();
+        let title_field = schema.get_field("title").unwrap();
+        let desc_field = schema.get_field("desc").unwrap();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        index_writer.add_document(doc!(title_field => "value", desc_field => "first"));
+        index_writer.add_document(doc!(title_field => "values", desc_field => "second"));
+        index_writer.add_document(doc!(title_field => "valid", desc_field => "third"));
+        index_writer.add_document(doc!(title_field => "evaluate", desc_field => "fourth"));
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let count_hits =
+            |query_str: &str, fuzzy_query_config: &FuzzyQueryConfig| -> anyhow::Result<usize> {
+                let request = SearchRequest {
+                    index_id: "test_index".to_string(),
+                    query: query_str.to_string(),
+                    max_hits: 20,
+                    ..Default::default()
+                };
+                let query = build_query(
+                    schema.clone(),
+                    &request,
+                    &["title".to_string(), "desc".to_string()],
+                    &BTreeMap::new(),
+                    &BTreeMap::new(),
+                    &WildcardQueryConfig::default(),
+                    fuzzy_query_config,
+                    None,
+                    Some(&*searcher),
+                )?;
+                Ok(searcher.search(&query, &Count)?)
+            };
+        let default_config = FuzzyQueryConfig::default();
+        assert_eq!(count_hits("title:valeu~1", &default_config)?, 1);
+        assert_eq!(count_hits("title:valeu~", &default_config)?, 3);
+        assert_eq!(count_hits("title:valu~1", &default_config)?, 1);
+        assert_eq!(count_hits("title:value~2", &default_config)?, 3);
+        assert_eq!(count_hits("valeu~1 desc:third", &default_config)?, 2);
+        assert_eq!(count_hits("title:xalue~1", &default_config)?, 0);
+        assert!(count_hits("title:valeu~3", &default_config).is_err());
+        let no_transpositions_config = FuzzyQueryConfig {
+            transpositions: false,
+            ..Default::default()
+        };
+        assert_eq!(count_hits("title:valeu~1", &no_transpositions_config)?, 0);
+        let no_prefix_config = FuzzyQueryConfig {
+            prefix_length: 0,
+            ..Default::default()
+        };
+        assert_eq!(count_hits("title:xalue~1", &no_prefix_config)?, 1);
+        let small_expansion_config = FuzzyQueryConfig {
+            max_expanded_terms: 1,
+            ..Default::default()
+        };
+        assert_eq!(count_hits("title:value~1", &small_expansion_config)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_range_query() -> anyhow::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let title_field = schema_builder.add_text_field("title", TEXT);
+        let timestamp_field = schema_builder.add_i64_field("timestamp", INDEXED);
+        let latency_field = schema_builder.add_f64_field("latency", INDEXED);
+        let count_field = schema_builder.add_u64_field("count", FAST);
+        let size_field = schema_builder.add_u64_field("size", STORED);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        // 2021-05-31T22:30:00Z, 2021-06-01T12:00:00Z and 2021-06-02T12:00:00Z.
+        for (timestamp, latency) in &[
+            (1_622_500_200i64, 0.5),
+            (1_622_548_800, 1.5),
+            (1_622_635_200, 2.5),
+        ] {
+            index_writer.add_document(doc!(
+                title_field => "request",
+                timestamp_field => *timestamp,
+                latency_field => *latency,
+                count_field => 1u64,
+                size_field => 1u64,
+            ));
+        }
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let count_hits = |query_str: &str, time_zone: &str| -> anyhow::Result<usize> {
+            let request = SearchRequest {
+                index_id: "test_index".to_string(),
+                query: query_str.to_string(),
+                max_hits: 20,
+                time_zone: time_zone.to_string(),
+                ..Default::default()
+            };
+            let query = build_query(
+                schema.clone(),
+                &request,
+                &["title".to_string()],
+                &BTreeMap::new(),
+                &BTreeMap::new(),
+                &WildcardQueryConfig::default(),
+                &FuzzyQueryConfig::default(),
+                None,
+                None,
+            )?;
+            Ok(searcher.search(&query, &Count)?)
+        };
+        assert_eq!(count_hits("timestamp:[1622548800 TO *]", "")?, 2);
+        assert_eq!(count_hits("timestamp:{1622548800 TO *}", "")?, 1);
+        assert_eq!(count_hits("timestamp:[2021-06-01 TO 2021-06-02}", "")?, 1);
+        // The first document is on June 1st in Paris.
+        assert_eq!(
+            count_hits("timestamp:[2021-06-01 TO 2021-06-02}", "Europe/Paris")?,
+            2
+        );
+        assert_eq!(count_hits("+timestamp:>=2021-06-02 +request", "+02:00")?, 1);
+        assert_eq!(count_hits("latency:[1 TO 2]", "")?, 1);
+        assert!(count_hits("latency:[low TO high]", "").is_err());
+        // `count` is only a fast field.
+        assert_eq!(count_hits("count:[0 TO 2]", "")?, 3);
+        assert_eq!(count_hits("+count:>1 +request", "")?, 0);
+        assert!(count_hits("size:[0 TO 2]", "").is_err());
+        assert!(count_hits("timestamp:[2021-06-01 TO *]", "Mars/Olympus_Mons").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_coerced_query() -> anyhow::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let title_field = schema_builder.add_text_field("title", TEXT);
+        let status_field = schema_builder.add_u64_field("status", INDEXED);
+        let timestamp_field = schema_builder.add_i64_field("timestamp", INDEXED);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        index_writer.add_document(doc!(
+            title_field => "200",
+            status_field => 200u64,
+            timestamp_field => 1_622_505_600i64,
+        ));
+        index_writer.add_document(doc!(
+            title_field => "404",
+            status_field => 404u64,
+            timestamp_field => 0i64,
+        ));
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let request = |query_str: &str| SearchRequest {
+            index_id: "test_index".to_string(),
+            query: query_str.to_string(),
+            max_hits: 20,
+            ..Default::default()
+        };
+        let count_hits = |query_str: &str| -> anyhow::Result<usize> {
+            let query = build_query(
+                schema.clone(),
+                &request(query_str),
+                &["title".to_string()],
+                &BTreeMap::new(),
+                &BTreeMap::new(),
+                &WildcardQueryConfig::default(),
+                &FuzzyQueryConfig::default(),
+                None,
+                None,
+            )?;
+            Ok(searcher.search(&query, &Count)?)
+        };
+        assert_eq!(count_hits("title:200")?, 1);
+        assert_eq!(count_hits("status:\"200\"")?, 1);
+        assert_eq!(count_hits("status:200.0")?, 1);
+        assert_eq!(count_hits("timestamp:2021-06-01")?, 1);
+        assert_eq!(count_hits("status:OK")?, 0);
+        assert_eq!(count_hits("status:OK title:404")?, 1);
+
+        let warnings = query_warnings(
+            &schema,
+            &request("status:200 status:OK"),
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+        )?;
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].clause, "status:OK");
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_bytes_query() -> anyhow::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let title_field = schema_builder.add_text_field("title", TEXT);
+        let trace_id_field = schema_builder.add_bytes_field("trace_id", INDEXED);
+        let hash_field = schema_builder.add_bytes_field("hash", INDEXED);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        index_writer.add_document(doc!(
+            title_field => "first",
+            trace_id_field => vec![10u8, 243],
+            hash_field => b"abc".to_vec(),
+        ));
+        index_writer.add_document(doc!(
+            title_field => "second",
+            trace_id_field => vec![10u8, 244],
+            hash_field => b"abd".to_vec(),
+        ));
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let mut bytes_formats = BTreeMap::new();
+        bytes_formats.insert("trace_id".to_string(), BytesFormat::Hex);
+        let request = |query_str: &str| SearchRequest {
+            index_id: "test_index".to_string(),
+            query: query_str.to_string(),
+            max_hits: 20,
+            ..Default::default()
+        };
+        let count_hits = |query_str: &str| -> anyhow::Result<usize> {
+            let query = build_query(
+                schema.clone(),
+                &request(query_str),
+                &["title".to_string()],
+                &BTreeMap::new(),
+                &bytes_formats,
+                &WildcardQueryConfig::default(),
+                &FuzzyQueryConfig::default(),
+                None,
+                None,
+            )?;
+            Ok(searcher.search(&query, &Count)?)
+        };
+        assert_eq!(count_hits("trace_id:0af3")?, 1);
+        assert_eq!(count_hits("trace_id:0AF4")?, 1);
+        assert_eq!(count_hits("trace_id:0af")?, 0);
+        assert_eq!(count_hits("hash:YWJj")?, 1);
+        assert_eq!(count_hits("hash:YWJk title:first")?, 2);
+        assert_eq!(count_hits("hash:616263")?, 0);
+
+        let warnings = query_warnings(
+            &schema,
+            &request("trace_id:0af3 trace_id:0af"),
+            &BTreeMap::new(),
+            &bytes_formats,
+        )?;
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].clause, "trace_id:0af");
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_query_with_synonyms() -> anyhow::Result<()> {
+        let schema = make_schema();
+        let title_field = schema.get_field("title").unwrap();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        index_writer.add_document(doc!(title_field => "cheap laptop"));
+        index_writer.add_document(doc!(title_field => "refurbished notebook"));
+        index_writer.add_document(doc!(title_field => "desktop"));
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let synonym_map = SynonymMap::build(&[vec!["laptop".to_string(), "notebook".to_string()]])?;
+        let count_hits =
+            |query_str: &str, synonym_map_opt: Option<&SynonymMap>| -> anyhow::Result<usize> {
+                let request = SearchRequest {
+                    index_id: "test_index".to_string(),
+                    query: query_str.to_string(),
+                    max_hits: 20,
+                    ..Default::default()
+                };
+                let query = build_query(
+                    schema.clone(),
+                    &request,
+                    &["title".to_string()],
+                    &BTreeMap::new(),
+                    &BTreeMap::new(),
+                    &WildcardQueryConfig::default(),
+                    &FuzzyQueryConfig::default(),
+                    synonym_map_opt,
+                    None,
+                )?;
+                Ok(searcher.search(&query, &Count)?)
+            };
+        assert_eq!(count_hits("laptop", None)?, 1);
+        assert_eq!(count_hits("laptop", Some(&synonym_map))?, 2);
+        assert_eq!(count_hits("title:Notebook", Some(&synonym_map))?, 2);
+        assert_eq!(count_hits("+laptop +cheap", Some(&synonym_map))?, 1);
+        assert_eq!(count_hits("laptop desktop", Some(&synonym_map))?, 3);
+        assert_eq!(count_hits("\"cheap laptop\"", Some(&synonym_map))?, 1);
         Ok(())
     }
+
+    #[test]
+    fn test_resolve_field_aliases() {
+        let field_aliases = make_field_aliases();
+        assert_eq!(
+            resolve_field_aliases("message:foo", &field_aliases),
+            "desc:foo"
+        );
+        assert_eq!(
+            resolve_field_aliases(
+                "(+message:foo -host:\"message:bar\")^2 message",
+                &field_aliases
+            ),
+            "(+desc:foo -server.name:\"message:bar\")^2 message"
+        );
+        assert_eq!(
+            resolve_field_aliases("title:message:foo", &field_aliases),
+            "title:message:foo"
+        );
+        assert_eq!(
+            resolve_field_aliases("message:foo", &BTreeMap::new()),
+            "message:foo"
+        );
+    }
 }
@@ -0,0 +1,163 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::ops::Bound;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use chrono::{TimeZone as ChronoTimeZone, Utc};
+use tantivy::query::{Query, QueryParserError as TantivyQueryParserError, RangeQuery};
+use tantivy::schema::{Field, FieldType, Schema, Type};
+use tantivy::Term;
+use tantivy_query_grammar::UserInputBound;
+
+use crate::fast_field_range_query::FastFieldRangeQuery;
+use crate::TimeZone;
+
+fn map_bound<'a, T>(
+    bound: &'a UserInputBound,
+    parse: impl Fn(&'a str) -> anyhow::Result<T>,
+) -> anyhow::Result<Bound<T>> {
+    Ok(match bound {
+        UserInputBound::Inclusive(value) => Bound::Included(parse(value)?),
+        UserInputBound::Exclusive(value) => Bound::Excluded(parse(value)?),
+        UserInputBound::Unbounded => Bound::Unbounded,
+    })
+}
+
+fn parse_number<T: FromStr>(field_name: &str, value: &str) -> anyhow::Result<T> {
+    value.parse().map_err(|_| {
+        anyhow::anyhow!(
+            "Invalid bound `{}` for the range query on `{}`.",
+            value,
+            field_name
+        )
+    })
+}
+
+fn date_term(field: Field, time_zone: &TimeZone, date: &str) -> anyhow::Result<Term> {
+    let timestamp = time_zone.parse_timestamp(date)?;
+    let date_time = Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .with_context(|| format!("The date `{}` is out of range.", date))?;
+    Ok(Term::from_field_date(field, &date_time))
+}
+
+/// Builds the query matching the documents whose value of the field `field_name_opt`
+/// is between `lower` and `upper`.
+///
+/// The bounds of the `i64` and `date` fields can be dates, such as `2021-06-01`, which are
+/// interpreted in `time_zone` unless they carry an offset. The numeric fields that are not
+/// indexed but fast are filtered on their fast field.
+pub(crate) fn build_range_query(
+    schema: &Schema,
+    field_name_opt: Option<&str>,
+    lower: &UserInputBound,
+    upper: &UserInputBound,
+    time_zone: &TimeZone,
+) -> anyhow::Result<Box<dyn Query>> {
+    let field_name = field_name_opt.context("Range queries require a field name.")?;
+    let field = schema
+        .get_field(field_name)
+        .ok_or_else(|| TantivyQueryParserError::FieldDoesNotExist(field_name.to_string()))?;
+    let field_entry = schema.get_field_entry(field);
+    if !field_entry.is_indexed() {
+        return build_fast_field_range_query(
+            field,
+            field_name,
+            field_entry.field_type(),
+            lower,
+            upper,
+            time_zone,
+        );
+    }
+    let range_query = match field_entry.field_type() {
+        FieldType::I64(_) => RangeQuery::new_i64_bounds(
+            field,
+            map_bound(lower, |value| time_zone.parse_timestamp(value))?,
+            map_bound(upper, |value| time_zone.parse_timestamp(value))?,
+        ),
+        FieldType::U64(_) => RangeQuery::new_u64_bounds(
+            field,
+            map_bound(lower, |value| parse_number(field_name, value))?,
+            map_bound(upper, |value| parse_number(field_name, value))?,
+        ),
+        FieldType::F64(_) => RangeQuery::new_f64_bounds(
+            field,
+            map_bound(lower, |value| parse_number(field_name, value))?,
+            map_bound(upper, |value| parse_number(field_name, value))?,
+        ),
+        FieldType::Date(_) => RangeQuery::new_term_bounds(
+            field,
+            Type::Date,
+            &map_bound(lower, |value| date_term(field, time_zone, value))?,
+            &map_bound(upper, |value| date_term(field, time_zone, value))?,
+        ),
+        FieldType::Str(_) => {
+            RangeQuery::new_str_bounds(field, map_bound(lower, Ok)?, map_bound(upper, Ok)?)
+        }
+        _ => bail!(
+            "Range queries are not supported on the field `{}`.",
+            field_name
+        ),
+    };
+    Ok(Box::new(range_query))
+}
+
+fn build_fast_field_range_query(
+    field: Field,
+    field_name: &str,
+    field_type: &FieldType,
+    lower: &UserInputBound,
+    upper: &UserInputBound,
+    time_zone: &TimeZone,
+) -> anyhow::Result<Box<dyn Query>> {
+    let is_fast = match field_type {
+        FieldType::I64(options)
+        | FieldType::U64(options)
+        | FieldType::F64(options)
+        | FieldType::Date(options) => options.get_fastfield_cardinality().is_some(),
+        _ => false,
+    };
+    if !is_fast {
+        bail!(
+            "Range queries are only supported on indexed or numeric fast fields, `{}` is neither.",
+            field_name
+        );
+    }
+    let range_query = match field_type {
+        FieldType::I64(_) | FieldType::Date(_) => FastFieldRangeQuery::new_i64_bounds(
+            field,
+            map_bound(lower, |value| time_zone.parse_timestamp(value))?,
+            map_bound(upper, |value| time_zone.parse_timestamp(value))?,
+        ),
+        FieldType::U64(_) => FastFieldRangeQuery::new_u64_bounds(
+            field,
+            map_bound(lower, |value| parse_number(field_name, value))?,
+            map_bound(upper, |value| parse_number(field_name, value))?,
+        ),
+        _ => FastFieldRangeQuery::new_f64_bounds(
+            field,
+            map_bound(lower, |value| parse_number(field_name, value))?,
+            map_bound(upper, |value| parse_number(field_name, value))?,
+        ),
+    };
+    Ok(Box::new(range_query))
+}
@@ -0,0 +1,209 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::bail;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Placeholders rendered from the timestamp of a split, with their `chrono` format.
+const DATE_TIME_PLACEHOLDERS: &[(&str, &str)] = &[
+    ("date", "%Y-%m-%d"),
+    ("year", "%Y"),
+    ("month", "%m"),
+    ("day", "%d"),
+    ("hour", "%H"),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    SplitId,
+    DateTime(&'static str),
+}
+
+/// Layout of the paths of the split files, relative to the index URI, e.g.
+/// `{{date}}/{{split_id}}.split`.
+///
+/// The layout must contain `{{split_id}}`, so that splits never overwrite each other. The
+/// `{{date}}` (e.g. `2021-10-14`), `{{year}}`, `{{month}}`, `{{day}}` and `{{hour}}`
+/// placeholders are rendered in UTC from the timestamp of the split.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SplitPathLayout {
+    layout: String,
+    segments: Vec<Segment>,
+}
+
+impl SplitPathLayout {
+    /// Returns the path of the file of the split `split_id` whose timestamp is `timestamp`,
+    /// in seconds.
+    pub fn split_file_path(&self, split_id: &str, timestamp: i64) -> String {
+        let date_time = NaiveDateTime::from_timestamp_opt(timestamp, 0)
+            .unwrap_or_else(|| NaiveDateTime::from_timestamp(0, 0));
+        let mut split_file_path = String::with_capacity(self.layout.len() + split_id.len());
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => split_file_path.push_str(literal),
+                Segment::SplitId => split_file_path.push_str(split_id),
+                Segment::DateTime(format) => {
+                    split_file_path.push_str(&date_time.format(format).to_string())
+                }
+            }
+        }
+        split_file_path
+    }
+}
+
+impl FromStr for SplitPathLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(layout: &str) -> anyhow::Result<Self> {
+        if layout
+            .split('/')
+            .any(|component| component.is_empty() || component == "." || component == "..")
+        {
+            bail!(
+                "Split path layout `{}` must be a relative path without empty, `.` or `..` \
+                 components.",
+                layout
+            );
+        }
+        let mut segments = Vec::new();
+        let mut remaining = layout;
+        while let Some(start) = remaining.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Literal(remaining[..start].to_string()));
+            }
+            let end = match remaining[start..].find("}}") {
+                Some(end) => start + end,
+                None => bail!("Unclosed placeholder in split path layout `{}`.", layout),
+            };
+            let placeholder = remaining[start + 2..end].trim();
+            let segment = if placeholder == "split_id" {
+                Segment::SplitId
+            } else if let Some((_, format)) = DATE_TIME_PLACEHOLDERS
+                .iter()
+                .find(|(name, _)| *name == placeholder)
+            {
+                Segment::DateTime(format)
+            } else {
+                bail!(
+                    "Unknown placeholder `{{{{{}}}}}` in split path layout `{}`.",
+                    placeholder,
+                    layout
+                );
+            };
+            segments.push(segment);
+            remaining = &remaining[end + 2..];
+        }
+        if !remaining.is_empty() {
+            segments.push(Segment::Literal(remaining.to_string()));
+        }
+        if !segments.contains(&Segment::SplitId) {
+            bail!(
+                "Split path layout `{}` must contain the `{{{{split_id}}}}` placeholder.",
+                layout
+            );
+        }
+        Ok(SplitPathLayout {
+            layout: layout.to_string(),
+            segments,
+        })
+    }
+}
+
+impl TryFrom<String> for SplitPathLayout {
+    type Error = anyhow::Error;
+
+    fn try_from(layout: String) -> anyhow::Result<Self> {
+        layout.parse()
+    }
+}
+
+impl From<SplitPathLayout> for String {
+    fn from(split_path_layout: SplitPathLayout) -> Self {
+        split_path_layout.layout
+    }
+}
+
+impl fmt::Display for SplitPathLayout {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_path_layout() -> anyhow::Result<()> {
+        // 2021-10-14T09:30:00Z
+        let timestamp = 1_634_203_800;
+        let layout: SplitPathLayout = "{{date}}/{{split_id}}.split".parse()?;
+        assert_eq!(
+            layout.split_file_path("split1", timestamp),
+            "2021-10-14/split1.split"
+        );
+        let layout: SplitPathLayout =
+            "logs/{{ year }}/{{month}}/{{day}}/{{hour}}/{{split_id}}".parse()?;
+        assert_eq!(
+            layout.split_file_path("split1", timestamp),
+            "logs/2021/10/14/09/split1"
+        );
+        assert_eq!(
+            layout.to_string(),
+            "logs/{{ year }}/{{month}}/{{day}}/{{hour}}/{{split_id}}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_path_layout_invalid() {
+        for invalid_layout in &[
+            "{{date}}.split",
+            "{{date}}/{{split_id}",
+            "{{week}}/{{split_id}}.split",
+            "/{{split_id}}.split",
+            "{{date}}//{{split_id}}.split",
+            "../{{split_id}}.split",
+        ] {
+            assert!(
+                invalid_layout.parse::<SplitPathLayout>().is_err(),
+                "`{}` should be invalid",
+                invalid_layout
+            );
+        }
+    }
+
+    #[test]
+    fn test_split_path_layout_serde() -> anyhow::Result<()> {
+        let layout: SplitPathLayout = serde_json::from_str(r#""{{date}}/{{split_id}}.split""#)?;
+        assert_eq!(
+            serde_json::to_string(&layout)?,
+            r#""{{date}}/{{split_id}}.split""#
+        );
+        assert!(serde_json::from_str::<SplitPathLayout>(r#""{{date}}.split""#).is_err());
+        Ok(())
+    }
+}
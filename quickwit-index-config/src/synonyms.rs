@@ -0,0 +1,246 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::bail;
+use tantivy::query::Occur;
+use tantivy::schema::{FieldType, Schema};
+use tantivy_query_grammar::{UserInputAst, UserInputLeaf, UserInputLiteral};
+
+/// Maps the terms of the synonym sets of an index to the other terms of their sets.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct SynonymMap {
+    synonyms: HashMap<String, Vec<String>>,
+}
+
+impl SynonymMap {
+    /// Builds the map of the synonym sets, in which all the terms are equivalent.
+    ///
+    /// The terms are lowercased single words. A term belonging to several sets gets the
+    /// synonyms of all of them.
+    pub fn build(synonym_sets: &[Vec<String>]) -> anyhow::Result<SynonymMap> {
+        let mut synonym_term_sets: HashMap<String, BTreeSet<String>> = HashMap::new();
+        for synonym_set in synonym_sets {
+            let mut terms = BTreeSet::new();
+            for term in synonym_set {
+                let term = term.trim().to_lowercase();
+                if term.is_empty() || !term.chars().all(char::is_alphanumeric) {
+                    bail!("Synonym `{}` must be a single alphanumeric word.", term);
+                }
+                terms.insert(term);
+            }
+            if terms.len() < 2 {
+                bail!(
+                    "Synonym set `{}` must contain at least two distinct terms.",
+                    synonym_set.join(", ")
+                );
+            }
+            for term in terms.iter() {
+                synonym_term_sets
+                    .entry(term.clone())
+                    .or_default()
+                    .extend(terms.iter().filter(|synonym| *synonym != term).cloned());
+            }
+        }
+        let synonyms = synonym_term_sets
+            .into_iter()
+            .map(|(term, synonyms)| (term, synonyms.into_iter().collect()))
+            .collect();
+        Ok(SynonymMap { synonyms })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.synonyms.is_empty()
+    }
+
+    /// Returns the synonyms of `term`, if any.
+    pub fn get(&self, term: &str) -> Option<&[String]> {
+        self.synonyms
+            .get(&term.to_lowercase())
+            .map(|synonyms| synonyms.as_slice())
+    }
+}
+
+/// Parses synonym sets written one per line, as comma-separated terms.
+///
+/// Blank lines and lines starting with `#` are skipped.
+pub fn parse_synonym_sets(text: &str) -> Vec<Vec<String>> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.split(',')
+                .map(|term| term.trim().to_string())
+                .collect()
+        })
+        .collect()
+}
+
+/// Replaces the terms of the query on text fields by a clause matching any of the term
+/// and its synonyms.
+///
+/// Returns true if at least one term was expanded.
+pub(crate) fn expand_synonyms(
+    user_input_ast: &mut UserInputAst,
+    synonym_map: &SynonymMap,
+    schema: &Schema,
+) -> bool {
+    match user_input_ast {
+        UserInputAst::Clause(sub_queries) => {
+            let mut expanded = false;
+            for (_, sub_ast) in sub_queries.iter_mut() {
+                expanded |= expand_synonyms(sub_ast, synonym_map, schema);
+            }
+            expanded
+        }
+        UserInputAst::Boost(ast, _) => expand_synonyms(ast, synonym_map, schema),
+        UserInputAst::Leaf(leaf) => {
+            let literal = match leaf.as_ref() {
+                UserInputLeaf::Literal(literal) if is_text_literal(schema, literal) => literal,
+                _ => return false,
+            };
+            let synonyms = match synonym_map.get(&literal.phrase) {
+                Some(synonyms) => synonyms,
+                None => return false,
+            };
+            let mut clauses = Vec::with_capacity(synonyms.len() + 1);
+            for phrase in std::iter::once(&literal.phrase).chain(synonyms) {
+                let synonym_literal = UserInputLiteral {
+                    field_name: literal.field_name.clone(),
+                    phrase: phrase.clone(),
+                };
+                clauses.push((
+                    Some(Occur::Should),
+                    UserInputAst::Leaf(Box::new(UserInputLeaf::Literal(synonym_literal))),
+                ));
+            }
+            *user_input_ast = UserInputAst::Clause(clauses);
+            true
+        }
+    }
+}
+
+/// Returns true if `literal` targets a text field, or the default search fields.
+fn is_text_literal(schema: &Schema, literal: &UserInputLiteral) -> bool {
+    let field_name = match &literal.field_name {
+        Some(field_name) => field_name,
+        None => return true,
+    };
+    schema
+        .get_field(field_name)
+        .map(|field| {
+            matches!(
+                schema.get_field_entry(field).field_type(),
+                FieldType::Str(_)
+            )
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::{Schema, INDEXED, TEXT};
+    use tantivy_query_grammar::{UserInputAst, UserInputLeaf};
+
+    use super::{expand_synonyms, parse_synonym_sets, SynonymMap};
+
+    fn synonym_sets(sets: &[&[&str]]) -> Vec<Vec<String>> {
+        sets.iter()
+            .map(|set| set.iter().map(|term| term.to_string()).collect())
+            .collect()
+    }
+
+    fn collect_phrases(user_input_ast: &UserInputAst, phrases: &mut Vec<String>) {
+        match user_input_ast {
+            UserInputAst::Clause(sub_queries) => {
+                for (_, sub_ast) in sub_queries {
+                    collect_phrases(sub_ast, phrases);
+                }
+            }
+            UserInputAst::Boost(ast, _) => collect_phrases(ast, phrases),
+            UserInputAst::Leaf(leaf) => {
+                if let UserInputLeaf::Literal(literal) = leaf.as_ref() {
+                    phrases.push(format!(
+                        "{}:{}",
+                        literal.field_name.as_deref().unwrap_or_default(),
+                        literal.phrase
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_synonym_map() -> anyhow::Result<()> {
+        let synonym_map = SynonymMap::build(&synonym_sets(&[
+            &["Laptop", "notebook"],
+            &["notebook", "journal"],
+        ]))?;
+        assert_eq!(
+            synonym_map.get("laptop"),
+            Some(&["notebook".to_string()][..])
+        );
+        assert_eq!(
+            synonym_map.get("NoteBook"),
+            Some(&["journal".to_string(), "laptop".to_string()][..])
+        );
+        assert_eq!(
+            synonym_map.get("journal"),
+            Some(&["notebook".to_string()][..])
+        );
+        assert_eq!(synonym_map.get("desktop"), None);
+
+        assert!(SynonymMap::build(&synonym_sets(&[&["laptop"]])).is_err());
+        assert!(SynonymMap::build(&synonym_sets(&[&["laptop", "LAPTOP"]])).is_err());
+        assert!(SynonymMap::build(&synonym_sets(&[&["laptop", "portable computer"]])).is_err());
+        assert!(SynonymMap::build(&synonym_sets(&[&["laptop", ""]])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_synonym_sets() {
+        let text = "# Devices\nlaptop, notebook\n\n  tv,television ,telly\n";
+        assert_eq!(
+            parse_synonym_sets(text),
+            synonym_sets(&[&["laptop", "notebook"], &["tv", "television", "telly"]])
+        );
+    }
+
+    #[test]
+    fn test_expand_synonyms() -> anyhow::Result<()> {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT);
+        schema_builder.add_u64_field("count", INDEXED);
+        let schema = schema_builder.build();
+        let synonym_map = SynonymMap::build(&synonym_sets(&[&["laptop", "notebook"]]))?;
+
+        let mut user_input_ast = tantivy_query_grammar::parse_query("title:laptop AND computer")
+            .map_err(|_| anyhow::anyhow!("Invalid query."))?;
+        assert!(expand_synonyms(&mut user_input_ast, &synonym_map, &schema));
+        let mut phrases = Vec::new();
+        collect_phrases(&user_input_ast, &mut phrases);
+        assert_eq!(phrases, ["title:laptop", "title:notebook", ":computer"]);
+
+        let mut user_input_ast = tantivy_query_grammar::parse_query("count:10 desktop")
+            .map_err(|_| anyhow::anyhow!("Invalid query."))?;
+        assert!(!expand_synonyms(&mut user_input_ast, &synonym_map, &schema));
+        Ok(())
+    }
+}
@@ -0,0 +1,346 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::str::FromStr;
+
+use anyhow::bail;
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, Offset,
+    TimeZone as ChronoTimeZone,
+};
+use chrono_tz::Tz;
+
+/// Formats of the local dates accepted by [`TimeZone::parse_timestamp`], besides RFC 3339.
+const LOCAL_DATE_TIME_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"];
+
+/// Time zone the dates of a query are expressed in.
+///
+/// It is used to parse the dates that do not carry an offset, and to delimit the
+/// days, weeks, months and years of a date histogram.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeZone {
+    /// Fixed offset from UTC, e.g. `+02:00`.
+    Fixed(FixedOffset),
+    /// Time zone of the IANA database, e.g. `Europe/Paris`, whose offset follows
+    /// daylight saving time.
+    Named(Tz),
+}
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        TimeZone::Fixed(FixedOffset::east(0))
+    }
+}
+
+/// Calendar unit whose length depends on the time zone and on the date.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CalendarUnit {
+    /// Day, starting at midnight.
+    Day,
+    /// Week, starting on Monday at midnight.
+    Week,
+    /// Month, starting on its first day at midnight.
+    Month,
+    /// Year, starting on January 1st at midnight.
+    Year,
+}
+
+/// Parses an offset such as `+02:00`, `-0530` or `+02`.
+fn parse_fixed_offset(offset: &str) -> Option<FixedOffset> {
+    let sign = match offset.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = &offset[1..];
+    let (hours, minutes) = if let Some(colon_pos) = digits.find(':') {
+        (&digits[..colon_pos], &digits[colon_pos + 1..])
+    } else if digits.len() == 4 {
+        digits.split_at(2)
+    } else {
+        (digits, "00")
+    };
+    if hours.is_empty()
+        || hours.len() > 2
+        || minutes.len() != 2
+        || !hours
+            .chars()
+            .chain(minutes.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3_600 + minutes * 60))
+}
+
+impl FromStr for TimeZone {
+    type Err = anyhow::Error;
+
+    /// Parses an offset from UTC (`+02:00`) or an IANA time zone name (`Europe/Paris`).
+    ///
+    /// An empty string, `UTC` and `Z` stand for UTC.
+    fn from_str(time_zone: &str) -> anyhow::Result<Self> {
+        let time_zone = time_zone.trim();
+        if time_zone.is_empty() || time_zone.eq_ignore_ascii_case("utc") || time_zone == "Z" {
+            return Ok(TimeZone::default());
+        }
+        if time_zone.starts_with(|c: char| c == '+' || c == '-') {
+            let offset = parse_fixed_offset(time_zone).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid UTC offset `{}`: expected an offset such as `+02:00`.",
+                    time_zone
+                )
+            })?;
+            return Ok(TimeZone::Fixed(offset));
+        }
+        match Tz::from_str(time_zone) {
+            Ok(tz) => Ok(TimeZone::Named(tz)),
+            Err(_) => bail!(
+                "Unknown time zone `{}`: expected an offset such as `+02:00` or an IANA time zone \
+                 name such as `Europe/Paris`.",
+                time_zone
+            ),
+        }
+    }
+}
+
+impl TimeZone {
+    /// Returns the offset from UTC, in seconds, at the instant `timestamp`.
+    pub fn offset_seconds(&self, timestamp: i64) -> i64 {
+        match self {
+            TimeZone::Fixed(offset) => offset.local_minus_utc() as i64,
+            TimeZone::Named(tz) => NaiveDateTime::from_timestamp_opt(timestamp, 0)
+                .map(|utc_date_time| {
+                    tz.offset_from_utc_datetime(&utc_date_time)
+                        .fix()
+                        .local_minus_utc() as i64
+                })
+                .unwrap_or(0),
+        }
+    }
+
+    fn from_local(&self, local_date_time: &NaiveDateTime) -> LocalResult<i64> {
+        match self {
+            TimeZone::Fixed(offset) => offset
+                .from_local_datetime(local_date_time)
+                .map(|date_time| date_time.timestamp()),
+            TimeZone::Named(tz) => tz
+                .from_local_datetime(local_date_time)
+                .map(|date_time| date_time.timestamp()),
+        }
+    }
+
+    fn local_to_timestamp(&self, local_date_time: &NaiveDateTime) -> anyhow::Result<i64> {
+        // A local time repeated when the clocks are turned back stands for its first
+        // occurrence.
+        self.from_local(local_date_time).earliest().ok_or_else(|| {
+            anyhow::anyhow!(
+                "The local time `{}` does not exist in the time zone, it is skipped by a daylight \
+                 saving time transition.",
+                local_date_time
+            )
+        })
+    }
+
+    /// Parses a date into a timestamp in seconds.
+    ///
+    /// The date is either a timestamp in seconds, an RFC 3339 date with an offset
+    /// (`2021-06-01T12:00:00+02:00`), or a local date (`2021-06-01T12:00:00`, `2021-06-01`)
+    /// interpreted in this time zone. A local day stands for its midnight.
+    pub fn parse_timestamp(&self, date: &str) -> anyhow::Result<i64> {
+        let date = date.trim();
+        if let Ok(timestamp) = date.parse::<i64>() {
+            return Ok(timestamp);
+        }
+        if let Ok(date_time) = DateTime::parse_from_rfc3339(date) {
+            return Ok(date_time.timestamp());
+        }
+        for format in LOCAL_DATE_TIME_FORMATS {
+            if let Ok(local_date_time) = NaiveDateTime::parse_from_str(date, format) {
+                return self.local_to_timestamp(&local_date_time);
+            }
+        }
+        if let Ok(local_date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            return self.local_to_timestamp(&local_date.and_hms(0, 0, 0));
+        }
+        bail!(
+            "Failed to parse the date `{}`: expected a timestamp in seconds, an RFC 3339 date \
+             such as `2021-06-01T12:00:00+02:00`, or a local date such as `2021-06-01` or \
+             `2021-06-01T12:00:00`.",
+            date
+        )
+    }
+
+    /// Formats the timestamp `timestamp` as an RFC 3339 date in this time zone.
+    pub fn format_timestamp(&self, timestamp: i64) -> String {
+        FixedOffset::east_opt(self.offset_seconds(timestamp) as i32)
+            .and_then(|offset| offset.timestamp_opt(timestamp, 0).single())
+            .map(|date_time| date_time.to_rfc3339())
+            .unwrap_or_else(|| timestamp.to_string())
+    }
+
+    /// Returns the timestamp of the start of the calendar `unit` containing `timestamp`,
+    /// in this time zone.
+    pub fn truncate(&self, timestamp: i64, unit: CalendarUnit) -> i64 {
+        let local_date_time = match NaiveDateTime::from_timestamp_opt(
+            timestamp + self.offset_seconds(timestamp),
+            0,
+        ) {
+            Some(local_date_time) => local_date_time,
+            None => return timestamp,
+        };
+        let local_date = local_date_time.date();
+        let start_date = match unit {
+            CalendarUnit::Day => local_date,
+            CalendarUnit::Week => {
+                local_date - Duration::days(local_date.weekday().num_days_from_monday() as i64)
+            }
+            CalendarUnit::Month => NaiveDate::from_ymd(local_date.year(), local_date.month(), 1),
+            CalendarUnit::Year => NaiveDate::from_ymd(local_date.year(), 1, 1),
+        };
+        let start_date_time = start_date.and_hms(0, 0, 0);
+        // In some time zones, midnight is skipped by daylight saving time transitions:
+        // the unit then starts at the first local time that exists.
+        (0..24)
+            .find_map(|hours| {
+                self.from_local(&(start_date_time + Duration::hours(hours)))
+                    .earliest()
+            })
+            .unwrap_or(timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_zone() {
+        assert_eq!(TimeZone::from_str("").unwrap(), TimeZone::default());
+        assert_eq!(TimeZone::from_str("UTC").unwrap(), TimeZone::default());
+        assert_eq!(TimeZone::from_str("Z").unwrap(), TimeZone::default());
+        assert_eq!(
+            TimeZone::from_str("+02:00").unwrap(),
+            TimeZone::Fixed(FixedOffset::east(7_200))
+        );
+        assert_eq!(
+            TimeZone::from_str("-0530").unwrap(),
+            TimeZone::Fixed(FixedOffset::west(19_800))
+        );
+        assert_eq!(
+            TimeZone::from_str("+09").unwrap(),
+            TimeZone::Fixed(FixedOffset::east(32_400))
+        );
+        assert_eq!(
+            TimeZone::from_str("Europe/Paris").unwrap(),
+            TimeZone::Named(Tz::Europe__Paris)
+        );
+        assert!(TimeZone::from_str("+25:00").is_err());
+        assert!(TimeZone::from_str("+2:0").is_err());
+        assert!(TimeZone::from_str("Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let utc = TimeZone::default();
+        assert_eq!(utc.parse_timestamp("1622548800").unwrap(), 1_622_548_800);
+        assert_eq!(utc.parse_timestamp("-3600").unwrap(), -3_600);
+        assert_eq!(
+            utc.parse_timestamp("2021-06-01T12:00:00Z").unwrap(),
+            1_622_548_800
+        );
+        assert_eq!(utc.parse_timestamp("2021-06-01").unwrap(), 1_622_505_600);
+
+        let paris = TimeZone::from_str("Europe/Paris").unwrap();
+        // The dates with an offset ignore the time zone.
+        assert_eq!(
+            paris.parse_timestamp("2021-06-01T12:00:00Z").unwrap(),
+            1_622_548_800
+        );
+        // Summer time.
+        assert_eq!(
+            paris.parse_timestamp("2021-06-01T14:00:00").unwrap(),
+            1_622_548_800
+        );
+        assert_eq!(
+            paris.parse_timestamp("2021-06-01 14:00:00").unwrap(),
+            1_622_548_800
+        );
+        assert_eq!(paris.parse_timestamp("2021-06-01").unwrap(), 1_622_498_400);
+        // Winter time.
+        assert_eq!(paris.parse_timestamp("2021-01-01").unwrap(), 1_609_455_600);
+        // Skipped by the transition to summer time.
+        assert!(paris.parse_timestamp("2021-03-28T02:30:00").is_err());
+        // Repeated by the transition to winter time.
+        assert_eq!(
+            paris.parse_timestamp("2021-10-31T02:30:00").unwrap(),
+            1_635_640_200
+        );
+        assert!(paris.parse_timestamp("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(
+            TimeZone::default().format_timestamp(1_622_548_800),
+            "2021-06-01T12:00:00+00:00"
+        );
+        let paris = TimeZone::from_str("Europe/Paris").unwrap();
+        assert_eq!(
+            paris.format_timestamp(1_622_548_800),
+            "2021-06-01T14:00:00+02:00"
+        );
+        assert_eq!(
+            paris.format_timestamp(1_609_455_600),
+            "2021-01-01T00:00:00+01:00"
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        let utc = TimeZone::default();
+        // 2021-06-02T12:00:00Z is a Wednesday.
+        let timestamp = 1_622_635_200;
+        assert_eq!(utc.truncate(timestamp, CalendarUnit::Day), 1_622_592_000);
+        assert_eq!(utc.truncate(timestamp, CalendarUnit::Week), 1_622_419_200);
+        assert_eq!(utc.truncate(timestamp, CalendarUnit::Month), 1_622_505_600);
+        assert_eq!(utc.truncate(timestamp, CalendarUnit::Year), 1_609_459_200);
+
+        let paris = TimeZone::from_str("Europe/Paris").unwrap();
+        assert_eq!(paris.truncate(timestamp, CalendarUnit::Day), 1_622_584_800);
+        assert_eq!(
+            paris.truncate(timestamp, CalendarUnit::Month),
+            1_622_498_400
+        );
+        assert_eq!(paris.truncate(timestamp, CalendarUnit::Year), 1_609_455_600);
+        // 2021-06-01T23:30:00Z is already June 2nd in Paris.
+        assert_eq!(
+            paris.truncate(1_622_590_200, CalendarUnit::Day),
+            1_622_584_800
+        );
+
+        let tokyo = TimeZone::from_str("+09:00").unwrap();
+        assert_eq!(tokyo.truncate(timestamp, CalendarUnit::Day), 1_622_559_600);
+    }
+}
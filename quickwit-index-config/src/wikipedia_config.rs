@@ -17,6 +17,8 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
+
 use quickwit_proto::SearchRequest;
 use serde::{Deserialize, Serialize};
 use tantivy::query::Query;
@@ -24,7 +26,10 @@ use tantivy::schema::{Schema, TextFieldIndexing, TextOptions, STRING};
 use tantivy::Document;
 
 use crate::query_builder::build_query;
-use crate::{DocParsingError, IndexConfig, QueryParserError, TAGS_FIELD_NAME};
+use crate::{
+    DocParsingError, FuzzyQueryConfig, IndexConfig, QueryParserError, WildcardQueryConfig,
+    TAGS_FIELD_NAME,
+};
 
 /// A document config tailored for the wikipedia corpus.
 #[derive(Clone, Serialize, Deserialize)]
@@ -80,7 +85,17 @@ impl IndexConfig for WikipediaIndexConfig {
         request: &SearchRequest,
     ) -> Result<Box<dyn Query>, QueryParserError> {
         let default_search_field_names = &["body".to_string(), "title".to_string()];
-        build_query(split_schema, request, default_search_field_names)
+        build_query(
+            split_schema,
+            request,
+            default_search_field_names,
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            &WildcardQueryConfig::default(),
+            &FuzzyQueryConfig::default(),
+            None,
+            None,
+        )
     }
 
     fn schema(&self) -> Schema {
@@ -0,0 +1,361 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::bail;
+use regex::{Regex, RegexBuilder};
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, EmptyQuery, Occur, Query, QueryParser,
+    QueryParserError as TantivyQueryParserError, TermQuery,
+};
+use tantivy::schema::{Field, FieldType, IndexRecordOption, Schema};
+use tantivy::{Score, Searcher, Term};
+use tantivy_query_grammar::{UserInputAst, UserInputLeaf, UserInputLiteral};
+
+use crate::coercion::{coerce_literal, Coercion};
+use crate::default_index_config::BytesFormat;
+use crate::fuzzy_query::{build_fuzzy_query, fuzzy_term, FuzzyTerm};
+use crate::range_query::build_range_query;
+use crate::{FuzzyQueryConfig, TimeZone, WildcardQueryConfig};
+
+const REGEX_META_CHARACTERS: &[char] = &[
+    '.', '^', '$', '*', '+', '?', '(', ')', '[', ']', '{', '}', '|', '\\',
+];
+
+/// Returns the regex pattern of `phrase` if it is a regex (`/err.r/`) or wildcard (`err*r`) term.
+fn pattern(phrase: &str) -> Option<Pattern> {
+    if phrase.len() > 2 && phrase.starts_with('/') && phrase.ends_with('/') {
+        let regex = &phrase[1..phrase.len() - 1];
+        return Some(Pattern {
+            regex: regex.to_string(),
+            literal_prefix: regex_literal_prefix(regex),
+        });
+    }
+    if !phrase.contains(|c: char| c == '*' || c == '?') {
+        return None;
+    }
+    let mut regex = String::with_capacity(phrase.len() * 2);
+    for c in phrase.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    let literal_prefix_len = phrase
+        .find(|c: char| c == '*' || c == '?')
+        .unwrap_or_else(|| phrase.len());
+    Some(Pattern {
+        regex,
+        literal_prefix: phrase[..literal_prefix_len].to_string(),
+    })
+}
+
+/// Returns the literal characters every match of `regex` starts with.
+fn regex_literal_prefix(regex: &str) -> String {
+    let mut literal_prefix = String::new();
+    for c in regex.chars() {
+        if REGEX_META_CHARACTERS.contains(&c) {
+            // The last literal character is optional or repeated.
+            if matches!(c, '*' | '?' | '{') {
+                literal_prefix.pop();
+            }
+            // The alternatives of a top-level `|` do not share the prefix.
+            if regex.contains('|') {
+                literal_prefix.clear();
+            }
+            break;
+        }
+        literal_prefix.push(c);
+    }
+    literal_prefix
+}
+
+struct Pattern {
+    regex: String,
+    literal_prefix: String,
+}
+
+/// Returns true if `phrase` is a wildcard or regex term.
+pub(crate) fn is_pattern(phrase: &str) -> bool {
+    pattern(phrase).is_some()
+}
+
+/// Returns true if the query contains a wildcard or regex term.
+pub(crate) fn has_wildcard_clause(user_input_ast: &UserInputAst) -> bool {
+    match user_input_ast {
+        UserInputAst::Clause(sub_queries) => sub_queries
+            .iter()
+            .any(|(_, sub_ast)| has_wildcard_clause(sub_ast)),
+        UserInputAst::Boost(ast, _) => has_wildcard_clause(ast),
+        UserInputAst::Leaf(leaf) => match leaf.as_ref() {
+            UserInputLeaf::Literal(literal) => is_pattern(&literal.phrase),
+            _ => false,
+        },
+    }
+}
+
+/// Builds the queries containing wildcard, regex, fuzzy or range clauses, or values to
+/// coerce.
+///
+/// Wildcard, regex and fuzzy terms are expanded into the terms of the split
+/// `searcher_opt` reads. The bounds of the range clauses are parsed in `time_zone`. The values that
+/// do not match the type of their field are coerced, or match nothing if they cannot be.
+/// The values of bytes fields are decoded with the format of their field and matched
+/// exactly. The other terms are handed over to tantivy's query parser.
+pub(crate) struct UserInputQueryBuilder<'a> {
+    pub schema: &'a Schema,
+    pub search_fields: &'a [Field],
+    pub query_parser: &'a QueryParser,
+    pub config: &'a WildcardQueryConfig,
+    pub fuzzy_config: &'a FuzzyQueryConfig,
+    pub searcher_opt: Option<&'a Searcher>,
+    pub time_zone: &'a TimeZone,
+    pub bytes_formats: &'a BTreeMap<String, BytesFormat>,
+}
+
+impl<'a> UserInputQueryBuilder<'a> {
+    pub fn build(&self, user_input_ast: UserInputAst) -> anyhow::Result<Box<dyn Query>> {
+        match user_input_ast {
+            UserInputAst::Clause(sub_asts) => {
+                let mut clauses = Vec::with_capacity(sub_asts.len());
+                for (occur_opt, sub_ast) in sub_asts {
+                    clauses.push((occur_opt.unwrap_or(Occur::Should), self.build(sub_ast)?));
+                }
+                Ok(Box::new(BooleanQuery::new(clauses)))
+            }
+            UserInputAst::Boost(ast, boost) => {
+                Ok(Box::new(BoostQuery::new(self.build(*ast)?, boost as Score)))
+            }
+            UserInputAst::Leaf(leaf) => match *leaf {
+                UserInputLeaf::Literal(literal) => {
+                    if let Some(pattern) = pattern(&literal.phrase) {
+                        return self.build_pattern_query(&literal, &pattern);
+                    }
+                    if let Some(fuzzy_term) = fuzzy_term(&literal.phrase) {
+                        return self.build_fuzzy_query(&literal, &fuzzy_term);
+                    }
+                    let phrase = match coerce_literal(
+                        self.schema,
+                        &literal,
+                        self.bytes_formats,
+                        self.time_zone,
+                    ) {
+                        Coercion::Unchanged => literal.phrase.clone(),
+                        Coercion::Coerced(coerced_phrase) => coerced_phrase,
+                        Coercion::Mismatch => return Ok(Box::new(EmptyQuery)),
+                    };
+                    if let Some(bytes_query) = self.build_bytes_query(&literal, &phrase) {
+                        return Ok(bytes_query);
+                    }
+                    let literal_query = match &literal.field_name {
+                        Some(field_name) => format!("{}:\"{}\"", field_name, phrase),
+                        None => format!("\"{}\"", phrase),
+                    };
+                    Ok(self.query_parser.parse_query(&literal_query)?)
+                }
+                UserInputLeaf::All => Ok(Box::new(AllQuery)),
+                UserInputLeaf::Range {
+                    field,
+                    lower,
+                    upper,
+                } => build_range_query(
+                    self.schema,
+                    field.as_deref(),
+                    &lower,
+                    &upper,
+                    self.time_zone,
+                ),
+            },
+        }
+    }
+
+    /// Returns the exact-match query of `phrase` if the clause `literal` targets a bytes
+    /// field.
+    fn build_bytes_query(
+        &self,
+        literal: &UserInputLiteral,
+        phrase: &str,
+    ) -> Option<Box<dyn Query>> {
+        let field_name = literal.field_name.as_ref()?;
+        let field = self.schema.get_field(field_name)?;
+        if !matches!(
+            self.schema.get_field_entry(field).field_type(),
+            FieldType::Bytes(_)
+        ) {
+            return None;
+        }
+        let format = self
+            .bytes_formats
+            .get(field_name)
+            .copied()
+            .unwrap_or_default();
+        let bytes_query: Box<dyn Query> = match format.decode(phrase) {
+            Some(bytes) => Box::new(TermQuery::new(
+                Term::from_field_bytes(field, &bytes),
+                IndexRecordOption::Basic,
+            )),
+            None => Box::new(EmptyQuery),
+        };
+        Some(bytes_query)
+    }
+
+    fn build_pattern_query(
+        &self,
+        literal: &UserInputLiteral,
+        pattern: &Pattern,
+    ) -> anyhow::Result<Box<dyn Query>> {
+        if pattern.literal_prefix.is_empty() && !self.config.allow_leading_wildcard {
+            bail!(
+                "Patterns starting with a wildcard are not allowed: `{}`.",
+                literal.phrase
+            );
+        }
+        let searcher = self.split_searcher("Wildcard and regex")?;
+        let regex = self.compile_regex(&literal.phrase, &pattern.regex)?;
+        let fields = self.text_fields(literal, "Wildcard and regex")?;
+        let mut terms = BTreeSet::new();
+        for field in fields {
+            for segment_reader in searcher.segment_readers() {
+                let inverted_index = segment_reader.inverted_index(field)?;
+                let mut term_stream = inverted_index
+                    .terms()
+                    .range()
+                    .ge(pattern.literal_prefix.as_bytes())
+                    .into_stream()?;
+                while let Some((term_bytes, _)) = term_stream.next() {
+                    if !term_bytes.starts_with(pattern.literal_prefix.as_bytes()) {
+                        break;
+                    }
+                    let term_text = match std::str::from_utf8(term_bytes) {
+                        Ok(term_text) => term_text,
+                        Err(_) => continue,
+                    };
+                    if !regex.is_match(term_text) {
+                        continue;
+                    }
+                    terms.insert(Term::from_field_text(field, term_text));
+                    if terms.len() > self.config.max_expanded_terms {
+                        bail!(
+                            "The pattern `{}` matches more than {} terms.",
+                            literal.phrase,
+                            self.config.max_expanded_terms
+                        );
+                    }
+                }
+            }
+        }
+        let term_queries: Vec<(Occur, Box<dyn Query>)> = terms
+            .into_iter()
+            .map(|term| {
+                let term_query: Box<dyn Query> =
+                    Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
+                (Occur::Should, term_query)
+            })
+            .collect();
+        Ok(Box::new(BooleanQuery::new(term_queries)))
+    }
+
+    fn build_fuzzy_query(
+        &self,
+        literal: &UserInputLiteral,
+        fuzzy_term: &FuzzyTerm,
+    ) -> anyhow::Result<Box<dyn Query>> {
+        let searcher = self.split_searcher("Fuzzy")?;
+        let fields = self.text_fields(literal, "Fuzzy")?;
+        build_fuzzy_query(searcher, &fields, fuzzy_term, self.fuzzy_config)
+    }
+
+    /// Returns the searcher of the split the `query_kind` queries are expanded against.
+    fn split_searcher(&self, query_kind: &str) -> anyhow::Result<&'a Searcher> {
+        self.searcher_opt.ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} queries can only be built for a given split.",
+                query_kind
+            )
+        })
+    }
+
+    /// Returns the fields targeted by the clause `literal`, which must be text fields for
+    /// the `query_kind` queries.
+    fn text_fields(
+        &self,
+        literal: &UserInputLiteral,
+        query_kind: &str,
+    ) -> anyhow::Result<Vec<Field>> {
+        let fields = match &literal.field_name {
+            Some(field_name) => vec![self
+                .schema
+                .get_field(field_name)
+                .ok_or_else(|| TantivyQueryParserError::FieldDoesNotExist(field_name.clone()))?],
+            None => self.search_fields.to_vec(),
+        };
+        for &field in fields.iter() {
+            if !matches!(
+                self.schema.get_field_entry(field).field_type(),
+                FieldType::Str(_)
+            ) {
+                bail!(
+                    "{} queries are only supported on text fields, `{}` is not a text field.",
+                    query_kind,
+                    self.schema.get_field_name(field)
+                );
+            }
+        }
+        Ok(fields)
+    }
+
+    fn compile_regex(&self, phrase: &str, regex: &str) -> anyhow::Result<Regex> {
+        let size_limit = self.config.max_automaton_size_in_bytes;
+        RegexBuilder::new(&format!("^(?:{})$", regex))
+            .size_limit(size_limit)
+            .dfa_size_limit(size_limit)
+            .build()
+            .map_err(|error| anyhow::anyhow!("Invalid pattern `{}`: {}", phrase, error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern() {
+        assert!(pattern("error").is_none());
+        assert!(pattern("/").is_none());
+        let wildcard_pattern = pattern("er?o*r.").unwrap();
+        assert_eq!(wildcard_pattern.regex, "er.o.*r\\.");
+        assert_eq!(wildcard_pattern.literal_prefix, "er");
+        assert_eq!(pattern("*error").unwrap().literal_prefix, "");
+        let regex_pattern = pattern("/err[o]+r/").unwrap();
+        assert_eq!(regex_pattern.regex, "err[o]+r");
+        assert_eq!(regex_pattern.literal_prefix, "err");
+    }
+
+    #[test]
+    fn test_regex_literal_prefix() {
+        assert_eq!(regex_literal_prefix("error"), "error");
+        assert_eq!(regex_literal_prefix("err.r"), "err");
+        assert_eq!(regex_literal_prefix("erro?r"), "err");
+        assert_eq!(regex_literal_prefix("erro{2}r"), "err");
+        assert_eq!(regex_literal_prefix("error|warning"), "");
+        assert_eq!(regex_literal_prefix(".*error"), "");
+    }
+}
@@ -40,10 +40,11 @@ use std::time::Duration;
 
 use byte_unit::Byte;
 use fail::FailScenario;
+use quickwit_common::QuickwitUri;
 use quickwit_index_config::default_config_for_tests;
 use quickwit_indexing::actors::IndexerParams;
 use quickwit_indexing::index_data;
-use quickwit_indexing::models::{CommitPolicy, ScratchDirectory};
+use quickwit_indexing::models::{CommitPolicy, DiskGuardrails, DocSizeLimit, ScratchDirectory};
 use quickwit_indexing::source::SourceConfig;
 use quickwit_metastore::checkpoint::Checkpoint;
 use quickwit_metastore::{IndexMetadata, Metastore, SingleFileMetastore, SplitState};
@@ -160,9 +161,15 @@ async fn aux_test_failpoints() -> anyhow::Result<()> {
     metastore
         .create_index(IndexMetadata {
             index_id: "test-index".to_string(),
-            index_uri: "ram://test-index/".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://test-index/"),
             index_config: Arc::new(index_config),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         })
         .await?;
     let params = IndexerParams {
@@ -171,7 +178,11 @@ async fn aux_test_failpoints() -> anyhow::Result<()> {
         commit_policy: CommitPolicy {
             timeout: Duration::from_secs(3),
             num_docs_threshold: 2,
+            adaptive_opt: None,
         },
+        disk_guardrails: DiskGuardrails::default(),
+        doc_size_limit: DocSizeLimit::default(),
+        realtime_opt: None,
     };
     let source_config = SourceConfig {
         id: "test-source".to_string(),
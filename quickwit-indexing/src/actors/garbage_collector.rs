@@ -17,22 +17,68 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use quickwit_actors::{Actor, ActorContext, AsyncActor};
 use quickwit_metastore::Metastore;
 use quickwit_storage::Storage;
-use tracing::info;
+use serde::Serialize;
+use tantivy::chrono::Utc;
+use tracing::{info, warn};
 
-use crate::run_garbage_collect;
+use crate::{mark_expired_splits_for_deletion, run_garbage_collect, SplitDeletionStats};
 
 const RUN_INTERVAL: Duration = Duration::from_secs(60); // 1 minutes
 const GRACE_PERIOD: Duration = Duration::from_secs(60 * 60); // 1 hour
 
-#[derive(Debug, Clone, Default)]
+/// Number of run reports kept in the garbage collector observable state.
+const MAX_NUM_RUN_REPORTS: usize = 10;
+
+/// Settings driving the garbage collector actor.
+#[derive(Debug, Clone)]
+pub struct GarbageCollectorParams {
+    /// Interval between two garbage collection passes.
+    pub run_interval: Duration,
+    /// Threshold period after which staged splits can be garbage collected.
+    pub grace_period: Duration,
+    /// If set, published splits whose time range ends before `now - retention_period`
+    /// are marked for deletion at each pass.
+    pub retention_period: Option<Duration>,
+}
+
+impl Default for GarbageCollectorParams {
+    fn default() -> Self {
+        Self {
+            run_interval: RUN_INTERVAL,
+            grace_period: GRACE_PERIOD,
+            retention_period: None,
+        }
+    }
+}
+
+/// Report of a single garbage collection pass.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GarbageCollectionRunReport {
+    /// Timestamp at which the pass started.
+    pub start_timestamp: i64,
+    /// Duration of the pass in milliseconds.
+    pub elapsed_time_millis: u64,
+    /// The number of splits marked for deletion by the retention policy.
+    pub num_expired_splits: usize,
+    /// The number of deleted files.
+    pub num_deleted_files: usize,
+    /// The number of bytes deleted.
+    pub num_deleted_bytes: usize,
+    /// The number of failed to delete files.
+    pub num_failed_files: usize,
+    /// The error that interrupted the pass, if any.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct GarbageCollectorCounters {
     /// The number of passes the garbage collector has performed.
     pub num_passes: usize,
@@ -42,6 +88,8 @@ pub struct GarbageCollectorCounters {
     pub num_deleted_bytes: usize,
     /// The number of failed to delete files.
     pub num_failed_files: usize,
+    /// Reports of the most recent passes, oldest first.
+    pub last_run_reports: VecDeque<GarbageCollectionRunReport>,
 }
 
 /// An actor for collecting garbage periodically from an index.
@@ -49,6 +97,7 @@ pub struct GarbageCollector {
     index_id: String,
     index_storage: Arc<dyn Storage>,
     metastore: Arc<dyn Metastore>,
+    params: GarbageCollectorParams,
     counters: GarbageCollectorCounters,
 }
 
@@ -57,14 +106,62 @@ impl GarbageCollector {
         index_id: String,
         index_storage: Arc<dyn Storage>,
         metastore: Arc<dyn Metastore>,
+    ) -> Self {
+        Self::with_params(
+            index_id,
+            index_storage,
+            metastore,
+            GarbageCollectorParams::default(),
+        )
+    }
+
+    pub fn with_params(
+        index_id: String,
+        index_storage: Arc<dyn Storage>,
+        metastore: Arc<dyn Metastore>,
+        params: GarbageCollectorParams,
     ) -> Self {
         Self {
             index_id,
             index_storage,
             metastore,
+            params,
             counters: GarbageCollectorCounters::default(),
         }
     }
+
+    async fn run_pass(
+        &self,
+        run_report: &mut GarbageCollectionRunReport,
+    ) -> anyhow::Result<SplitDeletionStats> {
+        if let Some(retention_period) = self.params.retention_period {
+            let expired_split_ids = mark_expired_splits_for_deletion(
+                &self.index_id,
+                self.metastore.clone(),
+                retention_period,
+            )
+            .await?;
+            if !expired_split_ids.is_empty() {
+                info!(index = %self.index_id, splits = ?expired_split_ids, "gc-retention");
+            }
+            run_report.num_expired_splits = expired_split_ids.len();
+        }
+        run_garbage_collect(
+            &self.index_id,
+            self.index_storage.clone(),
+            self.metastore.clone(),
+            self.params.grace_period,
+            false,
+        )
+        .await
+    }
+
+    fn record_run_report(&mut self, run_report: GarbageCollectionRunReport) {
+        if self.counters.last_run_reports.len() == MAX_NUM_RUN_REPORTS {
+            self.counters.last_run_reports.pop_front();
+        }
+        self.counters.last_run_reports.push_back(run_report);
+    }
 }
 
 impl Actor for GarbageCollector {
@@ -93,14 +190,21 @@ impl AsyncActor for GarbageCollector {
         info!(index = %self.index_id, "garbage-collect-operation");
         self.counters.num_passes += 1;
 
-        let deletion_stats = run_garbage_collect(
-            &self.index_id,
-            self.index_storage.clone(),
-            self.metastore.clone(),
-            GRACE_PERIOD,
-            false,
-        )
-        .await?;
+        let start_instant = Instant::now();
+        let mut run_report = GarbageCollectionRunReport {
+            start_timestamp: Utc::now().timestamp(),
+            ..Default::default()
+        };
+        // A failed pass is recorded and retried at the next interval rather than
+        // killing the actor.
+        let deletion_stats = match self.run_pass(&mut run_report).await {
+            Ok(deletion_stats) => deletion_stats,
+            Err(error) => {
+                warn!(index = %self.index_id, error = ?error, "gc-failure");
+                run_report.error = Some(format!("{:?}", error));
+                SplitDeletionStats::default()
+            }
+        };
 
         if !deletion_stats.candidate_entries.is_empty() {
             let deletion_success: HashSet<&str> = deletion_stats
@@ -117,16 +221,22 @@ impl AsyncActor for GarbageCollector {
             info!(deletion_success=?deletion_success, deletion_failures=?deletion_failures, "gc-delete");
         }
 
-        self.counters.num_deleted_files += deletion_stats.deleted_entries.len();
-        self.counters.num_deleted_bytes += deletion_stats
+        run_report.num_deleted_files = deletion_stats.deleted_entries.len();
+        run_report.num_deleted_bytes = deletion_stats
             .deleted_entries
             .iter()
             .map(|entry| entry.file_size_in_bytes as usize)
             .sum::<usize>();
-        self.counters.num_failed_files +=
+        run_report.num_failed_files =
             deletion_stats.candidate_entries.len() - deletion_stats.deleted_entries.len();
+        run_report.elapsed_time_millis = start_instant.elapsed().as_millis() as u64;
 
-        ctx.schedule_self_msg(RUN_INTERVAL, ()).await;
+        self.counters.num_deleted_files += run_report.num_deleted_files;
+        self.counters.num_deleted_bytes += run_report.num_deleted_bytes;
+        self.counters.num_failed_files += run_report.num_failed_files;
+        self.record_run_report(run_report);
+
+        ctx.schedule_self_msg(self.params.run_interval, ()).await;
         Ok(())
     }
 }
@@ -137,7 +247,7 @@ mod tests {
 
     use quickwit_actors::Universe;
     use quickwit_metastore::{
-        MockMetastore, SplitMetadata, SplitMetadataAndFooterOffsets, SplitState,
+        MetastoreError, MockMetastore, SplitMetadata, SplitMetadataAndFooterOffsets, SplitState,
     };
     use quickwit_storage::{MockStorage, StorageErrorKind};
 
@@ -174,6 +284,9 @@ mod tests {
         });
 
         let mut mock_metastore = MockMetastore::default();
+        mock_metastore
+            .expect_now_timestamp()
+            .returning(|| Ok(Utc::now().timestamp()));
         mock_metastore.expect_list_splits().times(2).returning(
             |index_id, split_state, _time_range, _tags| {
                 assert_eq!(index_id, "foo-index");
@@ -195,6 +308,9 @@ mod tests {
                 assert_eq!(split_ids, vec!["a"]);
                 Ok(())
             });
+        mock_metastore
+            .expect_list_leased_split_ids()
+            .returning(|_index_id| Ok(Vec::new()));
         mock_metastore
             .expect_delete_splits()
             .times(1)
@@ -231,6 +347,9 @@ mod tests {
         });
 
         let mut mock_metastore = MockMetastore::default();
+        mock_metastore
+            .expect_now_timestamp()
+            .returning(|| Ok(Utc::now().timestamp()));
         mock_metastore.expect_list_splits().times(4).returning(
             |index_id, split_state, _time_range, _tags| {
                 assert_eq!(index_id, "foo-index");
@@ -250,6 +369,9 @@ mod tests {
                 assert_eq!(split_ids, vec!["a"]);
                 Ok(())
             });
+        mock_metastore
+            .expect_list_leased_split_ids()
+            .returning(|_index_id| Ok(Vec::new()));
         mock_metastore
             .expect_delete_splits()
             .times(2)
@@ -289,4 +411,185 @@ mod tests {
         assert_eq!(state_after_initialization.num_deleted_bytes, 80);
         assert_eq!(state_after_initialization.num_failed_files, 0);
     }
+
+    #[tokio::test]
+    async fn test_garbage_collect_applies_retention_policy() {
+        quickwit_common::setup_logging_for_tests();
+        let foo_index = "foo-index";
+
+        let mut mock_storage = MockStorage::default();
+        mock_storage.expect_delete().times(1).returning(|path| {
+            assert_eq!(path, Path::new("expired.split"));
+            Ok(())
+        });
+
+        let now_timestamp = Utc::now().timestamp();
+        let mut mock_metastore = MockMetastore::default();
+        mock_metastore
+            .expect_now_timestamp()
+            .returning(|| Ok(Utc::now().timestamp()));
+        mock_metastore.expect_list_splits().times(4).returning(
+            move |index_id, split_state, _time_range, _tags| {
+                assert_eq!(index_id, "foo-index");
+                let splits = match split_state {
+                    SplitState::Published => {
+                        let mut expired_split = make_split("expired");
+                        expired_split.split_metadata.time_range =
+                            Some(now_timestamp - 7200..=now_timestamp - 3600);
+                        let mut recent_split = make_split("recent");
+                        recent_split.split_metadata.time_range =
+                            Some(now_timestamp - 60..=now_timestamp);
+                        vec![expired_split, recent_split, make_split("no-time-range")]
+                    }
+                    SplitState::Quarantined => Vec::new(),
+                    SplitState::Staged => Vec::new(),
+                    SplitState::ScheduledForDeletion => vec![make_split("expired")],
+                    _ => panic!(
+                        "only Published, Quarantined, Staged and ScheduledForDeletion expected."
+                    ),
+                };
+                Ok(splits)
+            },
+        );
+        mock_metastore
+            .expect_mark_splits_for_deletion()
+            .times(2)
+            .returning(|index_id, split_ids| {
+                assert_eq!(index_id, "foo-index");
+                assert!(split_ids.is_empty() || split_ids == vec!["expired"]);
+                Ok(())
+            });
+        mock_metastore
+            .expect_list_leased_split_ids()
+            .returning(|_index_id| Ok(Vec::new()));
+        mock_metastore
+            .expect_delete_splits()
+            .times(1)
+            .returning(|index_id, split_ids| {
+                assert_eq!(index_id, "foo-index");
+                assert_eq!(split_ids, vec!["expired"]);
+                Ok(())
+            });
+
+        let universe = Universe::new();
+        let garbage_collect_actor = GarbageCollector::with_params(
+            foo_index.to_string(),
+            Arc::new(mock_storage),
+            Arc::new(mock_metastore),
+            GarbageCollectorParams {
+                retention_period: Some(Duration::from_secs(60 * 30)),
+                ..Default::default()
+            },
+        );
+        let (_maibox, handler) = universe.spawn_actor(garbage_collect_actor).spawn_async();
+
+        let state_after_initialization = handler.process_pending_and_observe().await.state;
+        assert_eq!(state_after_initialization.num_passes, 1);
+        assert_eq!(state_after_initialization.num_deleted_files, 1);
+        assert_eq!(state_after_initialization.last_run_reports.len(), 1);
+        let run_report = &state_after_initialization.last_run_reports[0];
+        assert_eq!(run_report.num_expired_splits, 1);
+        assert_eq!(run_report.num_deleted_files, 1);
+        assert!(run_report.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_garbage_collect_records_failed_pass() {
+        quickwit_common::setup_logging_for_tests();
+        let foo_index = "foo-index";
+
+        let mock_storage = MockStorage::default();
+        let mut mock_metastore = MockMetastore::default();
+        mock_metastore
+            .expect_now_timestamp()
+            .returning(|| Ok(Utc::now().timestamp()));
+        mock_metastore.expect_list_splits().times(2).returning(
+            |_index_id, _split_state, _time_range, _tags| {
+                Err(MetastoreError::ConnectionError {
+                    message: "Metastore is unreachable".to_string(),
+                })
+            },
+        );
+
+        let universe = Universe::new();
+        let garbage_collect_actor = GarbageCollector::new(
+            foo_index.to_string(),
+            Arc::new(mock_storage),
+            Arc::new(mock_metastore),
+        );
+        let (_maibox, handler) = universe.spawn_actor(garbage_collect_actor).spawn_async();
+
+        let state_after_initialization = handler.process_pending_and_observe().await.state;
+        assert_eq!(state_after_initialization.num_passes, 1);
+        assert_eq!(state_after_initialization.last_run_reports.len(), 1);
+        assert!(state_after_initialization.last_run_reports[0]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("Metastore is unreachable"));
+
+        // The actor is still alive and retries at the next interval.
+        universe.simulate_time_shift(RUN_INTERVAL).await;
+        let state_after_retry = handler.process_pending_and_observe().await.state;
+        assert_eq!(state_after_retry.num_passes, 2);
+        assert_eq!(state_after_retry.last_run_reports.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_garbage_collect_skips_leased_splits() {
+        quickwit_common::setup_logging_for_tests();
+        let mut mock_storage = MockStorage::default();
+        mock_storage.expect_delete().times(1).returning(|path| {
+            assert_eq!(path, Path::new("unleased-split.split"));
+            Ok(())
+        });
+        let mut mock_metastore = MockMetastore::default();
+        mock_metastore
+            .expect_now_timestamp()
+            .returning(|| Ok(Utc::now().timestamp()));
+        mock_metastore.expect_list_splits().times(2).returning(
+            |_index_id, split_state, _time_range, _tags| {
+                let splits = match split_state {
+                    SplitState::Staged => Vec::new(),
+                    SplitState::ScheduledForDeletion => vec![
+                        make_split("leased-split"),
+                        make_split("remotely-leased-split"),
+                        make_split("unleased-split"),
+                    ],
+                    _ => panic!("only Staged and ScheduledForDeletion expected."),
+                };
+                Ok(splits)
+            },
+        );
+        mock_metastore
+            .expect_mark_splits_for_deletion()
+            .times(1)
+            .returning(|_index_id, _split_ids| Ok(()));
+        // The split searched by another node is leased through the metastore.
+        mock_metastore
+            .expect_list_leased_split_ids()
+            .times(1)
+            .returning(|_index_id| Ok(vec!["remotely-leased-split".to_string()]));
+        mock_metastore
+            .expect_delete_splits()
+            .times(1)
+            .returning(|_index_id, split_ids| {
+                assert_eq!(split_ids, vec!["unleased-split"]);
+                Ok(())
+            });
+        let _split_lease = quickwit_common::global_split_leases()
+            .acquire(vec!["leased-split"], Duration::from_secs(60));
+
+        let universe = Universe::new();
+        let garbage_collect_actor = GarbageCollector::new(
+            "foo-index".to_string(),
+            Arc::new(mock_storage),
+            Arc::new(mock_metastore),
+        );
+        let (_maibox, handler) = universe.spawn_actor(garbage_collect_actor).spawn_async();
+        let state_after_initialization = handler.process_pending_and_observe().await.state;
+        assert_eq!(state_after_initialization.num_passes, 1);
+        assert_eq!(state_after_initialization.num_deleted_files, 1);
+        assert_eq!(state_after_initialization.num_failed_files, 0);
+    }
 }
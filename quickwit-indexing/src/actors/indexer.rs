@@ -17,9 +17,11 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::convert::TryInto;
 use std::io;
 use std::ops::RangeInclusive;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
 use byte_unit::Byte;
@@ -29,23 +31,38 @@ use quickwit_actors::{
 };
 use quickwit_index_config::{IndexConfig, SortBy};
 use tantivy::schema::{Field, Value};
-use tantivy::{Document, IndexBuilder, IndexSettings, IndexSortByField};
+use tantivy::{Document, IndexBuilder, IndexReader, IndexSettings, IndexSortByField, ReloadPolicy};
 use tracing::{info, warn};
 
-use crate::models::{CommitPolicy, IndexedSplit, IndexerMessage, RawDocBatch, ScratchDirectory};
+use crate::index_split_id;
+use crate::models::{
+    CommitPolicy, DiskGuardrails, DocSizeLimit, DocSizeOutcome, IndexedSplit, IndexerMessage,
+    IngestRate, RawDocBatch, RealtimeParams, ScratchDirectory,
+};
+
+/// Interval at which the disk guardrails are checked again while they are breached.
+///
+/// It must be shorter than the heartbeat, as the indexer records its progress in between.
+const DISK_GUARDRAILS_CHECK_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(Clone, Default, Debug, Eq, PartialEq)]
 pub struct IndexerCounters {
     /// Overall number of documents received, partitionned
-    /// into 3 categories:
+    /// into 4 categories:
     /// - number docs that did not parse correctly.
     /// - number docs missing a timestamp (if the index has no timestamp,
     /// then this counter is 0)
+    /// - number docs larger than the maximum document size that were dropped.
     /// - number of valid docs.
     pub num_parse_errors: u64,
     pub num_missing_timestamp: u64,
+    pub num_oversized_docs: u64,
     pub num_valid_docs: u64,
 
+    /// Number of docs larger than the maximum document size whose string values were
+    /// truncated. These docs are then counted in one of the categories above.
+    pub num_truncated_docs: u64,
+
     /// Number of splits that were emitted by the indexer.
     pub num_splits_emitted: u64,
 
@@ -63,19 +80,20 @@ pub struct IndexerCounters {
 impl IndexerCounters {
     /// Returns the overall number of docs that went through the indexer (valid or not).
     pub fn num_processed_docs(&self) -> u64 {
-        self.num_valid_docs + self.num_parse_errors + self.num_missing_timestamp
+        self.num_valid_docs + self.num_invalid_docs()
     }
 
     /// Returns the overall number of docs that were sent to the indexer but were invalid.
     /// (For instance, because they were missing a required field or because their because
     /// their format was invalid)
     pub fn num_invalid_docs(&self) -> u64 {
-        self.num_parse_errors + self.num_missing_timestamp
+        self.num_parse_errors + self.num_missing_timestamp + self.num_oversized_docs
     }
 }
 
 struct IndexerState {
     index_id: String,
+    index_create_timestamp: i64,
     index_config: Arc<dyn IndexConfig>,
     indexer_params: IndexerParams,
     timestamp_field_opt: Option<Field>,
@@ -91,7 +109,43 @@ enum PrepareDocumentOutcome {
 }
 
 impl IndexerState {
-    fn create_indexed_split(&self) -> anyhow::Result<IndexedSplit> {
+    /// Blocks until starting a new split does not break the disk guardrails, e.g. because
+    /// the splits being uploaded have freed some disk space.
+    ///
+    /// Blocking the indexer makes the source wait as well, rather than failing the pipeline.
+    fn wait_for_disk_guardrails(
+        &self,
+        ctx: &ActorContext<IndexerMessage>,
+    ) -> Result<(), ActorExitStatus> {
+        let scratch_directory_path = self.indexer_params.scratch_directory.path();
+        let mut is_waiting = false;
+        loop {
+            match self
+                .indexer_params
+                .disk_guardrails
+                .check(scratch_directory_path)
+            {
+                Ok(()) => {
+                    if is_waiting {
+                        info!(index_id=%self.index_id, "disk-guardrails-cleared");
+                    }
+                    return Ok(());
+                }
+                Err(error) if !is_waiting => {
+                    warn!(index_id=%self.index_id, error=?error, "Refusing to start a new split until the disk guardrails are cleared.");
+                    is_waiting = true;
+                }
+                Err(_) => {}
+            }
+            if ctx.kill_switch().is_dead() {
+                return Err(ActorExitStatus::Killed);
+            }
+            ctx.record_progress();
+            std::thread::sleep(DISK_GUARDRAILS_CHECK_INTERVAL);
+        }
+    }
+
+    fn create_indexed_split(&self, split_id_opt: Option<String>) -> anyhow::Result<IndexedSplit> {
         let schema = self.index_config.schema();
         let mut index_settings = IndexSettings::default();
         let sort_by_field = match self.index_config.sort_by() {
@@ -103,8 +157,19 @@ impl IndexerState {
         };
         index_settings.sort_by_field = sort_by_field;
         let index_builder = IndexBuilder::new().settings(index_settings).schema(schema);
-        let indexed_split =
-            IndexedSplit::new_in_dir(self.index_id.clone(), &self.indexer_params, index_builder)?;
+        let split_id_opt = split_id_opt.map(|source_split_id| {
+            index_split_id(
+                &self.index_id,
+                self.index_create_timestamp,
+                &source_split_id,
+            )
+        });
+        let indexed_split = IndexedSplit::new_in_dir(
+            self.index_id.clone(),
+            split_id_opt,
+            &self.indexer_params,
+            index_builder,
+        )?;
         info!(split_id=%indexed_split.split_id, "new-split");
         Ok(indexed_split)
     }
@@ -112,21 +177,21 @@ impl IndexerState {
     /// Returns the current_indexed_split. If this is the first message, then
     /// the indexed_split does not exist yet.
     ///
-    /// This function will then create it, and can hence return an Error.
+    /// This function will then create it, with the split ID provided by the source
+    /// if any, and can hence return an Error.
     fn get_or_create_current_indexed_split<'a>(
         &self,
         current_split_opt: &'a mut Option<IndexedSplit>,
+        split_id_opt: Option<String>,
+        commit_timeout: Duration,
         ctx: &ActorContext<IndexerMessage>,
     ) -> anyhow::Result<&'a mut IndexedSplit> {
         if current_split_opt.is_none() {
-            let new_indexed_split = self.create_indexed_split()?;
-            let commit_timeout = IndexerMessage::CommitTimeout {
+            let new_indexed_split = self.create_indexed_split(split_id_opt)?;
+            let commit_timeout_message = IndexerMessage::CommitTimeout {
                 split_id: new_indexed_split.split_id.clone(),
             };
-            ctx.schedule_self_msg_blocking(
-                self.indexer_params.commit_policy.timeout,
-                commit_timeout,
-            );
+            ctx.schedule_self_msg_blocking(commit_timeout, commit_timeout_message);
             *current_split_opt = Some(new_indexed_split);
         }
         let current_index_split = current_split_opt.as_mut().with_context(|| {
@@ -174,16 +239,43 @@ impl IndexerState {
         batch: RawDocBatch,
         current_split_opt: &mut Option<IndexedSplit>,
         counters: &mut IndexerCounters,
+        commit_timeout: Duration,
         ctx: &ActorContext<IndexerMessage>,
     ) -> Result<(), ActorExitStatus> {
-        let indexed_split = self.get_or_create_current_indexed_split(current_split_opt, ctx)?;
+        if current_split_opt.is_none() {
+            self.wait_for_disk_guardrails(ctx)?;
+        }
+        let indexed_split = self.get_or_create_current_indexed_split(
+            current_split_opt,
+            batch.split_id_opt,
+            commit_timeout,
+            ctx,
+        )?;
         indexed_split
             .checkpoint_delta
             .extend(batch.checkpoint_delta)
             .with_context(|| "Batch delta does not follow indexer checkpoint")?;
+        let num_docs_before_batch = indexed_split.num_docs;
+        counters.num_oversized_docs += batch.num_skipped_docs;
         for doc_json in batch.docs {
             counters.overall_num_bytes += doc_json.len() as u64;
             indexed_split.docs_size_in_bytes += doc_json.len() as u64;
+            let doc_size_outcome = {
+                let _protect_zone = ctx.protect_zone();
+                self.indexer_params.doc_size_limit.apply(doc_json)
+            };
+            let doc_json = match doc_size_outcome {
+                DocSizeOutcome::Accepted(doc_json) => doc_json,
+                DocSizeOutcome::Truncated(doc_json) => {
+                    counters.num_truncated_docs += 1;
+                    doc_json
+                }
+                DocSizeOutcome::Oversized => {
+                    counters.num_oversized_docs += 1;
+                    ctx.record_progress();
+                    continue;
+                }
+            };
             let prepared_doc = {
                 let _protect_zone = ctx.protect_zone();
                 self.prepare_document(&doc_json)
@@ -211,6 +303,13 @@ impl IndexerState {
             }
             ctx.record_progress();
         }
+        let num_docs_in_batch = indexed_split.num_docs - num_docs_before_batch;
+        if num_docs_in_batch > 0 {
+            *indexed_split
+                .num_docs_per_ingest_timestamp
+                .entry(batch.received_timestamp)
+                .or_default() += num_docs_in_batch;
+        }
         Ok(())
     }
 }
@@ -220,6 +319,8 @@ pub struct Indexer {
     packager_mailbox: Mailbox<IndexedSplit>,
     current_split_opt: Option<IndexedSplit>,
     counters: IndexerCounters,
+    last_realtime_refresh: Instant,
+    ingest_rate: IngestRate,
 }
 
 impl Actor for Indexer {
@@ -251,6 +352,11 @@ pub struct IndexerParams {
     pub scratch_directory: ScratchDirectory,
     pub heap_size: Byte,
     pub commit_policy: CommitPolicy,
+    pub disk_guardrails: DiskGuardrails,
+    pub doc_size_limit: DocSizeLimit,
+    /// If set, the documents of the split being indexed are searchable before it is
+    /// published.
+    pub realtime_opt: Option<RealtimeParams>,
 }
 
 impl IndexerParams {
@@ -260,6 +366,9 @@ impl IndexerParams {
             scratch_directory,
             heap_size: Byte::from_str("30MB").unwrap(),
             commit_policy: Default::default(),
+            disk_guardrails: Default::default(),
+            doc_size_limit: Default::default(),
+            realtime_opt: None,
         })
     }
 }
@@ -290,7 +399,18 @@ impl SyncActor for Indexer {
             ActorExitStatus::DownstreamClosed
             | ActorExitStatus::Killed
             | ActorExitStatus::Failure(_)
-            | ActorExitStatus::Panicked => return Ok(()),
+            | ActorExitStatus::Panicked => {
+                // The split will never be published.
+                if let (Some(realtime_params), Some(indexed_split)) = (
+                    self.indexer_state.indexer_params.realtime_opt.as_ref(),
+                    self.current_split_opt.as_ref(),
+                ) {
+                    realtime_params
+                        .splits
+                        .remove(&indexed_split.index_id, &[&indexed_split.split_id]);
+                }
+                return Ok(());
+            }
             ActorExitStatus::Quit | ActorExitStatus::Success => {
                 self.send_to_packager(CommitTrigger::NoMoreDocs, ctx)?;
             }
@@ -311,6 +431,7 @@ impl Indexer {
     // IndexerParams object.
     pub fn try_new(
         index_id: String,
+        index_create_timestamp: i64,
         index_config: Arc<dyn IndexConfig>,
         indexer_params: IndexerParams,
         packager_mailbox: Mailbox<IndexedSplit>,
@@ -320,6 +441,7 @@ impl Indexer {
         Ok(Indexer {
             indexer_state: IndexerState {
                 index_id,
+                index_create_timestamp,
                 index_config,
                 indexer_params,
                 timestamp_field_opt,
@@ -327,6 +449,8 @@ impl Indexer {
             packager_mailbox,
             current_split_opt: None,
             counters: IndexerCounters::default(),
+            last_realtime_refresh: Instant::now(),
+            ingest_rate: IngestRate::default(),
         })
     }
 
@@ -336,25 +460,56 @@ impl Indexer {
         ctx: &ActorContext<IndexerMessage>,
     ) -> Result<(), ActorExitStatus> {
         fail_point!("indexer:batch:before");
+        let commit_policy = self.indexer_state.indexer_params.commit_policy;
         self.indexer_state.process_batch(
             batch,
             &mut self.current_split_opt,
             &mut self.counters,
+            commit_policy.commit_timeout(&self.ingest_rate),
             ctx,
         )?;
-        if self.counters.num_docs_in_split
-            >= self
-                .indexer_state
-                .indexer_params
-                .commit_policy
-                .num_docs_threshold
-        {
+        self.refresh_realtime_split(ctx)?;
+        if self.counters.num_docs_in_split >= commit_policy.effective_num_docs_threshold() {
             self.send_to_packager(CommitTrigger::NumDocsLimit, ctx)?;
         }
         fail_point!("indexer:batch:after");
         Ok(())
     }
 
+    /// Commits the documents of the current split and places a searcher over them in the
+    /// realtime splits, at most once per refresh interval.
+    fn refresh_realtime_split(&mut self, ctx: &ActorContext<IndexerMessage>) -> anyhow::Result<()> {
+        let realtime_params = if let Some(realtime_params) =
+            self.indexer_state.indexer_params.realtime_opt.as_ref()
+        {
+            realtime_params
+        } else {
+            return Ok(());
+        };
+        let indexed_split = if let Some(indexed_split) = self.current_split_opt.as_mut() {
+            indexed_split
+        } else {
+            return Ok(());
+        };
+        if self.last_realtime_refresh.elapsed() < realtime_params.refresh_interval {
+            return Ok(());
+        }
+        let _protect_guard = ctx.protect_zone();
+        indexed_split.index_writer.commit()?;
+        let index_reader: IndexReader = indexed_split
+            .index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        realtime_params.splits.update(
+            &indexed_split.index_id,
+            &indexed_split.split_id,
+            index_reader.searcher(),
+        );
+        self.last_realtime_refresh = Instant::now();
+        Ok(())
+    }
+
     fn process_commit_timeout(
         &mut self,
         split_id: &str,
@@ -383,6 +538,10 @@ impl Indexer {
             return Ok(());
         };
         info!(commit_trigger=?commit_trigger, index=?indexed_split.index_id, split=?indexed_split.split_id,"send-to-packager");
+        self.ingest_rate.record_split(
+            indexed_split.num_docs,
+            indexed_split.split_date_of_birth.elapsed(),
+        );
         ctx.send_message_blocking(&self.packager_mailbox, indexed_split)?;
         self.counters.num_docs_in_split = 0;
         self.counters.num_splits_emitted += 1;
@@ -396,13 +555,18 @@ mod tests {
     use std::time::Duration;
 
     use byte_unit::Byte;
-    use quickwit_actors::{create_test_mailbox, Universe};
+    use quickwit_actors::{create_test_mailbox, ActorExitStatus, ActorState, Universe};
+    use quickwit_common::RealtimeSplits;
     use quickwit_metastore::checkpoint::CheckpointDelta;
 
     use super::Indexer;
     use crate::actors::indexer::{record_timestamp, IndexerCounters};
     use crate::actors::IndexerParams;
-    use crate::models::{CommitPolicy, RawDocBatch, ScratchDirectory};
+    use crate::index_split_id;
+    use crate::models::{
+        AdaptiveCommitPolicy, CommitPolicy, DiskGuardrails, DocSizeLimit, OversizedDocPolicy,
+        RawDocBatch, RealtimeParams, ScratchDirectory,
+    };
 
     #[test]
     fn test_record_timestamp() {
@@ -423,14 +587,19 @@ mod tests {
             commit_policy: CommitPolicy {
                 timeout: Duration::from_secs(60),
                 num_docs_threshold: 3,
+                adaptive_opt: None,
             },
             scratch_directory: ScratchDirectory::try_new_temp()?,
             heap_size: Byte::from_str("30MB").unwrap(),
+            disk_guardrails: DiskGuardrails::default(),
+            doc_size_limit: DocSizeLimit::default(),
+            realtime_opt: None,
         };
         let (mailbox, inbox) = create_test_mailbox();
         let index_config = Arc::new(quickwit_index_config::default_config_for_tests());
         let indexer = Indexer::try_new(
             "test-index".to_string(),
+            0,
             index_config,
             indexer_params,
             mailbox,
@@ -447,6 +616,9 @@ mod tests {
                         "{".to_string(),                    // invalid json
                     ],
                     checkpoint_delta: CheckpointDelta::from(0..4),
+                    split_id_opt: None,
+                    received_timestamp: 0,
+                    num_skipped_docs: 0,
                 }
                 .into(),
             )
@@ -457,7 +629,9 @@ mod tests {
             IndexerCounters {
                 num_parse_errors: 1,
                 num_missing_timestamp: 1,
+                num_oversized_docs: 0,
                 num_valid_docs: 2,
+                num_truncated_docs: 0,
                 num_splits_emitted: 0,
                 num_docs_in_split: 2, //< we have not reached the commit limit yet.
                 overall_num_bytes: 103
@@ -469,6 +643,9 @@ mod tests {
                 RawDocBatch {
                     docs: vec![r#"{"body": "happy3", "timestamp": 1628837062}"#.to_string()],
                     checkpoint_delta: CheckpointDelta::from(4..5),
+                    split_id_opt: None,
+                    received_timestamp: 0,
+                    num_skipped_docs: 0,
                 }
                 .into(),
             )
@@ -479,7 +656,9 @@ mod tests {
             IndexerCounters {
                 num_parse_errors: 1,
                 num_missing_timestamp: 1,
+                num_oversized_docs: 0,
                 num_valid_docs: 3,
+                num_truncated_docs: 0,
                 num_splits_emitted: 1,
                 num_docs_in_split: 0, //< the num docs in split counter has been reset.
                 overall_num_bytes: 146
@@ -495,6 +674,102 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_indexer_adaptive_commit_policy() -> anyhow::Result<()> {
+        let universe = Universe::new();
+        let indexer_params = IndexerParams {
+            commit_policy: CommitPolicy {
+                timeout: Duration::from_secs(60),
+                num_docs_threshold: 10_000_000,
+                adaptive_opt: Some(AdaptiveCommitPolicy::new(2)),
+            },
+            scratch_directory: ScratchDirectory::try_new_temp()?,
+            heap_size: Byte::from_str("30MB").unwrap(),
+            disk_guardrails: DiskGuardrails::default(),
+            doc_size_limit: DocSizeLimit::default(),
+            realtime_opt: None,
+        };
+        let (mailbox, inbox) = create_test_mailbox();
+        let index_config = Arc::new(quickwit_index_config::default_config_for_tests());
+        let indexer = Indexer::try_new(
+            "test-index".to_string(),
+            0,
+            index_config,
+            indexer_params,
+            mailbox,
+        )?;
+        let (indexer_mailbox, indexer_handle) = universe.spawn_actor(indexer).spawn_sync();
+        universe
+            .send_message(
+                &indexer_mailbox,
+                RawDocBatch {
+                    docs: vec![
+                        r#"{"body": "happy", "timestamp": 1628837062}"#.to_string(),
+                        r#"{"body": "happy2", "timestamp": 1628837062}"#.to_string(),
+                    ],
+                    checkpoint_delta: CheckpointDelta::from(0..2),
+                    split_id_opt: None,
+                    received_timestamp: 0,
+                    num_skipped_docs: 0,
+                }
+                .into(),
+            )
+            .await?;
+        let indexer_counters = indexer_handle.process_pending_and_observe().await.state;
+        assert_eq!(indexer_counters.num_splits_emitted, 1);
+        let output_messages = inbox.drain_available_message_for_test();
+        assert_eq!(output_messages.len(), 1);
+        assert_eq!(output_messages[0].num_docs, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_indexer_realtime() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let universe = Universe::new();
+        let realtime_splits = RealtimeSplits::default();
+        let indexer_params = IndexerParams {
+            realtime_opt: Some(RealtimeParams {
+                splits: realtime_splits.clone(),
+                refresh_interval: Duration::from_secs(0),
+            }),
+            ..IndexerParams::for_test()?
+        };
+        let (mailbox, inbox) = create_test_mailbox();
+        let index_config = Arc::new(quickwit_index_config::default_config_for_tests());
+        let indexer = Indexer::try_new(
+            "test-index".to_string(),
+            0,
+            index_config,
+            indexer_params,
+            mailbox,
+        )?;
+        let (indexer_mailbox, indexer_handle) = universe.spawn_actor(indexer).spawn_sync();
+        universe
+            .send_message(
+                &indexer_mailbox,
+                RawDocBatch {
+                    docs: vec![
+                        r#"{"body": "happy", "timestamp": 1628837062}"#.to_string(),
+                        r#"{"body": "happy2", "timestamp": 1628837062}"#.to_string(),
+                    ],
+                    checkpoint_delta: CheckpointDelta::from(0..2),
+                    split_id_opt: None,
+                    received_timestamp: 0,
+                    num_skipped_docs: 0,
+                }
+                .into(),
+            )
+            .await?;
+        indexer_handle.process_pending_and_observe().await;
+        // The split is not sent to the packager, but its documents are searchable.
+        assert!(inbox.drain_available_message_for_test().is_empty());
+        let searchers = realtime_splits.searchers("test-index");
+        assert_eq!(searchers.len(), 1);
+        assert_eq!(searchers[0].1.num_docs(), 2);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_indexer_timeout() -> anyhow::Result<()> {
         quickwit_common::setup_logging_for_tests();
@@ -503,14 +778,19 @@ mod tests {
             commit_policy: CommitPolicy {
                 timeout: Duration::from_secs(60),
                 num_docs_threshold: 10_000_000,
+                adaptive_opt: None,
             },
             scratch_directory: ScratchDirectory::try_new_temp()?,
             heap_size: Byte::from_str("30MB").unwrap(),
+            disk_guardrails: DiskGuardrails::default(),
+            doc_size_limit: DocSizeLimit::default(),
+            realtime_opt: None,
         };
         let (mailbox, inbox) = create_test_mailbox();
         let index_config = Arc::new(quickwit_index_config::default_config_for_tests());
         let indexer = Indexer::try_new(
             "test-index".to_string(),
+            0,
             index_config,
             indexer_params,
             mailbox,
@@ -522,6 +802,9 @@ mod tests {
                 RawDocBatch {
                     docs: vec![r#"{"body": "happy", "timestamp": 1628837062}"#.to_string()],
                     checkpoint_delta: CheckpointDelta::from(0..1),
+                    split_id_opt: None,
+                    received_timestamp: 0,
+                    num_skipped_docs: 0,
                 }
                 .into(),
             )
@@ -532,7 +815,9 @@ mod tests {
             IndexerCounters {
                 num_parse_errors: 0,
                 num_missing_timestamp: 0,
+                num_oversized_docs: 0,
                 num_valid_docs: 1,
+                num_truncated_docs: 0,
                 num_splits_emitted: 0,
                 num_docs_in_split: 1,
                 overall_num_bytes: 42
@@ -545,7 +830,9 @@ mod tests {
             IndexerCounters {
                 num_parse_errors: 0,
                 num_missing_timestamp: 0,
+                num_oversized_docs: 0,
                 num_valid_docs: 1,
+                num_truncated_docs: 0,
                 num_splits_emitted: 1,
                 num_docs_in_split: 0,
                 overall_num_bytes: 42
@@ -566,6 +853,7 @@ mod tests {
         let indexer_params = IndexerParams::for_test()?;
         let indexer = Indexer::try_new(
             "test-index".to_string(),
+            0,
             index_config,
             indexer_params,
             mailbox,
@@ -577,6 +865,9 @@ mod tests {
                 RawDocBatch {
                     docs: vec![r#"{"body": "happy", "timestamp": 1628837062}"#.to_string()],
                     checkpoint_delta: CheckpointDelta::from(0..1),
+                    split_id_opt: None,
+                    received_timestamp: 0,
+                    num_skipped_docs: 0,
                 }
                 .into(),
             )
@@ -589,7 +880,9 @@ mod tests {
             IndexerCounters {
                 num_parse_errors: 0,
                 num_missing_timestamp: 0,
+                num_oversized_docs: 0,
                 num_valid_docs: 1,
+                num_truncated_docs: 0,
                 num_splits_emitted: 1,
                 num_docs_in_split: 0,
                 overall_num_bytes: 42
@@ -600,4 +893,158 @@ mod tests {
         assert_eq!(output_messages[0].num_docs, 1);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_indexer_split_id_from_source() -> anyhow::Result<()> {
+        let universe = Universe::new();
+        let (mailbox, inbox) = create_test_mailbox();
+        let index_config = Arc::new(quickwit_index_config::default_config_for_tests());
+        let indexer_params = IndexerParams::for_test()?;
+        let indexer = Indexer::try_new(
+            "test-index".to_string(),
+            0,
+            index_config,
+            indexer_params,
+            mailbox,
+        )?;
+        let (indexer_mailbox, indexer_handle) = universe.spawn_actor(indexer).spawn_sync();
+        universe
+            .send_message(
+                &indexer_mailbox,
+                RawDocBatch {
+                    docs: vec![r#"{"body": "happy", "timestamp": 1628837062}"#.to_string()],
+                    checkpoint_delta: CheckpointDelta::from(0..1),
+                    split_id_opt: Some("split-from-source".to_string()),
+                    received_timestamp: 0,
+                    num_skipped_docs: 0,
+                }
+                .into(),
+            )
+            .await?;
+        // The split has already been started: this split ID is ignored.
+        universe
+            .send_message(
+                &indexer_mailbox,
+                RawDocBatch {
+                    docs: vec![r#"{"body": "happy2", "timestamp": 1628837062}"#.to_string()],
+                    checkpoint_delta: CheckpointDelta::from(1..2),
+                    split_id_opt: Some("other-split-from-source".to_string()),
+                    received_timestamp: 0,
+                    num_skipped_docs: 0,
+                }
+                .into(),
+            )
+            .await?;
+        universe.send_exit_with_success(&indexer_mailbox).await?;
+        let (exit_status, _indexer_counters) = indexer_handle.join().await;
+        assert!(exit_status.is_success());
+        let output_messages = inbox.drain_available_message_for_test();
+        assert_eq!(output_messages.len(), 1);
+        assert_eq!(
+            output_messages[0].split_id,
+            index_split_id("test-index", 0, "split-from-source")
+        );
+        assert_eq!(output_messages[0].num_docs, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_indexer_disk_guardrails() -> anyhow::Result<()> {
+        let universe = Universe::new();
+        let (mailbox, inbox) = create_test_mailbox();
+        let index_config = Arc::new(quickwit_index_config::default_config_for_tests());
+        let indexer_params = IndexerParams {
+            disk_guardrails: DiskGuardrails {
+                max_disk_usage: None,
+                min_free_disk_space: Some(Byte::from_bytes(u64::MAX as u128)),
+            },
+            ..IndexerParams::for_test()?
+        };
+        let indexer = Indexer::try_new(
+            "test-index".to_string(),
+            0,
+            index_config,
+            indexer_params,
+            mailbox,
+        )?;
+        let (indexer_mailbox, indexer_handle) = universe.spawn_actor(indexer).spawn_sync();
+        universe
+            .send_message(
+                &indexer_mailbox,
+                RawDocBatch {
+                    docs: vec![r#"{"body": "happy", "timestamp": 1628837062}"#.to_string()],
+                    checkpoint_delta: CheckpointDelta::from(0..1),
+                    split_id_opt: None,
+                    received_timestamp: 0,
+                    num_skipped_docs: 0,
+                }
+                .into(),
+            )
+            .await?;
+        // The indexer waits for the disk guardrails to be cleared instead of failing.
+        tokio::time::sleep(Duration::from_millis(1_200)).await;
+        assert_eq!(indexer_handle.state(), ActorState::Running);
+        assert_eq!(indexer_handle.last_observation().num_valid_docs, 0);
+        let (exit_status, _indexer_counters) = indexer_handle.kill().await;
+        assert!(matches!(exit_status, ActorExitStatus::Killed));
+        assert!(inbox.drain_available_message_for_test().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_indexer_doc_size_limit() -> anyhow::Result<()> {
+        let universe = Universe::new();
+        let (mailbox, _inbox) = create_test_mailbox();
+        let index_config = Arc::new(quickwit_index_config::default_config_for_tests());
+        let indexer_params = IndexerParams {
+            doc_size_limit: DocSizeLimit {
+                max_doc_size: Some(Byte::from_bytes(50)),
+                oversized_doc_policy: OversizedDocPolicy::Truncate,
+            },
+            ..IndexerParams::for_test()?
+        };
+        let indexer = Indexer::try_new(
+            "test-index".to_string(),
+            0,
+            index_config,
+            indexer_params,
+            mailbox,
+        )?;
+        let (indexer_mailbox, indexer_handle) = universe.spawn_actor(indexer).spawn_sync();
+        universe
+            .send_message(
+                &indexer_mailbox,
+                RawDocBatch {
+                    docs: vec![
+                        r#"{"body": "happy", "timestamp": 1628837062}"#.to_string(), // ok
+                        r#"{"body": "happy happy happy happy", "timestamp": 1628837062}"#
+                            .to_string(), // truncated
+                        r#"{"body": "happy", "timestamp": 1628837062, "padding": 1628837062}"#
+                            .to_string(), // oversized
+                    ],
+                    checkpoint_delta: CheckpointDelta::from(0..3),
+                    split_id_opt: None,
+                    received_timestamp: 0,
+                    num_skipped_docs: 0,
+                }
+                .into(),
+            )
+            .await?;
+        let indexer_counters = indexer_handle.process_pending_and_observe().await.state;
+        assert_eq!(
+            indexer_counters,
+            IndexerCounters {
+                num_parse_errors: 0,
+                num_missing_timestamp: 0,
+                num_oversized_docs: 1,
+                num_valid_docs: 2,
+                num_truncated_docs: 1,
+                num_splits_emitted: 0,
+                num_docs_in_split: 2,
+                overall_num_bytes: 167
+            }
+        );
+        assert_eq!(indexer_counters.num_invalid_docs(), 1);
+        Ok(())
+    }
 }
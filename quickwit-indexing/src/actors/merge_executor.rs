@@ -17,6 +17,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
 use std::ops::RangeInclusive;
 use std::path::Path;
 use std::time::Instant;
@@ -217,6 +218,8 @@ impl MergeExecutor {
             docs_size_in_bytes,
             // start_time is not very interesting here.
             split_date_of_birth: Instant::now(),
+            // The documents of the merged splits are searchable already.
+            num_docs_per_ingest_timestamp: BTreeMap::new(),
             checkpoint_delta: CheckpointDelta::default(), //< TODO fixme
             index: merged_index,
             index_writer,
@@ -273,10 +276,11 @@ mod tests {
         let downloaded_splits_directory = merge_scratch_directory.temp_child()?;
         let storage = test_index_builder.index_storage(index_id)?;
         for split in &splits {
-            let split_filename = split_file(&split.split_id);
-            let dest_filepath = downloaded_splits_directory.path().join(&split_filename);
+            let dest_filepath = downloaded_splits_directory
+                .path()
+                .join(split_file(&split.split_id));
             storage
-                .copy_to_file(Path::new(&split_filename), &dest_filepath)
+                .copy_to_file(Path::new(&split.split_file()), &dest_filepath)
                 .await?;
         }
         let merge_scratch = MergeScratch {
@@ -158,6 +158,11 @@ mod tests {
             split_state: SplitState::Published,
             update_timestamp,
             tags,
+            index_config_version: 0,
+            split_file_path: None,
+            split_part_num_bytes: None,
+            ingest_timestamps: None,
+            last_verification: None,
         }
     }
 
@@ -233,6 +238,11 @@ mod tests {
             time_range: Some(time_range),
             update_timestamp: 0,
             tags: Default::default(),
+            index_config_version: 0,
+            split_file_path: None,
+            split_part_num_bytes: None,
+            ingest_timestamps: None,
+            last_verification: None,
         }
     }
 
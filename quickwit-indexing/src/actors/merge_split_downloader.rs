@@ -25,7 +25,7 @@ use async_trait::async_trait;
 use quickwit_actors::{Actor, ActorContext, AsyncActor, Mailbox, QueueCapacity};
 use quickwit_common::split_file;
 use quickwit_metastore::SplitMetadata;
-use quickwit_storage::Storage;
+use quickwit_storage::{multipart_file_storage, Storage};
 use tracing::{debug, info};
 
 use crate::merge_policy::MergeOperation;
@@ -90,14 +90,17 @@ impl MergeSplitDownloader {
         ctx: &ActorContext<MergeOperation>,
     ) -> anyhow::Result<()> {
         // we download all of the split files in the scratch directory.
+        // Whatever their path in the index storage, they are flattened to `<split_id>.split`.
         for split in splits {
-            let split_filename = split_file(&split.split_id);
+            let dest_path = download_directory.join(split_file(&split.split_id));
+            let split_filename = split.split_file();
             let split_file = Path::new(&split_filename);
-            let dest_path = download_directory.join(split_file);
             let _protect_guard = ctx.protect_zone();
             let start_time = Instant::now();
             debug!(split_file=?split_file, dest_path=?dest_path, "download-file");
-            self.storage.copy_to_file(split_file, &dest_path).await?;
+            multipart_file_storage(self.storage.clone(), split.split_part_num_bytes)
+                .copy_to_file(split_file, &dest_path)
+                .await?;
             let elapsed = start_time.elapsed();
             debug!(split_file=?split_file, elapsed=?elapsed, "download-file-end");
         }
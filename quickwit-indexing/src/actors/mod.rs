@@ -23,20 +23,27 @@ mod garbage_collector;
 mod indexer;
 mod packager;
 mod publisher;
+mod router;
+mod split_verifier;
 mod uploader;
 
 pub use pipeline_supervisor::{
-    IndexingPipelineHandler, IndexingPipelineParams, IndexingPipelineSupervisor,
+    IndexPipelineHandler, IndexingPipelineHandler, IndexingPipelineParams,
+    IndexingPipelineSupervisor, SourceShardingParams,
 };
 mod merge_executor;
 mod merge_planner;
 mod merge_split_downloader;
 
-pub use self::garbage_collector::{GarbageCollector, GarbageCollectorCounters};
+pub use self::garbage_collector::{
+    GarbageCollectionRunReport, GarbageCollector, GarbageCollectorCounters, GarbageCollectorParams,
+};
 pub use self::indexer::{Indexer, IndexerCounters, IndexerParams};
 pub use self::merge_executor::MergeExecutor;
 pub use self::merge_planner::MergePlanner;
 pub use self::merge_split_downloader::MergeSplitDownloader;
 pub use self::packager::Packager;
 pub use self::publisher::{Publisher, PublisherCounters};
+pub use self::router::{Router, RouterTarget};
+pub use self::split_verifier::{SplitVerifier, SplitVerifierCounters, SplitVerifierParams};
 pub use self::uploader::{Uploader, UploaderCounters};
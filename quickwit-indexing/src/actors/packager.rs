@@ -27,11 +27,12 @@ use std::path::{Path, PathBuf};
 use anyhow::Context;
 use fail::fail_point;
 use quickwit_actors::{Actor, ActorContext, Mailbox, QueueCapacity, SyncActor};
-use quickwit_directories::write_hotcache;
+use quickwit_directories::{write_hotcache, HotcacheOptions, SplitStats, SPLIT_STATS_FILENAME};
+use quickwit_metastore::IngestTimestamps;
 use quickwit_storage::{BundleStorageBuilder, BUNDLE_FILENAME};
 use tantivy::common::CountingWriter;
 use tantivy::schema::Field;
-use tantivy::{ReloadPolicy, SegmentId, SegmentMeta};
+use tantivy::{IndexReader, ReloadPolicy, SegmentId, SegmentMeta};
 use tracing::*;
 
 use crate::models::{IndexedSplit, MergePlannerMessage, PackagedSplit, ScratchDirectory};
@@ -42,6 +43,7 @@ use crate::models::{IndexedSplit, MergePlannerMessage, PackagedSplit, ScratchDir
 /// This includes the following steps:
 /// - commit: this step is CPU heavy
 /// - indentifying the list of tags for the splits, and labelling it accordingly
+/// - computing the split statistics
 /// - creating a bundle file
 /// - computing the hotcache
 /// - appending it to the split file.
@@ -52,11 +54,14 @@ pub struct Packager {
     merge_planner_mailbox_opt: Option<Mailbox<MergePlannerMessage>>,
     /// The special field for extracting tags.
     tags_field: Field,
+    /// Defines what gets copied into the hotcache of the splits.
+    hotcache_options: HotcacheOptions,
 }
 
 impl Packager {
     pub fn new(
         tags_field: Field,
+        hotcache_options: HotcacheOptions,
         uploader_mailbox: Mailbox<PackagedSplit>,
         merge_planner_mailbox_opt: Option<Mailbox<MergePlannerMessage>>,
     ) -> Packager {
@@ -64,6 +69,7 @@ impl Packager {
             uploader_mailbox,
             merge_planner_mailbox_opt,
             tags_field,
+            hotcache_options,
         }
     }
 }
@@ -117,7 +123,10 @@ fn list_split_files(
     segment_metas: &[SegmentMeta],
     scratch_directory: &ScratchDirectory,
 ) -> Vec<PathBuf> {
-    let mut index_files = vec![scratch_directory.path().join("meta.json")];
+    let mut index_files = vec![
+        scratch_directory.path().join("meta.json"),
+        scratch_directory.path().join(SPLIT_STATS_FILENAME),
+    ];
 
     // list the segment files
     for segment_meta in segment_metas {
@@ -180,9 +189,31 @@ fn merge_segments_if_required(
     Ok(segment_metas_after_merge)
 }
 
-fn build_hotcache<W: io::Write>(split_path: &Path, split_file: &mut W) -> anyhow::Result<()> {
+/// Computes the statistics of the split, and writes them into its scratch directory so that
+/// they get bundled with its files.
+fn write_split_stats(split: &IndexedSplit, ctx: &ActorContext<IndexedSplit>) -> anyhow::Result<()> {
+    let _protected_zone_guard = ctx.protect_zone();
+    let index_reader: IndexReader = split
+        .index
+        .reader_builder()
+        .reload_policy(ReloadPolicy::Manual)
+        .try_into()?;
+    let split_stats = SplitStats::compute(&index_reader.searcher())?;
+    let split_stats_path = split
+        .split_scratch_directory
+        .path()
+        .join(SPLIT_STATS_FILENAME);
+    std::fs::write(split_stats_path, serde_json::to_vec(&split_stats)?)?;
+    Ok(())
+}
+
+fn build_hotcache<W: io::Write>(
+    split_path: &Path,
+    hotcache_options: &HotcacheOptions,
+    split_file: &mut W,
+) -> anyhow::Result<()> {
     let mmap_directory = tantivy::directory::MmapDirectory::open(split_path)?;
-    write_hotcache(mmap_directory, split_file)?;
+    write_hotcache(mmap_directory, hotcache_options, split_file)?;
     Ok(())
 }
 
@@ -190,6 +221,7 @@ fn create_packaged_split(
     segment_metas: &[SegmentMeta],
     split: IndexedSplit,
     tags_field: Field,
+    hotcache_options: &HotcacheOptions,
     ctx: &ActorContext<IndexedSplit>,
 ) -> anyhow::Result<PackagedSplit> {
     info!(split = ?split, "create-packaged-split");
@@ -197,6 +229,10 @@ fn create_packaged_split(
     let split_filepath = split.split_scratch_directory.path().join(BUNDLE_FILENAME); // TODO rename <split_id>.split
     let mut split_file = CountingWriter::wrap(File::create(split_filepath)?);
 
+    debug!(split = ?split, "write-split-stats");
+    write_split_stats(&split, ctx)?;
+    ctx.record_progress();
+
     debug!(split = ?split, "create-file-bundle");
     let Range {
         start: footer_start,
@@ -231,7 +267,11 @@ fn create_packaged_split(
 
     debug!(split = ?split, "build-hotcache");
     let hotcache_offset_start = split_file.written_bytes();
-    build_hotcache(split.split_scratch_directory.path(), &mut split_file)?;
+    build_hotcache(
+        split.split_scratch_directory.path(),
+        hotcache_options,
+        &mut split_file,
+    )?;
     let hotcache_offset_end = split_file.written_bytes();
     let hotcache_num_bytes = hotcache_offset_end - hotcache_offset_start;
     ctx.record_progress();
@@ -254,6 +294,9 @@ fn create_packaged_split(
         tags,
         footer_offsets: footer_start..footer_end,
         split_date_of_birth: split.split_date_of_birth,
+        ingest_timestamps: IngestTimestamps::from_num_docs_per_timestamp(
+            &split.num_docs_per_ingest_timestamp,
+        ),
     };
     Ok(packaged_split)
 }
@@ -267,8 +310,13 @@ impl SyncActor for Packager {
         fail_point!("packager:before");
         commit_split(&mut split, ctx)?;
         let segment_metas = merge_segments_if_required(&mut split, ctx)?;
-        let packaged_split =
-            create_packaged_split(&segment_metas[..], split, self.tags_field, ctx)?;
+        let packaged_split = create_packaged_split(
+            &segment_metas[..],
+            split,
+            self.tags_field,
+            &self.hotcache_options,
+            ctx,
+        )?;
         ctx.send_message_blocking(&self.uploader_mailbox, packaged_split)?;
         fail_point!("packager:after");
         Ok(())
@@ -349,6 +397,7 @@ mod tests {
             num_docs,
             docs_size_in_bytes: num_docs * 15, //< bogus number
             split_date_of_birth: Instant::now(),
+            num_docs_per_ingest_timestamp: vec![(1_628_837_062, num_docs)].into_iter().collect(),
             index,
             index_writer,
             split_scratch_directory,
@@ -370,7 +419,7 @@ mod tests {
             .schema()
             .get_field(quickwit_index_config::TAGS_FIELD_NAME)
             .unwrap();
-        let packager = Packager::new(tags_field, mailbox, None);
+        let packager = Packager::new(tags_field, HotcacheOptions::default(), mailbox, None);
         let (packager_mailbox, packager_handle) = universe.spawn_actor(packager).spawn_sync();
         universe
             .send_message(&packager_mailbox, indexed_split)
@@ -381,6 +430,21 @@ mod tests {
         );
         let packaged_splits = inbox.drain_available_message_for_test();
         assert_eq!(packaged_splits.len(), 1);
+        let ingest_timestamps = packaged_splits[0].ingest_timestamps.as_ref().unwrap();
+        assert_eq!(ingest_timestamps.max_timestamp, 1_628_837_062);
+        assert_eq!(ingest_timestamps.age_bucket_counts[0], 2);
+        let split_stats_json = std::fs::read(
+            packaged_splits[0]
+                .split_scratch_directory
+                .path()
+                .join(SPLIT_STATS_FILENAME),
+        )?;
+        let split_stats: SplitStats = serde_json::from_slice(&split_stats_json)?;
+        assert_eq!(split_stats.num_docs, 2);
+        assert_eq!(
+            split_stats.fields["timestamp"].max_value,
+            Some(quickwit_directories::FastFieldValue::U64(1628203640))
+        );
         Ok(())
     }
 
@@ -395,7 +459,7 @@ mod tests {
             .schema()
             .get_field(quickwit_index_config::TAGS_FIELD_NAME)
             .unwrap();
-        let packager = Packager::new(tags_field, mailbox, None);
+        let packager = Packager::new(tags_field, HotcacheOptions::default(), mailbox, None);
         let (packager_mailbox, packager_handle) = universe.spawn_actor(packager).spawn_sync();
         universe
             .send_message(&packager_mailbox, indexed_split)
@@ -418,6 +482,7 @@ mod tests {
 
         let packager = Packager::new(
             Field::from_field_id(0u32),
+            HotcacheOptions::default(),
             mailbox,
             Some(merge_planner_mailbox),
         );
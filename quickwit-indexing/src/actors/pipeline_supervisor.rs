@@ -18,30 +18,44 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
+use futures::future::join_all;
 use quickwit_actors::{
     create_mailbox, Actor, ActorContext, ActorExitStatus, ActorHandle, ActorState, AsyncActor,
-    Health, KillSwitch, QueueCapacity, Supervisable,
+    Health, KillSwitch, Mailbox, QueueCapacity, Supervisable,
 };
-use quickwit_metastore::{Metastore, SplitState};
+use quickwit_directories::HotcacheOptions;
+use quickwit_metastore::{IndexMetadata, Metastore, SourceShardLeaseHolder, SplitState};
 use quickwit_storage::{create_storage_with_upload_cache, CacheParams, StorageUriResolver};
 use tokio::join;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::actors::merge_split_downloader::MergeSplitDownloader;
+use crate::actors::router::oldest_checkpoint;
 use crate::actors::{
     GarbageCollector, Indexer, IndexerParams, MergeExecutor, MergePlanner, Packager, Publisher,
-    Uploader,
+    Router, RouterTarget, Uploader,
 };
-use crate::models::IndexingStatistics;
-use crate::source::{quickwit_supported_sources, SourceActor, SourceConfig};
+use crate::models::{IndexerMessage, IndexingStatistics, RoutingRule};
+use crate::source::{quickwit_supported_sources, SourceActor, SourceConfig, SourceShard};
 use crate::{MergePolicy, StableMultitenantWithTimestampMergePolicy};
 
 pub struct IndexingPipelineHandler {
-    /// Indexing pipeline
     pub source: ActorHandle<SourceActor>,
+    /// Router splitting the documents of the source across the indexes, if there are routing
+    /// rules.
+    pub router_opt: Option<ActorHandle<Router>>,
+    /// Pipelines of the indexes the documents are sent to, starting with the main index.
+    pub index_pipelines: Vec<IndexPipelineHandler>,
+}
+
+/// Actors indexing the documents of the source into one index.
+pub struct IndexPipelineHandler {
+    pub index_id: String,
+
+    /// Indexing pipeline
     pub indexer: ActorHandle<Indexer>,
     pub packager: ActorHandle<Packager>,
     pub uploader: ActorHandle<Uploader>,
@@ -70,6 +84,9 @@ pub struct IndexingPipelineSupervisor {
     handlers: Option<IndexingPipelineHandler>,
     // Killswitch used for the actors in the pipeline. This is not the supervisor killswitch.
     kill_switch: KillSwitch,
+    /// Shard of the source leased by the pipeline, along with the instant the lease was last
+    /// renewed, if the source is sharded.
+    leased_source_shard_opt: Option<(SourceShard, Instant)>,
 }
 
 impl Actor for IndexingPipelineSupervisor {
@@ -90,24 +107,105 @@ impl IndexingPipelineSupervisor {
             handlers: None,
             kill_switch: KillSwitch::default(),
             statistics: IndexingStatistics::default(),
+            leased_source_shard_opt: None,
+        }
+    }
+
+    /// Acquires the lease of a shard of the source, if the source is sharded.
+    /// Returns false if all the shards are leased by other indexers.
+    async fn acquire_source_shard_lease(&mut self) -> anyhow::Result<bool> {
+        let sharding_params = match self.params.source_sharding_opt.as_ref() {
+            Some(sharding_params) => sharding_params,
+            None => return Ok(true),
+        };
+        let shard_id_opt = self
+            .params
+            .metastore
+            .acquire_source_shard_lease(
+                &self.params.index_id,
+                &self.params.source_config.source_id,
+                sharding_params.num_shards,
+                &sharding_params.owner,
+                sharding_params.lease_duration,
+            )
+            .await?;
+        if let Some(shard_id) = shard_id_opt {
+            info!(index_id=%self.params.index_id, source_id=%self.params.source_config.source_id, shard_id=shard_id, num_shards=sharding_params.num_shards, "acquired-source-shard");
+            let source_shard = SourceShard {
+                shard_id,
+                num_shards: sharding_params.num_shards,
+            };
+            self.leased_source_shard_opt = Some((source_shard, Instant::now()));
+            Ok(true)
+        } else {
+            info!(index_id=%self.params.index_id, source_id=%self.params.source_config.source_id, num_shards=sharding_params.num_shards, "All the shards of the source are leased, waiting for one to be released.");
+            Ok(false)
         }
     }
 
+    /// Renews the lease of the shard of the source once half of the lease duration has elapsed.
+    async fn renew_source_shard_lease_if_needed(&mut self) -> anyhow::Result<()> {
+        let (sharding_params, (source_shard, renewal_instant)) = match (
+            self.params.source_sharding_opt.as_ref(),
+            self.leased_source_shard_opt.as_mut(),
+        ) {
+            (Some(sharding_params), Some(leased_source_shard)) => {
+                (sharding_params, leased_source_shard)
+            }
+            _ => return Ok(()),
+        };
+        if renewal_instant.elapsed() < sharding_params.lease_duration / 2 {
+            return Ok(());
+        }
+        self.params
+            .metastore
+            .renew_source_shard_lease(
+                &self.params.index_id,
+                &self.params.source_config.source_id,
+                source_shard.shard_id,
+                &sharding_params.owner,
+                sharding_params.lease_duration,
+            )
+            .await?;
+        *renewal_instant = Instant::now();
+        Ok(())
+    }
+
+    /// Releases the lease of the shard of the source, so that another indexer can take over.
+    async fn release_source_shard_lease(&mut self) -> anyhow::Result<()> {
+        if let (Some(sharding_params), Some((source_shard, _))) = (
+            self.params.source_sharding_opt.as_ref(),
+            self.leased_source_shard_opt.take(),
+        ) {
+            self.params
+                .metastore
+                .release_source_shard_lease(
+                    &self.params.index_id,
+                    &self.params.source_config.source_id,
+                    source_shard.shard_id,
+                    &sharding_params.owner,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
     async fn process_observe(&mut self, ctx: &ActorContext<Msg>) -> Result<(), ActorExitStatus> {
         if let Some(handlers) = self.handlers.as_ref() {
-            let (indexer_counters, uploader_counters, publisher_counters) = join!(
-                handlers.indexer.observe(),
-                handlers.uploader.observe(),
-                handlers.publisher.observe(),
-            );
-            self.statistics = self
-                .previous_generations_statistics
-                .clone()
-                .add_actor_counters(
+            let mut statistics = self.previous_generations_statistics.clone();
+            for index_pipeline in &handlers.index_pipelines {
+                let (indexer_counters, uploader_counters, publisher_counters) = join!(
+                    index_pipeline.indexer.observe(),
+                    index_pipeline.uploader.observe(),
+                    index_pipeline.publisher.observe(),
+                );
+                statistics = statistics.add_actor_counters(
                     &*indexer_counters,
                     &*uploader_counters,
                     &*publisher_counters,
                 );
+            }
+            self.statistics = statistics;
         }
         ctx.schedule_self_msg(Duration::from_secs(1), Msg::Observe)
             .await;
@@ -116,18 +214,23 @@ impl IndexingPipelineSupervisor {
 
     fn supervisables(&self) -> Vec<&dyn Supervisable> {
         if let Some(handlers) = self.handlers.as_ref() {
-            let supervisables: Vec<&dyn Supervisable> = vec![
-                &handlers.source,
-                &handlers.indexer,
-                &handlers.packager,
-                &handlers.uploader,
-                &handlers.publisher,
-                &handlers.merge_planner,
-                &handlers.merge_split_downloader,
-                &handlers.merge_executor,
-                &handlers.merge_packager,
-                &handlers.merge_uploader,
-            ];
+            let mut supervisables: Vec<&dyn Supervisable> = vec![&handlers.source];
+            if let Some(router) = handlers.router_opt.as_ref() {
+                supervisables.push(router);
+            }
+            for index_pipeline in &handlers.index_pipelines {
+                supervisables.extend_from_slice(&[
+                    &index_pipeline.indexer as &dyn Supervisable,
+                    &index_pipeline.packager,
+                    &index_pipeline.uploader,
+                    &index_pipeline.publisher,
+                    &index_pipeline.merge_planner,
+                    &index_pipeline.merge_split_downloader,
+                    &index_pipeline.merge_executor,
+                    &index_pipeline.merge_packager,
+                    &index_pipeline.merge_uploader,
+                ]);
+            }
             supervisables
         } else {
             Vec::new()
@@ -172,31 +275,147 @@ impl IndexingPipelineSupervisor {
 
     // TODO this should return an error saying whether we can retry or not.
     async fn spawn_pipeline(&mut self, ctx: &ActorContext<Msg>) -> anyhow::Result<()> {
+        // Only the publications into the main index are fenced by the lease of the shard.
+        if self.params.source_sharding_opt.is_some() && !self.params.routing_rules.is_empty() {
+            anyhow::bail!("A sharded source cannot be routed to several indexes.");
+        }
+        // The shard must be leased before reading the checkpoint, so that the checkpoint includes
+        // the progress of the previous owner of the shard.
+        if !self.acquire_source_shard_lease().await? {
+            return Ok(());
+        }
         info!(index_id=%self.params.index_id, "spawn-indexing-pipeline");
         self.kill_switch = KillSwitch::default();
-        let index_metadata = self
-            .params
-            .metastore
-            .index_metadata(&self.params.index_id)
-            .await?;
-        let index_storage = self
-            .params
-            .storage_uri_resolver
-            .resolve(&index_metadata.index_uri)?;
+        let mut index_ids = vec![self.params.index_id.clone()];
+        for routing_rule in &self.params.routing_rules {
+            if !index_ids.contains(&routing_rule.index_id) {
+                index_ids.push(routing_rule.index_id.clone());
+            }
+        }
+        let mut index_metadatas = Vec::with_capacity(index_ids.len());
+        for index_id in index_ids {
+            let index_metadata = self.params.metastore.index_metadata(&index_id).await?;
+            if index_metadata.read_only {
+                anyhow::bail!(
+                    "The index `{}` is read-only: it was attached from the storage of another \
+                     cluster and cannot be indexed into.",
+                    index_id
+                );
+            }
+            index_metadatas.push(index_metadata);
+        }
+        let mut index_pipelines = Vec::with_capacity(index_metadatas.len());
+        let mut router_targets = Vec::with_capacity(index_metadatas.len());
+        for index_metadata in index_metadatas {
+            let index_id = index_metadata.index_id.clone();
+            let checkpoint = index_metadata.checkpoint.clone();
+            let (indexer_mailbox, index_pipeline) =
+                self.spawn_index_pipeline(index_metadata, ctx).await?;
+            index_pipelines.push(index_pipeline);
+            router_targets.push(RouterTarget {
+                index_id,
+                indexer_mailbox,
+                checkpoint,
+            });
+        }
+
+        // Router
+        let (batch_sink, checkpoint, router_opt) = if self.params.routing_rules.is_empty() {
+            let router_target = router_targets.pop().expect("The main index is a target.");
+            (
+                router_target.indexer_mailbox,
+                router_target.checkpoint,
+                None,
+            )
+        } else {
+            let checkpoints: Vec<_> = router_targets
+                .iter()
+                .map(|router_target| router_target.checkpoint.clone())
+                .collect();
+            let router = Router::new(
+                self.params.routing_rules.clone(),
+                self.params.index_id.clone(),
+                router_targets,
+            );
+            let (router_mailbox, router_handler) = ctx
+                .spawn_actor(router)
+                .set_kill_switch(self.kill_switch.clone())
+                .spawn_sync();
+            (
+                router_mailbox,
+                oldest_checkpoint(&checkpoints),
+                Some(router_handler),
+            )
+        };
+
+        // Source
+        // A source with a single shard is indexed whole, by the indexer holding its lease, while
+        // the others stand by.
+        let source = if let Some((source_shard, _)) = self
+            .leased_source_shard_opt
+            .filter(|(source_shard, _)| source_shard.num_shards > 1)
+        {
+            quickwit_supported_sources()
+                .load_source_shard(self.params.source_config.clone(), checkpoint, source_shard)
+                .await?
+        } else {
+            quickwit_supported_sources()
+                .load_source(self.params.source_config.clone(), checkpoint)
+                .await?
+        };
+        let actor_source = SourceActor { source, batch_sink };
+        let (_source_mailbox, source_handler) = ctx
+            .spawn_actor(actor_source)
+            .set_kill_switch(self.kill_switch.clone())
+            .spawn_async();
+
+        self.handlers = Some(IndexingPipelineHandler {
+            source: source_handler,
+            router_opt,
+            index_pipelines,
+        });
+        Ok(())
+    }
+
+    /// Spawns the actors indexing documents into the index, from the indexer to the garbage
+    /// collector, and returns the mailbox of the indexer.
+    async fn spawn_index_pipeline(
+        &self,
+        index_metadata: IndexMetadata,
+        ctx: &ActorContext<Msg>,
+    ) -> anyhow::Result<(Mailbox<IndexerMessage>, IndexPipelineHandler)> {
+        let index_id = index_metadata.index_id.clone();
+        let index_storage = self.params.storage_uri_resolver.resolve_with_mirror(
+            &index_metadata.index_uri,
+            index_metadata.mirror_index_uri.as_deref(),
+        )?;
 
         // TODO: Make cache path configurable [https://github.com/quickwit-inc/quickwit/issues/520]
         // Using the scratch_directory directly is fine since the cache storage will create its own
-        // folder to work with.
-        let cache_directory = self.params.indexer_params.scratch_directory.path();
+        // folder to work with. The indexes targeted by routing rules get a folder of their own.
+        let mut cache_directory = self
+            .params
+            .indexer_params
+            .scratch_directory
+            .path()
+            .to_path_buf();
+        if index_id != self.params.index_id {
+            cache_directory.push(&index_id);
+        }
         let index_storage = create_storage_with_upload_cache(
             index_storage,
-            cache_directory,
+            &cache_directory,
             CacheParams::default(),
         )?;
 
         let tags_field = index_metadata
             .index_config
             .tags_field(&index_metadata.index_config.schema());
+        let hotcache_config = index_metadata.index_config.hotcache_config();
+        let hotcache_options = HotcacheOptions {
+            fieldnorms: hotcache_config.fieldnorms,
+            fast_field_names: hotcache_config.fast_fields,
+        };
 
         let (publisher_mailbox, publisher_inbox) = create_mailbox::<<Publisher as Actor>::Message>(
             "publisher".to_string(),
@@ -208,6 +427,9 @@ impl IndexingPipelineSupervisor {
             self.params.metastore.clone(),
             index_storage.clone(),
             publisher_mailbox.clone(),
+            index_metadata.index_config_version(),
+            index_metadata.index_config.split_path_layout(),
+            index_metadata.index_config.split_part_num_bytes(),
         );
         let (merge_uploader_mailbox, merge_uploader_handler) = ctx
             .spawn_actor(merge_uploader)
@@ -215,14 +437,18 @@ impl IndexingPipelineSupervisor {
             .spawn_async();
 
         // Merge Packager
-        let merge_packager = Packager::new(tags_field, merge_uploader_mailbox, None);
+        let merge_packager = Packager::new(
+            tags_field,
+            hotcache_options.clone(),
+            merge_uploader_mailbox,
+            None,
+        );
         let (merge_packager_mailbox, merge_packager_handler) = ctx
             .spawn_actor(merge_packager)
             .set_kill_switch(self.kill_switch.clone())
             .spawn_sync();
 
-        let merge_executor =
-            MergeExecutor::new(self.params.index_id.clone(), merge_packager_mailbox);
+        let merge_executor = MergeExecutor::new(index_id.clone(), merge_packager_mailbox);
         let (merge_executor_mailbox, merge_executor_handler) = ctx
             .spawn_actor(merge_executor)
             .set_kill_switch(self.kill_switch.clone())
@@ -242,13 +468,17 @@ impl IndexingPipelineSupervisor {
         let merge_policy: Arc<dyn MergePolicy> =
             Arc::new(StableMultitenantWithTimestampMergePolicy::default());
         let mut merge_planner = MergePlanner::new(merge_policy, merge_split_downloader_mailbox);
-        for split in self
-            .params
-            .metastore
-            .list_splits(&self.params.index_id, SplitState::Published, None, &[])
-            .await?
-        {
-            merge_planner.add_split(split.split_metadata);
+        // The indexers sharing a sharded source only merge the splits they produce, so that no
+        // two indexers merge the same split.
+        if self.params.source_sharding_opt.is_none() {
+            for split in self
+                .params
+                .metastore
+                .list_splits(&index_id, SplitState::Published, None, &[])
+                .await?
+            {
+                merge_planner.add_split(split.split_metadata);
+            }
         }
         let (merge_planner_mailbox, merge_planner_handler) = ctx
             .spawn_actor(merge_planner)
@@ -256,8 +486,21 @@ impl IndexingPipelineSupervisor {
             .spawn_sync();
 
         // Publisher
-        let publisher =
+        let mut publisher =
             Publisher::new(self.params.metastore.clone(), merge_planner_mailbox.clone());
+        if let Some(realtime_params) = self.params.indexer_params.realtime_opt.as_ref() {
+            publisher = publisher.with_realtime_splits(realtime_params.splits.clone());
+        }
+        if let (Some(sharding_params), Some((source_shard, _))) = (
+            self.params.source_sharding_opt.as_ref(),
+            self.leased_source_shard_opt.as_ref(),
+        ) {
+            publisher = publisher.with_source_shard_lease(SourceShardLeaseHolder {
+                source_id: self.params.source_config.source_id.clone(),
+                shard_id: source_shard.shard_id,
+                owner: sharding_params.owner.clone(),
+            });
+        }
         let (publisher_mailbox, publisher_handler) = ctx
             .spawn_actor(publisher)
             .set_kill_switch(self.kill_switch.clone())
@@ -269,6 +512,9 @@ impl IndexingPipelineSupervisor {
             self.params.metastore.clone(),
             index_storage.clone(),
             publisher_mailbox,
+            index_metadata.index_config_version(),
+            index_metadata.index_config.split_path_layout(),
+            index_metadata.index_config.split_part_num_bytes(),
         );
         let (uploader_mailbox, uploader_handler) = ctx
             .spawn_actor(uploader)
@@ -276,7 +522,12 @@ impl IndexingPipelineSupervisor {
             .spawn_async();
 
         // Packager
-        let packager = Packager::new(tags_field, uploader_mailbox, Some(merge_planner_mailbox));
+        let packager = Packager::new(
+            tags_field,
+            hotcache_options,
+            uploader_mailbox,
+            Some(merge_planner_mailbox),
+        );
         let (packager_mailbox, packager_handler) = ctx
             .spawn_actor(packager)
             .set_kill_switch(self.kill_switch.clone())
@@ -284,7 +535,8 @@ impl IndexingPipelineSupervisor {
 
         // Indexer
         let indexer = Indexer::try_new(
-            self.params.index_id.clone(),
+            index_id.clone(),
+            index_metadata.create_timestamp,
             index_metadata.index_config.clone(),
             self.params.indexer_params.clone(),
             packager_mailbox,
@@ -294,21 +546,8 @@ impl IndexingPipelineSupervisor {
             .set_kill_switch(self.kill_switch.clone())
             .spawn_sync();
 
-        // Source
-        let source = quickwit_supported_sources()
-            .load_source(self.params.source_config.clone(), index_metadata.checkpoint)
-            .await?;
-        let actor_source = SourceActor {
-            source,
-            batch_sink: indexer_mailbox,
-        };
-        let (_source_mailbox, source_handler) = ctx
-            .spawn_actor(actor_source)
-            .set_kill_switch(self.kill_switch.clone())
-            .spawn_async();
-
         let garbage_collector = GarbageCollector::new(
-            self.params.index_id.clone(),
+            index_id.clone(),
             index_storage,
             self.params.metastore.clone(),
         );
@@ -317,8 +556,8 @@ impl IndexingPipelineSupervisor {
             .set_kill_switch(self.kill_switch.clone())
             .spawn_async();
 
-        self.handlers = Some(IndexingPipelineHandler {
-            source: source_handler,
+        let index_pipeline = IndexPipelineHandler {
+            index_id,
             indexer: indexer_handler,
             packager: packager_handler,
             uploader: uploader_handler,
@@ -330,8 +569,8 @@ impl IndexingPipelineSupervisor {
             merge_executor: merge_executor_handler,
             merge_packager: merge_packager_handler,
             merge_uploader: merge_uploader_handler,
-        });
-        Ok(())
+        };
+        Ok((indexer_mailbox, index_pipeline))
     }
 
     async fn process_supervise(&mut self, ctx: &ActorContext<Msg>) -> Result<(), ActorExitStatus> {
@@ -343,6 +582,11 @@ impl IndexingPipelineSupervisor {
             //    error!(err=?spawn_error, "Error while spawning");
             //    self.terminate().await;
             // }
+        } else if let Err(error) = self.renew_source_shard_lease_if_needed().await {
+            // Another indexer may have taken over the shard: we stop indexing it.
+            warn!(index_id=%self.params.index_id, error=?error, "Failed to renew the lease of the source shard.");
+            self.leased_source_shard_opt = None;
+            self.terminate().await;
         } else {
             match self.healthcheck() {
                 Health::Healthy => {
@@ -356,16 +600,17 @@ impl IndexingPipelineSupervisor {
                         // packager panics, the finalizer may never be
                         // called, so we defensively send the message if we
                         // detect that the packager is not running, while the merge planner is.
-
-                        if handlers.packager.state() != ActorState::Running
-                            && handlers.merge_planner.state() == ActorState::Running
-                        {
-                            // Failing to send is fine here.
-                            info!("Stopping the merge planner since the packager is dead.");
-                            // If the message cannot be sent this is not necessarily an error.
-                            let _ = ctx
-                                .send_exit_with_success(handlers.merge_planner.mailbox())
-                                .await;
+                        for index_pipeline in &handlers.index_pipelines {
+                            if index_pipeline.packager.state() != ActorState::Running
+                                && index_pipeline.merge_planner.state() == ActorState::Running
+                            {
+                                // Failing to send is fine here.
+                                info!(index_id=%index_pipeline.index_id, "Stopping the merge planner since the packager is dead.");
+                                // If the message cannot be sent this is not necessarily an error.
+                                let _ = ctx
+                                    .send_exit_with_success(index_pipeline.merge_planner.mailbox())
+                                    .await;
+                            }
                         }
                     }
                 }
@@ -384,18 +629,29 @@ impl IndexingPipelineSupervisor {
     async fn terminate(&mut self) {
         self.kill_switch.kill();
         if let Some(handlers) = self.handlers.take() {
-            tokio::join!(
-                handlers.source.kill(),
-                handlers.indexer.kill(),
-                handlers.packager.kill(),
-                handlers.uploader.kill(),
-                handlers.publisher.kill(),
-                handlers.garbage_collector.kill(),
-                handlers.merge_split_downloader.kill(),
-                handlers.merge_executor.kill(),
-                handlers.merge_packager.kill(),
-                handlers.merge_uploader.kill()
-            );
+            handlers.source.kill().await;
+            if let Some(router) = handlers.router_opt {
+                router.kill().await;
+            }
+            join_all(
+                handlers
+                    .index_pipelines
+                    .into_iter()
+                    .map(|index_pipeline| async move {
+                        tokio::join!(
+                            index_pipeline.indexer.kill(),
+                            index_pipeline.packager.kill(),
+                            index_pipeline.uploader.kill(),
+                            index_pipeline.publisher.kill(),
+                            index_pipeline.garbage_collector.kill(),
+                            index_pipeline.merge_split_downloader.kill(),
+                            index_pipeline.merge_executor.kill(),
+                            index_pipeline.merge_packager.kill(),
+                            index_pipeline.merge_uploader.kill()
+                        );
+                    }),
+            )
+            .await;
         }
     }
 }
@@ -423,6 +679,14 @@ impl AsyncActor for IndexingPipelineSupervisor {
         }
         Ok(())
     }
+
+    async fn finalize(
+        &mut self,
+        _exit_status: &ActorExitStatus,
+        _ctx: &ActorContext<Self::Message>,
+    ) -> anyhow::Result<()> {
+        self.release_source_shard_lease().await
+    }
 }
 
 pub struct IndexingPipelineParams {
@@ -431,21 +695,57 @@ pub struct IndexingPipelineParams {
     pub indexer_params: IndexerParams,
     pub metastore: Arc<dyn Metastore>,
     pub storage_uri_resolver: StorageUriResolver,
+    /// Rules routing the documents of the source to other indexes than `index_id`.
+    pub routing_rules: Vec<RoutingRule>,
+    /// Sharding of the source across several indexers, if any.
+    pub source_sharding_opt: Option<SourceShardingParams>,
+}
+
+/// Parameters for indexing one shard of a source shared by several indexers. The shards are
+/// assigned to the indexers through leases held in the metastore.
+#[derive(Clone, Debug)]
+pub struct SourceShardingParams {
+    /// Number of shards of the source, usually the number of indexers sharing it.
+    pub num_shards: usize,
+    /// Identifier of the indexer, unique across the indexers sharing the source.
+    pub owner: String,
+    /// Duration of the lease of a shard. The lease is renewed once half of it has elapsed.
+    pub lease_duration: Duration,
+}
+
+impl SourceShardingParams {
+    /// Default duration of the lease of a shard, i.e. the time it takes for another indexer to
+    /// take over the shard of an indexer that stopped abruptly.
+    const DEFAULT_LEASE_DURATION: Duration = Duration::from_secs(60);
+
+    /// Creates parameters for an indexer sharing the source with others, identified by a new
+    /// unique owner ID.
+    pub fn new(num_shards: usize) -> Self {
+        Self {
+            num_shards,
+            owner: ulid::Ulid::new().to_string(),
+            lease_duration: Self::DEFAULT_LEASE_DURATION,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
+    use std::time::Duration;
 
     use quickwit_actors::Universe;
+    use quickwit_common::QuickwitUri;
     use quickwit_metastore::{IndexMetadata, MockMetastore, SplitState};
     use quickwit_storage::StorageUriResolver;
     use serde_json::json;
 
-    use super::{IndexingPipelineParams, IndexingPipelineSupervisor};
+    use super::{IndexingPipelineParams, IndexingPipelineSupervisor, SourceShardingParams};
     use crate::actors::IndexerParams;
+    use crate::models::RoutingRule;
     use crate::source::SourceConfig;
 
     #[tokio::test]
@@ -463,9 +763,15 @@ mod tests {
             .returning(|_| {
                 let index_metadata = IndexMetadata {
                     index_id: "test-index".to_string(),
-                    index_uri: "ram://test-index".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("ram://test-index"),
                     index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
                     checkpoint: Default::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 };
                 Ok(index_metadata)
             });
@@ -499,6 +805,233 @@ mod tests {
             indexer_params,
             metastore: Arc::new(metastore),
             storage_uri_resolver: StorageUriResolver::for_test(),
+            routing_rules: Vec::new(),
+            source_sharding_opt: None,
+        };
+        let indexing_supervisor = IndexingPipelineSupervisor::new(indexing_pipeline_params);
+        let (_pipeline_mailbox, pipeline_handler) =
+            universe.spawn_actor(indexing_supervisor).spawn_async();
+        let (pipeline_termination, pipeline_statistics) = pipeline_handler.join().await;
+        assert!(pipeline_termination.is_success());
+        assert_eq!(pipeline_statistics.num_published_splits, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_indexing_pipeline_with_routing_rules() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let mut metastore = MockMetastore::default();
+        metastore
+            .expect_list_splits()
+            .times(2)
+            .returning(|_, _, _, _| Ok(Vec::new()));
+        metastore
+            .expect_index_metadata()
+            .withf(|index_id| index_id == "test-index" || index_id == "tax-index")
+            .times(2)
+            .returning(|index_id| {
+                let index_metadata = IndexMetadata {
+                    index_id: index_id.to_string(),
+                    index_uri: QuickwitUri::from_well_formed(&format!("ram://{}", index_id)),
+                    index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+                    checkpoint: Default::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                };
+                Ok(index_metadata)
+            });
+        metastore
+            .expect_stage_split()
+            .times(2)
+            .returning(|_, _| Ok(()));
+        metastore
+            .expect_publish_splits()
+            .withf(move |index_id, splits, checkpoint_delta| -> bool {
+                (index_id == "test-index" || index_id == "tax-index")
+                    && splits.len() == 1
+                    && format!("{:?}", checkpoint_delta)
+                        .ends_with(":(00000000000000000000..00000000000000000070])")
+            })
+            .times(2)
+            .returning(|_, _, _| Ok(()));
+        let universe = Universe::new();
+        let source_config = SourceConfig {
+            source_id: "test-source".to_string(),
+            source_type: "file".to_string(),
+            params: json!({ "filepath": PathBuf::from("data/test_corpus.json") }),
+        };
+        let indexer_params = IndexerParams::for_test()?;
+        let indexing_pipeline_params = IndexingPipelineParams {
+            index_id: "test-index".to_string(),
+            source_config,
+            indexer_params,
+            metastore: Arc::new(metastore),
+            storage_uri_resolver: StorageUriResolver::for_test(),
+            routing_rules: vec!["body=tax:tax-index".parse::<RoutingRule>()?],
+            source_sharding_opt: None,
+        };
+        let indexing_supervisor = IndexingPipelineSupervisor::new(indexing_pipeline_params);
+        let (_pipeline_mailbox, pipeline_handler) =
+            universe.spawn_actor(indexing_supervisor).spawn_async();
+        let (pipeline_termination, pipeline_statistics) = pipeline_handler.join().await;
+        assert!(pipeline_termination.is_success());
+        assert_eq!(pipeline_statistics.num_docs, 4);
+        assert_eq!(pipeline_statistics.num_published_splits, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_indexing_pipeline_with_source_sharding() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let mut metastore = MockMetastore::default();
+        let num_acquisition_attempts = Arc::new(AtomicUsize::new(0));
+        metastore
+            .expect_acquire_source_shard_lease()
+            .withf(|index_id, source_id, num_shards, owner, _| {
+                index_id == "test-index"
+                    && source_id == "test-source"
+                    && *num_shards == 2
+                    && owner == "indexer-a"
+            })
+            .times(3)
+            .returning(move |_, _, _, _, _| {
+                // The shards are leased by other indexers until the third attempt.
+                if num_acquisition_attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Ok(None)
+                } else {
+                    Ok(Some(1))
+                }
+            });
+        metastore
+            .expect_index_metadata()
+            .withf(|index_id| index_id == "test-index")
+            .times(1)
+            .returning(|_| {
+                let index_metadata = IndexMetadata {
+                    index_id: "test-index".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("ram://test-index"),
+                    index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+                    checkpoint: Default::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                };
+                Ok(index_metadata)
+            });
+        metastore
+            .expect_release_source_shard_lease()
+            .withf(|index_id, source_id, shard_id, owner| {
+                index_id == "test-index"
+                    && source_id == "test-source"
+                    && *shard_id == 1
+                    && owner == "indexer-a"
+            })
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+        let universe = Universe::new();
+        // File sources cannot be sharded: the pipeline fails once it has leased a shard.
+        let source_config = SourceConfig {
+            source_id: "test-source".to_string(),
+            source_type: "file".to_string(),
+            params: json!({ "filepath": PathBuf::from("data/test_corpus.json") }),
+        };
+        let indexer_params = IndexerParams::for_test()?;
+        let indexing_pipeline_params = IndexingPipelineParams {
+            index_id: "test-index".to_string(),
+            source_config,
+            indexer_params,
+            metastore: Arc::new(metastore),
+            storage_uri_resolver: StorageUriResolver::for_test(),
+            routing_rules: Vec::new(),
+            source_sharding_opt: Some(SourceShardingParams {
+                num_shards: 2,
+                owner: "indexer-a".to_string(),
+                lease_duration: Duration::from_secs(60),
+            }),
+        };
+        let indexing_supervisor = IndexingPipelineSupervisor::new(indexing_pipeline_params);
+        let (_pipeline_mailbox, pipeline_handler) =
+            universe.spawn_actor(indexing_supervisor).spawn_async();
+        let (pipeline_termination, _pipeline_statistics) = pipeline_handler.join().await;
+        assert!(!pipeline_termination.is_success());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_indexing_pipeline_with_standby_indexers() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let mut metastore = MockMetastore::default();
+        metastore
+            .expect_acquire_source_shard_lease()
+            .withf(|_, _, num_shards, owner, _| *num_shards == 1 && owner == "indexer-a")
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(Some(0)));
+        metastore
+            .expect_index_metadata()
+            .withf(|index_id| index_id == "test-index")
+            .times(1)
+            .returning(|_| {
+                let index_metadata = IndexMetadata {
+                    index_id: "test-index".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("ram://test-index"),
+                    index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+                    checkpoint: Default::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                };
+                Ok(index_metadata)
+            });
+        metastore
+            .expect_stage_split()
+            .times(1)
+            .returning(|_, _| Ok(()));
+        metastore
+            .expect_publish_splits_with_lease()
+            .withf(|index_id, splits, _, lease_holder| {
+                index_id == "test-index"
+                    && splits.len() == 1
+                    && lease_holder.source_id == "test-source"
+                    && lease_holder.shard_id == 0
+                    && lease_holder.owner == "indexer-a"
+            })
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+        metastore
+            .expect_release_source_shard_lease()
+            .withf(|_, _, shard_id, owner| *shard_id == 0 && owner == "indexer-a")
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+        let universe = Universe::new();
+        // A source with a single shard is indexed whole, even if it cannot be sharded.
+        let source_config = SourceConfig {
+            source_id: "test-source".to_string(),
+            source_type: "file".to_string(),
+            params: json!({ "filepath": PathBuf::from("data/test_corpus.json") }),
+        };
+        let indexer_params = IndexerParams::for_test()?;
+        let indexing_pipeline_params = IndexingPipelineParams {
+            index_id: "test-index".to_string(),
+            source_config,
+            indexer_params,
+            metastore: Arc::new(metastore),
+            storage_uri_resolver: StorageUriResolver::for_test(),
+            routing_rules: Vec::new(),
+            source_sharding_opt: Some(SourceShardingParams {
+                num_shards: 1,
+                owner: "indexer-a".to_string(),
+                lease_duration: Duration::from_secs(60),
+            }),
         };
         let indexing_supervisor = IndexingPipelineSupervisor::new(indexing_pipeline_params);
         let (_pipeline_mailbox, pipeline_handler) =
@@ -22,13 +22,57 @@ use std::sync::Arc;
 use anyhow::Context;
 use async_trait::async_trait;
 use fail::fail_point;
+use once_cell::sync::Lazy;
+use prometheus::IntGaugeVec;
 use quickwit_actors::{Actor, ActorContext, AsyncActor, Mailbox, QueueCapacity};
-use quickwit_metastore::Metastore;
+use quickwit_common::metrics::new_gauge_vec;
+use quickwit_metastore::{
+    time_to_searchable_percentile, Metastore, SourceShardLeaseHolder, SplitMetadata,
+};
+use tantivy::chrono::Utc;
 use tokio::sync::oneshot::Receiver;
 use tracing::info;
 
 use crate::models::{MergePlannerMessage, PublishOperation, PublisherMessage};
 
+static TIME_TO_SEARCHABLE_P50_SECS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    new_gauge_vec(
+        "indexing:time_to_searchable:p50_secs",
+        "Median time, in seconds, between the reception of the documents of the last published \
+         split of the index and its publication",
+        &["index_id"],
+    )
+});
+
+static TIME_TO_SEARCHABLE_P99_SECS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    new_gauge_vec(
+        "indexing:time_to_searchable:p99_secs",
+        "99th percentile of the time, in seconds, between the reception of the documents of the \
+         last published split of the index and its publication",
+        &["index_id"],
+    )
+});
+
+/// Updates the time to searchable gauges of the index with the split that was just published.
+fn record_time_to_searchable(index_id: &str, published_split: &SplitMetadata) {
+    let ingest_timestamps = if let Some(ingest_timestamps) = &published_split.ingest_timestamps {
+        ingest_timestamps
+    } else {
+        return;
+    };
+    let times_to_searchable = ingest_timestamps.times_to_searchable(Utc::now().timestamp());
+    if let Some(p50_secs) = time_to_searchable_percentile(&times_to_searchable, 50.0) {
+        TIME_TO_SEARCHABLE_P50_SECS
+            .with_label_values(&[index_id])
+            .set(p50_secs);
+    }
+    if let Some(p99_secs) = time_to_searchable_percentile(&times_to_searchable, 99.0) {
+        TIME_TO_SEARCHABLE_P99_SECS
+            .with_label_values(&[index_id])
+            .set(p99_secs);
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct PublisherCounters {
     pub num_published_splits: u64,
@@ -37,6 +81,8 @@ pub struct Publisher {
     metastore: Arc<dyn Metastore>,
     merge_planner_mailbox: Mailbox<MergePlannerMessage>,
     counters: PublisherCounters,
+    realtime_splits_opt: Option<RealtimeSplits>,
+    lease_holder_opt: Option<SourceShardLeaseHolder>,
 }
 
 impl Publisher {
@@ -48,9 +94,24 @@ impl Publisher {
             metastore,
             merge_planner_mailbox,
             counters: PublisherCounters::default(),
+            realtime_splits_opt: None,
+            lease_holder_opt: None,
         }
     }
 
+    /// Only publishes the new splits as long as the lease of the shard of the source is held by
+    /// `lease_holder`, so that the splits of an indexer that lost its lease are not published.
+    pub fn with_source_shard_lease(mut self, lease_holder: SourceShardLeaseHolder) -> Self {
+        self.lease_holder_opt = Some(lease_holder);
+        self
+    }
+
+    /// Removes the splits from `realtime_splits` once they are published.
+    pub fn with_realtime_splits(mut self, realtime_splits: RealtimeSplits) -> Self {
+        self.realtime_splits_opt = Some(realtime_splits);
+        self
+    }
+
     pub async fn run_publish_operation(
         &self,
         publisher_message: &PublisherMessage,
@@ -62,14 +123,30 @@ impl Publisher {
                 checkpoint_delta,
                 ..
             } => {
-                self.metastore
-                    .publish_splits(
-                        &publisher_message.index_id,
-                        &[&new_split.split_id],
-                        checkpoint_delta.clone(),
-                    )
-                    .await
-                    .context("Failed to publish splits.")?;
+                if let Some(lease_holder) = self.lease_holder_opt.as_ref() {
+                    self.metastore
+                        .publish_splits_with_lease(
+                            &publisher_message.index_id,
+                            &[&new_split.split_id],
+                            checkpoint_delta.clone(),
+                            lease_holder,
+                        )
+                        .await
+                        .context("Failed to publish splits.")?;
+                } else {
+                    self.metastore
+                        .publish_splits(
+                            &publisher_message.index_id,
+                            &[&new_split.split_id],
+                            checkpoint_delta.clone(),
+                        )
+                        .await
+                        .context("Failed to publish splits.")?;
+                }
+                record_time_to_searchable(&publisher_message.index_id, new_split);
+                if let Some(realtime_splits) = self.realtime_splits_opt.as_ref() {
+                    realtime_splits.remove(&publisher_message.index_id, &[&new_split.split_id]);
+                }
             }
             PublishOperation::ReplaceSplits {
                 new_splits: new_split_id,
@@ -222,6 +299,53 @@ mod tests {
         assert_eq!(publisher_observation.num_published_splits, 2);
     }
 
+    #[tokio::test]
+    async fn test_publisher_publishes_with_source_shard_lease() {
+        quickwit_common::setup_logging_for_tests();
+        let lease_holder = SourceShardLeaseHolder {
+            source_id: "source".to_string(),
+            shard_id: 1,
+            owner: "indexer-a".to_string(),
+        };
+        let mut mock_metastore = MockMetastore::default();
+        let expected_lease_holder = lease_holder.clone();
+        mock_metastore
+            .expect_publish_splits_with_lease()
+            .withf(move |index_id, split_ids, checkpoint_delta, lease_holder| {
+                index_id == "index"
+                    && split_ids[..] == ["split1"]
+                    && checkpoint_delta == &CheckpointDelta::from(1..3)
+                    && lease_holder == &expected_lease_holder
+            })
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+        let (merge_planner_mailbox, _merge_planner_inbox) = create_test_mailbox();
+        let publisher = Publisher::new(Arc::new(mock_metastore), merge_planner_mailbox)
+            .with_source_shard_lease(lease_holder);
+        let universe = Universe::new();
+        let (publisher_mailbox, publisher_handle) = universe.spawn_actor(publisher).spawn_async();
+        let (split_future_tx, split_future_rx) = oneshot::channel::<PublisherMessage>();
+        assert!(universe
+            .send_message(&publisher_mailbox, split_future_rx)
+            .await
+            .is_ok());
+        assert!(split_future_tx
+            .send(PublisherMessage {
+                index_id: "index".to_string(),
+                operation: PublishOperation::PublishNewSplit {
+                    new_split: SplitMetadata {
+                        split_id: "split1".to_string(),
+                        ..Default::default()
+                    },
+                    checkpoint_delta: CheckpointDelta::from(1..3),
+                    split_date_of_birth: Instant::now(),
+                },
+            })
+            .is_ok());
+        let publisher_observation = publisher_handle.process_pending_and_observe().await.state;
+        assert_eq!(publisher_observation.num_published_splits, 1);
+    }
+
     #[tokio::test]
     async fn test_publisher_replace_operation() {
         quickwit_common::setup_logging_for_tests();
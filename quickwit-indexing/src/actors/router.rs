@@ -0,0 +1,284 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+
+use quickwit_actors::{Actor, ActorContext, ActorExitStatus, Mailbox, QueueCapacity, SyncActor};
+use quickwit_metastore::checkpoint::Checkpoint;
+use tracing::warn;
+
+use crate::models::{route_doc, IndexerMessage, RawDocBatch, RoutingRule};
+
+/// Index the router sends documents to.
+pub struct RouterTarget {
+    pub index_id: String,
+    pub indexer_mailbox: Mailbox<IndexerMessage>,
+    /// Checkpoint of the index, which the router advances as it forwards batches.
+    pub checkpoint: Checkpoint,
+}
+
+/// The router splits the batches of a source across several indexes, according to a list of
+/// [`RoutingRule`].
+///
+/// Every target receives every batch, with only the documents routed to it, so that the
+/// checkpoints of all the targets move forward with the source. The source starts from the
+/// oldest checkpoint of the targets: the part of a batch that a target has already indexed is
+/// not sent to it again.
+pub struct Router {
+    routing_rules: Vec<RoutingRule>,
+    /// Index of the documents that no rule matches. It is also one of the targets.
+    default_index_id: String,
+    targets: Vec<RouterTarget>,
+}
+
+impl Router {
+    pub fn new(
+        routing_rules: Vec<RoutingRule>,
+        default_index_id: String,
+        targets: Vec<RouterTarget>,
+    ) -> Router {
+        Router {
+            routing_rules,
+            default_index_id,
+            targets,
+        }
+    }
+
+    fn process_batch(
+        &mut self,
+        batch: RawDocBatch,
+        ctx: &ActorContext<IndexerMessage>,
+    ) -> Result<(), ActorExitStatus> {
+        let mut docs_per_index: HashMap<&str, Vec<String>> = HashMap::new();
+        for doc in batch.docs {
+            let index_id = route_doc(&self.routing_rules, &self.default_index_id, &doc);
+            docs_per_index.entry(index_id).or_default().push(doc);
+        }
+        for target in &mut self.targets {
+            let checkpoint_delta = batch.checkpoint_delta.uncovered_by(&target.checkpoint);
+            if checkpoint_delta.is_empty() && !batch.checkpoint_delta.is_empty() {
+                // The target has already indexed this batch.
+                continue;
+            }
+            if checkpoint_delta != batch.checkpoint_delta {
+                warn!(index_id=%target.index_id, checkpoint=?target.checkpoint, delta=?batch.checkpoint_delta, "Batch partially indexed already: its documents may be indexed twice.");
+            }
+            target
+                .checkpoint
+                .try_apply_delta(checkpoint_delta.clone())
+                .map_err(anyhow::Error::from)?;
+            let docs = docs_per_index
+                .remove(target.index_id.as_str())
+                .unwrap_or_default();
+            // The skipped documents cannot be routed, they are counted by the default index.
+            let num_skipped_docs = if target.index_id == self.default_index_id {
+                batch.num_skipped_docs
+            } else {
+                0
+            };
+            let target_batch = RawDocBatch {
+                docs,
+                checkpoint_delta,
+                // The indexer of each index derives its own split ID from the one of the source.
+                split_id_opt: batch.split_id_opt.clone(),
+                received_timestamp: batch.received_timestamp,
+                num_skipped_docs,
+            };
+            ctx.send_message_blocking(&target.indexer_mailbox, target_batch.into())?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the oldest position of each partition across `checkpoints`: starting a source from
+/// there makes it emit all the partitions the targets have not indexed yet.
+///
+/// A partition missing from one of the checkpoints has not been indexed at all by that target,
+/// and is therefore left out.
+pub fn oldest_checkpoint(checkpoints: &[Checkpoint]) -> Checkpoint {
+    let (first_checkpoint, other_checkpoints) = match checkpoints.split_first() {
+        Some(split) => split,
+        None => return Checkpoint::default(),
+    };
+    first_checkpoint
+        .iter()
+        .filter_map(|(partition_id, position)| {
+            other_checkpoints
+                .iter()
+                .try_fold(position, |oldest_position, checkpoint| {
+                    checkpoint
+                        .position_for_partition(&partition_id)
+                        .map(|other_position| oldest_position.min(other_position.clone()))
+                })
+                .map(|oldest_position| (partition_id, oldest_position))
+        })
+        .collect()
+}
+
+impl Actor for Router {
+    type Message = IndexerMessage;
+
+    type ObservableState = ();
+
+    #[allow(clippy::unused_unit)]
+    fn observable_state(&self) -> Self::ObservableState {
+        ()
+    }
+
+    fn queue_capacity(&self) -> QueueCapacity {
+        QueueCapacity::Bounded(10)
+    }
+}
+
+impl SyncActor for Router {
+    fn process_message(
+        &mut self,
+        message: IndexerMessage,
+        ctx: &ActorContext<IndexerMessage>,
+    ) -> Result<(), ActorExitStatus> {
+        match message {
+            IndexerMessage::Batch(batch) => self.process_batch(batch, ctx),
+            // Commit timeouts are only sent by the indexers to themselves.
+            IndexerMessage::CommitTimeout { .. } => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use quickwit_actors::{create_test_mailbox, Universe};
+    use quickwit_metastore::checkpoint::{CheckpointDelta, PartitionId, Position};
+
+    use super::*;
+
+    fn batch_docs(message: &IndexerMessage) -> (Vec<String>, String) {
+        match message {
+            IndexerMessage::Batch(batch) => {
+                (batch.docs.clone(), format!("{:?}", batch.checkpoint_delta))
+            }
+            IndexerMessage::CommitTimeout { .. } => panic!("Expected a batch."),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_router() -> anyhow::Result<()> {
+        let universe = Universe::new();
+        let (logs_mailbox, logs_inbox) = create_test_mailbox();
+        let (errors_mailbox, errors_inbox) = create_test_mailbox();
+        let errors_checkpoint: Checkpoint = vec![(PartitionId::default(), Position::from(1u64))]
+            .into_iter()
+            .collect();
+        let router = Router::new(
+            vec![RoutingRule::from_str("level=error:errors")?],
+            "logs".to_string(),
+            vec![
+                RouterTarget {
+                    index_id: "logs".to_string(),
+                    indexer_mailbox: logs_mailbox,
+                    checkpoint: Checkpoint::default(),
+                },
+                RouterTarget {
+                    index_id: "errors".to_string(),
+                    indexer_mailbox: errors_mailbox,
+                    checkpoint: errors_checkpoint,
+                },
+            ],
+        );
+        let (router_mailbox, router_handle) = universe.spawn_actor(router).spawn_sync();
+        let info_doc = r#"{"level": "info"}"#.to_string();
+        let error_doc = r#"{"level": "error"}"#.to_string();
+        for (docs, checkpoint_delta) in vec![
+            (vec![info_doc.clone(), error_doc.clone()], 0..2),
+            (vec![info_doc.clone()], 2..3),
+            (vec![error_doc.clone()], 3..4),
+        ] {
+            let batch = RawDocBatch {
+                docs,
+                checkpoint_delta: CheckpointDelta::from(checkpoint_delta),
+                split_id_opt: None,
+                received_timestamp: 0,
+                num_skipped_docs: 0,
+            };
+            universe.send_message(&router_mailbox, batch.into()).await?;
+        }
+        router_handle.process_pending_and_observe().await;
+        let logs_batches: Vec<(Vec<String>, String)> = logs_inbox
+            .drain_available_message_for_test()
+            .iter()
+            .map(batch_docs)
+            .collect();
+        assert_eq!(
+            logs_batches,
+            vec![
+                (
+                    vec![info_doc.clone()],
+                    "∆(:(..00000000000000000001])".to_string()
+                ),
+                (
+                    vec![info_doc],
+                    "∆(:(00000000000000000001..00000000000000000002])".to_string()
+                ),
+                (
+                    Vec::new(),
+                    "∆(:(00000000000000000002..00000000000000000003])".to_string()
+                ),
+            ]
+        );
+        // The first batch was already indexed into `errors`.
+        let errors_batches: Vec<(Vec<String>, String)> = errors_inbox
+            .drain_available_message_for_test()
+            .iter()
+            .map(batch_docs)
+            .collect();
+        assert_eq!(
+            errors_batches,
+            vec![
+                (
+                    Vec::new(),
+                    "∆(:(00000000000000000001..00000000000000000002])".to_string()
+                ),
+                (
+                    vec![error_doc],
+                    "∆(:(00000000000000000002..00000000000000000003])".to_string()
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_oldest_checkpoint() {
+        let checkpoint = |positions: &[(&str, &str)]| -> Checkpoint {
+            positions
+                .iter()
+                .map(|(partition_id, position)| {
+                    (PartitionId::from(*partition_id), Position::from(*position))
+                })
+                .collect()
+        };
+        assert_eq!(format!("{:?}", oldest_checkpoint(&[])), "Ckpt()");
+        let oldest_checkpoint = oldest_checkpoint(&[
+            checkpoint(&[("a", "00012"), ("b", "00003"), ("c", "00001")]),
+            checkpoint(&[("a", "00010"), ("b", "00004")]),
+        ]);
+        assert_eq!(format!("{:?}", oldest_checkpoint), "Ckpt(a:00010 b:00003)");
+    }
+}
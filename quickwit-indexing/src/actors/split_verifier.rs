@@ -0,0 +1,210 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use quickwit_actors::{Actor, ActorContext, AsyncActor};
+use quickwit_metastore::{retry_on_transient_error, Metastore};
+use quickwit_storage::StorageUriResolver;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{run_split_verification, SplitVerificationStats};
+
+const RUN_INTERVAL: Duration = Duration::from_secs(60 * 60); // 1 hour
+const NUM_SPLITS_PER_PASS: usize = 10;
+
+/// Settings driving the split verifier actor.
+#[derive(Debug, Clone)]
+pub struct SplitVerifierParams {
+    /// Interval between two verification passes.
+    pub run_interval: Duration,
+    /// Maximum number of splits verified at each pass.
+    pub num_splits_per_pass: usize,
+}
+
+impl Default for SplitVerifierParams {
+    fn default() -> Self {
+        Self {
+            run_interval: RUN_INTERVAL,
+            num_splits_per_pass: NUM_SPLITS_PER_PASS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SplitVerifierCounters {
+    /// The number of passes the split verifier has performed.
+    pub num_passes: usize,
+    /// The number of verified splits.
+    pub num_verified_splits: usize,
+    /// The number of splits that could not be verified.
+    pub num_failed_splits: usize,
+    /// The number of published splits whose last verification detected a corruption, as of
+    /// the last successful pass.
+    pub num_corrupted_splits: usize,
+    /// The IDs of the splits found corrupted during the last successful pass.
+    pub last_corrupted_split_ids: Vec<String>,
+    /// The error that interrupted the last pass, if any.
+    pub last_error: Option<String>,
+}
+
+/// Periodically verifies the integrity of a sample of the published splits of an index, so that
+/// the corruption of the split files on the storage is detected before it is hit by searches.
+pub struct SplitVerifier {
+    index_id: String,
+    storage_resolver: StorageUriResolver,
+    metastore: Arc<dyn Metastore>,
+    params: SplitVerifierParams,
+    counters: SplitVerifierCounters,
+}
+
+impl SplitVerifier {
+    pub fn new(
+        index_id: String,
+        storage_resolver: StorageUriResolver,
+        metastore: Arc<dyn Metastore>,
+        params: SplitVerifierParams,
+    ) -> Self {
+        Self {
+            index_id,
+            storage_resolver,
+            metastore,
+            params,
+            counters: SplitVerifierCounters::default(),
+        }
+    }
+
+    async fn run_pass(&self) -> anyhow::Result<SplitVerificationStats> {
+        let index_metadata =
+            retry_on_transient_error(|| self.metastore.index_metadata(&self.index_id))
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to fetch the metadata of the index `{}`.",
+                        self.index_id
+                    )
+                })?;
+        let index_storage = self.storage_resolver.resolve_with_mirror(
+            &index_metadata.index_uri,
+            index_metadata.mirror_index_uri.as_deref(),
+        )?;
+        run_split_verification(
+            &self.index_id,
+            index_storage,
+            self.metastore.clone(),
+            self.params.num_splits_per_pass,
+        )
+        .await
+    }
+}
+
+impl Actor for SplitVerifier {
+    type Message = ();
+    type ObservableState = SplitVerifierCounters;
+
+    fn observable_state(&self) -> Self::ObservableState {
+        self.counters.clone()
+    }
+}
+
+#[async_trait]
+impl AsyncActor for SplitVerifier {
+    async fn initialize(
+        &mut self,
+        ctx: &ActorContext<Self::Message>,
+    ) -> Result<(), quickwit_actors::ActorExitStatus> {
+        self.process_message((), ctx).await
+    }
+
+    async fn process_message(
+        &mut self,
+        _: (),
+        ctx: &ActorContext<Self::Message>,
+    ) -> Result<(), quickwit_actors::ActorExitStatus> {
+        self.counters.num_passes += 1;
+        // A failed pass is recorded and retried at the next interval rather than
+        // killing the actor.
+        let run_pass_result = {
+            let _protected_zone_guard = ctx.protect_zone();
+            self.run_pass().await
+        };
+        match run_pass_result {
+            Ok(stats) => {
+                self.counters.num_verified_splits += stats.num_verified_splits;
+                self.counters.num_failed_splits += stats.num_failed_splits;
+                self.counters.num_corrupted_splits = stats.num_corrupted_splits;
+                self.counters.last_corrupted_split_ids = stats.corrupted_split_ids;
+                self.counters.last_error = None;
+            }
+            Err(error) => {
+                warn!(index = %self.index_id, error = ?error, "split-verification-failure");
+                self.counters.last_error = Some(format!("{:?}", error));
+            }
+        }
+        ctx.schedule_self_msg(self.params.run_interval, ()).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_actors::Universe;
+    use quickwit_index_config::WikipediaIndexConfig;
+    use serde_json::json;
+
+    use super::*;
+    use crate::TestSandbox;
+
+    #[tokio::test]
+    async fn test_split_verifier() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let index_id = "test-split-verifier-index";
+        let index_config = Arc::new(WikipediaIndexConfig::new());
+        let test_sandbox = TestSandbox::create(index_id, index_config).await?;
+        test_sandbox
+            .add_documents(vec![
+                json!({"title": "Hurricane Fay", "body": "...", "url": "http://hurricane-fay"}),
+            ])
+            .await?;
+
+        let universe = Universe::new();
+        let split_verifier = SplitVerifier::new(
+            index_id.to_string(),
+            test_sandbox.storage_uri_resolver(),
+            test_sandbox.metastore(),
+            SplitVerifierParams::default(),
+        );
+        let (_mailbox, handle) = universe.spawn_actor(split_verifier).spawn_async();
+        let counters = handle.process_pending_and_observe().await.state;
+        assert_eq!(counters.num_passes, 1);
+        assert_eq!(counters.num_verified_splits, 1);
+        assert_eq!(counters.num_corrupted_splits, 0);
+        assert_eq!(counters.last_error, None);
+
+        universe.simulate_time_shift(RUN_INTERVAL).await;
+        let counters = handle.process_pending_and_observe().await.state;
+        assert_eq!(counters.num_passes, 2);
+        assert_eq!(counters.num_verified_splits, 2);
+        Ok(())
+    }
+}
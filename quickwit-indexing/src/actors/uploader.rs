@@ -18,7 +18,7 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use std::mem;
-use std::path::PathBuf;
+use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
@@ -27,8 +27,9 @@ use anyhow::{bail, Context};
 use async_trait::async_trait;
 use fail::fail_point;
 use quickwit_actors::{Actor, ActorContext, ActorExitStatus, AsyncActor, Mailbox, QueueCapacity};
+use quickwit_index_config::SplitPathLayout;
 use quickwit_metastore::{Metastore, SplitMetadata, SplitMetadataAndFooterOffsets, SplitState};
-use quickwit_storage::{PutPayload, Storage, BUNDLE_FILENAME};
+use quickwit_storage::{multipart_file_storage, PutPayload, Storage, BUNDLE_FILENAME};
 use tantivy::chrono::Utc;
 use tokio::sync::oneshot::Receiver;
 use tracing::{info, warn};
@@ -44,6 +45,12 @@ pub struct Uploader {
     publisher_mailbox: Mailbox<Receiver<PublisherMessage>>,
     concurrent_upload_permits: Semaphore,
     counters: UploaderCounters,
+    /// Version of the index config the uploaded splits are built with.
+    index_config_version: u64,
+    /// Layout of the paths of the uploaded split files.
+    split_path_layout_opt: Option<SplitPathLayout>,
+    /// Size of the parts the split files larger than it are stored as.
+    split_part_num_bytes_opt: Option<u64>,
 }
 
 impl Uploader {
@@ -51,11 +58,17 @@ impl Uploader {
         metastore: Arc<dyn Metastore>,
         index_storage: Arc<dyn Storage>,
         publisher_mailbox: Mailbox<Receiver<PublisherMessage>>,
+        index_config_version: u64,
+        split_path_layout_opt: Option<SplitPathLayout>,
+        split_part_num_bytes_opt: Option<u64>,
     ) -> Uploader {
         Uploader {
             metastore,
             index_storage,
             publisher_mailbox,
+            index_config_version,
+            split_path_layout_opt,
+            split_part_num_bytes_opt,
             concurrent_upload_permits: Semaphore::new(MAX_CONCURRENT_SPLIT_UPLOAD),
             counters: Default::default(),
         }
@@ -86,16 +99,17 @@ impl Actor for Uploader {
 /// Upload all files within a single split to the storage
 async fn put_split_file_to_storage(
     split: &PackagedSplit,
+    split_file: &str,
     storage: &dyn Storage,
 ) -> anyhow::Result<()> {
     let bundle_path = split.split_scratch_directory.path().join(BUNDLE_FILENAME);
-    let key = PathBuf::from(format!("{}.split", &split.split_id));
+    let key = Path::new(split_file);
 
     let start = Instant::now();
 
     info!(bundle_path=%bundle_path.display(), split_id=%split.split_id, "upload-split-bundle");
     let payload = PutPayload::from(bundle_path);
-    storage.put(&key, payload).await.with_context(|| {
+    storage.put(key, payload).await.with_context(|| {
         format!(
             "Failed uploading key {} in bucket {}",
             key.display(),
@@ -117,7 +131,25 @@ async fn put_split_file_to_storage(
     Ok(())
 }
 
-fn create_split_metadata(split: &PackagedSplit) -> SplitMetadataAndFooterOffsets {
+fn create_split_metadata(
+    split: &PackagedSplit,
+    index_config_version: u64,
+    split_path_layout_opt: Option<&SplitPathLayout>,
+    split_part_num_bytes_opt: Option<u64>,
+) -> SplitMetadataAndFooterOffsets {
+    let update_timestamp = Utc::now().timestamp();
+    // Splits are placed at the date of their oldest document, if they have a time range.
+    let split_file_path = split_path_layout_opt.map(|split_path_layout| {
+        let timestamp = split
+            .time_range
+            .as_ref()
+            .map(|time_range| *time_range.start())
+            .unwrap_or(update_timestamp);
+        split_path_layout.split_file_path(&split.split_id, timestamp)
+    });
+    // Only the split files larger than a part are stored in parts.
+    let split_part_num_bytes = split_part_num_bytes_opt
+        .filter(|part_num_bytes| split.footer_offsets.end > *part_num_bytes);
     SplitMetadataAndFooterOffsets {
         split_metadata: SplitMetadata {
             split_id: split.split_id.clone(),
@@ -125,8 +157,13 @@ fn create_split_metadata(split: &PackagedSplit) -> SplitMetadataAndFooterOffsets
             time_range: split.time_range.clone(),
             size_in_bytes: split.size_in_bytes,
             split_state: SplitState::New,
-            update_timestamp: Utc::now().timestamp(),
+            update_timestamp,
             tags: split.tags.clone(),
+            index_config_version,
+            split_file_path,
+            ingest_timestamps: split.ingest_timestamps.clone(),
+            split_part_num_bytes,
+            last_verification: None,
         },
         footer_offsets: split.footer_offsets.clone(),
     }
@@ -154,11 +191,19 @@ fn make_publish_operation(
 
 async fn stage_and_upload_split(
     packaged_split: PackagedSplit,
-    index_storage: &dyn Storage,
+    index_storage: Arc<dyn Storage>,
     metastore: &dyn Metastore,
     counters: UploaderCounters,
+    index_config_version: u64,
+    split_path_layout_opt: Option<&SplitPathLayout>,
+    split_part_num_bytes_opt: Option<u64>,
 ) -> anyhow::Result<PublisherMessage> {
-    let split_metadata_and_footer_offsets = create_split_metadata(&packaged_split);
+    let split_metadata_and_footer_offsets = create_split_metadata(
+        &packaged_split,
+        index_config_version,
+        split_path_layout_opt,
+        split_part_num_bytes_opt,
+    );
     let index_id = packaged_split.index_id.clone();
     let split_metadata = split_metadata_and_footer_offsets.split_metadata.clone();
     info!(split_id=%packaged_split.split_id, "staging-split");
@@ -166,7 +211,13 @@ async fn stage_and_upload_split(
         .stage_split(&index_id, split_metadata_and_footer_offsets)
         .await?;
     counters.num_staged_splits.fetch_add(1, Ordering::SeqCst);
-    put_split_file_to_storage(&packaged_split, &*index_storage).await?;
+    let split_storage = multipart_file_storage(index_storage, split_metadata.split_part_num_bytes);
+    put_split_file_to_storage(
+        &packaged_split,
+        &split_metadata.split_file(),
+        &*split_storage,
+    )
+    .await?;
     counters.num_uploaded_splits.fetch_add(1, Ordering::SeqCst);
     let publish_operation = make_publish_operation(split_metadata, packaged_split);
     Ok(PublisherMessage {
@@ -213,22 +264,31 @@ impl AsyncActor for Uploader {
         let index_storage = self.index_storage.clone();
 
         let counters = self.counters.clone();
+        let index_config_version = self.index_config_version;
+        let split_path_layout_opt = self.split_path_layout_opt.clone();
+        let split_part_num_bytes_opt = self.split_part_num_bytes_opt;
 
         tokio::spawn(async move {
             fail_point!("uploader:intask:before");
-            let stage_and_upload_res: anyhow::Result<()> =
-                stage_and_upload_split(split, &*index_storage, &*metastore, counters)
-                    .await
-                    .and_then(|publisher_message| {
-                        if let Err(publisher_message) = split_uploaded_tx.send(publisher_message) {
-                            bail!(
-                                "Failed to send upload split `{:?}`. The publisher is probably \
-                                 dead.",
-                                &publisher_message
-                            );
-                        }
-                        Ok(())
-                    });
+            let stage_and_upload_res: anyhow::Result<()> = stage_and_upload_split(
+                split,
+                index_storage,
+                &*metastore,
+                counters,
+                index_config_version,
+                split_path_layout_opt.as_ref(),
+                split_part_num_bytes_opt,
+            )
+            .await
+            .and_then(|publisher_message| {
+                if let Err(publisher_message) = split_uploaded_tx.send(publisher_message) {
+                    bail!(
+                        "Failed to send upload split `{:?}`. The publisher is probably dead.",
+                        &publisher_message
+                    );
+                }
+                Ok(())
+            });
             if let Err(cause) = stage_and_upload_res {
                 warn!(cause=%cause, "Failed to upload split. Killing!");
                 kill_switch.kill();
@@ -244,6 +304,8 @@ impl AsyncActor for Uploader {
 
 #[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
+
     use quickwit_actors::{create_test_mailbox, ObservationType, Universe};
     use quickwit_metastore::checkpoint::CheckpointDelta;
     use quickwit_metastore::MockMetastore;
@@ -270,7 +332,14 @@ mod tests {
             .returning(|_, _| Ok(()));
         let ram_storage = RamStorage::default();
         let index_storage: Arc<dyn Storage> = Arc::new(ram_storage.clone());
-        let uploader = Uploader::new(Arc::new(mock_metastore), index_storage.clone(), mailbox);
+        let uploader = Uploader::new(
+            Arc::new(mock_metastore),
+            index_storage.clone(),
+            mailbox,
+            0,
+            None,
+            None,
+        );
         let (uploader_mailbox, uploader_handle) = universe.spawn_actor(uploader).spawn_async();
         let split_scratch_directory = ScratchDirectory::try_new_temp()?;
         std::fs::write(
@@ -292,6 +361,7 @@ mod tests {
                     tags: Default::default(),
                     replaced_split_ids: Vec::new(),
                     split_date_of_birth: Instant::now(),
+                    ingest_timestamps: None,
                 },
             )
             .await?;
@@ -321,6 +391,147 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_uploader_with_split_path_layout() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let universe = Universe::new();
+        let (mailbox, inbox) = create_test_mailbox();
+        let mut mock_metastore = MockMetastore::default();
+        mock_metastore
+            .expect_stage_split()
+            .withf(move |index_id, metadata| -> bool {
+                (index_id == "test-index")
+                    && &metadata.split_metadata.split_id == "test-split"
+                    && metadata.split_metadata.time_range == Some(1628203589..=1628203640)
+                    && metadata.split_metadata.split_file_path.as_deref()
+                        == Some("2021/08/05/test-split.split")
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let ram_storage = RamStorage::default();
+        let index_storage: Arc<dyn Storage> = Arc::new(ram_storage.clone());
+        let uploader = Uploader::new(
+            Arc::new(mock_metastore),
+            index_storage.clone(),
+            mailbox,
+            0,
+            Some("{{year}}/{{month}}/{{day}}/{{split_id}}.split".parse()?),
+            None,
+        );
+        let (uploader_mailbox, uploader_handle) = universe.spawn_actor(uploader).spawn_async();
+        let split_scratch_directory = ScratchDirectory::try_new_temp()?;
+        std::fs::write(
+            split_scratch_directory.path().join(BUNDLE_FILENAME),
+            &b"bubu"[..],
+        )?;
+        universe
+            .send_message(
+                &uploader_mailbox,
+                PackagedSplit {
+                    split_id: "test-split".to_string(),
+                    index_id: "test-index".to_string(),
+                    checkpoint_deltas: vec![CheckpointDelta::from(3..15)],
+                    time_range: Some(1_628_203_589i64..=1_628_203_640i64),
+                    size_in_bytes: 1_000,
+                    footer_offsets: 1000..2000,
+                    split_scratch_directory,
+                    num_docs: 10,
+                    tags: Default::default(),
+                    replaced_split_ids: Vec::new(),
+                    split_date_of_birth: Instant::now(),
+                    ingest_timestamps: None,
+                },
+            )
+            .await?;
+        assert_eq!(
+            uploader_handle.process_pending_and_observe().await.obs_type,
+            ObservationType::Alive
+        );
+        let publish_futures = inbox.drain_available_message_for_test();
+        assert_eq!(publish_futures.len(), 1);
+        publish_futures.into_iter().next().unwrap().await?;
+        let mut files = ram_storage.list_files().await;
+        files.sort();
+        assert_eq!(&files, &[PathBuf::from("2021/08/05/test-split.split")]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_uploader_with_split_parts() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let universe = Universe::new();
+        let (mailbox, inbox) = create_test_mailbox();
+        let mut mock_metastore = MockMetastore::default();
+        mock_metastore
+            .expect_stage_split()
+            .withf(move |index_id, metadata| -> bool {
+                (index_id == "test-index")
+                    && &metadata.split_metadata.split_id == "test-split"
+                    && metadata.split_metadata.time_range == Some(1628203589..=1628203640)
+                    && metadata.split_metadata.split_part_num_bytes == Some(3)
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let ram_storage = RamStorage::default();
+        let index_storage: Arc<dyn Storage> = Arc::new(ram_storage.clone());
+        let uploader = Uploader::new(
+            Arc::new(mock_metastore),
+            index_storage.clone(),
+            mailbox,
+            0,
+            None,
+            Some(3),
+        );
+        let (uploader_mailbox, uploader_handle) = universe.spawn_actor(uploader).spawn_async();
+        let split_scratch_directory = ScratchDirectory::try_new_temp()?;
+        std::fs::write(
+            split_scratch_directory.path().join(BUNDLE_FILENAME),
+            &b"bubu"[..],
+        )?;
+        universe
+            .send_message(
+                &uploader_mailbox,
+                PackagedSplit {
+                    split_id: "test-split".to_string(),
+                    index_id: "test-index".to_string(),
+                    checkpoint_deltas: vec![CheckpointDelta::from(3..15)],
+                    time_range: Some(1_628_203_589i64..=1_628_203_640i64),
+                    size_in_bytes: 1_000,
+                    footer_offsets: 0..4,
+                    split_scratch_directory,
+                    num_docs: 10,
+                    tags: Default::default(),
+                    replaced_split_ids: Vec::new(),
+                    split_date_of_birth: Instant::now(),
+                    ingest_timestamps: None,
+                },
+            )
+            .await?;
+        assert_eq!(
+            uploader_handle.process_pending_and_observe().await.obs_type,
+            ObservationType::Alive
+        );
+        let publish_futures = inbox.drain_available_message_for_test();
+        assert_eq!(publish_futures.len(), 1);
+        publish_futures.into_iter().next().unwrap().await?;
+        let mut files = ram_storage.list_files().await;
+        files.sort();
+        assert_eq!(
+            &files,
+            &[
+                PathBuf::from("test-split.split.part-00000"),
+                PathBuf::from("test-split.split.part-00001")
+            ]
+        );
+        assert_eq!(
+            &ram_storage
+                .get_all(Path::new("test-split.split.part-00001"))
+                .await?[..],
+            b"u"
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_uploader_emits_replace() -> anyhow::Result<()> {
         quickwit_common::setup_logging_for_tests();
@@ -339,7 +550,14 @@ mod tests {
             .returning(|_, _| Ok(()));
         let ram_storage = RamStorage::default();
         let index_storage: Arc<dyn Storage> = Arc::new(ram_storage.clone());
-        let uploader = Uploader::new(Arc::new(mock_metastore), index_storage.clone(), mailbox);
+        let uploader = Uploader::new(
+            Arc::new(mock_metastore),
+            index_storage.clone(),
+            mailbox,
+            0,
+            None,
+            None,
+        );
         let (uploader_mailbox, uploader_handle) = universe.spawn_actor(uploader).spawn_async();
         let split_scratch_directory = ScratchDirectory::try_new_temp()?;
         std::fs::write(
@@ -371,6 +589,7 @@ mod tests {
                         "replaced-split-2".to_string(),
                     ],
                     split_date_of_birth: Instant::now(),
+                    ingest_timestamps: None,
                 },
             )
             .await?;
@@ -17,15 +17,18 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::HashSet;
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
 use futures::StreamExt;
+use quickwit_common::global_split_leases;
 use quickwit_metastore::{Metastore, SplitMetadataAndFooterOffsets, SplitState};
-use quickwit_storage::Storage;
+use quickwit_storage::{multipart_file_storage, Storage};
+use serde::Serialize;
 use tantivy::chrono::Utc;
-use tracing::warn;
+use tracing::{info, warn};
 
 const MAX_CONCURRENT_STORAGE_REQUESTS: usize = if cfg!(test) { 2 } else { 10 };
 
@@ -36,22 +39,28 @@ pub struct SplitDeletionStats {
     pub candidate_entries: Vec<FileEntry>,
     /// Entries that were successfully deleted.
     pub deleted_entries: Vec<FileEntry>,
+    /// Entries that were not deleted because their split is currently being searched.
+    pub leased_entries: Vec<FileEntry>,
 }
 
 #[allow(missing_docs)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct FileEntry {
     /// The file_name is a file name, within an index directory.
     pub file_name: String,
     /// File size in bytes.
     pub file_size_in_bytes: u64, //< TODO switch to `byte_unit::Byte`.
+    /// Size of the parts the file is stored as, if it is stored as several objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split_part_num_bytes: Option<u64>,
 }
 
 impl From<&SplitMetadataAndFooterOffsets> for FileEntry {
     fn from(split: &SplitMetadataAndFooterOffsets) -> Self {
         FileEntry {
-            file_name: quickwit_common::split_file(&split.split_metadata.split_id),
+            file_name: split.split_metadata.split_file(),
             file_size_in_bytes: split.footer_offsets.end,
+            split_part_num_bytes: split.split_metadata.split_part_num_bytes,
         }
     }
 }
@@ -71,7 +80,9 @@ pub async fn run_garbage_collect(
     dry_run: bool,
 ) -> anyhow::Result<SplitDeletionStats> {
     // Select staged splits with staging timestamp older than grace period timestamp.
-    let grace_period_timestamp = Utc::now().timestamp() - grace_period.as_secs() as i64;
+    // The staging timestamps are assigned by the metastore clock, so the grace period is measured
+    // with that clock rather than with the clock of this node, which may be skewed.
+    let grace_period_timestamp = metastore.now_timestamp().await? - grace_period.as_secs() as i64;
     let deletable_staged_splits: Vec<SplitMetadataAndFooterOffsets> = metastore
         .list_splits(index_id, SplitState::Staged, None, &[])
         .await?
@@ -120,6 +131,45 @@ pub async fn run_garbage_collect(
     Ok(deleted_files)
 }
 
+/// Marks for deletion the published and quarantined splits whose time range lies entirely
+/// before `now - retention_period`.
+///
+/// Splits without a time range are never considered expired.
+/// Returns the IDs of the splits that were marked for deletion.
+pub async fn mark_expired_splits_for_deletion(
+    index_id: &str,
+    metastore: Arc<dyn Metastore>,
+    retention_period: Duration,
+) -> anyhow::Result<Vec<String>> {
+    let retention_timestamp = Utc::now().timestamp() - retention_period.as_secs() as i64;
+    let mut expired_split_ids: Vec<String> = Vec::new();
+    for split_state in [SplitState::Published, SplitState::Quarantined] {
+        let splits = metastore
+            .list_splits(index_id, split_state, None, &[])
+            .await?;
+        expired_split_ids.extend(
+            splits
+                .into_iter()
+                .filter(|meta| {
+                    meta.split_metadata
+                        .time_range
+                        .as_ref()
+                        .map(|time_range| *time_range.end() < retention_timestamp)
+                        .unwrap_or(false)
+                })
+                .map(|meta| meta.split_metadata.split_id),
+        );
+    }
+    if expired_split_ids.is_empty() {
+        return Ok(expired_split_ids);
+    }
+    let split_ids: Vec<&str> = expired_split_ids.iter().map(String::as_str).collect();
+    metastore
+        .mark_splits_for_deletion(index_id, &split_ids)
+        .await?;
+    Ok(expired_split_ids)
+}
+
 /// Delete a list of splits from the storage and the metastore.
 /// It should leave the index and the metastore in good state.
 ///
@@ -127,6 +177,10 @@ pub async fn run_garbage_collect(
 /// * `storage - The storage managing the target index.
 /// * `metastore` - The metastore managing the target index.
 /// * `splits`  - The list of splits to delete.
+///
+/// Splits leased by a searcher, either of this process (see [`quickwit_common::SplitLeases`])
+/// or of any node through the metastore (see [`Metastore::list_leased_split_ids`]), are left
+/// untouched, so that they can be garbage collected once the search is over.
 pub async fn delete_splits_with_files(
     index_id: &str,
     storage: Arc<dyn Storage>,
@@ -134,14 +188,35 @@ pub async fn delete_splits_with_files(
     splits: Vec<SplitMetadataAndFooterOffsets>,
 ) -> anyhow::Result<SplitDeletionStats> {
     let mut deletion_stats = SplitDeletionStats::default();
+    if splits.is_empty() {
+        return Ok(deletion_stats);
+    }
+    let remotely_leased_split_ids: HashSet<String> = metastore
+        .list_leased_split_ids(index_id)
+        .await?
+        .into_iter()
+        .collect();
+    let (leased_splits, splits): (Vec<_>, Vec<_>) = splits.into_iter().partition(|meta| {
+        let split_id = &meta.split_metadata.split_id;
+        global_split_leases().is_leased(split_id) || remotely_leased_split_ids.contains(split_id)
+    });
+    if !leased_splits.is_empty() {
+        let leased_split_ids: Vec<&str> = leased_splits
+            .iter()
+            .map(|meta| meta.split_metadata.split_id.as_str())
+            .collect();
+        info!(splits=?leased_split_ids, "Skipping deletion of splits currently being searched.");
+        deletion_stats.leased_entries = leased_splits.iter().map(FileEntry::from).collect();
+    }
     let mut deleted_split_ids: Vec<String> = Vec::new();
     let mut failed_split_ids: Vec<String> = Vec::new();
 
     let mut delete_splits_results_stream = tokio_stream::iter(splits.into_iter())
         .map(|meta| {
-            let moved_storage = storage.clone();
+            let file_entry = FileEntry::from(&meta);
+            let moved_storage =
+                multipart_file_storage(storage.clone(), file_entry.split_part_num_bytes);
             async move {
-                let file_entry = FileEntry::from(&meta);
                 let delete_result = moved_storage.delete(Path::new(&file_entry.file_name)).await;
                 (
                     meta.split_metadata.split_id.clone(),
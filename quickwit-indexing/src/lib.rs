@@ -21,6 +21,7 @@ use std::sync::Arc;
 
 use anyhow::bail;
 use quickwit_actors::Universe;
+use quickwit_metastore::checkpoint::CheckpointDelta;
 use quickwit_metastore::Metastore;
 use quickwit_storage::StorageUriResolver;
 
@@ -34,14 +35,20 @@ mod merge_policy;
 pub mod models;
 pub(crate) mod semaphore;
 pub mod source;
+mod split_verification;
 mod test_utils;
 
 pub use test_utils::{mock_split_meta, TestSandbox};
 
 pub use self::garbage_collection::{
-    delete_splits_with_files, run_garbage_collect, FileEntry, SplitDeletionStats,
+    delete_splits_with_files, mark_expired_splits_for_deletion, run_garbage_collect, FileEntry,
+    SplitDeletionStats,
 };
 pub use self::merge_policy::{MergePolicy, StableMultitenantWithTimestampMergePolicy};
+pub use self::split_verification::{
+    run_split_verification, select_splits_to_verify, verify_quarantined_splits, verify_split_file,
+    SplitVerificationStats,
+};
 
 pub async fn index_data(
     index_id: String,
@@ -57,6 +64,8 @@ pub async fn index_data(
         indexer_params,
         metastore,
         storage_uri_resolver,
+        routing_rules: Vec::new(),
+        source_sharding_opt: None,
     };
     let indexing_supervisor = IndexingPipelineSupervisor::new(indexing_pipeline_params);
     let (_pipeline_mailbox, pipeline_handler) =
@@ -68,6 +77,200 @@ pub async fn index_data(
     Ok(statistics)
 }
 
-pub(crate) fn new_split_id() -> String {
+/// Generates a new, unique split ID.
+pub fn new_split_id() -> String {
     ulid::Ulid::new().to_string()
 }
+
+/// Derives a split ID from the checkpoint delta of the first batch of a split.
+///
+/// Unlike [`new_split_id`], building the same split twice yields the same ID, which
+/// makes staging and publishing the split idempotent.
+///
+/// The ID is the hash of the partition IDs and positions of the delta, each prefixed
+/// by its length so that no two deltas share the same serialization. Watermarks are
+/// left out, as they do not identify the documents of the split.
+pub fn split_id_from_checkpoint_delta(checkpoint_delta: &CheckpointDelta) -> String {
+    let mut context = md5::Context::new();
+    for (partition_id, from_position, to_position) in checkpoint_delta.iter() {
+        for component in &[
+            partition_id.0.as_str(),
+            from_position.as_str(),
+            to_position.as_str(),
+        ] {
+            context.consume((component.len() as u64).to_le_bytes());
+            context.consume(component.as_bytes());
+        }
+    }
+    format!("{:x}", context.compute())
+}
+
+/// Derives the ID of a split of the index `index_id` from the split ID provided by its
+/// source, e.g. with [`split_id_from_checkpoint_delta`].
+///
+/// The split IDs provided by the sources only depend on their positions, which are the same
+/// for two indexes fed the same input, or for an index deleted and created again with the
+/// same ID. The searchers cache the split footers and files by split ID, so the ID of the
+/// index and its creation timestamp are hashed along, each prefixed by its length.
+pub fn index_split_id(
+    index_id: &str,
+    index_create_timestamp: i64,
+    source_split_id: &str,
+) -> String {
+    let mut context = md5::Context::new();
+    context.consume((index_id.len() as u64).to_le_bytes());
+    context.consume(index_id.as_bytes());
+    context.consume(index_create_timestamp.to_le_bytes());
+    context.consume((source_split_id.len() as u64).to_le_bytes());
+    context.consume(source_split_id.as_bytes());
+    format!("{:x}", context.compute())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use quickwit_common::QuickwitUri;
+    use quickwit_metastore::checkpoint::{CheckpointDelta, PartitionId, Position};
+    use quickwit_metastore::{IndexMetadata, Metastore, MetastoreUriResolver};
+    use quickwit_storage::StorageUriResolver;
+    use serde_json::json;
+
+    use super::{index_data, index_split_id, split_id_from_checkpoint_delta};
+    use crate::actors::IndexerParams;
+    use crate::source::SourceConfig;
+
+    #[test]
+    fn test_split_id_from_checkpoint_delta() {
+        let split_id = split_id_from_checkpoint_delta(&CheckpointDelta::from(0..10));
+        assert_eq!(split_id.len(), 32);
+        assert_eq!(
+            split_id,
+            split_id_from_checkpoint_delta(&CheckpointDelta::from(0..10))
+        );
+        assert_ne!(
+            split_id,
+            split_id_from_checkpoint_delta(&CheckpointDelta::from(10..20))
+        );
+        // Partition IDs containing the separators of the debug representation do not collide.
+        let delta = CheckpointDelta::from_partition_delta(
+            PartitionId::from("a:(b..c] d"),
+            Position::from("e"),
+            Position::from("f"),
+        );
+        let other_delta = {
+            let mut delta = CheckpointDelta::from_partition_delta(
+                PartitionId::from("a"),
+                Position::from("b"),
+                Position::from("c"),
+            );
+            delta
+                .record_partition_delta(
+                    PartitionId::from("d"),
+                    Position::from("e"),
+                    Position::from("f"),
+                )
+                .unwrap();
+            delta
+        };
+        assert_eq!(format!("{:?}", delta), format!("{:?}", other_delta));
+        assert_ne!(
+            split_id_from_checkpoint_delta(&delta),
+            split_id_from_checkpoint_delta(&other_delta)
+        );
+    }
+
+    #[test]
+    fn test_index_split_id() {
+        let split_id = index_split_id("my-index", 1_600_000_000, "source-split");
+        assert_eq!(split_id.len(), 32);
+        assert_eq!(
+            split_id,
+            index_split_id("my-index", 1_600_000_000, "source-split")
+        );
+        assert_ne!(
+            split_id,
+            index_split_id("other-index", 1_600_000_000, "source-split")
+        );
+        assert_ne!(
+            split_id,
+            index_split_id("my-index", 1_600_000_001, "source-split")
+        );
+        assert_ne!(
+            index_split_id("my-index", 0, "a-source-split"),
+            index_split_id("my-index-a", 0, "-source-split")
+        );
+    }
+
+    async fn create_index(
+        metastore: &dyn Metastore,
+        index_id: &str,
+        create_timestamp: i64,
+    ) -> anyhow::Result<()> {
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_str(&format!("ram://indexes/{}", index_id))?,
+            index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+            checkpoint: Default::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp,
+        };
+        metastore.create_index(index_metadata).await?;
+        Ok(())
+    }
+
+    async fn index_test_corpus(
+        metastore: Arc<dyn Metastore>,
+        index_id: &str,
+    ) -> anyhow::Result<HashSet<String>> {
+        let source_config = SourceConfig {
+            source_id: "test-source".to_string(),
+            source_type: "file".to_string(),
+            params: json!({ "filepath": PathBuf::from("data/test_corpus.json") }),
+        };
+        let statistics = index_data(
+            index_id.to_string(),
+            metastore.clone(),
+            IndexerParams::for_test()?,
+            source_config,
+            StorageUriResolver::for_test(),
+        )
+        .await?;
+        assert_eq!(statistics.num_published_splits, 1);
+        let split_ids = metastore
+            .list_all_splits(index_id)
+            .await?
+            .into_iter()
+            .map(|split| split.split_metadata.split_id)
+            .collect();
+        Ok(split_ids)
+    }
+
+    #[tokio::test]
+    async fn test_index_data_split_ids_are_unique_across_indexes() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let metastore = MetastoreUriResolver::default()
+            .resolve("ram://test-split-ids-across-indexes")
+            .await?;
+        create_index(&*metastore, "index-a", 1_600_000_000).await?;
+        create_index(&*metastore, "index-b", 1_600_000_000).await?;
+        let split_ids_a = index_test_corpus(metastore.clone(), "index-a").await?;
+        let split_ids_b = index_test_corpus(metastore.clone(), "index-b").await?;
+        assert_eq!(split_ids_a.len(), 1);
+        assert!(split_ids_a.is_disjoint(&split_ids_b));
+
+        // The same file fed to an index deleted and created again yields new splits.
+        metastore.delete_index("index-a").await?;
+        create_index(&*metastore, "index-a", 1_600_000_001).await?;
+        let new_split_ids_a = index_test_corpus(metastore.clone(), "index-a").await?;
+        assert!(split_ids_a.is_disjoint(&new_split_ids_a));
+        Ok(())
+    }
+}
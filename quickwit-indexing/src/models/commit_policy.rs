@@ -19,6 +19,11 @@
 
 const DEFAULT_COMMIT_TIMEOUT: Duration = Duration::from_secs(30);
 const DEFAULT_NUM_DOCS_COMMIT_THRESHOLD: u64 = 10_000_000;
+const DEFAULT_MIN_COMMIT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_COMMIT_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Weight of the most recent split in the estimated ingest rate.
+const INGEST_RATE_SMOOTHING: f64 = 0.5;
 
 use std::time::Duration;
 
@@ -26,6 +31,9 @@ use std::time::Duration;
 pub struct CommitPolicy {
     pub timeout: Duration,
     pub num_docs_threshold: u64,
+    /// If set, the timeout of each split is derived from the ingest rate, and `timeout` is
+    /// only used until the rate is known.
+    pub adaptive_opt: Option<AdaptiveCommitPolicy>,
 }
 
 impl Default for CommitPolicy {
@@ -33,6 +41,151 @@ impl Default for CommitPolicy {
         CommitPolicy {
             timeout: DEFAULT_COMMIT_TIMEOUT,
             num_docs_threshold: DEFAULT_NUM_DOCS_COMMIT_THRESHOLD,
+            adaptive_opt: None,
+        }
+    }
+}
+
+/// Targets splits of a stable number of documents, whatever the ingest rate.
+///
+/// The timeout of a new split is the time expected to receive `target_num_docs` documents
+/// at the rate observed on the previous splits, within `[min_timeout, max_timeout]`. A
+/// split reaching `target_num_docs` documents is committed right away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdaptiveCommitPolicy {
+    pub target_num_docs: u64,
+    pub min_timeout: Duration,
+    pub max_timeout: Duration,
+}
+
+impl AdaptiveCommitPolicy {
+    /// Targets splits of `target_num_docs` documents, with the default timeout bounds.
+    pub fn new(target_num_docs: u64) -> Self {
+        AdaptiveCommitPolicy {
+            target_num_docs,
+            min_timeout: DEFAULT_MIN_COMMIT_TIMEOUT,
+            max_timeout: DEFAULT_MAX_COMMIT_TIMEOUT,
+        }
+    }
+}
+
+impl CommitPolicy {
+    /// Returns the timeout of a new split, given the ingest rate observed so far.
+    pub fn commit_timeout(&self, ingest_rate: &IngestRate) -> Duration {
+        let adaptive = if let Some(adaptive) = self.adaptive_opt.as_ref() {
+            adaptive
+        } else {
+            return self.timeout;
+        };
+        let timeout = match ingest_rate.docs_per_sec_opt {
+            Some(docs_per_sec) if docs_per_sec > 0.0 => {
+                let timeout_secs = adaptive.target_num_docs as f64 / docs_per_sec;
+                Duration::from_secs_f64(timeout_secs.min(adaptive.max_timeout.as_secs_f64()))
+            }
+            Some(_) => adaptive.max_timeout,
+            None => self.timeout,
+        };
+        timeout.max(adaptive.min_timeout).min(adaptive.max_timeout)
+    }
+
+    /// Returns the number of documents from which the current split is committed.
+    pub fn effective_num_docs_threshold(&self) -> u64 {
+        match self.adaptive_opt.as_ref() {
+            Some(adaptive) => self.num_docs_threshold.min(adaptive.target_num_docs),
+            None => self.num_docs_threshold,
         }
     }
 }
+
+/// Ingest rate of an indexer, estimated from the splits it emitted.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IngestRate {
+    docs_per_sec_opt: Option<f64>,
+}
+
+impl IngestRate {
+    /// Records a split of `num_docs` documents, received over `elapsed`.
+    pub fn record_split(&mut self, num_docs: u64, elapsed: Duration) {
+        if elapsed.as_secs_f64() <= 0.0 {
+            return;
+        }
+        let split_docs_per_sec = num_docs as f64 / elapsed.as_secs_f64();
+        let docs_per_sec = match self.docs_per_sec_opt {
+            Some(docs_per_sec) => {
+                INGEST_RATE_SMOOTHING * split_docs_per_sec
+                    + (1.0 - INGEST_RATE_SMOOTHING) * docs_per_sec
+            }
+            None => split_docs_per_sec,
+        };
+        self.docs_per_sec_opt = Some(docs_per_sec);
+    }
+
+    /// Returns the estimated number of documents received per second, if a split was
+    /// recorded.
+    pub fn docs_per_sec(&self) -> Option<f64> {
+        self.docs_per_sec_opt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_policy_commit_timeout() {
+        let commit_policy = CommitPolicy::default();
+        let mut ingest_rate = IngestRate::default();
+        assert_eq!(
+            commit_policy.commit_timeout(&ingest_rate),
+            DEFAULT_COMMIT_TIMEOUT
+        );
+        ingest_rate.record_split(1_000, Duration::from_secs(1));
+        assert_eq!(
+            commit_policy.commit_timeout(&ingest_rate),
+            DEFAULT_COMMIT_TIMEOUT
+        );
+        assert_eq!(
+            commit_policy.effective_num_docs_threshold(),
+            DEFAULT_NUM_DOCS_COMMIT_THRESHOLD
+        );
+
+        let commit_policy = CommitPolicy {
+            adaptive_opt: Some(AdaptiveCommitPolicy {
+                target_num_docs: 60_000,
+                min_timeout: Duration::from_secs(10),
+                max_timeout: Duration::from_secs(600),
+            }),
+            ..Default::default()
+        };
+        let mut ingest_rate = IngestRate::default();
+        assert_eq!(commit_policy.effective_num_docs_threshold(), 60_000);
+        assert_eq!(
+            commit_policy.commit_timeout(&ingest_rate),
+            DEFAULT_COMMIT_TIMEOUT
+        );
+        ingest_rate.record_split(1_000, Duration::from_secs(1));
+        assert_eq!(
+            commit_policy.commit_timeout(&ingest_rate),
+            Duration::from_secs(60)
+        );
+        // Peak traffic.
+        ingest_rate.record_split(1_000_000, Duration::from_secs(10));
+        assert_eq!(ingest_rate.docs_per_sec(), Some(50_500.0));
+        assert_eq!(
+            commit_policy.commit_timeout(&ingest_rate),
+            Duration::from_secs(10)
+        );
+        // Quiet night.
+        let mut ingest_rate = IngestRate::default();
+        ingest_rate.record_split(10, Duration::from_secs(60));
+        assert_eq!(
+            commit_policy.commit_timeout(&ingest_rate),
+            Duration::from_secs(600)
+        );
+        ingest_rate.record_split(1, Duration::from_secs(0));
+        assert_eq!(
+            commit_policy.commit_timeout(&ingest_rate),
+            Duration::from_secs(600)
+        );
+    }
+}
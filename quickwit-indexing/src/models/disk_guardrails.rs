@@ -0,0 +1,115 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::path::Path;
+use std::{fs, io};
+
+use byte_unit::Byte;
+
+/// Limits checked by the indexer before starting a new split in its scratch directory.
+///
+/// Without them, an indexer that cannot upload its splits fast enough fills the disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiskGuardrails {
+    /// Maximum number of bytes used by the files of the scratch directory.
+    pub max_disk_usage: Option<Byte>,
+    /// Minimum number of bytes that must be available on the disk of the scratch directory.
+    pub min_free_disk_space: Option<Byte>,
+}
+
+impl DiskGuardrails {
+    /// Returns an error if starting a new split in `directory` would break one of the limits.
+    pub fn check(&self, directory: &Path) -> anyhow::Result<()> {
+        if let Some(max_disk_usage) = self.max_disk_usage {
+            let disk_usage = disk_usage(directory)?;
+            if disk_usage >= max_disk_usage.get_bytes() as u64 {
+                anyhow::bail!(
+                    "The scratch directory `{}` uses {} bytes, which reaches the maximum disk \
+                     usage of {} bytes.",
+                    directory.display(),
+                    disk_usage,
+                    max_disk_usage.get_bytes()
+                );
+            }
+        }
+        if let Some(min_free_disk_space) = self.min_free_disk_space {
+            let free_disk_space = fs2::available_space(directory)?;
+            if free_disk_space < min_free_disk_space.get_bytes() as u64 {
+                anyhow::bail!(
+                    "Only {} bytes are available on the disk of the scratch directory `{}`, which \
+                     is below the minimum free disk space of {} bytes.",
+                    free_disk_space,
+                    directory.display(),
+                    min_free_disk_space.get_bytes()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the total size of the files in `directory` and its subdirectories.
+fn disk_usage(directory: &Path) -> io::Result<u64> {
+    let mut num_bytes = 0;
+    for dir_entry_res in fs::read_dir(directory)? {
+        let dir_entry = dir_entry_res?;
+        let metadata = dir_entry.metadata()?;
+        if metadata.is_dir() {
+            num_bytes += disk_usage(&dir_entry.path())?;
+        } else {
+            num_bytes += metadata.len();
+        }
+    }
+    Ok(num_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_guardrails_max_disk_usage() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        fs::write(tempdir.path().join("file"), vec![0u8; 600])?;
+        fs::create_dir(tempdir.path().join("dir"))?;
+        fs::write(tempdir.path().join("dir").join("file"), vec![0u8; 300])?;
+        assert_eq!(disk_usage(tempdir.path())?, 900);
+
+        assert!(DiskGuardrails::default().check(tempdir.path()).is_ok());
+        let disk_guardrails = DiskGuardrails {
+            max_disk_usage: Some(Byte::from_bytes(1_000)),
+            min_free_disk_space: None,
+        };
+        assert!(disk_guardrails.check(tempdir.path()).is_ok());
+        fs::write(tempdir.path().join("dir").join("file2"), vec![0u8; 100])?;
+        assert!(disk_guardrails.check(tempdir.path()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_guardrails_min_free_disk_space() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let disk_guardrails = DiskGuardrails {
+            max_disk_usage: None,
+            min_free_disk_space: Some(Byte::from_bytes(u64::MAX as u128)),
+        };
+        assert!(disk_guardrails.check(tempdir.path()).is_err());
+        Ok(())
+    }
+}
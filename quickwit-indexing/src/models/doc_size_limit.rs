@@ -0,0 +1,349 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::str::FromStr;
+
+use byte_unit::Byte;
+
+/// Truncating a document requires reading it into memory: the documents larger than this
+/// factor times the maximum document size are dropped by the sources without being read.
+const MAX_TRUNCATED_DOC_SIZE_FACTOR: u64 = 16;
+
+/// What the indexer does with the documents larger than the maximum document size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OversizedDocPolicy {
+    /// The document is dropped.
+    Reject,
+    /// The string values of the document are truncated until it fits. The document is dropped
+    /// if it does not fit even with its string values emptied.
+    Truncate,
+}
+
+impl Default for OversizedDocPolicy {
+    fn default() -> Self {
+        OversizedDocPolicy::Reject
+    }
+}
+
+impl FromStr for OversizedDocPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(policy_str: &str) -> anyhow::Result<Self> {
+        match policy_str {
+            "reject" => Ok(OversizedDocPolicy::Reject),
+            "truncate" => Ok(OversizedDocPolicy::Truncate),
+            _ => anyhow::bail!(
+                "Unknown oversized doc policy `{}`, expected `reject` or `truncate`.",
+                policy_str
+            ),
+        }
+    }
+}
+
+/// Maximum size of the JSON documents accepted by the indexer.
+///
+/// Without it, a single pathological document of several megabytes balloons the memory of
+/// the indexer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DocSizeLimit {
+    pub max_doc_size: Option<Byte>,
+    pub oversized_doc_policy: OversizedDocPolicy,
+}
+
+/// Outcome of checking a document against the [`DocSizeLimit`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum DocSizeOutcome {
+    Accepted(String),
+    Truncated(String),
+    Oversized,
+}
+
+impl DocSizeLimit {
+    /// Returns the size beyond which sources can skip a document without reading it, since the
+    /// indexer would drop it anyway or it is too large to be truncated.
+    pub fn max_read_num_bytes(&self) -> Option<u64> {
+        let max_doc_size = self.max_doc_size?.get_bytes() as u64;
+        match self.oversized_doc_policy {
+            OversizedDocPolicy::Reject => Some(max_doc_size),
+            OversizedDocPolicy::Truncate => {
+                Some(max_doc_size.saturating_mul(MAX_TRUNCATED_DOC_SIZE_FACTOR))
+            }
+        }
+    }
+
+    /// Checks `doc_json` against the limit, truncating it if the policy allows it.
+    pub fn apply(&self, doc_json: String) -> DocSizeOutcome {
+        let max_doc_size = match self.max_doc_size {
+            Some(max_doc_size) if doc_json.len() as u128 > max_doc_size.get_bytes() => {
+                max_doc_size.get_bytes() as usize
+            }
+            _ => return DocSizeOutcome::Accepted(doc_json),
+        };
+        if self.oversized_doc_policy == OversizedDocPolicy::Reject {
+            return DocSizeOutcome::Oversized;
+        }
+        match truncate_string_values(&doc_json, max_doc_size) {
+            Some(truncated_doc_json) => DocSizeOutcome::Truncated(truncated_doc_json),
+            None => DocSizeOutcome::Oversized,
+        }
+    }
+}
+
+enum JsonSegment<'a> {
+    /// Characters outside of the string values, including the object keys and the quotes
+    /// around the string values.
+    Other(&'a str),
+    /// A character, or an escape sequence, of a string value.
+    StringValueUnit(&'a str),
+    EndOfStringValue,
+}
+
+#[derive(Clone, Copy)]
+enum Container {
+    Array,
+    Object { expects_key: bool },
+}
+
+/// Splits a JSON document into segments without parsing it into a tree, calling `visit` on
+/// each of them. Malformed documents are split on a best effort basis.
+fn visit_json_segments<'a>(doc_json: &'a str, mut visit: impl FnMut(JsonSegment<'a>)) {
+    let bytes = doc_json.as_bytes();
+    let mut containers: Vec<Container> = Vec::new();
+    let mut other_start = 0;
+    let mut position = 0;
+    while position < bytes.len() {
+        match bytes[position] {
+            b'{' => containers.push(Container::Object { expects_key: true }),
+            b'[' => containers.push(Container::Array),
+            b'}' | b']' => {
+                containers.pop();
+            }
+            b',' => {
+                if let Some(Container::Object { expects_key }) = containers.last_mut() {
+                    *expects_key = true;
+                }
+            }
+            b'"' => {
+                let is_key = match containers.last_mut() {
+                    Some(Container::Object { expects_key }) if *expects_key => {
+                        *expects_key = false;
+                        true
+                    }
+                    _ => false,
+                };
+                position += 1;
+                if is_key {
+                    position = end_of_string(bytes, position);
+                } else {
+                    visit(JsonSegment::Other(&doc_json[other_start..position]));
+                    while position < bytes.len() && bytes[position] != b'"' {
+                        let unit_len = string_unit_len(doc_json, position);
+                        visit(JsonSegment::StringValueUnit(
+                            &doc_json[position..position + unit_len],
+                        ));
+                        position += unit_len;
+                    }
+                    visit(JsonSegment::EndOfStringValue);
+                    other_start = position;
+                }
+            }
+            _ => {}
+        }
+        position += 1;
+    }
+    visit(JsonSegment::Other(
+        &doc_json[other_start.min(bytes.len())..],
+    ));
+}
+
+/// Returns the position of the closing quote of the string starting at `position`.
+fn end_of_string(bytes: &[u8], mut position: usize) -> usize {
+    while position < bytes.len() && bytes[position] != b'"' {
+        position += if bytes[position] == b'\\' { 2 } else { 1 };
+    }
+    position.min(bytes.len())
+}
+
+/// Returns the length of the character or escape sequence starting at `position`, so that
+/// string values are never truncated in the middle of one. A surrogate pair counts as a single
+/// escape sequence.
+fn string_unit_len(doc_json: &str, position: usize) -> usize {
+    let rest = &doc_json[position..];
+    let unit_len = if rest.starts_with("\\u") {
+        let is_high_surrogate = rest.len() >= 12
+            && matches!(rest.as_bytes()[2].to_ascii_lowercase(), b'd')
+            && matches!(rest.as_bytes()[3].to_ascii_lowercase(), b'8'..=b'9' | b'a'..=b'b')
+            && rest[6..].starts_with("\\u");
+        if is_high_surrogate {
+            12
+        } else {
+            6
+        }
+    } else if rest.starts_with('\\') {
+        2
+    } else {
+        rest.chars().next().map_or(1, char::len_utf8)
+    };
+    // The unit never spans past the closing quote of a malformed string.
+    let unit_len = unit_len.min(rest.len());
+    rest.char_indices()
+        .map(|(index, _)| index)
+        .find(|index| *index >= unit_len)
+        .unwrap_or_else(|| rest.len())
+}
+
+/// Truncates the string values of a JSON document so that it fits in `max_num_bytes`, cutting
+/// the longest values first. The object keys and everything but the string values are kept.
+///
+/// Returns `None` if the document does not fit even with its string values emptied.
+fn truncate_string_values(doc_json: &str, max_num_bytes: usize) -> Option<String> {
+    let mut other_num_bytes = 0;
+    let mut value_num_bytes = Vec::new();
+    let mut current_value_num_bytes = 0;
+    visit_json_segments(doc_json, |segment| match segment {
+        JsonSegment::Other(other) => other_num_bytes += other.len(),
+        JsonSegment::StringValueUnit(unit) => current_value_num_bytes += unit.len(),
+        JsonSegment::EndOfStringValue => {
+            value_num_bytes.push(current_value_num_bytes);
+            current_value_num_bytes = 0;
+        }
+    });
+    let value_budget = max_num_bytes.checked_sub(other_num_bytes)?;
+    let max_value_num_bytes = max_value_num_bytes(value_num_bytes, value_budget);
+    let mut truncated_doc_json = String::with_capacity(max_num_bytes);
+    let mut current_value_num_bytes = 0;
+    visit_json_segments(doc_json, |segment| match segment {
+        JsonSegment::Other(other) => truncated_doc_json.push_str(other),
+        JsonSegment::StringValueUnit(unit) => {
+            if current_value_num_bytes + unit.len() <= max_value_num_bytes {
+                truncated_doc_json.push_str(unit);
+                current_value_num_bytes += unit.len();
+            } else {
+                // The following units are dropped too, even if they are shorter.
+                current_value_num_bytes = usize::MAX / 2;
+            }
+        }
+        JsonSegment::EndOfStringValue => current_value_num_bytes = 0,
+    });
+    Some(truncated_doc_json)
+}
+
+/// Returns the largest length such that truncating the values longer than it fits the
+/// values in `budget`.
+fn max_value_num_bytes(mut value_num_bytes: Vec<usize>, mut budget: usize) -> usize {
+    value_num_bytes.sort_unstable();
+    let num_values = value_num_bytes.len();
+    for (index, num_bytes) in value_num_bytes.into_iter().enumerate() {
+        let share = budget / (num_values - index);
+        if num_bytes > share {
+            return share;
+        }
+        budget -= num_bytes;
+    }
+    usize::MAX
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_doc_size_limit(
+        max_doc_size: u128,
+        oversized_doc_policy: OversizedDocPolicy,
+    ) -> DocSizeLimit {
+        DocSizeLimit {
+            max_doc_size: Some(Byte::from_bytes(max_doc_size)),
+            oversized_doc_policy,
+        }
+    }
+
+    #[test]
+    fn test_doc_size_limit_reject() {
+        let doc_json = r#"{"body": "hello world"}"#.to_string();
+        assert_eq!(
+            DocSizeLimit::default().apply(doc_json.clone()),
+            DocSizeOutcome::Accepted(doc_json.clone())
+        );
+        let doc_size_limit =
+            build_doc_size_limit(doc_json.len() as u128, OversizedDocPolicy::Reject);
+        assert_eq!(
+            doc_size_limit.apply(doc_json.clone()),
+            DocSizeOutcome::Accepted(doc_json.clone())
+        );
+        let doc_size_limit = build_doc_size_limit(10, OversizedDocPolicy::Reject);
+        assert_eq!(doc_size_limit.apply(doc_json), DocSizeOutcome::Oversized);
+    }
+
+    #[test]
+    fn test_doc_size_limit_truncate() -> anyhow::Result<()> {
+        let doc_json =
+            r#"{"title": "short", "body": "a very long body", "tags": ["x", "y"], "n": 1}"#;
+        let doc_size_limit = build_doc_size_limit(63, OversizedDocPolicy::Truncate);
+        let truncated_doc_json = match doc_size_limit.apply(doc_json.to_string()) {
+            DocSizeOutcome::Truncated(truncated_doc_json) => truncated_doc_json,
+            outcome => anyhow::bail!("Unexpected outcome {:?}", outcome),
+        };
+        assert!(truncated_doc_json.len() <= 63);
+        let truncated_doc: serde_json::Value = serde_json::from_str(&truncated_doc_json)?;
+        assert_eq!(
+            truncated_doc,
+            serde_json::json!({"title": "short", "body": "a ver", "tags": ["x", "y"], "n": 1})
+        );
+
+        let doc_size_limit = build_doc_size_limit(10, OversizedDocPolicy::Truncate);
+        assert_eq!(
+            doc_size_limit.apply(doc_json.to_string()),
+            DocSizeOutcome::Oversized
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_string_values_keeps_escape_sequences() -> anyhow::Result<()> {
+        let doc_json = r#"{"body": "é\n\"😀 tail", "key\"with\"quotes": "value"}"#;
+        for max_num_bytes in 40..doc_json.len() {
+            let truncated_doc_json = truncate_string_values(doc_json, max_num_bytes).unwrap();
+            assert!(truncated_doc_json.len() <= max_num_bytes);
+            let truncated_doc: serde_json::Value = serde_json::from_str(&truncated_doc_json)?;
+            assert!(truncated_doc.get("key\"with\"quotes").is_some());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_doc_size_limit_max_read_num_bytes() {
+        assert_eq!(DocSizeLimit::default().max_read_num_bytes(), None);
+        assert_eq!(
+            build_doc_size_limit(100, OversizedDocPolicy::Reject).max_read_num_bytes(),
+            Some(100)
+        );
+        assert_eq!(
+            build_doc_size_limit(100, OversizedDocPolicy::Truncate).max_read_num_bytes(),
+            Some(1_600)
+        );
+    }
+
+    #[test]
+    fn test_max_value_num_bytes() {
+        assert_eq!(max_value_num_bytes(vec![5, 100], 45), 40);
+        assert_eq!(max_value_num_bytes(vec![30, 30], 40), 20);
+        assert_eq!(max_value_num_bytes(vec![1, 2], 10), usize::MAX);
+        assert_eq!(max_value_num_bytes(Vec::new(), 0), usize::MAX);
+    }
+}
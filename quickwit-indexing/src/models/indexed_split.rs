@@ -17,6 +17,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::ops::RangeInclusive;
 use std::path::Path;
@@ -52,6 +53,10 @@ pub struct IndexedSplit {
     /// before actually reaching the indexer.
     pub split_date_of_birth: Instant,
 
+    /// Number of valid documents per timestamp, in seconds, at which their source received
+    /// them. Empty for the splits produced by merges.
+    pub num_docs_per_ingest_timestamp: BTreeMap<i64, u64>,
+
     pub checkpoint_delta: CheckpointDelta,
 
     pub index: tantivy::Index,
@@ -73,6 +78,7 @@ impl fmt::Debug for IndexedSplit {
 impl IndexedSplit {
     pub fn new_in_dir(
         index_id: String,
+        split_id_opt: Option<String>,
         indexer_params: &IndexerParams,
         index_builder: IndexBuilder,
     ) -> anyhow::Result<Self> {
@@ -84,7 +90,7 @@ impl IndexedSplit {
         let index_writer =
             index.writer_with_num_threads(1, indexer_params.heap_size.get_bytes() as usize)?;
         index_writer.set_merge_policy(Box::new(NoMergePolicy));
-        let split_id = new_split_id();
+        let split_id = split_id_opt.unwrap_or_else(new_split_id);
         Ok(IndexedSplit {
             split_id,
             index_id,
@@ -93,6 +99,7 @@ impl IndexedSplit {
             docs_size_in_bytes: 0,
             num_docs: 0,
             split_date_of_birth: Instant::now(),
+            num_docs_per_ingest_timestamp: BTreeMap::new(),
             index,
             index_writer,
             split_scratch_directory,
@@ -18,6 +18,8 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 mod commit_policy;
+mod disk_guardrails;
+mod doc_size_limit;
 mod indexed_split;
 mod indexer_message;
 mod indexing_statistics;
@@ -26,9 +28,13 @@ mod merge_scratch;
 mod packaged_split;
 mod publisher_message;
 mod raw_doc_batch;
+mod realtime_params;
+mod routing_rule;
 mod scratch_directory;
 
-pub use commit_policy::CommitPolicy;
+pub use commit_policy::{AdaptiveCommitPolicy, CommitPolicy, IngestRate};
+pub use disk_guardrails::DiskGuardrails;
+pub use doc_size_limit::{DocSizeLimit, DocSizeOutcome, OversizedDocPolicy};
 pub use indexed_split::IndexedSplit;
 pub use indexer_message::IndexerMessage;
 pub use indexing_statistics::IndexingStatistics;
@@ -37,4 +43,6 @@ pub use merge_scratch::MergeScratch;
 pub use packaged_split::PackagedSplit;
 pub use publisher_message::{PublishOperation, PublisherMessage};
 pub use raw_doc_batch::RawDocBatch;
+pub use realtime_params::RealtimeParams;
+pub use routing_rule::{route_doc, RoutingRule};
 pub use scratch_directory::ScratchDirectory;
@@ -22,6 +22,7 @@ use std::ops::{Range, RangeInclusive};
 use std::time::Instant;
 
 use quickwit_metastore::checkpoint::CheckpointDelta;
+use quickwit_metastore::IngestTimestamps;
 
 use crate::models::ScratchDirectory;
 
@@ -38,4 +39,5 @@ pub struct PackagedSplit {
     pub num_docs: u64,
     pub tags: HashSet<String>,
     pub split_date_of_birth: Instant,
+    pub ingest_timestamps: Option<IngestTimestamps>,
 }
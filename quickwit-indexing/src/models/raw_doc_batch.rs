@@ -23,4 +23,18 @@ use quickwit_metastore::checkpoint::CheckpointDelta;
 pub struct RawDocBatch {
     pub docs: Vec<String>,
     pub checkpoint_delta: CheckpointDelta,
+    /// ID of the split started by this batch, if the source provides one.
+    ///
+    /// Sources that replay the exact same batches after a failure can set it
+    /// (e.g. using [`crate::split_id_from_checkpoint_delta`]) so that a split is staged and
+    /// published under the same ID no matter how many times it is built. The indexer scopes it
+    /// to its index with [`crate::index_split_id`].
+    /// It is ignored if the batch does not start a new split.
+    pub split_id_opt: Option<String>,
+    /// Timestamp, in seconds, at which the source received the documents of the batch. It
+    /// is the starting point of their time to searchable.
+    pub received_timestamp: i64,
+    /// Number of documents the source skipped while reading the batch, e.g. the lines longer
+    /// than its maximum line size. The indexer counts them as oversized documents.
+    pub num_skipped_docs: u64,
 }
@@ -0,0 +1,32 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::time::Duration;
+
+use quickwit_common::RealtimeSplits;
+
+/// Makes the documents of the split being indexed searchable before it is published.
+#[derive(Clone, Debug)]
+pub struct RealtimeParams {
+    /// Searchers over the splits being indexed, served to the roots of the cluster.
+    pub splits: RealtimeSplits,
+    /// Minimum interval between two commits of the split being indexed. The documents
+    /// received in the meantime are not searchable yet.
+    pub refresh_interval: Duration,
+}
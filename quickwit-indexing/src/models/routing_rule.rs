@@ -0,0 +1,147 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::str::FromStr;
+
+use serde_json::Value as JsonValue;
+
+/// Sends the documents whose `field` is equal to `value` to the index `index_id`.
+///
+/// Routing rules make it possible to split a single source across several indexes, each with
+/// its own config, within the same indexing pipeline.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoutingRule {
+    /// Name of a top-level field of the documents.
+    pub field: String,
+    /// Value of the field, compared to the string representation of the number and boolean
+    /// values.
+    pub value: String,
+    pub index_id: String,
+}
+
+impl RoutingRule {
+    /// Returns true if the field of the document matches the value of the rule.
+    pub fn matches(&self, doc: &JsonValue) -> bool {
+        match doc.get(&self.field) {
+            Some(JsonValue::String(field_value)) => *field_value == self.value,
+            Some(field_value @ JsonValue::Number(_)) | Some(field_value @ JsonValue::Bool(_)) => {
+                field_value.to_string() == self.value
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for RoutingRule {
+    type Err = anyhow::Error;
+
+    /// Parses a rule expressed as `<field>=<value>:<index id>`.
+    fn from_str(rule_str: &str) -> anyhow::Result<Self> {
+        let (condition, index_id) = rule_str.rsplit_once(':').ok_or_else(|| {
+            anyhow::anyhow!(
+                "Invalid routing rule `{}`, expected `<field>=<value>:<index id>`.",
+                rule_str
+            )
+        })?;
+        let (field, value) = condition.split_once('=').ok_or_else(|| {
+            anyhow::anyhow!(
+                "Invalid routing rule `{}`, expected `<field>=<value>:<index id>`.",
+                rule_str
+            )
+        })?;
+        if field.is_empty() || index_id.is_empty() {
+            anyhow::bail!(
+                "Invalid routing rule `{}`, the field and the index id cannot be empty.",
+                rule_str
+            );
+        }
+        Ok(RoutingRule {
+            field: field.to_string(),
+            value: value.to_string(),
+            index_id: index_id.to_string(),
+        })
+    }
+}
+
+/// Returns the index of the first rule matching the document, or `default_index_id` if none
+/// matches or the document is not a valid JSON object. The indexer of the default index then
+/// counts invalid documents as such.
+pub fn route_doc<'a>(
+    routing_rules: &'a [RoutingRule],
+    default_index_id: &'a str,
+    doc_json: &str,
+) -> &'a str {
+    let doc: JsonValue = match serde_json::from_str(doc_json) {
+        Ok(doc) => doc,
+        Err(_) => return default_index_id,
+    };
+    routing_rules
+        .iter()
+        .find(|routing_rule| routing_rule.matches(&doc))
+        .map(|routing_rule| routing_rule.index_id.as_str())
+        .unwrap_or(default_index_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routing_rule_from_str() {
+        assert_eq!(
+            RoutingRule::from_str("service=api:api-logs").unwrap(),
+            RoutingRule {
+                field: "service".to_string(),
+                value: "api".to_string(),
+                index_id: "api-logs".to_string(),
+            }
+        );
+        assert_eq!(
+            RoutingRule::from_str("url=http://host:errors").unwrap(),
+            RoutingRule {
+                field: "url".to_string(),
+                value: "http://host".to_string(),
+                index_id: "errors".to_string(),
+            }
+        );
+        assert_eq!(RoutingRule::from_str("service=:empty").unwrap().value, "");
+        assert!(RoutingRule::from_str("service=api").is_err());
+        assert!(RoutingRule::from_str("service:api-logs").is_err());
+        assert!(RoutingRule::from_str("=api:api-logs").is_err());
+        assert!(RoutingRule::from_str("service=api:").is_err());
+    }
+
+    #[test]
+    fn test_route_doc() {
+        let routing_rules = vec![
+            RoutingRule::from_str("service=api:api-logs").unwrap(),
+            RoutingRule::from_str("level=error:errors").unwrap(),
+            RoutingRule::from_str("status=500:errors").unwrap(),
+            RoutingRule::from_str("debug=true:debug-logs").unwrap(),
+        ];
+        let route = |doc_json| route_doc(&routing_rules, "logs", doc_json);
+        assert_eq!(route(r#"{"service": "api", "level": "error"}"#), "api-logs");
+        assert_eq!(route(r#"{"service": "web", "level": "error"}"#), "errors");
+        assert_eq!(route(r#"{"service": "web", "status": 500}"#), "errors");
+        assert_eq!(route(r#"{"debug": true}"#), "debug-logs");
+        assert_eq!(route(r#"{"service": "web", "level": "info"}"#), "logs");
+        assert_eq!(route(r#"{"service": ["api"]}"#), "logs");
+        assert_eq!(route("not json"), "logs");
+    }
+}
@@ -17,9 +17,49 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::fs::{File, OpenOptions};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::{fmt, io};
+use std::{fmt, fs, io};
+
+use fs2::FileExt;
+use tracing::{info, warn};
+
+/// Extension of the file locked by a running indexing pipeline, next to its scratch directory.
+const PIPELINE_LOCK_FILE_EXTENSION: &str = "lock";
+
+/// Lock held by a running indexing pipeline on its scratch directory.
+///
+/// The lock is released by the OS if the process crashes, which is how the directories
+/// left over by a crashed pipeline are told apart from those of the running ones.
+struct PipelineLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl PipelineLock {
+    /// Returns `None` if the lock is held by another pipeline.
+    fn try_acquire(path: PathBuf) -> io::Result<Option<PipelineLock>> {
+        let file = OpenOptions::new().create(true).write(true).open(&path)?;
+        if file.try_lock_exclusive().is_err() {
+            return Ok(None);
+        }
+        Ok(Some(PipelineLock { path, _file: file }))
+    }
+}
+
+impl Drop for PipelineLock {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            warn!(path=%self.path.display(), error=?error, "Failed to remove the pipeline lock file.");
+        }
+    }
+}
+
+fn pipeline_lock_path(directory_path: &Path) -> PathBuf {
+    directory_path.with_extension(PIPELINE_LOCK_FILE_EXTENSION)
+}
+
 enum ScratchDirectoryType {
     Path(PathBuf),
     TempDir(tempfile::TempDir),
@@ -53,6 +93,8 @@ struct Inner {
     // its child.
     _parent: Option<Arc<Inner>>,
     dir: ScratchDirectoryType,
+    // Declared after `dir` so that the lock is released once the directory is deleted.
+    _lock_opt: Option<PipelineLock>,
 }
 
 impl ScratchDirectory {
@@ -61,12 +103,68 @@ impl ScratchDirectory {
         let inner = Inner {
             _parent: None,
             dir: ScratchDirectoryType::Path(path),
+            _lock_opt: None,
         };
         ScratchDirectory {
             inner: Arc::new(inner),
         }
     }
 
+    /// Creates a scratch directory for an indexing pipeline of the source `source_id` of
+    /// `index_id`, in `<root_path>/indexing/<index_id>/<source_id>`.
+    ///
+    /// Several pipelines may run for the same source, e.g. on different shards. Each of
+    /// them gets its own directory, locked until it is dropped. The directories left over
+    /// by the pipelines that crashed, whose lock was released, are removed. Those of the
+    /// running pipelines are kept.
+    pub fn for_indexing_pipeline(
+        root_path: &Path,
+        index_id: &str,
+        source_id: &str,
+    ) -> io::Result<ScratchDirectory> {
+        let pipelines_path = root_path.join("indexing").join(index_id).join(source_id);
+        fs::create_dir_all(&pipelines_path)?;
+        let mut num_removed_dirs = 0;
+        for dir_entry_res in fs::read_dir(&pipelines_path)? {
+            let dir_entry = dir_entry_res?;
+            if !dir_entry.file_type()?.is_dir() {
+                continue;
+            }
+            let dir_path = dir_entry.path();
+            if let Some(_lock) = PipelineLock::try_acquire(pipeline_lock_path(&dir_path))? {
+                fs::remove_dir_all(&dir_path)?;
+                num_removed_dirs += 1;
+            }
+        }
+        if num_removed_dirs > 0 {
+            info!(index_id=%index_id, source_id=%source_id, num_removed_dirs=num_removed_dirs, "removed-orphan-scratch-directories");
+        }
+        let dir_name = format!("pipeline-{}", ulid::Ulid::new());
+        // The lock is acquired before the directory is created, so that another pipeline
+        // never mistakes it for a leftover.
+        let lock = PipelineLock::try_acquire(
+            pipelines_path.join(format!("{}.{}", dir_name, PIPELINE_LOCK_FILE_EXTENSION)),
+        )?
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("The scratch directory `{}` is already locked.", dir_name),
+            )
+        })?;
+        let temp_dir = tempfile::Builder::new()
+            .prefix(&dir_name)
+            .rand_bytes(0)
+            .tempdir_in(&pipelines_path)?;
+        let inner = Inner {
+            _parent: None,
+            dir: ScratchDirectoryType::TempDir(temp_dir),
+            _lock_opt: Some(lock),
+        };
+        Ok(ScratchDirectory {
+            inner: Arc::new(inner),
+        })
+    }
+
     /// Creates a new ScratchDirectory in an existing directory.
     /// The directory location will depend on the OS settings.
     pub fn try_new_temp() -> io::Result<ScratchDirectory> {
@@ -74,6 +172,7 @@ impl ScratchDirectory {
         let inner = Inner {
             _parent: None,
             dir: ScratchDirectoryType::TempDir(temp_dir),
+            _lock_opt: None,
         };
         Ok(ScratchDirectory {
             inner: Arc::new(inner),
@@ -96,6 +195,7 @@ impl ScratchDirectory {
         let inner = Inner {
             _parent: Some(self.inner.clone()),
             dir: ScratchDirectoryType::TempDir(temp_dir),
+            _lock_opt: None,
         };
         Ok(ScratchDirectory {
             inner: Arc::new(inner),
@@ -154,4 +254,39 @@ mod tests {
         assert!(!tempdir_path.exists());
         Ok(())
     }
+
+    #[test]
+    fn test_scratch_directory_for_indexing_pipeline() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let pipelines_path = tempdir
+            .path()
+            .join("indexing")
+            .join("my-index")
+            .join("my-source");
+        let pa = ScratchDirectory::for_indexing_pipeline(tempdir.path(), "my-index", "my-source")?;
+        assert_eq!(pa.path().parent(), Some(pipelines_path.as_path()));
+        let kid = pa.temp_child()?;
+        let kid_path = kid.path().to_path_buf();
+
+        // Simulates a pipeline that crashed: its directory is left over and unlocked.
+        let crashed_pipeline_path = pipelines_path.join("pipeline-crashed");
+        fs::create_dir(&crashed_pipeline_path)?;
+        fs::write(crashed_pipeline_path.join("split.tmp"), b"hello")?;
+        fs::write(pipeline_lock_path(&crashed_pipeline_path), b"")?;
+
+        let other_pa =
+            ScratchDirectory::for_indexing_pipeline(tempdir.path(), "my-index", "my-source")?;
+        assert_ne!(other_pa.path(), pa.path());
+        assert!(!crashed_pipeline_path.exists());
+        assert!(!pipeline_lock_path(&crashed_pipeline_path).exists());
+        // The directory of the running pipeline is kept.
+        assert!(kid_path.exists());
+
+        let pa_path = pa.path().to_path_buf();
+        mem::drop(kid);
+        mem::drop(pa);
+        assert!(!pa_path.exists());
+        assert!(!pipeline_lock_path(&pa_path).exists());
+        Ok(())
+    }
 }
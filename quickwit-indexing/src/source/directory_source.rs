@@ -0,0 +1,544 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::io;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use quickwit_actors::{ActorExitStatus, Mailbox};
+use quickwit_metastore::checkpoint::{Checkpoint, CheckpointDelta, PartitionId, Position};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tantivy::chrono::Utc;
+use tokio::fs::File;
+use tokio::io::{AsyncSeekExt, BufReader};
+use tracing::{info, warn};
+
+use crate::models::{IndexerMessage, RawDocBatch};
+use crate::source::file_source::read_bounded_line;
+use crate::source::{Source, SourceContext, TypedSourceFactory};
+
+/// Cut a new batch as soon as we have read BATCH_NUM_BYTES_THRESHOLD.
+const BATCH_NUM_BYTES_THRESHOLD: u64 = 500_000u64;
+
+/// Parameters of a [`DirectorySource`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DirectorySourceParams {
+    /// Directory holding the files to index. Its subdirectories are not watched.
+    pub dirpath: PathBuf,
+    /// If set, only the files whose name matches this regular expression are indexed, e.g.
+    /// `^app\.log(\.1)?$`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_pattern: Option<String>,
+    /// If set, the lines longer than this number of bytes are skipped instead of being read
+    /// into memory.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_line_num_bytes: Option<u64>,
+}
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct DirectorySourceCounters {
+    pub num_watched_files: usize,
+    pub num_bytes_processed: u64,
+    pub num_lines_processed: u64,
+    /// Number of lines longer than the maximum line size that were skipped.
+    pub num_skipped_lines: u64,
+    /// Number of times a file was truncated, e.g. by a `copytruncate` log rotation, and read
+    /// again from its beginning.
+    pub num_truncations: u64,
+}
+
+/// Position reached in a file.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct FileState {
+    /// Number of times the file was truncated.
+    generation: u64,
+    offset: u64,
+}
+
+/// A file with data left to read.
+struct PendingFile {
+    file_key: String,
+    path: PathBuf,
+    modified: SystemTime,
+}
+
+/// A source that watches a directory and indexes the lines appended to its files, like
+/// `tail -F` would.
+///
+/// The directory is scanned every half heartbeat. A file is identified by its device and
+/// inode rather than its name, so that a file renamed by a log rotation is read to its end but
+/// not indexed twice. A file shorter than the position reached in it was truncated: it is read
+/// again from its beginning. The partition of a file in the checkpoint is
+/// `<file key>:<generation>`, where the generation counts the truncations of the file.
+///
+/// The last line of a file is only indexed once it ends with a line break, since it may still
+/// be being written.
+pub struct DirectorySource {
+    params: DirectorySourceParams,
+    file_pattern_opt: Option<Regex>,
+    file_states: HashMap<String, FileState>,
+    counters: DirectorySourceCounters,
+}
+
+impl DirectorySource {
+    /// Lists the files of the directory that have data left to read, the least recently
+    /// modified first.
+    async fn scan_directory(&mut self) -> io::Result<Vec<PendingFile>> {
+        let mut pending_files = Vec::new();
+        let mut num_watched_files = 0;
+        let mut dir_entries = tokio::fs::read_dir(&self.params.dirpath).await?;
+        while let Some(dir_entry) = dir_entries.next_entry().await? {
+            let path = dir_entry.path();
+            if !self.is_watched(&path) {
+                continue;
+            }
+            // The metadata of the target of the symlinks.
+            let metadata = match tokio::fs::metadata(&path).await {
+                Ok(metadata) if metadata.is_file() => metadata,
+                // The file may have been removed in the meantime.
+                _ => continue,
+            };
+            num_watched_files += 1;
+            let file_key = file_key(&path, &metadata);
+            let file_state = self.file_states.entry(file_key.clone()).or_default();
+            if metadata.len() < file_state.offset {
+                info!(path=%path.display(), offset=file_state.offset, len=metadata.len(), "File truncated, reading it from its beginning.");
+                file_state.generation += 1;
+                file_state.offset = 0;
+                self.counters.num_truncations += 1;
+            }
+            if metadata.len() > file_state.offset {
+                pending_files.push(PendingFile {
+                    file_key,
+                    path,
+                    modified: metadata.modified()?,
+                });
+            }
+        }
+        self.counters.num_watched_files = num_watched_files;
+        pending_files.sort_by_key(|pending_file| pending_file.modified);
+        Ok(pending_files)
+    }
+
+    fn is_watched(&self, path: &Path) -> bool {
+        let file_name = match path.file_name().and_then(|file_name| file_name.to_str()) {
+            Some(file_name) => file_name,
+            None => return false,
+        };
+        if file_name.starts_with('.') {
+            return false;
+        }
+        self.file_pattern_opt
+            .as_ref()
+            .map(|file_pattern| file_pattern.is_match(file_name))
+            .unwrap_or(true)
+    }
+
+    /// Reads the complete lines of the file from the position reached in it, up to about
+    /// `BATCH_NUM_BYTES_THRESHOLD` bytes.
+    async fn read_batch(&mut self, pending_file: &PendingFile) -> io::Result<Option<RawDocBatch>> {
+        let file_state = self
+            .file_states
+            .get_mut(&pending_file.file_key)
+            .expect("The pending files have a state.");
+        let mut file = match File::open(&pending_file.path).await {
+            Ok(file) => file,
+            // The file may have been removed in the meantime.
+            Err(io_error) if io_error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(io_error) => return Err(io_error),
+        };
+        file.seek(SeekFrom::Start(file_state.offset)).await?;
+        let mut reader = BufReader::new(file);
+        let previous_offset = file_state.offset;
+        let mut docs = Vec::new();
+        let mut num_skipped_docs = 0;
+        while file_state.offset < previous_offset + BATCH_NUM_BYTES_THRESHOLD {
+            let (num_bytes, doc_line_opt) =
+                read_bounded_line(&mut reader, self.params.max_line_num_bytes).await?;
+            if num_bytes == 0 {
+                break;
+            }
+            if let Some(doc_line) = doc_line_opt {
+                if !doc_line.ends_with('\n') {
+                    // The line is still being written.
+                    break;
+                }
+                docs.push(doc_line);
+            } else {
+                warn!(
+                    path = %pending_file.path.display(),
+                    offset = file_state.offset,
+                    num_bytes = num_bytes,
+                    "Skipping line longer than the maximum line size."
+                );
+                self.counters.num_skipped_lines += 1;
+                num_skipped_docs += 1;
+            }
+            file_state.offset += num_bytes as u64;
+            self.counters.num_bytes_processed += num_bytes as u64;
+            self.counters.num_lines_processed += 1;
+        }
+        if file_state.offset == previous_offset {
+            return Ok(None);
+        }
+        let partition_id = PartitionId::from(format!(
+            "{}:{}",
+            pending_file.file_key, file_state.generation
+        ));
+        let checkpoint_delta = CheckpointDelta::from_partition_delta(
+            partition_id,
+            Position::from(previous_offset),
+            Position::from(file_state.offset),
+        );
+        Ok(Some(RawDocBatch {
+            docs,
+            checkpoint_delta,
+            split_id_opt: None,
+            received_timestamp: Utc::now().timestamp(),
+            num_skipped_docs,
+        }))
+    }
+}
+
+#[async_trait]
+impl Source for DirectorySource {
+    async fn emit_batches(
+        &mut self,
+        batch_sink: &Mailbox<IndexerMessage>,
+        ctx: &SourceContext,
+    ) -> Result<(), ActorExitStatus> {
+        let pending_files = self.scan_directory().await.with_context(|| {
+            format!(
+                "Failed to scan source directory `{}`.",
+                self.params.dirpath.display()
+            )
+        })?;
+        for pending_file in &pending_files {
+            let batch_opt = self.read_batch(pending_file).await.with_context(|| {
+                format!(
+                    "Failed to read source file `{}`.",
+                    pending_file.path.display()
+                )
+            })?;
+            if let Some(batch) = batch_opt {
+                ctx.send_message(batch_sink, batch.into()).await?;
+                return Ok(());
+            }
+        }
+        // Nothing new: we wait a bit before scanning the directory again.
+        tokio::time::sleep(quickwit_actors::HEARTBEAT / 2).await;
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        "directory-source".to_string()
+    }
+
+    fn observable_state(&self) -> serde_json::Value {
+        serde_json::to_value(&self.counters).unwrap()
+    }
+}
+
+/// Identifies a file regardless of its name.
+#[cfg(unix)]
+fn file_key(_path: &Path, metadata: &Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    format!("{}-{}", metadata.dev(), metadata.ino())
+}
+
+/// Identifies a file by its name, as there are no inodes: the renamed files are read again.
+#[cfg(not(unix))]
+fn file_key(path: &Path, _metadata: &Metadata) -> String {
+    path.to_string_lossy().to_string()
+}
+
+/// Recovers the position reached in each file from the checkpoint, keeping the latest
+/// generation of each file.
+fn file_states_from_checkpoint(
+    checkpoint: &Checkpoint,
+) -> anyhow::Result<HashMap<String, FileState>> {
+    let mut file_states: HashMap<String, FileState> = HashMap::new();
+    for (partition_id, position) in checkpoint.iter() {
+        let (file_key, generation) = partition_id
+            .0
+            .rsplit_once(':')
+            .and_then(|(file_key, generation_str)| {
+                generation_str
+                    .parse::<u64>()
+                    .ok()
+                    .map(|generation| (file_key, generation))
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid partition `{}` in the checkpoint of a directory source, expected \
+                     `<file key>:<generation>`.",
+                    partition_id.0
+                )
+            })?;
+        let offset = match &position {
+            Position::Beginning => 0,
+            Position::Offset(offset_str) => offset_str.parse::<u64>().with_context(|| {
+                format!(
+                    "Invalid position `{}` in the checkpoint of a directory source.",
+                    offset_str
+                )
+            })?,
+        };
+        let file_state = FileState { generation, offset };
+        file_states
+            .entry(file_key.to_string())
+            .and_modify(|latest_file_state| {
+                if latest_file_state.generation < generation {
+                    *latest_file_state = file_state;
+                }
+            })
+            .or_insert(file_state);
+    }
+    Ok(file_states)
+}
+
+pub struct DirectorySourceFactory;
+
+#[async_trait]
+impl TypedSourceFactory for DirectorySourceFactory {
+    type Source = DirectorySource;
+
+    type Params = DirectorySourceParams;
+
+    async fn typed_create_source(
+        mut params: DirectorySourceParams,
+        checkpoint: Checkpoint,
+    ) -> anyhow::Result<DirectorySource> {
+        params.dirpath = std::fs::canonicalize(&params.dirpath).with_context(|| {
+            format!(
+                "Failed to canonicalize source directory path `{}`.",
+                params.dirpath.display()
+            )
+        })?;
+        if !params.dirpath.is_dir() {
+            anyhow::bail!(
+                "The source directory path `{}` is not a directory.",
+                params.dirpath.display()
+            );
+        }
+        let file_pattern_opt = params
+            .file_pattern
+            .as_ref()
+            .map(|file_pattern| {
+                Regex::new(file_pattern)
+                    .with_context(|| format!("Invalid file pattern `{}`.", file_pattern))
+            })
+            .transpose()?;
+        let file_states = file_states_from_checkpoint(&checkpoint)?;
+        Ok(DirectorySource {
+            params,
+            file_pattern_opt,
+            file_states,
+            counters: DirectorySourceCounters::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::time::Duration;
+
+    use quickwit_actors::{create_test_mailbox, Universe};
+
+    use super::*;
+    use crate::source::SourceActor;
+
+    /// Collects the docs of the batches sent by the source until there are `num_docs` of them.
+    async fn wait_for_docs(
+        drain_messages: impl Fn() -> Vec<IndexerMessage>,
+        num_docs: usize,
+    ) -> Vec<(String, Vec<String>)> {
+        let mut batches = Vec::new();
+        let mut total_num_docs = 0;
+        for _ in 0..100 {
+            for message in drain_messages() {
+                if let IndexerMessage::Batch(batch) = message {
+                    total_num_docs += batch.docs.len();
+                    batches.push((format!("{:?}", batch.checkpoint_delta), batch.docs));
+                }
+            }
+            if total_num_docs >= num_docs {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        batches
+    }
+
+    fn append(path: &Path, content: &str) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(content.as_bytes())?;
+        file.flush()
+    }
+
+    fn first_delta(batches: &[(String, Vec<String>)]) -> &str {
+        &batches[0].0
+    }
+
+    fn docs(batches: &[(String, Vec<String>)]) -> Vec<&str> {
+        batches
+            .iter()
+            .flat_map(|(_, docs)| docs.iter().map(|doc| doc.as_str()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_directory_source() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let universe = Universe::new();
+        let (mailbox, inbox) = create_test_mailbox();
+        let drain_messages = || inbox.drain_available_message_for_test();
+        let temp_dir = tempfile::tempdir()?;
+        let log_path = temp_dir.path().join("app.log");
+        append(&log_path, "{\"line\": 1}\n{\"line\": 2}\n{\"li")?;
+        append(&temp_dir.path().join("other.txt"), "{\"other\": 1}\n")?;
+        let params = DirectorySourceParams {
+            dirpath: temp_dir.path().to_path_buf(),
+            file_pattern: Some(r"^app\.log(\.1)?$".to_string()),
+            max_line_num_bytes: None,
+        };
+        let source =
+            DirectorySourceFactory::typed_create_source(params, Checkpoint::default()).await?;
+        let source_actor = SourceActor {
+            source: Box::new(source),
+            batch_sink: mailbox,
+        };
+        let (_source_mailbox, source_handle) = universe.spawn_actor(source_actor).spawn_async();
+
+        // The incomplete last line is left for later.
+        let batches = wait_for_docs(drain_messages, 2).await;
+        assert_eq!(docs(&batches), vec!["{\"line\": 1}\n", "{\"line\": 2}\n"]);
+        assert!(first_delta(&batches).ends_with(":0:(00000000000000000000..00000000000000000024])"));
+
+        append(&log_path, "ne\": 3}\n")?;
+        let batches = wait_for_docs(drain_messages, 1).await;
+        assert_eq!(docs(&batches), vec!["{\"line\": 3}\n"]);
+        assert!(first_delta(&batches).ends_with(":0:(00000000000000000024..00000000000000000036])"));
+
+        // The rotated file is read to its end, but not indexed again.
+        let rotated_log_path = temp_dir.path().join("app.log.1");
+        std::fs::rename(&log_path, &rotated_log_path)?;
+        append(&rotated_log_path, "{\"line\": 4}\n")?;
+        let batches = wait_for_docs(drain_messages, 1).await;
+        assert_eq!(docs(&batches), vec!["{\"line\": 4}\n"]);
+        append(&log_path, "{\"line\": 5}\n")?;
+        let batches = wait_for_docs(drain_messages, 1).await;
+        assert_eq!(docs(&batches), vec!["{\"line\": 5}\n"]);
+        assert!(first_delta(&batches).ends_with(":0:(00000000000000000000..00000000000000000012])"));
+
+        // The truncated file is read again from its beginning, as a new partition.
+        std::fs::write(&log_path, "{\"l\": 6}\n")?;
+        let batches = wait_for_docs(drain_messages, 1).await;
+        assert_eq!(docs(&batches), vec!["{\"l\": 6}\n"]);
+        assert!(first_delta(&batches).ends_with(":1:(00000000000000000000..00000000000000000009])"));
+
+        let (_exit_status, counters) = source_handle.kill().await;
+        assert_eq!(
+            counters,
+            serde_json::json!({
+                "num_watched_files": 2,
+                "num_bytes_processed": 69,
+                "num_lines_processed": 6,
+                "num_skipped_lines": 0,
+                "num_truncations": 1,
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_directory_source_resume_from_checkpoint() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let universe = Universe::new();
+        let (mailbox, inbox) = create_test_mailbox();
+        let temp_dir = tempfile::tempdir()?;
+        let log_path = temp_dir.path().join("app.log");
+        append(&log_path, "{\"line\": 1}\n{\"line\": 2}\n")?;
+        let metadata = std::fs::metadata(&log_path)?;
+        let checkpoint: Checkpoint = vec![(
+            PartitionId::from(format!("{}:0", file_key(&log_path, &metadata))),
+            Position::from(12u64),
+        )]
+        .into_iter()
+        .collect();
+        let params = DirectorySourceParams {
+            dirpath: temp_dir.path().to_path_buf(),
+            file_pattern: None,
+            max_line_num_bytes: None,
+        };
+        let source = DirectorySourceFactory::typed_create_source(params, checkpoint).await?;
+        let source_actor = SourceActor {
+            source: Box::new(source),
+            batch_sink: mailbox,
+        };
+        let (_source_mailbox, source_handle) = universe.spawn_actor(source_actor).spawn_async();
+        let batches = wait_for_docs(|| inbox.drain_available_message_for_test(), 1).await;
+        assert_eq!(docs(&batches), vec!["{\"line\": 2}\n"]);
+        source_handle.kill().await;
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_states_from_checkpoint() -> anyhow::Result<()> {
+        let checkpoint: Checkpoint = vec![
+            (PartitionId::from("12-345:0"), Position::from(120u64)),
+            (PartitionId::from("12-345:1"), Position::from(10u64)),
+            (PartitionId::from("12-678:0"), Position::from(35u64)),
+        ]
+        .into_iter()
+        .collect();
+        let file_states = file_states_from_checkpoint(&checkpoint)?;
+        assert_eq!(file_states.len(), 2);
+        assert_eq!(
+            file_states["12-345"],
+            FileState {
+                generation: 1,
+                offset: 10
+            }
+        );
+        assert_eq!(
+            file_states["12-678"],
+            FileState {
+                generation: 0,
+                offset: 35
+            }
+        );
+        let invalid_checkpoint: Checkpoint =
+            vec![(PartitionId::from("app.log"), Position::from(3u64))]
+                .into_iter()
+                .collect();
+        assert!(file_states_from_checkpoint(&invalid_checkpoint).is_err());
+        Ok(())
+    }
+}
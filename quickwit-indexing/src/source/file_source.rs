@@ -26,12 +26,14 @@ use async_trait::async_trait;
 use quickwit_actors::{ActorExitStatus, Mailbox};
 use quickwit_metastore::checkpoint::{CheckpointDelta, PartitionId, Position};
 use serde::{Deserialize, Serialize};
+use tantivy::chrono::Utc;
 use tokio::fs::File;
-use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncSeekExt, BufReader};
-use tracing::info;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, BufReader};
+use tracing::{info, warn};
 
 use crate::models::{IndexerMessage, RawDocBatch};
 use crate::source::{Source, SourceContext, TypedSourceFactory};
+use crate::split_id_from_checkpoint_delta;
 
 /// Cut a new batch as soon as we have read BATCH_NUM_BYTES_THRESHOLD.
 const BATCH_NUM_BYTES_THRESHOLD: u64 = 500_000u64;
@@ -41,6 +43,8 @@ pub struct FileSourceCounters {
     pub previous_offset: u64,
     pub current_offset: u64,
     pub num_lines_processed: u64,
+    /// Number of lines longer than the maximum line size that were skipped.
+    pub num_skipped_lines: u64,
 }
 
 pub struct FileSource {
@@ -65,22 +69,31 @@ impl Source for FileSource {
         let limit_num_bytes = self.counters.previous_offset + BATCH_NUM_BYTES_THRESHOLD;
         let mut reached_eof = false;
         let mut docs = Vec::new();
+        let mut num_skipped_docs = 0;
         while self.counters.current_offset < limit_num_bytes {
-            let mut doc_line = String::new();
-            let num_bytes = self
-                .reader
-                .read_line(&mut doc_line)
-                .await
-                .map_err(|io_err: io::Error| anyhow::anyhow!(io_err))?;
+            let (num_bytes, doc_line_opt) =
+                read_bounded_line(&mut self.reader, self.params.max_line_num_bytes)
+                    .await
+                    .map_err(|io_err: io::Error| anyhow::anyhow!(io_err))?;
             if num_bytes == 0 {
                 reached_eof = true;
                 break;
             }
-            docs.push(doc_line);
+            if let Some(doc_line) = doc_line_opt {
+                docs.push(doc_line);
+            } else {
+                warn!(
+                    offset = self.counters.current_offset,
+                    num_bytes = num_bytes,
+                    "Skipping line longer than the maximum line size."
+                );
+                self.counters.num_skipped_lines += 1;
+                num_skipped_docs += 1;
+            }
             self.counters.current_offset += num_bytes as u64;
             self.counters.num_lines_processed += 1;
         }
-        if !docs.is_empty() {
+        if !docs.is_empty() || num_skipped_docs > 0 {
             let checkpoint_delta = self
                 .params
                 .filepath
@@ -93,9 +106,19 @@ impl Source for FileSource {
                     )
                 })
                 .unwrap_or_else(CheckpointDelta::default);
+            // Restarting from a checkpoint replays the exact same batches, unless the
+            // documents are read from stdin, which leaves the checkpoint delta empty.
+            let split_id_opt = if checkpoint_delta.is_empty() {
+                None
+            } else {
+                Some(split_id_from_checkpoint_delta(&checkpoint_delta))
+            };
             let raw_doc_batch = RawDocBatch {
                 docs,
                 checkpoint_delta,
+                split_id_opt,
+                received_timestamp: Utc::now().timestamp(),
+                num_skipped_docs,
             };
             self.counters.previous_offset = self.counters.current_offset;
             ctx.send_message(batch_sink, raw_doc_batch.into()).await?;
@@ -117,10 +140,58 @@ impl Source for FileSource {
     }
 }
 
-// TODO handle log directories.
+/// Reads the next line of `reader`, line break included.
+///
+/// The lines longer than `max_line_num_bytes_opt` (line break excluded) are consumed without
+/// being held in memory, and returned as `None`. Returns the number of bytes consumed, which is
+/// 0 at EOF.
+pub(crate) async fn read_bounded_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_line_num_bytes_opt: Option<u64>,
+) -> io::Result<(usize, Option<String>)> {
+    let max_line_num_bytes = if let Some(max_line_num_bytes) = max_line_num_bytes_opt {
+        max_line_num_bytes
+    } else {
+        let mut line = String::new();
+        let num_bytes = reader.read_line(&mut line).await?;
+        return Ok((num_bytes, Some(line)));
+    };
+    let mut line_bytes = Vec::new();
+    let mut num_bytes = (&mut *reader)
+        .take(max_line_num_bytes + 1)
+        .read_until(b'\n', &mut line_bytes)
+        .await?;
+    if line_bytes.len() as u64 <= max_line_num_bytes || line_bytes.ends_with(b"\n") {
+        let line = String::from_utf8(line_bytes)
+            .map_err(|utf8_error| io::Error::new(io::ErrorKind::InvalidData, utf8_error))?;
+        return Ok((num_bytes, Some(line)));
+    }
+    // The line is too long: we skip the rest of it chunk by chunk.
+    loop {
+        let buffer = reader.fill_buf().await?;
+        if buffer.is_empty() {
+            break;
+        }
+        if let Some(line_break_pos) = buffer.iter().position(|byte| *byte == b'\n') {
+            reader.consume(line_break_pos + 1);
+            num_bytes += line_break_pos + 1;
+            break;
+        }
+        let buffer_len = buffer.len();
+        reader.consume(buffer_len);
+        num_bytes += buffer_len;
+    }
+    Ok((num_bytes, None))
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FileSourceParams {
     pub filepath: Option<PathBuf>, //< If None read from stdin.
+    /// If set, the lines longer than this number of bytes are skipped instead of being read
+    /// into memory.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_line_num_bytes: Option<u64>,
 }
 
 pub struct FileSourceFactory;
@@ -170,6 +241,7 @@ impl TypedSourceFactory for FileSourceFactory {
                 previous_offset: offset,
                 current_offset: offset,
                 num_lines_processed: 0,
+                num_skipped_lines: 0,
             },
             reader: BufReader::new(reader),
             params,
@@ -195,6 +267,7 @@ mod tests {
         let (mailbox, inbox) = create_test_mailbox();
         let params = FileSourceParams {
             filepath: Some(PathBuf::from("data/test_corpus.json")),
+            max_line_num_bytes: None,
         };
         let file_source =
             FileSourceFactory::typed_create_source(params, Checkpoint::default()).await?;
@@ -211,7 +284,8 @@ mod tests {
             serde_json::json!({
                 "previous_offset": 70u64,
                 "current_offset": 70u64,
-                "num_lines_processed": 4
+                "num_lines_processed": 4,
+                "num_skipped_lines": 0
             })
         );
         let batch = inbox.drain_available_message_or_command_for_test();
@@ -238,6 +312,7 @@ mod tests {
         temp_file.flush()?;
         let params = FileSourceParams {
             filepath: Some(temp_path.as_path().to_path_buf()),
+            max_line_num_bytes: None,
         };
         let source = FileSourceFactory::typed_create_source(params, Checkpoint::default()).await?;
         let file_source_actor = SourceActor {
@@ -253,7 +328,8 @@ mod tests {
             serde_json::json!({
                 "previous_offset": 700_000u64,
                 "current_offset": 700_000u64,
-                "num_lines_processed": 20_000
+                "num_lines_processed": 20_000,
+                "num_skipped_lines": 0
             })
         );
         let indexer_msgs = inbox.drain_available_message_or_command_for_test();
@@ -272,6 +348,11 @@ mod tests {
             &extract_position_delta(&batch2.checkpoint_delta).unwrap(),
             "00000000000000500010..00000000000000700000"
         );
+        assert_eq!(
+            batch1.split_id_opt,
+            Some(split_id_from_checkpoint_delta(&batch1.checkpoint_delta))
+        );
+        assert_ne!(batch1.split_id_opt, batch2.split_id_opt);
         assert!(matches!(
             &msg3,
             &CommandOrMessage::Command(Command::ExitWithSuccess)
@@ -294,6 +375,59 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_file_source_counts_skipped_lines_in_batches() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let universe = Universe::new();
+        let (mailbox, inbox) = create_test_mailbox();
+        let mut temp_file = tempfile::NamedTempFile::new()?;
+        temp_file.write_all(b"{\"body\": \"short\"}\n")?;
+        temp_file.write_all(format!("{{\"body\": \"{}\"}}\n", "x".repeat(100)).as_bytes())?;
+        temp_file.flush()?;
+        let params = FileSourceParams {
+            filepath: Some(temp_file.path().to_path_buf()),
+            max_line_num_bytes: Some(50),
+        };
+        let source = FileSourceFactory::typed_create_source(params, Checkpoint::default()).await?;
+        let file_source_actor = SourceActor {
+            source: Box::new(source),
+            batch_sink: mailbox,
+        };
+        let (_file_source_mailbox, file_source_handle) =
+            universe.spawn_actor(file_source_actor).spawn_async();
+        let (actor_termination, counters) = file_source_handle.join().await;
+        assert!(actor_termination.is_success());
+        assert_eq!(counters["num_skipped_lines"], 1);
+        let indexer_msg = inbox
+            .drain_available_message_or_command_for_test()
+            .into_iter()
+            .next()
+            .unwrap();
+        let batch = extract_batch_from_indexer_message(indexer_msg.message().unwrap()).unwrap();
+        assert_eq!(batch.docs.len(), 1);
+        assert_eq!(batch.num_skipped_docs, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_bounded_line() -> anyhow::Result<()> {
+        let mut reader = BufReader::with_capacity(4, "short\nway too long\nlast".as_bytes());
+        assert_eq!(
+            read_bounded_line(&mut reader, Some(8)).await?,
+            (6, Some("short\n".to_string()))
+        );
+        assert_eq!(read_bounded_line(&mut reader, Some(8)).await?, (13, None));
+        assert_eq!(
+            read_bounded_line(&mut reader, Some(8)).await?,
+            (4, Some("last".to_string()))
+        );
+        assert_eq!(
+            read_bounded_line(&mut reader, Some(8)).await?,
+            (0, Some(String::new()))
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_file_source_resume_from_checkpoint() -> anyhow::Result<()> {
         quickwit_common::setup_logging_for_tests();
@@ -308,6 +442,7 @@ mod tests {
         temp_file.flush()?;
         let params = FileSourceParams {
             filepath: Some(temp_path.as_path().to_path_buf()),
+            max_line_num_bytes: None,
         };
         let mut checkpoint = Checkpoint::default();
         let partition_id = PartitionId::from(
@@ -337,7 +472,8 @@ mod tests {
             serde_json::json!({
                 "previous_offset": 290u64,
                 "current_offset": 290u64,
-                "num_lines_processed": 98
+                "num_lines_processed": 98,
+                "num_skipped_lines": 0
             })
         );
         let indexer_msgs = inbox.drain_available_message_for_test();
@@ -40,11 +40,12 @@ use rdkafka::util::Timeout;
 use rdkafka::{ClientContext, Message, Offset};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tantivy::chrono::Utc;
 use tokio::task::spawn_blocking;
 use tracing::{debug, info, warn};
 
 use crate::models::RawDocBatch;
-use crate::source::{IndexerMessage, Source, SourceContext, TypedSourceFactory};
+use crate::source::{IndexerMessage, Source, SourceContext, SourceShard, TypedSourceFactory};
 
 /// We try to emit chewable batches for the indexer.
 /// One batch = one message to the indexer actor.
@@ -80,7 +81,15 @@ impl TypedSourceFactory for KafkaSourceFactory {
         params: KafkaSourceParams,
         checkpoint: Checkpoint,
     ) -> anyhow::Result<Self::Source> {
-        KafkaSource::try_new(params, checkpoint).await
+        KafkaSource::try_new(params, checkpoint, None).await
+    }
+
+    async fn typed_create_source_shard(
+        params: KafkaSourceParams,
+        checkpoint: Checkpoint,
+        source_shard: SourceShard,
+    ) -> anyhow::Result<Self::Source> {
+        KafkaSource::try_new(params, checkpoint, Some(source_shard)).await
     }
 }
 
@@ -134,14 +143,27 @@ impl fmt::Debug for KafkaSource {
 }
 
 impl KafkaSource {
-    /// Instantiates a new `KafkaSource`.
+    /// Instantiates a new `KafkaSource`. If a `source_shard` is provided, the source only
+    /// consumes the partitions of the shard.
     pub async fn try_new(
         params: KafkaSourceParams,
         checkpoint: Checkpoint,
+        source_shard_opt: Option<SourceShard>,
     ) -> anyhow::Result<KafkaSource> {
         let topic = params.topic;
         let consumer = create_consumer(params.client_log_level, params.client_params)?;
-        let partition_ids = fetch_partition_ids(consumer.clone(), &topic).await?;
+        let mut partition_ids = fetch_partition_ids(consumer.clone(), &topic).await?;
+        if let Some(source_shard) = source_shard_opt {
+            partition_ids = shard_partition_ids(partition_ids, &source_shard);
+            if partition_ids.is_empty() {
+                bail!(
+                    "Shard {} of {} of topic `{}` has no partitions.",
+                    source_shard.shard_id,
+                    source_shard.num_shards,
+                    topic
+                );
+            }
+        }
         let assigned_partition_ids = partition_ids
             .iter()
             .map(|partition_id| {
@@ -242,8 +264,12 @@ impl Source for KafkaSource {
                 .insert(message.partition(), current_position.clone())
                 .unwrap_or_else(|| previous_position_for_offset(message.offset()));
             checkpoint_delta
-                .record_partition_delta(partition_id, previous_position, current_position)
+                .record_partition_delta(partition_id.clone(), previous_position, current_position)
                 .context("Failed to record partition delta.")?;
+            if let Some(timestamp_millis) = message.timestamp().to_millis() {
+                checkpoint_delta
+                    .record_partition_watermark(partition_id, timestamp_millis.div_euclid(1_000));
+            }
 
             if batch_num_bytes >= TARGET_BATCH_NUM_BYTES {
                 break;
@@ -253,6 +279,9 @@ impl Source for KafkaSource {
             let batch = RawDocBatch {
                 docs,
                 checkpoint_delta,
+                split_id_opt: None,
+                received_timestamp: Utc::now().timestamp(),
+                num_skipped_docs: 0,
             };
             ctx.send_message(batch_sink, IndexerMessage::from(batch))
                 .await?;
@@ -479,6 +508,17 @@ async fn fetch_watermarks_for_partition_id(
     ).await?
 }
 
+/// Returns the partition IDs of the `source_shard`, ranking the partitions by ID.
+fn shard_partition_ids(mut partition_ids: Vec<i32>, source_shard: &SourceShard) -> Vec<i32> {
+    partition_ids.sort_unstable();
+    partition_ids
+        .into_iter()
+        .enumerate()
+        .filter(|(partition_rank, _)| source_shard.contains(*partition_rank))
+        .map(|(_, partition_id)| partition_id)
+        .collect()
+}
+
 /// Given a checkpoint, computes the next offset from which to start reading messages for the
 /// provided partition IDs. See `compute_next_offset` for further explanation.
 fn compute_assignment(
@@ -577,6 +617,32 @@ fn parse_message_payload(message: &BorrowedMessage) -> Option<String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_shard_partition_ids() {
+        let partition_ids = vec![4, 0, 3, 1, 2];
+        let shard_0 = SourceShard {
+            shard_id: 0,
+            num_shards: 2,
+        };
+        assert_eq!(
+            shard_partition_ids(partition_ids.clone(), &shard_0),
+            vec![0, 2, 4]
+        );
+        let shard_1 = SourceShard {
+            shard_id: 1,
+            num_shards: 2,
+        };
+        assert_eq!(
+            shard_partition_ids(partition_ids.clone(), &shard_1),
+            vec![1, 3]
+        );
+        let shard_5 = SourceShard {
+            shard_id: 5,
+            num_shards: 6,
+        };
+        assert!(shard_partition_ids(partition_ids, &shard_5).is_empty());
+    }
+
     #[test]
     fn test_compute_assignment() -> anyhow::Result<()> {
         let partition_ids = &[0, 1, 2];
@@ -781,6 +847,11 @@ mod kafka_broker_tests {
         Ok(message_map)
     }
 
+    /// Timestamp of the messages produced to a given partition in the tests.
+    fn message_timestamp_millis(partition_id: i32) -> i64 {
+        1_600_000_000_000 + partition_id as i64 * 60_000
+    }
+
     fn key_fn(id: i32) -> String {
         format!("Key {}", id)
     }
@@ -873,7 +944,7 @@ mod kafka_broker_tests {
                     }
                 },
                 Some(partition_id),
-                None,
+                Some(message_timestamp_millis(partition_id)),
             )
             .await?;
         }
@@ -912,6 +983,10 @@ mod kafka_broker_tests {
                     Position::Beginning,
                     Position::from(2u64),
                 )?;
+                expected_checkpoint_delta.record_partition_watermark(
+                    PartitionId::from(partition),
+                    message_timestamp_millis(partition) / 1_000,
+                );
             }
             assert_eq!(batch.checkpoint_delta, expected_checkpoint_delta);
 
@@ -963,6 +1038,12 @@ mod kafka_broker_tests {
                 Position::Beginning,
                 Position::from(2u64),
             )?;
+            for partition in [0, 2] {
+                expected_checkpoint_delta.record_partition_watermark(
+                    PartitionId::from(partition),
+                    message_timestamp_millis(partition) / 1_000,
+                );
+            }
             assert_eq!(batch.checkpoint_delta, expected_checkpoint_delta,);
 
             let expected_exit_state = json!({
@@ -17,6 +17,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+mod directory_source;
 mod file_source;
 #[cfg(feature = "kafka")]
 mod kafka_source;
@@ -26,6 +27,7 @@ mod vec_source;
 use std::fmt;
 
 use async_trait::async_trait;
+pub use directory_source::{DirectorySource, DirectorySourceFactory, DirectorySourceParams};
 pub use file_source::{FileSource, FileSourceFactory, FileSourceParams};
 #[cfg(feature = "kafka")]
 pub use kafka_source::{KafkaSource, KafkaSourceFactory, KafkaSourceParams};
@@ -161,6 +163,7 @@ pub fn quickwit_supported_sources() -> &'static SourceLoader {
     static SOURCE_LOADER: OnceCell<SourceLoader> = OnceCell::new();
     SOURCE_LOADER.get_or_init(|| {
         let mut source_factory = SourceLoader::default();
+        source_factory.add_source("directory", DirectorySourceFactory);
         source_factory.add_source("file", FileSourceFactory);
         #[cfg(feature = "kafka")]
         source_factory.add_source("kafka", KafkaSourceFactory);
@@ -172,7 +175,7 @@ pub fn quickwit_supported_sources() -> &'static SourceLoader {
 /// A `SourceConfig` describes the properties of a source. A source config can be created
 /// dynamically or loaded from a file consisting of a JSON object with 3 mandatory properties:
 /// - `source_id`, a name identifying the source uniquely;
-/// - `source_type`, the type of the target source, for instance, `file` or `kafka`;
+/// - `source_type`, the type of the target source, for instance, `file`, `directory` or `kafka`;
 /// - `params`, an arbitrary object whose keys and values are specific to the source type.
 ///
 /// For instance, a valid source config JSON object for a Kafka source is:
@@ -190,6 +193,22 @@ pub fn quickwit_supported_sources() -> &'static SourceLoader {
 ///     }
 /// }
 /// ```
+/// A `SourceShard` designates the subset of the partitions of a source read by one of the
+/// `num_shards` indexers sharing the source: the partitions whose rank modulo `num_shards`
+/// equals `shard_id`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceShard {
+    pub shard_id: usize,
+    pub num_shards: usize,
+}
+
+impl SourceShard {
+    /// Returns true if the partition of rank `partition_rank` belongs to the shard.
+    pub fn contains(&self, partition_rank: usize) -> bool {
+        partition_rank % self.num_shards == self.shard_id
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct SourceConfig {
     pub source_id: String,
@@ -19,13 +19,14 @@
 
 use std::collections::HashMap;
 
+use anyhow::bail;
 use async_trait::async_trait;
 use itertools::Itertools;
 use quickwit_metastore::checkpoint::Checkpoint;
 use thiserror::Error;
 
 use super::Source;
-use crate::source::SourceConfig;
+use crate::source::{SourceConfig, SourceShard};
 
 #[async_trait]
 pub trait SourceFactory: 'static + Send + Sync {
@@ -34,6 +35,13 @@ pub trait SourceFactory: 'static + Send + Sync {
         params: serde_json::Value,
         checkpoint: Checkpoint,
     ) -> anyhow::Result<Box<dyn Source>>;
+
+    async fn create_source_shard(
+        &self,
+        params: serde_json::Value,
+        checkpoint: Checkpoint,
+        source_shard: SourceShard,
+    ) -> anyhow::Result<Box<dyn Source>>;
 }
 
 #[async_trait]
@@ -44,6 +52,16 @@ pub trait TypedSourceFactory: Send + Sync + 'static {
         params: Self::Params,
         checkpoint: quickwit_metastore::checkpoint::Checkpoint,
     ) -> anyhow::Result<Self::Source>;
+
+    /// Creates a source reading only the partitions of the `source_shard`.
+    /// By default, sources cannot be sharded.
+    async fn typed_create_source_shard(
+        _params: Self::Params,
+        _checkpoint: quickwit_metastore::checkpoint::Checkpoint,
+        _source_shard: SourceShard,
+    ) -> anyhow::Result<Self::Source> {
+        bail!("This type of source cannot be sharded.")
+    }
 }
 
 #[async_trait]
@@ -57,6 +75,18 @@ impl<T: TypedSourceFactory> SourceFactory for T {
         let file_source = Self::typed_create_source(typed_params, checkpoint).await?;
         Ok(Box::new(file_source))
     }
+
+    async fn create_source_shard(
+        &self,
+        params: serde_json::Value,
+        checkpoint: quickwit_metastore::checkpoint::Checkpoint,
+        source_shard: SourceShard,
+    ) -> anyhow::Result<Box<dyn Source>> {
+        let typed_params: T::Params = serde_json::from_value(params)?;
+        let source =
+            Self::typed_create_source_shard(typed_params, checkpoint, source_shard).await?;
+        Ok(Box::new(source))
+    }
 }
 
 #[derive(Default)]
@@ -93,6 +123,27 @@ impl SourceLoader {
         &self,
         source_config: SourceConfig,
         checkpoint: Checkpoint,
+    ) -> Result<Box<dyn Source>, SourceLoaderError> {
+        self.load_source_opt_shard(source_config, checkpoint, None)
+            .await
+    }
+
+    /// Loads a source reading only the partitions of the `source_shard`.
+    pub async fn load_source_shard(
+        &self,
+        source_config: SourceConfig,
+        checkpoint: Checkpoint,
+        source_shard: SourceShard,
+    ) -> Result<Box<dyn Source>, SourceLoaderError> {
+        self.load_source_opt_shard(source_config, checkpoint, Some(source_shard))
+            .await
+    }
+
+    async fn load_source_opt_shard(
+        &self,
+        source_config: SourceConfig,
+        checkpoint: Checkpoint,
+        source_shard_opt: Option<SourceShard>,
     ) -> Result<Box<dyn Source>, SourceLoaderError> {
         let source_factory = self
             .type_to_factory
@@ -106,14 +157,18 @@ impl SourceLoader {
             source_type,
             params,
         } = source_config;
-        source_factory
-            .create_source(params, checkpoint)
-            .await
-            .map_err(|error| SourceLoaderError::FailedToCreateSource {
-                source_id,
-                source_type,
-                error,
-            })
+        let source_res = if let Some(source_shard) = source_shard_opt {
+            source_factory
+                .create_source_shard(params, checkpoint, source_shard)
+                .await
+        } else {
+            source_factory.create_source(params, checkpoint).await
+        };
+        source_res.map_err(|error| SourceLoaderError::FailedToCreateSource {
+            source_id,
+            source_type,
+            error,
+        })
     }
 }
 
@@ -172,4 +227,25 @@ mod tests {
         ));
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_source_loader_unshardable_source() {
+        let source_loader = quickwit_supported_sources();
+        let source_config = SourceConfig {
+            source_id: "test-source".to_string(),
+            source_type: "vec".to_string(),
+            params: json!({"items": [], "batch_num_docs": 3}),
+        };
+        let source_shard = SourceShard {
+            shard_id: 0,
+            num_shards: 2,
+        };
+        let source_result = source_loader
+            .load_source_shard(source_config, Checkpoint::default(), source_shard)
+            .await;
+        assert!(matches!(
+            source_result,
+            Err(SourceLoaderError::FailedToCreateSource { .. })
+        ));
+    }
 }
@@ -17,14 +17,17 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use anyhow::bail;
 use async_trait::async_trait;
 use quickwit_actors::{ActorExitStatus, Mailbox};
 use quickwit_metastore::checkpoint::{Checkpoint, CheckpointDelta, PartitionId, Position};
 use serde::{Deserialize, Serialize};
+use tantivy::chrono::Utc;
 use tracing::info;
 
 use crate::models::{IndexerMessage, RawDocBatch};
 use crate::source::{Source, SourceContext, TypedSourceFactory};
+use crate::split_id_from_checkpoint_delta;
 
 #[derive(Deserialize, Serialize)]
 pub struct VecSourceParams {
@@ -32,9 +35,16 @@ pub struct VecSourceParams {
     pub batch_num_docs: usize,
     #[serde(default)]
     pub partition: String,
+    /// Offset of the first item in the partition.
+    ///
+    /// It lets successive vec sources feed the same partition, each one carrying the items
+    /// following the ones of the previous.
+    #[serde(default)]
+    pub start_offset: usize,
 }
 
 pub struct VecSource {
+    /// Offset in the partition of the next item to emit.
     next_item_idx: usize,
     params: VecSourceParams,
     partition: PartitionId,
@@ -54,6 +64,15 @@ impl TypedSourceFactory for VecSourceFactory {
             Some(Position::Offset(offset_str)) => offset_str.parse::<usize>()? + 1,
             Some(Position::Beginning) | None => 0,
         };
+        if next_item_idx < params.start_offset {
+            bail!(
+                "The items of partition `{}` start at offset {}, but the checkpoint is at offset \
+                 {}.",
+                params.partition,
+                params.start_offset,
+                next_item_idx
+            );
+        }
         Ok(VecSource {
             next_item_idx,
             params,
@@ -76,7 +95,12 @@ impl Source for VecSource {
         batch_sink: &Mailbox<IndexerMessage>,
         ctx: &SourceContext,
     ) -> Result<(), ActorExitStatus> {
-        let line_docs: Vec<String> = self.params.items[self.next_item_idx..]
+        let first_item_idx = self.next_item_idx - self.params.start_offset;
+        let line_docs: Vec<String> = self
+            .params
+            .items
+            .get(first_item_idx..)
+            .unwrap_or_default()
             .iter()
             .take(self.params.batch_num_docs)
             .cloned()
@@ -94,9 +118,13 @@ impl Source for VecSource {
             position_from_offset(from_item_idx),
             position_from_offset(to_item_idx),
         );
+        let split_id_opt = Some(split_id_from_checkpoint_delta(&checkpoint_delta));
         let batch = RawDocBatch {
             docs: line_docs,
             checkpoint_delta,
+            split_id_opt,
+            received_timestamp: Utc::now().timestamp(),
+            num_skipped_docs: 0,
         };
         ctx.send_message(batch_sink, IndexerMessage::from(batch))
             .await?;
@@ -134,6 +162,7 @@ mod tests {
             items,
             batch_num_docs: 3,
             partition: "partition".to_string(),
+            start_offset: 0,
         };
         let vec_source =
             VecSourceFactory::typed_create_source(params, Checkpoint::default()).await?;
@@ -169,6 +198,7 @@ mod tests {
             items,
             batch_num_docs: 3,
             partition: "".to_string(),
+            start_offset: 0,
         };
         let mut checkpoint = Checkpoint::default();
         checkpoint.try_apply_delta(CheckpointDelta::from(0u64..2u64))?;
@@ -189,4 +219,47 @@ mod tests {
         );
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_vec_source_with_start_offset() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let universe = Universe::new();
+        let (mailbox, inbox) = create_test_mailbox();
+        let params = VecSourceParams {
+            items: (10..15).map(|i| format!("{}", i)).collect(),
+            batch_num_docs: 3,
+            partition: "".to_string(),
+            start_offset: 10,
+        };
+        let mut checkpoint = Checkpoint::default();
+        checkpoint.try_apply_delta(CheckpointDelta::from(0u64..11u64))?;
+
+        let vec_source = VecSourceFactory::typed_create_source(params, checkpoint).await?;
+        let vec_source_actor = SourceActor {
+            source: Box::new(vec_source),
+            batch_sink: mailbox,
+        };
+        let (_vec_source_mailbox, vec_source_handle) =
+            universe.spawn_actor(vec_source_actor).spawn_async();
+        let (actor_termination, last_observation) = vec_source_handle.join().await;
+        assert!(actor_termination.is_success());
+        assert_eq!(last_observation, json!({"next_item_idx": 15}));
+        let messages = inbox.drain_available_message_for_test();
+        assert!(
+            matches!(&messages[0], &IndexerMessage::Batch(ref raw_batch) if &raw_batch.docs[0] == "11")
+        );
+
+        let params = VecSourceParams {
+            items: vec!["20".to_string()],
+            batch_num_docs: 3,
+            partition: "".to_string(),
+            start_offset: 20,
+        };
+        assert!(
+            VecSourceFactory::typed_create_source(params, Checkpoint::default())
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
 }
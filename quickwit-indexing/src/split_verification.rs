@@ -0,0 +1,435 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use prometheus::{IntCounter, IntGaugeVec};
+use quickwit_common::metrics::{new_counter, new_gauge_vec};
+use quickwit_directories::verify_split;
+use quickwit_metastore::{
+    retry_on_transient_error, Metastore, SplitMetadataAndFooterOffsets, SplitState,
+    SplitVerification,
+};
+use quickwit_storage::{multipart_file_storage, Storage, StorageErrorKind};
+use serde::Serialize;
+use tantivy::chrono::Utc;
+use tantivy::directory::MmapDirectory;
+use tantivy::Directory;
+use tracing::{error, info, warn};
+
+use crate::models::ScratchDirectory;
+
+/// Name of the local copy of the split file being verified.
+const VERIFIED_SPLIT_FILENAME: &str = "verified.split";
+
+static NUM_VERIFIED_SPLITS: Lazy<IntCounter> = Lazy::new(|| {
+    new_counter(
+        "split_verification:num_verified_splits",
+        "Number of split files whose integrity was verified",
+    )
+});
+
+static NUM_CORRUPTED_SPLITS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    new_gauge_vec(
+        "split_verification:num_corrupted_splits",
+        "Number of published or quarantined splits of the index whose last integrity verification \
+         detected a corruption",
+        &["index_id"],
+    )
+});
+
+/// Statistics of a split verification pass.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SplitVerificationStats {
+    /// The number of splits verified during the pass.
+    pub num_verified_splits: usize,
+    /// The number of splits that could not be verified, e.g. because their download failed.
+    pub num_failed_splits: usize,
+    /// The IDs of the splits found corrupted during the pass. The published ones are
+    /// quarantined.
+    pub corrupted_split_ids: Vec<String>,
+    /// The IDs of the quarantined splits found intact during the pass, which are published
+    /// again.
+    pub restored_split_ids: Vec<String>,
+    /// The number of published or quarantined splits of the index whose last verification
+    /// detected a corruption, including the splits verified during previous passes.
+    pub num_corrupted_splits: usize,
+}
+
+/// Returns the `max_num_splits` splits to verify next: the splits that were never verified
+/// come first, then the splits verified the longest time ago.
+pub fn select_splits_to_verify(
+    mut splits: Vec<SplitMetadataAndFooterOffsets>,
+    max_num_splits: usize,
+) -> Vec<SplitMetadataAndFooterOffsets> {
+    splits.sort_by_key(|split| {
+        split
+            .split_metadata
+            .last_verification
+            .as_ref()
+            .map(|verification| verification.verification_timestamp)
+    });
+    splits.truncate(max_num_splits);
+    splits
+}
+
+/// Downloads the file of `split` from `storage` and verifies its integrity.
+///
+/// A split whose file is missing or does not have the length recorded in the metastore is
+/// reported as corrupted. The errors that say nothing about the integrity of the split file,
+/// such as the failure of the download, are returned as errors.
+pub async fn verify_split_file(
+    storage: Arc<dyn Storage>,
+    split: &SplitMetadataAndFooterOffsets,
+) -> anyhow::Result<SplitVerification> {
+    let verification_timestamp = Utc::now().timestamp();
+    let split_file = split.split_metadata.split_file();
+    let scratch_directory = ScratchDirectory::try_new_temp()?;
+    let local_split_path = scratch_directory.path().join(VERIFIED_SPLIT_FILENAME);
+    let split_storage = multipart_file_storage(storage, split.split_metadata.split_part_num_bytes);
+    if let Err(storage_error) = split_storage
+        .copy_to_file(Path::new(&split_file), &local_split_path)
+        .await
+    {
+        if storage_error.kind() == StorageErrorKind::DoesNotExist {
+            return Ok(SplitVerification {
+                verification_timestamp,
+                corruption: Some(format!("The split file `{}` is missing.", split_file)),
+            });
+        }
+        return Err(storage_error)
+            .with_context(|| format!("Failed to download the split file `{}`.", split_file));
+    }
+    let split_file_num_bytes = std::fs::metadata(&local_split_path)?.len();
+    if split_file_num_bytes != split.footer_offsets.end {
+        return Ok(SplitVerification {
+            verification_timestamp,
+            corruption: Some(format!(
+                "The split file is {} bytes long, while the metastore expects {} bytes.",
+                split_file_num_bytes, split.footer_offsets.end
+            )),
+        });
+    }
+    let split_file_slice = MmapDirectory::open(scratch_directory.path())?
+        .open_read(Path::new(VERIFIED_SPLIT_FILENAME))?;
+    // Checksumming the whole split file is CPU bound.
+    let verification_result =
+        tokio::task::spawn_blocking(move || verify_split(split_file_slice)).await?;
+    Ok(SplitVerification {
+        verification_timestamp,
+        corruption: verification_result.err().map(|error| error.to_string()),
+    })
+}
+
+/// Verifies the integrity of up to `max_num_splits` published splits of an index, and records
+/// the outcome of each verification in the metastore. The splits found corrupted are
+/// quarantined, so that they are no longer searched.
+///
+/// * `index_id` - The target index id.
+/// * `storage` - The storage managing the target index.
+/// * `metastore` - The metastore managing the target index.
+/// * `max_num_splits` - The maximum number of splits verified, see [`select_splits_to_verify`].
+pub async fn run_split_verification(
+    index_id: &str,
+    storage: Arc<dyn Storage>,
+    metastore: Arc<dyn Metastore>,
+    max_num_splits: usize,
+) -> anyhow::Result<SplitVerificationStats> {
+    verify_splits(
+        index_id,
+        storage,
+        metastore,
+        SplitState::Published,
+        max_num_splits,
+    )
+    .await
+}
+
+/// Verifies the integrity of up to `max_num_splits` quarantined splits of an index again, and
+/// records the outcome of each verification in the metastore. The splits found intact are
+/// published again, e.g. once a corrupted split file was restored from a backup.
+///
+/// See [`run_split_verification`] for the arguments.
+pub async fn verify_quarantined_splits(
+    index_id: &str,
+    storage: Arc<dyn Storage>,
+    metastore: Arc<dyn Metastore>,
+    max_num_splits: usize,
+) -> anyhow::Result<SplitVerificationStats> {
+    verify_splits(
+        index_id,
+        storage,
+        metastore,
+        SplitState::Quarantined,
+        max_num_splits,
+    )
+    .await
+}
+
+async fn verify_splits(
+    index_id: &str,
+    storage: Arc<dyn Storage>,
+    metastore: Arc<dyn Metastore>,
+    split_state: SplitState,
+    max_num_splits: usize,
+) -> anyhow::Result<SplitVerificationStats> {
+    let published_splits = retry_on_transient_error(|| {
+        metastore.list_splits(index_id, SplitState::Published, None, &[])
+    })
+    .await?;
+    let quarantined_splits = retry_on_transient_error(|| {
+        metastore.list_splits(index_id, SplitState::Quarantined, None, &[])
+    })
+    .await?;
+    let mut num_corrupted_splits = published_splits
+        .iter()
+        .chain(&quarantined_splits)
+        .filter(|split| {
+            split
+                .split_metadata
+                .last_verification
+                .as_ref()
+                .map_or(false, SplitVerification::is_corrupted)
+        })
+        .count();
+    let splits = if split_state == SplitState::Published {
+        published_splits
+    } else {
+        quarantined_splits
+    };
+    let mut stats = SplitVerificationStats::default();
+    for split in select_splits_to_verify(splits, max_num_splits) {
+        let split_id = split.split_metadata.split_id.as_str();
+        let verification = match verify_split_file(storage.clone(), &split).await {
+            Ok(verification) => verification,
+            Err(error) => {
+                warn!(
+                    index_id = %index_id,
+                    split_id = %split_id,
+                    error = ?error,
+                    "Failed to verify split."
+                );
+                stats.num_failed_splits += 1;
+                continue;
+            }
+        };
+        NUM_VERIFIED_SPLITS.inc();
+        stats.num_verified_splits += 1;
+        let was_corrupted = split
+            .split_metadata
+            .last_verification
+            .as_ref()
+            .map_or(false, SplitVerification::is_corrupted);
+        let is_corrupted = verification.is_corrupted();
+        if let Some(corruption) = &verification.corruption {
+            error!(
+                index_id = %index_id,
+                split_id = %split_id,
+                corruption = %corruption,
+                "Split is corrupted."
+            );
+            stats.corrupted_split_ids.push(split_id.to_string());
+            if !was_corrupted {
+                num_corrupted_splits += 1;
+            }
+        } else if was_corrupted {
+            num_corrupted_splits -= 1;
+        }
+        metastore
+            .record_split_verification(index_id, split_id, verification)
+            .await?;
+        if is_corrupted && split_state == SplitState::Published {
+            warn!(index_id = %index_id, split_id = %split_id, "Quarantining corrupted split.");
+            metastore.quarantine_splits(index_id, &[split_id]).await?;
+        } else if !is_corrupted && split_state == SplitState::Quarantined {
+            info!(index_id = %index_id, split_id = %split_id, "Publishing intact split again.");
+            metastore.unquarantine_splits(index_id, &[split_id]).await?;
+            stats.restored_split_ids.push(split_id.to_string());
+        }
+    }
+    NUM_CORRUPTED_SPLITS
+        .with_label_values(&[index_id])
+        .set(num_corrupted_splits as i64);
+    stats.num_corrupted_splits = num_corrupted_splits;
+    info!(index_id = %index_id, stats = ?stats, "split-verification");
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use quickwit_directories::{BundleDirectory, SPLIT_STATS_FILENAME};
+    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_metastore::SplitMetadata;
+    use quickwit_storage::PutPayload;
+    use serde_json::json;
+
+    use super::*;
+    use crate::TestSandbox;
+
+    fn make_split(
+        split_id: &str,
+        verification_timestamp_opt: Option<i64>,
+    ) -> SplitMetadataAndFooterOffsets {
+        SplitMetadataAndFooterOffsets {
+            split_metadata: SplitMetadata {
+                split_id: split_id.to_string(),
+                last_verification: verification_timestamp_opt.map(|verification_timestamp| {
+                    SplitVerification {
+                        verification_timestamp,
+                        corruption: None,
+                    }
+                }),
+                ..Default::default()
+            },
+            footer_offsets: 5..20,
+        }
+    }
+
+    #[test]
+    fn test_select_splits_to_verify() {
+        let splits = vec![
+            make_split("a", Some(30)),
+            make_split("b", None),
+            make_split("c", Some(10)),
+            make_split("d", Some(20)),
+        ];
+        let selected_split_ids: Vec<String> = select_splits_to_verify(splits, 3)
+            .into_iter()
+            .map(|split| split.split_metadata.split_id)
+            .collect();
+        assert_eq!(selected_split_ids, vec!["b", "c", "d"]);
+    }
+
+    #[tokio::test]
+    async fn test_run_split_verification() -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let index_id = "test-split-verification-index";
+        let index_config = Arc::new(WikipediaIndexConfig::new());
+        let test_sandbox = TestSandbox::create(index_id, index_config).await?;
+        test_sandbox
+            .add_documents(vec![
+                json!({"title": "Hurricane Fay", "body": "...", "url": "http://hurricane-fay"}),
+            ])
+            .await?;
+        test_sandbox
+            .add_documents(vec![
+                json!({"title": "Ganimede", "body": "...", "url": "http://ganimede"}),
+            ])
+            .await?;
+        let metastore = test_sandbox.metastore();
+        let storage = test_sandbox.index_storage(index_id)?;
+
+        let stats =
+            run_split_verification(index_id, storage.clone(), metastore.clone(), 10).await?;
+        assert_eq!(stats.num_verified_splits, 2);
+        assert_eq!(stats.num_failed_splits, 0);
+        assert!(stats.corrupted_split_ids.is_empty());
+        assert_eq!(stats.num_corrupted_splits, 0);
+
+        // Flip a bit in the middle of a segment file of the first split.
+        let splits = metastore.list_all_splits(index_id).await?;
+        assert!(splits
+            .iter()
+            .all(|split| split.split_metadata.last_verification.is_some()));
+        let corrupted_split_id = splits[0].split_metadata.split_id.clone();
+        let split_file = PathBuf::from(splits[0].split_metadata.split_file());
+        let split_bytes = storage.get_all(&split_file).await?;
+        let file_offsets = BundleDirectory::get_file_offsets_split(split_bytes.clone())?;
+        let segment_file_range = file_offsets
+            .files
+            .iter()
+            .find(|(path, _)| {
+                path.as_path() != Path::new("meta.json")
+                    && path.as_path() != Path::new(SPLIT_STATS_FILENAME)
+            })
+            .map(|(_, range)| range.clone())
+            .unwrap();
+        let mut corrupted_split_bytes = split_bytes.to_vec();
+        corrupted_split_bytes[(segment_file_range.start + segment_file_range.end) / 2] ^= 1;
+        storage
+            .put(&split_file, PutPayload::from(corrupted_split_bytes))
+            .await?;
+
+        let stats =
+            run_split_verification(index_id, storage.clone(), metastore.clone(), 10).await?;
+        assert_eq!(stats.num_verified_splits, 2);
+        assert_eq!(stats.corrupted_split_ids, vec![corrupted_split_id.clone()]);
+        assert_eq!(stats.num_corrupted_splits, 1);
+        let quarantined_splits = metastore
+            .list_splits(index_id, SplitState::Quarantined, None, &[])
+            .await?;
+        assert_eq!(quarantined_splits.len(), 1);
+        assert_eq!(
+            quarantined_splits[0].split_metadata.split_id,
+            corrupted_split_id
+        );
+
+        // The quarantined split is no longer verified with the published splits.
+        let stats =
+            run_split_verification(index_id, storage.clone(), metastore.clone(), 10).await?;
+        assert_eq!(stats.num_verified_splits, 1);
+        assert!(stats.corrupted_split_ids.is_empty());
+        assert_eq!(stats.num_corrupted_splits, 1);
+
+        // Once the split file is gone, the split is still reported as corrupted.
+        storage.delete(&split_file).await?;
+        let stats =
+            verify_quarantined_splits(index_id, storage.clone(), metastore.clone(), 10).await?;
+        assert_eq!(stats.corrupted_split_ids, vec![corrupted_split_id.clone()]);
+        assert!(stats.restored_split_ids.is_empty());
+        assert_eq!(stats.num_corrupted_splits, 1);
+        let corrupted_split = metastore
+            .list_all_splits(index_id)
+            .await?
+            .into_iter()
+            .find(|split| split.split_metadata.split_id == corrupted_split_id)
+            .unwrap();
+        assert_eq!(
+            corrupted_split.split_metadata.split_state,
+            SplitState::Quarantined
+        );
+        assert!(corrupted_split
+            .split_metadata
+            .last_verification
+            .unwrap()
+            .is_corrupted());
+
+        // Once the split file is restored, the split is published again.
+        storage
+            .put(&split_file, PutPayload::from(split_bytes.to_vec()))
+            .await?;
+        let stats = verify_quarantined_splits(index_id, storage, metastore.clone(), 10).await?;
+        assert!(stats.corrupted_split_ids.is_empty());
+        assert_eq!(stats.restored_split_ids, vec![corrupted_split_id]);
+        assert_eq!(stats.num_corrupted_splits, 0);
+        assert_eq!(
+            metastore
+                .list_splits(index_id, SplitState::Published, None, &[])
+                .await?
+                .len(),
+            2
+        );
+        Ok(())
+    }
+}
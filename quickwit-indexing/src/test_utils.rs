@@ -17,11 +17,13 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use byte_unit::Byte;
+use quickwit_common::QuickwitUri;
 use quickwit_index_config::IndexConfig;
 use quickwit_metastore::checkpoint::Checkpoint;
 use quickwit_metastore::{
@@ -32,7 +34,9 @@ use quickwit_storage::{Storage, StorageResolverError, StorageUriResolver};
 
 use crate::actors::IndexerParams;
 use crate::index_data;
-use crate::models::{CommitPolicy, IndexingStatistics, ScratchDirectory};
+use crate::models::{
+    CommitPolicy, DiskGuardrails, DocSizeLimit, IndexingStatistics, ScratchDirectory,
+};
 use crate::source::{SourceConfig, VecSourceParams};
 
 /// Creates a Test environment.
@@ -59,12 +63,17 @@ impl TestSandbox {
         index_id: &str,
         index_config: Arc<dyn IndexConfig>,
     ) -> anyhow::Result<Self> {
-        let index_uri = index_uri(index_id);
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri,
+            index_uri: QuickwitUri::from_str(&index_uri(index_id))?,
             index_config,
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
         let storage_uri_resolver = StorageUriResolver::for_test();
         let metastore_uri_resolver = MetastoreUriResolver::default();
@@ -98,6 +107,7 @@ impl TestSandbox {
                 items: docs,
                 batch_num_docs: 10,
                 partition: format!("add_docs{}", self.add_docs_id.load(Ordering::SeqCst)),
+                start_offset: 0,
             })?,
         };
         self.add_docs_id.fetch_add(1, Ordering::SeqCst);
@@ -107,7 +117,11 @@ impl TestSandbox {
             commit_policy: CommitPolicy {
                 timeout: Duration::from_secs(3600),
                 num_docs_threshold: 5_000_000,
+                adaptive_opt: None,
             },
+            disk_guardrails: DiskGuardrails::default(),
+            doc_size_limit: DocSizeLimit::default(),
+            realtime_opt: None,
         };
         let statistics = index_data(
             self.index_id.clone(),
@@ -122,9 +136,7 @@ impl TestSandbox {
 
     /// Returns the metastore of the TestIndex
     ///
-    /// The metastore is a single file metastore.
-    /// Its data can be found via the `storage_uri_resolver` in
-    /// the `ram://quickwit-test-indices` directory.
+    /// The metastore is held in memory, and shared by the test sandboxes of the process.
     pub fn metastore(&self) -> Arc<dyn Metastore> {
         self.metastore.clone()
     }
@@ -152,6 +164,11 @@ pub fn mock_split_meta(split_id: &str) -> SplitMetadataAndFooterOffsets {
             time_range: None,
             update_timestamp: 0,
             tags: Default::default(),
+            index_config_version: 0,
+            split_file_path: None,
+            ingest_timestamps: None,
+            last_verification: None,
+            split_part_num_bytes: None,
         },
     }
 }
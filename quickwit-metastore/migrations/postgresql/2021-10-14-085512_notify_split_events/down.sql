@@ -0,0 +1,2 @@
+DROP TRIGGER IF EXISTS notify_split_events ON splits;
+DROP FUNCTION IF EXISTS notify_split_events();
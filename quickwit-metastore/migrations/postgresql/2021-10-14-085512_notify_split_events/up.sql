@@ -0,0 +1,32 @@
+-- Notifies the listeners of the `quickwit_split_events` channel when a split starts or stops
+-- being published, i.e. searchable.
+CREATE OR REPLACE FUNCTION notify_split_events() RETURNS trigger AS $$
+DECLARE
+    was_published BOOLEAN := FALSE;
+    is_published BOOLEAN := FALSE;
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        was_published := OLD.split_state = 'Published';
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        is_published := NEW.split_state = 'Published';
+    END IF;
+    IF is_published AND NOT was_published THEN
+        PERFORM pg_notify('quickwit_split_events', json_build_object(
+            'index_id', NEW.index_id,
+            'split_id', NEW.split_id,
+            'event', 'Published'
+        )::text);
+    ELSIF was_published AND NOT is_published THEN
+        PERFORM pg_notify('quickwit_split_events', json_build_object(
+            'index_id', OLD.index_id,
+            'split_id', OLD.split_id,
+            'event', 'Deleted'
+        )::text);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER notify_split_events AFTER INSERT OR UPDATE OR DELETE ON splits
+    FOR EACH ROW EXECUTE PROCEDURE notify_split_events();
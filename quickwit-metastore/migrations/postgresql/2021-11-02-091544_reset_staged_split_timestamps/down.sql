@@ -0,0 +1,2 @@
+-- The timestamps previously assigned by the clients cannot be restored, and need not be.
+SELECT 1;
@@ -0,0 +1,11 @@
+-- The update timestamps of the splits used to be assigned by the clocks of the metastore clients,
+-- which may be skewed. Resets the timestamps of the staged splits to the database clock, so that
+-- the garbage collection grace period, now measured with this clock, never deletes a split that
+-- is still being uploaded. At worst, it delays the deletion of a dangling split by a grace period.
+UPDATE splits
+SET split_metadata_json = jsonb_set(
+    split_metadata_json::jsonb,
+    '{split_metadata,update_timestamp}',
+    to_jsonb(CAST(EXTRACT(EPOCH FROM NOW()) AS BIGINT))
+)::text
+WHERE split_state = 'Staged';
@@ -0,0 +1,13 @@
+-- Leases of the shards of the sources, granting an indexer the exclusive right to index the
+-- partitions of a shard.
+CREATE TABLE source_shard_leases (
+    index_id VARCHAR(50) NOT NULL,
+    source_id VARCHAR(50) NOT NULL,
+    shard_id INTEGER NOT NULL,
+    num_shards INTEGER NOT NULL,
+    owner VARCHAR(50) NOT NULL,
+    expiration_timestamp BIGINT NOT NULL,
+
+    PRIMARY KEY(index_id, source_id, shard_id),
+    FOREIGN KEY(index_id) REFERENCES indexes(index_id) ON DELETE CASCADE
+);
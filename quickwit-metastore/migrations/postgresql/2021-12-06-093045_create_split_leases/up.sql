@@ -0,0 +1,11 @@
+-- Leases of the splits being read by the searches, which the garbage collectors of all the
+-- nodes leave untouched until they are released or expire.
+CREATE TABLE split_leases (
+    index_id VARCHAR(50) NOT NULL,
+    lease_id VARCHAR(50) NOT NULL,
+    split_id VARCHAR(50) NOT NULL,
+    expiration_timestamp BIGINT NOT NULL,
+
+    PRIMARY KEY(index_id, lease_id, split_id),
+    FOREIGN KEY(index_id) REFERENCES indexes(index_id) ON DELETE CASCADE
+);
@@ -141,9 +141,14 @@ impl<'a> From<&'a str> for Position {
 ///
 /// If a partition is missing, it implicitely means that none of its message
 /// has been processed.
+///
+/// Sources that know when their messages were produced also record a watermark
+/// for their partitions: the maximum event timestamp, in seconds, of the messages
+/// processed so far. The ingest lag of a partition is the time elapsed since it.
 #[derive(Default, Clone)]
 pub struct Checkpoint {
     per_partition: BTreeMap<PartitionId, Position>,
+    per_partition_watermark: BTreeMap<PartitionId, i64>,
 }
 
 impl Checkpoint {
@@ -173,32 +178,86 @@ impl FromIterator<(PartitionId, Position)> for Checkpoint {
     where I: IntoIterator<Item = (PartitionId, Position)> {
         Checkpoint {
             per_partition: iter.into_iter().collect(),
+            per_partition_watermark: BTreeMap::new(),
         }
     }
 }
 
-impl Serialize for Checkpoint {
+/// Positions of a checkpoint, serialized as a map from partition to position.
+struct SerializedPositions<'a>(&'a BTreeMap<PartitionId, Position>);
+
+impl<'a> Serialize for SerializedPositions<'a> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where S: serde::Serializer {
-        let mut map = serializer.serialize_map(Some(self.per_partition.len()))?;
-        for (partition, position) in &self.per_partition {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (partition, position) in self.0 {
             map.serialize_entry(&*partition.0, &*position.as_str())?;
         }
         map.end()
     }
 }
 
+/// Serialized form of a checkpoint with watermarks.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CheckpointWithWatermarks<P> {
+    positions: P,
+    watermarks: BTreeMap<String, i64>,
+}
+
+/// A checkpoint without watermarks is serialized as the bare map of its positions,
+/// which is how checkpoints were serialized before watermarks were introduced.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SerializedCheckpoint {
+    WithWatermarks(CheckpointWithWatermarks<BTreeMap<String, String>>),
+    Positions(BTreeMap<String, String>),
+}
+
+impl Serialize for Checkpoint {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: serde::Serializer {
+        let positions = SerializedPositions(&self.per_partition);
+        if self.per_partition_watermark.is_empty() {
+            return positions.serialize(serializer);
+        }
+        let watermarks = self
+            .per_partition_watermark
+            .iter()
+            .map(|(partition_id, watermark)| (partition_id.0.to_string(), *watermark))
+            .collect();
+        CheckpointWithWatermarks {
+            positions,
+            watermarks,
+        }
+        .serialize(serializer)
+    }
+}
+
 impl<'de> Deserialize<'de> for Checkpoint {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where D: serde::Deserializer<'de> {
-        let string_to_string_map: BTreeMap<String, String> = BTreeMap::deserialize(deserializer)?;
+        let (string_to_string_map, watermarks) =
+            match SerializedCheckpoint::deserialize(deserializer)? {
+                SerializedCheckpoint::WithWatermarks(checkpoint) => {
+                    (checkpoint.positions, checkpoint.watermarks)
+                }
+                SerializedCheckpoint::Positions(positions) => (positions, BTreeMap::new()),
+            };
         let per_partition: BTreeMap<PartitionId, Position> = string_to_string_map
             .into_iter()
             .map(|(partition_id, position)| {
                 (PartitionId::from(partition_id), Position::from(position))
             })
             .collect();
-        Ok(Checkpoint { per_partition })
+        let per_partition_watermark: BTreeMap<PartitionId, i64> = watermarks
+            .into_iter()
+            .map(|(partition_id, watermark)| (PartitionId::from(partition_id), watermark))
+            .collect();
+        Ok(Checkpoint {
+            per_partition,
+            per_partition_watermark,
+        })
     }
 }
 
@@ -232,6 +291,21 @@ impl Checkpoint {
             .map(|(partition_id, position)| (partition_id.clone(), position.clone()))
     }
 
+    /// Returns the watermark reached for a given partition, if its source records
+    /// watermarks.
+    pub fn watermark_for_partition(&self, partition_id: &PartitionId) -> Option<i64> {
+        self.per_partition_watermark.get(partition_id).copied()
+    }
+
+    /// Moves the position of a partition, backward or forward.
+    ///
+    /// Unlike applying a delta, the new position is not checked against the current one: this
+    /// is meant for the manual repositioning of a source, e.g. to index a range of messages
+    /// again. The watermark of the partition is left untouched.
+    pub fn set_position(&mut self, partition_id: PartitionId, position: Position) {
+        self.per_partition.insert(partition_id, position);
+    }
+
     fn check_compatibility(
         &self,
         delta: &CheckpointDelta,
@@ -285,8 +359,27 @@ impl Checkpoint {
             self.per_partition
                 .insert(partition_id, partition_position.to);
         }
+        for (partition_id, watermark) in delta.per_partition_watermark {
+            record_watermark(&mut self.per_partition_watermark, partition_id, watermark);
+        }
         Ok(())
     }
+
+    /// Returns `true` if the checkpoint has reached the end of the delta for all
+    /// of its partitions, which is the case once the delta has been applied.
+    ///
+    /// An empty delta is covered by any checkpoint.
+    pub fn covers(&self, delta: &CheckpointDelta) -> bool {
+        delta
+            .per_partition
+            .iter()
+            .all(|(partition_id, partition_delta)| {
+                self.per_partition
+                    .get(partition_id)
+                    .map(|position| *position >= partition_delta.to)
+                    .unwrap_or(false)
+            })
+    }
 }
 
 impl fmt::Debug for Checkpoint {
@@ -326,6 +419,21 @@ struct PartitionDelta {
 #[derive(Default, Clone, Eq, PartialEq)]
 pub struct CheckpointDelta {
     per_partition: BTreeMap<PartitionId, PartitionDelta>,
+    per_partition_watermark: BTreeMap<PartitionId, i64>,
+}
+
+/// Raises the watermark of a partition to `watermark`.
+///
+/// Watermarks never go back, as messages are not necessarily produced in order.
+fn record_watermark(
+    per_partition_watermark: &mut BTreeMap<PartitionId, i64>,
+    partition_id: PartitionId,
+    watermark: i64,
+) {
+    let current_watermark = per_partition_watermark
+        .entry(partition_id)
+        .or_insert(watermark);
+    *current_watermark = (*current_watermark).max(watermark);
 }
 
 impl fmt::Debug for CheckpointDelta {
@@ -378,6 +486,19 @@ impl CheckpointDelta {
         delta
     }
 
+    /// Iterates over the `(from, to]` partition deltas, ordered by partition ID.
+    pub fn iter(&self) -> impl Iterator<Item = (PartitionId, Position, Position)> + '_ {
+        self.per_partition
+            .iter()
+            .map(|(partition_id, partition_delta)| {
+                (
+                    partition_id.clone(),
+                    partition_delta.from.clone(),
+                    partition_delta.to.clone(),
+                )
+            })
+    }
+
     /// Records a `(from, to]` partition delta for a given partition.
     pub fn record_partition_delta(
         &mut self,
@@ -410,6 +531,13 @@ impl CheckpointDelta {
         Ok(())
     }
 
+    /// Records the event timestamp, in seconds, of a message of a given partition.
+    ///
+    /// The watermark of the partition is the maximum of the timestamps recorded for it.
+    pub fn record_partition_watermark(&mut self, partition_id: PartitionId, timestamp: i64) {
+        record_watermark(&mut self.per_partition_watermark, partition_id, timestamp);
+    }
+
     /// Extends the current checkpoint delta in-place with the provided checkpoint delta.
     ///
     /// Contrary to checkpoint update, the two deltas here need to chain perfectly.
@@ -417,9 +545,41 @@ impl CheckpointDelta {
         for (partition_id, partition_delta) in delta.per_partition {
             self.record_partition_delta(partition_id, partition_delta.from, partition_delta.to)?;
         }
+        for (partition_id, watermark) in delta.per_partition_watermark {
+            record_watermark(&mut self.per_partition_watermark, partition_id, watermark);
+        }
         Ok(())
     }
 
+    /// Returns the part of the delta that has not been applied to `checkpoint` yet.
+    ///
+    /// The partitions the checkpoint has reached the end of are dropped, and the partitions the
+    /// checkpoint is in the middle of start from its position, so that the result can be
+    /// applied to the checkpoint.
+    pub fn uncovered_by(&self, checkpoint: &Checkpoint) -> CheckpointDelta {
+        let mut uncovered_delta = CheckpointDelta::default();
+        for (partition_id, partition_delta) in &self.per_partition {
+            let from = match checkpoint.position_for_partition(partition_id) {
+                Some(position) if *position >= partition_delta.to => continue,
+                Some(position) if *position > partition_delta.from => position.clone(),
+                _ => partition_delta.from.clone(),
+            };
+            let uncovered_partition_delta = PartitionDelta {
+                from,
+                to: partition_delta.to.clone(),
+            };
+            uncovered_delta
+                .per_partition
+                .insert(partition_id.clone(), uncovered_partition_delta);
+            if let Some(watermark) = self.per_partition_watermark.get(partition_id) {
+                uncovered_delta
+                    .per_partition_watermark
+                    .insert(partition_id.clone(), *watermark);
+            }
+        }
+        uncovered_delta
+    }
+
     /// Returns the number of partitions covered by the checkpoint delta.
     pub fn num_partitions(&self) -> usize {
         self.per_partition.len()
@@ -513,6 +673,95 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_checkpoint_covers_delta() -> anyhow::Result<()> {
+        let mut checkpoint = Checkpoint::default();
+        let delta = {
+            let mut delta = CheckpointDelta::from_partition_delta(
+                PartitionId::from("a"),
+                Position::from("00123"),
+                Position::from("00128"),
+            );
+            delta.record_partition_delta(
+                PartitionId::from("b"),
+                Position::from("60002"),
+                Position::from("60187"),
+            )?;
+            delta
+        };
+        assert!(checkpoint.covers(&CheckpointDelta::default()));
+        assert!(!checkpoint.covers(&delta));
+        checkpoint.try_apply_delta(delta.clone())?;
+        assert!(checkpoint.covers(&delta));
+        let next_delta = CheckpointDelta::from_partition_delta(
+            PartitionId::from("a"),
+            Position::from("00128"),
+            Position::from("00130"),
+        );
+        assert!(!checkpoint.covers(&next_delta));
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_delta_uncovered_by() -> anyhow::Result<()> {
+        let checkpoint: Checkpoint = vec![
+            (PartitionId::from("a"), Position::from("00128")),
+            (PartitionId::from("b"), Position::from("60010")),
+        ]
+        .into_iter()
+        .collect();
+        let delta = {
+            let mut delta = CheckpointDelta::from_partition_delta(
+                PartitionId::from("a"),
+                Position::from("00123"),
+                Position::from("00128"),
+            );
+            delta.record_partition_delta(
+                PartitionId::from("b"),
+                Position::from("60002"),
+                Position::from("60187"),
+            )?;
+            delta.record_partition_delta(
+                PartitionId::from("c"),
+                Position::Beginning,
+                Position::from("00002"),
+            )?;
+            delta
+        };
+        let uncovered_delta = delta.uncovered_by(&checkpoint);
+        assert_eq!(
+            format!("{:?}", uncovered_delta),
+            "∆(b:(60010..60187] c:(..00002])"
+        );
+        let mut checkpoint = checkpoint;
+        checkpoint.try_apply_delta(uncovered_delta)?;
+        assert!(checkpoint.covers(&delta));
+        assert!(delta.uncovered_by(&checkpoint).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_set_position() -> anyhow::Result<()> {
+        let mut checkpoint = Checkpoint::default();
+        checkpoint.try_apply_delta(CheckpointDelta::from_partition_delta(
+            PartitionId::from("a"),
+            Position::from("00123"),
+            Position::from("00128"),
+        ))?;
+        checkpoint.set_position(PartitionId::from("a"), Position::from("00100"));
+        checkpoint.set_position(PartitionId::from("b"), Position::from("60187"));
+        assert_eq!(format!("{:?}", checkpoint), "Ckpt(a:00100 b:60187)");
+        // The deltas are checked against the new positions.
+        assert!(checkpoint
+            .try_apply_delta(CheckpointDelta::from_partition_delta(
+                PartitionId::from("a"),
+                Position::from("00100"),
+                Position::from("00128"),
+            ))
+            .is_ok());
+        Ok(())
+    }
+
     #[test]
     fn test_adding_new_partition() -> anyhow::Result<()> {
         let mut checkpoint = Checkpoint::default();
@@ -614,6 +863,89 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_checkpoint_watermarks() -> anyhow::Result<()> {
+        let mut checkpoint = Checkpoint::default();
+        let mut delta1 = CheckpointDelta::from_partition_delta(
+            PartitionId::from("a"),
+            Position::Beginning,
+            Position::from(3u64),
+        );
+        delta1.record_partition_watermark(PartitionId::from("a"), 1_600_000_100);
+        delta1.record_partition_watermark(PartitionId::from("a"), 1_600_000_050);
+        let mut delta2 = CheckpointDelta::from_partition_delta(
+            PartitionId::from("a"),
+            Position::from(3u64),
+            Position::from(5u64),
+        );
+        delta2.record_partition_watermark(PartitionId::from("a"), 1_600_000_080);
+        delta2.record_partition_delta(
+            PartitionId::from("b"),
+            Position::Beginning,
+            Position::from(2u64),
+        )?;
+        delta1.extend(delta2)?;
+        checkpoint.try_apply_delta(delta1)?;
+        assert_eq!(
+            checkpoint.watermark_for_partition(&PartitionId::from("a")),
+            Some(1_600_000_100)
+        );
+        assert_eq!(
+            checkpoint.watermark_for_partition(&PartitionId::from("b")),
+            None
+        );
+
+        let mut delta3 = CheckpointDelta::from_partition_delta(
+            PartitionId::from("a"),
+            Position::from(5u64),
+            Position::from(6u64),
+        );
+        delta3.record_partition_watermark(PartitionId::from("a"), 1_600_000_200);
+        checkpoint.try_apply_delta(delta3)?;
+        assert_eq!(
+            checkpoint.watermark_for_partition(&PartitionId::from("a")),
+            Some(1_600_000_200)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_serialization() -> anyhow::Result<()> {
+        let checkpoint: Checkpoint = serde_json::from_str(r#"{"a": "00012", "b": "00034"}"#)?;
+        assert_eq!(format!("{:?}", checkpoint), "Ckpt(a:00012 b:00034)");
+        assert_eq!(
+            serde_json::to_value(&checkpoint)?,
+            serde_json::json!({"a": "00012", "b": "00034"})
+        );
+
+        let mut checkpoint = checkpoint;
+        let mut delta = CheckpointDelta::from_partition_delta(
+            PartitionId::from("a"),
+            Position::from("00012"),
+            Position::from("00020"),
+        );
+        delta.record_partition_watermark(PartitionId::from("a"), 1_600_000_000);
+        checkpoint.try_apply_delta(delta)?;
+        let checkpoint_json = serde_json::to_value(&checkpoint)?;
+        assert_eq!(
+            checkpoint_json,
+            serde_json::json!({
+                "positions": {"a": "00020", "b": "00034"},
+                "watermarks": {"a": 1_600_000_000},
+            })
+        );
+        let deserialized_checkpoint: Checkpoint = serde_json::from_value(checkpoint_json)?;
+        assert_eq!(
+            format!("{:?}", deserialized_checkpoint),
+            "Ckpt(a:00020 b:00034)"
+        );
+        assert_eq!(
+            deserialized_checkpoint.watermark_for_partition(&PartitionId::from("a")),
+            Some(1_600_000_000)
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_position_u64() {
         let pos = Position::from(4u64);
@@ -0,0 +1,394 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Companion files are auxiliary artifacts attached to an index, e.g. synonym dictionaries,
+//! enrichment lookup tables or UDF configs. They are stored next to the `quickwit.json`
+//! metadata file at the root of the index storage, under `companions/`, and listed in the
+//! `companions.json` manifest.
+//!
+//! Each write of a companion file creates a new version, which is never modified: until the
+//! file is deleted, searchers reading a given version always get the same content.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use chrono::Utc;
+use quickwit_storage::{PutPayload, Storage, StorageError, StorageErrorKind};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Directory holding the versions of the companion files in the index storage.
+const COMPANION_DIRECTORY: &str = "companions";
+
+/// Manifest listing the companion files of an index and their versions.
+const COMPANION_MANIFEST_FILENAME: &str = "companions.json";
+
+/// Maximum length of the name of a companion file.
+const MAX_COMPANION_NAME_LEN: usize = 255;
+
+/// Companion file errors.
+#[allow(missing_docs)]
+#[derive(Debug, Error)]
+pub enum CompanionFileError {
+    #[error(
+        "Invalid companion file name `{0}`: names are made of at most 255 ASCII alphanumeric \
+         characters, `-`, `_` or `.`, and cannot start with `.`."
+    )]
+    InvalidName(String),
+
+    #[error("Companion file `{name}` does not exist.")]
+    FileDoesNotExist { name: String },
+
+    #[error("Version {version} of companion file `{name}` does not exist.")]
+    VersionDoesNotExist { name: String, version: u64 },
+
+    #[error("Failed to deserialize the companion file manifest: `{0}`.")]
+    InvalidManifest(#[from] serde_json::Error),
+
+    #[error("Storage error: `{0}`.")]
+    StorageError(#[from] StorageError),
+}
+
+/// Generic Result type for companion file operations.
+pub type CompanionFileResult<T> = Result<T, CompanionFileError>;
+
+/// A version of a companion file.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CompanionFileVersion {
+    /// Version number, starting at 1 and incremented by each write of the file.
+    pub version: u64,
+    /// Size of this version of the file.
+    pub num_bytes: u64,
+    /// Timestamp of the write of this version of the file.
+    pub create_timestamp: i64,
+}
+
+/// A companion file and its versions.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CompanionFileMetadata {
+    /// Name of the file.
+    pub name: String,
+    /// Versions of the file, from the oldest to the latest.
+    pub versions: Vec<CompanionFileVersion>,
+}
+
+impl CompanionFileMetadata {
+    /// Returns the latest version of the file.
+    pub fn latest_version(&self) -> Option<&CompanionFileVersion> {
+        self.versions.last()
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CompanionManifest {
+    files: BTreeMap<String, Vec<CompanionFileVersion>>,
+}
+
+/// Returns the path of a version of a companion file in the index storage.
+fn companion_file_path(name: &str, version: u64) -> PathBuf {
+    Path::new(COMPANION_DIRECTORY)
+        .join(name)
+        .join(format!("v{}", version))
+}
+
+fn validate_companion_name(name: &str) -> CompanionFileResult<()> {
+    let is_valid = !name.is_empty()
+        && name.len() <= MAX_COMPANION_NAME_LEN
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !is_valid {
+        return Err(CompanionFileError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Reads and writes the companion files of an index.
+///
+/// Like the single file metastore, the manifest is updated with a read-modify-write: the
+/// companion files of an index are expected to be written by a single process at a time.
+#[derive(Clone)]
+pub struct CompanionFiles {
+    index_storage: Arc<dyn Storage>,
+}
+
+impl CompanionFiles {
+    /// Creates the companion files accessor of the index stored in `index_storage`.
+    pub fn new(index_storage: Arc<dyn Storage>) -> Self {
+        CompanionFiles { index_storage }
+    }
+
+    async fn load_manifest(&self) -> CompanionFileResult<CompanionManifest> {
+        match self
+            .index_storage
+            .get_all(Path::new(COMPANION_MANIFEST_FILENAME))
+            .await
+        {
+            Ok(content) => Ok(serde_json::from_slice(&content)?),
+            Err(error) if error.kind() == StorageErrorKind::DoesNotExist => {
+                Ok(CompanionManifest::default())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn save_manifest(&self, manifest: &CompanionManifest) -> CompanionFileResult<()> {
+        let content = serde_json::to_vec_pretty(manifest)?;
+        self.index_storage
+            .put(
+                Path::new(COMPANION_MANIFEST_FILENAME),
+                PutPayload::from(content),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Lists the companion files of the index, sorted by name.
+    pub async fn list(&self) -> CompanionFileResult<Vec<CompanionFileMetadata>> {
+        let manifest = self.load_manifest().await?;
+        Ok(manifest
+            .files
+            .into_iter()
+            .map(|(name, versions)| CompanionFileMetadata { name, versions })
+            .collect())
+    }
+
+    /// Returns the versions of the companion file `name`.
+    pub async fn metadata(&self, name: &str) -> CompanionFileResult<CompanionFileMetadata> {
+        validate_companion_name(name)?;
+        let mut manifest = self.load_manifest().await?;
+        let versions =
+            manifest
+                .files
+                .remove(name)
+                .ok_or_else(|| CompanionFileError::FileDoesNotExist {
+                    name: name.to_string(),
+                })?;
+        Ok(CompanionFileMetadata {
+            name: name.to_string(),
+            versions,
+        })
+    }
+
+    /// Writes a new version of the companion file `name`, creating the file if needed, and
+    /// returns it.
+    ///
+    /// The content is written before the manifest, so the version is never listed before it
+    /// can be read.
+    pub async fn put(
+        &self,
+        name: &str,
+        payload: PutPayload,
+    ) -> CompanionFileResult<CompanionFileVersion> {
+        validate_companion_name(name)?;
+        let mut manifest = self.load_manifest().await?;
+        let versions = manifest.files.entry(name.to_string()).or_default();
+        let version = versions.last().map_or(1, |latest| latest.version + 1);
+        let path = companion_file_path(name, version);
+        self.index_storage.put(&path, payload).await?;
+        let num_bytes = self.index_storage.file_num_bytes(&path).await?;
+        let file_version = CompanionFileVersion {
+            version,
+            num_bytes,
+            create_timestamp: Utc::now().timestamp(),
+        };
+        versions.push(file_version.clone());
+        self.save_manifest(&manifest).await?;
+        Ok(file_version)
+    }
+
+    /// Resolves `version_opt`, or the latest version if it is not set, of the companion file
+    /// `name`.
+    async fn resolve_version(
+        &self,
+        name: &str,
+        version_opt: Option<u64>,
+    ) -> CompanionFileResult<CompanionFileVersion> {
+        let metadata = self.metadata(name).await?;
+        let file_version_opt = match version_opt {
+            Some(version) => metadata
+                .versions
+                .iter()
+                .find(|file_version| file_version.version == version),
+            None => metadata.latest_version(),
+        };
+        file_version_opt
+            .cloned()
+            .ok_or_else(|| CompanionFileError::VersionDoesNotExist {
+                name: name.to_string(),
+                version: version_opt.unwrap_or_default(),
+            })
+    }
+
+    /// Reads `version_opt`, or the latest version if it is not set, of the companion file
+    /// `name`.
+    pub async fn get(
+        &self,
+        name: &str,
+        version_opt: Option<u64>,
+    ) -> CompanionFileResult<(CompanionFileVersion, Bytes)> {
+        let file_version = self.resolve_version(name, version_opt).await?;
+        let content = self
+            .index_storage
+            .get_all(&companion_file_path(name, file_version.version))
+            .await?;
+        Ok((file_version, content))
+    }
+
+    /// Deletes all the versions of the companion file `name`, and returns them.
+    ///
+    /// The file is removed from the manifest before its versions are deleted, so it is never
+    /// listed with missing versions.
+    pub async fn delete(&self, name: &str) -> CompanionFileResult<CompanionFileMetadata> {
+        validate_companion_name(name)?;
+        let mut manifest = self.load_manifest().await?;
+        let versions =
+            manifest
+                .files
+                .remove(name)
+                .ok_or_else(|| CompanionFileError::FileDoesNotExist {
+                    name: name.to_string(),
+                })?;
+        self.save_manifest(&manifest).await?;
+        for file_version in &versions {
+            self.index_storage
+                .delete(&companion_file_path(name, file_version.version))
+                .await?;
+        }
+        Ok(CompanionFileMetadata {
+            name: name.to_string(),
+            versions,
+        })
+    }
+
+    /// Deletes all the companion files of the index along with the manifest, e.g. when the
+    /// index is deleted.
+    pub async fn delete_all(&self) -> CompanionFileResult<()> {
+        let manifest = self.load_manifest().await?;
+        self.index_storage
+            .delete(Path::new(COMPANION_MANIFEST_FILENAME))
+            .await?;
+        for (name, versions) in &manifest.files {
+            for file_version in versions {
+                self.index_storage
+                    .delete(&companion_file_path(name, file_version.version))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_storage::RamStorage;
+
+    use super::*;
+
+    #[test]
+    fn test_validate_companion_name() {
+        assert!(validate_companion_name("synonyms.txt").is_ok());
+        assert!(validate_companion_name("ip_geo-v2.csv").is_ok());
+        assert!(validate_companion_name("").is_err());
+        assert!(validate_companion_name(".hidden").is_err());
+        assert!(validate_companion_name("../quickwit.json").is_err());
+        assert!(validate_companion_name("lookup/teams.csv").is_err());
+        assert!(validate_companion_name(&"a".repeat(256)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_companion_files_versions() -> anyhow::Result<()> {
+        let companion_files = CompanionFiles::new(Arc::new(RamStorage::default()));
+        assert!(companion_files.list().await?.is_empty());
+
+        let first_version = companion_files
+            .put("synonyms.txt", PutPayload::from(&b"car,automobile"[..]))
+            .await?;
+        assert_eq!(first_version.version, 1);
+        assert_eq!(first_version.num_bytes, 14);
+        let second_version = companion_files
+            .put("synonyms.txt", PutPayload::from(&b"car,auto"[..]))
+            .await?;
+        assert_eq!(second_version.version, 2);
+
+        let (latest_version, content) = companion_files.get("synonyms.txt", None).await?;
+        assert_eq!(latest_version, second_version);
+        assert_eq!(&content[..], b"car,auto");
+        let (_, content) = companion_files.get("synonyms.txt", Some(1)).await?;
+        assert_eq!(&content[..], b"car,automobile");
+        assert!(matches!(
+            companion_files.get("synonyms.txt", Some(3)).await,
+            Err(CompanionFileError::VersionDoesNotExist { version: 3, .. })
+        ));
+        assert!(matches!(
+            companion_files.get("missing.txt", None).await,
+            Err(CompanionFileError::FileDoesNotExist { .. })
+        ));
+
+        let metadata = companion_files.metadata("synonyms.txt").await?;
+        assert_eq!(metadata.versions, vec![first_version, second_version]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_companion_files_list_and_delete() -> anyhow::Result<()> {
+        let storage = Arc::new(RamStorage::default());
+        let companion_files = CompanionFiles::new(storage.clone());
+        companion_files
+            .put("udf.json", PutPayload::from(&b"{}"[..]))
+            .await?;
+        companion_files
+            .put("ip_geo.csv", PutPayload::from(&b"ip,country"[..]))
+            .await?;
+        let names: Vec<String> = companion_files
+            .list()
+            .await?
+            .into_iter()
+            .map(|metadata| metadata.name)
+            .collect();
+        assert_eq!(names, vec!["ip_geo.csv", "udf.json"]);
+
+        let deleted = companion_files.delete("udf.json").await?;
+        assert_eq!(deleted.versions.len(), 1);
+        assert_eq!(companion_files.list().await?.len(), 1);
+        let deleted_version_path = companion_file_path("udf.json", 1);
+        assert!(!storage.exists(&deleted_version_path).await?);
+        assert!(matches!(
+            companion_files.delete("udf.json").await,
+            Err(CompanionFileError::FileDoesNotExist { .. })
+        ));
+
+        // A deleted file starts over from the first version.
+        let file_version = companion_files
+            .put("udf.json", PutPayload::from(&b"[]"[..]))
+            .await?;
+        assert_eq!(file_version.version, 1);
+
+        companion_files.delete_all().await?;
+        assert!(companion_files.list().await?.is_empty());
+        let manifest_path = Path::new(COMPANION_MANIFEST_FILENAME);
+        assert!(!storage.exists(manifest_path).await?);
+        let deleted_version_path = companion_file_path("ip_geo.csv", 1);
+        assert!(!storage.exists(&deleted_version_path).await?);
+        Ok(())
+    }
+}
@@ -19,10 +19,37 @@
 
 use std::io;
 
+use quickwit_storage::{StorageError, StorageErrorKind};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::checkpoint::IncompatibleCheckpointDelta;
 
+/// Metastore error categories, telling the errors worth retrying from the others.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MetastoreErrorKind {
+    /// The target index or split does not exist.
+    NotFound,
+    /// The operation conflicts with the current state of the metastore, e.g. the index
+    /// already exists or the split is not staged.
+    Conflict,
+    /// The request credentials do not allow for this operation.
+    Forbidden,
+    /// The metastore is temporarily unavailable: the operation may succeed if retried.
+    Unavailable,
+    /// The metastore holds data that cannot be read.
+    Corrupted,
+    /// Any generic internal error.
+    Internal,
+}
+
+impl MetastoreErrorKind {
+    /// Returns true if the operation may succeed if retried.
+    pub fn is_transient(self) -> bool {
+        self == MetastoreErrorKind::Unavailable
+    }
+}
+
 /// Metastore error kinds.
 #[allow(missing_docs)]
 #[derive(Debug, Error)]
@@ -68,6 +95,83 @@ pub enum MetastoreError {
     DbError(diesel::result::Error),
 }
 
+impl MetastoreError {
+    /// Returns the category of the error.
+    pub fn kind(&self) -> MetastoreErrorKind {
+        match self {
+            MetastoreError::ConnectionError { .. } => MetastoreErrorKind::Unavailable,
+            MetastoreError::IndexAlreadyExists { .. }
+            | MetastoreError::SplitIsNotStaged { .. }
+            | MetastoreError::IncompatibleCheckpointDelta(_) => MetastoreErrorKind::Conflict,
+            MetastoreError::Forbidden { .. } => MetastoreErrorKind::Forbidden,
+            MetastoreError::IndexDoesNotExist { .. } | MetastoreError::SplitDoesNotExist { .. } => {
+                MetastoreErrorKind::NotFound
+            }
+            // The storage errors of the file-backed metastores end up in internal errors.
+            MetastoreError::InternalError { cause, .. } => cause
+                .downcast_ref::<StorageError>()
+                .map(|storage_error| storage_error_kind(storage_error.kind()))
+                .unwrap_or(MetastoreErrorKind::Internal),
+            MetastoreError::InvalidManifest { .. } => MetastoreErrorKind::Corrupted,
+            MetastoreError::Io(io_error) => io_error_kind(io_error),
+            #[cfg(feature = "postgres")]
+            MetastoreError::DbError(db_error) => db_error_kind(db_error),
+        }
+    }
+
+    /// Returns true if the operation may succeed if retried.
+    pub fn is_transient(&self) -> bool {
+        self.kind().is_transient()
+    }
+}
+
+fn storage_error_kind(storage_error_kind: StorageErrorKind) -> MetastoreErrorKind {
+    match storage_error_kind {
+        StorageErrorKind::DoesNotExist => MetastoreErrorKind::NotFound,
+        StorageErrorKind::Unauthorized => MetastoreErrorKind::Forbidden,
+        StorageErrorKind::Service | StorageErrorKind::Io | StorageErrorKind::Timeout => {
+            MetastoreErrorKind::Unavailable
+        }
+        StorageErrorKind::InternalError => MetastoreErrorKind::Internal,
+    }
+}
+
+fn io_error_kind(io_error: &io::Error) -> MetastoreErrorKind {
+    match io_error.kind() {
+        io::ErrorKind::NotFound => MetastoreErrorKind::NotFound,
+        io::ErrorKind::PermissionDenied => MetastoreErrorKind::Forbidden,
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => MetastoreErrorKind::Corrupted,
+        io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::NotConnected
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::TimedOut
+        | io::ErrorKind::Interrupted
+        | io::ErrorKind::WouldBlock => MetastoreErrorKind::Unavailable,
+        _ => MetastoreErrorKind::Internal,
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn db_error_kind(db_error: &diesel::result::Error) -> MetastoreErrorKind {
+    use diesel::result::{DatabaseErrorKind, Error as DieselError};
+    match db_error {
+        DieselError::NotFound => MetastoreErrorKind::NotFound,
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)
+        | DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => {
+            MetastoreErrorKind::Conflict
+        }
+        // Serialization failures are the expected outcome of concurrent transactions.
+        DieselError::DatabaseError(DatabaseErrorKind::SerializationFailure, _)
+        | DieselError::DatabaseError(DatabaseErrorKind::UnableToSendCommand, _) => {
+            MetastoreErrorKind::Unavailable
+        }
+        DieselError::DeserializationError(_) => MetastoreErrorKind::Corrupted,
+        _ => MetastoreErrorKind::Internal,
+    }
+}
+
 #[cfg(feature = "postgres")]
 impl From<diesel::result::Error> for MetastoreError {
     fn from(err: diesel::result::Error) -> MetastoreError {
@@ -82,8 +186,8 @@ pub type MetastoreResult<T> = Result<T, MetastoreError>;
 #[derive(Error, Debug)]
 pub enum MetastoreResolverError {
     /// The input is not a valid URI.
-    /// A protocol is required for the URI.
-    #[error("Invalid URI format: required: `{0}`")]
+    /// The message describes what is wrong with it.
+    #[error("{0}")]
     InvalidUri(String),
 
     /// The protocol is not supported by this resolver.
@@ -97,3 +201,34 @@ pub enum MetastoreResolverError {
     #[error("Failed to open metastore: `{0}`")]
     FailedToOpenMetastore(MetastoreError),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metastore_error_kind() {
+        let index_does_not_exist = MetastoreError::IndexDoesNotExist {
+            index_id: "test-index".to_string(),
+        };
+        assert_eq!(index_does_not_exist.kind(), MetastoreErrorKind::NotFound);
+        assert!(!index_does_not_exist.is_transient());
+        let split_is_not_staged = MetastoreError::SplitIsNotStaged {
+            split_id: "split".to_string(),
+        };
+        assert_eq!(split_is_not_staged.kind(), MetastoreErrorKind::Conflict);
+        let storage_error = StorageErrorKind::Timeout.with_error(anyhow::anyhow!("Timeout."));
+        let storage_unavailable = MetastoreError::InternalError {
+            message: "Failed to get index files.".to_string(),
+            cause: anyhow::anyhow!(storage_error),
+        };
+        assert!(storage_unavailable.is_transient());
+        let internal_error = MetastoreError::InternalError {
+            message: "Inconsistent manifest: index_id mismatch.".to_string(),
+            cause: anyhow::anyhow!("Expected index_id `foo`, but found `bar`"),
+        };
+        assert_eq!(internal_error.kind(), MetastoreErrorKind::Internal);
+        let connection_reset = MetastoreError::Io(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert!(connection_reset.is_transient());
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Upper bounds, in seconds, of the buckets of ages of the documents of a split. The documents
+/// older than the last bound fall into an extra bucket.
+const AGE_BUCKET_UPPER_BOUNDS_SECS: [i64; 15] = [
+    0, 1, 2, 5, 10, 30, 60, 120, 300, 600, 1_800, 3_600, 7_200, 21_600, 86_400,
+];
+
+/// Times at which the documents of a split were received by the indexing pipeline.
+///
+/// The documents are counted per bucket of age, relative to the most recent document, so that
+/// the histogram stays small no matter how many documents the split holds. Along with the
+/// publish timestamp of the split, it gives the time it took for its documents to become
+/// searchable.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IngestTimestamps {
+    /// Timestamp, in seconds, at which the oldest document of the split was received.
+    pub min_timestamp: i64,
+    /// Timestamp, in seconds, at which the most recent document of the split was received.
+    pub max_timestamp: i64,
+    /// Number of documents per bucket of age.
+    pub age_bucket_counts: Vec<u64>,
+}
+
+impl IngestTimestamps {
+    /// Builds the histogram of the documents, given their number per reception timestamp.
+    ///
+    /// Returns `None` if there are no documents.
+    pub fn from_num_docs_per_timestamp(
+        num_docs_per_timestamp: &BTreeMap<i64, u64>,
+    ) -> Option<IngestTimestamps> {
+        let min_timestamp = *num_docs_per_timestamp.keys().next()?;
+        let max_timestamp = *num_docs_per_timestamp.keys().next_back()?;
+        let mut age_bucket_counts = vec![0; AGE_BUCKET_UPPER_BOUNDS_SECS.len() + 1];
+        for (timestamp, num_docs) in num_docs_per_timestamp {
+            let age = max_timestamp - timestamp;
+            let bucket_ord = AGE_BUCKET_UPPER_BOUNDS_SECS
+                .iter()
+                .position(|&upper_bound| age <= upper_bound)
+                .unwrap_or_else(|| AGE_BUCKET_UPPER_BOUNDS_SECS.len());
+            age_bucket_counts[bucket_ord] += num_docs;
+        }
+        Some(IngestTimestamps {
+            min_timestamp,
+            max_timestamp,
+            age_bucket_counts,
+        })
+    }
+
+    /// Returns the times to searchable of the documents, in seconds, if the split was
+    /// published at `publish_timestamp`, along with the number of documents of each.
+    ///
+    /// The time to searchable of the documents of a bucket is estimated with the upper bound
+    /// of the bucket, so the estimates are never below the actual times.
+    pub fn times_to_searchable(&self, publish_timestamp: i64) -> Vec<(i64, u64)> {
+        let publish_delay = (publish_timestamp - self.max_timestamp).max(0);
+        let max_age = self.max_timestamp - self.min_timestamp;
+        self.age_bucket_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &num_docs)| num_docs > 0)
+            .map(|(bucket_ord, &num_docs)| {
+                let age_upper_bound = AGE_BUCKET_UPPER_BOUNDS_SECS
+                    .get(bucket_ord)
+                    .map_or(max_age, |&upper_bound| upper_bound.min(max_age));
+                (publish_delay + age_upper_bound, num_docs)
+            })
+            .collect()
+    }
+}
+
+/// Returns the `percentile` (between 0 and 100) of the times to searchable, given along with
+/// their number of documents.
+///
+/// Returns `None` if there are no documents.
+pub fn time_to_searchable_percentile(
+    times_to_searchable: &[(i64, u64)],
+    percentile: f64,
+) -> Option<i64> {
+    let mut sorted_times_to_searchable = times_to_searchable.to_vec();
+    sorted_times_to_searchable.sort_unstable();
+    let num_docs: u64 = sorted_times_to_searchable
+        .iter()
+        .map(|(_, num_docs)| num_docs)
+        .sum();
+    if num_docs == 0 {
+        return None;
+    }
+    let rank = ((percentile / 100.0) * num_docs as f64).ceil().max(1.0) as u64;
+    let mut num_docs_so_far = 0;
+    for (time_to_searchable, num_docs) in sorted_times_to_searchable {
+        num_docs_so_far += num_docs;
+        if num_docs_so_far >= rank {
+            return Some(time_to_searchable);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ingest_timestamps() {
+        assert!(IngestTimestamps::from_num_docs_per_timestamp(&BTreeMap::new()).is_none());
+        let num_docs_per_timestamp: BTreeMap<i64, u64> =
+            vec![(1_000, 5), (1_055, 3), (1_097, 2), (1_100, 10)]
+                .into_iter()
+                .collect();
+        let ingest_timestamps =
+            IngestTimestamps::from_num_docs_per_timestamp(&num_docs_per_timestamp).unwrap();
+        assert_eq!(ingest_timestamps.min_timestamp, 1_000);
+        assert_eq!(ingest_timestamps.max_timestamp, 1_100);
+        assert_eq!(ingest_timestamps.age_bucket_counts[0], 10);
+        assert_eq!(ingest_timestamps.age_bucket_counts[3], 2);
+        assert_eq!(ingest_timestamps.age_bucket_counts[6], 3);
+        assert_eq!(ingest_timestamps.age_bucket_counts[7], 5);
+        assert_eq!(
+            ingest_timestamps.times_to_searchable(1_110),
+            vec![(10, 10), (15, 2), (70, 3), (110, 5)]
+        );
+    }
+
+    #[test]
+    fn test_time_to_searchable_percentile() {
+        assert_eq!(time_to_searchable_percentile(&[], 50.0), None);
+        let times_to_searchable = vec![(70, 3), (10, 10), (110, 5), (15, 2)];
+        assert_eq!(
+            time_to_searchable_percentile(&times_to_searchable, 50.0),
+            Some(10)
+        );
+        assert_eq!(
+            time_to_searchable_percentile(&times_to_searchable, 60.0),
+            Some(15)
+        );
+        assert_eq!(
+            time_to_searchable_percentile(&times_to_searchable, 99.0),
+            Some(110)
+        );
+        assert_eq!(
+            time_to_searchable_percentile(&times_to_searchable, 0.0),
+            Some(10)
+        );
+    }
+}
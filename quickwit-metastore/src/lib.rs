@@ -23,7 +23,10 @@
 //! `quickwit-metastore` is the abstraction used in quickwit to interface itself to different
 //! metastore:
 //! - single file metastore
+//! - in-memory metastore, for the `ram://` URIs
 //! etc.
+//!
+//! It also manages the companion files of the indexes, stored next to their metadata.
 
 #[macro_use]
 mod tests;
@@ -38,21 +41,36 @@ extern crate diesel;
 
 #[allow(missing_docs)]
 pub mod checkpoint;
+mod companion_files;
 mod error;
+mod ingest_timestamps;
 mod metastore;
 mod metastore_resolver;
+mod retry;
 
 #[cfg(feature = "postgres")]
 #[allow(missing_docs)]
 pub mod postgresql;
 
-pub use error::{MetastoreError, MetastoreResolverError, MetastoreResult};
+pub use companion_files::{
+    CompanionFileError, CompanionFileMetadata, CompanionFileResult, CompanionFileVersion,
+    CompanionFiles,
+};
+pub use error::{MetastoreError, MetastoreErrorKind, MetastoreResolverError, MetastoreResult};
+pub use ingest_timestamps::{time_to_searchable_percentile, IngestTimestamps};
+pub use metastore::negative_caching_metastore::NegativeCachingMetastore;
 #[cfg(feature = "postgres")]
 pub use metastore::postgresql_metastore::PostgresqlMetastore;
 pub use metastore::single_file_metastore::SingleFileMetastore;
+pub use metastore::webhook_metastore::{
+    IndexEvent, IndexEventSink, SplitStats, WebhookMetastore, Webhooks, WEBHOOK_URLS_ENV_KEY,
+};
 #[cfg(feature = "testsuite")]
 pub use metastore::MockMetastore;
 pub use metastore::{
-    IndexMetadata, MetadataSet, Metastore, SplitMetadata, SplitMetadataAndFooterOffsets, SplitState,
+    IndexMetadata, MetadataSet, Metastore, SourceShardLease, SourceShardLeaseHolder, SplitEvent,
+    SplitEventReceiver, SplitMetadata, SplitMetadataAndFooterOffsets, SplitState,
+    SplitVerification,
 };
 pub use metastore_resolver::{MetastoreFactory, MetastoreUriResolver};
+pub use retry::retry_on_transient_error;
@@ -17,23 +17,31 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+pub mod negative_caching_metastore;
 #[cfg(feature = "postgres")]
 pub mod postgresql_metastore;
+pub mod ram_metastore;
 pub mod single_file_metastore;
+mod split_events;
+mod tag_index;
+pub mod webhook_metastore;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{self, Debug};
 use std::ops::{Range, RangeInclusive};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use chrono::Utc;
+use quickwit_common::QuickwitUri;
 use quickwit_index_config::IndexConfig;
 use serde::{Deserialize, Serialize};
 
-use crate::checkpoint::{Checkpoint, CheckpointDelta};
-use crate::MetastoreResult;
+pub use self::split_events::{SplitEvent, SplitEventReceiver};
+use crate::checkpoint::{Checkpoint, CheckpointDelta, PartitionId, Position};
+use crate::{IngestTimestamps, MetastoreResult};
 
 /// An index metadata carries all meta data about an index.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -42,11 +50,89 @@ pub struct IndexMetadata {
     pub index_id: String,
     /// Index URI. The index URI defines the location of the storage that contains the
     /// split files.
-    pub index_uri: String,
+    pub index_uri: QuickwitUri,
+    /// URI of the storage the split files are mirrored to, e.g. in another region.
+    ///
+    /// The splits are written to both storages, and read from the mirror when the storage
+    /// at `index_uri` fails.
+    #[serde(default)]
+    pub mirror_index_uri: Option<QuickwitUri>,
     /// The config used for this index.
     pub index_config: Arc<dyn IndexConfig>,
+    /// The configs previously used for this index, oldest first.
+    ///
+    /// The config at position `i` is the version `i` of the index config, and the current
+    /// `index_config` is the version `index_config_history.len()`.
+    #[serde(default)]
+    pub index_config_history: Vec<Arc<dyn IndexConfig>>,
     /// Checkpoint relative to a source. It express up to where documents have been indexed.
     pub checkpoint: Checkpoint,
+    /// True if the index was attached from the storage of another cluster.
+    ///
+    /// The split files of a read-only index are not owned by this cluster: they can be
+    /// searched, but the index cannot be indexed into, garbage collected or reset, and
+    /// deleting the index only removes its metastore records.
+    #[serde(default)]
+    pub read_only: bool,
+    /// Free-form description of the index.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Free-form labels of the index, such as its owning team, its environment or its cost
+    /// center.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Timestamp, in seconds, at which the index was created.
+    ///
+    /// It tells the index apart from a deleted index of the same ID, e.g. in the split IDs
+    /// derived from the positions of the source. It is `0` for the indexes created before it
+    /// was recorded.
+    #[serde(default)]
+    pub create_timestamp: i64,
+}
+
+impl IndexMetadata {
+    /// Returns the version of the current index config.
+    pub fn index_config_version(&self) -> u64 {
+        self.index_config_history.len() as u64
+    }
+
+    /// Returns the index config with the given version, if it exists.
+    pub fn index_config_for_version(&self, version: u64) -> Option<Arc<dyn IndexConfig>> {
+        let current_version = self.index_config_version();
+        if version == current_version {
+            return Some(self.index_config.clone());
+        }
+        self.index_config_history.get(version as usize).cloned()
+    }
+
+    /// Returns a fingerprint of the index config with the given version, if it exists.
+    ///
+    /// Versions start over from 0 when an index is deleted and recreated with the same ID,
+    /// so the version alone does not identify an index config.
+    pub fn index_config_fingerprint(&self, version: u64) -> Option<u64> {
+        let index_config = self.index_config_for_version(version)?;
+        let index_config_json = serde_json::to_vec(&index_config).unwrap_or_default();
+        let digest = md5::compute(index_config_json);
+        let mut fingerprint_bytes = [0u8; 8];
+        fingerprint_bytes.copy_from_slice(&digest.0[..8]);
+        Some(u64::from_le_bytes(fingerprint_bytes))
+    }
+
+    /// Returns the index config that was active when the given split was built.
+    pub fn index_config_for_split(
+        &self,
+        split_metadata: &SplitMetadata,
+    ) -> Option<Arc<dyn IndexConfig>> {
+        self.index_config_for_version(split_metadata.index_config_version)
+    }
+
+    /// Replaces the current index config with `index_config`, keeping the former
+    /// config in the history. Returns the version of the new config.
+    pub fn update_index_config(&mut self, index_config: Arc<dyn IndexConfig>) -> u64 {
+        let previous_index_config = std::mem::replace(&mut self.index_config, index_config);
+        self.index_config_history.push(previous_index_config);
+        self.index_config_version()
+    }
 }
 
 /// Carries split and bundle offsets for single read metadata.
@@ -86,11 +172,37 @@ pub struct SplitMetadata {
     pub split_state: SplitState,
 
     /// Timestamp for tracking when the split state was last modified.
+    /// It is assigned by the metastore clock, see [`Metastore::now_timestamp`].
     pub update_timestamp: i64,
 
     /// A set of tags for categorizing and searching group of splits.
     #[serde(default)]
     pub tags: HashSet<String>,
+
+    /// Version of the index config the split was built with.
+    /// See [`IndexMetadata::index_config_for_split`].
+    #[serde(default)]
+    pub index_config_version: u64,
+
+    /// Path of the split file relative to the index URI, if the index config has a split
+    /// path layout. Otherwise, the split file is `<split ID>.split`.
+    /// See [`SplitMetadata::split_file`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split_file_path: Option<String>,
+
+    /// Size of the parts the split file is stored as, if it is stored as several objects.
+    /// See [`quickwit_storage::MultipartFileStorage`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split_part_num_bytes: Option<u64>,
+
+    /// Times at which the documents of the split were received by the indexing pipeline.
+    /// Not set for the splits produced by merges, whose documents were already searchable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingest_timestamps: Option<IngestTimestamps>,
+
+    /// Outcome of the last integrity verification of the split file, if it was ever verified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_verification: Option<SplitVerification>,
 }
 
 impl SplitMetadata {
@@ -104,8 +216,46 @@ impl SplitMetadata {
             time_range: None,
             update_timestamp: Utc::now().timestamp(),
             tags: Default::default(),
+            index_config_version: 0,
+            split_file_path: None,
+            split_part_num_bytes: None,
+            ingest_timestamps: None,
+            last_verification: None,
         }
     }
+
+    /// Returns the path of the split file, relative to the index URI.
+    pub fn split_file(&self) -> String {
+        self.split_file_path
+            .clone()
+            .unwrap_or_else(|| quickwit_common::split_file(&self.split_id))
+    }
+
+    /// Returns true if the timestamps of all the documents of the split are within
+    /// `time_range`. A split without a time range is never contained in a time range.
+    pub fn is_contained_in(&self, time_range: &Range<i64>) -> bool {
+        self.time_range.as_ref().map_or(false, |split_time_range| {
+            *split_time_range.start() >= time_range.start
+                && *split_time_range.end() < time_range.end
+        })
+    }
+}
+
+/// Outcome of the integrity verification of a split file.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SplitVerification {
+    /// Timestamp at which the split file was verified.
+    pub verification_timestamp: i64,
+    /// Description of the corruption detected in the split file, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corruption: Option<String>,
+}
+
+impl SplitVerification {
+    /// Returns true if the split file was found to be corrupted.
+    pub fn is_corrupted(&self) -> bool {
+        self.corruption.is_some()
+    }
 }
 
 /// A split state.
@@ -122,6 +272,11 @@ pub enum SplitState {
 
     /// The split is scheduled for deletion.
     ScheduledForDeletion,
+
+    /// The split is quarantined: it failed its integrity verification or repeatedly failed to
+    /// be searched, so it is not searched anymore until it is found intact and published again,
+    /// or marked for deletion.
+    Quarantined,
 }
 
 impl Default for SplitState {
@@ -139,6 +294,7 @@ impl FromStr for SplitState {
             "Staged" => Ok(SplitState::Staged),
             "Published" => Ok(SplitState::Published),
             "ScheduledForDeletion" => Ok(SplitState::ScheduledForDeletion),
+            "Quarantined" => Ok(SplitState::Quarantined),
             _ => Err("Unknown split state"),
         }
     }
@@ -159,6 +315,38 @@ pub struct MetadataSet {
     pub splits: HashMap<String, SplitMetadataAndFooterOffsets>,
 }
 
+/// A lease granting an indexer the exclusive right to index a shard of a source, see
+/// [`Metastore::acquire_source_shard_lease`].
+///
+/// The partitions of a source sharded into `num_shards` shards are spread across the shards, so
+/// that several indexers can index the same source without indexing a partition twice.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceShardLease {
+    /// ID of the source the shard belongs to.
+    pub source_id: String,
+    /// ID of the shard, between 0 and `num_shards` excluded.
+    pub shard_id: usize,
+    /// Number of shards of the source.
+    pub num_shards: usize,
+    /// Owner of the lease, identifying the indexer holding it.
+    pub owner: String,
+    /// Timestamp, in seconds of the metastore clock, at which the lease expires unless renewed.
+    /// Once expired, another indexer can lease the shard.
+    pub expiration_timestamp: i64,
+}
+
+/// Identifies the holder of the lease of a shard of a source, on behalf of which splits are
+/// published, see [`Metastore::publish_splits_with_lease`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceShardLeaseHolder {
+    /// ID of the leased source.
+    pub source_id: String,
+    /// ID of the leased shard.
+    pub shard_id: usize,
+    /// Owner of the lease.
+    pub owner: String,
+}
+
 /// Metastore meant to manage Quickwit's indexes and their splits.
 ///
 /// Quickwit needs a way to ensure that we can cleanup unused files,
@@ -199,6 +387,36 @@ pub trait Metastore: Send + Sync + 'static {
     /// TODO consider merging with list_splits to remove one round-trip
     async fn index_metadata(&self, index_id: &str) -> MetastoreResult<IndexMetadata>;
 
+    /// Replaces the config of an index, keeping the former config in the index config history.
+    ///
+    /// Splits built from now on should record the returned version in their
+    /// [`SplitMetadata::index_config_version`].
+    async fn update_index_config(
+        &self,
+        index_id: &str,
+        index_config: Arc<dyn IndexConfig>,
+    ) -> MetastoreResult<u64>;
+
+    /// Overrides the checkpoint positions of the given partitions of an index.
+    ///
+    /// Unlike publishing splits, the new positions may be behind the current ones: this rewinds
+    /// or advances the source of the index, e.g. to index a range of messages again.
+    /// An error will occur if an index that does not exist in the storage is specified.
+    async fn set_checkpoint_positions(
+        &self,
+        index_id: &str,
+        positions: Vec<(PartitionId, Position)>,
+    ) -> MetastoreResult<()>;
+
+    /// Replaces the description and the labels of an index.
+    /// An error will occur if an index that does not exist in the storage is specified.
+    async fn update_index_labels(
+        &self,
+        index_id: &str,
+        description: Option<String>,
+        labels: BTreeMap<String, String>,
+    ) -> MetastoreResult<()>;
+
     /// Deletes an index.
     /// This API removes the specified index metadata set from the metastore,
     /// but does not remove the index from the storage.
@@ -208,7 +426,8 @@ pub trait Metastore: Send + Sync + 'static {
     /// Stages a split.
     /// A split needs to be staged before uploading any of its files to the storage.
     /// An error will occur if an index that does not exist in the storage is specified.
-    /// Also, an error will occur if you specify a split that already exists.
+    /// Also, an error will occur if you specify a split that already exists, unless it is
+    /// still staged, in which case its metadata is replaced.
     async fn stage_split(
         &self,
         index_id: &str,
@@ -219,6 +438,9 @@ pub trait Metastore: Send + Sync + 'static {
     /// This API only updates the state of the split from `Staged` to `Published`.
     /// At this point, the split files are assumed to have already been uploaded.
     /// If the split is already published, this API call returns a success.
+    /// If all the splits are already published and the checkpoint delta has already been
+    /// applied, e.g. because a previous attempt timed out after succeeding, this API call
+    /// is a no-op and returns a success.
     /// An error will occur if you specify an index or split that does not exist in the storage.
     async fn publish_splits<'a>(
         &self,
@@ -227,6 +449,20 @@ pub trait Metastore: Send + Sync + 'static {
         checkpoint_delta: CheckpointDelta,
     ) -> MetastoreResult<()>;
 
+    /// Publishes a list of splits, like [`Metastore::publish_splits`], provided the lease of the
+    /// shard of the source designated by `lease_holder` is held by its owner and has not expired.
+    /// The lease is checked and the splits are published atomically, so that an indexer that lost
+    /// its lease cannot publish the documents of a shard that another indexer took over.
+    /// An error will occur if the lease is not held by `lease_holder`, or if the metastore is
+    /// file-backed, as the file-backed metastores cannot check the lease atomically.
+    async fn publish_splits_with_lease<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+        checkpoint_delta: CheckpointDelta,
+        lease_holder: &SourceShardLeaseHolder,
+    ) -> MetastoreResult<()>;
+
     /// Replaces a list of splits with another list.
     /// This API is useful during merge and demux operations.
     /// The new splits should be staged, and the replaced splits should exist.
@@ -258,7 +494,8 @@ pub trait Metastore: Send + Sync + 'static {
 
     /// Marks a list of splits for deletion.
     /// This API will change the state to `ScheduledForDeletion` so that it is not referenced by the
-    /// client. It actually does not remove the split from storage.
+    /// client. It actually does not remove the split from storage. Splits in any state can be
+    /// marked for deletion, including the quarantined ones.
     /// An error will occur if you specify an index or split that does not exist in the storage.
     async fn mark_splits_for_deletion<'a>(
         &self,
@@ -274,6 +511,132 @@ pub trait Metastore: Send + Sync + 'static {
     async fn delete_splits<'a>(&self, index_id: &str, split_ids: &[&'a str])
         -> MetastoreResult<()>;
 
+    /// Quarantines a list of splits.
+    /// This API only updates the state of the split from `Published` to `Quarantined`, so that
+    /// it is no longer searched. If the split is already quarantined, this API call returns a
+    /// success.
+    /// An error will occur if you specify an index or split that does not exist in the storage,
+    /// or a split that is neither published nor quarantined.
+    async fn quarantine_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()>;
+
+    /// Releases a list of splits from quarantine.
+    /// This API only updates the state of the split from `Quarantined` back to `Published`, e.g.
+    /// once its file was verified again and found intact. If the split is already published, this
+    /// API call returns a success.
+    /// An error will occur if you specify an index or split that does not exist in the storage,
+    /// or a split that is neither quarantined nor published.
+    async fn unquarantine_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()>;
+
+    /// Records the outcome of the integrity verification of a split.
+    /// This API only updates the split's [`SplitMetadata::last_verification`], leaving its state
+    /// and update timestamp untouched.
+    /// An error will occur if you specify an index or split that does not exist in the storage.
+    async fn record_split_verification(
+        &self,
+        index_id: &str,
+        split_id: &str,
+        verification: SplitVerification,
+    ) -> MetastoreResult<()>;
+
+    /// Leases a shard of the source `source_id` to `owner` for `lease_duration`, and returns the
+    /// ID of the shard, or `None` if all the `num_shards` shards are leased by other owners.
+    /// If `owner` already holds the lease of a shard, this lease is renewed. Otherwise, the first
+    /// shard that is not leased, or whose lease expired, is leased.
+    /// An error will occur if you specify an index that does not exist in the storage, or if the
+    /// source is leased with another number of shards.
+    /// Only the PostgreSQL metastore holds leases: the file-backed metastores return an error for
+    /// all the lease API calls.
+    async fn acquire_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        num_shards: usize,
+        owner: &str,
+        lease_duration: Duration,
+    ) -> MetastoreResult<Option<usize>>;
+
+    /// Renews the lease of a shard held by `owner` for `lease_duration`, from now on.
+    /// An error will occur if you specify an index that does not exist in the storage, or if
+    /// `owner` no longer holds the lease, i.e. it expired and another owner leased the shard
+    /// since, or it was released.
+    async fn renew_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        shard_id: usize,
+        owner: &str,
+        lease_duration: Duration,
+    ) -> MetastoreResult<()>;
+
+    /// Releases the lease of a shard held by `owner`, so that another owner can lease the shard
+    /// right away. If `owner` no longer holds the lease, this API call returns a success.
+    /// An error will occur if you specify an index that does not exist in the storage.
+    async fn release_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        shard_id: usize,
+        owner: &str,
+    ) -> MetastoreResult<()>;
+
+    /// Lists the leases of the shards of the sources of an index, including the expired ones.
+    /// An error will occur if you specify an index that does not exist in the storage.
+    async fn list_source_shard_leases(
+        &self,
+        index_id: &str,
+    ) -> MetastoreResult<Vec<SourceShardLease>>;
+
+    /// Leases splits of an index to the search `lease_id` for `lease_duration`, so that the
+    /// garbage collectors of all the nodes leave them untouched while they are being read.
+    /// Acquiring the same lease again adds the splits to it and renews it.
+    /// The metastore does not check that the splits exist.
+    /// An error will occur if you specify an index that does not exist in the storage.
+    async fn acquire_split_lease<'a>(
+        &self,
+        index_id: &str,
+        lease_id: &str,
+        split_ids: &[&'a str],
+        lease_duration: Duration,
+    ) -> MetastoreResult<()>;
+
+    /// Releases the lease `lease_id` on the splits of an index. If the lease does not exist,
+    /// e.g. because it expired, this API call returns a success.
+    /// An error will occur if you specify an index that does not exist in the storage.
+    async fn release_split_lease(&self, index_id: &str, lease_id: &str) -> MetastoreResult<()>;
+
+    /// Lists the IDs of the splits of an index covered by at least one lease that has not
+    /// expired, sorted and deduplicated.
+    /// An error will occur if you specify an index that does not exist in the storage.
+    async fn list_leased_split_ids(&self, index_id: &str) -> MetastoreResult<Vec<String>>;
+
+    /// Refreshes the view of an index.
+    /// The following reads of the index see the changes made by other processes until now,
+    /// even if this metastore caches the index. A no-op for the metastores that do not.
+    /// An error will occur if an index that does not exist in the storage is specified.
+    async fn refresh_index(&self, index_id: &str) -> MetastoreResult<()>;
+
+    /// Watches the splits of an index.
+    /// Returns a receiver of the events of the splits published, or no longer searchable, from
+    /// now on. This lets the metadata caches of the searchers and the external systems know when
+    /// new data is searchable.
+    /// An error will occur if an index that does not exist in the storage is specified.
+    async fn watch_splits(&self, index_id: &str) -> MetastoreResult<SplitEventReceiver>;
+
+    /// Returns the current timestamp of the metastore clock, in seconds.
+    /// The metastore assigns the update timestamps of the splits with this clock rather than with
+    /// the clocks of its clients, so that the clients compare a split's update timestamp against
+    /// this clock instead of their own, e.g. when applying a grace period, to be immune to the
+    /// clock skew between the nodes.
+    async fn now_timestamp(&self) -> MetastoreResult<i64>;
+
     /// Returns the Metastore uri.
     fn uri(&self) -> String;
 }
@@ -0,0 +1,420 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use quickwit_index_config::IndexConfig;
+
+use crate::checkpoint::{CheckpointDelta, PartitionId, Position};
+use crate::{
+    IndexMetadata, Metastore, MetastoreError, MetastoreResult, SourceShardLease,
+    SourceShardLeaseHolder, SplitEventReceiver, SplitMetadataAndFooterOffsets, SplitState,
+    SplitVerification,
+};
+
+/// Wraps a metastore, remembering for a while the indexes it reported as missing.
+///
+/// During this while, reading the metadata or the splits of these indexes fails right away with
+/// [`MetastoreError::IndexDoesNotExist`], without querying the underlying metastore. This keeps
+/// the requests targeting a not yet created index from hammering the metastore. Creating an
+/// index through this metastore forgets it was missing.
+pub struct NegativeCachingMetastore {
+    underlying: Arc<dyn Metastore>,
+    /// Time during which an index reported as missing is assumed to still be missing.
+    negative_ttl: Duration,
+    /// Instants at which the missing indexes were reported as missing, by index ID.
+    missing_indexes: Mutex<HashMap<String, Instant>>,
+}
+
+impl NegativeCachingMetastore {
+    /// Creates a metastore remembering the missing indexes of `underlying` for
+    /// `negative_ttl`.
+    pub fn new(underlying: Arc<dyn Metastore>, negative_ttl: Duration) -> Self {
+        NegativeCachingMetastore {
+            underlying,
+            negative_ttl,
+            missing_indexes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns an error if the index was reported as missing less than `negative_ttl` ago.
+    fn check_not_missing(&self, index_id: &str) -> MetastoreResult<()> {
+        let mut missing_indexes = self.missing_indexes.lock().unwrap();
+        if let Some(reported_at) = missing_indexes.get(index_id) {
+            if reported_at.elapsed() < self.negative_ttl {
+                return Err(MetastoreError::IndexDoesNotExist {
+                    index_id: index_id.to_string(),
+                });
+            }
+            missing_indexes.remove(index_id);
+        }
+        Ok(())
+    }
+
+    /// Remembers the index as missing if `result` says so.
+    fn record_result<T>(&self, index_id: &str, result: MetastoreResult<T>) -> MetastoreResult<T> {
+        if let Err(MetastoreError::IndexDoesNotExist { .. }) = &result {
+            self.missing_indexes
+                .lock()
+                .unwrap()
+                .insert(index_id.to_string(), Instant::now());
+        }
+        result
+    }
+
+    fn forget_missing(&self, index_id: &str) {
+        self.missing_indexes.lock().unwrap().remove(index_id);
+    }
+}
+
+#[async_trait]
+impl Metastore for NegativeCachingMetastore {
+    async fn create_index(&self, index_metadata: IndexMetadata) -> MetastoreResult<()> {
+        let index_id = index_metadata.index_id.clone();
+        let create_res = self.underlying.create_index(index_metadata).await;
+        self.forget_missing(&index_id);
+        create_res
+    }
+
+    async fn index_metadata(&self, index_id: &str) -> MetastoreResult<IndexMetadata> {
+        self.check_not_missing(index_id)?;
+        let index_metadata_res = self.underlying.index_metadata(index_id).await;
+        self.record_result(index_id, index_metadata_res)
+    }
+
+    async fn update_index_config(
+        &self,
+        index_id: &str,
+        index_config: Arc<dyn IndexConfig>,
+    ) -> MetastoreResult<u64> {
+        self.underlying
+            .update_index_config(index_id, index_config)
+            .await
+    }
+
+    async fn set_checkpoint_positions(
+        &self,
+        index_id: &str,
+        positions: Vec<(PartitionId, Position)>,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .set_checkpoint_positions(index_id, positions)
+            .await
+    }
+
+    async fn update_index_labels(
+        &self,
+        index_id: &str,
+        description: Option<String>,
+        labels: BTreeMap<String, String>,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .update_index_labels(index_id, description, labels)
+            .await
+    }
+
+    async fn delete_index(&self, index_id: &str) -> MetastoreResult<()> {
+        self.underlying.delete_index(index_id).await
+    }
+
+    async fn stage_split(
+        &self,
+        index_id: &str,
+        split_metadata: SplitMetadataAndFooterOffsets,
+    ) -> MetastoreResult<()> {
+        self.underlying.stage_split(index_id, split_metadata).await
+    }
+
+    async fn publish_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+        checkpoint_delta: CheckpointDelta,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .publish_splits(index_id, split_ids, checkpoint_delta)
+            .await
+    }
+
+    async fn publish_splits_with_lease<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+        checkpoint_delta: CheckpointDelta,
+        lease_holder: &SourceShardLeaseHolder,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .publish_splits_with_lease(index_id, split_ids, checkpoint_delta, lease_holder)
+            .await
+    }
+
+    async fn replace_splits<'a>(
+        &self,
+        index_id: &str,
+        new_split_ids: &[&'a str],
+        replaced_split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .replace_splits(index_id, new_split_ids, replaced_split_ids)
+            .await
+    }
+
+    async fn list_splits(
+        &self,
+        index_id: &str,
+        split_state: SplitState,
+        time_range: Option<Range<i64>>,
+        tags: &[String],
+    ) -> MetastoreResult<Vec<SplitMetadataAndFooterOffsets>> {
+        self.check_not_missing(index_id)?;
+        let list_splits_res = self
+            .underlying
+            .list_splits(index_id, split_state, time_range, tags)
+            .await;
+        self.record_result(index_id, list_splits_res)
+    }
+
+    async fn list_all_splits(
+        &self,
+        index_id: &str,
+    ) -> MetastoreResult<Vec<SplitMetadataAndFooterOffsets>> {
+        self.check_not_missing(index_id)?;
+        let list_all_splits_res = self.underlying.list_all_splits(index_id).await;
+        self.record_result(index_id, list_all_splits_res)
+    }
+
+    async fn mark_splits_for_deletion<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .mark_splits_for_deletion(index_id, split_ids)
+            .await
+    }
+
+    async fn delete_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.underlying.delete_splits(index_id, split_ids).await
+    }
+
+    async fn quarantine_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.underlying.quarantine_splits(index_id, split_ids).await
+    }
+
+    async fn unquarantine_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .unquarantine_splits(index_id, split_ids)
+            .await
+    }
+
+    async fn record_split_verification(
+        &self,
+        index_id: &str,
+        split_id: &str,
+        verification: SplitVerification,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .record_split_verification(index_id, split_id, verification)
+            .await
+    }
+
+    async fn acquire_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        num_shards: usize,
+        owner: &str,
+        lease_duration: Duration,
+    ) -> MetastoreResult<Option<usize>> {
+        self.underlying
+            .acquire_source_shard_lease(index_id, source_id, num_shards, owner, lease_duration)
+            .await
+    }
+
+    async fn renew_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        shard_id: usize,
+        owner: &str,
+        lease_duration: Duration,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .renew_source_shard_lease(index_id, source_id, shard_id, owner, lease_duration)
+            .await
+    }
+
+    async fn release_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        shard_id: usize,
+        owner: &str,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .release_source_shard_lease(index_id, source_id, shard_id, owner)
+            .await
+    }
+
+    async fn list_source_shard_leases(
+        &self,
+        index_id: &str,
+    ) -> MetastoreResult<Vec<SourceShardLease>> {
+        self.underlying.list_source_shard_leases(index_id).await
+    }
+
+    async fn acquire_split_lease<'a>(
+        &self,
+        index_id: &str,
+        lease_id: &str,
+        split_ids: &[&'a str],
+        lease_duration: Duration,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .acquire_split_lease(index_id, lease_id, split_ids, lease_duration)
+            .await
+    }
+
+    async fn release_split_lease(&self, index_id: &str, lease_id: &str) -> MetastoreResult<()> {
+        self.underlying
+            .release_split_lease(index_id, lease_id)
+            .await
+    }
+
+    async fn list_leased_split_ids(&self, index_id: &str) -> MetastoreResult<Vec<String>> {
+        self.underlying.list_leased_split_ids(index_id).await
+    }
+
+    async fn refresh_index(&self, index_id: &str) -> MetastoreResult<()> {
+        // The index may have been created by another process in the meantime.
+        self.forget_missing(index_id);
+        let refresh_res = self.underlying.refresh_index(index_id).await;
+        self.record_result(index_id, refresh_res)
+    }
+
+    async fn watch_splits(&self, index_id: &str) -> MetastoreResult<SplitEventReceiver> {
+        self.check_not_missing(index_id)?;
+        let watch_splits_res = self.underlying.watch_splits(index_id).await;
+        self.record_result(index_id, watch_splits_res)
+    }
+
+    async fn now_timestamp(&self) -> MetastoreResult<i64> {
+        self.underlying.now_timestamp().await
+    }
+
+    fn uri(&self) -> String {
+        self.underlying.uri()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_common::QuickwitUri;
+    use quickwit_index_config::WikipediaIndexConfig;
+
+    use super::*;
+    use crate::checkpoint::Checkpoint;
+    use crate::MockMetastore;
+
+    fn index_metadata(index_id: &str) -> IndexMetadata {
+        IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed(&format!("ram:///indexes/{}", index_id)),
+            index_config: Arc::new(WikipediaIndexConfig::new()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_negative_caching_metastore() {
+        let mut mock_metastore = MockMetastore::new();
+        mock_metastore
+            .expect_index_metadata()
+            .times(2)
+            .returning(|index_id: &str| {
+                if index_id == "existing-index" {
+                    return Ok(index_metadata(index_id));
+                }
+                Err(MetastoreError::IndexDoesNotExist {
+                    index_id: index_id.to_string(),
+                })
+            });
+        mock_metastore
+            .expect_create_index()
+            .times(1)
+            .returning(|_index_metadata| Ok(()));
+        let metastore =
+            NegativeCachingMetastore::new(Arc::new(mock_metastore), Duration::from_secs(60));
+        assert!(metastore.index_metadata("existing-index").await.is_ok());
+        for _ in 0..3 {
+            assert!(matches!(
+                metastore.index_metadata("missing-index").await,
+                Err(MetastoreError::IndexDoesNotExist { .. })
+            ));
+        }
+        assert!(matches!(
+            metastore.list_all_splits("missing-index").await,
+            Err(MetastoreError::IndexDoesNotExist { .. })
+        ));
+        metastore
+            .create_index(index_metadata("missing-index"))
+            .await
+            .unwrap();
+        assert!(metastore.missing_indexes.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_negative_caching_metastore_entries_expire() {
+        let mut mock_metastore = MockMetastore::new();
+        mock_metastore
+            .expect_index_metadata()
+            .times(2)
+            .returning(|index_id: &str| {
+                Err(MetastoreError::IndexDoesNotExist {
+                    index_id: index_id.to_string(),
+                })
+            });
+        let metastore =
+            NegativeCachingMetastore::new(Arc::new(mock_metastore), Duration::from_secs(0));
+        assert!(metastore.index_metadata("missing-index").await.is_err());
+        assert!(metastore.index_metadata("missing-index").await.is_err());
+    }
+}
@@ -17,27 +17,36 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
 use std::ops::Range;
 use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
-use chrono::Utc;
 use diesel::pg::Pg;
 use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
 use diesel::result::DatabaseErrorKind;
 use diesel::result::Error::DatabaseError;
+use diesel::sql_types::BigInt;
 use diesel::{
-    debug_query, BoolExpressionMethods, Connection, ExpressionMethods, PgConnection, QueryDsl,
-    RunQueryDsl,
+    debug_query, BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension,
+    PgConnection, QueryDsl, RunQueryDsl,
 };
+use futures::future;
+use quickwit_common::QuickwitUri;
+use quickwit_index_config::IndexConfig;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, NoTls};
 use tracing::{debug, error, info, warn};
 
-use crate::metastore::{match_tags_filter, CheckpointDelta};
+use crate::metastore::split_events::SPLIT_EVENTS_BUFFER_SIZE;
+use crate::metastore::{match_tags_filter, CheckpointDelta, PartitionId, Position};
 use crate::postgresql::{model, schema};
 use crate::{
     IndexMetadata, Metastore, MetastoreError, MetastoreFactory, MetastoreResolverError,
-    MetastoreResult, SplitMetadataAndFooterOffsets, SplitState,
+    MetastoreResult, SourceShardLease, SourceShardLeaseHolder, SplitEvent, SplitEventReceiver,
+    SplitMetadataAndFooterOffsets, SplitState, SplitVerification,
 };
 
 embed_migrations!("migrations/postgresql");
@@ -48,6 +57,17 @@ const CONNECTION_POOL_MAX_RETRY_COUNT: u32 = 10;
 const CONNECTION_STATUS_CHECK_MAX_RETRY_COUNT: u32 = 3;
 const CONNECTION_STATUS_CHECK_INTERVAL: Duration = Duration::from_secs(2);
 
+/// Channel on which the `notify_split_events` trigger notifies the split events.
+const SPLIT_EVENTS_CHANNEL: &str = "quickwit_split_events";
+
+/// Payload of the notifications sent by the `notify_split_events` trigger.
+#[derive(Deserialize)]
+struct SplitEventNotification {
+    index_id: String,
+    #[serde(flatten)]
+    split_event: SplitEvent,
+}
+
 /// Establishes a connection to the given database URI.
 fn establish_connection(
     database_uri: &str,
@@ -122,6 +142,81 @@ fn check_all_splits_were_modified<'a>(
     Ok(())
 }
 
+/// Listens to the split events notified on [`SPLIT_EVENTS_CHANNEL`] and forwards the events
+/// of the index to the returned receiver.
+///
+/// The listening connection is dedicated to the subscription, since the connections of the pool
+/// cannot receive notifications. It is closed once the receiver is dropped.
+async fn listen_split_events(
+    database_uri: &str,
+    index_id: &str,
+) -> MetastoreResult<SplitEventReceiver> {
+    let connect_res = tokio_postgres::connect(database_uri, NoTls).await;
+    let (client, mut connection) = connect_res.map_err(|err| MetastoreError::ConnectionError {
+        message: format!("Failed to open the split events connection: `{}`.", err),
+    })?;
+    // The notifications are only received while the connection is polled.
+    let (notification_sender, mut notification_receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(message_res) = future::poll_fn(|cx| connection.poll_message(cx)).await {
+            match message_res {
+                Ok(AsyncMessage::Notification(notification)) => {
+                    if notification_sender.send(notification).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    error!(err=?err, "The split events connection failed.");
+                    return;
+                }
+            }
+        }
+    });
+    client
+        .batch_execute(&format!("LISTEN {}", SPLIT_EVENTS_CHANNEL))
+        .await
+        .map_err(|err| MetastoreError::ConnectionError {
+            message: format!("Failed to listen to the split events: `{}`.", err),
+        })?;
+
+    let (event_sender, event_receiver) = mpsc::channel(SPLIT_EVENTS_BUFFER_SIZE);
+    let index_id = index_id.to_string();
+    tokio::spawn(async move {
+        // Dropping the client closes the connection.
+        let _client = client;
+        loop {
+            let notification = tokio::select! {
+                notification_opt = notification_receiver.recv() => match notification_opt {
+                    Some(notification) => notification,
+                    None => return,
+                },
+                _ = event_sender.closed() => return,
+            };
+            let notification_res =
+                serde_json::from_str::<SplitEventNotification>(notification.payload());
+            let split_event_notification = match notification_res {
+                Ok(split_event_notification) => split_event_notification,
+                Err(err) => {
+                    warn!(err=?err, payload=notification.payload(), "Invalid split event notification.");
+                    continue;
+                }
+            };
+            if split_event_notification.index_id != index_id {
+                continue;
+            }
+            if event_sender
+                .send(split_event_notification.split_event)
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    });
+    Ok(event_receiver)
+}
+
 /// PostgreSQL metastore implementation.
 #[derive(Clone)]
 pub struct PostgresqlMetastore {
@@ -212,6 +307,23 @@ impl PostgresqlMetastore {
         Ok(index_exists)
     }
 
+    /// Returns the current timestamp of the database clock, in seconds.
+    /// Within a transaction, this is the time at which the transaction started.
+    fn db_now_timestamp(
+        &self,
+        conn: &PooledConnection<ConnectionManager<PgConnection>>,
+    ) -> MetastoreResult<i64> {
+        let now_timestamp_statement = diesel::select(diesel::dsl::sql::<BigInt>(
+            "CAST(EXTRACT(EPOCH FROM NOW()) AS BIGINT)",
+        ));
+        debug!(sql=%debug_query::<Pg, _>(&now_timestamp_statement).to_string());
+        let now_timestamp: i64 = now_timestamp_statement
+            .get_result(conn)
+            .map_err(MetastoreError::DbError)?;
+
+        Ok(now_timestamp)
+    }
+
     /// Publish splits.
     /// Returns the successful split IDs.
     fn publish_splits(
@@ -231,7 +343,7 @@ impl PostgresqlMetastore {
             .get_results(conn)
             .map_err(MetastoreError::DbError)?;
 
-        let now_timestamp = Utc::now().timestamp();
+        let now_timestamp = self.db_now_timestamp(conn)?;
         let mut succeeded_split_ids = Vec::new();
         for model_split in model_splits {
             // Check for the inclusion of non-publishable split IDs.
@@ -330,7 +442,7 @@ impl PostgresqlMetastore {
             .get_results(conn)
             .map_err(MetastoreError::DbError)?;
 
-        let now_timestamp = Utc::now().timestamp();
+        let now_timestamp = self.db_now_timestamp(conn)?;
         let mut succeeded_split_ids = Vec::new();
         for model_split in model_splits {
             // Deserialize the target split metadata.
@@ -401,6 +513,134 @@ impl PostgresqlMetastore {
         Ok(succeeded_split_ids)
     }
 
+    /// Move splits from `source_state` to `target_state`. The splits already in `target_state`
+    /// are left untouched.
+    /// Returns the IDs of the splits that are now in `target_state`.
+    fn move_splits(
+        &self,
+        conn: &PooledConnection<ConnectionManager<PgConnection>>,
+        index_id: &str,
+        split_ids: &[&str],
+        source_state: SplitState,
+        target_state: SplitState,
+    ) -> MetastoreResult<Vec<String>> {
+        // Select splits to move.
+        let select_splits_statement = schema::splits::dsl::splits.filter(
+            schema::splits::dsl::index_id
+                .eq(index_id)
+                .and(schema::splits::dsl::split_id.eq_any(split_ids)),
+        );
+        debug!(sql=%debug_query::<Pg, _>(&select_splits_statement).to_string());
+        let model_splits: Vec<model::Split> = select_splits_statement
+            .get_results(conn)
+            .map_err(MetastoreError::DbError)?;
+
+        let now_timestamp = self.db_now_timestamp(conn)?;
+        let mut succeeded_split_ids = Vec::new();
+        for model_split in model_splits {
+            if model_split.split_state == target_state.to_string() {
+                succeeded_split_ids.push(model_split.split_id);
+                continue;
+            }
+            if model_split.split_state != source_state.to_string() {
+                let message: String = format!(
+                    "This split is not in the {:?} state: {:?}:{:?}",
+                    source_state, model_split.split_id, model_split.split_state
+                );
+                return Err(MetastoreError::Forbidden { message });
+            }
+
+            // Deserialize the target split metadata.
+            let mut split_metadata_and_footer_offsets = model_split
+                .make_split_metadata_and_footer_offsets()
+                .map_err(|err| MetastoreError::InternalError {
+                    message: format!(
+                        "Failed to make split metadata and footer offsets split_id={:?}",
+                        model_split.split_id
+                    ),
+                    cause: err,
+                })?;
+
+            // Update its split_state and update_timestamp.
+            split_metadata_and_footer_offsets.split_metadata.split_state = target_state.clone();
+            split_metadata_and_footer_offsets
+                .split_metadata
+                .update_timestamp = now_timestamp;
+
+            // Serialize to JSON.
+            let split_metadata_and_footer_offsets_json =
+                serde_json::to_string(&split_metadata_and_footer_offsets).map_err(|err| {
+                    MetastoreError::InternalError {
+                        message: format!(
+                            "Failed to serialize from JSON to SplitMetadataAndFooterOffsets \
+                             split_id={:?}",
+                            model_split.split_id
+                        ),
+                        cause: anyhow::anyhow!(err),
+                    }
+                })?;
+
+            // Update database.
+            let update_splits_statement = diesel::update(
+                schema::splits::dsl::splits.filter(
+                    schema::splits::dsl::index_id
+                        .eq(index_id)
+                        .and(schema::splits::dsl::split_id.eq(model_split.split_id)),
+                ),
+            )
+            .set((
+                schema::splits::dsl::split_state.eq(target_state.to_string()),
+                schema::splits::dsl::split_metadata_json.eq(split_metadata_and_footer_offsets_json),
+            ));
+            debug!(sql=%debug_query::<Pg, _>(&update_splits_statement).to_string());
+            let updated_split: model::Split = update_splits_statement
+                .get_result(&*conn)
+                .map_err(MetastoreError::DbError)?;
+
+            succeeded_split_ids.push(updated_split.split_id);
+        }
+
+        debug!(succeeded_split_ids=?succeeded_split_ids, target_state=?target_state, "Move");
+
+        Ok(succeeded_split_ids)
+    }
+
+    /// Moves splits from `source_state` to `target_state` within a transaction, see
+    /// [`Self::move_splits`].
+    fn move_splits_in_transaction(
+        &self,
+        index_id: &str,
+        split_ids: &[&str],
+        source_state: SplitState,
+        target_state: SplitState,
+    ) -> MetastoreResult<()> {
+        let conn = self.get_conn()?;
+        // Check for the existence of index.
+        let index_exists: bool = self.is_index_exist(&conn, index_id)?;
+        if !index_exists {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        conn.transaction::<_, MetastoreError, _>(|| {
+            let moved_split_ids =
+                self.move_splits(&conn, index_id, split_ids, source_state, target_state)?;
+
+            if moved_split_ids.len() < split_ids.len() {
+                // Return an error if there are any splits that could not be moved.
+                check_all_splits_were_modified(
+                    split_ids,
+                    &moved_split_ids
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>(),
+                )?;
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
     fn delete_splits(
         &self,
         conn: &PooledConnection<ConnectionManager<PgConnection>>,
@@ -455,13 +695,194 @@ impl PostgresqlMetastore {
         Ok(succeeded_split_ids)
     }
 
+    /// Returns true if all the splits exist and are published.
+    fn are_splits_published(
+        &self,
+        conn: &PooledConnection<ConnectionManager<PgConnection>>,
+        index_id: &str,
+        split_ids: &[&str],
+    ) -> MetastoreResult<bool> {
+        let count_published_splits_statement = schema::splits::dsl::splits
+            .filter(
+                schema::splits::dsl::index_id
+                    .eq(index_id)
+                    .and(schema::splits::dsl::split_id.eq_any(split_ids))
+                    .and(schema::splits::dsl::split_state.eq(SplitState::Published.to_string())),
+            )
+            .count();
+        debug!(sql=%debug_query::<Pg, _>(&count_published_splits_statement).to_string());
+        let num_published_splits: i64 = count_published_splits_statement
+            .get_result(conn)
+            .map_err(MetastoreError::DbError)?;
+        Ok(num_published_splits as usize == split_ids.len())
+    }
+
     /// Apply checkpoint delta.
+    /// Returns true if the delta was already applied by a publication of the same
+    /// splits that succeeded, in which case the checkpoint is left untouched.
     fn apply_checkpoint_delta(
         &self,
         conn: &PooledConnection<ConnectionManager<PgConnection>>,
         index_id: &str,
         checkpoint_delta: CheckpointDelta,
+        splits_are_published: bool,
+    ) -> MetastoreResult<bool> {
+        self.update_index_metadata(conn, index_id, |index_metadata| {
+            if splits_are_published && index_metadata.checkpoint.covers(&checkpoint_delta) {
+                return Ok(true);
+            }
+            index_metadata
+                .checkpoint
+                .try_apply_delta(checkpoint_delta)?;
+            Ok(false)
+        })
+    }
+
+    /// Publishes splits and applies the checkpoint delta to the index checkpoint, unless this is
+    /// the retry of a publication that succeeded. Must be called within a transaction.
+    fn publish_splits_with_checkpoint_delta(
+        &self,
+        conn: &PooledConnection<ConnectionManager<PgConnection>>,
+        index_id: &str,
+        split_ids: &[&str],
+        checkpoint_delta: CheckpointDelta,
     ) -> MetastoreResult<()> {
+        // Update the index checkpoint, unless this is the retry of a publication
+        // that succeeded.
+        let splits_are_published = self.are_splits_published(conn, index_id, split_ids)?;
+        let is_publication_retry =
+            self.apply_checkpoint_delta(conn, index_id, checkpoint_delta, splits_are_published)?;
+        if is_publication_retry {
+            return Ok(());
+        }
+
+        // Publish splits.
+        let published_split_ids = self.publish_splits(conn, index_id, split_ids)?;
+
+        if published_split_ids.len() < split_ids.len() {
+            // Return an error if there are any splits that could not be published.
+            check_all_splits_were_modified(
+                split_ids,
+                &published_split_ids
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Locks the lease of a shard of a source until the end of the transaction, provided it is
+    /// held by `lease_holder` and has not expired.
+    fn lock_source_shard_lease(
+        &self,
+        conn: &PooledConnection<ConnectionManager<PgConnection>>,
+        index_id: &str,
+        lease_holder: &SourceShardLeaseHolder,
+    ) -> MetastoreResult<()> {
+        let now_timestamp = self.db_now_timestamp(conn)?;
+        let lock_lease_statement = schema::source_shard_leases::dsl::source_shard_leases
+            .filter(
+                schema::source_shard_leases::dsl::index_id
+                    .eq(index_id)
+                    .and(schema::source_shard_leases::dsl::source_id.eq(&lease_holder.source_id))
+                    .and(
+                        schema::source_shard_leases::dsl::shard_id.eq(lease_holder.shard_id as i32),
+                    )
+                    .and(schema::source_shard_leases::dsl::owner.eq(&lease_holder.owner))
+                    .and(schema::source_shard_leases::dsl::expiration_timestamp.gt(now_timestamp)),
+            )
+            .for_update();
+        debug!(sql=%debug_query::<Pg, _>(&lock_lease_statement).to_string());
+        let model_lease_opt: Option<model::SourceShardLease> = lock_lease_statement
+            .first(conn)
+            .optional()
+            .map_err(MetastoreError::DbError)?;
+        if model_lease_opt.is_none() {
+            let message = format!(
+                "The lease of shard {} of source `{}` is not held by `{}`.",
+                lease_holder.shard_id, lease_holder.source_id, lease_holder.owner
+            );
+            return Err(MetastoreError::Forbidden { message });
+        }
+        Ok(())
+    }
+
+    /// Selects the leases of the shards of a source.
+    fn select_source_shard_leases(
+        &self,
+        conn: &PooledConnection<ConnectionManager<PgConnection>>,
+        index_id: &str,
+        source_id: &str,
+    ) -> MetastoreResult<Vec<model::SourceShardLease>> {
+        let select_leases_statement = schema::source_shard_leases::dsl::source_shard_leases.filter(
+            schema::source_shard_leases::dsl::index_id
+                .eq(index_id)
+                .and(schema::source_shard_leases::dsl::source_id.eq(source_id)),
+        );
+        debug!(sql=%debug_query::<Pg, _>(&select_leases_statement).to_string());
+        select_leases_statement
+            .get_results(conn)
+            .map_err(MetastoreError::DbError)
+    }
+
+    /// Assigns the lease of a shard to `owner` until `expiration_timestamp`, provided it is
+    /// currently held by `current_owner_opt`, or expired if `None`.
+    /// Returns true if the lease was updated.
+    #[allow(clippy::too_many_arguments)]
+    fn update_source_shard_lease(
+        &self,
+        conn: &PooledConnection<ConnectionManager<PgConnection>>,
+        index_id: &str,
+        source_id: &str,
+        shard_id: usize,
+        current_owner_opt: Option<&str>,
+        owner: &str,
+        expiration_timestamp: i64,
+    ) -> MetastoreResult<bool> {
+        let lease_filter = schema::source_shard_leases::dsl::index_id
+            .eq(index_id)
+            .and(schema::source_shard_leases::dsl::source_id.eq(source_id))
+            .and(schema::source_shard_leases::dsl::shard_id.eq(shard_id as i32));
+        let set_lease = (
+            schema::source_shard_leases::dsl::owner.eq(owner),
+            schema::source_shard_leases::dsl::expiration_timestamp.eq(expiration_timestamp),
+        );
+        let num_updated_leases = if let Some(current_owner) = current_owner_opt {
+            let update_lease_statement = diesel::update(
+                schema::source_shard_leases::dsl::source_shard_leases.filter(
+                    lease_filter.and(schema::source_shard_leases::dsl::owner.eq(current_owner)),
+                ),
+            )
+            .set(set_lease);
+            debug!(sql=%debug_query::<Pg, _>(&update_lease_statement).to_string());
+            update_lease_statement.execute(conn)
+        } else {
+            let now_timestamp = self.db_now_timestamp(conn)?;
+            let expired_lease_filter = lease_filter
+                .and(schema::source_shard_leases::dsl::expiration_timestamp.le(now_timestamp));
+            let update_lease_statement = diesel::update(
+                schema::source_shard_leases::dsl::source_shard_leases.filter(expired_lease_filter),
+            )
+            .set(set_lease);
+            debug!(sql=%debug_query::<Pg, _>(&update_lease_statement).to_string());
+            update_lease_statement.execute(conn)
+        }
+        .map_err(MetastoreError::DbError)?;
+        Ok(num_updated_leases == 1)
+    }
+
+    /// Reads the index metadata, applies `mutate` to it, and writes it back.
+    fn update_index_metadata<T, F>(
+        &self,
+        conn: &PooledConnection<ConnectionManager<PgConnection>>,
+        index_id: &str,
+        mutate: F,
+    ) -> MetastoreResult<T>
+    where
+        F: FnOnce(&mut IndexMetadata) -> MetastoreResult<T>,
+    {
         // Get index metadata.
         let select_index_statement =
             schema::indexes::dsl::indexes.filter(schema::indexes::dsl::index_id.eq(index_id));
@@ -487,12 +908,9 @@ impl PostgresqlMetastore {
                     cause: anyhow::anyhow!(err),
                 })?;
 
-        // Apply checkpoint_delta
-        index_metadata
-            .checkpoint
-            .try_apply_delta(checkpoint_delta)?;
+        let result = mutate(&mut index_metadata)?;
 
-        // Serialize the checkpoint to fit the database model.
+        // Serialize the index metadata to fit the database model.
         let index_metadata_json = serde_json::to_string(&index_metadata).map_err(|err| {
             MetastoreError::InternalError {
                 message: "Failed to serialize index metadata".to_string(),
@@ -500,7 +918,7 @@ impl PostgresqlMetastore {
             }
         })?;
 
-        // Update the index checkpoint.
+        // Update the index metadata.
         let update_index_statement = diesel::update(schema::indexes::dsl::indexes.find(index_id))
             .set(schema::indexes::dsl::index_metadata_json.eq(index_metadata_json));
         debug!(sql=%debug_query::<Pg, _>(&update_index_statement).to_string());
@@ -508,7 +926,7 @@ impl PostgresqlMetastore {
             .execute(&*conn)
             .map_err(MetastoreError::DbError)?;
 
-        Ok(())
+        Ok(result)
     }
 }
 
@@ -586,9 +1004,11 @@ impl Metastore for PostgresqlMetastore {
         index_id: &str,
         mut metadata: SplitMetadataAndFooterOffsets,
     ) -> MetastoreResult<()> {
+        let conn = self.get_conn()?;
+
         // Modify split state to Staged.
         metadata.split_metadata.split_state = SplitState::Staged;
-        metadata.split_metadata.update_timestamp = Utc::now().timestamp();
+        metadata.split_metadata.update_timestamp = self.db_now_timestamp(&conn)?;
 
         // Fit the time_range to the database model.
         let start_time_range = metadata
@@ -610,20 +1030,57 @@ impl Metastore for PostgresqlMetastore {
             })?;
 
         let model_split = model::Split {
-            split_id: metadata.split_metadata.split_id,
+            split_id: metadata.split_metadata.split_id.clone(),
             split_state: metadata.split_metadata.split_state.to_string(),
             start_time_range,
             end_time_range,
             tags: metadata
                 .split_metadata
                 .tags
-                .into_iter()
+                .iter()
+                .cloned()
                 .collect::<Vec<String>>(),
             split_metadata_json: split_metadata_and_footer_offsets_json,
             index_id: index_id.to_string(),
         };
-        let conn = self.get_conn()?;
         conn.transaction::<_, MetastoreError, _>(|| {
+            // A staged split is replaced when it is staged again, e.g. because a previous
+            // attempt timed out or the split was rebuilt under the ID provided by its source.
+            let select_split_statement = schema::splits::dsl::splits.filter(
+                schema::splits::dsl::index_id
+                    .eq(index_id)
+                    .and(schema::splits::dsl::split_id.eq(&model_split.split_id)),
+            );
+            debug!(sql=%debug_query::<Pg, _>(&select_split_statement).to_string());
+            let existing_split_opt: Option<model::Split> = select_split_statement
+                .first(&*conn)
+                .optional()
+                .map_err(MetastoreError::DbError)?;
+            if let Some(existing_split) = existing_split_opt {
+                if existing_split.get_split_state() == Some(SplitState::Staged) {
+                    let update_split_statement = diesel::update(
+                        schema::splits::dsl::splits.filter(
+                            schema::splits::dsl::index_id
+                                .eq(index_id)
+                                .and(schema::splits::dsl::split_id.eq(&model_split.split_id)),
+                        ),
+                    )
+                    .set((
+                        schema::splits::dsl::start_time_range.eq(model_split.start_time_range),
+                        schema::splits::dsl::end_time_range.eq(model_split.end_time_range),
+                        schema::splits::dsl::tags.eq(&model_split.tags),
+                        schema::splits::dsl::split_metadata_json
+                            .eq(&model_split.split_metadata_json),
+                    ));
+                    debug!(sql=%debug_query::<Pg, _>(&update_split_statement).to_string());
+                    update_split_statement
+                        .execute(&*conn)
+                        .map_err(MetastoreError::DbError)?;
+                    debug!(index_id=?index_id, split_id=?model_split.split_id, "The staged split has been replaced");
+                    return Ok(());
+                }
+            }
+
             // Insert a new split metadata as `Staged` state.
             let insert_staged_split_statement =
                 diesel::insert_into(schema::splits::dsl::splits).values(&model_split);
@@ -679,24 +1136,32 @@ impl Metastore for PostgresqlMetastore {
             });
         }
         conn.transaction::<_, MetastoreError, _>(|| {
-            // Update the index checkpoint.
-            self.apply_checkpoint_delta(&conn, index_id, checkpoint_delta)?;
-
-            // Publish splits.
-            let published_split_ids = self.publish_splits(&conn, index_id, split_ids)?;
+            self.publish_splits_with_checkpoint_delta(&conn, index_id, split_ids, checkpoint_delta)
+        })?;
 
-            if published_split_ids.len() < split_ids.len() {
-                // Return an error if there are any splits that could not be published.
-                check_all_splits_were_modified(
-                    split_ids,
-                    &published_split_ids
-                        .iter()
-                        .map(String::as_str)
-                        .collect::<Vec<_>>(),
-                )?;
-            }
+        Ok(())
+    }
 
-            Ok(())
+    async fn publish_splits_with_lease<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+        checkpoint_delta: CheckpointDelta,
+        lease_holder: &SourceShardLeaseHolder,
+    ) -> MetastoreResult<()> {
+        let conn = self.get_conn()?;
+        // Check for the existence of index.
+        let index_exists: bool = self.is_index_exist(&conn, index_id)?;
+        if !index_exists {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        conn.transaction::<_, MetastoreError, _>(|| {
+            // The lease stays locked until the transaction ends, so that it cannot be taken over
+            // before the splits are published.
+            self.lock_source_shard_lease(&conn, index_id, lease_holder)?;
+            self.publish_splits_with_checkpoint_delta(&conn, index_id, split_ids, checkpoint_delta)
         })?;
 
         Ok(())
@@ -933,6 +1398,104 @@ impl Metastore for PostgresqlMetastore {
         Ok(())
     }
 
+    async fn quarantine_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.move_splits_in_transaction(
+            index_id,
+            split_ids,
+            SplitState::Published,
+            SplitState::Quarantined,
+        )
+    }
+
+    async fn unquarantine_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.move_splits_in_transaction(
+            index_id,
+            split_ids,
+            SplitState::Quarantined,
+            SplitState::Published,
+        )
+    }
+
+    async fn record_split_verification(
+        &self,
+        index_id: &str,
+        split_id: &str,
+        verification: SplitVerification,
+    ) -> MetastoreResult<()> {
+        let conn = self.get_conn()?;
+        // Check for the existence of index.
+        let index_exists: bool = self.is_index_exist(&conn, index_id)?;
+        if !index_exists {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        conn.transaction::<_, MetastoreError, _>(|| {
+            let select_split_statement = schema::splits::dsl::splits.filter(
+                schema::splits::dsl::index_id
+                    .eq(index_id)
+                    .and(schema::splits::dsl::split_id.eq(split_id)),
+            );
+            debug!(sql=%debug_query::<Pg, _>(&select_split_statement).to_string());
+            let model_split: model::Split =
+                select_split_statement
+                    .first(&*conn)
+                    .map_err(|err| match err {
+                        diesel::result::Error::NotFound => MetastoreError::SplitDoesNotExist {
+                            split_id: split_id.to_string(),
+                        },
+                        _ => MetastoreError::DbError(err),
+                    })?;
+            let mut split_metadata_and_footer_offsets = model_split
+                .make_split_metadata_and_footer_offsets()
+                .map_err(|err| MetastoreError::InternalError {
+                    message: format!(
+                        "Failed to make split metadata and footer offsets split_id={:?}",
+                        split_id
+                    ),
+                    cause: err,
+                })?;
+            split_metadata_and_footer_offsets
+                .split_metadata
+                .last_verification = Some(verification);
+            let split_metadata_and_footer_offsets_json =
+                serde_json::to_string(&split_metadata_and_footer_offsets).map_err(|err| {
+                    MetastoreError::InternalError {
+                        message: format!(
+                            "Failed to serialize from JSON to SplitMetadataAndFooterOffsets \
+                             split_id={:?}",
+                            split_id
+                        ),
+                        cause: anyhow::anyhow!(err),
+                    }
+                })?;
+            let update_split_statement = diesel::update(
+                schema::splits::dsl::splits.filter(
+                    schema::splits::dsl::index_id
+                        .eq(index_id)
+                        .and(schema::splits::dsl::split_id.eq(split_id)),
+                ),
+            )
+            .set(
+                schema::splits::dsl::split_metadata_json.eq(split_metadata_and_footer_offsets_json),
+            );
+            debug!(sql=%debug_query::<Pg, _>(&update_split_statement).to_string());
+            update_split_statement
+                .execute(&*conn)
+                .map_err(MetastoreError::DbError)?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
     async fn index_metadata(&self, index_id: &str) -> MetastoreResult<IndexMetadata> {
         let conn = self.get_conn()?;
         let select_index_statement =
@@ -958,6 +1521,436 @@ impl Metastore for PostgresqlMetastore {
         Ok(index_metadata)
     }
 
+    async fn update_index_config(
+        &self,
+        index_id: &str,
+        index_config: Arc<dyn IndexConfig>,
+    ) -> MetastoreResult<u64> {
+        let conn = self.get_conn()?;
+        let index_config_version = conn.transaction::<_, MetastoreError, _>(|| {
+            self.update_index_metadata(&conn, index_id, |index_metadata| {
+                Ok(index_metadata.update_index_config(index_config))
+            })
+        })?;
+        info!(
+            index_id = index_id,
+            index_config_version = index_config_version,
+            "updated-index-config"
+        );
+        Ok(index_config_version)
+    }
+
+    async fn set_checkpoint_positions(
+        &self,
+        index_id: &str,
+        positions: Vec<(PartitionId, Position)>,
+    ) -> MetastoreResult<()> {
+        let conn = self.get_conn()?;
+        let num_positions = positions.len();
+        conn.transaction::<_, MetastoreError, _>(|| {
+            self.update_index_metadata(&conn, index_id, |index_metadata| {
+                for (partition_id, position) in positions {
+                    index_metadata
+                        .checkpoint
+                        .set_position(partition_id, position);
+                }
+                Ok(())
+            })
+        })?;
+        info!(
+            index_id = index_id,
+            num_positions = num_positions,
+            "set-checkpoint-positions"
+        );
+        Ok(())
+    }
+
+    async fn update_index_labels(
+        &self,
+        index_id: &str,
+        description: Option<String>,
+        labels: BTreeMap<String, String>,
+    ) -> MetastoreResult<()> {
+        let conn = self.get_conn()?;
+        let num_labels = labels.len();
+        conn.transaction::<_, MetastoreError, _>(|| {
+            self.update_index_metadata(&conn, index_id, |index_metadata| {
+                index_metadata.description = description;
+                index_metadata.labels = labels;
+                Ok(())
+            })
+        })?;
+        info!(
+            index_id = index_id,
+            num_labels = num_labels,
+            "updated-index-labels"
+        );
+        Ok(())
+    }
+
+    async fn acquire_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        num_shards: usize,
+        owner: &str,
+        lease_duration: Duration,
+    ) -> MetastoreResult<Option<usize>> {
+        let conn = self.get_conn()?;
+        // Check for the existence of index.
+        let index_exists: bool = self.is_index_exist(&conn, index_id)?;
+        if !index_exists {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        let shard_id_opt = conn.transaction::<_, MetastoreError, _>(|| {
+            let now_timestamp = self.db_now_timestamp(&conn)?;
+            let expiration_timestamp = now_timestamp + lease_duration.as_secs() as i64;
+            let mut model_leases = self.select_source_shard_leases(&conn, index_id, source_id)?;
+            if let Some(model_lease) = model_leases.iter().find(|model_lease| {
+                model_lease.num_shards != num_shards as i32
+                    && model_lease.expiration_timestamp > now_timestamp
+            }) {
+                let message = format!(
+                    "The source `{}` is leased with {} shards, not {}.",
+                    source_id, model_lease.num_shards, num_shards
+                );
+                return Err(MetastoreError::Forbidden { message });
+            }
+            // The expired leases of a previous number of shards are obsolete.
+            let delete_leases_statement = diesel::delete(
+                schema::source_shard_leases::dsl::source_shard_leases.filter(
+                    schema::source_shard_leases::dsl::index_id
+                        .eq(index_id)
+                        .and(schema::source_shard_leases::dsl::source_id.eq(source_id))
+                        .and(schema::source_shard_leases::dsl::num_shards.ne(num_shards as i32))
+                        .and(
+                            schema::source_shard_leases::dsl::expiration_timestamp
+                                .le(now_timestamp),
+                        ),
+                ),
+            );
+            debug!(sql=%debug_query::<Pg, _>(&delete_leases_statement).to_string());
+            delete_leases_statement
+                .execute(&*conn)
+                .map_err(MetastoreError::DbError)?;
+            model_leases.retain(|model_lease| model_lease.num_shards == num_shards as i32);
+
+            if let Some(model_lease) = model_leases
+                .iter()
+                .find(|model_lease| model_lease.owner == owner)
+            {
+                let shard_id = model_lease.shard_id as usize;
+                if self.update_source_shard_lease(
+                    &conn,
+                    index_id,
+                    source_id,
+                    shard_id,
+                    Some(owner),
+                    owner,
+                    expiration_timestamp,
+                )? {
+                    return Ok(Some(shard_id));
+                }
+            }
+            for shard_id in 0..num_shards {
+                let is_leased = match model_leases
+                    .iter()
+                    .find(|model_lease| model_lease.shard_id == shard_id as i32)
+                {
+                    // The concurrent acquisitions of the same shard are serialized by the
+                    // primary key and the conditions of the statements: only one succeeds.
+                    None => {
+                        let model_lease = model::SourceShardLease {
+                            index_id: index_id.to_string(),
+                            source_id: source_id.to_string(),
+                            shard_id: shard_id as i32,
+                            num_shards: num_shards as i32,
+                            owner: owner.to_string(),
+                            expiration_timestamp,
+                        };
+                        let insert_lease_statement = diesel::insert_into(
+                            schema::source_shard_leases::dsl::source_shard_leases,
+                        )
+                        .values(&model_lease)
+                        .on_conflict_do_nothing();
+                        debug!(sql=%debug_query::<Pg, _>(&insert_lease_statement).to_string());
+                        insert_lease_statement
+                            .execute(&*conn)
+                            .map_err(MetastoreError::DbError)?
+                            == 1
+                    }
+                    Some(model_lease) if model_lease.expiration_timestamp <= now_timestamp => self
+                        .update_source_shard_lease(
+                            &conn,
+                            index_id,
+                            source_id,
+                            shard_id,
+                            None,
+                            owner,
+                            expiration_timestamp,
+                        )?,
+                    Some(_) => false,
+                };
+                if is_leased {
+                    return Ok(Some(shard_id));
+                }
+            }
+            Ok(None)
+        })?;
+        if let Some(shard_id) = shard_id_opt {
+            info!(
+                index_id = index_id,
+                source_id = source_id,
+                shard_id = shard_id,
+                owner = owner,
+                "acquired-source-shard-lease"
+            );
+        }
+        Ok(shard_id_opt)
+    }
+
+    async fn renew_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        shard_id: usize,
+        owner: &str,
+        lease_duration: Duration,
+    ) -> MetastoreResult<()> {
+        let conn = self.get_conn()?;
+        // Check for the existence of index.
+        let index_exists: bool = self.is_index_exist(&conn, index_id)?;
+        if !index_exists {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        let expiration_timestamp = self.db_now_timestamp(&conn)? + lease_duration.as_secs() as i64;
+        let is_renewed = self.update_source_shard_lease(
+            &conn,
+            index_id,
+            source_id,
+            shard_id,
+            Some(owner),
+            owner,
+            expiration_timestamp,
+        )?;
+        if !is_renewed {
+            let message = format!(
+                "The lease of shard {} of source `{}` is no longer held by `{}`.",
+                shard_id, source_id, owner
+            );
+            return Err(MetastoreError::Forbidden { message });
+        }
+        Ok(())
+    }
+
+    async fn release_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        shard_id: usize,
+        owner: &str,
+    ) -> MetastoreResult<()> {
+        let conn = self.get_conn()?;
+        // Check for the existence of index.
+        let index_exists: bool = self.is_index_exist(&conn, index_id)?;
+        if !index_exists {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        let delete_lease_statement = diesel::delete(
+            schema::source_shard_leases::dsl::source_shard_leases.filter(
+                schema::source_shard_leases::dsl::index_id
+                    .eq(index_id)
+                    .and(schema::source_shard_leases::dsl::source_id.eq(source_id))
+                    .and(schema::source_shard_leases::dsl::shard_id.eq(shard_id as i32))
+                    .and(schema::source_shard_leases::dsl::owner.eq(owner)),
+            ),
+        );
+        debug!(sql=%debug_query::<Pg, _>(&delete_lease_statement).to_string());
+        delete_lease_statement
+            .execute(&*conn)
+            .map_err(MetastoreError::DbError)?;
+        Ok(())
+    }
+
+    async fn list_source_shard_leases(
+        &self,
+        index_id: &str,
+    ) -> MetastoreResult<Vec<SourceShardLease>> {
+        let conn = self.get_conn()?;
+        // Check for the existence of index.
+        let index_exists: bool = self.is_index_exist(&conn, index_id)?;
+        if !index_exists {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        let select_leases_statement = schema::source_shard_leases::dsl::source_shard_leases
+            .filter(schema::source_shard_leases::dsl::index_id.eq(index_id))
+            .order((
+                schema::source_shard_leases::dsl::source_id,
+                schema::source_shard_leases::dsl::shard_id,
+            ));
+        debug!(sql=%debug_query::<Pg, _>(&select_leases_statement).to_string());
+        let model_leases: Vec<model::SourceShardLease> = select_leases_statement
+            .get_results(&*conn)
+            .map_err(MetastoreError::DbError)?;
+        Ok(model_leases
+            .iter()
+            .map(model::SourceShardLease::make_source_shard_lease)
+            .collect())
+    }
+
+    async fn acquire_split_lease<'a>(
+        &self,
+        index_id: &str,
+        lease_id: &str,
+        split_ids: &[&'a str],
+        lease_duration: Duration,
+    ) -> MetastoreResult<()> {
+        let conn = self.get_conn()?;
+        // Check for the existence of index.
+        let index_exists: bool = self.is_index_exist(&conn, index_id)?;
+        if !index_exists {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        conn.transaction::<_, MetastoreError, _>(|| {
+            let now_timestamp = self.db_now_timestamp(&conn)?;
+            let expiration_timestamp = now_timestamp + lease_duration.as_secs() as i64;
+            let delete_expired_leases_statement = diesel::delete(
+                schema::split_leases::dsl::split_leases.filter(
+                    schema::split_leases::dsl::index_id
+                        .eq(index_id)
+                        .and(schema::split_leases::dsl::expiration_timestamp.le(now_timestamp)),
+                ),
+            );
+            debug!(sql=%debug_query::<Pg, _>(&delete_expired_leases_statement).to_string());
+            delete_expired_leases_statement
+                .execute(&*conn)
+                .map_err(MetastoreError::DbError)?;
+
+            // Renew the splits already covered by the lease, then add the other ones.
+            let renew_lease_statement = diesel::update(
+                schema::split_leases::dsl::split_leases.filter(
+                    schema::split_leases::dsl::index_id
+                        .eq(index_id)
+                        .and(schema::split_leases::dsl::lease_id.eq(lease_id)),
+                ),
+            )
+            .set(schema::split_leases::dsl::expiration_timestamp.eq(expiration_timestamp));
+            debug!(sql=%debug_query::<Pg, _>(&renew_lease_statement).to_string());
+            renew_lease_statement
+                .execute(&*conn)
+                .map_err(MetastoreError::DbError)?;
+
+            if split_ids.is_empty() {
+                return Ok(());
+            }
+            let model_leases: Vec<model::SplitLease> = split_ids
+                .iter()
+                .map(|split_id| model::SplitLease {
+                    index_id: index_id.to_string(),
+                    lease_id: lease_id.to_string(),
+                    split_id: split_id.to_string(),
+                    expiration_timestamp,
+                })
+                .collect();
+            let insert_leases_statement =
+                diesel::insert_into(schema::split_leases::dsl::split_leases)
+                    .values(&model_leases)
+                    .on_conflict_do_nothing();
+            debug!(sql=%debug_query::<Pg, _>(&insert_leases_statement).to_string());
+            insert_leases_statement
+                .execute(&*conn)
+                .map_err(MetastoreError::DbError)?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    async fn release_split_lease(&self, index_id: &str, lease_id: &str) -> MetastoreResult<()> {
+        let conn = self.get_conn()?;
+        // Check for the existence of index.
+        let index_exists: bool = self.is_index_exist(&conn, index_id)?;
+        if !index_exists {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        let delete_lease_statement = diesel::delete(
+            schema::split_leases::dsl::split_leases.filter(
+                schema::split_leases::dsl::index_id
+                    .eq(index_id)
+                    .and(schema::split_leases::dsl::lease_id.eq(lease_id)),
+            ),
+        );
+        debug!(sql=%debug_query::<Pg, _>(&delete_lease_statement).to_string());
+        delete_lease_statement
+            .execute(&*conn)
+            .map_err(MetastoreError::DbError)?;
+        Ok(())
+    }
+
+    async fn list_leased_split_ids(&self, index_id: &str) -> MetastoreResult<Vec<String>> {
+        let conn = self.get_conn()?;
+        // Check for the existence of index.
+        let index_exists: bool = self.is_index_exist(&conn, index_id)?;
+        if !index_exists {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        let now_timestamp = self.db_now_timestamp(&conn)?;
+        let select_split_ids_statement = schema::split_leases::dsl::split_leases
+            .filter(
+                schema::split_leases::dsl::index_id
+                    .eq(index_id)
+                    .and(schema::split_leases::dsl::expiration_timestamp.gt(now_timestamp)),
+            )
+            .select(schema::split_leases::dsl::split_id)
+            .distinct()
+            .order(schema::split_leases::dsl::split_id);
+        debug!(sql=%debug_query::<Pg, _>(&select_split_ids_statement).to_string());
+        select_split_ids_statement
+            .get_results(&*conn)
+            .map_err(MetastoreError::DbError)
+    }
+
+    async fn refresh_index(&self, index_id: &str) -> MetastoreResult<()> {
+        // The reads always query the database.
+        let conn = self.get_conn()?;
+        if !self.is_index_exist(&conn, index_id)? {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    async fn watch_splits(&self, index_id: &str) -> MetastoreResult<SplitEventReceiver> {
+        // Listening first ensures no event is missed between the check and the subscription.
+        let event_receiver = listen_split_events(&self.uri, index_id).await?;
+        let conn = self.get_conn()?;
+        if !self.is_index_exist(&conn, index_id)? {
+            return Err(MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            });
+        }
+        Ok(event_receiver)
+    }
+
+    async fn now_timestamp(&self) -> MetastoreResult<i64> {
+        let conn = self.get_conn()?;
+        self.db_now_timestamp(&conn)
+    }
+
     fn uri(&self) -> String {
         self.uri.clone()
     }
@@ -975,8 +1968,11 @@ impl Default for PostgresqlMetastoreFactory {
 
 #[async_trait]
 impl MetastoreFactory for PostgresqlMetastoreFactory {
-    async fn resolve(&self, uri: &str) -> Result<Arc<dyn Metastore>, MetastoreResolverError> {
-        let metastore = PostgresqlMetastore::new(uri)
+    async fn resolve(
+        &self,
+        uri: &QuickwitUri,
+    ) -> Result<Arc<dyn Metastore>, MetastoreResolverError> {
+        let metastore = PostgresqlMetastore::new(uri.as_str())
             .await
             .map_err(MetastoreResolverError::FailedToOpenMetastore)?;
 
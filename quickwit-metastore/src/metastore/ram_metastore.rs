@@ -0,0 +1,119 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use quickwit_common::QuickwitUri;
+use quickwit_storage::RamStorage;
+
+use crate::{Metastore, MetastoreFactory, MetastoreResolverError, SingleFileMetastore};
+
+/// Metastores of the `ram://` URIs resolved so far, by URI.
+static RAM_METASTORES: Lazy<Mutex<HashMap<String, Arc<dyn Metastore>>>> =
+    Lazy::new(Default::default);
+
+/// Resolves the `ram://` URIs to metastores held in memory, which are lost when the process
+/// exits. They suit tests, demos and ephemeral sessions, e.g. attaching the indexes of another
+/// cluster to search them, without requiring a writable directory for the metadata files.
+///
+/// Like the storages of the `ram://` URIs, the metastores are shared by the whole process:
+/// resolving the same URI twice returns the same metastore, whatever the resolver. Each
+/// metastore keeps its metadata files in a storage of its own, which the storage resolvers do
+/// not see.
+#[derive(Default)]
+pub struct RamMetastoreFactory;
+
+#[async_trait]
+impl MetastoreFactory for RamMetastoreFactory {
+    async fn resolve(
+        &self,
+        uri: &QuickwitUri,
+    ) -> Result<Arc<dyn Metastore>, MetastoreResolverError> {
+        if uri.protocol() != "ram" {
+            return Err(MetastoreResolverError::ProtocolUnsupported(
+                uri.protocol().to_string(),
+            ));
+        }
+        let mut ram_metastores = RAM_METASTORES.lock().unwrap();
+        let metastore = ram_metastores
+            .entry(uri.as_str().to_string())
+            .or_insert_with(|| Arc::new(SingleFileMetastore::new(Arc::new(RamStorage::default()))))
+            .clone();
+        Ok(metastore)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use quickwit_common::QuickwitUri;
+    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_storage::quickwit_storage_uri_resolver;
+
+    use crate::checkpoint::Checkpoint;
+    use crate::{IndexMetadata, MetastoreError, MetastoreUriResolver};
+
+    #[tokio::test]
+    async fn test_ram_metastore_factory() -> anyhow::Result<()> {
+        let index_metadata = IndexMetadata {
+            index_id: "my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(WikipediaIndexConfig::new()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+        let metastore = MetastoreUriResolver::default()
+            .resolve("ram://test-ram-metastore")
+            .await?;
+        metastore.create_index(index_metadata).await?;
+
+        // Another resolver returns the same metastore.
+        let same_metastore = MetastoreUriResolver::default()
+            .resolve("ram://test-ram-metastore")
+            .await?;
+        assert_eq!(
+            same_metastore.index_metadata("my-index").await?.index_uri,
+            "ram://indexes/my-index"
+        );
+
+        // Other URIs designate other metastores.
+        let other_metastore = MetastoreUriResolver::default()
+            .resolve("ram://test-ram-metastore/other")
+            .await?;
+        assert!(matches!(
+            other_metastore.index_metadata("my-index").await,
+            Err(MetastoreError::IndexDoesNotExist { .. })
+        ));
+
+        // The metadata files are not written to the storages of the `ram://` URIs.
+        let storage = quickwit_storage_uri_resolver().resolve("ram://test-ram-metastore")?;
+        assert!(!storage.exists(Path::new("my-index/quickwit.json")).await?);
+        Ok(())
+    }
+}
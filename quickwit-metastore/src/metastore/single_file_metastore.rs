@@ -17,44 +17,184 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ops::{Range, RangeInclusive};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use chrono::Utc;
+use quickwit_common::QuickwitUri;
+use quickwit_index_config::IndexConfig;
 use quickwit_storage::{
     quickwit_storage_uri_resolver, PutPayload, Storage, StorageErrorKind, StorageResolverError,
     StorageUriResolver,
 };
-use tokio::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tracing::warn;
 
-use crate::checkpoint::CheckpointDelta;
-use crate::metastore::match_tags_filter;
+use crate::checkpoint::{CheckpointDelta, PartitionId, Position};
+use crate::metastore::split_events::{published_split_events, SPLIT_EVENTS_BUFFER_SIZE};
+use crate::metastore::tag_index::TagIndex;
 use crate::{
     IndexMetadata, MetadataSet, Metastore, MetastoreError, MetastoreFactory,
-    MetastoreResolverError, MetastoreResult, SplitMetadata, SplitMetadataAndFooterOffsets,
-    SplitState,
+    MetastoreResolverError, MetastoreResult, SourceShardLease, SourceShardLeaseHolder, SplitEvent,
+    SplitEventReceiver, SplitMetadata, SplitMetadataAndFooterOffsets, SplitState,
+    SplitVerification,
 };
 
 /// Metadata file managed by [`SingleFileMetastore`].
 const META_FILENAME: &str = "quickwit.json";
 
+/// File of the split leases of an index managed by [`SingleFileMetastore`].
+///
+/// The leases are kept out of the metadata file: the searches write them, and overwriting the
+/// metadata file from the possibly stale cache of a searcher would revert the changes made to
+/// the splits by the indexers.
+const SPLIT_LEASES_FILENAME: &str = "split_leases.json";
+
+/// Interval at which the metadata file of a watched index is read again, to detect the splits
+/// published or deleted by other processes.
+const SPLIT_EVENTS_POLLING_INTERVAL: Duration = Duration::from_secs(10);
+
 /// Creates a path to the metadata file from the given index ID.
 fn meta_path(index_id: &str) -> PathBuf {
     Path::new(index_id).join(Path::new(META_FILENAME))
 }
 
+/// Creates a path to the split leases file from the given index ID.
+fn split_leases_path(index_id: &str) -> PathBuf {
+    Path::new(index_id).join(Path::new(SPLIT_LEASES_FILENAME))
+}
+
+/// A lease of splits by a search, as recorded in the split leases file of an index.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct SplitLeaseEntry {
+    split_ids: BTreeSet<String>,
+    expiration_timestamp: i64,
+}
+
+/// Split leases of an index, by lease ID.
+type SplitLeaseEntries = BTreeMap<String, SplitLeaseEntry>;
+
 /// Takes 2 intervals and returns true iff their intersection is empty
 fn is_disjoint(left: &Range<i64>, right: &RangeInclusive<i64>) -> bool {
     left.end <= *right.start() || *right.end() < left.start
 }
 
+/// The leases of the shards of the sources cannot be held by a file-backed metastore: each
+/// process updates its own cached copy of the metadata file and overwrites the file, so the
+/// indexers of another process would never see the renewals of the leases.
+fn source_shard_leases_unsupported() -> MetastoreError {
+    MetastoreError::Forbidden {
+        message: "Leasing the shards of a source requires a PostgreSQL metastore.".to_string(),
+    }
+}
+
+/// Metadata set of an index held in the cache, along with the tag index of its splits.
+struct CachedIndex {
+    metadata_set: MetadataSet,
+    tag_index: TagIndex,
+}
+
+impl From<MetadataSet> for CachedIndex {
+    fn from(metadata_set: MetadataSet) -> Self {
+        let tag_index = TagIndex::build(&metadata_set.splits);
+        CachedIndex {
+            metadata_set,
+            tag_index,
+        }
+    }
+}
+
+/// Reads the metadata set of an index from the storage, bypassing the cache.
+async fn load_metadata_set(storage: &dyn Storage, index_id: &str) -> MetastoreResult<MetadataSet> {
+    let metadata_path = meta_path(index_id);
+    let content = storage
+        .get_all(&metadata_path)
+        .await
+        .map_err(|storage_err| match storage_err.kind() {
+            StorageErrorKind::DoesNotExist => MetastoreError::IndexDoesNotExist {
+                index_id: index_id.to_string(),
+            },
+            StorageErrorKind::Unauthorized => MetastoreError::Forbidden {
+                message: "The request credentials do not allow for this operation.".to_string(),
+            },
+            _ => MetastoreError::InternalError {
+                message: "Failed to get index files.".to_string(),
+                cause: anyhow::anyhow!(storage_err),
+            },
+        })?;
+
+    let metadata_set = serde_json::from_slice::<MetadataSet>(&content[..])
+        .map_err(|serde_err| MetastoreError::InvalidManifest { cause: serde_err })?;
+
+    if metadata_set.index.index_id != index_id {
+        return Err(MetastoreError::InternalError {
+            message: "Inconsistent manifest: index_id mismatch.".to_string(),
+            cause: anyhow::anyhow!(
+                "Expected index_id `{}`, but found `{}`",
+                index_id,
+                metadata_set.index.index_id
+            ),
+        });
+    }
+    Ok(metadata_set)
+}
+
+fn published_split_ids(metadata_set: &MetadataSet) -> BTreeSet<String> {
+    metadata_set
+        .splits
+        .values()
+        .filter(|split| split.split_metadata.split_state == SplitState::Published)
+        .map(|split| split.split_metadata.split_id.clone())
+        .collect()
+}
+
+/// Reads the metadata file of the index every `polling_interval` and sends the changes of its
+/// published splits to `event_sender`, until the receiver is dropped or the index is deleted.
+async fn poll_split_events(
+    storage: Arc<dyn Storage>,
+    index_id: String,
+    mut published_split_ids: BTreeSet<String>,
+    polling_interval: Duration,
+    event_sender: mpsc::Sender<SplitEvent>,
+) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(polling_interval) => {}
+            _ = event_sender.closed() => return,
+        }
+        let metadata_set_res = load_metadata_set(&*storage, &index_id).await;
+        let (current_split_ids, index_exists) = match metadata_set_res {
+            Ok(metadata_set) => (published_split_ids(&metadata_set), true),
+            Err(MetastoreError::IndexDoesNotExist { .. }) => (BTreeSet::new(), false),
+            Err(error) => {
+                warn!(index_id = %index_id, error = ?error, "Failed to poll the splits of the index.");
+                continue;
+            }
+        };
+        for split_event in published_split_events(&published_split_ids, &current_split_ids) {
+            if event_sender.send(split_event).await.is_err() {
+                return;
+            }
+        }
+        if !index_exists {
+            return;
+        }
+        published_split_ids = current_split_ids;
+    }
+}
+
 /// Single file metastore implementation.
 pub struct SingleFileMetastore {
     storage: Arc<dyn Storage>,
-    cache: Arc<RwLock<HashMap<String, MetadataSet>>>,
+    cache: Arc<RwLock<HashMap<String, CachedIndex>>>,
+    split_events_polling_interval: Duration,
+    /// Serializes the updates of the split leases files made by this process.
+    split_leases_lock: Arc<Mutex<()>>,
 }
 
 #[allow(dead_code)]
@@ -63,7 +203,10 @@ impl SingleFileMetastore {
     #[doc(hidden)]
     pub fn for_test() -> Self {
         use quickwit_storage::RamStorage;
-        SingleFileMetastore::new(Arc::new(RamStorage::default()))
+        SingleFileMetastore {
+            split_events_polling_interval: Duration::from_millis(50),
+            ..SingleFileMetastore::new(Arc::new(RamStorage::default()))
+        }
     }
 
     /// Creates a [`SingleFileMetastore`] for a specified storage.
@@ -71,6 +214,26 @@ impl SingleFileMetastore {
         SingleFileMetastore {
             storage,
             cache: Arc::new(RwLock::new(HashMap::new())),
+            split_events_polling_interval: SPLIT_EVENTS_POLLING_INTERVAL,
+            split_leases_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Creates a [`SingleFileMetastore`] for a specified storage, whose cache is filled with
+    /// a snapshot of the metadata sets of some indexes.
+    ///
+    /// The metadata files of these indexes are never read from the storage, so the metastore
+    /// does not see the changes made by other processes after the snapshot was taken.
+    pub fn with_snapshot(storage: Arc<dyn Storage>, metadata_sets: Vec<MetadataSet>) -> Self {
+        let cache: HashMap<String, CachedIndex> = metadata_sets
+            .into_iter()
+            .map(|metadata_set| (metadata_set.index.index_id.clone(), metadata_set.into()))
+            .collect();
+        SingleFileMetastore {
+            storage,
+            cache: Arc::new(RwLock::new(cache)),
+            split_events_polling_interval: SPLIT_EVENTS_POLLING_INTERVAL,
+            split_leases_lock: Arc::new(Mutex::new(())),
         }
     }
 
@@ -106,51 +269,44 @@ impl SingleFileMetastore {
         // We first check if the index is in the cache...
         {
             let cache = self.cache.read().await;
-            if let Some(index_metadata) = cache.get(index_id) {
-                return Ok(index_metadata.clone());
+            if let Some(cached_index) = cache.get(index_id) {
+                return Ok(cached_index.metadata_set.clone());
             }
         }
 
         // It is not in the cache yet, let's fetch it from the storage...
-        let metadata_path = meta_path(index_id);
-        let content = self
-            .storage
-            .get_all(&metadata_path)
-            .await
-            .map_err(|storage_err| match storage_err.kind() {
-                StorageErrorKind::DoesNotExist => MetastoreError::IndexDoesNotExist {
-                    index_id: index_id.to_string(),
-                },
-                StorageErrorKind::Unauthorized => MetastoreError::Forbidden {
-                    message: "The request credentials do not allow for this operation.".to_string(),
-                },
-                _ => MetastoreError::InternalError {
-                    message: "Failed to get index files.".to_string(),
-                    cause: anyhow::anyhow!(storage_err),
-                },
-            })?;
-
-        let metadata_set = serde_json::from_slice::<MetadataSet>(&content[..])
-            .map_err(|serde_err| MetastoreError::InvalidManifest { cause: serde_err })?;
-
-        if metadata_set.index.index_id != index_id {
-            return Err(MetastoreError::InternalError {
-                message: "Inconsistent manifest: index_id mismatch.".to_string(),
-                cause: anyhow::anyhow!(
-                    "Expected index_id `{}`, but found `{}`",
-                    index_id,
-                    metadata_set.index.index_id
-                ),
-            });
-        }
+        let metadata_set = load_metadata_set(&*self.storage, index_id).await?;
 
         // Finally, update the cache accordingly
         let mut cache = self.cache.write().await;
-        cache.insert(index_id.to_string(), metadata_set.clone());
+        cache.insert(index_id.to_string(), metadata_set.clone().into());
 
         Ok(metadata_set)
     }
 
+    /// Applies `f` to the cached index, fetching it from the storage first if needed.
+    ///
+    /// Unlike [`Self::get_index`], this does not copy the metadata set of the index.
+    async fn with_cached_index<T>(
+        &self,
+        index_id: &str,
+        f: impl FnOnce(&CachedIndex) -> T,
+    ) -> MetastoreResult<T> {
+        {
+            let cache = self.cache.read().await;
+            if let Some(cached_index) = cache.get(index_id) {
+                return Ok(f(cached_index));
+            }
+        }
+        let metadata_set = self.get_index(index_id).await?;
+        let cache = self.cache.read().await;
+        match cache.get(index_id) {
+            Some(cached_index) => Ok(f(cached_index)),
+            // The index has been deleted in the meantime.
+            None => Ok(f(&CachedIndex::from(metadata_set))),
+        }
+    }
+
     /// Serializes the metadata set and stores the data on the storage.
     async fn put_index(&self, metadata_set: MetadataSet) -> MetastoreResult<()> {
         // Serialize metadata set.
@@ -183,11 +339,83 @@ impl SingleFileMetastore {
 
         // Update the internal data if the storage is successfully updated.
         let mut cache = self.cache.write().await;
-        cache.insert(index_id, metadata_set);
+        cache.insert(index_id, metadata_set.into());
 
         Ok(())
     }
 
+    /// Reads the split leases of an index from the storage, bypassing the cache so that the
+    /// leases recorded by other processes are seen too.
+    async fn load_split_leases(&self, index_id: &str) -> MetastoreResult<SplitLeaseEntries> {
+        let split_leases_path = split_leases_path(index_id);
+        let content = match self.storage.get_all(&split_leases_path).await {
+            Ok(content) => content,
+            Err(storage_err) => {
+                return match storage_err.kind() {
+                    StorageErrorKind::DoesNotExist => Ok(SplitLeaseEntries::new()),
+                    StorageErrorKind::Unauthorized => Err(MetastoreError::Forbidden {
+                        message: "The request credentials do not allow for this operation."
+                            .to_string(),
+                    }),
+                    _ => Err(MetastoreError::InternalError {
+                        message: "Failed to get split leases file.".to_string(),
+                        cause: anyhow::anyhow!(storage_err),
+                    }),
+                };
+            }
+        };
+        serde_json::from_slice::<SplitLeaseEntries>(&content[..]).map_err(|serde_err| {
+            MetastoreError::InternalError {
+                message: "Failed to deserialize split leases file.".to_string(),
+                cause: anyhow::anyhow!(serde_err),
+            }
+        })
+    }
+
+    /// Applies `mutate` to the split leases of an index, dropping the expired ones, and writes
+    /// them back to the storage.
+    ///
+    /// The updates made by this process are serialized, but another process updating the file
+    /// at the same time may overwrite them: unlike the PostgreSQL metastore, this metastore
+    /// cannot guarantee that a lease is never lost.
+    async fn update_split_leases(
+        &self,
+        index_id: &str,
+        mutate: impl FnOnce(&mut SplitLeaseEntries, i64),
+    ) -> MetastoreResult<()> {
+        // Check for the existence of index.
+        self.with_cached_index(index_id, |_| ()).await?;
+        let _split_leases_guard = self.split_leases_lock.lock().await;
+        let now_timestamp = self.now_timestamp().await?;
+        let mut split_leases = self.load_split_leases(index_id).await?;
+        split_leases.retain(|_, split_lease| split_lease.expiration_timestamp > now_timestamp);
+        mutate(&mut split_leases, now_timestamp);
+
+        let content: Vec<u8> = serde_json::to_vec_pretty(&split_leases).map_err(|serde_err| {
+            MetastoreError::InternalError {
+                message: "Failed to serialize split leases".to_string(),
+                cause: anyhow::anyhow!(serde_err),
+            }
+        })?;
+        let split_leases_path = split_leases_path(index_id);
+        self.storage
+            .put(&split_leases_path, PutPayload::from(content))
+            .await
+            .map_err(|storage_err| match storage_err.kind() {
+                StorageErrorKind::Unauthorized => MetastoreError::Forbidden {
+                    message: "The request credentials do not allow for this operation.".to_string(),
+                },
+                _ => MetastoreError::InternalError {
+                    message: format!(
+                        "Failed to write split leases file to `{}`.",
+                        split_leases_path.display()
+                    ),
+                    cause: anyhow::anyhow!(storage_err),
+                },
+            })?;
+        Ok(())
+    }
+
     /// Helper to publish a list of splits.
     fn publish_splits_helper<'a>(
         split_ids: &[&'a str],
@@ -222,6 +450,36 @@ impl SingleFileMetastore {
         Ok(())
     }
 
+    /// Helper to publish a list of splits and apply the checkpoint delta to the index checkpoint.
+    /// Returns false if the publication was a no-op.
+    fn publish_splits_with_checkpoint_delta_helper<'a>(
+        split_ids: &[&'a str],
+        checkpoint_delta: CheckpointDelta,
+        metadata_set: &mut MetadataSet,
+    ) -> MetastoreResult<bool> {
+        // A publication retried after succeeding finds the splits published and the
+        // checkpoint delta applied.
+        let is_publication_retry = metadata_set.index.checkpoint.covers(&checkpoint_delta)
+            && split_ids.iter().all(|split_id| {
+                metadata_set
+                    .splits
+                    .get(*split_id)
+                    .map(|metadata| metadata.split_metadata.split_state == SplitState::Published)
+                    .unwrap_or(false)
+            });
+        if is_publication_retry {
+            return Ok(false);
+        }
+
+        metadata_set
+            .index
+            .checkpoint
+            .try_apply_delta(checkpoint_delta)?;
+
+        SingleFileMetastore::publish_splits_helper(split_ids, metadata_set)?;
+        Ok(true)
+    }
+
     /// Helper to mark a list of splits for deletion.
     fn mark_splits_for_deletion_helper<'a>(
         split_ids: &[&'a str],
@@ -248,6 +506,42 @@ impl SingleFileMetastore {
 
         Ok(is_modified)
     }
+
+    /// Helper to move a list of splits from `source_state` to `target_state`. The splits
+    /// already in `target_state` are left untouched.
+    fn move_splits_helper<'a>(
+        split_ids: &[&'a str],
+        source_state: SplitState,
+        target_state: SplitState,
+        metadata_set: &mut MetadataSet,
+    ) -> MetastoreResult<bool> {
+        let mut is_modified = false;
+        for &split_id in split_ids {
+            // Check for the existence of split.
+            let metadata = metadata_set.splits.get_mut(split_id).ok_or_else(|| {
+                MetastoreError::SplitDoesNotExist {
+                    split_id: split_id.to_string(),
+                }
+            })?;
+
+            if metadata.split_metadata.split_state == target_state {
+                continue;
+            }
+            if metadata.split_metadata.split_state != source_state {
+                let message: String = format!(
+                    "This split is not in the {:?} state: {:?}:{:?}",
+                    source_state, split_id, &metadata.split_metadata.split_state
+                );
+                return Err(MetastoreError::Forbidden { message });
+            }
+
+            metadata.split_metadata.split_state = target_state.clone();
+            metadata.split_metadata.update_timestamp = Utc::now().timestamp();
+            is_modified = true;
+        }
+
+        Ok(is_modified)
+    }
 }
 
 #[async_trait]
@@ -300,6 +594,11 @@ impl Metastore for SingleFileMetastore {
                 },
             })?;
 
+        // The leases of the splits of a deleted index are obsolete.
+        if let Err(storage_err) = self.storage.delete(&split_leases_path(index_id)).await {
+            warn!(index_id = %index_id, error = ?storage_err, "Failed to delete the split leases file.");
+        }
+
         // Update the internal data if the storage is successfully updated.
         let mut cache = self.cache.write().await;
         cache.remove(index_id);
@@ -315,11 +614,17 @@ impl Metastore for SingleFileMetastore {
         let mut metadata_set = self.get_index(index_id).await?;
 
         // Check whether the split exists.
-        // If the split exists, return an error to prevent the split from being registered.
-        if metadata_set
+        // If the split exists, return an error to prevent the split from being registered,
+        // unless it is still staged: staging it again, e.g. because a previous attempt timed
+        // out or the split was rebuilt under the ID provided by its source, replaces it.
+        let is_split_registered = metadata_set
             .splits
-            .contains_key(&metadata.split_metadata.split_id)
-        {
+            .get(&metadata.split_metadata.split_id)
+            .map(|existing_metadata| {
+                existing_metadata.split_metadata.split_state != SplitState::Staged
+            })
+            .unwrap_or(false);
+        if is_split_registered {
             return Err(MetastoreError::InternalError {
                 message: format!(
                     "Try to stage split that already exists ({})",
@@ -348,16 +653,28 @@ impl Metastore for SingleFileMetastore {
         checkpoint_delta: CheckpointDelta,
     ) -> MetastoreResult<()> {
         let mut metadata_set = self.get_index(index_id).await?;
-        metadata_set
-            .index
-            .checkpoint
-            .try_apply_delta(checkpoint_delta)?;
 
-        SingleFileMetastore::publish_splits_helper(split_ids, &mut metadata_set)?;
-        self.put_index(metadata_set).await?;
+        let is_modified = SingleFileMetastore::publish_splits_with_checkpoint_delta_helper(
+            split_ids,
+            checkpoint_delta,
+            &mut metadata_set,
+        )?;
+        if is_modified {
+            self.put_index(metadata_set).await?;
+        }
         Ok(())
     }
 
+    async fn publish_splits_with_lease<'a>(
+        &self,
+        _index_id: &str,
+        _split_ids: &[&'a str],
+        _checkpoint_delta: CheckpointDelta,
+        _lease_holder: &SourceShardLeaseHolder,
+    ) -> MetastoreResult<()> {
+        Err(source_shard_leases_unsupported())
+    }
+
     async fn replace_splits<'a>(
         &self,
         index_id: &str,
@@ -396,26 +713,30 @@ impl Metastore for SingleFileMetastore {
             _ => true, // Return `true` if `time_range` is omitted or the split has no time range.
         };
 
-        let tag_filter = |split_metadata: &SplitMetadata| {
-            let split_tags = split_metadata
-                .tags
-                .clone()
-                .into_iter()
-                .collect::<Vec<String>>();
-            match_tags_filter(split_tags.as_slice(), tags)
+        let is_matching = |metadata: &SplitMetadataAndFooterOffsets| {
+            metadata.split_metadata.split_state == state
+                && time_range_filter(&metadata.split_metadata)
         };
 
-        let metadata_set = self.get_index(index_id).await?;
-        let splits = metadata_set
-            .splits
-            .into_values()
-            .filter(|metadata| {
-                metadata.split_metadata.split_state == state
-                    && time_range_filter(&metadata.split_metadata)
-                    && tag_filter(&metadata.split_metadata)
-            })
-            .collect();
-        Ok(splits)
+        self.with_cached_index(index_id, |cached_index| {
+            let splits = &cached_index.metadata_set.splits;
+            if tags.is_empty() {
+                return splits
+                    .values()
+                    .filter(|metadata| is_matching(metadata))
+                    .cloned()
+                    .collect();
+            }
+            // Only the splits carrying one of the tags are looked at.
+            cached_index
+                .tag_index
+                .matching_split_ids(tags)
+                .filter_map(|split_id| splits.get(split_id))
+                .filter(|metadata| is_matching(metadata))
+                .cloned()
+                .collect()
+        })
+        .await
     }
 
     async fn list_all_splits(
@@ -478,11 +799,226 @@ impl Metastore for SingleFileMetastore {
         Ok(())
     }
 
+    async fn quarantine_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        let mut metadata_set = self.get_index(index_id).await?;
+
+        let is_modified = SingleFileMetastore::move_splits_helper(
+            split_ids,
+            SplitState::Published,
+            SplitState::Quarantined,
+            &mut metadata_set,
+        )?;
+        if is_modified {
+            self.put_index(metadata_set).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn unquarantine_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        let mut metadata_set = self.get_index(index_id).await?;
+
+        let is_modified = SingleFileMetastore::move_splits_helper(
+            split_ids,
+            SplitState::Quarantined,
+            SplitState::Published,
+            &mut metadata_set,
+        )?;
+        if is_modified {
+            self.put_index(metadata_set).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn record_split_verification(
+        &self,
+        index_id: &str,
+        split_id: &str,
+        verification: SplitVerification,
+    ) -> MetastoreResult<()> {
+        let mut metadata_set = self.get_index(index_id).await?;
+
+        // Check for the existence of split.
+        let metadata = metadata_set.splits.get_mut(split_id).ok_or_else(|| {
+            MetastoreError::SplitDoesNotExist {
+                split_id: split_id.to_string(),
+            }
+        })?;
+        metadata.split_metadata.last_verification = Some(verification);
+
+        self.put_index(metadata_set).await?;
+
+        Ok(())
+    }
+
     async fn index_metadata(&self, index_id: &str) -> MetastoreResult<IndexMetadata> {
         let index_metadata = self.get_index(index_id).await?;
         Ok(index_metadata.index)
     }
 
+    async fn update_index_config(
+        &self,
+        index_id: &str,
+        index_config: Arc<dyn IndexConfig>,
+    ) -> MetastoreResult<u64> {
+        let mut metadata_set = self.get_index(index_id).await?;
+        let index_config_version = metadata_set.index.update_index_config(index_config);
+        self.put_index(metadata_set).await?;
+        Ok(index_config_version)
+    }
+
+    async fn set_checkpoint_positions(
+        &self,
+        index_id: &str,
+        positions: Vec<(PartitionId, Position)>,
+    ) -> MetastoreResult<()> {
+        let mut metadata_set = self.get_index(index_id).await?;
+        for (partition_id, position) in positions {
+            metadata_set
+                .index
+                .checkpoint
+                .set_position(partition_id, position);
+        }
+        self.put_index(metadata_set).await?;
+        Ok(())
+    }
+
+    async fn update_index_labels(
+        &self,
+        index_id: &str,
+        description: Option<String>,
+        labels: BTreeMap<String, String>,
+    ) -> MetastoreResult<()> {
+        let mut metadata_set = self.get_index(index_id).await?;
+        metadata_set.index.description = description;
+        metadata_set.index.labels = labels;
+        self.put_index(metadata_set).await?;
+        Ok(())
+    }
+
+    async fn acquire_source_shard_lease(
+        &self,
+        _index_id: &str,
+        _source_id: &str,
+        _num_shards: usize,
+        _owner: &str,
+        _lease_duration: Duration,
+    ) -> MetastoreResult<Option<usize>> {
+        Err(source_shard_leases_unsupported())
+    }
+
+    async fn renew_source_shard_lease(
+        &self,
+        _index_id: &str,
+        _source_id: &str,
+        _shard_id: usize,
+        _owner: &str,
+        _lease_duration: Duration,
+    ) -> MetastoreResult<()> {
+        Err(source_shard_leases_unsupported())
+    }
+
+    async fn release_source_shard_lease(
+        &self,
+        _index_id: &str,
+        _source_id: &str,
+        _shard_id: usize,
+        _owner: &str,
+    ) -> MetastoreResult<()> {
+        Err(source_shard_leases_unsupported())
+    }
+
+    async fn list_source_shard_leases(
+        &self,
+        _index_id: &str,
+    ) -> MetastoreResult<Vec<SourceShardLease>> {
+        Err(source_shard_leases_unsupported())
+    }
+
+    async fn acquire_split_lease<'a>(
+        &self,
+        index_id: &str,
+        lease_id: &str,
+        split_ids: &[&'a str],
+        lease_duration: Duration,
+    ) -> MetastoreResult<()> {
+        self.update_split_leases(index_id, |split_leases, now_timestamp| {
+            let split_lease = split_leases.entry(lease_id.to_string()).or_default();
+            split_lease
+                .split_ids
+                .extend(split_ids.iter().map(|split_id| split_id.to_string()));
+            split_lease.expiration_timestamp = now_timestamp + lease_duration.as_secs() as i64;
+        })
+        .await
+    }
+
+    async fn release_split_lease(&self, index_id: &str, lease_id: &str) -> MetastoreResult<()> {
+        self.update_split_leases(index_id, |split_leases, _| {
+            split_leases.remove(lease_id);
+        })
+        .await
+    }
+
+    async fn list_leased_split_ids(&self, index_id: &str) -> MetastoreResult<Vec<String>> {
+        // Check for the existence of index.
+        self.with_cached_index(index_id, |_| ()).await?;
+        let now_timestamp = self.now_timestamp().await?;
+        let split_leases = self.load_split_leases(index_id).await?;
+        let leased_split_ids: BTreeSet<String> = split_leases
+            .into_values()
+            .filter(|split_lease| split_lease.expiration_timestamp > now_timestamp)
+            .flat_map(|split_lease| split_lease.split_ids)
+            .collect();
+        Ok(leased_split_ids.into_iter().collect())
+    }
+
+    async fn refresh_index(&self, index_id: &str) -> MetastoreResult<()> {
+        let metadata_set_res = load_metadata_set(&*self.storage, index_id).await;
+        let mut cache = self.cache.write().await;
+        match metadata_set_res {
+            Ok(metadata_set) => {
+                cache.insert(index_id.to_string(), metadata_set.into());
+                Ok(())
+            }
+            Err(error) => {
+                if let MetastoreError::IndexDoesNotExist { .. } = &error {
+                    cache.remove(index_id);
+                }
+                Err(error)
+            }
+        }
+    }
+
+    async fn watch_splits(&self, index_id: &str) -> MetastoreResult<SplitEventReceiver> {
+        // The initial state is read from the storage as well, so that the first poll does not
+        // report the changes made by other processes before the subscription.
+        let metadata_set = load_metadata_set(&*self.storage, index_id).await?;
+        let (event_sender, event_receiver) = mpsc::channel(SPLIT_EVENTS_BUFFER_SIZE);
+        tokio::spawn(poll_split_events(
+            self.storage.clone(),
+            index_id.to_string(),
+            published_split_ids(&metadata_set),
+            self.split_events_polling_interval,
+            event_sender,
+        ));
+        Ok(event_receiver)
+    }
+
+    async fn now_timestamp(&self) -> MetastoreResult<i64> {
+        // The storage has no clock: the single file metastore assigns the update timestamps with
+        // the clock of the process it runs in, which is only shared by its own clients.
+        Ok(Utc::now().timestamp())
+    }
+
     fn uri(&self) -> String {
         self.storage.uri()
     }
@@ -504,10 +1040,13 @@ impl Default for SingleFileMetastoreFactory {
 
 #[async_trait]
 impl MetastoreFactory for SingleFileMetastoreFactory {
-    async fn resolve(&self, uri: &str) -> Result<Arc<dyn Metastore>, MetastoreResolverError> {
+    async fn resolve(
+        &self,
+        uri: &QuickwitUri,
+    ) -> Result<Arc<dyn Metastore>, MetastoreResolverError> {
         let storage = self
             .storage_uri_resolver
-            .resolve(uri)
+            .resolve(uri.as_str())
             .map_err(|err| match err {
                 StorageResolverError::InvalidUri { message } => {
                     MetastoreResolverError::InvalidUri(message)
@@ -531,8 +1070,7 @@ impl MetastoreFactory for SingleFileMetastoreFactory {
 #[async_trait]
 impl crate::tests::test_suite::DefaultForTest for SingleFileMetastore {
     async fn default_for_test() -> Self {
-        use quickwit_storage::RamStorage;
-        SingleFileMetastore::new(Arc::new(RamStorage::default()))
+        SingleFileMetastore::for_test()
     }
 }
 
@@ -544,16 +1082,20 @@ mod tests {
     use std::ops::RangeInclusive;
     use std::path::Path;
     use std::sync::Arc;
+    use std::time::Duration;
 
     use chrono::Utc;
+    use quickwit_common::QuickwitUri;
     use quickwit_index_config::WikipediaIndexConfig;
-    use quickwit_storage::{MockStorage, PutPayload, StorageErrorKind};
+    use quickwit_storage::{MockStorage, PutPayload, RamStorage, Storage, StorageErrorKind};
 
     use crate::checkpoint::{Checkpoint, CheckpointDelta};
-    use crate::metastore::single_file_metastore::meta_path;
+    use crate::metastore::single_file_metastore::{
+        load_metadata_set, meta_path, split_leases_path,
+    };
     use crate::{
-        IndexMetadata, MetadataSet, Metastore, MetastoreError, SingleFileMetastore, SplitMetadata,
-        SplitMetadataAndFooterOffsets, SplitState,
+        IndexMetadata, MetadataSet, Metastore, MetastoreError, SingleFileMetastore,
+        SourceShardLeaseHolder, SplitMetadata, SplitMetadataAndFooterOffsets, SplitState,
     };
 
     #[tokio::test]
@@ -569,9 +1111,15 @@ mod tests {
 
             let index_metadata = IndexMetadata {
                 index_id: index_id.to_string(),
-                index_uri: "ram://indexes/my-index".to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
                 index_config: Arc::new(WikipediaIndexConfig::default()),
                 checkpoint: Checkpoint::default(),
+                index_config_history: Vec::new(),
+                mirror_index_uri: None,
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
             };
 
             // Create index
@@ -597,9 +1145,15 @@ mod tests {
 
             let index_metadata = IndexMetadata {
                 index_id: index_id.to_string(),
-                index_uri: "ram://indexes/my-index".to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
                 index_config: Arc::new(WikipediaIndexConfig::default()),
                 checkpoint: Checkpoint::default(),
+                index_config_history: Vec::new(),
+                mirror_index_uri: None,
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
             };
 
             // Create index
@@ -673,9 +1227,15 @@ mod tests {
 
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         // create index
@@ -708,6 +1268,49 @@ mod tests {
         assert!(!split.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_single_file_metastore_with_snapshot() {
+        // The storage panics if any of its methods is called.
+        let mock_storage = MockStorage::default();
+        let index_id = "my-index";
+        let split_metadata = SplitMetadataAndFooterOffsets {
+            footer_offsets: 1000..2000,
+            split_metadata: SplitMetadata {
+                split_id: "split-one".to_string(),
+                split_state: SplitState::Published,
+                ..Default::default()
+            },
+        };
+        let mut splits = HashMap::new();
+        splits.insert("split-one".to_string(), split_metadata);
+        let metadata_set = MetadataSet {
+            index: IndexMetadata {
+                index_id: index_id.to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+                index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+                checkpoint: Checkpoint::default(),
+                index_config_history: Vec::new(),
+                mirror_index_uri: None,
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
+            },
+            splits,
+        };
+        let metastore =
+            SingleFileMetastore::with_snapshot(Arc::new(mock_storage), vec![metadata_set]);
+
+        let index_metadata = metastore.index_metadata(index_id).await.unwrap();
+        assert_eq!(index_metadata.index_uri, "ram://indexes/my-index");
+        let splits = metastore
+            .list_splits(index_id, SplitState::Published, None, &[])
+            .await
+            .unwrap();
+        assert_eq!(splits.len(), 1);
+        assert_eq!(splits[0].split_metadata.split_id, "split-one");
+    }
+
     #[tokio::test]
     async fn test_single_file_metastore_get_index_checks_for_inconsistent_index_id() {
         let metastore = SingleFileMetastore::for_test();
@@ -717,9 +1320,15 @@ mod tests {
         let metadata_set = MetadataSet {
             index: IndexMetadata {
                 index_id: "inconsistent_index_id".to_string(),
-                index_uri: "ram://indexes/my-index".to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
                 index_config: Arc::new(WikipediaIndexConfig::default()),
                 checkpoint: Checkpoint::default(),
+                index_config_history: Vec::new(),
+                mirror_index_uri: None,
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
             },
             splits: HashMap::new(),
         };
@@ -738,4 +1347,289 @@ mod tests {
             MetastoreError::InternalError { .. }
         ));
     }
+
+    #[tokio::test]
+    async fn test_single_file_metastore_refresh_index() {
+        let storage: Arc<dyn Storage> = Arc::new(RamStorage::default());
+        let writer_metastore = SingleFileMetastore::new(storage.clone());
+        let reader_metastore = SingleFileMetastore::new(storage);
+        let index_id = "my-index";
+
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(WikipediaIndexConfig::default()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+        writer_metastore.create_index(index_metadata).await.unwrap();
+        let splits = reader_metastore
+            .list_splits(index_id, SplitState::Published, None, &[])
+            .await
+            .unwrap();
+        assert!(splits.is_empty());
+
+        let split_metadata = SplitMetadataAndFooterOffsets {
+            footer_offsets: 1000..2000,
+            split_metadata: SplitMetadata {
+                split_id: "split-one".to_string(),
+                ..Default::default()
+            },
+        };
+        writer_metastore
+            .stage_split(index_id, split_metadata)
+            .await
+            .unwrap();
+        writer_metastore
+            .publish_splits(index_id, &["split-one"], CheckpointDelta::default())
+            .await
+            .unwrap();
+
+        // The reader still serves the index from its cache...
+        let splits = reader_metastore
+            .list_splits(index_id, SplitState::Published, None, &[])
+            .await
+            .unwrap();
+        assert!(splits.is_empty());
+
+        // ... until it is refreshed.
+        reader_metastore.refresh_index(index_id).await.unwrap();
+        let splits = reader_metastore
+            .list_splits(index_id, SplitState::Published, None, &[])
+            .await
+            .unwrap();
+        assert_eq!(splits.len(), 1);
+
+        writer_metastore.delete_index(index_id).await.unwrap();
+        let metastore_error = reader_metastore.refresh_index(index_id).await.unwrap_err();
+        assert!(matches!(
+            metastore_error,
+            MetastoreError::IndexDoesNotExist { .. }
+        ));
+        let metastore_error = reader_metastore.index_metadata(index_id).await.unwrap_err();
+        assert!(matches!(
+            metastore_error,
+            MetastoreError::IndexDoesNotExist { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_single_file_metastore_parses_mirror_index_uri() {
+        let index_id = "my-index";
+        let metadata_set = MetadataSet {
+            index: IndexMetadata {
+                index_id: index_id.to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+                index_config: Arc::new(WikipediaIndexConfig::default()),
+                checkpoint: Checkpoint::default(),
+                index_config_history: Vec::new(),
+                mirror_index_uri: None,
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
+            },
+            splits: HashMap::new(),
+        };
+        let metadata_set_json = serde_json::to_value(&metadata_set).unwrap();
+
+        // Returns the mirror URI read from an index metadata file whose mirror URI is
+        // `mirror_index_uri_json`, or missing if it is `None`.
+        let load_mirror_index_uri = |mirror_index_uri_json: Option<serde_json::Value>| {
+            let mut metadata_set_json = metadata_set_json.clone();
+            let index_json = metadata_set_json["index"].as_object_mut().unwrap();
+            match mirror_index_uri_json {
+                Some(mirror_index_uri_json) => {
+                    index_json.insert("mirror_index_uri".to_string(), mirror_index_uri_json);
+                }
+                None => {
+                    index_json.remove("mirror_index_uri");
+                }
+            }
+            async move {
+                let metastore = SingleFileMetastore::for_test();
+                let content = serde_json::to_vec(&metadata_set_json).unwrap();
+                metastore
+                    .storage
+                    .put(&meta_path(index_id), PutPayload::from(content))
+                    .await
+                    .unwrap();
+                metastore
+                    .index_metadata(index_id)
+                    .await
+                    .map(|index_metadata| index_metadata.mirror_index_uri)
+            }
+        };
+
+        // The metadata files written before the mirrors have no mirror URI.
+        assert_eq!(load_mirror_index_uri(None).await.unwrap(), None);
+        assert_eq!(
+            load_mirror_index_uri(Some(serde_json::Value::Null))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            load_mirror_index_uri(Some("S3://mirror/my-index/".into()))
+                .await
+                .unwrap(),
+            Some(QuickwitUri::from_well_formed("s3://mirror/my-index"))
+        );
+        let metastore_error = load_mirror_index_uri(Some("mirror/my-index".into()))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            metastore_error,
+            MetastoreError::InvalidManifest { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_single_file_metastore_refuses_source_shard_leases() {
+        let metastore = SingleFileMetastore::for_test();
+        let index_id = "my-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(WikipediaIndexConfig::default()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+        metastore.create_index(index_metadata).await.unwrap();
+        let lease_duration = Duration::from_secs(60);
+
+        let result = metastore
+            .acquire_source_shard_lease(index_id, "kafka-source", 2, "indexer-a", lease_duration)
+            .await
+            .unwrap_err();
+        assert!(matches!(result, MetastoreError::Forbidden { .. }));
+        let result = metastore
+            .renew_source_shard_lease(index_id, "kafka-source", 0, "indexer-a", lease_duration)
+            .await
+            .unwrap_err();
+        assert!(matches!(result, MetastoreError::Forbidden { .. }));
+        let result = metastore
+            .release_source_shard_lease(index_id, "kafka-source", 0, "indexer-a")
+            .await
+            .unwrap_err();
+        assert!(matches!(result, MetastoreError::Forbidden { .. }));
+        let result = metastore
+            .list_source_shard_leases(index_id)
+            .await
+            .unwrap_err();
+        assert!(matches!(result, MetastoreError::Forbidden { .. }));
+
+        let split_metadata = SplitMetadataAndFooterOffsets {
+            footer_offsets: 1000..2000,
+            split_metadata: SplitMetadata {
+                split_id: "my-split".to_string(),
+                split_state: SplitState::Staged,
+                ..Default::default()
+            },
+        };
+        metastore
+            .stage_split(index_id, split_metadata)
+            .await
+            .unwrap();
+        let lease_holder = SourceShardLeaseHolder {
+            source_id: "kafka-source".to_string(),
+            shard_id: 0,
+            owner: "indexer-a".to_string(),
+        };
+        let result = metastore
+            .publish_splits_with_lease(
+                index_id,
+                &["my-split"],
+                CheckpointDelta::from(0..10),
+                &lease_holder,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(result, MetastoreError::Forbidden { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_single_file_metastore_split_leases_are_shared_through_the_storage() {
+        let storage: Arc<dyn Storage> = Arc::new(RamStorage::default());
+        let searcher_metastore = SingleFileMetastore::new(storage.clone());
+        let janitor_metastore = SingleFileMetastore::new(storage.clone());
+        let index_id = "my-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(WikipediaIndexConfig::default()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+        janitor_metastore
+            .create_index(index_metadata)
+            .await
+            .unwrap();
+        let lease_duration = Duration::from_secs(60);
+
+        searcher_metastore
+            .acquire_split_lease(
+                index_id,
+                "search-a",
+                &["split-1", "split-2"],
+                lease_duration,
+            )
+            .await
+            .unwrap();
+        searcher_metastore
+            .acquire_split_lease(index_id, "search-b", &["split-2"], lease_duration)
+            .await
+            .unwrap();
+        // The leases live out of the metadata file, which the janitor may have cached.
+        let metadata_set = load_metadata_set(&*storage, index_id).await.unwrap();
+        assert!(metadata_set.splits.is_empty());
+        assert_eq!(
+            janitor_metastore
+                .list_leased_split_ids(index_id)
+                .await
+                .unwrap(),
+            vec!["split-1".to_string(), "split-2".to_string()]
+        );
+
+        searcher_metastore
+            .release_split_lease(index_id, "search-a")
+            .await
+            .unwrap();
+        assert_eq!(
+            janitor_metastore
+                .list_leased_split_ids(index_id)
+                .await
+                .unwrap(),
+            vec!["split-2".to_string()]
+        );
+
+        // Expired leases are ignored.
+        searcher_metastore
+            .acquire_split_lease(index_id, "search-b", &["split-3"], Duration::from_secs(0))
+            .await
+            .unwrap();
+        assert!(janitor_metastore
+            .list_leased_split_ids(index_id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        janitor_metastore.delete_index(index_id).await.unwrap();
+        assert!(!storage.exists(&split_leases_path(index_id)).await.unwrap());
+    }
 }
@@ -0,0 +1,110 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+/// Number of events buffered for a subscriber before the watcher waits for it.
+pub(crate) const SPLIT_EVENTS_BUFFER_SIZE: usize = 1_000;
+
+/// A change of the set of searchable splits of an index.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event")]
+pub enum SplitEvent {
+    /// The split was published and is now searchable.
+    Published {
+        /// ID of the published split.
+        split_id: String,
+    },
+    /// The split was marked for deletion, replaced, deleted or quarantined, and is no longer
+    /// searchable.
+    Deleted {
+        /// ID of the deleted split.
+        split_id: String,
+    },
+}
+
+/// Receives the [`SplitEvent`]s of an index, as returned by
+/// [`Metastore::watch_splits`](crate::Metastore::watch_splits).
+///
+/// Dropping the receiver ends the subscription. The receiver is closed if the metastore stops
+/// being watched, e.g. because the index was deleted or the connection to the metastore was lost.
+pub type SplitEventReceiver = mpsc::Receiver<SplitEvent>;
+
+/// Returns the events turning the published splits `previous_split_ids` into
+/// `current_split_ids`, deletions first.
+pub(crate) fn published_split_events(
+    previous_split_ids: &BTreeSet<String>,
+    current_split_ids: &BTreeSet<String>,
+) -> Vec<SplitEvent> {
+    let deleted_events = previous_split_ids
+        .difference(current_split_ids)
+        .map(|split_id| SplitEvent::Deleted {
+            split_id: split_id.clone(),
+        });
+    let published_events = current_split_ids
+        .difference(previous_split_ids)
+        .map(|split_id| SplitEvent::Published {
+            split_id: split_id.clone(),
+        });
+    deleted_events.chain(published_events).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_set(split_ids: &[&str]) -> BTreeSet<String> {
+        split_ids.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_published_split_events() {
+        assert!(published_split_events(&to_set(&["a", "b"]), &to_set(&["b", "a"])).is_empty());
+        assert_eq!(
+            published_split_events(&to_set(&["a", "b"]), &to_set(&["b", "d", "c"])),
+            vec![
+                SplitEvent::Deleted {
+                    split_id: "a".to_string()
+                },
+                SplitEvent::Published {
+                    split_id: "c".to_string()
+                },
+                SplitEvent::Published {
+                    split_id: "d".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_event_serialization() -> anyhow::Result<()> {
+        let split_event: SplitEvent =
+            serde_json::from_str(r#"{"event": "Published", "split_id": "split-1"}"#)?;
+        assert_eq!(
+            split_event,
+            SplitEvent::Published {
+                split_id: "split-1".to_string()
+            }
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+
+use roaring::RoaringBitmap;
+
+use crate::SplitMetadataAndFooterOffsets;
+
+/// Inverted index of the tags of the splits of an index.
+///
+/// Each tag is mapped to the bitmap of the ordinals of the splits carrying it, so that
+/// the splits matching a tag filter are found without going through the tags of
+/// every split.
+#[derive(Debug, Default)]
+pub(crate) struct TagIndex {
+    split_ids: Vec<String>,
+    tag_bitmaps: HashMap<String, RoaringBitmap>,
+}
+
+impl TagIndex {
+    /// Builds the tag index of `splits`.
+    pub fn build(splits: &HashMap<String, SplitMetadataAndFooterOffsets>) -> Self {
+        let mut tag_index = TagIndex::default();
+        for (split_ord, (split_id, split)) in splits.iter().enumerate() {
+            tag_index.split_ids.push(split_id.clone());
+            for tag in &split.split_metadata.tags {
+                tag_index
+                    .tag_bitmaps
+                    .entry(tag.clone())
+                    .or_default()
+                    .insert(split_ord as u32);
+            }
+        }
+        tag_index
+    }
+
+    /// Returns the IDs of the splits carrying at least one of the `filter_tags`.
+    pub fn matching_split_ids<'a>(
+        &'a self,
+        filter_tags: &[String],
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        let mut matching_split_ords = RoaringBitmap::new();
+        for filter_tag in filter_tags {
+            if let Some(tag_bitmap) = self.tag_bitmaps.get(filter_tag) {
+                matching_split_ords |= tag_bitmap;
+            }
+        }
+        matching_split_ords
+            .into_iter()
+            .map(move |split_ord| self.split_ids[split_ord as usize].as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SplitMetadata;
+
+    fn split(split_id: &str, tags: &[&str]) -> (String, SplitMetadataAndFooterOffsets) {
+        let split = SplitMetadataAndFooterOffsets {
+            split_metadata: SplitMetadata {
+                split_id: split_id.to_string(),
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                ..Default::default()
+            },
+            footer_offsets: 0..1,
+        };
+        (split_id.to_string(), split)
+    }
+
+    #[test]
+    fn test_tag_index() {
+        let splits: HashMap<String, SplitMetadataAndFooterOffsets> = vec![
+            split("split1", &["tenant:foo"]),
+            split("split2", &["tenant:foo", "tenant:bar"]),
+            split("split3", &["tenant:baz"]),
+            split("split4", &[]),
+        ]
+        .into_iter()
+        .collect();
+        let tag_index = TagIndex::build(&splits);
+        let matching_split_ids = |filter_tags: &[&str]| {
+            let filter_tags: Vec<String> = filter_tags.iter().map(|tag| tag.to_string()).collect();
+            let mut split_ids: Vec<&str> = tag_index.matching_split_ids(&filter_tags).collect();
+            split_ids.sort_unstable();
+            split_ids
+        };
+        assert_eq!(
+            matching_split_ids(&["tenant:foo"]),
+            vec!["split1", "split2"]
+        );
+        assert_eq!(
+            matching_split_ids(&["tenant:bar", "tenant:baz"]),
+            vec!["split2", "split3"]
+        );
+        assert!(matching_split_ids(&["tenant:qux"]).is_empty());
+    }
+}
@@ -0,0 +1,821 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Range, RangeInclusive};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use quickwit_index_config::IndexConfig;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::checkpoint::{CheckpointDelta, PartitionId, Position};
+use crate::{
+    IndexMetadata, Metastore, MetastoreResult, SourceShardLease, SourceShardLeaseHolder,
+    SplitEventReceiver, SplitMetadataAndFooterOffsets, SplitState, SplitVerification,
+};
+
+/// This environment variable can be set to a comma-separated list of URLs the index events are
+/// posted to, see [`WebhookMetastore`].
+pub const WEBHOOK_URLS_ENV_KEY: &str = "QUICKWIT_WEBHOOK_URLS";
+
+/// Time after which posting an event to a webhook is considered failed.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum number of events waiting to be posted to a webhook. The events fired while its
+/// queue is full are dropped.
+const WEBHOOK_QUEUE_CAPACITY: usize = 1_000;
+
+/// Statistics of a split, sent along with the events of the splits.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SplitStats {
+    /// ID of the split.
+    pub split_id: String,
+    /// Number of documents in the split.
+    pub num_records: usize,
+    /// Sum of the size of the documents in the split, in bytes.
+    pub size_in_bytes: u64,
+    /// Min and max timestamps of the documents of the split, if the index has a timestamp
+    /// field.
+    pub time_range: Option<RangeInclusive<i64>>,
+}
+
+impl From<&SplitMetadataAndFooterOffsets> for SplitStats {
+    fn from(split: &SplitMetadataAndFooterOffsets) -> Self {
+        SplitStats {
+            split_id: split.split_metadata.split_id.clone(),
+            num_records: split.split_metadata.num_records,
+            size_in_bytes: split.split_metadata.size_in_bytes,
+            time_range: split.split_metadata.time_range.clone(),
+        }
+    }
+}
+
+/// An activity event of an index, fired by a [`WebhookMetastore`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum IndexEvent {
+    /// The index was created.
+    IndexCreated {
+        /// ID of the index.
+        index_id: String,
+        /// URI of the index.
+        index_uri: String,
+    },
+    /// The index was deleted.
+    IndexDeleted {
+        /// ID of the index.
+        index_id: String,
+    },
+    /// Splits were published, e.g. by an indexing pipeline, or by a merge replacing other
+    /// splits.
+    SplitsPublished {
+        /// ID of the index.
+        index_id: String,
+        /// Statistics of the published splits.
+        splits: Vec<SplitStats>,
+        /// IDs of the splits replaced by the published splits, e.g. by a merge.
+        replaced_split_ids: Vec<String>,
+    },
+    /// Splits were deleted, e.g. by the garbage collection.
+    SplitsDeleted {
+        /// ID of the index.
+        index_id: String,
+        /// IDs of the deleted splits.
+        split_ids: Vec<String>,
+    },
+    /// Splits were quarantined, e.g. because their integrity verification detected a
+    /// corruption, and are no longer searched.
+    SplitsQuarantined {
+        /// ID of the index.
+        index_id: String,
+        /// IDs of the quarantined splits.
+        split_ids: Vec<String>,
+    },
+}
+
+/// Receives the events fired by a [`WebhookMetastore`].
+#[async_trait]
+pub trait IndexEventSink: Send + Sync + 'static {
+    /// Sends an event. Failing to send it does not fail the operation that fired it.
+    async fn send_event(&self, event: IndexEvent);
+}
+
+#[async_trait]
+impl IndexEventSink for mpsc::UnboundedSender<IndexEvent> {
+    async fn send_event(&self, event: IndexEvent) {
+        let _ = self.send(event);
+    }
+}
+
+/// Payload posted to the webhooks.
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    /// Unix time in seconds.
+    unixtime: u64,
+    #[serde(flatten)]
+    event: &'a IndexEvent,
+}
+
+/// Posts the events as JSON to a list of URLs.
+///
+/// Each URL is posted to by its own background task, in the order the events were sent, so
+/// that sending an event never waits for the webhooks.
+pub struct Webhooks {
+    event_senders: Vec<(String, mpsc::Sender<(u64, IndexEvent)>)>,
+}
+
+impl Webhooks {
+    /// Creates a sink posting the events to `urls`. It must be called within a Tokio runtime.
+    pub fn new(urls: Vec<String>) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()?;
+        let event_senders = urls
+            .into_iter()
+            .map(|url| {
+                let (event_sender, event_receiver) = mpsc::channel(WEBHOOK_QUEUE_CAPACITY);
+                tokio::spawn(post_events(client.clone(), url.clone(), event_receiver));
+                (url, event_sender)
+            })
+            .collect();
+        Ok(Webhooks { event_senders })
+    }
+}
+
+/// Posts the events of `event_receiver`, with their Unix time, to `url` until the
+/// [`Webhooks`] are dropped.
+async fn post_events(
+    client: reqwest::Client,
+    url: String,
+    mut event_receiver: mpsc::Receiver<(u64, IndexEvent)>,
+) {
+    while let Some((unixtime, event)) = event_receiver.recv().await {
+        let payload = WebhookPayload {
+            unixtime,
+            event: &event,
+        };
+        let post_res = client
+            .post(url.as_str())
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(error) = post_res {
+            warn!(
+                url = %url,
+                event = ?event,
+                error = ?error,
+                "Failed to post event to webhook."
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl IndexEventSink for Webhooks {
+    async fn send_event(&self, event: IndexEvent) {
+        let unixtime = UNIX_EPOCH
+            .elapsed()
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        for (url, event_sender) in &self.event_senders {
+            if let Err(error) = event_sender.try_send((unixtime, event.clone())) {
+                warn!(
+                    url = %url,
+                    event = ?event,
+                    error = %error,
+                    "Failed to queue event for webhook."
+                );
+            }
+        }
+    }
+}
+
+/// Returns the URLs of the `QUICKWIT_WEBHOOK_URLS` environment variable.
+pub(crate) fn webhook_urls_from_env() -> Vec<String> {
+    match std::env::var(WEBHOOK_URLS_ENV_KEY) {
+        Ok(urls_str) => parse_webhook_urls(&urls_str),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn parse_webhook_urls(urls_str: &str) -> Vec<String> {
+    urls_str
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Wraps a metastore, firing an [`IndexEvent`] whenever an index is created or deleted, and
+/// whenever its splits are published or deleted.
+///
+/// The events are sent once the underlying metastore succeeded, so that external systems, e.g.
+/// catalogs or notification systems, can follow the activity of the indexes.
+pub struct WebhookMetastore {
+    underlying: Arc<dyn Metastore>,
+    event_sink: Arc<dyn IndexEventSink>,
+    /// Statistics of the splits staged through this metastore, by index ID and split ID, until
+    /// they are published or deleted.
+    staged_split_stats: Mutex<HashMap<(String, String), SplitStats>>,
+}
+
+impl WebhookMetastore {
+    /// Creates a metastore sending the events of `underlying` to `event_sink`.
+    pub fn new(underlying: Arc<dyn Metastore>, event_sink: Arc<dyn IndexEventSink>) -> Self {
+        WebhookMetastore {
+            underlying,
+            event_sink,
+            staged_split_stats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the statistics of the published splits `split_ids`.
+    ///
+    /// They are built from the metadata of the splits staged through this metastore: the
+    /// published splits are only listed for the splits staged elsewhere.
+    async fn published_split_stats(
+        &self,
+        index_id: &str,
+        split_ids: &[&str],
+    ) -> MetastoreResult<Vec<SplitStats>> {
+        let mut split_stats_opts: Vec<Option<SplitStats>> = {
+            let mut staged_split_stats = self.staged_split_stats.lock().unwrap();
+            split_ids
+                .iter()
+                .map(|split_id| {
+                    staged_split_stats.remove(&(index_id.to_string(), split_id.to_string()))
+                })
+                .collect()
+        };
+        if split_stats_opts.iter().any(Option::is_none) {
+            let published_splits = self
+                .underlying
+                .list_splits(index_id, SplitState::Published, None, &[])
+                .await?;
+            for (split_id, split_stats_opt) in split_ids.iter().zip(split_stats_opts.iter_mut()) {
+                if split_stats_opt.is_none() {
+                    *split_stats_opt = published_splits
+                        .iter()
+                        .find(|split| split.split_metadata.split_id == *split_id)
+                        .map(SplitStats::from);
+                }
+            }
+        }
+        Ok(split_stats_opts.into_iter().flatten().collect())
+    }
+
+    async fn send_splits_published_event(
+        &self,
+        index_id: &str,
+        split_ids: &[&str],
+        replaced_split_ids: &[&str],
+    ) {
+        let splits = match self.published_split_stats(index_id, split_ids).await {
+            Ok(splits) => splits,
+            Err(error) => {
+                warn!(index_id = %index_id, error = ?error, "Failed to list the published splits.");
+                return;
+            }
+        };
+        let event = IndexEvent::SplitsPublished {
+            index_id: index_id.to_string(),
+            splits,
+            replaced_split_ids: to_strings(replaced_split_ids),
+        };
+        self.event_sink.send_event(event).await;
+    }
+}
+
+fn to_strings(split_ids: &[&str]) -> Vec<String> {
+    split_ids.iter().map(ToString::to_string).collect()
+}
+
+#[async_trait]
+impl Metastore for WebhookMetastore {
+    async fn create_index(&self, index_metadata: IndexMetadata) -> MetastoreResult<()> {
+        let event = IndexEvent::IndexCreated {
+            index_id: index_metadata.index_id.clone(),
+            index_uri: index_metadata.index_uri.to_string(),
+        };
+        self.underlying.create_index(index_metadata).await?;
+        self.event_sink.send_event(event).await;
+        Ok(())
+    }
+
+    async fn index_metadata(&self, index_id: &str) -> MetastoreResult<IndexMetadata> {
+        self.underlying.index_metadata(index_id).await
+    }
+
+    async fn update_index_config(
+        &self,
+        index_id: &str,
+        index_config: Arc<dyn IndexConfig>,
+    ) -> MetastoreResult<u64> {
+        self.underlying
+            .update_index_config(index_id, index_config)
+            .await
+    }
+
+    async fn set_checkpoint_positions(
+        &self,
+        index_id: &str,
+        positions: Vec<(PartitionId, Position)>,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .set_checkpoint_positions(index_id, positions)
+            .await
+    }
+
+    async fn update_index_labels(
+        &self,
+        index_id: &str,
+        description: Option<String>,
+        labels: BTreeMap<String, String>,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .update_index_labels(index_id, description, labels)
+            .await
+    }
+
+    async fn delete_index(&self, index_id: &str) -> MetastoreResult<()> {
+        self.underlying.delete_index(index_id).await?;
+        self.staged_split_stats
+            .lock()
+            .unwrap()
+            .retain(|(split_index_id, _), _| split_index_id != index_id);
+        let event = IndexEvent::IndexDeleted {
+            index_id: index_id.to_string(),
+        };
+        self.event_sink.send_event(event).await;
+        Ok(())
+    }
+
+    async fn stage_split(
+        &self,
+        index_id: &str,
+        split_metadata: SplitMetadataAndFooterOffsets,
+    ) -> MetastoreResult<()> {
+        let split_stats = SplitStats::from(&split_metadata);
+        self.underlying
+            .stage_split(index_id, split_metadata)
+            .await?;
+        self.staged_split_stats.lock().unwrap().insert(
+            (index_id.to_string(), split_stats.split_id.clone()),
+            split_stats,
+        );
+        Ok(())
+    }
+
+    async fn publish_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+        checkpoint_delta: CheckpointDelta,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .publish_splits(index_id, split_ids, checkpoint_delta)
+            .await?;
+        self.send_splits_published_event(index_id, split_ids, &[])
+            .await;
+        Ok(())
+    }
+
+    async fn publish_splits_with_lease<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+        checkpoint_delta: CheckpointDelta,
+        lease_holder: &SourceShardLeaseHolder,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .publish_splits_with_lease(index_id, split_ids, checkpoint_delta, lease_holder)
+            .await?;
+        self.send_splits_published_event(index_id, split_ids, &[])
+            .await;
+        Ok(())
+    }
+
+    async fn replace_splits<'a>(
+        &self,
+        index_id: &str,
+        new_split_ids: &[&'a str],
+        replaced_split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .replace_splits(index_id, new_split_ids, replaced_split_ids)
+            .await?;
+        self.send_splits_published_event(index_id, new_split_ids, replaced_split_ids)
+            .await;
+        Ok(())
+    }
+
+    async fn list_splits(
+        &self,
+        index_id: &str,
+        split_state: SplitState,
+        time_range: Option<Range<i64>>,
+        tags: &[String],
+    ) -> MetastoreResult<Vec<SplitMetadataAndFooterOffsets>> {
+        self.underlying
+            .list_splits(index_id, split_state, time_range, tags)
+            .await
+    }
+
+    async fn list_all_splits(
+        &self,
+        index_id: &str,
+    ) -> MetastoreResult<Vec<SplitMetadataAndFooterOffsets>> {
+        self.underlying.list_all_splits(index_id).await
+    }
+
+    async fn mark_splits_for_deletion<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .mark_splits_for_deletion(index_id, split_ids)
+            .await
+    }
+
+    async fn delete_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.underlying.delete_splits(index_id, split_ids).await?;
+        {
+            let mut staged_split_stats = self.staged_split_stats.lock().unwrap();
+            for split_id in split_ids {
+                staged_split_stats.remove(&(index_id.to_string(), split_id.to_string()));
+            }
+        }
+        if !split_ids.is_empty() {
+            let event = IndexEvent::SplitsDeleted {
+                index_id: index_id.to_string(),
+                split_ids: to_strings(split_ids),
+            };
+            self.event_sink.send_event(event).await;
+        }
+        Ok(())
+    }
+
+    async fn quarantine_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .quarantine_splits(index_id, split_ids)
+            .await?;
+        if !split_ids.is_empty() {
+            let event = IndexEvent::SplitsQuarantined {
+                index_id: index_id.to_string(),
+                split_ids: to_strings(split_ids),
+            };
+            self.event_sink.send_event(event).await;
+        }
+        Ok(())
+    }
+
+    async fn unquarantine_splits<'a>(
+        &self,
+        index_id: &str,
+        split_ids: &[&'a str],
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .unquarantine_splits(index_id, split_ids)
+            .await
+    }
+
+    async fn record_split_verification(
+        &self,
+        index_id: &str,
+        split_id: &str,
+        verification: SplitVerification,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .record_split_verification(index_id, split_id, verification)
+            .await
+    }
+
+    async fn acquire_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        num_shards: usize,
+        owner: &str,
+        lease_duration: Duration,
+    ) -> MetastoreResult<Option<usize>> {
+        self.underlying
+            .acquire_source_shard_lease(index_id, source_id, num_shards, owner, lease_duration)
+            .await
+    }
+
+    async fn renew_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        shard_id: usize,
+        owner: &str,
+        lease_duration: Duration,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .renew_source_shard_lease(index_id, source_id, shard_id, owner, lease_duration)
+            .await
+    }
+
+    async fn release_source_shard_lease(
+        &self,
+        index_id: &str,
+        source_id: &str,
+        shard_id: usize,
+        owner: &str,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .release_source_shard_lease(index_id, source_id, shard_id, owner)
+            .await
+    }
+
+    async fn list_source_shard_leases(
+        &self,
+        index_id: &str,
+    ) -> MetastoreResult<Vec<SourceShardLease>> {
+        self.underlying.list_source_shard_leases(index_id).await
+    }
+
+    async fn acquire_split_lease<'a>(
+        &self,
+        index_id: &str,
+        lease_id: &str,
+        split_ids: &[&'a str],
+        lease_duration: Duration,
+    ) -> MetastoreResult<()> {
+        self.underlying
+            .acquire_split_lease(index_id, lease_id, split_ids, lease_duration)
+            .await
+    }
+
+    async fn release_split_lease(&self, index_id: &str, lease_id: &str) -> MetastoreResult<()> {
+        self.underlying
+            .release_split_lease(index_id, lease_id)
+            .await
+    }
+
+    async fn list_leased_split_ids(&self, index_id: &str) -> MetastoreResult<Vec<String>> {
+        self.underlying.list_leased_split_ids(index_id).await
+    }
+
+    async fn refresh_index(&self, index_id: &str) -> MetastoreResult<()> {
+        self.underlying.refresh_index(index_id).await
+    }
+
+    async fn watch_splits(&self, index_id: &str) -> MetastoreResult<SplitEventReceiver> {
+        self.underlying.watch_splits(index_id).await
+    }
+
+    async fn now_timestamp(&self) -> MetastoreResult<i64> {
+        self.underlying.now_timestamp().await
+    }
+
+    fn uri(&self) -> String {
+        self.underlying.uri()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_common::QuickwitUri;
+    use quickwit_index_config::WikipediaIndexConfig;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::checkpoint::Checkpoint;
+    use crate::{MetastoreError, MockMetastore, SplitMetadata};
+
+    fn index_metadata(index_id: &str) -> IndexMetadata {
+        IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed(&format!("ram:///indexes/{}", index_id)),
+            index_config: Arc::new(WikipediaIndexConfig::new()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        }
+    }
+
+    fn split(split_id: &str, num_records: usize) -> SplitMetadataAndFooterOffsets {
+        SplitMetadataAndFooterOffsets {
+            footer_offsets: 700..800,
+            split_metadata: SplitMetadata {
+                split_id: split_id.to_string(),
+                split_state: SplitState::Published,
+                num_records,
+                size_in_bytes: 1_000,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse_webhook_urls() {
+        assert_eq!(
+            parse_webhook_urls("http://catalog:8080/events, https://hooks.example.com/quickwit,"),
+            vec![
+                "http://catalog:8080/events".to_string(),
+                "https://hooks.example.com/quickwit".to_string()
+            ]
+        );
+        assert!(parse_webhook_urls(" ").is_empty());
+    }
+
+    #[test]
+    fn test_webhook_payload_serialization() -> anyhow::Result<()> {
+        let event = IndexEvent::SplitsDeleted {
+            index_id: "my-index".to_string(),
+            split_ids: vec!["split1".to_string()],
+        };
+        let payload = WebhookPayload {
+            unixtime: 1_622_548_800,
+            event: &event,
+        };
+        assert_eq!(
+            serde_json::to_value(&payload)?,
+            serde_json::json!({
+                "unixtime": 1_622_548_800,
+                "event": "splits_deleted",
+                "index_id": "my-index",
+                "split_ids": ["split1"],
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_webhooks_post_events_in_the_background() -> anyhow::Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/events", listener.local_addr()?);
+        let webhooks = Webhooks::new(vec![url])?;
+        // The webhook is not answering yet, sending the event does not wait for it.
+        webhooks
+            .send_event(IndexEvent::IndexDeleted {
+                index_id: "my-index".to_string(),
+            })
+            .await;
+        let (mut stream, _) = listener.accept().await?;
+        let mut request_bytes = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while !String::from_utf8_lossy(&request_bytes).contains("my-index") {
+            let num_bytes = stream.read(&mut buffer).await?;
+            assert!(num_bytes > 0);
+            request_bytes.extend_from_slice(&buffer[..num_bytes]);
+        }
+        let request = String::from_utf8_lossy(&request_bytes);
+        assert!(request.starts_with("POST /events "));
+        assert!(request.contains(r#""event":"index_deleted""#));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_webhook_metastore() -> anyhow::Result<()> {
+        let mut mock_metastore = MockMetastore::new();
+        mock_metastore
+            .expect_stage_split()
+            .times(2)
+            .returning(|_index_id, _split_metadata| Ok(()));
+        mock_metastore
+            .expect_create_index()
+            .times(1)
+            .returning(|_index_metadata| Ok(()));
+        mock_metastore
+            .expect_publish_splits()
+            .times(1)
+            .returning(|_index_id, _split_ids, _checkpoint_delta| Ok(()));
+        mock_metastore
+            .expect_replace_splits()
+            .times(1)
+            .returning(|_index_id, _new_split_ids, _replaced_split_ids| Ok(()));
+        // Only the splits staged elsewhere, here `split3`, are listed to build their statistics.
+        mock_metastore.expect_list_splits().times(1).returning(
+            |_index_id: &str,
+             _split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| {
+                Ok(vec![
+                    split("split1", 10),
+                    split("split2", 20),
+                    split("split3", 30),
+                ])
+            },
+        );
+        mock_metastore
+            .expect_delete_splits()
+            .times(1)
+            .returning(|_index_id, _split_ids| Ok(()));
+        mock_metastore
+            .expect_quarantine_splits()
+            .times(1)
+            .returning(|_index_id, _split_ids| Ok(()));
+        mock_metastore
+            .expect_delete_index()
+            .times(2)
+            .returning(|index_id: &str| {
+                if index_id == "my-index" {
+                    return Ok(());
+                }
+                Err(MetastoreError::IndexDoesNotExist {
+                    index_id: index_id.to_string(),
+                })
+            });
+        let (event_sender, mut event_receiver) = mpsc::unbounded_channel();
+        let metastore = WebhookMetastore::new(Arc::new(mock_metastore), Arc::new(event_sender));
+
+        metastore.create_index(index_metadata("my-index")).await?;
+        metastore
+            .stage_split("my-index", split("split1", 10))
+            .await?;
+        metastore
+            .stage_split("my-index", split("split2", 20))
+            .await?;
+        metastore
+            .publish_splits(
+                "my-index",
+                &["split1", "split2"],
+                CheckpointDelta::default(),
+            )
+            .await?;
+        metastore
+            .replace_splits("my-index", &["split3"], &["split1", "split2"])
+            .await?;
+        metastore.delete_splits("my-index", &["split1"]).await?;
+        metastore.quarantine_splits("my-index", &["split3"]).await?;
+        metastore.delete_index("my-index").await?;
+        assert!(metastore.delete_index("missing-index").await.is_err());
+        drop(metastore);
+
+        let mut events = Vec::new();
+        while let Some(event) = event_receiver.recv().await {
+            events.push(event);
+        }
+        assert_eq!(
+            events,
+            vec![
+                IndexEvent::IndexCreated {
+                    index_id: "my-index".to_string(),
+                    index_uri: "ram:///indexes/my-index".to_string(),
+                },
+                IndexEvent::SplitsPublished {
+                    index_id: "my-index".to_string(),
+                    splits: vec![
+                        SplitStats::from(&split("split1", 10)),
+                        SplitStats::from(&split("split2", 20))
+                    ],
+                    replaced_split_ids: Vec::new(),
+                },
+                IndexEvent::SplitsPublished {
+                    index_id: "my-index".to_string(),
+                    splits: vec![SplitStats::from(&split("split3", 30))],
+                    replaced_split_ids: vec!["split1".to_string(), "split2".to_string()],
+                },
+                IndexEvent::SplitsDeleted {
+                    index_id: "my-index".to_string(),
+                    split_ids: vec!["split1".to_string()],
+                },
+                IndexEvent::SplitsQuarantined {
+                    index_id: "my-index".to_string(),
+                    split_ids: vec!["split3".to_string()],
+                },
+                IndexEvent::IndexDeleted {
+                    index_id: "my-index".to_string(),
+                },
+            ]
+        );
+        Ok(())
+    }
+}
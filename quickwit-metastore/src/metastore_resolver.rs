@@ -18,21 +18,29 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use quickwit_common::QuickwitUri;
+use tracing::error;
 
 #[cfg(feature = "postgres")]
 use crate::metastore::postgresql_metastore::PostgresqlMetastoreFactory;
+use crate::metastore::ram_metastore::RamMetastoreFactory;
 use crate::metastore::single_file_metastore::SingleFileMetastoreFactory;
-use crate::{Metastore, MetastoreResolverError};
+use crate::metastore::webhook_metastore::webhook_urls_from_env;
+use crate::{Metastore, MetastoreResolverError, WebhookMetastore, Webhooks, WEBHOOK_URLS_ENV_KEY};
 
 /// A metastore factory builds a [`Metastore`] object from an URI.
 #[cfg_attr(any(test, feature = "testsuite"), mockall::automock)]
 #[async_trait]
 pub trait MetastoreFactory: Send + Sync + 'static {
     /// Given an URI, returns a [`Metastore`] object.
-    async fn resolve(&self, uri: &str) -> Result<Arc<dyn Metastore>, MetastoreResolverError>;
+    async fn resolve(
+        &self,
+        uri: &QuickwitUri,
+    ) -> Result<Arc<dyn Metastore>, MetastoreResolverError>;
 }
 
 #[derive(Default)]
@@ -64,10 +72,11 @@ impl Default for MetastoreUriResolver {
     fn default() -> Self {
         #[allow(unused_mut)]
         let mut builder = MetastoreUriResolver::builder()
-            .register("ram", SingleFileMetastoreFactory::default())
+            .register("ram", RamMetastoreFactory::default())
             .register("file", SingleFileMetastoreFactory::default())
             .register("s3", SingleFileMetastoreFactory::default())
-            .register("s3+localstack", SingleFileMetastoreFactory::default());
+            .register("s3+localstack", SingleFileMetastoreFactory::default())
+            .register("azure", SingleFileMetastoreFactory::default());
         #[cfg(feature = "postgres")]
         {
             builder = builder.register("postgres", PostgresqlMetastoreFactory::default());
@@ -84,27 +93,46 @@ impl MetastoreUriResolver {
     }
 
     /// Resolves the given URI.
+    ///
+    /// The URI is normalized first, see [`QuickwitUri`]. If the `QUICKWIT_WEBHOOK_URLS`
+    /// environment variable is set, the metastore posts its events to the webhooks, see
+    /// [`WebhookMetastore`].
     pub async fn resolve(&self, uri: &str) -> Result<Arc<dyn Metastore>, MetastoreResolverError> {
-        let protocol = uri.split("://").next().ok_or_else(|| {
-            MetastoreResolverError::InvalidUri(format!(
-                "Protocol not found in metastore URI: {}",
-                uri
-            ))
-        })?;
+        let uri = QuickwitUri::from_str(uri)
+            .map_err(|error| MetastoreResolverError::InvalidUri(error.to_string()))?;
 
         let resolver = self
             .per_protocol_resolver
-            .get(protocol)
-            .ok_or_else(|| MetastoreResolverError::ProtocolUnsupported(protocol.to_string()))?;
-
-        let metastore = resolver.resolve(uri).await?;
-        Ok(metastore)
+            .get(uri.protocol())
+            .ok_or_else(|| {
+                MetastoreResolverError::ProtocolUnsupported(uri.protocol().to_string())
+            })?;
+
+        let metastore = resolver.resolve(&uri).await?;
+        let webhook_urls = webhook_urls_from_env();
+        if webhook_urls.is_empty() {
+            return Ok(metastore);
+        }
+        match Webhooks::new(webhook_urls) {
+            Ok(webhooks) => Ok(Arc::new(WebhookMetastore::new(
+                metastore,
+                Arc::new(webhooks),
+            ))),
+            Err(error) => {
+                error!(
+                    error = ?error,
+                    "Failed to create the webhooks of `{}`.",
+                    WEBHOOK_URLS_ENV_KEY
+                );
+                Ok(metastore)
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::MetastoreUriResolver;
+    use crate::{MetastoreResolverError, MetastoreUriResolver};
 
     #[tokio::test]
     async fn test_metastore_resolver_should_not_raise_errors_on_file_and_s3() -> anyhow::Result<()>
@@ -117,6 +145,19 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_metastore_resolver_should_raise_error_on_invalid_uri() {
+        let metastore_resolver = MetastoreUriResolver::default();
+        let error = metastore_resolver
+            .resolve("s3:/bucket/path/to/object")
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(error, MetastoreResolverError::InvalidUri(message) if message.contains("expected `://`"))
+        );
+    }
+
     #[tokio::test]
     #[should_panic(expected = "ProtocolUnsupported(\"s4\")")]
     async fn test_metastore_resolver_should_raise_error_on_storage_error() {
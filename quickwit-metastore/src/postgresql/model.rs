@@ -20,7 +20,7 @@
 use std::ops::RangeInclusive;
 use std::str::FromStr;
 
-use crate::postgresql::schema::{indexes, splits};
+use crate::postgresql::schema::{indexes, source_shard_leases, split_leases, splits};
 use crate::{IndexMetadata, SplitMetadataAndFooterOffsets, SplitState};
 
 /// A model structure for handling index metadata in a database.
@@ -94,3 +94,48 @@ impl Split {
         Ok(split_metadata_and_fotter_offsets)
     }
 }
+
+/// A model structure for handling the lease of a shard of a source in a database.
+#[derive(Insertable, Queryable, Debug)]
+#[table_name = "source_shard_leases"]
+pub struct SourceShardLease {
+    /// Index ID. It is used as a foreign key in the database.
+    pub index_id: String,
+    /// ID of the source the shard belongs to.
+    pub source_id: String,
+    /// ID of the shard.
+    pub shard_id: i32,
+    /// Number of shards of the source.
+    pub num_shards: i32,
+    /// Owner of the lease.
+    pub owner: String,
+    /// Timestamp at which the lease expires unless renewed.
+    pub expiration_timestamp: i64,
+}
+
+impl SourceShardLease {
+    /// Make SourceShardLease from the database model.
+    pub fn make_source_shard_lease(&self) -> crate::SourceShardLease {
+        crate::SourceShardLease {
+            source_id: self.source_id.clone(),
+            shard_id: self.shard_id as usize,
+            num_shards: self.num_shards as usize,
+            owner: self.owner.clone(),
+            expiration_timestamp: self.expiration_timestamp,
+        }
+    }
+}
+
+/// A model structure for handling the lease of a split by a search in a database.
+#[derive(Insertable, Queryable, Debug)]
+#[table_name = "split_leases"]
+pub struct SplitLease {
+    /// Index ID. It is used as a foreign key in the database.
+    pub index_id: String,
+    /// ID of the lease, identifying the search holding it.
+    pub lease_id: String,
+    /// ID of the leased split.
+    pub split_id: String,
+    /// Timestamp at which the lease expires unless released before.
+    pub expiration_timestamp: i64,
+}
@@ -36,6 +36,28 @@ table! {
     }
 }
 
+table! {
+    source_shard_leases (index_id, source_id, shard_id) {
+        index_id -> Varchar,
+        source_id -> Varchar,
+        shard_id -> Int4,
+        num_shards -> Int4,
+        owner -> Varchar,
+        expiration_timestamp -> Int8,
+    }
+}
+
+table! {
+    split_leases (index_id, lease_id, split_id) {
+        index_id -> Varchar,
+        lease_id -> Varchar,
+        split_id -> Varchar,
+        expiration_timestamp -> Int8,
+    }
+}
+
+joinable!(source_shard_leases -> indexes (index_id));
+joinable!(split_leases -> indexes (index_id));
 joinable!(splits -> indexes (index_id));
 
-allow_tables_to_appear_in_same_query!(indexes, splits,);
+allow_tables_to_appear_in_same_query!(indexes, source_shard_leases, split_leases, splits,);
@@ -0,0 +1,114 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::future::Future;
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::MetastoreResult;
+
+const MAX_NUM_ATTEMPTS: usize = 5;
+const BASE_DELAY: Duration = Duration::from_millis(if cfg!(test) { 1 } else { 200 });
+const MAX_DELAY: Duration = Duration::from_millis(if cfg!(test) { 1 } else { 5_000 });
+
+/// Runs the metastore operation `f` until it succeeds or fails with an error that is not
+/// transient, at most 5 times, doubling the delay between two attempts.
+///
+/// An operation failing with a transient error may still have been applied, so only idempotent
+/// operations, e.g. reads, should be retried.
+pub async fn retry_on_transient_error<T, F, Fut>(f: F) -> MetastoreResult<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = MetastoreResult<T>>,
+{
+    let mut delay = BASE_DELAY;
+    let mut num_attempts = 0;
+    loop {
+        num_attempts += 1;
+        match f().await {
+            Err(error) if error.is_transient() && num_attempts < MAX_NUM_ATTEMPTS => {
+                warn!(
+                    num_attempts = num_attempts,
+                    delay_ms = delay.as_millis() as u64,
+                    error = %error,
+                    "Metastore operation failed, retrying."
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::MetastoreError;
+
+    async fn simulate_retries(errors: Vec<MetastoreError>) -> (MetastoreResult<()>, usize) {
+        let num_calls = AtomicUsize::new(0);
+        let errors = Mutex::new(errors.into_iter());
+        let result = retry_on_transient_error(|| {
+            num_calls.fetch_add(1, Ordering::SeqCst);
+            let result = errors.lock().unwrap().next().map_or(Ok(()), Err);
+            async move { result }
+        })
+        .await;
+        (result, num_calls.load(Ordering::SeqCst))
+    }
+
+    fn connection_error() -> MetastoreError {
+        MetastoreError::ConnectionError {
+            message: "Connection refused.".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_transient_error() {
+        let (result, num_calls) = simulate_retries(vec![connection_error()]).await;
+        assert!(result.is_ok());
+        assert_eq!(num_calls, 2);
+
+        let (result, num_calls) = simulate_retries(vec![
+            MetastoreError::IndexDoesNotExist {
+                index_id: "test-index".to_string(),
+            },
+            connection_error(),
+        ])
+        .await;
+        assert!(matches!(
+            result,
+            Err(MetastoreError::IndexDoesNotExist { .. })
+        ));
+        assert_eq!(num_calls, 1);
+
+        let (result, num_calls) =
+            simulate_retries((0..MAX_NUM_ATTEMPTS).map(|_| connection_error()).collect()).await;
+        assert!(matches!(
+            result,
+            Err(MetastoreError::ConnectionError { .. })
+        ));
+        assert_eq!(num_calls, MAX_NUM_ATTEMPTS);
+    }
+}
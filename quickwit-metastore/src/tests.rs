@@ -19,18 +19,21 @@
 
 #[cfg(test)]
 pub mod test_suite {
-    use std::collections::HashSet;
+    use std::collections::{BTreeMap, HashSet};
     use std::ops::{Range, RangeInclusive};
     use std::sync::Arc;
 
     use async_trait::async_trait;
     use chrono::Utc;
-    use tokio::time::{sleep, Duration};
+    use quickwit_common::QuickwitUri;
+    use tokio::time::{sleep, timeout, Duration};
 
-    use crate::checkpoint::{Checkpoint, CheckpointDelta};
+    use crate::checkpoint::{Checkpoint, CheckpointDelta, PartitionId, Position};
+    #[cfg(feature = "postgres")]
+    use crate::SourceShardLeaseHolder;
     use crate::{
-        IndexMetadata, Metastore, MetastoreError, SplitMetadata, SplitMetadataAndFooterOffsets,
-        SplitState,
+        IndexMetadata, Metastore, MetastoreError, SplitEvent, SplitEventReceiver, SplitMetadata,
+        SplitMetadataAndFooterOffsets, SplitState, SplitVerification,
     };
 
     #[async_trait]
@@ -76,9 +79,15 @@ pub mod test_suite {
         let index_id = "create-index-index";
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         // Create an index
@@ -97,9 +106,15 @@ pub mod test_suite {
         let index_id = "delte-index-index";
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         // Delete a non-existent index
@@ -126,9 +141,15 @@ pub mod test_suite {
         let index_id = "index-metadata-index";
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         // Get a non-existent index metadata
@@ -150,6 +171,206 @@ pub mod test_suite {
         cleanup_index(&metastore, index_id).await;
     }
 
+    pub async fn test_metastore_update_index_config<MetastoreToTest: Metastore + DefaultForTest>() {
+        let metastore = MetastoreToTest::default_for_test().await;
+
+        let index_id = "update-index-config-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+
+        // Update the config of a non-existent index
+        let result = metastore
+            .update_index_config(
+                "non-existent-index",
+                Arc::new(quickwit_index_config::WikipediaIndexConfig::new()),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(result, MetastoreError::IndexDoesNotExist { .. }));
+
+        metastore
+            .create_index(index_metadata.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            metastore
+                .index_metadata(index_id)
+                .await
+                .unwrap()
+                .index_config_version(),
+            0
+        );
+
+        // Update the index config
+        let new_index_config: Arc<dyn quickwit_index_config::IndexConfig> =
+            Arc::new(quickwit_index_config::WikipediaIndexConfig::new());
+        let version = metastore
+            .update_index_config(index_id, new_index_config.clone())
+            .await
+            .unwrap();
+        assert_eq!(version, 1);
+
+        let result = metastore.index_metadata(index_id).await.unwrap();
+        assert_eq!(result.index_config_version(), 1);
+        assert_eq!(
+            format!("{:?}", result.index_config),
+            format!("{:?}", new_index_config)
+        );
+        assert_eq!(
+            format!("{:?}", result.index_config_for_version(0).unwrap()),
+            format!("{:?}", index_metadata.index_config)
+        );
+        assert!(result.index_config_for_version(2).is_none());
+        assert_eq!(
+            result.index_config_fingerprint(0),
+            index_metadata.index_config_fingerprint(0)
+        );
+        assert_ne!(
+            result.index_config_fingerprint(0),
+            result.index_config_fingerprint(1)
+        );
+        assert!(result.index_config_fingerprint(2).is_none());
+
+        // The config a split was built with can be retrieved from its metadata.
+        let split_metadata = SplitMetadata {
+            split_id: "split-built-with-first-config".to_string(),
+            index_config_version: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            format!(
+                "{:?}",
+                result.index_config_for_split(&split_metadata).unwrap()
+            ),
+            format!("{:?}", index_metadata.index_config)
+        );
+
+        cleanup_index(&metastore, index_id).await;
+    }
+
+    pub async fn test_metastore_set_checkpoint_positions<
+        MetastoreToTest: Metastore + DefaultForTest,
+    >() {
+        let metastore = MetastoreToTest::default_for_test().await;
+
+        let index_id = "set-checkpoint-positions-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+
+        // Set the positions of a non-existent index
+        let result = metastore
+            .set_checkpoint_positions("non-existent-index", Vec::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(result, MetastoreError::IndexDoesNotExist { .. }));
+
+        metastore
+            .create_index(index_metadata.clone())
+            .await
+            .unwrap();
+        metastore
+            .publish_splits(index_id, &[], CheckpointDelta::from(0..10))
+            .await
+            .unwrap();
+
+        // Rewind the source
+        metastore
+            .set_checkpoint_positions(
+                index_id,
+                vec![(PartitionId::default(), Position::from(4u64))],
+            )
+            .await
+            .unwrap();
+        let result = metastore.index_metadata(index_id).await.unwrap();
+        assert_eq!(
+            result
+                .checkpoint
+                .position_for_partition(&PartitionId::default()),
+            Some(&Position::from(4u64))
+        );
+
+        // The messages after the new position can be indexed again.
+        metastore
+            .publish_splits(index_id, &[], CheckpointDelta::from(5..8))
+            .await
+            .unwrap();
+        let result = metastore.index_metadata(index_id).await.unwrap();
+        assert_eq!(
+            result
+                .checkpoint
+                .position_for_partition(&PartitionId::default()),
+            Some(&Position::from(7u64))
+        );
+
+        cleanup_index(&metastore, index_id).await;
+    }
+
+    pub async fn test_metastore_update_index_labels<MetastoreToTest: Metastore + DefaultForTest>() {
+        let metastore = MetastoreToTest::default_for_test().await;
+
+        let index_id = "update-index-labels-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: Some("My index".to_string()),
+            labels: BTreeMap::new(),
+            create_timestamp: 0,
+        };
+
+        // Update the labels of a non-existent index
+        let result = metastore
+            .update_index_labels("non-existent-index", None, BTreeMap::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(result, MetastoreError::IndexDoesNotExist { .. }));
+
+        metastore
+            .create_index(index_metadata.clone())
+            .await
+            .unwrap();
+        let result = metastore.index_metadata(index_id).await.unwrap();
+        assert_eq!(result.description.as_deref(), Some("My index"));
+        assert!(result.labels.is_empty());
+
+        let mut labels = BTreeMap::new();
+        labels.insert("team".to_string(), "search".to_string());
+        labels.insert("env".to_string(), "prod".to_string());
+        metastore
+            .update_index_labels(index_id, None, labels.clone())
+            .await
+            .unwrap();
+        let result = metastore.index_metadata(index_id).await.unwrap();
+        assert_eq!(result.description, None);
+        assert_eq!(result.labels, labels);
+
+        cleanup_index(&metastore, index_id).await;
+    }
+
     pub async fn test_metastore_stage_split<MetastoreToTest: Metastore + DefaultForTest>() {
         let metastore = MetastoreToTest::default_for_test().await;
 
@@ -158,9 +379,15 @@ pub mod test_suite {
         let index_id = "stage-split-index";
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         let split_id = "stage-split-my-index-one";
@@ -196,7 +423,29 @@ pub mod test_suite {
             .unwrap();
         assert!(matches!(result, ()));
 
-        // Stage a existent-split on an index
+        // Stage the same split again on an index
+        metastore
+            .stage_split(index_id, split_metadata.clone())
+            .await
+            .unwrap();
+        assert_eq!(metastore.list_all_splits(index_id).await.unwrap().len(), 1);
+
+        // Stage a rebuilt split with the ID of a staged split on an index
+        let mut other_split_metadata = split_metadata.clone();
+        other_split_metadata.split_metadata.num_records = 10;
+        metastore
+            .stage_split(index_id, other_split_metadata.clone())
+            .await
+            .unwrap();
+        let splits = metastore.list_all_splits(index_id).await.unwrap();
+        assert_eq!(splits.len(), 1);
+        assert_eq!(splits[0].split_metadata.num_records, 10);
+
+        // Stage a published split on an index
+        metastore
+            .publish_splits(index_id, &[split_id], CheckpointDelta::default())
+            .await
+            .unwrap();
         let result = metastore
             .stage_split(index_id, split_metadata.clone())
             .await
@@ -214,9 +463,15 @@ pub mod test_suite {
         let index_id = "publish-splits-index";
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         let split_id_1 = "publish-splits-index-one";
@@ -313,14 +568,25 @@ pub mod test_suite {
                 .await
                 .unwrap();
 
+            metastore
+                .stage_split(index_id, split_metadata_2.clone())
+                .await
+                .unwrap();
+
             metastore
                 .publish_splits(index_id, &[split_id_1], CheckpointDelta::from(1..12))
                 .await
                 .unwrap();
 
-            let publish_error = metastore
+            // Retrying the publication is a no-op.
+            metastore
                 .publish_splits(index_id, &[split_id_1], CheckpointDelta::from(1..12))
                 .await
+                .unwrap();
+
+            let publish_error = metastore
+                .publish_splits(index_id, &[split_id_2], CheckpointDelta::from(1..12))
+                .await
                 .unwrap_err();
             assert!(matches!(
                 publish_error,
@@ -543,18 +809,19 @@ pub mod test_suite {
                 .await
                 .unwrap();
 
-            let publish_error = metastore
+            // Retrying the publication is a no-op.
+            metastore
                 .publish_splits(
                     index_id,
                     &[split_id_1, split_id_2],
                     CheckpointDelta::from(30..31),
                 )
                 .await
-                .unwrap_err();
-            assert!(matches!(
-                publish_error,
-                MetastoreError::IncompatibleCheckpointDelta(_)
-            ));
+                .unwrap();
+            let index_metadata = metastore.index_metadata(index_id).await.unwrap();
+            assert!(index_metadata
+                .checkpoint
+                .covers(&CheckpointDelta::from(30..31)));
 
             cleanup_index(&metastore, index_id).await;
         }
@@ -568,9 +835,15 @@ pub mod test_suite {
         let index_id = "replace_splits-index";
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         let split_id_1 = "replace_splits-index-one";
@@ -792,9 +1065,15 @@ pub mod test_suite {
         let index_id = "mark-splits-as-deleted-my-index";
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         let split_id_1 = "mark-splits-as-deleted-my-index-one";
@@ -866,9 +1145,15 @@ pub mod test_suite {
         let index_id = "delete-splits-index";
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         let split_id_1 = "delete-splits-index-one";
@@ -992,9 +1277,15 @@ pub mod test_suite {
         let index_id = "list-all-splits-index";
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         let split_id_1 = "list-all-splits-index-one";
@@ -1127,9 +1418,15 @@ pub mod test_suite {
         let index_id = "list-splits-index";
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         let split_id_1 = "list-splits-one";
@@ -1143,6 +1440,11 @@ pub mod test_suite {
                 time_range: Some(RangeInclusive::new(0, 99)),
                 update_timestamp: current_timestamp,
                 tags: to_set(&["foo", "bar"]),
+                index_config_version: 0,
+                split_file_path: None,
+                ingest_timestamps: None,
+                last_verification: None,
+                split_part_num_bytes: None,
             },
         };
 
@@ -1156,6 +1458,11 @@ pub mod test_suite {
                 time_range: Some(RangeInclusive::new(100, 199)),
                 update_timestamp: current_timestamp,
                 tags: to_set(&["bar"]),
+                index_config_version: 0,
+                split_file_path: None,
+                ingest_timestamps: None,
+                last_verification: None,
+                split_part_num_bytes: None,
             },
         };
 
@@ -1169,6 +1476,11 @@ pub mod test_suite {
                 time_range: Some(RangeInclusive::new(200, 299)),
                 update_timestamp: current_timestamp,
                 tags: to_set(&["foo", "baz"]),
+                index_config_version: 0,
+                split_file_path: None,
+                ingest_timestamps: None,
+                last_verification: None,
+                split_part_num_bytes: None,
             },
         };
 
@@ -1182,6 +1494,11 @@ pub mod test_suite {
                 time_range: Some(RangeInclusive::new(300, 399)),
                 update_timestamp: current_timestamp,
                 tags: to_set(&["foo"]),
+                index_config_version: 0,
+                split_file_path: None,
+                ingest_timestamps: None,
+                last_verification: None,
+                split_part_num_bytes: None,
             },
         };
 
@@ -1195,6 +1512,11 @@ pub mod test_suite {
                 time_range: None,
                 update_timestamp: current_timestamp,
                 tags: to_set(&["baz", "biz"]),
+                index_config_version: 0,
+                split_file_path: None,
+                ingest_timestamps: None,
+                last_verification: None,
+                split_part_num_bytes: None,
             },
         };
 
@@ -1656,9 +1978,15 @@ pub mod test_suite {
         let index_id = "split-update-timestamp-index";
         let index_metadata = IndexMetadata {
             index_id: index_id.to_string(),
-            index_uri: "ram://indexes/my-index".to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
             index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
             checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
         };
 
         let split_id = "split-update-timestamp-one";
@@ -1720,85 +2048,1010 @@ pub mod test_suite {
 
         cleanup_index(&metastore, index_id).await;
     }
-}
 
-macro_rules! metastore_test_suite {
-    ($metastore_type:ty) => {
-        #[cfg(test)]
-        mod common_tests {
-            #[tokio::test]
-            async fn test_metastore_create_index() {
-                crate::tests::test_suite::test_metastore_create_index::<$metastore_type>().await;
-            }
+    pub async fn test_metastore_now_timestamp<MetastoreToTest: Metastore + DefaultForTest>() {
+        let metastore = MetastoreToTest::default_for_test().await;
 
-            #[tokio::test]
-            async fn test_metastore_delete_index() {
-                crate::tests::test_suite::test_metastore_delete_index::<$metastore_type>().await;
-            }
+        let index_id = "now-timestamp-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+        metastore.create_index(index_metadata).await.unwrap();
 
-            #[tokio::test]
-            async fn test_metastore_index_metadata() {
-                crate::tests::test_suite::test_metastore_index_metadata::<$metastore_type>().await;
-            }
+        // The timestamp set by a client whose clock is an hour ahead is overwritten.
+        let split_id = "now-timestamp-one";
+        let split_metadata = SplitMetadataAndFooterOffsets {
+            footer_offsets: 1000..2000,
+            split_metadata: SplitMetadata {
+                split_id: split_id.to_string(),
+                split_state: SplitState::Staged,
+                num_records: 1,
+                size_in_bytes: 2,
+                update_timestamp: Utc::now().timestamp() + 3_600,
+                ..Default::default()
+            },
+        };
+        let before_timestamp = metastore.now_timestamp().await.unwrap();
+        metastore
+            .stage_split(index_id, split_metadata)
+            .await
+            .unwrap();
+        let after_timestamp = metastore.now_timestamp().await.unwrap();
+        assert!(before_timestamp <= after_timestamp);
 
-            #[tokio::test]
-            async fn test_metastore_stage_split() {
-                crate::tests::test_suite::test_metastore_stage_split::<$metastore_type>().await;
-            }
+        let split_meta = metastore.list_all_splits(index_id).await.unwrap()[0]
+            .clone()
+            .split_metadata;
+        assert!(split_meta.update_timestamp >= before_timestamp);
+        assert!(split_meta.update_timestamp <= after_timestamp);
 
-            #[tokio::test]
-            async fn test_metastore_publish_splits() {
-                crate::tests::test_suite::test_metastore_publish_splits::<$metastore_type>().await;
-            }
+        cleanup_index(&metastore, index_id).await;
+    }
 
-            #[tokio::test]
-            async fn test_metastore_replace_splits() {
-                crate::tests::test_suite::test_metastore_replace_splits::<$metastore_type>().await;
-            }
+    pub async fn test_metastore_record_split_verification<
+        MetastoreToTest: Metastore + DefaultForTest,
+    >() {
+        let metastore = MetastoreToTest::default_for_test().await;
 
-            #[tokio::test]
-            async fn test_metastore_mark_splits_for_deletion() {
-                crate::tests::test_suite::test_metastore_mark_splits_for_deletion::<$metastore_type>(
-                )
-                .await;
-            }
+        let index_id = "record-split-verification-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
 
-            #[tokio::test]
-            async fn test_metastore_delete_splits() {
-                crate::tests::test_suite::test_metastore_delete_splits::<$metastore_type>().await;
-            }
+        let split_id = "record-split-verification-one";
+        let split_metadata = SplitMetadataAndFooterOffsets {
+            footer_offsets: 1000..2000,
+            split_metadata: SplitMetadata {
+                split_id: split_id.to_string(),
+                split_state: SplitState::Staged,
+                num_records: 1,
+                size_in_bytes: 2,
+                time_range: Some(RangeInclusive::new(0, 99)),
+                update_timestamp: Utc::now().timestamp(),
+                ..Default::default()
+            },
+        };
+        let verification = SplitVerification {
+            verification_timestamp: 1_600_000_000,
+            corruption: Some("The checksum does not match.".to_string()),
+        };
 
-            #[tokio::test]
-            async fn test_metastore_list_all_splits() {
-                crate::tests::test_suite::test_metastore_list_all_splits::<$metastore_type>().await;
-            }
+        // Record a verification on a non-existent index
+        {
+            let result = metastore
+                .record_split_verification("non-existent-index", split_id, verification.clone())
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::IndexDoesNotExist { .. }));
+        }
 
-            #[tokio::test]
-            async fn test_metastore_list_splits() {
-                crate::tests::test_suite::test_metastore_list_splits::<$metastore_type>().await;
-            }
+        metastore
+            .create_index(index_metadata.clone())
+            .await
+            .unwrap();
 
-            #[tokio::test]
-            async fn test_metastore_split_update_timestamp() {
-                crate::tests::test_suite::test_metastore_split_update_timestamp::<$metastore_type>(
-                )
-                .await;
-            }
+        // Record a verification on a non-existent split
+        {
+            let result = metastore
+                .record_split_verification(index_id, split_id, verification.clone())
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::SplitDoesNotExist { .. }));
         }
-    };
-}
 
-#[cfg(feature = "postgres")]
-macro_rules! metastore_test_suite_for_postgresql {
-    ($metastore_type:ty) => {
-        #[cfg(test)]
-        mod common_tests {
-            #[tokio::test]
-            async fn test_metastore_create_index() {
-                crate::tests::test_suite::test_metastore_create_index::<$metastore_type>().await;
-            }
+        metastore
+            .stage_split(index_id, split_metadata.clone())
+            .await
+            .unwrap();
+        metastore
+            .publish_splits(index_id, &[split_id], CheckpointDelta::from(0..5))
+            .await
+            .unwrap();
+        let published_split_meta = metastore.list_all_splits(index_id).await.unwrap()[0]
+            .clone()
+            .split_metadata;
+        assert_eq!(published_split_meta.last_verification, None);
 
-            #[tokio::test]
+        // Record a verification and check that only the verification changed
+        metastore
+            .record_split_verification(index_id, split_id, verification.clone())
+            .await
+            .unwrap();
+        let verified_split_meta = metastore.list_all_splits(index_id).await.unwrap()[0]
+            .clone()
+            .split_metadata;
+        assert_eq!(verified_split_meta.last_verification, Some(verification));
+        assert_eq!(verified_split_meta.split_state, SplitState::Published);
+        assert_eq!(
+            verified_split_meta.update_timestamp,
+            published_split_meta.update_timestamp
+        );
+
+        cleanup_index(&metastore, index_id).await;
+    }
+
+    pub async fn test_metastore_quarantine_splits<MetastoreToTest: Metastore + DefaultForTest>() {
+        let metastore = MetastoreToTest::default_for_test().await;
+
+        let index_id = "quarantine-splits-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+
+        let split_id_1 = "quarantine-splits-one";
+        let split_metadata_1 = SplitMetadataAndFooterOffsets {
+            footer_offsets: 1000..2000,
+            split_metadata: SplitMetadata {
+                split_id: split_id_1.to_string(),
+                split_state: SplitState::Staged,
+                num_records: 1,
+                size_in_bytes: 2,
+                time_range: Some(RangeInclusive::new(0, 99)),
+                update_timestamp: Utc::now().timestamp(),
+                ..Default::default()
+            },
+        };
+        let split_id_2 = "quarantine-splits-two";
+        let split_metadata_2 = SplitMetadataAndFooterOffsets {
+            footer_offsets: 1000..2000,
+            split_metadata: SplitMetadata {
+                split_id: split_id_2.to_string(),
+                split_state: SplitState::Staged,
+                num_records: 1,
+                size_in_bytes: 2,
+                time_range: Some(RangeInclusive::new(100, 199)),
+                update_timestamp: Utc::now().timestamp(),
+                ..Default::default()
+            },
+        };
+
+        // Quarantine a split on a non-existent index
+        {
+            let result = metastore
+                .quarantine_splits("non-existent-index", &[split_id_1])
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::IndexDoesNotExist { .. }));
+        }
+
+        metastore
+            .create_index(index_metadata.clone())
+            .await
+            .unwrap();
+
+        // Quarantine a non-existent split
+        {
+            let result = metastore
+                .quarantine_splits(index_id, &[split_id_1])
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::SplitDoesNotExist { .. }));
+        }
+
+        metastore
+            .stage_split(index_id, split_metadata_1.clone())
+            .await
+            .unwrap();
+        metastore
+            .stage_split(index_id, split_metadata_2.clone())
+            .await
+            .unwrap();
+
+        // Quarantine a staged split
+        {
+            let result = metastore
+                .quarantine_splits(index_id, &[split_id_1])
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::Forbidden { .. }));
+        }
+
+        metastore
+            .publish_splits(
+                index_id,
+                &[split_id_1, split_id_2],
+                CheckpointDelta::from(0..5),
+            )
+            .await
+            .unwrap();
+
+        // Quarantine a published split, then quarantine it again
+        for _ in 0..2 {
+            metastore
+                .quarantine_splits(index_id, &[split_id_1])
+                .await
+                .unwrap();
+            let quarantined_splits = metastore
+                .list_splits(index_id, SplitState::Quarantined, None, &[])
+                .await
+                .unwrap();
+            assert_eq!(quarantined_splits.len(), 1);
+            assert_eq!(quarantined_splits[0].split_metadata.split_id, split_id_1);
+            let published_splits = metastore
+                .list_splits(index_id, SplitState::Published, None, &[])
+                .await
+                .unwrap();
+            assert_eq!(published_splits.len(), 1);
+            assert_eq!(published_splits[0].split_metadata.split_id, split_id_2);
+        }
+
+        // Release the quarantined split, along with a published split
+        metastore
+            .unquarantine_splits(index_id, &[split_id_1, split_id_2])
+            .await
+            .unwrap();
+        assert!(metastore
+            .list_splits(index_id, SplitState::Quarantined, None, &[])
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            metastore
+                .list_splits(index_id, SplitState::Published, None, &[])
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // Mark a quarantined split for deletion
+        metastore
+            .quarantine_splits(index_id, &[split_id_2])
+            .await
+            .unwrap();
+        metastore
+            .mark_splits_for_deletion(index_id, &[split_id_2])
+            .await
+            .unwrap();
+        let deleted_splits = metastore
+            .list_splits(index_id, SplitState::ScheduledForDeletion, None, &[])
+            .await
+            .unwrap();
+        assert_eq!(deleted_splits.len(), 1);
+        assert_eq!(deleted_splits[0].split_metadata.split_id, split_id_2);
+
+        // Release a split marked for deletion
+        {
+            let result = metastore
+                .unquarantine_splits(index_id, &[split_id_2])
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::Forbidden { .. }));
+        }
+
+        cleanup_index(&metastore, index_id).await;
+    }
+
+    // The file-backed metastores cannot hold leases, see `source_shard_leases_unsupported`.
+    #[cfg(feature = "postgres")]
+    pub async fn test_metastore_source_shard_leases<MetastoreToTest: Metastore + DefaultForTest>() {
+        let metastore = MetastoreToTest::default_for_test().await;
+
+        let index_id = "source-shard-leases-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+        let source_id = "kafka-source";
+        let lease_duration = Duration::from_secs(60);
+
+        // Acquire a lease on a non-existent index
+        {
+            let result = metastore
+                .acquire_source_shard_lease(
+                    "non-existent-index",
+                    source_id,
+                    2,
+                    "indexer-a",
+                    lease_duration,
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::IndexDoesNotExist { .. }));
+        }
+
+        metastore
+            .create_index(index_metadata.clone())
+            .await
+            .unwrap();
+
+        // Acquire the two shards, then fail to acquire a third one
+        {
+            let shard_id_opt = metastore
+                .acquire_source_shard_lease(index_id, source_id, 2, "indexer-a", lease_duration)
+                .await
+                .unwrap();
+            assert_eq!(shard_id_opt, Some(0));
+
+            let shard_id_opt = metastore
+                .acquire_source_shard_lease(index_id, source_id, 2, "indexer-b", lease_duration)
+                .await
+                .unwrap();
+            assert_eq!(shard_id_opt, Some(1));
+
+            let shard_id_opt = metastore
+                .acquire_source_shard_lease(index_id, source_id, 2, "indexer-c", lease_duration)
+                .await
+                .unwrap();
+            assert_eq!(shard_id_opt, None);
+
+            // Acquiring again renews the lease of the owner.
+            let shard_id_opt = metastore
+                .acquire_source_shard_lease(index_id, source_id, 2, "indexer-b", lease_duration)
+                .await
+                .unwrap();
+            assert_eq!(shard_id_opt, Some(1));
+
+            let leases = metastore.list_source_shard_leases(index_id).await.unwrap();
+            assert_eq!(leases.len(), 2);
+            assert_eq!(leases[0].shard_id, 0);
+            assert_eq!(leases[0].owner, "indexer-a");
+            assert_eq!(leases[1].shard_id, 1);
+            assert_eq!(leases[1].owner, "indexer-b");
+        }
+
+        // Acquire a lease with another number of shards
+        {
+            let result = metastore
+                .acquire_source_shard_lease(index_id, source_id, 3, "indexer-c", lease_duration)
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::Forbidden { .. }));
+        }
+
+        // Renew a lease
+        {
+            metastore
+                .renew_source_shard_lease(index_id, source_id, 0, "indexer-a", lease_duration)
+                .await
+                .unwrap();
+
+            let result = metastore
+                .renew_source_shard_lease(index_id, source_id, 0, "indexer-c", lease_duration)
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::Forbidden { .. }));
+        }
+
+        // Release a lease, then acquire the shard with another owner
+        {
+            metastore
+                .release_source_shard_lease(index_id, source_id, 0, "indexer-c")
+                .await
+                .unwrap();
+            let leases = metastore.list_source_shard_leases(index_id).await.unwrap();
+            assert_eq!(leases.len(), 2);
+
+            metastore
+                .release_source_shard_lease(index_id, source_id, 0, "indexer-a")
+                .await
+                .unwrap();
+            let leases = metastore.list_source_shard_leases(index_id).await.unwrap();
+            assert_eq!(leases.len(), 1);
+
+            let shard_id_opt = metastore
+                .acquire_source_shard_lease(index_id, source_id, 2, "indexer-c", lease_duration)
+                .await
+                .unwrap();
+            assert_eq!(shard_id_opt, Some(0));
+        }
+
+        // Take over expired leases
+        {
+            metastore
+                .renew_source_shard_lease(index_id, source_id, 1, "indexer-b", Duration::ZERO)
+                .await
+                .unwrap();
+            let shard_id_opt = metastore
+                .acquire_source_shard_lease(index_id, source_id, 2, "indexer-d", lease_duration)
+                .await
+                .unwrap();
+            assert_eq!(shard_id_opt, Some(1));
+
+            let result = metastore
+                .renew_source_shard_lease(index_id, source_id, 1, "indexer-b", lease_duration)
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::Forbidden { .. }));
+        }
+
+        // Change the number of shards once all the leases have expired
+        {
+            metastore
+                .renew_source_shard_lease(index_id, source_id, 0, "indexer-c", Duration::ZERO)
+                .await
+                .unwrap();
+            metastore
+                .renew_source_shard_lease(index_id, source_id, 1, "indexer-d", Duration::ZERO)
+                .await
+                .unwrap();
+            let shard_id_opt = metastore
+                .acquire_source_shard_lease(index_id, source_id, 3, "indexer-a", lease_duration)
+                .await
+                .unwrap();
+            assert_eq!(shard_id_opt, Some(0));
+
+            let leases = metastore.list_source_shard_leases(index_id).await.unwrap();
+            assert_eq!(leases.len(), 1);
+            assert_eq!(leases[0].num_shards, 3);
+        }
+
+        cleanup_index(&metastore, index_id).await;
+    }
+
+    #[cfg(feature = "postgres")]
+    pub async fn test_metastore_publish_splits_with_lease<
+        MetastoreToTest: Metastore + DefaultForTest,
+    >() {
+        let metastore = MetastoreToTest::default_for_test().await;
+
+        let index_id = "publish-splits-with-lease-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+        let split_id_1 = "publish-splits-with-lease-one";
+        let split_metadata_1 = SplitMetadataAndFooterOffsets {
+            footer_offsets: 1000..2000,
+            split_metadata: SplitMetadata {
+                split_id: split_id_1.to_string(),
+                split_state: SplitState::Staged,
+                num_records: 1,
+                size_in_bytes: 2,
+                time_range: Some(RangeInclusive::new(0, 99)),
+                update_timestamp: Utc::now().timestamp(),
+                ..Default::default()
+            },
+        };
+        let split_id_2 = "publish-splits-with-lease-two";
+        let split_metadata_2 = SplitMetadataAndFooterOffsets {
+            footer_offsets: 1000..2000,
+            split_metadata: SplitMetadata {
+                split_id: split_id_2.to_string(),
+                split_state: SplitState::Staged,
+                num_records: 1,
+                size_in_bytes: 2,
+                time_range: Some(RangeInclusive::new(100, 199)),
+                update_timestamp: Utc::now().timestamp(),
+                ..Default::default()
+            },
+        };
+        let source_id = "kafka-source";
+        let lease_holder_a = SourceShardLeaseHolder {
+            source_id: source_id.to_string(),
+            shard_id: 0,
+            owner: "indexer-a".to_string(),
+        };
+        let lease_holder_b = SourceShardLeaseHolder {
+            source_id: source_id.to_string(),
+            shard_id: 0,
+            owner: "indexer-b".to_string(),
+        };
+
+        // Publish a split on a non-existent index
+        {
+            let result = metastore
+                .publish_splits_with_lease(
+                    "non-existent-index",
+                    &[split_id_1],
+                    CheckpointDelta::from(0..10),
+                    &lease_holder_a,
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::IndexDoesNotExist { .. }));
+        }
+
+        metastore
+            .create_index(index_metadata.clone())
+            .await
+            .unwrap();
+        metastore
+            .stage_split(index_id, split_metadata_1)
+            .await
+            .unwrap();
+        metastore
+            .stage_split(index_id, split_metadata_2)
+            .await
+            .unwrap();
+
+        // Publish a split without holding the lease
+        {
+            let result = metastore
+                .publish_splits_with_lease(
+                    index_id,
+                    &[split_id_1],
+                    CheckpointDelta::from(0..10),
+                    &lease_holder_a,
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::Forbidden { .. }));
+        }
+
+        // Publish a split holding the lease, then retry the publication
+        {
+            let shard_id_opt = metastore
+                .acquire_source_shard_lease(
+                    index_id,
+                    source_id,
+                    1,
+                    "indexer-a",
+                    Duration::from_secs(60),
+                )
+                .await
+                .unwrap();
+            assert_eq!(shard_id_opt, Some(0));
+
+            for _ in 0..2 {
+                metastore
+                    .publish_splits_with_lease(
+                        index_id,
+                        &[split_id_1],
+                        CheckpointDelta::from(0..10),
+                        &lease_holder_a,
+                    )
+                    .await
+                    .unwrap();
+            }
+            let published_splits = metastore
+                .list_splits(index_id, SplitState::Published, None, &[])
+                .await
+                .unwrap();
+            assert_eq!(published_splits.len(), 1);
+            assert_eq!(published_splits[0].split_metadata.split_id, split_id_1);
+        }
+
+        // Publish a split after another indexer took over the expired lease
+        {
+            metastore
+                .renew_source_shard_lease(index_id, source_id, 0, "indexer-a", Duration::ZERO)
+                .await
+                .unwrap();
+            let result = metastore
+                .publish_splits_with_lease(
+                    index_id,
+                    &[split_id_2],
+                    CheckpointDelta::from(10..20),
+                    &lease_holder_a,
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::Forbidden { .. }));
+
+            let shard_id_opt = metastore
+                .acquire_source_shard_lease(
+                    index_id,
+                    source_id,
+                    1,
+                    "indexer-b",
+                    Duration::from_secs(60),
+                )
+                .await
+                .unwrap();
+            assert_eq!(shard_id_opt, Some(0));
+
+            // The new holder of the lease cannot publish the offsets published by the previous
+            // one again.
+            let result = metastore
+                .publish_splits_with_lease(
+                    index_id,
+                    &[split_id_2],
+                    CheckpointDelta::from(5..20),
+                    &lease_holder_b,
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                result,
+                MetastoreError::IncompatibleCheckpointDelta(_)
+            ));
+
+            metastore
+                .publish_splits_with_lease(
+                    index_id,
+                    &[split_id_2],
+                    CheckpointDelta::from(10..20),
+                    &lease_holder_b,
+                )
+                .await
+                .unwrap();
+            let published_splits = metastore
+                .list_splits(index_id, SplitState::Published, None, &[])
+                .await
+                .unwrap();
+            assert_eq!(published_splits.len(), 2);
+        }
+
+        cleanup_index(&metastore, index_id).await;
+    }
+
+    pub async fn test_metastore_split_leases<MetastoreToTest: Metastore + DefaultForTest>() {
+        let metastore = MetastoreToTest::default_for_test().await;
+
+        let index_id = "split-leases-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+        let lease_duration = Duration::from_secs(60);
+
+        // Lease the splits of a non-existent index
+        {
+            let result = metastore
+                .acquire_split_lease(
+                    "non-existent-index",
+                    "search-a",
+                    &["split-1"],
+                    lease_duration,
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::IndexDoesNotExist { .. }));
+            let result = metastore
+                .list_leased_split_ids("non-existent-index")
+                .await
+                .unwrap_err();
+            assert!(matches!(result, MetastoreError::IndexDoesNotExist { .. }));
+        }
+
+        metastore
+            .create_index(index_metadata.clone())
+            .await
+            .unwrap();
+        assert!(metastore
+            .list_leased_split_ids(index_id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Two searches lease overlapping splits
+        {
+            metastore
+                .acquire_split_lease(
+                    index_id,
+                    "search-a",
+                    &["split-1", "split-2"],
+                    lease_duration,
+                )
+                .await
+                .unwrap();
+            metastore
+                .acquire_split_lease(index_id, "search-b", &["split-2"], lease_duration)
+                .await
+                .unwrap();
+            metastore
+                .acquire_split_lease(index_id, "search-b", &["split-3"], lease_duration)
+                .await
+                .unwrap();
+            assert_eq!(
+                metastore.list_leased_split_ids(index_id).await.unwrap(),
+                vec![
+                    "split-1".to_string(),
+                    "split-2".to_string(),
+                    "split-3".to_string()
+                ]
+            );
+        }
+
+        // Releasing a lease leaves the splits of the other one leased
+        {
+            metastore
+                .release_split_lease(index_id, "search-a")
+                .await
+                .unwrap();
+            metastore
+                .release_split_lease(index_id, "search-a")
+                .await
+                .unwrap();
+            assert_eq!(
+                metastore.list_leased_split_ids(index_id).await.unwrap(),
+                vec!["split-2".to_string(), "split-3".to_string()]
+            );
+        }
+
+        // Expired leases are ignored
+        {
+            metastore
+                .acquire_split_lease(index_id, "search-b", &[], Duration::from_secs(0))
+                .await
+                .unwrap();
+            assert!(metastore
+                .list_leased_split_ids(index_id)
+                .await
+                .unwrap()
+                .is_empty());
+        }
+
+        cleanup_index(&metastore, index_id).await;
+    }
+
+    async fn next_split_events(
+        event_receiver: &mut SplitEventReceiver,
+        num_events: usize,
+    ) -> Vec<SplitEvent> {
+        let mut split_events = Vec::new();
+        for _ in 0..num_events {
+            let split_event = timeout(Duration::from_secs(10), event_receiver.recv())
+                .await
+                .expect("No split event was received in time.")
+                .expect("The split event receiver was closed.");
+            split_events.push(split_event);
+        }
+        split_events
+    }
+
+    pub async fn test_metastore_watch_splits<MetastoreToTest: Metastore + DefaultForTest>() {
+        let metastore = MetastoreToTest::default_for_test().await;
+
+        let index_id = "watch-splits-index";
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed("ram://indexes/my-index"),
+            index_config: Arc::new(quickwit_index_config::default_config_for_tests()),
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        };
+        let split_metadata = |split_id: &str| SplitMetadataAndFooterOffsets {
+            footer_offsets: 1000..2000,
+            split_metadata: SplitMetadata {
+                split_id: split_id.to_string(),
+                split_state: SplitState::Staged,
+                num_records: 1,
+                size_in_bytes: 2,
+                time_range: Some(RangeInclusive::new(0, 99)),
+                update_timestamp: Utc::now().timestamp(),
+                ..Default::default()
+            },
+        };
+        let split_id_1 = "watch-splits-one";
+        let split_id_2 = "watch-splits-two";
+
+        // Watch a non-existent index
+        {
+            let result = metastore.watch_splits(index_id).await;
+            assert!(matches!(
+                result,
+                Err(MetastoreError::IndexDoesNotExist { .. })
+            ));
+        }
+
+        metastore
+            .create_index(index_metadata.clone())
+            .await
+            .unwrap();
+        let mut event_receiver = metastore.watch_splits(index_id).await.unwrap();
+
+        // Staging a split is not an event
+        metastore
+            .stage_split(index_id, split_metadata(split_id_1))
+            .await
+            .unwrap();
+        metastore
+            .stage_split(index_id, split_metadata(split_id_2))
+            .await
+            .unwrap();
+
+        metastore
+            .publish_splits(index_id, &[split_id_1], CheckpointDelta::from(0..5))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_split_events(&mut event_receiver, 1).await,
+            vec![SplitEvent::Published {
+                split_id: split_id_1.to_string()
+            }]
+        );
+
+        metastore
+            .replace_splits(index_id, &[split_id_2], &[split_id_1])
+            .await
+            .unwrap();
+        let split_events = next_split_events(&mut event_receiver, 2).await;
+        assert!(split_events.contains(&SplitEvent::Deleted {
+            split_id: split_id_1.to_string()
+        }));
+        assert!(split_events.contains(&SplitEvent::Published {
+            split_id: split_id_2.to_string()
+        }));
+
+        metastore
+            .mark_splits_for_deletion(index_id, &[split_id_2])
+            .await
+            .unwrap();
+        assert_eq!(
+            next_split_events(&mut event_receiver, 1).await,
+            vec![SplitEvent::Deleted {
+                split_id: split_id_2.to_string()
+            }]
+        );
+
+        cleanup_index(&metastore, index_id).await;
+    }
+}
+
+macro_rules! metastore_test_suite {
+    ($metastore_type:ty) => {
+        #[cfg(test)]
+        mod common_tests {
+            #[tokio::test]
+            async fn test_metastore_create_index() {
+                crate::tests::test_suite::test_metastore_create_index::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_delete_index() {
+                crate::tests::test_suite::test_metastore_delete_index::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_index_metadata() {
+                crate::tests::test_suite::test_metastore_index_metadata::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_update_index_config() {
+                crate::tests::test_suite::test_metastore_update_index_config::<$metastore_type>()
+                    .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_set_checkpoint_positions() {
+                crate::tests::test_suite::test_metastore_set_checkpoint_positions::<
+                    $metastore_type,
+                >()
+                .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_update_index_labels() {
+                crate::tests::test_suite::test_metastore_update_index_labels::<$metastore_type>()
+                    .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_stage_split() {
+                crate::tests::test_suite::test_metastore_stage_split::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_publish_splits() {
+                crate::tests::test_suite::test_metastore_publish_splits::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_replace_splits() {
+                crate::tests::test_suite::test_metastore_replace_splits::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_mark_splits_for_deletion() {
+                crate::tests::test_suite::test_metastore_mark_splits_for_deletion::<$metastore_type>(
+                )
+                .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_delete_splits() {
+                crate::tests::test_suite::test_metastore_delete_splits::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_list_all_splits() {
+                crate::tests::test_suite::test_metastore_list_all_splits::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_list_splits() {
+                crate::tests::test_suite::test_metastore_list_splits::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_split_update_timestamp() {
+                crate::tests::test_suite::test_metastore_split_update_timestamp::<$metastore_type>(
+                )
+                .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_now_timestamp() {
+                crate::tests::test_suite::test_metastore_now_timestamp::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_record_split_verification() {
+                crate::tests::test_suite::test_metastore_record_split_verification::<
+                    $metastore_type,
+                >()
+                .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_quarantine_splits() {
+                crate::tests::test_suite::test_metastore_quarantine_splits::<$metastore_type>()
+                    .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_split_leases() {
+                crate::tests::test_suite::test_metastore_split_leases::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_watch_splits() {
+                crate::tests::test_suite::test_metastore_watch_splits::<$metastore_type>().await;
+            }
+        }
+    };
+}
+
+#[cfg(feature = "postgres")]
+macro_rules! metastore_test_suite_for_postgresql {
+    ($metastore_type:ty) => {
+        #[cfg(test)]
+        mod common_tests {
+            #[tokio::test]
+            async fn test_metastore_create_index() {
+                crate::tests::test_suite::test_metastore_create_index::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
             async fn test_metastore_delete_index() {
                 crate::tests::test_suite::test_metastore_delete_index::<$metastore_type>().await;
             }
@@ -1808,6 +3061,26 @@ macro_rules! metastore_test_suite_for_postgresql {
                 crate::tests::test_suite::test_metastore_index_metadata::<$metastore_type>().await;
             }
 
+            #[tokio::test]
+            async fn test_metastore_update_index_config() {
+                crate::tests::test_suite::test_metastore_update_index_config::<$metastore_type>()
+                    .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_set_checkpoint_positions() {
+                crate::tests::test_suite::test_metastore_set_checkpoint_positions::<
+                    $metastore_type,
+                >()
+                .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_update_index_labels() {
+                crate::tests::test_suite::test_metastore_update_index_labels::<$metastore_type>()
+                    .await;
+            }
+
             #[tokio::test]
             async fn test_metastore_stage_split() {
                 crate::tests::test_suite::test_metastore_stage_split::<$metastore_type>().await;
@@ -1851,6 +3124,49 @@ macro_rules! metastore_test_suite_for_postgresql {
                 )
                 .await;
             }
+
+            #[tokio::test]
+            async fn test_metastore_now_timestamp() {
+                crate::tests::test_suite::test_metastore_now_timestamp::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_record_split_verification() {
+                crate::tests::test_suite::test_metastore_record_split_verification::<
+                    $metastore_type,
+                >()
+                .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_quarantine_splits() {
+                crate::tests::test_suite::test_metastore_quarantine_splits::<$metastore_type>()
+                    .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_source_shard_leases() {
+                crate::tests::test_suite::test_metastore_source_shard_leases::<$metastore_type>()
+                    .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_publish_splits_with_lease() {
+                crate::tests::test_suite::test_metastore_publish_splits_with_lease::<
+                    $metastore_type,
+                >()
+                .await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_split_leases() {
+                crate::tests::test_suite::test_metastore_split_leases::<$metastore_type>().await;
+            }
+
+            #[tokio::test]
+            async fn test_metastore_watch_splits() {
+                crate::tests::test_suite::test_metastore_watch_splits::<$metastore_type>().await;
+            }
         }
     };
 }
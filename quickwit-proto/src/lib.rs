@@ -37,6 +37,23 @@ impl From<SearchStreamRequest> for SearchRequest {
             max_hits: 0,
             start_offset: 0,
             tags: item.tags,
+            distinct_count_fields: Vec::new(),
+            percentile_fields: Vec::new(),
+            percentiles: Vec::new(),
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: item.time_zone,
+            geo_filter: None,
+            mandatory_filter: item.mandatory_filter,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: item.disable_synonyms,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: item.cache_policy,
+            max_num_splits: None,
+            hit_sampling: None,
         }
     }
 }
@@ -0,0 +1,310 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use quickwit_storage::MemorySizedCache;
+use serde::Serialize;
+
+use crate::fast_field_pinning::{global_pinned_fast_fields, PinnedFastFields};
+use crate::leaf::global_split_footer_cache;
+use crate::split_pool::{global_split_pool, SplitPool};
+
+/// The caches of a searcher node that can be inspected and invalidated at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearcherCache {
+    /// Footers of the splits, which hold their hotcache. Its capacity is in bytes.
+    SplitFooter,
+    /// Opened splits. Its capacity is a number of splits.
+    SplitPool,
+    /// Fast fields of the recent splits. Its capacity is in bytes.
+    PinnedFastFields,
+}
+
+impl FromStr for SearcherCache {
+    type Err = anyhow::Error;
+
+    fn from_str(cache_str: &str) -> anyhow::Result<Self> {
+        match cache_str {
+            "split_footer" => Ok(SearcherCache::SplitFooter),
+            "split_pool" => Ok(SearcherCache::SplitPool),
+            "pinned_fast_fields" => Ok(SearcherCache::PinnedFastFields),
+            _ => anyhow::bail!(
+                "Unknown cache `{}`. Expected `split_footer`, `split_pool` or \
+                 `pinned_fast_fields`.",
+                cache_str
+            ),
+        }
+    }
+}
+
+/// Usage of one of the caches of a searcher node.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheUsage {
+    /// The cache.
+    pub cache: SearcherCache,
+    /// Number of entries in the cache.
+    pub num_entries: usize,
+    /// Number of bytes held by the cache.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_bytes: Option<usize>,
+    /// Capacity of the cache, in the unit of the cache. `None` if it is unlimited.
+    pub capacity: Option<usize>,
+}
+
+/// Memory held by the caches of a searcher node for a given split.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitCacheUsage {
+    /// ID of the split.
+    pub split_id: String,
+    /// Size of the cached footer of the split.
+    pub split_footer_num_bytes: usize,
+    /// Size of the pinned fast fields of the split.
+    pub pinned_fast_fields_num_bytes: usize,
+    /// True if the split is in the pool of opened splits.
+    pub in_split_pool: bool,
+}
+
+/// Contents of the caches of a searcher node.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SearcherCachesReport {
+    /// Usage of each cache. Fast field pinning is left out if it is not enabled.
+    pub caches: Vec<CacheUsage>,
+    /// Usage of the caches per split, sorted by split ID.
+    pub splits: Vec<SplitCacheUsage>,
+}
+
+struct SearcherCaches<'a> {
+    split_footer_cache: &'a MemorySizedCache<String>,
+    split_pool: &'a SplitPool,
+    pinned_fast_fields_opt: Option<&'a PinnedFastFields>,
+}
+
+fn global_searcher_caches() -> SearcherCaches<'static> {
+    SearcherCaches {
+        split_footer_cache: global_split_footer_cache(),
+        split_pool: global_split_pool(),
+        pinned_fast_fields_opt: global_pinned_fast_fields(),
+    }
+}
+
+impl<'a> SearcherCaches<'a> {
+    fn report(&self) -> SearcherCachesReport {
+        let mut splits: BTreeMap<String, SplitCacheUsage> = BTreeMap::new();
+        let mut caches = Vec::new();
+
+        let split_footers = self.split_footer_cache.entries();
+        caches.push(CacheUsage {
+            cache: SearcherCache::SplitFooter,
+            num_entries: split_footers.len(),
+            num_bytes: Some(self.split_footer_cache.num_bytes()),
+            capacity: self.split_footer_cache.capacity_in_bytes(),
+        });
+        for (split_id, num_bytes) in split_footers {
+            split_cache_usage(&mut splits, split_id).split_footer_num_bytes = num_bytes;
+        }
+
+        let pooled_split_ids = self.split_pool.split_ids();
+        caches.push(CacheUsage {
+            cache: SearcherCache::SplitPool,
+            num_entries: pooled_split_ids.len(),
+            num_bytes: Some(self.split_pool.num_bytes()),
+            capacity: Some(self.split_pool.capacity()),
+        });
+        for split_id in pooled_split_ids {
+            split_cache_usage(&mut splits, split_id).in_split_pool = true;
+        }
+
+        if let Some(pinned_fast_fields) = self.pinned_fast_fields_opt {
+            let pinned_splits = pinned_fast_fields.split_num_bytes();
+            caches.push(CacheUsage {
+                cache: SearcherCache::PinnedFastFields,
+                num_entries: pinned_splits.len(),
+                num_bytes: Some(pinned_fast_fields.num_bytes()),
+                capacity: Some(pinned_fast_fields.budget_in_bytes()),
+            });
+            for (split_id, num_bytes) in pinned_splits {
+                split_cache_usage(&mut splits, split_id).pinned_fast_fields_num_bytes = num_bytes;
+            }
+        }
+        SearcherCachesReport {
+            caches,
+            splits: splits.into_iter().map(|(_, split)| split).collect(),
+        }
+    }
+
+    fn invalidate_splits(&self, split_ids: &[String]) -> usize {
+        let mut num_invalidated_splits = 0;
+        for split_id in split_ids {
+            let mut invalidated = self.split_footer_cache.remove(split_id);
+            invalidated |= self.split_pool.evict(split_id);
+            if let Some(pinned_fast_fields) = self.pinned_fast_fields_opt {
+                invalidated |= pinned_fast_fields.unpin_split(split_id);
+            }
+            if invalidated {
+                num_invalidated_splits += 1;
+            }
+        }
+        num_invalidated_splits
+    }
+
+    fn clear(&self) {
+        self.split_footer_cache.clear();
+        self.split_pool.clear();
+        if let Some(pinned_fast_fields) = self.pinned_fast_fields_opt {
+            pinned_fast_fields.clear();
+        }
+    }
+
+    fn set_capacity(&self, cache: SearcherCache, capacity: usize) -> anyhow::Result<()> {
+        match cache {
+            SearcherCache::SplitFooter => self.split_footer_cache.set_capacity_in_bytes(capacity),
+            SearcherCache::SplitPool => self.split_pool.set_capacity(capacity)?,
+            SearcherCache::PinnedFastFields => self
+                .pinned_fast_fields_opt
+                .ok_or_else(|| anyhow::anyhow!("Fast field pinning is not enabled."))?
+                .set_budget_in_bytes(capacity),
+        }
+        Ok(())
+    }
+}
+
+fn split_cache_usage(
+    splits: &mut BTreeMap<String, SplitCacheUsage>,
+    split_id: String,
+) -> &mut SplitCacheUsage {
+    splits
+        .entry(split_id.clone())
+        .or_insert_with(|| SplitCacheUsage {
+            split_id,
+            ..Default::default()
+        })
+}
+
+/// Returns the contents of the caches of this searcher node.
+pub fn searcher_caches_report() -> SearcherCachesReport {
+    global_searcher_caches().report()
+}
+
+/// Removes the given splits from all of the caches of this searcher node.
+///
+/// Returns the number of splits that were in at least one cache. An opened split that
+/// is being searched stays alive until its search completes.
+pub fn invalidate_split_caches(split_ids: &[String]) -> usize {
+    global_searcher_caches().invalidate_splits(split_ids)
+}
+
+/// Removes all of the entries from the caches of this searcher node.
+pub fn clear_searcher_caches() {
+    global_searcher_caches().clear()
+}
+
+/// Changes the capacity of one of the caches of this searcher node, evicting the entries
+/// that do not fit in it anymore.
+pub fn set_searcher_cache_capacity(cache: SearcherCache, capacity: usize) -> anyhow::Result<()> {
+    global_searcher_caches().set_capacity(cache, capacity)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::split_pool::opened_split_for_test;
+
+    #[test]
+    fn test_searcher_caches() {
+        let split_footer_cache = MemorySizedCache::with_capacity_in_bytes(100);
+        let split_pool = SplitPool::new(10, 1_000, 10);
+        let searcher_caches = SearcherCaches {
+            split_footer_cache: &split_footer_cache,
+            split_pool: &split_pool,
+            pinned_fast_fields_opt: None,
+        };
+        split_footer_cache.put("split1".to_string(), Bytes::from(vec![0u8; 30]));
+        split_footer_cache.put("split2".to_string(), Bytes::from(vec![0u8; 20]));
+        split_pool.put("split2".to_string(), opened_split_for_test(40));
+        split_pool.put("split3".to_string(), opened_split_for_test(60));
+
+        let report = searcher_caches.report();
+        assert_eq!(
+            report.caches,
+            vec![
+                CacheUsage {
+                    cache: SearcherCache::SplitFooter,
+                    num_entries: 2,
+                    num_bytes: Some(50),
+                    capacity: Some(100),
+                },
+                CacheUsage {
+                    cache: SearcherCache::SplitPool,
+                    num_entries: 2,
+                    num_bytes: Some(100),
+                    capacity: Some(10),
+                },
+            ]
+        );
+        assert_eq!(
+            report.splits[1],
+            SplitCacheUsage {
+                split_id: "split2".to_string(),
+                split_footer_num_bytes: 20,
+                pinned_fast_fields_num_bytes: 0,
+                in_split_pool: true,
+            }
+        );
+        assert_eq!(report.splits.len(), 3);
+
+        let split_ids = vec!["split2".to_string(), "split4".to_string()];
+        assert_eq!(searcher_caches.invalidate_splits(&split_ids), 1);
+        let split_ids: Vec<String> = searcher_caches
+            .report()
+            .splits
+            .into_iter()
+            .map(|split| split.split_id)
+            .collect();
+        assert_eq!(split_ids, vec!["split1", "split3"]);
+
+        searcher_caches
+            .set_capacity(SearcherCache::SplitFooter, 10)
+            .unwrap();
+        assert_eq!(split_footer_cache.capacity_in_bytes(), Some(10));
+        assert_eq!(split_footer_cache.num_bytes(), 0);
+        assert!(searcher_caches
+            .set_capacity(SearcherCache::PinnedFastFields, 10)
+            .is_err());
+
+        searcher_caches.clear();
+        assert!(searcher_caches.report().splits.is_empty());
+    }
+
+    #[test]
+    fn test_searcher_cache_from_str() {
+        assert_eq!(
+            SearcherCache::from_str("split_pool").unwrap(),
+            SearcherCache::SplitPool
+        );
+        assert!(SearcherCache::from_str("split-pool").is_err());
+    }
+}
@@ -19,21 +19,22 @@
 
 use std::fmt;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use futures::{StreamExt, TryStreamExt};
 use http::Uri;
 use opentelemetry::global;
 use opentelemetry::propagation::Injector;
-use quickwit_proto::LeafSearchStreamResult;
-use tokio_stream::wrappers::UnboundedReceiverStream;
 use tonic::transport::{Channel, Endpoint};
 use tonic::Request;
 use tracing::*;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+use crate::client_pool::circuit_breaker::CircuitBreaker;
 use crate::error::parse_grpc_error;
-use crate::SearchService;
+use crate::search_stream::{result_channel, spawn_until_receiver_dropped, ResultReceiverStream};
+use crate::{NodeAttributes, SearchService};
 
 struct MetadataMap<'a>(&'a mut tonic::metadata::MetadataMap);
 
@@ -59,10 +60,16 @@ enum SearchServiceClientImpl {
 /// A search service client.
 /// It contains the client implementation and the gRPC address of the node to which the client
 /// connects.
+///
+/// Clones share the same [`CircuitBreaker`], which tracks whether the node can be reached,
+/// and the same node attributes and standby flag.
 #[derive(Clone)]
 pub struct SearchServiceClient {
     client_impl: SearchServiceClientImpl,
     grpc_addr: SocketAddr,
+    circuit_breaker: CircuitBreaker,
+    node_attributes: Arc<Mutex<Option<NodeAttributes>>>,
+    standby: Arc<AtomicBool>,
 }
 
 impl fmt::Debug for SearchServiceClient {
@@ -87,6 +94,9 @@ impl SearchServiceClient {
         SearchServiceClient {
             client_impl: SearchServiceClientImpl::Grpc(client),
             grpc_addr,
+            circuit_breaker: CircuitBreaker::default(),
+            node_attributes: Default::default(),
+            standby: Default::default(),
         }
     }
 
@@ -95,6 +105,9 @@ impl SearchServiceClient {
         SearchServiceClient {
             client_impl: SearchServiceClientImpl::Local(service),
             grpc_addr,
+            circuit_breaker: CircuitBreaker::default(),
+            node_attributes: Default::default(),
+            standby: Default::default(),
         }
     }
 
@@ -103,6 +116,32 @@ impl SearchServiceClient {
         self.grpc_addr
     }
 
+    /// Returns the circuit breaker tracking the health of the node the client connects to.
+    pub fn circuit_breaker(&self) -> &CircuitBreaker {
+        &self.circuit_breaker
+    }
+
+    /// Returns the attributes of the node the client connects to,
+    /// or `None` if they have not been fetched yet.
+    pub fn node_attributes(&self) -> Option<NodeAttributes> {
+        self.node_attributes.lock().unwrap().clone()
+    }
+
+    /// Sets the attributes of the node the client connects to.
+    pub fn set_node_attributes(&self, node_attributes: NodeAttributes) {
+        *self.node_attributes.lock().unwrap() = Some(node_attributes);
+    }
+
+    /// Returns true if the node the client connects to is known to be a standby searcher.
+    pub fn is_standby(&self) -> bool {
+        self.standby.load(Ordering::Relaxed)
+    }
+
+    /// Sets whether the node the client connects to is a standby searcher.
+    pub fn set_standby(&self, standby: bool) {
+        self.standby.store(standby, Ordering::Relaxed);
+    }
+
     /// Perform root search.
     pub async fn root_search(
         &mut self,
@@ -111,10 +150,10 @@ impl SearchServiceClient {
         match &mut self.client_impl {
             SearchServiceClientImpl::Grpc(grpc_client) => {
                 let tonic_request = Request::new(request);
-                let tonic_response = grpc_client
-                    .root_search(tonic_request)
-                    .await
-                    .map_err(|tonic_error| parse_grpc_error(&tonic_error))?;
+                let tonic_result = grpc_client.root_search(tonic_request).await;
+                record_grpc_result(&self.circuit_breaker, &tonic_result);
+                let tonic_response =
+                    tonic_result.map_err(|tonic_error| parse_grpc_error(&tonic_error))?;
                 Ok(tonic_response.into_inner())
             }
             SearchServiceClientImpl::Local(service) => service.root_search(request).await,
@@ -135,10 +174,10 @@ impl SearchServiceClient {
                         &mut MetadataMap(tonic_request.metadata_mut()),
                     )
                 });
-                let tonic_response = grpc_client
-                    .leaf_search(tonic_request)
-                    .await
-                    .map_err(|tonic_error| parse_grpc_error(&tonic_error))?;
+                let tonic_result = grpc_client.leaf_search(tonic_request).await;
+                record_grpc_result(&self.circuit_breaker, &tonic_result);
+                let tonic_response =
+                    tonic_result.map_err(|tonic_error| parse_grpc_error(&tonic_error))?;
                 Ok(tonic_response.into_inner())
             }
             SearchServiceClientImpl::Local(service) => service.leaf_search(request).await,
@@ -149,10 +188,11 @@ impl SearchServiceClient {
     pub async fn leaf_search_stream(
         &mut self,
         request: quickwit_proto::LeafSearchStreamRequest,
-    ) -> UnboundedReceiverStream<crate::Result<LeafSearchStreamResult>> {
+    ) -> ResultReceiverStream {
         match &mut self.client_impl {
             SearchServiceClientImpl::Grpc(grpc_client) => {
                 let mut grpc_client_clone = grpc_client.clone();
+                let circuit_breaker = self.circuit_breaker.clone();
                 let span = info_span!(
                     "client:leaf_search_stream",
                     grpc_addr=?self.grpc_addr()
@@ -164,43 +204,41 @@ impl SearchServiceClient {
                         &mut MetadataMap(tonic_request.metadata_mut()),
                     )
                 });
-                let (result_sender, result_receiver) = tokio::sync::mpsc::unbounded_channel();
-                tokio::spawn(
+                let (result_sender, result_receiver) = result_channel();
+                spawn_until_receiver_dropped(result_sender, |result_sender| {
                     async move {
-                        let tonic_result = grpc_client_clone
-                            .leaf_search_stream(tonic_request)
-                            .await
-                            .map_err(|tonic_error| parse_grpc_error(&tonic_error));
+                        let tonic_result =
+                            grpc_client_clone.leaf_search_stream(tonic_request).await;
+                        record_grpc_result(&circuit_breaker, &tonic_result);
+                        let tonic_result =
+                            tonic_result.map_err(|tonic_error| parse_grpc_error(&tonic_error));
                         // If the grpc client fails, send the error in the channel and stop.
                         if let Err(error) = tonic_result {
                             // It is ok to ignore error sending error.
-                            let _ = result_sender.send(Err(error));
+                            let _ = result_sender.send(Err(error)).await;
                             return;
                         }
                         let mut results_stream = tonic_result
                             .unwrap()
                             .into_inner()
                             .map_err(|tonic_error| parse_grpc_error(&tonic_error));
+                        // The next results are only pulled from the gRPC stream once the
+                        // consumer has made room for them, which slows the leaf down.
                         while let Some(search_result) = results_stream.next().await {
-                            let send_result = result_sender.send(search_result);
+                            let send_result = result_sender.send(search_result).await;
                             // If we get a sending error, stop consuming the stream.
                             if send_result.is_err() {
                                 break;
                             }
                         }
                     }
-                    .instrument(span),
-                );
-                UnboundedReceiverStream::new(result_receiver)
+                    .instrument(span)
+                });
+                result_receiver
             }
             SearchServiceClientImpl::Local(service) => {
                 let stream_result = service.leaf_search_stream(request).await;
-                stream_result.unwrap_or_else(|error| {
-                    let (result_sender, result_receiver) = tokio::sync::mpsc::unbounded_channel();
-                    // Receiver cannot be closed here, ignore error.
-                    let _ = result_sender.send(Err(error));
-                    UnboundedReceiverStream::new(result_receiver)
-                })
+                stream_result.unwrap_or_else(ResultReceiverStream::from_error)
             }
         }
     }
@@ -219,14 +257,113 @@ impl SearchServiceClient {
                         &mut MetadataMap(tonic_request.metadata_mut()),
                     )
                 });
+                let tonic_result = grpc_client.fetch_docs(tonic_request).await;
+                record_grpc_result(&self.circuit_breaker, &tonic_result);
+                let tonic_response =
+                    tonic_result.map_err(|tonic_error| parse_grpc_error(&tonic_error))?;
+                Ok(tonic_response.into_inner())
+            }
+            SearchServiceClientImpl::Local(service) => service.fetch_docs(request).await,
+        }
+    }
+
+    /// Perform a leaf search on the splits being indexed by the node.
+    pub async fn leaf_search_realtime(
+        &mut self,
+        request: quickwit_proto::LeafSearchRealtimeRequest,
+    ) -> crate::Result<quickwit_proto::LeafSearchRealtimeResponse> {
+        match &mut self.client_impl {
+            SearchServiceClientImpl::Grpc(grpc_client) => {
+                let mut tonic_request = Request::new(request);
+                global::get_text_map_propagator(|propagator| {
+                    propagator.inject_context(
+                        &tracing::Span::current().context(),
+                        &mut MetadataMap(tonic_request.metadata_mut()),
+                    )
+                });
+                let tonic_result = grpc_client.leaf_search_realtime(tonic_request).await;
+                record_grpc_result(&self.circuit_breaker, &tonic_result);
+                let tonic_response =
+                    tonic_result.map_err(|tonic_error| parse_grpc_error(&tonic_error))?;
+                Ok(tonic_response.into_inner())
+            }
+            SearchServiceClientImpl::Local(service) => service.leaf_search_realtime(request).await,
+        }
+    }
+
+    /// Perform leaf searches on other nodes and merge their responses.
+    pub async fn intermediate_search(
+        &mut self,
+        request: quickwit_proto::IntermediateSearchRequest,
+    ) -> crate::Result<quickwit_proto::LeafSearchResponse> {
+        match &mut self.client_impl {
+            SearchServiceClientImpl::Grpc(grpc_client) => {
+                let mut tonic_request = Request::new(request);
+                global::get_text_map_propagator(|propagator| {
+                    propagator.inject_context(
+                        &tracing::Span::current().context(),
+                        &mut MetadataMap(tonic_request.metadata_mut()),
+                    )
+                });
+                let tonic_result = grpc_client.intermediate_search(tonic_request).await;
+                record_grpc_result(&self.circuit_breaker, &tonic_result);
+                let tonic_response =
+                    tonic_result.map_err(|tonic_error| parse_grpc_error(&tonic_error))?;
+                Ok(tonic_response.into_inner())
+            }
+            SearchServiceClientImpl::Local(service) => service.intermediate_search(request).await,
+        }
+    }
+
+    /// Perform node info.
+    pub async fn node_info(
+        &mut self,
+        request: quickwit_proto::NodeInfoRequest,
+    ) -> crate::Result<quickwit_proto::NodeInfoResponse> {
+        match &mut self.client_impl {
+            SearchServiceClientImpl::Grpc(grpc_client) => {
+                let tonic_result = grpc_client.node_info(Request::new(request)).await;
+                record_grpc_result(&self.circuit_breaker, &tonic_result);
+                let tonic_response =
+                    tonic_result.map_err(|tonic_error| parse_grpc_error(&tonic_error))?;
+                Ok(tonic_response.into_inner())
+            }
+            SearchServiceClientImpl::Local(service) => service.node_info(request).await,
+        }
+    }
+
+    /// Performs node info for a health check. Unlike [`Self::node_info`], the outcome is not
+    /// recorded by the circuit breaker: the health check records it itself.
+    pub(crate) async fn probe_node_info(
+        &mut self,
+    ) -> crate::Result<quickwit_proto::NodeInfoResponse> {
+        let request = quickwit_proto::NodeInfoRequest {};
+        match &mut self.client_impl {
+            SearchServiceClientImpl::Grpc(grpc_client) => {
                 let tonic_response = grpc_client
-                    .fetch_docs(tonic_request)
+                    .node_info(Request::new(request))
                     .await
                     .map_err(|tonic_error| parse_grpc_error(&tonic_error))?;
                 Ok(tonic_response.into_inner())
             }
-            SearchServiceClientImpl::Local(service) => service.fetch_docs(request).await,
+            SearchServiceClientImpl::Local(service) => service.node_info(request).await,
+        }
+    }
+}
+
+// Only transport-level failures count against the node: an error returned by the
+// search service itself means that the node is up.
+fn record_grpc_result<T>(circuit_breaker: &CircuitBreaker, grpc_result: &Result<T, tonic::Status>) {
+    match grpc_result {
+        Err(status)
+            if matches!(
+                status.code(),
+                tonic::Code::Unavailable | tonic::Code::DeadlineExceeded
+            ) =>
+        {
+            circuit_breaker.record_failure()
         }
+        _ => circuit_breaker.record_success(),
     }
 }
 
@@ -17,6 +17,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+pub mod circuit_breaker;
 pub mod search_client_pool;
 
 use std::collections::HashSet;
@@ -41,12 +42,13 @@ pub struct Job {
 /// The client pool provides the available clients.
 #[async_trait]
 pub trait ClientPool: Send + Sync + 'static {
-    /// Assign the given job to the clients.
+    /// Assign the given job of the index `index_id` to the clients.
     /// Returns a list of pair (SearchServiceClient, Vec<Job>)
     ///
     /// Excluded addresses are ignored when they include all the clients.
     async fn assign_jobs(
         &self,
+        index_id: &str,
         jobs: Vec<Job>,
         excluded_addresses: &HashSet<SocketAddr>,
     ) -> anyhow::Result<Vec<(SearchServiceClient, Vec<Job>)>>;
@@ -54,10 +56,11 @@ pub trait ClientPool: Send + Sync + 'static {
     /// Assigns one job to a client.
     async fn assign_job(
         &self,
+        index_id: &str,
         job: Job,
         excluded_addresses: &HashSet<SocketAddr>,
     ) -> anyhow::Result<SearchServiceClient> {
-        self.assign_jobs(vec![job], excluded_addresses)
+        self.assign_jobs(index_id, vec![job], excluded_addresses)
             .await?
             .into_iter()
             .next()
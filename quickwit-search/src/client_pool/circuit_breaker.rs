@@ -0,0 +1,186 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Parameters of a [`CircuitBreaker`].
+#[derive(Clone, Copy, Debug)]
+pub struct CircuitBreakerParams {
+    /// Number of consecutive failures after which the circuit opens.
+    pub failure_threshold: u32,
+    /// Time during which the circuit stays open after it first trips.
+    pub initial_backoff: Duration,
+    /// The backoff doubles every time the circuit trips again without any success in between,
+    /// up to `max_backoff`.
+    pub max_backoff: Duration,
+}
+
+impl Default for CircuitBreakerParams {
+    fn default() -> Self {
+        CircuitBreakerParams {
+            failure_threshold: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// State of a [`CircuitBreaker`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// The node is healthy, requests are routed to it.
+    Closed,
+    /// The node failed recently, requests are not routed to it until the backoff elapses.
+    Open,
+    /// The backoff elapsed, requests are routed to the node again. The next failure
+    /// reopens the circuit with a longer backoff, the next success closes it.
+    HalfOpen,
+}
+
+#[derive(Default)]
+struct CircuitBreakerInner {
+    consecutive_failures: u32,
+    num_trips: u32,
+    open_until_opt: Option<Instant>,
+}
+
+/// Tracks the health of a searcher node, so that requests stop being routed to it
+/// when it keeps failing.
+///
+/// Clones share the same state.
+#[derive(Clone, Default)]
+pub struct CircuitBreaker {
+    params: CircuitBreakerParams,
+    inner: Arc<Mutex<CircuitBreakerInner>>,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed circuit breaker.
+    pub fn new(params: CircuitBreakerParams) -> Self {
+        CircuitBreaker {
+            params,
+            inner: Default::default(),
+        }
+    }
+
+    /// Returns the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        let inner = self.inner.lock().unwrap();
+        Self::state_at(&inner, Instant::now())
+    }
+
+    /// Returns false if requests should not be routed to the node.
+    pub fn is_available(&self) -> bool {
+        self.state() != CircuitState::Open
+    }
+
+    /// Records a request or health check that reached the node.
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner = CircuitBreakerInner::default();
+    }
+
+    /// Records a request or health check that failed to reach the node.
+    pub fn record_failure(&self) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        let should_trip = match Self::state_at(&inner, now) {
+            CircuitState::Closed => inner.consecutive_failures >= self.params.failure_threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if should_trip {
+            let backoff = self
+                .params
+                .initial_backoff
+                .checked_mul(1u32.checked_shl(inner.num_trips).unwrap_or(u32::MAX))
+                .unwrap_or(self.params.max_backoff)
+                .min(self.params.max_backoff);
+            inner.num_trips += 1;
+            inner.open_until_opt = Some(now + backoff);
+        }
+    }
+
+    fn state_at(inner: &CircuitBreakerInner, now: Instant) -> CircuitState {
+        match inner.open_until_opt {
+            None => CircuitState::Closed,
+            Some(open_until) if now < open_until => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_circuit_breaker_opens_after_consecutive_failures() {
+        let circuit_breaker = CircuitBreaker::new(CircuitBreakerParams {
+            failure_threshold: 2,
+            initial_backoff: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(60),
+        });
+        assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+        circuit_breaker.record_failure();
+        circuit_breaker.record_success();
+        circuit_breaker.record_failure();
+        assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+        circuit_breaker.record_failure();
+        assert_eq!(circuit_breaker.state(), CircuitState::Open);
+        assert!(!circuit_breaker.is_available());
+        circuit_breaker.record_success();
+        assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_half_open_failure_reopens_with_longer_backoff() {
+        tokio::time::pause();
+        let circuit_breaker = CircuitBreaker::new(CircuitBreakerParams {
+            failure_threshold: 1,
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(25),
+        });
+        circuit_breaker.record_failure();
+        assert_eq!(circuit_breaker.state(), CircuitState::Open);
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(circuit_breaker.state(), CircuitState::HalfOpen);
+        assert!(circuit_breaker.is_available());
+        circuit_breaker.record_failure();
+        // The backoff doubled to 20s.
+        tokio::time::advance(Duration::from_secs(19)).await;
+        assert_eq!(circuit_breaker.state(), CircuitState::Open);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(circuit_breaker.state(), CircuitState::HalfOpen);
+        circuit_breaker.record_failure();
+        // The backoff is capped to 25s.
+        tokio::time::advance(Duration::from_secs(24)).await;
+        assert_eq!(circuit_breaker.state(), CircuitState::Open);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(circuit_breaker.state(), CircuitState::HalfOpen);
+        circuit_breaker.record_success();
+        assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+    }
+}
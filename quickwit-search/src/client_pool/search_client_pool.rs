@@ -21,26 +21,52 @@ use std::cmp::Ordering;
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use quickwit_cluster::cluster::Cluster;
+use quickwit_proto::{NodeInfoRequest, NodeInfoResponse};
 use tokio::sync::RwLock;
 use tokio_stream::StreamExt;
 use tracing::*;
 
 use crate::client::create_search_service_client;
+use crate::client_pool::circuit_breaker::CircuitState;
 use crate::client_pool::{ClientPool, Job};
 use crate::rendezvous_hasher::{sort_by_rendez_vous_hash, Node};
-use crate::{swim_addr_to_grpc_addr, SearchServiceClient};
+use crate::{
+    failure_domain, swim_addr_to_grpc_addr, NodeAttributes, PlacementRules, SearchServiceClient,
+};
+
+/// Interval between two health checks of the searcher nodes.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Time after which a health check is considered failed.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// Search client pool implementation.
-#[derive(Clone)]
+///
+/// Requests are not routed to nodes whose circuit breaker is open. The circuit breakers
+/// are fed both by the outcome of the requests, and by a background task that periodically
+/// checks that the nodes answer a node info request. Nodes whose circuit is open are only
+/// checked again once their backoff has elapsed.
+///
+/// The attributes of the nodes are fetched as soon as they are added to the pool, and
+/// refreshed by the same background task. Jobs are only assigned to the nodes satisfying the
+/// placement rules of the searched index.
+///
+/// Standby nodes are not assigned any job unless they are the only eligible nodes. The
+/// background task keeps polling them, so that their promotion is noticed.
+#[derive(Clone, Default)]
 pub struct SearchClientPool {
     /// Search clients.
     /// A hash map with gRPC's SocketAddr as the key and SearchServiceClient as the value.
     /// It is not the cluster listen address.
     pub clients: Arc<RwLock<HashMap<SocketAddr, SearchServiceClient>>>,
+
+    /// Rules restricting the nodes that can search a given index.
+    pub placement_rules: Arc<PlacementRules>,
 }
 
 impl SearchClientPool {
@@ -58,13 +84,34 @@ impl SearchClientPool {
 
         Ok(SearchClientPool {
             clients: Arc::new(RwLock::new(mock_clients)),
+            placement_rules: Default::default(),
         })
     }
 
+    /// Creates a search client pool made of a single search service of the current process.
+    ///
+    /// There is no cluster to watch nor any node to health check, so no background task is
+    /// started.
+    pub fn for_local_service(service: Arc<dyn crate::SearchService>) -> Self {
+        let grpc_addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let mut clients = HashMap::new();
+        clients.insert(
+            grpc_addr,
+            SearchServiceClient::from_service(service, grpc_addr),
+        );
+        SearchClientPool {
+            clients: Arc::new(RwLock::new(clients)),
+            placement_rules: Default::default(),
+        }
+    }
+
     /// Create a search client pool given a cluster.
     /// When a client pool is created, the thread that monitors cluster members
     /// will be started at the same time.
-    pub async fn new(cluster: Arc<Cluster>) -> anyhow::Result<Self> {
+    pub async fn new(
+        cluster: Arc<Cluster>,
+        placement_rules: PlacementRules,
+    ) -> anyhow::Result<Self> {
         let mut clients = HashMap::new();
 
         // Initialize the client pool with members of the cluster.
@@ -80,12 +127,18 @@ impl SearchClientPool {
                 }
             };
         }
+        // Fetch the attributes of the initial members before serving any request, so that
+        // the placement rules apply right away.
+        futures::future::join_all(clients.values().map(fetch_node_attributes)).await;
 
         // Create search client pool.
         let client_pool = SearchClientPool {
             clients: Arc::new(RwLock::new(clients)),
+            placement_rules: Arc::new(placement_rules),
         };
 
+        spawn_health_checker(Arc::downgrade(&client_pool.clients));
+
         // Prepare to start a thread that will monitor cluster members.
         let thread_clients = Arc::clone(&client_pool.clients);
         let mut members_watch_channel = cluster.member_change_watcher();
@@ -121,6 +174,12 @@ impl SearchClientPool {
                         match create_search_service_client(grpc_addr).await {
                             Ok(client) => {
                                 debug!(grpc_addr=?grpc_addr, "Add a new client that is connecting to the node that has been joined the cluster.");
+                                // The write lock on the clients must not be held while
+                                // waiting for the node.
+                                let new_client = client.clone();
+                                tokio::spawn(async move {
+                                    fetch_node_attributes(&new_client).await;
+                                });
                                 clients.insert(grpc_addr, client);
                             }
                             Err(err) => {
@@ -134,6 +193,157 @@ impl SearchClientPool {
 
         Ok(client_pool)
     }
+
+    /// Assigns shadow copies of the jobs of the index `index_id` to the standby nodes.
+    ///
+    /// The jobs are distributed among the standby nodes the way
+    /// [`ClientPool::assign_jobs`] distributes them among the active nodes, so that a
+    /// standby fleet warms up the caches it needs once promoted. Nothing is assigned when
+    /// no active node can search the index, as the standby nodes then get the actual jobs.
+    pub async fn assign_shadow_jobs(
+        &self,
+        index_id: &str,
+        jobs: Vec<Job>,
+    ) -> Vec<(SearchServiceClient, Vec<Job>)> {
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut socket_to_client: HashMap<SocketAddr, SearchServiceClient> = Default::default();
+        {
+            let clients = self.clients.read().await;
+            let eligible_clients: Vec<(&SocketAddr, &SearchServiceClient)> = clients
+                .iter()
+                .filter(|(_, client)| {
+                    self.placement_rules
+                        .is_node_eligible(index_id, client.node_attributes().as_ref())
+                })
+                .collect();
+            if eligible_clients
+                .iter()
+                .all(|(_, client)| client.is_standby())
+            {
+                return Vec::new();
+            }
+            for (grpc_addr, client) in eligible_clients {
+                if client.is_standby() && client.circuit_breaker().is_available() {
+                    nodes.push(Node::new(*grpc_addr, 0));
+                    socket_to_client.insert(*grpc_addr, client.clone());
+                }
+            }
+        }
+        distribute_jobs(nodes, socket_to_client, jobs)
+    }
+
+    /// Returns the addresses of the nodes in the same failure domain as `client`, including
+    /// `client` itself. See [`FAILURE_DOMAIN_ATTRIBUTE_KEY`](crate::FAILURE_DOMAIN_ATTRIBUTE_KEY).
+    pub async fn failure_domain_addresses(
+        &self,
+        client: &SearchServiceClient,
+    ) -> HashSet<SocketAddr> {
+        let mut addresses = HashSet::new();
+        addresses.insert(client.grpc_addr());
+        let node_attributes_opt = client.node_attributes();
+        let failure_domain_opt = node_attributes_opt
+            .as_ref()
+            .and_then(|node_attributes| failure_domain(node_attributes));
+        if let Some(client_failure_domain) = failure_domain_opt {
+            let clients = self.clients.read().await;
+            for (grpc_addr, other_client) in clients.iter() {
+                let is_same_failure_domain = other_client
+                    .node_attributes()
+                    .as_ref()
+                    .and_then(|node_attributes| failure_domain(node_attributes))
+                    == Some(client_failure_domain);
+                if is_same_failure_domain {
+                    addresses.insert(*grpc_addr);
+                }
+            }
+        }
+        addresses
+    }
+}
+
+// Periodically checks that the nodes whose circuit is not open answer a node info request,
+// and refreshes their attributes with the response. The task stops when the client pool is
+// dropped.
+fn spawn_health_checker(clients: Weak<RwLock<HashMap<SocketAddr, SearchServiceClient>>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let clients_to_check: Vec<SearchServiceClient> =
+                if let Some(clients) = clients.upgrade() {
+                    clients
+                        .read()
+                        .await
+                        .values()
+                        .filter(|client| client.circuit_breaker().state() != CircuitState::Open)
+                        .cloned()
+                        .collect()
+                } else {
+                    return;
+                };
+            for client in clients_to_check {
+                check_node_health(&client).await;
+            }
+        }
+    });
+}
+
+// Only a successful node info response within `HEALTH_CHECK_TIMEOUT` counts as a success: a
+// node accepting connections without answering, e.g. because it is overloaded or is not a
+// searcher, counts as a failure.
+async fn check_node_health(client: &SearchServiceClient) {
+    let grpc_addr = client.grpc_addr();
+    let node_info_result =
+        tokio::time::timeout(HEALTH_CHECK_TIMEOUT, client.clone().probe_node_info()).await;
+    match node_info_result {
+        Ok(Ok(node_info_response)) => {
+            if client.circuit_breaker().state() == CircuitState::HalfOpen {
+                info!(grpc_addr=?grpc_addr, "Searcher node is reachable again.");
+            }
+            client.circuit_breaker().record_success();
+            set_node_info(client, node_info_response);
+        }
+        Ok(Err(err)) => {
+            client.circuit_breaker().record_failure();
+            if client.circuit_breaker().state() == CircuitState::Open {
+                warn!(grpc_addr=?grpc_addr, err=?err, "Searcher node is unhealthy.");
+            }
+        }
+        Err(_) => {
+            client.circuit_breaker().record_failure();
+            if client.circuit_breaker().state() == CircuitState::Open {
+                warn!(grpc_addr=?grpc_addr, "Searcher node health check timed out.");
+            }
+        }
+    }
+}
+
+async fn fetch_node_attributes(client: &SearchServiceClient) {
+    let grpc_addr = client.grpc_addr();
+    let node_info_result = tokio::time::timeout(
+        HEALTH_CHECK_TIMEOUT,
+        client.clone().node_info(NodeInfoRequest {}),
+    )
+    .await;
+    match node_info_result {
+        Ok(Ok(node_info_response)) => set_node_info(client, node_info_response),
+        Ok(Err(err)) => {
+            warn!(grpc_addr=?grpc_addr, err=?err, "Failed to fetch searcher node attributes.")
+        }
+        Err(_) => warn!(grpc_addr=?grpc_addr, "Fetching searcher node attributes timed out."),
+    }
+}
+
+fn set_node_info(client: &SearchServiceClient, node_info_response: NodeInfoResponse) {
+    let grpc_addr = client.grpc_addr();
+    let node_attributes: NodeAttributes = node_info_response.attributes.into_iter().collect();
+    if client.node_attributes().is_none() {
+        info!(grpc_addr=?grpc_addr, node_attributes=?node_attributes, standby=node_info_response.standby, "Fetched searcher node attributes.");
+    } else if client.is_standby() && !node_info_response.standby {
+        info!(grpc_addr=?grpc_addr, "Standby searcher node was promoted.");
+    }
+    client.set_node_attributes(node_attributes);
+    client.set_standby(node_info_response.standby);
 }
 
 #[async_trait]
@@ -141,14 +351,14 @@ impl ClientPool for SearchClientPool {
     /// Assign the given job to the clients.
     /// Returns a list of pair (SocketAddr, Vec<Job>)
     ///
+    /// Only the clients satisfying the placement rules of the index are considered.
     /// When exclude_addresses filters all clients it is ignored.
     async fn assign_jobs(
         &self,
-        mut jobs: Vec<Job>,
+        index_id: &str,
+        jobs: Vec<Job>,
         mut exclude_addresses: &HashSet<SocketAddr>,
     ) -> anyhow::Result<Vec<(SearchServiceClient, Vec<Job>)>> {
-        let mut splits_groups: HashMap<SocketAddr, Vec<Job>> = HashMap::new();
-
         // Distribute using rendez-vous hashing
         let mut nodes: Vec<Node> = Vec::new();
         let mut socket_to_client: HashMap<SocketAddr, SearchServiceClient> = Default::default();
@@ -159,14 +369,49 @@ impl ClientPool for SearchClientPool {
             // TODO optimize the case where there are few jobs and many clients.
             let clients = self.clients.read().await;
 
+            let mut eligible_clients: Vec<(&SocketAddr, &SearchServiceClient)> = clients
+                .iter()
+                .filter(|(_, client)| {
+                    self.placement_rules
+                        .is_node_eligible(index_id, client.node_attributes().as_ref())
+                })
+                .collect();
+            if eligible_clients.is_empty() && self.placement_rules.has_rules_for(index_id) {
+                anyhow::bail!(
+                    "No searcher node satisfies the placement rules of index `{}`.",
+                    index_id
+                );
+            }
+            // Standby nodes only get shadow copies of the jobs, unless no active node can
+            // search the index.
+            if eligible_clients
+                .iter()
+                .any(|(_, client)| !client.is_standby())
+            {
+                eligible_clients.retain(|(_, client)| !client.is_standby());
+            }
+
+            let mut candidate_clients: Vec<(&SocketAddr, &SearchServiceClient)> = eligible_clients
+                .iter()
+                .filter(|(_, client)| client.circuit_breaker().is_available())
+                .cloned()
+                .collect();
+            // when all the nodes are unavailable, trying them anyway is better than failing
+            if candidate_clients.is_empty() {
+                candidate_clients = eligible_clients;
+            }
+
             let fallback = HashSet::default();
             // when exclude_addresses excludes all adresses we discard it
-            if exclude_addresses.len() == clients.len() {
+            if candidate_clients
+                .iter()
+                .all(|(grpc_addr, _)| exclude_addresses.contains(grpc_addr))
+            {
                 exclude_addresses = &fallback;
             }
 
-            for (grpc_addr, client) in clients
-                .iter()
+            for (grpc_addr, client) in candidate_clients
+                .into_iter()
                 .filter(|(grpc_addr, _)| !exclude_addresses.contains(grpc_addr))
             {
                 let node = Node::new(*grpc_addr, 0);
@@ -175,50 +420,64 @@ impl ClientPool for SearchClientPool {
             }
         }
 
-        // Sort job
-        jobs.sort_by(|left, right| {
-            let cost_ord = right.cost.cmp(&left.cost);
-            if cost_ord != Ordering::Equal {
-                return cost_ord;
-            }
-            left.split_id.cmp(&right.split_id)
-        });
+        Ok(distribute_jobs(nodes, socket_to_client, jobs))
+    }
+}
 
-        for job in jobs {
-            sort_by_rendez_vous_hash(&mut nodes, &job.split_id);
-            // choose one of the the first two nodes based on least loaded
-            let chosen_node_index: usize = if nodes.len() >= 2 {
-                if nodes[0].load > nodes[1].load {
-                    1
-                } else {
-                    0
-                }
+// Distributes the jobs among the nodes using rendez-vous hashing, balancing their load.
+fn distribute_jobs(
+    mut nodes: Vec<Node>,
+    mut socket_to_client: HashMap<SocketAddr, SearchServiceClient>,
+    mut jobs: Vec<Job>,
+) -> Vec<(SearchServiceClient, Vec<Job>)> {
+    if nodes.is_empty() {
+        return Vec::new();
+    }
+    let mut splits_groups: HashMap<SocketAddr, Vec<Job>> = HashMap::new();
+
+    // Sort job
+    jobs.sort_by(|left, right| {
+        let cost_ord = right.cost.cmp(&left.cost);
+        if cost_ord != Ordering::Equal {
+            return cost_ord;
+        }
+        left.split_id.cmp(&right.split_id)
+    });
+
+    for job in jobs {
+        sort_by_rendez_vous_hash(&mut nodes, &job.split_id);
+        // choose one of the the first two nodes based on least loaded
+        let chosen_node_index: usize = if nodes.len() >= 2 {
+            if nodes[0].load > nodes[1].load {
+                1
             } else {
                 0
-            };
+            }
+        } else {
+            0
+        };
 
-            // update node load for next round
-            nodes[chosen_node_index].load += job.cost as u64;
+        // update node load for next round
+        nodes[chosen_node_index].load += job.cost as u64;
 
-            let chosen_leaf_grpc_addr: SocketAddr = nodes[chosen_node_index].peer_grpc_addr;
-            splits_groups
-                .entry(chosen_leaf_grpc_addr)
-                .or_insert_with(Vec::new)
-                .push(job);
-        }
+        let chosen_leaf_grpc_addr: SocketAddr = nodes[chosen_node_index].peer_grpc_addr;
+        splits_groups
+            .entry(chosen_leaf_grpc_addr)
+            .or_insert_with(Vec::new)
+            .push(job);
+    }
 
-        let mut client_to_jobs = Vec::new();
-        for (socket_addr, jobs) in splits_groups {
-            // Removing the client in order to ensure a 1:1 cardinality on grpc_addr and clients
-            if let Some(client) = socket_to_client.remove(&socket_addr) {
-                client_to_jobs.push((client, jobs));
-            } else {
-                error!("Missing client. This should never happen! Please report");
-            }
+    let mut client_to_jobs = Vec::new();
+    for (socket_addr, jobs) in splits_groups {
+        // Removing the client in order to ensure a 1:1 cardinality on grpc_addr and clients
+        if let Some(client) = socket_to_client.remove(&socket_addr) {
+            client_to_jobs.push((client, jobs));
+        } else {
+            error!("Missing client. This should never happen! Please report");
         }
-
-        Ok(client_to_jobs)
     }
+
+    client_to_jobs
 }
 
 #[cfg(test)]
@@ -230,15 +489,23 @@ mod tests {
 
     use itertools::Itertools;
     use quickwit_cluster::cluster::create_cluster_for_test;
+    use quickwit_proto::{NodeInfoRequest, NodeInfoResponse};
+    use tokio::net::TcpListener;
 
+    use super::check_node_health;
+    use crate::client_pool::circuit_breaker::CircuitState;
     use crate::client_pool::search_client_pool::create_search_service_client;
     use crate::client_pool::{ClientPool, Job};
-    use crate::{swim_addr_to_grpc_addr, SearchClientPool};
+    use crate::{
+        swim_addr_to_grpc_addr, MockSearchService, NodeAttributes, PlacementRules,
+        SearchClientPool, SearchError, SearchServiceClient,
+    };
 
     #[tokio::test]
     async fn test_search_client_pool_single_node() -> anyhow::Result<()> {
         let cluster = Arc::new(create_cluster_for_test()?);
-        let client_pool = Arc::new(SearchClientPool::new(cluster.clone()).await?);
+        let client_pool =
+            Arc::new(SearchClientPool::new(cluster.clone(), PlacementRules::default()).await?);
         let clients = client_pool.clients.read().await;
 
         let addrs: Vec<SocketAddr> = clients.clone().into_keys().collect();
@@ -257,7 +524,8 @@ mod tests {
             .wait_for_members(|members| members.len() == 2, Duration::from_secs(5))
             .await?;
 
-        let client_pool = Arc::new(SearchClientPool::new(cluster1.clone()).await?);
+        let client_pool =
+            Arc::new(SearchClientPool::new(cluster1.clone(), PlacementRules::default()).await?);
         let clients = client_pool.clients.read().await;
 
         let addrs: Vec<SocketAddr> = clients.clone().into_keys().sorted().collect();
@@ -273,7 +541,8 @@ mod tests {
     #[tokio::test]
     async fn test_search_client_pool_single_node_assign_jobs() -> anyhow::Result<()> {
         let cluster = Arc::new(create_cluster_for_test()?);
-        let client_pool = Arc::new(SearchClientPool::new(cluster.clone()).await?);
+        let client_pool =
+            Arc::new(SearchClientPool::new(cluster.clone(), PlacementRules::default()).await?);
 
         let jobs = vec![
             Job {
@@ -294,7 +563,9 @@ mod tests {
             },
         ];
 
-        let assigned_jobs = client_pool.assign_jobs(jobs, &HashSet::default()).await?;
+        let assigned_jobs = client_pool
+            .assign_jobs("test-idx", jobs, &HashSet::default())
+            .await?;
         let expected_assigned_jobs = vec![(
             create_search_service_client(swim_addr_to_grpc_addr(cluster.listen_addr)).await?,
             vec![
@@ -322,4 +593,206 @@ mod tests {
         );
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_search_client_pool_assign_jobs_skips_open_circuits() -> anyhow::Result<()> {
+        let client_pool = SearchClientPool::from_mocks(vec![
+            Arc::new(MockSearchService::new()),
+            Arc::new(MockSearchService::new()),
+        ])
+        .await?;
+        let jobs: Vec<Job> = (0..10)
+            .map(|split_ord| Job {
+                split_id: format!("split{}", split_ord),
+                cost: 1,
+            })
+            .collect();
+        let healthy_addr = {
+            let clients = client_pool.clients.read().await;
+            let addrs: Vec<SocketAddr> = clients.keys().cloned().sorted().collect();
+            for _ in 0..3 {
+                clients[&addrs[0]].circuit_breaker().record_failure();
+            }
+            addrs[1]
+        };
+
+        let assigned_jobs = client_pool
+            .assign_jobs("test-idx", jobs.clone(), &HashSet::default())
+            .await?;
+        assert_eq!(assigned_jobs.len(), 1);
+        assert_eq!(assigned_jobs[0].0.grpc_addr(), healthy_addr);
+        assert_eq!(assigned_jobs[0].1.len(), 10);
+
+        // When all the circuits are open, jobs are still assigned.
+        {
+            let clients = client_pool.clients.read().await;
+            for _ in 0..3 {
+                clients[&healthy_addr].circuit_breaker().record_failure();
+            }
+        }
+        let assigned_jobs = client_pool
+            .assign_jobs("test-idx", jobs, &HashSet::default())
+            .await?;
+        let num_assigned_jobs: usize = assigned_jobs.iter().map(|(_, jobs)| jobs.len()).sum();
+        assert_eq!(num_assigned_jobs, 10);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_client_pool_assign_jobs_with_placement_rules() -> anyhow::Result<()> {
+        let mut client_pool = SearchClientPool::from_mocks(vec![
+            Arc::new(MockSearchService::new()),
+            Arc::new(MockSearchService::new()),
+        ])
+        .await?;
+        client_pool.placement_rules = Arc::new(PlacementRules::new(vec![
+            "logs-*:tier=hot".parse()?,
+            "archive:tier=cold".parse()?,
+        ]));
+        let hot_addr = {
+            let clients = client_pool.clients.read().await;
+            let addrs: Vec<SocketAddr> = clients.keys().cloned().sorted().collect();
+            let hot_node_attributes: NodeAttributes = vec![("tier".to_string(), "hot".to_string())]
+                .into_iter()
+                .collect();
+            clients[&addrs[0]].set_node_attributes(hot_node_attributes);
+            // The attributes of the second node are unknown.
+            addrs[0]
+        };
+        let jobs: Vec<Job> = (0..10)
+            .map(|split_ord| Job {
+                split_id: format!("split{}", split_ord),
+                cost: 1,
+            })
+            .collect();
+
+        let assigned_jobs = client_pool
+            .assign_jobs("logs-2021", jobs.clone(), &HashSet::default())
+            .await?;
+        assert_eq!(assigned_jobs.len(), 1);
+        assert_eq!(assigned_jobs[0].0.grpc_addr(), hot_addr);
+        assert_eq!(assigned_jobs[0].1.len(), 10);
+
+        let assigned_jobs = client_pool
+            .assign_jobs("other", jobs.clone(), &HashSet::default())
+            .await?;
+        assert_eq!(assigned_jobs.len(), 2);
+
+        assert!(client_pool
+            .assign_jobs("archive", jobs, &HashSet::default())
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_client_pool_assign_jobs_with_standby_node() -> anyhow::Result<()> {
+        let client_pool = SearchClientPool::from_mocks(vec![
+            Arc::new(MockSearchService::new()),
+            Arc::new(MockSearchService::new()),
+        ])
+        .await?;
+        let (active_addr, standby_addr) = {
+            let clients = client_pool.clients.read().await;
+            let addrs: Vec<SocketAddr> = clients.keys().cloned().sorted().collect();
+            clients[&addrs[1]].set_standby(true);
+            (addrs[0], addrs[1])
+        };
+        let jobs: Vec<Job> = (0..10)
+            .map(|split_ord| Job {
+                split_id: format!("split{}", split_ord),
+                cost: 1,
+            })
+            .collect();
+
+        let assigned_jobs = client_pool
+            .assign_jobs("test-idx", jobs.clone(), &HashSet::default())
+            .await?;
+        assert_eq!(assigned_jobs.len(), 1);
+        assert_eq!(assigned_jobs[0].0.grpc_addr(), active_addr);
+        assert_eq!(assigned_jobs[0].1.len(), 10);
+
+        let shadow_jobs = client_pool
+            .assign_shadow_jobs("test-idx", jobs.clone())
+            .await;
+        assert_eq!(shadow_jobs.len(), 1);
+        assert_eq!(shadow_jobs[0].0.grpc_addr(), standby_addr);
+        assert_eq!(shadow_jobs[0].1.len(), 10);
+
+        // When all the nodes are standby nodes, they get the actual jobs.
+        {
+            let clients = client_pool.clients.read().await;
+            clients[&active_addr].set_standby(true);
+        }
+        let assigned_jobs = client_pool
+            .assign_jobs("test-idx", jobs.clone(), &HashSet::default())
+            .await?;
+        assert_eq!(assigned_jobs.len(), 2);
+        assert!(client_pool
+            .assign_shadow_jobs("test-idx", jobs)
+            .await
+            .is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_node_health_records_node_info_outcome() {
+        let grpc_addr: SocketAddr = ([127, 0, 0, 1], 10_000).into();
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_node_info()
+            .times(1)
+            .returning(|_request: NodeInfoRequest| {
+                Ok(NodeInfoResponse {
+                    attributes: vec![("tier".to_string(), "hot".to_string())]
+                        .into_iter()
+                        .collect(),
+                    standby: true,
+                })
+            });
+        let client = SearchServiceClient::from_service(Arc::new(mock_search_service), grpc_addr);
+        check_node_health(&client).await;
+        assert_eq!(client.circuit_breaker().state(), CircuitState::Closed);
+        let expected_node_attributes: NodeAttributes =
+            vec![("tier".to_string(), "hot".to_string())]
+                .into_iter()
+                .collect();
+        assert_eq!(client.node_attributes(), Some(expected_node_attributes));
+        assert!(client.is_standby());
+
+        // A node answering with an error is not healthy.
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_node_info()
+            .times(3)
+            .returning(|_request: NodeInfoRequest| {
+                Err(SearchError::InternalError(
+                    "Node is shutting down.".to_string(),
+                ))
+            });
+        let client = SearchServiceClient::from_service(Arc::new(mock_search_service), grpc_addr);
+        for _ in 0..3 {
+            check_node_health(&client).await;
+        }
+        assert_eq!(client.circuit_breaker().state(), CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn test_check_node_health_fails_on_node_accepting_connections_only() -> anyhow::Result<()>
+    {
+        // The node accepts the connections, but closes them without serving any gRPC request.
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let grpc_addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            while let Ok((tcp_stream, _)) = listener.accept().await {
+                drop(tcp_stream);
+            }
+        });
+        let client = create_search_service_client(grpc_addr).await?;
+        for _ in 0..3 {
+            check_node_health(&client).await;
+        }
+        assert_eq!(client.circuit_breaker().state(), CircuitState::Open);
+        Ok(())
+    }
 }
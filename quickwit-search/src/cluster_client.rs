@@ -18,6 +18,7 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use std::sync::Arc;
+use std::time::Instant;
 
 use futures::StreamExt;
 use quickwit_proto::{
@@ -25,13 +26,17 @@ use quickwit_proto::{
     LeafSearchStreamRequest, LeafSearchStreamResult,
 };
 use tokio::sync::mpsc::error::SendError;
-use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
-use tokio_stream::wrappers::UnboundedReceiverStream;
 use tracing::debug;
 
+use crate::hedging::{HedgingParams, HedgingPolicy};
 use crate::retry::search::LeafSearchRetryPolicy;
 use crate::retry::search_stream::{LeafSearchStreamRetryPolicy, SuccessfullSplitIds};
 use crate::retry::{retry_client, DefaultRetryPolicy, RetryPolicy};
+use crate::search_stream::{
+    result_channel, spawn_until_receiver_dropped, ResultReceiverStream, ResultSender,
+};
+use crate::split_quarantine::{SplitFailureTracker, DEFAULT_SPLIT_QUARANTINE_THRESHOLD};
+use crate::tree_aggregation::DEFAULT_TREE_AGGREGATION_MIN_NODES;
 use crate::{SearchClientPool, SearchError, SearchServiceClient};
 
 /// Client that executes placed requests (Request, `SearchServiceClient`) and provides
@@ -40,12 +45,73 @@ use crate::{SearchClientPool, SearchError, SearchServiceClient};
 #[derive(Clone)]
 pub struct ClusterClient {
     client_pool: Arc<SearchClientPool>,
+    hedging_policy_opt: Option<HedgingPolicy>,
+    realtime_clients: Vec<SearchServiceClient>,
+    tree_aggregation_min_nodes: usize,
+    split_failure_tracker: SplitFailureTracker,
 }
 
 impl ClusterClient {
     /// Instantiates [`ClusterClient`].
     pub fn new(client_pool: Arc<SearchClientPool>) -> Self {
-        Self { client_pool }
+        Self {
+            client_pool,
+            hedging_policy_opt: None,
+            realtime_clients: Vec::new(),
+            tree_aggregation_min_nodes: DEFAULT_TREE_AGGREGATION_MIN_NODES,
+            split_failure_tracker: SplitFailureTracker::new(DEFAULT_SPLIT_QUARANTINE_THRESHOLD),
+        }
+    }
+
+    /// Instantiates a [`ClusterClient`] that hedges slow leaf searches.
+    /// See [`HedgingParams`].
+    pub fn with_hedging(client_pool: Arc<SearchClientPool>, hedging_params: HedgingParams) -> Self {
+        Self {
+            client_pool,
+            hedging_policy_opt: Some(HedgingPolicy::new(hedging_params)),
+            realtime_clients: Vec::new(),
+            tree_aggregation_min_nodes: DEFAULT_TREE_AGGREGATION_MIN_NODES,
+            split_failure_tracker: SplitFailureTracker::new(DEFAULT_SPLIT_QUARANTINE_THRESHOLD),
+        }
+    }
+
+    /// Also searches the splits being indexed by the indexers of `realtime_clients` in
+    /// root searches, see [`SearchService::leaf_search_realtime`](crate::SearchService).
+    pub fn with_realtime_clients(mut self, realtime_clients: Vec<SearchServiceClient>) -> Self {
+        self.realtime_clients = realtime_clients;
+        self
+    }
+
+    /// Returns the clients of the indexers whose splits being indexed are searched.
+    pub fn realtime_clients(&self) -> &[SearchServiceClient] {
+        &self.realtime_clients
+    }
+
+    /// Merges the leaf responses of the root searches on intermediate nodes when they are sent
+    /// to more than `tree_aggregation_min_nodes` nodes, see
+    /// [`SearchService::intermediate_search`](crate::SearchService).
+    pub fn with_tree_aggregation_min_nodes(mut self, tree_aggregation_min_nodes: usize) -> Self {
+        self.tree_aggregation_min_nodes = tree_aggregation_min_nodes;
+        self
+    }
+
+    /// Returns the number of leaf nodes above which the leaf responses are merged on
+    /// intermediate nodes.
+    pub fn tree_aggregation_min_nodes(&self) -> usize {
+        self.tree_aggregation_min_nodes
+    }
+
+    /// Quarantines the splits failing `split_quarantine_threshold` consecutive root searches,
+    /// while the other splits of the searches succeed. A threshold of 0 disables it.
+    /// See [`SplitState::Quarantined`](quickwit_metastore::SplitState::Quarantined).
+    pub fn with_split_quarantine_threshold(mut self, split_quarantine_threshold: usize) -> Self {
+        self.split_failure_tracker = SplitFailureTracker::new(split_quarantine_threshold);
+        self
+    }
+
+    /// Returns the tracker of the splits failing repeatedly.
+    pub(crate) fn split_failure_tracker(&self) -> &SplitFailureTracker {
+        &self.split_failure_tracker
     }
 
     /// Fetches docs with retry on another node client.
@@ -73,7 +139,12 @@ impl ClusterClient {
         placed_request: (LeafSearchRequest, SearchServiceClient),
     ) -> crate::Result<LeafSearchResponse> {
         let (request, mut client) = placed_request;
-        let mut result = client.leaf_search(request.clone()).await;
+        let mut result = if let Some(hedging_policy) = &self.hedging_policy_opt {
+            self.hedged_leaf_search(hedging_policy, request.clone(), &client)
+                .await
+        } else {
+            leaf_search_on_client(client.clone(), request.clone()).await
+        };
         let retry_policy = LeafSearchRetryPolicy {};
         if let Some(retry_request) = retry_policy.retry_request(&request, result.as_ref()) {
             client = retry_client(&self.client_pool, &client, &retry_request).await?;
@@ -81,25 +152,79 @@ impl ClusterClient {
                 "Leaf search response error: `{:?}`. Retry once to execute {:?} with {:?}",
                 result, retry_request, client
             );
-            let retry_result = client.leaf_search(retry_request).await;
+            let retry_result = leaf_search_on_client(client.clone(), retry_request).await;
             result = merge_leaf_search_results(result, retry_result);
         }
         result
     }
 
+    // Sends the leaf search to `client`, and to a second node if `client` has not
+    // responded after the hedging delay. Returns the first successful response.
+    async fn hedged_leaf_search(
+        &self,
+        hedging_policy: &HedgingPolicy,
+        request: LeafSearchRequest,
+        client: &SearchServiceClient,
+    ) -> crate::Result<LeafSearchResponse> {
+        let start = Instant::now();
+        let primary_search = leaf_search_on_client(client.clone(), request.clone());
+        tokio::pin!(primary_search);
+        tokio::select! {
+            primary_result = &mut primary_search => {
+                hedging_policy.record_latency(start.elapsed());
+                return primary_result;
+            }
+            _ = tokio::time::sleep(hedging_policy.hedging_delay()) => {}
+        }
+        let hedge_client = match retry_client(&self.client_pool, client, &request).await {
+            Ok(hedge_client) if hedge_client.grpc_addr() != client.grpc_addr() => hedge_client,
+            _ => {
+                // There is no other node to send the hedged request to.
+                let primary_result = primary_search.await;
+                hedging_policy.record_latency(start.elapsed());
+                return primary_result;
+            }
+        };
+        debug!(
+            "Leaf search on {:?} is slow. Hedging the request with {:?}",
+            client, hedge_client
+        );
+        let hedge_search = leaf_search_on_client(hedge_client, request);
+        tokio::pin!(hedge_search);
+        // The latency of the slow request is at least the elapsed time when the other one wins.
+        let result = tokio::select! {
+            primary_result = &mut primary_search => {
+                if primary_result.is_ok() {
+                    primary_result
+                } else {
+                    hedge_search.await
+                }
+            }
+            hedge_result = &mut hedge_search => {
+                if hedge_result.is_ok() {
+                    hedge_result
+                } else {
+                    primary_search.await
+                }
+            }
+        };
+        hedging_policy.record_latency(start.elapsed());
+        result
+    }
+
     /// Leaf search stream with retry on another node client.
     pub async fn leaf_search_stream(
         &self,
         placed_request: (LeafSearchStreamRequest, SearchServiceClient),
-    ) -> UnboundedReceiverStream<crate::Result<LeafSearchStreamResult>> {
+    ) -> ResultReceiverStream {
         let (request, mut client) = placed_request;
         // We need a dedicated channel to send results with retry. First we send only the successful
         // responses and and ignore errors. If there are some errors, we make one retry and
         // in this case we send all results.
-        let (result_sender, result_receiver) = unbounded_channel();
+        let (result_sender, result_receiver) = result_channel();
         let client_pool = self.client_pool.clone();
         let retry_policy = LeafSearchStreamRetryPolicy {};
-        tokio::spawn(async move {
+        spawn_until_receiver_dropped(result_sender, |result_sender| async move {
             let result_stream = client.leaf_search_stream(request.clone()).await;
             // Forward only responses and not errors to the sender as we will make one retry on
             // errors.
@@ -111,7 +236,7 @@ impl ClusterClient {
                 let retry_client_opt = retry_client(&client_pool, &client, &retry_request).await;
                 // Propagates the error if we cannot get a new client and stops the task.
                 if let Err(error) = retry_client_opt {
-                    let _ = result_sender.send(Err(SearchError::from(error)));
+                    let _ = result_sender.send(Err(SearchError::from(error))).await;
                     return;
                 }
                 let mut retry_client = retry_client_opt.unwrap();
@@ -128,8 +253,22 @@ impl ClusterClient {
             }
         });
 
-        UnboundedReceiverStream::new(result_receiver)
+        result_receiver
+    }
+}
+
+// Sends the leaf search to `client`, and records the node in the partial hits it returns so
+// that the root fetches their documents from the node that has the split cached.
+async fn leaf_search_on_client(
+    mut client: SearchServiceClient,
+    request: LeafSearchRequest,
+) -> crate::Result<LeafSearchResponse> {
+    let mut response = client.leaf_search(request).await?;
+    let leaf_grpc_addr = client.grpc_addr().to_string();
+    for partial_hit in response.partial_hits.iter_mut() {
+        partial_hit.leaf_grpc_addr = leaf_grpc_addr.clone();
     }
+    Ok(response)
 }
 
 // Merge initial leaf search results with results obtained from a retry.
@@ -142,12 +281,29 @@ fn merge_leaf_search_results(
             initial_response
                 .partial_hits
                 .append(&mut retry_response.partial_hits);
+            // Sketches of the same field are merged by the root.
+            initial_response
+                .distinct_count_sketches
+                .append(&mut retry_response.distinct_count_sketches);
+            initial_response
+                .percentile_sketches
+                .append(&mut retry_response.percentile_sketches);
+            initial_response
+                .terms_buckets
+                .append(&mut retry_response.terms_buckets);
+            initial_response
+                .date_histogram_buckets
+                .append(&mut retry_response.date_histogram_buckets);
             let merged_response = LeafSearchResponse {
                 num_hits: initial_response.num_hits + retry_response.num_hits,
                 num_attempted_splits: initial_response.num_attempted_splits
                     + retry_response.num_attempted_splits,
                 failed_splits: retry_response.failed_splits,
                 partial_hits: initial_response.partial_hits,
+                distinct_count_sketches: initial_response.distinct_count_sketches,
+                percentile_sketches: initial_response.percentile_sketches,
+                terms_buckets: initial_response.terms_buckets,
+                date_histogram_buckets: initial_response.date_histogram_buckets,
             };
             Ok(merged_response)
         }
@@ -162,8 +318,8 @@ fn merge_leaf_search_results(
 // If `send_error` is false, errors are ignored and not forwarded. This is
 // useful if you want to make a retry before propagating errors.
 async fn forward_leaf_search_stream(
-    mut stream: UnboundedReceiverStream<crate::Result<LeafSearchStreamResult>>,
-    sender: UnboundedSender<Result<LeafSearchStreamResult, SearchError>>,
+    mut stream: ResultReceiverStream,
+    sender: ResultSender,
     send_error: bool,
 ) -> Result<SuccessfullSplitIds, SendError<crate::Result<LeafSearchStreamResult>>> {
     let mut successful_split_ids: Vec<String> = Vec::new();
@@ -171,11 +327,11 @@ async fn forward_leaf_search_stream(
         match result {
             Ok(response) => {
                 successful_split_ids.push(response.split_id.clone());
-                sender.send(Ok(response))?;
+                sender.send(Ok(response)).await?;
             }
             Err(error) => {
                 if send_error {
-                    sender.send(Err(error))?;
+                    sender.send(Err(error)).await?;
                 }
             }
         }
@@ -188,6 +344,7 @@ mod tests {
     use std::collections::HashSet;
     use std::net::SocketAddr;
     use std::sync::Arc;
+    use std::time::{Duration, Instant};
 
     use futures::StreamExt;
     use quickwit_proto::{
@@ -195,11 +352,14 @@ mod tests {
         LeafSearchStreamResult, PartialHit, SearchRequest, SearchStreamRequest,
         SplitIdAndFooterOffsets, SplitSearchError,
     };
-    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tokio::net::TcpListener;
 
     use crate::client_pool::Job;
     use crate::cluster_client::{merge_leaf_search_results, ClusterClient};
-    use crate::{ClientPool, MockSearchService, SearchClientPool, SearchError};
+    use crate::{
+        create_search_service_client, result_channel, ClientPool, HedgingParams, MockSearchService,
+        SearchClientPool, SearchError,
+    };
 
     fn mock_partial_hit(split_id: &str, sorting_field_value: u64, doc_id: u32) -> PartialHit {
         PartialHit {
@@ -207,6 +367,7 @@ mod tests {
             split_id: split_id.to_string(),
             segment_ord: 1,
             doc_id,
+            leaf_grpc_addr: String::new(),
         }
     }
 
@@ -218,8 +379,16 @@ mod tests {
             split_metadata: vec![SplitIdAndFooterOffsets {
                 split_id: split_id.to_string(),
                 split_footer_end: 100,
+                time_range_end: None,
+                split_file: String::new(),
                 split_footer_start: 0,
+                split_part_num_bytes: None,
             }],
+            index_config_version: 0,
+            mirror_index_uri: String::new(),
+            index_config_fingerprint: 0,
+            flatten_docs: false,
+            cache_policy: 0,
         }
     }
 
@@ -233,21 +402,47 @@ mod tests {
             max_hits: 10,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         LeafSearchRequest {
             search_request: Some(search_request),
-            index_config: "config".to_string(),
+            index_config: String::new(),
             index_uri: "uri".to_string(),
+            index_config_version: 0,
+            mirror_index_uri: String::new(),
+            index_config_fingerprint: 0,
             split_metadata: vec![
                 SplitIdAndFooterOffsets {
                     split_id: "split_1".to_string(),
                     split_footer_start: 0,
                     split_footer_end: 100,
+                    time_range_end: None,
+                    split_file: String::new(),
+                    split_part_num_bytes: None,
                 },
                 SplitIdAndFooterOffsets {
                     split_id: "split_2".to_string(),
                     split_footer_start: 0,
                     split_footer_end: 100,
+                    time_range_end: None,
+                    split_file: String::new(),
+                    split_part_num_bytes: None,
                 },
             ],
         }
@@ -264,21 +459,37 @@ mod tests {
             output_format: 0,
             partition_by_field: None,
             tags: vec![],
+            distinct_count: false,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: Vec::new(),
+            cache_policy: 0,
         };
         LeafSearchStreamRequest {
             request: Some(search_request),
-            index_config: "config".to_string(),
+            index_config: String::new(),
             index_uri: "uri".to_string(),
+            index_config_version: 0,
+            mirror_index_uri: String::new(),
+            index_config_fingerprint: 0,
             split_metadata: vec![
                 SplitIdAndFooterOffsets {
                     split_id: "split_1".to_string(),
                     split_footer_start: 0,
                     split_footer_end: 100,
+                    time_range_end: None,
+                    split_file: String::new(),
+                    split_part_num_bytes: None,
                 },
                 SplitIdAndFooterOffsets {
                     split_id: "split_2".to_string(),
                     split_footer_start: 0,
                     split_footer_end: 100,
+                    time_range_end: None,
+                    split_file: String::new(),
+                    split_part_num_bytes: None,
                 },
             ],
         }
@@ -297,6 +508,7 @@ mod tests {
             Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_service)]).await?);
         let first_client = client_pool
             .assign_job(
+                "test-idx",
                 Job {
                     split_id: "split_1".to_string(),
                     cost: 0,
@@ -374,12 +586,17 @@ mod tests {
                     partial_hits: vec![],
                     failed_splits: vec![],
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             });
         let client_pool =
             Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_service)]).await?);
         let first_client = client_pool
             .assign_job(
+                "test-idx",
                 Job {
                     split_id: "split_1".to_string(),
                     cost: 0,
@@ -410,6 +627,10 @@ mod tests {
                         retryable_error: true,
                     }],
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             });
         mock_service
@@ -425,12 +646,17 @@ mod tests {
                         retryable_error: true,
                     }],
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             });
         let client_pool =
             Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_service)]).await?);
         let first_client = client_pool
             .assign_job(
+                "test-idx",
                 Job {
                     split_id: "split_1".to_string(),
                     cost: 0,
@@ -445,6 +671,39 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_cluster_client_leaf_search_records_leaf_node() -> anyhow::Result<()> {
+        let mut mock_service = MockSearchService::new();
+        mock_service
+            .expect_leaf_search()
+            .return_once(|_: LeafSearchRequest| {
+                Ok(LeafSearchResponse {
+                    num_hits: 1,
+                    partial_hits: vec![mock_partial_hit("split_1", 3, 1)],
+                    ..Default::default()
+                })
+            });
+        let client_pool =
+            Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_service)]).await?);
+        let first_client = client_pool
+            .assign_job(
+                "test-idx",
+                Job {
+                    split_id: "split_1".to_string(),
+                    cost: 0,
+                },
+                &HashSet::new(),
+            )
+            .await?;
+        let leaf_grpc_addr = first_client.grpc_addr().to_string();
+        let cluster_client = ClusterClient::new(client_pool);
+        let response = cluster_client
+            .leaf_search((mock_leaf_search_request(), first_client))
+            .await?;
+        assert_eq!(response.partial_hits[0].leaf_grpc_addr, leaf_grpc_addr);
+        Ok(())
+    }
+
     #[test]
     fn test_merge_leaf_search_retry_on_partial_success() -> anyhow::Result<()> {
         let split_error = SplitSearchError {
@@ -457,12 +716,20 @@ mod tests {
             partial_hits: vec![mock_partial_hit("split_1", 3, 1)],
             failed_splits: vec![split_error],
             num_attempted_splits: 1,
+            distinct_count_sketches: vec![],
+            percentile_sketches: vec![],
+            terms_buckets: vec![],
+            date_histogram_buckets: vec![],
         };
         let leaf_response_retry = LeafSearchResponse {
             num_hits: 1,
             partial_hits: vec![mock_partial_hit("split_2", 3, 1)],
             failed_splits: vec![],
             num_attempted_splits: 1,
+            distinct_count_sketches: vec![],
+            percentile_sketches: vec![],
+            terms_buckets: vec![],
+            date_histogram_buckets: vec![],
         };
         let merged_leaf_search_response =
             merge_leaf_search_results(Ok(leaf_response), Ok(leaf_response_retry)).unwrap();
@@ -485,6 +752,10 @@ mod tests {
             partial_hits: vec![mock_partial_hit("split_1", 3, 1)],
             failed_splits: vec![split_error],
             num_attempted_splits: 1,
+            distinct_count_sketches: vec![],
+            percentile_sketches: vec![],
+            terms_buckets: vec![],
+            date_histogram_buckets: vec![],
         };
         let merged_result = merge_leaf_search_results(
             Err(SearchError::InternalError("error".to_string())),
@@ -517,21 +788,25 @@ mod tests {
             .expect_leaf_search_stream()
             .return_once(|_| Err(SearchError::InternalError("error".to_string())));
         let mut mock_service_2 = MockSearchService::new();
-        let (result_sender, result_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let (result_sender, result_receiver) = result_channel();
         mock_service_2
             .expect_leaf_search_stream()
-            .return_once(|_| Ok(UnboundedReceiverStream::new(result_receiver)));
+            .return_once(|_| Ok(result_receiver));
         let client_pool = Arc::new(
             SearchClientPool::from_mocks(vec![Arc::new(mock_service_1), Arc::new(mock_service_2)])
                 .await?,
         );
-        result_sender.send(Ok(LeafSearchStreamResult {
-            data: Vec::new(),
-            split_id: "split_1".to_string(),
-        }))?;
-        result_sender.send(Err(SearchError::InternalError(
-            "last split error".to_string(),
-        )))?;
+        result_sender
+            .send(Ok(LeafSearchStreamResult {
+                data: Vec::new(),
+                split_id: "split_1".to_string(),
+            }))
+            .await?;
+        result_sender
+            .send(Err(SearchError::InternalError(
+                "last split error".to_string(),
+            )))
+            .await?;
         drop(result_sender);
         let client_hashmap = client_pool.clients.read().await;
         let first_grpc_addr: SocketAddr = "127.0.0.1:10000".parse()?;
@@ -545,4 +820,35 @@ mod tests {
         assert!(results[0].is_ok());
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_cluster_client_leaf_search_hedging() -> anyhow::Result<()> {
+        // The slow node accepts connections but never answers them.
+        let slow_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let slow_client = create_search_service_client(slow_listener.local_addr()?).await?;
+        let mut fast_service = MockSearchService::new();
+        fast_service
+            .expect_leaf_search()
+            .returning(|_: LeafSearchRequest| {
+                Ok(LeafSearchResponse {
+                    num_hits: 2,
+                    ..Default::default()
+                })
+            });
+        let client_pool =
+            Arc::new(SearchClientPool::from_mocks(vec![Arc::new(fast_service)]).await?);
+        let hedging_params = HedgingParams {
+            percentile: 0.95,
+            min_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(10),
+        };
+        let cluster_client = ClusterClient::with_hedging(client_pool.clone(), hedging_params);
+        let start = Instant::now();
+        let leaf_search_response = cluster_client
+            .leaf_search((mock_leaf_search_request(), slow_client))
+            .await?;
+        assert_eq!(leaf_search_response.num_hits, 2);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        Ok(())
+    }
 }
@@ -0,0 +1,225 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use quickwit_metastore::{
+    retry_on_transient_error, MetadataSet, Metastore, SingleFileMetastore, SplitState,
+};
+use quickwit_storage::{multipart_file_storage, StorageUriResolver};
+use tracing::info;
+
+use crate::leaf::put_split_footer_in_cache;
+
+/// File of a cold start bundle holding the snapshot of the metastore.
+const METASTORE_SNAPSHOT_FILENAME: &str = "metastore-snapshot.json";
+
+/// Directory of a cold start bundle holding the hotcaches and footers of the splits.
+const HOTCACHES_DIRNAME: &str = "hotcaches";
+
+const HOTCACHE_FILE_EXTENSION: &str = "hotcache";
+
+/// Options minimizing the work done by [`crate::SearcherHandle::open_with_profile`] before the
+/// first search, for short-lived searchers such as serverless functions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ColdStartProfile {
+    /// Defers the creation of the storage clients until their first use, see
+    /// [`StorageUriResolver::lazy`].
+    pub lazy_storage: bool,
+    /// Directory of a bundle written by [`write_cold_start_bundle`], typically shipped with
+    /// the deployment artifact of the searcher.
+    pub bundle_dir_opt: Option<PathBuf>,
+}
+
+/// Writes a cold start bundle of the indexes `index_ids` into `bundle_dir`.
+///
+/// The bundle holds a snapshot of the metadata of the indexes and of their published splits,
+/// and, if `include_hotcaches` is set, the hotcache and footer of each of these splits. It
+/// must be written again whenever splits are published or deleted, as the searchers loading
+/// it do not see these changes.
+pub async fn write_cold_start_bundle(
+    metastore: &dyn Metastore,
+    storage_resolver: &StorageUriResolver,
+    index_ids: &[String],
+    include_hotcaches: bool,
+    bundle_dir: &Path,
+) -> anyhow::Result<()> {
+    let hotcaches_dir = bundle_dir.join(HOTCACHES_DIRNAME);
+    tokio::fs::create_dir_all(&hotcaches_dir).await?;
+    let mut metadata_sets = Vec::with_capacity(index_ids.len());
+    for index_id in index_ids {
+        let index_metadata =
+            retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+        let splits = retry_on_transient_error(|| {
+            metastore.list_splits(index_id, SplitState::Published, None, &[])
+        })
+        .await?;
+        if include_hotcaches {
+            let index_storage = storage_resolver.resolve(&index_metadata.index_uri)?;
+            for split in &splits {
+                let split_storage = multipart_file_storage(
+                    index_storage.clone(),
+                    split.split_metadata.split_part_num_bytes,
+                );
+                let footer_data = split_storage
+                    .get_slice(
+                        Path::new(&split.split_metadata.split_file()),
+                        split.footer_offsets.start as usize..split.footer_offsets.end as usize,
+                    )
+                    .await?;
+                let hotcache_path = hotcaches_dir
+                    .join(&split.split_metadata.split_id)
+                    .with_extension(HOTCACHE_FILE_EXTENSION);
+                tokio::fs::write(hotcache_path, footer_data).await?;
+            }
+        }
+        let splits = splits
+            .into_iter()
+            .map(|split| (split.split_metadata.split_id.clone(), split))
+            .collect::<HashMap<_, _>>();
+        metadata_sets.push(MetadataSet {
+            index: index_metadata,
+            splits,
+        });
+    }
+    let snapshot = serde_json::to_vec(&metadata_sets)?;
+    tokio::fs::write(bundle_dir.join(METASTORE_SNAPSHOT_FILENAME), snapshot).await?;
+    Ok(())
+}
+
+/// Loads the cold start bundle in `bundle_dir`, see [`write_cold_start_bundle`].
+///
+/// The hotcaches of the bundle are placed in the split footer cache, and the returned
+/// metastore serves the snapshot of the bundle. It replaces the file-backed metastore at
+/// `metastore_uri`, and never reads its files.
+pub fn load_cold_start_bundle(
+    bundle_dir: &Path,
+    metastore_uri: &str,
+    storage_resolver: &StorageUriResolver,
+) -> anyhow::Result<Arc<dyn Metastore>> {
+    let snapshot_path = bundle_dir.join(METASTORE_SNAPSHOT_FILENAME);
+    let snapshot = fs::read(&snapshot_path).with_context(|| {
+        format!(
+            "Failed to read the metastore snapshot `{}`.",
+            snapshot_path.display()
+        )
+    })?;
+    let metadata_sets: Vec<MetadataSet> = serde_json::from_slice(&snapshot)?;
+    let hotcaches_dir = bundle_dir.join(HOTCACHES_DIRNAME);
+    let mut num_hotcaches = 0;
+    if hotcaches_dir.exists() {
+        for dir_entry_res in fs::read_dir(&hotcaches_dir)? {
+            let hotcache_path = dir_entry_res?.path();
+            if hotcache_path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                != Some(HOTCACHE_FILE_EXTENSION)
+            {
+                continue;
+            }
+            let split_id = match hotcache_path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(split_id) => split_id.to_string(),
+                None => continue,
+            };
+            put_split_footer_in_cache(split_id, fs::read(&hotcache_path)?.into());
+            num_hotcaches += 1;
+        }
+    }
+    info!(
+        bundle_dir = %bundle_dir.display(),
+        num_indexes = metadata_sets.len(),
+        num_hotcaches = num_hotcaches,
+        "Loaded cold start bundle."
+    );
+    let metastore_storage = storage_resolver.resolve(metastore_uri)?;
+    Ok(Arc::new(SingleFileMetastore::with_snapshot(
+        metastore_storage,
+        metadata_sets,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_indexing::TestSandbox;
+    use quickwit_proto::SearchRequest;
+    use serde_json::json;
+
+    use super::*;
+    use crate::SearcherHandle;
+
+    #[tokio::test]
+    async fn test_cold_start_bundle() -> anyhow::Result<()> {
+        let index_id = "cold-start-bundle";
+        let test_sandbox =
+            TestSandbox::create(index_id, Arc::new(WikipediaIndexConfig::new())).await?;
+        let docs = vec![
+            json!({"title": "snoopy", "body": "snoopy is a beagle", "url": "http://snoopy"}),
+            json!({"title": "beagle", "body": "the beagle is a dog", "url": "http://beagle"}),
+        ];
+        test_sandbox.add_documents(docs).await?;
+        let splits = test_sandbox
+            .metastore()
+            .list_splits(index_id, SplitState::Published, None, &[])
+            .await?;
+        assert_eq!(splits.len(), 1);
+
+        let bundle_dir = tempfile::tempdir()?;
+        write_cold_start_bundle(
+            &*test_sandbox.metastore(),
+            &test_sandbox.storage_uri_resolver(),
+            &[index_id.to_string()],
+            true,
+            bundle_dir.path(),
+        )
+        .await?;
+        assert!(bundle_dir.path().join(METASTORE_SNAPSHOT_FILENAME).exists());
+        let hotcache_path = bundle_dir
+            .path()
+            .join(HOTCACHES_DIRNAME)
+            .join(&splits[0].split_metadata.split_id)
+            .with_extension(HOTCACHE_FILE_EXTENSION);
+        assert_eq!(
+            fs::metadata(hotcache_path)?.len(),
+            splits[0].footer_offsets.end - splits[0].footer_offsets.start
+        );
+
+        let storage_resolver = test_sandbox.storage_uri_resolver().lazy();
+        let metastore = load_cold_start_bundle(
+            bundle_dir.path(),
+            "ram:///cold-start-metastore",
+            &storage_resolver,
+        )?;
+        let searcher_handle = SearcherHandle::new(metastore, storage_resolver);
+        let search_request = SearchRequest {
+            index_id: index_id.to_string(),
+            query: "beagle".to_string(),
+            search_fields: vec!["body".to_string()],
+            max_hits: 10,
+            ..Default::default()
+        };
+        let search_response = searcher_handle.search(&search_request).await?;
+        assert_eq!(search_response.num_hits, 2);
+        Ok(())
+    }
+}
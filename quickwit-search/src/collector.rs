@@ -18,18 +18,28 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashSet};
+use std::collections::{BinaryHeap, HashMap, HashSet};
 
 use itertools::Itertools;
 use quickwit_index_config::{IndexConfig, SortBy, SortOrder};
-use quickwit_proto::{LeafSearchResponse, PartialHit, SearchRequest};
+use quickwit_proto::{
+    DateHistogramAggregation, GeoFilter, HitSampling, LeafSearchResponse, PartialHit,
+    SearchRequest, TermsAggregation, TermsOrder,
+};
 use tantivy::collector::{Collector, SegmentCollector};
-use tantivy::fastfield::{DynamicFastFieldReader, FastFieldReader};
-use tantivy::schema::{Field, Schema};
-use tantivy::{DocId, Score, SegmentOrdinal, SegmentReader};
-
-use crate::filters::TimestampFilter;
-use crate::partial_hit_sorting_key;
+use tantivy::fastfield::{DynamicFastFieldReader, FastFieldReader, FastValue};
+use tantivy::schema::{Field, Schema, Type};
+use tantivy::{DocId, Score, SegmentOrdinal, SegmentReader, TantivyError};
+
+use crate::date_histogram::{
+    merge_date_histogram_buckets, DateHistogramAccumulator, DateHistogramBucketer,
+};
+use crate::distinct_count::{merge_distinct_count_sketches, HyperLogLog};
+use crate::filters::{GeoPointFilter, TimestampFilter};
+use crate::hit_sampling::{hit_sampling_sort_by, HitSampler};
+use crate::percentiles::{fast_value_to_f64, merge_percentile_sketches, TDigest};
+use crate::terms_aggregation::{merge_terms_buckets, terms_order, TermsAccumulator};
+use crate::{partial_hit_sorting_key, SearchError};
 
 /// The `SortingFieldComputer` can be seen as the specialization of `SortBy` applied to a specific
 /// `SegmentReader`. Its role is to compute the sorting field given a `DocId`.
@@ -86,6 +96,125 @@ fn resolve_sort_by(
     }
 }
 
+/// Accumulates the values of a fast field into a HyperLogLog sketch.
+struct DistinctCounter {
+    field_name: String,
+    fast_field_reader: DynamicFastFieldReader<u64>,
+    hyperloglog: HyperLogLog,
+}
+
+fn resolve_distinct_counter(
+    field_name: &str,
+    segment_reader: &SegmentReader,
+) -> tantivy::Result<DistinctCounter> {
+    let field = segment_reader
+        .schema()
+        .get_field(field_name)
+        .ok_or_else(|| {
+            TantivyError::SchemaError(format!("Field `{}` does not exist in schema.", field_name))
+        })?;
+    let fast_field_reader = segment_reader.fast_fields().u64_lenient(field)?;
+    Ok(DistinctCounter {
+        field_name: field_name.to_string(),
+        fast_field_reader,
+        hyperloglog: HyperLogLog::default(),
+    })
+}
+
+/// Accumulates the values of a fast field into a t-digest.
+struct PercentileCollector {
+    field_name: String,
+    fast_field_reader: DynamicFastFieldReader<u64>,
+    value_type: Type,
+    digest: TDigest,
+}
+
+fn resolve_percentile_collector(
+    field_name: &str,
+    segment_reader: &SegmentReader,
+) -> tantivy::Result<PercentileCollector> {
+    let schema = segment_reader.schema();
+    let field = schema.get_field(field_name).ok_or_else(|| {
+        TantivyError::SchemaError(format!("Field `{}` does not exist in schema.", field_name))
+    })?;
+    let fast_field_reader = segment_reader.fast_fields().u64_lenient(field)?;
+    Ok(PercentileCollector {
+        field_name: field_name.to_string(),
+        fast_field_reader,
+        value_type: schema.get_field_entry(field).field_type().value_type(),
+        digest: TDigest::default(),
+    })
+}
+
+/// Accumulates the buckets of a terms aggregation.
+struct TermsCollector {
+    term_reader: DynamicFastFieldReader<u64>,
+    metric_reader_opt: Option<(DynamicFastFieldReader<u64>, Type)>,
+    terms_accumulator: TermsAccumulator,
+}
+
+fn resolve_terms_collector(
+    terms_aggregation: &TermsAggregation,
+    segment_reader: &SegmentReader,
+) -> tantivy::Result<TermsCollector> {
+    let schema = segment_reader.schema();
+    let resolve_field = |field_name: &str| {
+        schema.get_field(field_name).ok_or_else(|| {
+            TantivyError::SchemaError(format!("Field `{}` does not exist in schema.", field_name))
+        })
+    };
+    let term_field = resolve_field(&terms_aggregation.field_name)?;
+    let term_reader = segment_reader.fast_fields().u64_lenient(term_field)?;
+    let metric_reader_opt = match &terms_aggregation.metric_field {
+        Some(metric_field_name) if terms_order(terms_aggregation) != TermsOrder::Count => {
+            let metric_field = resolve_field(metric_field_name)?;
+            let metric_reader = segment_reader.fast_fields().u64_lenient(metric_field)?;
+            let value_type = schema
+                .get_field_entry(metric_field)
+                .field_type()
+                .value_type();
+            Some((metric_reader, value_type))
+        }
+        _ => None,
+    };
+    Ok(TermsCollector {
+        term_reader,
+        metric_reader_opt,
+        terms_accumulator: TermsAccumulator::new(terms_aggregation),
+    })
+}
+
+/// Accumulates the buckets of a date histogram.
+struct DateHistogramCollector {
+    timestamp_reader: DynamicFastFieldReader<u64>,
+    bucketer: DateHistogramBucketer,
+    date_histogram_accumulator: DateHistogramAccumulator,
+}
+
+fn resolve_date_histogram_collector(
+    date_histogram: &DateHistogramAggregation,
+    time_zone: &str,
+    segment_reader: &SegmentReader,
+) -> tantivy::Result<DateHistogramCollector> {
+    let field = segment_reader
+        .schema()
+        .get_field(&date_histogram.field_name)
+        .ok_or_else(|| {
+            TantivyError::SchemaError(format!(
+                "Field `{}` does not exist in schema.",
+                date_histogram.field_name
+            ))
+        })?;
+    let timestamp_reader = segment_reader.fast_fields().u64_lenient(field)?;
+    let bucketer = DateHistogramBucketer::new(date_histogram, time_zone)
+        .map_err(|error| TantivyError::InvalidArgument(error.to_string()))?;
+    Ok(DateHistogramCollector {
+        timestamp_reader,
+        bucketer,
+        date_histogram_accumulator: DateHistogramAccumulator::default(),
+    })
+}
+
 /// PartialHitHeapItem order is the inverse of the natural order
 /// so that we actually have a min-heap.
 #[derive(Clone, Copy)]
@@ -134,41 +263,72 @@ pub struct QuickwitSegmentCollector {
     sort_by: SortingFieldComputer,
     hits: BinaryHeap<PartialHitHeapItem>,
     max_hits: usize,
+    /// If the hits are sampled, the top hits of each time bucket, by bucket key.
+    hit_sampler_opt: Option<HitSampler>,
+    sampled_hits: HashMap<i64, BinaryHeap<PartialHitHeapItem>>,
     segment_ord: u32,
     timestamp_filter_opt: Option<TimestampFilter>,
+    geo_point_filter_opt: Option<GeoPointFilter>,
+    distinct_counters: Vec<DistinctCounter>,
+    percentile_collectors: Vec<PercentileCollector>,
+    terms_collector_opt: Option<TermsCollector>,
+    date_histogram_collector_opt: Option<DateHistogramCollector>,
 }
 
-impl QuickwitSegmentCollector {
-    fn at_capacity(&self) -> bool {
-        self.hits.len() >= self.max_hits
+/// Pushes a hit into a heap holding the top `max_hits` hits.
+fn collect_top_k(
+    hits: &mut BinaryHeap<PartialHitHeapItem>,
+    max_hits: usize,
+    sorting_field_value: u64,
+    doc_id: DocId,
+) {
+    if hits.len() >= max_hits {
+        if let Some(limit_sorting_field) = hits.peek().map(|head| head.sorting_field_value) {
+            // In case of a tie, we keep the document with a lower `DocId`.
+            if limit_sorting_field < sorting_field_value {
+                if let Some(mut head) = hits.peek_mut() {
+                    head.sorting_field_value = sorting_field_value;
+                    head.doc_id = doc_id;
+                }
+            }
+        }
+    } else {
+        // we have not reached capacity yet, so we can just push the
+        // element.
+        hits.push(PartialHitHeapItem {
+            sorting_field_value,
+            doc_id,
+        });
     }
+}
 
-    fn collect_top_k(&mut self, doc_id: DocId) {
+impl QuickwitSegmentCollector {
+    fn collect_hit(&mut self, doc_id: DocId) {
         let sorting_field_value: u64 = self.sort_by.compute_sorting_field(doc_id);
-        if self.at_capacity() {
-            if let Some(limit_sorting_field) = self.hits.peek().map(|head| head.sorting_field_value)
-            {
-                // In case of a tie, we keep the document with a lower `DocId`.
-                if limit_sorting_field < sorting_field_value {
-                    if let Some(mut head) = self.hits.peek_mut() {
-                        head.sorting_field_value = sorting_field_value;
-                        head.doc_id = doc_id;
-                    }
-                }
-            }
-        } else {
-            // we have not reached capacity yet, so we can just push the
-            // element.
-            self.hits.push(PartialHitHeapItem {
+        if let Some(hit_sampler) = &self.hit_sampler_opt {
+            let bucket_hits = self
+                .sampled_hits
+                .entry(hit_sampler.bucket_key(sorting_field_value))
+                .or_default();
+            collect_top_k(
+                bucket_hits,
+                hit_sampler.max_hits_per_bucket(),
                 sorting_field_value,
                 doc_id,
-            });
+            );
+        } else {
+            collect_top_k(&mut self.hits, self.max_hits, sorting_field_value, doc_id);
         }
     }
 
     fn accept_document(&self, doc_id: DocId) -> bool {
         if let Some(ref timestamp_filter) = self.timestamp_filter_opt {
-            return timestamp_filter.is_within_range(doc_id);
+            if !timestamp_filter.is_within_range(doc_id) {
+                return false;
+            }
+        }
+        if let Some(ref geo_point_filter) = self.geo_point_filter_opt {
+            return geo_point_filter.is_within_area(doc_id);
         }
         true
     }
@@ -183,29 +343,105 @@ impl SegmentCollector for QuickwitSegmentCollector {
         }
 
         self.num_hits += 1;
-        self.collect_top_k(doc_id);
+        self.collect_hit(doc_id);
+        for distinct_counter in &mut self.distinct_counters {
+            let value = distinct_counter.fast_field_reader.get(doc_id);
+            distinct_counter.hyperloglog.insert(value);
+        }
+        for percentile_collector in &mut self.percentile_collectors {
+            let value = percentile_collector.fast_field_reader.get(doc_id);
+            let value = fast_value_to_f64(percentile_collector.value_type, value);
+            percentile_collector.digest.insert(value);
+        }
+        if let Some(terms_collector) = self.terms_collector_opt.as_mut() {
+            let term = terms_collector.term_reader.get(doc_id);
+            let metric_value_opt =
+                terms_collector
+                    .metric_reader_opt
+                    .as_ref()
+                    .map(|(metric_reader, value_type)| {
+                        fast_value_to_f64(*value_type, metric_reader.get(doc_id))
+                    });
+            terms_collector
+                .terms_accumulator
+                .collect(term, metric_value_opt);
+        }
+        if let Some(date_histogram_collector) = self.date_histogram_collector_opt.as_mut() {
+            let timestamp = i64::from_u64(date_histogram_collector.timestamp_reader.get(doc_id));
+            let key = date_histogram_collector.bucketer.bucket_key(timestamp);
+            date_histogram_collector
+                .date_histogram_accumulator
+                .collect(key);
+        }
     }
 
     fn harvest(self) -> LeafSearchResponse {
         let segment_ord = self.segment_ord;
         // TODO use into_iter_sorted() once it gets stable.
         let split_id = self.split_id;
-        let partial_hits: Vec<PartialHit> = self
-            .hits
-            .into_sorted_vec()
+        let hits = if self.hit_sampler_opt.is_some() {
+            // The sampled hits are capped to the top hits of the whole segment all the same.
+            let mut hits: Vec<PartialHitHeapItem> = self
+                .sampled_hits
+                .into_iter()
+                .flat_map(|(_, bucket_hits)| bucket_hits)
+                .collect();
+            hits.sort();
+            hits.truncate(self.max_hits);
+            hits
+        } else {
+            self.hits.into_sorted_vec()
+        };
+        let partial_hits: Vec<PartialHit> = hits
             .into_iter()
             .map(|hit| PartialHit {
                 sorting_field_value: hit.sorting_field_value,
                 segment_ord,
                 doc_id: hit.doc_id,
                 split_id: split_id.clone(),
+                leaf_grpc_addr: String::new(),
             })
             .collect();
+        let distinct_count_sketches = self
+            .distinct_counters
+            .into_iter()
+            .map(|distinct_counter| {
+                distinct_counter
+                    .hyperloglog
+                    .into_sketch(distinct_counter.field_name)
+            })
+            .collect();
+        let percentile_sketches = self
+            .percentile_collectors
+            .into_iter()
+            .map(|percentile_collector| {
+                percentile_collector
+                    .digest
+                    .into_sketch(percentile_collector.field_name)
+            })
+            .collect();
+        let terms_buckets = self
+            .terms_collector_opt
+            .map(|terms_collector| terms_collector.terms_accumulator.into_terms_buckets())
+            .into_iter()
+            .collect();
+        let date_histogram_buckets = self
+            .date_histogram_collector_opt
+            .map(|date_histogram_collector| {
+                date_histogram_collector
+                    .date_histogram_accumulator
+                    .into_buckets()
+            })
+            .unwrap_or_default();
         LeafSearchResponse {
             num_hits: self.num_hits,
             partial_hits,
             failed_splits: vec![],
             num_attempted_splits: 1,
+            distinct_count_sketches,
+            percentile_sketches,
+            terms_buckets,
+            date_histogram_buckets,
         }
     }
 }
@@ -229,6 +465,30 @@ pub struct QuickwitCollector {
     pub timestamp_field_opt: Option<Field>,
     pub start_timestamp_opt: Option<i64>,
     pub end_timestamp_opt: Option<i64>,
+    /// Geo filter the geo points of the documents must satisfy.
+    pub geo_filter_opt: Option<GeoFilter>,
+    /// Fast fields whose distinct values are counted.
+    pub distinct_count_fields: Vec<String>,
+    /// Fast fields whose percentiles are estimated.
+    pub percentile_fields: Vec<String>,
+    /// Terms aggregation whose buckets are accumulated.
+    pub terms_aggregation_opt: Option<TermsAggregation>,
+    /// Date histogram whose buckets are accumulated.
+    pub date_histogram_opt: Option<DateHistogramAggregation>,
+    /// Time zone delimiting the buckets of the date histogram.
+    pub time_zone: String,
+    /// Sampling of the hits per time bucket.
+    pub hit_sampling_opt: Option<HitSampling>,
+}
+
+impl QuickwitCollector {
+    fn hit_sampler(&self) -> tantivy::Result<Option<HitSampler>> {
+        self.hit_sampling_opt
+            .as_ref()
+            .map(|hit_sampling| HitSampler::new(hit_sampling, &self.time_zone))
+            .transpose()
+            .map_err(|error| TantivyError::InvalidArgument(error.to_string()))
+    }
 }
 
 impl GenericQuickwitCollector for QuickwitCollector {
@@ -261,6 +521,35 @@ impl Collector for QuickwitCollector {
         } else {
             None
         };
+        let geo_point_filter_opt = self
+            .geo_filter_opt
+            .as_ref()
+            .map(|geo_filter| GeoPointFilter::new(geo_filter, segment_reader))
+            .transpose()?;
+
+        let distinct_counters = self
+            .distinct_count_fields
+            .iter()
+            .map(|field_name| resolve_distinct_counter(field_name, segment_reader))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+        let percentile_collectors = self
+            .percentile_fields
+            .iter()
+            .map(|field_name| resolve_percentile_collector(field_name, segment_reader))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+        let terms_collector_opt = self
+            .terms_aggregation_opt
+            .as_ref()
+            .map(|terms_aggregation| resolve_terms_collector(terms_aggregation, segment_reader))
+            .transpose()?;
+        let date_histogram_collector_opt = self
+            .date_histogram_opt
+            .as_ref()
+            .map(|date_histogram| {
+                resolve_date_histogram_collector(date_histogram, &self.time_zone, segment_reader)
+            })
+            .transpose()?;
+        let hit_sampler_opt = self.hit_sampler()?;
 
         Ok(QuickwitSegmentCollector {
             num_hits: 0u64,
@@ -269,7 +558,14 @@ impl Collector for QuickwitCollector {
             hits: BinaryHeap::with_capacity(leaf_max_hits),
             segment_ord,
             max_hits: leaf_max_hits,
+            hit_sampler_opt,
+            sampled_hits: HashMap::new(),
             timestamp_filter_opt,
+            geo_point_filter_opt,
+            distinct_counters,
+            percentile_collectors,
+            terms_collector_opt,
+            date_histogram_collector_opt,
         })
     }
 
@@ -288,7 +584,9 @@ impl Collector for QuickwitCollector {
         // All leaves will return their top [0..max_hits) documents.
         // We compute the overall [0..start_offset + max_hits) documents ...
         let num_hits = self.start_offset + self.max_hits;
-        let mut merged_leaf_response = merge_leaf_responses(segment_fruits, num_hits);
+        let hit_sampler_opt = self.hit_sampler()?;
+        let mut merged_leaf_response =
+            merge_leaf_responses(segment_fruits, num_hits, hit_sampler_opt.as_ref())?;
         // ... and drop the first [..start_offets) hits.
         merged_leaf_response
             .partial_hits
@@ -300,12 +598,13 @@ impl Collector for QuickwitCollector {
 
 /// Merges a set of Leaf Results.
 fn merge_leaf_responses(
-    leaf_responses: Vec<LeafSearchResponse>,
+    mut leaf_responses: Vec<LeafSearchResponse>,
     max_hits: usize,
-) -> LeafSearchResponse {
+    hit_sampler_opt: Option<&HitSampler>,
+) -> tantivy::Result<LeafSearchResponse> {
     // Optimization: No merging needed if there is only one result.
     if leaf_responses.len() == 1 {
-        return leaf_responses.into_iter().next().unwrap_or_default(); //< default is actually never called
+        return Ok(leaf_responses.into_iter().next().unwrap_or_default()); //< default is actually never called
     }
     let num_attempted_splits = leaf_responses
         .iter()
@@ -319,18 +618,45 @@ fn merge_leaf_responses(
         .iter()
         .flat_map(|leaf_response| leaf_response.failed_splits.iter().cloned())
         .collect_vec();
-    let all_partial_hits: Vec<PartialHit> = leaf_responses
+    let distinct_count_sketches = merge_distinct_count_sketches(
+        leaf_responses
+            .iter_mut()
+            .flat_map(|leaf_response| leaf_response.distinct_count_sketches.drain(..)),
+    )?;
+    let percentile_sketches = merge_percentile_sketches(
+        leaf_responses
+            .iter_mut()
+            .flat_map(|leaf_response| leaf_response.percentile_sketches.drain(..)),
+    )?;
+    let terms_buckets = merge_terms_buckets(
+        leaf_responses
+            .iter_mut()
+            .flat_map(|leaf_response| leaf_response.terms_buckets.drain(..)),
+    )?;
+    let date_histogram_buckets = merge_date_histogram_buckets(
+        leaf_responses
+            .iter_mut()
+            .flat_map(|leaf_response| leaf_response.date_histogram_buckets.drain(..)),
+    );
+    let mut all_partial_hits: Vec<PartialHit> = leaf_responses
         .into_iter()
         .flat_map(|leaf_response| leaf_response.partial_hits)
         .collect();
+    if let Some(hit_sampler) = hit_sampler_opt {
+        all_partial_hits = hit_sampler.sample_partial_hits(all_partial_hits);
+    }
     // TODO optimize
     let top_k_partial_hits = top_k_partial_hits(all_partial_hits, max_hits);
-    LeafSearchResponse {
+    Ok(LeafSearchResponse {
         num_hits,
         partial_hits: top_k_partial_hits,
         failed_splits,
         num_attempted_splits,
-    }
+        distinct_count_sketches,
+        percentile_sketches,
+        terms_buckets,
+        date_histogram_buckets,
+    })
 }
 
 /// Mutates partial_hits so that it contains the top-num_hitso hits,
@@ -359,6 +685,31 @@ fn extract_fast_field_names(index_config: &dyn IndexConfig) -> HashSet<String> {
     fast_fields
 }
 
+/// Returns an error if the hits cannot be sorted by the requested sort field.
+pub(crate) fn validate_sort_by_field(
+    sort_by_field_opt: Option<&str>,
+    schema: &Schema,
+) -> crate::Result<()> {
+    let sort_by_field = if let Some(sort_by_field) = sort_by_field_opt {
+        sort_by_field
+    } else {
+        return Ok(());
+    };
+    let field = schema.get_field(sort_by_field).ok_or_else(|| {
+        SearchError::InvalidQuery(format!(
+            "Field `{}` does not exist in schema",
+            sort_by_field
+        ))
+    })?;
+    if !schema.get_field_entry(field).is_fast() {
+        return Err(SearchError::InvalidQuery(format!(
+            "Hits can only be sorted by fast fields, `{}` is not a fast field",
+            sort_by_field
+        )));
+    }
+    Ok(())
+}
+
 /// Builds the QuickwitCollector, in function of the information that was requested by the user.
 pub fn make_collector_for_split(
     split_id: String,
@@ -366,15 +717,59 @@ pub fn make_collector_for_split(
     search_request: &SearchRequest,
     split_schema: &Schema,
 ) -> QuickwitCollector {
+    let mut fast_field_names = extract_fast_field_names(index_config);
+    fast_field_names.extend(search_request.distinct_count_fields.iter().cloned());
+    fast_field_names.extend(search_request.percentile_fields.iter().cloned());
+    if let Some(terms_aggregation) = &search_request.terms_aggregation {
+        fast_field_names.insert(terms_aggregation.field_name.clone());
+        if terms_order(terms_aggregation) != TermsOrder::Count {
+            fast_field_names.extend(terms_aggregation.metric_field.iter().cloned());
+        }
+    }
+    if let Some(date_histogram) = &search_request.date_histogram {
+        fast_field_names.insert(date_histogram.field_name.clone());
+    }
+    if let Some(geo_filter) = &search_request.geo_filter {
+        fast_field_names.insert(geo_filter.field_name.clone());
+    }
+    let sort_by = match &search_request.sort_by_field {
+        Some(sort_by_field) => {
+            fast_field_names.insert(sort_by_field.clone());
+            SortBy::SortByFastField {
+                field_name: sort_by_field.clone(),
+                order: if search_request.sort_ascending {
+                    SortOrder::Asc
+                } else {
+                    SortOrder::Desc
+                },
+            }
+        }
+        None => match (
+            &search_request.hit_sampling,
+            index_config.timestamp_field_name(),
+        ) {
+            (Some(hit_sampling), Some(timestamp_field_name)) => {
+                hit_sampling_sort_by(hit_sampling, timestamp_field_name)
+            }
+            _ => index_config.sort_by(),
+        },
+    };
     QuickwitCollector {
         split_id,
         start_offset: search_request.start_offset as usize,
         max_hits: search_request.max_hits as usize,
-        sort_by: index_config.sort_by(),
-        fast_field_names: extract_fast_field_names(index_config),
+        sort_by,
+        fast_field_names,
         timestamp_field_opt: index_config.timestamp_field(split_schema),
         start_timestamp_opt: search_request.start_timestamp,
         end_timestamp_opt: search_request.end_timestamp,
+        geo_filter_opt: search_request.geo_filter.clone(),
+        distinct_count_fields: search_request.distinct_count_fields.clone(),
+        percentile_fields: search_request.percentile_fields.clone(),
+        terms_aggregation_opt: search_request.terms_aggregation.clone(),
+        date_histogram_opt: search_request.date_histogram.clone(),
+        time_zone: search_request.time_zone.clone(),
+        hit_sampling_opt: search_request.hit_sampling.clone(),
     }
 }
 
@@ -392,6 +787,13 @@ pub fn make_merge_collector(search_request: &SearchRequest) -> QuickwitCollector
         timestamp_field_opt: None,
         start_timestamp_opt: search_request.start_timestamp,
         end_timestamp_opt: search_request.end_timestamp,
+        geo_filter_opt: None,
+        distinct_count_fields: Vec::new(),
+        percentile_fields: Vec::new(),
+        terms_aggregation_opt: None,
+        date_histogram_opt: None,
+        time_zone: search_request.time_zone.clone(),
+        hit_sampling_opt: search_request.hit_sampling.clone(),
     }
 }
 
@@ -424,6 +826,7 @@ mod tests {
             split_id: "split1".to_string(),
             segment_ord: 0u32,
             doc_id: 0u32,
+            leaf_grpc_addr: String::new(),
         };
         assert_eq!(
             top_k_partial_hits(vec![make_doc(1u64), make_doc(3u64), make_doc(2u64),], 2),
@@ -438,6 +841,7 @@ mod tests {
             split_id: format!("split_{}", split_id),
             segment_ord: 0u32,
             doc_id: 0u32,
+            leaf_grpc_addr: String::new(),
         };
         assert_eq!(
             top_k_partial_hits(
@@ -0,0 +1,328 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use quickwit_index_config::{CalendarUnit, TimeZone};
+use quickwit_proto::{DateHistogramAggregation, DateHistogramBucket};
+use tantivy::schema::{Schema, Type};
+
+use crate::SearchError;
+
+/// Width of the buckets of a date histogram.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum DateHistogramInterval {
+    /// Fixed number of seconds, aligned on the local midnight of the time zone.
+    Fixed(i64),
+    /// Calendar unit, whose length depends on the date and on the time zone.
+    Calendar(CalendarUnit),
+}
+
+impl FromStr for DateHistogramInterval {
+    type Err = SearchError;
+
+    fn from_str(interval: &str) -> crate::Result<Self> {
+        let calendar_unit_opt = match interval {
+            "day" => Some(CalendarUnit::Day),
+            "week" => Some(CalendarUnit::Week),
+            "month" => Some(CalendarUnit::Month),
+            "year" => Some(CalendarUnit::Year),
+            _ => None,
+        };
+        if let Some(calendar_unit) = calendar_unit_opt {
+            return Ok(DateHistogramInterval::Calendar(calendar_unit));
+        }
+        let invalid_interval = || {
+            SearchError::InvalidQuery(format!(
+                "Invalid date histogram interval `{}`: expected a positive number of seconds, \
+                 minutes, hours or days (`30s`, `15m`, `1h`, `1d`), or one of `day`, `week`, \
+                 `month` and `year`",
+                interval
+            ))
+        };
+        let unit_pos = interval
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid_interval)?;
+        let (num_units, unit) = interval.split_at(unit_pos);
+        let unit_in_seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3_600,
+            "d" => 86_400,
+            _ => return Err(invalid_interval()),
+        };
+        let num_units: i64 = num_units.parse().map_err(|_| invalid_interval())?;
+        if num_units == 0 {
+            return Err(invalid_interval());
+        }
+        num_units
+            .checked_mul(unit_in_seconds)
+            .map(DateHistogramInterval::Fixed)
+            .ok_or_else(invalid_interval)
+    }
+}
+
+pub(crate) fn parse_time_zone(time_zone: &str) -> crate::Result<TimeZone> {
+    TimeZone::from_str(time_zone).map_err(|error| SearchError::InvalidQuery(error.to_string()))
+}
+
+/// Assigns timestamps to the bucket containing them.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DateHistogramBucketer {
+    interval: DateHistogramInterval,
+    time_zone: TimeZone,
+}
+
+impl DateHistogramBucketer {
+    pub fn new(date_histogram: &DateHistogramAggregation, time_zone: &str) -> crate::Result<Self> {
+        DateHistogramBucketer::from_interval(&date_histogram.interval, time_zone)
+    }
+
+    /// Creates a bucketer from an interval in the format of the date histograms.
+    pub fn from_interval(interval: &str, time_zone: &str) -> crate::Result<Self> {
+        Ok(DateHistogramBucketer {
+            interval: DateHistogramInterval::from_str(interval)?,
+            time_zone: parse_time_zone(time_zone)?,
+        })
+    }
+
+    /// Returns the timestamp of the start of the bucket containing `timestamp`.
+    pub fn bucket_key(&self, timestamp: i64) -> i64 {
+        match self.interval {
+            DateHistogramInterval::Fixed(interval) => {
+                let local_timestamp = timestamp + self.time_zone.offset_seconds(timestamp);
+                timestamp - local_timestamp.rem_euclid(interval)
+            }
+            DateHistogramInterval::Calendar(calendar_unit) => {
+                self.time_zone.truncate(timestamp, calendar_unit)
+            }
+        }
+    }
+}
+
+/// Counts the documents of each bucket of a date histogram.
+#[derive(Default)]
+pub(crate) struct DateHistogramAccumulator {
+    doc_counts: BTreeMap<i64, u64>,
+}
+
+impl DateHistogramAccumulator {
+    /// Adds a document to the bucket starting at `key`.
+    pub fn collect(&mut self, key: i64) {
+        *self.doc_counts.entry(key).or_insert(0) += 1;
+    }
+
+    fn merge_buckets(&mut self, buckets: impl IntoIterator<Item = DateHistogramBucket>) {
+        for bucket in buckets {
+            *self.doc_counts.entry(bucket.key).or_insert(0) += bucket.doc_count;
+        }
+    }
+
+    /// Returns the buckets, in chronological order.
+    pub fn into_buckets(self) -> Vec<DateHistogramBucket> {
+        self.doc_counts
+            .into_iter()
+            .map(|(key, doc_count)| DateHistogramBucket {
+                key,
+                doc_count,
+                key_as_string: String::new(),
+            })
+            .collect()
+    }
+}
+
+/// Merges the buckets of several segments, splits or leaves.
+pub(crate) fn merge_date_histogram_buckets(
+    buckets: impl IntoIterator<Item = DateHistogramBucket>,
+) -> Vec<DateHistogramBucket> {
+    let mut date_histogram_accumulator = DateHistogramAccumulator::default();
+    date_histogram_accumulator.merge_buckets(buckets);
+    date_histogram_accumulator.into_buckets()
+}
+
+/// Returns the buckets of the date histogram, with their start formatted in the time zone
+/// of the request.
+pub(crate) fn build_date_histogram_result(
+    buckets: &[DateHistogramBucket],
+    time_zone: &str,
+) -> crate::Result<Vec<DateHistogramBucket>> {
+    let time_zone = parse_time_zone(time_zone)?;
+    Ok(buckets
+        .iter()
+        .map(|bucket| DateHistogramBucket {
+            key: bucket.key,
+            doc_count: bucket.doc_count,
+            key_as_string: time_zone.format_timestamp(bucket.key),
+        })
+        .collect())
+}
+
+/// Checks that the time zone is valid, and that the date histogram, if any, is over an
+/// `i64` or `date` fast field with a valid interval.
+pub(crate) fn validate_date_histogram(
+    date_histogram_opt: Option<&DateHistogramAggregation>,
+    time_zone: &str,
+    schema: &Schema,
+) -> crate::Result<()> {
+    parse_time_zone(time_zone)?;
+    let date_histogram = if let Some(date_histogram) = date_histogram_opt {
+        date_histogram
+    } else {
+        return Ok(());
+    };
+    let field = schema
+        .get_field(&date_histogram.field_name)
+        .ok_or_else(|| {
+            SearchError::InvalidQuery(format!(
+                "Field `{}` does not exist in schema",
+                date_histogram.field_name
+            ))
+        })?;
+    let field_entry = schema.get_field_entry(field);
+    if !field_entry.is_fast()
+        || !matches!(
+            field_entry.field_type().value_type(),
+            Type::I64 | Type::Date
+        )
+    {
+        return Err(SearchError::InvalidQuery(format!(
+            "Date histograms are only supported on `i64` and `date` fast fields, `{}` is not one \
+             of them",
+            date_histogram.field_name
+        )));
+    }
+    DateHistogramInterval::from_str(&date_histogram.interval)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::{FAST, INDEXED, TEXT};
+
+    use super::*;
+
+    fn date_histogram(interval: &str) -> DateHistogramAggregation {
+        DateHistogramAggregation {
+            field_name: "timestamp".to_string(),
+            interval: interval.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_date_histogram_interval() {
+        assert_eq!(
+            DateHistogramInterval::from_str("30s").unwrap(),
+            DateHistogramInterval::Fixed(30)
+        );
+        assert_eq!(
+            DateHistogramInterval::from_str("15m").unwrap(),
+            DateHistogramInterval::Fixed(900)
+        );
+        assert_eq!(
+            DateHistogramInterval::from_str("2h").unwrap(),
+            DateHistogramInterval::Fixed(7_200)
+        );
+        assert_eq!(
+            DateHistogramInterval::from_str("1d").unwrap(),
+            DateHistogramInterval::Fixed(86_400)
+        );
+        assert_eq!(
+            DateHistogramInterval::from_str("week").unwrap(),
+            DateHistogramInterval::Calendar(CalendarUnit::Week)
+        );
+        for invalid_interval in &["", "0h", "h", "1w", "-1h", "1.5h", "99999999999999999d"] {
+            assert!(DateHistogramInterval::from_str(invalid_interval).is_err());
+        }
+    }
+
+    #[test]
+    fn test_date_histogram_bucket_key() {
+        // 2021-06-01T23:30:00Z
+        let timestamp = 1_622_590_200;
+        let utc_hours = DateHistogramBucketer::new(&date_histogram("1h"), "").unwrap();
+        assert_eq!(utc_hours.bucket_key(timestamp), 1_622_588_400);
+        let utc_days = DateHistogramBucketer::new(&date_histogram("1d"), "").unwrap();
+        assert_eq!(utc_days.bucket_key(timestamp), 1_622_505_600);
+        assert_eq!(utc_days.bucket_key(-1), -86_400);
+        // It is already June 2nd in Paris and in Kolkata.
+        let paris_days =
+            DateHistogramBucketer::new(&date_histogram("day"), "Europe/Paris").unwrap();
+        assert_eq!(paris_days.bucket_key(timestamp), 1_622_584_800);
+        let kolkata_days = DateHistogramBucketer::new(&date_histogram("1d"), "+05:30").unwrap();
+        assert_eq!(kolkata_days.bucket_key(timestamp), 1_622_572_200);
+        let kolkata_hours = DateHistogramBucketer::new(&date_histogram("1h"), "+05:30").unwrap();
+        assert_eq!(kolkata_hours.bucket_key(timestamp), 1_622_590_200);
+        assert!(DateHistogramBucketer::new(&date_histogram("1h"), "Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn test_merge_date_histogram_buckets() {
+        let mut accumulator = DateHistogramAccumulator::default();
+        accumulator.collect(3_600);
+        accumulator.collect(0);
+        accumulator.collect(3_600);
+        let mut other_accumulator = DateHistogramAccumulator::default();
+        other_accumulator.collect(7_200);
+        other_accumulator.collect(0);
+        let buckets = merge_date_histogram_buckets(
+            accumulator
+                .into_buckets()
+                .into_iter()
+                .chain(other_accumulator.into_buckets()),
+        );
+        let keys_and_doc_counts: Vec<(i64, u64)> = buckets
+            .iter()
+            .map(|bucket| (bucket.key, bucket.doc_count))
+            .collect();
+        assert_eq!(keys_and_doc_counts, vec![(0, 2), (3_600, 2), (7_200, 1)]);
+
+        let date_histogram_result = build_date_histogram_result(&buckets, "+01:00").unwrap();
+        assert_eq!(
+            date_histogram_result[1].key_as_string,
+            "1970-01-01T02:00:00+01:00"
+        );
+    }
+
+    #[test]
+    fn test_validate_date_histogram() {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("body", TEXT);
+        schema_builder.add_i64_field("timestamp", FAST);
+        schema_builder.add_date_field("response_date", FAST);
+        schema_builder.add_i64_field("status", INDEXED);
+        let schema = schema_builder.build();
+        assert!(validate_date_histogram(None, "", &schema).is_ok());
+        assert!(validate_date_histogram(None, "Mars/Olympus_Mons", &schema).is_err());
+        assert!(validate_date_histogram(Some(&date_histogram("1h")), "UTC", &schema).is_ok());
+        let response_date_histogram = DateHistogramAggregation {
+            field_name: "response_date".to_string(),
+            interval: "month".to_string(),
+        };
+        assert!(validate_date_histogram(Some(&response_date_histogram), "", &schema).is_ok());
+        assert!(validate_date_histogram(Some(&date_histogram("1w")), "", &schema).is_err());
+        for field_name in &["body", "status", "unknown"] {
+            let date_histogram = DateHistogramAggregation {
+                field_name: field_name.to_string(),
+                interval: "1h".to_string(),
+            };
+            assert!(validate_date_histogram(Some(&date_histogram), "", &schema).is_err());
+        }
+    }
+}
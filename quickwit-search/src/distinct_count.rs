@@ -0,0 +1,280 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashMap};
+
+use quickwit_proto::DistinctCountSketch;
+use tantivy::schema::Schema;
+use tantivy::TantivyError;
+
+use crate::SearchError;
+
+/// Number of bits of the hash used to pick a register.
+const PRECISION: u32 = 12;
+
+/// With 4096 registers, the standard error of the estimate is about 1.6%.
+const NUM_REGISTERS: usize = 1 << PRECISION;
+
+/// A HyperLogLog sketch, estimating the number of distinct values inserted into it.
+///
+/// Sketches are computed for each split by the leaves, and merged by the root:
+/// merging the sketches of several sets gives the sketch of their union.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog {
+            registers: vec![0u8; NUM_REGISTERS],
+        }
+    }
+}
+
+impl HyperLogLog {
+    /// Inserts a fast field value, as a `u64`, into the sketch.
+    pub fn insert(&mut self, value: u64) {
+        let hash = mix64(value);
+        let register_ord = (hash >> (64 - PRECISION)) as usize;
+        // The sentinel bit caps the rank to `64 - PRECISION + 1`.
+        let rank = ((hash << PRECISION) | (1 << (PRECISION - 1))).leading_zeros() + 1;
+        let register = &mut self.registers[register_ord];
+        *register = (*register).max(rank as u8);
+    }
+
+    /// Merges the values of `other` into the sketch.
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (register, other_register) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other_register);
+        }
+    }
+
+    /// Returns the estimated number of distinct values inserted into the sketch.
+    pub fn estimate(&self) -> u64 {
+        let num_registers = NUM_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / num_registers);
+        let inverse_sum: f64 = self
+            .registers
+            .iter()
+            .map(|&register| 2f64.powi(-(register as i32)))
+            .sum();
+        let raw_estimate = alpha * num_registers * num_registers / inverse_sum;
+        let num_empty_registers = self
+            .registers
+            .iter()
+            .filter(|&&register| register == 0)
+            .count();
+        // Small cardinalities are better estimated with linear counting.
+        if raw_estimate <= 2.5 * num_registers && num_empty_registers > 0 {
+            let linear_count = num_registers * (num_registers / num_empty_registers as f64).ln();
+            return linear_count.round() as u64;
+        }
+        raw_estimate.round() as u64
+    }
+
+    fn from_registers(registers: Vec<u8>) -> Option<HyperLogLog> {
+        if registers.len() != NUM_REGISTERS {
+            return None;
+        }
+        Some(HyperLogLog { registers })
+    }
+
+    /// Returns the registers of the sketch, as streamed by the leaves of a search stream.
+    pub fn into_registers(self) -> Vec<u8> {
+        self.registers
+    }
+
+    /// Returns the sketch of the values of `field_name`, as sent from the leaves to the root.
+    pub fn into_sketch(self, field_name: String) -> DistinctCountSketch {
+        DistinctCountSketch {
+            field_name,
+            registers: self.into_registers(),
+        }
+    }
+}
+
+/// Murmur3's 64-bit finalizer. It is a bijection, so distinct values get distinct hashes.
+///
+/// The hash must be the same on all the nodes of the cluster, which rules out
+/// std's `RandomState`.
+fn mix64(mut value: u64) -> u64 {
+    value ^= value >> 33;
+    value = value.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    value ^= value >> 33;
+    value = value.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    value ^= value >> 33;
+    value
+}
+
+/// Merges the sketches of the same fields.
+pub(crate) fn merge_distinct_count_sketches(
+    sketches: impl IntoIterator<Item = DistinctCountSketch>,
+) -> tantivy::Result<Vec<DistinctCountSketch>> {
+    let mut merged_sketches: BTreeMap<String, HyperLogLog> = BTreeMap::new();
+    for sketch in sketches {
+        let hyperloglog = HyperLogLog::from_registers(sketch.registers).ok_or_else(|| {
+            TantivyError::InvalidArgument(format!(
+                "Invalid distinct count sketch for field `{}`.",
+                sketch.field_name
+            ))
+        })?;
+        if let Some(merged_sketch) = merged_sketches.get_mut(&sketch.field_name) {
+            merged_sketch.merge(&hyperloglog);
+        } else {
+            merged_sketches.insert(sketch.field_name, hyperloglog);
+        }
+    }
+    Ok(merged_sketches
+        .into_iter()
+        .map(|(field_name, hyperloglog)| hyperloglog.into_sketch(field_name))
+        .collect())
+}
+
+/// Returns the estimated distinct count of each of the `distinct_count_fields`, given the
+/// sketches returned by the leaves.
+///
+/// Fields without any sketch, e.g. because no split matched the request, have a count of 0.
+pub(crate) fn estimate_distinct_counts(
+    distinct_count_fields: &[String],
+    sketches: &[DistinctCountSketch],
+) -> crate::Result<HashMap<String, u64>> {
+    let mut distinct_counts: HashMap<String, u64> = distinct_count_fields
+        .iter()
+        .map(|field_name| (field_name.clone(), 0))
+        .collect();
+    for merged_sketch in merge_distinct_count_sketches(sketches.iter().cloned())? {
+        let hyperloglog = HyperLogLog::from_registers(merged_sketch.registers)
+            .expect("Merged sketches should have a valid number of registers.");
+        distinct_counts.insert(merged_sketch.field_name, hyperloglog.estimate());
+    }
+    Ok(distinct_counts)
+}
+
+/// Returns the estimated number of distinct values of the sketches serialized by
+/// the leaves of a search stream.
+pub(crate) fn estimate_distinct_count_from_bytes<'a>(
+    sketches: impl IntoIterator<Item = &'a [u8]>,
+) -> crate::Result<u64> {
+    let mut merged_sketch = HyperLogLog::default();
+    for sketch in sketches {
+        let hyperloglog = HyperLogLog::from_registers(sketch.to_vec()).ok_or_else(|| {
+            SearchError::InternalError("Invalid distinct count sketch.".to_string())
+        })?;
+        merged_sketch.merge(&hyperloglog);
+    }
+    Ok(merged_sketch.estimate())
+}
+
+/// Checks that the distinct count fields exist and are fast fields.
+pub(crate) fn validate_distinct_count_fields(
+    distinct_count_fields: &[String],
+    schema: &Schema,
+) -> crate::Result<()> {
+    for field_name in distinct_count_fields {
+        let field = schema.get_field(field_name).ok_or_else(|| {
+            SearchError::InvalidQuery(format!("Field `{}` does not exist in schema", field_name))
+        })?;
+        if !schema.get_field_entry(field).is_fast() {
+            return Err(SearchError::InvalidQuery(format!(
+                "Distinct counts are only supported on fast fields, `{}` is not a fast field",
+                field_name
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_estimate_close(hyperloglog: &HyperLogLog, expected_count: u64) {
+        let estimate = hyperloglog.estimate() as f64;
+        let error = (estimate - expected_count as f64).abs() / expected_count as f64;
+        assert!(
+            error < 0.05,
+            "estimate {} too far from {}",
+            estimate,
+            expected_count
+        );
+    }
+
+    #[test]
+    fn test_hyperloglog_estimate() {
+        let hyperloglog = HyperLogLog::default();
+        assert_eq!(hyperloglog.estimate(), 0);
+
+        let mut hyperloglog = HyperLogLog::default();
+        for value in 0..10 {
+            hyperloglog.insert(value);
+            hyperloglog.insert(value);
+        }
+        assert_eq!(hyperloglog.estimate(), 10);
+
+        let mut hyperloglog = HyperLogLog::default();
+        for value in 0..100_000u64 {
+            hyperloglog.insert(value * 7);
+        }
+        assert_estimate_close(&hyperloglog, 100_000);
+    }
+
+    #[test]
+    fn test_hyperloglog_merge() {
+        let mut left = HyperLogLog::default();
+        let mut right = HyperLogLog::default();
+        for value in 0..30_000u64 {
+            left.insert(value);
+        }
+        for value in 20_000..50_000u64 {
+            right.insert(value);
+        }
+        left.merge(&right);
+        assert_estimate_close(&left, 50_000);
+    }
+
+    #[test]
+    fn test_merge_distinct_count_sketches() -> anyhow::Result<()> {
+        let sketch = |field_name: &str, values: std::ops::Range<u64>| {
+            let mut hyperloglog = HyperLogLog::default();
+            for value in values {
+                hyperloglog.insert(value);
+            }
+            hyperloglog.into_sketch(field_name.to_string())
+        };
+        let sketches = vec![
+            sketch("user", 0..10),
+            sketch("host", 0..3),
+            sketch("user", 5..20),
+        ];
+        let distinct_count_fields = vec!["user".to_string(), "host".to_string(), "ip".to_string()];
+        let distinct_counts = estimate_distinct_counts(&distinct_count_fields, &sketches)?;
+        assert_eq!(distinct_counts.len(), 3);
+        assert_eq!(distinct_counts["user"], 20);
+        assert_eq!(distinct_counts["host"], 3);
+        assert_eq!(distinct_counts["ip"], 0);
+
+        let invalid_sketch = DistinctCountSketch {
+            field_name: "user".to_string(),
+            registers: vec![0u8; 3],
+        };
+        assert!(merge_distinct_count_sketches(vec![invalid_sketch]).is_err());
+        Ok(())
+    }
+}
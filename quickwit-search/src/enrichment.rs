@@ -0,0 +1,442 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use lru::LruCache;
+use once_cell::sync::OnceCell;
+use quickwit_index_config::EnrichmentConfig;
+use quickwit_metastore::CompanionFiles;
+use quickwit_storage::Storage;
+use serde_json::{Map as JsonMap, Value as JsonValue};
+
+/// Prefix of the lookup table paths referring to the latest version of a companion file of
+/// the index rather than to a file of the index storage.
+const COMPANION_FILE_PREFIX: &str = "companion:";
+
+/// Maximum number of parsed lookup tables kept in memory.
+const LOOKUP_TABLE_CACHE_CAPACITY: usize = 64;
+
+/// Time after which a cached lookup table of the index storage is read again. The files of
+/// the index storage have no versions, unlike the companion files.
+const LOOKUP_TABLE_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+type LookupTable = HashMap<String, JsonValue>;
+
+/// Identifies a version of a lookup table. The version is only set for the companion files.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct LookupTableKey {
+    storage_uri: String,
+    path: String,
+    version_opt: Option<u64>,
+}
+
+struct CachedLookupTable {
+    lookup_table: Arc<LookupTable>,
+    load_instant: Instant,
+}
+
+/// Returns the lookup tables parsed by the previous fetches, so that they are not read from
+/// the index storage for every fetch.
+fn lookup_table_cache() -> &'static Mutex<LruCache<LookupTableKey, CachedLookupTable>> {
+    static INSTANCE: OnceCell<Mutex<LruCache<LookupTableKey, CachedLookupTable>>> = OnceCell::new();
+    INSTANCE.get_or_init(|| Mutex::new(LruCache::new(LOOKUP_TABLE_CACHE_CAPACITY)))
+}
+
+/// Joins the fetched documents with the lookup tables of the enrichments of an index.
+pub(crate) struct Enricher {
+    lookup_tables: Vec<(EnrichmentConfig, Arc<LookupTable>)>,
+}
+
+impl Enricher {
+    /// Loads the lookup tables of the `enrichments`, from the cache if their version is
+    /// already there, from the index storage otherwise.
+    pub async fn load(
+        enrichments: Vec<EnrichmentConfig>,
+        index_storage: Arc<dyn Storage>,
+    ) -> anyhow::Result<Self> {
+        let companion_files = CompanionFiles::new(index_storage.clone());
+        let mut lookup_tables = Vec::with_capacity(enrichments.len());
+        for enrichment in enrichments {
+            let lookup_table = load_lookup_table(
+                &enrichment.lookup_table_path,
+                &*index_storage,
+                &companion_files,
+            )
+            .await?;
+            lookup_tables.push((enrichment, lookup_table));
+        }
+        Ok(Enricher { lookup_tables })
+    }
+
+    /// Adds the entries of the lookup tables matching the values of the source fields to
+    /// the document.
+    ///
+    /// Values without a matching entry are skipped, and the target field is only added when
+    /// at least one value matches.
+    pub fn enrich(&self, doc_json: &str) -> anyhow::Result<String> {
+        let mut doc: JsonMap<String, JsonValue> =
+            serde_json::from_str(doc_json).context("Failed to parse fetched document.")?;
+        for (enrichment, lookup_table) in &self.lookup_tables {
+            let entries: Vec<JsonValue> = match doc.get(&enrichment.source_field) {
+                Some(JsonValue::Array(values)) => values
+                    .iter()
+                    .filter_map(lookup_key)
+                    .filter_map(|key| lookup_table.get(&key))
+                    .cloned()
+                    .collect(),
+                _ => Vec::new(),
+            };
+            if !entries.is_empty() {
+                doc.insert(enrichment.target_field.clone(), JsonValue::Array(entries));
+            }
+        }
+        Ok(serde_json::to_string(&doc)?)
+    }
+}
+
+/// Returns the lookup table at `path` in the index storage, or the latest version of the
+/// companion file it refers to.
+async fn load_lookup_table(
+    path: &str,
+    index_storage: &dyn Storage,
+    companion_files: &CompanionFiles,
+) -> anyhow::Result<Arc<LookupTable>> {
+    let load_error = || format!("Failed to load lookup table `{}`.", path);
+    let companion_name_opt = path.strip_prefix(COMPANION_FILE_PREFIX);
+    let version_opt = if let Some(name) = companion_name_opt {
+        let metadata = companion_files
+            .metadata(name)
+            .await
+            .with_context(load_error)?;
+        metadata
+            .latest_version()
+            .map(|file_version| file_version.version)
+    } else {
+        None
+    };
+    let key = LookupTableKey {
+        storage_uri: index_storage.uri(),
+        path: path.to_string(),
+        version_opt,
+    };
+    if let Some(cached) = lookup_table_cache().lock().unwrap().get(&key) {
+        if version_opt.is_some() || cached.load_instant.elapsed() < LOOKUP_TABLE_REFRESH_INTERVAL {
+            return Ok(cached.lookup_table.clone());
+        }
+    }
+    let content = if let Some(name) = companion_name_opt {
+        let (_, content) = companion_files
+            .get(name, version_opt)
+            .await
+            .with_context(load_error)?;
+        content
+    } else {
+        index_storage
+            .get_all(Path::new(path))
+            .await
+            .with_context(load_error)?
+    };
+    let lookup_table = Arc::new(parse_lookup_table(path, &content)?);
+    lookup_table_cache().lock().unwrap().put(
+        key,
+        CachedLookupTable {
+            lookup_table: lookup_table.clone(),
+            load_instant: Instant::now(),
+        },
+    );
+    Ok(lookup_table)
+}
+
+fn lookup_key(value: &JsonValue) -> Option<String> {
+    match value {
+        JsonValue::String(text) => Some(text.clone()),
+        JsonValue::Number(number) => Some(number.to_string()),
+        JsonValue::Bool(boolean) => Some(boolean.to_string()),
+        _ => None,
+    }
+}
+
+/// Parses a lookup table: a CSV file keyed by its first column if `path` ends with `.csv`,
+/// a JSON object mapping keys to entries otherwise.
+fn parse_lookup_table(path: &str, bytes: &[u8]) -> anyhow::Result<HashMap<String, JsonValue>> {
+    if path.ends_with(".csv") {
+        let text = std::str::from_utf8(bytes)
+            .with_context(|| format!("Lookup table `{}` is not valid UTF-8.", path))?;
+        return parse_csv_lookup_table(text)
+            .with_context(|| format!("Failed to parse CSV lookup table `{}`.", path));
+    }
+    serde_json::from_slice(bytes)
+        .with_context(|| format!("Failed to parse JSON lookup table `{}`.", path))
+}
+
+/// Parses a CSV lookup table into entries holding the columns but the first one, which is
+/// the key.
+fn parse_csv_lookup_table(text: &str) -> anyhow::Result<HashMap<String, JsonValue>> {
+    let mut records = parse_csv(text)?.into_iter();
+    let header = records.next().context("Missing header row.")?;
+    if header.len() < 2 {
+        bail!("Expected a key column and at least one value column.");
+    }
+    let mut lookup_table = HashMap::new();
+    for (record_idx, record) in records.enumerate() {
+        if record.len() != header.len() {
+            bail!(
+                "Row {} has {} columns, expected {}.",
+                record_idx + 1,
+                record.len(),
+                header.len()
+            );
+        }
+        let mut record = record.into_iter();
+        let key = record.next().unwrap_or_default();
+        let entry: JsonMap<String, JsonValue> = header[1..]
+            .iter()
+            .cloned()
+            .zip(record.map(JsonValue::String))
+            .collect();
+        lookup_table.insert(key, JsonValue::Object(entry));
+    }
+    Ok(lookup_table)
+}
+
+/// Splits a CSV text into records (RFC 4180), skipping the blank lines.
+fn parse_csv(text: &str) -> anyhow::Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c != '"' {
+                field.push(c);
+            } else if chars.peek() == Some(&'"') {
+                field.push('"');
+                chars.next();
+            } else {
+                in_quotes = false;
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        bail!("Unterminated quoted field.");
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|record| record.len() > 1 || record.iter().any(|field| !field.is_empty()));
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_storage::{PutPayload, RamStorage};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() -> anyhow::Result<()> {
+        assert_eq!(
+            parse_csv("ip,country\r\n10.0.0.1,\"France, \"\"FR\"\"\"\n\n10.0.0.2,US")?,
+            vec![
+                vec!["ip".to_string(), "country".to_string()],
+                vec!["10.0.0.1".to_string(), "France, \"FR\"".to_string()],
+                vec!["10.0.0.2".to_string(), "US".to_string()],
+            ]
+        );
+        assert!(parse_csv("ip,\"country").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_lookup_table() -> anyhow::Result<()> {
+        let csv_lookup_table = parse_lookup_table(
+            "ip_geo.csv",
+            b"ip,country,city\n10.0.0.1,FR,Paris\n10.0.0.2,US,Boston\n",
+        )?;
+        assert_eq!(csv_lookup_table.len(), 2);
+        assert_eq!(
+            csv_lookup_table["10.0.0.1"],
+            json!({"country": "FR", "city": "Paris"})
+        );
+        assert!(parse_lookup_table("ip_geo.csv", b"ip,country\n10.0.0.1\n").is_err());
+        assert!(parse_lookup_table("ip_geo.csv", b"ip\n10.0.0.1\n").is_err());
+
+        let json_lookup_table =
+            parse_lookup_table("teams.json", br#"{"auth": {"team": "identity"}, "42": 1}"#)?;
+        assert_eq!(json_lookup_table["auth"], json!({"team": "identity"}));
+        assert_eq!(json_lookup_table["42"], json!(1));
+        assert!(parse_lookup_table("teams.json", b"[]").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_enricher() -> anyhow::Result<()> {
+        let storage: Arc<dyn Storage> = Arc::new(
+            RamStorage::builder()
+                .put(
+                    "lookup/ip_geo.csv",
+                    b"ip,country\n10.0.0.1,FR\n10.0.0.2,US\n",
+                )
+                .put("lookup/teams.json", br#"{"auth": "identity"}"#)
+                .build(),
+        );
+        let enrichments = vec![
+            EnrichmentConfig {
+                source_field: "ip".to_string(),
+                target_field: "geo".to_string(),
+                lookup_table_path: "lookup/ip_geo.csv".to_string(),
+            },
+            EnrichmentConfig {
+                source_field: "service".to_string(),
+                target_field: "team".to_string(),
+                lookup_table_path: "lookup/teams.json".to_string(),
+            },
+        ];
+        let enricher = Enricher::load(enrichments, storage.clone()).await?;
+        let enriched_doc_json =
+            enricher.enrich(r#"{"ip": ["10.0.0.2", "10.0.0.3"], "service": ["billing"]}"#)?;
+        let enriched_doc: JsonValue = serde_json::from_str(&enriched_doc_json)?;
+        assert_eq!(
+            enriched_doc,
+            json!({
+                "ip": ["10.0.0.2", "10.0.0.3"],
+                "service": ["billing"],
+                "geo": [{"country": "US"}],
+            })
+        );
+
+        let missing_lookup_table = vec![EnrichmentConfig {
+            source_field: "ip".to_string(),
+            target_field: "geo".to_string(),
+            lookup_table_path: "lookup/missing.csv".to_string(),
+        }];
+        assert!(Enricher::load(missing_lookup_table, storage).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_enricher_with_companion_file() -> anyhow::Result<()> {
+        let storage: Arc<dyn Storage> = Arc::new(RamStorage::default());
+        let companion_files = CompanionFiles::new(storage.clone());
+        companion_files
+            .put(
+                "teams.csv",
+                PutPayload::from(&b"service,team\nauth,sso\n"[..]),
+            )
+            .await?;
+        companion_files
+            .put(
+                "teams.csv",
+                PutPayload::from(&b"service,team\nauth,identity\n"[..]),
+            )
+            .await?;
+        let enrichments = vec![EnrichmentConfig {
+            source_field: "service".to_string(),
+            target_field: "team".to_string(),
+            lookup_table_path: "companion:teams.csv".to_string(),
+        }];
+        let enricher = Enricher::load(enrichments, storage.clone()).await?;
+        let enriched_doc: JsonValue =
+            serde_json::from_str(&enricher.enrich(r#"{"service": ["auth"]}"#)?)?;
+        assert_eq!(
+            enriched_doc,
+            json!({"service": ["auth"], "team": [{"team": "identity"}]})
+        );
+
+        let missing_companion_file = vec![EnrichmentConfig {
+            source_field: "service".to_string(),
+            target_field: "team".to_string(),
+            lookup_table_path: "companion:missing.csv".to_string(),
+        }];
+        assert!(Enricher::load(missing_companion_file, storage)
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_lookup_table_is_cached_per_version() -> anyhow::Result<()> {
+        let storage: Arc<dyn Storage> = Arc::new(RamStorage::default());
+        let companion_files = CompanionFiles::new(storage.clone());
+        storage
+            .put(
+                Path::new("lookup/cached_hosts.json"),
+                PutPayload::from(&br#"{"web-1": "eu"}"#[..]),
+            )
+            .await?;
+        companion_files
+            .put(
+                "cached_teams.json",
+                PutPayload::from(&br#"{"auth": "sso"}"#[..]),
+            )
+            .await?;
+        let hosts_table =
+            load_lookup_table("lookup/cached_hosts.json", &*storage, &companion_files).await?;
+        let teams_table =
+            load_lookup_table("companion:cached_teams.json", &*storage, &companion_files).await?;
+        assert_eq!(teams_table["auth"], json!("sso"));
+
+        // The unchanged versions are not read again.
+        storage
+            .put(
+                Path::new("lookup/cached_hosts.json"),
+                PutPayload::from(&br#"{"web-1": "us"}"#[..]),
+            )
+            .await?;
+        assert!(Arc::ptr_eq(
+            &load_lookup_table("lookup/cached_hosts.json", &*storage, &companion_files).await?,
+            &hosts_table
+        ));
+        assert!(Arc::ptr_eq(
+            &load_lookup_table("companion:cached_teams.json", &*storage, &companion_files).await?,
+            &teams_table
+        ));
+
+        // A new version of a companion file is loaded right away.
+        companion_files
+            .put(
+                "cached_teams.json",
+                PutPayload::from(&br#"{"auth": "identity"}"#[..]),
+            )
+            .await?;
+        let new_teams_table =
+            load_lookup_table("companion:cached_teams.json", &*storage, &companion_files).await?;
+        assert_eq!(new_teams_table["auth"], json!("identity"));
+        Ok(())
+    }
+}
@@ -37,6 +37,10 @@ pub enum SearchError {
     StorageResolverError(#[from] StorageResolverError),
     #[error("Invalid query: {0}")]
     InvalidQuery(String),
+    #[error("Document `{address}` does not exist.")]
+    DocDoesNotExist { address: String },
+    #[error("Service unavailable: `{0}`.")]
+    Unavailable(String),
 }
 
 impl From<SearchError> for tonic::Status {
@@ -46,6 +50,9 @@ impl From<SearchError> for tonic::Status {
             SearchError::InternalError(_) => tonic::Code::Internal,
             SearchError::StorageResolverError(_) => tonic::Code::Internal,
             SearchError::InvalidQuery(_) => tonic::Code::InvalidArgument,
+            SearchError::DocDoesNotExist { .. } => tonic::Code::NotFound,
+            // `Unavailable` is kept for transport failures, which count against the node.
+            SearchError::Unavailable(_) => tonic::Code::Aborted,
         };
         let message = error.to_string();
         tonic::Status::new(code, message)
@@ -82,6 +89,9 @@ impl From<MetastoreError> for SearchError {
             MetastoreError::IndexDoesNotExist { index_id } => {
                 SearchError::IndexDoesNotExist { index_id }
             }
+            _ if metastore_error.is_transient() => {
+                SearchError::Unavailable(format!("{}", metastore_error))
+            }
             _ => SearchError::InternalError(format!("{}", metastore_error)),
         }
     }
@@ -0,0 +1,243 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use quickwit_metastore::{Metastore, SplitMetadataAndFooterOffsets};
+use quickwit_proto::{QueryWarning, SearchRequest};
+use serde::Serialize;
+
+use crate::federation::{resolve_index_targets, validate_federated_fields};
+use crate::root::validate_index_request;
+use crate::{list_relevant_splits, SearchClientPool};
+
+/// Rough duration of the opening of a split missing from the caches of a searcher: fetching its
+/// footer and hotcache from the storage.
+const ESTIMATED_SPLIT_OPENING_MILLIS: u64 = 50;
+
+/// Rough number of splits a searcher node opens concurrently.
+const ESTIMATED_CONCURRENT_SPLITS_PER_NODE: u64 = 8;
+
+/// Rough number of bytes a searcher node downloads and searches per second.
+const ESTIMATED_NODE_THROUGHPUT_BYTES_PER_SEC: u64 = 500_000_000;
+
+/// Estimated cost of a search, computed from the metadata of the splits it would search,
+/// without executing it.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchEstimate {
+    /// Number of splits the search would touch, once pruned by time range and tags.
+    pub num_splits: usize,
+    /// Number of documents of these splits.
+    pub num_docs: u64,
+    /// Size of the files of these splits.
+    pub num_bytes: u64,
+    /// Timestamp of the oldest document of these splits, if their indexes have a timestamp
+    /// field.
+    pub start_timestamp: Option<i64>,
+    /// Timestamp of the most recent document of these splits, if their indexes have a
+    /// timestamp field.
+    pub end_timestamp: Option<i64>,
+    /// Number of searcher nodes the splits would be spread over.
+    pub num_searcher_nodes: usize,
+    /// Rough estimate of the duration of the search, in milliseconds.
+    ///
+    /// It assumes the worst case: the splits are missing from the caches of the searchers,
+    /// and the search reads them entirely, e.g. to scan the documents matching a frequent term.
+    pub estimated_latency_millis: u64,
+    /// Warnings about the query, as returned by the search.
+    pub warnings: Vec<QueryWarning>,
+}
+
+/// Estimates the cost of a search without executing it.
+///
+/// The splits the search would touch are the published splits left by the pruning of the
+/// metastore on the time range and the tags of the request. Like the search, the request
+/// is validated against the config of its indexes.
+pub async fn root_estimate(
+    search_request: &SearchRequest,
+    metastore: &dyn Metastore,
+    client_pool: &SearchClientPool,
+) -> crate::Result<SearchEstimate> {
+    let index_targets = resolve_index_targets(search_request, metastore).await?;
+    validate_federated_fields(search_request, &index_targets)?;
+    let mut search_estimate = SearchEstimate::default();
+    for index_target in &index_targets {
+        search_estimate
+            .warnings
+            .extend(validate_index_request(index_target)?);
+        let index_id = &index_target.search_request.index_id;
+        let splits = list_relevant_splits(&index_target.search_request, metastore).await?;
+        let num_searcher_nodes = num_searcher_nodes(client_pool, index_id).await;
+        let num_bytes: u64 = splits.iter().map(|split| split.footer_offsets.end).sum();
+        let latency_millis = estimate_latency_millis(splits.len(), num_bytes, num_searcher_nodes);
+        // The indexes of a federated search are searched concurrently.
+        search_estimate.estimated_latency_millis =
+            search_estimate.estimated_latency_millis.max(latency_millis);
+        search_estimate.num_searcher_nodes =
+            search_estimate.num_searcher_nodes.max(num_searcher_nodes);
+        search_estimate.num_bytes += num_bytes;
+        add_splits(&mut search_estimate, &splits);
+    }
+    Ok(search_estimate)
+}
+
+fn add_splits(search_estimate: &mut SearchEstimate, splits: &[SplitMetadataAndFooterOffsets]) {
+    for split in splits {
+        search_estimate.num_splits += 1;
+        search_estimate.num_docs += split.split_metadata.num_records as u64;
+        if let Some(time_range) = &split.split_metadata.time_range {
+            search_estimate.start_timestamp = Some(
+                search_estimate
+                    .start_timestamp
+                    .map_or(*time_range.start(), |start| start.min(*time_range.start())),
+            );
+            search_estimate.end_timestamp = Some(
+                search_estimate
+                    .end_timestamp
+                    .map_or(*time_range.end(), |end| end.max(*time_range.end())),
+            );
+        }
+    }
+}
+
+// Returns the number of searcher nodes the jobs of the index would be assigned to. Like the
+// job assignment, the standby nodes only count if they are the only eligible nodes.
+async fn num_searcher_nodes(client_pool: &SearchClientPool, index_id: &str) -> usize {
+    let clients = client_pool.clients.read().await;
+    let eligible_clients: Vec<_> = clients
+        .values()
+        .filter(|client| {
+            client_pool
+                .placement_rules
+                .is_node_eligible(index_id, client.node_attributes().as_ref())
+        })
+        .collect();
+    let num_active_nodes = eligible_clients
+        .iter()
+        .filter(|client| !client.is_standby())
+        .count();
+    if num_active_nodes > 0 {
+        num_active_nodes
+    } else {
+        eligible_clients.len().max(1)
+    }
+}
+
+// The splits are spread evenly over the nodes, which open their splits a few at a time and
+// download them at a constant throughput.
+fn estimate_latency_millis(num_splits: usize, num_bytes: u64, num_searcher_nodes: usize) -> u64 {
+    if num_splits == 0 {
+        return 0;
+    }
+    let num_nodes = num_searcher_nodes.max(1) as u64;
+    let num_splits_per_node = (num_splits as u64 + num_nodes - 1) / num_nodes;
+    let num_opening_rounds = (num_splits_per_node + ESTIMATED_CONCURRENT_SPLITS_PER_NODE - 1)
+        / ESTIMATED_CONCURRENT_SPLITS_PER_NODE;
+    let opening_millis = num_opening_rounds * ESTIMATED_SPLIT_OPENING_MILLIS;
+    let download_millis = num_bytes / num_nodes * 1_000 / ESTIMATED_NODE_THROUGHPUT_BYTES_PER_SEC;
+    opening_millis + download_millis
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+    use std::sync::Arc;
+
+    use quickwit_common::QuickwitUri;
+    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_indexing::mock_split_meta;
+    use quickwit_metastore::checkpoint::Checkpoint;
+    use quickwit_metastore::{IndexMetadata, MockMetastore, SplitState};
+
+    use super::*;
+    use crate::MockSearchService;
+
+    #[test]
+    fn test_estimate_latency_millis() {
+        assert_eq!(estimate_latency_millis(0, 0, 1), 0);
+        assert_eq!(estimate_latency_millis(1, 0, 1), 50);
+        assert_eq!(estimate_latency_millis(8, 0, 1), 50);
+        assert_eq!(estimate_latency_millis(9, 0, 1), 100);
+        assert_eq!(estimate_latency_millis(9, 0, 2), 50);
+        assert_eq!(estimate_latency_millis(1, 1_000_000_000, 1), 2_050);
+        assert_eq!(estimate_latency_millis(2, 1_000_000_000, 2), 1_050);
+        assert_eq!(estimate_latency_millis(1, 1_000_000_000, 0), 2_050);
+    }
+
+    #[tokio::test]
+    async fn test_root_estimate() -> anyhow::Result<()> {
+        let mut metastore = MockMetastore::new();
+        metastore
+            .expect_index_metadata()
+            .returning(|_index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_list_splits().returning(
+            |_index_id: &str,
+             split_state: SplitState,
+             time_range: Option<Range<i64>>,
+             _tags: &[String]| {
+                assert_eq!(split_state, SplitState::Published);
+                assert_eq!(time_range, Some(10..i64::MAX));
+                let mut split1 = mock_split_meta("split1");
+                split1.split_metadata.time_range = Some(10..=20);
+                let mut split2 = mock_split_meta("split2");
+                split2.split_metadata.time_range = Some(15..=30);
+                Ok(vec![split1, split2])
+            },
+        );
+        let client_pool = SearchClientPool::from_mocks(vec![
+            Arc::new(MockSearchService::new()),
+            Arc::new(MockSearchService::new()),
+        ])
+        .await?;
+        let search_request = SearchRequest {
+            index_id: "test-idx".to_string(),
+            query: "test".to_string(),
+            start_timestamp: Some(10),
+            max_hits: 10,
+            ..Default::default()
+        };
+        let search_estimate = root_estimate(&search_request, &metastore, &client_pool).await?;
+        assert_eq!(
+            search_estimate,
+            SearchEstimate {
+                num_splits: 2,
+                num_docs: 20,
+                num_bytes: 1_600,
+                start_timestamp: Some(10),
+                end_timestamp: Some(30),
+                num_searcher_nodes: 2,
+                estimated_latency_millis: 50,
+                warnings: Vec::new(),
+            }
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,330 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bytes::Bytes;
+use chrono::Utc;
+use once_cell::sync::OnceCell;
+use quickwit_directories::CachingDirectory;
+use quickwit_proto::SplitIdAndFooterOffsets;
+use tantivy::directory::FileHandle;
+use tantivy::{Directory, HasLen, Index, SegmentComponent};
+use tracing::*;
+
+/// Parameters of fast field pinning.
+///
+/// The fast fields of the splits whose time range ends less than `time_window` ago
+/// are kept in memory, so that time filters and streaming exports on recent data
+/// do not read them from the storage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FastFieldPinningParams {
+    /// Age of the most recent document of a split under which its fast fields are pinned.
+    pub time_window: Duration,
+    /// Maximum number of bytes pinned in memory.
+    pub budget_in_bytes: usize,
+}
+
+static PINNED_FAST_FIELDS: OnceCell<PinnedFastFields> = OnceCell::new();
+
+/// Enables fast field pinning on this searcher node.
+///
+/// Fast field pinning can only be enabled once.
+pub fn enable_fast_field_pinning(params: FastFieldPinningParams) -> anyhow::Result<()> {
+    PINNED_FAST_FIELDS
+        .set(PinnedFastFields::new(params))
+        .map_err(|_| anyhow::anyhow!("Fast field pinning is already enabled."))
+}
+
+/// Returns the pinned fast fields, if fast field pinning is enabled.
+pub(crate) fn global_pinned_fast_fields() -> Option<&'static PinnedFastFields> {
+    PINNED_FAST_FIELDS.get()
+}
+
+struct PinnedSplit {
+    time_range_end: i64,
+    num_bytes: usize,
+    fast_field_files: Vec<(PathBuf, Bytes)>,
+}
+
+struct PinnedFastFieldsInner {
+    budget_in_bytes: usize,
+    num_bytes: usize,
+    pinned_splits: HashMap<String, PinnedSplit>,
+}
+
+/// Fast field files of the recent splits, kept in memory within a budget.
+///
+/// Unlike the slice caches, which only keep what has been read recently, the fast
+/// fields of a split are pinned as soon as the split is opened and stay pinned
+/// until the split leaves the time window.
+pub(crate) struct PinnedFastFields {
+    params: FastFieldPinningParams,
+    inner: Mutex<PinnedFastFieldsInner>,
+}
+
+impl PinnedFastFields {
+    pub fn new(params: FastFieldPinningParams) -> Self {
+        let inner = PinnedFastFieldsInner {
+            budget_in_bytes: params.budget_in_bytes,
+            num_bytes: 0,
+            pinned_splits: HashMap::new(),
+        };
+        PinnedFastFields {
+            params,
+            inner: Mutex::new(inner),
+        }
+    }
+
+    fn is_in_time_window(&self, time_range_end: i64, now: i64) -> bool {
+        time_range_end >= now - self.params.time_window.as_secs() as i64
+    }
+
+    /// Pins the fast field files of the split in `caching_directory`, fetching them
+    /// from `storage_directory` if they are not pinned yet.
+    ///
+    /// Splits outside of the time window and splits that do not fit in the budget
+    /// are left untouched.
+    pub async fn pin_split(
+        &self,
+        split: &SplitIdAndFooterOffsets,
+        index: &Index,
+        storage_directory: &dyn Directory,
+        caching_directory: &CachingDirectory,
+    ) -> anyhow::Result<()> {
+        let now = Utc::now().timestamp();
+        let time_range_end = match split.time_range_end {
+            Some(time_range_end) if self.is_in_time_window(time_range_end, now) => time_range_end,
+            _ => return Ok(()),
+        };
+        if let Some(fast_field_files) = self.pinned_fast_field_files(&split.split_id) {
+            for (path, file_bytes) in fast_field_files {
+                caching_directory.pin_file(path, file_bytes);
+            }
+            return Ok(());
+        }
+        let mut file_handles = Vec::new();
+        for segment_meta in index.searchable_segment_metas()? {
+            let path = segment_meta.relative_path(SegmentComponent::FastFields);
+            let file_handle = storage_directory.get_file_handle(&path)?;
+            file_handles.push((path, file_handle));
+        }
+        let num_bytes: usize = file_handles
+            .iter()
+            .map(|(_, file_handle)| file_handle.len())
+            .sum();
+        if !self.has_room_for(num_bytes, now) {
+            debug!(split_id = %split.split_id, num_bytes = num_bytes, "Fast field pinning budget exhausted.");
+            return Ok(());
+        }
+        let mut fast_field_files = Vec::new();
+        for (path, file_handle) in file_handles {
+            let file_bytes = file_handle.read_bytes_async(0..file_handle.len()).await?;
+            fast_field_files.push((path, Bytes::from(file_bytes.to_vec())));
+        }
+        let pinned_split = PinnedSplit {
+            time_range_end,
+            num_bytes,
+            fast_field_files: fast_field_files.clone(),
+        };
+        if self.insert(split.split_id.clone(), pinned_split, now) {
+            for (path, file_bytes) in fast_field_files {
+                caching_directory.pin_file(path, file_bytes);
+            }
+        }
+        Ok(())
+    }
+
+    fn pinned_fast_field_files(&self, split_id: &str) -> Option<Vec<(PathBuf, Bytes)>> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .pinned_splits
+            .get(split_id)
+            .map(|pinned_split| pinned_split.fast_field_files.clone())
+    }
+
+    /// Unpins the splits that left the time window.
+    fn unpin_cold_splits(&self, inner: &mut PinnedFastFieldsInner, now: i64) {
+        let mut num_unpinned_bytes = 0;
+        inner.pinned_splits.retain(|_, pinned_split| {
+            let is_in_time_window = self.is_in_time_window(pinned_split.time_range_end, now);
+            if !is_in_time_window {
+                num_unpinned_bytes += pinned_split.num_bytes;
+            }
+            is_in_time_window
+        });
+        inner.num_bytes -= num_unpinned_bytes;
+    }
+
+    fn has_room_for(&self, num_bytes: usize, now: i64) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        self.unpin_cold_splits(&mut inner, now);
+        inner.num_bytes + num_bytes <= inner.budget_in_bytes
+    }
+
+    /// Returns false if the split does not fit in the budget.
+    fn insert(&self, split_id: String, pinned_split: PinnedSplit, now: i64) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.pinned_splits.contains_key(&split_id) {
+            return true;
+        }
+        self.unpin_cold_splits(&mut inner, now);
+        if inner.num_bytes + pinned_split.num_bytes > inner.budget_in_bytes {
+            return false;
+        }
+        inner.num_bytes += pinned_split.num_bytes;
+        inner.pinned_splits.insert(split_id, pinned_split);
+        true
+    }
+
+    /// Returns the number of bytes pinned for each split.
+    pub fn split_num_bytes(&self) -> Vec<(String, usize)> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .pinned_splits
+            .iter()
+            .map(|(split_id, pinned_split)| (split_id.clone(), pinned_split.num_bytes))
+            .collect()
+    }
+
+    /// Returns the number of pinned bytes.
+    pub fn num_bytes(&self) -> usize {
+        self.inner.lock().unwrap().num_bytes
+    }
+
+    /// Returns the maximum number of bytes pinned in memory.
+    pub fn budget_in_bytes(&self) -> usize {
+        self.inner.lock().unwrap().budget_in_bytes
+    }
+
+    /// Unpins the fast fields of a split. Returns false if the split was not pinned.
+    ///
+    /// The files stay pinned in the directories of the splits opened already.
+    pub fn unpin_split(&self, split_id: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(pinned_split) = inner.pinned_splits.remove(split_id) {
+            inner.num_bytes -= pinned_split.num_bytes;
+            return true;
+        }
+        false
+    }
+
+    /// Unpins the fast fields of all of the splits.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pinned_splits.clear();
+        inner.num_bytes = 0;
+    }
+
+    /// Changes the pinning budget, unpinning the oldest splits that do not fit in it
+    /// anymore.
+    pub fn set_budget_in_bytes(&self, budget_in_bytes: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.budget_in_bytes = budget_in_bytes;
+        let mut pinned_splits: Vec<(i64, String)> = inner
+            .pinned_splits
+            .iter()
+            .map(|(split_id, pinned_split)| (pinned_split.time_range_end, split_id.clone()))
+            .collect();
+        pinned_splits.sort();
+        for (_, split_id) in pinned_splits {
+            if inner.num_bytes <= inner.budget_in_bytes {
+                break;
+            }
+            if let Some(pinned_split) = inner.pinned_splits.remove(&split_id) {
+                inner.num_bytes -= pinned_split.num_bytes;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pinned_split(time_range_end: i64, num_bytes: usize) -> PinnedSplit {
+        PinnedSplit {
+            time_range_end,
+            num_bytes,
+            fast_field_files: vec![(
+                PathBuf::from("segment.fast"),
+                Bytes::from(vec![0u8; num_bytes]),
+            )],
+        }
+    }
+
+    #[test]
+    fn test_pinned_fast_fields_budget_and_time_window() {
+        let pinned_fast_fields = PinnedFastFields::new(FastFieldPinningParams {
+            time_window: Duration::from_secs(60),
+            budget_in_bytes: 100,
+        });
+        let now = 1_000;
+        assert!(pinned_fast_fields.insert("split1".to_string(), pinned_split(now, 60), now));
+        assert!(pinned_fast_fields
+            .pinned_fast_field_files("split1")
+            .is_some());
+        assert!(!pinned_fast_fields.has_room_for(60, now));
+        assert!(!pinned_fast_fields.insert("split2".to_string(), pinned_split(now, 60), now));
+        assert!(pinned_fast_fields
+            .pinned_fast_field_files("split2")
+            .is_none());
+
+        // `split1` leaves the time window and gets unpinned.
+        let later = now + 61;
+        assert!(pinned_fast_fields.has_room_for(100, later));
+        assert!(pinned_fast_fields
+            .pinned_fast_field_files("split1")
+            .is_none());
+        assert!(pinned_fast_fields.insert("split2".to_string(), pinned_split(later, 60), later));
+    }
+
+    #[test]
+    fn test_pinned_fast_fields_admin() {
+        let pinned_fast_fields = PinnedFastFields::new(FastFieldPinningParams {
+            time_window: Duration::from_secs(60),
+            budget_in_bytes: 100,
+        });
+        let now = 1_000;
+        assert!(pinned_fast_fields.insert("split1".to_string(), pinned_split(now - 10, 30), now));
+        assert!(pinned_fast_fields.insert("split2".to_string(), pinned_split(now, 40), now));
+        assert!(pinned_fast_fields.insert("split3".to_string(), pinned_split(now, 20), now));
+        assert_eq!(pinned_fast_fields.num_bytes(), 90);
+
+        assert!(pinned_fast_fields.unpin_split("split3"));
+        assert!(!pinned_fast_fields.unpin_split("split3"));
+        assert_eq!(pinned_fast_fields.num_bytes(), 70);
+
+        // `split1` is the oldest split.
+        pinned_fast_fields.set_budget_in_bytes(50);
+        assert_eq!(pinned_fast_fields.budget_in_bytes(), 50);
+        assert_eq!(
+            pinned_fast_fields.split_num_bytes(),
+            vec![("split2".to_string(), 40)]
+        );
+        assert!(!pinned_fast_fields.has_room_for(20, now));
+
+        pinned_fast_fields.clear();
+        assert_eq!(pinned_fast_fields.num_bytes(), 0);
+        assert!(pinned_fast_fields.split_num_bytes().is_empty());
+    }
+}
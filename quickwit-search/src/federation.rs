@@ -0,0 +1,302 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use quickwit_index_config::IndexConfig;
+use quickwit_metastore::{retry_on_transient_error, IndexMetadata, Metastore};
+use quickwit_proto::SearchRequest;
+use tantivy::schema::{Schema, Type};
+
+use crate::field_aliases::resolve_request_field_aliases;
+use crate::SearchError;
+
+/// Separates the IDs of the indexes searched together, e.g. `logs-app,logs-infra`.
+const INDEX_ID_SEPARATOR: char = ',';
+
+/// Returns the IDs of the indexes targeted by `index_id`, which lists the indexes of a
+/// federated search separated by commas.
+pub fn parse_index_ids(index_id: &str) -> Vec<&str> {
+    index_id
+        .split(INDEX_ID_SEPARATOR)
+        .map(str::trim)
+        .filter(|index_id| !index_id.is_empty())
+        .collect()
+}
+
+/// An index searched by a root search, along with the request its leaves execute.
+pub(crate) struct IndexTarget {
+    pub index_metadata: IndexMetadata,
+    pub search_request: SearchRequest,
+}
+
+/// Resolves the indexes targeted by a search request.
+///
+/// Each index gets its own copy of the request, which its leaves execute with the index
+/// config of the index. In a federated search, the search fields are the union of the fields
+/// of the indexes: each index is only searched on the ones it has, and the indexes that have
+/// none of them are skipped.
+///
+/// The field aliases of the index config of each index are resolved in its copy of the request.
+pub(crate) async fn resolve_index_targets(
+    search_request: &SearchRequest,
+    metastore: &dyn Metastore,
+) -> crate::Result<Vec<IndexTarget>> {
+    let index_ids = parse_index_ids(&search_request.index_id);
+    if index_ids.len() <= 1 {
+        let index_metadata =
+            retry_on_transient_error(|| metastore.index_metadata(&search_request.index_id)).await?;
+        let mut index_search_request = search_request.clone();
+        resolve_request_field_aliases(&mut index_search_request, &*index_metadata.index_config);
+        return Ok(vec![IndexTarget {
+            index_metadata,
+            search_request: index_search_request,
+        }]);
+    }
+    let mut index_targets = Vec::with_capacity(index_ids.len());
+    for index_id in index_ids {
+        let index_metadata =
+            retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+        let schema = index_metadata.index_config.schema();
+        let mut index_search_request = search_request.clone();
+        index_search_request.index_id = index_id.to_string();
+        resolve_request_field_aliases(&mut index_search_request, &*index_metadata.index_config);
+        if !search_request.search_fields.is_empty() {
+            index_search_request
+                .search_fields
+                .retain(|field_name| schema.get_field(field_name).is_some());
+            if index_search_request.search_fields.is_empty() {
+                continue;
+            }
+        }
+        index_targets.push(IndexTarget {
+            index_metadata,
+            search_request: index_search_request,
+        });
+    }
+    if index_targets.is_empty() {
+        return Err(SearchError::InvalidQuery(format!(
+            "None of the indexes `{}` has any of the search fields {:?}.",
+            search_request.index_id, search_request.search_fields
+        )));
+    }
+    Ok(index_targets)
+}
+
+/// Checks that the results of the indexes of a federated search can be merged.
+///
+/// The indexes must sort their hits the same way, and the fields of the aggregations must
+/// have the same type in all of them, once resolved in the field aliases of each index.
+pub(crate) fn validate_federated_fields(
+    search_request: &SearchRequest,
+    index_targets: &[IndexTarget],
+) -> crate::Result<()> {
+    let (first_target, other_targets) = if let Some(targets) = index_targets.split_first() {
+        targets
+    } else {
+        return Ok(());
+    };
+    let first_index_id = &first_target.index_metadata.index_id;
+    let first_index_config = &*first_target.index_metadata.index_config;
+    let first_schema = first_index_config.schema();
+    let mut field_names: Vec<&str> = search_request
+        .distinct_count_fields
+        .iter()
+        .chain(search_request.percentile_fields.iter())
+        .map(String::as_str)
+        .collect();
+    if let Some(terms_aggregation) = &search_request.terms_aggregation {
+        field_names.push(&terms_aggregation.field_name);
+        field_names.extend(terms_aggregation.metric_field.as_deref());
+    }
+    if let Some(date_histogram) = &search_request.date_histogram {
+        field_names.push(&date_histogram.field_name);
+    }
+    if let Some(geo_filter) = &search_request.geo_filter {
+        field_names.push(&geo_filter.field_name);
+    }
+    for other_target in other_targets {
+        let other_index_id = &other_target.index_metadata.index_id;
+        let other_index_config = &*other_target.index_metadata.index_config;
+        if other_index_config.sort_by() != first_index_config.sort_by() {
+            return Err(SearchError::InvalidQuery(format!(
+                "The indexes `{}` and `{}` sort their documents differently, so they cannot be \
+                 searched together.",
+                first_index_id, other_index_id
+            )));
+        }
+        let other_schema = other_index_config.schema();
+        for field_name in &field_names {
+            let first_type_opt = field_value_type(first_index_config, &first_schema, field_name);
+            let other_type_opt = field_value_type(other_index_config, &other_schema, field_name);
+            if first_type_opt != other_type_opt {
+                return Err(SearchError::InvalidQuery(format!(
+                    "The field `{}` has the type {:?} in the index `{}` and {:?} in `{}`, so its \
+                     values cannot be merged.",
+                    field_name, first_type_opt, first_index_id, other_type_opt, other_index_id
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn field_value_type(
+    index_config: &dyn IndexConfig,
+    schema: &Schema,
+    field_name: &str,
+) -> Option<Type> {
+    let field_name = index_config
+        .resolve_field_alias(field_name)
+        .unwrap_or(field_name);
+    let field = schema.get_field(field_name)?;
+    Some(schema.get_field_entry(field).field_type().value_type())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use quickwit_common::QuickwitUri;
+    use quickwit_index_config::{DefaultIndexConfigBuilder, IndexConfig};
+    use quickwit_metastore::checkpoint::Checkpoint;
+    use quickwit_metastore::MockMetastore;
+    use quickwit_proto::TermsAggregation;
+
+    use super::*;
+
+    fn index_metadata(index_id: &str, field_mappings_json: &str) -> IndexMetadata {
+        let index_config_json = format!(
+            r#"{{"default_search_fields": [], "field_mappings": {}}}"#,
+            field_mappings_json
+        );
+        let index_config: Arc<dyn IndexConfig> = Arc::new(
+            serde_json::from_str::<DefaultIndexConfigBuilder>(&index_config_json)
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+        IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_well_formed(&format!("ram:///indexes/{}", index_id)),
+            index_config,
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: 0,
+        }
+    }
+
+    fn mock_metastore() -> MockMetastore {
+        let mut metastore = MockMetastore::new();
+        metastore
+            .expect_index_metadata()
+            .returning(|index_id: &str| match index_id {
+                "logs-app" => Ok(index_metadata(
+                    "logs-app",
+                    r#"[{"name": "message", "type": "text"}, {"name": "status", "type": "u64", "fast": true}]"#,
+                )),
+                "logs-infra" => Ok(index_metadata(
+                    "logs-infra",
+                    r#"[{"name": "host", "type": "text"}, {"name": "status", "type": "i64", "fast": true}]"#,
+                )),
+                _ => Err(quickwit_metastore::MetastoreError::IndexDoesNotExist {
+                    index_id: index_id.to_string(),
+                }),
+            });
+        metastore
+    }
+
+    #[test]
+    fn test_parse_index_ids() {
+        assert_eq!(parse_index_ids("logs-app"), vec!["logs-app"]);
+        assert_eq!(
+            parse_index_ids("logs-app, logs-infra,"),
+            vec!["logs-app", "logs-infra"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_index_targets() -> anyhow::Result<()> {
+        let metastore = mock_metastore();
+        let search_request = SearchRequest {
+            index_id: "logs-app,logs-infra".to_string(),
+            query: "error".to_string(),
+            search_fields: vec!["message".to_string(), "host".to_string()],
+            ..Default::default()
+        };
+        let index_targets = resolve_index_targets(&search_request, &metastore).await?;
+        assert_eq!(index_targets.len(), 2);
+        assert_eq!(index_targets[0].search_request.index_id, "logs-app");
+        assert_eq!(
+            index_targets[0].search_request.search_fields,
+            vec!["message".to_string()]
+        );
+        assert_eq!(index_targets[1].search_request.index_id, "logs-infra");
+        assert_eq!(
+            index_targets[1].search_request.search_fields,
+            vec!["host".to_string()]
+        );
+
+        let search_request = SearchRequest {
+            search_fields: vec!["message".to_string()],
+            ..search_request
+        };
+        let index_targets = resolve_index_targets(&search_request, &metastore).await?;
+        assert_eq!(index_targets.len(), 1);
+        assert_eq!(index_targets[0].index_metadata.index_id, "logs-app");
+
+        let search_request = SearchRequest {
+            search_fields: vec!["missing".to_string()],
+            ..search_request
+        };
+        assert!(matches!(
+            resolve_index_targets(&search_request, &metastore).await,
+            Err(SearchError::InvalidQuery(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_federated_fields() -> anyhow::Result<()> {
+        let metastore = mock_metastore();
+        let search_request = SearchRequest {
+            index_id: "logs-app,logs-infra".to_string(),
+            query: "*".to_string(),
+            ..Default::default()
+        };
+        let index_targets = resolve_index_targets(&search_request, &metastore).await?;
+        assert!(validate_federated_fields(&search_request, &index_targets).is_ok());
+
+        let search_request = SearchRequest {
+            terms_aggregation: Some(TermsAggregation {
+                field_name: "status".to_string(),
+                size: 10,
+                ..Default::default()
+            }),
+            ..search_request
+        };
+        assert!(matches!(
+            validate_federated_fields(&search_request, &index_targets),
+            Err(SearchError::InvalidQuery(_))
+        ));
+        Ok(())
+    }
+}
@@ -22,11 +22,14 @@ use std::sync::Arc;
 
 use anyhow::Context;
 use itertools::Itertools;
-use quickwit_proto::{FetchDocsResponse, Hit, PartialHit, SplitIdAndFooterOffsets};
+use quickwit_index_config::EnrichmentConfig;
+use quickwit_proto::{CachePolicy, FetchDocsResponse, Hit, PartialHit, SplitIdAndFooterOffsets};
 use quickwit_storage::Storage;
+use serde_json::{Map as JsonMap, Value as JsonValue};
 use tantivy::{IndexReader, ReloadPolicy};
 use tracing::error;
 
+use crate::enrichment::Enricher;
 use crate::leaf::open_index;
 use crate::GlobalDocAddress;
 
@@ -37,6 +40,7 @@ async fn fetch_docs_to_map<'a>(
     mut global_doc_addrs: Vec<GlobalDocAddress<'a>>,
     index_storage: Arc<dyn Storage>,
     splits: &[SplitIdAndFooterOffsets],
+    cache_policy: CachePolicy,
 ) -> anyhow::Result<HashMap<GlobalDocAddress<'a>, String>> {
     let mut split_fetch_docs_futures = Vec::new();
 
@@ -61,6 +65,7 @@ async fn fetch_docs_to_map<'a>(
             global_doc_addrs,
             index_storage.clone(),
             *split_and_offset,
+            cache_policy,
         ));
     }
 
@@ -93,21 +98,31 @@ async fn fetch_docs_to_map<'a>(
 ///
 /// This function takes a list of partial hits (possibly from different splits)
 /// and the storage associated to an index, fetches the document from
-/// the split document stores, and returns the full hits.
+/// the split document stores, joins them with the lookup tables of the
+/// `enrichments`, and returns the full hits, flattened if `flatten_docs` is set.
+/// The splits are opened according to `cache_policy`, as in the leaf search.
 pub async fn fetch_docs(
     partial_hits: Vec<PartialHit>,
     index_storage: Arc<dyn Storage>,
     splits: &[SplitIdAndFooterOffsets],
+    enrichments: Vec<EnrichmentConfig>,
+    flatten_docs: bool,
+    cache_policy: CachePolicy,
 ) -> anyhow::Result<FetchDocsResponse> {
     let global_doc_addrs: Vec<GlobalDocAddress> = partial_hits
         .iter()
         .map(GlobalDocAddress::from_partial_hit)
         .collect();
 
-    let mut global_doc_addr_to_doc_json =
-        fetch_docs_to_map(global_doc_addrs, index_storage, splits).await?;
+    let mut global_doc_addr_to_doc_json = fetch_docs_to_map(
+        global_doc_addrs,
+        index_storage.clone(),
+        splits,
+        cache_policy,
+    )
+    .await?;
 
-    let hits: Vec<Hit> = partial_hits
+    let mut hits: Vec<Hit> = partial_hits
         .iter()
         .flat_map(|partial_hit| {
             let global_doc_addr = GlobalDocAddress::from_partial_hit(partial_hit);
@@ -115,21 +130,71 @@ pub async fn fetch_docs(
                 Some(Hit {
                     json,
                     partial_hit: Some(partial_hit.clone()),
+                    address: String::new(),
                 })
             } else {
                 None
             }
         })
         .collect();
+    if !enrichments.is_empty() {
+        let enricher = Enricher::load(enrichments, index_storage.clone()).await?;
+        for hit in hits.iter_mut() {
+            hit.json = enricher.enrich(&hit.json)?;
+        }
+    }
+    if flatten_docs {
+        for hit in hits.iter_mut() {
+            hit.json = flatten_hit_json(&hit.json)?;
+        }
+    }
     Ok(FetchDocsResponse { hits })
 }
 
+/// Flattens the keys of the nested objects of the JSON document `json` into
+/// dot-separated keys: `{"user": {"name": "fred"}}` becomes `{"user.name": "fred"}`.
+///
+/// Arrays are kept as they are, including the objects they contain.
+pub(crate) fn flatten_hit_json(json: &str) -> anyhow::Result<String> {
+    let doc: JsonValue = serde_json::from_str(json)?;
+    let doc_obj = match doc {
+        JsonValue::Object(doc_obj) => doc_obj,
+        _ => return Ok(json.to_string()),
+    };
+    let mut flattened_doc = JsonMap::new();
+    flatten_json_object("", doc_obj, &mut flattened_doc);
+    Ok(serde_json::to_string(&flattened_doc)?)
+}
+
+fn flatten_json_object(
+    key_prefix: &str,
+    json_obj: JsonMap<String, JsonValue>,
+    flattened_obj: &mut JsonMap<String, JsonValue>,
+) {
+    for (key, value) in json_obj {
+        let flattened_key = if key_prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", key_prefix, key)
+        };
+        match value {
+            JsonValue::Object(sub_obj) if !sub_obj.is_empty() => {
+                flatten_json_object(&flattened_key, sub_obj, flattened_obj);
+            }
+            _ => {
+                flattened_obj.insert(flattened_key, value);
+            }
+        }
+    }
+}
+
 async fn get_searcher_for_split(
     num_searchers: usize,
     index_storage: Arc<dyn Storage>,
     split: &SplitIdAndFooterOffsets,
+    cache_policy: CachePolicy,
 ) -> anyhow::Result<IndexReader> {
-    let index = open_index(index_storage, split)
+    let index = open_index(index_storage, split, cache_policy)
         .await
         .with_context(|| "open-index-for-split")?;
     let reader = index
@@ -141,15 +206,35 @@ async fn get_searcher_for_split(
 }
 
 /// Fetching docs from a specific split.
+///
+/// The addresses may come from the users, through the hit addresses, so the ones pointing past
+/// the segments of the split or past their documents are skipped: their documents do not exist.
 #[tracing::instrument(skip(global_doc_addrs, index_storage, split))]
 #[allow(clippy::needless_lifetimes)]
 async fn fetch_docs_in_split<'a>(
     global_doc_addrs: Vec<GlobalDocAddress<'a>>,
     index_storage: Arc<dyn Storage>,
     split: &SplitIdAndFooterOffsets,
+    cache_policy: CachePolicy,
 ) -> anyhow::Result<Vec<(GlobalDocAddress<'a>, String)>> {
-    let index_reader = get_searcher_for_split(global_doc_addrs.len(), index_storage, split).await?;
-    let doc_futures = global_doc_addrs.into_iter().map(|global_doc_addr| {
+    let index_reader =
+        get_searcher_for_split(global_doc_addrs.len(), index_storage, split, cache_policy).await?;
+    // The searcher is released before fetching the docs, which lease one searcher each.
+    let existing_doc_addrs: Vec<GlobalDocAddress<'a>> = {
+        let searcher = index_reader.searcher();
+        let segment_readers = searcher.segment_readers();
+        global_doc_addrs
+            .into_iter()
+            .filter(|global_doc_addr| {
+                let doc_addr = global_doc_addr.doc_addr;
+                segment_readers
+                    .get(doc_addr.segment_ord as usize)
+                    .map(|segment_reader| doc_addr.doc_id < segment_reader.max_doc())
+                    .unwrap_or(false)
+            })
+            .collect()
+    };
+    let doc_futures = existing_doc_addrs.into_iter().map(|global_doc_addr| {
         let searcher = index_reader.searcher();
         async move {
             let doc = searcher
@@ -162,3 +247,79 @@ async fn fetch_docs_in_split<'a>(
     });
     futures::future::try_join_all(doc_futures).await
 }
+
+#[cfg(test)]
+mod tests {
+    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_indexing::TestSandbox;
+    use serde_json::json;
+
+    use super::*;
+    use crate::extract_split_and_footer_offsets;
+
+    #[tokio::test]
+    async fn test_fetch_docs_skips_out_of_range_addresses() -> anyhow::Result<()> {
+        let index_id = "fetch-docs-out-of-range";
+        let test_sandbox =
+            TestSandbox::create(index_id, Arc::new(WikipediaIndexConfig::new())).await?;
+        test_sandbox
+            .add_documents(vec![
+                json!({"title": "snoopy", "body": "beagle", "url": "http://snoopy"}),
+            ])
+            .await?;
+        let split_meta = test_sandbox
+            .metastore()
+            .list_all_splits(index_id)
+            .await?
+            .into_iter()
+            .next()
+            .unwrap();
+        let split = extract_split_and_footer_offsets(&split_meta);
+        let partial_hit = |segment_ord: u32, doc_id: u32| PartialHit {
+            split_id: split.split_id.clone(),
+            segment_ord,
+            doc_id,
+            ..Default::default()
+        };
+        let fetch_docs_response = fetch_docs(
+            vec![partial_hit(0, 0), partial_hit(0, 1), partial_hit(1, 0)],
+            test_sandbox.index_storage(index_id)?,
+            &[split.clone()],
+            Vec::new(),
+            false,
+            CachePolicy::Use,
+        )
+        .await?;
+        assert_eq!(fetch_docs_response.hits.len(), 1);
+        assert_eq!(
+            fetch_docs_response.hits[0].partial_hit,
+            Some(partial_hit(0, 0))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatten_hit_json() -> anyhow::Result<()> {
+        let json = json!({
+            "title": "snoopy",
+            "user": {"name": "fred", "address": {"city": "paris"}},
+            "tags": [{"name": "dog"}],
+            "empty": {},
+        })
+        .to_string();
+        let flattened_doc: JsonValue = serde_json::from_str(&flatten_hit_json(&json)?)?;
+        assert_eq!(
+            flattened_doc,
+            json!({
+                "title": "snoopy",
+                "user.name": "fred",
+                "user.address.city": "paris",
+                "tags": [{"name": "dog"}],
+                "empty": {},
+            })
+        );
+        assert_eq!(flatten_hit_json("[1,2]")?, "[1,2]");
+        assert!(flatten_hit_json("{").is_err());
+        Ok(())
+    }
+}
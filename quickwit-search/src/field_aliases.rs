@@ -0,0 +1,163 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use quickwit_index_config::IndexConfig;
+use quickwit_proto::{SearchRequest, SearchStreamRequest};
+
+fn resolve_field_name(field_name: &mut String, index_config: &dyn IndexConfig) {
+    if let Some(resolved_field_name) = index_config.resolve_field_alias(field_name) {
+        *field_name = resolved_field_name.to_string();
+    }
+}
+
+/// Resolves the field alias of a `field:value` tag.
+fn resolve_tag(tag: &mut String, index_config: &dyn IndexConfig) {
+    if let Some((field_name, value)) = tag.split_once(':') {
+        if let Some(resolved_field_name) = index_config.resolve_field_alias(field_name) {
+            *tag = format!("{}:{}", resolved_field_name, value);
+        }
+    }
+}
+
+/// Replaces the field aliases among the field names of a search request by the field names
+/// they stand for, so that sorting, pruning splits by tags or aggregating on an alias behaves
+/// exactly like on its field. The aliases of the query itself are resolved when it is built.
+pub(crate) fn resolve_request_field_aliases(
+    search_request: &mut SearchRequest,
+    index_config: &dyn IndexConfig,
+) {
+    let field_names = search_request
+        .search_fields
+        .iter_mut()
+        .chain(search_request.distinct_count_fields.iter_mut())
+        .chain(search_request.percentile_fields.iter_mut())
+        .chain(search_request.sort_by_field.iter_mut());
+    for field_name in field_names {
+        resolve_field_name(field_name, index_config);
+    }
+    for tag in search_request.tags.iter_mut() {
+        resolve_tag(tag, index_config);
+    }
+    if let Some(terms_aggregation) = search_request.terms_aggregation.as_mut() {
+        resolve_field_name(&mut terms_aggregation.field_name, index_config);
+        if let Some(metric_field) = terms_aggregation.metric_field.as_mut() {
+            resolve_field_name(metric_field, index_config);
+        }
+    }
+    if let Some(date_histogram) = search_request.date_histogram.as_mut() {
+        resolve_field_name(&mut date_histogram.field_name, index_config);
+    }
+    if let Some(geo_filter) = search_request.geo_filter.as_mut() {
+        resolve_field_name(&mut geo_filter.field_name, index_config);
+    }
+}
+
+/// Same as [`resolve_request_field_aliases`] for a search stream request.
+pub(crate) fn resolve_stream_request_field_aliases(
+    search_stream_request: &mut SearchStreamRequest,
+    index_config: &dyn IndexConfig,
+) {
+    let field_names = search_stream_request
+        .search_fields
+        .iter_mut()
+        .chain(std::iter::once(&mut search_stream_request.fast_field))
+        .chain(search_stream_request.partition_by_field.iter_mut());
+    for field_name in field_names {
+        resolve_field_name(field_name, index_config);
+    }
+    for tag in search_stream_request.tags.iter_mut() {
+        resolve_tag(tag, index_config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_index_config::{DefaultIndexConfigBuilder, IndexConfig};
+    use quickwit_proto::{SearchRequest, SearchStreamRequest, TermsAggregation};
+
+    use super::{resolve_request_field_aliases, resolve_stream_request_field_aliases};
+
+    fn index_config_with_aliases() -> anyhow::Result<impl IndexConfig> {
+        let index_config_json = r#"{
+            "default_search_fields": ["body"],
+            "tag_fields": ["service"],
+            "field_aliases": {
+                "message": "body",
+                "@timestamp": "ts",
+                "app": "service"
+            },
+            "field_mappings": [
+                {"name": "body", "type": "text"},
+                {"name": "ts", "type": "i64", "fast": true},
+                {"name": "service", "type": "text", "tokenizer": "raw"}
+            ]
+        }"#;
+        Ok(serde_json::from_str::<DefaultIndexConfigBuilder>(index_config_json)?.build()?)
+    }
+
+    #[test]
+    fn test_resolve_request_field_aliases() -> anyhow::Result<()> {
+        let index_config = index_config_with_aliases()?;
+        let mut search_request = SearchRequest {
+            index_id: "test-index".to_string(),
+            query: "message:foo".to_string(),
+            search_fields: vec!["message".to_string(), "service".to_string()],
+            tags: vec!["app:api".to_string(), "service:web".to_string()],
+            sort_by_field: Some("@timestamp".to_string()),
+            distinct_count_fields: vec!["@timestamp".to_string()],
+            terms_aggregation: Some(TermsAggregation {
+                field_name: "app".to_string(),
+                metric_field: Some("@timestamp".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        resolve_request_field_aliases(&mut search_request, &index_config);
+        assert_eq!(search_request.query, "message:foo");
+        assert_eq!(search_request.search_fields, vec!["body", "service"]);
+        assert_eq!(search_request.tags, vec!["service:api", "service:web"]);
+        assert_eq!(search_request.sort_by_field.as_deref(), Some("ts"));
+        assert_eq!(search_request.distinct_count_fields, vec!["ts"]);
+        let terms_aggregation = search_request.terms_aggregation.unwrap();
+        assert_eq!(terms_aggregation.field_name, "service");
+        assert_eq!(terms_aggregation.metric_field.as_deref(), Some("ts"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_stream_request_field_aliases() -> anyhow::Result<()> {
+        let index_config = index_config_with_aliases()?;
+        let mut search_stream_request = SearchStreamRequest {
+            index_id: "test-index".to_string(),
+            query: "*".to_string(),
+            fast_field: "@timestamp".to_string(),
+            partition_by_field: Some("app".to_string()),
+            tags: vec!["app:api".to_string()],
+            ..Default::default()
+        };
+        resolve_stream_request_field_aliases(&mut search_stream_request, &index_config);
+        assert_eq!(search_stream_request.fast_field, "ts");
+        assert_eq!(
+            search_stream_request.partition_by_field.as_deref(),
+            Some("service")
+        );
+        assert_eq!(search_stream_request.tags, vec!["service:api"]);
+        Ok(())
+    }
+}
@@ -19,10 +19,14 @@
 
 use std::ops::{Bound, RangeBounds};
 
+use quickwit_index_config::GeoPoint;
+use quickwit_proto::GeoFilter;
 use tantivy::fastfield::{DynamicFastFieldReader, FastFieldReader};
 use tantivy::schema::{Field, Type};
 use tantivy::{DocId, SegmentReader, TantivyError};
 
+use crate::geo_filter::GeoArea;
+
 /// A filter that only retains docs within a time range.
 #[derive(Clone)]
 pub struct TimestampFilter {
@@ -87,6 +91,43 @@ impl TimestampFilter {
     }
 }
 
+/// A filter that only retains docs whose geo point is within an area.
+#[derive(Clone)]
+pub struct GeoPointFilter {
+    /// The area the geo points must be in.
+    area: GeoArea,
+    /// The geo point fast field reader.
+    geo_point_field_reader: DynamicFastFieldReader<u64>,
+}
+
+impl GeoPointFilter {
+    pub fn new(geo_filter: &GeoFilter, segment_reader: &SegmentReader) -> tantivy::Result<Self> {
+        let field = segment_reader
+            .schema()
+            .get_field(&geo_filter.field_name)
+            .ok_or_else(|| {
+                TantivyError::SchemaError(format!(
+                    "Field `{}` does not exist",
+                    geo_filter.field_name
+                ))
+            })?;
+        let area = GeoArea::from_geo_filter(geo_filter)
+            .map_err(|error| TantivyError::InvalidArgument(error.to_string()))?;
+        let geo_point_field_reader = segment_reader.fast_fields().u64(field)?;
+        Ok(GeoPointFilter {
+            area,
+            geo_point_field_reader,
+        })
+    }
+
+    /// Returns true if the doc has a geo point within the area.
+    pub fn is_within_area(&self, doc_id: DocId) -> bool {
+        GeoPoint::from_u64(self.geo_point_field_reader.get(doc_id))
+            .map(|point| self.area.contains(&point))
+            .unwrap_or(false)
+    }
+}
+
 /// Determine if all docs of a segment always satisfy the requested timestamp range.
 ///
 /// Note:
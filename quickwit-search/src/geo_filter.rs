@@ -0,0 +1,228 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use quickwit_index_config::{GeoPoint, IndexConfig};
+use quickwit_proto::GeoFilter;
+
+use crate::SearchError;
+
+/// Area the geo points of the documents must be in to satisfy a geo filter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum GeoArea {
+    /// Box delimited by two parallels and two meridians. It crosses the antimeridian if
+    /// `left_lon > right_lon`.
+    BoundingBox {
+        top_lat: f64,
+        left_lon: f64,
+        bottom_lat: f64,
+        right_lon: f64,
+    },
+    /// Points within `distance_in_meters` of `center`.
+    Circle {
+        center: GeoPoint,
+        distance_in_meters: f64,
+    },
+}
+
+impl GeoArea {
+    /// Returns the area of `geo_filter`, or an error if it is not well-formed.
+    pub fn from_geo_filter(geo_filter: &GeoFilter) -> crate::Result<GeoArea> {
+        let invalid_geo_filter = |reason: String| {
+            SearchError::InvalidQuery(format!(
+                "Invalid geo filter on field `{}`: {}",
+                geo_filter.field_name, reason
+            ))
+        };
+        match (&geo_filter.bounding_box, &geo_filter.distance) {
+            (Some(bounding_box), None) => {
+                let top_left = GeoPoint::new(bounding_box.top_lat, bounding_box.left_lon)
+                    .map_err(|error| invalid_geo_filter(error.to_string()))?;
+                let bottom_right = GeoPoint::new(bounding_box.bottom_lat, bounding_box.right_lon)
+                    .map_err(|error| invalid_geo_filter(error.to_string()))?;
+                if top_left.lat < bottom_right.lat {
+                    return Err(invalid_geo_filter(format!(
+                        "the top latitude `{}` is below the bottom latitude `{}`",
+                        top_left.lat, bottom_right.lat
+                    )));
+                }
+                Ok(GeoArea::BoundingBox {
+                    top_lat: top_left.lat,
+                    left_lon: top_left.lon,
+                    bottom_lat: bottom_right.lat,
+                    right_lon: bottom_right.lon,
+                })
+            }
+            (None, Some(distance)) => {
+                let center = GeoPoint::new(distance.lat, distance.lon)
+                    .map_err(|error| invalid_geo_filter(error.to_string()))?;
+                if distance.distance_in_meters.is_nan() || distance.distance_in_meters < 0.0 {
+                    return Err(invalid_geo_filter(format!(
+                        "the distance `{}` is not a positive number of meters",
+                        distance.distance_in_meters
+                    )));
+                }
+                Ok(GeoArea::Circle {
+                    center,
+                    distance_in_meters: distance.distance_in_meters,
+                })
+            }
+            _ => Err(invalid_geo_filter(
+                "expected either a bounding box or a distance".to_string(),
+            )),
+        }
+    }
+
+    /// Returns true if `point` is within the area.
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        match *self {
+            GeoArea::BoundingBox {
+                top_lat,
+                left_lon,
+                bottom_lat,
+                right_lon,
+            } => {
+                if point.lat < bottom_lat || point.lat > top_lat {
+                    return false;
+                }
+                if left_lon <= right_lon {
+                    left_lon <= point.lon && point.lon <= right_lon
+                } else {
+                    left_lon <= point.lon || point.lon <= right_lon
+                }
+            }
+            GeoArea::Circle {
+                center,
+                distance_in_meters,
+            } => center.distance_in_meters(point) <= distance_in_meters,
+        }
+    }
+}
+
+/// Checks that the geo filter targets a geo point field and delimits a valid area.
+pub(crate) fn validate_geo_filter(
+    geo_filter_opt: Option<&GeoFilter>,
+    index_config: &dyn IndexConfig,
+) -> crate::Result<()> {
+    let geo_filter = if let Some(geo_filter) = geo_filter_opt {
+        geo_filter
+    } else {
+        return Ok(());
+    };
+    if !index_config
+        .geo_point_field_names()
+        .contains(&geo_filter.field_name)
+    {
+        return Err(SearchError::InvalidQuery(format!(
+            "Geo filters are only supported on `geo_point` fields, `{}` is not a `geo_point` field",
+            geo_filter.field_name
+        )));
+    }
+    GeoArea::from_geo_filter(geo_filter)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_proto::{GeoBoundingBox, GeoDistance};
+
+    use super::*;
+
+    fn bounding_box_filter(
+        top_lat: f64,
+        left_lon: f64,
+        bottom_lat: f64,
+        right_lon: f64,
+    ) -> GeoFilter {
+        GeoFilter {
+            field_name: "location".to_string(),
+            bounding_box: Some(GeoBoundingBox {
+                top_lat,
+                left_lon,
+                bottom_lat,
+                right_lon,
+            }),
+            distance: None,
+        }
+    }
+
+    fn distance_filter(lat: f64, lon: f64, distance_in_meters: f64) -> GeoFilter {
+        GeoFilter {
+            field_name: "location".to_string(),
+            bounding_box: None,
+            distance: Some(GeoDistance {
+                lat,
+                lon,
+                distance_in_meters,
+            }),
+        }
+    }
+
+    fn point(lat: f64, lon: f64) -> GeoPoint {
+        GeoPoint::new(lat, lon).unwrap()
+    }
+
+    #[test]
+    fn test_geo_area_bounding_box() {
+        let area = GeoArea::from_geo_filter(&bounding_box_filter(49.0, 2.0, 48.0, 3.0)).unwrap();
+        assert!(area.contains(&point(48.8566, 2.3522)));
+        assert!(area.contains(&point(49.0, 2.0)));
+        assert!(!area.contains(&point(51.5074, -0.1278)));
+        assert!(!area.contains(&point(48.5, 3.5)));
+    }
+
+    #[test]
+    fn test_geo_area_bounding_box_crossing_antimeridian() {
+        let area =
+            GeoArea::from_geo_filter(&bounding_box_filter(10.0, 170.0, -10.0, -170.0)).unwrap();
+        assert!(area.contains(&point(0.0, 175.0)));
+        assert!(area.contains(&point(0.0, -175.0)));
+        assert!(!area.contains(&point(0.0, 0.0)));
+        assert!(!area.contains(&point(20.0, 175.0)));
+    }
+
+    #[test]
+    fn test_geo_area_circle() {
+        // Paris to London is about 344km.
+        let area = GeoArea::from_geo_filter(&distance_filter(48.8566, 2.3522, 350_000.0)).unwrap();
+        assert!(area.contains(&point(51.5074, -0.1278)));
+        let area = GeoArea::from_geo_filter(&distance_filter(48.8566, 2.3522, 340_000.0)).unwrap();
+        assert!(!area.contains(&point(51.5074, -0.1278)));
+        assert!(area.contains(&point(48.8566, 2.3522)));
+    }
+
+    #[test]
+    fn test_geo_area_invalid() {
+        assert!(GeoArea::from_geo_filter(&bounding_box_filter(48.0, 2.0, 49.0, 3.0)).is_err());
+        assert!(GeoArea::from_geo_filter(&bounding_box_filter(91.0, 2.0, 48.0, 3.0)).is_err());
+        assert!(GeoArea::from_geo_filter(&bounding_box_filter(49.0, 2.0, 48.0, 181.0)).is_err());
+        assert!(GeoArea::from_geo_filter(&distance_filter(48.0, 2.0, -1.0)).is_err());
+        assert!(GeoArea::from_geo_filter(&distance_filter(48.0, 2.0, f64::NAN)).is_err());
+        let no_area_filter = GeoFilter {
+            field_name: "location".to_string(),
+            bounding_box: None,
+            distance: None,
+        };
+        assert!(GeoArea::from_geo_filter(&no_area_filter).is_err());
+        let two_areas_filter = GeoFilter {
+            distance: distance_filter(48.0, 2.0, 1.0).distance,
+            ..bounding_box_filter(49.0, 2.0, 48.0, 3.0)
+        };
+        assert!(GeoArea::from_geo_filter(&two_areas_filter).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Number of most recent leaf search latencies the hedging delay is computed from.
+const LATENCY_WINDOW_SIZE: usize = 1_000;
+
+/// Below this number of samples, the latency percentile is not meaningful
+/// and `max_delay` is used instead.
+const MIN_NUM_LATENCY_SAMPLES: usize = 20;
+
+/// Parameters of leaf search hedging.
+///
+/// A leaf search that has not responded after the `percentile` of the recent leaf
+/// search latencies is sent to a second node, and the first response wins.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HedgingParams {
+    /// Latency percentile after which a hedged request is sent, between 0 and 1.
+    pub percentile: f64,
+    /// Lower bound of the hedging delay, to avoid doubling the load when all the
+    /// leaf searches are fast.
+    pub min_delay: Duration,
+    /// Upper bound of the hedging delay.
+    pub max_delay: Duration,
+}
+
+impl Default for HedgingParams {
+    fn default() -> Self {
+        HedgingParams {
+            percentile: 0.95,
+            min_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Computes the hedging delay from the latencies of the recent leaf searches.
+///
+/// Clones share the same latency window.
+#[derive(Clone)]
+pub(crate) struct HedgingPolicy {
+    params: HedgingParams,
+    latencies: Arc<Mutex<VecDeque<Duration>>>,
+}
+
+impl HedgingPolicy {
+    pub fn new(params: HedgingParams) -> Self {
+        HedgingPolicy {
+            params,
+            latencies: Default::default(),
+        }
+    }
+
+    /// Records the latency of a leaf search.
+    pub fn record_latency(&self, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.len() == LATENCY_WINDOW_SIZE {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+
+    /// Returns the time to wait for a leaf search response before sending a hedged request.
+    pub fn hedging_delay(&self) -> Duration {
+        let mut sorted_latencies: Vec<Duration> = {
+            let latencies = self.latencies.lock().unwrap();
+            if latencies.len() < MIN_NUM_LATENCY_SAMPLES {
+                return self.params.max_delay;
+            }
+            latencies.iter().cloned().collect()
+        };
+        sorted_latencies.sort_unstable();
+        let percentile = self.params.percentile.max(0.0).min(1.0);
+        let rank = ((sorted_latencies.len() - 1) as f64 * percentile).round() as usize;
+        sorted_latencies[rank]
+            .max(self.params.min_delay)
+            .min(self.params.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_hedging_delay() {
+        let hedging_policy = HedgingPolicy::new(HedgingParams {
+            percentile: 0.9,
+            min_delay: Duration::from_millis(5),
+            max_delay: Duration::from_millis(500),
+        });
+        // Not enough samples.
+        hedging_policy.record_latency(Duration::from_millis(10));
+        assert_eq!(hedging_policy.hedging_delay(), Duration::from_millis(500));
+
+        for latency_millis in 1..=100 {
+            hedging_policy.record_latency(Duration::from_millis(latency_millis));
+        }
+        assert_eq!(hedging_policy.hedging_delay(), Duration::from_millis(90));
+
+        for _ in 0..LATENCY_WINDOW_SIZE {
+            hedging_policy.record_latency(Duration::from_millis(1));
+        }
+        assert_eq!(hedging_policy.hedging_delay(), Duration::from_millis(5));
+
+        for _ in 0..LATENCY_WINDOW_SIZE {
+            hedging_policy.record_latency(Duration::from_secs(1));
+        }
+        assert_eq!(hedging_policy.hedging_delay(), Duration::from_millis(500));
+    }
+}
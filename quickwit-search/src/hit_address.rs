@@ -0,0 +1,161 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use quickwit_proto::{Hit, PartialHit};
+
+/// Stable address of a hit, from which the hit can be fetched again after the search.
+///
+/// Its string representation, `{split_id}:{segment_ord}:{doc_id}:{generation}`, is returned
+/// along with the hits of the search responses. The generation is the version of the index
+/// config the hit was rendered with, so that fetching it again renders the same document.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HitAddress {
+    /// Split containing the document.
+    pub split_id: String,
+    /// Ordinal of the segment containing the document in the split.
+    pub segment_ord: u32,
+    /// Id of the document in the segment.
+    pub doc_id: u32,
+    /// Version of the index config the hit is rendered with.
+    pub generation: u64,
+}
+
+impl HitAddress {
+    /// Returns the address of `partial_hit`, rendered with the index config `generation`.
+    pub fn from_partial_hit(partial_hit: &PartialHit, generation: u64) -> Self {
+        HitAddress {
+            split_id: partial_hit.split_id.clone(),
+            segment_ord: partial_hit.segment_ord,
+            doc_id: partial_hit.doc_id,
+            generation,
+        }
+    }
+
+    /// Returns the partial hit pointing at the document of this address.
+    pub fn to_partial_hit(&self) -> PartialHit {
+        PartialHit {
+            sorting_field_value: 0,
+            split_id: self.split_id.clone(),
+            segment_ord: self.segment_ord,
+            doc_id: self.doc_id,
+            leaf_grpc_addr: String::new(),
+        }
+    }
+}
+
+impl fmt::Display for HitAddress {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}:{}:{}:{}",
+            self.split_id, self.segment_ord, self.doc_id, self.generation
+        )
+    }
+}
+
+impl FromStr for HitAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(hit_address_str: &str) -> anyhow::Result<Self> {
+        // Split ids could contain a `:`, hence the split from the right.
+        let mut parts = hit_address_str.rsplitn(4, ':');
+        let generation = parts.next().unwrap_or_default();
+        let doc_id = parts.next().unwrap_or_default();
+        let segment_ord = parts.next().unwrap_or_default();
+        let split_id = parts.next().unwrap_or_default();
+        if split_id.is_empty() {
+            bail!(
+                "Invalid hit address `{}`: expected \
+                 `{{split_id}}:{{segment_ord}}:{{doc_id}}:{{generation}}`.",
+                hit_address_str
+            );
+        }
+        Ok(HitAddress {
+            split_id: split_id.to_string(),
+            segment_ord: segment_ord.parse().with_context(|| {
+                format!("Invalid segment ord in hit address `{}`.", hit_address_str)
+            })?,
+            doc_id: doc_id
+                .parse()
+                .with_context(|| format!("Invalid doc id in hit address `{}`.", hit_address_str))?,
+            generation: generation.parse().with_context(|| {
+                format!("Invalid generation in hit address `{}`.", hit_address_str)
+            })?,
+        })
+    }
+}
+
+/// Sets the address of the hits rendered with the index config `generation`.
+pub(crate) fn set_hit_addresses(hits: &mut [Hit], generation: u64) {
+    for hit in hits.iter_mut() {
+        if let Some(partial_hit) = &hit.partial_hit {
+            hit.address = HitAddress::from_partial_hit(partial_hit, generation).to_string();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_address_to_and_from_string() -> anyhow::Result<()> {
+        let hit_address = HitAddress {
+            split_id: "split:1".to_string(),
+            segment_ord: 2,
+            doc_id: 3,
+            generation: 4,
+        };
+        assert_eq!(hit_address.to_string(), "split:1:2:3:4");
+        assert_eq!("split:1:2:3:4".parse::<HitAddress>()?, hit_address);
+        assert!("2:3:4".parse::<HitAddress>().is_err());
+        assert!("split:a:3:4".parse::<HitAddress>().is_err());
+        assert!("split:2:3:-1".parse::<HitAddress>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_hit_addresses() {
+        let mut hits = vec![Hit {
+            json: "{}".to_string(),
+            partial_hit: Some(PartialHit {
+                sorting_field_value: 10,
+                split_id: "split1".to_string(),
+                segment_ord: 0,
+                doc_id: 5,
+                leaf_grpc_addr: String::new(),
+            }),
+            address: String::new(),
+        }];
+        set_hit_addresses(&mut hits, 1);
+        assert_eq!(hits[0].address, "split1:0:5:1");
+        let hit_address: HitAddress = hits[0].address.parse().unwrap();
+        assert_eq!(
+            hit_address.to_partial_hit(),
+            PartialHit {
+                sorting_field_value: 0,
+                ..hits[0].partial_hit.clone().unwrap()
+            }
+        );
+    }
+}
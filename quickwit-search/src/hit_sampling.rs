@@ -0,0 +1,193 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+
+use quickwit_index_config::{IndexConfig, SortBy, SortOrder};
+use quickwit_proto::{HitSampling, PartialHit, SearchRequest};
+use tantivy::fastfield::FastValue;
+
+use crate::date_histogram::DateHistogramBucketer;
+use crate::{partial_hit_sorting_key, SearchError};
+
+/// Samples the hits per time bucket, keeping at most `max_hits_per_bucket` hits of each bucket.
+///
+/// The hits of a sampled search are sorted by the timestamp field, most recent first, or
+/// oldest first to sample the first documents of the buckets. Their sorting field value thus
+/// tells their bucket.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct HitSampler {
+    bucketer: DateHistogramBucketer,
+    max_hits_per_bucket: usize,
+    first: bool,
+}
+
+impl HitSampler {
+    pub fn new(hit_sampling: &HitSampling, time_zone: &str) -> crate::Result<Self> {
+        Ok(HitSampler {
+            bucketer: DateHistogramBucketer::from_interval(&hit_sampling.interval, time_zone)?,
+            max_hits_per_bucket: hit_sampling.max_hits_per_bucket as usize,
+            first: hit_sampling.first,
+        })
+    }
+
+    pub fn max_hits_per_bucket(&self) -> usize {
+        self.max_hits_per_bucket
+    }
+
+    /// Returns the key of the bucket of a hit, given its sorting field value.
+    pub fn bucket_key(&self, sorting_field_value: u64) -> i64 {
+        let timestamp = if self.first {
+            i64::from_u64(u64::MAX - sorting_field_value)
+        } else {
+            i64::from_u64(sorting_field_value)
+        };
+        self.bucketer.bucket_key(timestamp)
+    }
+
+    /// Keeps the first `max_hits_per_bucket` hits of each bucket, and returns them sorted.
+    pub fn sample_partial_hits(&self, mut partial_hits: Vec<PartialHit>) -> Vec<PartialHit> {
+        partial_hits.sort_by(|left, right| {
+            partial_hit_sorting_key(left).cmp(&partial_hit_sorting_key(right))
+        });
+        let mut num_hits_per_bucket: HashMap<i64, usize> = HashMap::new();
+        partial_hits.retain(|partial_hit| {
+            let num_hits = num_hits_per_bucket
+                .entry(self.bucket_key(partial_hit.sorting_field_value))
+                .or_insert(0);
+            *num_hits += 1;
+            *num_hits <= self.max_hits_per_bucket
+        });
+        partial_hits
+    }
+}
+
+/// Returns how the sampled hits are sorted, given the timestamp field of the index.
+pub(crate) fn hit_sampling_sort_by(
+    hit_sampling: &HitSampling,
+    timestamp_field_name: String,
+) -> SortBy {
+    SortBy::SortByFastField {
+        field_name: timestamp_field_name,
+        order: if hit_sampling.first {
+            SortOrder::Asc
+        } else {
+            SortOrder::Desc
+        },
+    }
+}
+
+/// Checks that the hit sampling of the request, if any, has a valid interval, and that the
+/// index has a timestamp field to bucket the hits by.
+pub(crate) fn validate_hit_sampling(
+    search_request: &SearchRequest,
+    index_config: &dyn IndexConfig,
+) -> crate::Result<()> {
+    let hit_sampling = if let Some(hit_sampling) = &search_request.hit_sampling {
+        hit_sampling
+    } else {
+        return Ok(());
+    };
+    HitSampler::new(hit_sampling, &search_request.time_zone)?;
+    if hit_sampling.max_hits_per_bucket == 0 {
+        return Err(SearchError::InvalidQuery(
+            "The maximum number of hits per bucket of the hit sampling must be strictly positive"
+                .to_string(),
+        ));
+    }
+    if index_config.timestamp_field_name().is_none() {
+        return Err(SearchError::InvalidQuery(
+            "Hits can only be sampled on indexes with a timestamp field".to_string(),
+        ));
+    }
+    if search_request.sort_by_field.is_some() {
+        return Err(SearchError::InvalidQuery(
+            "Sampled hits are sorted by timestamp, they cannot be sorted by another field"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial_hit(timestamp: i64, doc_id: u32) -> PartialHit {
+        PartialHit {
+            sorting_field_value: timestamp.to_u64(),
+            split_id: "split1".to_string(),
+            segment_ord: 0,
+            doc_id,
+            leaf_grpc_addr: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_hit_sampler_sample_partial_hits() -> anyhow::Result<()> {
+        let hit_sampling = HitSampling {
+            interval: "1m".to_string(),
+            max_hits_per_bucket: 1,
+            first: false,
+        };
+        let hit_sampler = HitSampler::new(&hit_sampling, "")?;
+        let partial_hits = vec![
+            partial_hit(10, 0),
+            partial_hit(50, 1),
+            partial_hit(70, 2),
+            partial_hit(130, 3),
+            partial_hit(110, 4),
+        ];
+        let doc_ids: Vec<u32> = hit_sampler
+            .sample_partial_hits(partial_hits)
+            .iter()
+            .map(|partial_hit| partial_hit.doc_id)
+            .collect();
+        assert_eq!(doc_ids, vec![3, 2, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hit_sampler_first_hits() -> anyhow::Result<()> {
+        let hit_sampling = HitSampling {
+            interval: "1m".to_string(),
+            max_hits_per_bucket: 2,
+            first: true,
+        };
+        let hit_sampler = HitSampler::new(&hit_sampling, "")?;
+        let ascending_partial_hit = |timestamp: i64, doc_id: u32| PartialHit {
+            sorting_field_value: u64::MAX - timestamp.to_u64(),
+            ..partial_hit(timestamp, doc_id)
+        };
+        assert_eq!(hit_sampler.bucket_key(u64::MAX - 70i64.to_u64()), 60);
+        let partial_hits = vec![
+            ascending_partial_hit(10, 0),
+            ascending_partial_hit(50, 1),
+            ascending_partial_hit(30, 2),
+            ascending_partial_hit(70, 3),
+        ];
+        let doc_ids: Vec<u32> = hit_sampler
+            .sample_partial_hits(partial_hits)
+            .iter()
+            .map(|partial_hit| partial_hit.doc_id)
+            .collect();
+        assert_eq!(doc_ids, vec![0, 2, 3]);
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+use quickwit_index_config::IndexConfig;
+
+use crate::SearchError;
+
+/// Maximum number of decoded index configs kept in memory.
+const INDEX_CONFIG_CACHE_CAPACITY: usize = 256;
+
+/// Cache of the index configs serialized in JSON in the leaf requests of the roots that
+/// predate index config versioning, keyed by a hash of their serialized form.
+///
+/// Leaves receive the same handful of configs over and over, so decoding
+/// them on every request is wasteful.
+#[derive(Clone)]
+pub(crate) struct IndexConfigCache {
+    decoded_index_configs: Arc<Mutex<LruCache<u64, Arc<dyn IndexConfig>>>>,
+}
+
+impl Default for IndexConfigCache {
+    fn default() -> Self {
+        IndexConfigCache {
+            decoded_index_configs: Arc::new(Mutex::new(LruCache::new(INDEX_CONFIG_CACHE_CAPACITY))),
+        }
+    }
+}
+
+impl IndexConfigCache {
+    /// Returns the decoded index config, only decoding it if it is not in the cache already.
+    pub fn get_or_decode(&self, index_config_json: &[u8]) -> crate::Result<Arc<dyn IndexConfig>> {
+        let mut hasher = DefaultHasher::new();
+        index_config_json.hash(&mut hasher);
+        let cache_key = hasher.finish();
+
+        if let Some(index_config) = self.decoded_index_configs.lock().unwrap().get(&cache_key) {
+            return Ok(index_config.clone());
+        }
+        let index_config = serde_json::from_slice::<Arc<dyn IndexConfig>>(index_config_json)
+            .map_err(|err| {
+                SearchError::InternalError(format!("Failed to deserialize index config: `{}`", err))
+            })?;
+        self.decoded_index_configs
+            .lock()
+            .unwrap()
+            .put(cache_key, index_config.clone());
+        Ok(index_config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_index_config::WikipediaIndexConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_index_config_cache_decodes_once() -> anyhow::Result<()> {
+        let index_config = WikipediaIndexConfig::new();
+        let index_config_json = serde_json::to_vec(&(&index_config as &dyn IndexConfig))?;
+        let cache = IndexConfigCache::default();
+        let decoded_index_config = cache.get_or_decode(&index_config_json)?;
+        assert_eq!(
+            format!("{:?}", decoded_index_config),
+            format!("{:?}", index_config)
+        );
+        let cached_index_config = cache.get_or_decode(&index_config_json)?;
+        assert!(Arc::ptr_eq(&decoded_index_config, &cached_index_config));
+        assert!(cache.get_or_decode(b"not json").is_err());
+        Ok(())
+    }
+}
@@ -19,7 +19,8 @@
 
 use std::collections::{BTreeMap, HashSet};
 use std::convert::TryInto;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::Context;
@@ -27,37 +28,108 @@ use bytes::Bytes;
 use futures::future::try_join_all;
 use itertools::{Either, Itertools};
 use once_cell::sync::OnceCell;
-use quickwit_directories::{CachingDirectory, HotDirectory, StorageDirectory};
+use quickwit_directories::{CachingDirectory, HotDirectory, SplitStats, StorageDirectory};
 use quickwit_index_config::IndexConfig;
 use quickwit_proto::{
-    LeafSearchResponse, SearchRequest, SplitIdAndFooterOffsets, SplitSearchError,
+    CachePolicy, LeafSearchResponse, SearchRequest, SplitIdAndFooterOffsets, SplitSearchError,
+};
+use quickwit_storage::{
+    multipart_file_storage, BundleStorage, MemorySizedCache, PutPayload, RamStorage, Storage,
+    StorageError, StorageErrorKind,
 };
-use quickwit_storage::{BundleStorage, MemorySizedCache, Storage};
 use tantivy::collector::Collector;
 use tantivy::query::Query;
-use tantivy::{Index, ReloadPolicy, Searcher, Term};
+use tantivy::schema::{Cardinality, FieldEntry, FieldType};
+use tantivy::{Index, ReloadPolicy, Searcher, TantivyError, Term};
 use tokio::task::spawn_blocking;
 use tracing::*;
 
 use crate::collector::{make_collector_for_split, make_merge_collector, GenericQuickwitCollector};
-use crate::SearchError;
+use crate::fast_field_pinning::global_pinned_fast_fields;
+use crate::query_planner::{plan_query, query_fast_field_names};
+use crate::search_thread_pool::{global_search_thread_pool, QueryThreadBudget};
+use crate::split_pool::{global_split_pool, OpenedSplit};
+use crate::terms_aggregation::prune_terms_buckets;
+use crate::{lease_splits, SearchError};
 
-fn global_split_footer_cache() -> &'static MemorySizedCache<String> {
+pub(crate) fn global_split_footer_cache() -> &'static MemorySizedCache<String> {
     static INSTANCE: OnceCell<MemorySizedCache<String>> = OnceCell::new();
     INSTANCE.get_or_init(|| MemorySizedCache::with_capacity_in_bytes(500_000_000))
 }
 
+static WHOLE_SPLIT_DOWNLOAD_MAX_NUM_BYTES: OnceCell<u64> = OnceCell::new();
+
+/// Makes the leaf searches download the split files of at most `max_num_bytes` bytes in a
+/// single request when they open them, instead of fetching their footer and then issuing a
+/// ranged request for each piece of data the search reads.
+///
+/// For small splits, the latency of the requests outweighs the time spent downloading the
+/// bytes the search does not need. The downloaded split stays in memory as long as it is in
+/// the split pool, and counts towards the number of bytes the pool may hold. This can only be
+/// configured once, before the first search.
+pub fn configure_whole_split_download(max_num_bytes: u64) -> anyhow::Result<()> {
+    WHOLE_SPLIT_DOWNLOAD_MAX_NUM_BYTES
+        .set(max_num_bytes)
+        .map_err(|_| anyhow::anyhow!("The whole split download is already configured."))
+}
+
+/// Downloads the split file `split_file` of `split_num_bytes` bytes in a single request, and
+/// returns a storage serving it from memory.
+async fn download_whole_split(
+    index_storage: &dyn Storage,
+    split_file: &Path,
+    split_num_bytes: u64,
+) -> anyhow::Result<Arc<dyn Storage>> {
+    let split_bytes = index_storage
+        .get_slice(split_file, 0..split_num_bytes as usize)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to download the split file `{}` from {}",
+                split_file.display(),
+                index_storage.uri()
+            )
+        })?;
+    let split_storage = RamStorage::default();
+    split_storage
+        .put(split_file, PutPayload::InMemory(split_bytes))
+        .await?;
+    Ok(Arc::new(split_storage))
+}
+
+/// Places the hotcache and footer of a split in the split footer cache, sparing the first
+/// search of the split their download.
+pub(crate) fn put_split_footer_in_cache(split_id: String, footer_data: Bytes) {
+    global_split_footer_cache().put(split_id, footer_data);
+}
+
+/// Returns the cache policy of a request, `CachePolicy::Use` if it is unknown to this node.
+pub(crate) fn cache_policy(cache_policy: i32) -> CachePolicy {
+    CachePolicy::from_i32(cache_policy).unwrap_or(CachePolicy::Use)
+}
+
+/// Returns the path of the split file, relative to the index storage.
+fn split_file_path(split_and_footer_offsets: &SplitIdAndFooterOffsets) -> PathBuf {
+    if split_and_footer_offsets.split_file.is_empty() {
+        return PathBuf::from(quickwit_common::split_file(
+            &split_and_footer_offsets.split_id,
+        ));
+    }
+    PathBuf::from(&split_and_footer_offsets.split_file)
+}
+
 async fn get_split_footer_from_cache_or_fetch(
     index_storage: Arc<dyn Storage>,
     split_and_footer_offsets: &SplitIdAndFooterOffsets,
+    cache_policy: CachePolicy,
 ) -> anyhow::Result<Bytes> {
-    {
+    if cache_policy == CachePolicy::Use {
         let possible_val = global_split_footer_cache().get(&split_and_footer_offsets.split_id);
         if let Some(footer_data) = possible_val {
             return Ok(footer_data);
         }
     }
-    let split_file = PathBuf::from(format!("{}.split", split_and_footer_offsets.split_id));
+    let split_file = split_file_path(split_and_footer_offsets);
     let footer_data_opt = index_storage
         .get_slice(
             &split_file,
@@ -73,25 +145,67 @@ async fn get_split_footer_from_cache_or_fetch(
             )
         })?;
 
-    global_split_footer_cache().put(
-        split_and_footer_offsets.split_id.to_owned(),
-        footer_data_opt.clone(),
-    );
+    if cache_policy != CachePolicy::Bypass {
+        global_split_footer_cache().put(
+            split_and_footer_offsets.split_id.to_owned(),
+            footer_data_opt.clone(),
+        );
+    }
 
     Ok(footer_data_opt)
 }
 
-/// Opens a `tantivy::Index` for the given split.
+/// Opens a `tantivy::Index` for the given split, or reuses the one
+/// opened by a previous search if it is still in the split pool.
 ///
-/// The resulting index uses a dynamic and a static cache.
+/// The resulting index uses a dynamic and a static cache. With `CachePolicy::Bypass`, the
+/// split is opened from the storage and left out of the split pool, the split footer cache
+/// and the pinned fast fields. With `CachePolicy::Refresh`, it is opened from the storage
+/// and replaces the cached copies of the split.
 pub(crate) async fn open_index(
     index_storage: Arc<dyn Storage>,
     split_and_footer_offsets: &SplitIdAndFooterOffsets,
+    cache_policy: CachePolicy,
 ) -> anyhow::Result<Index> {
-    let split_file = PathBuf::from(format!("{}.split", split_and_footer_offsets.split_id));
-    let mut footer_data =
-        get_split_footer_from_cache_or_fetch(index_storage.clone(), split_and_footer_offsets)
-            .await?;
+    if cache_policy == CachePolicy::Use {
+        if let Some(index) = global_split_pool().get(&split_and_footer_offsets.split_id) {
+            return Ok(index);
+        }
+    }
+    let opened_split =
+        open_split_index(index_storage, split_and_footer_offsets, cache_policy).await?;
+    let index = opened_split.index.clone();
+    if cache_policy != CachePolicy::Bypass {
+        global_split_pool().put(split_and_footer_offsets.split_id.clone(), opened_split);
+    }
+    Ok(index)
+}
+
+async fn open_split_index(
+    index_storage: Arc<dyn Storage>,
+    split_and_footer_offsets: &SplitIdAndFooterOffsets,
+    cache_policy: CachePolicy,
+) -> anyhow::Result<OpenedSplit> {
+    let split_file = split_file_path(split_and_footer_offsets);
+    let index_storage =
+        multipart_file_storage(index_storage, split_and_footer_offsets.split_part_num_bytes);
+    // The footer is at the end of the split file.
+    let split_num_bytes = split_and_footer_offsets.split_footer_end;
+    let (index_storage, whole_split_num_bytes) = match WHOLE_SPLIT_DOWNLOAD_MAX_NUM_BYTES.get() {
+        Some(&max_num_bytes) if split_num_bytes <= max_num_bytes => {
+            let split_storage =
+                download_whole_split(&*index_storage, &split_file, split_num_bytes).await?;
+            (split_storage, split_num_bytes as usize)
+        }
+        _ => (index_storage, 0),
+    };
+    let mut footer_data = get_split_footer_from_cache_or_fetch(
+        index_storage.clone(),
+        split_and_footer_offsets,
+        cache_policy,
+    )
+    .await?;
+    let footer_num_bytes = footer_data.len();
     let hotcache_len_bytes = footer_data.split_off(footer_data.len() - 8);
     let hotcache_num_bytes =
         u64::from_le_bytes((&*hotcache_len_bytes).try_into().unwrap()) as usize;
@@ -99,11 +213,35 @@ pub(crate) async fn open_index(
     let hotcache_bytes = footer_data.split_off(footer_data.len() - hotcache_num_bytes);
 
     let bundle = BundleStorage::new(index_storage, split_file, &footer_data)?;
-    let directory = StorageDirectory::new(Arc::new(bundle));
-    let caching_directory = CachingDirectory::new_with_unlimited_capacity(Arc::new(directory));
-    let hot_directory = HotDirectory::open(caching_directory, hotcache_bytes)?;
+    let directory = Arc::new(StorageDirectory::new(Arc::new(bundle)));
+    let caching_directory = CachingDirectory::new_with_unlimited_capacity(directory.clone());
+    let hot_directory = HotDirectory::open(caching_directory.clone(), hotcache_bytes)?;
     let index = Index::open(hot_directory)?;
-    Ok(index)
+    let pinned_fast_fields_opt =
+        global_pinned_fast_fields().filter(|_| cache_policy != CachePolicy::Bypass);
+    if let Some(pinned_fast_fields) = pinned_fast_fields_opt {
+        if cache_policy == CachePolicy::Refresh {
+            pinned_fast_fields.unpin_split(&split_and_footer_offsets.split_id);
+        }
+        // Pinning is an optimization: the split can still be searched without it.
+        if let Err(error) = pinned_fast_fields
+            .pin_split(
+                split_and_footer_offsets,
+                &index,
+                directory.as_ref(),
+                &caching_directory,
+            )
+            .await
+        {
+            warn!(split_id = %split_and_footer_offsets.split_id, error = ?error, "Failed to pin the fast fields of the split.");
+        }
+    }
+    Ok(OpenedSplit {
+        index,
+        footer_num_bytes,
+        caching_directory,
+        whole_split_num_bytes,
+    })
 }
 
 /// Tantivy search does not make it possible to fetch data asynchronously during
@@ -113,7 +251,7 @@ pub(crate) async fn open_index(
 /// This is the role of the `warmup` function.
 ///
 /// The downloaded data depends on the query (which term's posting list is required,
-/// are position required too), and the collector.
+/// are position required too, which fast fields filter it), and the collector.
 #[instrument(skip(searcher, query, fast_field_names))]
 pub(crate) async fn warmup(
     searcher: &Searcher,
@@ -123,7 +261,9 @@ pub(crate) async fn warmup(
     warm_up_terms(searcher, query)
         .instrument(debug_span!("warm_up_terms"))
         .await?;
-    warm_up_fastfields(searcher, fast_field_names)
+    let mut fast_field_names = fast_field_names.clone();
+    fast_field_names.extend(query_fast_field_names(query, searcher));
+    warm_up_fastfields(searcher, &fast_field_names)
         .instrument(debug_span!("warm_up_fastfields"))
         .await?;
     Ok(())
@@ -146,23 +286,49 @@ async fn warm_up_fastfields(
             })?;
 
         let field_entry = searcher.schema().get_field_entry(fast_field);
-        if !field_entry.is_fast() {
+        // The term ordinals of a facet field are a multi-valued fast field, holding the
+        // dictionary encoded values of a text fast field. The facet dictionary of these
+        // low-cardinality fields is served by the hotcache, as the other term dictionaries.
+        let is_facet_field = matches!(field_entry.field_type(), FieldType::HierarchicalFacet(_));
+        if !field_entry.is_fast() && !is_facet_field {
             anyhow::bail!("Field {:?} is not a fast field.", fast_field_name);
         }
-        fast_fields.push(fast_field);
+        fast_fields.push((
+            fast_field,
+            is_facet_field || is_multivalued_fast_field(field_entry),
+        ));
     }
 
     let mut warm_up_futures = Vec::new();
-    for field in fast_fields {
+    for (field, is_multivalued) in fast_fields {
         for segment_reader in searcher.segment_readers() {
             let fast_field_slice = segment_reader.fast_fields().fast_field_data(field, 0)?;
             warm_up_futures.push(async move { fast_field_slice.read_bytes_async().await });
+            // The values of multi-valued fast fields follow the offsets of each document.
+            if is_multivalued {
+                let vals_slice = segment_reader.fast_fields().fast_field_data(field, 1)?;
+                warm_up_futures.push(async move { vals_slice.read_bytes_async().await });
+            }
         }
     }
     try_join_all(warm_up_futures).await?;
     Ok(())
 }
 
+/// Returns true if `field_entry` is the fast field of an array field, which holds any number
+/// of values per document.
+pub(crate) fn is_multivalued_fast_field(field_entry: &FieldEntry) -> bool {
+    match field_entry.field_type() {
+        FieldType::U64(options)
+        | FieldType::I64(options)
+        | FieldType::F64(options)
+        | FieldType::Date(options) => {
+            options.get_fastfield_cardinality() == Some(Cardinality::MultiValues)
+        }
+        _ => false,
+    }
+}
+
 async fn warm_up_terms(searcher: &Searcher, query: &dyn Query) -> anyhow::Result<()> {
     let mut terms: BTreeMap<Term, bool> = Default::default();
     query.query_terms(&mut terms);
@@ -186,15 +352,16 @@ async fn warm_up_terms(searcher: &Searcher, query: &dyn Query) -> anyhow::Result
 }
 
 /// Apply a leaf search on a single split.
-#[instrument(skip(search_request, storage, split, index_config))]
+#[instrument(skip(search_request, storage, split, index_config, thread_budget))]
 async fn leaf_search_single_split(
     search_request: &SearchRequest,
     storage: Arc<dyn Storage>,
     split: SplitIdAndFooterOffsets,
     index_config: Arc<dyn IndexConfig>,
-) -> crate::Result<LeafSearchResponse> {
+    thread_budget: &QueryThreadBudget,
+) -> anyhow::Result<LeafSearchResponse> {
     let split_id = split.split_id.to_string();
-    let index = open_index(storage, &split).await?;
+    let index = open_index(storage, &split, cache_policy(search_request.cache_policy)).await?;
     let split_schema = index.schema();
     let quickwit_collector = make_collector_for_split(
         split_id,
@@ -202,24 +369,48 @@ async fn leaf_search_single_split(
         search_request,
         &split_schema,
     );
-    let query = index_config.query(split_schema, search_request)?;
-
     let reader = index
         .reader_builder()
         .num_searchers(1)
         .reload_policy(ReloadPolicy::Manual)
         .try_into()?;
     let searcher = reader.searcher();
+    let query = index_config.split_query(&*searcher, search_request)?;
+    // Pruning the clauses that cannot match spares the warmup of their postings.
+    let split_stats_opt = SplitStats::open(index.directory())?;
+    let query = plan_query(query.as_ref(), &searcher, split_stats_opt.as_ref())?;
     warmup(&*searcher, &query, &quickwit_collector.fast_field_names()).await?;
     let span = info_span!(
         "search",
         split_id = %split.split_id,
     );
-    let _ = span.enter();
-    let leaf_search_response = searcher.search(&query, &quickwit_collector)?;
+    let leaf_search_response = thread_budget
+        .spawn_blocking(move || {
+            let _span_guard = span.enter();
+            searcher.search(&query, &quickwit_collector)
+        })
+        .await??;
     Ok(leaf_search_response)
 }
 
+/// Returns true if the search of a split failing with `error` may succeed when retried, e.g.
+/// after a timeout or a throttling of the storage. The errors coming from the split itself,
+/// i.e. a missing or corrupted split file, are not retryable.
+fn is_retryable_split_error(error: &anyhow::Error) -> bool {
+    for cause in error.chain() {
+        if let Some(storage_error) = cause.downcast_ref::<StorageError>() {
+            return storage_error.kind() != StorageErrorKind::DoesNotExist;
+        }
+        if let Some(io_error) = cause.downcast_ref::<io::Error>() {
+            return io_error.kind() != io::ErrorKind::NotFound;
+        }
+        if let Some(TantivyError::DataCorruption(_)) = cause.downcast_ref::<TantivyError>() {
+            return false;
+        }
+    }
+    true
+}
+
 /// `leaf` step of search.
 ///
 /// The leaf search collects all kind of information, and returns a set of [PartialHit] candidates.
@@ -231,26 +422,35 @@ pub async fn leaf_search(
     splits: &[SplitIdAndFooterOffsets],
     index_config: Arc<dyn IndexConfig>,
 ) -> Result<LeafSearchResponse, SearchError> {
+    let _split_lease = lease_splits(splits.iter().map(|split| split.split_id.as_str()));
+    let thread_budget = global_search_thread_pool().query_budget();
     let leaf_search_single_split_futures: Vec<_> = splits
         .iter()
         .map(|split| {
             let index_config_clone = index_config.clone();
             let index_storage_clone = index_storage.clone();
+            let thread_budget = &thread_budget;
             async move {
                 leaf_search_single_split(
                     request,
                     index_storage_clone,
                     split.clone(),
                     index_config_clone,
+                    thread_budget,
                 )
                 .await
-                .map_err(|err| (split.split_id.clone(), err))
+                .map_err(|err| {
+                    // The opened split may be the culprit, e.g. if the split has been
+                    // deleted from the storage.
+                    global_split_pool().evict(&split.split_id);
+                    (split.split_id.clone(), err)
+                })
             }
         })
         .collect();
     let split_search_results = futures::future::join_all(leaf_search_single_split_futures).await;
 
-    let (split_search_responses, errors): (Vec<LeafSearchResponse>, Vec<(String, SearchError)>) =
+    let (split_search_responses, errors): (Vec<LeafSearchResponse>, Vec<(String, anyhow::Error)>) =
         split_search_results
             .into_iter()
             .partition_map(|split_search_res| match split_search_res {
@@ -264,13 +464,116 @@ pub async fn leaf_search(
             .instrument(info_span!("merge_search_responses"))
             .await
             .context("Failed to merge split search responses.")??;
+    if let Some(terms_aggregation) = &request.terms_aggregation {
+        let terms_buckets = std::mem::take(&mut merged_search_response.terms_buckets);
+        merged_search_response.terms_buckets =
+            prune_terms_buckets(terms_aggregation, terms_buckets)?;
+    }
 
     merged_search_response
         .failed_splits
         .extend(errors.iter().map(|(split_id, err)| SplitSearchError {
             split_id: split_id.to_string(),
             error: format!("{:?}", err),
-            retryable_error: true,
+            retryable_error: is_retryable_split_error(err),
         }));
     Ok(merged_search_response)
 }
+
+#[cfg(test)]
+mod tests {
+    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_indexing::TestSandbox;
+    use serde_json::json;
+
+    use super::*;
+    use crate::extract_split_and_footer_offsets;
+
+    #[test]
+    fn test_is_retryable_split_error() {
+        let timeout_error = anyhow::Error::new(
+            StorageErrorKind::Timeout.with_error(anyhow::anyhow!("Request timed out.")),
+        )
+        .context("Failed to fetch hotcache and footer");
+        assert!(is_retryable_split_error(&timeout_error));
+        let missing_file_error = anyhow::Error::new(
+            StorageErrorKind::DoesNotExist.with_error(anyhow::anyhow!("Missing split file.")),
+        )
+        .context("Failed to fetch hotcache and footer");
+        assert!(!is_retryable_split_error(&missing_file_error));
+        let not_found_error = anyhow::Error::new(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Missing segment file.",
+        ));
+        assert!(!is_retryable_split_error(&not_found_error));
+        let corruption_error = anyhow::Error::new(TantivyError::DataCorruption(
+            tantivy::error::DataCorruption::comment_only("Invalid hotcache.".to_string()),
+        ));
+        assert!(!is_retryable_split_error(&corruption_error));
+    }
+
+    #[tokio::test]
+    async fn test_download_whole_split() -> anyhow::Result<()> {
+        let index_storage = RamStorage::default();
+        let split_file = Path::new("split1.split");
+        index_storage
+            .put(split_file, PutPayload::from(&b"split1 footer"[..]))
+            .await?;
+        let split_storage = download_whole_split(&index_storage, split_file, 13).await?;
+        // The split is served from memory.
+        index_storage.delete(split_file).await?;
+        assert_eq!(
+            &split_storage.get_slice(split_file, 7..13).await?[..],
+            b"footer"
+        );
+        assert!(
+            download_whole_split(&index_storage, Path::new("split2.split"), 13)
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_open_index_cache_policy() -> anyhow::Result<()> {
+        let index_id = "open-index-cache-policy";
+        let test_sandbox =
+            TestSandbox::create(index_id, Arc::new(WikipediaIndexConfig::new())).await?;
+        test_sandbox
+            .add_documents(vec![
+                json!({"title": "snoopy", "body": "beagle", "url": "http://snoopy"}),
+            ])
+            .await?;
+        let split_meta = test_sandbox
+            .metastore()
+            .list_all_splits(index_id)
+            .await?
+            .into_iter()
+            .next()
+            .unwrap();
+        let split = extract_split_and_footer_offsets(&split_meta);
+        let index_storage = test_sandbox.index_storage(index_id)?;
+        let is_cached = || {
+            (
+                global_split_pool().split_ids().contains(&split.split_id),
+                global_split_footer_cache().get(&split.split_id).is_some(),
+            )
+        };
+
+        open_index(index_storage.clone(), &split, CachePolicy::Bypass).await?;
+        assert_eq!(is_cached(), (false, false));
+
+        open_index(index_storage.clone(), &split, CachePolicy::Refresh).await?;
+        assert_eq!(is_cached(), (true, true));
+
+        // A bypassing search does not evict the cached split.
+        open_index(index_storage.clone(), &split, CachePolicy::Bypass).await?;
+        assert_eq!(is_cached(), (true, true));
+
+        global_split_pool().evict(&split.split_id);
+        global_split_footer_cache().remove(&split.split_id);
+        open_index(index_storage, &split, CachePolicy::Use).await?;
+        assert_eq!(is_cached(), (true, true));
+        Ok(())
+    }
+}
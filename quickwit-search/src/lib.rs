@@ -21,45 +21,118 @@
 #![warn(missing_docs)]
 #![allow(clippy::bool_assert_comparison)]
 
+mod cache_admin;
 mod client;
 mod client_pool;
 mod cluster_client;
+mod cold_start;
 mod collector;
+mod date_histogram;
+mod distinct_count;
+mod enrichment;
 mod error;
+mod estimate;
+mod fast_field_pinning;
+mod federation;
 mod fetch_docs;
+mod field_aliases;
 mod filters;
+mod geo_filter;
+mod hedging;
+mod hit_address;
+mod hit_sampling;
+mod index_config_cache;
 mod leaf;
+mod percentiles;
+mod placement;
+mod query_planner;
+mod read_after;
+mod realtime;
 mod rendezvous_hasher;
 mod retry;
 mod root;
 mod search_response_rest;
+mod search_session;
 mod search_stream;
+mod search_thread_pool;
+mod searcher_handle;
 mod service;
+mod split_limit;
+mod split_pool;
+mod split_quarantine;
+mod sql;
+mod standby;
+mod synonyms;
+mod terms_aggregation;
+mod tree_aggregation;
 
 /// Refer to this as `crate::Result<T>`.
 pub type Result<T> = std::result::Result<T, SearchError>;
 
 use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
 use std::net::SocketAddr;
 use std::ops::Range;
 
 use anyhow::Context;
-use quickwit_metastore::{Metastore, MetastoreResult, SplitMetadataAndFooterOffsets, SplitState};
+use quickwit_common::{global_split_leases, SplitLease, DEFAULT_SPLIT_LEASE_TTL};
+use quickwit_metastore::{
+    retry_on_transient_error, Metastore, MetastoreResult, SplitMetadata,
+    SplitMetadataAndFooterOffsets, SplitState,
+};
 use quickwit_proto::{PartialHit, SearchRequest, SearchResponse, SplitIdAndFooterOffsets};
 use quickwit_storage::StorageUriResolver;
 use tantivy::DocAddress;
+use tracing::warn;
+use ulid::Ulid;
 
+pub use crate::cache_admin::{
+    clear_searcher_caches, invalidate_split_caches, searcher_caches_report,
+    set_searcher_cache_capacity, CacheUsage, SearcherCache, SearcherCachesReport, SplitCacheUsage,
+};
 pub use crate::client::{create_search_service_client, SearchServiceClient};
 pub use crate::client_pool::search_client_pool::SearchClientPool;
 pub use crate::client_pool::ClientPool;
 pub use crate::cluster_client::ClusterClient;
+pub use crate::cold_start::{load_cold_start_bundle, write_cold_start_bundle, ColdStartProfile};
+use crate::collector::validate_sort_by_field;
+use crate::date_histogram::{build_date_histogram_result, validate_date_histogram};
+use crate::distinct_count::{estimate_distinct_counts, validate_distinct_count_fields};
 pub use crate::error::{parse_grpc_error, SearchError};
+pub use crate::estimate::{root_estimate, SearchEstimate};
+pub use crate::fast_field_pinning::{enable_fast_field_pinning, FastFieldPinningParams};
+pub use crate::federation::parse_index_ids;
 use crate::fetch_docs::fetch_docs;
-use crate::leaf::leaf_search;
-pub use crate::root::root_search;
+use crate::field_aliases::resolve_request_field_aliases;
+use crate::geo_filter::validate_geo_filter;
+pub use crate::hedging::HedgingParams;
+use crate::hit_address::set_hit_addresses;
+pub use crate::hit_address::HitAddress;
+use crate::hit_sampling::validate_hit_sampling;
+pub use crate::leaf::configure_whole_split_download;
+use crate::leaf::{cache_policy, leaf_search};
+use crate::percentiles::{estimate_percentiles, validate_percentile_request};
+pub use crate::placement::{
+    failure_domain, parse_node_attribute, NodeAttributes, PlacementRule, PlacementRules,
+    FAILURE_DOMAIN_ATTRIBUTE_KEY,
+};
+pub use crate::root::{root_fetch_doc, root_search};
 pub use crate::search_response_rest::SearchResponseRest;
-pub use crate::search_stream::root_search_stream;
+pub use crate::search_session::SearchSession;
+pub use crate::search_stream::{
+    result_channel, root_search_stream, ResultReceiverStream, ResultSender,
+};
+pub use crate::search_thread_pool::{configure_search_thread_pool, SearchThreadPoolParams};
+pub use crate::searcher_handle::SearcherHandle;
 pub use crate::service::{MockSearchService, SearchService, SearchServiceImpl};
+use crate::split_limit::{plan_split_chunks, resolve_split_limit};
+pub use crate::split_quarantine::DEFAULT_SPLIT_QUARANTINE_THRESHOLD;
+pub use crate::sql::{SqlQuery, SqlResponse};
+pub use crate::standby::StandbyMode;
+use crate::synonyms::load_companion_synonyms;
+use crate::terms_aggregation::{build_terms_aggregation_result, validate_terms_aggregation};
+use crate::tree_aggregation::merge_leaf_responses;
+pub use crate::tree_aggregation::DEFAULT_TREE_AGGREGATION_MIN_NODES;
 
 /// Compute the SWIM port from the HTTP port.
 /// Add 1 to the HTTP port to get the SWIM port.
@@ -133,6 +206,91 @@ fn extract_split_and_footer_offsets(
             .clone(),
         split_footer_start: split_metadata_and_footer_offsets.footer_offsets.start as u64,
         split_footer_end: split_metadata_and_footer_offsets.footer_offsets.end as u64,
+        time_range_end: split_metadata_and_footer_offsets
+            .split_metadata
+            .time_range
+            .as_ref()
+            .map(|time_range| *time_range.end()),
+        split_file: split_metadata_and_footer_offsets
+            .split_metadata
+            .split_file_path
+            .clone()
+            .unwrap_or_default(),
+        split_part_num_bytes: split_metadata_and_footer_offsets
+            .split_metadata
+            .split_part_num_bytes,
+    }
+}
+
+/// Leases the given splits in the process-local `global_split_leases` registry, so that the
+/// garbage collector running in this process does not delete them while they are being read.
+///
+/// The leaf searches only lease their splits locally: the root search they are part of
+/// leases them on all the nodes with [`lease_search_splits`].
+fn lease_splits<'a>(split_ids: impl IntoIterator<Item = &'a str>) -> SplitLease {
+    global_split_leases().acquire(split_ids, DEFAULT_SPLIT_LEASE_TTL)
+}
+
+/// Lease of the splits of a root search, recorded in the metastore as well as in the
+/// process-local registry, so that the garbage collectors of all the nodes leave the splits
+/// untouched.
+///
+/// Dropping the lease only releases it locally: [`SearchSplitLease::release`] releases it in
+/// the metastore. Otherwise, e.g. when the search fails, it expires after
+/// `DEFAULT_SPLIT_LEASE_TTL`.
+struct SearchSplitLease {
+    lease_id: String,
+    leased_index_ids: Vec<String>,
+    _local_split_lease: SplitLease,
+}
+
+impl SearchSplitLease {
+    async fn release(self, metastore: &dyn Metastore) {
+        for index_id in &self.leased_index_ids {
+            if let Err(error) = metastore
+                .release_split_lease(index_id, &self.lease_id)
+                .await
+            {
+                warn!(index_id = %index_id, lease_id = %self.lease_id, error = ?error, "Failed to release the split lease.");
+            }
+        }
+    }
+}
+
+/// Leases the splits of a search, given as `(index_id, split_id)` pairs, for the duration of
+/// the search, see [`SearchSplitLease`].
+///
+/// The search goes on if the metastore fails to record the lease, e.g. because its storage is
+/// read-only: the splits are then only protected from the garbage collector of this process.
+async fn lease_search_splits<'a>(
+    metastore: &dyn Metastore,
+    index_split_ids: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> SearchSplitLease {
+    let mut split_ids_per_index: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (index_id, split_id) in index_split_ids {
+        split_ids_per_index
+            .entry(index_id)
+            .or_default()
+            .push(split_id);
+    }
+    let local_split_lease = lease_splits(split_ids_per_index.values().flatten().copied());
+    let lease_id = Ulid::new().to_string();
+    let mut leased_index_ids = Vec::with_capacity(split_ids_per_index.len());
+    for (index_id, split_ids) in &split_ids_per_index {
+        match metastore
+            .acquire_split_lease(index_id, &lease_id, split_ids, DEFAULT_SPLIT_LEASE_TTL)
+            .await
+        {
+            Ok(()) => leased_index_ids.push(index_id.to_string()),
+            Err(error) => {
+                warn!(index_id = %index_id, error = ?error, "Failed to record the split lease in the metastore.");
+            }
+        }
+    }
+    SearchSplitLease {
+        lease_id,
+        leased_index_ids,
+        _local_split_lease: local_split_lease,
     }
 }
 
@@ -142,14 +300,15 @@ async fn list_relevant_splits(
     metastore: &dyn Metastore,
 ) -> MetastoreResult<Vec<SplitMetadataAndFooterOffsets>> {
     let time_range_opt = extract_time_range(search_request);
-    let split_metas = metastore
-        .list_splits(
+    let split_metas = retry_on_transient_error(|| {
+        metastore.list_splits(
             &search_request.index_id,
             SplitState::Published,
-            time_range_opt,
+            time_range_opt.clone(),
             &search_request.tags,
         )
-        .await?;
+    })
+    .await?;
     Ok(split_metas)
 }
 
@@ -159,45 +318,184 @@ pub async fn single_node_search(
     search_request: &SearchRequest,
     metastore: &dyn Metastore,
     storage_resolver: StorageUriResolver,
+) -> crate::Result<SearchResponse> {
+    single_node_search_with_split_filter(search_request, metastore, storage_resolver, |_| true)
+        .await
+}
+
+/// Performs a search on the current node, restricted to the relevant splits accepted by
+/// `split_filter`.
+pub async fn single_node_search_with_split_filter(
+    search_request: &SearchRequest,
+    metastore: &dyn Metastore,
+    storage_resolver: StorageUriResolver,
+    split_filter: impl Fn(&SplitMetadata) -> bool,
 ) -> crate::Result<SearchResponse> {
     let start_instant = tokio::time::Instant::now();
-    let index_metadata = metastore.index_metadata(&search_request.index_id).await?;
-    let index_storage = storage_resolver.resolve(&index_metadata.index_uri)?;
-    let metas = list_relevant_splits(search_request, metastore).await?;
+    let mut search_request = search_request.clone();
+    let index_metadata =
+        retry_on_transient_error(|| metastore.index_metadata(&search_request.index_id)).await?;
+    resolve_request_field_aliases(&mut search_request, &*index_metadata.index_config);
+    let search_request = &search_request;
+    let index_schema = index_metadata.index_config.schema();
+    let warnings = index_metadata.index_config.query_warnings(search_request)?;
+    validate_distinct_count_fields(&search_request.distinct_count_fields, &index_schema)?;
+    validate_sort_by_field(search_request.sort_by_field.as_deref(), &index_schema)?;
+    validate_percentile_request(
+        &search_request.percentile_fields,
+        &search_request.percentiles,
+        &index_schema,
+    )?;
+    validate_terms_aggregation(search_request.terms_aggregation.as_ref(), &index_schema)?;
+    validate_date_histogram(
+        search_request.date_histogram.as_ref(),
+        &search_request.time_zone,
+        &index_schema,
+    )?;
+    validate_geo_filter(
+        search_request.geo_filter.as_ref(),
+        &*index_metadata.index_config,
+    )?;
+    validate_hit_sampling(search_request, &*index_metadata.index_config)?;
+    let index_storage = storage_resolver.resolve_with_mirror(
+        &index_metadata.index_uri,
+        index_metadata.mirror_index_uri.as_deref(),
+    )?;
+    let metas: Vec<SplitMetadataAndFooterOffsets> = list_relevant_splits(search_request, metastore)
+        .await?
+        .into_iter()
+        .filter(|meta| split_filter(&meta.split_metadata))
+        .collect();
+    let split_limit_opt = resolve_split_limit(
+        search_request,
+        std::iter::once(index_metadata.index_config.split_limit_config()),
+    );
+    let split_metadata_maps: [HashMap<String, SplitMetadataAndFooterOffsets>; 1] = [metas
+        .iter()
+        .map(|meta| (meta.split_metadata.split_id.clone(), meta.clone()))
+        .collect()];
+    let split_chunks = plan_split_chunks(&split_metadata_maps, split_limit_opt)?;
+    let split_lease = lease_search_splits(
+        metastore,
+        metas.iter().map(|meta| {
+            (
+                search_request.index_id.as_str(),
+                meta.split_metadata.split_id.as_str(),
+            )
+        }),
+    )
+    .await;
     let split_metadata: Vec<SplitIdAndFooterOffsets> =
         metas.iter().map(extract_split_and_footer_offsets).collect();
-    let index_config = index_metadata.index_config;
-    let leaf_search_response = leaf_search(
-        search_request,
+    let index_config_version = index_metadata.index_config_version();
+    let index_config = load_companion_synonyms(
+        index_metadata.index_config,
         index_storage.clone(),
-        &split_metadata[..],
-        index_config,
+        search_request.disable_synonyms,
     )
-    .await
-    .context("Failed to perform leaf search.")?;
-    let fetch_docs_response = fetch_docs(
+    .await?;
+    let enrichments = index_config.enrichments();
+    let leaf_search_response = if split_chunks.len() > 1 {
+        // The chunks of splits are searched one after the other, as in `root_search`.
+        let mut request_with_offset_0 = search_request.clone();
+        request_with_offset_0.start_offset = 0;
+        request_with_offset_0.max_hits += search_request.start_offset;
+        let mut leaf_search_responses = Vec::with_capacity(split_chunks.len());
+        for split_chunk in &split_chunks {
+            let chunk_split_metadata: Vec<SplitIdAndFooterOffsets> = split_metadata
+                .iter()
+                .filter(|split| split_chunk[0].contains(&split.split_id))
+                .cloned()
+                .collect();
+            let chunk_leaf_search_response = leaf_search(
+                &request_with_offset_0,
+                index_storage.clone(),
+                &chunk_split_metadata[..],
+                index_config.clone(),
+            )
+            .await
+            .context("Failed to perform leaf search.")?;
+            leaf_search_responses.push(chunk_leaf_search_response);
+        }
+        merge_leaf_responses(search_request, leaf_search_responses).await?
+    } else {
+        leaf_search(
+            search_request,
+            index_storage.clone(),
+            &split_metadata[..],
+            index_config,
+        )
+        .await
+        .context("Failed to perform leaf search.")?
+    };
+    let mut fetch_docs_response = fetch_docs(
         leaf_search_response.partial_hits,
         index_storage,
         &split_metadata,
+        enrichments,
+        search_request.flatten_hits,
+        cache_policy(search_request.cache_policy),
     )
     .await
     .context("Failed to perform fetch docs.")?;
+    split_lease.release(metastore).await;
+    set_hit_addresses(&mut fetch_docs_response.hits, index_config_version);
+    let distinct_counts = estimate_distinct_counts(
+        &search_request.distinct_count_fields,
+        &leaf_search_response.distinct_count_sketches,
+    )?;
+    let percentiles = estimate_percentiles(
+        &search_request.percentiles,
+        &leaf_search_response.percentile_sketches,
+    )?;
+    let terms_aggregation = search_request
+        .terms_aggregation
+        .as_ref()
+        .map(|terms_aggregation| {
+            build_terms_aggregation_result(
+                terms_aggregation,
+                &leaf_search_response.terms_buckets,
+                &index_schema,
+            )
+        })
+        .transpose()?;
+    let date_histogram = if search_request.date_histogram.is_some() {
+        build_date_histogram_result(
+            &leaf_search_response.date_histogram_buckets,
+            &search_request.time_zone,
+        )?
+    } else {
+        Vec::new()
+    };
     let elapsed = start_instant.elapsed();
     Ok(SearchResponse {
         num_hits: leaf_search_response.num_hits,
         hits: fetch_docs_response.hits,
         elapsed_time_micros: elapsed.as_micros() as u64,
         errors: vec![],
+        distinct_counts,
+        percentiles,
+        terms_aggregation,
+        date_histogram,
+        warnings,
+        session_token: String::new(),
+        quarantined_split_ids: Vec::new(),
     })
 }
 
 #[cfg(test)]
 mod tests {
+    use std::path::Path;
     use std::sync::Arc;
 
     use assert_json_diff::assert_json_include;
     use quickwit_index_config::{DefaultIndexConfigBuilder, WikipediaIndexConfig};
     use quickwit_indexing::TestSandbox;
+    use quickwit_proto::{
+        DateHistogramAggregation, GeoBoundingBox, GeoDistance, GeoFilter, HitSampling,
+        TermsAggregation, TermsOrder,
+    };
+    use quickwit_storage::PutPayload;
     use serde_json::json;
 
     use super::*;
@@ -221,6 +519,23 @@ mod tests {
             max_hits: 2,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let single_node_result = single_node_search(
             &search_request,
@@ -238,6 +553,70 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_single_node_with_split_filter() -> anyhow::Result<()> {
+        let index_id = "single-node-split-filter";
+        let test_sandbox =
+            TestSandbox::create(index_id, Arc::new(WikipediaIndexConfig::new())).await?;
+        test_sandbox
+            .add_documents(vec![
+                json!({"title": "snoopy", "body": "beagle", "url": "http://snoopy"}),
+            ])
+            .await?;
+        let first_split_id = test_sandbox
+            .metastore()
+            .list_all_splits(index_id)
+            .await?
+            .into_iter()
+            .next()
+            .unwrap()
+            .split_metadata
+            .split_id;
+        test_sandbox
+            .add_documents(vec![
+                json!({"title": "odie", "body": "beagle", "url": "http://odie"}),
+            ])
+            .await?;
+        let search_request = SearchRequest {
+            index_id: index_id.to_string(),
+            query: "beagle".to_string(),
+            search_fields: vec!["body".to_string()],
+            start_timestamp: None,
+            end_timestamp: None,
+            max_hits: 10,
+            start_offset: 0,
+            tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
+        };
+        let single_node_result = single_node_search_with_split_filter(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+            |split_metadata| split_metadata.split_id != first_split_id,
+        )
+        .await?;
+        assert_eq!(single_node_result.num_hits, 1);
+        let hit_json: serde_json::Value = serde_json::from_str(&single_node_result.hits[0].json)?;
+        assert_json_include!(actual: hit_json, expected: json!({"title": ["odie"]}));
+        Ok(())
+    }
+
     // TODO remove me once `Iterator::is_sorted_by_key` is stabilized.
     fn is_sorted<E, I: Iterator<Item = E>>(mut it: I) -> bool
     where E: Ord {
@@ -276,6 +655,23 @@ mod tests {
             max_hits: 6,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let single_node_result = single_node_search(
             &search_request,
@@ -295,6 +691,547 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_single_node_distinct_count() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "tag_fields": [],
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "user_id",
+                    "type": "u64",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let index_config =
+            serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?;
+        let index_id = "single-node-distinct-count";
+        let test_sandbox = TestSandbox::create(index_id, Arc::new(index_config)).await?;
+        for split_ord in 0..3u64 {
+            let docs: Vec<_> = (0..10u64)
+                .map(|i| json!({"body": "info", "user_id": split_ord * 3 + i}))
+                .collect();
+            test_sandbox.add_documents(docs).await?;
+        }
+        let search_request = SearchRequest {
+            index_id: index_id.to_string(),
+            query: "info".to_string(),
+            search_fields: vec![],
+            start_timestamp: None,
+            end_timestamp: None,
+            max_hits: 1,
+            start_offset: 0,
+            tags: vec![],
+            distinct_count_fields: vec!["user_id".to_string()],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await?;
+        assert_eq!(single_node_result.num_hits, 30);
+        assert_eq!(single_node_result.distinct_counts.len(), 1);
+        assert_eq!(single_node_result.distinct_counts["user_id"], 16);
+
+        let search_request = SearchRequest {
+            distinct_count_fields: vec!["body".to_string()],
+            ..search_request
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await;
+        assert!(matches!(
+            single_node_result,
+            Err(SearchError::InvalidQuery(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_single_node_percentiles() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "tag_fields": [],
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "latency",
+                    "type": "f64",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let index_config =
+            serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?;
+        let index_id = "single-node-percentiles";
+        let test_sandbox = TestSandbox::create(index_id, Arc::new(index_config)).await?;
+        for split_ord in 0..3u64 {
+            let docs: Vec<_> = (0..100u64)
+                .map(|i| json!({"body": "info", "latency": (split_ord * 100 + i + 1) as f64}))
+                .collect();
+            test_sandbox.add_documents(docs).await?;
+        }
+        let search_request = SearchRequest {
+            index_id: index_id.to_string(),
+            query: "info".to_string(),
+            search_fields: vec![],
+            start_timestamp: None,
+            end_timestamp: None,
+            max_hits: 1,
+            start_offset: 0,
+            tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec!["latency".to_string()],
+            percentiles: vec![50.0, 100.0],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await?;
+        assert_eq!(single_node_result.num_hits, 300);
+        let latency_percentiles = &single_node_result.percentiles["latency"];
+        assert_eq!(latency_percentiles.percentiles, vec![50.0, 100.0]);
+        assert!((latency_percentiles.values[0] - 150.5).abs() < 3.0);
+        assert_eq!(latency_percentiles.values[1], 300.0);
+
+        let search_request = SearchRequest {
+            percentiles: vec![101.0],
+            ..search_request
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await;
+        assert!(matches!(
+            single_node_result,
+            Err(SearchError::InvalidQuery(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_single_node_enrichment() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "tag_fields": [],
+            "enrichments": [
+                {
+                    "source_field": "service",
+                    "target_field": "team",
+                    "lookup_table_path": "lookup/teams.csv"
+                }
+            ],
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "service",
+                    "type": "text",
+                    "tokenizer": "raw"
+                }
+            ]
+        }"#;
+        let index_config =
+            serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?;
+        let index_id = "single-node-enrichment";
+        let test_sandbox = TestSandbox::create(index_id, Arc::new(index_config)).await?;
+        test_sandbox
+            .index_storage(index_id)?
+            .put(
+                Path::new("lookup/teams.csv"),
+                PutPayload::from(b"service,team\nauth,identity\n".to_vec()),
+            )
+            .await?;
+        let docs = vec![
+            json!({"body": "info", "service": "auth"}),
+            json!({"body": "info", "service": "billing"}),
+        ];
+        test_sandbox.add_documents(docs).await?;
+        let search_request = SearchRequest {
+            index_id: index_id.to_string(),
+            query: "info".to_string(),
+            max_hits: 10,
+            ..Default::default()
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await?;
+        let mut hits: Vec<serde_json::Value> = single_node_result
+            .hits
+            .iter()
+            .map(|hit| serde_json::from_str(&hit.json))
+            .collect::<Result<_, _>>()?;
+        hits.sort_by_key(|hit| hit["service"][0].as_str().unwrap_or_default().to_string());
+        assert_eq!(hits[0]["team"], json!([{"team": "identity"}]));
+        assert!(hits[1].get("team").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_single_node_terms_aggregation() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "tag_fields": [],
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "endpoint",
+                    "type": "u64",
+                    "fast": true
+                },
+                {
+                    "name": "latency",
+                    "type": "f64",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let index_config =
+            serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?;
+        let index_id = "single-node-terms-aggregation";
+        let test_sandbox = TestSandbox::create(index_id, Arc::new(index_config)).await?;
+        for _ in 0..3 {
+            let docs: Vec<_> = (0..20u64)
+                .map(|i| {
+                    let endpoint = i % 4;
+                    let latency = (endpoint * 100 + i) as f64;
+                    json!({"body": "info", "endpoint": endpoint, "latency": latency})
+                })
+                .collect();
+            test_sandbox.add_documents(docs).await?;
+        }
+        let terms_aggregation = TermsAggregation {
+            field_name: "endpoint".to_string(),
+            size: 2,
+            shard_size: None,
+            order: TermsOrder::Max as i32,
+            metric_field: Some("latency".to_string()),
+            percentile: 0.0,
+            ascending: false,
+        };
+        let search_request = SearchRequest {
+            index_id: index_id.to_string(),
+            query: "info".to_string(),
+            search_fields: vec![],
+            start_timestamp: None,
+            end_timestamp: None,
+            max_hits: 1,
+            start_offset: 0,
+            tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: Some(terms_aggregation.clone()),
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await?;
+        let terms_aggregation_result = single_node_result.terms_aggregation.unwrap();
+        let buckets: Vec<(&str, u64, Option<f64>)> = terms_aggregation_result
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.key.as_str(), bucket.doc_count, bucket.metric_value))
+            .collect();
+        assert_eq!(
+            buckets,
+            vec![("3", 15, Some(319.0)), ("2", 15, Some(218.0))]
+        );
+        assert_eq!(terms_aggregation_result.sum_other_doc_count, 30);
+
+        let search_request = SearchRequest {
+            terms_aggregation: Some(TermsAggregation {
+                metric_field: None,
+                ..terms_aggregation
+            }),
+            ..search_request
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await;
+        assert!(matches!(
+            single_node_result,
+            Err(SearchError::InvalidQuery(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_single_node_date_histogram_time_zone() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "tag_fields": [],
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "timestamp",
+                    "type": "i64",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let index_config =
+            serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?;
+        let index_id = "single-node-date-histogram-time-zone";
+        let test_sandbox = TestSandbox::create(index_id, Arc::new(index_config)).await?;
+        // One document every 6 hours, from 2021-05-31T23:00:00Z, in two splits.
+        for split_docs in (0..8i64).collect::<Vec<_>>().chunks(4) {
+            let docs: Vec<_> = split_docs
+                .iter()
+                .map(|i| json!({"body": "info", "timestamp": 1_622_502_000 + i * 21_600}))
+                .collect();
+            test_sandbox.add_documents(docs).await?;
+        }
+        let search_request = SearchRequest {
+            index_id: index_id.to_string(),
+            query: "info".to_string(),
+            max_hits: 0,
+            date_histogram: Some(DateHistogramAggregation {
+                field_name: "timestamp".to_string(),
+                interval: "day".to_string(),
+            }),
+            time_zone: "Europe/Paris".to_string(),
+            ..Default::default()
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await?;
+        let buckets: Vec<(i64, u64, &str)> = single_node_result
+            .date_histogram
+            .iter()
+            .map(|bucket| (bucket.key, bucket.doc_count, bucket.key_as_string.as_str()))
+            .collect();
+        assert_eq!(
+            buckets,
+            vec![
+                (1_622_498_400, 4, "2021-06-01T00:00:00+02:00"),
+                (1_622_584_800, 4, "2021-06-02T00:00:00+02:00"),
+            ]
+        );
+
+        // The same days, in UTC.
+        let search_request = SearchRequest {
+            time_zone: String::new(),
+            ..search_request
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await?;
+        let doc_counts: Vec<u64> = single_node_result
+            .date_histogram
+            .iter()
+            .map(|bucket| bucket.doc_count)
+            .collect();
+        assert_eq!(doc_counts, vec![1, 4, 3]);
+
+        let search_request = SearchRequest {
+            query: "+info +timestamp:[2021-06-02 TO *]".to_string(),
+            time_zone: "Europe/Paris".to_string(),
+            ..search_request
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await?;
+        assert_eq!(single_node_result.num_hits, 4);
+        assert_eq!(single_node_result.date_histogram.len(), 1);
+
+        let search_request = SearchRequest {
+            time_zone: "Mars/Olympus_Mons".to_string(),
+            ..search_request
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await;
+        assert!(matches!(
+            single_node_result,
+            Err(SearchError::InvalidQuery(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_single_node_hit_sampling() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "timestamp_field": "timestamp",
+            "tag_fields": [],
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "timestamp",
+                    "type": "i64",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let index_config =
+            serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?;
+        let index_id = "single-node-hit-sampling";
+        let test_sandbox = TestSandbox::create(index_id, Arc::new(index_config)).await?;
+        // One document every 20 seconds, over 4 minutes, in two splits.
+        for split_docs in (0..12i64).collect::<Vec<_>>().chunks(6) {
+            let docs: Vec<_> = split_docs
+                .iter()
+                .map(|i| json!({"body": "info", "timestamp": 1_622_502_000 + i * 20}))
+                .collect();
+            test_sandbox.add_documents(docs).await?;
+        }
+        let hit_timestamps = |search_response: &SearchResponse| -> anyhow::Result<Vec<i64>> {
+            search_response
+                .hits
+                .iter()
+                .map(|hit| {
+                    let hit_json: serde_json::Value = serde_json::from_str(&hit.json)?;
+                    Ok(hit_json["timestamp"][0].as_i64().unwrap_or_default() - 1_622_502_000)
+                })
+                .collect()
+        };
+        let search_request = SearchRequest {
+            index_id: index_id.to_string(),
+            query: "info".to_string(),
+            max_hits: 10,
+            hit_sampling: Some(HitSampling {
+                interval: "1m".to_string(),
+                max_hits_per_bucket: 1,
+                first: false,
+            }),
+            ..Default::default()
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await?;
+        assert_eq!(single_node_result.num_hits, 12);
+        assert_eq!(
+            hit_timestamps(&single_node_result)?,
+            vec![220, 160, 100, 40]
+        );
+
+        let search_request = SearchRequest {
+            hit_sampling: Some(HitSampling {
+                interval: "2m".to_string(),
+                max_hits_per_bucket: 2,
+                first: true,
+            }),
+            ..search_request
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await?;
+        assert_eq!(hit_timestamps(&single_node_result)?, vec![0, 20, 120, 140]);
+
+        let search_request = SearchRequest {
+            sort_by_field: Some("timestamp".to_string()),
+            ..search_request
+        };
+        let single_node_result = single_node_search(
+            &search_request,
+            &*test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        )
+        .await;
+        assert!(matches!(
+            single_node_result,
+            Err(SearchError::InvalidQuery(_))
+        ));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_single_node_filtering() -> anyhow::Result<()> {
         let index_config = r#"{
@@ -338,6 +1275,23 @@ mod tests {
             max_hits: 15,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let single_node_response = single_node_search(
             &search_request,
@@ -360,6 +1314,23 @@ mod tests {
             max_hits: 25,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let single_node_response = single_node_search(
             &search_request,
@@ -382,6 +1353,23 @@ mod tests {
             max_hits: 25,
             start_offset: 0,
             tags: vec!["foo".to_string()],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let single_node_response = single_node_search(
             &search_request,
@@ -394,4 +1382,125 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_single_node_geo_filter() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["city"],
+            "tag_fields": [],
+            "field_mappings": [
+                {
+                    "name": "city",
+                    "type": "text"
+                },
+                {
+                    "name": "location",
+                    "type": "geo_point"
+                }
+            ]
+        }"#;
+        let index_config =
+            serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?;
+        let index_id = "single-node-geo-filter";
+        let test_sandbox = TestSandbox::create(index_id, Arc::new(index_config)).await?;
+        let docs = vec![
+            json!({"city": "paris", "location": {"lat": 48.8566, "lon": 2.3522}}),
+            json!({"city": "london", "location": "51.5074,-0.1278"}),
+            json!({"city": "tokyo", "location": {"lat": 35.6762, "lon": 139.6503}}),
+            json!({"city": "suva", "location": {"lat": -18.1248, "lon": 178.4501}}),
+            json!({"city": "nowhere"}),
+        ];
+        test_sandbox.add_documents(docs).await?;
+        let search_cities = |geo_filter: GeoFilter| {
+            let search_request = SearchRequest {
+                index_id: index_id.to_string(),
+                query: "*".to_string(),
+                search_fields: vec![],
+                start_timestamp: None,
+                end_timestamp: None,
+                max_hits: 10,
+                start_offset: 0,
+                tags: vec![],
+                distinct_count_fields: vec![],
+                percentile_fields: vec![],
+                percentiles: vec![],
+                terms_aggregation: None,
+                date_histogram: None,
+                time_zone: String::new(),
+                geo_filter: Some(geo_filter),
+                mandatory_filter: None,
+                flatten_hits: false,
+                sort_by_field: None,
+                sort_ascending: false,
+                disable_synonyms: false,
+                session_token: None,
+                min_publish_timestamp: None,
+                cache_policy: 0,
+                max_num_splits: None,
+                hit_sampling: None,
+            };
+            let metastore = test_sandbox.metastore();
+            let storage_uri_resolver = test_sandbox.storage_uri_resolver();
+            async move {
+                let search_response =
+                    single_node_search(&search_request, &*metastore, storage_uri_resolver).await?;
+                let mut cities: Vec<String> = search_response
+                    .hits
+                    .iter()
+                    .map(|hit| {
+                        let doc: serde_json::Value = serde_json::from_str(&hit.json).unwrap();
+                        doc["city"][0].as_str().unwrap().to_string()
+                    })
+                    .collect();
+                cities.sort();
+                crate::Result::Ok(cities)
+            }
+        };
+        let europe_filter = GeoFilter {
+            field_name: "location".to_string(),
+            bounding_box: Some(GeoBoundingBox {
+                top_lat: 60.0,
+                left_lon: -10.0,
+                bottom_lat: 35.0,
+                right_lon: 30.0,
+            }),
+            distance: None,
+        };
+        assert_eq!(search_cities(europe_filter).await?, vec!["london", "paris"]);
+        let pacific_filter = GeoFilter {
+            field_name: "location".to_string(),
+            bounding_box: Some(GeoBoundingBox {
+                top_lat: 60.0,
+                left_lon: 130.0,
+                bottom_lat: -60.0,
+                right_lon: -100.0,
+            }),
+            distance: None,
+        };
+        assert_eq!(search_cities(pacific_filter).await?, vec!["suva", "tokyo"]);
+        let around_paris_filter = GeoFilter {
+            field_name: "location".to_string(),
+            bounding_box: None,
+            distance: Some(GeoDistance {
+                lat: 48.8566,
+                lon: 2.3522,
+                distance_in_meters: 100_000.0,
+            }),
+        };
+        assert_eq!(search_cities(around_paris_filter).await?, vec!["paris"]);
+        let city_filter = GeoFilter {
+            field_name: "city".to_string(),
+            bounding_box: None,
+            distance: Some(GeoDistance {
+                lat: 48.8566,
+                lon: 2.3522,
+                distance_in_meters: 100_000.0,
+            }),
+        };
+        assert!(matches!(
+            search_cities(city_filter).await,
+            Err(SearchError::InvalidQuery(_))
+        ));
+        Ok(())
+    }
 }
@@ -0,0 +1,413 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
+
+use quickwit_proto::{PercentileSketch, PercentileValues};
+use tantivy::fastfield::FastValue;
+use tantivy::schema::{Schema, Type};
+use tantivy::TantivyError;
+
+use crate::SearchError;
+
+/// Percentiles estimated when the request does not specify any.
+pub const DEFAULT_PERCENTILES: [f64; 3] = [50.0, 95.0, 99.0];
+
+/// Bounds the number of centroids of a digest to `COMPRESSION`.
+const COMPRESSION: f64 = 100.0;
+
+/// Number of values buffered before they are merged into the centroids.
+const BUFFER_CAPACITY: usize = 1_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// The `k1` scale function of the t-digest paper, mapping a quantile to an index.
+///
+/// Two centroids are only merged if the indexes of the quantiles they cover are
+/// less than 1 apart, which keeps the centroids small near the extreme quantiles.
+fn scale(quantile: f64) -> f64 {
+    COMPRESSION / (2.0 * PI) * (2.0 * quantile.min(1.0) - 1.0).asin()
+}
+
+/// A t-digest, estimating the percentiles of the values inserted into it.
+///
+/// Digests are computed for each split by the leaves, and merged by the root.
+/// Centroids are small near the extreme percentiles, so that p99 is estimated
+/// much more accurately than with a fixed-size histogram.
+#[derive(Clone, Debug)]
+pub struct TDigest {
+    centroids: Vec<Centroid>,
+    buffer: Vec<Centroid>,
+    min: f64,
+    max: f64,
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        TDigest {
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl TDigest {
+    /// Inserts a value into the digest. NaN values are ignored.
+    pub fn insert(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.buffer.push(Centroid {
+            mean: value,
+            weight: 1.0,
+        });
+        if self.buffer.len() >= BUFFER_CAPACITY {
+            self.compress();
+        }
+    }
+
+    /// Merges the values of `other` into the digest.
+    pub fn merge(&mut self, other: &TDigest) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.buffer.extend_from_slice(&other.centroids);
+        self.buffer.extend_from_slice(&other.buffer);
+        self.compress();
+    }
+
+    /// Merges the buffered values into the centroids.
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut centroids = std::mem::take(&mut self.buffer);
+        centroids.append(&mut self.centroids);
+        centroids.sort_by(|left, right| {
+            left.mean
+                .partial_cmp(&right.mean)
+                .unwrap_or(Ordering::Equal)
+        });
+        let total_weight: f64 = centroids.iter().map(|centroid| centroid.weight).sum();
+        let mut compressed_centroids = Vec::with_capacity(COMPRESSION as usize);
+        let mut centroids_iter = centroids.into_iter();
+        let mut current = match centroids_iter.next() {
+            Some(centroid) => centroid,
+            None => return,
+        };
+        let mut weight_so_far = 0.0;
+        for centroid in centroids_iter {
+            let merged_weight = current.weight + centroid.weight;
+            let index_span = scale((weight_so_far + merged_weight) / total_weight)
+                - scale(weight_so_far / total_weight);
+            if index_span <= 1.0 {
+                current.mean += (centroid.mean - current.mean) * centroid.weight / merged_weight;
+                current.weight = merged_weight;
+            } else {
+                weight_so_far += current.weight;
+                compressed_centroids.push(current);
+                current = centroid;
+            }
+        }
+        compressed_centroids.push(current);
+        self.centroids = compressed_centroids;
+    }
+
+    /// Returns the estimated value of the `percentile` (between 0 and 100), or `None` if the
+    /// digest is empty.
+    pub fn percentile(&mut self, percentile: f64) -> Option<f64> {
+        self.compress();
+        if self.centroids.is_empty() {
+            return None;
+        }
+        let total_weight: f64 = self.centroids.iter().map(|centroid| centroid.weight).sum();
+        let rank = (percentile / 100.0).max(0.0).min(1.0) * total_weight;
+        // Each centroid is assumed to sit at the middle of the ranks it covers.
+        // The value is interpolated between the two closest centroids.
+        let mut previous_center = 0.0;
+        let mut previous_mean = self.min;
+        let mut weight_so_far = 0.0;
+        for centroid in &self.centroids {
+            let center = weight_so_far + centroid.weight / 2.0;
+            if rank < center {
+                let ratio = (rank - previous_center) / (center - previous_center);
+                return Some(previous_mean + ratio * (centroid.mean - previous_mean));
+            }
+            previous_center = center;
+            previous_mean = centroid.mean;
+            weight_so_far += centroid.weight;
+        }
+        if total_weight <= previous_center {
+            return Some(self.max);
+        }
+        let ratio = (rank - previous_center) / (total_weight - previous_center);
+        Some(previous_mean + ratio * (self.max - previous_mean))
+    }
+
+    /// Rebuilds the digest of a sketch, or returns `None` if the sketch is invalid.
+    pub(crate) fn from_sketch(sketch: PercentileSketch) -> Option<TDigest> {
+        if sketch.means.len() != sketch.weights.len() {
+            return None;
+        }
+        let centroids = sketch
+            .means
+            .into_iter()
+            .zip(sketch.weights)
+            .map(|(mean, weight)| Centroid { mean, weight })
+            .collect();
+        Some(TDigest {
+            centroids,
+            buffer: Vec::new(),
+            min: sketch.min,
+            max: sketch.max,
+        })
+    }
+
+    /// Returns the sketch of the values of `field_name`, as sent from the leaves to the root.
+    pub fn into_sketch(mut self, field_name: String) -> PercentileSketch {
+        self.compress();
+        let (means, weights) = self
+            .centroids
+            .iter()
+            .map(|centroid| (centroid.mean, centroid.weight))
+            .unzip();
+        PercentileSketch {
+            field_name,
+            means,
+            weights,
+            min: self.min,
+            max: self.max,
+        }
+    }
+}
+
+/// Converts the `u64` representation of a fast field value into a `f64`.
+pub(crate) fn fast_value_to_f64(value_type: Type, value: u64) -> f64 {
+    match value_type {
+        Type::I64 | Type::Date => i64::from_u64(value) as f64,
+        Type::F64 => f64::from_u64(value),
+        _ => value as f64,
+    }
+}
+
+/// Merges the sketches of the same fields.
+pub(crate) fn merge_percentile_sketches(
+    sketches: impl IntoIterator<Item = PercentileSketch>,
+) -> tantivy::Result<Vec<PercentileSketch>> {
+    let mut merged_digests: BTreeMap<String, TDigest> = BTreeMap::new();
+    for sketch in sketches {
+        let field_name = sketch.field_name.clone();
+        let digest = TDigest::from_sketch(sketch).ok_or_else(|| {
+            TantivyError::InvalidArgument(format!(
+                "Invalid percentile sketch for field `{}`.",
+                field_name
+            ))
+        })?;
+        if let Some(merged_digest) = merged_digests.get_mut(&field_name) {
+            merged_digest.merge(&digest);
+        } else {
+            merged_digests.insert(field_name, digest);
+        }
+    }
+    Ok(merged_digests
+        .into_iter()
+        .map(|(field_name, digest)| digest.into_sketch(field_name))
+        .collect())
+}
+
+/// Returns the requested percentiles, or the default ones if none were requested.
+pub(crate) fn requested_percentiles(percentiles: &[f64]) -> &[f64] {
+    if percentiles.is_empty() {
+        &DEFAULT_PERCENTILES
+    } else {
+        percentiles
+    }
+}
+
+/// Returns the estimated percentiles of each of the fields of the `sketches` returned by
+/// the leaves.
+///
+/// Fields without any value, e.g. because no document matched the request, are left out.
+pub(crate) fn estimate_percentiles(
+    percentiles: &[f64],
+    sketches: &[PercentileSketch],
+) -> crate::Result<HashMap<String, PercentileValues>> {
+    let mut field_percentiles = HashMap::with_capacity(sketches.len());
+    let percentiles = requested_percentiles(percentiles);
+    for merged_sketch in merge_percentile_sketches(sketches.iter().cloned())? {
+        let field_name = merged_sketch.field_name.clone();
+        let mut digest = TDigest::from_sketch(merged_sketch)
+            .expect("Merged sketches should have as many means as weights.");
+        let values: Option<Vec<f64>> = percentiles
+            .iter()
+            .map(|&percentile| digest.percentile(percentile))
+            .collect();
+        if let Some(values) = values {
+            let percentile_values = PercentileValues {
+                percentiles: percentiles.to_vec(),
+                values,
+            };
+            field_percentiles.insert(field_name, percentile_values);
+        }
+    }
+    Ok(field_percentiles)
+}
+
+/// Checks that the percentile fields are numeric fast fields, and that the percentiles are
+/// between 0 and 100.
+pub(crate) fn validate_percentile_request(
+    percentile_fields: &[String],
+    percentiles: &[f64],
+    schema: &Schema,
+) -> crate::Result<()> {
+    for field_name in percentile_fields {
+        let field = schema.get_field(field_name).ok_or_else(|| {
+            SearchError::InvalidQuery(format!("Field `{}` does not exist in schema", field_name))
+        })?;
+        let field_entry = schema.get_field_entry(field);
+        if !field_entry.is_fast() {
+            return Err(SearchError::InvalidQuery(format!(
+                "Percentiles are only supported on fast fields, `{}` is not a fast field",
+                field_name
+            )));
+        }
+        if !matches!(
+            field_entry.field_type().value_type(),
+            Type::U64 | Type::I64 | Type::F64 | Type::Date
+        ) {
+            return Err(SearchError::InvalidQuery(format!(
+                "Percentiles are only supported on numeric fields, `{}` is not a numeric field",
+                field_name
+            )));
+        }
+    }
+    if let Some(percentile) = percentiles
+        .iter()
+        .find(|percentile| !(0.0..=100.0).contains(*percentile))
+    {
+        return Err(SearchError::InvalidQuery(format!(
+            "Percentile `{}` is not between 0 and 100",
+            percentile
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_tdigest_percentile() {
+        let mut digest = TDigest::default();
+        assert!(digest.percentile(50.0).is_none());
+
+        digest.insert(42.0);
+        assert_eq!(digest.percentile(0.0), Some(42.0));
+        assert_eq!(digest.percentile(99.0), Some(42.0));
+
+        let mut digest = TDigest::default();
+        for value in 1..=100_000 {
+            digest.insert(value as f64);
+        }
+        assert_eq!(digest.percentile(0.0), Some(1.0));
+        assert_eq!(digest.percentile(100.0), Some(100_000.0));
+        assert_close(digest.percentile(50.0).unwrap(), 50_000.0, 500.0);
+        assert_close(digest.percentile(99.0).unwrap(), 99_000.0, 100.0);
+        assert_close(digest.percentile(99.9).unwrap(), 99_900.0, 20.0);
+        assert!(digest.centroids.len() <= COMPRESSION as usize);
+    }
+
+    #[test]
+    fn test_tdigest_merge() {
+        let mut left = TDigest::default();
+        let mut right = TDigest::default();
+        for value in 0..10_000 {
+            left.insert(value as f64);
+            right.insert((value + 10_000) as f64);
+        }
+        left.merge(&right);
+        assert_eq!(left.percentile(0.0), Some(0.0));
+        assert_eq!(left.percentile(100.0), Some(19_999.0));
+        assert_close(left.percentile(50.0).unwrap(), 10_000.0, 200.0);
+        assert_close(left.percentile(95.0).unwrap(), 19_000.0, 100.0);
+    }
+
+    #[test]
+    fn test_estimate_percentiles() -> anyhow::Result<()> {
+        let sketch = |field_name: &str, values: std::ops::Range<i32>| {
+            let mut digest = TDigest::default();
+            for value in values {
+                digest.insert(value as f64);
+            }
+            digest.into_sketch(field_name.to_string())
+        };
+        let sketches = vec![
+            sketch("latency", 0..500),
+            sketch("size", 0..0),
+            sketch("latency", 500..1_000),
+        ];
+        let field_percentiles = estimate_percentiles(&[], &sketches)?;
+        assert_eq!(field_percentiles.len(), 1);
+        assert_eq!(
+            field_percentiles["latency"].percentiles,
+            DEFAULT_PERCENTILES.to_vec()
+        );
+        let latency_percentiles = &field_percentiles["latency"].values;
+        assert_close(latency_percentiles[0], 500.0, 10.0);
+        assert_close(latency_percentiles[1], 950.0, 5.0);
+        assert_close(latency_percentiles[2], 990.0, 2.0);
+
+        let invalid_sketch = PercentileSketch {
+            field_name: "latency".to_string(),
+            means: vec![1.0],
+            weights: vec![],
+            min: 1.0,
+            max: 1.0,
+        };
+        assert!(merge_percentile_sketches(vec![invalid_sketch]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fast_value_to_f64() {
+        assert_eq!(fast_value_to_f64(Type::U64, 3), 3.0);
+        assert_eq!(fast_value_to_f64(Type::I64, (-3i64).as_u64()), -3.0);
+        assert_eq!(fast_value_to_f64(Type::F64, 1.5f64.as_u64()), 1.5);
+    }
+}
@@ -0,0 +1,219 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anyhow::Context;
+
+/// Attributes of a searcher node, e.g. `tier` => `hot`.
+pub type NodeAttributes = BTreeMap<String, String>;
+
+/// Key of the node attribute holding the failure domain of a node, e.g. its availability zone.
+///
+/// The jobs are still placed regardless of the failure domains, so that each split keeps
+/// being searched by the same node, but the retries of the jobs that failed on a node go to
+/// a node of another failure domain when possible.
+pub const FAILURE_DOMAIN_ATTRIBUTE_KEY: &str = "zone";
+
+/// Returns the failure domain of a node with the given attributes, if it has one.
+pub fn failure_domain(node_attributes: &NodeAttributes) -> Option<&str> {
+    node_attributes
+        .get(FAILURE_DOMAIN_ATTRIBUTE_KEY)
+        .map(String::as_str)
+}
+
+/// Parses a node attribute of the form `<key>=<value>`.
+pub fn parse_node_attribute(node_attribute: &str) -> anyhow::Result<(String, String)> {
+    let (key, value) = node_attribute.split_once('=').with_context(|| {
+        format!(
+            "Node attribute `{}` should be of the form `<key>=<value>`.",
+            node_attribute
+        )
+    })?;
+    if key.is_empty() {
+        anyhow::bail!("Node attribute `{}` has an empty key.", node_attribute);
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Restricts the searches of the indexes matching `index_id_pattern` to the searcher nodes
+/// whose attribute `attribute_key` is `attribute_value`.
+///
+/// The pattern is either an index ID, or an index ID prefix followed by `*`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlacementRule {
+    /// Index ID or index ID prefix followed by `*`.
+    pub index_id_pattern: String,
+    /// Key of the attribute the searcher nodes must have.
+    pub attribute_key: String,
+    /// Value of the attribute the searcher nodes must have.
+    pub attribute_value: String,
+}
+
+impl PlacementRule {
+    fn matches_index(&self, index_id: &str) -> bool {
+        if let Some(index_id_prefix) = self.index_id_pattern.strip_suffix('*') {
+            index_id.starts_with(index_id_prefix)
+        } else {
+            index_id == self.index_id_pattern
+        }
+    }
+
+    fn matches_node(&self, node_attributes: &NodeAttributes) -> bool {
+        node_attributes.get(&self.attribute_key) == Some(&self.attribute_value)
+    }
+}
+
+/// Parses a placement rule of the form `<index ID pattern>:<key>=<value>`,
+/// e.g. `logs-archive-*:tier=cold`.
+impl FromStr for PlacementRule {
+    type Err = anyhow::Error;
+
+    fn from_str(placement_rule: &str) -> anyhow::Result<Self> {
+        let (index_id_pattern, node_attribute) =
+            placement_rule.split_once(':').with_context(|| {
+                format!(
+                    "Placement rule `{}` should be of the form `<index ID pattern>:<key>=<value>`.",
+                    placement_rule
+                )
+            })?;
+        if index_id_pattern.is_empty() {
+            anyhow::bail!(
+                "Placement rule `{}` has an empty index ID pattern.",
+                placement_rule
+            );
+        }
+        let (attribute_key, attribute_value) = parse_node_attribute(node_attribute)?;
+        Ok(PlacementRule {
+            index_id_pattern: index_id_pattern.to_string(),
+            attribute_key,
+            attribute_value,
+        })
+    }
+}
+
+/// Set of placement rules enforced when assigning search jobs to searcher nodes.
+///
+/// A node can search an index if it satisfies all the rules matching the index.
+/// Indexes without rules can be searched by any node.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlacementRules {
+    rules: Vec<PlacementRule>,
+}
+
+impl PlacementRules {
+    /// Creates a set of placement rules.
+    pub fn new(rules: Vec<PlacementRule>) -> Self {
+        PlacementRules { rules }
+    }
+
+    /// Returns true if at least one rule matches the index.
+    pub fn has_rules_for(&self, index_id: &str) -> bool {
+        self.rules.iter().any(|rule| rule.matches_index(index_id))
+    }
+
+    /// Returns true if a node with the given attributes can search the index.
+    ///
+    /// The attributes of a node are `None` until they have been fetched from the node.
+    pub fn is_node_eligible(
+        &self,
+        index_id: &str,
+        node_attributes_opt: Option<&NodeAttributes>,
+    ) -> bool {
+        let mut matching_rules = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches_index(index_id))
+            .peekable();
+        if matching_rules.peek().is_none() {
+            return true;
+        }
+        if let Some(node_attributes) = node_attributes_opt {
+            matching_rules.all(|rule| rule.matches_node(node_attributes))
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_attributes(attributes: &[(&str, &str)]) -> NodeAttributes {
+        attributes
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_placement_rule() -> anyhow::Result<()> {
+        assert_eq!(
+            "logs-*:tier=hot".parse::<PlacementRule>()?,
+            PlacementRule {
+                index_id_pattern: "logs-*".to_string(),
+                attribute_key: "tier".to_string(),
+                attribute_value: "hot".to_string(),
+            }
+        );
+        assert!("logs-*".parse::<PlacementRule>().is_err());
+        assert!("logs-*:tier".parse::<PlacementRule>().is_err());
+        assert!(":tier=hot".parse::<PlacementRule>().is_err());
+        assert!("logs-*:=hot".parse::<PlacementRule>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_failure_domain() {
+        assert_eq!(
+            failure_domain(&node_attributes(&[("tier", "hot"), ("zone", "eu-west-1a")])),
+            Some("eu-west-1a")
+        );
+        assert_eq!(failure_domain(&node_attributes(&[("tier", "hot")])), None);
+    }
+
+    #[test]
+    fn test_placement_rules_node_eligibility() -> anyhow::Result<()> {
+        let placement_rules = PlacementRules::new(vec![
+            "logs-*:tier=hot".parse()?,
+            "logs-eu:region=eu".parse()?,
+            "archive:tier=cold".parse()?,
+        ]);
+        let hot_eu_node = node_attributes(&[("tier", "hot"), ("region", "eu")]);
+        let hot_us_node = node_attributes(&[("tier", "hot"), ("region", "us")]);
+        let cold_node = node_attributes(&[("tier", "cold")]);
+
+        assert!(placement_rules.has_rules_for("logs-us"));
+        assert!(placement_rules.is_node_eligible("logs-us", Some(&hot_us_node)));
+        assert!(placement_rules.is_node_eligible("logs-eu", Some(&hot_eu_node)));
+        assert!(!placement_rules.is_node_eligible("logs-eu", Some(&hot_us_node)));
+        assert!(!placement_rules.is_node_eligible("logs-us", Some(&cold_node)));
+        assert!(!placement_rules.is_node_eligible("logs-us", None));
+        assert!(placement_rules.is_node_eligible("archive", Some(&cold_node)));
+        // `archive` is not a prefix pattern.
+        assert!(placement_rules.is_node_eligible("archive-2021", Some(&hot_us_node)));
+
+        assert!(!placement_rules.has_rules_for("other"));
+        assert!(placement_rules.is_node_eligible("other", Some(&cold_node)));
+        assert!(placement_rules.is_node_eligible("other", None));
+        Ok(())
+    }
+}
@@ -0,0 +1,428 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::ops::Bound;
+
+use quickwit_directories::{FastFieldValue, SplitStats};
+use quickwit_index_config::FastFieldRangeQuery;
+use tantivy::fastfield::FastValue;
+use tantivy::query::{
+    AllQuery, BooleanQuery, EmptyQuery, Occur, PhraseQuery, Query, RangeQuery, TermQuery,
+};
+use tantivy::schema::Field;
+use tantivy::{Searcher, Term};
+
+/// Plans the execution of `query` on the split `searcher` reads.
+///
+/// The clauses of the boolean queries are ordered by their estimated number of matching
+/// documents, so that the most selective ones are executed first. The clauses that cannot
+/// match any document of the split are pruned, which turns queries requiring them into an
+/// `EmptyQuery`: the postings of their terms are then not even loaded.
+///
+/// The number of documents matching a term is read from the term dictionary of the split.
+/// The range clauses outside of the min and max values of their field, as recorded in the
+/// `split_stats_opt` of the split, match nothing. This includes the ranges on the fields
+/// that are only fast fields.
+pub(crate) fn plan_query(
+    query: &dyn Query,
+    searcher: &Searcher,
+    split_stats_opt: Option<&SplitStats>,
+) -> tantivy::Result<Box<dyn Query>> {
+    let query_planner = QueryPlanner {
+        searcher,
+        split_stats_opt,
+        num_docs: searcher.num_docs(),
+    };
+    Ok(query_planner.plan(query)?.query)
+}
+
+struct PlannedQuery {
+    query: Box<dyn Query>,
+    /// Upper bound of the number of documents matching the query.
+    estimated_num_docs: u64,
+}
+
+impl PlannedQuery {
+    fn empty() -> Self {
+        PlannedQuery {
+            query: Box::new(EmptyQuery),
+            estimated_num_docs: 0,
+        }
+    }
+}
+
+struct QueryPlanner<'a> {
+    searcher: &'a Searcher,
+    split_stats_opt: Option<&'a SplitStats>,
+    num_docs: u64,
+}
+
+impl<'a> QueryPlanner<'a> {
+    fn plan(&self, query: &dyn Query) -> tantivy::Result<PlannedQuery> {
+        if let Some(boolean_query) = query.downcast_ref::<BooleanQuery>() {
+            return self.plan_boolean_query(boolean_query);
+        }
+        let estimated_num_docs = self.estimate_num_docs(query)?;
+        if estimated_num_docs == 0 {
+            return Ok(PlannedQuery::empty());
+        }
+        Ok(PlannedQuery {
+            query: query.box_clone(),
+            estimated_num_docs,
+        })
+    }
+
+    fn plan_boolean_query(&self, boolean_query: &BooleanQuery) -> tantivy::Result<PlannedQuery> {
+        let mut must_clauses = Vec::new();
+        let mut should_clauses = Vec::new();
+        let mut must_not_clauses = Vec::new();
+        for (occur, sub_query) in boolean_query.clauses() {
+            let planned_sub_query = self.plan(sub_query.as_ref())?;
+            let is_empty = planned_sub_query.estimated_num_docs == 0;
+            match occur {
+                // A required clause matching nothing prunes the whole query.
+                Occur::Must if is_empty => return Ok(PlannedQuery::empty()),
+                Occur::Must => must_clauses.push(planned_sub_query),
+                Occur::Should if !is_empty => should_clauses.push(planned_sub_query),
+                Occur::MustNot if !is_empty => must_not_clauses.push(planned_sub_query),
+                Occur::Should | Occur::MustNot => {}
+            }
+        }
+        // A boolean query without any positive clause matches nothing.
+        if must_clauses.is_empty() && should_clauses.is_empty() {
+            return Ok(PlannedQuery::empty());
+        }
+        let estimated_num_docs = if must_clauses.is_empty() {
+            should_clauses
+                .iter()
+                .map(|clause| clause.estimated_num_docs)
+                .sum::<u64>()
+                .min(self.num_docs)
+        } else {
+            must_clauses
+                .iter()
+                .map(|clause| clause.estimated_num_docs)
+                .min()
+                .unwrap_or(self.num_docs)
+        };
+        let mut clauses = Vec::new();
+        for (occur, mut planned_clauses) in vec![
+            (Occur::Must, must_clauses),
+            (Occur::Should, should_clauses),
+            (Occur::MustNot, must_not_clauses),
+        ] {
+            planned_clauses.sort_by_key(|clause| clause.estimated_num_docs);
+            clauses.extend(
+                planned_clauses
+                    .into_iter()
+                    .map(|clause| (occur, clause.query)),
+            );
+        }
+        Ok(PlannedQuery {
+            query: Box::new(BooleanQuery::new(clauses)),
+            estimated_num_docs,
+        })
+    }
+
+    fn estimate_num_docs(&self, query: &dyn Query) -> tantivy::Result<u64> {
+        if query.downcast_ref::<EmptyQuery>().is_some() {
+            return Ok(0);
+        }
+        if query.downcast_ref::<AllQuery>().is_some() {
+            return Ok(self.num_docs);
+        }
+        if let Some(term_query) = query.downcast_ref::<TermQuery>() {
+            return self.term_doc_freq(term_query.term());
+        }
+        if let Some(phrase_query) = query.downcast_ref::<PhraseQuery>() {
+            let mut estimated_num_docs = self.num_docs;
+            for term in phrase_query.phrase_terms() {
+                estimated_num_docs = estimated_num_docs.min(self.term_doc_freq(&term)?);
+            }
+            return Ok(estimated_num_docs);
+        }
+        if let Some(range_query) = query.downcast_ref::<RangeQuery>() {
+            let left_bound = term_bound_u64_value(range_query.left_bound());
+            let right_bound = term_bound_u64_value(range_query.right_bound());
+            if self.is_range_out_of_split(range_query.field(), left_bound, right_bound) {
+                return Ok(0);
+            }
+        }
+        if let Some(fast_field_range_query) = query.downcast_ref::<FastFieldRangeQuery>() {
+            if self.is_range_out_of_split(
+                fast_field_range_query.field(),
+                fast_field_range_query.left_bound(),
+                fast_field_range_query.right_bound(),
+            ) {
+                return Ok(0);
+            }
+        }
+        Ok(self.num_docs)
+    }
+
+    fn term_doc_freq(&self, term: &Term) -> tantivy::Result<u64> {
+        let mut doc_freq = 0;
+        for segment_reader in self.searcher.segment_readers() {
+            let inverted_index = segment_reader.inverted_index(term.field())?;
+            doc_freq += inverted_index.doc_freq(term) as u64;
+        }
+        Ok(doc_freq)
+    }
+
+    /// Returns true if the values of the range, in the `u64` representation of the values of
+    /// `field`, are all below the min value or above the max value of the field in the split.
+    fn is_range_out_of_split(
+        &self,
+        field: Field,
+        left_bound: Bound<u64>,
+        right_bound: Bound<u64>,
+    ) -> bool {
+        let (min_value, max_value) = match self.field_min_max_values(field) {
+            Some(min_max_values) => min_max_values,
+            None => return false,
+        };
+        let is_below_min = match right_bound {
+            Bound::Included(value) => value < min_value,
+            Bound::Excluded(value) => value <= min_value,
+            Bound::Unbounded => false,
+        };
+        let is_above_max = match left_bound {
+            Bound::Included(value) => value > max_value,
+            Bound::Excluded(value) => value >= max_value,
+            Bound::Unbounded => false,
+        };
+        is_below_min || is_above_max
+    }
+
+    /// Returns the min and max values of `field` in the split, in the `u64` representation of
+    /// the terms of numeric fields, which preserves the order of the values.
+    fn field_min_max_values(&self, field: Field) -> Option<(u64, u64)> {
+        let field_name = self.searcher.schema().get_field_name(field);
+        let field_stats = self.split_stats_opt?.fields.get(field_name)?;
+        Some((
+            fast_field_value_to_u64(field_stats.min_value?),
+            fast_field_value_to_u64(field_stats.max_value?),
+        ))
+    }
+}
+
+fn fast_field_value_to_u64(value: FastFieldValue) -> u64 {
+    match value {
+        FastFieldValue::U64(value) => value,
+        FastFieldValue::I64(value) => value.to_u64(),
+        FastFieldValue::F64(value) => value.to_u64(),
+    }
+}
+
+/// Returns the `u64` representation of the value of a numeric term.
+fn term_u64_value(term: &Term) -> Option<u64> {
+    let value_bytes: [u8; 8] = term.value_bytes().try_into().ok()?;
+    Some(u64::from_be_bytes(value_bytes))
+}
+
+/// Returns the bound in the `u64` representation of the values of a numeric field. The
+/// bounds on other fields are considered unbounded, which never prunes their range.
+fn term_bound_u64_value(bound: Bound<Term>) -> Bound<u64> {
+    match bound {
+        Bound::Included(term) => term_u64_value(&term).map_or(Bound::Unbounded, Bound::Included),
+        Bound::Excluded(term) => term_u64_value(&term).map_or(Bound::Unbounded, Bound::Excluded),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// Returns the names of the fast fields the query reads, which must be warmed up before
+/// executing it.
+pub(crate) fn query_fast_field_names(query: &dyn Query, searcher: &Searcher) -> HashSet<String> {
+    let mut fast_field_names = HashSet::new();
+    collect_fast_field_names(query, searcher, &mut fast_field_names);
+    fast_field_names
+}
+
+fn collect_fast_field_names(
+    query: &dyn Query,
+    searcher: &Searcher,
+    fast_field_names: &mut HashSet<String>,
+) {
+    if let Some(boolean_query) = query.downcast_ref::<BooleanQuery>() {
+        for (_, sub_query) in boolean_query.clauses() {
+            collect_fast_field_names(sub_query.as_ref(), searcher, fast_field_names);
+        }
+    } else if let Some(fast_field_range_query) = query.downcast_ref::<FastFieldRangeQuery>() {
+        let field_name = searcher
+            .schema()
+            .get_field_name(fast_field_range_query.field());
+        fast_field_names.insert(field_name.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::{IndexRecordOption, Schema, FAST, INDEXED, TEXT};
+    use tantivy::{doc, Index};
+
+    use super::*;
+
+    struct TestSplit {
+        index: Index,
+        body_field: Field,
+        timestamp_field: Field,
+    }
+
+    fn test_split() -> anyhow::Result<TestSplit> {
+        let mut schema_builder = Schema::builder();
+        let body_field = schema_builder.add_text_field("body", TEXT);
+        let timestamp_field = schema_builder.add_i64_field("timestamp", FAST | INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer = index.writer_with_num_threads(1, 10_000_000)?;
+        index_writer.add_document(doc!(body_field => "rare common", timestamp_field => 10i64));
+        index_writer.add_document(doc!(body_field => "common", timestamp_field => 20i64));
+        index_writer.add_document(doc!(body_field => "common", timestamp_field => 30i64));
+        index_writer.commit()?;
+        Ok(TestSplit {
+            index,
+            body_field,
+            timestamp_field,
+        })
+    }
+
+    fn term_query(field: Field, text: &str) -> Box<dyn Query> {
+        Box::new(TermQuery::new(
+            Term::from_field_text(field, text),
+            IndexRecordOption::Basic,
+        ))
+    }
+
+    fn clause_terms(query: &dyn Query) -> Vec<(Occur, Term)> {
+        query
+            .downcast_ref::<BooleanQuery>()
+            .unwrap()
+            .clauses()
+            .iter()
+            .map(|(occur, sub_query)| {
+                let term_query = sub_query.downcast_ref::<TermQuery>().unwrap();
+                (*occur, term_query.term().clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_query_orders_clauses_by_selectivity() -> anyhow::Result<()> {
+        let split = test_split()?;
+        let searcher = split.index.reader()?.searcher();
+        let query = BooleanQuery::new(vec![
+            (Occur::Should, term_query(split.body_field, "missing")),
+            (Occur::Must, term_query(split.body_field, "common")),
+            (Occur::MustNot, term_query(split.body_field, "absent")),
+            (Occur::Must, term_query(split.body_field, "rare")),
+        ]);
+        let planned_query = plan_query(&query, &searcher, None)?;
+        let rare_term = Term::from_field_text(split.body_field, "rare");
+        let common_term = Term::from_field_text(split.body_field, "common");
+        assert_eq!(
+            clause_terms(planned_query.as_ref()),
+            vec![(Occur::Must, rare_term), (Occur::Must, common_term)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_query_prunes_missing_required_terms() -> anyhow::Result<()> {
+        let split = test_split()?;
+        let searcher = split.index.reader()?.searcher();
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, term_query(split.body_field, "common")),
+            (Occur::Must, term_query(split.body_field, "missing")),
+        ]);
+        let planned_query = plan_query(&query, &searcher, None)?;
+        assert!(planned_query.downcast_ref::<EmptyQuery>().is_some());
+
+        let query = BooleanQuery::new(vec![
+            (Occur::Should, term_query(split.body_field, "missing")),
+            (Occur::MustNot, term_query(split.body_field, "common")),
+        ]);
+        let planned_query = plan_query(&query, &searcher, None)?;
+        assert!(planned_query.downcast_ref::<EmptyQuery>().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_query_prunes_ranges_with_split_stats() -> anyhow::Result<()> {
+        let split = test_split()?;
+        let searcher = split.index.reader()?.searcher();
+        let split_stats = SplitStats::compute(&searcher)?;
+        let out_of_split_range = RangeQuery::new_i64_bounds(
+            split.timestamp_field,
+            Bound::Excluded(30),
+            Bound::Unbounded,
+        );
+        let planned_query = plan_query(&out_of_split_range, &searcher, Some(&split_stats))?;
+        assert!(planned_query.downcast_ref::<EmptyQuery>().is_some());
+        // Without the split stats, the range cannot be pruned.
+        let planned_query = plan_query(&out_of_split_range, &searcher, None)?;
+        assert!(planned_query.downcast_ref::<RangeQuery>().is_some());
+
+        let overlapping_range = RangeQuery::new_i64_bounds(
+            split.timestamp_field,
+            Bound::Included(-5),
+            Bound::Included(10),
+        );
+        let planned_query = plan_query(&overlapping_range, &searcher, Some(&split_stats))?;
+        assert!(planned_query.downcast_ref::<RangeQuery>().is_some());
+        assert_eq!(
+            searcher.search(planned_query.as_ref(), &tantivy::collector::Count)?,
+            1
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_query_prunes_fast_field_ranges_with_split_stats() -> anyhow::Result<()> {
+        let split = test_split()?;
+        let searcher = split.index.reader()?.searcher();
+        let split_stats = SplitStats::compute(&searcher)?;
+        let out_of_split_range = FastFieldRangeQuery::new_i64_bounds(
+            split.timestamp_field,
+            Bound::Unbounded,
+            Bound::Excluded(10),
+        );
+        let planned_query = plan_query(&out_of_split_range, &searcher, Some(&split_stats))?;
+        assert!(planned_query.downcast_ref::<EmptyQuery>().is_some());
+
+        let overlapping_range = FastFieldRangeQuery::new_i64_bounds(
+            split.timestamp_field,
+            Bound::Included(15),
+            Bound::Unbounded,
+        );
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, term_query(split.body_field, "common")),
+            (Occur::Must, Box::new(overlapping_range)),
+        ]);
+        let planned_query = plan_query(&query, &searcher, Some(&split_stats))?;
+        assert_eq!(
+            searcher.search(planned_query.as_ref(), &tantivy::collector::Count)?,
+            2
+        );
+        let fast_field_names = query_fast_field_names(planned_query.as_ref(), &searcher);
+        assert_eq!(fast_field_names.len(), 1);
+        assert!(fast_field_names.contains("timestamp"));
+        Ok(())
+    }
+}
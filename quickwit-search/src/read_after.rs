@@ -0,0 +1,150 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::time::Duration;
+
+use quickwit_metastore::{retry_on_transient_error, Metastore, SplitState};
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::SearchError;
+
+/// Time during which a search waits for the splits published at or after its
+/// `min_publish_timestamp` to be visible.
+pub const MIN_PUBLISH_TIMESTAMP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Interval at which the view of the metastore is refreshed while waiting.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Waits until each of the indexes has a split published at or after
+/// `min_publish_timestamp`, refreshing the view of the metastore in the meantime.
+///
+/// This gives read-your-writes to the clients that just published splits, e.g. an indexing
+/// pipeline, as the metastore of the root may only see them later. Fails with
+/// [`SearchError::Unavailable`] if the splits are still not visible after `timeout`.
+pub(crate) async fn wait_for_published_splits<'a>(
+    index_ids: impl IntoIterator<Item = &'a str>,
+    metastore: &dyn Metastore,
+    min_publish_timestamp: i64,
+    timeout: Duration,
+) -> crate::Result<()> {
+    let deadline = Instant::now() + timeout;
+    for index_id in index_ids {
+        loop {
+            let split_metas = retry_on_transient_error(|| {
+                metastore.list_splits(index_id, SplitState::Published, None, &[])
+            })
+            .await?;
+            if split_metas.iter().any(|split_meta| {
+                split_meta.split_metadata.update_timestamp >= min_publish_timestamp
+            }) {
+                break;
+            }
+            if Instant::now() + REFRESH_INTERVAL > deadline {
+                return Err(SearchError::Unavailable(format!(
+                    "No split of index `{}` published at or after timestamp {} is visible after \
+                     {:?}.",
+                    index_id, min_publish_timestamp, timeout
+                )));
+            }
+            debug!(index_id = %index_id, "Waiting for the splits to be published.");
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+            metastore.refresh_index(index_id).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use quickwit_indexing::mock_split_meta;
+    use quickwit_metastore::MockMetastore;
+
+    use super::*;
+
+    fn mock_metastore(num_stale_listings: usize) -> MockMetastore {
+        let num_listings = Arc::new(AtomicUsize::new(0));
+        let mut metastore = MockMetastore::new();
+        metastore.expect_list_splits().returning(
+            move |_index_id: &str,
+                  _split_state: SplitState,
+                  _time_range: Option<Range<i64>>,
+                  _tags: &[String]| {
+                let mut split_meta = mock_split_meta("split1");
+                if num_listings.fetch_add(1, Ordering::SeqCst) >= num_stale_listings {
+                    split_meta.split_metadata.update_timestamp = 1_622_548_800;
+                }
+                Ok(vec![split_meta])
+            },
+        );
+        metastore
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_published_splits() -> anyhow::Result<()> {
+        let mut metastore = mock_metastore(2);
+        metastore
+            .expect_refresh_index()
+            .times(2)
+            .returning(|_index_id: &str| Ok(()));
+        wait_for_published_splits(
+            vec!["test-idx"],
+            &metastore,
+            1_622_548_800,
+            Duration::from_secs(5),
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_published_splits_already_visible() -> anyhow::Result<()> {
+        let mut metastore = mock_metastore(0);
+        metastore.expect_refresh_index().never();
+        wait_for_published_splits(
+            vec!["test-idx"],
+            &metastore,
+            1_622_548_800,
+            Duration::from_secs(5),
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_published_splits_timeout() {
+        let mut metastore = mock_metastore(usize::MAX);
+        metastore
+            .expect_refresh_index()
+            .returning(|_index_id: &str| Ok(()));
+        let search_error = wait_for_published_splits(
+            vec!["test-idx"],
+            &metastore,
+            1_622_548_800,
+            Duration::from_millis(500),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(search_error, SearchError::Unavailable(_)));
+    }
+}
@@ -0,0 +1,276 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use quickwit_common::RealtimeSplits;
+use quickwit_index_config::IndexConfig;
+use quickwit_proto::{
+    Hit, LeafSearchRealtimeRequest, LeafSearchRealtimeResponse, LeafSearchResponse, PartialHit,
+    SearchRequest,
+};
+use tantivy::collector::Collector;
+use tantivy::DocAddress;
+use tokio::task::spawn_blocking;
+use tracing::warn;
+
+use crate::cluster_client::ClusterClient;
+use crate::collector::{make_collector_for_split, make_merge_collector};
+use crate::terms_aggregation::prune_terms_buckets;
+use crate::SearchError;
+
+/// Maximum duration of the search of an indexer, after which its splits being indexed are
+/// skipped, so that an unresponsive indexer does not stall the root searches.
+const REALTIME_SEARCH_TIMEOUT: Duration = if cfg!(test) {
+    Duration::from_millis(100)
+} else {
+    Duration::from_secs(5)
+};
+
+/// Leaf search over the splits being indexed by this node.
+///
+/// The splits of `excluded_split_ids` are already published, so the root searches them
+/// like any other split. The hits are returned with their documents, which cannot be
+/// fetched later on, as the split being indexed changes until it is published.
+pub(crate) async fn leaf_search_realtime(
+    request: &SearchRequest,
+    excluded_split_ids: &[String],
+    index_config: Arc<dyn IndexConfig>,
+    realtime_splits: &RealtimeSplits,
+) -> crate::Result<LeafSearchRealtimeResponse> {
+    let searchers: Vec<_> = realtime_splits
+        .searchers(&request.index_id)
+        .into_iter()
+        .filter(|(split_id, _)| !excluded_split_ids.contains(split_id))
+        .collect();
+    let request = request.clone();
+    spawn_blocking(move || -> crate::Result<LeafSearchRealtimeResponse> {
+        let mut split_search_responses = Vec::with_capacity(searchers.len());
+        for (split_id, searcher) in &searchers {
+            let collector = make_collector_for_split(
+                split_id.clone(),
+                &*index_config,
+                &request,
+                &searcher.schema(),
+            );
+            let query = index_config.split_query(searcher, &request)?;
+            split_search_responses.push(searcher.search(&query, &collector)?);
+        }
+        let mut leaf_search_response =
+            make_merge_collector(&request).merge_fruits(split_search_responses)?;
+        if let Some(terms_aggregation) = &request.terms_aggregation {
+            let terms_buckets = std::mem::take(&mut leaf_search_response.terms_buckets);
+            leaf_search_response.terms_buckets =
+                prune_terms_buckets(terms_aggregation, terms_buckets)?;
+        }
+        let split_searchers: HashMap<&str, _> = searchers
+            .iter()
+            .map(|(split_id, searcher)| (split_id.as_str(), searcher))
+            .collect();
+        let mut hits = Vec::with_capacity(leaf_search_response.partial_hits.len());
+        for partial_hit in &leaf_search_response.partial_hits {
+            let searcher = split_searchers[partial_hit.split_id.as_str()];
+            let doc = searcher.doc(DocAddress {
+                segment_ord: partial_hit.segment_ord,
+                doc_id: partial_hit.doc_id,
+            })?;
+            hits.push(Hit {
+                json: searcher.schema().to_json(&doc),
+                partial_hit: Some(partial_hit.clone()),
+                address: String::new(),
+            });
+        }
+        Ok(LeafSearchRealtimeResponse {
+            leaf_search_response: Some(leaf_search_response),
+            hits,
+        })
+    })
+    .await?
+}
+
+/// Hits of the splits being indexed, returned along with the realtime leaf responses.
+#[derive(Default)]
+pub(crate) struct RealtimeHits {
+    split_ids: HashSet<String>,
+    hits: HashMap<(String, u32, u32), Hit>,
+}
+
+impl RealtimeHits {
+    /// Returns true if `split_id` is the ID of a split being indexed.
+    pub fn is_realtime_split(&self, split_id: &str) -> bool {
+        self.split_ids.contains(split_id)
+    }
+
+    /// Removes and returns the hit of `partial_hit`.
+    pub fn take(&mut self, partial_hit: &PartialHit) -> Option<Hit> {
+        self.hits.remove(&(
+            partial_hit.split_id.clone(),
+            partial_hit.segment_ord,
+            partial_hit.doc_id,
+        ))
+    }
+
+    /// Adds the hits of `other`, e.g. those of another index of a federated search.
+    pub fn extend(&mut self, other: RealtimeHits) {
+        self.split_ids.extend(other.split_ids);
+        self.hits.extend(other.hits);
+    }
+}
+
+/// Searches the splits being indexed by the realtime indexers of the cluster.
+///
+/// The indexers are searched on a best effort basis: the ones that fail or do not respond
+/// within [`REALTIME_SEARCH_TIMEOUT`] are skipped, which only delays the moment their documents
+/// become searchable. The splits of
+/// `published_split_ids` are searched by the root already, so the indexers skip them.
+pub(crate) async fn root_search_realtime(
+    search_request: &SearchRequest,
+    published_split_ids: Vec<String>,
+    cluster_client: &ClusterClient,
+) -> (Vec<LeafSearchResponse>, RealtimeHits) {
+    if cluster_client.realtime_clients().is_empty() {
+        return (Vec::new(), RealtimeHits::default());
+    }
+    let mut request_with_offset_0 = search_request.clone();
+    request_with_offset_0.start_offset = 0;
+    request_with_offset_0.max_hits += search_request.start_offset;
+    let realtime_request = LeafSearchRealtimeRequest {
+        search_request: Some(request_with_offset_0),
+        excluded_split_ids: published_split_ids,
+    };
+    let realtime_responses: Vec<_> =
+        futures::stream::iter(cluster_client.realtime_clients().iter().cloned())
+            .map(|mut client| {
+                let realtime_request = realtime_request.clone();
+                async move {
+                    let grpc_addr = client.grpc_addr();
+                    tokio::time::timeout(
+                        REALTIME_SEARCH_TIMEOUT,
+                        client.leaf_search_realtime(realtime_request),
+                    )
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(SearchError::Unavailable(format!(
+                            "The indexer did not respond within {:?}",
+                            REALTIME_SEARCH_TIMEOUT
+                        )))
+                    })
+                    .map_err(|error| (grpc_addr, error))
+                }
+            })
+            .buffer_unordered(crate::root::MAX_CONCURRENT_LEAF_TASKS)
+            .collect()
+            .await;
+    let mut leaf_search_responses = Vec::new();
+    let mut realtime_hits = RealtimeHits::default();
+    for realtime_response_res in realtime_responses {
+        let realtime_response = match realtime_response_res {
+            Ok(realtime_response) => realtime_response,
+            Err((grpc_addr, error)) => {
+                warn!(grpc_addr=?grpc_addr, error=?error, "Failed to search the splits being indexed.");
+                continue;
+            }
+        };
+        leaf_search_responses.extend(realtime_response.leaf_search_response);
+        for hit in realtime_response.hits {
+            if let Some(partial_hit) = &hit.partial_hit {
+                let hit_key = (
+                    partial_hit.split_id.clone(),
+                    partial_hit.segment_ord,
+                    partial_hit.doc_id,
+                );
+                realtime_hits.split_ids.insert(partial_hit.split_id.clone());
+                realtime_hits.hits.insert(hit_key, hit);
+            }
+        }
+    }
+    (leaf_search_responses, realtime_hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_index_config::WikipediaIndexConfig;
+    use tantivy::Index;
+
+    use super::*;
+    use crate::{create_search_service_client, SearchClientPool};
+
+    #[tokio::test]
+    async fn test_leaf_search_realtime() -> anyhow::Result<()> {
+        let index_config: Arc<dyn IndexConfig> = Arc::new(WikipediaIndexConfig::new());
+        let index = Index::create_in_ram(index_config.schema());
+        let mut index_writer = index.writer_with_num_threads(1, 3_000_000)?;
+        index_writer.add_document(index_config.doc_from_json(
+            r#"{"title": "snoopy", "body": "snoopy is a dog", "url": "http://snoopy"}"#,
+        )?);
+        index_writer.add_document(index_config.doc_from_json(
+            r#"{"title": "garfield", "body": "garfield is a cat", "url": "http://garfield"}"#,
+        )?);
+        index_writer.commit()?;
+        let realtime_splits = RealtimeSplits::default();
+        realtime_splits.update("test-idx", "split1", index.reader()?.searcher());
+        realtime_splits.update("test-idx", "split2", index.reader()?.searcher());
+        let search_request = SearchRequest {
+            index_id: "test-idx".to_string(),
+            query: "snoopy".to_string(),
+            search_fields: vec!["body".to_string()],
+            max_hits: 10,
+            ..Default::default()
+        };
+
+        let realtime_response = leaf_search_realtime(
+            &search_request,
+            &["split2".to_string()],
+            index_config,
+            &realtime_splits,
+        )
+        .await?;
+        let leaf_search_response = realtime_response.leaf_search_response.unwrap();
+        assert_eq!(leaf_search_response.num_hits, 1);
+        assert_eq!(leaf_search_response.partial_hits[0].split_id, "split1");
+        assert_eq!(realtime_response.hits.len(), 1);
+        assert!(realtime_response.hits[0].json.contains("snoopy is a dog"));
+        assert!(realtime_response.hits[0].address.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_root_search_realtime_skips_unresponsive_indexers() -> anyhow::Result<()> {
+        // The listener accepts the connections but never answers the requests.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let realtime_client = create_search_service_client(listener.local_addr()?).await?;
+        let client_pool = Arc::new(SearchClientPool::from_mocks(Vec::new()).await?);
+        let cluster_client =
+            ClusterClient::new(client_pool).with_realtime_clients(vec![realtime_client]);
+        let search_request = SearchRequest {
+            index_id: "test-idx".to_string(),
+            query: "snoopy".to_string(),
+            max_hits: 10,
+            ..Default::default()
+        };
+        let (leaf_search_responses, realtime_hits) =
+            root_search_realtime(&search_request, Vec::new(), &cluster_client).await;
+        assert!(leaf_search_responses.is_empty());
+        assert!(!realtime_hits.is_realtime_split("split1"));
+        Ok(())
+    }
+}
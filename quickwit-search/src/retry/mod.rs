@@ -56,7 +56,10 @@ where Request: RequestOnSplit + Clone
 
 // Select a new client from the client pool by the following oversimplified policy:
 // 1. Take the first split_id of the request
-// 2. Ask for a relevant client for that split while excluding the failing client.
+// 2. Ask for a relevant client for that split while excluding the nodes of the failure domain of
+//    the failing client, in case the whole domain is down.
+// 3. If only nodes of that failure domain can search the split, ask again while only excluding the
+//    failing client.
 pub async fn retry_client<Request>(
     client_pool: &Arc<SearchClientPool>,
     failing_client: &SearchServiceClient,
@@ -76,7 +79,23 @@ where
         split_id: split_ids[0].clone(),
         cost: 0,
     };
-    client_pool.assign_job(job, &exclude_addresses).await
+    let failure_domain_addresses = client_pool.failure_domain_addresses(failing_client).await;
+    if failure_domain_addresses.len() > 1 {
+        let client = client_pool
+            .assign_job(
+                retry_request.index_id(),
+                job.clone(),
+                &failure_domain_addresses,
+            )
+            .await?;
+        // The exclusion is ignored when it excludes all the clients.
+        if !failure_domain_addresses.contains(&client.grpc_addr()) {
+            return Ok(client);
+        }
+    }
+    client_pool
+        .assign_job(retry_request.index_id(), job, &exclude_addresses)
+        .await
 }
 
 /// Split based request. This includes:
@@ -85,6 +104,8 @@ where
 /// - LeafSearchStreamRequest
 pub trait RequestOnSplit {
     fn split_ids(&self) -> Vec<String>;
+
+    fn index_id(&self) -> &str;
 }
 
 impl RequestOnSplit for FetchDocsRequest {
@@ -94,6 +115,10 @@ impl RequestOnSplit for FetchDocsRequest {
             .map(|split| split.split_id.clone())
             .collect_vec()
     }
+
+    fn index_id(&self) -> &str {
+        &self.index_id
+    }
 }
 
 impl RequestOnSplit for LeafSearchRequest {
@@ -103,6 +128,13 @@ impl RequestOnSplit for LeafSearchRequest {
             .map(|split| split.split_id.clone())
             .collect_vec()
     }
+
+    fn index_id(&self) -> &str {
+        self.search_request
+            .as_ref()
+            .map(|search_request| search_request.index_id.as_str())
+            .unwrap_or_default()
+    }
 }
 
 impl RequestOnSplit for LeafSearchStreamRequest {
@@ -112,6 +144,13 @@ impl RequestOnSplit for LeafSearchStreamRequest {
             .map(|split| split.split_id.clone())
             .collect_vec()
     }
+
+    fn index_id(&self) -> &str {
+        self.request
+            .as_ref()
+            .map(|request| request.index_id.as_str())
+            .unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
@@ -122,7 +161,10 @@ mod tests {
     use quickwit_proto::{FetchDocsRequest, FetchDocsResponse, SplitIdAndFooterOffsets};
 
     use crate::retry::{retry_client, DefaultRetryPolicy, RetryPolicy};
-    use crate::{MockSearchService, SearchClientPool, SearchError};
+    use crate::{
+        MockSearchService, NodeAttributes, SearchClientPool, SearchError,
+        FAILURE_DOMAIN_ATTRIBUTE_KEY,
+    };
 
     fn mock_doc_request() -> FetchDocsRequest {
         FetchDocsRequest {
@@ -132,8 +174,16 @@ mod tests {
             split_metadata: vec![SplitIdAndFooterOffsets {
                 split_id: "split_1".to_string(),
                 split_footer_end: 100,
+                time_range_end: None,
+                split_file: String::new(),
                 split_footer_start: 0,
+                split_part_num_bytes: None,
             }],
+            index_config_version: 0,
+            mirror_index_uri: String::new(),
+            index_config_fingerprint: 0,
+            flatten_docs: false,
+            cache_policy: 0,
         }
     }
 
@@ -180,4 +230,39 @@ mod tests {
         assert_eq!(client_for_retry.grpc_addr().to_string(), "127.0.0.1:10010");
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_retry_client_should_prefer_another_failure_domain() -> anyhow::Result<()> {
+        let request = mock_doc_request();
+        let client_pool = Arc::new(
+            SearchClientPool::from_mocks(vec![
+                Arc::new(MockSearchService::new()),
+                Arc::new(MockSearchService::new()),
+                Arc::new(MockSearchService::new()),
+            ])
+            .await?,
+        );
+        let client_hashmap = client_pool.clients.read().await;
+        let zone_attributes = |zone: &str| -> NodeAttributes {
+            vec![(FAILURE_DOMAIN_ATTRIBUTE_KEY.to_string(), zone.to_string())]
+                .into_iter()
+                .collect()
+        };
+        let failing_grpc_addr: SocketAddr = "127.0.0.1:10000".parse()?;
+        let same_zone_grpc_addr: SocketAddr = "127.0.0.1:10010".parse()?;
+        let other_zone_grpc_addr: SocketAddr = "127.0.0.1:10020".parse()?;
+        client_hashmap[&failing_grpc_addr].set_node_attributes(zone_attributes("zone-a"));
+        client_hashmap[&same_zone_grpc_addr].set_node_attributes(zone_attributes("zone-a"));
+        client_hashmap[&other_zone_grpc_addr].set_node_attributes(zone_attributes("zone-b"));
+        let failing_client = &client_hashmap[&failing_grpc_addr];
+
+        let client_for_retry = retry_client(&client_pool, failing_client, &request).await?;
+        assert_eq!(client_for_retry.grpc_addr(), other_zone_grpc_addr);
+
+        // Falls back on the other nodes of the failure domain of the failing client.
+        client_hashmap[&other_zone_grpc_addr].set_node_attributes(zone_attributes("zone-a"));
+        let client_for_retry = retry_client(&client_pool, failing_client, &request).await?;
+        assert_ne!(client_for_retry.grpc_addr(), failing_grpc_addr);
+        Ok(())
+    }
 }
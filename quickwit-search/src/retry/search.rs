@@ -75,19 +75,45 @@ mod tests {
                 max_hits: 10,
                 start_offset: 0,
                 tags: vec![],
+                distinct_count_fields: vec![],
+                percentile_fields: vec![],
+                percentiles: vec![],
+                terms_aggregation: None,
+                date_histogram: None,
+                time_zone: String::new(),
+                geo_filter: None,
+                mandatory_filter: None,
+                flatten_hits: false,
+                sort_by_field: None,
+                sort_ascending: false,
+                disable_synonyms: false,
+                session_token: None,
+                min_publish_timestamp: None,
+                cache_policy: 0,
+                max_num_splits: None,
+                hit_sampling: None,
             }),
-            index_config: "index_config".to_string(),
+            index_config: String::new(),
             index_uri: "uri".to_string(),
+            index_config_version: 0,
+            mirror_index_uri: String::new(),
+            index_config_fingerprint: 0,
             split_metadata: vec![
                 SplitIdAndFooterOffsets {
                     split_id: "split_1".to_string(),
                     split_footer_end: 100,
+                    time_range_end: None,
+                    split_file: String::new(),
                     split_footer_start: 0,
+                    split_part_num_bytes: None,
                 },
                 SplitIdAndFooterOffsets {
                     split_id: "split_2".to_string(),
                     split_footer_end: 100,
+                    time_range_end: None,
+                    split_file: String::new(),
                     split_footer_start: 0,
+                    split_part_num_bytes: None,
                 },
             ],
         }
@@ -116,6 +142,10 @@ mod tests {
             partial_hits: vec![],
             failed_splits: vec![],
             num_attempted_splits: 1,
+            distinct_count_sketches: vec![],
+            percentile_sketches: vec![],
+            terms_buckets: vec![],
+            date_histogram_buckets: vec![],
         };
         let result = Result::<LeafSearchResponse, SearchError>::Ok(leaf_response);
         let retry = retry_policy
@@ -141,6 +171,10 @@ mod tests {
             partial_hits: vec![],
             failed_splits: vec![split_error],
             num_attempted_splits: 1,
+            distinct_count_sketches: vec![],
+            percentile_sketches: vec![],
+            terms_buckets: vec![],
+            date_histogram_buckets: vec![],
         };
         let result = Result::<LeafSearchResponse, SearchError>::Ok(leaf_response);
         let retry_request = retry_policy.retry_request(&request, result.as_ref());
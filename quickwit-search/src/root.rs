@@ -18,35 +18,72 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
 use std::sync::Arc;
 
-use futures::{StreamExt, TryStreamExt};
+use futures::{StreamExt, TryFutureExt, TryStreamExt};
 use itertools::Itertools;
-use quickwit_metastore::{Metastore, SplitMetadata, SplitMetadataAndFooterOffsets};
+use quickwit_metastore::{
+    retry_on_transient_error, Metastore, SplitMetadata, SplitMetadataAndFooterOffsets, SplitState,
+};
 use quickwit_proto::{
-    FetchDocsRequest, FetchDocsResponse, LeafSearchRequest, LeafSearchResponse, PartialHit,
-    SearchRequest, SearchResponse,
+    CachePolicy, FetchDocsRequest, FetchDocsResponse, Hit, LeafSearchRequest, LeafSearchResponse,
+    PartialHit, QueryWarning, SearchRequest, SearchResponse, SplitSearchError,
 };
 use tantivy::collector::Collector;
 use tantivy::TantivyError;
 use tokio::task::spawn_blocking;
-use tracing::{debug, error, instrument};
+use tracing::{debug, error, instrument, warn};
 
 use crate::client_pool::Job;
 use crate::cluster_client::ClusterClient;
-use crate::collector::make_merge_collector;
+use crate::collector::{make_merge_collector, validate_sort_by_field};
+use crate::date_histogram::{build_date_histogram_result, validate_date_histogram};
+use crate::distinct_count::{estimate_distinct_counts, validate_distinct_count_fields};
+use crate::federation::{
+    parse_index_ids, resolve_index_targets, validate_federated_fields, IndexTarget,
+};
+use crate::fetch_docs::flatten_hit_json;
+use crate::geo_filter::validate_geo_filter;
+use crate::hit_address::{set_hit_addresses, HitAddress};
+use crate::hit_sampling::validate_hit_sampling;
+use crate::percentiles::{estimate_percentiles, validate_percentile_request};
+use crate::read_after::{wait_for_published_splits, MIN_PUBLISH_TIMESTAMP_TIMEOUT};
+use crate::realtime::{root_search_realtime, RealtimeHits};
+use crate::search_session::{list_snapshot_splits, SearchSession};
+use crate::split_limit::{plan_split_chunks, resolve_split_limit};
+use crate::standby::spawn_shadow_leaf_searches;
+use crate::terms_aggregation::{build_terms_aggregation_result, validate_terms_aggregation};
+use crate::tree_aggregation::{merge_leaf_responses, scatter_gather_leaf_searches};
 use crate::{
-    extract_split_and_footer_offsets, list_relevant_splits, ClientPool, SearchClientPool,
-    SearchError,
+    extract_split_and_footer_offsets, extract_time_range, lease_search_splits, ClientPool,
+    SearchClientPool, SearchError, SearchServiceClient,
 };
 
 pub const MAX_CONCURRENT_LEAF_TASKS: usize = if cfg!(test) { 2 } else { 10 };
 
 /// Performs a distributed search.
-/// 1. Sends leaf request over gRPC to multiple leaf nodes.
-/// 2. Merges the search results.
+/// 1. Sends leaf request over gRPC to multiple leaf nodes, and shadow copies of them to the standby
+///    nodes. On large clusters, the leaf responses are first merged on intermediate nodes.
+/// 2. Merges the search results, along with the ones of the splits being indexed by the realtime
+///    indexers.
 /// 3. Sends fetch docs requests to multiple leaf nodes.
 /// 4. Builds the response with docs and returns.
+///
+/// The `index_id` of the request may list several indexes separated by commas, e.g.
+/// `logs-app,logs-infra`: their splits are searched together, each with its own index config,
+/// and their results are merged.
+///
+/// The quarantined splits are skipped, and reported in the response. A split failing
+/// repeatedly while the other splits succeed is quarantined, see
+/// [`ClusterClient::with_split_quarantine_threshold`].
+///
+/// The response comes with a session token: when given the token, the following searches,
+/// e.g. for the next pages of the hits, run on the same splits, ignoring the splits
+/// published since and the hits of the splits being indexed.
+///
+/// If the request has a `min_publish_timestamp`, the search first waits for the splits
+/// published since then to be visible.
 #[instrument(skip(search_request, cluster_client, client_pool, metastore))]
 pub async fn root_search(
     search_request: &SearchRequest,
@@ -55,32 +92,144 @@ pub async fn root_search(
     client_pool: &Arc<SearchClientPool>,
 ) -> Result<SearchResponse, SearchError> {
     let start_instant = tokio::time::Instant::now();
-    let index_metadata = metastore.index_metadata(&search_request.index_id).await?;
-    let index_config_str = serde_json::to_string(&index_metadata.index_config)
-        .map_err(|error| SearchError::InternalError(error.to_string()))?;
-    let split_metadata_list = list_relevant_splits(search_request, metastore).await?;
-    let split_metadata_map: HashMap<String, SplitMetadataAndFooterOffsets> = split_metadata_list
-        .into_iter()
-        .map(|metadata| (metadata.split_metadata.split_id.clone(), metadata))
-        .collect();
-    let jobs: Vec<Job> = job_for_splits(&split_metadata_map.keys().collect(), &split_metadata_map);
-    let assigned_leaf_search_jobs = client_pool.assign_jobs(jobs, &HashSet::default()).await?;
-    debug!(assigned_leaf_search_jobs=?assigned_leaf_search_jobs, "Assigned leaf search jobs.");
-    let leaf_search_responses: Vec<LeafSearchResponse> =
-        futures::stream::iter(assigned_leaf_search_jobs.into_iter())
-            .map(|(client, client_jobs)| {
-                let leaf_request = jobs_to_leaf_request(
-                    search_request,
-                    &index_config_str,
-                    &index_metadata.index_uri,
-                    &split_metadata_map,
-                    &client_jobs,
-                );
-                cluster_client.leaf_search((leaf_request, client))
-            })
-            .buffer_unordered(MAX_CONCURRENT_LEAF_TASKS)
-            .try_collect()
+    let index_targets = resolve_index_targets(search_request, metastore).await?;
+    validate_federated_fields(search_request, &index_targets)?;
+    if let Some(min_publish_timestamp) = search_request.min_publish_timestamp {
+        wait_for_published_splits(
+            index_targets
+                .iter()
+                .map(|index_target| index_target.search_request.index_id.as_str()),
+            metastore,
+            min_publish_timestamp,
+            MIN_PUBLISH_TIMESTAMP_TIMEOUT,
+        )
+        .await?;
+    }
+    let mut warnings = Vec::new();
+    for index_target in &index_targets {
+        warnings.extend(validate_index_request(index_target)?);
+    }
+    let index_schema = index_targets[0].index_metadata.index_config.schema();
+    let search_session_opt = search_request
+        .session_token
+        .as_deref()
+        .map(|session_token| {
+            session_token
+                .parse::<SearchSession>()
+                .map_err(|error| SearchError::InvalidQuery(error.to_string()))
+        })
+        .transpose()?;
+    // The snapshot is taken with the metastore clock, which assigns the update timestamps of
+    // the splits.
+    let snapshot_timestamp = match search_session_opt {
+        Some(search_session) => search_session.snapshot_timestamp,
+        None => retry_on_transient_error(|| metastore.now_timestamp()).await?,
+    };
+
+    let mut split_metadata_maps: Vec<HashMap<String, SplitMetadataAndFooterOffsets>> =
+        Vec::with_capacity(index_targets.len());
+    let mut quarantined_split_ids = Vec::new();
+    for index_target in &index_targets {
+        quarantined_split_ids
+            .extend(list_quarantined_split_ids(&index_target.search_request, metastore).await?);
+        let split_metadata_list =
+            list_snapshot_splits(&index_target.search_request, metastore, snapshot_timestamp)
+                .await?;
+        split_metadata_maps.push(
+            split_metadata_list
+                .into_iter()
+                .map(|metadata| (metadata.split_metadata.split_id.clone(), metadata))
+                .collect(),
+        );
+    }
+    let search_session = SearchSession::new(
+        snapshot_timestamp,
+        index_targets.iter().zip(&split_metadata_maps).flat_map(
+            |(index_target, split_metadata_map)| {
+                let index_id = index_target.search_request.index_id.as_str();
+                split_metadata_map
+                    .keys()
+                    .map(move |split_id| (index_id, split_id.as_str()))
+            },
+        ),
+    );
+    if let Some(expected_search_session) = search_session_opt {
+        if search_session != expected_search_session {
+            return Err(SearchError::InvalidQuery(
+                "The search session expired as some of its splits were deleted, start a new \
+                 session."
+                    .to_string(),
+            ));
+        }
+    }
+    let split_limit_opt = resolve_split_limit(
+        search_request,
+        index_targets.iter().map(|index_target| {
+            index_target
+                .index_metadata
+                .index_config
+                .split_limit_config()
+        }),
+    );
+    let split_chunks = plan_split_chunks(&split_metadata_maps, split_limit_opt)?;
+    let split_lease = lease_search_splits(
+        metastore,
+        index_targets.iter().zip(&split_metadata_maps).flat_map(
+            |(index_target, split_metadata_map)| {
+                split_metadata_map.keys().map(move |split_id| {
+                    (
+                        index_target.search_request.index_id.as_str(),
+                        split_id.as_str(),
+                    )
+                })
+            },
+        ),
+    )
+    .await;
+
+    let mut realtime_leaf_search_responses = Vec::new();
+    let mut realtime_hits = RealtimeHits::default();
+    // The splits being indexed are not part of the search sessions.
+    if search_session_opt.is_none() {
+        for (index_target, split_metadata_map) in index_targets.iter().zip(&split_metadata_maps) {
+            let (target_leaf_search_responses, target_realtime_hits) = root_search_realtime(
+                &index_target.search_request,
+                split_metadata_map.keys().cloned().collect(),
+                cluster_client,
+            )
+            .await;
+            realtime_leaf_search_responses.extend(target_leaf_search_responses);
+            realtime_hits.extend(target_realtime_hits);
+        }
+    }
+    let mut request_with_offset_0 = search_request.clone();
+    request_with_offset_0.start_offset = 0;
+    request_with_offset_0.max_hits += search_request.start_offset;
+    // The chunks of splits of the queries exceeding the split limit are searched one after the
+    // other. The responses of each chunk are merged right away, keeping only the top hits.
+    let mut leaf_search_responses: Vec<LeafSearchResponse> = Vec::new();
+    for split_chunk in &split_chunks {
+        let leaf_requests = assign_leaf_requests(
+            &index_targets,
+            &split_metadata_maps,
+            split_chunk,
+            client_pool,
+        )
+        .await?;
+        leaf_search_responses.extend(
+            scatter_gather_leaf_searches(&request_with_offset_0, leaf_requests, cluster_client)
+                .await?,
+        );
+        if split_chunks.len() > 1 {
+            let chunk_leaf_search_response = merge_leaf_responses(
+                &request_with_offset_0,
+                std::mem::take(&mut leaf_search_responses),
+            )
             .await?;
+            leaf_search_responses.push(chunk_leaf_search_response);
+        }
+    }
+    leaf_search_responses.extend(realtime_leaf_search_responses);
 
     let merge_collector = make_merge_collector(search_request);
     let leaf_search_response =
@@ -91,54 +240,103 @@ pub async fn root_search(
             })?;
     debug!(leaf_search_response = ?leaf_search_response, "Merged leaf search response.");
 
-    if !leaf_search_response.failed_splits.is_empty() {
+    // The search succeeds if the only splits that failed were just quarantined: the following
+    // searches skip them the same way.
+    let newly_quarantined_split_ids = quarantine_failing_splits(
+        &index_targets,
+        &split_metadata_maps,
+        &leaf_search_response.failed_splits,
+        metastore,
+        cluster_client,
+    )
+    .await;
+    if leaf_search_response
+        .failed_splits
+        .iter()
+        .any(|failed_split| !newly_quarantined_split_ids.contains(&failed_split.split_id))
+    {
         error!(failed_splits = ?leaf_search_response.failed_splits, "Leaf search response contains at least one failed split.");
         return Err(SearchError::InternalError(format!(
             "{:?}",
             leaf_search_response.failed_splits
         )));
     }
+    quarantined_split_ids.extend(newly_quarantined_split_ids);
 
-    // Create a hash map of PartialHit with split as a key.
-    let mut partial_hits_map: HashMap<String, Vec<PartialHit>> = HashMap::new();
+    // Create a hash map of PartialHit with split as a key, for each index. The hits of the
+    // splits being indexed come with their documents already.
+    let mut partial_hits_maps: Vec<HashMap<String, Vec<PartialHit>>> =
+        vec![HashMap::new(); index_targets.len()];
+    let mut fetched_realtime_hits = Vec::new();
     for partial_hit in leaf_search_response.partial_hits.iter() {
-        partial_hits_map
+        if realtime_hits.is_realtime_split(&partial_hit.split_id) {
+            if let Some(mut realtime_hit) = realtime_hits.take(partial_hit) {
+                if search_request.flatten_hits {
+                    realtime_hit.json = flatten_hit_json(&realtime_hit.json)?;
+                }
+                fetched_realtime_hits.push(realtime_hit);
+            }
+            continue;
+        }
+        let target_ord = split_metadata_maps
+            .iter()
+            .position(|split_metadata_map| split_metadata_map.contains_key(&partial_hit.split_id))
+            .ok_or_else(|| {
+                SearchError::InternalError(format!(
+                    "Leaf search returned a hit of the unknown split `{}`.",
+                    partial_hit.split_id
+                ))
+            })?;
+        partial_hits_maps[target_ord]
             .entry(partial_hit.split_id.clone())
             .or_insert_with(Vec::new)
             .push(partial_hit.clone());
     }
 
-    let fetch_docs_req_jobs = partial_hits_map
-        .keys()
-        .map(|split_id| Job {
-            split_id: split_id.clone(),
-            cost: 1,
+    let mut doc_requests: Vec<(usize, FetchDocsRequest, SearchServiceClient)> = Vec::new();
+    for (target_ord, partial_hits_map) in partial_hits_maps.iter_mut().enumerate() {
+        let index_target = &index_targets[target_ord];
+        let assigned_doc_fetch_jobs = assign_fetch_docs_jobs(
+            client_pool,
+            &index_target.search_request.index_id,
+            partial_hits_map,
+        )
+        .await?;
+        for (client, client_jobs) in assigned_doc_fetch_jobs {
+            let doc_request = jobs_to_fetch_docs_request(
+                index_target,
+                &split_metadata_maps[target_ord],
+                partial_hits_map,
+                &client_jobs,
+            );
+            doc_requests.push((target_ord, doc_request, client));
+        }
+    }
+    let fetch_docs_responses: Vec<(usize, FetchDocsResponse)> = futures::stream::iter(doc_requests)
+        .map(|(target_ord, doc_request, client)| {
+            cluster_client
+                .fetch_docs((doc_request, client))
+                .map_ok(move |fetch_docs_response| (target_ord, fetch_docs_response))
         })
-        .collect_vec();
-    let assigned_doc_fetch_jobs = client_pool
-        .assign_jobs(fetch_docs_req_jobs, &HashSet::new())
+        .buffer_unordered(MAX_CONCURRENT_LEAF_TASKS)
+        .try_collect()
         .await?;
-    let fetch_docs_responses: Vec<FetchDocsResponse> =
-        futures::stream::iter(assigned_doc_fetch_jobs.into_iter())
-            .map(|(client, client_jobs)| {
-                let doc_request = jobs_to_fetch_docs_request(
-                    &search_request.index_id,
-                    &index_metadata.index_uri,
-                    &split_metadata_map,
-                    &mut partial_hits_map,
-                    &client_jobs,
-                );
-                cluster_client.fetch_docs((doc_request, client))
-            })
-            .buffer_unordered(MAX_CONCURRENT_LEAF_TASKS)
-            .try_collect()
-            .await?;
+    split_lease.release(metastore).await;
 
-    // Merge the fetched docs.
-    let hits = fetch_docs_responses
-        .iter()
-        .map(|response| response.hits.clone())
-        .flatten()
+    // Merge the fetched docs. Their addresses refer to the index config version of their
+    // index.
+    let mut fetched_hits = Vec::new();
+    for (target_ord, fetch_docs_response) in fetch_docs_responses {
+        let index_metadata = &index_targets[target_ord].index_metadata;
+        let mut hits = fetch_docs_response.hits;
+        set_hit_addresses(&mut hits, index_metadata.index_config_version());
+        fetched_hits.extend(hits);
+    }
+    // The hits of the splits being indexed have no address, as their documents move once the
+    // split is published.
+    let hits = fetched_hits
+        .into_iter()
+        .chain(fetched_realtime_hits)
         .sorted_by(|hit1, hit2| {
             let value1 = if let Some(partial_hit) = &hit1.partial_hit {
                 partial_hit.sorting_field_value
@@ -155,6 +353,34 @@ pub async fn root_search(
         })
         .collect_vec();
 
+    let distinct_counts = estimate_distinct_counts(
+        &search_request.distinct_count_fields,
+        &leaf_search_response.distinct_count_sketches,
+    )?;
+    let percentiles = estimate_percentiles(
+        &search_request.percentiles,
+        &leaf_search_response.percentile_sketches,
+    )?;
+    let terms_aggregation = search_request
+        .terms_aggregation
+        .as_ref()
+        .map(|terms_aggregation| {
+            build_terms_aggregation_result(
+                terms_aggregation,
+                &leaf_search_response.terms_buckets,
+                &index_schema,
+            )
+        })
+        .transpose()?;
+    let date_histogram = if search_request.date_histogram.is_some() {
+        build_date_histogram_result(
+            &leaf_search_response.date_histogram_buckets,
+            &search_request.time_zone,
+        )?
+    } else {
+        Vec::new()
+    };
+
     let elapsed = start_instant.elapsed();
 
     Ok(SearchResponse {
@@ -162,7 +388,230 @@ pub async fn root_search(
         hits,
         elapsed_time_micros: elapsed.as_micros() as u64,
         errors: vec![],
+        distinct_counts,
+        percentiles,
+        terms_aggregation,
+        date_histogram,
+        warnings,
+        session_token: search_session.to_string(),
+        quarantined_split_ids,
+    })
+}
+
+/// Assigns the leaf search jobs of the splits `split_ids`, one set per index, to the leaf
+/// nodes, and sends shadow copies of them to the standby nodes.
+async fn assign_leaf_requests(
+    index_targets: &[IndexTarget],
+    split_metadata_maps: &[HashMap<String, SplitMetadataAndFooterOffsets>],
+    split_ids: &[HashSet<&String>],
+    client_pool: &Arc<SearchClientPool>,
+) -> crate::Result<Vec<(LeafSearchRequest, SearchServiceClient)>> {
+    let mut leaf_requests: Vec<(LeafSearchRequest, SearchServiceClient)> = Vec::new();
+    for ((index_target, split_metadata_map), target_split_ids) in
+        index_targets.iter().zip(split_metadata_maps).zip(split_ids)
+    {
+        let index_id = &index_target.search_request.index_id;
+        let jobs: Vec<Job> = job_for_splits(target_split_ids, split_metadata_map);
+        let shadow_leaf_search_jobs = client_pool.assign_shadow_jobs(index_id, jobs.clone()).await;
+        let assigned_leaf_search_jobs = client_pool
+            .assign_jobs(index_id, jobs, &HashSet::default())
+            .await?;
+        debug!(index_id=%index_id, assigned_leaf_search_jobs=?assigned_leaf_search_jobs, "Assigned leaf search jobs.");
+        spawn_shadow_leaf_searches(
+            shadow_leaf_search_jobs
+                .into_iter()
+                .map(|(client, client_jobs)| {
+                    let leaf_request =
+                        jobs_to_leaf_request(index_target, split_metadata_map, &client_jobs);
+                    (client, leaf_request)
+                })
+                .collect(),
+        );
+        leaf_requests.extend(
+            assigned_leaf_search_jobs
+                .into_iter()
+                .map(|(client, client_jobs)| {
+                    let leaf_request =
+                        jobs_to_leaf_request(index_target, split_metadata_map, &client_jobs);
+                    (leaf_request, client)
+                }),
+        );
+    }
+    Ok(leaf_requests)
+}
+
+/// Returns the IDs of the quarantined splits the search would search otherwise.
+async fn list_quarantined_split_ids(
+    search_request: &SearchRequest,
+    metastore: &dyn Metastore,
+) -> crate::Result<Vec<String>> {
+    let time_range_opt = extract_time_range(search_request);
+    let quarantined_splits = retry_on_transient_error(|| {
+        metastore.list_splits(
+            &search_request.index_id,
+            SplitState::Quarantined,
+            time_range_opt.clone(),
+            &search_request.tags,
+        )
     })
+    .await?;
+    Ok(quarantined_splits
+        .into_iter()
+        .map(|split| split.split_metadata.split_id)
+        .collect())
+}
+
+/// Records the failed splits of a search, and quarantines the splits failing repeatedly, see
+/// [`ClusterClient::with_split_quarantine_threshold`].
+/// Returns the IDs of the splits quarantined.
+async fn quarantine_failing_splits(
+    index_targets: &[IndexTarget],
+    split_metadata_maps: &[HashMap<String, SplitMetadataAndFooterOffsets>],
+    failed_splits: &[SplitSearchError],
+    metastore: &dyn Metastore,
+    cluster_client: &ClusterClient,
+) -> Vec<String> {
+    let mut quarantined_split_ids = Vec::new();
+    for (index_target, split_metadata_map) in index_targets.iter().zip(split_metadata_maps) {
+        let index_id = &index_target.search_request.index_id;
+        let index_failed_splits: Vec<SplitSearchError> = failed_splits
+            .iter()
+            .filter(|failed_split| split_metadata_map.contains_key(&failed_split.split_id))
+            .cloned()
+            .collect();
+        let split_ids_to_quarantine = cluster_client.split_failure_tracker().record_search(
+            split_metadata_map.keys().map(String::as_str),
+            &index_failed_splits,
+        );
+        if split_ids_to_quarantine.is_empty() {
+            continue;
+        }
+        warn!(
+            index_id = %index_id,
+            split_ids = ?split_ids_to_quarantine,
+            "Quarantining splits failing repeatedly."
+        );
+        let split_ids: Vec<&str> = split_ids_to_quarantine.iter().map(String::as_str).collect();
+        if let Err(error) = metastore.quarantine_splits(index_id, &split_ids).await {
+            error!(index_id = %index_id, error = ?error, "Failed to quarantine splits.");
+            continue;
+        }
+        quarantined_split_ids.extend(split_ids_to_quarantine);
+    }
+    quarantined_split_ids
+}
+
+/// Validates the request of one of the indexes of a search, and returns its warnings.
+pub(crate) fn validate_index_request(
+    index_target: &IndexTarget,
+) -> crate::Result<Vec<QueryWarning>> {
+    let search_request = &index_target.search_request;
+    let index_config = &index_target.index_metadata.index_config;
+    let index_schema = index_config.schema();
+    let warnings = index_config.query_warnings(search_request)?;
+    validate_distinct_count_fields(&search_request.distinct_count_fields, &index_schema)?;
+    validate_sort_by_field(search_request.sort_by_field.as_deref(), &index_schema)?;
+    validate_percentile_request(
+        &search_request.percentile_fields,
+        &search_request.percentiles,
+        &index_schema,
+    )?;
+    validate_terms_aggregation(search_request.terms_aggregation.as_ref(), &index_schema)?;
+    validate_date_histogram(
+        search_request.date_histogram.as_ref(),
+        &search_request.time_zone,
+        &index_schema,
+    )?;
+    validate_geo_filter(search_request.geo_filter.as_ref(), &**index_config)?;
+    validate_hit_sampling(search_request, &**index_config)?;
+    Ok(warnings)
+}
+
+/// Fetches the document of a hit address returned by a previous search.
+///
+/// The document is rendered with the index config version of the address, so that it is
+/// identical to the hit of the search, as long as its split is published. If `index_id` lists
+/// the indexes of a federated search, the document is fetched from the one owning its split.
+#[instrument(skip(cluster_client, client_pool, metastore))]
+pub async fn root_fetch_doc(
+    index_id: &str,
+    hit_address: &HitAddress,
+    metastore: &dyn Metastore,
+    cluster_client: &ClusterClient,
+    client_pool: &Arc<SearchClientPool>,
+) -> crate::Result<Hit> {
+    let doc_does_not_exist = || SearchError::DocDoesNotExist {
+        address: hit_address.to_string(),
+    };
+    let mut index_split_opt = None;
+    for index_id in parse_index_ids(index_id) {
+        let split_opt = retry_on_transient_error(|| {
+            metastore.list_splits(index_id, SplitState::Published, None, &[])
+        })
+        .await?
+        .into_iter()
+        .find(|split| split.split_metadata.split_id == hit_address.split_id);
+        if let Some(split) = split_opt {
+            index_split_opt = Some((index_id, split));
+            break;
+        }
+    }
+    let (index_id, split_metadata_and_footer_offsets) =
+        index_split_opt.ok_or_else(doc_does_not_exist)?;
+    let index_metadata = retry_on_transient_error(|| metastore.index_metadata(index_id)).await?;
+    if index_metadata
+        .index_config_for_version(hit_address.generation)
+        .is_none()
+    {
+        return Err(doc_does_not_exist());
+    }
+    let split_lease = lease_search_splits(
+        metastore,
+        std::iter::once((index_id, hit_address.split_id.as_str())),
+    )
+    .await;
+    let job = Job {
+        split_id: hit_address.split_id.clone(),
+        cost: compute_split_cost(&split_metadata_and_footer_offsets.split_metadata),
+    };
+    let (client, _) = client_pool
+        .assign_jobs(index_id, vec![job], &HashSet::new())
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            SearchError::InternalError("No search node to fetch the document from.".to_string())
+        })?;
+    let fetch_docs_request = FetchDocsRequest {
+        partial_hits: vec![hit_address.to_partial_hit()],
+        index_id: index_id.to_string(),
+        split_metadata: vec![extract_split_and_footer_offsets(
+            &split_metadata_and_footer_offsets,
+        )],
+        index_uri: index_metadata.index_uri.to_string(),
+        index_config_version: hit_address.generation,
+        mirror_index_uri: index_metadata
+            .mirror_index_uri
+            .as_deref()
+            .unwrap_or_default()
+            .to_string(),
+        flatten_docs: false,
+        cache_policy: CachePolicy::Use as i32,
+        index_config_fingerprint: index_metadata
+            .index_config_fingerprint(hit_address.generation)
+            .unwrap_or_default(),
+    };
+    let fetch_docs_response = cluster_client
+        .fetch_docs((fetch_docs_request, client))
+        .await?;
+    split_lease.release(metastore).await;
+    let mut hit = fetch_docs_response
+        .hits
+        .into_iter()
+        .next()
+        .ok_or_else(doc_does_not_exist)?;
+    hit.address = hit_address.to_string();
+    Ok(hit)
 }
 
 // Measure the cost associated to searching in a given split metadata.
@@ -188,13 +637,63 @@ pub(crate) fn job_for_splits(
     leaf_search_jobs
 }
 
+/// Assigns the fetch docs job of each split to the node that returned its hits, as the split
+/// is already open and cached there.
+///
+/// The jobs of the splits whose node left the cluster or is unavailable are assigned like
+/// leaf search jobs.
+async fn assign_fetch_docs_jobs(
+    client_pool: &SearchClientPool,
+    index_id: &str,
+    partial_hits_map: &HashMap<String, Vec<PartialHit>>,
+) -> anyhow::Result<Vec<(SearchServiceClient, Vec<Job>)>> {
+    let mut assigned_jobs: HashMap<SocketAddr, (SearchServiceClient, Vec<Job>)> = HashMap::new();
+    let mut unplaced_jobs = Vec::new();
+    {
+        let clients = client_pool.clients.read().await;
+        for (split_id, partial_hits) in partial_hits_map {
+            let job = Job {
+                split_id: split_id.clone(),
+                cost: 1,
+            };
+            let leaf_client_opt = partial_hits
+                .first()
+                .and_then(|partial_hit| partial_hit.leaf_grpc_addr.parse::<SocketAddr>().ok())
+                .and_then(|leaf_grpc_addr| clients.get(&leaf_grpc_addr))
+                .filter(|client| client.circuit_breaker().is_available());
+            if let Some(leaf_client) = leaf_client_opt {
+                assigned_jobs
+                    .entry(leaf_client.grpc_addr())
+                    .or_insert_with(|| (leaf_client.clone(), Vec::new()))
+                    .1
+                    .push(job);
+            } else {
+                unplaced_jobs.push(job);
+            }
+        }
+    }
+    if !unplaced_jobs.is_empty() {
+        for (client, jobs) in client_pool
+            .assign_jobs(index_id, unplaced_jobs, &HashSet::new())
+            .await?
+        {
+            assigned_jobs
+                .entry(client.grpc_addr())
+                .or_insert_with(|| (client, Vec::new()))
+                .1
+                .extend(jobs);
+        }
+    }
+    Ok(assigned_jobs.into_iter().map(|(_, jobs)| jobs).collect())
+}
+
 fn jobs_to_leaf_request(
-    request: &SearchRequest,
-    index_config_str: &str,
-    index_uri: &str,
+    index_target: &IndexTarget,
     split_metadata_map: &HashMap<String, SplitMetadataAndFooterOffsets>,
     jobs: &[Job],
 ) -> LeafSearchRequest {
+    let request = &index_target.search_request;
+    let index_metadata = &index_target.index_metadata;
     let mut request_with_offset_0 = request.clone();
     request_with_offset_0.start_offset = 0;
     request_with_offset_0.max_hits += request.start_offset;
@@ -207,14 +706,22 @@ fn jobs_to_leaf_request(
                 extract_split_and_footer_offsets(split_metadata_map.get(&job.split_id).unwrap())
             })
             .collect(),
-        index_config: index_config_str.to_string(),
-        index_uri: index_uri.to_string(),
+        index_config: String::new(),
+        index_uri: index_metadata.index_uri.to_string(),
+        index_config_version: index_metadata.index_config_version(),
+        mirror_index_uri: index_metadata
+            .mirror_index_uri
+            .as_deref()
+            .unwrap_or_default()
+            .to_string(),
+        index_config_fingerprint: index_metadata
+            .index_config_fingerprint(index_metadata.index_config_version())
+            .unwrap_or_default(),
     }
 }
 
 fn jobs_to_fetch_docs_request(
-    index_id: &str,
-    index_uri: &str,
+    index_target: &IndexTarget,
     split_metadata_map: &HashMap<String, SplitMetadataAndFooterOffsets>,
     partial_hits_map: &mut HashMap<String, Vec<PartialHit>>,
     jobs: &[Job],
@@ -230,11 +737,23 @@ fn jobs_to_fetch_docs_request(
         .map(extract_split_and_footer_offsets)
         .collect_vec();
 
+    let index_metadata = &index_target.index_metadata;
     FetchDocsRequest {
         partial_hits,
-        index_id: index_id.to_string(),
+        index_id: index_target.search_request.index_id.clone(),
         split_metadata: splits_footer_and_offsets,
-        index_uri: index_uri.to_string(),
+        index_uri: index_metadata.index_uri.to_string(),
+        index_config_version: index_metadata.index_config_version(),
+        mirror_index_uri: index_metadata
+            .mirror_index_uri
+            .as_deref()
+            .unwrap_or_default()
+            .to_string(),
+        flatten_docs: index_target.search_request.flatten_hits,
+        cache_policy: index_target.search_request.cache_policy,
+        index_config_fingerprint: index_metadata
+            .index_config_fingerprint(index_metadata.index_config_version())
+            .unwrap_or_default(),
     }
 }
 
@@ -242,10 +761,11 @@ fn jobs_to_fetch_docs_request(
 mod tests {
     use std::ops::Range;
 
+    use quickwit_common::QuickwitUri;
     use quickwit_index_config::WikipediaIndexConfig;
     use quickwit_indexing::mock_split_meta;
     use quickwit_metastore::checkpoint::Checkpoint;
-    use quickwit_metastore::{IndexMetadata, MockMetastore, SplitState};
+    use quickwit_metastore::{IndexMetadata, MockMetastore};
     use quickwit_proto::SplitSearchError;
 
     use super::*;
@@ -261,6 +781,7 @@ mod tests {
             split_id: split_id.to_string(),
             segment_ord: 1,
             doc_id,
+            leaf_grpc_addr: String::new(),
         }
     }
 
@@ -275,6 +796,7 @@ mod tests {
                     + &req.doc_id.to_string()
                     + r#"", "body" : "test 1", "url" : "http://127.0.0.1/1"}"#,
                 partial_hit: Some(req),
+                address: String::new(),
             })
             .collect_vec()
     }
@@ -290,16 +812,52 @@ mod tests {
             max_hits: 10,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .withf(|index_id, _lease_id, split_ids, _lease_duration| {
+                index_id == "test-idx" && split_ids == ["split1"]
+            })
+            .times(1)
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .withf(|index_id, _lease_id| index_id == "test-idx")
+            .times(1)
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
@@ -320,6 +878,10 @@ mod tests {
                     ],
                     failed_splits: Vec::new(),
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             },
         );
@@ -351,16 +913,46 @@ mod tests {
             max_hits: 10,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
@@ -382,6 +974,10 @@ mod tests {
                     ],
                     failed_splits: Vec::new(),
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             },
         );
@@ -400,6 +996,10 @@ mod tests {
                     partial_hits: vec![mock_partial_hit("split2", 2, 2)],
                     failed_splits: Vec::new(),
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             },
         );
@@ -436,16 +1036,46 @@ mod tests {
             max_hits: 10,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
@@ -472,6 +1102,10 @@ mod tests {
                         retryable_error: true,
                     }],
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             });
 
@@ -502,6 +1136,10 @@ mod tests {
                         ],
                         failed_splits: Vec::new(),
                         num_attempted_splits: 1,
+                        distinct_count_sketches: vec![],
+                        percentile_sketches: vec![],
+                        terms_buckets: vec![],
+                        date_histogram_buckets: vec![],
                     })
                 } else if split_ids == vec!["split2".to_string()] {
                     // RETRY REQUEST!
@@ -510,6 +1148,10 @@ mod tests {
                         partial_hits: vec![mock_partial_hit("split2", 2, 2)],
                         failed_splits: Vec::new(),
                         num_attempted_splits: 1,
+                        distinct_count_sketches: vec![],
+                        percentile_sketches: vec![],
+                        terms_buckets: vec![],
+                        date_histogram_buckets: vec![],
                     })
                 } else {
                     panic!("unexpected request in test {:?}", split_ids);
@@ -536,8 +1178,9 @@ mod tests {
         assert_eq!(search_response.hits.len(), 3);
         Ok(())
     }
+
     #[tokio::test]
-    async fn test_root_search_multiple_splits_retry_on_all_nodes() -> anyhow::Result<()> {
+    async fn test_root_search_fetches_docs_from_leaf_node() -> anyhow::Result<()> {
         let search_request = quickwit_proto::SearchRequest {
             index_id: "test-idx".to_string(),
             query: "test".to_string(),
@@ -547,16 +1190,46 @@ mod tests {
             max_hits: 10,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
@@ -567,15 +1240,14 @@ mod tests {
                 Ok(vec![mock_split_meta("split1"), mock_split_meta("split2")])
             },
         );
+
         let mut mock_search_service1 = MockSearchService::new();
         mock_search_service1
             .expect_leaf_search()
-            .withf(|leaf_search_req| leaf_search_req.split_metadata[0].split_id == "split2")
-            .return_once(|_| {
-                println!("request from service1 split2?");
-                // requests from split 2 arrive here - simulate failure.
-                // a retry will be made on the second service.
+            .times(1)
+            .returning(|_leaf_search_req: quickwit_proto::LeafSearchRequest| {
                 Ok(quickwit_proto::LeafSearchResponse {
+                    // requests from split 2 arrive here - simulate failure
                     num_hits: 0,
                     partial_hits: vec![],
                     failed_splits: vec![SplitSearchError {
@@ -584,62 +1256,54 @@ mod tests {
                         retryable_error: true,
                     }],
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             });
-        mock_search_service1
-            .expect_leaf_search()
-            .withf(|leaf_search_req| leaf_search_req.split_metadata[0].split_id == "split1")
-            .return_once(|_| {
-                println!("request from service1 split1?");
-                // RETRY REQUEST from split1
-                Ok(quickwit_proto::LeafSearchResponse {
-                    num_hits: 2,
-                    partial_hits: vec![
-                        mock_partial_hit("split1", 3, 1),
-                        mock_partial_hit("split1", 1, 3),
-                    ],
-                    failed_splits: Vec::new(),
-                    num_attempted_splits: 1,
-                })
-            });
-        mock_search_service1.expect_fetch_docs().returning(
-            |fetch_docs_req: quickwit_proto::FetchDocsRequest| {
-                Ok(quickwit_proto::FetchDocsResponse {
-                    hits: get_doc_for_fetch_req(fetch_docs_req),
-                })
-            },
-        );
+        // The documents of split2 are fetched from the node that searched it on retry.
+        mock_search_service1.expect_fetch_docs().never();
+
         let mut mock_search_service2 = MockSearchService::new();
         mock_search_service2
             .expect_leaf_search()
-            .withf(|leaf_search_req| leaf_search_req.split_metadata[0].split_id == "split2")
-            .return_once(|_| {
-                println!("request from service2 split2?");
-                // retry for split 2 arrive here, simulate success.
-                Ok(quickwit_proto::LeafSearchResponse {
-                    num_hits: 1,
-                    partial_hits: vec![mock_partial_hit("split2", 2, 2)],
-                    failed_splits: Vec::new(),
-                    num_attempted_splits: 1,
-                })
-            });
-        mock_search_service2
-            .expect_leaf_search()
-            .withf(|leaf_search_req| leaf_search_req.split_metadata[0].split_id == "split1")
-            .return_once(|_| {
-                println!("request from service2 split1?");
-                // requests from split 1 arrive here - simulate failure, then success.
-                Ok(quickwit_proto::LeafSearchResponse {
-                    // requests from split 2 arrive here - simulate failure
-                    num_hits: 0,
-                    partial_hits: vec![],
-                    failed_splits: vec![SplitSearchError {
-                        error: "mock_error".to_string(),
-                        split_id: "split1".to_string(),
-                        retryable_error: true,
-                    }],
-                    num_attempted_splits: 1,
-                })
+            .times(2)
+            .returning(|leaf_search_req: quickwit_proto::LeafSearchRequest| {
+                let split_ids = leaf_search_req
+                    .split_metadata
+                    .iter()
+                    .map(|metadata| metadata.split_id.to_string())
+                    .collect_vec();
+                if split_ids == vec!["split1".to_string()] {
+                    Ok(quickwit_proto::LeafSearchResponse {
+                        num_hits: 2,
+                        partial_hits: vec![
+                            mock_partial_hit("split1", 3, 1),
+                            mock_partial_hit("split1", 1, 3),
+                        ],
+                        failed_splits: Vec::new(),
+                        num_attempted_splits: 1,
+                        distinct_count_sketches: vec![],
+                        percentile_sketches: vec![],
+                        terms_buckets: vec![],
+                        date_histogram_buckets: vec![],
+                    })
+                } else if split_ids == vec!["split2".to_string()] {
+                    // RETRY REQUEST!
+                    Ok(quickwit_proto::LeafSearchResponse {
+                        num_hits: 1,
+                        partial_hits: vec![mock_partial_hit("split2", 2, 2)],
+                        failed_splits: Vec::new(),
+                        num_attempted_splits: 1,
+                        distinct_count_sketches: vec![],
+                        percentile_sketches: vec![],
+                        terms_buckets: vec![],
+                        date_histogram_buckets: vec![],
+                    })
+                } else {
+                    panic!("unexpected request in test {:?}", split_ids);
+                }
             });
         mock_search_service2.expect_fetch_docs().returning(
             |fetch_docs_req: quickwit_proto::FetchDocsRequest| {
@@ -664,7 +1328,7 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_root_search_single_split_retry_single_node() -> anyhow::Result<()> {
+    async fn test_root_search_sends_shadow_requests_to_standby_node() -> anyhow::Result<()> {
         let search_request = quickwit_proto::SearchRequest {
             index_id: "test-idx".to_string(),
             query: "test".to_string(),
@@ -674,51 +1338,75 @@ mod tests {
             max_hits: 10,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
             |_index_id: &str,
              _split_state: SplitState,
              _time_range: Option<Range<i64>>,
-             _tags: &[String]| { Ok(vec![mock_split_meta("split1")]) },
+             _tags: &[String]| {
+                Ok(vec![mock_split_meta("split1"), mock_split_meta("split2")])
+            },
         );
-        let mut first_call = true;
+
         let mut mock_search_service1 = MockSearchService::new();
         mock_search_service1
             .expect_leaf_search()
-            .times(2)
-            .returning(move |_leaf_search_req: quickwit_proto::LeafSearchRequest| {
-                // requests from split 2 arrive here - simulate failure, then success
-                if first_call {
-                    first_call = false;
-                    Ok(quickwit_proto::LeafSearchResponse {
-                        num_hits: 0,
-                        partial_hits: vec![],
-                        failed_splits: vec![SplitSearchError {
-                            error: "mock_error".to_string(),
-                            split_id: "split1".to_string(),
-                            retryable_error: true,
-                        }],
-                        num_attempted_splits: 1,
-                    })
-                } else {
-                    Ok(quickwit_proto::LeafSearchResponse {
-                        num_hits: 1,
-                        partial_hits: vec![mock_partial_hit("split1", 2, 2)],
-                        failed_splits: Vec::new(),
-                        num_attempted_splits: 1,
-                    })
-                }
+            .times(1)
+            .returning(|_leaf_search_req: quickwit_proto::LeafSearchRequest| {
+                Ok(quickwit_proto::LeafSearchResponse {
+                    num_hits: 2,
+                    partial_hits: vec![
+                        mock_partial_hit("split1", 2, 1),
+                        mock_partial_hit("split2", 1, 1),
+                    ],
+                    failed_splits: Vec::new(),
+                    num_attempted_splits: 2,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
+                })
             });
         mock_search_service1.expect_fetch_docs().returning(
             |fetch_docs_req: quickwit_proto::FetchDocsRequest| {
@@ -727,37 +1415,389 @@ mod tests {
                 })
             },
         );
-        let client_pool =
-            Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_search_service1)]).await?);
-        let cluster_client = ClusterClient::new(client_pool.clone());
-        let search_response =
-            root_search(&search_request, &metastore, &cluster_client, &client_pool).await?;
-        assert_eq!(search_response.num_hits, 1);
-        assert_eq!(search_response.hits.len(), 1);
-        Ok(())
-    }
 
-    #[tokio::test]
-    async fn test_root_search_single_split_retry_single_node_fails() -> anyhow::Result<()> {
-        let search_request = quickwit_proto::SearchRequest {
-            index_id: "test-idx".to_string(),
-            query: "test".to_string(),
-            search_fields: vec!["body".to_string()],
-            start_timestamp: None,
-            end_timestamp: None,
+        let (shadow_request_sender, mut shadow_request_receiver) =
+            tokio::sync::mpsc::unbounded_channel();
+        let mut mock_search_service2 = MockSearchService::new();
+        mock_search_service2
+            .expect_leaf_search()
+            .times(1)
+            .returning(move |leaf_search_req: quickwit_proto::LeafSearchRequest| {
+                let split_ids = leaf_search_req
+                    .split_metadata
+                    .iter()
+                    .map(|metadata| metadata.split_id.to_string())
+                    .sorted()
+                    .collect_vec();
+                shadow_request_sender.send(split_ids).unwrap();
+                // The response of the standby node is ignored.
+                Ok(quickwit_proto::LeafSearchResponse {
+                    num_hits: 100,
+                    num_attempted_splits: 2,
+                    ..Default::default()
+                })
+            });
+        mock_search_service2.expect_fetch_docs().never();
+        let client_pool = Arc::new(
+            SearchClientPool::from_mocks(vec![
+                Arc::new(mock_search_service1),
+                Arc::new(mock_search_service2),
+            ])
+            .await?,
+        );
+        {
+            let clients = client_pool.clients.read().await;
+            let standby_addr = clients.keys().max().unwrap();
+            clients[standby_addr].set_standby(true);
+        }
+        let cluster_client = ClusterClient::new(client_pool.clone());
+        let search_response =
+            root_search(&search_request, &metastore, &cluster_client, &client_pool).await?;
+        assert_eq!(search_response.num_hits, 2);
+        assert_eq!(search_response.hits.len(), 2);
+        assert_eq!(
+            shadow_request_receiver.recv().await,
+            Some(vec!["split1".to_string(), "split2".to_string()])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_root_search_multiple_splits_retry_on_all_nodes() -> anyhow::Result<()> {
+        let search_request = quickwit_proto::SearchRequest {
+            index_id: "test-idx".to_string(),
+            query: "test".to_string(),
+            search_fields: vec!["body".to_string()],
+            start_timestamp: None,
+            end_timestamp: None,
             max_hits: 10,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_list_splits().returning(
+            |_index_id: &str,
+             _split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| {
+                Ok(vec![mock_split_meta("split1"), mock_split_meta("split2")])
+            },
+        );
+        let mut mock_search_service1 = MockSearchService::new();
+        mock_search_service1
+            .expect_leaf_search()
+            .withf(|leaf_search_req| leaf_search_req.split_metadata[0].split_id == "split2")
+            .return_once(|_| {
+                println!("request from service1 split2?");
+                // requests from split 2 arrive here - simulate failure.
+                // a retry will be made on the second service.
+                Ok(quickwit_proto::LeafSearchResponse {
+                    num_hits: 0,
+                    partial_hits: vec![],
+                    failed_splits: vec![SplitSearchError {
+                        error: "mock_error".to_string(),
+                        split_id: "split2".to_string(),
+                        retryable_error: true,
+                    }],
+                    num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
+                })
+            });
+        mock_search_service1
+            .expect_leaf_search()
+            .withf(|leaf_search_req| leaf_search_req.split_metadata[0].split_id == "split1")
+            .return_once(|_| {
+                println!("request from service1 split1?");
+                // RETRY REQUEST from split1
+                Ok(quickwit_proto::LeafSearchResponse {
+                    num_hits: 2,
+                    partial_hits: vec![
+                        mock_partial_hit("split1", 3, 1),
+                        mock_partial_hit("split1", 1, 3),
+                    ],
+                    failed_splits: Vec::new(),
+                    num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
+                })
+            });
+        mock_search_service1.expect_fetch_docs().returning(
+            |fetch_docs_req: quickwit_proto::FetchDocsRequest| {
+                Ok(quickwit_proto::FetchDocsResponse {
+                    hits: get_doc_for_fetch_req(fetch_docs_req),
+                })
+            },
+        );
+        let mut mock_search_service2 = MockSearchService::new();
+        mock_search_service2
+            .expect_leaf_search()
+            .withf(|leaf_search_req| leaf_search_req.split_metadata[0].split_id == "split2")
+            .return_once(|_| {
+                println!("request from service2 split2?");
+                // retry for split 2 arrive here, simulate success.
+                Ok(quickwit_proto::LeafSearchResponse {
+                    num_hits: 1,
+                    partial_hits: vec![mock_partial_hit("split2", 2, 2)],
+                    failed_splits: Vec::new(),
+                    num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
+                })
+            });
+        mock_search_service2
+            .expect_leaf_search()
+            .withf(|leaf_search_req| leaf_search_req.split_metadata[0].split_id == "split1")
+            .return_once(|_| {
+                println!("request from service2 split1?");
+                // requests from split 1 arrive here - simulate failure, then success.
+                Ok(quickwit_proto::LeafSearchResponse {
+                    // requests from split 2 arrive here - simulate failure
+                    num_hits: 0,
+                    partial_hits: vec![],
+                    failed_splits: vec![SplitSearchError {
+                        error: "mock_error".to_string(),
+                        split_id: "split1".to_string(),
+                        retryable_error: true,
+                    }],
+                    num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
+                })
+            });
+        mock_search_service2.expect_fetch_docs().returning(
+            |fetch_docs_req: quickwit_proto::FetchDocsRequest| {
+                Ok(quickwit_proto::FetchDocsResponse {
+                    hits: get_doc_for_fetch_req(fetch_docs_req),
+                })
+            },
+        );
+        let client_pool = Arc::new(
+            SearchClientPool::from_mocks(vec![
+                Arc::new(mock_search_service1),
+                Arc::new(mock_search_service2),
+            ])
+            .await?,
+        );
+        let cluster_client = ClusterClient::new(client_pool.clone());
+        let search_response =
+            root_search(&search_request, &metastore, &cluster_client, &client_pool).await?;
+        assert_eq!(search_response.num_hits, 3);
+        assert_eq!(search_response.hits.len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_root_search_single_split_retry_single_node() -> anyhow::Result<()> {
+        let search_request = quickwit_proto::SearchRequest {
+            index_id: "test-idx".to_string(),
+            query: "test".to_string(),
+            search_fields: vec!["body".to_string()],
+            start_timestamp: None,
+            end_timestamp: None,
+            max_hits: 10,
+            start_offset: 0,
+            tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
+        };
+        let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
+        metastore
+            .expect_index_metadata()
+            .returning(|_index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_list_splits().returning(
+            |_index_id: &str,
+             _split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| { Ok(vec![mock_split_meta("split1")]) },
+        );
+        let mut first_call = true;
+        let mut mock_search_service1 = MockSearchService::new();
+        mock_search_service1
+            .expect_leaf_search()
+            .times(2)
+            .returning(move |_leaf_search_req: quickwit_proto::LeafSearchRequest| {
+                // requests from split 2 arrive here - simulate failure, then success
+                if first_call {
+                    first_call = false;
+                    Ok(quickwit_proto::LeafSearchResponse {
+                        num_hits: 0,
+                        partial_hits: vec![],
+                        failed_splits: vec![SplitSearchError {
+                            error: "mock_error".to_string(),
+                            split_id: "split1".to_string(),
+                            retryable_error: true,
+                        }],
+                        num_attempted_splits: 1,
+                        distinct_count_sketches: vec![],
+                        percentile_sketches: vec![],
+                        terms_buckets: vec![],
+                        date_histogram_buckets: vec![],
+                    })
+                } else {
+                    Ok(quickwit_proto::LeafSearchResponse {
+                        num_hits: 1,
+                        partial_hits: vec![mock_partial_hit("split1", 2, 2)],
+                        failed_splits: Vec::new(),
+                        num_attempted_splits: 1,
+                        distinct_count_sketches: vec![],
+                        percentile_sketches: vec![],
+                        terms_buckets: vec![],
+                        date_histogram_buckets: vec![],
+                    })
+                }
+            });
+        mock_search_service1.expect_fetch_docs().returning(
+            |fetch_docs_req: quickwit_proto::FetchDocsRequest| {
+                Ok(quickwit_proto::FetchDocsResponse {
+                    hits: get_doc_for_fetch_req(fetch_docs_req),
+                })
+            },
+        );
+        let client_pool =
+            Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_search_service1)]).await?);
+        let cluster_client = ClusterClient::new(client_pool.clone());
+        let search_response =
+            root_search(&search_request, &metastore, &cluster_client, &client_pool).await?;
+        assert_eq!(search_response.num_hits, 1);
+        assert_eq!(search_response.hits.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_root_search_single_split_retry_single_node_fails() -> anyhow::Result<()> {
+        let search_request = quickwit_proto::SearchRequest {
+            index_id: "test-idx".to_string(),
+            query: "test".to_string(),
+            search_fields: vec!["body".to_string()],
+            start_timestamp: None,
+            end_timestamp: None,
+            max_hits: 10,
+            start_offset: 0,
+            tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
+        };
+        let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
+        metastore
+            .expect_index_metadata()
+            .returning(|_index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
@@ -781,6 +1821,10 @@ mod tests {
                         retryable_error: true,
                     }],
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             });
         mock_search_service1.expect_fetch_docs().returning(
@@ -809,16 +1853,46 @@ mod tests {
             max_hits: 10,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
@@ -837,6 +1911,10 @@ mod tests {
                     partial_hits: vec![mock_partial_hit("split1", 2, 2)],
                     failed_splits: Vec::new(),
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             },
         );
@@ -860,6 +1938,10 @@ mod tests {
                         retryable_error: true,
                     }],
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             },
         );
@@ -895,16 +1977,46 @@ mod tests {
             max_hits: 10,
             start_offset: 0,
             tags: vec![],
+            distinct_count_fields: vec![],
+            percentile_fields: vec![],
+            percentiles: vec![],
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: 0,
+            max_num_splits: None,
+            hit_sampling: None,
         };
         let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
@@ -923,6 +2035,10 @@ mod tests {
                     partial_hits: vec![mock_partial_hit("split1", 2, 2)],
                     failed_splits: Vec::new(),
                     num_attempted_splits: 1,
+                    distinct_count_sketches: vec![],
+                    percentile_sketches: vec![],
+                    terms_buckets: vec![],
+                    date_histogram_buckets: vec![],
                 })
             },
         );
@@ -959,4 +2075,512 @@ mod tests {
         assert_eq!(search_response.hits.len(), 1);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_root_fetch_doc() -> anyhow::Result<()> {
+        let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
+        metastore
+            .expect_index_metadata()
+            .returning(|_index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_list_splits().returning(
+            |_index_id: &str,
+             _split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| { Ok(vec![mock_split_meta("split1")]) },
+        );
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service.expect_fetch_docs().returning(
+            |fetch_docs_req: quickwit_proto::FetchDocsRequest| {
+                assert_eq!(fetch_docs_req.index_config_version, 0);
+                assert_eq!(fetch_docs_req.split_metadata[0].split_id, "split1");
+                Ok(quickwit_proto::FetchDocsResponse {
+                    hits: get_doc_for_fetch_req(fetch_docs_req),
+                })
+            },
+        );
+        let client_pool =
+            Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_search_service)]).await?);
+        let cluster_client = ClusterClient::new(client_pool.clone());
+        let hit_address: HitAddress = "split1:1:2:0".parse()?;
+        let hit = root_fetch_doc(
+            "test-idx",
+            &hit_address,
+            &metastore,
+            &cluster_client,
+            &client_pool,
+        )
+        .await?;
+        assert_eq!(hit.address, "split1:1:2:0");
+        assert_eq!(hit.partial_hit.unwrap().doc_id, 2);
+
+        let missing_split_address: HitAddress = "split2:1:2:0".parse()?;
+        let fetch_doc_res = root_fetch_doc(
+            "test-idx",
+            &missing_split_address,
+            &metastore,
+            &cluster_client,
+            &client_pool,
+        )
+        .await;
+        assert!(matches!(
+            fetch_doc_res,
+            Err(SearchError::DocDoesNotExist { .. })
+        ));
+
+        let missing_generation_address: HitAddress = "split1:1:2:1".parse()?;
+        let fetch_doc_res = root_fetch_doc(
+            "test-idx",
+            &missing_generation_address,
+            &metastore,
+            &cluster_client,
+            &client_pool,
+        )
+        .await;
+        assert!(matches!(
+            fetch_doc_res,
+            Err(SearchError::DocDoesNotExist { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_root_search_forwards_mandatory_filter() -> anyhow::Result<()> {
+        let search_request = quickwit_proto::SearchRequest {
+            index_id: "test-idx".to_string(),
+            query: "test OR other".to_string(),
+            search_fields: vec!["body".to_string()],
+            max_hits: 10,
+            mandatory_filter: Some("title:acme".to_string()),
+            ..Default::default()
+        };
+        let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
+        metastore
+            .expect_index_metadata()
+            .returning(|_index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_list_splits().returning(
+            |_index_id: &str,
+             _split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| { Ok(vec![mock_split_meta("split1")]) },
+        );
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service.expect_leaf_search().returning(
+            |leaf_search_req: quickwit_proto::LeafSearchRequest| {
+                let leaf_search_request = leaf_search_req.search_request.unwrap();
+                // The leaves combine the filter with the parsed query.
+                assert_eq!(leaf_search_request.query, "test OR other");
+                assert_eq!(
+                    leaf_search_request.mandatory_filter.as_deref(),
+                    Some("title:acme")
+                );
+                Ok(quickwit_proto::LeafSearchResponse {
+                    num_hits: 1,
+                    partial_hits: vec![mock_partial_hit("split1", 1, 1)],
+                    failed_splits: Vec::new(),
+                    num_attempted_splits: 1,
+                    ..Default::default()
+                })
+            },
+        );
+        mock_search_service.expect_fetch_docs().returning(
+            |fetch_docs_req: quickwit_proto::FetchDocsRequest| {
+                Ok(quickwit_proto::FetchDocsResponse {
+                    hits: get_doc_for_fetch_req(fetch_docs_req),
+                })
+            },
+        );
+        let client_pool =
+            Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_search_service)]).await?);
+        let cluster_client = ClusterClient::new(client_pool.clone());
+        let search_response =
+            root_search(&search_request, &metastore, &cluster_client, &client_pool).await?;
+        assert_eq!(search_response.num_hits, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_root_search_federated_indexes() -> anyhow::Result<()> {
+        let search_request = quickwit_proto::SearchRequest {
+            index_id: "logs-app,logs-infra".to_string(),
+            query: "test".to_string(),
+            search_fields: vec!["body".to_string()],
+            max_hits: 10,
+            ..Default::default()
+        };
+        let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
+        metastore
+            .expect_index_metadata()
+            .times(2)
+            .returning(|index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: index_id.to_string(),
+                    index_uri: QuickwitUri::from_well_formed(&format!(
+                        "file:///path/to/index/{}",
+                        index_id
+                    )),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_list_splits().times(6).returning(
+            |index_id: &str,
+             split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| {
+                if split_state != SplitState::Published {
+                    return Ok(Vec::new());
+                }
+                let split_id = if index_id == "logs-app" {
+                    "split-app"
+                } else {
+                    "split-infra"
+                };
+                Ok(vec![mock_split_meta(split_id)])
+            },
+        );
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service.expect_leaf_search().times(2).returning(
+            |leaf_search_req: quickwit_proto::LeafSearchRequest| {
+                let index_id = leaf_search_req.search_request.unwrap().index_id;
+                assert!(leaf_search_req.index_uri.ends_with(&index_id));
+                let split_id = &leaf_search_req.split_metadata[0].split_id;
+                let partial_hits = if index_id == "logs-app" {
+                    assert_eq!(split_id, "split-app");
+                    vec![mock_partial_hit("split-app", 3, 1)]
+                } else {
+                    assert_eq!(split_id, "split-infra");
+                    vec![
+                        mock_partial_hit("split-infra", 4, 1),
+                        mock_partial_hit("split-infra", 2, 2),
+                    ]
+                };
+                Ok(quickwit_proto::LeafSearchResponse {
+                    num_hits: partial_hits.len() as u64,
+                    partial_hits,
+                    failed_splits: Vec::new(),
+                    num_attempted_splits: 1,
+                    ..Default::default()
+                })
+            },
+        );
+        mock_search_service.expect_fetch_docs().times(2).returning(
+            |fetch_docs_req: quickwit_proto::FetchDocsRequest| {
+                let expected_split_id = if fetch_docs_req.index_id == "logs-app" {
+                    "split-app"
+                } else {
+                    "split-infra"
+                };
+                for partial_hit in &fetch_docs_req.partial_hits {
+                    assert_eq!(partial_hit.split_id, expected_split_id);
+                }
+                Ok(quickwit_proto::FetchDocsResponse {
+                    hits: get_doc_for_fetch_req(fetch_docs_req),
+                })
+            },
+        );
+        let client_pool =
+            Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_search_service)]).await?);
+        let cluster_client = ClusterClient::new(client_pool.clone());
+        let search_response =
+            root_search(&search_request, &metastore, &cluster_client, &client_pool).await?;
+        assert_eq!(search_response.num_hits, 3);
+        let hit_split_ids: Vec<&str> = search_response
+            .hits
+            .iter()
+            .map(|hit| hit.partial_hit.as_ref().unwrap().split_id.as_str())
+            .collect();
+        assert_eq!(
+            hit_split_ids,
+            vec!["split-infra", "split-app", "split-infra"]
+        );
+        Ok(())
+    }
+
+    fn mock_split_meta_updated_at(
+        split_id: &str,
+        split_state: SplitState,
+        update_timestamp: i64,
+    ) -> SplitMetadataAndFooterOffsets {
+        let mut split_meta = mock_split_meta(split_id);
+        split_meta.split_metadata.split_state = split_state;
+        split_meta.split_metadata.update_timestamp = update_timestamp;
+        split_meta
+    }
+
+    #[tokio::test]
+    async fn test_root_search_with_session_token() -> anyhow::Result<()> {
+        let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(100));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
+        metastore
+            .expect_index_metadata()
+            .returning(|_index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_list_splits().returning(
+            |_index_id: &str,
+             split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| {
+                // `split0` was deleted before the snapshot of the session, and `split1` was
+                // merged into `split3` after it.
+                let split_metas = match split_state {
+                    SplitState::Published => vec![
+                        mock_split_meta_updated_at("split2", SplitState::Published, 50),
+                        mock_split_meta_updated_at("split3", SplitState::Published, 200),
+                    ],
+                    SplitState::ScheduledForDeletion => vec![
+                        mock_split_meta_updated_at("split0", SplitState::ScheduledForDeletion, 90),
+                        mock_split_meta_updated_at("split1", SplitState::ScheduledForDeletion, 200),
+                    ],
+                    _ => Vec::new(),
+                };
+                Ok(split_metas)
+            },
+        );
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service.expect_leaf_search().returning(
+            |leaf_search_req: quickwit_proto::LeafSearchRequest| {
+                let mut split_ids: Vec<&str> = leaf_search_req
+                    .split_metadata
+                    .iter()
+                    .map(|split_meta| split_meta.split_id.as_str())
+                    .collect();
+                split_ids.sort_unstable();
+                assert_eq!(split_ids, vec!["split1", "split2"]);
+                Ok(quickwit_proto::LeafSearchResponse {
+                    num_hits: 2,
+                    partial_hits: vec![
+                        mock_partial_hit("split1", 2, 1),
+                        mock_partial_hit("split2", 1, 1),
+                    ],
+                    failed_splits: Vec::new(),
+                    num_attempted_splits: 2,
+                    ..Default::default()
+                })
+            },
+        );
+        mock_search_service.expect_fetch_docs().returning(
+            |fetch_docs_req: quickwit_proto::FetchDocsRequest| {
+                Ok(quickwit_proto::FetchDocsResponse {
+                    hits: get_doc_for_fetch_req(fetch_docs_req),
+                })
+            },
+        );
+        let client_pool =
+            Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_search_service)]).await?);
+        let cluster_client = ClusterClient::new(client_pool.clone());
+        let search_session =
+            SearchSession::new(100, vec![("test-idx", "split1"), ("test-idx", "split2")]);
+        // The first page is searched on the splits published at the time of the metastore
+        // clock.
+        let mut search_request = quickwit_proto::SearchRequest {
+            index_id: "test-idx".to_string(),
+            query: "test".to_string(),
+            search_fields: vec!["body".to_string()],
+            max_hits: 10,
+            ..Default::default()
+        };
+        let search_response =
+            root_search(&search_request, &metastore, &cluster_client, &client_pool).await?;
+        assert_eq!(search_response.num_hits, 2);
+        assert_eq!(search_response.session_token, search_session.to_string());
+
+        search_request.session_token = Some(search_session.to_string());
+        let search_response =
+            root_search(&search_request, &metastore, &cluster_client, &client_pool).await?;
+        assert_eq!(search_response.num_hits, 2);
+        assert_eq!(search_response.session_token, search_session.to_string());
+
+        search_request.session_token =
+            Some(SearchSession::new(100, vec![("test-idx", "split1")]).to_string());
+        let search_error = root_search(&search_request, &metastore, &cluster_client, &client_pool)
+            .await
+            .unwrap_err();
+        assert!(matches!(search_error, SearchError::InvalidQuery(_)));
+
+        search_request.session_token = Some("not-a-token".to_string());
+        let search_error = root_search(&search_request, &metastore, &cluster_client, &client_pool)
+            .await
+            .unwrap_err();
+        assert!(matches!(search_error, SearchError::InvalidQuery(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_root_search_quarantines_failing_split() -> anyhow::Result<()> {
+        let mut metastore = MockMetastore::new();
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
+        metastore
+            .expect_index_metadata()
+            .returning(|_index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_list_splits().returning(
+            |_index_id: &str,
+             split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| {
+                let split_metas = match split_state {
+                    SplitState::Published => {
+                        vec![mock_split_meta("split1"), mock_split_meta("split2")]
+                    }
+                    SplitState::Quarantined => vec![mock_split_meta("split0")],
+                    _ => Vec::new(),
+                };
+                Ok(split_metas)
+            },
+        );
+        metastore.expect_quarantine_splits().times(1).returning(
+            |index_id: &str, split_ids: &[&str]| {
+                assert_eq!(index_id, "test-idx");
+                assert_eq!(split_ids, ["split2"]);
+                Ok(())
+            },
+        );
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service.expect_leaf_search().returning(
+            |leaf_search_req: quickwit_proto::LeafSearchRequest| {
+                let searches_split1 = leaf_search_req
+                    .split_metadata
+                    .iter()
+                    .any(|split_meta| split_meta.split_id == "split1");
+                let partial_hits = if searches_split1 {
+                    vec![mock_partial_hit("split1", 2, 1)]
+                } else {
+                    Vec::new()
+                };
+                Ok(quickwit_proto::LeafSearchResponse {
+                    num_hits: partial_hits.len() as u64,
+                    partial_hits,
+                    failed_splits: vec![SplitSearchError {
+                        error: "mock_error".to_string(),
+                        split_id: "split2".to_string(),
+                        retryable_error: false,
+                    }],
+                    num_attempted_splits: leaf_search_req.split_metadata.len() as u64,
+                    ..Default::default()
+                })
+            },
+        );
+        mock_search_service.expect_fetch_docs().returning(
+            |fetch_docs_req: quickwit_proto::FetchDocsRequest| {
+                Ok(quickwit_proto::FetchDocsResponse {
+                    hits: get_doc_for_fetch_req(fetch_docs_req),
+                })
+            },
+        );
+        let client_pool =
+            Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_search_service)]).await?);
+        let cluster_client =
+            ClusterClient::new(client_pool.clone()).with_split_quarantine_threshold(2);
+        let search_request = quickwit_proto::SearchRequest {
+            index_id: "test-idx".to_string(),
+            query: "test".to_string(),
+            search_fields: vec!["body".to_string()],
+            max_hits: 10,
+            ..Default::default()
+        };
+        // The first failure of `split2` fails the search.
+        assert!(
+            root_search(&search_request, &metastore, &cluster_client, &client_pool)
+                .await
+                .is_err()
+        );
+        // The second one quarantines it, and the search succeeds without it.
+        let search_response =
+            root_search(&search_request, &metastore, &cluster_client, &client_pool).await?;
+        assert_eq!(search_response.num_hits, 1);
+        assert_eq!(
+            search_response.quarantined_split_ids,
+            vec!["split0".to_string(), "split2".to_string()]
+        );
+        Ok(())
+    }
 }
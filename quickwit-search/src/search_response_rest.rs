@@ -17,8 +17,10 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 
+use quickwit_proto::{DateHistogramBucket, QueryWarning, TermsAggregationResult};
 use serde::Serialize;
 
 use crate::error::SearchError;
@@ -32,14 +34,52 @@ pub struct SearchResponseRest {
     pub num_hits: u64,
     /// List of hits returned.
     pub hits: Vec<serde_json::Value>,
+    /// Addresses of the hits, in the same order, accepted by the doc fetching API.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hit_addresses: Vec<String>,
     /// Elapsed time.
     pub elapsed_time_micros: u64,
+    /// Estimated number of distinct values of the requested fast fields.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub distinct_counts: BTreeMap<String, u64>,
+    /// Estimated percentiles of the requested fast fields, keyed by percentile (e.g. `"99"`).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub percentiles: BTreeMap<String, BTreeMap<String, f64>>,
+    /// Top buckets of the requested terms aggregation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terms_aggregation: Option<TermsAggregationResult>,
+    /// Non-empty buckets of the requested date histogram, in chronological order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub date_histogram: Vec<DateHistogramBucket>,
+    /// Clauses of the query whose value does not match the type of their field.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<QueryWarning>,
+    /// Token of the search session, accepted by the following searches so that they run on
+    /// the same splits.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub session_token: String,
+    /// IDs of the splits skipped because they are quarantined.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quarantined_split_ids: Vec<String>,
 }
 
 impl TryFrom<quickwit_proto::SearchResponse> for SearchResponseRest {
     type Error = SearchError;
 
     fn try_from(search_response: quickwit_proto::SearchResponse) -> Result<Self, Self::Error> {
+        let hit_addresses = if search_response
+            .hits
+            .iter()
+            .all(|hit| !hit.address.is_empty())
+        {
+            search_response
+                .hits
+                .iter()
+                .map(|hit| hit.address.clone())
+                .collect()
+        } else {
+            Vec::new()
+        };
         let hits = search_response
             .hits
             .into_iter()
@@ -52,10 +92,31 @@ impl TryFrom<quickwit_proto::SearchResponse> for SearchResponseRest {
                 })
             })
             .collect::<crate::Result<Vec<serde_json::Value>>>()?;
+        let percentiles = search_response
+            .percentiles
+            .into_iter()
+            .map(|(field_name, percentile_values)| {
+                let field_percentiles = percentile_values
+                    .percentiles
+                    .iter()
+                    .map(|percentile| percentile.to_string())
+                    .zip(percentile_values.values)
+                    .collect();
+                (field_name, field_percentiles)
+            })
+            .collect();
         Ok(SearchResponseRest {
             num_hits: search_response.num_hits,
             hits,
+            hit_addresses,
             elapsed_time_micros: search_response.elapsed_time_micros,
+            distinct_counts: search_response.distinct_counts.into_iter().collect(),
+            percentiles,
+            terms_aggregation: search_response.terms_aggregation,
+            date_histogram: search_response.date_histogram,
+            warnings: search_response.warnings,
+            session_token: search_response.session_token,
+            quarantined_split_ids: search_response.quarantined_split_ids,
         })
     }
 }
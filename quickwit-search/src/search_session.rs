@@ -0,0 +1,165 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Context;
+use quickwit_metastore::{
+    retry_on_transient_error, Metastore, MetastoreResult, SplitMetadataAndFooterOffsets, SplitState,
+};
+use quickwit_proto::SearchRequest;
+
+use crate::extract_time_range;
+
+/// Snapshot of the splits a search ran on, so that the following pages of the hits are
+/// searched on the same splits even if splits get published or merged in the meantime.
+///
+/// Its string representation, `{snapshot_timestamp}:{fingerprint}`, is returned as the
+/// session token of the search responses. The splits of the session are the ones that were
+/// published at the snapshot timestamp: the fingerprint of their IDs detects the sessions
+/// whose splits cannot be searched again, e.g. because they were garbage collected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchSession {
+    /// Time at which the splits of the session were listed, in seconds.
+    pub snapshot_timestamp: i64,
+    /// Hash of the IDs of the splits of the session, with their index IDs. It is stable across
+    /// builds, so that any searcher can check the sessions of the others.
+    pub fingerprint: u64,
+}
+
+impl SearchSession {
+    /// Returns the session of the splits `split_ids`, given with their index ID, listed
+    /// at `snapshot_timestamp`.
+    pub fn new<'a>(
+        snapshot_timestamp: i64,
+        split_ids: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Self {
+        let mut split_ids: Vec<(&str, &str)> = split_ids.into_iter().collect();
+        split_ids.sort_unstable();
+        // Each ID is prefixed by its length so that no two lists of IDs share the same
+        // serialization.
+        let mut context = md5::Context::new();
+        for (index_id, split_id) in split_ids {
+            for id in &[index_id, split_id] {
+                context.consume((id.len() as u64).to_le_bytes());
+                context.consume(id.as_bytes());
+            }
+        }
+        let digest = context.compute();
+        let mut fingerprint_bytes = [0u8; 8];
+        fingerprint_bytes.copy_from_slice(&digest.0[..8]);
+        SearchSession {
+            snapshot_timestamp,
+            fingerprint: u64::from_le_bytes(fingerprint_bytes),
+        }
+    }
+}
+
+impl fmt::Display for SearchSession {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}:{:016x}",
+            self.snapshot_timestamp, self.fingerprint
+        )
+    }
+}
+
+impl FromStr for SearchSession {
+    type Err = anyhow::Error;
+
+    fn from_str(session_token: &str) -> anyhow::Result<Self> {
+        let (snapshot_timestamp_str, fingerprint_str) = session_token
+            .split_once(':')
+            .with_context(|| format!("Invalid session token `{}`.", session_token))?;
+        let snapshot_timestamp = snapshot_timestamp_str
+            .parse()
+            .with_context(|| format!("Invalid session token `{}`.", session_token))?;
+        let fingerprint = u64::from_str_radix(fingerprint_str, 16)
+            .with_context(|| format!("Invalid session token `{}`.", session_token))?;
+        Ok(SearchSession {
+            snapshot_timestamp,
+            fingerprint,
+        })
+    }
+}
+
+/// Lists the splits relevant to the request that were published at `snapshot_timestamp`.
+///
+/// They are the published splits that have not changed since, and the splits scheduled for
+/// deletion after it, e.g. because they were merged.
+pub(crate) async fn list_snapshot_splits(
+    search_request: &SearchRequest,
+    metastore: &dyn Metastore,
+    snapshot_timestamp: i64,
+) -> MetastoreResult<Vec<SplitMetadataAndFooterOffsets>> {
+    let time_range_opt = extract_time_range(search_request);
+    let mut split_metas = Vec::new();
+    for split_state in [SplitState::Published, SplitState::ScheduledForDeletion] {
+        let state_split_metas = retry_on_transient_error(|| {
+            metastore.list_splits(
+                &search_request.index_id,
+                split_state,
+                time_range_opt.clone(),
+                &search_request.tags,
+            )
+        })
+        .await?;
+        split_metas.extend(state_split_metas.into_iter().filter(|split_meta| {
+            let update_timestamp = split_meta.split_metadata.update_timestamp;
+            match split_state {
+                SplitState::Published => update_timestamp <= snapshot_timestamp,
+                _ => update_timestamp > snapshot_timestamp,
+            }
+        }));
+    }
+    Ok(split_metas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SearchSession;
+
+    #[test]
+    fn test_search_session_serialization() -> anyhow::Result<()> {
+        let session = SearchSession::new(1_622_548_800, vec![("index", "split1")]);
+        let session_token = session.to_string();
+        assert_eq!(session_token, "1622548800:1a1955eda2183d70");
+        assert_eq!(session_token.parse::<SearchSession>()?, session);
+        assert!("1622548800".parse::<SearchSession>().is_err());
+        assert!("now:0123".parse::<SearchSession>().is_err());
+        assert!("1622548800:xyz".parse::<SearchSession>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_session_fingerprint() {
+        let session = SearchSession::new(0, vec![("index", "split1"), ("index", "split2")]);
+        assert_eq!(
+            SearchSession::new(0, vec![("index", "split2"), ("index", "split1")]),
+            session
+        );
+        assert_ne!(SearchSession::new(0, vec![("index", "split1")]), session);
+        assert_ne!(
+            SearchSession::new(0, vec![("index", "split1"), ("other", "split2")]),
+            session
+        );
+    }
+}
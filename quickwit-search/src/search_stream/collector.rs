@@ -17,27 +17,63 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::marker::PhantomData;
 
 use tantivy::collector::{Collector, SegmentCollector};
-use tantivy::fastfield::{DynamicFastFieldReader, FastFieldReader, FastValue};
-use tantivy::schema::Field;
+use tantivy::fastfield::{DynamicFastFieldReader, FacetReader, FastFieldReader, FastValue};
+use tantivy::schema::{Facet, Field, Type};
 use tantivy::{DocId, Score, SegmentOrdinal, SegmentReader, TantivyError};
 
+use super::computed_fields::ComputedFields;
 use crate::filters::TimestampFilter;
+use crate::leaf::is_multivalued_fast_field;
+
+/// Reads the values of a fast field: one per document, or any number of them for the
+/// fast fields of array fields.
+#[derive(Clone)]
+pub enum FastFieldValuesReader<Item: FastValue> {
+    SingleValue(DynamicFastFieldReader<Item>),
+    MultiValues {
+        /// Offset of the first value of each document in `vals_reader`.
+        idx_reader: DynamicFastFieldReader<u64>,
+        vals_reader: DynamicFastFieldReader<Item>,
+    },
+}
+
+impl<Item: FastValue> FastFieldValuesReader<Item> {
+    /// Appends the values of the document `doc_id` to `values`.
+    pub fn append_values(&self, doc_id: DocId, values: &mut Vec<Item>) {
+        match self {
+            FastFieldValuesReader::SingleValue(fast_field_reader) => {
+                values.push(fast_field_reader.get(doc_id))
+            }
+            FastFieldValuesReader::MultiValues {
+                idx_reader,
+                vals_reader,
+            } => {
+                let start = idx_reader.get(doc_id);
+                let num_values = (idx_reader.get(doc_id + 1) - start) as usize;
+                let num_values_before = values.len();
+                values.resize(num_values_before + num_values, Item::make_zero());
+                vals_reader.get_range(start, &mut values[num_values_before..]);
+            }
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct FastFieldSegmentCollector<Item: FastValue> {
     fast_field_values: Vec<Item>,
-    fast_field_reader: DynamicFastFieldReader<Item>,
+    fast_field_reader: FastFieldValuesReader<Item>,
     timestamp_filter_opt: Option<TimestampFilter>,
 }
 
 impl<Item: FastValue> FastFieldSegmentCollector<Item> {
     pub fn new(
-        fast_field_reader: DynamicFastFieldReader<Item>,
+        fast_field_reader: FastFieldValuesReader<Item>,
         timestamp_filter_opt: Option<TimestampFilter>,
     ) -> Self {
         Self {
@@ -62,8 +98,8 @@ impl<Item: FastValue> SegmentCollector for FastFieldSegmentCollector<Item> {
         if !self.accept_document(doc_id) {
             return;
         }
-        let fast_field_value = self.fast_field_reader.get(doc_id);
-        self.fast_field_values.push(fast_field_value);
+        self.fast_field_reader
+            .append_values(doc_id, &mut self.fast_field_values);
     }
 
     fn harvest(self) -> Vec<Item> {
@@ -190,17 +226,20 @@ pub struct PartitionedFastFieldSegmentCollector<
     PartitionItem: FastValue + Eq + Hash,
 > {
     fast_field_values: std::collections::HashMap<PartitionItem, Vec<Item>>,
-    fast_field_reader: DynamicFastFieldReader<Item>,
-    partition_by_fast_field_reader: DynamicFastFieldReader<PartitionItem>,
+    fast_field_reader: FastFieldValuesReader<Item>,
+    partition_by_fast_field_reader: FastFieldValuesReader<PartitionItem>,
     timestamp_filter_opt: Option<TimestampFilter>,
+    // Values of the document being collected, reused from one document to the next.
+    doc_values: Vec<Item>,
+    doc_partition_values: Vec<PartitionItem>,
 }
 
 impl<Item: FastValue, PartitionItem: FastValue + Eq + Hash>
     PartitionedFastFieldSegmentCollector<Item, PartitionItem>
 {
     pub fn new(
-        fast_field_reader: DynamicFastFieldReader<Item>,
-        partition_by_fast_field_reader: DynamicFastFieldReader<PartitionItem>,
+        fast_field_reader: FastFieldValuesReader<Item>,
+        partition_by_fast_field_reader: FastFieldValuesReader<PartitionItem>,
         timestamp_filter_opt: Option<TimestampFilter>,
     ) -> Self {
         Self {
@@ -208,6 +247,8 @@ impl<Item: FastValue, PartitionItem: FastValue + Eq + Hash>
             fast_field_reader,
             partition_by_fast_field_reader,
             timestamp_filter_opt,
+            doc_values: Vec::new(),
+            doc_partition_values: Vec::new(),
         }
     }
 
@@ -228,13 +269,21 @@ impl<Item: FastValue, PartitionItem: FastValue + Hash + Eq> SegmentCollector
         if !self.accept_document(doc_id) {
             return;
         }
-        let fast_field_value = self.fast_field_reader.get(doc_id);
-        let fast_field_partition = &self.partition_by_fast_field_reader.get(doc_id);
-        if let Some(values) = self.fast_field_values.get_mut(fast_field_partition) {
-            values.push(fast_field_value);
-        } else {
+        self.doc_values.clear();
+        self.fast_field_reader
+            .append_values(doc_id, &mut self.doc_values);
+        if self.doc_values.is_empty() {
+            return;
+        }
+        self.doc_partition_values.clear();
+        self.partition_by_fast_field_reader
+            .append_values(doc_id, &mut self.doc_partition_values);
+        // The values of a document with several partition values belong to each partition.
+        for fast_field_partition in &self.doc_partition_values {
             self.fast_field_values
-                .insert(*fast_field_partition, vec![fast_field_value]);
+                .entry(*fast_field_partition)
+                .or_insert_with(Vec::new)
+                .extend_from_slice(&self.doc_values);
         }
     }
 
@@ -243,19 +292,312 @@ impl<Item: FastValue, PartitionItem: FastValue + Hash + Eq> SegmentCollector
     }
 }
 
+/// Values of the fast field of the collected documents, with their computed fields.
+#[derive(Debug, PartialEq)]
+pub struct ComputedRows<Item: FastValue> {
+    pub fast_field_values: Vec<Item>,
+    /// Values of the computed fields of the documents, one document after the other.
+    pub computed_values: Vec<i64>,
+}
+
+impl<Item: FastValue> Default for ComputedRows<Item> {
+    fn default() -> Self {
+        ComputedRows {
+            fast_field_values: Vec::new(),
+            computed_values: Vec::new(),
+        }
+    }
+}
+
+/// Collects the value of a single-valued fast field of each document, with the computed fields
+/// of the document.
+#[derive(Clone)]
+pub struct ComputedFieldsCollector<Item: FastValue> {
+    pub fast_field_to_collect: String,
+    pub computed_fields: ComputedFields,
+    pub timestamp_field_opt: Option<Field>,
+    pub start_timestamp_opt: Option<i64>,
+    pub end_timestamp_opt: Option<i64>,
+    pub _marker: PhantomData<Item>,
+}
+
+impl<Item: FastValue> Collector for ComputedFieldsCollector<Item> {
+    type Child = ComputedFieldsSegmentCollector<Item>;
+    type Fruit = ComputedRows<Item>;
+
+    fn for_segment(
+        &self,
+        _segment_ord: SegmentOrdinal,
+        segment_reader: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        let timestamp_filter_opt = helpers::make_timestamp_filter(
+            segment_reader,
+            self.timestamp_field_opt,
+            self.start_timestamp_opt,
+            self.end_timestamp_opt,
+        )?;
+        let fast_field_reader =
+            helpers::make_fast_field_reader::<Item>(segment_reader, &self.fast_field_to_collect)?;
+        let field_readers = self
+            .computed_fields
+            .field_names()
+            .iter()
+            .map(|field_name| helpers::make_i64_value_reader(segment_reader, field_name))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+        Ok(ComputedFieldsSegmentCollector {
+            rows: ComputedRows::default(),
+            fast_field_reader,
+            field_readers,
+            computed_fields: self.computed_fields.clone(),
+            timestamp_filter_opt,
+            doc_field_values: Vec::new(),
+            error_opt: None,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        // We do not need BM25 scoring in Quickwit.
+        false
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<Result<ComputedRows<Item>, String>>,
+    ) -> tantivy::Result<Self::Fruit> {
+        let mut rows = ComputedRows::default();
+        for segment_rows in segment_fruits {
+            let segment_rows = segment_rows.map_err(TantivyError::InvalidArgument)?;
+            rows.fast_field_values
+                .extend(segment_rows.fast_field_values);
+            rows.computed_values.extend(segment_rows.computed_values);
+        }
+        Ok(rows)
+    }
+}
+
+/// Reads the values of a single-valued `i64`, `u64` or `date` fast field as `i64`.
+#[derive(Clone)]
+pub struct I64ValueReader {
+    fast_field_reader: DynamicFastFieldReader<u64>,
+    value_type: Type,
+}
+
+impl I64ValueReader {
+    fn get(&self, doc_id: DocId) -> i64 {
+        let value = self.fast_field_reader.get(doc_id);
+        match self.value_type {
+            Type::U64 => value as i64,
+            _ => i64::from_u64(value),
+        }
+    }
+}
+
+pub struct ComputedFieldsSegmentCollector<Item: FastValue> {
+    rows: ComputedRows<Item>,
+    fast_field_reader: FastFieldValuesReader<Item>,
+    field_readers: Vec<I64ValueReader>,
+    computed_fields: ComputedFields,
+    timestamp_filter_opt: Option<TimestampFilter>,
+    // Values of the fast fields of the document being collected, reused from one document to
+    // the next.
+    doc_field_values: Vec<i64>,
+    // The first evaluation error, which fails the search once the segment is harvested.
+    error_opt: Option<String>,
+}
+
+impl<Item: FastValue> SegmentCollector for ComputedFieldsSegmentCollector<Item> {
+    type Fruit = Result<ComputedRows<Item>, String>;
+
+    fn collect(&mut self, doc_id: DocId, _score: Score) {
+        if self.error_opt.is_some() {
+            return;
+        }
+        if let Some(ref timestamp_filter) = self.timestamp_filter_opt {
+            if !timestamp_filter.is_within_range(doc_id) {
+                return;
+            }
+        }
+        self.doc_field_values.clear();
+        self.doc_field_values.extend(
+            self.field_readers
+                .iter()
+                .map(|field_reader| field_reader.get(doc_id)),
+        );
+        let num_computed_values = self.rows.computed_values.len();
+        if let Err(error) = self
+            .computed_fields
+            .eval(&self.doc_field_values, &mut self.rows.computed_values)
+        {
+            self.rows.computed_values.truncate(num_computed_values);
+            self.error_opt = Some(format!("{:#}", error));
+            return;
+        }
+        self.fast_field_reader
+            .append_values(doc_id, &mut self.rows.fast_field_values);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        match self.error_opt {
+            Some(error) => Err(error),
+            None => Ok(self.rows),
+        }
+    }
+}
+
+/// Collects the values of a text fast field, by decoding the term ordinals held by the fast
+/// field of its hidden facet field with the facet dictionary.
+#[derive(Clone)]
+pub struct StringFastFieldCollector {
+    pub dictionary_field: Field,
+    pub timestamp_field_opt: Option<Field>,
+    pub start_timestamp_opt: Option<i64>,
+    pub end_timestamp_opt: Option<i64>,
+}
+
+impl Collector for StringFastFieldCollector {
+    type Child = StringFastFieldSegmentCollector;
+    type Fruit = Vec<String>;
+
+    fn for_segment(
+        &self,
+        _segment_ord: SegmentOrdinal,
+        segment_reader: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        let timestamp_filter_opt = helpers::make_timestamp_filter(
+            segment_reader,
+            self.timestamp_field_opt,
+            self.start_timestamp_opt,
+            self.end_timestamp_opt,
+        )?;
+        let facet_reader = segment_reader.facet_reader(self.dictionary_field)?;
+        Ok(StringFastFieldSegmentCollector {
+            term_ords: Vec::new(),
+            facet_reader,
+            timestamp_filter_opt,
+            doc_term_ords: Vec::new(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        // We do not need BM25 scoring in Quickwit.
+        false
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<tantivy::Result<Vec<String>>>,
+    ) -> tantivy::Result<Self::Fruit> {
+        let mut values = Vec::new();
+        for segment_values in segment_fruits {
+            values.extend(segment_values?);
+        }
+        Ok(values)
+    }
+}
+
+pub struct StringFastFieldSegmentCollector {
+    // Term ordinal of the value of each collected document, `None` for the documents without
+    // value.
+    term_ords: Vec<Option<u64>>,
+    facet_reader: FacetReader,
+    timestamp_filter_opt: Option<TimestampFilter>,
+    // Term ordinals of the document being collected, reused from one document to the next.
+    doc_term_ords: Vec<u64>,
+}
+
+impl SegmentCollector for StringFastFieldSegmentCollector {
+    type Fruit = tantivy::Result<Vec<String>>;
+
+    fn collect(&mut self, doc_id: DocId, _score: Score) {
+        if let Some(ref timestamp_filter) = self.timestamp_filter_opt {
+            if !timestamp_filter.is_within_range(doc_id) {
+                return;
+            }
+        }
+        self.doc_term_ords.clear();
+        self.facet_reader
+            .facet_ords(doc_id, &mut self.doc_term_ords);
+        self.term_ords.push(self.doc_term_ords.first().copied());
+    }
+
+    fn harvest(mut self) -> Self::Fruit {
+        // Each distinct value is looked up once in the dictionary, which is what makes
+        // low-cardinality fields cheap to export.
+        let mut values_by_term_ord: HashMap<u64, String> = HashMap::new();
+        let mut facet = Facet::root();
+        let mut values = Vec::with_capacity(self.term_ords.len());
+        for term_ord_opt in self.term_ords {
+            let term_ord = match term_ord_opt {
+                Some(term_ord) => term_ord,
+                None => {
+                    values.push(String::new());
+                    continue;
+                }
+            };
+            let value = match values_by_term_ord.entry(term_ord) {
+                Entry::Occupied(entry) => entry.get().clone(),
+                Entry::Vacant(entry) => {
+                    self.facet_reader.facet_from_ord(term_ord, &mut facet)?;
+                    // The facets of the dictionary have a single step: the original value.
+                    entry.insert(facet.to_path().concat()).clone()
+                }
+            };
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
+
 mod helpers {
     use super::*;
 
+    pub fn make_i64_value_reader(
+        segment_reader: &SegmentReader,
+        field_name: &str,
+    ) -> tantivy::Result<I64ValueReader> {
+        let field = segment_reader
+            .schema()
+            .get_field(field_name)
+            .ok_or_else(|| {
+                TantivyError::SchemaError(format!("Field `{}` does not exist.", field_name))
+            })?;
+        let field_entry = segment_reader.schema().get_field_entry(field);
+        let value_type = field_entry.field_type().value_type();
+        if !field_entry.is_fast()
+            || is_multivalued_fast_field(field_entry)
+            || !matches!(value_type, Type::I64 | Type::U64 | Type::Date)
+        {
+            return Err(TantivyError::SchemaError(format!(
+                "Field `{}` is not a single-valued i64, u64 or date fast field.",
+                field_name
+            )));
+        }
+        let fast_field_slice = segment_reader.fast_fields().fast_field_data(field, 0)?;
+        Ok(I64ValueReader {
+            fast_field_reader: DynamicFastFieldReader::open(fast_field_slice)?,
+            value_type,
+        })
+    }
+
     pub fn make_fast_field_reader<T: FastValue>(
         segment_reader: &SegmentReader,
         fast_field_to_collect: &str,
-    ) -> tantivy::Result<DynamicFastFieldReader<T>> {
+    ) -> tantivy::Result<FastFieldValuesReader<T>> {
         let field = segment_reader
             .schema()
             .get_field(fast_field_to_collect)
             .ok_or_else(|| TantivyError::SchemaError("field does not exist".to_owned()))?;
         let fast_field_slice = segment_reader.fast_fields().fast_field_data(field, 0)?;
-        DynamicFastFieldReader::open(fast_field_slice)
+        if !is_multivalued_fast_field(segment_reader.schema().get_field_entry(field)) {
+            return Ok(FastFieldValuesReader::SingleValue(
+                DynamicFastFieldReader::open(fast_field_slice)?,
+            ));
+        }
+        let vals_slice = segment_reader.fast_fields().fast_field_data(field, 1)?;
+        Ok(FastFieldValuesReader::MultiValues {
+            idx_reader: DynamicFastFieldReader::open(fast_field_slice)?,
+            vals_reader: DynamicFastFieldReader::open(vals_slice)?,
+        })
     }
 
     pub fn make_timestamp_filter(
@@ -0,0 +1,331 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Columns computed from the fast fields of the documents of a search stream.
+//!
+//! A computed field is defined as `name = expression`, where the expression combines fast
+//! fields and integer literals with `+`, `-`, `*`, `/`, `%` and parentheses, e.g.
+//! `latency_ms = latency_us / 1000` or `minute = ts - ts % 60000`. Computed fields are
+//! evaluated as `i64` by the leaves: the division truncates toward zero, and the other
+//! operations wrap on overflow.
+
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use anyhow::{bail, Context};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Literal(i64),
+    /// Index of the field in the field names of the `ComputedFields`.
+    Field(usize),
+    Neg(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Number(i64),
+    Ident(&'a str),
+    Operator(Operator),
+    OpenParen,
+    CloseParen,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn tokenize(expression: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars: Peekable<CharIndices> = expression.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            ' ' | '\t' => continue,
+            '+' => Token::Operator(Operator::Add),
+            '-' => Token::Operator(Operator::Sub),
+            '*' => Token::Operator(Operator::Mul),
+            '/' => Token::Operator(Operator::Div),
+            '%' => Token::Operator(Operator::Rem),
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            c if is_ident_char(c) => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(position, next_c)) = chars.peek() {
+                    if !is_ident_char(next_c) {
+                        break;
+                    }
+                    end = position + next_c.len_utf8();
+                    chars.next();
+                }
+                let word = &expression[start..end];
+                if word.starts_with(|c: char| c.is_ascii_digit()) {
+                    let number = word
+                        .parse::<i64>()
+                        .with_context(|| format!("Invalid integer `{}`.", word))?;
+                    Token::Number(number)
+                } else {
+                    Token::Ident(word)
+                }
+            }
+            _ => bail!("Unexpected character `{}`.", c),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser of the expressions, which binds `*`, `/` and `%` tighter than
+/// `+` and `-`.
+struct Parser<'a, 'b> {
+    tokens: &'b [Token<'a>],
+    position: usize,
+    field_names: &'b mut Vec<String>,
+}
+
+impl<'a, 'b> Parser<'a, 'b> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.position)
+    }
+
+    /// Returns the next token if it is one of `operators`.
+    fn peek_operator(&self, operators: &[Operator]) -> Option<Operator> {
+        match self.peek() {
+            Some(Token::Operator(operator)) if operators.contains(operator) => Some(*operator),
+            _ => None,
+        }
+    }
+
+    fn next_token(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn parse_sum(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.parse_product()?;
+        while let Some(operator) = self.peek_operator(&[Operator::Add, Operator::Sub]) {
+            self.position += 1;
+            let right = self.parse_product()?;
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn parse_product(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.parse_factor()?;
+        while let Some(operator) =
+            self.peek_operator(&[Operator::Mul, Operator::Div, Operator::Rem])
+        {
+            self.position += 1;
+            let right = self.parse_factor()?;
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn parse_factor(&mut self) -> anyhow::Result<Expr> {
+        match self.next_token() {
+            Some(Token::Number(number)) => Ok(Expr::Literal(number)),
+            Some(Token::Ident(field_name)) => {
+                let field_idx = match self.field_names.iter().position(|name| name == field_name) {
+                    Some(field_idx) => field_idx,
+                    None => {
+                        self.field_names.push(field_name.to_string());
+                        self.field_names.len() - 1
+                    }
+                };
+                Ok(Expr::Field(field_idx))
+            }
+            Some(Token::Operator(Operator::Sub)) => Ok(Expr::Neg(Box::new(self.parse_factor()?))),
+            Some(Token::OpenParen) => {
+                let expr = self.parse_sum()?;
+                if self.next_token() != Some(Token::CloseParen) {
+                    bail!("Missing closing parenthesis.");
+                }
+                Ok(expr)
+            }
+            Some(token) => bail!("Unexpected token {:?}.", token),
+            None => bail!("Unexpected end of expression."),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, field_values: &[i64]) -> anyhow::Result<i64> {
+        let value = match self {
+            Expr::Literal(value) => *value,
+            Expr::Field(field_idx) => field_values[*field_idx],
+            Expr::Neg(expr) => expr.eval(field_values)?.wrapping_neg(),
+            Expr::Binary(operator, left, right) => {
+                let left = left.eval(field_values)?;
+                let right = right.eval(field_values)?;
+                match operator {
+                    Operator::Add => left.wrapping_add(right),
+                    Operator::Sub => left.wrapping_sub(right),
+                    Operator::Mul => left.wrapping_mul(right),
+                    Operator::Div if right == 0 => bail!("Division by zero."),
+                    Operator::Div => left.wrapping_div(right),
+                    Operator::Rem if right == 0 => bail!("Division by zero."),
+                    Operator::Rem => left.wrapping_rem(right),
+                }
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// The computed fields of a search stream request.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ComputedFields {
+    computed_fields: Vec<(String, Expr)>,
+    /// Names of the fast fields the expressions read.
+    field_names: Vec<String>,
+}
+
+impl ComputedFields {
+    /// Parses the definitions of the computed fields, given as `name = expression`.
+    pub fn parse(definitions: &[String]) -> anyhow::Result<Self> {
+        let mut computed_fields = Vec::new();
+        let mut field_names = Vec::new();
+        for definition in definitions {
+            let (name, expression) = definition.split_once('=').with_context(|| {
+                format!(
+                    "Invalid computed field `{}`, expected `name = expression`.",
+                    definition
+                )
+            })?;
+            let name = name.trim();
+            if name.is_empty() || !name.chars().all(is_ident_char) {
+                bail!("Invalid name `{}` for a computed field.", name);
+            }
+            let tokens = tokenize(expression)
+                .with_context(|| format!("Invalid computed field `{}`.", definition))?;
+            let mut parser = Parser {
+                tokens: &tokens,
+                position: 0,
+                field_names: &mut field_names,
+            };
+            let expr = parser
+                .parse_sum()
+                .and_then(|expr| {
+                    if let Some(token) = parser.peek() {
+                        bail!("Unexpected token {:?}.", token);
+                    }
+                    Ok(expr)
+                })
+                .with_context(|| format!("Invalid computed field `{}`.", definition))?;
+            computed_fields.push((name.to_string(), expr));
+        }
+        Ok(ComputedFields {
+            computed_fields,
+            field_names,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.computed_fields.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.computed_fields.len()
+    }
+
+    /// Returns the names of the computed fields, in the order of their columns.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.computed_fields.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Returns the names of the fast fields read by the expressions.
+    pub fn field_names(&self) -> &[String] {
+        &self.field_names
+    }
+
+    /// Appends the value of each computed field to `computed_values`, given the values of
+    /// the fast fields of a document in the order of `field_names`.
+    pub fn eval(&self, field_values: &[i64], computed_values: &mut Vec<i64>) -> anyhow::Result<()> {
+        for (name, expr) in &self.computed_fields {
+            let value = expr
+                .eval(field_values)
+                .with_context(|| format!("Failed to compute the field `{}`.", name))?;
+            computed_values.push(value);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(definition: &str, field_values: &[i64]) -> anyhow::Result<i64> {
+        let computed_fields = ComputedFields::parse(&[definition.to_string()])?;
+        let mut computed_values = Vec::new();
+        computed_fields.eval(field_values, &mut computed_values)?;
+        Ok(computed_values[0])
+    }
+
+    #[test]
+    fn test_computed_fields_eval() -> anyhow::Result<()> {
+        assert_eq!(eval("latency_ms = latency_us / 1000", &[12_345])?, 12);
+        assert_eq!(eval("minute = ts - ts % 60000", &[123_456])?, 120_000);
+        assert_eq!(eval("x = 1 + 2 * 3", &[])?, 7);
+        assert_eq!(eval("x = (1 + 2) * 3", &[])?, 9);
+        assert_eq!(eval("x = 10 - 4 - 3", &[])?, 3);
+        assert_eq!(eval("x = -a * 2", &[4])?, -8);
+        assert_eq!(eval("x = a.b + a.b", &[4])?, 8);
+        assert!(eval("x = a / 0", &[4]).is_err());
+        assert!(eval("x = a % (b - b)", &[4, 1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_computed_fields_parse() -> anyhow::Result<()> {
+        let computed_fields = ComputedFields::parse(&[
+            "bucket = ts - ts % 60".to_string(),
+            "total=sent+received".to_string(),
+        ])?;
+        assert_eq!(computed_fields.len(), 2);
+        assert_eq!(
+            computed_fields.names().collect::<Vec<_>>(),
+            vec!["bucket", "total"]
+        );
+        assert_eq!(computed_fields.field_names(), &["ts", "sent", "received"]);
+        let mut computed_values = Vec::new();
+        computed_fields.eval(&[125, 3, 4], &mut computed_values)?;
+        assert_eq!(computed_values, vec![120, 7]);
+
+        assert!(ComputedFields::parse(&["latency".to_string()]).is_err());
+        assert!(ComputedFields::parse(&[" = 1".to_string()]).is_err());
+        assert!(ComputedFields::parse(&["x = (1 + 2".to_string()]).is_err());
+        assert!(ComputedFields::parse(&["x = 1 2".to_string()]).is_err());
+        assert!(ComputedFields::parse(&["x = 1 ^ 2".to_string()]).is_err());
+        assert!(ComputedFields::parse(&["x = 1 +".to_string()]).is_err());
+        Ok(())
+    }
+}
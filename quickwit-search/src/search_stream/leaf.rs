@@ -23,7 +23,7 @@ use std::marker::PhantomData;
 use std::sync::Arc;
 
 use futures::{FutureExt, StreamExt};
-use quickwit_index_config::IndexConfig;
+use quickwit_index_config::{string_fast_field_name, IndexConfig};
 use quickwit_proto::{
     LeafSearchStreamResult, OutputFormat, SearchRequest, SearchStreamRequest,
     SplitIdAndFooterOffsets,
@@ -33,14 +33,20 @@ use tantivy::fastfield::FastValue;
 use tantivy::query::Query;
 use tantivy::schema::{Field, Schema, Type};
 use tantivy::{LeasedItem, ReloadPolicy, Searcher};
-use tokio::task::spawn_blocking;
-use tokio_stream::wrappers::UnboundedReceiverStream;
 use tracing::*;
 
-use super::collector::{PartionnedFastFieldCollector, PartitionValues};
-use super::FastFieldCollector;
-use crate::leaf::{open_index, warmup};
-use crate::{Result, SearchError};
+use super::collector::{
+    ComputedFieldsCollector, ComputedRows, PartionnedFastFieldCollector, PartitionValues,
+    StringFastFieldCollector,
+};
+use super::computed_fields::ComputedFields;
+use super::{
+    result_channel, spawn_until_receiver_dropped, FastFieldCollector, ResultReceiverStream,
+};
+use crate::distinct_count::HyperLogLog;
+use crate::leaf::{cache_policy, is_multivalued_fast_field, open_index, warmup};
+use crate::search_thread_pool::{global_search_thread_pool, QueryThreadBudget};
+use crate::{lease_splits, Result, SearchError};
 
 // TODO: buffer of 5 seems to be sufficient to do the job locally, needs to be tested on a cluster.
 const CONCURRENT_SPLIT_SEARCH_STREAM: usize = 5;
@@ -57,15 +63,20 @@ pub async fn leaf_search_stream(
     storage: Arc<dyn Storage>,
     splits: Vec<SplitIdAndFooterOffsets>,
     index_config: Arc<dyn IndexConfig>,
-) -> UnboundedReceiverStream<crate::Result<LeafSearchStreamResult>> {
-    let (result_sender, result_receiver) = tokio::sync::mpsc::unbounded_channel();
+) -> ResultReceiverStream {
+    let (result_sender, result_receiver) = result_channel();
     let span = info_span!("leaf_search_stream",);
-    tokio::spawn(
+    let split_lease = lease_splits(splits.iter().map(|split| split.split_id.as_str()));
+    spawn_until_receiver_dropped(result_sender, |result_sender| {
         async move {
+            // The lease is released once all the splits have been streamed.
+            let _split_lease = split_lease;
             let mut stream =
                 leaf_search_results_stream(request, storage, splits, index_config).await;
+            // The next splits are only searched once the client has made room for their
+            // results.
             while let Some(item) = stream.next().await {
-                if let Err(error) = result_sender.send(item) {
+                if let Err(error) = result_sender.send(item).await {
                     error!(
                         "Failed to send leaf search stream result. Stop sending. Cause: {}",
                         error
@@ -74,9 +85,9 @@ pub async fn leaf_search_stream(
                 }
             }
         }
-        .instrument(span),
-    );
-    UnboundedReceiverStream::new(result_receiver)
+        .instrument(span)
+    });
+    result_receiver
 }
 
 async fn leaf_search_results_stream(
@@ -85,6 +96,7 @@ async fn leaf_search_results_stream(
     splits: Vec<SplitIdAndFooterOffsets>,
     index_config: Arc<dyn IndexConfig>,
 ) -> impl futures::Stream<Item = crate::Result<LeafSearchStreamResult>> + Sync + Send + 'static {
+    let thread_budget = global_search_thread_pool().query_budget();
     futures::stream::iter(splits)
         .map(move |split| {
             leaf_search_stream_single_split(
@@ -92,6 +104,7 @@ async fn leaf_search_results_stream(
                 index_config.clone(),
                 request.clone(),
                 storage.clone(),
+                thread_budget.clone(),
             )
             .shared()
         })
@@ -99,14 +112,15 @@ async fn leaf_search_results_stream(
 }
 
 /// Apply a leaf search on a single split.
-#[instrument(fields(split_id = %split.split_id), skip(split, index_config, stream_request, storage))]
+#[instrument(fields(split_id = %split.split_id), skip(split, index_config, stream_request, storage, thread_budget))]
 async fn leaf_search_stream_single_split(
     split: SplitIdAndFooterOffsets,
     index_config: Arc<dyn IndexConfig>,
     stream_request: SearchStreamRequest,
     storage: Arc<dyn Storage>,
+    thread_budget: QueryThreadBudget,
 ) -> crate::Result<LeafSearchStreamResult> {
-    let index = open_index(storage, &split).await?;
+    let index = open_index(storage, &split, cache_policy(stream_request.cache_policy)).await?;
     let split_schema = index.schema();
 
     let request_fields = Arc::new(SearchStreamRequestFields::from_request(
@@ -119,6 +133,22 @@ async fn leaf_search_stream_single_split(
         SearchError::InternalError("Invalid output format specified.".to_string())
     })?;
 
+    if request_fields.partition_by_fast_field.is_some() && stream_request.distinct_count {
+        return Err(SearchError::InvalidQuery(
+            "Distinct counts cannot be computed when you provide a partition-by field.".to_string(),
+        ));
+    }
+
+    if !request_fields.computed_fields.is_empty()
+        && (request_fields.partition_by_fast_field.is_some() || stream_request.distinct_count)
+    {
+        return Err(SearchError::InvalidQuery(
+            "Computed fields cannot be streamed when you provide a partition-by field or request \
+             a distinct count."
+                .to_string(),
+        ));
+    }
+
     if request_fields.partition_by_fast_field.is_some()
         && output_format != OutputFormat::ClickHouseRowBinary
     {
@@ -129,14 +159,27 @@ async fn leaf_search_stream_single_split(
         ));
     }
 
+    if request_fields.dictionary_field.is_some()
+        && (request_fields.partition_by_fast_field.is_some()
+            || stream_request.distinct_count
+            || !request_fields.computed_fields.is_empty()
+            || output_format == OutputFormat::Parquet)
+    {
+        return Err(SearchError::InvalidQuery(format!(
+            "The text fast field `{}` can only be streamed on its own, in the CSV or ClickHouse \
+             formats.",
+            request_fields.fast_field_name()
+        )));
+    }
+
     let search_request = Arc::new(SearchRequest::from(stream_request.clone()));
-    let query = index_config.query(split_schema.clone(), &search_request)?;
     let reader = index
         .reader_builder()
         .num_searchers(1)
         .reload_policy(ReloadPolicy::Manual)
         .try_into()?;
     let searcher = reader.searcher();
+    let query = index_config.split_query(&*searcher, &search_request)?;
     warmup(
         &*searcher,
         query.as_ref(),
@@ -152,8 +195,84 @@ async fn leaf_search_stream_single_split(
 
     let _ = span.enter();
     let m_request_fields = request_fields.clone();
-    let collect_handle = spawn_blocking(move || {
+    let collect_handle = thread_budget.spawn_blocking(move || {
+        if stream_request.distinct_count {
+            return collect_distinct_count_sketch(
+                &m_request_fields,
+                stream_request.start_timestamp,
+                stream_request.end_timestamp,
+                searcher,
+                query.as_ref(),
+            );
+        }
         let mut buffer = Vec::new();
+        if let Some(dictionary_field) = m_request_fields.dictionary_field {
+            let collector = StringFastFieldCollector {
+                dictionary_field,
+                timestamp_field_opt: m_request_fields.timestamp_field,
+                start_timestamp_opt: stream_request.start_timestamp,
+                end_timestamp_opt: stream_request.end_timestamp,
+            };
+            let collected_values = searcher.search(query.as_ref(), &collector)?;
+            super::serialize_strings(
+                &collected_values,
+                &stream_request.fast_field,
+                &mut buffer,
+                output_format,
+            )
+            .map_err(|_| {
+                SearchError::InternalError("Error when serializing text during export".to_owned())
+            })?;
+            return Result::<Vec<u8>>::Ok(buffer);
+        }
+        if !m_request_fields.computed_fields.is_empty() {
+            let serialize_result = match m_request_fields.fast_field_types() {
+                (Type::I64, None) => {
+                    let rows = collect_computed_rows::<i64>(
+                        &m_request_fields,
+                        stream_request.start_timestamp,
+                        stream_request.end_timestamp,
+                        searcher,
+                        query.as_ref(),
+                    )?;
+                    super::serialize_with_computed_fields::<i64>(
+                        &rows,
+                        &m_request_fields.computed_fields,
+                        &stream_request.fast_field,
+                        &mut buffer,
+                        output_format,
+                    )
+                }
+                (Type::U64, None) => {
+                    let rows = collect_computed_rows::<u64>(
+                        &m_request_fields,
+                        stream_request.start_timestamp,
+                        stream_request.end_timestamp,
+                        searcher,
+                        query.as_ref(),
+                    )?;
+                    super::serialize_with_computed_fields::<u64>(
+                        &rows,
+                        &m_request_fields.computed_fields,
+                        &stream_request.fast_field,
+                        &mut buffer,
+                        output_format,
+                    )
+                }
+                (value_type, _) => {
+                    return Err(SearchError::InvalidQuery(format!(
+                        "Computed fields cannot be streamed with a fast field of type {:?}.",
+                        value_type
+                    )));
+                }
+            };
+            serialize_result.map_err(|_| {
+                SearchError::InternalError(
+                    "Error when serializing computed fields during export".to_owned(),
+                )
+            })?;
+            return Result::<Vec<u8>>::Ok(buffer);
+        }
         match m_request_fields.fast_field_types() {
             (Type::I64, None) => {
                 let collected_values = collect_values::<i64>(
@@ -163,13 +282,17 @@ async fn leaf_search_stream_single_split(
                     searcher,
                     query.as_ref(),
                 )?;
-                super::serialize::<i64>(&collected_values, &mut buffer, output_format).map_err(
-                    |_| {
-                        SearchError::InternalError(
-                            "Error when serializing i64 during export".to_owned(),
-                        )
-                    },
-                )?;
+                super::serialize::<i64>(
+                    &collected_values,
+                    &stream_request.fast_field,
+                    &mut buffer,
+                    output_format,
+                )
+                .map_err(|_| {
+                    SearchError::InternalError(
+                        "Error when serializing i64 during export".to_owned(),
+                    )
+                })?;
             }
             (Type::U64, None) => {
                 let collected_values = collect_values::<u64>(
@@ -179,13 +302,17 @@ async fn leaf_search_stream_single_split(
                     searcher,
                     query.as_ref(),
                 )?;
-                super::serialize::<u64>(&collected_values, &mut buffer, output_format).map_err(
-                    |_| {
-                        SearchError::InternalError(
-                            "Error when serializing u64 during export".to_owned(),
-                        )
-                    },
-                )?;
+                super::serialize::<u64>(
+                    &collected_values,
+                    &stream_request.fast_field,
+                    &mut buffer,
+                    output_format,
+                )
+                .map_err(|_| {
+                    SearchError::InternalError(
+                        "Error when serializing u64 during export".to_owned(),
+                    )
+                })?;
             }
             (Type::I64, Some(Type::I64)) => {
                 let collected_values = collect_partitioned_values::<i64, i64>(
@@ -255,6 +382,67 @@ fn collect_values<TFastValue: FastValue>(
     Ok(result)
 }
 
+fn collect_computed_rows<TFastValue: FastValue>(
+    request_fields: &SearchStreamRequestFields,
+    start_timestamp: Option<i64>,
+    end_timestamp: Option<i64>,
+    searcher: LeasedItem<Searcher>,
+    query: &dyn Query,
+) -> crate::Result<ComputedRows<TFastValue>> {
+    let collector = ComputedFieldsCollector::<TFastValue> {
+        fast_field_to_collect: request_fields.fast_field_name().to_string(),
+        computed_fields: request_fields.computed_fields.clone(),
+        timestamp_field_opt: request_fields.timestamp_field,
+        start_timestamp_opt: start_timestamp,
+        end_timestamp_opt: end_timestamp,
+        _marker: PhantomData,
+    };
+    let result = searcher.search(query, &collector)?;
+    Ok(result)
+}
+
+/// Returns the registers of the HyperLogLog sketch of the values of the fast field.
+///
+/// The root merges the sketches of all the splits to estimate the distinct count.
+fn collect_distinct_count_sketch(
+    request_fields: &SearchStreamRequestFields,
+    start_timestamp: Option<i64>,
+    end_timestamp: Option<i64>,
+    searcher: LeasedItem<Searcher>,
+    query: &dyn Query,
+) -> crate::Result<Vec<u8>> {
+    let values: Vec<u64> = match request_fields.fast_field_types().0 {
+        Type::I64 => collect_values::<i64>(
+            request_fields,
+            start_timestamp,
+            end_timestamp,
+            searcher,
+            query,
+        )?
+        .into_iter()
+        .map(|value| value.as_u64())
+        .collect(),
+        Type::U64 => collect_values::<u64>(
+            request_fields,
+            start_timestamp,
+            end_timestamp,
+            searcher,
+            query,
+        )?,
+        value_type => {
+            return Err(SearchError::InvalidQuery(format!(
+                "Distinct counts are not supported on fast fields of type {:?}.",
+                value_type
+            )));
+        }
+    };
+    let mut hyperloglog = HyperLogLog::default();
+    for value in values {
+        hyperloglog.insert(value);
+    }
+    Ok(hyperloglog.into_registers())
+}
+
 fn collect_partitioned_values<TFastValue: FastValue, TPartitionValue: FastValue + Eq + Hash>(
     request_fields: &SearchStreamRequestFields,
     start_timestamp_opt: Option<i64>,
@@ -281,8 +469,12 @@ fn collect_partitioned_values<TFastValue: FastValue, TPartitionValue: FastValue
 // TODO move to owned values, implement Send + Sync
 struct SearchStreamRequestFields {
     fast_field: Field,
+    /// Hidden facet field holding the dictionary encoded values of the streamed field, when it
+    /// is a text fast field.
+    dictionary_field: Option<Field>,
     partition_by_fast_field: Option<Field>,
     timestamp_field: Option<Field>,
+    computed_fields: ComputedFields,
     schema: Schema,
 }
 
@@ -318,7 +510,9 @@ impl<'a> SearchStreamRequestFields {
                 ))
             })?;
 
-        if !Self::is_fast_field(schema, &fast_field) {
+        let dictionary_field =
+            schema.get_field(&string_fast_field_name(&stream_request.fast_field));
+        if dictionary_field.is_none() && !Self::is_fast_field(schema, &fast_field) {
             return Err(SearchError::InvalidQuery(format!(
                 "Field `{}` is not a fast field",
                 &stream_request.fast_field
@@ -340,14 +534,61 @@ impl<'a> SearchStreamRequestFields {
             )));
         }
 
+        let computed_fields = Self::computed_fields(stream_request, schema, fast_field)?;
+
         Ok(SearchStreamRequestFields {
             schema: schema.to_owned(),
             fast_field,
+            dictionary_field,
             partition_by_fast_field,
             timestamp_field,
+            computed_fields,
         })
     }
 
+    /// Parses the computed fields of the request, and checks that the fast fields they read
+    /// can be evaluated as `i64` values.
+    fn computed_fields(
+        stream_request: &SearchStreamRequest,
+        schema: &Schema,
+        fast_field: Field,
+    ) -> crate::Result<ComputedFields> {
+        let computed_fields = ComputedFields::parse(&stream_request.computed_fields)
+            .map_err(|error| SearchError::InvalidQuery(format!("{:#}", error)))?;
+        if computed_fields.is_empty() {
+            return Ok(computed_fields);
+        }
+        if is_multivalued_fast_field(schema.get_field_entry(fast_field)) {
+            return Err(SearchError::InvalidQuery(format!(
+                "Computed fields cannot be streamed with the array field `{}`.",
+                &stream_request.fast_field
+            )));
+        }
+        for field_name in computed_fields.field_names() {
+            let field = schema.get_field(field_name).ok_or_else(|| {
+                SearchError::InvalidQuery(format!(
+                    "Field `{}` does not exist in schema",
+                    field_name
+                ))
+            })?;
+            let field_entry = schema.get_field_entry(field);
+            if !field_entry.is_fast()
+                || is_multivalued_fast_field(field_entry)
+                || !matches!(
+                    field_entry.field_type().value_type(),
+                    Type::I64 | Type::U64 | Type::Date
+                )
+            {
+                return Err(SearchError::InvalidQuery(format!(
+                    "Field `{}` is not a single-valued i64, u64 or date fast field, it cannot be \
+                     read by a computed field.",
+                    field_name
+                )));
+            }
+        }
+        Ok(computed_fields)
+    }
+
     pub fn fast_field_types(&self) -> (Type, Option<Type>) {
         (
             self.schema
@@ -361,13 +602,16 @@ impl<'a> SearchStreamRequestFields {
 
     pub fn fast_fields_for_request(&self) -> HashSet<String> {
         let mut set = HashSet::new();
-        set.insert(self.fast_field_name().to_string());
+        // The values of a text fast field are read from the fast field of its dictionary.
+        let streamed_field = self.dictionary_field.unwrap_or(self.fast_field);
+        set.insert(self.schema.get_field_name(streamed_field).to_string());
         if let Some(timestamp_field) = self.timestamp_field_name() {
             set.insert(timestamp_field.to_string());
         }
         if let Some(partition_by_fast_field) = self.partition_by_fast_field_name() {
             set.insert(partition_by_fast_field.to_string());
         }
+        set.extend(self.computed_fields.field_names().iter().cloned());
         set
     }
 
@@ -402,6 +646,7 @@ mod tests {
     use serde_json::json;
 
     use super::*;
+    use crate::distinct_count::estimate_distinct_count_from_bytes;
 
     #[tokio::test]
     async fn test_leaf_search_stream_to_csv_output_with_filtering() -> anyhow::Result<()> {
@@ -448,6 +693,13 @@ mod tests {
             output_format: 0,
             partition_by_field: None,
             tags: vec![],
+            distinct_count: false,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: Vec::new(),
+            cache_policy: 0,
         };
         let index_metadata = test_sandbox.metastore().index_metadata(index_id).await?;
         let splits = test_sandbox.metastore().list_all_splits(index_id).await?;
@@ -457,6 +709,9 @@ mod tests {
                 split_id: split_meta.split_metadata.split_id,
                 split_footer_start: split_meta.footer_offsets.start,
                 split_footer_end: split_meta.footer_offsets.end,
+                time_range_end: None,
+                split_file: String::new(),
+                split_part_num_bytes: None,
             })
             .collect();
         let mut single_node_stream = leaf_search_stream(
@@ -476,6 +731,360 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_leaf_search_stream_text_fast_field() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "timestamp_field": "ts",
+            "tag_fields": [],
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "level",
+                    "type": "text",
+                    "tokenizer": "raw",
+                    "fast": true
+                },
+                {
+                    "name": "ts",
+                    "type": "i64",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let index_config =
+            Arc::new(serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?);
+        let index_id = "single-node-text-fast-field";
+        let test_sandbox = TestSandbox::create(index_id, index_config.clone()).await?;
+        let levels = ["info", "warn", "error"];
+        let docs = (0..6)
+            .map(|i| json!({"body": "info", "level": levels[i % 3], "ts": i}))
+            .collect();
+        test_sandbox.add_documents(docs).await?;
+
+        let mut request = SearchStreamRequest {
+            index_id: index_id.to_string(),
+            query: "info".to_string(),
+            search_fields: vec![],
+            start_timestamp: None,
+            end_timestamp: None,
+            fast_field: "level".to_string(),
+            output_format: OutputFormat::Csv as i32,
+            partition_by_field: None,
+            tags: vec![],
+            distinct_count: false,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: Vec::new(),
+            cache_policy: 0,
+        };
+        let index_metadata = test_sandbox.metastore().index_metadata(index_id).await?;
+        let splits = test_sandbox.metastore().list_all_splits(index_id).await?;
+        let splits_offsets: Vec<SplitIdAndFooterOffsets> = splits
+            .into_iter()
+            .map(|split_meta| SplitIdAndFooterOffsets {
+                split_id: split_meta.split_metadata.split_id,
+                split_footer_start: split_meta.footer_offsets.start,
+                split_footer_end: split_meta.footer_offsets.end,
+                time_range_end: None,
+                split_file: String::new(),
+                split_part_num_bytes: None,
+            })
+            .collect();
+        let storage = test_sandbox
+            .storage_uri_resolver()
+            .resolve(&index_metadata.index_uri)?;
+        let mut single_node_stream = leaf_search_stream(
+            request.clone(),
+            storage.clone(),
+            splits_offsets.clone(),
+            index_config.clone(),
+        )
+        .await;
+        let res = single_node_stream.next().await.expect("no leaf result")?;
+        let mut values: Vec<&str> = from_utf8(&res.data)?.lines().collect();
+        values.sort_unstable();
+        assert_eq!(
+            values,
+            vec!["error", "error", "info", "info", "warn", "warn"]
+        );
+
+        request.output_format = OutputFormat::Parquet as i32;
+        let mut single_node_stream =
+            leaf_search_stream(request, storage, splits_offsets, index_config).await;
+        let error = single_node_stream
+            .next()
+            .await
+            .expect("no leaf result")
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("The text fast field `level` can only be streamed on its own"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_leaf_search_stream_multivalued_fast_field() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "timestamp_field": "ts",
+            "tag_fields": [],
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "ts",
+                    "type": "i64",
+                    "fast": true
+                },
+                {
+                    "name": "ports",
+                    "type": "array<u64>",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let index_config =
+            Arc::new(serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?);
+        let index_id = "single-node-stream-multivalued";
+        let test_sandbox = TestSandbox::create(index_id, index_config.clone()).await?;
+        let docs = vec![
+            json!({"body": "info", "ts": 1, "ports": [80, 443]}),
+            json!({"body": "info", "ts": 2, "ports": []}),
+            json!({"body": "info", "ts": 3, "ports": [8080]}),
+        ];
+        test_sandbox.add_documents(docs).await?;
+
+        let request = SearchStreamRequest {
+            index_id: index_id.to_string(),
+            query: "info".to_string(),
+            search_fields: vec![],
+            start_timestamp: None,
+            end_timestamp: None,
+            fast_field: "ports".to_string(),
+            output_format: 0,
+            partition_by_field: None,
+            tags: vec![],
+            distinct_count: false,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: Vec::new(),
+            cache_policy: 0,
+        };
+        let index_metadata = test_sandbox.metastore().index_metadata(index_id).await?;
+        let splits = test_sandbox.metastore().list_all_splits(index_id).await?;
+        let splits_offsets = splits
+            .into_iter()
+            .map(|split_meta| SplitIdAndFooterOffsets {
+                split_id: split_meta.split_metadata.split_id,
+                split_footer_start: split_meta.footer_offsets.start,
+                split_footer_end: split_meta.footer_offsets.end,
+                time_range_end: None,
+                split_file: String::new(),
+                split_part_num_bytes: None,
+            })
+            .collect();
+        let mut single_node_stream = leaf_search_stream(
+            request,
+            test_sandbox
+                .storage_uri_resolver()
+                .resolve(&index_metadata.index_uri)?,
+            splits_offsets,
+            index_config,
+        )
+        .await;
+        let res = single_node_stream.next().await.expect("no leaf result")?;
+        assert_eq!(from_utf8(&res.data)?, "80\n443\n8080\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_leaf_search_stream_computed_fields() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "timestamp_field": "ts",
+            "tag_fields": [],
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "ts",
+                    "type": "i64",
+                    "fast": true
+                },
+                {
+                    "name": "latency_us",
+                    "type": "u64",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let index_config =
+            Arc::new(serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?);
+        let index_id = "single-node-stream-computed-fields";
+        let test_sandbox = TestSandbox::create(index_id, index_config.clone()).await?;
+        let docs = vec![
+            json!({"body": "info", "ts": 59, "latency_us": 1_500}),
+            json!({"body": "info", "ts": 61, "latency_us": 250}),
+            json!({"body": "info", "ts": 125, "latency_us": 12_000}),
+        ];
+        test_sandbox.add_documents(docs).await?;
+
+        let request = SearchStreamRequest {
+            index_id: index_id.to_string(),
+            query: "info".to_string(),
+            search_fields: vec![],
+            start_timestamp: None,
+            end_timestamp: None,
+            fast_field: "ts".to_string(),
+            output_format: 0,
+            partition_by_field: None,
+            tags: vec![],
+            distinct_count: false,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: vec![
+                "minute = ts - ts % 60".to_string(),
+                "latency_ms = latency_us / 1000".to_string(),
+            ],
+            cache_policy: 0,
+        };
+        let index_metadata = test_sandbox.metastore().index_metadata(index_id).await?;
+        let splits = test_sandbox.metastore().list_all_splits(index_id).await?;
+        let splits_offsets: Vec<SplitIdAndFooterOffsets> = splits
+            .into_iter()
+            .map(|split_meta| SplitIdAndFooterOffsets {
+                split_id: split_meta.split_metadata.split_id,
+                split_footer_start: split_meta.footer_offsets.start,
+                split_footer_end: split_meta.footer_offsets.end,
+                time_range_end: None,
+                split_file: String::new(),
+                split_part_num_bytes: None,
+            })
+            .collect();
+        let storage = test_sandbox
+            .storage_uri_resolver()
+            .resolve(&index_metadata.index_uri)?;
+        let mut single_node_stream = leaf_search_stream(
+            request.clone(),
+            storage.clone(),
+            splits_offsets.clone(),
+            index_config.clone(),
+        )
+        .await;
+        let res = single_node_stream.next().await.expect("no leaf result")?;
+        assert_eq!(from_utf8(&res.data)?, "59,0,1\n61,60,0\n125,120,12\n");
+
+        let request_dividing_by_zero = SearchStreamRequest {
+            computed_fields: vec!["ratio = latency_us / (ts - 61)".to_string()],
+            ..request
+        };
+        let mut single_node_stream = leaf_search_stream(
+            request_dividing_by_zero,
+            storage,
+            splits_offsets,
+            index_config,
+        )
+        .await;
+        assert!(single_node_stream
+            .next()
+            .await
+            .expect("no leaf result")
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_leaf_search_stream_distinct_count() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "timestamp_field": "ts",
+            "tag_fields": [],
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "ts",
+                    "type": "i64",
+                    "fast": true
+                },
+                {
+                    "name": "user_id",
+                    "type": "u64",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let index_config =
+            Arc::new(serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?);
+        let index_id = "single-node-stream-distinct-count";
+        let test_sandbox = TestSandbox::create(index_id, index_config.clone()).await?;
+        let docs: Vec<_> = (0..30)
+            .map(|i| json!({"body": "info", "ts": i + 1, "user_id": i % 7}))
+            .collect();
+        test_sandbox.add_documents(docs).await?;
+
+        let request = SearchStreamRequest {
+            index_id: index_id.to_string(),
+            query: "info".to_string(),
+            search_fields: vec![],
+            start_timestamp: None,
+            end_timestamp: None,
+            fast_field: "user_id".to_string(),
+            output_format: 0,
+            partition_by_field: None,
+            tags: vec![],
+            distinct_count: true,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: Vec::new(),
+            cache_policy: 0,
+        };
+        let index_metadata = test_sandbox.metastore().index_metadata(index_id).await?;
+        let splits = test_sandbox.metastore().list_all_splits(index_id).await?;
+        let splits_offsets = splits
+            .into_iter()
+            .map(|split_meta| SplitIdAndFooterOffsets {
+                split_id: split_meta.split_metadata.split_id,
+                split_footer_start: split_meta.footer_offsets.start,
+                split_footer_end: split_meta.footer_offsets.end,
+                time_range_end: None,
+                split_file: String::new(),
+                split_part_num_bytes: None,
+            })
+            .collect();
+        let mut single_node_stream = leaf_search_stream(
+            request,
+            test_sandbox
+                .storage_uri_resolver()
+                .resolve(&index_metadata.index_uri)?,
+            splits_offsets,
+            index_config,
+        )
+        .await;
+        let res = single_node_stream.next().await.expect("no leaf result")?;
+        let distinct_count = estimate_distinct_count_from_bytes(vec![res.data.as_slice()])?;
+        assert_eq!(distinct_count, 7);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_leaf_search_stream_to_partitionned_clickhouse_binary_output_with_filtering(
     ) -> anyhow::Result<()> {
@@ -552,6 +1161,13 @@ mod tests {
             output_format: 1,
             partition_by_field: Some(String::from("partition_by_fast_field")),
             tags: vec![],
+            distinct_count: false,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: Vec::new(),
+            cache_policy: 0,
         };
         let index_metadata = test_sandbox.metastore().index_metadata(index_id).await?;
         let splits = test_sandbox.metastore().list_all_splits(index_id).await?;
@@ -561,6 +1177,9 @@ mod tests {
                 split_id: split_meta.split_metadata.split_id,
                 split_footer_start: split_meta.footer_offsets.start,
                 split_footer_end: split_meta.footer_offsets.end,
+                time_range_end: None,
+                split_file: String::new(),
+                split_part_num_bytes: None,
             })
             .collect();
         let mut single_node_stream = leaf_search_stream(
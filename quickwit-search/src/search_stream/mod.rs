@@ -17,34 +17,217 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+mod click_house;
 mod collector;
+mod computed_fields;
 mod leaf;
+mod parquet;
+mod result_channel;
 mod root;
 
 use std::fmt::Display;
+use std::future::Future;
 use std::io;
 use std::io::Write;
 
 pub use collector::FastFieldCollector;
 pub use leaf::leaf_search_stream;
 use quickwit_proto::OutputFormat;
+pub use result_channel::{result_channel, ResultReceiverStream, ResultSender};
 pub use root::root_search_stream;
 use tantivy::fastfield::FastValue;
+use tokio::task::JoinHandle;
+use tracing::debug;
 
-use self::collector::PartitionValues;
+use self::collector::{ComputedRows, PartitionValues};
+use self::computed_fields::ComputedFields;
+use self::parquet::{ParquetColumn, ParquetColumnType, ParquetType, ParquetWriter};
+
+/// Spawns the task built by `task_builder`, which sends its results to `result_sender`.
+///
+/// The task is dropped as soon as the receiver of `result_sender` is dropped, which cancels
+/// the searches and downloads it is waiting on instead of running them until the next send.
+pub(crate) fn spawn_until_receiver_dropped<F, Fut>(
+    result_sender: ResultSender,
+    task_builder: F,
+) -> JoinHandle<()>
+where
+    F: FnOnce(ResultSender) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let closed_sender = result_sender.clone();
+    let task = task_builder(result_sender);
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = task => {}
+            _ = closed_sender.closed() => {
+                debug!("The receiver of the stream results was dropped, cancelling the task.");
+            }
+        }
+    })
+}
 
 /// Serialize the values into the `buffer` as bytes.
 ///
+/// The `column_name` is only used by the formats carrying a header, like `ClickHouseNative`.
 /// Please note that the `buffer` is always cleared.
-pub fn serialize<TFastValue: FastValue + Display>(
+pub fn serialize<TFastValue: FastValue + Display + ClickHouseType + ParquetType>(
     values: &[TFastValue],
+    column_name: &str,
     buffer: &mut Vec<u8>,
     format: OutputFormat,
 ) -> io::Result<()> {
     match format {
         OutputFormat::Csv => serialize_csv(values, buffer),
         OutputFormat::ClickHouseRowBinary => serialize_click_house_row_binary(values, buffer),
+        OutputFormat::ClickHouseNative => serialize_click_house_native(values, column_name, buffer),
+        OutputFormat::Parquet => serialize_parquet(values, column_name, buffer),
+    }
+}
+
+/// Serialize the values of a text fast field into the `buffer` as bytes.
+///
+/// The values are ClickHouse `String`s in the ClickHouse formats, and are quoted in the CSV
+/// format when needed. Parquet is not supported, as only 64-bit integer columns are.
+/// Please note that the `buffer` is always cleared.
+pub fn serialize_strings(
+    values: &[String],
+    column_name: &str,
+    buffer: &mut Vec<u8>,
+    format: OutputFormat,
+) -> io::Result<()> {
+    buffer.clear();
+    match format {
+        OutputFormat::Csv => {
+            for value in values {
+                write_csv_string(buffer, value)?;
+                writeln!(buffer)?;
+            }
+        }
+        OutputFormat::ClickHouseRowBinary => {
+            for value in values {
+                click_house::write_string(buffer, value);
+            }
+        }
+        OutputFormat::ClickHouseNative => {
+            click_house::write_block_header(buffer, column_name, "String", values.len());
+            for value in values {
+                click_house::write_string(buffer, value);
+            }
+        }
+        OutputFormat::Parquet => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Text fast fields cannot be exported to Parquet.",
+            ));
+        }
     }
+    Ok(())
+}
+
+/// Writes a CSV field, quoted if it holds a separator, a quote or a line break.
+fn write_csv_string(buffer: &mut Vec<u8>, value: &str) -> io::Result<()> {
+    if !value.contains(&[',', '"', '\n', '\r'][..]) {
+        return buffer.write_all(value.as_bytes());
+    }
+    write!(buffer, "\"{}\"", value.replace('"', "\"\""))
+}
+
+/// Serialize the values and their computed fields into the `buffer` as bytes, one row per
+/// document.
+///
+/// The computed fields are `Int64` columns following the `column_name` column.
+/// Please note that the `buffer` is always cleared.
+pub fn serialize_with_computed_fields<
+    TFastValue: FastValue + Display + ClickHouseType + ParquetType,
+>(
+    rows: &ComputedRows<TFastValue>,
+    computed_fields: &ComputedFields,
+    column_name: &str,
+    buffer: &mut Vec<u8>,
+    format: OutputFormat,
+) -> io::Result<()> {
+    buffer.clear();
+    let num_computed_fields = computed_fields.len();
+    let computed_rows = rows.computed_values.chunks(num_computed_fields.max(1));
+    match format {
+        OutputFormat::Csv => {
+            for (value, computed_values) in rows.fast_field_values.iter().zip(computed_rows) {
+                write!(buffer, "{}", value)?;
+                for computed_value in computed_values {
+                    write!(buffer, ",{}", computed_value)?;
+                }
+                writeln!(buffer)?;
+            }
+        }
+        OutputFormat::ClickHouseRowBinary => {
+            buffer.reserve_exact(
+                std::mem::size_of::<u64>()
+                    * (rows.fast_field_values.len() + rows.computed_values.len()),
+            );
+            for (value, computed_values) in rows.fast_field_values.iter().zip(computed_rows) {
+                buffer.extend(value.as_u64().to_le_bytes());
+                for computed_value in computed_values {
+                    buffer.extend(computed_value.to_le_bytes());
+                }
+            }
+        }
+        OutputFormat::ClickHouseNative => {
+            let num_rows = rows.fast_field_values.len();
+            click_house::write_block_size(buffer, 1 + num_computed_fields, num_rows);
+            click_house::write_column_header(buffer, column_name, TFastValue::CLICK_HOUSE_TYPE);
+            for value in &rows.fast_field_values {
+                buffer.extend(value.as_u64().to_le_bytes());
+            }
+            for (computed_field_ord, name) in computed_fields.names().enumerate() {
+                click_house::write_column_header(buffer, name, i64::CLICK_HOUSE_TYPE);
+                for row_ord in 0..num_rows {
+                    let computed_value =
+                        rows.computed_values[row_ord * num_computed_fields + computed_field_ord];
+                    buffer.extend(computed_value.to_le_bytes());
+                }
+            }
+        }
+        OutputFormat::Parquet => {
+            let mut columns = vec![ParquetColumn::new(column_name, TFastValue::PARQUET_TYPE)];
+            columns.extend(
+                computed_fields
+                    .names()
+                    .map(|name| ParquetColumn::new(name, ParquetColumnType::Int64)),
+            );
+            let mut writer = ParquetWriter::new(columns);
+            let mut row_values =
+                Vec::with_capacity(rows.computed_values.len() + rows.fast_field_values.len());
+            for (value, computed_values) in rows.fast_field_values.iter().zip(computed_rows) {
+                row_values.push(value.as_u64());
+                row_values.extend(
+                    computed_values
+                        .iter()
+                        .map(|computed_value| *computed_value as u64),
+                );
+            }
+            writer.write_row_group(&row_values);
+            buffer.extend(writer.finish());
+        }
+    }
+    Ok(())
+}
+
+/// Name of the ClickHouse type of a fast value.
+pub trait ClickHouseType {
+    const CLICK_HOUSE_TYPE: &'static str;
+}
+
+impl ClickHouseType for u64 {
+    const CLICK_HOUSE_TYPE: &'static str = "UInt64";
+}
+
+impl ClickHouseType for i64 {
+    const CLICK_HOUSE_TYPE: &'static str = "Int64";
+}
+
+impl ClickHouseType for f64 {
+    const CLICK_HOUSE_TYPE: &'static str = "Float64";
 }
 
 pub fn serialize_partitions<TFastValue: FastValue + Display, TPartitionFastValue: FastValue>(
@@ -91,6 +274,47 @@ fn serialize_click_house_row_binary<TFastValue: FastValue + Display>(
     Ok(())
 }
 
+/// Serializes the values as a block of ClickHouse's `Native` format, with a single column.
+///
+/// Each chunk of the stream is a self-contained block, so the concatenation of the chunks
+/// can be piped as is to `INSERT INTO ... FORMAT Native`.
+fn serialize_click_house_native<TFastValue: FastValue + Display + ClickHouseType>(
+    values: &[TFastValue],
+    column_name: &str,
+    buffer: &mut Vec<u8>,
+) -> io::Result<()> {
+    buffer.clear();
+    click_house::write_block_header(
+        buffer,
+        column_name,
+        TFastValue::CLICK_HOUSE_TYPE,
+        values.len(),
+    );
+    buffer.reserve_exact(std::mem::size_of::<TFastValue>() * values.len());
+    for value in values {
+        buffer.extend(value.as_u64().to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Serializes the values as an Apache Parquet file, with a single column and a single row
+/// group.
+fn serialize_parquet<TFastValue: FastValue + ParquetType>(
+    values: &[TFastValue],
+    column_name: &str,
+    buffer: &mut Vec<u8>,
+) -> io::Result<()> {
+    buffer.clear();
+    let mut writer = ParquetWriter::new(vec![ParquetColumn::new(
+        column_name,
+        TFastValue::PARQUET_TYPE,
+    )]);
+    let values: Vec<u64> = values.iter().map(|value| value.as_u64()).collect();
+    writer.write_row_group(&values);
+    buffer.extend(writer.finish());
+    Ok(())
+}
+
 mod helpers {
     use std::fmt::Display;
 
@@ -124,8 +348,15 @@ mod helpers {
 
 #[cfg(test)]
 mod tests {
-    use crate::search_stream::collector::PartitionValues;
-    use crate::search_stream::{serialize_click_house_row_binary, serialize_csv};
+    use quickwit_proto::OutputFormat;
+
+    use crate::search_stream::collector::{ComputedRows, PartitionValues};
+    use crate::search_stream::computed_fields::ComputedFields;
+    use crate::search_stream::parquet::{ParquetColumn, ParquetColumnType, ParquetWriter};
+    use crate::search_stream::{
+        serialize_click_house_native, serialize_click_house_row_binary, serialize_csv,
+        serialize_parquet, serialize_strings, serialize_with_computed_fields,
+    };
 
     #[test]
     fn test_serialize_row_binary() {
@@ -138,6 +369,22 @@ mod tests {
         assert_eq!(buffer, (-10f64).to_le_bytes());
     }
 
+    #[test]
+    fn test_serialize_native() {
+        let mut buffer = Vec::new();
+        serialize_click_house_native::<i64>(&[-10i64, 3i64], "id", &mut buffer).unwrap();
+        let mut expected_buffer = vec![1u8, 2u8, 2u8, b'i', b'd', 5u8];
+        expected_buffer.extend(b"Int64");
+        expected_buffer.extend((-10i64).to_le_bytes());
+        expected_buffer.extend(3i64.to_le_bytes());
+        assert_eq!(buffer, expected_buffer);
+
+        serialize_click_house_native::<u64>(&[], "id", &mut buffer).unwrap();
+        let mut expected_buffer = vec![1u8, 0u8, 2u8, b'i', b'd', 6u8];
+        expected_buffer.extend(b"UInt64");
+        assert_eq!(buffer, expected_buffer);
+    }
+
     #[test]
     fn test_serialize_csv() {
         let mut buffer = Vec::new();
@@ -145,6 +392,141 @@ mod tests {
         assert_eq!(buffer, "-10\n".as_bytes());
     }
 
+    #[test]
+    fn test_serialize_strings() {
+        let values = vec![
+            "error".to_string(),
+            "".to_string(),
+            "say \"hi\", then leave".to_string(),
+        ];
+        let mut buffer = Vec::new();
+        serialize_strings(&values, "level", &mut buffer, OutputFormat::Csv).unwrap();
+        assert_eq!(
+            buffer,
+            "error\n\n\"say \"\"hi\"\", then leave\"\n".as_bytes()
+        );
+
+        serialize_strings(
+            &values[..2],
+            "level",
+            &mut buffer,
+            OutputFormat::ClickHouseRowBinary,
+        )
+        .unwrap();
+        assert_eq!(buffer, b"\x05error\x00");
+
+        serialize_strings(
+            &values[..1],
+            "level",
+            &mut buffer,
+            OutputFormat::ClickHouseNative,
+        )
+        .unwrap();
+        let mut expected_buffer = vec![1u8, 1u8, 5u8];
+        expected_buffer.extend(b"level");
+        expected_buffer.push(6u8);
+        expected_buffer.extend(b"String");
+        expected_buffer.push(5u8);
+        expected_buffer.extend(b"error");
+        assert_eq!(buffer, expected_buffer);
+
+        assert!(serialize_strings(&values, "level", &mut buffer, OutputFormat::Parquet).is_err());
+    }
+
+    #[test]
+    fn test_serialize_parquet() {
+        let mut buffer = Vec::new();
+        serialize_parquet::<i64>(&[-10i64, 3i64], "id", &mut buffer).unwrap();
+        let mut writer =
+            ParquetWriter::new(vec![ParquetColumn::new("id", ParquetColumnType::Int64)]);
+        writer.write_row_group(&[(-10i64) as u64, 3u64]);
+        assert_eq!(buffer, writer.finish());
+    }
+
+    #[test]
+    fn test_serialize_with_computed_fields() {
+        let computed_fields =
+            ComputedFields::parse(&["minute=ts/60".to_string(), "double=2*ts".to_string()])
+                .unwrap();
+        let rows = ComputedRows {
+            fast_field_values: vec![3u64, 4u64],
+            computed_values: vec![1, 240, -1, 120],
+        };
+        let mut buffer = Vec::new();
+        serialize_with_computed_fields(
+            &rows,
+            &computed_fields,
+            "id",
+            &mut buffer,
+            OutputFormat::Csv,
+        )
+        .unwrap();
+        assert_eq!(buffer, "3,1,240\n4,-1,120\n".as_bytes());
+
+        serialize_with_computed_fields(
+            &rows,
+            &computed_fields,
+            "id",
+            &mut buffer,
+            OutputFormat::ClickHouseRowBinary,
+        )
+        .unwrap();
+        let expected_buffer: Vec<u8> = vec![
+            3u64.to_le_bytes(),
+            1i64.to_le_bytes(),
+            240i64.to_le_bytes(),
+            4u64.to_le_bytes(),
+            (-1i64).to_le_bytes(),
+            120i64.to_le_bytes(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        assert_eq!(buffer, expected_buffer);
+
+        serialize_with_computed_fields(
+            &rows,
+            &computed_fields,
+            "id",
+            &mut buffer,
+            OutputFormat::ClickHouseNative,
+        )
+        .unwrap();
+        let mut expected_buffer = vec![3u8, 2u8, 2u8, b'i', b'd', 6u8];
+        expected_buffer.extend(b"UInt64");
+        expected_buffer.extend(3u64.to_le_bytes());
+        expected_buffer.extend(4u64.to_le_bytes());
+        expected_buffer.extend([6u8]);
+        expected_buffer.extend(b"minute");
+        expected_buffer.extend([5u8]);
+        expected_buffer.extend(b"Int64");
+        expected_buffer.extend(1i64.to_le_bytes());
+        expected_buffer.extend((-1i64).to_le_bytes());
+        expected_buffer.extend([6u8]);
+        expected_buffer.extend(b"double");
+        expected_buffer.extend([5u8]);
+        expected_buffer.extend(b"Int64");
+        expected_buffer.extend(240i64.to_le_bytes());
+        expected_buffer.extend(120i64.to_le_bytes());
+        assert_eq!(buffer, expected_buffer);
+
+        serialize_with_computed_fields(
+            &rows,
+            &computed_fields,
+            "id",
+            &mut buffer,
+            OutputFormat::Parquet,
+        )
+        .unwrap();
+        let mut writer = ParquetWriter::new(vec![
+            ParquetColumn::new("id", ParquetColumnType::UInt64),
+            ParquetColumn::new("minute", ParquetColumnType::Int64),
+            ParquetColumn::new("double", ParquetColumnType::Int64),
+        ]);
+        writer.write_row_group(&[3, 1, 240, 4, (-1i64) as u64, 120]);
+        assert_eq!(buffer, writer.finish());
+    }
+
     #[test]
     fn test_serialize_partitions() {
         let mut buffer = Vec::new();
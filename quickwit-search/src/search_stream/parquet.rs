@@ -0,0 +1,426 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Minimal writer of Apache Parquet files, used to export the values of a search stream to
+//! data lakes.
+//!
+//! Only what 64-bit integer columns need is implemented: every column is required, and its
+//! values are written uncompressed with the `PLAIN` encoding, without statistics.
+//! The metadata are serialized with the Thrift compact protocol.
+//! https://github.com/apache/parquet-format
+
+const MAGIC: &[u8] = b"PAR1";
+const CREATED_BY: &str = "quickwit";
+const FORMAT_VERSION: i32 = 1;
+
+/// Maximum number of values of a data page, so that a page weighs at most 1MB.
+const MAX_NUM_VALUES_PER_PAGE: usize = 1 << 17;
+
+const TYPE_INT64: i32 = 2;
+const REPETITION_REQUIRED: i32 = 0;
+const CONVERTED_TYPE_UINT_64: i32 = 10;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+const PAGE_TYPE_DATA_PAGE: i32 = 0;
+
+const COMPACT_I32: u8 = 5;
+const COMPACT_I64: u8 = 6;
+const COMPACT_BINARY: u8 = 8;
+const COMPACT_LIST: u8 = 9;
+const COMPACT_STRUCT: u8 = 12;
+
+/// Type of the values of a Parquet column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParquetColumnType {
+    Int64,
+    UInt64,
+}
+
+/// Parquet type of a fast value.
+pub trait ParquetType {
+    const PARQUET_TYPE: ParquetColumnType;
+}
+
+impl ParquetType for u64 {
+    const PARQUET_TYPE: ParquetColumnType = ParquetColumnType::UInt64;
+}
+
+impl ParquetType for i64 {
+    const PARQUET_TYPE: ParquetColumnType = ParquetColumnType::Int64;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParquetColumn {
+    pub name: String,
+    pub column_type: ParquetColumnType,
+}
+
+impl ParquetColumn {
+    pub fn new(name: &str, column_type: ParquetColumnType) -> Self {
+        ParquetColumn {
+            name: name.to_string(),
+            column_type,
+        }
+    }
+}
+
+struct ColumnChunkMetadata {
+    offset: u64,
+    num_bytes: u64,
+}
+
+struct RowGroupMetadata {
+    num_rows: u64,
+    column_chunks: Vec<ColumnChunkMetadata>,
+}
+
+/// Writes a Parquet file, one row group at a time.
+pub struct ParquetWriter {
+    columns: Vec<ParquetColumn>,
+    buffer: Vec<u8>,
+    row_groups: Vec<RowGroupMetadata>,
+}
+
+impl ParquetWriter {
+    pub fn new(columns: Vec<ParquetColumn>) -> Self {
+        ParquetWriter {
+            columns,
+            buffer: MAGIC.to_vec(),
+            row_groups: Vec::new(),
+        }
+    }
+
+    pub fn num_columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Appends a row group holding the `rows`, given row after row: the values of a row
+    /// are the 64-bit representations of its columns, in the order of the columns.
+    ///
+    /// Empty row groups are skipped.
+    pub fn write_row_group(&mut self, rows: &[u64]) {
+        let num_columns = self.columns.len();
+        assert_eq!(rows.len() % num_columns.max(1), 0);
+        let num_rows = rows.len() / num_columns.max(1);
+        if num_rows == 0 {
+            return;
+        }
+        let mut column_chunks = Vec::with_capacity(num_columns);
+        let mut page_values = Vec::with_capacity(num_rows.min(MAX_NUM_VALUES_PER_PAGE));
+        for column_ord in 0..num_columns {
+            let offset = self.buffer.len() as u64;
+            let mut column_values = rows.iter().skip(column_ord).step_by(num_columns).copied();
+            let mut num_remaining_rows = num_rows;
+            while num_remaining_rows > 0 {
+                page_values.clear();
+                page_values.extend(column_values.by_ref().take(MAX_NUM_VALUES_PER_PAGE));
+                num_remaining_rows -= page_values.len();
+                write_data_page(&mut self.buffer, &page_values);
+            }
+            column_chunks.push(ColumnChunkMetadata {
+                offset,
+                num_bytes: self.buffer.len() as u64 - offset,
+            });
+        }
+        self.row_groups.push(RowGroupMetadata {
+            num_rows: num_rows as u64,
+            column_chunks,
+        });
+    }
+
+    /// Writes the footer of the file, and returns its bytes.
+    pub fn finish(mut self) -> Vec<u8> {
+        let footer_offset = self.buffer.len();
+        write_file_metadata(&mut self.buffer, &self.columns, &self.row_groups);
+        let footer_len = (self.buffer.len() - footer_offset) as u32;
+        self.buffer.extend(footer_len.to_le_bytes());
+        self.buffer.extend_from_slice(MAGIC);
+        self.buffer
+    }
+}
+
+/// Writes a `DATA_PAGE` holding the `values`, preceded by its header.
+///
+/// The columns being required, the page holds neither repetition nor definition levels.
+fn write_data_page(buffer: &mut Vec<u8>, values: &[u64]) {
+    let num_bytes = (values.len() * std::mem::size_of::<u64>()) as i32;
+    let mut writer = CompactWriter::new(buffer);
+    writer.write_i32_field(1, PAGE_TYPE_DATA_PAGE);
+    writer.write_i32_field(2, num_bytes);
+    writer.write_i32_field(3, num_bytes);
+    writer.begin_struct_field(5);
+    writer.write_i32_field(1, values.len() as i32);
+    writer.write_i32_field(2, ENCODING_PLAIN);
+    writer.write_i32_field(3, ENCODING_RLE);
+    writer.write_i32_field(4, ENCODING_RLE);
+    writer.end_struct();
+    writer.end_struct();
+    for value in values {
+        buffer.extend(value.to_le_bytes());
+    }
+}
+
+fn write_file_metadata(
+    buffer: &mut Vec<u8>,
+    columns: &[ParquetColumn],
+    row_groups: &[RowGroupMetadata],
+) {
+    let mut writer = CompactWriter::new(buffer);
+    writer.write_i32_field(1, FORMAT_VERSION);
+    writer.begin_list_field(2, COMPACT_STRUCT, 1 + columns.len());
+    writer.begin_struct_element();
+    writer.write_string_field(4, "schema");
+    writer.write_i32_field(5, columns.len() as i32);
+    writer.end_struct();
+    for column in columns {
+        writer.begin_struct_element();
+        writer.write_i32_field(1, TYPE_INT64);
+        writer.write_i32_field(3, REPETITION_REQUIRED);
+        writer.write_string_field(4, &column.name);
+        if column.column_type == ParquetColumnType::UInt64 {
+            writer.write_i32_field(6, CONVERTED_TYPE_UINT_64);
+        }
+        writer.end_struct();
+    }
+    let num_rows: u64 = row_groups.iter().map(|row_group| row_group.num_rows).sum();
+    writer.write_i64_field(3, num_rows as i64);
+    writer.begin_list_field(4, COMPACT_STRUCT, row_groups.len());
+    for row_group in row_groups {
+        writer.begin_struct_element();
+        writer.begin_list_field(1, COMPACT_STRUCT, columns.len());
+        for (column, column_chunk) in columns.iter().zip(&row_group.column_chunks) {
+            writer.begin_struct_element();
+            writer.write_i64_field(2, column_chunk.offset as i64);
+            writer.begin_struct_field(3);
+            writer.write_i32_field(1, TYPE_INT64);
+            writer.begin_list_field(2, COMPACT_I32, 1);
+            writer.write_i32_element(ENCODING_PLAIN);
+            writer.begin_list_field(3, COMPACT_BINARY, 1);
+            writer.write_string_element(&column.name);
+            writer.write_i32_field(4, CODEC_UNCOMPRESSED);
+            writer.write_i64_field(5, row_group.num_rows as i64);
+            writer.write_i64_field(6, column_chunk.num_bytes as i64);
+            writer.write_i64_field(7, column_chunk.num_bytes as i64);
+            writer.write_i64_field(9, column_chunk.offset as i64);
+            writer.end_struct();
+            writer.end_struct();
+        }
+        let total_byte_size: u64 = row_group
+            .column_chunks
+            .iter()
+            .map(|column_chunk| column_chunk.num_bytes)
+            .sum();
+        writer.write_i64_field(2, total_byte_size as i64);
+        writer.write_i64_field(3, row_group.num_rows as i64);
+        writer.end_struct();
+    }
+    writer.write_string_field(6, CREATED_BY);
+    writer.end_struct();
+}
+
+/// Writer of a struct in the Thrift compact protocol.
+///
+/// Field headers encode the difference between the ids of consecutive fields, so the id of
+/// the last field written is kept for each struct being written.
+struct CompactWriter<'a> {
+    buffer: &'a mut Vec<u8>,
+    last_field_id: i16,
+    parent_last_field_ids: Vec<i16>,
+}
+
+impl<'a> CompactWriter<'a> {
+    fn new(buffer: &'a mut Vec<u8>) -> Self {
+        CompactWriter {
+            buffer,
+            last_field_id: 0,
+            parent_last_field_ids: Vec::new(),
+        }
+    }
+
+    fn write_field_header(&mut self, field_id: i16, field_type: u8) {
+        let delta = field_id - self.last_field_id;
+        if (1..=15).contains(&delta) {
+            self.buffer.push(((delta as u8) << 4) | field_type);
+        } else {
+            self.buffer.push(field_type);
+            write_varint(self.buffer, zigzag_i64(i64::from(field_id)));
+        }
+        self.last_field_id = field_id;
+    }
+
+    fn write_i32_field(&mut self, field_id: i16, value: i32) {
+        self.write_field_header(field_id, COMPACT_I32);
+        self.write_i32_element(value);
+    }
+
+    fn write_i64_field(&mut self, field_id: i16, value: i64) {
+        self.write_field_header(field_id, COMPACT_I64);
+        write_varint(self.buffer, zigzag_i64(value));
+    }
+
+    fn write_string_field(&mut self, field_id: i16, value: &str) {
+        self.write_field_header(field_id, COMPACT_BINARY);
+        self.write_string_element(value);
+    }
+
+    /// Begins a struct field, whose fields are written until the matching `end_struct`.
+    fn begin_struct_field(&mut self, field_id: i16) {
+        self.write_field_header(field_id, COMPACT_STRUCT);
+        self.begin_struct_element();
+    }
+
+    /// Begins a list field, whose `num_elements` elements must be written right after.
+    fn begin_list_field(&mut self, field_id: i16, element_type: u8, num_elements: usize) {
+        self.write_field_header(field_id, COMPACT_LIST);
+        if num_elements < 15 {
+            self.buffer.push(((num_elements as u8) << 4) | element_type);
+        } else {
+            self.buffer.push(0xf0 | element_type);
+            write_varint(self.buffer, num_elements as u64);
+        }
+    }
+
+    fn write_i32_element(&mut self, value: i32) {
+        write_varint(self.buffer, zigzag_i64(i64::from(value)));
+    }
+
+    fn write_string_element(&mut self, value: &str) {
+        write_varint(self.buffer, value.len() as u64);
+        self.buffer.extend_from_slice(value.as_bytes());
+    }
+
+    fn begin_struct_element(&mut self) {
+        self.parent_last_field_ids.push(self.last_field_id);
+        self.last_field_id = 0;
+    }
+
+    /// Ends the current struct, or the top-level struct if no struct has begun.
+    fn end_struct(&mut self) {
+        self.buffer.push(0);
+        self.last_field_id = self.parent_last_field_ids.pop().unwrap_or(0);
+    }
+}
+
+fn zigzag_i64(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_HEADER_TWO_VALUES: [u8; 17] = [
+        0x15, 0x00, 0x15, 0x20, 0x15, 0x20, 0x2c, 0x15, 0x04, 0x15, 0x00, 0x15, 0x06, 0x15, 0x06,
+        0x00, 0x00,
+    ];
+
+    fn footer(file: &[u8]) -> &[u8] {
+        let footer_len_offset = file.len() - MAGIC.len() - 4;
+        let mut footer_len_bytes = [0u8; 4];
+        footer_len_bytes.copy_from_slice(&file[footer_len_offset..footer_len_offset + 4]);
+        let footer_len = u32::from_le_bytes(footer_len_bytes) as usize;
+        &file[footer_len_offset - footer_len..footer_len_offset]
+    }
+
+    #[test]
+    fn test_compact_writer() {
+        let mut buffer = Vec::new();
+        let mut writer = CompactWriter::new(&mut buffer);
+        writer.write_i32_field(1, -1);
+        writer.write_i64_field(20, 300);
+        writer.begin_struct_field(21);
+        writer.write_string_field(4, "id");
+        writer.end_struct();
+        writer.begin_list_field(22, COMPACT_I32, 15);
+        for _ in 0..15 {
+            writer.write_i32_element(1);
+        }
+        writer.end_struct();
+        let mut expected_buffer = vec![0x15, 0x01, 0x06, 0x28, 0xd8, 0x04, 0x1c, 0x48, 0x02];
+        expected_buffer.extend(b"id");
+        expected_buffer.extend([0x00, 0x19, 0xf5, 0x0f]);
+        expected_buffer.extend([0x02; 15]);
+        expected_buffer.push(0x00);
+        assert_eq!(buffer, expected_buffer);
+    }
+
+    #[test]
+    fn test_parquet_writer() {
+        let mut writer = ParquetWriter::new(vec![
+            ParquetColumn::new("ts", ParquetColumnType::UInt64),
+            ParquetColumn::new("minute", ParquetColumnType::Int64),
+        ]);
+        writer.write_row_group(&[120, 2, 180, (-3i64) as u64]);
+        writer.write_row_group(&[]);
+        let file = writer.finish();
+        assert_eq!(&file[..4], MAGIC);
+        assert_eq!(&file[file.len() - 4..], MAGIC);
+
+        let mut expected_column_chunks = Vec::new();
+        for values in [[120u64, 180u64], [2u64, (-3i64) as u64]] {
+            expected_column_chunks.extend(PAGE_HEADER_TWO_VALUES);
+            for value in values {
+                expected_column_chunks.extend(value.to_le_bytes());
+            }
+        }
+        let footer = footer(&file);
+        let column_chunks_len = file.len() - 8 - footer.len() - MAGIC.len();
+        assert_eq!(&file[4..4 + column_chunks_len], &expected_column_chunks[..]);
+        // Version 1, then the schema: the root and its two columns.
+        assert_eq!(&footer[..4], &[0x15, 0x02, 0x19, 0x3c]);
+        assert!(footer.ends_with(b"quickwit\x00"));
+        assert!(footer
+            .windows(11)
+            .any(|window| window == b"\x15\x04\x25\x00\x18\x02ts\x25\x14\x00"));
+    }
+
+    #[test]
+    fn test_parquet_writer_pages() {
+        let num_rows = MAX_NUM_VALUES_PER_PAGE + 1;
+        let rows: Vec<u64> = (0..num_rows as u64).collect();
+        let mut writer =
+            ParquetWriter::new(vec![ParquetColumn::new("id", ParquetColumnType::UInt64)]);
+        writer.write_row_group(&rows);
+        let file = writer.finish();
+        let page_headers_len = file.len() - 4 - 8 * num_rows - footer(&file).len() - 8;
+        // The sizes of the first page, 2^20 bytes, take four bytes each and its 2^17 values
+        // three bytes, instead of one byte each in the header of the last page.
+        assert_eq!(
+            page_headers_len,
+            2 * PAGE_HEADER_TWO_VALUES.len() + 2 * 3 + 2
+        );
+    }
+
+    #[test]
+    fn test_parquet_writer_no_rows() {
+        let writer = ParquetWriter::new(vec![ParquetColumn::new("id", ParquetColumnType::Int64)]);
+        let file = writer.finish();
+        assert_eq!(&file[..4], MAGIC);
+        assert_eq!(footer(&file).len(), file.len() - 12);
+    }
+}
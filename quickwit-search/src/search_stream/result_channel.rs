@@ -0,0 +1,182 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use once_cell::sync::Lazy;
+use prometheus::IntGauge;
+use quickwit_common::metrics::new_gauge;
+use quickwit_proto::LeafSearchStreamResult;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::SendError;
+
+use crate::SearchError;
+
+/// Maximum number of results buffered in a channel before the sender has to wait for the
+/// receiver to consume them.
+///
+/// A result holds the values of a whole split, so this bounds the memory used by a stream
+/// whose client reads slowly.
+const RESULT_CHANNEL_CAPACITY: usize = 2;
+
+static NUM_QUEUED_RESULTS: Lazy<IntGauge> = Lazy::new(|| {
+    new_gauge(
+        "search_stream:num_queued_results",
+        "Number of search stream results waiting to be consumed by the client of the stream",
+    )
+});
+
+/// Creates a bounded channel of search stream results.
+///
+/// Sending a result waits while the channel is full, so that the producer of the results
+/// follows the pace of their consumer: for a gRPC stream, the consumer only pulls the
+/// results the client is ready to receive.
+pub fn result_channel() -> (ResultSender, ResultReceiverStream) {
+    let (sender, receiver) = mpsc::channel(RESULT_CHANNEL_CAPACITY);
+    (ResultSender { sender }, ResultReceiverStream { receiver })
+}
+
+/// Sending half of a [`result_channel`].
+#[derive(Clone)]
+pub struct ResultSender {
+    sender: mpsc::Sender<crate::Result<LeafSearchStreamResult>>,
+}
+
+impl ResultSender {
+    /// Sends `result`, waiting for the receiver to make room for it if the channel is full.
+    /// Fails if the receiver was dropped.
+    pub async fn send(
+        &self,
+        result: crate::Result<LeafSearchStreamResult>,
+    ) -> Result<(), SendError<crate::Result<LeafSearchStreamResult>>> {
+        NUM_QUEUED_RESULTS.inc();
+        let send_result = self.sender.send(result).await;
+        if send_result.is_err() {
+            NUM_QUEUED_RESULTS.dec();
+        }
+        send_result
+    }
+
+    /// Completes when the receiver was dropped.
+    pub async fn closed(&self) {
+        self.sender.closed().await
+    }
+}
+
+/// Receiving half of a [`result_channel`], as a stream of results.
+pub struct ResultReceiverStream {
+    receiver: mpsc::Receiver<crate::Result<LeafSearchStreamResult>>,
+}
+
+impl ResultReceiverStream {
+    /// Returns a stream yielding `error` only.
+    pub fn from_error(error: SearchError) -> Self {
+        let (result_sender, result_receiver) = result_channel();
+        NUM_QUEUED_RESULTS.inc();
+        // The channel is empty and its receiver is alive, so this cannot fail.
+        if result_sender.sender.try_send(Err(error)).is_err() {
+            NUM_QUEUED_RESULTS.dec();
+        }
+        result_receiver
+    }
+}
+
+impl futures::Stream for ResultReceiverStream {
+    type Item = crate::Result<LeafSearchStreamResult>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.receiver.poll_recv(cx);
+        if let Poll::Ready(Some(_)) = &poll {
+            NUM_QUEUED_RESULTS.dec();
+        }
+        poll
+    }
+}
+
+impl Drop for ResultReceiverStream {
+    fn drop(&mut self) {
+        // The results that will never be consumed leave the queue.
+        self.receiver.close();
+        while self.receiver.try_recv().is_ok() {
+            NUM_QUEUED_RESULTS.dec();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    fn mock_result(split_id: &str) -> crate::Result<LeafSearchStreamResult> {
+        Ok(LeafSearchStreamResult {
+            data: Vec::new(),
+            split_id: split_id.to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_result_channel_waits_for_receiver() -> anyhow::Result<()> {
+        let (result_sender, mut result_receiver) = result_channel();
+        for split_id in &["split1", "split2"] {
+            result_sender.send(mock_result(split_id)).await?;
+        }
+        {
+            let send_future = result_sender.send(mock_result("split3"));
+            tokio::pin!(send_future);
+            assert!(
+                tokio::time::timeout(std::time::Duration::from_millis(50), &mut send_future)
+                    .await
+                    .is_err()
+            );
+            assert_eq!(
+                result_receiver.next().await.unwrap()?.split_id,
+                "split1".to_string()
+            );
+            send_future.await?;
+        }
+        drop(result_sender);
+        let split_ids: Vec<String> = result_receiver
+            .map(|result| result.unwrap().split_id)
+            .collect()
+            .await;
+        assert_eq!(split_ids, vec!["split2".to_string(), "split3".to_string()]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_result_channel_fails_once_receiver_is_dropped() {
+        let (result_sender, result_receiver) = result_channel();
+        drop(result_receiver);
+        result_sender.closed().await;
+        assert!(result_sender.send(mock_result("split1")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_result_receiver_stream_from_error() {
+        let results: Vec<_> =
+            ResultReceiverStream::from_error(SearchError::InternalError("error".to_string()))
+                .collect()
+                .await;
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+    }
+}
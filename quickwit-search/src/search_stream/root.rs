@@ -22,16 +22,25 @@ use std::sync::Arc;
 
 use bytes::Bytes;
 use futures::{StreamExt, TryStreamExt};
-use quickwit_metastore::{Metastore, SplitMetadataAndFooterOffsets};
-use quickwit_proto::{LeafSearchStreamRequest, SearchRequest, SearchStreamRequest};
+use quickwit_metastore::{
+    retry_on_transient_error, IndexMetadata, Metastore, SplitMetadataAndFooterOffsets,
+};
+use quickwit_proto::{LeafSearchStreamRequest, OutputFormat, SearchRequest, SearchStreamRequest};
+use tantivy::schema::{Schema, Type};
 use tracing::*;
 
+use super::click_house::insert_into_click_house;
+use super::computed_fields::ComputedFields;
+use super::parquet::{ParquetColumn, ParquetColumnType, ParquetType, ParquetWriter};
+use super::ClickHouseType;
 use crate::client_pool::Job;
 use crate::cluster_client::ClusterClient;
+use crate::distinct_count::estimate_distinct_count_from_bytes;
+use crate::field_aliases::resolve_stream_request_field_aliases;
 use crate::root::{job_for_splits, MAX_CONCURRENT_LEAF_TASKS};
 use crate::{
-    extract_split_and_footer_offsets, list_relevant_splits, ClientPool, SearchClientPool,
-    SearchError,
+    extract_split_and_footer_offsets, lease_search_splits, list_relevant_splits, ClientPool,
+    SearchClientPool, SearchError,
 };
 
 /// Perform a distributed search stream.
@@ -43,11 +52,50 @@ pub async fn root_search_stream(
     client_pool: &Arc<SearchClientPool>,
 ) -> Result<Vec<Bytes>, SearchError> {
     let start_instant = tokio::time::Instant::now();
+    let mut search_stream_request = search_stream_request.clone();
+    let index_metadata =
+        retry_on_transient_error(|| metastore.index_metadata(&search_stream_request.index_id))
+            .await?;
+    resolve_stream_request_field_aliases(&mut search_stream_request, &*index_metadata.index_config);
+    let search_stream_request = &search_stream_request;
     // TODO: building a search request should not be necessary for listing splits.
     // This needs some refactoring: relevant splits, metadata_map, jobs...
     let search_request = SearchRequest::from(search_stream_request.clone());
     let split_metadata_list = list_relevant_splits(&search_request, metastore).await?;
-    let index_metadata = metastore.index_metadata(&search_request.index_id).await?;
+    let split_lease = lease_search_splits(
+        metastore,
+        split_metadata_list.iter().map(|meta| {
+            (
+                search_stream_request.index_id.as_str(),
+                meta.split_metadata.split_id.as_str(),
+            )
+        }),
+    )
+    .await;
+    let click_house_column_type_opt = search_stream_request
+        .click_house_sink
+        .as_ref()
+        .map(|_| {
+            click_house_column_type(search_stream_request, &index_metadata.index_config.schema())
+        })
+        .transpose()?;
+    let parquet_columns_opt = if search_stream_request.output_format == OutputFormat::Parquet as i32
+        && search_stream_request.click_house_sink.is_none()
+        && !search_stream_request.distinct_count
+    {
+        Some(parquet_columns(
+            search_stream_request,
+            &index_metadata.index_config.schema(),
+        )?)
+    } else {
+        None
+    };
+    // The leaves stream raw values, which are inserted as is into ClickHouse, or written
+    // as the row groups of a single Parquet file.
+    let mut leaf_search_stream_request = search_stream_request.clone();
+    if click_house_column_type_opt.is_some() || parquet_columns_opt.is_some() {
+        leaf_search_stream_request.output_format = OutputFormat::ClickHouseRowBinary as i32;
+    }
 
     // Create a hash map of SplitMetadata with split id as a key.
     let split_metadata_map: HashMap<String, SplitMetadataAndFooterOffsets> = split_metadata_list
@@ -58,20 +106,20 @@ pub async fn root_search_stream(
     let leaf_search_jobs: Vec<Job> =
         job_for_splits(&split_metadata_map.keys().collect(), &split_metadata_map);
     let assigned_leaf_search_jobs = client_pool
-        .assign_jobs(leaf_search_jobs.clone(), &HashSet::default())
+        .assign_jobs(
+            &search_request.index_id,
+            leaf_search_jobs.clone(),
+            &HashSet::default(),
+        )
         .await?;
 
     debug!(assigned_leaf_search_jobs=?assigned_leaf_search_jobs, "Assigned leaf search jobs.");
 
-    let index_config_str = serde_json::to_string(&index_metadata.index_config).map_err(|err| {
-        SearchError::InternalError(format!("Could not serialize index config {}", err))
-    })?;
-    let bytes: Vec<_> = futures::stream::iter(assigned_leaf_search_jobs.into_iter())
+    let leaf_responses = futures::stream::iter(assigned_leaf_search_jobs.into_iter())
         .map(|(client, client_jobs)| {
             let leaf_request = jobs_to_leaf_request(
-                search_stream_request,
-                &index_config_str,
-                &index_metadata.index_uri,
+                &leaf_search_stream_request,
+                &index_metadata,
                 &split_metadata_map,
                 &client_jobs,
             );
@@ -79,18 +127,180 @@ pub async fn root_search_stream(
         })
         .buffer_unordered(MAX_CONCURRENT_LEAF_TASKS)
         .flatten()
-        .map_ok(|response| Bytes::from(response.data))
-        .try_collect()
+        .map_ok(|response| Bytes::from(response.data));
+    let bytes = if let (Some(click_house_sink), Some(click_house_column_type)) = (
+        &search_stream_request.click_house_sink,
+        click_house_column_type_opt,
+    ) {
+        let num_inserted_rows = insert_into_click_house(
+            click_house_sink,
+            &search_stream_request.fast_field,
+            click_house_column_type,
+            leaf_responses,
+        )
         .await?;
+        info!(
+            num_inserted_rows = num_inserted_rows,
+            table = %click_house_sink.table,
+            "Inserted values into ClickHouse."
+        );
+        vec![serialize_count(
+            search_stream_request,
+            "num_inserted_rows",
+            num_inserted_rows,
+        )?]
+    } else if search_stream_request.distinct_count {
+        let sketches: Vec<Bytes> = leaf_responses.try_collect().await?;
+        let distinct_count =
+            estimate_distinct_count_from_bytes(sketches.iter().map(|sketch| sketch.as_ref()))?;
+        vec![serialize_count(
+            search_stream_request,
+            "distinct_count",
+            distinct_count,
+        )?]
+    } else if let Some(parquet_columns) = parquet_columns_opt {
+        let writer = leaf_responses
+            .try_fold(
+                ParquetWriter::new(parquet_columns),
+                |mut writer, rows| async move {
+                    let values = decode_row_binary(&rows, writer.num_columns())?;
+                    writer.write_row_group(&values);
+                    Ok(writer)
+                },
+            )
+            .await?;
+        vec![Bytes::from(writer.finish())]
+    } else {
+        leaf_responses.try_collect().await?
+    };
+    split_lease.release(metastore).await;
     let elapsed = start_instant.elapsed();
     info!("Root search stream completed in {:?}", elapsed);
     Ok(bytes)
 }
 
+/// Returns the ClickHouse type of the streamed fast field, after checking that the values of
+/// the request can be inserted into a ClickHouse table.
+fn click_house_column_type(
+    search_stream_request: &SearchStreamRequest,
+    schema: &Schema,
+) -> Result<&'static str, SearchError> {
+    if search_stream_request.partition_by_field.is_some()
+        || search_stream_request.distinct_count
+        || !search_stream_request.computed_fields.is_empty()
+    {
+        return Err(SearchError::InvalidQuery(
+            "Values cannot be inserted into ClickHouse when you provide a partition-by field, \
+             request a distinct count or computed fields."
+                .to_string(),
+        ));
+    }
+    let field = schema
+        .get_field(&search_stream_request.fast_field)
+        .ok_or_else(|| {
+            SearchError::InvalidQuery(format!(
+                "Field `{}` does not exist in schema",
+                &search_stream_request.fast_field
+            ))
+        })?;
+    match schema.get_field_entry(field).field_type().value_type() {
+        Type::U64 => Ok(u64::CLICK_HOUSE_TYPE),
+        Type::I64 => Ok(i64::CLICK_HOUSE_TYPE),
+        value_type => Err(SearchError::InvalidQuery(format!(
+            "Field `{}` of type {:?} cannot be inserted into ClickHouse, only i64 and u64 fast \
+             fields are supported.",
+            &search_stream_request.fast_field, value_type
+        ))),
+    }
+}
+
+/// Returns the columns of the Parquet file of the response, after checking that the values of
+/// the request can be exported to Parquet: the streamed fast field, followed by the computed
+/// fields.
+fn parquet_columns(
+    search_stream_request: &SearchStreamRequest,
+    schema: &Schema,
+) -> Result<Vec<ParquetColumn>, SearchError> {
+    if search_stream_request.partition_by_field.is_some() {
+        return Err(SearchError::InvalidQuery(
+            "Values cannot be exported to Parquet when you provide a partition-by field."
+                .to_string(),
+        ));
+    }
+    let field = schema
+        .get_field(&search_stream_request.fast_field)
+        .ok_or_else(|| {
+            SearchError::InvalidQuery(format!(
+                "Field `{}` does not exist in schema",
+                &search_stream_request.fast_field
+            ))
+        })?;
+    let column_type = match schema.get_field_entry(field).field_type().value_type() {
+        Type::U64 => u64::PARQUET_TYPE,
+        Type::I64 => i64::PARQUET_TYPE,
+        value_type => {
+            return Err(SearchError::InvalidQuery(format!(
+                "Field `{}` of type {:?} cannot be exported to Parquet, only i64 and u64 fast \
+                 fields are supported.",
+                &search_stream_request.fast_field, value_type
+            )))
+        }
+    };
+    let computed_fields = ComputedFields::parse(&search_stream_request.computed_fields)
+        .map_err(|error| SearchError::InvalidQuery(format!("{:#}", error)))?;
+    let mut columns = vec![ParquetColumn::new(
+        &search_stream_request.fast_field,
+        column_type,
+    )];
+    columns.extend(
+        computed_fields
+            .names()
+            .map(|name| ParquetColumn::new(name, ParquetColumnType::Int64)),
+    );
+    Ok(columns)
+}
+
+/// Decodes the 64-bit values of a chunk streamed by a leaf in the `ClickHouseRowBinary` format.
+fn decode_row_binary(rows: &[u8], num_columns: usize) -> Result<Vec<u64>, SearchError> {
+    let row_num_bytes = num_columns * std::mem::size_of::<u64>();
+    if rows.len() % row_num_bytes != 0 {
+        return Err(SearchError::InternalError(format!(
+            "Invalid leaf response of {} bytes, expected rows of {} bytes.",
+            rows.len(),
+            row_num_bytes
+        )));
+    }
+    Ok(rows
+        .chunks_exact(std::mem::size_of::<u64>())
+        .map(|value_bytes| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(value_bytes);
+            u64::from_le_bytes(bytes)
+        })
+        .collect())
+}
+
+/// Serializes a count computed by the root, like the estimated distinct count, in the
+/// requested output format.
+fn serialize_count(
+    search_stream_request: &SearchStreamRequest,
+    column_name: &str,
+    count: u64,
+) -> Result<Bytes, SearchError> {
+    let output_format =
+        OutputFormat::from_i32(search_stream_request.output_format).ok_or_else(|| {
+            SearchError::InternalError("Invalid output format specified.".to_string())
+        })?;
+    let mut buffer = Vec::new();
+    super::serialize::<u64>(&[count], column_name, &mut buffer, output_format).map_err(|_| {
+        SearchError::InternalError(format!("Error when serializing `{}`.", column_name))
+    })?;
+    Ok(Bytes::from(buffer))
+}
+
 fn jobs_to_leaf_request(
     request: &SearchStreamRequest,
-    index_config_str: &str,
-    index_uri: &str,
+    index_metadata: &IndexMetadata,
     split_metadata_map: &HashMap<String, SplitMetadataAndFooterOffsets>,
     jobs: &[Job],
 ) -> LeafSearchStreamRequest {
@@ -102,8 +312,17 @@ fn jobs_to_leaf_request(
                 extract_split_and_footer_offsets(split_metadata_map.get(&job.split_id).unwrap())
             })
             .collect(),
-        index_config: index_config_str.to_string(),
-        index_uri: index_uri.to_string(),
+        index_config: String::new(),
+        index_uri: index_metadata.index_uri.to_string(),
+        index_config_version: index_metadata.index_config_version(),
+        mirror_index_uri: index_metadata
+            .mirror_index_uri
+            .as_deref()
+            .unwrap_or_default()
+            .to_string(),
+        index_config_fingerprint: index_metadata
+            .index_config_fingerprint(index_metadata.index_config_version())
+            .unwrap_or_default(),
     }
 }
 
@@ -111,15 +330,15 @@ fn jobs_to_leaf_request(
 mod tests {
     use std::ops::Range;
 
-    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_common::QuickwitUri;
+    use quickwit_index_config::{DefaultIndexConfigBuilder, WikipediaIndexConfig};
     use quickwit_indexing::mock_split_meta;
     use quickwit_metastore::checkpoint::Checkpoint;
-    use quickwit_metastore::{IndexMetadata, MockMetastore, SplitState};
+    use quickwit_metastore::{MockMetastore, SplitState};
     use quickwit_proto::OutputFormat;
-    use tokio_stream::wrappers::UnboundedReceiverStream;
 
     use super::*;
-    use crate::MockSearchService;
+    use crate::{result_channel, MockSearchService};
 
     #[tokio::test]
     async fn test_root_search_stream_single_split() -> anyhow::Result<()> {
@@ -133,16 +352,35 @@ mod tests {
             output_format: OutputFormat::Csv as i32,
             partition_by_field: None,
             tags: vec![],
+            distinct_count: false,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: Vec::new(),
+            cache_policy: 0,
         };
         let mut metastore = MockMetastore::new();
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
@@ -152,19 +390,21 @@ mod tests {
              _tags: &[String]| { Ok(vec![mock_split_meta("split1")]) },
         );
         let mut mock_search_service = MockSearchService::new();
-        let (result_sender, result_receiver) = tokio::sync::mpsc::unbounded_channel();
-        result_sender.send(Ok(quickwit_proto::LeafSearchStreamResult {
-            data: b"123".to_vec(),
-            split_id: "split_1".to_string(),
-        }))?;
-        result_sender.send(Ok(quickwit_proto::LeafSearchStreamResult {
-            data: b"456".to_vec(),
-            split_id: "split_1".to_string(),
-        }))?;
+        let (result_sender, result_receiver) = result_channel();
+        result_sender
+            .send(Ok(quickwit_proto::LeafSearchStreamResult {
+                data: b"123".to_vec(),
+                split_id: "split_1".to_string(),
+            }))
+            .await?;
+        result_sender
+            .send(Ok(quickwit_proto::LeafSearchStreamResult {
+                data: b"456".to_vec(),
+                split_id: "split_1".to_string(),
+            }))
+            .await?;
         mock_search_service.expect_leaf_search_stream().return_once(
-            |_leaf_search_req: quickwit_proto::LeafSearchStreamRequest| {
-                Ok(UnboundedReceiverStream::new(result_receiver))
-            },
+            |_leaf_search_req: quickwit_proto::LeafSearchStreamRequest| Ok(result_receiver),
         );
         // The test will hang on indefinitely if we don't drop the receiver.
         drop(result_sender);
@@ -192,16 +432,35 @@ mod tests {
             output_format: OutputFormat::Csv as i32,
             partition_by_field: Some("timestamp".to_string()),
             tags: vec![],
+            distinct_count: false,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: Vec::new(),
+            cache_policy: 0,
         };
         let mut metastore = MockMetastore::new();
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
@@ -211,19 +470,21 @@ mod tests {
              _tags: &[String]| { Ok(vec![mock_split_meta("split1")]) },
         );
         let mut mock_search_service = MockSearchService::new();
-        let (result_sender, result_receiver) = tokio::sync::mpsc::unbounded_channel();
-        result_sender.send(Ok(quickwit_proto::LeafSearchStreamResult {
-            data: b"123".to_vec(),
-            split_id: "1".to_string(),
-        }))?;
-        result_sender.send(Ok(quickwit_proto::LeafSearchStreamResult {
-            data: b"456".to_vec(),
-            split_id: "2".to_string(),
-        }))?;
+        let (result_sender, result_receiver) = result_channel();
+        result_sender
+            .send(Ok(quickwit_proto::LeafSearchStreamResult {
+                data: b"123".to_vec(),
+                split_id: "1".to_string(),
+            }))
+            .await?;
+        result_sender
+            .send(Ok(quickwit_proto::LeafSearchStreamResult {
+                data: b"456".to_vec(),
+                split_id: "2".to_string(),
+            }))
+            .await?;
         mock_search_service.expect_leaf_search_stream().return_once(
-            |_leaf_search_req: quickwit_proto::LeafSearchStreamRequest| {
-                Ok(UnboundedReceiverStream::new(result_receiver))
-            },
+            |_leaf_search_req: quickwit_proto::LeafSearchStreamRequest| Ok(result_receiver),
         );
         // The test will hang on indefinitely if we don't drop the sender.
         drop(result_sender);
@@ -238,6 +499,171 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_root_search_stream_click_house_sink_partitionned() -> anyhow::Result<()> {
+        let request = quickwit_proto::SearchStreamRequest {
+            index_id: "test-idx".to_string(),
+            query: "test".to_string(),
+            search_fields: vec!["body".to_string()],
+            start_timestamp: None,
+            end_timestamp: None,
+            fast_field: "timestamp".to_string(),
+            output_format: OutputFormat::Csv as i32,
+            partition_by_field: Some("timestamp".to_string()),
+            tags: vec![],
+            distinct_count: false,
+            click_house_sink: Some(quickwit_proto::ClickHouseSink {
+                dsn: "tcp://localhost:9000".to_string(),
+                table: "events".to_string(),
+            }),
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: Vec::new(),
+            cache_policy: 0,
+        };
+        let mut metastore = MockMetastore::new();
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
+        metastore
+            .expect_index_metadata()
+            .returning(|_index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_list_splits().returning(
+            |_index_id: &str,
+             _split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| { Ok(vec![mock_split_meta("split1")]) },
+        );
+        let client_pool =
+            Arc::new(SearchClientPool::from_mocks(vec![Arc::new(MockSearchService::new())]).await?);
+        let cluster_client = ClusterClient::new(client_pool.clone());
+        let result = root_search_stream(&request, &metastore, &cluster_client, &client_pool).await;
+        assert!(matches!(result, Err(SearchError::InvalidQuery(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_root_search_stream_parquet() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "ts",
+                    "type": "i64",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let index_config =
+            Arc::new(serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?);
+        let mut request = quickwit_proto::SearchStreamRequest {
+            index_id: "test-idx".to_string(),
+            query: "test".to_string(),
+            search_fields: vec!["body".to_string()],
+            start_timestamp: None,
+            end_timestamp: None,
+            fast_field: "ts".to_string(),
+            output_format: OutputFormat::Parquet as i32,
+            partition_by_field: None,
+            tags: vec![],
+            distinct_count: false,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: vec!["minute=ts/60".to_string()],
+            cache_policy: 0,
+        };
+        let mut metastore = MockMetastore::new();
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
+        metastore
+            .expect_index_metadata()
+            .returning(move |_index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
+                    index_config: index_config.clone(),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_list_splits().returning(
+            |_index_id: &str,
+             _split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| { Ok(vec![mock_split_meta("split1")]) },
+        );
+        let mut mock_search_service = MockSearchService::new();
+        let (result_sender, result_receiver) = result_channel();
+        for rows in [[120i64, 2i64], [-60i64, -1i64]] {
+            result_sender
+                .send(Ok(quickwit_proto::LeafSearchStreamResult {
+                    data: rows.iter().flat_map(|value| value.to_le_bytes()).collect(),
+                    split_id: "split1".to_string(),
+                }))
+                .await?;
+        }
+        mock_search_service
+            .expect_leaf_search_stream()
+            .withf(|leaf_request| {
+                leaf_request.request.as_ref().unwrap().output_format
+                    == OutputFormat::ClickHouseRowBinary as i32
+            })
+            .return_once(
+                |_leaf_search_req: quickwit_proto::LeafSearchStreamRequest| Ok(result_receiver),
+            );
+        // The test will hang on indefinitely if we don't drop the sender.
+        drop(result_sender);
+        let client_pool =
+            Arc::new(SearchClientPool::from_mocks(vec![Arc::new(mock_search_service)]).await?);
+        let cluster_client = ClusterClient::new(client_pool.clone());
+        let result: Vec<Bytes> =
+            root_search_stream(&request, &metastore, &cluster_client, &client_pool).await?;
+        let mut writer = ParquetWriter::new(vec![
+            ParquetColumn::new("ts", ParquetColumnType::Int64),
+            ParquetColumn::new("minute", ParquetColumnType::Int64),
+        ]);
+        writer.write_row_group(&[120, 2]);
+        writer.write_row_group(&[(-60i64) as u64, (-1i64) as u64]);
+        assert_eq!(result, vec![Bytes::from(writer.finish())]);
+
+        request.partition_by_field = Some("ts".to_string());
+        let result = root_search_stream(&request, &metastore, &cluster_client, &client_pool).await;
+        assert!(matches!(result, Err(SearchError::InvalidQuery(_))));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_root_search_stream_single_split_with_error() -> anyhow::Result<()> {
         let request = quickwit_proto::SearchStreamRequest {
@@ -250,16 +676,35 @@ mod tests {
             output_format: OutputFormat::Csv as i32,
             partition_by_field: None,
             tags: vec![],
+            distinct_count: false,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: Vec::new(),
+            cache_policy: 0,
         };
         let mut metastore = MockMetastore::new();
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
@@ -271,19 +716,21 @@ mod tests {
             },
         );
         let mut mock_search_service = MockSearchService::new();
-        let (result_sender, result_receiver) = tokio::sync::mpsc::unbounded_channel();
-        result_sender.send(Ok(quickwit_proto::LeafSearchStreamResult {
-            data: b"123".to_vec(),
-            split_id: "split1".to_string(),
-        }))?;
-        result_sender.send(Err(SearchError::InternalError("error".to_string())))?;
+        let (result_sender, result_receiver) = result_channel();
+        result_sender
+            .send(Ok(quickwit_proto::LeafSearchStreamResult {
+                data: b"123".to_vec(),
+                split_id: "split1".to_string(),
+            }))
+            .await?;
+        result_sender
+            .send(Err(SearchError::InternalError("error".to_string())))
+            .await?;
         mock_search_service
             .expect_leaf_search_stream()
             .withf(|request| request.split_metadata.len() == 2) // First request.
             .return_once(
-                |_leaf_search_req: quickwit_proto::LeafSearchStreamRequest| {
-                    Ok(UnboundedReceiverStream::new(result_receiver))
-                },
+                |_leaf_search_req: quickwit_proto::LeafSearchStreamRequest| Ok(result_receiver),
             );
         mock_search_service
             .expect_leaf_search_stream()
@@ -0,0 +1,201 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+
+use once_cell::sync::OnceCell;
+use tokio::sync::Semaphore;
+use tokio::task::JoinError;
+
+/// Limits on the CPU used by the leaf searches of a searcher node.
+///
+/// Each split is searched by a single thread, so these limits are expressed in
+/// splits searched at the same time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchThreadPoolParams {
+    /// Number of splits searched at the same time, across all the queries.
+    pub num_threads: usize,
+    /// Number of splits searched at the same time for a single query.
+    pub max_threads_per_query: usize,
+}
+
+impl Default for SearchThreadPoolParams {
+    fn default() -> Self {
+        let num_threads = num_cpus::get();
+        SearchThreadPoolParams {
+            num_threads,
+            max_threads_per_query: (num_threads + 1) / 2,
+        }
+    }
+}
+
+static SEARCH_THREAD_POOL: OnceCell<SearchThreadPool> = OnceCell::new();
+
+/// Configures the thread pool running the leaf searches of this searcher node.
+///
+/// The thread pool can only be configured once, before the first search.
+pub fn configure_search_thread_pool(params: SearchThreadPoolParams) -> anyhow::Result<()> {
+    if params.num_threads == 0 || params.max_threads_per_query == 0 {
+        anyhow::bail!("The number of search threads should be strictly positive.");
+    }
+    SEARCH_THREAD_POOL
+        .set(SearchThreadPool::new(params))
+        .map_err(|_| anyhow::anyhow!("The search thread pool is already configured."))
+}
+
+/// Returns the search thread pool, configured with the default parameters if
+/// [`configure_search_thread_pool`] has not been called.
+pub(crate) fn global_search_thread_pool() -> &'static SearchThreadPool {
+    SEARCH_THREAD_POOL.get_or_init(|| SearchThreadPool::new(SearchThreadPoolParams::default()))
+}
+
+/// Shares the search threads between the queries running at the same time.
+///
+/// The threads are handed out first come, first served, and a query only waits for
+/// them with at most `max_threads_per_query` of its splits. A query searching many
+/// splits therefore takes turns with the other queries instead of starving them.
+pub(crate) struct SearchThreadPool {
+    max_threads_per_query: usize,
+    threads: Arc<Semaphore>,
+}
+
+impl SearchThreadPool {
+    fn new(params: SearchThreadPoolParams) -> Self {
+        SearchThreadPool {
+            max_threads_per_query: params.max_threads_per_query.min(params.num_threads),
+            threads: Arc::new(Semaphore::new(params.num_threads)),
+        }
+    }
+
+    /// Returns the thread budget of a new query.
+    pub fn query_budget(&self) -> QueryThreadBudget {
+        QueryThreadBudget {
+            pool_threads: self.threads.clone(),
+            query_threads: Arc::new(Semaphore::new(self.max_threads_per_query)),
+        }
+    }
+}
+
+/// Threads available to the splits of a single query.
+#[derive(Clone)]
+pub(crate) struct QueryThreadBudget {
+    pool_threads: Arc<Semaphore>,
+    query_threads: Arc<Semaphore>,
+}
+
+impl QueryThreadBudget {
+    /// Runs `cpu_task` on a blocking thread, once both the query and the pool have a
+    /// thread available.
+    pub async fn spawn_blocking<F, T>(&self, cpu_task: F) -> Result<T, JoinError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let query_permit = self
+            .query_threads
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("The query thread semaphore should not be closed.");
+        let pool_permit = self
+            .pool_threads
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("The pool thread semaphore should not be closed.");
+        tokio::task::spawn_blocking(move || {
+            // The permits are released once the task is done, even if the
+            // query has been cancelled in the meantime.
+            let _permits = (query_permit, pool_permit);
+            cpu_task()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use futures::future::join_all;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_query_thread_budget_limits_concurrent_splits() {
+        let thread_pool = SearchThreadPool::new(SearchThreadPoolParams {
+            num_threads: 4,
+            max_threads_per_query: 2,
+        });
+        let query_budget = thread_pool.query_budget();
+        let num_running = Arc::new(AtomicUsize::new(0));
+        let max_num_running = Arc::new(AtomicUsize::new(0));
+        let split_futures = (0..6).map(|_| {
+            let num_running = num_running.clone();
+            let max_num_running = max_num_running.clone();
+            query_budget.spawn_blocking(move || {
+                let num_running_now = num_running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_num_running.fetch_max(num_running_now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                num_running.fetch_sub(1, Ordering::SeqCst);
+            })
+        });
+        assert!(join_all(split_futures).await.iter().all(Result::is_ok));
+        assert_eq!(max_num_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_search_thread_pool_shares_threads_between_queries() {
+        let thread_pool = SearchThreadPool::new(SearchThreadPoolParams {
+            num_threads: 2,
+            max_threads_per_query: 1,
+        });
+        let large_query_budget = thread_pool.query_budget();
+        let small_query_budget = thread_pool.query_budget();
+        let completed_queries = Arc::new(Mutex::new(Vec::new()));
+        let search_split = |budget: &QueryThreadBudget, query: &'static str| {
+            let completed_queries = completed_queries.clone();
+            budget.spawn_blocking(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                completed_queries.lock().unwrap().push(query);
+            })
+        };
+        let large_query_futures: Vec<_> = (0..4)
+            .map(|_| search_split(&large_query_budget, "large"))
+            .collect();
+        let small_query_future = search_split(&small_query_budget, "small");
+        let (_, small_query_result) =
+            futures::join!(join_all(large_query_futures), small_query_future);
+        assert!(small_query_result.is_ok());
+        let completed_queries = completed_queries.lock().unwrap();
+        assert_eq!(completed_queries.len(), 5);
+        assert_ne!(completed_queries.last(), Some(&"small"));
+    }
+
+    #[test]
+    fn test_configure_search_thread_pool_rejects_zero_threads() {
+        assert!(configure_search_thread_pool(SearchThreadPoolParams {
+            num_threads: 0,
+            max_threads_per_query: 1,
+        })
+        .is_err());
+    }
+}
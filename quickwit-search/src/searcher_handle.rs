@@ -0,0 +1,218 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use quickwit_metastore::{Metastore, MetastoreUriResolver};
+use quickwit_proto::{Hit, SearchRequest, SearchResponse, SearchStreamRequest};
+use quickwit_storage::{quickwit_storage_uri_resolver, StorageUriResolver};
+
+use crate::{
+    load_cold_start_bundle, root_fetch_doc, root_search, root_search_stream, ClusterClient,
+    ColdStartProfile, HitAddress, NodeAttributes, SearchClientPool, SearchServiceImpl, StandbyMode,
+};
+
+/// Handle running the root and leaf searches in process, without joining a cluster or
+/// starting any gRPC server.
+///
+/// It lets an application, e.g. a serverless query function, search the indexes of a
+/// metastore directly. The root search dispatches the leaf searches to a search service of
+/// the same process, so the responses are those of a search server.
+#[derive(Clone)]
+pub struct SearcherHandle {
+    metastore: Arc<dyn Metastore>,
+    cluster_client: ClusterClient,
+    client_pool: Arc<SearchClientPool>,
+}
+
+impl SearcherHandle {
+    /// Creates a handle searching the indexes of `metastore`, whose splits are read
+    /// through `storage_resolver`.
+    pub fn new(metastore: Arc<dyn Metastore>, storage_resolver: StorageUriResolver) -> Self {
+        // The leaf service never dispatches searches, so it gets an empty client pool.
+        let leaf_client_pool = Arc::new(SearchClientPool::default());
+        let leaf_service = Arc::new(SearchServiceImpl::new(
+            metastore.clone(),
+            storage_resolver,
+            ClusterClient::new(leaf_client_pool.clone()),
+            leaf_client_pool,
+            NodeAttributes::default(),
+            StandbyMode::new(false),
+        ));
+        let client_pool = Arc::new(SearchClientPool::for_local_service(leaf_service));
+        SearcherHandle {
+            metastore,
+            cluster_client: ClusterClient::new(client_pool.clone()),
+            client_pool,
+        }
+    }
+
+    /// Creates a handle searching the indexes of the metastore at `metastore_uri`, with the
+    /// default storage resolver.
+    pub async fn open(metastore_uri: &str) -> anyhow::Result<Self> {
+        let metastore = MetastoreUriResolver::default()
+            .resolve(metastore_uri)
+            .await?;
+        Ok(Self::new(
+            metastore,
+            quickwit_storage_uri_resolver().clone(),
+        ))
+    }
+
+    /// Creates a handle searching the indexes of the metastore at `metastore_uri`, with the
+    /// default storage resolver, skipping the startup work avoided by `profile`.
+    ///
+    /// If the profile has a cold start bundle, the metastore at `metastore_uri` must be
+    /// file-backed, and is replaced by the snapshot of the bundle.
+    pub async fn open_with_profile(
+        metastore_uri: &str,
+        profile: &ColdStartProfile,
+    ) -> anyhow::Result<Self> {
+        let mut storage_resolver = quickwit_storage_uri_resolver().clone();
+        if profile.lazy_storage {
+            storage_resolver = storage_resolver.lazy();
+        }
+        let metastore = match &profile.bundle_dir_opt {
+            Some(bundle_dir) => {
+                load_cold_start_bundle(bundle_dir, metastore_uri, &storage_resolver)?
+            }
+            None => {
+                MetastoreUriResolver::default()
+                    .resolve(metastore_uri)
+                    .await?
+            }
+        };
+        Ok(Self::new(metastore, storage_resolver))
+    }
+
+    /// Performs a search, see [`root_search`].
+    pub async fn search(&self, search_request: &SearchRequest) -> crate::Result<SearchResponse> {
+        root_search(
+            search_request,
+            &*self.metastore,
+            &self.cluster_client,
+            &self.client_pool,
+        )
+        .await
+    }
+
+    /// Performs a search stream, see [`root_search_stream`].
+    pub async fn search_stream(
+        &self,
+        search_stream_request: &SearchStreamRequest,
+    ) -> crate::Result<Vec<Bytes>> {
+        root_search_stream(
+            search_stream_request,
+            &*self.metastore,
+            &self.cluster_client,
+            &self.client_pool,
+        )
+        .await
+    }
+
+    /// Fetches the document of a hit address returned by [`SearcherHandle::search`].
+    pub async fn fetch_doc(&self, index_id: &str, hit_address: &HitAddress) -> crate::Result<Hit> {
+        root_fetch_doc(
+            index_id,
+            hit_address,
+            &*self.metastore,
+            &self.cluster_client,
+            &self.client_pool,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::from_utf8;
+
+    use quickwit_index_config::DefaultIndexConfigBuilder;
+    use quickwit_indexing::TestSandbox;
+    use quickwit_proto::OutputFormat;
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_searcher_handle() -> anyhow::Result<()> {
+        let index_config = r#"{
+            "default_search_fields": ["body"],
+            "timestamp_field": "ts",
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                },
+                {
+                    "name": "ts",
+                    "type": "i64",
+                    "fast": true
+                }
+            ]
+        }"#;
+        let index_config =
+            Arc::new(serde_json::from_str::<DefaultIndexConfigBuilder>(index_config)?.build()?);
+        let index_id = "searcher-handle";
+        let test_sandbox = TestSandbox::create(index_id, index_config).await?;
+        let docs = vec![
+            json!({"body": "info beagle", "ts": 1}),
+            json!({"body": "info snoopy", "ts": 2}),
+            json!({"body": "warn snoopy", "ts": 3}),
+        ];
+        test_sandbox.add_documents(docs).await?;
+        let searcher_handle = SearcherHandle::new(
+            test_sandbox.metastore(),
+            test_sandbox.storage_uri_resolver(),
+        );
+
+        let search_request = SearchRequest {
+            index_id: index_id.to_string(),
+            query: "snoopy".to_string(),
+            max_hits: 10,
+            ..Default::default()
+        };
+        let search_response = searcher_handle.search(&search_request).await?;
+        assert_eq!(search_response.num_hits, 2);
+        assert_eq!(search_response.hits.len(), 2);
+
+        let hit_address: HitAddress = search_response.hits[0].address.parse()?;
+        let hit = searcher_handle.fetch_doc(index_id, &hit_address).await?;
+        assert_eq!(hit.json, search_response.hits[0].json);
+
+        let search_stream_request = SearchStreamRequest {
+            index_id: index_id.to_string(),
+            query: "info".to_string(),
+            fast_field: "ts".to_string(),
+            output_format: OutputFormat::Csv as i32,
+            ..Default::default()
+        };
+        let data = searcher_handle
+            .search_stream(&search_stream_request)
+            .await?;
+        let mut values: Vec<&str> = data
+            .iter()
+            .flat_map(|chunk| from_utf8(chunk).unwrap().lines())
+            .collect();
+        values.sort_unstable();
+        assert_eq!(values, vec!["1", "2"]);
+        Ok(())
+    }
+}
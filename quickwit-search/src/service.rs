@@ -17,23 +17,35 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use lru::LruCache;
+use quickwit_common::RealtimeSplits;
 use quickwit_index_config::IndexConfig;
-use quickwit_metastore::Metastore;
+use quickwit_metastore::{retry_on_transient_error, Metastore};
 use quickwit_proto::{
-    FetchDocsRequest, FetchDocsResponse, LeafSearchRequest, LeafSearchResponse,
-    LeafSearchStreamRequest, LeafSearchStreamResult, SearchRequest, SearchResponse,
-    SearchStreamRequest,
+    FetchDocsRequest, FetchDocsResponse, Hit, IntermediateSearchRequest, LeafSearchRealtimeRequest,
+    LeafSearchRealtimeResponse, LeafSearchRequest, LeafSearchResponse, LeafSearchStreamRequest,
+    NodeInfoRequest, NodeInfoResponse, SearchRequest, SearchResponse, SearchStreamRequest,
 };
 use quickwit_storage::StorageUriResolver;
-use tokio_stream::wrappers::UnboundedReceiverStream;
 use tracing::info;
 
-use crate::search_stream::{leaf_search_stream, root_search_stream};
-use crate::{fetch_docs, leaf_search, root_search, ClusterClient, SearchClientPool, SearchError};
+use crate::index_config_cache::IndexConfigCache;
+use crate::leaf::cache_policy;
+use crate::realtime::leaf_search_realtime;
+use crate::search_stream::{leaf_search_stream, root_search_stream, ResultReceiverStream};
+use crate::synonyms::load_companion_synonyms;
+use crate::tree_aggregation::intermediate_search;
+use crate::{
+    fetch_docs, leaf_search, root_estimate, root_fetch_doc, root_search, ClusterClient, HitAddress,
+    NodeAttributes, SearchClientPool, SearchError, SearchEstimate, StandbyMode,
+};
+
+/// Maximum number of index configs resolved against the metastore kept in memory.
+const INDEX_CONFIG_VERSIONS_CACHE_CAPACITY: usize = 256;
 
 #[derive(Clone)]
 /// The search service implementation.
@@ -42,6 +54,13 @@ pub struct SearchServiceImpl {
     storage_resolver: StorageUriResolver,
     cluster_client: ClusterClient,
     client_pool: Arc<SearchClientPool>,
+    // Index configs are immutable once versioned, so they can be cached until evicted. The
+    // fingerprint in the key tells apart the configs of recreated indexes.
+    index_config_versions_cache: Arc<Mutex<LruCache<(String, u64, u64), Arc<dyn IndexConfig>>>>,
+    serialized_index_configs_cache: IndexConfigCache,
+    node_attributes: NodeAttributes,
+    standby_mode: StandbyMode,
+    realtime_splits: RealtimeSplits,
 }
 
 /// Trait representing a search service.
@@ -73,6 +92,14 @@ pub trait SearchService: 'static + Send + Sync {
     /// This methods takes `PartialHit`s and returns `Hit`s.
     async fn fetch_docs(&self, request: FetchDocsRequest) -> crate::Result<FetchDocsResponse>;
 
+    /// Fetches the document of a hit address returned by a previous root search.
+    async fn root_fetch_doc(&self, index_id: String, hit_address: HitAddress)
+        -> crate::Result<Hit>;
+
+    /// Estimates the cost of a search without executing it.
+    /// See [`root_estimate`](crate::root_estimate).
+    async fn root_estimate(&self, request: SearchRequest) -> crate::Result<SearchEstimate>;
+
     /// Performs a root search returning a receiver for streaming
     async fn root_search_stream(&self, request: SearchStreamRequest) -> crate::Result<Vec<Bytes>>;
 
@@ -80,7 +107,29 @@ pub trait SearchService: 'static + Send + Sync {
     async fn leaf_search_stream(
         &self,
         request: LeafSearchStreamRequest,
-    ) -> crate::Result<UnboundedReceiverStream<crate::Result<LeafSearchStreamResult>>>;
+    ) -> crate::Result<ResultReceiverStream>;
+
+    /// Returns the attributes of the node, which are matched against
+    /// the placement rules of the indexes, and whether it is a standby searcher.
+    async fn node_info(&self, request: NodeInfoRequest) -> crate::Result<NodeInfoResponse>;
+
+    /// Performs a leaf search on the splits being indexed by the node.
+    ///
+    /// The hits are returned with their documents, as they cannot be fetched from the
+    /// published splits.
+    async fn leaf_search_realtime(
+        &self,
+        request: LeafSearchRealtimeRequest,
+    ) -> crate::Result<LeafSearchRealtimeResponse>;
+
+    /// Performs leaf searches on other nodes and merges their responses.
+    ///
+    /// On large clusters, the root delegates the leaf searches to a few intermediate nodes,
+    /// so that it merges a handful of responses rather than one per leaf node.
+    async fn intermediate_search(
+        &self,
+        request: IntermediateSearchRequest,
+    ) -> crate::Result<LeafSearchResponse>;
 }
 
 impl SearchServiceImpl {
@@ -90,25 +139,95 @@ impl SearchServiceImpl {
         storage_resolver: StorageUriResolver,
         cluster_client: ClusterClient,
         client_pool: Arc<SearchClientPool>,
+        node_attributes: NodeAttributes,
+        standby_mode: StandbyMode,
     ) -> Self {
         SearchServiceImpl {
             metastore,
             storage_resolver,
             cluster_client,
             client_pool,
+            index_config_versions_cache: Arc::new(Mutex::new(LruCache::new(
+                INDEX_CONFIG_VERSIONS_CACHE_CAPACITY,
+            ))),
+            serialized_index_configs_cache: IndexConfigCache::default(),
+            node_attributes,
+            standby_mode,
+            realtime_splits: RealtimeSplits::default(),
         }
     }
-}
 
-fn deserialize_index_config(index_config_str: &str) -> crate::Result<Arc<dyn IndexConfig>> {
-    let index_config =
-        serde_json::from_str::<Arc<dyn IndexConfig>>(index_config_str).map_err(|err| {
-            SearchError::InternalError(format!(
-                "Failed to deserialize index config: `{}`",
-                err.to_string()
-            ))
-        })?;
-    Ok(index_config)
+    /// Serves leaf searches over the splits being indexed by the indexers of this node.
+    pub fn with_realtime_splits(mut self, realtime_splits: RealtimeSplits) -> Self {
+        self.realtime_splits = realtime_splits;
+        self
+    }
+
+    /// Returns the index config a leaf request should be executed with.
+    ///
+    /// Requests either carry the serialized index config, or only its version, which is
+    /// resolved against the metastore. Roots that predate index config versioning
+    /// serialize the config in JSON.
+    ///
+    /// Configs resolved against the metastore are only cached when the request carries their
+    /// fingerprint: the version alone does not tell apart the configs of an index deleted and
+    /// recreated with the same ID.
+    async fn leaf_index_config(
+        &self,
+        index_id: &str,
+        index_config_version: u64,
+        index_config_fingerprint: u64,
+        index_config_str: &str,
+    ) -> crate::Result<Arc<dyn IndexConfig>> {
+        if !index_config_str.is_empty() {
+            return self
+                .serialized_index_configs_cache
+                .get_or_decode(index_config_str.as_bytes());
+        }
+        let cache_key = (
+            index_id.to_string(),
+            index_config_version,
+            index_config_fingerprint,
+        );
+        if index_config_fingerprint != 0 {
+            let cached_index_config_opt = self
+                .index_config_versions_cache
+                .lock()
+                .unwrap()
+                .get(&cache_key)
+                .cloned();
+            if let Some(index_config) = cached_index_config_opt {
+                return Ok(index_config);
+            }
+        }
+        let index_metadata =
+            retry_on_transient_error(|| self.metastore.index_metadata(index_id)).await?;
+        let index_config = index_metadata
+            .index_config_for_version(index_config_version)
+            .ok_or_else(|| {
+                SearchError::InternalError(format!(
+                    "Index config version {} of index `{}` does not exist.",
+                    index_config_version, index_id
+                ))
+            })?;
+        if index_config_fingerprint == 0 {
+            return Ok(index_config);
+        }
+        if index_metadata.index_config_fingerprint(index_config_version)
+            != Some(index_config_fingerprint)
+        {
+            return Err(SearchError::InternalError(format!(
+                "Index config version {} of index `{}` does not match the one of the request, the \
+                 index was probably recreated.",
+                index_config_version, index_id
+            )));
+        }
+        self.index_config_versions_cache
+            .lock()
+            .unwrap()
+            .put(cache_key, index_config.clone());
+        Ok(index_config)
+    }
 }
 
 #[async_trait]
@@ -133,11 +252,25 @@ impl SearchService for SearchServiceImpl {
             .search_request
             .ok_or_else(|| SearchError::InternalError("No search request.".to_string()))?;
         info!(index=?search_request.index_id, splits=?leaf_search_request.split_metadata, "leaf_search");
-        let storage = self
-            .storage_resolver
-            .resolve(&leaf_search_request.index_uri)?;
+        let storage = self.storage_resolver.resolve_with_mirror(
+            &leaf_search_request.index_uri,
+            mirror_index_uri_opt(&leaf_search_request.mirror_index_uri),
+        )?;
         let split_ids = leaf_search_request.split_metadata;
-        let index_config = deserialize_index_config(&leaf_search_request.index_config)?;
+        let index_config = self
+            .leaf_index_config(
+                &search_request.index_id,
+                leaf_search_request.index_config_version,
+                leaf_search_request.index_config_fingerprint,
+                &leaf_search_request.index_config,
+            )
+            .await?;
+        let index_config = load_companion_synonyms(
+            index_config,
+            storage.clone(),
+            search_request.disable_synonyms,
+        )
+        .await?;
 
         let leaf_search_response = leaf_search(
             &search_request,
@@ -154,20 +287,51 @@ impl SearchService for SearchServiceImpl {
         &self,
         fetch_docs_request: FetchDocsRequest,
     ) -> crate::Result<FetchDocsResponse> {
-        let storage = self
-            .storage_resolver
-            .resolve(&fetch_docs_request.index_uri)?;
+        let storage = self.storage_resolver.resolve_with_mirror(
+            &fetch_docs_request.index_uri,
+            mirror_index_uri_opt(&fetch_docs_request.mirror_index_uri),
+        )?;
+        let index_config = self
+            .leaf_index_config(
+                &fetch_docs_request.index_id,
+                fetch_docs_request.index_config_version,
+                fetch_docs_request.index_config_fingerprint,
+                "",
+            )
+            .await?;
 
         let fetch_docs_response = fetch_docs(
             fetch_docs_request.partial_hits,
             storage,
             &fetch_docs_request.split_metadata,
+            index_config.enrichments(),
+            fetch_docs_request.flatten_docs,
+            cache_policy(fetch_docs_request.cache_policy),
         )
         .await?;
 
         Ok(fetch_docs_response)
     }
 
+    async fn root_fetch_doc(
+        &self,
+        index_id: String,
+        hit_address: HitAddress,
+    ) -> crate::Result<Hit> {
+        root_fetch_doc(
+            &index_id,
+            &hit_address,
+            self.metastore.as_ref(),
+            &self.cluster_client,
+            &self.client_pool,
+        )
+        .await
+    }
+
+    async fn root_estimate(&self, search_request: SearchRequest) -> crate::Result<SearchEstimate> {
+        root_estimate(&search_request, self.metastore.as_ref(), &self.client_pool).await
+    }
+
     async fn root_search_stream(
         &self,
         stream_request: SearchStreamRequest,
@@ -185,15 +349,29 @@ impl SearchService for SearchServiceImpl {
     async fn leaf_search_stream(
         &self,
         leaf_stream_request: LeafSearchStreamRequest,
-    ) -> crate::Result<UnboundedReceiverStream<crate::Result<LeafSearchStreamResult>>> {
+    ) -> crate::Result<ResultReceiverStream> {
         let stream_request = leaf_stream_request
             .request
             .ok_or_else(|| SearchError::InternalError("No search request.".to_string()))?;
         info!(index=?stream_request.index_id, splits=?leaf_stream_request.split_metadata, "leaf_search");
-        let storage = self
-            .storage_resolver
-            .resolve(&leaf_stream_request.index_uri)?;
-        let index_config = deserialize_index_config(&leaf_stream_request.index_config)?;
+        let storage = self.storage_resolver.resolve_with_mirror(
+            &leaf_stream_request.index_uri,
+            mirror_index_uri_opt(&leaf_stream_request.mirror_index_uri),
+        )?;
+        let index_config = self
+            .leaf_index_config(
+                &stream_request.index_id,
+                leaf_stream_request.index_config_version,
+                leaf_stream_request.index_config_fingerprint,
+                &leaf_stream_request.index_config,
+            )
+            .await?;
+        let index_config = load_companion_synonyms(
+            index_config,
+            storage.clone(),
+            stream_request.disable_synonyms,
+        )
+        .await?;
         let leaf_receiver = leaf_search_stream(
             stream_request,
             storage.clone(),
@@ -203,4 +381,61 @@ impl SearchService for SearchServiceImpl {
         .await;
         Ok(leaf_receiver)
     }
+
+    async fn node_info(&self, _request: NodeInfoRequest) -> crate::Result<NodeInfoResponse> {
+        Ok(NodeInfoResponse {
+            attributes: self.node_attributes.clone().into_iter().collect(),
+            standby: self.standby_mode.is_standby(),
+        })
+    }
+
+    async fn leaf_search_realtime(
+        &self,
+        realtime_request: LeafSearchRealtimeRequest,
+    ) -> crate::Result<LeafSearchRealtimeResponse> {
+        let search_request = realtime_request
+            .search_request
+            .ok_or_else(|| SearchError::InternalError("No search request.".to_string()))?;
+        let index_metadata =
+            retry_on_transient_error(|| self.metastore.index_metadata(&search_request.index_id))
+                .await?;
+        let storage = self.storage_resolver.resolve_with_mirror(
+            &index_metadata.index_uri,
+            index_metadata.mirror_index_uri.as_deref(),
+        )?;
+        let index_config = load_companion_synonyms(
+            index_metadata.index_config,
+            storage,
+            search_request.disable_synonyms,
+        )
+        .await?;
+        leaf_search_realtime(
+            &search_request,
+            &realtime_request.excluded_split_ids,
+            index_config,
+            &self.realtime_splits,
+        )
+        .await
+    }
+
+    async fn intermediate_search(
+        &self,
+        intermediate_request: IntermediateSearchRequest,
+    ) -> crate::Result<LeafSearchResponse> {
+        intermediate_search(
+            intermediate_request,
+            &self.cluster_client,
+            &self.client_pool,
+        )
+        .await
+    }
+}
+
+/// Returns the mirror index URI of a leaf request, which is empty if the index has no mirror.
+fn mirror_index_uri_opt(mirror_index_uri: &str) -> Option<&str> {
+    if mirror_index_uri.is_empty() {
+        None
+    } else {
+        Some(mirror_index_uri)
+    }
 }
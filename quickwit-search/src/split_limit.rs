@@ -0,0 +1,267 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use quickwit_index_config::{SplitLimitConfig, SplitLimitPolicy};
+use quickwit_metastore::SplitMetadataAndFooterOffsets;
+use quickwit_proto::SearchRequest;
+
+use crate::SearchError;
+
+/// Maximum number of splits a query may span, and what happens to the queries spanning more.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SplitLimit {
+    pub max_num_splits: usize,
+    pub policy: SplitLimitPolicy,
+}
+
+/// Resolves the split limit of a search request targeting indexes with the split limit configs
+/// `split_limit_configs`.
+///
+/// The limit is the lowest of the limit of the request, set by its API key, and of the limits
+/// of the indexes. The queries exceeding it are chunked only if all the indexes chunk them.
+pub(crate) fn resolve_split_limit(
+    search_request: &SearchRequest,
+    split_limit_configs: impl IntoIterator<Item = SplitLimitConfig>,
+) -> Option<SplitLimit> {
+    let mut max_num_splits_opt = search_request
+        .max_num_splits
+        .map(|max_num_splits| max_num_splits as usize);
+    let mut policy = SplitLimitPolicy::Chunk;
+    for split_limit_config in split_limit_configs {
+        if let Some(max_num_splits) = split_limit_config.max_num_splits {
+            max_num_splits_opt =
+                Some(max_num_splits_opt.map_or(max_num_splits, |limit| limit.min(max_num_splits)));
+        }
+        if split_limit_config.on_limit_exceeded == SplitLimitPolicy::Reject {
+            policy = SplitLimitPolicy::Reject;
+        }
+    }
+    max_num_splits_opt.map(|max_num_splits| SplitLimit {
+        // A limit of 0 would never let any query run.
+        max_num_splits: max_num_splits.max(1),
+        policy,
+    })
+}
+
+/// Splits the splits of a query into the chunks of splits to search sequentially, one set of
+/// split IDs per index for each chunk.
+///
+/// Without a limit, or within it, the query runs in a single chunk. Otherwise, the query is
+/// either rejected or chunked, with the most recent splits first.
+pub(crate) fn plan_split_chunks<'a>(
+    split_metadata_maps: &'a [HashMap<String, SplitMetadataAndFooterOffsets>],
+    split_limit_opt: Option<SplitLimit>,
+) -> crate::Result<Vec<Vec<HashSet<&'a String>>>> {
+    let num_splits: usize = split_metadata_maps.iter().map(HashMap::len).sum();
+    let split_limit = match split_limit_opt {
+        Some(split_limit) if num_splits > split_limit.max_num_splits => split_limit,
+        _ => {
+            return Ok(vec![split_metadata_maps
+                .iter()
+                .map(|split_metadata_map| split_metadata_map.keys().collect())
+                .collect()]);
+        }
+    };
+    if split_limit.policy == SplitLimitPolicy::Reject {
+        return Err(SearchError::InvalidQuery(format!(
+            "The query spans {} splits, more than the limit of {}. Narrow down its time range, or \
+             search fewer indexes.",
+            num_splits, split_limit.max_num_splits
+        )));
+    }
+    let mut splits: Vec<(usize, &SplitMetadataAndFooterOffsets)> = split_metadata_maps
+        .iter()
+        .enumerate()
+        .flat_map(|(target_ord, split_metadata_map)| {
+            split_metadata_map
+                .values()
+                .map(move |split_metadata| (target_ord, split_metadata))
+        })
+        .collect();
+    // The splits without a time range may contain documents of any time, they come first.
+    splits.sort_by_key(|&(_, split_metadata)| {
+        let split_metadata = &split_metadata.split_metadata;
+        let time_range_end_opt = split_metadata
+            .time_range
+            .as_ref()
+            .map(|time_range| Reverse(*time_range.end()));
+        (time_range_end_opt, &split_metadata.split_id)
+    });
+    let chunks = splits
+        .chunks(split_limit.max_num_splits)
+        .map(|chunk_splits| {
+            let mut chunk: Vec<HashSet<&String>> = vec![HashSet::new(); split_metadata_maps.len()];
+            for (target_ord, split_metadata) in chunk_splits {
+                chunk[*target_ord].insert(&split_metadata.split_metadata.split_id);
+            }
+            chunk
+        })
+        .collect();
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_metastore::SplitMetadata;
+
+    use super::*;
+
+    fn split_metadata_map(
+        splits: &[(&str, Option<(i64, i64)>)],
+    ) -> HashMap<String, SplitMetadataAndFooterOffsets> {
+        splits
+            .iter()
+            .map(|(split_id, time_range_opt)| {
+                let split_metadata = SplitMetadataAndFooterOffsets {
+                    split_metadata: SplitMetadata {
+                        split_id: split_id.to_string(),
+                        time_range: time_range_opt.map(|(start, end)| start..=end),
+                        ..Default::default()
+                    },
+                    footer_offsets: 700..800,
+                };
+                (split_id.to_string(), split_metadata)
+            })
+            .collect()
+    }
+
+    fn chunk_split_ids(chunks: Vec<Vec<HashSet<&String>>>) -> Vec<Vec<Vec<String>>> {
+        chunks
+            .into_iter()
+            .map(|chunk| {
+                chunk
+                    .into_iter()
+                    .map(|split_ids| {
+                        let mut split_ids: Vec<String> = split_ids.into_iter().cloned().collect();
+                        split_ids.sort();
+                        split_ids
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_split_limit() {
+        let search_request = SearchRequest::default();
+        assert_eq!(
+            resolve_split_limit(&search_request, vec![SplitLimitConfig::default()]),
+            None
+        );
+        let chunk_config = |max_num_splits_opt| SplitLimitConfig {
+            max_num_splits: max_num_splits_opt,
+            on_limit_exceeded: SplitLimitPolicy::Chunk,
+        };
+        assert_eq!(
+            resolve_split_limit(
+                &search_request,
+                vec![chunk_config(Some(10)), chunk_config(Some(5))]
+            ),
+            Some(SplitLimit {
+                max_num_splits: 5,
+                policy: SplitLimitPolicy::Chunk
+            })
+        );
+        assert_eq!(
+            resolve_split_limit(
+                &search_request,
+                vec![chunk_config(Some(10)), SplitLimitConfig::default()]
+            ),
+            Some(SplitLimit {
+                max_num_splits: 10,
+                policy: SplitLimitPolicy::Reject
+            })
+        );
+        let search_request = SearchRequest {
+            max_num_splits: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve_split_limit(&search_request, vec![chunk_config(Some(10))]),
+            Some(SplitLimit {
+                max_num_splits: 3,
+                policy: SplitLimitPolicy::Chunk
+            })
+        );
+    }
+
+    #[test]
+    fn test_plan_split_chunks_within_limit() -> anyhow::Result<()> {
+        let split_metadata_maps = vec![
+            split_metadata_map(&[("split1", None), ("split2", None)]),
+            split_metadata_map(&[("split3", None)]),
+        ];
+        let split_limit = SplitLimit {
+            max_num_splits: 3,
+            policy: SplitLimitPolicy::Reject,
+        };
+        let chunks = plan_split_chunks(&split_metadata_maps, Some(split_limit))?;
+        assert_eq!(
+            chunk_split_ids(chunks),
+            vec![vec![
+                vec!["split1".to_string(), "split2".to_string()],
+                vec!["split3".to_string()]
+            ]]
+        );
+        let chunks = plan_split_chunks(&split_metadata_maps, None)?;
+        assert_eq!(chunks.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_split_chunks_rejects_queries_exceeding_limit() {
+        let split_metadata_maps = vec![split_metadata_map(&[
+            ("split1", None),
+            ("split2", None),
+            ("split3", None),
+        ])];
+        let split_limit = SplitLimit {
+            max_num_splits: 2,
+            policy: SplitLimitPolicy::Reject,
+        };
+        let error = plan_split_chunks(&split_metadata_maps, Some(split_limit)).unwrap_err();
+        assert!(
+            matches!(error, SearchError::InvalidQuery(message) if message.starts_with("The query spans 3 splits, more than the limit of 2."))
+        );
+    }
+
+    #[test]
+    fn test_plan_split_chunks_chunks_most_recent_splits_first() -> anyhow::Result<()> {
+        let split_metadata_maps = vec![
+            split_metadata_map(&[("split1", Some((0, 10))), ("split2", Some((20, 30)))]),
+            split_metadata_map(&[("split3", Some((10, 20))), ("split4", None)]),
+        ];
+        let split_limit = SplitLimit {
+            max_num_splits: 2,
+            policy: SplitLimitPolicy::Chunk,
+        };
+        let chunks = plan_split_chunks(&split_metadata_maps, Some(split_limit))?;
+        assert_eq!(
+            chunk_split_ids(chunks),
+            vec![
+                vec![vec!["split2".to_string()], vec!["split4".to_string()]],
+                vec![vec!["split1".to_string()], vec!["split3".to_string()]],
+            ]
+        );
+        Ok(())
+    }
+}
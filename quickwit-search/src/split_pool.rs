@@ -0,0 +1,276 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Mutex;
+
+use lru::LruCache;
+use once_cell::sync::OnceCell;
+use quickwit_directories::CachingDirectory;
+use tantivy::Index;
+
+/// Maximum number of opened splits kept in the global split pool.
+const SPLIT_POOL_CAPACITY: usize = 1_000;
+
+/// Maximum number of bytes the opened splits of the global split pool may hold in memory.
+const SPLIT_POOL_CAPACITY_IN_BYTES: usize = 2_000_000_000;
+
+/// Number of times an opened split is reused before being reopened.
+///
+/// The directory of an opened split caches every byte range read by the searches,
+/// so an opened split cannot live forever.
+const SPLIT_POOL_MAX_NUM_REUSES: usize = 100;
+
+pub(crate) fn global_split_pool() -> &'static SplitPool {
+    static INSTANCE: OnceCell<SplitPool> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        SplitPool::new(
+            SPLIT_POOL_CAPACITY,
+            SPLIT_POOL_CAPACITY_IN_BYTES,
+            SPLIT_POOL_MAX_NUM_REUSES,
+        )
+    })
+}
+
+/// An opened split, along with the memory it holds.
+#[derive(Clone)]
+pub(crate) struct OpenedSplit {
+    pub index: Index,
+    /// Number of bytes of the split footer, hotcache included.
+    pub footer_num_bytes: usize,
+    /// Directory caching the byte ranges read by the searches on the split.
+    pub caching_directory: CachingDirectory,
+    /// Number of bytes of the split file if it was downloaded whole, 0 otherwise.
+    pub whole_split_num_bytes: usize,
+}
+
+impl OpenedSplit {
+    fn num_bytes(&self) -> usize {
+        self.footer_num_bytes + self.caching_directory.num_bytes() + self.whole_split_num_bytes
+    }
+}
+
+struct PooledSplit {
+    opened_split: OpenedSplit,
+    num_reuses: usize,
+}
+
+/// LRU pool of opened splits, keyed by split ID.
+///
+/// Opening a split requires fetching its footer and opening its hotcache, which
+/// takes tens of milliseconds. The pool allows consecutive searches on the same
+/// split to skip this step.
+///
+/// The pool is bounded both by a number of splits and by the number of bytes the splits
+/// hold, which grows as their directories cache the byte ranges read by the searches. The
+/// bytes of the splits downloaded whole are part of it.
+pub(crate) struct SplitPool {
+    capacity_in_bytes: usize,
+    max_num_reuses: usize,
+    opened_splits: Mutex<LruCache<String, PooledSplit>>,
+}
+
+impl SplitPool {
+    pub fn new(capacity: usize, capacity_in_bytes: usize, max_num_reuses: usize) -> Self {
+        SplitPool {
+            capacity_in_bytes,
+            max_num_reuses,
+            opened_splits: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Returns the opened split if it is in the pool.
+    ///
+    /// The split is removed from the pool once it has been reused `max_num_reuses` times.
+    pub fn get(&self, split_id: &str) -> Option<Index> {
+        // Without the `nightly` feature of `lru`, the keys cannot be looked up by `&str`.
+        let split_id = split_id.to_string();
+        let mut opened_splits = self.opened_splits.lock().unwrap();
+        let pooled_split = opened_splits.get_mut(&split_id)?;
+        pooled_split.num_reuses += 1;
+        let index = pooled_split.opened_split.index.clone();
+        if pooled_split.num_reuses >= self.max_num_reuses {
+            opened_splits.pop(&split_id);
+        }
+        Some(index)
+    }
+
+    /// Adds an opened split to the pool, evicting the least recently used splits
+    /// if the pool is full.
+    pub fn put(&self, split_id: String, opened_split: OpenedSplit) {
+        if self.max_num_reuses == 0 || opened_split.num_bytes() > self.capacity_in_bytes {
+            return;
+        }
+        let mut opened_splits = self.opened_splits.lock().unwrap();
+        opened_splits.put(
+            split_id,
+            PooledSplit {
+                opened_split,
+                num_reuses: 0,
+            },
+        );
+        // The splits already in the pool may have grown since they were added.
+        let mut num_bytes = num_bytes(&opened_splits);
+        while num_bytes > self.capacity_in_bytes {
+            if let Some((_, evicted_split)) = opened_splits.pop_lru() {
+                num_bytes -= evicted_split.opened_split.num_bytes();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Removes a split from the pool, e.g. because searching it failed.
+    ///
+    /// Returns false if the split was not in the pool.
+    pub fn evict(&self, split_id: &str) -> bool {
+        self.opened_splits
+            .lock()
+            .unwrap()
+            .pop(&split_id.to_string())
+            .is_some()
+    }
+
+    /// Removes all of the splits from the pool.
+    pub fn clear(&self) {
+        self.opened_splits.lock().unwrap().clear();
+    }
+
+    /// Returns the IDs of the pooled splits, most recently used first.
+    pub fn split_ids(&self) -> Vec<String> {
+        self.opened_splits
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(split_id, _)| split_id.clone())
+            .collect()
+    }
+
+    /// Returns the number of bytes held by the pooled splits.
+    pub fn num_bytes(&self) -> usize {
+        num_bytes(&self.opened_splits.lock().unwrap())
+    }
+
+    /// Returns the maximum number of splits kept in the pool.
+    pub fn capacity(&self) -> usize {
+        self.opened_splits.lock().unwrap().cap()
+    }
+
+    /// Changes the maximum number of splits kept in the pool, evicting the least
+    /// recently used splits that do not fit in it anymore.
+    pub fn set_capacity(&self, capacity: usize) -> anyhow::Result<()> {
+        if capacity == 0 {
+            anyhow::bail!("The capacity of the split pool must be at least 1.");
+        }
+        self.opened_splits.lock().unwrap().resize(capacity);
+        Ok(())
+    }
+}
+
+fn num_bytes(opened_splits: &LruCache<String, PooledSplit>) -> usize {
+    opened_splits
+        .iter()
+        .map(|(_, pooled_split)| pooled_split.opened_split.num_bytes())
+        .sum()
+}
+
+#[cfg(test)]
+pub(crate) fn opened_split_for_test(footer_num_bytes: usize) -> OpenedSplit {
+    use std::sync::Arc;
+
+    use tantivy::directory::RamDirectory;
+    use tantivy::schema::Schema;
+
+    OpenedSplit {
+        index: Index::create_in_ram(Schema::builder().build()),
+        footer_num_bytes,
+        caching_directory: CachingDirectory::new_with_unlimited_capacity(Arc::new(
+            RamDirectory::create(),
+        )),
+        whole_split_num_bytes: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_pool_bounded_reuse() {
+        let split_pool = SplitPool::new(2, 1_000, 2);
+        let opened_split = opened_split_for_test(10);
+        assert!(split_pool.get("split1").is_none());
+
+        split_pool.put("split1".to_string(), opened_split.clone());
+        assert!(split_pool.get("split1").is_some());
+        assert!(split_pool.get("split1").is_some());
+        // The split has been reused twice.
+        assert!(split_pool.get("split1").is_none());
+
+        split_pool.put("split1".to_string(), opened_split.clone());
+        split_pool.put("split2".to_string(), opened_split.clone());
+        assert!(split_pool.get("split1").is_some());
+        split_pool.put("split3".to_string(), opened_split);
+        // `split2` was the least recently used split.
+        assert!(split_pool.get("split2").is_none());
+        assert!(split_pool.get("split3").is_some());
+
+        assert!(split_pool.evict("split3"));
+        assert!(!split_pool.evict("split3"));
+        assert!(split_pool.get("split3").is_none());
+    }
+
+    #[test]
+    fn test_split_pool_bounded_num_bytes() {
+        let split_pool = SplitPool::new(10, 100, 10);
+        split_pool.put("split1".to_string(), opened_split_for_test(40));
+        split_pool.put("split2".to_string(), opened_split_for_test(40));
+        assert_eq!(split_pool.num_bytes(), 80);
+        split_pool.put("split3".to_string(), opened_split_for_test(40));
+        // `split1` was the least recently used split.
+        assert_eq!(split_pool.split_ids(), vec!["split3", "split2"]);
+        assert_eq!(split_pool.num_bytes(), 80);
+        // Splits larger than the pool are not pooled.
+        split_pool.put("split4".to_string(), opened_split_for_test(101));
+        assert_eq!(split_pool.split_ids(), vec!["split3", "split2"]);
+        // The bytes of the splits downloaded whole count.
+        let mut whole_split = opened_split_for_test(10);
+        whole_split.whole_split_num_bytes = 50;
+        split_pool.put("split5".to_string(), whole_split);
+        assert_eq!(split_pool.split_ids(), vec!["split5", "split3"]);
+        assert_eq!(split_pool.num_bytes(), 100);
+    }
+
+    #[test]
+    fn test_split_pool_admin() {
+        let split_pool = SplitPool::new(3, 1_000, 10);
+        let opened_split = opened_split_for_test(10);
+        split_pool.put("split1".to_string(), opened_split.clone());
+        split_pool.put("split2".to_string(), opened_split.clone());
+        split_pool.put("split3".to_string(), opened_split);
+        assert_eq!(split_pool.split_ids(), vec!["split3", "split2", "split1"]);
+
+        assert!(split_pool.set_capacity(0).is_err());
+        split_pool.set_capacity(2).unwrap();
+        assert_eq!(split_pool.capacity(), 2);
+        assert_eq!(split_pool.split_ids(), vec!["split3", "split2"]);
+
+        split_pool.clear();
+        assert!(split_pool.split_ids().is_empty());
+    }
+}
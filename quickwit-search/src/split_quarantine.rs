@@ -0,0 +1,185 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use quickwit_proto::SplitSearchError;
+
+/// Default number of consecutive searches a split must fail before the roots quarantine it.
+pub const DEFAULT_SPLIT_QUARANTINE_THRESHOLD: usize = 3;
+
+/// Counts the consecutive searches each split failed, to quarantine the splits failing
+/// repeatedly, e.g. because their file is corrupted.
+///
+/// The failures are the ones remaining after the leaf searches were retried. Only the failures
+/// telling something about the split itself are counted: the error must not be retryable, and
+/// other splits of the same search must have succeeded. This way, neither a few slow searches,
+/// e.g. timing out or throttled by the storage, nor a search failing on every split, e.g.
+/// because the storage is unavailable, quarantine anything.
+///
+/// Clones share the same counts.
+#[derive(Clone)]
+pub(crate) struct SplitFailureTracker {
+    quarantine_threshold: usize,
+    num_consecutive_failures: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl SplitFailureTracker {
+    /// Creates a tracker quarantining the splits after `quarantine_threshold` consecutive
+    /// failed searches. A threshold of 0 never quarantines any split.
+    pub fn new(quarantine_threshold: usize) -> Self {
+        SplitFailureTracker {
+            quarantine_threshold,
+            num_consecutive_failures: Default::default(),
+        }
+    }
+
+    /// Records the outcome of a search of the splits `searched_split_ids`, of which
+    /// `failed_splits` failed, and returns the IDs of the splits to quarantine.
+    pub fn record_search<'a>(
+        &self,
+        searched_split_ids: impl IntoIterator<Item = &'a str>,
+        failed_splits: &[SplitSearchError],
+    ) -> Vec<String> {
+        if self.quarantine_threshold == 0 {
+            return Vec::new();
+        }
+        let mut num_consecutive_failures = self.num_consecutive_failures.lock().unwrap();
+        if failed_splits.is_empty() && num_consecutive_failures.is_empty() {
+            return Vec::new();
+        }
+        let failed_split_ids: HashSet<&str> = failed_splits
+            .iter()
+            .map(|failed_split| failed_split.split_id.as_str())
+            .collect();
+        let mut num_succeeded_splits = 0;
+        for split_id in searched_split_ids {
+            if !failed_split_ids.contains(split_id) {
+                num_succeeded_splits += 1;
+                num_consecutive_failures.remove(split_id);
+            }
+        }
+        if num_succeeded_splits == 0 {
+            return Vec::new();
+        }
+        let mut split_ids_to_quarantine = Vec::new();
+        // The retryable failures neither count nor reset the count of the split.
+        for failed_split in failed_splits
+            .iter()
+            .filter(|failed_split| !failed_split.retryable_error)
+        {
+            let split_num_failures = num_consecutive_failures
+                .entry(failed_split.split_id.clone())
+                .or_insert(0);
+            *split_num_failures += 1;
+            if *split_num_failures >= self.quarantine_threshold {
+                num_consecutive_failures.remove(&failed_split.split_id);
+                split_ids_to_quarantine.push(failed_split.split_id.clone());
+            }
+        }
+        split_ids_to_quarantine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split_error(split_id: &str) -> SplitSearchError {
+        SplitSearchError {
+            error: "error".to_string(),
+            split_id: split_id.to_string(),
+            retryable_error: false,
+        }
+    }
+
+    fn retryable_split_error(split_id: &str) -> SplitSearchError {
+        SplitSearchError {
+            retryable_error: true,
+            ..split_error(split_id)
+        }
+    }
+
+    #[test]
+    fn test_split_failure_tracker() {
+        let tracker = SplitFailureTracker::new(2);
+        let searched_split_ids = ["split1", "split2", "split3"];
+        assert!(tracker
+            .record_search(searched_split_ids, &[split_error("split1")])
+            .is_empty());
+        assert_eq!(
+            tracker.record_search(
+                searched_split_ids,
+                &[split_error("split1"), split_error("split2")]
+            ),
+            vec!["split1".to_string()]
+        );
+        // A successful search resets the count of the split.
+        assert!(tracker.record_search(searched_split_ids, &[]).is_empty());
+        assert!(tracker
+            .record_search(searched_split_ids, &[split_error("split2")])
+            .is_empty());
+    }
+
+    #[test]
+    fn test_split_failure_tracker_ignores_searches_failing_everywhere() {
+        let tracker = SplitFailureTracker::new(1);
+        let searched_split_ids = ["split1", "split2"];
+        assert!(tracker
+            .record_search(
+                searched_split_ids,
+                &[split_error("split1"), split_error("split2")]
+            )
+            .is_empty());
+        assert_eq!(
+            tracker.record_search(searched_split_ids, &[split_error("split2")]),
+            vec!["split2".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_split_failure_tracker_ignores_retryable_errors() {
+        let tracker = SplitFailureTracker::new(2);
+        let searched_split_ids = ["split1", "split2"];
+        assert!(tracker
+            .record_search(searched_split_ids, &[split_error("split1")])
+            .is_empty());
+        for _ in 0..10 {
+            assert!(tracker
+                .record_search(searched_split_ids, &[retryable_split_error("split1")])
+                .is_empty());
+        }
+        // The retryable failures did not reset the count of the split either.
+        assert_eq!(
+            tracker.record_search(searched_split_ids, &[split_error("split1")]),
+            vec!["split1".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_split_failure_tracker_disabled() {
+        let tracker = SplitFailureTracker::new(0);
+        for _ in 0..10 {
+            assert!(tracker
+                .record_search(["split1", "split2"], &[split_error("split1")])
+                .is_empty());
+        }
+    }
+}
@@ -0,0 +1,1080 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! A subset of SQL over an index, translated into search requests.
+//!
+//! ```sql
+//! SELECT <field>, ... | * FROM <index> [WHERE <condition>]
+//!     [ORDER BY <fast field> [ASC | DESC]] [LIMIT <n>]
+//! SELECT COUNT(*) FROM <index> [WHERE <condition>]
+//! SELECT <fast field>, COUNT(*), SUM | AVG | MIN | MAX(<fast field>) FROM <index>
+//!     [WHERE <condition>] GROUP BY <fast field> [ORDER BY <aggregate> [ASC | DESC]] [LIMIT <n>]
+//! ```
+//!
+//! The condition is translated into a query in the tantivy syntax. It combines with `AND`,
+//! `OR`, `NOT` and parentheses comparisons of a field with a value (`=`, `!=`, `<>`, `<`, `<=`,
+//! `>`, `>=`), `IN (<value>, ...)`, `BETWEEN <value> AND <value>` and `LIKE '<pattern>'`.
+//! Grouping is done with a terms aggregation, which computes at most one metric besides the
+//! number of documents per group: the one ordering the groups.
+
+use std::fmt;
+
+use quickwit_proto::{CachePolicy, SearchRequest, SearchResponse, TermsAggregation, TermsOrder};
+use serde::Serialize;
+use serde_json::{Map as JsonMap, Value as JsonValue};
+
+use crate::SearchError;
+
+/// Number of rows returned by a query without a `LIMIT` clause.
+const DEFAULT_LIMIT: u64 = 100;
+
+const KEYWORDS: [&str; 15] = [
+    "SELECT", "FROM", "WHERE", "GROUP", "BY", "ORDER", "LIMIT", "AND", "OR", "NOT", "ASC", "DESC",
+    "IN", "BETWEEN", "LIKE",
+];
+
+/// Characters that cannot appear unquoted in a query in the tantivy syntax.
+const QUERY_SPECIAL_CHARACTERS: &[char] = &[
+    '(', ')', '[', ']', '{', '}', '"', ':', '^', '~', '+', '-', '!', '\\', '*', '?',
+];
+
+fn invalid_query(message: impl Into<String>) -> SearchError {
+    SearchError::InvalidQuery(message.into())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// Identifier or keyword.
+    Word(String),
+    /// Identifier between double quotes or backquotes.
+    QuotedIdentifier(String),
+    String(String),
+    Number(String),
+    Symbol(&'static str),
+}
+
+fn tokenize(sql: &str) -> crate::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-') {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        } else if c.is_ascii_digit() || c == '-' {
+            let mut number = String::new();
+            number.push(c);
+            chars.next();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                number.push(c);
+                chars.next();
+            }
+            if number == "-" {
+                return Err(invalid_query("Expected a number after `-`."));
+            }
+            tokens.push(Token::Number(number));
+        } else if c == '\'' || c == '"' || c == '`' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    // A doubled quote stands for the quote itself.
+                    Some(quote) if quote == c && chars.peek() == Some(&c) => {
+                        text.push(c);
+                        chars.next();
+                    }
+                    Some(quote) if quote == c => break,
+                    Some(c) => text.push(c),
+                    None => return Err(invalid_query("Unterminated quoted text.")),
+                }
+            }
+            if c == '\'' {
+                tokens.push(Token::String(text));
+            } else {
+                tokens.push(Token::QuotedIdentifier(text));
+            }
+        } else {
+            chars.next();
+            let symbol = match (c, chars.peek()) {
+                ('<', Some('=')) => "<=",
+                ('>', Some('=')) => ">=",
+                ('<', Some('>')) => "<>",
+                ('!', Some('=')) => "!=",
+                ('<', _) => "<",
+                ('>', _) => ">",
+                ('=', _) => "=",
+                ('(', _) => "(",
+                (')', _) => ")",
+                (',', _) => ",",
+                ('*', _) => "*",
+                (';', _) => ";",
+                _ => return Err(invalid_query(format!("Unexpected character `{}`.", c))),
+            };
+            if symbol.len() == 2 {
+                chars.next();
+            }
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFunction {
+    fn from_name(name: &str) -> Option<AggregateFunction> {
+        match name.to_ascii_uppercase().as_str() {
+            "COUNT" => Some(AggregateFunction::Count),
+            "SUM" => Some(AggregateFunction::Sum),
+            "AVG" => Some(AggregateFunction::Avg),
+            "MIN" => Some(AggregateFunction::Min),
+            "MAX" => Some(AggregateFunction::Max),
+            _ => None,
+        }
+    }
+
+    fn terms_order(self) -> TermsOrder {
+        match self {
+            AggregateFunction::Count => TermsOrder::Count,
+            AggregateFunction::Sum => TermsOrder::Sum,
+            AggregateFunction::Avg => TermsOrder::Avg,
+            AggregateFunction::Min => TermsOrder::Min,
+            AggregateFunction::Max => TermsOrder::Max,
+        }
+    }
+}
+
+/// `COUNT(*)`, or a metric of a fast field over the documents of a group.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Aggregate {
+    function: AggregateFunction,
+    /// Not set for `COUNT(*)`.
+    field_opt: Option<String>,
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let function_name = format!("{:?}", self.function).to_ascii_uppercase();
+        write!(
+            f,
+            "{}({})",
+            function_name,
+            self.field_opt.as_deref().unwrap_or("*")
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SelectItem {
+    Wildcard,
+    Field(String),
+    Aggregate(Aggregate),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Literal {
+    String(String),
+    Number(String),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let token_opt = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token_opt
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let is_keyword = self.peek_keyword(keyword);
+        if is_keyword {
+            self.position += 1;
+        }
+        is_keyword
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> crate::Result<()> {
+        if !self.eat_keyword(keyword) {
+            return Err(self.unexpected(&format!("`{}`", keyword)));
+        }
+        Ok(())
+    }
+
+    fn eat_symbol(&mut self, symbol: &'static str) -> bool {
+        let is_symbol = self.peek() == Some(&Token::Symbol(symbol));
+        if is_symbol {
+            self.position += 1;
+        }
+        is_symbol
+    }
+
+    fn expect_symbol(&mut self, symbol: &'static str) -> crate::Result<()> {
+        if !self.eat_symbol(symbol) {
+            return Err(self.unexpected(&format!("`{}`", symbol)));
+        }
+        Ok(())
+    }
+
+    fn unexpected(&self, expected: &str) -> SearchError {
+        match self.peek() {
+            Some(token) => invalid_query(format!("Expected {}, found {:?}.", expected, token)),
+            None => invalid_query(format!(
+                "Expected {}, found the end of the query.",
+                expected
+            )),
+        }
+    }
+
+    fn parse_identifier(&mut self) -> crate::Result<String> {
+        match self.peek().cloned() {
+            Some(Token::Word(word))
+                if !KEYWORDS
+                    .iter()
+                    .any(|keyword| word.eq_ignore_ascii_case(keyword)) =>
+            {
+                self.position += 1;
+                Ok(word)
+            }
+            Some(Token::QuotedIdentifier(identifier)) => {
+                self.position += 1;
+                Ok(identifier)
+            }
+            _ => Err(self.unexpected("an identifier")),
+        }
+    }
+
+    /// Parses a field name, which must be usable as is in a query in the tantivy syntax.
+    fn parse_field_name(&mut self) -> crate::Result<String> {
+        let field_name = self.parse_identifier()?;
+        if field_name.is_empty()
+            || !field_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            return Err(invalid_query(format!(
+                "Invalid field name `{}`: field names are made of letters, digits, `_` and `.`.",
+                field_name
+            )));
+        }
+        Ok(field_name)
+    }
+
+    fn parse_literal(&mut self) -> crate::Result<Literal> {
+        match self.next_token() {
+            Some(Token::String(text)) => Ok(Literal::String(text)),
+            Some(Token::Number(number)) => Ok(Literal::Number(number)),
+            _ => {
+                self.position -= 1;
+                Err(self.unexpected("a string or a number"))
+            }
+        }
+    }
+
+    fn parse_select_item(&mut self) -> crate::Result<SelectItem> {
+        if self.eat_symbol("*") {
+            return Ok(SelectItem::Wildcard);
+        }
+        let function_opt = match (self.peek(), self.tokens.get(self.position + 1)) {
+            (Some(Token::Word(word)), Some(Token::Symbol("("))) => {
+                AggregateFunction::from_name(word)
+            }
+            _ => None,
+        };
+        let function = if let Some(function) = function_opt {
+            function
+        } else {
+            return Ok(SelectItem::Field(self.parse_field_name()?));
+        };
+        Ok(SelectItem::Aggregate(self.parse_aggregate(function)?))
+    }
+
+    fn parse_aggregate(&mut self, function: AggregateFunction) -> crate::Result<Aggregate> {
+        self.position += 1;
+        self.expect_symbol("(")?;
+        let field_opt = if function == AggregateFunction::Count {
+            self.expect_symbol("*")?;
+            None
+        } else {
+            Some(self.parse_field_name()?)
+        };
+        self.expect_symbol(")")?;
+        Ok(Aggregate {
+            function,
+            field_opt,
+        })
+    }
+
+    fn parse_or(&mut self) -> crate::Result<String> {
+        let mut clauses = vec![self.parse_and()?];
+        while self.eat_keyword("OR") {
+            clauses.push(self.parse_and()?);
+        }
+        Ok(join_clauses(clauses, "OR"))
+    }
+
+    fn parse_and(&mut self) -> crate::Result<String> {
+        let mut clauses = vec![self.parse_not()?];
+        while self.eat_keyword("AND") {
+            clauses.push(self.parse_not()?);
+        }
+        Ok(join_clauses(clauses, "AND"))
+    }
+
+    fn parse_not(&mut self) -> crate::Result<String> {
+        if self.eat_keyword("NOT") {
+            return Ok(negate(&self.parse_not()?));
+        }
+        self.parse_predicate()
+    }
+
+    fn parse_predicate(&mut self) -> crate::Result<String> {
+        if self.eat_symbol("(") {
+            let clause = self.parse_or()?;
+            self.expect_symbol(")")?;
+            return Ok(clause);
+        }
+        let field_name = self.parse_field_name()?;
+        let is_negated = self.eat_keyword("NOT");
+        let clause = if self.eat_keyword("IN") {
+            self.expect_symbol("(")?;
+            let mut clauses = vec![term_clause(&field_name, &self.parse_literal()?)?];
+            while self.eat_symbol(",") {
+                clauses.push(term_clause(&field_name, &self.parse_literal()?)?);
+            }
+            self.expect_symbol(")")?;
+            join_clauses(clauses, "OR")
+        } else if self.eat_keyword("BETWEEN") {
+            let lower_bound = range_bound(&self.parse_literal()?)?;
+            self.expect_keyword("AND")?;
+            let upper_bound = range_bound(&self.parse_literal()?)?;
+            format!("{}:[{} TO {}]", field_name, lower_bound, upper_bound)
+        } else if self.eat_keyword("LIKE") {
+            like_clause(&field_name, &self.parse_literal()?)?
+        } else if is_negated {
+            return Err(self.unexpected("`IN`, `BETWEEN` or `LIKE`"));
+        } else {
+            let operator = match self.next_token() {
+                Some(Token::Symbol(symbol))
+                    if ["=", "!=", "<>", "<", "<=", ">", ">="].contains(&symbol) =>
+                {
+                    symbol
+                }
+                _ => {
+                    self.position -= 1;
+                    return Err(self.unexpected("a comparison operator"));
+                }
+            };
+            let literal = self.parse_literal()?;
+            match operator {
+                "=" => term_clause(&field_name, &literal)?,
+                "!=" | "<>" => negate(&term_clause(&field_name, &literal)?),
+                "<" => format!("{}:{{* TO {}}}", field_name, range_bound(&literal)?),
+                "<=" => format!("{}:{{* TO {}]", field_name, range_bound(&literal)?),
+                ">" => format!("{}:{{{} TO *}}", field_name, range_bound(&literal)?),
+                _ => format!("{}:[{} TO *}}", field_name, range_bound(&literal)?),
+            }
+        };
+        if is_negated {
+            return Ok(negate(&clause));
+        }
+        Ok(clause)
+    }
+}
+
+fn join_clauses(mut clauses: Vec<String>, operator: &str) -> String {
+    if clauses.len() == 1 {
+        return clauses.pop().unwrap_or_default();
+    }
+    format!("({})", clauses.join(&format!(" {} ", operator)))
+}
+
+/// Matches all the documents but those matching `clause`.
+fn negate(clause: &str) -> String {
+    format!("(* -{})", clause)
+}
+
+fn term_clause(field_name: &str, literal: &Literal) -> crate::Result<String> {
+    match literal {
+        Literal::Number(number) => Ok(format!("{}:{}", field_name, number)),
+        Literal::String(text) if text.contains('"') => Err(invalid_query(format!(
+            "Values cannot contain double quotes: `{}`.",
+            text
+        ))),
+        Literal::String(text) => Ok(format!("{}:\"{}\"", field_name, text)),
+    }
+}
+
+fn range_bound(literal: &Literal) -> crate::Result<String> {
+    match literal {
+        Literal::Number(number) => Ok(number.clone()),
+        Literal::String(text)
+            if text.is_empty()
+                || text.contains(|c: char| {
+                    c.is_whitespace() || matches!(c, '[' | ']' | '{' | '}' | '"' | '(' | ')')
+                }) =>
+        {
+            Err(invalid_query(format!(
+                "Invalid bound `{}`: the bounds of ranges cannot contain spaces, brackets or \
+                 quotes.",
+                text
+            )))
+        }
+        Literal::String(text) => Ok(text.clone()),
+    }
+}
+
+/// Translates a `LIKE` pattern, where `%` stands for any sequence of characters and `_` for
+/// any character, into a wildcard term.
+fn like_clause(field_name: &str, literal: &Literal) -> crate::Result<String> {
+    let pattern = match literal {
+        Literal::String(pattern) => pattern,
+        Literal::Number(_) => return Err(invalid_query("`LIKE` patterns must be strings.")),
+    };
+    let mut wildcard_pattern = String::with_capacity(pattern.len());
+    for c in pattern.chars() {
+        match c {
+            '%' => wildcard_pattern.push('*'),
+            '_' => wildcard_pattern.push('?'),
+            c if c.is_whitespace() || QUERY_SPECIAL_CHARACTERS.contains(&c) => {
+                return Err(invalid_query(format!(
+                    "Unsupported character `{}` in the `LIKE` pattern `{}`.",
+                    c, pattern
+                )));
+            }
+            c => wildcard_pattern.push(c),
+        }
+    }
+    if wildcard_pattern.is_empty() {
+        return Err(invalid_query("`LIKE` patterns cannot be empty."));
+    }
+    Ok(format!("{}:{}", field_name, wildcard_pattern))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum SqlQueryKind {
+    Select {
+        /// Not set for `SELECT *`.
+        fields_opt: Option<Vec<String>>,
+        sort_by_field_opt: Option<String>,
+        sort_ascending: bool,
+    },
+    Count,
+    GroupBy {
+        field_name: String,
+        items: Vec<SelectItem>,
+        order_by: Aggregate,
+        ascending: bool,
+    },
+}
+
+/// A query in the subset of SQL described in the [module documentation](self).
+#[derive(Clone, Debug, PartialEq)]
+pub struct SqlQuery {
+    index_id: String,
+    /// Condition of the `WHERE` clause, in the tantivy syntax.
+    query: String,
+    kind: SqlQueryKind,
+    limit: u64,
+}
+
+/// Result of a SQL query: one row per hit or per group.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SqlResponse {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<JsonValue>>,
+    /// Number of documents matching the `WHERE` clause.
+    pub num_hits: u64,
+    pub elapsed_time_micros: u64,
+}
+
+impl SqlQuery {
+    /// Parses a query, returning an invalid query error if it falls outside of the supported
+    /// subset of SQL.
+    pub fn parse(sql: &str) -> crate::Result<SqlQuery> {
+        let mut parser = Parser {
+            tokens: tokenize(sql)?,
+            position: 0,
+        };
+        parser.expect_keyword("SELECT")?;
+        let mut items = vec![parser.parse_select_item()?];
+        while parser.eat_symbol(",") {
+            items.push(parser.parse_select_item()?);
+        }
+        parser.expect_keyword("FROM")?;
+        let index_id = parser.parse_identifier()?;
+        let query = if parser.eat_keyword("WHERE") {
+            parser.parse_or()?
+        } else {
+            "*".to_string()
+        };
+        let group_by_opt = if parser.eat_keyword("GROUP") {
+            parser.expect_keyword("BY")?;
+            Some(parser.parse_field_name()?)
+        } else {
+            None
+        };
+        let order_by_opt = if parser.eat_keyword("ORDER") {
+            parser.expect_keyword("BY")?;
+            let order_by = parser.parse_select_item()?;
+            let ascending = if parser.eat_keyword("DESC") {
+                false
+            } else {
+                parser.eat_keyword("ASC");
+                true
+            };
+            Some((order_by, ascending))
+        } else {
+            None
+        };
+        let limit = if parser.eat_keyword("LIMIT") {
+            match parser.parse_literal()? {
+                Literal::Number(number) => number
+                    .parse::<u64>()
+                    .map_err(|_| invalid_query(format!("Invalid limit `{}`.", number)))?,
+                Literal::String(_) => return Err(invalid_query("The limit must be a number.")),
+            }
+        } else {
+            DEFAULT_LIMIT
+        };
+        parser.eat_symbol(";");
+        if parser.peek().is_some() {
+            return Err(parser.unexpected("the end of the query"));
+        }
+        let kind = match group_by_opt {
+            Some(field_name) => build_group_by(field_name, items, order_by_opt)?,
+            None => build_select(items, order_by_opt)?,
+        };
+        Ok(SqlQuery {
+            index_id,
+            query,
+            kind,
+            limit,
+        })
+    }
+
+    pub fn index_id(&self) -> &str {
+        &self.index_id
+    }
+
+    /// Returns the search request computing the result of the query.
+    pub fn search_request(&self) -> SearchRequest {
+        let mut search_request = SearchRequest {
+            index_id: self.index_id.clone(),
+            query: self.query.clone(),
+            search_fields: Vec::new(),
+            start_timestamp: None,
+            end_timestamp: None,
+            max_hits: 0,
+            start_offset: 0,
+            tags: Vec::new(),
+            distinct_count_fields: Vec::new(),
+            percentile_fields: Vec::new(),
+            percentiles: Vec::new(),
+            terms_aggregation: None,
+            date_histogram: None,
+            time_zone: String::new(),
+            geo_filter: None,
+            mandatory_filter: None,
+            flatten_hits: false,
+            sort_by_field: None,
+            sort_ascending: false,
+            disable_synonyms: false,
+            session_token: None,
+            min_publish_timestamp: None,
+            cache_policy: CachePolicy::Use as i32,
+            max_num_splits: None,
+            hit_sampling: None,
+        };
+        match &self.kind {
+            SqlQueryKind::Select {
+                sort_by_field_opt,
+                sort_ascending,
+                ..
+            } => {
+                search_request.max_hits = self.limit;
+                search_request.flatten_hits = true;
+                search_request.sort_by_field = sort_by_field_opt.clone();
+                search_request.sort_ascending = *sort_ascending;
+            }
+            SqlQueryKind::Count => {}
+            SqlQueryKind::GroupBy {
+                field_name,
+                order_by,
+                ascending,
+                ..
+            } => {
+                search_request.terms_aggregation = Some(TermsAggregation {
+                    field_name: field_name.clone(),
+                    size: self.limit,
+                    shard_size: None,
+                    order: order_by.function.terms_order() as i32,
+                    metric_field: order_by.field_opt.clone(),
+                    percentile: 0.0,
+                    ascending: *ascending,
+                });
+            }
+        }
+        search_request
+    }
+
+    /// Builds the rows of the result of the query from the response to its search request.
+    pub fn build_response(&self, search_response: SearchResponse) -> crate::Result<SqlResponse> {
+        let (columns, rows) = match &self.kind {
+            SqlQueryKind::Select { fields_opt, .. } => {
+                let docs = search_response
+                    .hits
+                    .iter()
+                    .map(|hit| {
+                        serde_json::from_str::<JsonMap<String, JsonValue>>(&hit.json).map_err(
+                            |error| {
+                                SearchError::InternalError(format!(
+                                    "Failed to parse the hit `{}`: {}",
+                                    hit.json, error
+                                ))
+                            },
+                        )
+                    })
+                    .collect::<crate::Result<Vec<_>>>()?;
+                let columns = match fields_opt {
+                    Some(fields) => fields.clone(),
+                    None => {
+                        let mut columns: Vec<String> = Vec::new();
+                        for doc in &docs {
+                            for key in doc.keys() {
+                                if !columns.contains(key) {
+                                    columns.push(key.clone());
+                                }
+                            }
+                        }
+                        columns
+                    }
+                };
+                let rows = docs
+                    .iter()
+                    .map(|doc| {
+                        columns
+                            .iter()
+                            .map(|column| column_value(doc.get(column)))
+                            .collect()
+                    })
+                    .collect();
+                (columns, rows)
+            }
+            SqlQueryKind::Count => (
+                vec![Aggregate {
+                    function: AggregateFunction::Count,
+                    field_opt: None,
+                }
+                .to_string()],
+                vec![vec![JsonValue::from(search_response.num_hits)]],
+            ),
+            SqlQueryKind::GroupBy { items, .. } => {
+                let columns = items
+                    .iter()
+                    .map(|item| match item {
+                        SelectItem::Aggregate(aggregate) => aggregate.to_string(),
+                        SelectItem::Field(field_name) => field_name.clone(),
+                        SelectItem::Wildcard => "*".to_string(),
+                    })
+                    .collect();
+                let buckets = search_response
+                    .terms_aggregation
+                    .map(|terms_aggregation| terms_aggregation.buckets)
+                    .unwrap_or_default();
+                let rows = buckets
+                    .into_iter()
+                    .map(|bucket| {
+                        items
+                            .iter()
+                            .map(|item| match item {
+                                SelectItem::Aggregate(Aggregate {
+                                    function: AggregateFunction::Count,
+                                    ..
+                                }) => JsonValue::from(bucket.doc_count),
+                                SelectItem::Aggregate(_) => bucket
+                                    .metric_value
+                                    .and_then(serde_json::Number::from_f64)
+                                    .map(JsonValue::Number)
+                                    .unwrap_or(JsonValue::Null),
+                                _ => JsonValue::String(bucket.key.clone()),
+                            })
+                            .collect()
+                    })
+                    .collect();
+                (columns, rows)
+            }
+        };
+        Ok(SqlResponse {
+            columns,
+            rows,
+            num_hits: search_response.num_hits,
+            elapsed_time_micros: search_response.elapsed_time_micros,
+        })
+    }
+}
+
+/// Returns the value of a column, given the values of the field in a hit.
+fn column_value(values_opt: Option<&JsonValue>) -> JsonValue {
+    match values_opt {
+        Some(JsonValue::Array(values)) if values.len() <= 1 => {
+            values.first().cloned().unwrap_or(JsonValue::Null)
+        }
+        Some(value) => value.clone(),
+        None => JsonValue::Null,
+    }
+}
+
+fn build_select(
+    items: Vec<SelectItem>,
+    order_by_opt: Option<(SelectItem, bool)>,
+) -> crate::Result<SqlQueryKind> {
+    let count_star = SelectItem::Aggregate(Aggregate {
+        function: AggregateFunction::Count,
+        field_opt: None,
+    });
+    if items == [count_star] {
+        return Ok(SqlQueryKind::Count);
+    }
+    let mut fields = Vec::with_capacity(items.len());
+    let mut is_wildcard = false;
+    for item in items {
+        match item {
+            SelectItem::Field(field_name) => fields.push(field_name),
+            SelectItem::Wildcard => is_wildcard = true,
+            SelectItem::Aggregate(aggregate) => {
+                return Err(invalid_query(format!(
+                    "`{}` requires a `GROUP BY` clause, only `COUNT(*)` can be selected alone \
+                     without one.",
+                    aggregate
+                )))
+            }
+        }
+    }
+    if is_wildcard && !fields.is_empty() {
+        return Err(invalid_query(
+            "`*` cannot be selected along with other fields.",
+        ));
+    }
+    let (sort_by_field_opt, sort_ascending) = match order_by_opt {
+        Some((SelectItem::Field(field_name), ascending)) => (Some(field_name), ascending),
+        Some(_) => return Err(invalid_query("Rows can only be ordered by a fast field.")),
+        None => (None, false),
+    };
+    Ok(SqlQueryKind::Select {
+        fields_opt: if is_wildcard { None } else { Some(fields) },
+        sort_by_field_opt,
+        sort_ascending,
+    })
+}
+
+fn build_group_by(
+    field_name: String,
+    items: Vec<SelectItem>,
+    order_by_opt: Option<(SelectItem, bool)>,
+) -> crate::Result<SqlQueryKind> {
+    let mut metric_opt: Option<Aggregate> = None;
+    for item in &items {
+        match item {
+            SelectItem::Field(selected_field_name) if *selected_field_name == field_name => {}
+            SelectItem::Aggregate(aggregate) if aggregate.function == AggregateFunction::Count => {}
+            SelectItem::Aggregate(aggregate) => match &metric_opt {
+                Some(metric) if metric != aggregate => {
+                    return Err(invalid_query(format!(
+                        "Only one aggregate besides `COUNT(*)` can be selected, found `{}` and \
+                         `{}`.",
+                        metric, aggregate
+                    )))
+                }
+                _ => metric_opt = Some(aggregate.clone()),
+            },
+            _ => {
+                return Err(invalid_query(format!(
+                    "Only the `GROUP BY` field `{}` and aggregates can be selected.",
+                    field_name
+                )))
+            }
+        }
+    }
+    let (order_by, ascending) = match (order_by_opt, metric_opt) {
+        (Some((SelectItem::Aggregate(order_by), ascending)), None) => (order_by, ascending),
+        (Some((SelectItem::Aggregate(order_by), ascending)), Some(metric)) => {
+            // The terms aggregation only computes the metric ordering the groups.
+            if order_by != metric {
+                return Err(invalid_query(format!(
+                    "Selecting `{}` requires ordering the groups by it.",
+                    metric
+                )));
+            }
+            (order_by, ascending)
+        }
+        (Some(_), _) => return Err(invalid_query("Groups can only be ordered by an aggregate.")),
+        (None, Some(metric)) => (metric, false),
+        (None, None) => (
+            Aggregate {
+                function: AggregateFunction::Count,
+                field_opt: None,
+            },
+            false,
+        ),
+    };
+    Ok(SqlQueryKind::GroupBy {
+        field_name,
+        items,
+        order_by,
+        ascending,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_proto::{Hit, TermsAggregationResult, TermsBucketResult};
+    use serde_json::json;
+
+    use super::*;
+
+    fn sql_query_string(sql: &str) -> String {
+        SqlQuery::parse(sql).unwrap().query
+    }
+
+    #[test]
+    fn test_sql_where_clause() {
+        assert_eq!(sql_query_string("SELECT * FROM logs"), "*");
+        assert_eq!(
+            sql_query_string("select * from logs where level = 'error'"),
+            "level:\"error\""
+        );
+        assert_eq!(
+            sql_query_string(
+                "SELECT * FROM logs WHERE (level = 'error' OR status >= 500) AND NOT host = 'h1'"
+            ),
+            "((level:\"error\" OR status:[500 TO *}) AND (* -host:\"h1\"))"
+        );
+        assert_eq!(
+            sql_query_string("SELECT * FROM logs WHERE latency < 2.5 AND latency > -1"),
+            "(latency:{* TO 2.5} AND latency:{-1 TO *})"
+        );
+        assert_eq!(
+            sql_query_string("SELECT * FROM logs WHERE status != 200 AND status <= 299"),
+            "((* -status:200) AND status:{* TO 299])"
+        );
+        assert_eq!(
+            sql_query_string("SELECT * FROM logs WHERE level NOT IN ('info', 'debug')"),
+            "(* -(level:\"info\" OR level:\"debug\"))"
+        );
+        assert_eq!(
+            sql_query_string(
+                "SELECT * FROM logs WHERE ts BETWEEN '2021-06-01' AND '2021-06-02' AND message \
+                 LIKE 'time_ut%'"
+            ),
+            "(ts:[2021-06-01 TO 2021-06-02] AND message:time?ut*)"
+        );
+        assert_eq!(
+            sql_query_string("SELECT * FROM logs WHERE name = 'it''s'"),
+            "name:\"it's\""
+        );
+    }
+
+    #[test]
+    fn test_sql_invalid_queries() {
+        for sql in &[
+            "",
+            "SELECT",
+            "SELECT * FROM",
+            "SELECT * FROM logs WHERE",
+            "SELECT * FROM logs WHERE level",
+            "SELECT * FROM logs WHERE level = error",
+            "SELECT * FROM logs WHERE message LIKE 'a b%'",
+            "SELECT * FROM logs WHERE level = 'a\"b'",
+            "SELECT * FROM logs WHERE level = 'error",
+            "SELECT * FROM logs LIMIT ten",
+            "SELECT * FROM logs LIMIT 10 OFFSET 10",
+            "SELECT *, level FROM logs",
+            "SELECT AVG(latency) FROM logs",
+            "SELECT * FROM logs ORDER BY COUNT(*)",
+            "SELECT host, COUNT(*) FROM logs GROUP BY level",
+            "SELECT level, AVG(latency) FROM logs GROUP BY level ORDER BY COUNT(*)",
+            "SELECT level, AVG(latency), MAX(latency) FROM logs GROUP BY level",
+            "SELECT level, COUNT(*) FROM logs GROUP BY level ORDER BY level",
+        ] {
+            assert!(
+                matches!(SqlQuery::parse(sql), Err(SearchError::InvalidQuery(_))),
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn test_sql_select() -> anyhow::Result<()> {
+        let sql_query = SqlQuery::parse("SELECT ts, user.name FROM logs ORDER BY ts DESC LIMIT 2")?;
+        assert_eq!(sql_query.index_id(), "logs");
+        let search_request = sql_query.search_request();
+        assert_eq!(search_request.index_id, "logs");
+        assert_eq!(search_request.query, "*");
+        assert_eq!(search_request.max_hits, 2);
+        assert_eq!(search_request.sort_by_field.as_deref(), Some("ts"));
+        assert!(!search_request.sort_ascending);
+        assert!(search_request.flatten_hits);
+        assert!(search_request.terms_aggregation.is_none());
+
+        let search_response = SearchResponse {
+            num_hits: 3,
+            hits: vec![
+                Hit {
+                    json: r#"{"ts": [2], "user.name": ["fred"], "tags": ["a", "b"]}"#.to_string(),
+                    partial_hit: None,
+                    address: String::new(),
+                },
+                Hit {
+                    json: r#"{"ts": [1]}"#.to_string(),
+                    partial_hit: None,
+                    address: String::new(),
+                },
+            ],
+            ..Default::default()
+        };
+        let sql_response = sql_query.build_response(search_response.clone())?;
+        assert_eq!(sql_response.columns, vec!["ts", "user.name"]);
+        assert_eq!(
+            sql_response.rows,
+            vec![
+                vec![json!(2), json!("fred")],
+                vec![json!(1), JsonValue::Null]
+            ]
+        );
+        assert_eq!(sql_response.num_hits, 3);
+
+        let sql_query = SqlQuery::parse("SELECT * FROM logs ORDER BY ts")?;
+        assert_eq!(sql_query.search_request().max_hits, DEFAULT_LIMIT);
+        assert!(sql_query.search_request().sort_ascending);
+        let sql_response = sql_query.build_response(search_response)?;
+        assert_eq!(sql_response.columns, vec!["tags", "ts", "user.name"]);
+        assert_eq!(
+            sql_response.rows[0],
+            vec![json!(["a", "b"]), json!(2), json!("fred")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sql_count() -> anyhow::Result<()> {
+        let sql_query = SqlQuery::parse("SELECT COUNT(*) FROM logs WHERE level = 'error';")?;
+        let search_request = sql_query.search_request();
+        assert_eq!(search_request.max_hits, 0);
+        assert!(search_request.terms_aggregation.is_none());
+        let sql_response = sql_query.build_response(SearchResponse {
+            num_hits: 42,
+            ..Default::default()
+        })?;
+        assert_eq!(sql_response.columns, vec!["COUNT(*)"]);
+        assert_eq!(sql_response.rows, vec![vec![json!(42)]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sql_group_by() -> anyhow::Result<()> {
+        let sql_query = SqlQuery::parse(
+            "SELECT level, COUNT(*), AVG(latency) FROM logs GROUP BY level ORDER BY avg(latency) \
+             ASC LIMIT 5",
+        )?;
+        let search_request = sql_query.search_request();
+        assert_eq!(search_request.max_hits, 0);
+        assert_eq!(
+            search_request.terms_aggregation,
+            Some(TermsAggregation {
+                field_name: "level".to_string(),
+                size: 5,
+                shard_size: None,
+                order: TermsOrder::Avg as i32,
+                metric_field: Some("latency".to_string()),
+                percentile: 0.0,
+                ascending: true,
+            })
+        );
+        let sql_response = sql_query.build_response(SearchResponse {
+            num_hits: 30,
+            terms_aggregation: Some(TermsAggregationResult {
+                buckets: vec![
+                    TermsBucketResult {
+                        key: "info".to_string(),
+                        doc_count: 20,
+                        metric_value: Some(1.5),
+                    },
+                    TermsBucketResult {
+                        key: "error".to_string(),
+                        doc_count: 10,
+                        metric_value: Some(3.0),
+                    },
+                ],
+                sum_other_doc_count: 0,
+                doc_count_error_upper_bound: 0,
+            }),
+            ..Default::default()
+        })?;
+        assert_eq!(
+            sql_response.columns,
+            vec!["level", "COUNT(*)", "AVG(latency)"]
+        );
+        assert_eq!(
+            sql_response.rows,
+            vec![
+                vec![json!("info"), json!(20), json!(1.5)],
+                vec![json!("error"), json!(10), json!(3.0)],
+            ]
+        );
+
+        let sql_query = SqlQuery::parse("SELECT COUNT(*), host FROM logs GROUP BY host")?;
+        let terms_aggregation = sql_query.search_request().terms_aggregation.unwrap();
+        assert_eq!(terms_aggregation.order, TermsOrder::Count as i32);
+        assert_eq!(terms_aggregation.size, DEFAULT_LIMIT);
+        assert!(!terms_aggregation.ascending);
+        Ok(())
+    }
+}
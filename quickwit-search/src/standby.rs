@@ -0,0 +1,88 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use quickwit_proto::LeafSearchRequest;
+use tracing::debug;
+
+use crate::SearchServiceClient;
+
+/// Tracks whether a searcher node is a standby.
+///
+/// Standby searchers are not assigned any leaf request by the roots of the cluster. They
+/// receive shadow copies of the leaf requests sent to the active searchers instead, whose
+/// responses are ignored, so that their caches are warm by the time they are promoted.
+///
+/// Clones share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct StandbyMode(Arc<AtomicBool>);
+
+impl StandbyMode {
+    /// Creates the state of a standby searcher if `standby` is true, of an active one
+    /// otherwise.
+    pub fn new(standby: bool) -> Self {
+        StandbyMode(Arc::new(AtomicBool::new(standby)))
+    }
+
+    /// Returns true if the node is a standby searcher.
+    pub fn is_standby(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Promotes the node to an active searcher.
+    ///
+    /// Returns false if the node was already active.
+    pub fn promote(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Sends shadow copies of leaf requests to standby searchers in the background.
+///
+/// The root does not wait for their responses, which are dropped.
+pub(crate) fn spawn_shadow_leaf_searches(
+    shadow_leaf_requests: Vec<(SearchServiceClient, LeafSearchRequest)>,
+) {
+    for (mut client, leaf_request) in shadow_leaf_requests {
+        tokio::spawn(async move {
+            let grpc_addr = client.grpc_addr();
+            if let Err(error) = client.leaf_search(leaf_request).await {
+                debug!(grpc_addr=?grpc_addr, error=?error, "Shadow leaf search failed.");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standby_mode_promote() {
+        let standby_mode = StandbyMode::new(true);
+        let standby_mode_clone = standby_mode.clone();
+        assert!(standby_mode.is_standby());
+        assert!(standby_mode_clone.promote());
+        assert!(!standby_mode.is_standby());
+        assert!(!standby_mode.promote());
+        assert!(!StandbyMode::default().is_standby());
+    }
+}
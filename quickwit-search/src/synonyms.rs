@@ -0,0 +1,138 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use quickwit_index_config::{parse_synonym_sets, IndexConfig};
+use quickwit_metastore::{CompanionFileError, CompanionFiles};
+use quickwit_storage::Storage;
+use tracing::warn;
+
+/// Returns the index config a search request should be executed with, expanding the
+/// terms of the query into the synonyms of the companion file of the index as well,
+/// unless the request sets `disable_synonyms`.
+///
+/// The companion file is read from the index storage for each request, so that a new
+/// version of it applies right away. A missing companion file adds no synonyms.
+pub(crate) async fn load_companion_synonyms(
+    index_config: Arc<dyn IndexConfig>,
+    index_storage: Arc<dyn Storage>,
+    disable_synonyms: bool,
+) -> anyhow::Result<Arc<dyn IndexConfig>> {
+    if disable_synonyms {
+        return Ok(index_config);
+    }
+    let companion_file_name = match index_config.synonyms_companion_file() {
+        Some(companion_file_name) => companion_file_name,
+        None => return Ok(index_config),
+    };
+    let content = match CompanionFiles::new(index_storage)
+        .get(&companion_file_name, None)
+        .await
+    {
+        Ok((_, content)) => content,
+        Err(CompanionFileError::FileDoesNotExist { .. }) => {
+            warn!(name = %companion_file_name, "Synonyms companion file does not exist.");
+            return Ok(index_config);
+        }
+        Err(error) => {
+            return Err(error).with_context(|| {
+                format!(
+                    "Failed to load synonyms companion file `{}`.",
+                    companion_file_name
+                )
+            })
+        }
+    };
+    let synonym_sets = parse_synonym_sets(&String::from_utf8_lossy(&content));
+    let index_config = index_config
+        .with_companion_synonyms(synonym_sets)
+        .with_context(|| format!("Invalid synonyms companion file `{}`.", companion_file_name))?;
+    Ok(Arc::from(index_config))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use quickwit_index_config::{DefaultIndexConfigBuilder, IndexConfig};
+    use quickwit_metastore::CompanionFiles;
+    use quickwit_proto::SearchRequest;
+    use quickwit_storage::{PutPayload, RamStorage, Storage};
+
+    use super::load_companion_synonyms;
+
+    #[tokio::test]
+    async fn test_load_companion_synonyms() -> anyhow::Result<()> {
+        let index_config_json = r#"{
+            "default_search_fields": ["body"],
+            "synonyms": {
+                "companion_file": "synonyms.txt"
+            },
+            "field_mappings": [
+                {
+                    "name": "body",
+                    "type": "text"
+                }
+            ]
+        }"#;
+        let index_config: Arc<dyn IndexConfig> = Arc::new(
+            serde_json::from_str::<DefaultIndexConfigBuilder>(index_config_json)?.build()?,
+        );
+        let storage: Arc<dyn Storage> = Arc::new(RamStorage::default());
+        let search_request = SearchRequest {
+            index_id: "test-index".to_string(),
+            query: "tv".to_string(),
+            max_hits: 10,
+            ..Default::default()
+        };
+        let query_str = |index_config: &dyn IndexConfig, search_request: &SearchRequest| {
+            let query = index_config
+                .query(index_config.schema(), search_request)
+                .unwrap();
+            format!("{:?}", query)
+        };
+
+        let missing_file_index_config =
+            load_companion_synonyms(index_config.clone(), storage.clone(), false).await?;
+        assert!(!query_str(&*missing_file_index_config, &search_request).contains("television"));
+
+        CompanionFiles::new(storage.clone())
+            .put(
+                "synonyms.txt",
+                PutPayload::from(&b"# Devices\ntv, television\n"[..]),
+            )
+            .await?;
+        let companion_index_config =
+            load_companion_synonyms(index_config.clone(), storage.clone(), false).await?;
+        assert!(query_str(&*companion_index_config, &search_request).contains("television"));
+
+        let disabled_index_config =
+            load_companion_synonyms(index_config.clone(), storage.clone(), true).await?;
+        assert!(disabled_index_config.synonyms_companion_file().is_some());
+
+        CompanionFiles::new(storage.clone())
+            .put("synonyms.txt", PutPayload::from(&b"tv\n"[..]))
+            .await?;
+        let invalid_file_result = load_companion_synonyms(index_config, storage, false).await;
+        assert!(invalid_file_result.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,624 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use quickwit_proto::{
+    TermsAggregation, TermsAggregationResult, TermsBucket, TermsBucketResult, TermsBuckets,
+    TermsOrder,
+};
+use tantivy::fastfield::FastValue;
+use tantivy::schema::{Schema, Type};
+use tantivy::TantivyError;
+
+use crate::percentiles::TDigest;
+use crate::SearchError;
+
+/// Returns the number of buckets returned by each leaf.
+///
+/// Leaves return more buckets than requested, so that a bucket ranking just below the top
+/// buckets of some leaves still makes it to the overall top buckets with an accurate count.
+pub(crate) fn shard_size(terms_aggregation: &TermsAggregation) -> usize {
+    let shard_size = terms_aggregation
+        .shard_size
+        .unwrap_or(terms_aggregation.size * 3 / 2 + 10);
+    shard_size.max(terms_aggregation.size) as usize
+}
+
+pub(crate) fn terms_order(terms_aggregation: &TermsAggregation) -> TermsOrder {
+    TermsOrder::from_i32(terms_aggregation.order).unwrap_or(TermsOrder::Count)
+}
+
+/// Statistics of the documents of a bucket.
+struct BucketAccumulator {
+    doc_count: u64,
+    metric_sum: f64,
+    metric_min: f64,
+    metric_max: f64,
+    metric_digest_opt: Option<TDigest>,
+}
+
+impl BucketAccumulator {
+    fn new(with_digest: bool) -> Self {
+        BucketAccumulator {
+            doc_count: 0,
+            metric_sum: 0.0,
+            metric_min: f64::INFINITY,
+            metric_max: f64::NEG_INFINITY,
+            metric_digest_opt: if with_digest {
+                Some(TDigest::default())
+            } else {
+                None
+            },
+        }
+    }
+
+    fn from_bucket(bucket: TermsBucket) -> tantivy::Result<Self> {
+        let metric_digest_opt = bucket
+            .metric_sketch
+            .map(|metric_sketch| {
+                TDigest::from_sketch(metric_sketch).ok_or_else(|| {
+                    TantivyError::InvalidArgument(format!(
+                        "Invalid metric sketch for the bucket `{}`.",
+                        bucket.term
+                    ))
+                })
+            })
+            .transpose()?;
+        Ok(BucketAccumulator {
+            doc_count: bucket.doc_count,
+            metric_sum: bucket.metric_sum,
+            metric_min: bucket.metric_min,
+            metric_max: bucket.metric_max,
+            metric_digest_opt,
+        })
+    }
+
+    fn collect(&mut self, metric_value_opt: Option<f64>) {
+        self.doc_count += 1;
+        if let Some(metric_value) = metric_value_opt {
+            self.metric_sum += metric_value;
+            self.metric_min = self.metric_min.min(metric_value);
+            self.metric_max = self.metric_max.max(metric_value);
+            if let Some(metric_digest) = self.metric_digest_opt.as_mut() {
+                metric_digest.insert(metric_value);
+            }
+        }
+    }
+
+    fn merge(&mut self, other: BucketAccumulator) {
+        self.doc_count += other.doc_count;
+        self.metric_sum += other.metric_sum;
+        self.metric_min = self.metric_min.min(other.metric_min);
+        self.metric_max = self.metric_max.max(other.metric_max);
+        match (self.metric_digest_opt.as_mut(), other.metric_digest_opt) {
+            (Some(metric_digest), Some(other_metric_digest)) => {
+                metric_digest.merge(&other_metric_digest)
+            }
+            (None, Some(other_metric_digest)) => self.metric_digest_opt = Some(other_metric_digest),
+            (_, None) => {}
+        }
+    }
+
+    /// Returns the value of the metric ordering the buckets, or `None` when ordering by count.
+    fn metric_value(&mut self, terms_aggregation: &TermsAggregation) -> Option<f64> {
+        match terms_order(terms_aggregation) {
+            TermsOrder::Count => None,
+            TermsOrder::Sum => Some(self.metric_sum),
+            TermsOrder::Avg => Some(self.metric_sum / self.doc_count as f64),
+            TermsOrder::Min => Some(self.metric_min),
+            TermsOrder::Max => Some(self.metric_max),
+            TermsOrder::Percentile => Some(
+                self.metric_digest_opt
+                    .as_mut()
+                    .and_then(|metric_digest| {
+                        metric_digest.percentile(terms_aggregation.percentile)
+                    })
+                    .unwrap_or(f64::NAN),
+            ),
+        }
+    }
+
+    fn into_bucket(self, term: u64) -> TermsBucket {
+        TermsBucket {
+            term,
+            doc_count: self.doc_count,
+            metric_sum: self.metric_sum,
+            metric_min: self.metric_min,
+            metric_max: self.metric_max,
+            // The sketch belongs to the bucket, it does not need a field name.
+            metric_sketch: self
+                .metric_digest_opt
+                .map(|metric_digest| metric_digest.into_sketch(String::new())),
+        }
+    }
+}
+
+struct RankedBucket {
+    term: u64,
+    metric_value_opt: Option<f64>,
+    bucket: BucketAccumulator,
+}
+
+/// Accumulates the buckets of a terms aggregation, from documents or from partial buckets.
+#[derive(Default)]
+pub(crate) struct TermsAccumulator {
+    buckets: HashMap<u64, BucketAccumulator>,
+    sum_other_doc_count: u64,
+    doc_count_error_upper_bound: u64,
+    with_digest: bool,
+}
+
+impl TermsAccumulator {
+    pub fn new(terms_aggregation: &TermsAggregation) -> Self {
+        TermsAccumulator {
+            with_digest: terms_order(terms_aggregation) == TermsOrder::Percentile,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a document with the value `term` to its bucket.
+    pub fn collect(&mut self, term: u64, metric_value_opt: Option<f64>) {
+        let with_digest = self.with_digest;
+        self.buckets
+            .entry(term)
+            .or_insert_with(|| BucketAccumulator::new(with_digest))
+            .collect(metric_value_opt);
+    }
+
+    fn merge_terms_buckets(&mut self, terms_buckets: TermsBuckets) -> tantivy::Result<()> {
+        self.sum_other_doc_count += terms_buckets.sum_other_doc_count;
+        self.doc_count_error_upper_bound += terms_buckets.doc_count_error_upper_bound;
+        for bucket in terms_buckets.buckets {
+            let term = bucket.term;
+            let bucket_accumulator = BucketAccumulator::from_bucket(bucket)?;
+            match self.buckets.entry(term) {
+                Entry::Occupied(mut entry) => entry.get_mut().merge(bucket_accumulator),
+                Entry::Vacant(entry) => {
+                    entry.insert(bucket_accumulator);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the buckets, in the order requested by `terms_aggregation`.
+    ///
+    /// Ties are broken by increasing term, so that all nodes agree on the order.
+    fn into_ranked_buckets(self, terms_aggregation: &TermsAggregation) -> Vec<RankedBucket> {
+        let mut ranked_buckets: Vec<RankedBucket> = self
+            .buckets
+            .into_iter()
+            .map(|(term, mut bucket)| RankedBucket {
+                term,
+                metric_value_opt: bucket.metric_value(terms_aggregation),
+                bucket,
+            })
+            .collect();
+        ranked_buckets.sort_by(|left, right| {
+            let order = match (left.metric_value_opt, right.metric_value_opt) {
+                (Some(left_value), Some(right_value)) => left_value
+                    .partial_cmp(&right_value)
+                    .unwrap_or(Ordering::Equal),
+                _ => left.bucket.doc_count.cmp(&right.bucket.doc_count),
+            };
+            let order = if terms_aggregation.ascending {
+                order
+            } else {
+                order.reverse()
+            };
+            order.then_with(|| left.term.cmp(&right.term))
+        });
+        ranked_buckets
+    }
+
+    /// Returns the partial buckets, as sent to the root.
+    pub fn into_terms_buckets(self) -> TermsBuckets {
+        let mut buckets: Vec<TermsBucket> = self
+            .buckets
+            .into_iter()
+            .map(|(term, bucket)| bucket.into_bucket(term))
+            .collect();
+        buckets.sort_by_key(|bucket| bucket.term);
+        TermsBuckets {
+            buckets,
+            sum_other_doc_count: self.sum_other_doc_count,
+            doc_count_error_upper_bound: self.doc_count_error_upper_bound,
+        }
+    }
+}
+
+fn accumulate_terms_buckets(
+    terms_buckets: impl IntoIterator<Item = TermsBuckets>,
+) -> tantivy::Result<TermsAccumulator> {
+    let mut terms_accumulator = TermsAccumulator::default();
+    for terms_buckets in terms_buckets {
+        terms_accumulator.merge_terms_buckets(terms_buckets)?;
+    }
+    Ok(terms_accumulator)
+}
+
+/// Merges the partial buckets of several splits or leaves.
+pub(crate) fn merge_terms_buckets(
+    terms_buckets: impl IntoIterator<Item = TermsBuckets>,
+) -> tantivy::Result<Vec<TermsBuckets>> {
+    let mut terms_buckets = terms_buckets.into_iter().peekable();
+    if terms_buckets.peek().is_none() {
+        return Ok(Vec::new());
+    }
+    Ok(vec![
+        accumulate_terms_buckets(terms_buckets)?.into_terms_buckets()
+    ])
+}
+
+/// Keeps the `shard_size` top buckets of a leaf, out of its partial buckets.
+///
+/// The documents of the pruned buckets are counted in `sum_other_doc_count`. When ordering by
+/// decreasing count, a pruned bucket has at most as many documents as the last bucket kept, which
+/// bounds the number of documents missing from a bucket that the leaf did not return.
+pub(crate) fn prune_terms_buckets(
+    terms_aggregation: &TermsAggregation,
+    terms_buckets: Vec<TermsBuckets>,
+) -> tantivy::Result<Vec<TermsBuckets>> {
+    let terms_accumulator = accumulate_terms_buckets(terms_buckets)?;
+    let mut sum_other_doc_count = terms_accumulator.sum_other_doc_count;
+    let mut doc_count_error_upper_bound = terms_accumulator.doc_count_error_upper_bound;
+    let mut ranked_buckets = terms_accumulator.into_ranked_buckets(terms_aggregation);
+    let shard_size = shard_size(terms_aggregation);
+    if ranked_buckets.len() > shard_size {
+        sum_other_doc_count += ranked_buckets[shard_size..]
+            .iter()
+            .map(|ranked_bucket| ranked_bucket.bucket.doc_count)
+            .sum::<u64>();
+        ranked_buckets.truncate(shard_size);
+        if is_ordered_by_decreasing_count(terms_aggregation) {
+            doc_count_error_upper_bound += ranked_buckets
+                .last()
+                .map(|ranked_bucket| ranked_bucket.bucket.doc_count)
+                .unwrap_or(0);
+        }
+    }
+    let buckets = ranked_buckets
+        .into_iter()
+        .map(|ranked_bucket| ranked_bucket.bucket.into_bucket(ranked_bucket.term))
+        .collect();
+    Ok(vec![TermsBuckets {
+        buckets,
+        sum_other_doc_count,
+        doc_count_error_upper_bound,
+    }])
+}
+
+fn is_ordered_by_decreasing_count(terms_aggregation: &TermsAggregation) -> bool {
+    terms_order(terms_aggregation) == TermsOrder::Count && !terms_aggregation.ascending
+}
+
+/// Formats the `u64` representation of a fast field value.
+fn format_term(value_type: Type, term: u64) -> String {
+    match value_type {
+        Type::I64 | Type::Date => i64::from_u64(term).to_string(),
+        Type::F64 => f64::from_u64(term).to_string(),
+        _ => term.to_string(),
+    }
+}
+
+/// Returns the top buckets of the terms aggregation, out of the partial buckets returned by
+/// the leaves.
+pub(crate) fn build_terms_aggregation_result(
+    terms_aggregation: &TermsAggregation,
+    terms_buckets: &[TermsBuckets],
+    schema: &Schema,
+) -> crate::Result<TermsAggregationResult> {
+    let field = schema
+        .get_field(&terms_aggregation.field_name)
+        .ok_or_else(|| {
+            SearchError::InvalidQuery(format!(
+                "Field `{}` does not exist in schema",
+                terms_aggregation.field_name
+            ))
+        })?;
+    let value_type = schema.get_field_entry(field).field_type().value_type();
+    let terms_accumulator = accumulate_terms_buckets(terms_buckets.iter().cloned())?;
+    let mut sum_other_doc_count = terms_accumulator.sum_other_doc_count;
+    let doc_count_error_upper_bound = if is_ordered_by_decreasing_count(terms_aggregation) {
+        terms_accumulator.doc_count_error_upper_bound
+    } else {
+        0
+    };
+    let mut ranked_buckets = terms_accumulator.into_ranked_buckets(terms_aggregation);
+    let size = terms_aggregation.size as usize;
+    if ranked_buckets.len() > size {
+        sum_other_doc_count += ranked_buckets[size..]
+            .iter()
+            .map(|ranked_bucket| ranked_bucket.bucket.doc_count)
+            .sum::<u64>();
+        ranked_buckets.truncate(size);
+    }
+    let buckets = ranked_buckets
+        .into_iter()
+        .map(|ranked_bucket| TermsBucketResult {
+            key: format_term(value_type, ranked_bucket.term),
+            doc_count: ranked_bucket.bucket.doc_count,
+            metric_value: ranked_bucket.metric_value_opt,
+        })
+        .collect();
+    Ok(TermsAggregationResult {
+        buckets,
+        sum_other_doc_count,
+        doc_count_error_upper_bound,
+    })
+}
+
+fn validate_fast_field(field_name: &str, schema: &Schema) -> crate::Result<()> {
+    let field = schema.get_field(field_name).ok_or_else(|| {
+        SearchError::InvalidQuery(format!("Field `{}` does not exist in schema", field_name))
+    })?;
+    let field_entry = schema.get_field_entry(field);
+    if !field_entry.is_fast() {
+        return Err(SearchError::InvalidQuery(format!(
+            "Terms aggregations are only supported on fast fields, `{}` is not a fast field",
+            field_name
+        )));
+    }
+    if !matches!(
+        field_entry.field_type().value_type(),
+        Type::U64 | Type::I64 | Type::F64 | Type::Date
+    ) {
+        return Err(SearchError::InvalidQuery(format!(
+            "Terms aggregations are only supported on numeric fields, `{}` is not a numeric field",
+            field_name
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that the terms aggregation, if any, is over numeric fast fields, and that it
+/// is well-formed.
+pub(crate) fn validate_terms_aggregation(
+    terms_aggregation_opt: Option<&TermsAggregation>,
+    schema: &Schema,
+) -> crate::Result<()> {
+    let terms_aggregation = if let Some(terms_aggregation) = terms_aggregation_opt {
+        terms_aggregation
+    } else {
+        return Ok(());
+    };
+    validate_fast_field(&terms_aggregation.field_name, schema)?;
+    if terms_aggregation.size == 0 {
+        return Err(SearchError::InvalidQuery(
+            "The number of buckets of a terms aggregation must be positive".to_string(),
+        ));
+    }
+    let order = TermsOrder::from_i32(terms_aggregation.order).ok_or_else(|| {
+        SearchError::InvalidQuery(format!(
+            "Unknown terms aggregation order `{}`",
+            terms_aggregation.order
+        ))
+    })?;
+    if order == TermsOrder::Count {
+        return Ok(());
+    }
+    let metric_field = terms_aggregation.metric_field.as_ref().ok_or_else(|| {
+        SearchError::InvalidQuery(format!(
+            "Ordering the buckets of a terms aggregation by `{:?}` requires a metric field",
+            order
+        ))
+    })?;
+    validate_fast_field(metric_field, schema)?;
+    if order == TermsOrder::Percentile && !(0.0..=100.0).contains(&terms_aggregation.percentile) {
+        return Err(SearchError::InvalidQuery(format!(
+            "Percentile `{}` is not between 0 and 100",
+            terms_aggregation.percentile
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::{FAST, TEXT};
+
+    use super::*;
+
+    fn terms_aggregation(
+        order: TermsOrder,
+        size: u64,
+        shard_size: Option<u64>,
+    ) -> TermsAggregation {
+        TermsAggregation {
+            field_name: "endpoint".to_string(),
+            size,
+            shard_size,
+            order: order as i32,
+            metric_field: Some("latency".to_string()),
+            percentile: 99.0,
+            ascending: false,
+        }
+    }
+
+    fn terms_buckets(terms_aggregation: &TermsAggregation, docs: &[(u64, f64)]) -> TermsBuckets {
+        let mut terms_accumulator = TermsAccumulator::new(terms_aggregation);
+        for &(term, metric_value) in docs {
+            terms_accumulator.collect(term, Some(metric_value));
+        }
+        terms_accumulator.into_terms_buckets()
+    }
+
+    fn schema() -> Schema {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("body", TEXT);
+        schema_builder.add_u64_field("endpoint", FAST);
+        schema_builder.add_f64_field("latency", FAST);
+        schema_builder.build()
+    }
+
+    fn bucket_keys(terms_aggregation_result: &TermsAggregationResult) -> Vec<(&str, u64)> {
+        terms_aggregation_result
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.key.as_str(), bucket.doc_count))
+            .collect()
+    }
+
+    #[test]
+    fn test_shard_size() {
+        assert_eq!(
+            shard_size(&terms_aggregation(TermsOrder::Count, 10, None)),
+            25
+        );
+        assert_eq!(
+            shard_size(&terms_aggregation(TermsOrder::Count, 10, Some(3))),
+            10
+        );
+        assert_eq!(
+            shard_size(&terms_aggregation(TermsOrder::Count, 10, Some(100))),
+            100
+        );
+    }
+
+    #[test]
+    fn test_prune_terms_buckets_by_count() -> tantivy::Result<()> {
+        let terms_aggregation = terms_aggregation(TermsOrder::Count, 1, Some(2));
+        let docs = [(1, 0.0), (1, 0.0), (1, 0.0), (2, 0.0), (2, 0.0), (3, 0.0)];
+        let pruned_terms_buckets = prune_terms_buckets(
+            &terms_aggregation,
+            vec![terms_buckets(&terms_aggregation, &docs)],
+        )?;
+        assert_eq!(pruned_terms_buckets.len(), 1);
+        let terms: Vec<(u64, u64)> = pruned_terms_buckets[0]
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.term, bucket.doc_count))
+            .collect();
+        assert_eq!(terms, vec![(1, 3), (2, 2)]);
+        assert_eq!(pruned_terms_buckets[0].sum_other_doc_count, 1);
+        assert_eq!(pruned_terms_buckets[0].doc_count_error_upper_bound, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_terms_aggregation_result_by_count() -> anyhow::Result<()> {
+        let terms_aggregation = terms_aggregation(TermsOrder::Count, 2, Some(2));
+        let leaf_docs = [
+            vec![(1, 0.0); 5]
+                .into_iter()
+                .chain(vec![(2, 0.0); 4])
+                .chain(vec![(3, 0.0); 3])
+                .collect::<Vec<_>>(),
+            vec![(3, 0.0); 5]
+                .into_iter()
+                .chain(vec![(1, 0.0), (2, 0.0)])
+                .collect::<Vec<_>>(),
+        ];
+        let leaf_terms_buckets = leaf_docs
+            .iter()
+            .map(|docs| {
+                let terms_buckets = terms_buckets(&terms_aggregation, docs);
+                prune_terms_buckets(&terms_aggregation, vec![terms_buckets])
+            })
+            .collect::<tantivy::Result<Vec<_>>>()?
+            .concat();
+        let terms_aggregation_result =
+            build_terms_aggregation_result(&terms_aggregation, &leaf_terms_buckets, &schema())?;
+        assert_eq!(
+            bucket_keys(&terms_aggregation_result),
+            vec![("1", 6), ("3", 5)]
+        );
+        assert_eq!(terms_aggregation_result.buckets[0].metric_value, None);
+        assert_eq!(terms_aggregation_result.sum_other_doc_count, 8);
+        assert_eq!(terms_aggregation_result.doc_count_error_upper_bound, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_terms_aggregation_result_by_metric() -> anyhow::Result<()> {
+        let mut terms_aggregation = terms_aggregation(TermsOrder::Percentile, 1, None);
+        let mut first_leaf_docs: Vec<(u64, f64)> = (1..=10).map(|i| (1, i as f64)).collect();
+        first_leaf_docs.push((2, 1.0));
+        let second_leaf_docs = [(2, 50.0), (3, 5.0)];
+        let leaf_terms_buckets = vec![
+            terms_buckets(&terms_aggregation, &first_leaf_docs),
+            terms_buckets(&terms_aggregation, &second_leaf_docs),
+        ];
+        let terms_aggregation_result =
+            build_terms_aggregation_result(&terms_aggregation, &leaf_terms_buckets, &schema())?;
+        assert_eq!(bucket_keys(&terms_aggregation_result), vec![("2", 2)]);
+        assert_eq!(terms_aggregation_result.buckets[0].metric_value, Some(50.0));
+        assert_eq!(terms_aggregation_result.sum_other_doc_count, 11);
+        assert_eq!(terms_aggregation_result.doc_count_error_upper_bound, 0);
+
+        terms_aggregation.ascending = true;
+        let terms_aggregation_result =
+            build_terms_aggregation_result(&terms_aggregation, &leaf_terms_buckets, &schema())?;
+        assert_eq!(bucket_keys(&terms_aggregation_result), vec![("3", 1)]);
+
+        terms_aggregation.order = TermsOrder::Avg as i32;
+        terms_aggregation.size = 2;
+        let leaf_terms_buckets = vec![
+            terms_buckets(&terms_aggregation, &first_leaf_docs),
+            terms_buckets(&terms_aggregation, &second_leaf_docs),
+        ];
+        let terms_aggregation_result =
+            build_terms_aggregation_result(&terms_aggregation, &leaf_terms_buckets, &schema())?;
+        assert_eq!(
+            bucket_keys(&terms_aggregation_result),
+            vec![("3", 1), ("1", 10)]
+        );
+        assert_eq!(terms_aggregation_result.buckets[1].metric_value, Some(5.5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_terms_aggregation() {
+        let schema = schema();
+        assert!(validate_terms_aggregation(None, &schema).is_ok());
+        let terms_aggregation = terms_aggregation(TermsOrder::Percentile, 10, None);
+        assert!(validate_terms_aggregation(Some(&terms_aggregation), &schema).is_ok());
+        let invalid_terms_aggregations = vec![
+            TermsAggregation {
+                field_name: "body".to_string(),
+                ..terms_aggregation.clone()
+            },
+            TermsAggregation {
+                size: 0,
+                ..terms_aggregation.clone()
+            },
+            TermsAggregation {
+                order: 42,
+                ..terms_aggregation.clone()
+            },
+            TermsAggregation {
+                metric_field: None,
+                ..terms_aggregation.clone()
+            },
+            TermsAggregation {
+                percentile: 120.0,
+                ..terms_aggregation.clone()
+            },
+        ];
+        for invalid_terms_aggregation in &invalid_terms_aggregations {
+            assert!(matches!(
+                validate_terms_aggregation(Some(invalid_terms_aggregation), &schema),
+                Err(SearchError::InvalidQuery(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,465 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashSet};
+use std::net::SocketAddr;
+
+use futures::{StreamExt, TryStreamExt};
+use itertools::Itertools;
+use quickwit_proto::{
+    IntermediateSearchRequest, LeafSearchRequest, LeafSearchResponse, PlacedLeafSearchRequest,
+    SearchRequest,
+};
+use tantivy::collector::Collector;
+use tantivy::TantivyError;
+use tokio::task::spawn_blocking;
+use tracing::{debug, warn};
+
+use crate::collector::make_merge_collector;
+use crate::root::MAX_CONCURRENT_LEAF_TASKS;
+use crate::{ClusterClient, SearchClientPool, SearchError, SearchServiceClient};
+
+/// Number of leaf nodes above which the leaf responses of a root search are merged on
+/// intermediate nodes. Beyond it, merging one response per leaf node makes the root the
+/// bottleneck of the search.
+pub const DEFAULT_TREE_AGGREGATION_MIN_NODES: usize = 50;
+
+/// Performs the leaf searches of a root search and returns their responses.
+///
+/// When the leaf searches are sent to more than
+/// [`ClusterClient::tree_aggregation_min_nodes`] nodes, the nodes are split into groups of
+/// about the square root of their number. The leaf searches of each group are sent to one of
+/// its nodes, which performs them and merges their responses, so that the root only merges
+/// one response per group. The root performs the leaf searches of a group itself if its
+/// intermediate search fails, e.g. because the intermediate node is down.
+///
+/// `search_request` is the search request the responses are merged for: like the search
+/// requests of the leaf requests, it must start at offset 0.
+pub(crate) async fn scatter_gather_leaf_searches(
+    search_request: &SearchRequest,
+    leaf_requests: Vec<(LeafSearchRequest, SearchServiceClient)>,
+    cluster_client: &ClusterClient,
+) -> crate::Result<Vec<LeafSearchResponse>> {
+    let num_nodes = leaf_requests
+        .iter()
+        .map(|(_, client)| client.grpc_addr())
+        .collect::<HashSet<SocketAddr>>()
+        .len();
+    if num_nodes <= cluster_client.tree_aggregation_min_nodes() {
+        return search_leaves(leaf_requests, cluster_client).await;
+    }
+    let leaf_request_groups = group_leaf_requests(leaf_requests);
+    debug!(
+        num_nodes = num_nodes,
+        num_groups = leaf_request_groups.len(),
+        "Merging the leaf responses on intermediate nodes."
+    );
+    let leaf_responses_per_group: Vec<Vec<LeafSearchResponse>> =
+        futures::stream::iter(leaf_request_groups)
+            .map(|leaf_request_group| {
+                search_leaf_group(search_request, leaf_request_group, cluster_client)
+            })
+            .buffer_unordered(MAX_CONCURRENT_LEAF_TASKS)
+            .try_collect()
+            .await?;
+    Ok(leaf_responses_per_group.into_iter().flatten().collect())
+}
+
+/// Performs the leaf searches of an intermediate search request, and merges their responses.
+pub(crate) async fn intermediate_search(
+    intermediate_request: IntermediateSearchRequest,
+    cluster_client: &ClusterClient,
+    client_pool: &SearchClientPool,
+) -> crate::Result<LeafSearchResponse> {
+    let search_request = intermediate_request
+        .search_request
+        .ok_or_else(|| SearchError::InternalError("No search request.".to_string()))?;
+    let mut leaf_requests = Vec::with_capacity(intermediate_request.leaf_requests.len());
+    {
+        let clients = client_pool.clients.read().await;
+        for placed_request in intermediate_request.leaf_requests {
+            let leaf_request = placed_request
+                .leaf_request
+                .ok_or_else(|| SearchError::InternalError("No leaf request.".to_string()))?;
+            let leaf_grpc_addr: SocketAddr =
+                placed_request.leaf_grpc_addr.parse().map_err(|_| {
+                    SearchError::InternalError(format!(
+                        "Invalid leaf gRPC address `{}`.",
+                        placed_request.leaf_grpc_addr
+                    ))
+                })?;
+            let client = clients.get(&leaf_grpc_addr).cloned().ok_or_else(|| {
+                SearchError::InternalError(format!("Unknown leaf node `{}`.", leaf_grpc_addr))
+            })?;
+            leaf_requests.push((leaf_request, client));
+        }
+    }
+    let leaf_responses = search_leaves(leaf_requests, cluster_client).await?;
+    merge_leaf_responses(&search_request, leaf_responses).await
+}
+
+// Sends the leaf searches of a group to its first node, or performs them from this node if
+// the intermediate search fails.
+async fn search_leaf_group(
+    search_request: &SearchRequest,
+    leaf_request_group: Vec<(LeafSearchRequest, SearchServiceClient)>,
+    cluster_client: &ClusterClient,
+) -> crate::Result<Vec<LeafSearchResponse>> {
+    let mut intermediate_client = leaf_request_group[0].1.clone();
+    let intermediate_request = IntermediateSearchRequest {
+        search_request: Some(search_request.clone()),
+        leaf_requests: leaf_request_group
+            .iter()
+            .map(|(leaf_request, client)| PlacedLeafSearchRequest {
+                leaf_request: Some(leaf_request.clone()),
+                leaf_grpc_addr: client.grpc_addr().to_string(),
+            })
+            .collect(),
+    };
+    match intermediate_client
+        .intermediate_search(intermediate_request)
+        .await
+    {
+        Ok(leaf_response) => Ok(vec![leaf_response]),
+        Err(error) => {
+            warn!(intermediate_node=?intermediate_client.grpc_addr(), error=?error, "Intermediate search failed, performing its leaf searches from the root.");
+            search_leaves(leaf_request_group, cluster_client).await
+        }
+    }
+}
+
+async fn search_leaves(
+    leaf_requests: Vec<(LeafSearchRequest, SearchServiceClient)>,
+    cluster_client: &ClusterClient,
+) -> crate::Result<Vec<LeafSearchResponse>> {
+    futures::stream::iter(leaf_requests)
+        .map(|placed_request| cluster_client.leaf_search(placed_request))
+        .buffer_unordered(MAX_CONCURRENT_LEAF_TASKS)
+        .try_collect()
+        .await
+}
+
+pub(crate) async fn merge_leaf_responses(
+    search_request: &SearchRequest,
+    leaf_responses: Vec<LeafSearchResponse>,
+) -> crate::Result<LeafSearchResponse> {
+    let merge_collector = make_merge_collector(search_request);
+    let leaf_response = spawn_blocking(move || merge_collector.merge_fruits(leaf_responses))
+        .await?
+        .map_err(|merge_error: TantivyError| {
+            SearchError::InternalError(format!("{}", merge_error))
+        })?;
+    Ok(leaf_response)
+}
+
+// Groups the leaf requests by node, and the nodes in groups of about the square root of their
+// number. The requests sent to the same node always belong to the same group.
+fn group_leaf_requests(
+    leaf_requests: Vec<(LeafSearchRequest, SearchServiceClient)>,
+) -> Vec<Vec<(LeafSearchRequest, SearchServiceClient)>> {
+    let mut leaf_requests_per_node: BTreeMap<
+        SocketAddr,
+        Vec<(LeafSearchRequest, SearchServiceClient)>,
+    > = BTreeMap::new();
+    for (leaf_request, client) in leaf_requests {
+        leaf_requests_per_node
+            .entry(client.grpc_addr())
+            .or_default()
+            .push((leaf_request, client));
+    }
+    let num_nodes_per_group = (leaf_requests_per_node.len() as f64).sqrt().ceil() as usize;
+    leaf_requests_per_node
+        .into_iter()
+        .map(|(_, node_leaf_requests)| node_leaf_requests)
+        .chunks(num_nodes_per_group.max(1))
+        .into_iter()
+        .map(|node_leaf_requests| node_leaf_requests.flatten().collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use quickwit_proto::{PartialHit, SplitIdAndFooterOffsets};
+
+    use super::*;
+    use crate::MockSearchService;
+
+    fn mock_leaf_request(split_id: &str) -> LeafSearchRequest {
+        LeafSearchRequest {
+            search_request: Some(mock_search_request()),
+            split_metadata: vec![SplitIdAndFooterOffsets {
+                split_id: split_id.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn mock_search_request() -> SearchRequest {
+        SearchRequest {
+            index_id: "test-idx".to_string(),
+            query: "test".to_string(),
+            max_hits: 10,
+            ..Default::default()
+        }
+    }
+
+    fn mock_leaf_response(split_id: &str, sorting_field_value: u64) -> LeafSearchResponse {
+        LeafSearchResponse {
+            num_hits: 1,
+            partial_hits: vec![PartialHit {
+                sorting_field_value,
+                split_id: split_id.to_string(),
+                segment_ord: 0,
+                doc_id: 0,
+                leaf_grpc_addr: String::new(),
+            }],
+            num_attempted_splits: 1,
+            ..Default::default()
+        }
+    }
+
+    async fn mock_leaf_requests(
+        mock_services: Vec<MockSearchService>,
+    ) -> anyhow::Result<(ClusterClient, Vec<(LeafSearchRequest, SearchServiceClient)>)> {
+        let client_pool = SearchClientPool::from_mocks(
+            mock_services
+                .into_iter()
+                .map(|mock_service| Arc::new(mock_service) as Arc<dyn crate::SearchService>)
+                .collect(),
+        )
+        .await?;
+        let leaf_requests = client_pool
+            .clients
+            .read()
+            .await
+            .values()
+            .map(|client| {
+                let split_id = format!("split-{}", client.grpc_addr().port());
+                (mock_leaf_request(&split_id), client.clone())
+            })
+            .collect();
+        let cluster_client = ClusterClient::new(Arc::new(client_pool));
+        Ok((cluster_client, leaf_requests))
+    }
+
+    #[test]
+    fn test_group_leaf_requests() {
+        let mut leaf_requests = Vec::new();
+        for node_ord in 0..9u16 {
+            let grpc_addr = SocketAddr::from(([127, 0, 0, 1], 10000 + node_ord));
+            let client =
+                SearchServiceClient::from_service(Arc::new(MockSearchService::new()), grpc_addr);
+            leaf_requests.push((mock_leaf_request("split"), client.clone()));
+            if node_ord == 4 {
+                leaf_requests.push((mock_leaf_request("other-split"), client));
+            }
+        }
+        let leaf_request_groups = group_leaf_requests(leaf_requests);
+        let group_ports: Vec<Vec<u16>> = leaf_request_groups
+            .iter()
+            .map(|leaf_request_group| {
+                leaf_request_group
+                    .iter()
+                    .map(|(_, client)| client.grpc_addr().port())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            group_ports,
+            vec![
+                vec![10000, 10001, 10002],
+                vec![10003, 10004, 10004, 10005],
+                vec![10006, 10007, 10008],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scatter_gather_leaf_searches_below_threshold() -> anyhow::Result<()> {
+        let mut mock_services = Vec::new();
+        for _ in 0..3 {
+            let mut mock_service = MockSearchService::new();
+            mock_service
+                .expect_leaf_search()
+                .times(1)
+                .returning(|_| Ok(mock_leaf_response("split", 1)));
+            mock_services.push(mock_service);
+        }
+        let (cluster_client, leaf_requests) = mock_leaf_requests(mock_services).await?;
+        let leaf_responses =
+            scatter_gather_leaf_searches(&mock_search_request(), leaf_requests, &cluster_client)
+                .await?;
+        assert_eq!(leaf_responses.len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scatter_gather_leaf_searches_on_intermediate_nodes() -> anyhow::Result<()> {
+        // With 4 nodes, the first and the third nodes merge the responses of groups of 2 nodes.
+        let mut mock_services = Vec::new();
+        for node_ord in 0..4 {
+            let mut mock_service = MockSearchService::new();
+            if node_ord % 2 == 0 {
+                mock_service
+                    .expect_intermediate_search()
+                    .times(1)
+                    .withf(|intermediate_request| {
+                        intermediate_request.leaf_requests.len() == 2
+                            && intermediate_request.search_request.is_some()
+                    })
+                    .returning(|_| {
+                        Ok(LeafSearchResponse {
+                            num_hits: 2,
+                            ..Default::default()
+                        })
+                    });
+            }
+            mock_services.push(mock_service);
+        }
+        let (cluster_client, leaf_requests) = mock_leaf_requests(mock_services).await?;
+        let cluster_client = cluster_client.with_tree_aggregation_min_nodes(2);
+        let leaf_responses =
+            scatter_gather_leaf_searches(&mock_search_request(), leaf_requests, &cluster_client)
+                .await?;
+        assert_eq!(leaf_responses.len(), 2);
+        assert_eq!(
+            leaf_responses
+                .iter()
+                .map(|leaf_response| leaf_response.num_hits)
+                .sum::<u64>(),
+            4
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scatter_gather_leaf_searches_falls_back_to_root() -> anyhow::Result<()> {
+        // The first intermediate node fails: the root searches its group itself.
+        let mut mock_services = Vec::new();
+        for node_ord in 0..4 {
+            let mut mock_service = MockSearchService::new();
+            match node_ord {
+                0 => {
+                    mock_service
+                        .expect_intermediate_search()
+                        .times(1)
+                        .returning(|_| Err(SearchError::InternalError("Node is down".to_string())));
+                }
+                2 => {
+                    mock_service
+                        .expect_intermediate_search()
+                        .times(1)
+                        .returning(|_| {
+                            Ok(LeafSearchResponse {
+                                num_hits: 2,
+                                ..Default::default()
+                            })
+                        });
+                }
+                _ => {}
+            }
+            if node_ord < 2 {
+                mock_service
+                    .expect_leaf_search()
+                    .times(1)
+                    .returning(|_| Ok(mock_leaf_response("split", 1)));
+            }
+            mock_services.push(mock_service);
+        }
+        let (cluster_client, leaf_requests) = mock_leaf_requests(mock_services).await?;
+        let cluster_client = cluster_client.with_tree_aggregation_min_nodes(2);
+        let leaf_responses =
+            scatter_gather_leaf_searches(&mock_search_request(), leaf_requests, &cluster_client)
+                .await?;
+        assert_eq!(leaf_responses.len(), 3);
+        assert_eq!(
+            leaf_responses
+                .iter()
+                .map(|leaf_response| leaf_response.num_hits)
+                .sum::<u64>(),
+            4
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_intermediate_search_merges_leaf_responses() -> anyhow::Result<()> {
+        let mut mock_service1 = MockSearchService::new();
+        mock_service1
+            .expect_leaf_search()
+            .times(1)
+            .returning(|_| Ok(mock_leaf_response("split1", 1)));
+        let mut mock_service2 = MockSearchService::new();
+        mock_service2
+            .expect_leaf_search()
+            .times(1)
+            .returning(|_| Ok(mock_leaf_response("split2", 2)));
+        let client_pool = Arc::new(
+            SearchClientPool::from_mocks(vec![Arc::new(mock_service1), Arc::new(mock_service2)])
+                .await?,
+        );
+        let cluster_client = ClusterClient::new(client_pool.clone());
+        let intermediate_request = IntermediateSearchRequest {
+            search_request: Some(mock_search_request()),
+            leaf_requests: vec![
+                PlacedLeafSearchRequest {
+                    leaf_request: Some(mock_leaf_request("split1")),
+                    leaf_grpc_addr: "127.0.0.1:10000".to_string(),
+                },
+                PlacedLeafSearchRequest {
+                    leaf_request: Some(mock_leaf_request("split2")),
+                    leaf_grpc_addr: "127.0.0.1:10010".to_string(),
+                },
+            ],
+        };
+        let leaf_response =
+            intermediate_search(intermediate_request, &cluster_client, &client_pool).await?;
+        assert_eq!(leaf_response.num_hits, 2);
+        let hits: Vec<(&str, &str)> = leaf_response
+            .partial_hits
+            .iter()
+            .map(|partial_hit| {
+                (
+                    partial_hit.split_id.as_str(),
+                    partial_hit.leaf_grpc_addr.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            hits,
+            vec![("split2", "127.0.0.1:10010"), ("split1", "127.0.0.1:10000")]
+        );
+
+        let unknown_node_request = IntermediateSearchRequest {
+            search_request: Some(mock_search_request()),
+            leaf_requests: vec![PlacedLeafSearchRequest {
+                leaf_request: Some(mock_leaf_request("split3")),
+                leaf_grpc_addr: "127.0.0.1:10020".to_string(),
+            }],
+        };
+        let error = intermediate_search(unknown_node_request, &cluster_client, &client_pool)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, SearchError::InternalError(_)));
+        Ok(())
+    }
+}
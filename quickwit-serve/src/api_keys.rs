@@ -0,0 +1,260 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use thiserror::Error;
+
+/// An API key accepted by the search routes of the REST API, given as `<token>` or
+/// `<token>=<filter>`.
+///
+/// The filter is a query AND-ed into every query of the requests carrying the token as a
+/// bearer token, e.g. `tenant_id:acme`. A key without a filter grants unrestricted access.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub token: String,
+    pub filter_opt: Option<String>,
+}
+
+impl FromStr for ApiKey {
+    type Err = anyhow::Error;
+
+    fn from_str(api_key_str: &str) -> anyhow::Result<Self> {
+        let (token, filter_opt) = match api_key_str.split_once('=') {
+            Some((token, filter)) => {
+                if filter.trim().is_empty() {
+                    bail!("The filter of the API key `{}` is empty.", token);
+                }
+                (token, Some(filter.to_string()))
+            }
+            None => (api_key_str, None),
+        };
+        if token.is_empty() {
+            bail!("API keys must have a non-empty token.");
+        }
+        Ok(ApiKey {
+            token: token.to_string(),
+            filter_opt,
+        })
+    }
+}
+
+/// Maximum number of splits the queries carrying an API key may span, given as
+/// `<token>=<max number of splits>`.
+///
+/// The limit applies on top of the split limits of the indexes, see
+/// [`quickwit_index_config::SplitLimitConfig`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeySplitLimit {
+    pub token: String,
+    pub max_num_splits: u64,
+}
+
+impl FromStr for ApiKeySplitLimit {
+    type Err = anyhow::Error;
+
+    fn from_str(split_limit_str: &str) -> anyhow::Result<Self> {
+        let (token, max_num_splits_str) = split_limit_str.split_once('=').with_context(|| {
+            format!(
+                "Failed to parse the API key split limit `{}`, expected `<token>=<max number of \
+                 splits>`.",
+                split_limit_str
+            )
+        })?;
+        let max_num_splits: u64 = max_num_splits_str.trim().parse().with_context(|| {
+            format!(
+                "Failed to parse the maximum number of splits of the API key `{}`.",
+                token
+            )
+        })?;
+        if max_num_splits == 0 {
+            bail!(
+                "The maximum number of splits of the API key `{}` must be strictly positive.",
+                token
+            );
+        }
+        Ok(ApiKeySplitLimit {
+            token: token.to_string(),
+            max_num_splits,
+        })
+    }
+}
+
+/// Error returned when the API key of a request does not allow it.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum ApiKeyError {
+    #[error("The request does not carry a valid API key")]
+    Unauthorized,
+    #[error("The API key of the request is restricted by a filter and cannot access this route")]
+    Forbidden,
+}
+
+/// The API keys accepted by the search routes.
+///
+/// When no key is configured, the search routes are open to all requests.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    filters: HashMap<String, Option<String>>,
+    split_limits: HashMap<String, u64>,
+}
+
+impl ApiKeys {
+    pub fn new(api_keys: Vec<ApiKey>) -> Self {
+        ApiKeys {
+            filters: api_keys
+                .into_iter()
+                .map(|api_key| (api_key.token, api_key.filter_opt))
+                .collect(),
+            split_limits: HashMap::new(),
+        }
+    }
+
+    /// Limits the number of splits the queries carrying some of the keys may span.
+    pub fn with_split_limits(mut self, split_limits: Vec<ApiKeySplitLimit>) -> Self {
+        self.split_limits = split_limits
+            .into_iter()
+            .map(|split_limit| (split_limit.token, split_limit.max_num_splits))
+            .collect();
+        self
+    }
+
+    /// Returns the token of the `Authorization` header of a request if it is one of the
+    /// configured API keys.
+    pub fn validated_token<'a>(&self, authorization_opt: Option<&'a str>) -> Option<&'a str> {
+        authorization_opt
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|token| self.filters.contains_key(*token))
+    }
+
+    /// Returns the maximum number of splits the queries of a request may span given its
+    /// `Authorization` header, if its API key limits it.
+    pub fn max_num_splits(&self, authorization_opt: Option<&str>) -> Option<u64> {
+        authorization_opt
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .and_then(|token| self.split_limits.get(token.trim()))
+            .copied()
+    }
+
+    /// Returns the filter enforced on the searches of a request given its `Authorization`
+    /// header.
+    pub fn mandatory_filter(
+        &self,
+        authorization_opt: Option<&str>,
+    ) -> Result<Option<String>, ApiKeyError> {
+        if self.filters.is_empty() {
+            return Ok(None);
+        }
+        authorization_opt
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .and_then(|token| self.filters.get(token.trim()))
+            .cloned()
+            .ok_or(ApiKeyError::Unauthorized)
+    }
+
+    /// Checks that a request may access a route that cannot enforce filters, such as fetching
+    /// a document by its address.
+    pub fn check_unrestricted(&self, authorization_opt: Option<&str>) -> Result<(), ApiKeyError> {
+        match self.mandatory_filter(authorization_opt)? {
+            Some(_) => Err(ApiKeyError::Forbidden),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_key_from_str() -> anyhow::Result<()> {
+        assert_eq!(
+            "my-token".parse::<ApiKey>()?,
+            ApiKey {
+                token: "my-token".to_string(),
+                filter_opt: None,
+            }
+        );
+        assert_eq!(
+            "my-token=tenant_id:acme AND env:prod".parse::<ApiKey>()?,
+            ApiKey {
+                token: "my-token".to_string(),
+                filter_opt: Some("tenant_id:acme AND env:prod".to_string()),
+            }
+        );
+        assert!("my-token=".parse::<ApiKey>().is_err());
+        assert!("=tenant_id:acme".parse::<ApiKey>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_api_keys_mandatory_filter() -> anyhow::Result<()> {
+        let api_keys = ApiKeys::default();
+        assert_eq!(api_keys.mandatory_filter(None), Ok(None));
+        assert_eq!(api_keys.check_unrestricted(Some("Bearer my-token")), Ok(()));
+
+        let api_keys = ApiKeys::new(vec!["admin".parse()?, "acme=tenant_id:acme".parse()?]);
+        assert_eq!(api_keys.mandatory_filter(Some("Bearer admin")), Ok(None));
+        assert_eq!(
+            api_keys.mandatory_filter(Some("Bearer acme")),
+            Ok(Some("tenant_id:acme".to_string()))
+        );
+        assert_eq!(
+            api_keys.mandatory_filter(None),
+            Err(ApiKeyError::Unauthorized)
+        );
+        assert_eq!(
+            api_keys.mandatory_filter(Some("Bearer other")),
+            Err(ApiKeyError::Unauthorized)
+        );
+        assert_eq!(api_keys.check_unrestricted(Some("Bearer admin")), Ok(()));
+        assert_eq!(
+            api_keys.check_unrestricted(Some("Bearer acme")),
+            Err(ApiKeyError::Forbidden)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_api_key_split_limit_from_str() -> anyhow::Result<()> {
+        assert_eq!(
+            "acme=100".parse::<ApiKeySplitLimit>()?,
+            ApiKeySplitLimit {
+                token: "acme".to_string(),
+                max_num_splits: 100,
+            }
+        );
+        assert!("acme".parse::<ApiKeySplitLimit>().is_err());
+        assert!("acme=many".parse::<ApiKeySplitLimit>().is_err());
+        assert!("acme=0".parse::<ApiKeySplitLimit>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_api_keys_max_num_splits() -> anyhow::Result<()> {
+        let api_keys = ApiKeys::new(vec!["admin".parse()?, "acme=tenant_id:acme".parse()?])
+            .with_split_limits(vec!["acme=100".parse()?]);
+        assert_eq!(api_keys.max_num_splits(Some("Bearer acme")), Some(100));
+        assert_eq!(api_keys.max_num_splits(Some("Bearer admin")), None);
+        assert_eq!(api_keys.max_num_splits(None), None);
+        Ok(())
+    }
+}
@@ -19,6 +19,14 @@
 
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
+
+use quickwit_search::{
+    FastFieldPinningParams, HedgingParams, NodeAttributes, PlacementRules, SearchThreadPoolParams,
+};
+use quickwit_storage::{S3HttpConfig, StorageTimeoutPolicy};
+
+use crate::{ApiKey, ApiKeySplitLimit, AuditLogConfig, ClickHouseSinkConfig, QuotaConfig};
 
 #[derive(Debug, PartialEq)]
 pub struct ServeArgs {
@@ -33,4 +41,81 @@ pub struct ServeArgs {
 
     /// Metastore URI.
     pub metastore_uri: String,
+
+    /// IDs of the indexes for which garbage collection runs in the background.
+    pub gc_index_ids: Vec<String>,
+
+    /// Interval between two background garbage collection passes.
+    pub gc_interval: Duration,
+
+    /// Threshold period after which staged splits can be garbage collected.
+    pub gc_grace_period: Duration,
+
+    /// If set, published splits older than this period are deleted by the background
+    /// garbage collector.
+    pub gc_retention_period: Option<Duration>,
+
+    /// IDs of the indexes whose split files are verified in the background.
+    pub verify_index_ids: Vec<String>,
+
+    /// Interval between two background split verification passes.
+    pub verify_interval: Duration,
+
+    /// Maximum number of splits verified at each background split verification pass.
+    pub verify_num_splits: usize,
+
+    /// Per-tenant limits enforced on the REST API.
+    pub quota_config: QuotaConfig,
+
+    /// If set, slow leaf searches are sent to a second node.
+    pub leaf_search_hedging: Option<HedgingParams>,
+
+    /// Number of leaf nodes above which the leaf responses of a search are merged on
+    /// intermediate nodes.
+    pub tree_aggregation_min_nodes: usize,
+
+    /// Number of consecutive failed searches after which a split is quarantined. 0 disables the
+    /// quarantine.
+    pub split_quarantine_threshold: usize,
+
+    /// Attributes of this searcher node, matched against the placement rules.
+    pub node_attributes: NodeAttributes,
+
+    /// Rules restricting the searches of some indexes to some searcher nodes.
+    pub placement_rules: PlacementRules,
+
+    /// If set, the fast fields of the recent splits are pinned in memory.
+    pub fast_field_pinning: Option<FastFieldPinningParams>,
+
+    /// Limits on the CPU used by the leaf searches.
+    pub search_thread_pool: SearchThreadPoolParams,
+
+    /// If set, the split files of at most this number of bytes are downloaded in a single
+    /// request when a leaf search opens them.
+    pub whole_split_download_max_num_bytes: Option<u64>,
+
+    /// If set, the search requests are recorded into an audit log index.
+    pub audit_log: Option<AuditLogConfig>,
+
+    /// Deadlines of the operations on the object storages (S3, ...).
+    pub storage_timeout_policy: StorageTimeoutPolicy,
+
+    /// Settings of the HTTP client shared by the S3 storages.
+    pub s3_http_config: S3HttpConfig,
+
+    /// If true, the node starts as a standby searcher.
+    pub standby: bool,
+
+    /// API keys required by the search routes of the REST API. If empty, these routes are
+    /// open to all requests.
+    pub api_keys: Vec<ApiKey>,
+
+    /// Maximum number of splits the queries carrying some of the API keys may span.
+    pub api_key_split_limits: Vec<ApiKeySplitLimit>,
+
+    /// gRPC addresses of the indexers whose splits being indexed are searched as well.
+    pub realtime_indexer_addrs: Vec<SocketAddr>,
+
+    /// ClickHouse tables the search stream API may insert values into, referenced by name.
+    pub click_house_sinks: Vec<ClickHouseSinkConfig>,
 }
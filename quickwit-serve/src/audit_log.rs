@@ -0,0 +1,449 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Audit log of the search requests, written into a dedicated Quickwit index.
+//!
+//! The records are buffered in memory and periodically indexed through the regular indexing
+//! pipeline, so that the audit log can be searched like any other index.
+//!
+//! All the records are appended to a single partition of the index, whose offset is persisted
+//! in the index checkpoint: each flush resumes from the offset reached by the previous one. The
+//! pipeline of a flush merges the splits of the index like the one of any other index, so the
+//! many small splits of the audit log end up merged.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use byte_unit::Byte;
+use quickwit_common::{extract_index_id_from_index_uri, QuickwitUri};
+use quickwit_index_config::DefaultIndexConfigBuilder;
+use quickwit_indexing::actors::IndexerParams;
+use quickwit_indexing::index_data;
+use quickwit_indexing::models::{CommitPolicy, DiskGuardrails, DocSizeLimit, ScratchDirectory};
+use quickwit_indexing::source::{SourceConfig, VecSourceParams};
+use quickwit_metastore::checkpoint::{Checkpoint, PartitionId, Position};
+use quickwit_metastore::{IndexMetadata, Metastore, MetastoreError};
+use quickwit_storage::StorageUriResolver;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::ApiError;
+
+const AUDIT_LOG_SOURCE_ID: &str = "audit-log-source";
+
+/// Partition of the audit log index receiving all the records.
+const AUDIT_LOG_PARTITION: &str = "audit-log";
+
+/// Maximum number of records waiting for a flush. The records received beyond are dropped.
+const MAX_BUFFERED_RECORDS: usize = 100_000;
+
+/// Length of the prefix of the digest of the bearer tokens stored in the records.
+const TOKEN_DIGEST_PREFIX_LEN: usize = 16;
+
+const AUDIT_LOG_INDEX_CONFIG: &str = r#"{
+    "default_search_fields": ["query"],
+    "timestamp_field": "timestamp",
+    "field_mappings": [
+        {"name": "timestamp", "type": "i64", "fast": true},
+        {"name": "tenant", "type": "text", "tokenizer": "raw"},
+        {"name": "index_id", "type": "text", "tokenizer": "raw"},
+        {"name": "endpoint", "type": "text", "tokenizer": "raw"},
+        {"name": "query", "type": "text"},
+        {"name": "latency_ms", "type": "u64", "fast": true},
+        {"name": "num_hits", "type": "u64", "fast": true},
+        {"name": "status_code", "type": "u64", "fast": true},
+        {"name": "error", "type": "text"}
+    ]
+}"#;
+
+/// Configuration of the search audit log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditLogConfig {
+    /// URI of the index receiving the audit records. It is created if it does not exist.
+    pub index_uri: String,
+    /// Interval between two flushes of the buffered records into the index.
+    pub flush_interval: Duration,
+}
+
+/// A search request, as recorded in the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditRecord {
+    /// Time at which the request was received, in seconds since the epoch.
+    pub timestamp: i64,
+    /// Tenant of the request: a digest of its bearer token, or `anonymous`.
+    pub tenant: String,
+    pub index_id: String,
+    /// `search`, `search_stream` or `sql`.
+    pub endpoint: String,
+    pub query: String,
+    pub latency_ms: u64,
+    /// Number of documents matching the query, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_hits: Option<u64>,
+    pub status_code: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    /// Creates the record of a request that took `latency` and ended with `outcome`, holding
+    /// the number of hits of a successful request.
+    pub fn new(
+        endpoint: &str,
+        index_id: &str,
+        query: &str,
+        authorization_opt: Option<&str>,
+        latency: Duration,
+        outcome: Result<Option<u64>, &ApiError>,
+    ) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let (num_hits, status_code, error) = match outcome {
+            Ok(num_hits_opt) => (num_hits_opt, 200, None),
+            Err(error) => (
+                None,
+                error.http_status_code().as_u16() as u64,
+                Some(error.message()),
+            ),
+        };
+        AuditRecord {
+            timestamp: now.checked_sub(latency).unwrap_or_default().as_secs() as i64,
+            tenant: tenant(authorization_opt),
+            index_id: index_id.to_string(),
+            endpoint: endpoint.to_string(),
+            query: query.to_string(),
+            latency_ms: latency.as_millis() as u64,
+            num_hits,
+            status_code,
+            error,
+        }
+    }
+}
+
+/// Returns the tenant of a request. Bearer tokens are secrets, so only a digest of them is
+/// recorded.
+fn tenant(authorization_opt: Option<&str>) -> String {
+    match authorization_opt.and_then(|authorization| authorization.strip_prefix("Bearer ")) {
+        Some(token) => {
+            let digest = format!("{:x}", md5::compute(token.trim()));
+            format!("token:{}", &digest[..TOKEN_DIGEST_PREFIX_LEN])
+        }
+        None => "anonymous".to_string(),
+    }
+}
+
+/// Buffers the audit records and indexes them into the audit log index.
+pub struct AuditLog {
+    index_id: String,
+    metastore: Arc<dyn Metastore>,
+    storage_resolver: StorageUriResolver,
+    records: Mutex<Vec<AuditRecord>>,
+    /// Number of records dropped since the last flush because the buffer was full.
+    num_dropped_records: AtomicU64,
+    /// Serializes the flushes, which all append to the same partition.
+    flush_lock: tokio::sync::Mutex<()>,
+}
+
+impl AuditLog {
+    /// Creates the audit log index if needed, and starts flushing the records into it every
+    /// `flush_interval`.
+    pub async fn start(
+        config: &AuditLogConfig,
+        metastore: Arc<dyn Metastore>,
+        storage_resolver: StorageUriResolver,
+    ) -> anyhow::Result<Arc<AuditLog>> {
+        let index_id = extract_index_id_from_index_uri(&config.index_uri)?.to_string();
+        match metastore.index_metadata(&index_id).await {
+            Ok(_) => {}
+            Err(MetastoreError::IndexDoesNotExist { .. }) => {
+                info!(index_id = %index_id, "Creating the audit log index.");
+                let index_config =
+                    serde_json::from_str::<DefaultIndexConfigBuilder>(AUDIT_LOG_INDEX_CONFIG)?
+                        .build()?;
+                let index_metadata = IndexMetadata {
+                    index_id: index_id.clone(),
+                    index_uri: QuickwitUri::from_str(&config.index_uri)?,
+                    index_config: Arc::new(index_config),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                };
+                metastore.create_index(index_metadata).await?;
+            }
+            Err(error) => return Err(error.into()),
+        }
+        let audit_log = Arc::new(AuditLog {
+            index_id,
+            metastore,
+            storage_resolver,
+            records: Mutex::new(Vec::new()),
+            num_dropped_records: AtomicU64::new(0),
+            flush_lock: tokio::sync::Mutex::new(()),
+        });
+        let flushed_audit_log = audit_log.clone();
+        let flush_interval = config.flush_interval;
+        tokio::spawn(async move {
+            let first_flush = tokio::time::Instant::now() + flush_interval;
+            let mut interval = tokio::time::interval_at(first_flush, flush_interval);
+            loop {
+                interval.tick().await;
+                if let Err(error) = flushed_audit_log.flush().await {
+                    warn!(error = ?error, "Failed to flush the audit log.");
+                }
+            }
+        });
+        Ok(audit_log)
+    }
+
+    /// Buffers a record until the next flush. The record is dropped if the buffer is full.
+    pub fn record(&self, record: AuditRecord) {
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= MAX_BUFFERED_RECORDS {
+            self.num_dropped_records.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        records.push(record);
+    }
+
+    /// Puts back the records of a failed flush in front of the buffer, keeping it bounded.
+    fn requeue(&self, mut failed_records: Vec<AuditRecord>) {
+        let mut records = self.records.lock().unwrap();
+        failed_records.append(&mut records);
+        if failed_records.len() > MAX_BUFFERED_RECORDS {
+            let num_dropped_records = failed_records.len() - MAX_BUFFERED_RECORDS;
+            failed_records.truncate(MAX_BUFFERED_RECORDS);
+            self.num_dropped_records
+                .fetch_add(num_dropped_records as u64, Ordering::Relaxed);
+        }
+        *records = failed_records;
+    }
+
+    /// Indexes the buffered records, and returns their number.
+    ///
+    /// The records are put back in the buffer if the indexing fails, and retried with the next
+    /// flush.
+    pub async fn flush(&self) -> anyhow::Result<usize> {
+        let _flush_guard = self.flush_lock.lock().await;
+        let num_dropped_records = self.num_dropped_records.swap(0, Ordering::Relaxed);
+        if num_dropped_records > 0 {
+            warn!(
+                num_dropped_records = num_dropped_records,
+                "The audit log buffer was full, records were dropped."
+            );
+        }
+        let records = std::mem::take(&mut *self.records.lock().unwrap());
+        if records.is_empty() {
+            return Ok(0);
+        }
+        match self.index_records(&records).await {
+            Ok(()) => Ok(records.len()),
+            Err(error) => {
+                self.requeue(records);
+                Err(error)
+            }
+        }
+    }
+
+    /// Appends the records to the audit log partition, after the offset reached by the
+    /// previous flushes.
+    async fn index_records(&self, records: &[AuditRecord]) -> anyhow::Result<()> {
+        let items = records
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<serde_json::Result<Vec<String>>>()?;
+        let index_metadata = self.metastore.index_metadata(&self.index_id).await?;
+        let partition_id = PartitionId::from(AUDIT_LOG_PARTITION);
+        let start_offset = match index_metadata
+            .checkpoint
+            .position_for_partition(&partition_id)
+        {
+            Some(Position::Offset(offset_str)) => offset_str.parse::<usize>()? + 1,
+            Some(Position::Beginning) | None => 0,
+        };
+        let source_config = SourceConfig {
+            source_id: AUDIT_LOG_SOURCE_ID.to_string(),
+            source_type: "vec".to_string(),
+            params: serde_json::to_value(VecSourceParams {
+                items,
+                batch_num_docs: 1_000,
+                partition: AUDIT_LOG_PARTITION.to_string(),
+                start_offset,
+            })?,
+        };
+        let indexer_params = IndexerParams {
+            scratch_directory: ScratchDirectory::try_new_temp()?,
+            heap_size: Byte::from_bytes(30_000_000),
+            commit_policy: CommitPolicy::default(),
+            disk_guardrails: DiskGuardrails::default(),
+            doc_size_limit: DocSizeLimit::default(),
+            realtime_opt: None,
+        };
+        index_data(
+            self.index_id.clone(),
+            self.metastore.clone(),
+            indexer_params,
+            source_config,
+            self.storage_resolver.clone(),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_metastore::{SingleFileMetastore, SplitState};
+
+    use super::*;
+
+    #[test]
+    fn test_audit_record() -> anyhow::Result<()> {
+        let record = AuditRecord::new(
+            "search",
+            "my-index",
+            "severity:error",
+            Some("Bearer my-token"),
+            Duration::from_millis(42),
+            Ok(Some(3)),
+        );
+        assert_eq!(record.tenant, tenant(Some("Bearer my-token")));
+        assert!(!record.tenant.contains("my-token"));
+        assert_eq!(record.latency_ms, 42);
+        assert_eq!(record.status_code, 200);
+        let record_json = serde_json::to_value(&record)?;
+        assert_eq!(record_json["num_hits"], 3);
+        assert!(record_json.get("error").is_none());
+
+        let record = AuditRecord::new(
+            "search_stream",
+            "my-index",
+            "severity:error",
+            None,
+            Duration::from_millis(42),
+            Err(&ApiError::NotFound),
+        );
+        assert_eq!(record.tenant, "anonymous");
+        assert_eq!(record.status_code, 404);
+        assert!(record.error.is_some());
+        assert!(serde_json::to_value(&record)?.get("num_hits").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tenant() {
+        assert_eq!(tenant(None), "anonymous");
+        assert_eq!(tenant(Some("Basic Zm9vOmJhcg==")), "anonymous");
+        let token_tenant = tenant(Some("Bearer my-token"));
+        assert_eq!(token_tenant.len(), "token:".len() + TOKEN_DIGEST_PREFIX_LEN);
+        assert_eq!(token_tenant, tenant(Some("Bearer  my-token ")));
+        assert_ne!(token_tenant, tenant(Some("Bearer other-token")));
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_flush() -> anyhow::Result<()> {
+        let metastore: Arc<dyn Metastore> = Arc::new(SingleFileMetastore::for_test());
+        let config = AuditLogConfig {
+            index_uri: "ram://indexes/audit-log".to_string(),
+            flush_interval: Duration::from_secs(3_600),
+        };
+        let audit_log =
+            AuditLog::start(&config, metastore.clone(), StorageUriResolver::for_test()).await?;
+        assert!(metastore.index_metadata("audit-log").await.is_ok());
+        assert_eq!(audit_log.flush().await?, 0);
+
+        for query in &["severity:error", "severity:warn"] {
+            audit_log.record(AuditRecord::new(
+                "search",
+                "my-index",
+                query,
+                None,
+                Duration::from_millis(10),
+                Ok(Some(1)),
+            ));
+        }
+        assert_eq!(audit_log.flush().await?, 2);
+        audit_log.record(AuditRecord::new(
+            "search",
+            "my-index",
+            "*",
+            None,
+            Duration::from_millis(10),
+            Ok(Some(1)),
+        ));
+        assert_eq!(audit_log.flush().await?, 1);
+        let splits = metastore
+            .list_splits("audit-log", SplitState::Published, None, &[])
+            .await?;
+        let num_records: usize = splits
+            .iter()
+            .map(|split| split.split_metadata.num_records)
+            .sum();
+        assert_eq!(num_records, 3);
+        let index_metadata = metastore.index_metadata("audit-log").await?;
+        assert_eq!(
+            index_metadata
+                .checkpoint
+                .position_for_partition(&PartitionId::from(AUDIT_LOG_PARTITION)),
+            Some(&Position::from(2u64))
+        );
+        assert_eq!(index_metadata.checkpoint.iter().count(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_buffer_is_bounded() -> anyhow::Result<()> {
+        let metastore: Arc<dyn Metastore> = Arc::new(SingleFileMetastore::for_test());
+        let config = AuditLogConfig {
+            index_uri: "ram://indexes/audit-log".to_string(),
+            flush_interval: Duration::from_secs(3_600),
+        };
+        let audit_log = AuditLog::start(&config, metastore, StorageUriResolver::for_test()).await?;
+        let record = AuditRecord::new(
+            "search",
+            "my-index",
+            "*",
+            None,
+            Duration::from_millis(10),
+            Ok(Some(1)),
+        );
+        for _ in 0..MAX_BUFFERED_RECORDS + 2 {
+            audit_log.record(record.clone());
+        }
+        assert_eq!(
+            audit_log.records.lock().unwrap().len(),
+            MAX_BUFFERED_RECORDS
+        );
+        assert_eq!(audit_log.num_dropped_records.load(Ordering::Relaxed), 2);
+
+        audit_log.requeue(vec![record.clone(); 3]);
+        assert_eq!(
+            audit_log.records.lock().unwrap().len(),
+            MAX_BUFFERED_RECORDS
+        );
+        assert_eq!(audit_log.num_dropped_records.load(Ordering::Relaxed), 5);
+        Ok(())
+    }
+}
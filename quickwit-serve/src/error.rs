@@ -18,12 +18,16 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use quickwit_cluster::error::ClusterError;
+use quickwit_metastore::{MetastoreError, MetastoreErrorKind};
 use quickwit_search::SearchError;
 use serde::ser::SerializeMap;
 use thiserror::Error;
 use warp::http;
 use warp::hyper::StatusCode;
 
+use crate::api_keys::ApiKeyError;
+use crate::quota::QuotaExceeded;
+
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("InvalidArgument: {0}.")]
@@ -36,6 +40,12 @@ pub enum ApiError {
     SearchError(#[from] SearchError),
     #[error("Cluster error. {0}.")]
     ClusterError(#[from] ClusterError),
+    #[error("Metastore error. {0}.")]
+    MetastoreError(#[from] MetastoreError),
+    #[error("Quota exceeded. {0}.")]
+    QuotaExceeded(#[from] QuotaExceeded),
+    #[error("Access denied. {0}.")]
+    ApiKey(#[from] ApiKeyError),
     #[error("Route not found")]
     NotFound,
 }
@@ -48,9 +58,23 @@ impl ApiError {
                 SearchError::InternalError(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
                 SearchError::StorageResolverError(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
                 SearchError::InvalidQuery(_) => http::StatusCode::BAD_REQUEST,
+                SearchError::DocDoesNotExist { .. } => http::StatusCode::NOT_FOUND,
+                SearchError::Unavailable(_) => http::StatusCode::SERVICE_UNAVAILABLE,
             },
             ApiError::ClusterError(_cluster_error) => http::StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::MetastoreError(metastore_error) => match metastore_error.kind() {
+                MetastoreErrorKind::NotFound => http::StatusCode::NOT_FOUND,
+                MetastoreErrorKind::Conflict => http::StatusCode::CONFLICT,
+                MetastoreErrorKind::Forbidden => http::StatusCode::FORBIDDEN,
+                MetastoreErrorKind::Unavailable => http::StatusCode::SERVICE_UNAVAILABLE,
+                MetastoreErrorKind::Corrupted | MetastoreErrorKind::Internal => {
+                    http::StatusCode::INTERNAL_SERVER_ERROR
+                }
+            },
             ApiError::InvalidArgument(_err) => StatusCode::BAD_REQUEST,
+            ApiError::QuotaExceeded(_) => http::StatusCode::TOO_MANY_REQUESTS,
+            ApiError::ApiKey(ApiKeyError::Unauthorized) => http::StatusCode::UNAUTHORIZED,
+            ApiError::ApiKey(ApiKeyError::Forbidden) => http::StatusCode::FORBIDDEN,
             ApiError::NotFound => http::StatusCode::NOT_FOUND,
         }
     }
@@ -0,0 +1,156 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use quickwit_actors::{ActorHandle, Universe};
+use quickwit_indexing::actors::{
+    GarbageCollector, GarbageCollectorCounters, GarbageCollectorParams,
+};
+use quickwit_metastore::Metastore;
+use quickwit_storage::StorageUriResolver;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Runs garbage collection (and optionally retention enforcement) periodically for a set of
+/// indexes, as a replacement for invoking `quickwit gc` from a cron job.
+///
+/// Each index gets its own [`GarbageCollector`] actor, whose state can be observed through
+/// [`GarbageCollectionService::run_reports`]. The garbage collectors of the indexes that cannot
+/// be resolved yet, e.g. because they are still being created, are started later on by
+/// [`GarbageCollectionService::start_pending_garbage_collectors`].
+pub struct GarbageCollectionService {
+    // The universe needs to outlive the garbage collectors.
+    universe: Universe,
+    params: GarbageCollectorParams,
+    metastore: Arc<dyn Metastore>,
+    storage_resolver: StorageUriResolver,
+    garbage_collectors: Mutex<BTreeMap<String, ActorHandle<GarbageCollector>>>,
+    /// Indexes whose garbage collector is not started yet, with the reason why.
+    pending_index_ids: Mutex<BTreeMap<String, String>>,
+}
+
+impl GarbageCollectionService {
+    /// Spawns one garbage collector per index in `index_ids`.
+    ///
+    /// The indexes that cannot be resolved do not prevent the service from starting.
+    pub async fn start(
+        index_ids: &[String],
+        params: GarbageCollectorParams,
+        metastore: Arc<dyn Metastore>,
+        storage_resolver: StorageUriResolver,
+    ) -> anyhow::Result<Self> {
+        let pending_index_ids = index_ids
+            .iter()
+            .map(|index_id| (index_id.clone(), "Not started yet.".to_string()))
+            .collect();
+        let gc_service = GarbageCollectionService {
+            universe: Universe::new(),
+            params,
+            metastore,
+            storage_resolver,
+            garbage_collectors: Mutex::new(BTreeMap::new()),
+            pending_index_ids: Mutex::new(pending_index_ids),
+        };
+        gc_service.start_pending_garbage_collectors().await;
+        Ok(gc_service)
+    }
+
+    /// Tries to start the garbage collectors of the indexes that could not be resolved so far.
+    pub async fn start_pending_garbage_collectors(&self) {
+        let mut pending_index_ids = self.pending_index_ids.lock().await;
+        let mut garbage_collectors = self.garbage_collectors.lock().await;
+        for (index_id, pending_reason) in pending_index_ids.iter_mut() {
+            match self.start_garbage_collector(index_id).await {
+                Ok(garbage_collector_handle) => {
+                    garbage_collectors.insert(index_id.clone(), garbage_collector_handle);
+                }
+                Err(error) => {
+                    let reason = format!("{:#}", error);
+                    if *pending_reason != reason {
+                        warn!(
+                            index_id = %index_id,
+                            error = %reason,
+                            "Garbage collector is pending."
+                        );
+                        *pending_reason = reason;
+                    }
+                }
+            }
+        }
+        pending_index_ids.retain(|index_id, _| !garbage_collectors.contains_key(index_id));
+    }
+
+    async fn start_garbage_collector(
+        &self,
+        index_id: &str,
+    ) -> anyhow::Result<ActorHandle<GarbageCollector>> {
+        let index_metadata = self
+            .metastore
+            .index_metadata(index_id)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to enable garbage collection for index `{}`.",
+                    index_id
+                )
+            })?;
+        // The split files of an attached index belong to another cluster.
+        if index_metadata.read_only {
+            bail!(
+                "The index `{}` is read-only: it was attached from the storage of another cluster \
+                 and cannot be garbage collected.",
+                index_id
+            );
+        }
+        let index_storage = self.storage_resolver.resolve_with_mirror(
+            &index_metadata.index_uri,
+            index_metadata.mirror_index_uri.as_deref(),
+        )?;
+        let garbage_collector = GarbageCollector::with_params(
+            index_id.to_string(),
+            index_storage,
+            self.metastore.clone(),
+            self.params.clone(),
+        );
+        let (_garbage_collector_mailbox, garbage_collector_handle) =
+            self.universe.spawn_actor(garbage_collector).spawn_async();
+        info!(index_id = %index_id, params = ?self.params, "Started garbage collector.");
+        Ok(garbage_collector_handle)
+    }
+
+    /// Returns the counters and most recent run reports of each garbage collector,
+    /// keyed by index ID.
+    pub async fn run_reports(&self) -> BTreeMap<String, GarbageCollectorCounters> {
+        self.start_pending_garbage_collectors().await;
+        let mut run_reports = BTreeMap::new();
+        for (index_id, garbage_collector_handle) in self.garbage_collectors.lock().await.iter() {
+            let observation = garbage_collector_handle.observe().await;
+            run_reports.insert(index_id.clone(), observation.state);
+        }
+        run_reports
+    }
+
+    /// Returns the indexes whose garbage collector is not started yet, with the reason why.
+    pub async fn pending_index_ids(&self) -> BTreeMap<String, String> {
+        self.pending_index_ids.lock().await.clone()
+    }
+}
@@ -18,9 +18,16 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use std::net::SocketAddr;
+use std::sync::Arc;
 
+use quickwit_common::RealtimeSplits;
+use quickwit_metastore::Metastore;
 use quickwit_proto::cluster_service_server::ClusterServiceServer;
 use quickwit_proto::search_service_server::SearchServiceServer;
+use quickwit_search::{
+    ClusterClient, NodeAttributes, SearchClientPool, SearchServiceImpl, StandbyMode,
+};
+use quickwit_storage::quickwit_storage_uri_resolver;
 use tonic::transport::Server;
 use tracing::*;
 
@@ -42,3 +49,32 @@ pub async fn start_grpc_service(
 
     Ok(())
 }
+
+/// Start the gRPC service of an indexer, answering the realtime leaf searches of the roots
+/// over the splits being indexed in `realtime_splits`.
+pub async fn start_realtime_grpc_service(
+    grpc_addr: SocketAddr,
+    metastore: Arc<dyn Metastore>,
+    realtime_splits: RealtimeSplits,
+) -> anyhow::Result<()> {
+    // The indexer does not search the published splits, so it gets an empty client pool.
+    let client_pool = Arc::new(SearchClientPool::default());
+    let search_service = SearchServiceImpl::new(
+        metastore,
+        quickwit_storage_uri_resolver().clone(),
+        ClusterClient::new(client_pool.clone()),
+        client_pool,
+        NodeAttributes::default(),
+        StandbyMode::new(false),
+    )
+    .with_realtime_splits(realtime_splits);
+    info!(grpc_addr=?grpc_addr, "Start realtime gRPC service.");
+    Server::builder()
+        .add_service(SearchServiceServer::new(GrpcSearchAdapter::from(Arc::new(
+            search_service,
+        ))))
+        .serve(grpc_addr)
+        .await?;
+
+    Ok(())
+}
@@ -142,4 +142,51 @@ impl grpc::SearchService for GrpcSearchAdapter {
             .map_err(Into::<tonic::Status>::into);
         Ok(tonic::Response::new(Box::pin(leaf_search_result)))
     }
+
+    async fn node_info(
+        &self,
+        request: tonic::Request<quickwit_proto::NodeInfoRequest>,
+    ) -> Result<tonic::Response<quickwit_proto::NodeInfoResponse>, tonic::Status> {
+        let node_info_request = request.into_inner();
+        let node_info_response = self
+            .0
+            .node_info(node_info_request)
+            .await
+            .map_err(Into::<tonic::Status>::into)?;
+        Ok(tonic::Response::new(node_info_response))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn leaf_search_realtime(
+        &self,
+        request: tonic::Request<quickwit_proto::LeafSearchRealtimeRequest>,
+    ) -> Result<tonic::Response<quickwit_proto::LeafSearchRealtimeResponse>, tonic::Status> {
+        let parent_cx =
+            global::get_text_map_propagator(|prop| prop.extract(&MetadataMap(request.metadata())));
+        Span::current().set_parent(parent_cx);
+        let leaf_search_realtime_request = request.into_inner();
+        let leaf_search_realtime_response = self
+            .0
+            .leaf_search_realtime(leaf_search_realtime_request)
+            .await
+            .map_err(Into::<tonic::Status>::into)?;
+        Ok(tonic::Response::new(leaf_search_realtime_response))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn intermediate_search(
+        &self,
+        request: tonic::Request<quickwit_proto::IntermediateSearchRequest>,
+    ) -> Result<tonic::Response<quickwit_proto::LeafSearchResponse>, tonic::Status> {
+        let parent_cx =
+            global::get_text_map_propagator(|prop| prop.extract(&MetadataMap(request.metadata())));
+        Span::current().set_parent(parent_cx);
+        let intermediate_search_request = request.into_inner();
+        let leaf_search_response = self
+            .0
+            .intermediate_search(intermediate_search_request)
+            .await
+            .map_err(Into::<tonic::Status>::into)?;
+        Ok(tonic::Response::new(leaf_search_response))
+    }
 }
@@ -17,5 +17,10 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+pub mod cache_admin;
 pub mod cluster;
+pub mod garbage_collection;
 pub mod health_check;
+pub mod index_stats;
+pub mod split_timeline;
+pub mod standby;
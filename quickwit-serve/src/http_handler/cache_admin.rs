@@ -0,0 +1,341 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use quickwit_metastore::Metastore;
+use quickwit_search::{
+    clear_searcher_caches, invalidate_split_caches, searcher_caches_report,
+    set_searcher_cache_capacity, CacheUsage, SearcherCache, SearcherCachesReport,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use warp::hyper::header::AUTHORIZATION;
+use warp::{Filter, Rejection};
+
+use crate::api_keys::ApiKeys;
+use crate::rest::{from_simple_list, Format};
+use crate::ApiError;
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct CachesQueryString {
+    /// The output format requested.
+    #[serde(default)]
+    pub format: Format,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct InvalidateCachesQueryString {
+    /// If set, invalidates the cache entries of the splits of this index.
+    pub index_id: Option<String>,
+    /// If set, invalidates the cache entries of these splits.
+    #[serde(default)]
+    #[serde(deserialize_with = "from_simple_list")]
+    pub split_ids: Option<Vec<String>>,
+    /// The output format requested.
+    #[serde(default)]
+    pub format: Format,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InvalidateCachesResponse {
+    /// Number of splits that had at least one cache entry.
+    pub num_invalidated_splits: usize,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheCapacityQueryString {
+    /// The new capacity of the cache, in bytes or in number of splits for the split pool.
+    pub capacity: usize,
+    /// The output format requested.
+    #[serde(default)]
+    pub format: Format,
+}
+
+/// Handlers inspecting, invalidating and resizing the caches of the searcher node.
+///
+/// - `GET /admin/caches` lists the contents of the caches.
+/// - `DELETE /admin/caches` invalidates the entries of an index or of some splits, or all of
+/// the entries if neither is given.
+/// - `PUT /admin/caches/<cache>` changes the capacity of a cache.
+///
+/// Only the unrestricted API keys may invalidate or resize the caches.
+pub fn cache_admin_handler(
+    metastore: Arc<dyn Metastore>,
+    api_keys: Arc<ApiKeys>,
+) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+    let invalidate_api_keys = api_keys.clone();
+    list_caches_filter()
+        .and_then(list_caches)
+        .or(invalidate_caches_filter()
+            .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+            .and(warp::any().map(move || metastore.clone()))
+            .and(warp::any().map(move || invalidate_api_keys.clone()))
+            .and_then(invalidate_caches))
+        .or(cache_capacity_filter()
+            .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+            .and(warp::any().map(move || api_keys.clone()))
+            .and_then(set_cache_capacity))
+}
+
+fn list_caches_filter() -> impl Filter<Extract = (CachesQueryString,), Error = Rejection> + Clone {
+    warp::path!("admin" / "caches")
+        .and(warp::get())
+        .and(serde_qs::warp::query(serde_qs::Config::default()))
+}
+
+fn invalidate_caches_filter(
+) -> impl Filter<Extract = (InvalidateCachesQueryString,), Error = Rejection> + Clone {
+    warp::path!("admin" / "caches")
+        .and(warp::delete())
+        .and(serde_qs::warp::query(serde_qs::Config::default()))
+}
+
+fn cache_capacity_filter(
+) -> impl Filter<Extract = (String, CacheCapacityQueryString), Error = Rejection> + Clone {
+    warp::path!("admin" / "caches" / String)
+        .and(warp::put())
+        .and(serde_qs::warp::query(serde_qs::Config::default()))
+}
+
+async fn list_caches(request: CachesQueryString) -> Result<impl warp::Reply, Infallible> {
+    Ok(request.format.make_reply(list_caches_endpoint()))
+}
+
+fn list_caches_endpoint() -> Result<SearcherCachesReport, ApiError> {
+    Ok(searcher_caches_report())
+}
+
+async fn invalidate_caches(
+    request: InvalidateCachesQueryString,
+    authorization_opt: Option<String>,
+    metastore: Arc<dyn Metastore>,
+    api_keys: Arc<ApiKeys>,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(request.format.make_reply(
+        invalidate_caches_endpoint(
+            &request,
+            authorization_opt.as_deref(),
+            &*metastore,
+            &api_keys,
+        )
+        .await,
+    ))
+}
+
+async fn invalidate_caches_endpoint(
+    request: &InvalidateCachesQueryString,
+    authorization_opt: Option<&str>,
+    metastore: &dyn Metastore,
+    api_keys: &ApiKeys,
+) -> Result<InvalidateCachesResponse, ApiError> {
+    api_keys.check_unrestricted(authorization_opt)?;
+    if request.index_id.is_none() && request.split_ids.is_none() {
+        let num_invalidated_splits = searcher_caches_report().splits.len();
+        clear_searcher_caches();
+        info!("Searcher caches cleared.");
+        return Ok(InvalidateCachesResponse {
+            num_invalidated_splits,
+        });
+    }
+    let mut split_ids = request.split_ids.clone().unwrap_or_default();
+    if let Some(index_id) = &request.index_id {
+        let splits = metastore.list_all_splits(index_id).await?;
+        split_ids.extend(
+            splits
+                .into_iter()
+                .map(|split| split.split_metadata.split_id),
+        );
+    }
+    let num_invalidated_splits = invalidate_split_caches(&split_ids);
+    info!(
+        index_id = ?request.index_id,
+        num_invalidated_splits = num_invalidated_splits,
+        "Searcher cache entries invalidated."
+    );
+    Ok(InvalidateCachesResponse {
+        num_invalidated_splits,
+    })
+}
+
+async fn set_cache_capacity(
+    cache: String,
+    request: CacheCapacityQueryString,
+    authorization_opt: Option<String>,
+    api_keys: Arc<ApiKeys>,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(request.format.make_reply(set_cache_capacity_endpoint(
+        &cache,
+        request.capacity,
+        authorization_opt.as_deref(),
+        &api_keys,
+    )))
+}
+
+fn set_cache_capacity_endpoint(
+    cache_str: &str,
+    capacity: usize,
+    authorization_opt: Option<&str>,
+    api_keys: &ApiKeys,
+) -> Result<CacheUsage, ApiError> {
+    api_keys.check_unrestricted(authorization_opt)?;
+    let cache = SearcherCache::from_str(cache_str)
+        .map_err(|error| ApiError::InvalidArgument(error.to_string()))?;
+    set_searcher_cache_capacity(cache, capacity)
+        .map_err(|error| ApiError::InvalidArgument(error.to_string()))?;
+    info!(cache = ?cache, capacity = capacity, "Searcher cache capacity changed.");
+    searcher_caches_report()
+        .caches
+        .into_iter()
+        .find(|cache_usage| cache_usage.cache == cache)
+        .ok_or(ApiError::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_metastore::{MockMetastore, SplitMetadata, SplitMetadataAndFooterOffsets};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rest_invalidate_caches_of_index() -> anyhow::Result<()> {
+        let mut metastore = MockMetastore::new();
+        metastore
+            .expect_list_all_splits()
+            .withf(|index_id| index_id == "my-index")
+            .times(1)
+            .returning(|_| {
+                Ok(vec![SplitMetadataAndFooterOffsets {
+                    split_metadata: SplitMetadata {
+                        split_id: "split1".to_string(),
+                        ..Default::default()
+                    },
+                    footer_offsets: 0..1,
+                }])
+            });
+        let cache_admin_handler =
+            cache_admin_handler(Arc::new(metastore), Arc::new(ApiKeys::default()));
+        let resp = warp::test::request()
+            .method("DELETE")
+            .path("/admin/caches?indexId=my-index&format=json")
+            .reply(&cache_admin_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_eq!(resp_json["numInvalidatedSplits"], 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_list_caches() -> anyhow::Result<()> {
+        let cache_admin_handler =
+            cache_admin_handler(Arc::new(MockMetastore::new()), Arc::new(ApiKeys::default()));
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/admin/caches?format=json")
+            .reply(&cache_admin_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_eq!(resp_json["caches"][0]["cache"], "split_footer");
+        assert_eq!(resp_json["caches"][1]["cache"], "split_pool");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_set_cache_capacity() -> anyhow::Result<()> {
+        let cache_admin_handler =
+            cache_admin_handler(Arc::new(MockMetastore::new()), Arc::new(ApiKeys::default()));
+        let resp = warp::test::request()
+            .method("PUT")
+            .path("/admin/caches/split_pool?capacity=2000&format=json")
+            .reply(&cache_admin_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_eq!(resp_json["capacity"], 2000);
+
+        let resp = warp::test::request()
+            .method("PUT")
+            .path("/admin/caches/split_pool?capacity=0")
+            .reply(&cache_admin_handler)
+            .await;
+        assert_eq!(resp.status(), 400);
+
+        let resp = warp::test::request()
+            .method("PUT")
+            .path("/admin/caches/unknown?capacity=10")
+            .reply(&cache_admin_handler)
+            .await;
+        assert_eq!(resp.status(), 400);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_cache_admin_requires_unrestricted_api_key() -> anyhow::Result<()> {
+        let api_keys = Arc::new(ApiKeys::new(vec![
+            "admin".parse()?,
+            "acme=tenant_id:acme".parse()?,
+        ]));
+        let cache_admin_handler = cache_admin_handler(Arc::new(MockMetastore::new()), api_keys);
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/admin/caches?format=json")
+            .reply(&cache_admin_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        for method in ["DELETE", "PUT"] {
+            let path = if method == "DELETE" {
+                "/admin/caches?splitIds=split1"
+            } else {
+                "/admin/caches/split_footer?capacity=1000000000"
+            };
+            let resp = warp::test::request()
+                .method(method)
+                .path(path)
+                .reply(&cache_admin_handler)
+                .await;
+            assert_eq!(resp.status(), 401);
+            let resp = warp::test::request()
+                .method(method)
+                .path(path)
+                .header("Authorization", "Bearer acme")
+                .reply(&cache_admin_handler)
+                .await;
+            assert_eq!(resp.status(), 403);
+        }
+        let resp = warp::test::request()
+            .method("DELETE")
+            .path("/admin/caches?splitIds=split1")
+            .header("Authorization", "Bearer admin")
+            .reply(&cache_admin_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use quickwit_indexing::actors::GarbageCollectorCounters;
+use serde::Deserialize;
+use warp::hyper::header::AUTHORIZATION;
+use warp::{Filter, Rejection};
+
+use crate::api_keys::ApiKeys;
+use crate::garbage_collection::GarbageCollectionService;
+use crate::rest::Format;
+use crate::ApiError;
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct GarbageCollectionReportsQueryString {
+    /// The output format requested.
+    #[serde(default)]
+    pub format: Format,
+}
+
+/// Garbage collection reports handler.
+///
+/// Only the unrestricted API keys may read the reports.
+pub fn garbage_collection_handler(
+    gc_service: Arc<GarbageCollectionService>,
+    api_keys: Arc<ApiKeys>,
+) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+    garbage_collection_reports_filter()
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+        .and(warp::any().map(move || gc_service.clone()))
+        .and(warp::any().map(move || api_keys.clone()))
+        .and_then(garbage_collection_reports)
+}
+
+fn garbage_collection_reports_filter(
+) -> impl Filter<Extract = (GarbageCollectionReportsQueryString,), Error = Rejection> + Clone {
+    warp::path!("admin" / "gc")
+        .and(warp::get())
+        .and(serde_qs::warp::query(serde_qs::Config::default()))
+}
+
+async fn garbage_collection_reports(
+    request: GarbageCollectionReportsQueryString,
+    authorization_opt: Option<String>,
+    gc_service: Arc<GarbageCollectionService>,
+    api_keys: Arc<ApiKeys>,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(request.format.make_reply(
+        garbage_collection_reports_endpoint(authorization_opt.as_deref(), &*gc_service, &api_keys)
+            .await,
+    ))
+}
+
+async fn garbage_collection_reports_endpoint(
+    authorization_opt: Option<&str>,
+    gc_service: &GarbageCollectionService,
+    api_keys: &ApiKeys,
+) -> Result<BTreeMap<String, GarbageCollectorCounters>, ApiError> {
+    api_keys.check_unrestricted(authorization_opt)?;
+    Ok(gc_service.run_reports().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use quickwit_common::QuickwitUri;
+    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_indexing::actors::GarbageCollectorParams;
+    use quickwit_metastore::checkpoint::Checkpoint;
+    use quickwit_metastore::{IndexMetadata, MetastoreError, MockMetastore, SplitState};
+    use quickwit_storage::StorageUriResolver;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rest_garbage_collection_reports() -> anyhow::Result<()> {
+        let mut metastore = MockMetastore::new();
+        metastore
+            .expect_index_metadata()
+            .returning(|_index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("ram:///indexes/test-idx"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore.expect_list_splits().returning(
+            |_index_id: &str,
+             _split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| Ok(Vec::new()),
+        );
+        metastore
+            .expect_mark_splits_for_deletion()
+            .returning(|_index_id, _split_ids| Ok(()));
+        let gc_service = GarbageCollectionService::start(
+            &["test-idx".to_string()],
+            GarbageCollectorParams::default(),
+            Arc::new(metastore),
+            StorageUriResolver::for_test(),
+        )
+        .await?;
+        let api_keys = Arc::new(ApiKeys::new(vec![
+            "admin".parse()?,
+            "acme=tenant_id:acme".parse()?,
+        ]));
+        let gc_handler = garbage_collection_handler(Arc::new(gc_service), api_keys);
+        let resp = warp::test::request()
+            .path("/admin/gc?format=json")
+            .reply(&gc_handler)
+            .await;
+        assert_eq!(resp.status(), 401);
+        let resp = warp::test::request()
+            .path("/admin/gc?format=json")
+            .header("Authorization", "Bearer acme")
+            .reply(&gc_handler)
+            .await;
+        assert_eq!(resp.status(), 403);
+        let resp = warp::test::request()
+            .path("/admin/gc?format=json")
+            .header("Authorization", "Bearer admin")
+            .reply(&gc_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_eq!(resp_json["test-idx"]["num_passes"], 1);
+        assert_eq!(
+            resp_json["test-idx"]["last_run_reports"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_garbage_collection_service_starts_pending_garbage_collectors(
+    ) -> anyhow::Result<()> {
+        let mut metastore = MockMetastore::new();
+        let mut num_index_metadata_calls = 0;
+        metastore
+            .expect_index_metadata()
+            .returning(move |index_id: &str| {
+                num_index_metadata_calls += 1;
+                // The index does not exist yet when the service starts.
+                if num_index_metadata_calls == 1 {
+                    return Err(MetastoreError::IndexDoesNotExist {
+                        index_id: index_id.to_string(),
+                    });
+                }
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("ram:///indexes/test-idx"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore.expect_now_timestamp().returning(|| Ok(0));
+        metastore.expect_list_splits().returning(
+            |_index_id: &str,
+             _split_state: SplitState,
+             _time_range: Option<Range<i64>>,
+             _tags: &[String]| Ok(Vec::new()),
+        );
+        metastore
+            .expect_mark_splits_for_deletion()
+            .returning(|_index_id, _split_ids| Ok(()));
+        let gc_service = GarbageCollectionService::start(
+            &["test-idx".to_string()],
+            GarbageCollectorParams::default(),
+            Arc::new(metastore),
+            StorageUriResolver::for_test(),
+        )
+        .await?;
+        let pending_index_ids = gc_service.pending_index_ids().await;
+        assert_eq!(pending_index_ids.len(), 1);
+        assert!(pending_index_ids["test-idx"].contains("does not exist"));
+
+        let run_reports = gc_service.run_reports().await;
+        assert!(run_reports.contains_key("test-idx"));
+        assert!(gc_service.pending_index_ids().await.is_empty());
+        Ok(())
+    }
+}
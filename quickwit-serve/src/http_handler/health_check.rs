@@ -17,15 +17,24 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
+use std::convert::Infallible;
 use std::fmt;
+use std::sync::Arc;
 
+use quickwit_metastore::Metastore;
 use serde::Serialize;
 use serde_json::json;
 use warp::http::header::{HeaderMap, HeaderValue};
+use warp::hyper::header::AUTHORIZATION;
 use warp::hyper::StatusCode;
 use warp::reply::with_status;
 use warp::{Filter, Rejection};
 
+use crate::api_keys::ApiKeys;
+use crate::rest::Format;
+use crate::ApiError;
+
 /// A service status.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
 pub enum ServiceStatus {
@@ -52,6 +61,84 @@ pub fn liveness_check_handler() -> impl Filter<Extract = impl warp::Reply, Error
         .with(warp::reply::with::headers(headers))
 }
 
+/// Readiness of one of the indexes the node is configured with.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct IndexReadiness {
+    /// True if the metadata of the index can be read from the metastore.
+    pub ready: bool,
+    /// Why the index is not ready.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Index readiness check handler.
+///
+/// Reports the readiness of each index of `index_ids`. The node serves the ready indexes even
+/// when some others are not, e.g. because they are still being created. The errors may reveal
+/// the configuration of the indexes, so only the unrestricted API keys may read the report.
+pub fn index_readiness_check_handler(
+    metastore: Arc<dyn Metastore>,
+    index_ids: Vec<String>,
+    api_keys: Arc<ApiKeys>,
+) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+    let index_ids = Arc::new(index_ids);
+    warp::path!("health" / "indexes")
+        .and(warp::get())
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+        .and(warp::any().map(move || metastore.clone()))
+        .and(warp::any().map(move || index_ids.clone()))
+        .and(warp::any().map(move || api_keys.clone()))
+        .and_then(index_readiness_check)
+}
+
+async fn index_readiness_check(
+    authorization_opt: Option<String>,
+    metastore: Arc<dyn Metastore>,
+    index_ids: Arc<Vec<String>>,
+    api_keys: Arc<ApiKeys>,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(Format::Json.make_reply(
+        index_readiness_check_endpoint(
+            authorization_opt.as_deref(),
+            &*metastore,
+            &index_ids,
+            &api_keys,
+        )
+        .await,
+    ))
+}
+
+async fn index_readiness_check_endpoint(
+    authorization_opt: Option<&str>,
+    metastore: &dyn Metastore,
+    index_ids: &[String],
+    api_keys: &ApiKeys,
+) -> Result<BTreeMap<String, IndexReadiness>, ApiError> {
+    api_keys.check_unrestricted(authorization_opt)?;
+    Ok(index_readinesses(metastore, index_ids).await)
+}
+
+async fn index_readinesses(
+    metastore: &dyn Metastore,
+    index_ids: &[String],
+) -> BTreeMap<String, IndexReadiness> {
+    let mut index_readinesses = BTreeMap::new();
+    for index_id in index_ids {
+        let index_readiness = match metastore.index_metadata(index_id).await {
+            Ok(_) => IndexReadiness {
+                ready: true,
+                error: None,
+            },
+            Err(error) => IndexReadiness {
+                ready: false,
+                error: Some(error.to_string()),
+            },
+        };
+        index_readinesses.insert(index_id.clone(), index_readiness);
+    }
+    index_readinesses
+}
+
 /// Make an HTTP response based on the given service status.
 pub fn make_reply(ok: bool, service_status: ServiceStatus) -> impl warp::Reply {
     let mut status_code = if ok {
@@ -78,6 +165,86 @@ pub fn live_predicate(service_status: ServiceStatus) -> bool {
     matches!(service_status, ServiceStatus::Alive)
 }
 
+#[tokio::test]
+async fn test_rest_index_readiness_check() -> anyhow::Result<()> {
+    use quickwit_common::QuickwitUri;
+    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_metastore::checkpoint::Checkpoint;
+    use quickwit_metastore::{IndexMetadata, MetastoreError, MockMetastore};
+
+    let mut metastore = MockMetastore::new();
+    metastore
+        .expect_index_metadata()
+        .returning(|index_id: &str| {
+            if index_id != "existing-idx" {
+                return Err(MetastoreError::IndexDoesNotExist {
+                    index_id: index_id.to_string(),
+                });
+            }
+            Ok(IndexMetadata {
+                index_id: index_id.to_string(),
+                index_uri: QuickwitUri::from_well_formed("ram:///indexes/existing-idx"),
+                index_config: Arc::new(WikipediaIndexConfig::new()),
+                checkpoint: Checkpoint::default(),
+                index_config_history: Vec::new(),
+                mirror_index_uri: None,
+                read_only: false,
+                description: None,
+                labels: Default::default(),
+                create_timestamp: 0,
+            })
+        });
+    let index_readiness_check_handler = index_readiness_check_handler(
+        Arc::new(metastore),
+        vec!["existing-idx".to_string(), "missing-idx".to_string()],
+        Arc::new(ApiKeys::default()),
+    );
+    let resp = warp::test::request()
+        .path("/health/indexes")
+        .reply(&index_readiness_check_handler)
+        .await;
+    assert_eq!(resp.status(), 200);
+    let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+    assert_eq!(
+        resp_json,
+        json!({
+            "existing-idx": {"ready": true},
+            "missing-idx": {"ready": false, "error": "Index `missing-idx` does not exist."},
+        })
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rest_index_readiness_check_requires_unrestricted_api_key() -> anyhow::Result<()> {
+    use quickwit_metastore::MockMetastore;
+
+    let api_keys = Arc::new(ApiKeys::new(vec![
+        "admin".parse()?,
+        "acme=tenant_id:acme".parse()?,
+    ]));
+    let index_readiness_check_handler =
+        index_readiness_check_handler(Arc::new(MockMetastore::new()), Vec::new(), api_keys);
+    let resp = warp::test::request()
+        .path("/health/indexes")
+        .reply(&index_readiness_check_handler)
+        .await;
+    assert_eq!(resp.status(), 401);
+    let resp = warp::test::request()
+        .path("/health/indexes")
+        .header("Authorization", "Bearer acme")
+        .reply(&index_readiness_check_handler)
+        .await;
+    assert_eq!(resp.status(), 403);
+    let resp = warp::test::request()
+        .path("/health/indexes")
+        .header("Authorization", "Bearer admin")
+        .reply(&index_readiness_check_handler)
+        .await;
+    assert_eq!(resp.status(), 200);
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_rest_search_api_health_check_livez() {
     let rest_search_api_filter = liveness_check_handler();
@@ -0,0 +1,394 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use quickwit_metastore::checkpoint::Checkpoint;
+use quickwit_metastore::{
+    time_to_searchable_percentile, Metastore, SplitMetadataAndFooterOffsets, SplitState,
+};
+use serde::{Deserialize, Serialize};
+use warp::hyper::header::AUTHORIZATION;
+use warp::{Filter, Rejection};
+
+use crate::api_keys::ApiKeys;
+use crate::rest::Format;
+use crate::ApiError;
+
+/// This struct represents the QueryString passed to
+/// the rest API.
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStatsQueryString {
+    /// The output format requested.
+    #[serde(default)]
+    pub format: Format,
+}
+
+/// How far behind its source the indexing of a partition is.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PartitionStats {
+    pub partition_id: String,
+    /// Position up to which the partition has been indexed and published.
+    pub position: String,
+    /// Maximum event timestamp of the published messages of the partition, if its source
+    /// records one.
+    pub watermark: Option<i64>,
+    /// Number of seconds elapsed since the watermark.
+    pub ingest_lag_secs: Option<i64>,
+}
+
+/// Time it took for the documents of the published splits to become searchable, that is
+/// between their reception by the indexing pipeline and the publication of their split.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeToSearchableStats {
+    /// Number of documents the times to searchable are computed on.
+    pub num_docs: u64,
+    pub p50_secs: i64,
+    pub p99_secs: i64,
+}
+
+/// Statistics on the published splits of an index, and on its ingest lag.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStats {
+    pub index_id: String,
+    /// Free-form description of the index.
+    pub description: Option<String>,
+    /// Free-form labels of the index, such as its owning team.
+    pub labels: BTreeMap<String, String>,
+    pub num_published_splits: usize,
+    pub num_published_docs: usize,
+    pub published_splits_size_in_bytes: u64,
+    /// Largest ingest lag of the partitions, in seconds: this is how far behind its
+    /// source the indexing is. Not set if the source does not record watermarks.
+    pub ingest_lag_secs: Option<i64>,
+    /// Not set if none of the published splits records when its documents were received.
+    /// Splits produced by merges do not.
+    pub time_to_searchable: Option<TimeToSearchableStats>,
+    pub partitions: Vec<PartitionStats>,
+}
+
+fn build_time_to_searchable_stats(
+    published_splits: &[SplitMetadataAndFooterOffsets],
+) -> Option<TimeToSearchableStats> {
+    // The publish timestamp of a split is its last update timestamp.
+    let times_to_searchable: Vec<(i64, u64)> = published_splits
+        .iter()
+        .flat_map(|split| {
+            let split_metadata = &split.split_metadata;
+            split_metadata
+                .ingest_timestamps
+                .as_ref()
+                .map(|ingest_timestamps| {
+                    ingest_timestamps.times_to_searchable(split_metadata.update_timestamp)
+                })
+                .unwrap_or_default()
+        })
+        .collect();
+    Some(TimeToSearchableStats {
+        num_docs: times_to_searchable
+            .iter()
+            .map(|(_, num_docs)| num_docs)
+            .sum(),
+        p50_secs: time_to_searchable_percentile(&times_to_searchable, 50.0)?,
+        p99_secs: time_to_searchable_percentile(&times_to_searchable, 99.0)?,
+    })
+}
+
+fn build_index_stats(
+    index_id: &str,
+    published_splits: &[SplitMetadataAndFooterOffsets],
+    checkpoint: &Checkpoint,
+    now_timestamp: i64,
+) -> IndexStats {
+    let partitions: Vec<PartitionStats> = checkpoint
+        .iter()
+        .map(|(partition_id, position)| {
+            let watermark = checkpoint.watermark_for_partition(&partition_id);
+            PartitionStats {
+                partition_id: partition_id.0.to_string(),
+                position: position.as_str().to_string(),
+                watermark,
+                ingest_lag_secs: watermark
+                    .map(|watermark| now_timestamp.saturating_sub(watermark).max(0)),
+            }
+        })
+        .collect();
+    IndexStats {
+        index_id: index_id.to_string(),
+        num_published_splits: published_splits.len(),
+        num_published_docs: published_splits
+            .iter()
+            .map(|split| split.split_metadata.num_records)
+            .sum(),
+        published_splits_size_in_bytes: published_splits
+            .iter()
+            .map(|split| split.split_metadata.size_in_bytes)
+            .sum(),
+        ingest_lag_secs: partitions
+            .iter()
+            .filter_map(|partition| partition.ingest_lag_secs)
+            .max(),
+        time_to_searchable: build_time_to_searchable_stats(published_splits),
+        partitions,
+        ..Default::default()
+    }
+}
+
+/// Index stats handler.
+///
+/// The stats cover all the documents of the index regardless of the filters of the API keys, so
+/// only the unrestricted keys may read them.
+pub fn index_stats_handler(
+    metastore: Arc<dyn Metastore>,
+    api_keys: Arc<ApiKeys>,
+) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+    index_stats_filter()
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+        .and(warp::any().map(move || metastore.clone()))
+        .and(warp::any().map(move || api_keys.clone()))
+        .and_then(index_stats)
+}
+
+fn index_stats_filter(
+) -> impl Filter<Extract = (String, IndexStatsQueryString), Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / String / "stats")
+        .and(warp::get())
+        .and(serde_qs::warp::query(serde_qs::Config::default()))
+}
+
+async fn index_stats(
+    index_id: String,
+    request: IndexStatsQueryString,
+    authorization_opt: Option<String>,
+    metastore: Arc<dyn Metastore>,
+    api_keys: Arc<ApiKeys>,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(request.format.make_reply(
+        index_stats_endpoint(
+            &index_id,
+            authorization_opt.as_deref(),
+            &*metastore,
+            &api_keys,
+        )
+        .await,
+    ))
+}
+
+async fn index_stats_endpoint(
+    index_id: &str,
+    authorization_opt: Option<&str>,
+    metastore: &dyn Metastore,
+    api_keys: &ApiKeys,
+) -> Result<IndexStats, ApiError> {
+    api_keys.check_unrestricted(authorization_opt)?;
+    let index_metadata = metastore.index_metadata(index_id).await?;
+    let published_splits = metastore
+        .list_splits(index_id, SplitState::Published, None, &[])
+        .await?;
+    let now_timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default();
+    Ok(IndexStats {
+        description: index_metadata.description,
+        labels: index_metadata.labels,
+        ..build_index_stats(
+            index_id,
+            &published_splits,
+            &index_metadata.checkpoint,
+            now_timestamp,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_common::QuickwitUri;
+    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_metastore::checkpoint::{CheckpointDelta, PartitionId, Position};
+    use quickwit_metastore::{
+        IndexMetadata, IngestTimestamps, MetastoreError, MockMetastore, SplitMetadata,
+    };
+
+    use super::*;
+
+    fn published_split(split_id: &str) -> SplitMetadataAndFooterOffsets {
+        SplitMetadataAndFooterOffsets {
+            split_metadata: SplitMetadata {
+                split_id: split_id.to_string(),
+                split_state: SplitState::Published,
+                num_records: 10,
+                size_in_bytes: 100,
+                ..Default::default()
+            },
+            footer_offsets: 0..1,
+        }
+    }
+
+    fn checkpoint_with_watermarks() -> Checkpoint {
+        let mut checkpoint = Checkpoint::default();
+        let mut delta = CheckpointDelta::from_partition_delta(
+            PartitionId::from(0u64),
+            Position::Beginning,
+            Position::from(12u64),
+        );
+        delta.record_partition_watermark(PartitionId::from(0u64), 1_600_000_540);
+        delta
+            .record_partition_delta(
+                PartitionId::from(1u64),
+                Position::Beginning,
+                Position::from(34u64),
+            )
+            .unwrap();
+        delta.record_partition_watermark(PartitionId::from(1u64), 1_600_000_000);
+        checkpoint.try_apply_delta(delta).unwrap();
+        checkpoint
+    }
+
+    #[test]
+    fn test_build_index_stats() {
+        let splits = vec![published_split("split1"), published_split("split2")];
+        let stats = build_index_stats(
+            "test-idx",
+            &splits,
+            &checkpoint_with_watermarks(),
+            1_600_000_600,
+        );
+        assert_eq!(stats.num_published_splits, 2);
+        assert_eq!(stats.num_published_docs, 20);
+        assert_eq!(stats.published_splits_size_in_bytes, 200);
+        assert_eq!(stats.ingest_lag_secs, Some(600));
+        assert_eq!(stats.partitions.len(), 2);
+        assert_eq!(stats.partitions[0].watermark, Some(1_600_000_540));
+        assert_eq!(stats.partitions[0].ingest_lag_secs, Some(60));
+
+        let stats = build_index_stats("test-idx", &splits, &Checkpoint::default(), 1_600_000_600);
+        assert_eq!(stats.ingest_lag_secs, None);
+        assert_eq!(stats.time_to_searchable, None);
+        assert!(stats.partitions.is_empty());
+    }
+
+    #[test]
+    fn test_build_index_stats_time_to_searchable() {
+        let mut split1 = published_split("split1");
+        split1.split_metadata.update_timestamp = 1_600_000_010;
+        split1.split_metadata.ingest_timestamps = IngestTimestamps::from_num_docs_per_timestamp(
+            &vec![(1_600_000_000, 90)].into_iter().collect(),
+        );
+        let mut split2 = published_split("split2");
+        split2.split_metadata.update_timestamp = 1_600_000_100;
+        split2.split_metadata.ingest_timestamps = IngestTimestamps::from_num_docs_per_timestamp(
+            &vec![(1_600_000_000, 10)].into_iter().collect(),
+        );
+        let splits = vec![split1, split2, published_split("merged-split")];
+        let stats = build_index_stats("test-idx", &splits, &Checkpoint::default(), 1_600_000_600);
+        assert_eq!(
+            stats.time_to_searchable,
+            Some(TimeToSearchableStats {
+                num_docs: 100,
+                p50_secs: 10,
+                p99_secs: 100,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rest_index_stats() -> anyhow::Result<()> {
+        let mut metastore = MockMetastore::new();
+        metastore
+            .expect_index_metadata()
+            .returning(|index_id: &str| {
+                if index_id != "test-idx" {
+                    return Err(MetastoreError::IndexDoesNotExist {
+                        index_id: index_id.to_string(),
+                    });
+                }
+                Ok(IndexMetadata {
+                    index_id: "test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: checkpoint_with_watermarks(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: Some("Web server logs".to_string()),
+                    labels: vec![("team".to_string(), "search".to_string())]
+                        .into_iter()
+                        .collect(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore
+            .expect_list_splits()
+            .returning(|_, _, _, _| Ok(vec![published_split("split1")]));
+        let index_stats_handler =
+            index_stats_handler(Arc::new(metastore), Arc::new(ApiKeys::default()));
+        let resp = warp::test::request()
+            .path("/api/v1/test-idx/stats?format=json")
+            .reply(&index_stats_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_eq!(resp_json["numPublishedSplits"], 1);
+        assert_eq!(resp_json["description"], "Web server logs");
+        assert_eq!(resp_json["labels"]["team"], "search");
+        assert_eq!(
+            resp_json["partitions"][1]["partitionId"],
+            "00000000000000000001"
+        );
+        assert_eq!(resp_json["partitions"][1]["watermark"], 1_600_000_000);
+        assert!(resp_json["ingestLagSecs"].as_i64().unwrap() > 0);
+
+        let resp = warp::test::request()
+            .path("/api/v1/other-idx/stats")
+            .reply(&index_stats_handler)
+            .await;
+        assert_eq!(resp.status(), 404);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_index_stats_requires_unrestricted_api_key() -> anyhow::Result<()> {
+        let api_keys = Arc::new(ApiKeys::new(vec![
+            "admin".parse()?,
+            "acme=tenant_id:acme".parse()?,
+        ]));
+        let index_stats_handler = index_stats_handler(Arc::new(MockMetastore::new()), api_keys);
+        let resp = warp::test::request()
+            .path("/api/v1/test-idx/stats")
+            .reply(&index_stats_handler)
+            .await;
+        assert_eq!(resp.status(), 401);
+        let resp = warp::test::request()
+            .path("/api/v1/test-idx/stats")
+            .header("Authorization", "Bearer acme")
+            .reply(&index_stats_handler)
+            .await;
+        assert_eq!(resp.status(), 403);
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use quickwit_metastore::{Metastore, SplitMetadataAndFooterOffsets, SplitState};
+use serde::{Deserialize, Serialize};
+use warp::hyper::header::AUTHORIZATION;
+use warp::{Filter, Rejection};
+
+use crate::api_keys::ApiKeys;
+use crate::rest::Format;
+use crate::ApiError;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Maximum number of days on a timeline, i.e. about ten years.
+const MAX_TIMELINE_NUM_DAYS: usize = 3_660;
+
+/// This struct represents the QueryString passed to
+/// the rest API.
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitTimelineQueryString {
+    /// If set, restricts the timeline to the splits with documents with a
+    /// `timestamp >= start_timestamp`.
+    pub start_timestamp: Option<i64>,
+    /// If set, restricts the timeline to the splits with documents with a
+    /// `timestamp < end_timestamp`.
+    pub end_timestamp: Option<i64>,
+    /// The output format requested.
+    #[serde(default)]
+    pub format: Format,
+}
+
+/// A split, as displayed on the timeline.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineSplit {
+    pub split_id: String,
+    pub split_state: SplitState,
+    /// Timestamp of the oldest document of the split, if the index has a timestamp field.
+    pub start_timestamp: Option<i64>,
+    /// Timestamp of the most recent document of the split, if the index has a timestamp field.
+    pub end_timestamp: Option<i64>,
+    pub num_docs: usize,
+    pub size_in_bytes: u64,
+}
+
+impl From<SplitMetadataAndFooterOffsets> for TimelineSplit {
+    fn from(split: SplitMetadataAndFooterOffsets) -> Self {
+        let split_metadata = split.split_metadata;
+        TimelineSplit {
+            split_id: split_metadata.split_id,
+            split_state: split_metadata.split_state,
+            start_timestamp: split_metadata
+                .time_range
+                .as_ref()
+                .map(|time_range| *time_range.start()),
+            end_timestamp: split_metadata
+                .time_range
+                .as_ref()
+                .map(|time_range| *time_range.end()),
+            num_docs: split_metadata.num_records,
+            size_in_bytes: split_metadata.size_in_bytes,
+        }
+    }
+}
+
+/// The splits whose time range overlaps a given (UTC) day.
+///
+/// A split spanning several days appears in each of them.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitTimelineDay {
+    /// Timestamp of the start of the day.
+    pub day_start_timestamp: i64,
+    pub num_splits: usize,
+    pub num_docs: usize,
+    pub size_in_bytes: u64,
+    pub splits: Vec<TimelineSplit>,
+}
+
+/// The splits of an index, aggregated per day of their time range.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitTimeline {
+    /// Days with at least one split, oldest first.
+    pub days: Vec<SplitTimelineDay>,
+    /// Splits without a time range, which cannot be placed on the timeline.
+    pub splits_without_time_range: Vec<TimelineSplit>,
+}
+
+fn day_start_timestamp(timestamp: i64) -> i64 {
+    timestamp
+        .div_euclid(SECONDS_PER_DAY)
+        .saturating_mul(SECONDS_PER_DAY)
+}
+
+fn too_many_days_error() -> ApiError {
+    ApiError::InvalidArgument(format!(
+        "The timeline spans more than {} days. Please narrow it down with `startTimestamp` and \
+         `endTimestamp`",
+        MAX_TIMELINE_NUM_DAYS
+    ))
+}
+
+/// Places the splits overlapping `[start_timestamp, end_timestamp)` on a timeline.
+///
+/// Days outside of this window are left out of the timeline, which cannot span more than
+/// `MAX_TIMELINE_NUM_DAYS` days.
+fn build_split_timeline(
+    splits: Vec<SplitMetadataAndFooterOffsets>,
+    start_timestamp_opt: Option<i64>,
+    end_timestamp_opt: Option<i64>,
+) -> Result<SplitTimeline, ApiError> {
+    let window_start = start_timestamp_opt.unwrap_or(i64::MIN);
+    let window_end = end_timestamp_opt.unwrap_or(i64::MAX);
+    let mut days: BTreeMap<i64, SplitTimelineDay> = BTreeMap::new();
+    let mut splits_without_time_range = Vec::new();
+    for split in splits {
+        let time_range = if let Some(time_range) = split.split_metadata.time_range.clone() {
+            time_range
+        } else {
+            splits_without_time_range.push(TimelineSplit::from(split));
+            continue;
+        };
+        if *time_range.end() < window_start || *time_range.start() >= window_end {
+            continue;
+        }
+        let first_day = day_start_timestamp((*time_range.start()).max(window_start));
+        let last_day = day_start_timestamp((*time_range.end()).min(window_end.saturating_sub(1)));
+        let num_days = (last_day as i128 - first_day as i128) / SECONDS_PER_DAY as i128 + 1;
+        if num_days > MAX_TIMELINE_NUM_DAYS as i128 {
+            return Err(too_many_days_error());
+        }
+        let timeline_split = TimelineSplit::from(split);
+        for day in (first_day..=last_day).step_by(SECONDS_PER_DAY as usize) {
+            let timeline_day = days.entry(day).or_insert_with(|| SplitTimelineDay {
+                day_start_timestamp: day,
+                ..Default::default()
+            });
+            timeline_day.num_splits += 1;
+            timeline_day.num_docs += timeline_split.num_docs;
+            timeline_day.size_in_bytes += timeline_split.size_in_bytes;
+            timeline_day.splits.push(timeline_split.clone());
+        }
+        if days.len() > MAX_TIMELINE_NUM_DAYS {
+            return Err(too_many_days_error());
+        }
+    }
+    Ok(SplitTimeline {
+        days: days.into_iter().map(|(_, day)| day).collect(),
+        splits_without_time_range,
+    })
+}
+
+/// Split timeline handler.
+///
+/// The timeline lists the splits of the index regardless of the filters of the API keys, so
+/// only the unrestricted keys may read it.
+pub fn split_timeline_handler(
+    metastore: Arc<dyn Metastore>,
+    api_keys: Arc<ApiKeys>,
+) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+    split_timeline_filter()
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+        .and(warp::any().map(move || metastore.clone()))
+        .and(warp::any().map(move || api_keys.clone()))
+        .and_then(split_timeline)
+}
+
+fn split_timeline_filter(
+) -> impl Filter<Extract = (String, SplitTimelineQueryString), Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / String / "splits" / "timeline")
+        .and(warp::get())
+        .and(serde_qs::warp::query(serde_qs::Config::default()))
+}
+
+async fn split_timeline(
+    index_id: String,
+    request: SplitTimelineQueryString,
+    authorization_opt: Option<String>,
+    metastore: Arc<dyn Metastore>,
+    api_keys: Arc<ApiKeys>,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(request.format.make_reply(
+        split_timeline_endpoint(
+            &index_id,
+            &request,
+            authorization_opt.as_deref(),
+            &*metastore,
+            &api_keys,
+        )
+        .await,
+    ))
+}
+
+async fn split_timeline_endpoint(
+    index_id: &str,
+    request: &SplitTimelineQueryString,
+    authorization_opt: Option<&str>,
+    metastore: &dyn Metastore,
+    api_keys: &ApiKeys,
+) -> Result<SplitTimeline, ApiError> {
+    api_keys.check_unrestricted(authorization_opt)?;
+    let splits = metastore.list_all_splits(index_id).await?;
+    build_split_timeline(splits, request.start_timestamp, request.end_timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::RangeInclusive;
+
+    use quickwit_metastore::{MetastoreError, MockMetastore, SplitMetadata};
+
+    use super::*;
+
+    fn split(
+        split_id: &str,
+        split_state: SplitState,
+        time_range: Option<RangeInclusive<i64>>,
+    ) -> SplitMetadataAndFooterOffsets {
+        SplitMetadataAndFooterOffsets {
+            split_metadata: SplitMetadata {
+                split_id: split_id.to_string(),
+                split_state,
+                num_records: 10,
+                size_in_bytes: 100,
+                time_range,
+                ..Default::default()
+            },
+            footer_offsets: 0..1,
+        }
+    }
+
+    fn day_split_ids(timeline: &SplitTimeline) -> Vec<(i64, Vec<&str>)> {
+        timeline
+            .days
+            .iter()
+            .map(|day| {
+                let split_ids = day
+                    .splits
+                    .iter()
+                    .map(|split| split.split_id.as_str())
+                    .collect();
+                (day.day_start_timestamp / SECONDS_PER_DAY, split_ids)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_build_split_timeline() {
+        let splits = vec![
+            split("split1", SplitState::Published, Some(3_600..=7_200)),
+            split(
+                "split2",
+                SplitState::Published,
+                Some(SECONDS_PER_DAY - 1..=2 * SECONDS_PER_DAY),
+            ),
+            split(
+                "split3",
+                SplitState::ScheduledForDeletion,
+                Some(5 * SECONDS_PER_DAY..=5 * SECONDS_PER_DAY),
+            ),
+            split("split4", SplitState::Staged, None),
+        ];
+        let timeline = build_split_timeline(splits.clone(), None, None).unwrap();
+        assert_eq!(
+            day_split_ids(&timeline),
+            vec![
+                (0, vec!["split1", "split2"]),
+                (1, vec!["split2"]),
+                (2, vec!["split2"]),
+                (5, vec!["split3"]),
+            ]
+        );
+        assert_eq!(timeline.days[0].num_splits, 2);
+        assert_eq!(timeline.days[0].num_docs, 20);
+        assert_eq!(timeline.days[0].size_in_bytes, 200);
+        assert_eq!(timeline.days[3].splits[0].start_timestamp, Some(432_000));
+        assert_eq!(
+            timeline.days[3].splits[0].split_state,
+            SplitState::ScheduledForDeletion
+        );
+        assert_eq!(timeline.splits_without_time_range.len(), 1);
+        assert_eq!(timeline.splits_without_time_range[0].split_id, "split4");
+
+        let timeline =
+            build_split_timeline(splits, Some(SECONDS_PER_DAY), Some(2 * SECONDS_PER_DAY)).unwrap();
+        assert_eq!(day_split_ids(&timeline), vec![(1, vec!["split2"])]);
+    }
+
+    #[test]
+    fn test_build_split_timeline_bounds_the_number_of_days() {
+        let splits = vec![split(
+            "split1",
+            SplitState::Published,
+            Some(i64::MIN..=i64::MAX),
+        )];
+        assert!(matches!(
+            build_split_timeline(splits.clone(), None, None),
+            Err(ApiError::InvalidArgument(_))
+        ));
+        let timeline =
+            build_split_timeline(splits.clone(), Some(i64::MAX - 1), Some(i64::MAX)).unwrap();
+        assert_eq!(timeline.days.len(), 1);
+        let timeline = build_split_timeline(splits, Some(i64::MIN), Some(i64::MIN + 1)).unwrap();
+        assert_eq!(timeline.days.len(), 1);
+
+        let splits = (0..=MAX_TIMELINE_NUM_DAYS as i64)
+            .map(|day| {
+                let timestamp = day * SECONDS_PER_DAY;
+                split("split", SplitState::Published, Some(timestamp..=timestamp))
+            })
+            .collect();
+        assert!(matches!(
+            build_split_timeline(splits, None, None),
+            Err(ApiError::InvalidArgument(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rest_split_timeline() -> anyhow::Result<()> {
+        let mut metastore = MockMetastore::new();
+        metastore
+            .expect_list_all_splits()
+            .returning(|index_id: &str| {
+                if index_id != "test-idx" {
+                    return Err(MetastoreError::IndexDoesNotExist {
+                        index_id: index_id.to_string(),
+                    });
+                }
+                Ok(vec![split(
+                    "split1",
+                    SplitState::Published,
+                    Some(3_600..=7_200),
+                )])
+            });
+        let split_timeline_handler =
+            split_timeline_handler(Arc::new(metastore), Arc::new(ApiKeys::default()));
+        let resp = warp::test::request()
+            .path("/api/v1/test-idx/splits/timeline?startTimestamp=0&format=json")
+            .reply(&split_timeline_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_eq!(resp_json["days"][0]["dayStartTimestamp"], 0);
+        assert_eq!(resp_json["days"][0]["numSplits"], 1);
+        assert_eq!(resp_json["days"][0]["splits"][0]["splitId"], "split1");
+        assert_eq!(resp_json["days"][0]["splits"][0]["splitState"], "Published");
+
+        let resp = warp::test::request()
+            .path("/api/v1/other-idx/splits/timeline")
+            .reply(&split_timeline_handler)
+            .await;
+        assert_eq!(resp.status(), 404);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_split_timeline_requires_unrestricted_api_key() -> anyhow::Result<()> {
+        let mut metastore = MockMetastore::new();
+        metastore
+            .expect_list_all_splits()
+            .returning(|_index_id: &str| Ok(Vec::new()));
+        let api_keys = Arc::new(ApiKeys::new(vec![
+            "admin".parse()?,
+            "acme=tenant_id:acme".parse()?,
+        ]));
+        let split_timeline_handler = split_timeline_handler(Arc::new(metastore), api_keys);
+        let resp = warp::test::request()
+            .path("/api/v1/test-idx/splits/timeline")
+            .reply(&split_timeline_handler)
+            .await;
+        assert_eq!(resp.status(), 401);
+        let resp = warp::test::request()
+            .path("/api/v1/test-idx/splits/timeline")
+            .header("Authorization", "Bearer acme")
+            .reply(&split_timeline_handler)
+            .await;
+        assert_eq!(resp.status(), 403);
+        let resp = warp::test::request()
+            .path("/api/v1/test-idx/splits/timeline")
+            .header("Authorization", "Bearer admin")
+            .reply(&split_timeline_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use quickwit_search::StandbyMode;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use warp::hyper::header::AUTHORIZATION;
+use warp::{Filter, Rejection};
+
+use crate::api_keys::ApiKeys;
+use crate::rest::Format;
+use crate::ApiError;
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct PromoteQueryString {
+    /// The output format requested.
+    #[serde(default)]
+    pub format: Format,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PromoteResponse {
+    /// False if the node was already an active searcher.
+    pub promoted: bool,
+}
+
+/// Handler promoting a standby searcher node to an active one.
+///
+/// The roots of the cluster start assigning leaf requests to the node once their next
+/// health check notices the promotion. Only the unrestricted API keys may promote a node.
+pub fn promote_handler(
+    standby_mode: StandbyMode,
+    api_keys: Arc<ApiKeys>,
+) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+    promote_filter()
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+        .and(warp::any().map(move || standby_mode.clone()))
+        .and(warp::any().map(move || api_keys.clone()))
+        .and_then(promote)
+}
+
+fn promote_filter() -> impl Filter<Extract = (PromoteQueryString,), Error = Rejection> + Clone {
+    warp::path!("admin" / "promote")
+        .and(warp::post())
+        .and(serde_qs::warp::query(serde_qs::Config::default()))
+}
+
+async fn promote(
+    request: PromoteQueryString,
+    authorization_opt: Option<String>,
+    standby_mode: StandbyMode,
+    api_keys: Arc<ApiKeys>,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(request.format.make_reply(promote_endpoint(
+        authorization_opt.as_deref(),
+        &standby_mode,
+        &api_keys,
+    )))
+}
+
+fn promote_endpoint(
+    authorization_opt: Option<&str>,
+    standby_mode: &StandbyMode,
+    api_keys: &ApiKeys,
+) -> Result<PromoteResponse, ApiError> {
+    api_keys.check_unrestricted(authorization_opt)?;
+    let promoted = standby_mode.promote();
+    if promoted {
+        info!("Standby searcher promoted to active searcher.");
+    }
+    Ok(PromoteResponse { promoted })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rest_promote() -> anyhow::Result<()> {
+        let standby_mode = StandbyMode::new(true);
+        let promote_handler = promote_handler(standby_mode.clone(), Arc::new(ApiKeys::default()));
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/admin/promote?format=json")
+            .reply(&promote_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_eq!(resp_json["promoted"], true);
+        assert!(!standby_mode.is_standby());
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/admin/promote?format=json")
+            .reply(&promote_handler)
+            .await;
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_eq!(resp_json["promoted"], false);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_promote_requires_unrestricted_api_key() -> anyhow::Result<()> {
+        let standby_mode = StandbyMode::new(true);
+        let api_keys = Arc::new(ApiKeys::new(vec![
+            "admin".parse()?,
+            "acme=tenant_id:acme".parse()?,
+        ]));
+        let promote_handler = promote_handler(standby_mode.clone(), api_keys);
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/admin/promote?format=json")
+            .reply(&promote_handler)
+            .await;
+        assert_eq!(resp.status(), 401);
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/admin/promote?format=json")
+            .header("Authorization", "Bearer acme")
+            .reply(&promote_handler)
+            .await;
+        assert_eq!(resp.status(), 403);
+        assert!(standby_mode.is_standby());
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/admin/promote?format=json")
+            .header("Authorization", "Bearer admin")
+            .reply(&promote_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        assert!(!standby_mode.is_standby());
+        Ok(())
+    }
+}
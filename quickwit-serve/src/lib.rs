@@ -17,43 +17,68 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+mod api_keys;
 mod args;
+mod audit_log;
+mod click_house_sinks;
 mod counters;
 mod error;
+mod garbage_collection;
 mod grpc;
 mod grpc_adapter;
 mod http_handler;
 mod quickwit_cache;
+mod quota;
 mod rest;
 
 use std::io::Write;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use quickwit_actors::Universe;
 use quickwit_cache::QuickwitCache;
 use quickwit_cluster::cluster::{read_or_create_host_key, Cluster};
 use quickwit_cluster::service::ClusterServiceImpl;
-use quickwit_metastore::MetastoreUriResolver;
+use quickwit_indexing::actors::{GarbageCollectorParams, SplitVerifier, SplitVerifierParams};
+use quickwit_metastore::{Metastore, MetastoreUriResolver, NegativeCachingMetastore};
 use quickwit_search::{
-    http_addr_to_grpc_addr, http_addr_to_swim_addr, ClusterClient, SearchClientPool,
-    SearchServiceImpl,
+    configure_search_thread_pool, configure_whole_split_download, create_search_service_client,
+    enable_fast_field_pinning, http_addr_to_grpc_addr, http_addr_to_swim_addr, ClusterClient,
+    SearchClientPool, SearchServiceImpl, StandbyMode,
 };
 use quickwit_storage::{
-    LocalFileStorageFactory, RegionProvider, S3CompatibleObjectStorageFactory, StorageUriResolver,
-    StorageWithCacheFactory,
+    AzureBlobStorageFactory, LocalFileStorageFactory, RegionProvider,
+    S3CompatibleObjectStorageFactory, S3HttpConfig, StorageTimeoutPolicy, StorageUriResolver,
+    StorageWithCacheFactory, StorageWithTimeoutFactory,
 };
 use quickwit_telemetry::payload::{ServeEvent, TelemetryEvent};
 use termcolor::{self, Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
-use tracing::debug;
+use tracing::{debug, info};
 
+pub use crate::api_keys::{ApiKey, ApiKeyError, ApiKeySplitLimit, ApiKeys};
 pub use crate::args::ServeArgs;
+use crate::audit_log::AuditLog;
+pub use crate::audit_log::AuditLogConfig;
+pub use crate::click_house_sinks::{ClickHouseSinkConfig, ClickHouseSinks};
 pub use crate::counters::COUNTERS;
 pub use crate::error::ApiError;
+use crate::garbage_collection::GarbageCollectionService;
 use crate::grpc::start_grpc_service;
+pub use crate::grpc::start_realtime_grpc_service;
 use crate::grpc_adapter::cluster_adapter::GrpcClusterAdapter;
 use crate::grpc_adapter::search_adapter::GrpcSearchAdapter;
+pub use crate::quota::{QuotaConfig, QuotaExceeded, QuotaService};
 use crate::rest::start_rest_service;
 
+/// Time during which an index reported as missing by the metastore is assumed to still be
+/// missing, so that the requests targeting it do not hammer the metastore.
+const MISSING_INDEX_TTL: Duration = Duration::from_secs(10);
+
+/// Interval between two attempts to start the garbage collectors of the indexes that could not
+/// be resolved so far.
+const PENDING_GARBAGE_COLLECTORS_INTERVAL: Duration = Duration::from_secs(30);
+
 fn display_help_message(
     rest_socket_addr: SocketAddr,
     example_index_name: &str,
@@ -82,18 +107,39 @@ fn display_help_message(
 /// Builds a storage uri resolver that handles
 /// - s3:// uris. This storage comes with a cache that stores hotcache files.
 /// - s3+localstack://
+/// - azure:// uris. Like the s3:// storage, it comes with a cache storing the hotcache files.
 /// - file:// uris.
-fn storage_uri_resolver() -> StorageUriResolver {
+///
+/// The operations on the object storages are abandoned once they exceed the deadline given
+/// by `timeout_policy`. The S3 storages share an HTTP client configured by `s3_http_config`.
+fn storage_uri_resolver(
+    timeout_policy: StorageTimeoutPolicy,
+    s3_http_config: S3HttpConfig,
+) -> StorageUriResolver {
     let s3_storage = StorageWithCacheFactory::new(
-        Arc::new(S3CompatibleObjectStorageFactory::default()),
+        Arc::new(StorageWithTimeoutFactory::new(
+            Arc::new(S3CompatibleObjectStorageFactory::default().with_http_config(s3_http_config)),
+            timeout_policy,
+        )),
+        Arc::new(QuickwitCache::default()),
+    );
+    let azure_storage = StorageWithCacheFactory::new(
+        Arc::new(StorageWithTimeoutFactory::new(
+            Arc::new(AzureBlobStorageFactory::default()),
+            timeout_policy,
+        )),
         Arc::new(QuickwitCache::default()),
     );
     StorageUriResolver::builder()
         .register(LocalFileStorageFactory::default())
         .register(s3_storage)
-        .register(S3CompatibleObjectStorageFactory::new(
-            RegionProvider::Localstack,
-            "s3+localstack",
+        .register(azure_storage)
+        .register(StorageWithTimeoutFactory::new(
+            Arc::new(
+                S3CompatibleObjectStorageFactory::new(RegionProvider::Localstack, "s3+localstack")
+                    .with_http_config(s3_http_config),
+            ),
+            timeout_policy,
         ))
         .build()
 }
@@ -105,10 +151,15 @@ pub async fn serve_cli(args: ServeArgs) -> anyhow::Result<()> {
         has_seed: !args.peer_socket_addrs.is_empty(),
     }))
     .await;
-    let storage_resolver = storage_uri_resolver();
+    let storage_resolver = storage_uri_resolver(args.storage_timeout_policy, args.s3_http_config);
     let metastore_resolver = MetastoreUriResolver::default();
     let example_index_name = "my_index".to_string();
-    let metastore = metastore_resolver.resolve(&args.metastore_uri).await?;
+    // The index metadata is resolved on each request, so that the indexes created after the
+    // node started can be searched as well.
+    let metastore: Arc<dyn Metastore> = Arc::new(NegativeCachingMetastore::new(
+        metastore_resolver.resolve(&args.metastore_uri).await?,
+        MISSING_INDEX_TTL,
+    ));
 
     let host_key = read_or_create_host_key(args.host_key_path.as_path())?;
     let swim_addr = http_addr_to_swim_addr(args.rest_socket_addr);
@@ -125,13 +176,97 @@ pub async fn serve_cli(args: ServeArgs) -> anyhow::Result<()> {
         cluster.add_peer_node(peer_swim_addr).await;
     }
 
-    let client_pool = Arc::new(SearchClientPool::new(cluster.clone()).await?);
-    let cluster_client = ClusterClient::new(client_pool.clone());
+    let gc_params = GarbageCollectorParams {
+        run_interval: args.gc_interval,
+        grace_period: args.gc_grace_period,
+        retention_period: args.gc_retention_period,
+    };
+    let gc_service = Arc::new(
+        GarbageCollectionService::start(
+            &args.gc_index_ids,
+            gc_params,
+            metastore.clone(),
+            storage_resolver.clone(),
+        )
+        .await?,
+    );
+    let pending_gc_service = gc_service.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PENDING_GARBAGE_COLLECTORS_INTERVAL);
+        loop {
+            interval.tick().await;
+            pending_gc_service.start_pending_garbage_collectors().await;
+        }
+    });
+
+    // The universe needs to outlive the split verifiers.
+    let split_verification_universe = Universe::new();
+    let split_verifier_params = SplitVerifierParams {
+        run_interval: args.verify_interval,
+        num_splits_per_pass: args.verify_num_splits,
+    };
+    let mut split_verifier_handles = Vec::with_capacity(args.verify_index_ids.len());
+    for index_id in &args.verify_index_ids {
+        let split_verifier = SplitVerifier::new(
+            index_id.clone(),
+            storage_resolver.clone(),
+            metastore.clone(),
+            split_verifier_params.clone(),
+        );
+        let (_split_verifier_mailbox, split_verifier_handle) = split_verification_universe
+            .spawn_actor(split_verifier)
+            .spawn_async();
+        info!(index_id = %index_id, params = ?split_verifier_params, "Started split verifier.");
+        split_verifier_handles.push(split_verifier_handle);
+    }
+
+    let quota_service = Arc::new(QuotaService::new(args.quota_config.clone()));
+    let api_keys = Arc::new(
+        ApiKeys::new(args.api_keys.clone()).with_split_limits(args.api_key_split_limits.clone()),
+    );
+    let click_house_sinks = Arc::new(ClickHouseSinks::new(args.click_house_sinks.clone()));
+
+    let audit_log_opt = if let Some(audit_log_config) = args.audit_log.as_ref() {
+        let audit_log = AuditLog::start(
+            audit_log_config,
+            metastore.clone(),
+            storage_resolver.clone(),
+        )
+        .await?;
+        Some(audit_log)
+    } else {
+        None
+    };
+
+    if let Some(fast_field_pinning_params) = args.fast_field_pinning.clone() {
+        enable_fast_field_pinning(fast_field_pinning_params)?;
+    }
+    configure_search_thread_pool(args.search_thread_pool.clone())?;
+    if let Some(max_num_bytes) = args.whole_split_download_max_num_bytes {
+        configure_whole_split_download(max_num_bytes)?;
+    }
+    let client_pool =
+        Arc::new(SearchClientPool::new(cluster.clone(), args.placement_rules.clone()).await?);
+    let mut realtime_clients = Vec::with_capacity(args.realtime_indexer_addrs.len());
+    for realtime_indexer_addr in &args.realtime_indexer_addrs {
+        realtime_clients.push(create_search_service_client(*realtime_indexer_addr).await?);
+    }
+    let cluster_client = if let Some(hedging_params) = args.leaf_search_hedging.clone() {
+        ClusterClient::with_hedging(client_pool.clone(), hedging_params)
+    } else {
+        ClusterClient::new(client_pool.clone())
+    }
+    .with_realtime_clients(realtime_clients)
+    .with_tree_aggregation_min_nodes(args.tree_aggregation_min_nodes)
+    .with_split_quarantine_threshold(args.split_quarantine_threshold);
+    let standby_mode = StandbyMode::new(args.standby);
     let search_service = Arc::new(SearchServiceImpl::new(
-        metastore,
+        metastore.clone(),
         storage_resolver,
         cluster_client,
         client_pool,
+        args.node_attributes.clone(),
+        standby_mode.clone(),
     ));
 
     let cluster_service = Arc::new(ClusterServiceImpl::new(cluster.clone()));
@@ -142,7 +277,19 @@ pub async fn serve_cli(args: ServeArgs) -> anyhow::Result<()> {
     let grpc_server =
         start_grpc_service(grpc_socket_addr, grpc_search_service, grpc_cluster_service);
 
-    let rest_server = start_rest_service(args.rest_socket_addr, search_service, cluster_service);
+    let rest_server = start_rest_service(
+        args.rest_socket_addr,
+        search_service,
+        cluster_service,
+        gc_service,
+        quota_service,
+        metastore,
+        args.gc_index_ids.clone(),
+        audit_log_opt,
+        standby_mode,
+        api_keys,
+        click_house_sinks,
+    );
 
     display_help_message(args.rest_socket_addr, &example_index_name)?;
 
@@ -158,6 +305,7 @@ mod tests {
     use std::ops::Range;
     use std::sync::Arc;
 
+    use quickwit_common::QuickwitUri;
     use quickwit_index_config::WikipediaIndexConfig;
     use quickwit_indexing::mock_split_meta;
     use quickwit_metastore::checkpoint::Checkpoint;
@@ -165,11 +313,10 @@ mod tests {
     use quickwit_proto::search_service_server::SearchServiceServer;
     use quickwit_proto::OutputFormat;
     use quickwit_search::{
-        create_search_service_client, root_search_stream, MockSearchService, SearchError,
-        SearchService,
+        create_search_service_client, result_channel, root_search_stream, MockSearchService,
+        SearchError, SearchService,
     };
     use tokio::sync::RwLock;
-    use tokio_stream::wrappers::UnboundedReceiverStream;
     use tonic::transport::Server;
 
     use super::*;
@@ -202,16 +349,35 @@ mod tests {
             output_format: OutputFormat::Csv as i32,
             partition_by_field: None,
             tags: vec![],
+            distinct_count: false,
+            click_house_sink: None,
+            time_zone: String::new(),
+            mandatory_filter: None,
+            disable_synonyms: false,
+            computed_fields: Vec::new(),
+            cache_policy: 0,
         };
         let mut metastore = MockMetastore::new();
+        metastore
+            .expect_acquire_split_lease()
+            .returning(|_index_id, _lease_id, _split_ids, _lease_duration| Ok(()));
+        metastore
+            .expect_release_split_lease()
+            .returning(|_index_id, _lease_id| Ok(()));
         metastore
             .expect_index_metadata()
             .returning(|_index_id: &str| {
                 Ok(IndexMetadata {
                     index_id: "test-idx".to_string(),
-                    index_uri: "file:///path/to/index/test-idx".to_string(),
+                    index_uri: QuickwitUri::from_well_formed("file:///path/to/index/test-idx"),
                     index_config: Arc::new(WikipediaIndexConfig::new()),
                     checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
                 })
             });
         metastore.expect_list_splits().returning(
@@ -223,21 +389,23 @@ mod tests {
             },
         );
         let mut mock_search_service = MockSearchService::new();
-        let (result_sender, result_receiver) = tokio::sync::mpsc::unbounded_channel();
-        result_sender.send(Ok(quickwit_proto::LeafSearchStreamResult {
-            data: b"123".to_vec(),
-            split_id: "split_1".to_string(),
-        }))?;
-        result_sender.send(Err(SearchError::InternalError(
-            "Error on `split2`".to_string(),
-        )))?;
+        let (result_sender, result_receiver) = result_channel();
+        result_sender
+            .send(Ok(quickwit_proto::LeafSearchStreamResult {
+                data: b"123".to_vec(),
+                split_id: "split_1".to_string(),
+            }))
+            .await?;
+        result_sender
+            .send(Err(SearchError::InternalError(
+                "Error on `split2`".to_string(),
+            )))
+            .await?;
         mock_search_service
             .expect_leaf_search_stream()
             .withf(|request| request.split_metadata.len() == 2) // First request.
             .return_once(
-                |_leaf_search_req: quickwit_proto::LeafSearchStreamRequest| {
-                    Ok(UnboundedReceiverStream::new(result_receiver))
-                },
+                |_leaf_search_req: quickwit_proto::LeafSearchStreamRequest| Ok(result_receiver),
             );
         mock_search_service
             .expect_leaf_search_stream()
@@ -258,6 +426,7 @@ mod tests {
         let clients: HashMap<_, _> = IntoIter::new([(grpc_addr, client)]).collect();
         let client_pool = Arc::new(SearchClientPool {
             clients: Arc::new(RwLock::new(clients)),
+            placement_rules: Default::default(),
         });
         let cluster_client = ClusterClient::new(client_pool.clone());
         let search_result =
@@ -0,0 +1,578 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use quickwit_metastore::{Metastore, MetastoreError};
+use quickwit_search::parse_index_ids;
+use thiserror::Error;
+use warp::hyper::header::{HeaderValue, AUTHORIZATION};
+use warp::path::Peek;
+use warp::{Filter, Rejection, Reply};
+
+use crate::ApiKeys;
+
+const QPS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Interval between two sweeps of the idle tenants.
+const TENANT_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Index ID the SQL queries are attributed to, see [`searched_index_id`].
+const SQL_INDEX_ID: &str = "_sql";
+
+/// Limits applied to each tenant of the REST API.
+///
+/// A tenant is identified by the API key of the request if it carries a valid one, and by
+/// the targeted index otherwise. The requests targeting an index that does not exist are not
+/// limited, as they fail right away. A limit set to `None` is not enforced.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuotaConfig {
+    /// Maximum number of search requests per second.
+    pub max_qps: Option<u32>,
+    /// Maximum number of searches running at the same time.
+    pub max_concurrent_searches: Option<u32>,
+}
+
+impl QuotaConfig {
+    fn is_unlimited(&self) -> bool {
+        self.max_qps.is_none() && self.max_concurrent_searches.is_none()
+    }
+}
+
+/// Error returned when a tenant exceeds one of its quotas.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum QuotaExceeded {
+    #[error("Tenant `{tenant}` exceeded its quota of {limit} search request(s) per second")]
+    Qps { tenant: String, limit: u32 },
+    #[error("Tenant `{tenant}` exceeded its quota of {limit} concurrent search(es)")]
+    ConcurrentSearches { tenant: String, limit: u32 },
+}
+
+impl warp::reject::Reject for QuotaExceeded {}
+
+#[derive(Debug)]
+struct TenantUsage {
+    qps_window_start: Instant,
+    num_requests_in_window: u32,
+    num_concurrent_searches: u32,
+}
+
+impl TenantUsage {
+    fn new(now: Instant) -> Self {
+        TenantUsage {
+            qps_window_start: now,
+            num_requests_in_window: 0,
+            num_concurrent_searches: 0,
+        }
+    }
+
+    /// A tenant is idle when none of its searches is running and its QPS window is over:
+    /// its usage is then the same as the one of a new tenant.
+    fn is_idle(&self, now: Instant) -> bool {
+        self.num_concurrent_searches == 0 && now.duration_since(self.qps_window_start) >= QPS_WINDOW
+    }
+}
+
+#[derive(Default)]
+struct Tenants {
+    usages: HashMap<String, TenantUsage>,
+    last_sweep_opt: Option<Instant>,
+}
+
+impl Tenants {
+    /// Evicts the idle tenants, at most once per `TENANT_SWEEP_INTERVAL`, so that the usages
+    /// only cover the tenants active during the last interval.
+    fn sweep_idle_tenants(&mut self, now: Instant) {
+        match self.last_sweep_opt {
+            Some(last_sweep) if now.duration_since(last_sweep) < TENANT_SWEEP_INTERVAL => {}
+            _ => {
+                self.usages
+                    .retain(|_tenant, tenant_usage| !tenant_usage.is_idle(now));
+                self.last_sweep_opt = Some(now);
+            }
+        }
+    }
+}
+
+/// Tracks the usage of each tenant and enforces the limits of the [`QuotaConfig`].
+#[derive(Clone, Default)]
+pub struct QuotaService {
+    config: QuotaConfig,
+    tenants: Arc<Mutex<Tenants>>,
+}
+
+impl QuotaService {
+    /// Creates a quota service enforcing the given limits.
+    pub fn new(config: QuotaConfig) -> Self {
+        QuotaService {
+            config,
+            tenants: Default::default(),
+        }
+    }
+
+    /// Accounts for a new search request of `tenant`.
+    ///
+    /// The returned permit must be held for the duration of the search.
+    pub fn acquire_search_permit(&self, tenant: &str) -> Result<SearchPermit, QuotaExceeded> {
+        self.acquire_search_permit_at(tenant, Instant::now())
+    }
+
+    fn acquire_search_permit_at(
+        &self,
+        tenant: &str,
+        now: Instant,
+    ) -> Result<SearchPermit, QuotaExceeded> {
+        if self.config.is_unlimited() {
+            return Ok(SearchPermit::untracked(tenant));
+        }
+        let mut tenants = self.tenants.lock().unwrap();
+        tenants.sweep_idle_tenants(now);
+        let tenant_usage = tenants
+            .usages
+            .entry(tenant.to_string())
+            .or_insert_with(|| TenantUsage::new(now));
+        if now.duration_since(tenant_usage.qps_window_start) >= QPS_WINDOW {
+            tenant_usage.qps_window_start = now;
+            tenant_usage.num_requests_in_window = 0;
+        }
+        if let Some(limit) = self.config.max_qps {
+            if tenant_usage.num_requests_in_window >= limit {
+                return Err(QuotaExceeded::Qps {
+                    tenant: tenant.to_string(),
+                    limit,
+                });
+            }
+        }
+        if let Some(limit) = self.config.max_concurrent_searches {
+            if tenant_usage.num_concurrent_searches >= limit {
+                return Err(QuotaExceeded::ConcurrentSearches {
+                    tenant: tenant.to_string(),
+                    limit,
+                });
+            }
+        }
+        tenant_usage.num_requests_in_window += 1;
+        tenant_usage.num_concurrent_searches += 1;
+        let usage = QuotaUsage {
+            qps_limit: self.config.max_qps,
+            qps_remaining: self
+                .config
+                .max_qps
+                .map(|limit| limit - tenant_usage.num_requests_in_window),
+            concurrent_searches_limit: self.config.max_concurrent_searches,
+            concurrent_searches_remaining: self
+                .config
+                .max_concurrent_searches
+                .map(|limit| limit - tenant_usage.num_concurrent_searches),
+        };
+        Ok(SearchPermit {
+            tenant: tenant.to_string(),
+            usage,
+            quota_service: Some(self.clone()),
+        })
+    }
+
+    fn release_search_permit(&self, tenant: &str) {
+        let mut tenants = self.tenants.lock().unwrap();
+        if let Some(tenant_usage) = tenants.usages.get_mut(tenant) {
+            tenant_usage.num_concurrent_searches =
+                tenant_usage.num_concurrent_searches.saturating_sub(1);
+        }
+    }
+}
+
+/// Quota usage of a tenant, reported to the client through the `x-quota-*` response headers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub qps_limit: Option<u32>,
+    pub qps_remaining: Option<u32>,
+    pub concurrent_searches_limit: Option<u32>,
+    pub concurrent_searches_remaining: Option<u32>,
+}
+
+/// Permit held by a running search. The concurrent search slot is released on drop.
+pub struct SearchPermit {
+    tenant: String,
+    usage: QuotaUsage,
+    quota_service: Option<QuotaService>,
+}
+
+impl SearchPermit {
+    /// Returns a permit that does not account for the search.
+    fn untracked(tenant: &str) -> Self {
+        SearchPermit {
+            tenant: tenant.to_string(),
+            usage: QuotaUsage::default(),
+            quota_service: None,
+        }
+    }
+
+    /// Returns the usage of the tenant at the time the permit was acquired.
+    pub fn usage(&self) -> &QuotaUsage {
+        &self.usage
+    }
+
+    /// Adds the quota usage headers to `reply`.
+    pub fn with_usage_headers(&self, reply: impl Reply) -> warp::reply::Response {
+        let mut response = reply.into_response();
+        let headers = response.headers_mut();
+        let usage_headers = [
+            ("x-quota-qps-limit", self.usage.qps_limit),
+            ("x-quota-qps-remaining", self.usage.qps_remaining),
+            (
+                "x-quota-concurrent-searches-limit",
+                self.usage.concurrent_searches_limit,
+            ),
+            (
+                "x-quota-concurrent-searches-remaining",
+                self.usage.concurrent_searches_remaining,
+            ),
+        ];
+        for (header_name, value_opt) in usage_headers.iter() {
+            if let Some(value) = value_opt {
+                headers.insert(*header_name, HeaderValue::from(*value));
+            }
+        }
+        response
+    }
+}
+
+impl Drop for SearchPermit {
+    fn drop(&mut self) {
+        if let Some(quota_service) = &self.quota_service {
+            quota_service.release_search_permit(&self.tenant);
+        }
+    }
+}
+
+/// Identifies the tenant of a request: its API key if it carries a valid one, the index ID
+/// otherwise.
+///
+/// Unknown bearer tokens fall back to the index ID, so that clients cannot get fresh quotas by
+/// making up tokens. The requests without a valid token targeting an index that does not exist
+/// have no tenant, so that clients cannot grow the usages tracked by the [`QuotaService`] by
+/// making up index IDs.
+async fn tenant_key(
+    index_id: &str,
+    authorization_opt: Option<&str>,
+    api_keys: &ApiKeys,
+    metastore: &dyn Metastore,
+) -> Option<String> {
+    if let Some(token) = api_keys.validated_token(authorization_opt) {
+        return Some(format!("token:{}", token));
+    }
+    if index_id != SQL_INDEX_ID {
+        for index_id in parse_index_ids(index_id) {
+            if let Err(MetastoreError::IndexDoesNotExist { .. }) =
+                metastore.index_metadata(index_id).await
+            {
+                return None;
+            }
+        }
+    }
+    Some(format!("index:{}", index_id))
+}
+
+/// Extracts the index ID from a `api/v1/<index_id>/search[/...]` or
+/// `api/v1/<index_id>/doc/<address>` path.
+///
+/// The SQL queries of the `api/v1/_sql` path name their index in their body: they are
+/// attributed to `_sql`, which cannot be an index ID, as index IDs start with a letter.
+fn searched_index_id(path: &str) -> Option<&str> {
+    let mut segments = path.trim_start_matches('/').split('/');
+    match (
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+    ) {
+        (Some("api"), Some("v1"), Some(SQL_INDEX_ID), None) => Some(SQL_INDEX_ID),
+        (Some("api"), Some("v1"), Some(index_id), Some("search" | "doc")) => Some(index_id),
+        _ => None,
+    }
+}
+
+/// Filter acquiring a [`SearchPermit`] for search requests, and rejecting them with
+/// [`QuotaExceeded`] when the tenant is over quota.
+///
+/// This filter does not consume the path, so it must be combined with the search handlers.
+pub fn search_quota_filter(
+    quota_service: Arc<QuotaService>,
+    api_keys: Arc<ApiKeys>,
+    metastore: Arc<dyn Metastore>,
+) -> impl Filter<Extract = (SearchPermit,), Error = Rejection> + Clone {
+    warp::path::peek()
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+        .and_then(move |path: Peek, authorization_opt: Option<String>| {
+            let quota_service = quota_service.clone();
+            let api_keys = api_keys.clone();
+            let metastore = metastore.clone();
+            async move {
+                let index_id =
+                    searched_index_id(path.as_str()).ok_or_else(warp::reject::not_found)?;
+                let tenant_opt = tenant_key(
+                    index_id,
+                    authorization_opt.as_deref(),
+                    &api_keys,
+                    &*metastore,
+                )
+                .await;
+                match tenant_opt {
+                    Some(tenant) => quota_service
+                        .acquire_search_permit(&tenant)
+                        .map_err(warp::reject::custom),
+                    None => Ok(SearchPermit::untracked(index_id)),
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_common::QuickwitUri;
+    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_metastore::checkpoint::Checkpoint;
+    use quickwit_metastore::{IndexMetadata, MockMetastore};
+
+    use super::*;
+    use crate::ApiKey;
+
+    /// Returns a metastore holding the indexes `foo` and `my-index`.
+    fn mock_metastore() -> MockMetastore {
+        let mut metastore = MockMetastore::new();
+        metastore
+            .expect_index_metadata()
+            .returning(|index_id: &str| {
+                if index_id != "foo" && index_id != "my-index" {
+                    return Err(MetastoreError::IndexDoesNotExist {
+                        index_id: index_id.to_string(),
+                    });
+                }
+                Ok(IndexMetadata {
+                    index_id: index_id.to_string(),
+                    index_uri: QuickwitUri::from_well_formed(&format!(
+                        "ram:///indexes/{}",
+                        index_id
+                    )),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        metastore
+    }
+
+    #[test]
+    fn test_quota_service_unlimited() {
+        let quota_service = QuotaService::default();
+        let permits: Vec<SearchPermit> = (0..100)
+            .map(|_| quota_service.acquire_search_permit("index:foo").unwrap())
+            .collect();
+        assert_eq!(permits[99].usage(), &QuotaUsage::default());
+    }
+
+    #[test]
+    fn test_quota_service_max_qps() {
+        let quota_service = QuotaService::new(QuotaConfig {
+            max_qps: Some(2),
+            ..Default::default()
+        });
+        let first_permit = quota_service.acquire_search_permit("index:foo").unwrap();
+        assert_eq!(first_permit.usage().qps_remaining, Some(1));
+        drop(first_permit);
+        let second_permit = quota_service.acquire_search_permit("index:foo").unwrap();
+        assert_eq!(second_permit.usage().qps_remaining, Some(0));
+        assert!(matches!(
+            quota_service.acquire_search_permit("index:foo"),
+            Err(QuotaExceeded::Qps { limit: 2, .. })
+        ));
+        // Quotas are tracked per tenant.
+        assert!(quota_service.acquire_search_permit("index:bar").is_ok());
+    }
+
+    #[test]
+    fn test_quota_service_max_concurrent_searches() {
+        let quota_service = QuotaService::new(QuotaConfig {
+            max_concurrent_searches: Some(1),
+            ..Default::default()
+        });
+        let permit = quota_service.acquire_search_permit("token:foo").unwrap();
+        assert_eq!(permit.usage().concurrent_searches_remaining, Some(0));
+        assert!(matches!(
+            quota_service.acquire_search_permit("token:foo"),
+            Err(QuotaExceeded::ConcurrentSearches { limit: 1, .. })
+        ));
+        drop(permit);
+        assert!(quota_service.acquire_search_permit("token:foo").is_ok());
+    }
+
+    #[test]
+    fn test_quota_service_evicts_idle_tenants() {
+        let quota_service = QuotaService::new(QuotaConfig {
+            max_qps: Some(10),
+            ..Default::default()
+        });
+        let num_tenants = || quota_service.tenants.lock().unwrap().usages.len();
+        let start = Instant::now();
+        drop(
+            quota_service
+                .acquire_search_permit_at("index:foo", start)
+                .unwrap(),
+        );
+        let _running_search_permit = quota_service
+            .acquire_search_permit_at("index:bar", start)
+            .unwrap();
+        assert_eq!(num_tenants(), 2);
+        // The idle tenants are only evicted once per sweep interval.
+        quota_service
+            .acquire_search_permit_at("index:baz", start + QPS_WINDOW)
+            .unwrap();
+        assert_eq!(num_tenants(), 3);
+        quota_service
+            .acquire_search_permit_at("index:qux", start + TENANT_SWEEP_INTERVAL)
+            .unwrap();
+        // `bar` still has a running search.
+        let tenants = quota_service.tenants.lock().unwrap();
+        let mut tenant_keys: Vec<&str> = tenants.usages.keys().map(String::as_str).collect();
+        tenant_keys.sort_unstable();
+        assert_eq!(tenant_keys, vec!["index:bar", "index:qux"]);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_key() -> anyhow::Result<()> {
+        let api_keys = ApiKeys::new(vec!["my-token".parse::<ApiKey>()?]);
+        let metastore = mock_metastore();
+        assert_eq!(
+            tenant_key("foo", None, &api_keys, &metastore).await,
+            Some("index:foo".to_string())
+        );
+        assert_eq!(
+            tenant_key("foo", Some("Basic Zm9vOmJhcg=="), &api_keys, &metastore).await,
+            Some("index:foo".to_string())
+        );
+        assert_eq!(
+            tenant_key("foo", Some("Bearer my-token"), &api_keys, &metastore).await,
+            Some("token:my-token".to_string())
+        );
+        assert_eq!(
+            tenant_key("foo", Some("Bearer made-up-token"), &api_keys, &metastore).await,
+            Some("index:foo".to_string())
+        );
+        assert_eq!(
+            tenant_key(
+                "foo",
+                Some("Bearer my-token"),
+                &ApiKeys::default(),
+                &metastore
+            )
+            .await,
+            Some("index:foo".to_string())
+        );
+        assert_eq!(
+            tenant_key("foo,my-index", None, &api_keys, &metastore).await,
+            Some("index:foo,my-index".to_string())
+        );
+        assert_eq!(
+            tenant_key("_sql", None, &api_keys, &metastore).await,
+            Some("index:_sql".to_string())
+        );
+        // The made-up index IDs have no tenant, unless the request carries a valid token.
+        assert_eq!(
+            tenant_key("made-up-index", None, &api_keys, &metastore).await,
+            None
+        );
+        assert_eq!(
+            tenant_key("foo,made-up-index", None, &api_keys, &metastore).await,
+            None
+        );
+        assert_eq!(
+            tenant_key(
+                "made-up-index",
+                Some("Bearer my-token"),
+                &api_keys,
+                &metastore
+            )
+            .await,
+            Some("token:my-token".to_string())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_quota_filter() {
+        let quota_service = Arc::new(QuotaService::new(QuotaConfig {
+            max_qps: Some(1),
+            ..Default::default()
+        }));
+        let filter = search_quota_filter(
+            quota_service.clone(),
+            Arc::new(ApiKeys::default()),
+            Arc::new(mock_metastore()),
+        );
+        let permit = warp::test::request()
+            .path("/api/v1/my-index/search?query=*")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(permit.usage().qps_remaining, Some(0));
+        let rejection = warp::test::request()
+            .path("/api/v1/my-index/search/stream?query=*&fastField=id")
+            .filter(&filter)
+            .await
+            .err()
+            .unwrap();
+        assert!(rejection.find::<QuotaExceeded>().is_some());
+        let rejection = warp::test::request()
+            .path("/api/v1/my-index/doc/split1:0:3:1")
+            .filter(&filter)
+            .await
+            .err()
+            .unwrap();
+        assert!(rejection.find::<QuotaExceeded>().is_some());
+        let permit = warp::test::request()
+            .method("POST")
+            .path("/api/v1/_sql")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(permit.usage().qps_remaining, Some(0));
+        // The searches of the indexes that do not exist are not tracked.
+        let permit = warp::test::request()
+            .path("/api/v1/made-up-index/search?query=*")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(permit.usage(), &QuotaUsage::default());
+        assert_eq!(quota_service.tenants.lock().unwrap().usages.len(), 2);
+        let rejection = warp::test::request()
+            .path("/metrics")
+            .filter(&filter)
+            .await
+            .err()
+            .unwrap();
+        assert!(rejection.is_not_found());
+    }
+}
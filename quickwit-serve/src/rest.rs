@@ -19,29 +19,58 @@
 
 use std::convert::{Infallible, TryFrom};
 use std::net::SocketAddr;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
 
 use bytes::Bytes;
 use futures::stream::{self, StreamExt};
 use quickwit_cluster::service::ClusterServiceImpl;
 use quickwit_common::metrics;
-use quickwit_proto::OutputFormat;
-use quickwit_search::{SearchResponseRest, SearchService, SearchServiceImpl};
+use quickwit_index_config::TimeZone;
+use quickwit_metastore::Metastore;
+use quickwit_proto::{
+    CachePolicy, DateHistogramAggregation, GeoBoundingBox, GeoDistance, GeoFilter, HitSampling,
+    OutputFormat, TermsAggregation, TermsOrder,
+};
+use quickwit_search::{
+    HitAddress, SearchError, SearchEstimate, SearchResponseRest, SearchService, SearchServiceImpl,
+    SqlQuery, SqlResponse, StandbyMode,
+};
 use serde::{Deserialize, Deserializer};
 use tracing::info;
-use warp::hyper::header::CONTENT_TYPE;
+use warp::hyper::header::{AUTHORIZATION, CONTENT_TYPE};
 use warp::hyper::StatusCode;
 use warp::{reply, Filter, Rejection, Reply};
 
+use crate::api_keys::ApiKeys;
+use crate::audit_log::{AuditLog, AuditRecord};
+use crate::click_house_sinks::ClickHouseSinks;
+use crate::garbage_collection::GarbageCollectionService;
+use crate::http_handler::cache_admin::cache_admin_handler;
 use crate::http_handler::cluster::cluster_handler;
-use crate::http_handler::health_check::liveness_check_handler;
+use crate::http_handler::garbage_collection::garbage_collection_handler;
+use crate::http_handler::health_check::{index_readiness_check_handler, liveness_check_handler};
+use crate::http_handler::index_stats::index_stats_handler;
+use crate::http_handler::split_timeline::split_timeline_handler;
+use crate::http_handler::standby::promote_handler;
+use crate::quota::{search_quota_filter, QuotaExceeded, QuotaService, SearchPermit};
 use crate::ApiError;
 
 /// Start REST service given a HTTP address and a search service.
+#[allow(clippy::too_many_arguments)]
 pub async fn start_rest_service(
     rest_addr: SocketAddr,
     search_service: Arc<SearchServiceImpl>,
     cluster_service: Arc<ClusterServiceImpl>,
+    gc_service: Arc<GarbageCollectionService>,
+    quota_service: Arc<QuotaService>,
+    metastore: Arc<dyn Metastore>,
+    index_ids: Vec<String>,
+    audit_log_opt: Option<Arc<AuditLog>>,
+    standby_mode: StandbyMode,
+    api_keys: Arc<ApiKeys>,
+    click_house_sinks: Arc<ClickHouseSinks>,
 ) -> anyhow::Result<()> {
     info!(rest_addr=?rest_addr, "Starting REST service.");
     let request_counter = warp::log::custom(|_| {
@@ -50,10 +79,30 @@ pub async fn start_rest_service(
     let metrics_service = warp::path("metrics")
         .and(warp::get())
         .map(metrics::metrics_handler);
+    // Estimating a search only reads the metastore, so it does not consume the search quotas.
+    let search_estimate_routes = search_estimate_handler(search_service.clone(), api_keys.clone());
+    let search_routes = search_routes(
+        search_service,
+        quota_service,
+        metastore.clone(),
+        audit_log_opt,
+        api_keys.clone(),
+        click_house_sinks,
+    );
     let rest_routes = liveness_check_handler()
+        .or(index_readiness_check_handler(
+            metastore.clone(),
+            index_ids,
+            api_keys.clone(),
+        ))
         .or(cluster_handler(cluster_service))
-        .or(search_handler(search_service.clone()))
-        .or(search_stream_handler(search_service))
+        .or(garbage_collection_handler(gc_service, api_keys.clone()))
+        .or(split_timeline_handler(metastore.clone(), api_keys.clone()))
+        .or(index_stats_handler(metastore.clone(), api_keys.clone()))
+        .or(promote_handler(standby_mode, api_keys.clone()))
+        .or(cache_admin_handler(metastore, api_keys))
+        .or(search_estimate_routes)
+        .or(search_routes)
         .or(metrics_service)
         .with(request_counter)
         .recover(recover_fn);
@@ -61,10 +110,54 @@ pub async fn start_rest_service(
     Ok(())
 }
 
+/// Routes of the requests consuming the search quotas of their tenant.
+fn search_routes<TSearchService: SearchService>(
+    search_service: Arc<TSearchService>,
+    quota_service: Arc<QuotaService>,
+    metastore: Arc<dyn Metastore>,
+    audit_log_opt: Option<Arc<AuditLog>>,
+    api_keys: Arc<ApiKeys>,
+    click_house_sinks: Arc<ClickHouseSinks>,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    search_quota_filter(quota_service, api_keys.clone(), metastore)
+        .and(
+            search_handler(
+                search_service.clone(),
+                audit_log_opt.clone(),
+                api_keys.clone(),
+            )
+            .or(search_stream_handler(
+                search_service.clone(),
+                audit_log_opt.clone(),
+                api_keys.clone(),
+                click_house_sinks,
+            ))
+            .or(sql_handler(
+                search_service.clone(),
+                audit_log_opt,
+                api_keys.clone(),
+            ))
+            .or(fetch_doc_handler(search_service, api_keys)),
+        )
+        .map(|search_permit: SearchPermit, reply| search_permit.with_usage_headers(reply))
+}
+
 fn default_max_hits() -> u64 {
     20
 }
 
+fn default_terms_size() -> u64 {
+    10
+}
+
+fn default_date_histogram_interval() -> String {
+    "day".to_string()
+}
+
+fn default_sample_size() -> u32 {
+    1
+}
+
 /// Output format for the search results.
 #[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
@@ -119,7 +212,7 @@ impl Format {
 
 /// This struct represents the QueryString passed to
 /// the rest API.
-#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[derive(Deserialize, Debug, PartialEq)]
 #[serde(deny_unknown_fields)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchRequestQueryString {
@@ -134,6 +227,16 @@ pub struct SearchRequestQueryString {
     pub start_timestamp: Option<i64>,
     /// If set, restrict search to documents with a `timestamp < end_timestamp``.
     pub end_timestamp: Option<i64>,
+    /// Same as `start_timestamp`, given as a date such as `2021-06-01T12:00:00+02:00`,
+    /// or as a local date such as `2021-06-01` in the requested time zone.
+    pub start_time: Option<String>,
+    /// Same as `end_timestamp`, given as a date.
+    pub end_time: Option<String>,
+    /// Time zone of the local dates of the request and of the date histogram buckets:
+    /// an offset from UTC such as `+02:00`, or an IANA time zone name such as
+    /// `Europe/Paris` (by default UTC).
+    #[serde(default)]
+    pub time_zone: Option<String>,
     /// Maximum number of hits to return (by default 20).
     #[serde(default = "default_max_hits")]
     pub max_hits: u64,
@@ -151,23 +254,249 @@ pub struct SearchRequestQueryString {
     #[serde(default)]
     #[serde(deserialize_with = "from_simple_list")]
     pub tags: Option<Vec<String>>,
+    /// Fast fields whose number of distinct values among the matching documents is estimated.
+    #[serde(default)]
+    #[serde(deserialize_with = "from_simple_list")]
+    pub distinct_count_fields: Option<Vec<String>>,
+    /// Fast fields whose percentiles among the matching documents are estimated.
+    #[serde(default)]
+    #[serde(deserialize_with = "from_simple_list")]
+    pub percentile_fields: Option<Vec<String>>,
+    /// Percentiles to estimate, between 0 and 100 (by default 50, 95 and 99).
+    #[serde(default)]
+    #[serde(deserialize_with = "from_simple_f64_list")]
+    pub percentiles: Option<Vec<f64>>,
+    /// If set, the matching documents are grouped into one bucket per value of this fast field.
+    #[serde(default)]
+    pub terms_field: Option<String>,
+    /// Number of buckets of the terms aggregation to return (by default 10).
+    #[serde(default = "default_terms_size")]
+    pub terms_size: u64,
+    /// Number of buckets of the terms aggregation returned by each leaf.
+    #[serde(default)]
+    pub terms_shard_size: Option<u64>,
+    /// Metric ordering the buckets of the terms aggregation: `count` (by default), `sum`, `avg`,
+    /// `min`, `max`, or a percentile such as `p99`.
+    #[serde(default)]
+    #[serde(deserialize_with = "from_terms_order")]
+    pub terms_order: Option<(TermsOrder, f64)>,
+    /// Fast field the metric ordering the buckets of the terms aggregation is computed over.
+    #[serde(default)]
+    pub terms_metric_field: Option<String>,
+    /// If set, the buckets of the terms aggregation with the lowest metric come first.
+    #[serde(default)]
+    pub terms_ascending: bool,
+    /// If set, the matching documents are grouped into buckets of consecutive time intervals,
+    /// according to the timestamps held by this fast field.
+    #[serde(default)]
+    pub date_histogram_field: Option<String>,
+    /// Width of the buckets of the date histogram: `30s`, `15m`, `1h`, `1d`, or a calendar
+    /// unit among `day` (by default), `week`, `month` and `year`.
+    #[serde(default = "default_date_histogram_interval")]
+    pub date_histogram_interval: String,
+    /// If set, the hits are sampled per time bucket of this width, in the format of
+    /// `date_histogram_interval`, e.g. `1m` for an overview of the matching documents.
+    #[serde(default)]
+    pub sample_interval: Option<String>,
+    /// Maximum number of hits returned per time bucket of the sampling (by default 1).
+    #[serde(default = "default_sample_size")]
+    pub sample_size: u32,
+    /// If set, the sampling returns the first documents of each bucket, in chronological
+    /// order, instead of the last ones.
+    #[serde(default)]
+    pub sample_first: bool,
+    /// Geo point field filtered by `geo_bounding_box` or `geo_distance`.
+    #[serde(default)]
+    pub geo_field: Option<String>,
+    /// If set, restricts the search to the documents whose point is within this bounding
+    /// box, given as `top_lat,left_lon,bottom_lat,right_lon`.
+    #[serde(default)]
+    #[serde(deserialize_with = "from_simple_f64_list")]
+    pub geo_bounding_box: Option<Vec<f64>>,
+    /// If set, restricts the search to the documents whose point is within a distance of
+    /// a point, given as `lat,lon,distance_in_meters`.
+    #[serde(default)]
+    #[serde(deserialize_with = "from_simple_f64_list")]
+    pub geo_distance: Option<Vec<f64>>,
+    /// If set, the keys of the nested objects of the hits are flattened into dot-separated
+    /// keys.
+    #[serde(default)]
+    pub flatten_hits: bool,
+    /// If set, the terms of the query are not expanded into the synonyms of the index.
+    #[serde(default)]
+    pub disable_synonyms: bool,
+    /// Session token returned by a previous search, e.g. for the first page of the hits, so
+    /// that this search runs on the same splits.
+    #[serde(default)]
+    pub session_token: Option<String>,
+    /// If set, the search waits for the splits published at or after this timestamp, in
+    /// seconds, to be visible.
+    #[serde(default)]
+    pub min_publish_timestamp: Option<i64>,
+    /// Whether the caches of the searchers are used (`use`, by default), bypassed
+    /// (`bypass`), or refreshed (`refresh`) by the search.
+    #[serde(default)]
+    pub cache: CachePolicy,
 }
 
-async fn search_endpoint<TSearchService: SearchService>(
+/// Resolves a bound of the time filter, given either as a timestamp in seconds or as a date.
+fn resolve_timestamp(
+    timestamp_opt: Option<i64>,
+    date_opt: Option<&str>,
+    time_zone: &str,
+    bound_name: &str,
+) -> Result<Option<i64>, ApiError> {
+    match (timestamp_opt, date_opt) {
+        (Some(_), Some(_)) => Err(ApiError::InvalidArgument(format!(
+            "`{0}Timestamp` and `{0}Time` cannot both be set",
+            bound_name
+        ))),
+        (Some(timestamp), None) => Ok(Some(timestamp)),
+        (None, Some(date)) => TimeZone::from_str(time_zone)
+            .and_then(|time_zone| time_zone.parse_timestamp(date))
+            .map(Some)
+            .map_err(|error| {
+                ApiError::InvalidArgument(format!("Invalid `{}Time`: {}", bound_name, error))
+            }),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Resolves the geo filter, given as a bounding box or as a distance of a point.
+fn resolve_geo_filter(
+    geo_field_opt: Option<String>,
+    geo_bounding_box_opt: Option<Vec<f64>>,
+    geo_distance_opt: Option<Vec<f64>>,
+) -> Result<Option<GeoFilter>, ApiError> {
+    let field_name = match geo_field_opt {
+        Some(field_name) => field_name,
+        None if geo_bounding_box_opt.is_none() && geo_distance_opt.is_none() => return Ok(None),
+        None => {
+            return Err(ApiError::InvalidArgument(
+                "`geoField` must be set to filter by `geoBoundingBox` or `geoDistance`".to_string(),
+            ))
+        }
+    };
+    let bounding_box = match geo_bounding_box_opt.as_deref() {
+        Some(&[top_lat, left_lon, bottom_lat, right_lon]) => Some(GeoBoundingBox {
+            top_lat,
+            left_lon,
+            bottom_lat,
+            right_lon,
+        }),
+        Some(_) => {
+            return Err(ApiError::InvalidArgument(
+                "`geoBoundingBox` must be given as `top_lat,left_lon,bottom_lat,right_lon`"
+                    .to_string(),
+            ))
+        }
+        None => None,
+    };
+    let distance = match geo_distance_opt.as_deref() {
+        Some(&[lat, lon, distance_in_meters]) => Some(GeoDistance {
+            lat,
+            lon,
+            distance_in_meters,
+        }),
+        Some(_) => {
+            return Err(ApiError::InvalidArgument(
+                "`geoDistance` must be given as `lat,lon,distance_in_meters`".to_string(),
+            ))
+        }
+        None => None,
+    };
+    Ok(Some(GeoFilter {
+        field_name,
+        bounding_box,
+        distance,
+    }))
+}
+
+fn make_search_request(
     index_id: String,
     search_request: SearchRequestQueryString,
-    search_service: &TSearchService,
-) -> Result<SearchResponseRest, ApiError> {
+    mandatory_filter: Option<String>,
+) -> Result<quickwit_proto::SearchRequest, ApiError> {
+    let time_zone = search_request.time_zone.unwrap_or_default();
+    let start_timestamp = resolve_timestamp(
+        search_request.start_timestamp,
+        search_request.start_time.as_deref(),
+        &time_zone,
+        "start",
+    )?;
+    let end_timestamp = resolve_timestamp(
+        search_request.end_timestamp,
+        search_request.end_time.as_deref(),
+        &time_zone,
+        "end",
+    )?;
+    let geo_filter = resolve_geo_filter(
+        search_request.geo_field,
+        search_request.geo_bounding_box,
+        search_request.geo_distance,
+    )?;
     let search_request = quickwit_proto::SearchRequest {
         index_id,
         query: search_request.query,
         search_fields: search_request.search_fields.unwrap_or_default(),
-        start_timestamp: search_request.start_timestamp,
-        end_timestamp: search_request.end_timestamp,
+        start_timestamp,
+        end_timestamp,
         max_hits: search_request.max_hits,
         start_offset: search_request.start_offset,
         tags: search_request.tags.unwrap_or_default(),
+        distinct_count_fields: search_request.distinct_count_fields.unwrap_or_default(),
+        percentile_fields: search_request.percentile_fields.unwrap_or_default(),
+        percentiles: search_request.percentiles.unwrap_or_default(),
+        terms_aggregation: search_request.terms_field.map(|field_name| {
+            let (order, percentile) = search_request
+                .terms_order
+                .unwrap_or((TermsOrder::Count, 0.0));
+            TermsAggregation {
+                field_name,
+                size: search_request.terms_size,
+                shard_size: search_request.terms_shard_size,
+                order: order as i32,
+                metric_field: search_request.terms_metric_field,
+                percentile,
+                ascending: search_request.terms_ascending,
+            }
+        }),
+        date_histogram: search_request.date_histogram_field.map(|field_name| {
+            DateHistogramAggregation {
+                field_name,
+                interval: search_request.date_histogram_interval,
+            }
+        }),
+        time_zone,
+        geo_filter,
+        mandatory_filter,
+        flatten_hits: search_request.flatten_hits,
+        sort_by_field: None,
+        sort_ascending: false,
+        disable_synonyms: search_request.disable_synonyms,
+        session_token: search_request.session_token,
+        min_publish_timestamp: search_request.min_publish_timestamp,
+        cache_policy: search_request.cache as i32,
+        max_num_splits: None,
+        hit_sampling: search_request.sample_interval.map(|interval| HitSampling {
+            interval,
+            max_hits_per_bucket: search_request.sample_size,
+            first: search_request.sample_first,
+        }),
     };
+    Ok(search_request)
+}
+
+async fn search_endpoint<TSearchService: SearchService>(
+    index_id: String,
+    search_request: SearchRequestQueryString,
+    authorization_opt: Option<&str>,
+    search_service: &TSearchService,
+    api_keys: &ApiKeys,
+) -> Result<SearchResponseRest, ApiError> {
+    let mandatory_filter = api_keys.mandatory_filter(authorization_opt)?;
+    let mut search_request = make_search_request(index_id, search_request, mandatory_filter)?;
+    search_request.max_num_splits = api_keys.max_num_splits(authorization_opt);
     let search_response = search_service.root_search(search_request).await?;
     let search_response_rest =
         SearchResponseRest::try_from(search_response).map_err(ApiError::SearchError)?;
@@ -184,12 +513,36 @@ fn search_filter(
 async fn search<TSearchService: SearchService>(
     index_id: String,
     search_request: SearchRequestQueryString,
+    authorization_opt: Option<String>,
     search_service: Arc<TSearchService>,
+    audit_log_opt: Option<Arc<AuditLog>>,
+    api_keys: Arc<ApiKeys>,
 ) -> Result<impl warp::Reply, Infallible> {
     info!(index_id = %index_id, request =? search_request, "search");
-    Ok(search_request
-        .format
-        .make_reply(search_endpoint(index_id, search_request, &*search_service).await))
+    let format = search_request.format;
+    let query = search_request.query.clone();
+    let start = Instant::now();
+    let search_result = search_endpoint(
+        index_id.clone(),
+        search_request,
+        authorization_opt.as_deref(),
+        &*search_service,
+        &*api_keys,
+    )
+    .await;
+    if let Some(audit_log) = audit_log_opt {
+        audit_log.record(AuditRecord::new(
+            "search",
+            &index_id,
+            &query,
+            authorization_opt.as_deref(),
+            start.elapsed(),
+            search_result
+                .as_ref()
+                .map(|search_response| Some(search_response.num_hits)),
+        ));
+    }
+    Ok(format.make_reply(search_result))
 }
 
 /// REST search handler.
@@ -197,12 +550,242 @@ async fn search<TSearchService: SearchService>(
 /// Parses the search request from the
 pub fn search_handler<TSearchService: SearchService>(
     search_service: Arc<TSearchService>,
+    audit_log_opt: Option<Arc<AuditLog>>,
+    api_keys: Arc<ApiKeys>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
     search_filter()
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
         .and(warp::any().map(move || search_service.clone()))
+        .and(warp::any().map(move || audit_log_opt.clone()))
+        .and(warp::any().map(move || api_keys.clone()))
         .and_then(search)
 }
 
+async fn search_estimate_endpoint<TSearchService: SearchService>(
+    index_id: String,
+    search_request: SearchRequestQueryString,
+    authorization_opt: Option<&str>,
+    search_service: &TSearchService,
+    api_keys: &ApiKeys,
+) -> Result<SearchEstimate, ApiError> {
+    let mandatory_filter = api_keys.mandatory_filter(authorization_opt)?;
+    let search_request = make_search_request(index_id, search_request, mandatory_filter)?;
+    let search_estimate = search_service.root_estimate(search_request).await?;
+    Ok(search_estimate)
+}
+
+fn search_estimate_filter(
+) -> impl Filter<Extract = (String, SearchRequestQueryString), Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / String / "search" / "estimate")
+        .and(warp::get())
+        .and(serde_qs::warp::query(serde_qs::Config::default()))
+}
+
+async fn search_estimate<TSearchService: SearchService>(
+    index_id: String,
+    search_request: SearchRequestQueryString,
+    authorization_opt: Option<String>,
+    search_service: Arc<TSearchService>,
+    api_keys: Arc<ApiKeys>,
+) -> Result<impl warp::Reply, Infallible> {
+    info!(index_id = %index_id, request =? search_request, "search-estimate");
+    let format = search_request.format;
+    Ok(format.make_reply(
+        search_estimate_endpoint(
+            index_id,
+            search_request,
+            authorization_opt.as_deref(),
+            &*search_service,
+            &*api_keys,
+        )
+        .await,
+    ))
+}
+
+/// REST handler estimating the cost of a search without executing it.
+///
+/// It accepts the same query string as the search API.
+pub fn search_estimate_handler<TSearchService: SearchService>(
+    search_service: Arc<TSearchService>,
+    api_keys: Arc<ApiKeys>,
+) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+    search_estimate_filter()
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+        .and(warp::any().map(move || search_service.clone()))
+        .and(warp::any().map(move || api_keys.clone()))
+        .and_then(search_estimate)
+}
+
+/// This struct represents the QueryString passed to
+/// the REST API fetching a document.
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct FetchDocQueryString {
+    /// The output format.
+    #[serde(default)]
+    pub format: Format,
+}
+
+async fn fetch_doc_endpoint<TSearchService: SearchService>(
+    index_id: String,
+    hit_address_str: &str,
+    authorization_opt: Option<&str>,
+    search_service: &TSearchService,
+    api_keys: &ApiKeys,
+) -> Result<serde_json::Value, ApiError> {
+    // The address of a document does not tell whether it matches the filter of the API key.
+    api_keys.check_unrestricted(authorization_opt)?;
+    let hit_address = HitAddress::from_str(hit_address_str)
+        .map_err(|error| ApiError::InvalidArgument(error.to_string()))?;
+    let hit = search_service.root_fetch_doc(index_id, hit_address).await?;
+    let doc_json = serde_json::from_str(&hit.json).map_err(|error| {
+        ApiError::SearchError(SearchError::InternalError(format!(
+            "Failed to serialize document `{}` to JSON: `{}`.",
+            hit.json, error
+        )))
+    })?;
+    Ok(doc_json)
+}
+
+fn fetch_doc_filter(
+) -> impl Filter<Extract = (String, String, FetchDocQueryString), Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / String / "doc" / String)
+        .and(warp::get())
+        .and(serde_qs::warp::query(serde_qs::Config::default()))
+}
+
+async fn fetch_doc<TSearchService: SearchService>(
+    index_id: String,
+    hit_address_str: String,
+    request: FetchDocQueryString,
+    authorization_opt: Option<String>,
+    search_service: Arc<TSearchService>,
+    api_keys: Arc<ApiKeys>,
+) -> Result<impl warp::Reply, Infallible> {
+    info!(index_id = %index_id, hit_address = %hit_address_str, "fetch-doc");
+    Ok(request.format.make_reply(
+        fetch_doc_endpoint(
+            index_id,
+            &hit_address_str,
+            authorization_opt.as_deref(),
+            &*search_service,
+            &*api_keys,
+        )
+        .await,
+    ))
+}
+
+/// REST handler fetching the document of a hit address returned by the search API.
+pub fn fetch_doc_handler<TSearchService: SearchService>(
+    search_service: Arc<TSearchService>,
+    api_keys: Arc<ApiKeys>,
+) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+    fetch_doc_filter()
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+        .and(warp::any().map(move || search_service.clone()))
+        .and(warp::any().map(move || api_keys.clone()))
+        .and_then(fetch_doc)
+}
+
+/// Maximum size of the body of the requests to the SQL REST API.
+const MAX_SQL_BODY_NUM_BYTES: u64 = 64 * 1024;
+
+/// This struct represents the QueryString passed to
+/// the SQL REST API.
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SqlQueryString {
+    /// The output format.
+    #[serde(default)]
+    pub format: Format,
+}
+
+/// Body of the requests to the SQL REST API.
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SqlRequestBody {
+    /// Query in the subset of SQL described in [`SqlQuery`].
+    pub query: String,
+}
+
+async fn sql_endpoint<TSearchService: SearchService>(
+    sql_query: &SqlQuery,
+    authorization_opt: Option<&str>,
+    search_service: &TSearchService,
+    api_keys: &ApiKeys,
+) -> Result<SqlResponse, ApiError> {
+    let mut search_request = sql_query.search_request();
+    search_request.mandatory_filter = api_keys.mandatory_filter(authorization_opt)?;
+    search_request.max_num_splits = api_keys.max_num_splits(authorization_opt);
+    let search_response = search_service.root_search(search_request).await?;
+    Ok(sql_query.build_response(search_response)?)
+}
+
+fn sql_filter() -> impl Filter<Extract = (SqlQueryString, SqlRequestBody), Error = Rejection> + Clone
+{
+    warp::path!("api" / "v1" / "_sql")
+        .and(warp::post())
+        .and(serde_qs::warp::query(serde_qs::Config::default()))
+        .and(warp::body::content_length_limit(MAX_SQL_BODY_NUM_BYTES))
+        .and(warp::body::json())
+}
+
+async fn sql<TSearchService: SearchService>(
+    request: SqlQueryString,
+    body: SqlRequestBody,
+    authorization_opt: Option<String>,
+    search_service: Arc<TSearchService>,
+    audit_log_opt: Option<Arc<AuditLog>>,
+    api_keys: Arc<ApiKeys>,
+) -> Result<impl warp::Reply, Infallible> {
+    info!(query = %body.query, "sql");
+    let start = Instant::now();
+    let sql_query_res = SqlQuery::parse(&body.query);
+    let index_id = sql_query_res
+        .as_ref()
+        .map(|sql_query| sql_query.index_id().to_string())
+        .unwrap_or_default();
+    let sql_result = match sql_query_res {
+        Ok(sql_query) => {
+            sql_endpoint(
+                &sql_query,
+                authorization_opt.as_deref(),
+                &*search_service,
+                &*api_keys,
+            )
+            .await
+        }
+        Err(error) => Err(ApiError::SearchError(error)),
+    };
+    if let Some(audit_log) = audit_log_opt {
+        audit_log.record(AuditRecord::new(
+            "sql",
+            &index_id,
+            &body.query,
+            authorization_opt.as_deref(),
+            start.elapsed(),
+            sql_result
+                .as_ref()
+                .map(|sql_response| Some(sql_response.num_hits)),
+        ));
+    }
+    Ok(request.format.make_reply(sql_result))
+}
+
+/// REST handler running a query in a subset of SQL, given in the body of the request.
+pub fn sql_handler<TSearchService: SearchService>(
+    search_service: Arc<TSearchService>,
+    audit_log_opt: Option<Arc<AuditLog>>,
+    api_keys: Arc<ApiKeys>,
+) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+    sql_filter()
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
+        .and(warp::any().map(move || search_service.clone()))
+        .and(warp::any().map(move || audit_log_opt.clone()))
+        .and(warp::any().map(move || api_keys.clone()))
+        .and_then(sql)
+}
+
 /// This struct represents the search stream query passed to
 /// the REST API.
 #[derive(Deserialize, Debug, PartialEq, Eq)]
@@ -220,6 +803,15 @@ pub struct SearchStreamRequestQueryString {
     pub start_timestamp: Option<i64>,
     /// If set, restricts search to documents with a `timestamp < end_timestamp``.
     pub end_timestamp: Option<i64>,
+    /// Same as `start_timestamp`, given as a date such as `2021-06-01T12:00:00+02:00`,
+    /// or as a local date such as `2021-06-01` in the requested time zone.
+    pub start_time: Option<String>,
+    /// Same as `end_timestamp`, given as a date.
+    pub end_time: Option<String>,
+    /// Time zone of the local dates of the request: an offset from UTC such as `+02:00`,
+    /// or an IANA time zone name such as `Europe/Paris` (by default UTC).
+    #[serde(default)]
+    pub time_zone: Option<String>,
     /// The fast field to extract.
     #[serde(default)]
     pub fast_field: String,
@@ -232,23 +824,72 @@ pub struct SearchStreamRequestQueryString {
     #[serde(default)]
     #[serde(deserialize_with = "from_simple_list")]
     pub tags: Option<Vec<String>>,
+    /// If set, the estimated number of distinct values of the fast field is returned
+    /// instead of the values themselves.
+    #[serde(default)]
+    pub distinct_count: bool,
+    /// If set, the values are inserted into the ClickHouse sink configured on the node under
+    /// this name instead of being returned.
+    #[serde(default)]
+    pub click_house_sink: Option<String>,
+    /// If set, the terms of the query are not expanded into the synonyms of the index.
+    #[serde(default)]
+    pub disable_synonyms: bool,
+    /// Comma-separated columns computed from the fast fields and returned after the fast
+    /// field, defined as `name=expression`.
+    #[serde(default)]
+    #[serde(deserialize_with = "from_simple_list")]
+    pub computed_fields: Option<Vec<String>>,
+    /// Whether the caches of the searchers are used (`use`, by default), bypassed
+    /// (`bypass`), or refreshed (`refresh`) by the search.
+    #[serde(default)]
+    pub cache: CachePolicy,
 }
 
 async fn search_stream_endpoint<TSearchService: SearchService>(
     index_id: String,
     search_request: SearchStreamRequestQueryString,
+    authorization_opt: Option<&str>,
     search_service: &TSearchService,
+    api_keys: &ApiKeys,
+    click_house_sinks: &ClickHouseSinks,
 ) -> Result<hyper::Body, ApiError> {
+    let mandatory_filter = api_keys.mandatory_filter(authorization_opt)?;
+    let click_house_sink = search_request
+        .click_house_sink
+        .as_deref()
+        .map(|sink_name| click_house_sinks.resolve(sink_name))
+        .transpose()?;
+    let time_zone = search_request.time_zone.unwrap_or_default();
+    let start_timestamp = resolve_timestamp(
+        search_request.start_timestamp,
+        search_request.start_time.as_deref(),
+        &time_zone,
+        "start",
+    )?;
+    let end_timestamp = resolve_timestamp(
+        search_request.end_timestamp,
+        search_request.end_time.as_deref(),
+        &time_zone,
+        "end",
+    )?;
     let request = quickwit_proto::SearchStreamRequest {
         index_id,
         query: search_request.query,
         search_fields: search_request.search_fields.unwrap_or_default(),
-        start_timestamp: search_request.start_timestamp,
-        end_timestamp: search_request.end_timestamp,
+        start_timestamp,
+        end_timestamp,
         fast_field: search_request.fast_field,
         output_format: search_request.output_format as i32,
         tags: search_request.tags.unwrap_or_default(),
         partition_by_field: search_request.partition_by_field,
+        distinct_count: search_request.distinct_count,
+        click_house_sink,
+        time_zone,
+        mandatory_filter,
+        disable_synonyms: search_request.disable_synonyms,
+        computed_fields: search_request.computed_fields.unwrap_or_default(),
+        cache_policy: search_request.cache as i32,
     };
     let data = search_service.root_search_stream(request).await?;
     let stream = stream::iter(data).map(Result::<Bytes, std::io::Error>::Ok);
@@ -274,15 +915,42 @@ fn make_streaming_reply(result: Result<hyper::Body, ApiError>) -> impl Reply {
 async fn search_stream<TSearchService: SearchService>(
     index_id: String,
     request: SearchStreamRequestQueryString,
+    authorization_opt: Option<String>,
     search_service: Arc<TSearchService>,
+    audit_log_opt: Option<Arc<AuditLog>>,
+    api_keys: Arc<ApiKeys>,
+    click_house_sinks: Arc<ClickHouseSinks>,
 ) -> Result<impl warp::Reply, Infallible> {
     info!(index_id=%index_id,request=?request, "search_stream");
     let content_type = match request.output_format {
-        OutputFormat::ClickHouseRowBinary => "application/octet-stream",
+        OutputFormat::ClickHouseRowBinary | OutputFormat::ClickHouseNative => {
+            "application/octet-stream"
+        }
         OutputFormat::Csv => "text/csv",
+        OutputFormat::Parquet => "application/vnd.apache.parquet",
     };
-    let reply =
-        make_streaming_reply(search_stream_endpoint(index_id, request, &*search_service).await);
+    let query = request.query.clone();
+    let start = Instant::now();
+    let search_stream_result = search_stream_endpoint(
+        index_id.clone(),
+        request,
+        authorization_opt.as_deref(),
+        &*search_service,
+        &*api_keys,
+        &*click_house_sinks,
+    )
+    .await;
+    if let Some(audit_log) = audit_log_opt {
+        audit_log.record(AuditRecord::new(
+            "search_stream",
+            &index_id,
+            &query,
+            authorization_opt.as_deref(),
+            start.elapsed(),
+            search_stream_result.as_ref().map(|_| None),
+        ));
+    }
+    let reply = make_streaming_reply(search_stream_result);
     let reply_with_header = reply::with_header(reply, CONTENT_TYPE, content_type);
     Ok(reply_with_header)
 }
@@ -296,15 +964,36 @@ fn search_stream_filter(
 
 pub fn search_stream_handler<TSearchService: SearchService>(
     search_service: Arc<TSearchService>,
+    audit_log_opt: Option<Arc<AuditLog>>,
+    api_keys: Arc<ApiKeys>,
+    click_house_sinks: Arc<ClickHouseSinks>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
     search_stream_filter()
+        .and(warp::header::optional::<String>(AUTHORIZATION.as_str()))
         .and(warp::any().map(move || search_service.clone()))
+        .and(warp::any().map(move || audit_log_opt.clone()))
+        .and(warp::any().map(move || api_keys.clone()))
+        .and(warp::any().map(move || click_house_sinks.clone()))
         .and_then(search_stream)
 }
 
 /// This function returns a formated error based on the given rejection reason.
 async fn recover_fn(rejection: Rejection) -> Result<impl Reply, Rejection> {
     // TODO handle more errors.
+    if let Some(quota_exceeded) = rejection.find::<QuotaExceeded>() {
+        return Ok(
+            Format::PrettyJson.make_reply(Err::<(), ApiError>(ApiError::QuotaExceeded(
+                quota_exceeded.clone(),
+            ))),
+        );
+    }
+    if let Some(body_error) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        return Ok(
+            Format::PrettyJson.make_reply(Err::<(), ApiError>(ApiError::InvalidArgument(
+                body_error.to_string(),
+            ))),
+        );
+    }
     match rejection.find::<serde_qs::Error>() {
         Some(err) => {
             // The querystring was incorrect.
@@ -318,7 +1007,7 @@ async fn recover_fn(rejection: Rejection) -> Result<impl Reply, Rejection> {
     }
 }
 
-fn from_simple_list<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+pub(crate) fn from_simple_list<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
 where D: Deserializer<'de> {
     let str_sequence = String::deserialize(deserializer)?;
     Ok(Some(
@@ -330,27 +1019,85 @@ where D: Deserializer<'de> {
     ))
 }
 
+fn from_simple_f64_list<'de, D>(deserializer: D) -> Result<Option<Vec<f64>>, D::Error>
+where D: Deserializer<'de> {
+    let str_sequence = String::deserialize(deserializer)?;
+    let values = str_sequence
+        .trim_matches(',')
+        .split(',')
+        .map(|item| {
+            item.parse::<f64>()
+                .map_err(|_| serde::de::Error::custom(format!("`{}` is not a valid number", item)))
+        })
+        .collect::<Result<Vec<f64>, D::Error>>()?;
+    Ok(Some(values))
+}
+
+fn from_terms_order<'de, D>(deserializer: D) -> Result<Option<(TermsOrder, f64)>, D::Error>
+where D: Deserializer<'de> {
+    let terms_order = String::deserialize(deserializer)?;
+    let order_and_percentile = match terms_order.as_str() {
+        "count" => (TermsOrder::Count, 0.0),
+        "sum" => (TermsOrder::Sum, 0.0),
+        "avg" => (TermsOrder::Avg, 0.0),
+        "min" => (TermsOrder::Min, 0.0),
+        "max" => (TermsOrder::Max, 0.0),
+        _ => {
+            let percentile = terms_order
+                .strip_prefix('p')
+                .and_then(|percentile| percentile.parse::<f64>().ok())
+                .ok_or_else(|| {
+                    serde::de::Error::custom(format!(
+                        "`{}` is not a valid terms order, expected `count`, `sum`, `avg`, `min`, \
+                         `max` or a percentile such as `p99`",
+                        terms_order
+                    ))
+                })?;
+            (TermsOrder::Percentile, percentile)
+        }
+    };
+    Ok(Some(order_and_percentile))
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::time::Duration;
+
     use assert_json_diff::assert_json_include;
     use mockall::predicate;
-    use quickwit_search::{MockSearchService, SearchError};
+    use quickwit_common::QuickwitUri;
+    use quickwit_index_config::WikipediaIndexConfig;
+    use quickwit_metastore::checkpoint::Checkpoint;
+    use quickwit_metastore::{IndexMetadata, MockMetastore, SingleFileMetastore};
+    use quickwit_search::MockSearchService;
+    use quickwit_storage::StorageUriResolver;
     use serde_json::json;
 
     use super::*;
+    use crate::{AuditLogConfig, ClickHouseSinkConfig};
 
     #[test]
     fn test_serialize_search_response() -> anyhow::Result<()> {
         let search_response = SearchResponseRest {
             num_hits: 55,
             hits: Vec::new(),
+            hit_addresses: Vec::new(),
             elapsed_time_micros: 0u64,
+            distinct_counts: vec![("user".to_string(), 3)].into_iter().collect(),
+            percentiles: BTreeMap::new(),
+            terms_aggregation: None,
+            date_histogram: Vec::new(),
+            warnings: Vec::new(),
+            session_token: String::new(),
+            quarantined_split_ids: Vec::new(),
         };
         let search_response_json: serde_json::Value = serde_json::to_value(&search_response)?;
         let expected_search_response_json: serde_json::Value = json!({
             "numHits": 55,
             "hits": [],
             "elapsedTimeMicros": 0,
+            "distinctCounts": {"user": 3},
         });
         assert_json_include!(
             actual: search_response_json,
@@ -378,10 +1125,35 @@ mod tests {
                 search_fields: None,
                 start_timestamp: None,
                 end_timestamp: Some(1450720000),
+                start_time: None,
+                end_time: None,
+                time_zone: None,
                 max_hits: 10,
                 start_offset: 22,
                 format: Format::default(),
-                tags: None
+                tags: None,
+                distinct_count_fields: None,
+                percentile_fields: None,
+                percentiles: None,
+                terms_field: None,
+                terms_size: 10,
+                terms_shard_size: None,
+                terms_order: None,
+                terms_metric_field: None,
+                terms_ascending: false,
+                date_histogram_field: None,
+                date_histogram_interval: "day".to_string(),
+                geo_field: None,
+                geo_bounding_box: None,
+                geo_distance: None,
+                flatten_hits: false,
+                disable_synonyms: false,
+                session_token: None,
+                min_publish_timestamp: None,
+                cache: CachePolicy::Use,
+                sample_interval: None,
+                sample_size: 1,
+                sample_first: false,
             }
         );
     }
@@ -405,10 +1177,35 @@ mod tests {
                 search_fields: Some(vec!["title".to_string(), "body".to_string()]),
                 start_timestamp: None,
                 end_timestamp: Some(1450720000),
+                start_time: None,
+                end_time: None,
+                time_zone: None,
                 max_hits: 20,
                 start_offset: 0,
                 format: Format::default(),
-                tags: None
+                tags: None,
+                distinct_count_fields: None,
+                percentile_fields: None,
+                percentiles: None,
+                terms_field: None,
+                terms_size: 10,
+                terms_shard_size: None,
+                terms_order: None,
+                terms_metric_field: None,
+                terms_ascending: false,
+                date_histogram_field: None,
+                date_histogram_interval: "day".to_string(),
+                geo_field: None,
+                geo_bounding_box: None,
+                geo_distance: None,
+                flatten_hits: false,
+                disable_synonyms: false,
+                session_token: None,
+                min_publish_timestamp: None,
+                cache: CachePolicy::Use,
+                sample_interval: None,
+                sample_size: 1,
+                sample_first: false,
             }
         );
     }
@@ -428,20 +1225,120 @@ mod tests {
                 query: "*".to_string(),
                 start_timestamp: None,
                 end_timestamp: None,
+                start_time: None,
+                end_time: None,
+                time_zone: None,
                 max_hits: 20,
                 start_offset: 0,
                 format: Format::Json,
                 search_fields: None,
-                tags: None
+                tags: None,
+                distinct_count_fields: None,
+                percentile_fields: None,
+                percentiles: None,
+                terms_field: None,
+                terms_size: 10,
+                terms_shard_size: None,
+                terms_order: None,
+                terms_metric_field: None,
+                terms_ascending: false,
+                date_histogram_field: None,
+                date_histogram_interval: "day".to_string(),
+                geo_field: None,
+                geo_bounding_box: None,
+                geo_distance: None,
+                flatten_hits: false,
+                disable_synonyms: false,
+                session_token: None,
+                min_publish_timestamp: None,
+                cache: CachePolicy::Use,
+                sample_interval: None,
+                sample_size: 1,
+                sample_first: false,
             }
         );
     }
 
+    #[tokio::test]
+    async fn test_rest_search_api_route_distinct_count_fields() {
+        let rest_search_api_filter = search_filter();
+        let (_, req) = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*&distinctCountFields=user,host")
+            .filter(&rest_search_api_filter)
+            .await
+            .unwrap();
+        assert_eq!(
+            req.distinct_count_fields,
+            Some(vec!["user".to_string(), "host".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_route_percentiles() {
+        let rest_search_api_filter = search_filter();
+        let (_, req) = warp::test::request()
+            .path(
+                "/api/v1/quickwit-demo-index/search?query=*&percentileFields=latency&\
+                 percentiles=50,99.9",
+            )
+            .filter(&rest_search_api_filter)
+            .await
+            .unwrap();
+        assert_eq!(req.percentile_fields, Some(vec!["latency".to_string()]));
+        assert_eq!(req.percentiles, Some(vec![50.0, 99.9]));
+
+        let rejection = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*&percentiles=p99")
+            .filter(&rest_search_api_filter)
+            .await
+            .unwrap_err();
+        assert!(rejection.find::<serde_qs::Error>().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_route_terms_aggregation() {
+        let rest_search_api_filter = search_filter();
+        let (_, req) = warp::test::request()
+            .path(
+                "/api/v1/quickwit-demo-index/search?query=*&termsField=endpoint&termsOrder=p99.9&\
+                 termsMetricField=latency&termsShardSize=100",
+            )
+            .filter(&rest_search_api_filter)
+            .await
+            .unwrap();
+        assert_eq!(req.terms_field, Some("endpoint".to_string()));
+        assert_eq!(req.terms_size, 10);
+        assert_eq!(req.terms_shard_size, Some(100));
+        assert_eq!(req.terms_order, Some((TermsOrder::Percentile, 99.9)));
+        assert_eq!(req.terms_metric_field, Some("latency".to_string()));
+
+        let (_, req) = warp::test::request()
+            .path(
+                "/api/v1/quickwit-demo-index/search?query=*&termsField=endpoint&termsOrder=sum&\
+                 termsAscending=true",
+            )
+            .filter(&rest_search_api_filter)
+            .await
+            .unwrap();
+        assert_eq!(req.terms_order, Some((TermsOrder::Sum, 0.0)));
+        assert!(req.terms_ascending);
+
+        let rejection = warp::test::request()
+            .path(
+                "/api/v1/quickwit-demo-index/search?query=*&termsField=endpoint&termsOrder=median",
+            )
+            .filter(&rest_search_api_filter)
+            .await
+            .unwrap_err();
+        assert!(rejection.find::<serde_qs::Error>().is_some());
+    }
+
     #[tokio::test]
     async fn test_rest_search_api_route_invalid_key() -> anyhow::Result<()> {
         let mock_search_service = MockSearchService::new();
         let rest_search_api_handler =
-            super::search_handler(Arc::new(mock_search_service)).recover(recover_fn);
+            super::search_handler(Arc::new(mock_search_service), None, Default::default())
+                .recover(recover_fn);
         let resp = warp::test::request()
             .path("/api/v1/quickwit-demo-index/search?query=*&endUnixTimestamp=1450720000")
             .reply(&rest_search_api_handler)
@@ -449,7 +1346,7 @@ mod tests {
         assert_eq!(resp.status(), 400);
         let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
         let exp_resp_json = serde_json::json!({
-            "error": "InvalidArgument: failed with reason: unknown field `endUnixTimestamp`, expected one of `query`, `searchField`, `startTimestamp`, `endTimestamp`, `maxHits`, `startOffset`, `format`, `tags`."
+            "error": "InvalidArgument: failed with reason: unknown field `endUnixTimestamp`, expected one of `query`, `searchField`, `startTimestamp`, `endTimestamp`, `startTime`, `endTime`, `timeZone`, `maxHits`, `startOffset`, `format`, `tags`, `distinctCountFields`, `percentileFields`, `percentiles`, `termsField`, `termsSize`, `termsShardSize`, `termsOrder`, `termsMetricField`, `termsAscending`, `dateHistogramField`, `dateHistogramInterval`."
         });
         assert_eq!(resp_json, exp_resp_json);
         Ok(())
@@ -464,10 +1361,21 @@ mod tests {
                 num_hits: 10,
                 elapsed_time_micros: 16,
                 errors: vec![],
+                distinct_counts: HashMap::new(),
+                percentiles: HashMap::new(),
+                terms_aggregation: None,
+                date_histogram: vec![],
+                warnings: vec![quickwit_proto::QueryWarning {
+                    clause: "status:OK".to_string(),
+                    message: "The clause cannot match any document.".to_string(),
+                }],
+                session_token: String::new(),
+                quarantined_split_ids: Vec::new(),
             })
         });
         let rest_search_api_handler =
-            super::search_handler(Arc::new(mock_search_service)).recover(recover_fn);
+            super::search_handler(Arc::new(mock_search_service), None, Default::default())
+                .recover(recover_fn);
         let resp = warp::test::request()
             .path("/api/v1/quickwit-demo-index/search?query=*")
             .reply(&rest_search_api_handler)
@@ -478,53 +1386,483 @@ mod tests {
             "numHits": 10,
             "hits": [],
             "elapsedTimeMicros": 16,
+            "warnings": [{
+                "clause": "status:OK",
+                "message": "The clause cannot match any document.",
+            }],
         });
         assert_json_include!(actual: resp_json, expected: expected_response_json);
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_rest_search_api_start_offset_and_num_hits_parameter() -> anyhow::Result<()> {
+    async fn test_rest_search_api_audit_log() -> anyhow::Result<()> {
         let mut mock_search_service = MockSearchService::new();
         mock_search_service
             .expect_root_search()
-            .with(predicate::function(
-                |search_request: &quickwit_proto::SearchRequest| {
-                    search_request.start_offset == 5 && search_request.max_hits == 30
-                },
-            ))
             .returning(|_| Ok(Default::default()));
-        let rest_search_api_handler =
-            super::search_handler(Arc::new(mock_search_service)).recover(recover_fn);
-        assert_eq!(
-            warp::test::request()
-                .path("/api/v1/quickwit-demo-index/search?query=*&startOffset=5&maxHits=30")
-                .reply(&rest_search_api_handler)
-                .await
-                .status(),
-            200
-        );
+        let audit_log_config = AuditLogConfig {
+            index_uri: "ram://indexes/audit-log".to_string(),
+            flush_interval: Duration::from_secs(3_600),
+        };
+        let audit_log = AuditLog::start(
+            &audit_log_config,
+            Arc::new(SingleFileMetastore::for_test()),
+            StorageUriResolver::for_test(),
+        )
+        .await?;
+        let rest_search_api_handler = super::search_handler(
+            Arc::new(mock_search_service),
+            Some(audit_log.clone()),
+            Default::default(),
+        )
+        .recover(recover_fn);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*")
+            .header("Authorization", "Bearer my-token")
+            .reply(&rest_search_api_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*&startTime=yesterday")
+            .reply(&rest_search_api_handler)
+            .await;
+        assert_eq!(resp.status(), 400);
+        assert_eq!(audit_log.flush().await?, 2);
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_rest_search_api_with_index_does_not_exist() -> anyhow::Result<()> {
+    async fn test_rest_search_api_with_api_keys() -> anyhow::Result<()> {
         let mut mock_search_service = MockSearchService::new();
-        mock_search_service.expect_root_search().returning(|_| {
-            Err(SearchError::IndexDoesNotExist {
-                index_id: "not-found-index".to_string(),
-            })
-        });
-        let rest_search_api_handler =
-            super::search_handler(Arc::new(mock_search_service)).recover(recover_fn);
-        assert_eq!(
-            warp::test::request()
-                .path("/api/v1/index-does-not-exist/search?query=myfield:test")
-                .reply(&rest_search_api_handler)
-                .await
-                .status(),
-            404
-        );
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| {
+                    search_request.mandatory_filter.as_deref() == Some("tenant_id:acme")
+                },
+            ))
+            .times(1)
+            .returning(|_| Ok(Default::default()));
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| {
+                    search_request.mandatory_filter.is_none()
+                },
+            ))
+            .times(1)
+            .returning(|_| Ok(Default::default()));
+        let mock_search_service = Arc::new(mock_search_service);
+        let api_keys = Arc::new(ApiKeys::new(vec![
+            "admin".parse()?,
+            "acme=tenant_id:acme".parse()?,
+        ]));
+        let rest_search_api_handler =
+            super::search_handler(mock_search_service.clone(), None, api_keys.clone())
+                .recover(recover_fn);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*")
+            .header("Authorization", "Bearer acme")
+            .reply(&rest_search_api_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*")
+            .header("Authorization", "Bearer admin")
+            .reply(&rest_search_api_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*")
+            .reply(&rest_search_api_handler)
+            .await;
+        assert_eq!(resp.status(), 401);
+
+        let rest_fetch_doc_api_handler =
+            super::fetch_doc_handler(mock_search_service, api_keys).recover(recover_fn);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/doc/split1:0:3:1")
+            .header("Authorization", "Bearer acme")
+            .reply(&rest_fetch_doc_api_handler)
+            .await;
+        assert_eq!(resp.status(), 403);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_estimate_api() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_estimate()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| {
+                    search_request.query == "severity:ERROR"
+                        && search_request.start_timestamp == Some(1450720000)
+                        && search_request.mandatory_filter.as_deref() == Some("tenant_id:acme")
+                },
+            ))
+            .times(1)
+            .returning(|_| {
+                Ok(SearchEstimate {
+                    num_splits: 2,
+                    num_docs: 1_000,
+                    num_bytes: 20_000,
+                    start_timestamp: Some(1450720000),
+                    end_timestamp: Some(1450730000),
+                    num_searcher_nodes: 1,
+                    estimated_latency_millis: 50,
+                    warnings: Vec::new(),
+                })
+            });
+        let api_keys = Arc::new(ApiKeys::new(vec!["acme=tenant_id:acme".parse()?]));
+        let rest_search_estimate_api_handler =
+            super::search_estimate_handler(Arc::new(mock_search_service), api_keys)
+                .recover(recover_fn);
+        let resp = warp::test::request()
+            .path(
+                "/api/v1/quickwit-demo-index/search/estimate?query=severity:ERROR&\
+                 startTimestamp=1450720000",
+            )
+            .header("Authorization", "Bearer acme")
+            .reply(&rest_search_estimate_api_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        let expected_response_json = serde_json::json!({
+            "numSplits": 2,
+            "numDocs": 1_000,
+            "numBytes": 20_000,
+            "startTimestamp": 1450720000,
+            "endTimestamp": 1450730000,
+            "numSearcherNodes": 1,
+            "estimatedLatencyMillis": 50,
+            "warnings": [],
+        });
+        assert_eq!(resp_json, expected_response_json);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search/estimate?query=*")
+            .reply(&rest_search_estimate_api_handler)
+            .await;
+        assert_eq!(resp.status(), 401);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_flatten_hits() {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| search_request.flatten_hits,
+            ))
+            .times(1)
+            .returning(|_| Ok(Default::default()));
+        let rest_search_api_handler = super::search_handler(
+            Arc::new(mock_search_service),
+            None,
+            Arc::new(ApiKeys::default()),
+        )
+        .recover(recover_fn);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*&flattenHits=true")
+            .reply(&rest_search_api_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_disable_synonyms() {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| search_request.disable_synonyms,
+            ))
+            .times(1)
+            .returning(|_| Ok(Default::default()));
+        let rest_search_api_handler = super::search_handler(
+            Arc::new(mock_search_service),
+            None,
+            Arc::new(ApiKeys::default()),
+        )
+        .recover(recover_fn);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*&disableSynonyms=true")
+            .reply(&rest_search_api_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_session_token() {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| {
+                    search_request.session_token.as_deref() == Some("1622548800:00000000000000ff")
+                },
+            ))
+            .times(1)
+            .returning(|_| {
+                Ok(quickwit_proto::SearchResponse {
+                    session_token: "1622548800:00000000000000ff".to_string(),
+                    ..Default::default()
+                })
+            });
+        let rest_search_api_handler = super::search_handler(
+            Arc::new(mock_search_service),
+            None,
+            Arc::new(ApiKeys::default()),
+        )
+        .recover(recover_fn);
+        let resp = warp::test::request()
+            .path(
+                "/api/v1/quickwit-demo-index/search?query=*&sessionToken=1622548800:\
+                 00000000000000ff",
+            )
+            .reply(&rest_search_api_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(resp_json["sessionToken"], "1622548800:00000000000000ff");
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_min_publish_timestamp() {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| {
+                    search_request.min_publish_timestamp == Some(1622548800)
+                },
+            ))
+            .times(1)
+            .returning(|_| Ok(Default::default()));
+        let rest_search_api_handler = super::search_handler(
+            Arc::new(mock_search_service),
+            None,
+            Arc::new(ApiKeys::default()),
+        )
+        .recover(recover_fn);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*&minPublishTimestamp=1622548800")
+            .reply(&rest_search_api_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_cache_policy() {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| {
+                    search_request.cache_policy == CachePolicy::Bypass as i32
+                },
+            ))
+            .times(1)
+            .returning(|_| Ok(Default::default()));
+        let rest_search_api_handler = super::search_handler(
+            Arc::new(mock_search_service),
+            None,
+            Arc::new(ApiKeys::default()),
+        )
+        .recover(recover_fn);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*&cache=bypass")
+            .reply(&rest_search_api_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp = warp::test::request()
+            .path("/api/v1/quickwit-demo-index/search?query=*&cache=cold")
+            .reply(&rest_search_api_handler)
+            .await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_start_offset_and_num_hits_parameter() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| {
+                    search_request.start_offset == 5 && search_request.max_hits == 30
+                },
+            ))
+            .returning(|_| Ok(Default::default()));
+        let rest_search_api_handler =
+            super::search_handler(Arc::new(mock_search_service), None, Default::default())
+                .recover(recover_fn);
+        assert_eq!(
+            warp::test::request()
+                .path("/api/v1/quickwit-demo-index/search?query=*&startOffset=5&maxHits=30")
+                .reply(&rest_search_api_handler)
+                .await
+                .status(),
+            200
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_hit_sampling() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| {
+                    search_request.hit_sampling
+                        == Some(HitSampling {
+                            interval: "1m".to_string(),
+                            max_hits_per_bucket: 2,
+                            first: true,
+                        })
+                },
+            ))
+            .returning(|_| Ok(Default::default()));
+        let rest_search_api_handler =
+            super::search_handler(Arc::new(mock_search_service), None, Default::default())
+                .recover(recover_fn);
+        assert_eq!(
+            warp::test::request()
+                .path(
+                    "/api/v1/quickwit-demo-index/search?query=*&sampleInterval=1m&sampleSize=2&\
+                     sampleFirst=true"
+                )
+                .reply(&rest_search_api_handler)
+                .await
+                .status(),
+            200
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_time_zone() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| {
+                    search_request.start_timestamp == Some(1_622_498_400)
+                        && search_request.end_timestamp == Some(1_622_548_800)
+                        && search_request.time_zone == "Europe/Paris"
+                        && search_request.date_histogram
+                            == Some(DateHistogramAggregation {
+                                field_name: "timestamp".to_string(),
+                                interval: "1h".to_string(),
+                            })
+                },
+            ))
+            .returning(|_| Ok(Default::default()));
+        let rest_search_api_handler =
+            super::search_handler(Arc::new(mock_search_service), None, Default::default())
+                .recover(recover_fn);
+        assert_eq!(
+            warp::test::request()
+                .path(
+                    "/api/v1/quickwit-demo-index/search?query=*&timeZone=Europe/Paris&\
+                     startTime=2021-06-01&endTime=2021-06-01T14:00:00&\
+                     dateHistogramField=timestamp&dateHistogramInterval=1h"
+                )
+                .reply(&rest_search_api_handler)
+                .await
+                .status(),
+            200
+        );
+        for invalid_query_string in &[
+            "startTime=2021-06-01&startTimestamp=1622498400",
+            "startTime=yesterday",
+            "endTime=2021-06-01&timeZone=Mars/Olympus_Mons",
+        ] {
+            let response = warp::test::request()
+                .path(&format!(
+                    "/api/v1/quickwit-demo-index/search?query=*&{}",
+                    invalid_query_string
+                ))
+                .reply(&rest_search_api_handler)
+                .await;
+            assert_eq!(response.status(), 400);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_geo_filter() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| {
+                    search_request.geo_filter
+                        == Some(GeoFilter {
+                            field_name: "location".to_string(),
+                            bounding_box: Some(GeoBoundingBox {
+                                top_lat: 49.0,
+                                left_lon: 2.0,
+                                bottom_lat: 48.5,
+                                right_lon: 2.5,
+                            }),
+                            distance: None,
+                        })
+                },
+            ))
+            .returning(|_| Ok(Default::default()));
+        let rest_search_api_handler =
+            super::search_handler(Arc::new(mock_search_service), None, Default::default())
+                .recover(recover_fn);
+        assert_eq!(
+            warp::test::request()
+                .path(
+                    "/api/v1/quickwit-demo-index/search?query=*&geoField=location&\
+                     geoBoundingBox=49,2,48.5,2.5"
+                )
+                .reply(&rest_search_api_handler)
+                .await
+                .status(),
+            200
+        );
+        for invalid_query_string in &[
+            "geoBoundingBox=49,2,48.5,2.5",
+            "geoField=location&geoBoundingBox=49,2,48.5",
+            "geoField=location&geoDistance=48.8,2.3",
+        ] {
+            let response = warp::test::request()
+                .path(&format!(
+                    "/api/v1/quickwit-demo-index/search?query=*&{}",
+                    invalid_query_string
+                ))
+                .reply(&rest_search_api_handler)
+                .await;
+            assert_eq!(response.status(), 400);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_api_with_index_does_not_exist() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service.expect_root_search().returning(|_| {
+            Err(SearchError::IndexDoesNotExist {
+                index_id: "not-found-index".to_string(),
+            })
+        });
+        let rest_search_api_handler =
+            super::search_handler(Arc::new(mock_search_service), None, Default::default())
+                .recover(recover_fn);
+        assert_eq!(
+            warp::test::request()
+                .path("/api/v1/index-does-not-exist/search?query=myfield:test")
+                .reply(&rest_search_api_handler)
+                .await
+                .status(),
+            404
+        );
         Ok(())
     }
 
@@ -535,7 +1873,8 @@ mod tests {
             .expect_root_search()
             .returning(|_| Err(SearchError::InternalError("ty".to_string())));
         let rest_search_api_handler =
-            super::search_handler(Arc::new(mock_search_service)).recover(recover_fn);
+            super::search_handler(Arc::new(mock_search_service), None, Default::default())
+                .recover(recover_fn);
         assert_eq!(
             warp::test::request()
                 .path("/api/v1/index-does-not-exist/search?query=myfield:test")
@@ -554,7 +1893,8 @@ mod tests {
             .expect_root_search()
             .returning(|_| Err(SearchError::InvalidQuery("invalid query".to_string())));
         let rest_search_api_handler =
-            super::search_handler(Arc::new(mock_search_service)).recover(recover_fn);
+            super::search_handler(Arc::new(mock_search_service), None, Default::default())
+                .recover(recover_fn);
         assert_eq!(
             warp::test::request()
                 .path("/api/v1/my-index/search?query=myfield:test")
@@ -566,14 +1906,117 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_rest_fetch_doc_api() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service.expect_root_fetch_doc().returning(
+            |index_id: String, hit_address: HitAddress| {
+                assert_eq!(index_id, "my-index");
+                if hit_address.split_id != "split1" {
+                    return Err(SearchError::DocDoesNotExist {
+                        address: hit_address.to_string(),
+                    });
+                }
+                Ok(quickwit_proto::Hit {
+                    json: r#"{"title": "foo"}"#.to_string(),
+                    partial_hit: Some(hit_address.to_partial_hit()),
+                    address: hit_address.to_string(),
+                })
+            },
+        );
+        let rest_fetch_doc_api_handler =
+            super::fetch_doc_handler(Arc::new(mock_search_service), Default::default())
+                .recover(recover_fn);
+        let resp = warp::test::request()
+            .path("/api/v1/my-index/doc/split1:0:3:1?format=json")
+            .reply(&rest_fetch_doc_api_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_eq!(resp_json, json!({"title": "foo"}));
+
+        let resp = warp::test::request()
+            .path("/api/v1/my-index/doc/split2:0:3:1")
+            .reply(&rest_fetch_doc_api_handler)
+            .await;
+        assert_eq!(resp.status(), 404);
+
+        let resp = warp::test::request()
+            .path("/api/v1/my-index/doc/split1:0:3")
+            .reply(&rest_fetch_doc_api_handler)
+            .await;
+        assert_eq!(resp.status(), 400);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_fetch_doc_api_through_search_routes() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_fetch_doc()
+            .times(1)
+            .returning(|_index_id: String, hit_address: HitAddress| {
+                Ok(quickwit_proto::Hit {
+                    json: r#"{"title": "foo"}"#.to_string(),
+                    partial_hit: Some(hit_address.to_partial_hit()),
+                    address: hit_address.to_string(),
+                })
+            });
+        let mut metastore = MockMetastore::new();
+        metastore
+            .expect_index_metadata()
+            .times(1)
+            .returning(|index_id: &str| {
+                Ok(IndexMetadata {
+                    index_id: index_id.to_string(),
+                    index_uri: QuickwitUri::from_well_formed("ram:///indexes/my-index"),
+                    index_config: Arc::new(WikipediaIndexConfig::new()),
+                    checkpoint: Checkpoint::default(),
+                    index_config_history: Vec::new(),
+                    mirror_index_uri: None,
+                    read_only: false,
+                    description: None,
+                    labels: Default::default(),
+                    create_timestamp: 0,
+                })
+            });
+        let quota_service = Arc::new(QuotaService::new(crate::QuotaConfig {
+            max_qps: Some(10),
+            ..Default::default()
+        }));
+        let search_routes = super::search_routes(
+            Arc::new(mock_search_service),
+            quota_service,
+            Arc::new(metastore),
+            None,
+            Default::default(),
+            Default::default(),
+        )
+        .recover(recover_fn);
+        let resp = warp::test::request()
+            .path("/api/v1/my-index/doc/split1:0:3:1?format=json")
+            .reply(&search_routes)
+            .await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers()["x-quota-qps-remaining"], "9");
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_eq!(resp_json, json!({"title": "foo"}));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_rest_search_stream_api() -> anyhow::Result<()> {
         let mut mock_search_service = MockSearchService::new();
         mock_search_service
             .expect_root_search_stream()
             .return_once(|_| Ok(vec![Bytes::from("first row\n"), Bytes::from("second row")]));
-        let rest_search_stream_api_handler =
-            super::search_stream_handler(Arc::new(mock_search_service)).recover(recover_fn);
+        let rest_search_stream_api_handler = super::search_stream_handler(
+            Arc::new(mock_search_service),
+            None,
+            Default::default(),
+            Default::default(),
+        )
+        .recover(recover_fn);
         let response = warp::test::request()
             .path(
                 "/api/v1/my-index/search/stream?query=obama&fastField=external_id&outputFormat=csv",
@@ -603,10 +2046,18 @@ mod tests {
                 search_fields: None,
                 start_timestamp: None,
                 end_timestamp: None,
+                start_time: None,
+                end_time: None,
+                time_zone: None,
                 fast_field: "external_id".to_string(),
                 output_format: OutputFormat::Csv,
                 partition_by_field: None,
-                tags: None
+                tags: None,
+                distinct_count: false,
+                click_house_sink: None,
+                disable_synonyms: false,
+                computed_fields: None,
+                cache: CachePolicy::Use,
             }
         );
     }
@@ -616,7 +2067,7 @@ mod tests {
         let (index, req) = warp::test::request()
             .path(
                 "/api/v1/my-index/search/stream?query=obama&fastField=external_id&\
-                 outputFormat=clickHouseRowBinary&tags=lang:english",
+                 outputFormat=clickHouseRowBinary&tags=lang:english&cache=refresh",
             )
             .filter(&super::search_stream_filter())
             .await
@@ -629,14 +2080,127 @@ mod tests {
                 search_fields: None,
                 start_timestamp: None,
                 end_timestamp: None,
+                start_time: None,
+                end_time: None,
+                time_zone: None,
                 fast_field: "external_id".to_string(),
                 output_format: OutputFormat::ClickHouseRowBinary,
                 partition_by_field: None,
-                tags: Some(vec!["lang:english".to_string()])
+                tags: Some(vec!["lang:english".to_string()]),
+                distinct_count: false,
+                click_house_sink: None,
+                disable_synonyms: false,
+                computed_fields: None,
+                cache: CachePolicy::Refresh,
             }
         );
     }
 
+    #[tokio::test]
+    async fn test_rest_search_stream_api_computed_fields() {
+        let (_index, req) = warp::test::request()
+            .path(
+                "/api/v1/my-index/search/stream?query=obama&fastField=ts&computedFields=minute%\
+                 3Dts-ts%2560,latency_ms%3Dlatency_us/1000",
+            )
+            .filter(&super::search_stream_filter())
+            .await
+            .unwrap();
+        assert_eq!(
+            req.computed_fields,
+            Some(vec![
+                "minute=ts-ts%60".to_string(),
+                "latency_ms=latency_us/1000".to_string()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_stream_api_click_house_sink() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search_stream()
+            .withf(|request| {
+                request.output_format == OutputFormat::ClickHouseNative as i32
+                    && request.click_house_sink
+                        == Some(quickwit_proto::ClickHouseSink {
+                            dsn: "tcp://localhost:9000/db".to_string(),
+                            table: "events".to_string(),
+                        })
+            })
+            .return_once(|_| Ok(vec![Bytes::from("2\n")]));
+        let click_house_sinks = ClickHouseSinks::new(vec![ClickHouseSinkConfig {
+            name: "analytics".to_string(),
+            dsn: "tcp://localhost:9000/db".to_string(),
+            table: "events".to_string(),
+        }]);
+        let rest_search_stream_api_handler = super::search_stream_handler(
+            Arc::new(mock_search_service),
+            None,
+            Default::default(),
+            Arc::new(click_house_sinks),
+        )
+        .recover(recover_fn);
+        let response = warp::test::request()
+            .path(
+                "/api/v1/my-index/search/stream?query=obama&fastField=external_id&\
+                 outputFormat=clickHouseNative&clickHouseSink=analytics",
+            )
+            .reply(&rest_search_stream_api_handler)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "application/octet-stream"
+        );
+
+        let response = warp::test::request()
+            .path(
+                "/api/v1/my-index/search/stream?query=obama&fastField=external_id&\
+                 clickHouseSink=tcp://attacker:9000/db",
+            )
+            .reply(&rest_search_stream_api_handler)
+            .await;
+        assert_eq!(response.status(), 400);
+
+        let response = warp::test::request()
+            .path(
+                "/api/v1/my-index/search/stream?query=obama&fastField=external_id&\
+                 clickHouseDsn=tcp://attacker:9000/db&clickHouseTable=events",
+            )
+            .reply(&rest_search_stream_api_handler)
+            .await;
+        assert_eq!(response.status(), 400);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_search_stream_api_parquet() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search_stream()
+            .withf(|request| request.output_format == OutputFormat::Parquet as i32)
+            .return_once(|_| Ok(vec![Bytes::from("PAR1")]));
+        let rest_search_stream_api_handler = super::search_stream_handler(
+            Arc::new(mock_search_service),
+            None,
+            Default::default(),
+            Default::default(),
+        )
+        .recover(recover_fn);
+        let response = warp::test::request()
+            .path("/api/v1/my-index/search/stream?query=obama&fastField=ts&outputFormat=parquet")
+            .reply(&rest_search_stream_api_handler)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "application/vnd.apache.parquet"
+        );
+        assert_eq!(response.body(), "PAR1");
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_rest_search_stream_api_error() {
         let rejection = warp::test::request()
@@ -650,8 +2214,107 @@ mod tests {
         let parse_error = rejection.find::<serde_qs::Error>().unwrap();
         assert_eq!(
             parse_error.to_string(),
-            "failed with reason: unknown variant `click_house_row_binary`, expected `csv` or \
-             `clickHouseRowBinary`"
+            "failed with reason: unknown variant `click_house_row_binary`, expected one of `csv`, \
+             `clickHouseRowBinary`, `clickHouseNative`, `parquet`"
         );
     }
+
+    #[tokio::test]
+    async fn test_rest_sql_api() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search()
+            .with(predicate::function(
+                |search_request: &quickwit_proto::SearchRequest| {
+                    search_request.index_id == "logs"
+                        && search_request.query == "level:\"error\""
+                        && search_request.mandatory_filter.as_deref() == Some("tenant_id:acme")
+                        && search_request.max_hits == 0
+                },
+            ))
+            .times(1)
+            .returning(|_| {
+                Ok(quickwit_proto::SearchResponse {
+                    num_hits: 3,
+                    ..Default::default()
+                })
+            });
+        let api_keys = Arc::new(ApiKeys::new(vec!["acme=tenant_id:acme".parse()?]));
+        let rest_sql_api_handler =
+            super::sql_handler(Arc::new(mock_search_service), None, api_keys).recover(recover_fn);
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/api/v1/_sql?format=json")
+            .header("Authorization", "Bearer acme")
+            .json(&json!({"query": "SELECT COUNT(*) FROM logs WHERE level = 'error'"}))
+            .reply(&rest_sql_api_handler)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_json_include!(
+            actual: resp_json,
+            expected: json!({"columns": ["COUNT(*)"], "rows": [[3]], "numHits": 3})
+        );
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/api/v1/_sql")
+            .header("Authorization", "Bearer acme")
+            .json(&json!({"query": "DELETE FROM logs"}))
+            .reply(&rest_sql_api_handler)
+            .await;
+        assert_eq!(resp.status(), 400);
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/api/v1/_sql")
+            .header("Authorization", "Bearer acme")
+            .json(&json!({"sql": "SELECT * FROM logs"}))
+            .reply(&rest_sql_api_handler)
+            .await;
+        assert_eq!(resp.status(), 400);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rest_sql_api_through_search_routes() -> anyhow::Result<()> {
+        let mut mock_search_service = MockSearchService::new();
+        mock_search_service
+            .expect_root_search()
+            .times(1)
+            .returning(|_| {
+                Ok(quickwit_proto::SearchResponse {
+                    num_hits: 3,
+                    ..Default::default()
+                })
+            });
+        let quota_service = Arc::new(QuotaService::new(crate::QuotaConfig {
+            max_qps: Some(10),
+            ..Default::default()
+        }));
+        // The SQL queries are attributed to `_sql` without looking up an index.
+        let search_routes = super::search_routes(
+            Arc::new(mock_search_service),
+            quota_service,
+            Arc::new(MockMetastore::new()),
+            None,
+            Default::default(),
+            Default::default(),
+        )
+        .recover(recover_fn);
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/api/v1/_sql?format=json")
+            .json(&json!({"query": "SELECT COUNT(*) FROM logs"}))
+            .reply(&search_routes)
+            .await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers()["x-quota-qps-remaining"], "9");
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.body())?;
+        assert_json_include!(
+            actual: resp_json,
+            expected: json!({"columns": ["COUNT(*)"], "rows": [[3]]})
+        );
+        Ok(())
+    }
 }
@@ -132,6 +132,15 @@ impl Storage for BundleStorage {
         Err(unsupported_operation(path))
     }
 
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        _payload: crate::PutPayload,
+        _progress: &crate::UploadProgress,
+    ) -> crate::StorageResult<()> {
+        Err(unsupported_operation(path))
+    }
+
     async fn copy_to_file(&self, path: &Path, _output_path: &Path) -> crate::StorageResult<()> {
         Err(unsupported_operation(path))
     }
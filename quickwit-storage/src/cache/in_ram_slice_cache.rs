@@ -70,6 +70,11 @@ impl SliceCache {
         let slice_addr = SliceAddress { path, byte_range };
         self.inner.lock().unwrap().put(slice_addr, bytes);
     }
+
+    /// Returns the number of bytes held by the cache.
+    pub fn num_bytes(&self) -> usize {
+        self.inner.lock().unwrap().num_bytes()
+    }
 }
 
 #[cfg(test)]
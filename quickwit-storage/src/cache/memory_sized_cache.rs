@@ -38,6 +38,13 @@ impl Capacity {
             Capacity::InBytes(capacity_in_bytes) => num_bytes > capacity_in_bytes,
         }
     }
+
+    fn in_bytes(&self) -> Option<usize> {
+        match *self {
+            Capacity::Unlimited => None,
+            Capacity::InBytes(capacity_in_bytes) => Some(capacity_in_bytes),
+        }
+    }
 }
 struct NeedMutMemorySizedCache<K: Hash + Eq> {
     lru_cache: LruCache<K, Bytes>,
@@ -97,6 +104,35 @@ impl<K: Hash + Eq> NeedMutMemorySizedCache<K> {
         self.num_bytes += bytes.len();
         self.lru_cache.put(key, bytes);
     }
+
+    fn remove<Q>(&mut self, cache_key: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(bytes) = self.lru_cache.pop(cache_key) {
+            self.num_bytes -= bytes.len();
+            return true;
+        }
+        false
+    }
+
+    fn clear(&mut self) {
+        self.lru_cache.clear();
+        self.num_bytes = 0;
+    }
+
+    /// Evicts the least recently used entries until the cache fits in its new capacity.
+    fn set_capacity(&mut self, capacity: Capacity) {
+        self.capacity = capacity;
+        while self.capacity.exceeds_capacity(self.num_bytes) {
+            if let Some((_, bytes)) = self.lru_cache.pop_lru() {
+                self.num_bytes -= bytes.len();
+            } else {
+                break;
+            }
+        }
+    }
 }
 
 /// A simple in-resident memory slice cache.
@@ -136,6 +172,53 @@ impl<K: Hash + Eq> MemorySizedCache<K> {
     pub fn put(&self, val: K, bytes: Bytes) {
         self.inner.lock().unwrap().put(val, bytes);
     }
+
+    /// Removes the entry from the cache. Returns false if it was not cached.
+    pub fn remove<Q>(&self, cache_key: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.lock().unwrap().remove(cache_key)
+    }
+
+    /// Removes all of the entries from the cache.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().clear();
+    }
+
+    /// Changes the capacity of the cache, evicting the least recently used entries
+    /// that do not fit in it anymore.
+    pub fn set_capacity_in_bytes(&self, capacity_in_bytes: usize) {
+        self.inner
+            .lock()
+            .unwrap()
+            .set_capacity(Capacity::InBytes(capacity_in_bytes));
+    }
+
+    /// Returns the capacity of the cache, or `None` if it is unlimited.
+    pub fn capacity_in_bytes(&self) -> Option<usize> {
+        self.inner.lock().unwrap().capacity.in_bytes()
+    }
+
+    /// Returns the number of bytes stored in the cache.
+    pub fn num_bytes(&self) -> usize {
+        self.inner.lock().unwrap().num_bytes
+    }
+}
+
+impl<K: Hash + Eq + Clone> MemorySizedCache<K> {
+    /// Returns the keys of the cached entries with their size in bytes, most recently
+    /// used first.
+    pub fn entries(&self) -> Vec<(K, usize)> {
+        self.inner
+            .lock()
+            .unwrap()
+            .lru_cache
+            .iter()
+            .map(|(key, bytes)| (key.clone(), bytes.len()))
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -200,4 +283,35 @@ mod tests {
         cache.put("hello.seg", data);
         assert_eq!(cache.get(&"hello.seg").unwrap(), &b"werwer"[..]);
     }
+
+    #[test]
+    fn test_cache_admin() {
+        let cache = MemorySizedCache::<String>::with_capacity_in_bytes(10);
+        cache.put("1".to_string(), Bytes::from_static(&b"abc"[..]));
+        cache.put("2".to_string(), Bytes::from_static(&b"de"[..]));
+        cache.put("3".to_string(), Bytes::from_static(&b"fghi"[..]));
+        assert_eq!(cache.num_bytes(), 9);
+        assert_eq!(
+            cache.entries(),
+            vec![
+                ("3".to_string(), 4),
+                ("2".to_string(), 2),
+                ("1".to_string(), 3)
+            ]
+        );
+        assert!(cache.remove(&"2".to_string()));
+        assert!(!cache.remove(&"2".to_string()));
+        assert_eq!(cache.num_bytes(), 7);
+
+        // `1` is the least recently used entry.
+        cache.set_capacity_in_bytes(5);
+        assert_eq!(cache.capacity_in_bytes(), Some(5));
+        assert!(cache.get(&"1".to_string()).is_none());
+        assert_eq!(cache.get(&"3".to_string()).unwrap(), &b"fghi"[..]);
+        assert_eq!(cache.num_bytes(), 4);
+
+        cache.clear();
+        assert!(cache.entries().is_empty());
+        assert_eq!(cache.num_bytes(), 0);
+    }
 }
@@ -23,8 +23,9 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use quickwit_common::QuickwitUri;
 
-use crate::{Cache, PutPayload, Storage, StorageFactory, StorageResult};
+use crate::{Cache, PutPayload, Storage, StorageFactory, StorageResult, UploadProgress};
 
 /// Use with care, StorageWithCache is read-only.
 struct StorageWithCache {
@@ -38,6 +39,15 @@ impl Storage for StorageWithCache {
         unimplemented!("StorageWithCache is readonly. Failed to put {:?}", path)
     }
 
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        _payload: PutPayload,
+        _progress: &UploadProgress,
+    ) -> StorageResult<()> {
+        unimplemented!("StorageWithCache is readonly. Failed to put {:?}", path)
+    }
+
     async fn copy_to_file(&self, path: &Path, output_path: &Path) -> StorageResult<()> {
         self.storage.copy_to_file(path, output_path).await
     }
@@ -104,7 +114,7 @@ impl StorageFactory for StorageWithCacheFactory {
         self.storage_factory.protocol()
     }
 
-    fn resolve(&self, uri: &str) -> crate::StorageResult<Arc<dyn Storage>> {
+    fn resolve(&self, uri: &QuickwitUri) -> crate::StorageResult<Arc<dyn Storage>> {
         let storage = self.storage_factory.resolve(uri)?;
         Ok(Arc::new(StorageWithCache {
             storage,
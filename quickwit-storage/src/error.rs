@@ -35,6 +35,8 @@ pub enum StorageErrorKind {
     InternalError,
     /// Io error.
     Io,
+    /// The operation did not complete before its deadline.
+    Timeout,
 }
 
 /// Generic Storage Resolver Error.
@@ -42,8 +44,8 @@ pub enum StorageErrorKind {
 #[derive(Error, Debug, Serialize, Deserialize, Clone)]
 pub enum StorageResolverError {
     /// The input is not a valid URI.
-    /// A protocol is required for the URI.
-    #[error("Invalid format for URI: required: `{message}`")]
+    /// The message describes what is wrong with it.
+    #[error("{message}")]
     InvalidUri { message: String },
     /// The protocol is not supported by this resolver.
     #[error("Unsupported protocol: `{protocol}`")]
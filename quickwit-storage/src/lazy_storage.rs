@@ -0,0 +1,149 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use once_cell::sync::OnceCell;
+use quickwit_common::QuickwitUri;
+
+use crate::{PutPayload, Storage, StorageFactory, StorageResult, UploadProgress};
+
+/// Storage resolved by its factory on its first operation.
+///
+/// Creating the client of an object storage, e.g. loading its credentials and TLS
+/// certificates, takes a significant share of the start of a short-lived process. A
+/// lazy storage defers this work until the storage is actually used, and skips it
+/// entirely if it is never used.
+///
+/// If the resolution fails, the operation fails and the next operation tries again.
+pub struct LazyStorage {
+    storage_factory: Arc<dyn StorageFactory>,
+    uri: QuickwitUri,
+    underlying: OnceCell<Arc<dyn Storage>>,
+}
+
+impl LazyStorage {
+    /// Creates a storage resolving `uri` with `storage_factory` on its first operation.
+    pub fn new(storage_factory: Arc<dyn StorageFactory>, uri: QuickwitUri) -> Self {
+        LazyStorage {
+            storage_factory,
+            uri,
+            underlying: OnceCell::new(),
+        }
+    }
+
+    /// Returns true if the underlying storage has been resolved.
+    pub fn is_resolved(&self) -> bool {
+        self.underlying.get().is_some()
+    }
+
+    fn underlying(&self) -> StorageResult<&Arc<dyn Storage>> {
+        self.underlying
+            .get_or_try_init(|| self.storage_factory.resolve(&self.uri))
+    }
+}
+
+#[async_trait]
+impl Storage for LazyStorage {
+    async fn put(&self, path: &Path, payload: PutPayload) -> StorageResult<()> {
+        self.underlying()?.put(path, payload).await
+    }
+
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        payload: PutPayload,
+        progress: &UploadProgress,
+    ) -> StorageResult<()> {
+        self.underlying()?
+            .put_with_progress(path, payload, progress)
+            .await
+    }
+
+    async fn copy_to_file(&self, path: &Path, output_path: &Path) -> StorageResult<()> {
+        self.underlying()?.copy_to_file(path, output_path).await
+    }
+
+    async fn get_slice(&self, path: &Path, range: Range<usize>) -> StorageResult<Bytes> {
+        self.underlying()?.get_slice(path, range).await
+    }
+
+    async fn get_all(&self, path: &Path) -> StorageResult<Bytes> {
+        self.underlying()?.get_all(path).await
+    }
+
+    async fn delete(&self, path: &Path) -> StorageResult<()> {
+        self.underlying()?.delete(path).await
+    }
+
+    async fn exists(&self, path: &Path) -> StorageResult<bool> {
+        self.underlying()?.exists(path).await
+    }
+
+    async fn file_num_bytes(&self, path: &Path) -> StorageResult<u64> {
+        self.underlying()?.file_num_bytes(path).await
+    }
+
+    fn uri(&self) -> String {
+        match self.underlying.get() {
+            Some(underlying) => underlying.uri(),
+            None => self.uri.as_str().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{MockStorageFactory, RamStorage};
+
+    #[tokio::test]
+    async fn test_lazy_storage_resolves_on_first_operation() -> anyhow::Result<()> {
+        let num_resolutions = Arc::new(AtomicUsize::new(0));
+        let num_resolutions_clone = num_resolutions.clone();
+        let mut storage_factory = MockStorageFactory::new();
+        storage_factory.expect_resolve().returning(move |_uri| {
+            num_resolutions_clone.fetch_add(1, Ordering::SeqCst);
+            Ok(Arc::new(
+                RamStorage::builder().put("hello", b"hello_content").build(),
+            ))
+        });
+        let storage = LazyStorage::new(
+            Arc::new(storage_factory),
+            QuickwitUri::from_str("ram:///lazy")?,
+        );
+        assert_eq!(storage.uri(), "ram:///lazy");
+        assert!(!storage.is_resolved());
+        assert_eq!(num_resolutions.load(Ordering::SeqCst), 0);
+
+        let data = storage.get_all(Path::new("hello")).await?;
+        assert_eq!(&data[..], b"hello_content");
+        assert!(storage.exists(Path::new("hello")).await?);
+        assert!(storage.is_resolved());
+        assert_eq!(num_resolutions.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+}
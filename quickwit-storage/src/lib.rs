@@ -22,26 +22,38 @@
 
 //! `quickwit-storage` is the abstraction used in quickwit to interface itself
 //! to different storage:
-//! - object storages (S3)
+//! - object storages (S3, Azure Blob Storage)
 //! - local filesystem
 //! - distributed filesystems.
 //! etc.
 //!
 //! - The `BundleStorage` bundles together multiple files into a single file.
+//! - The `MultipartFileStorage` stores a single file as multiple objects.
 mod cache;
 mod storage;
 pub use self::storage::{PutPayload, Storage};
 
 mod bundle_storage;
 mod error;
+mod lazy_storage;
 mod local_file_storage;
+mod mirrored_storage;
+mod multipart_file_storage;
 mod object_storage;
 mod prefix_storage;
 mod ram_storage;
 mod retry;
 mod storage_resolver;
+mod storage_with_progress;
+mod storage_with_timeout;
 mod storage_with_upload_cache;
 
+pub use storage_with_progress::{
+    OngoingUpload, StorageProgress, StorageProgressSnapshot, StorageWithProgress, UploadProgress,
+};
+pub use storage_with_timeout::{
+    StorageTimeoutPolicy, StorageWithTimeout, StorageWithTimeoutFactory,
+};
 pub use storage_with_upload_cache::{
     create_storage_with_upload_cache, CacheParams, StorageWithUploadCache,
 };
@@ -51,9 +63,14 @@ pub use self::bundle_storage::{
 };
 #[cfg(any(test, feature = "testsuite"))]
 pub use self::cache::MockCache;
+pub use self::lazy_storage::LazyStorage;
 pub use self::local_file_storage::{LocalFileStorage, LocalFileStorageFactory};
+pub use self::mirrored_storage::MirroredStorage;
+pub use self::multipart_file_storage::{multipart_file_storage, MultipartFileStorage};
 pub use self::object_storage::{
-    MultiPartPolicy, RegionProvider, S3CompatibleObjectStorage, S3CompatibleObjectStorageFactory,
+    AzureBlobStorage, AzureBlobStorageFactory, MultiPartPolicy, RegionProvider,
+    S3CompatibleObjectStorage, S3CompatibleObjectStorageFactory, S3Credentials, S3CredentialsRule,
+    S3HttpConfig, S3_CREDENTIALS_ENV_KEY,
 };
 pub use self::prefix_storage::add_prefix_to_storage;
 pub use self::ram_storage::{RamStorage, RamStorageBuilder};
@@ -26,6 +26,7 @@ use std::{fmt, io};
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures::future::{BoxFuture, FutureExt};
+use quickwit_common::QuickwitUri;
 use tokio::fs;
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tracing::warn;
@@ -234,8 +235,8 @@ impl StorageFactory for LocalFileStorageFactory {
         "file".to_string()
     }
 
-    fn resolve(&self, uri: &str) -> StorageResult<Arc<dyn Storage>> {
-        let storage = LocalFileStorage::from_uri(uri)?;
+    fn resolve(&self, uri: &QuickwitUri) -> StorageResult<Arc<dyn Storage>> {
+        let storage = LocalFileStorage::from_uri(uri.as_str())?;
         Ok(Arc::new(storage))
     }
 }
@@ -280,16 +281,19 @@ mod tests {
         let test_dir = tempfile::tempdir()?;
         let index_dir_uri = format!("file://{}/foo/bar", test_dir.path().display());
         let file_storage_factory = LocalFileStorageFactory::default();
-        let storage = file_storage_factory.resolve(&index_dir_uri)?;
+        let storage = file_storage_factory.resolve(&index_dir_uri.parse()?)?;
         assert_eq!(storage.uri().as_str(), index_dir_uri);
 
         let err = file_storage_factory
-            .resolve("test://foo/bar")
+            .resolve(&"test://foo/bar".parse()?)
             .err()
             .unwrap();
         assert_eq!(err.kind(), StorageErrorKind::DoesNotExist);
 
-        let err = file_storage_factory.resolve("test://").err().unwrap();
+        let err = file_storage_factory
+            .resolve(&"test://".parse()?)
+            .err()
+            .unwrap();
         assert_eq!(err.kind(), StorageErrorKind::DoesNotExist);
         Ok(())
     }
@@ -0,0 +1,250 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::future::Future;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tracing::warn;
+
+use crate::{PutPayload, Storage, StorageErrorKind, StorageResult, UploadProgress};
+
+/// Storage writing its files to a primary storage and to a mirror, e.g. in another region.
+///
+/// Files are written to both storages concurrently. A failed write to the mirror does not fail
+/// the operation: the file is only logged as missing from the mirror, and is copied there later
+/// by the mirror reconciliation. Deletes, on the other hand, fail if the mirror fails to delete
+/// the file, so that the garbage collection retries them instead of leaving orphaned files on
+/// the mirror. Reads are served by the primary storage and fall back to the mirror when the
+/// primary storage fails.
+pub struct MirroredStorage {
+    primary: Arc<dyn Storage>,
+    mirror: Arc<dyn Storage>,
+}
+
+impl MirroredStorage {
+    /// Creates a storage mirroring the files of `primary` to `mirror`.
+    pub fn new(primary: Arc<dyn Storage>, mirror: Arc<dyn Storage>) -> Self {
+        MirroredStorage { primary, mirror }
+    }
+
+    fn log_mirror_error(&self, operation: &str, path: &Path, mirror_res: StorageResult<()>) {
+        if let Err(mirror_error) = mirror_res {
+            warn!(
+                primary_uri = %self.primary.uri(),
+                mirror_uri = %self.mirror.uri(),
+                path = %path.display(),
+                error = ?mirror_error,
+                "Failed to {} a file on the mirror storage.",
+                operation
+            );
+        }
+    }
+
+    async fn read_with_fallback<T, F, Fut>(&self, path: &Path, read: F) -> StorageResult<T>
+    where
+        F: Fn(Arc<dyn Storage>) -> Fut,
+        Fut: Future<Output = StorageResult<T>>,
+    {
+        let primary_error = match read(self.primary.clone()).await {
+            Ok(result) => return Ok(result),
+            Err(primary_error) => primary_error,
+        };
+        warn!(
+            primary_uri = %self.primary.uri(),
+            path = %path.display(),
+            error = ?primary_error,
+            "Failed to read a file from the primary storage, falling back to the mirror storage."
+        );
+        // The primary error is the most relevant one if the mirror fails as well.
+        read(self.mirror.clone()).await.map_err(|_| primary_error)
+    }
+}
+
+#[async_trait]
+impl Storage for MirroredStorage {
+    async fn put(&self, path: &Path, payload: PutPayload) -> StorageResult<()> {
+        let (primary_res, mirror_res) = tokio::join!(
+            self.primary.put(path, payload.clone()),
+            self.mirror.put(path, payload)
+        );
+        self.log_mirror_error("put", path, mirror_res);
+        primary_res
+    }
+
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        payload: PutPayload,
+        progress: &UploadProgress,
+    ) -> StorageResult<()> {
+        let (primary_res, mirror_res) = tokio::join!(
+            self.primary
+                .put_with_progress(path, payload.clone(), progress),
+            self.mirror.put(path, payload)
+        );
+        self.log_mirror_error("put", path, mirror_res);
+        primary_res
+    }
+
+    async fn copy_to_file(&self, path: &Path, output_path: &Path) -> StorageResult<()> {
+        self.read_with_fallback(path, |storage| async move {
+            storage.copy_to_file(path, output_path).await
+        })
+        .await
+    }
+
+    async fn get_slice(&self, path: &Path, range: Range<usize>) -> StorageResult<Bytes> {
+        self.read_with_fallback(path, |storage| {
+            let range = range.clone();
+            async move { storage.get_slice(path, range).await }
+        })
+        .await
+    }
+
+    async fn get_all(&self, path: &Path) -> StorageResult<Bytes> {
+        self.read_with_fallback(path, |storage| async move { storage.get_all(path).await })
+            .await
+    }
+
+    async fn delete(&self, path: &Path) -> StorageResult<()> {
+        let (primary_res, mirror_res) =
+            tokio::join!(self.primary.delete(path), self.mirror.delete(path));
+        primary_res?;
+        match mirror_res {
+            // The mirror may not have the file yet.
+            Err(mirror_error) if mirror_error.kind() == StorageErrorKind::DoesNotExist => Ok(()),
+            mirror_res => mirror_res,
+        }
+    }
+
+    async fn exists(&self, path: &Path) -> StorageResult<bool> {
+        self.read_with_fallback(path, |storage| async move { storage.exists(path).await })
+            .await
+    }
+
+    async fn file_num_bytes(&self, path: &Path) -> StorageResult<u64> {
+        self.read_with_fallback(
+            path,
+            |storage| async move { storage.file_num_bytes(path).await },
+        )
+        .await
+    }
+
+    fn uri(&self) -> String {
+        self.primary.uri()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockStorage, RamStorage};
+
+    #[tokio::test]
+    async fn test_mirrored_storage_writes_to_both_storages() -> anyhow::Result<()> {
+        let primary: Arc<dyn Storage> = Arc::new(RamStorage::default());
+        let mirror: Arc<dyn Storage> = Arc::new(RamStorage::default());
+        let mirrored_storage = MirroredStorage::new(primary.clone(), mirror.clone());
+        mirrored_storage
+            .put(Path::new("split1.split"), b"split1".to_vec().into())
+            .await?;
+        assert_eq!(
+            &primary.get_all(Path::new("split1.split")).await?[..],
+            b"split1"
+        );
+        assert_eq!(
+            &mirror.get_all(Path::new("split1.split")).await?[..],
+            b"split1"
+        );
+
+        // The mirror may not have the file yet.
+        primary
+            .put(Path::new("split2.split"), b"split2".to_vec().into())
+            .await?;
+        mirrored_storage.delete(Path::new("split1.split")).await?;
+        mirrored_storage.delete(Path::new("split2.split")).await?;
+        assert!(!primary.exists(Path::new("split1.split")).await?);
+        assert!(!mirror.exists(Path::new("split1.split")).await?);
+        assert!(!primary.exists(Path::new("split2.split")).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mirrored_storage_falls_back_to_mirror() -> anyhow::Result<()> {
+        let mut primary = MockStorage::default();
+        primary
+            .expect_uri()
+            .returning(|| "s3://primary".to_string());
+        primary.expect_get_all().returning(|_| {
+            Err(StorageErrorKind::Service.with_error(anyhow::anyhow!("Region unavailable.")))
+        });
+        let mirror: Arc<dyn Storage> =
+            Arc::new(RamStorage::builder().put("split1.split", b"split1").build());
+        let mirrored_storage = MirroredStorage::new(Arc::new(primary), mirror.clone());
+        assert_eq!(
+            &mirrored_storage.get_all(Path::new("split1.split")).await?[..],
+            b"split1"
+        );
+        let error = mirrored_storage
+            .get_all(Path::new("split2.split"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), StorageErrorKind::Service);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mirrored_storage_ignores_mirror_write_errors() -> anyhow::Result<()> {
+        let primary: Arc<dyn Storage> = Arc::new(RamStorage::default());
+        let mut mirror = MockStorage::default();
+        mirror.expect_uri().returning(|| "s3://mirror".to_string());
+        mirror.expect_put().returning(|_, _| {
+            Err(StorageErrorKind::Service.with_error(anyhow::anyhow!("Region unavailable.")))
+        });
+        let mirrored_storage = MirroredStorage::new(primary.clone(), Arc::new(mirror));
+        mirrored_storage
+            .put(Path::new("split1.split"), b"split1".to_vec().into())
+            .await?;
+        assert!(primary.exists(Path::new("split1.split")).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mirrored_storage_fails_on_mirror_delete_errors() -> anyhow::Result<()> {
+        let primary: Arc<dyn Storage> =
+            Arc::new(RamStorage::builder().put("split1.split", b"split1").build());
+        let mut mirror = MockStorage::default();
+        mirror.expect_uri().returning(|| "s3://mirror".to_string());
+        mirror.expect_delete().returning(|_| {
+            Err(StorageErrorKind::Service.with_error(anyhow::anyhow!("Region unavailable.")))
+        });
+        let mirrored_storage = MirroredStorage::new(primary.clone(), Arc::new(mirror));
+        let error = mirrored_storage
+            .delete(Path::new("split1.split"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), StorageErrorKind::Service);
+        assert!(!primary.exists(Path::new("split1.split")).await?);
+        Ok(())
+    }
+}
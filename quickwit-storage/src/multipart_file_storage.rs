@@ -0,0 +1,357 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::ffi::OsString;
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::future::try_join_all;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{PutPayload, Storage, StorageErrorKind, StorageResult, UploadProgress};
+
+/// Storage exposing the files stored as several objects, the "parts", as single files.
+///
+/// A file is stored as the objects `<path>.part-00000`, `<path>.part-00001`, ... of
+/// `part_num_bytes` bytes each, the last part holding the remaining bytes. It allows storing
+/// files larger than the object size limit of some storages, and the slices spanning several
+/// parts are downloaded concurrently.
+pub struct MultipartFileStorage {
+    underlying: Arc<dyn Storage>,
+    part_num_bytes: u64,
+}
+
+impl MultipartFileStorage {
+    /// Creates a storage storing the files of `underlying` as parts of `part_num_bytes` bytes.
+    pub fn new(underlying: Arc<dyn Storage>, part_num_bytes: u64) -> Self {
+        assert!(part_num_bytes > 0, "The parts must not be empty.");
+        MultipartFileStorage {
+            underlying,
+            part_num_bytes,
+        }
+    }
+
+    fn part_path(&self, path: &Path, part_ord: u64) -> PathBuf {
+        let mut part_path = OsString::from(path.as_os_str());
+        part_path.push(format!(".part-{:05}", part_ord));
+        PathBuf::from(part_path)
+    }
+
+    fn num_parts(&self, num_bytes: u64) -> u64 {
+        ((num_bytes + self.part_num_bytes - 1) / self.part_num_bytes).max(1)
+    }
+
+    fn part_range(&self, part_ord: u64, num_bytes: u64) -> Range<u64> {
+        let start = part_ord * self.part_num_bytes;
+        start..(start + self.part_num_bytes).min(num_bytes)
+    }
+
+    async fn put_parts(
+        &self,
+        path: &Path,
+        payload: PutPayload,
+        progress_opt: Option<&UploadProgress>,
+    ) -> StorageResult<()> {
+        let num_bytes = payload.len().await?;
+        let num_parts = self.num_parts(num_bytes);
+        for part_ord in 0..num_parts {
+            let part_range = self.part_range(part_ord, num_bytes);
+            let part_num_bytes = part_range.end - part_range.start;
+            let part_path = self.part_path(path, part_ord);
+            match &payload {
+                PutPayload::InMemory(bytes) => {
+                    let part_bytes =
+                        bytes.slice(part_range.start as usize..part_range.end as usize);
+                    self.underlying
+                        .put(&part_path, PutPayload::InMemory(part_bytes))
+                        .await?;
+                }
+                PutPayload::LocalFile(file_path) if num_parts == 1 => {
+                    self.underlying
+                        .put(&part_path, PutPayload::LocalFile(file_path.clone()))
+                        .await?;
+                }
+                PutPayload::LocalFile(file_path) => {
+                    // The part is written next to the file rather than held in memory, as
+                    // parts are typically several GBs.
+                    let scratch_dir = file_path.parent().unwrap_or_else(|| Path::new("."));
+                    let local_part_file = tempfile::NamedTempFile::new_in(scratch_dir)?;
+                    let mut file = tokio::fs::File::open(file_path).await?;
+                    file.seek(SeekFrom::Start(part_range.start)).await?;
+                    let mut local_part = tokio::fs::File::create(local_part_file.path()).await?;
+                    tokio::io::copy(&mut file.take(part_num_bytes), &mut local_part).await?;
+                    self.underlying
+                        .put(
+                            &part_path,
+                            PutPayload::LocalFile(local_part_file.path().to_path_buf()),
+                        )
+                        .await?;
+                }
+            }
+            if let Some(progress) = progress_opt {
+                progress.record_uploaded_bytes(part_num_bytes);
+            }
+        }
+        // The parts of a previous, larger, version of the file would be read as part of it.
+        self.delete_parts_from(path, num_parts).await
+    }
+
+    async fn delete_parts_from(&self, path: &Path, first_part_ord: u64) -> StorageResult<()> {
+        let mut part_ord = first_part_ord;
+        loop {
+            let part_path = self.part_path(path, part_ord);
+            if !self.underlying.exists(&part_path).await? {
+                return Ok(());
+            }
+            self.underlying.delete(&part_path).await?;
+            part_ord += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for MultipartFileStorage {
+    async fn put(&self, path: &Path, payload: PutPayload) -> StorageResult<()> {
+        self.put_parts(path, payload, None).await
+    }
+
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        payload: PutPayload,
+        progress: &UploadProgress,
+    ) -> StorageResult<()> {
+        self.put_parts(path, payload, Some(progress)).await?;
+        progress.record_upload_completed();
+        Ok(())
+    }
+
+    async fn copy_to_file(&self, path: &Path, output_path: &Path) -> StorageResult<()> {
+        let num_bytes = self.file_num_bytes(path).await?;
+        self.underlying
+            .copy_to_file(&self.part_path(path, 0), output_path)
+            .await?;
+        let mut output_file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(output_path)
+            .await?;
+        for part_ord in 1..self.num_parts(num_bytes) {
+            let local_part_path = self.part_path(output_path, part_ord);
+            self.underlying
+                .copy_to_file(&self.part_path(path, part_ord), &local_part_path)
+                .await?;
+            let mut local_part = tokio::fs::File::open(&local_part_path).await?;
+            tokio::io::copy(&mut local_part, &mut output_file).await?;
+            tokio::fs::remove_file(&local_part_path).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_slice(&self, path: &Path, range: Range<usize>) -> StorageResult<Bytes> {
+        let start = range.start as u64;
+        let end = range.end as u64;
+        let first_part_ord = start / self.part_num_bytes;
+        let last_part_ord = if end > start {
+            (end - 1) / self.part_num_bytes
+        } else {
+            first_part_ord
+        };
+        let part_slices = try_join_all((first_part_ord..=last_part_ord).map(|part_ord| {
+            let part_start = part_ord * self.part_num_bytes;
+            let slice_start = start.max(part_start) - part_start;
+            let slice_end = end.min(part_start + self.part_num_bytes).max(part_start) - part_start;
+            let part_path = self.part_path(path, part_ord);
+            async move {
+                self.underlying
+                    .get_slice(&part_path, slice_start as usize..slice_end as usize)
+                    .await
+            }
+        }))
+        .await?;
+        if part_slices.len() == 1 {
+            return Ok(part_slices.into_iter().next().unwrap());
+        }
+        let mut slice = BytesMut::with_capacity(range.len());
+        for part_slice in part_slices {
+            slice.extend_from_slice(&part_slice);
+        }
+        Ok(slice.freeze())
+    }
+
+    async fn get_all(&self, path: &Path) -> StorageResult<Bytes> {
+        let num_bytes = self.file_num_bytes(path).await?;
+        self.get_slice(path, 0..num_bytes as usize).await
+    }
+
+    async fn delete(&self, path: &Path) -> StorageResult<()> {
+        self.delete_parts_from(path, 0).await
+    }
+
+    async fn exists(&self, path: &Path) -> StorageResult<bool> {
+        self.underlying.exists(&self.part_path(path, 0)).await
+    }
+
+    async fn file_num_bytes(&self, path: &Path) -> StorageResult<u64> {
+        let mut num_bytes = 0;
+        let mut part_ord = 0;
+        loop {
+            match self
+                .underlying
+                .file_num_bytes(&self.part_path(path, part_ord))
+                .await
+            {
+                Ok(part_num_bytes) => {
+                    num_bytes += part_num_bytes;
+                    if part_num_bytes < self.part_num_bytes {
+                        return Ok(num_bytes);
+                    }
+                }
+                // A file whose size is a multiple of the part size has no partial last part.
+                Err(error) if part_ord > 0 && error.kind() == StorageErrorKind::DoesNotExist => {
+                    return Ok(num_bytes);
+                }
+                Err(error) => return Err(error),
+            }
+            part_ord += 1;
+        }
+    }
+
+    fn uri(&self) -> String {
+        self.underlying.uri()
+    }
+}
+
+/// Returns the storage the files stored as parts of `part_num_bytes_opt` bytes are accessed
+/// through, or `storage` itself for the files stored as single objects.
+pub fn multipart_file_storage(
+    storage: Arc<dyn Storage>,
+    part_num_bytes_opt: Option<u64>,
+) -> Arc<dyn Storage> {
+    match part_num_bytes_opt {
+        Some(part_num_bytes) => Arc::new(MultipartFileStorage::new(storage, part_num_bytes)),
+        None => storage,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::storage_test_suite;
+    use crate::RamStorage;
+
+    #[tokio::test]
+    async fn test_multipart_file_storage_test_suite() -> anyhow::Result<()> {
+        let mut multipart_storage = MultipartFileStorage::new(Arc::new(RamStorage::default()), 4);
+        storage_test_suite(&mut multipart_storage).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_multipart_file_storage_parts() -> anyhow::Result<()> {
+        let ram_storage: Arc<dyn Storage> = Arc::new(RamStorage::default());
+        let multipart_storage = MultipartFileStorage::new(ram_storage.clone(), 4);
+        let split_path = Path::new("split1.split");
+        multipart_storage
+            .put(split_path, PutPayload::from(&b"abcdefghij"[..]))
+            .await?;
+        assert_eq!(
+            &ram_storage
+                .get_all(Path::new("split1.split.part-00000"))
+                .await?[..],
+            b"abcd"
+        );
+        assert_eq!(
+            &ram_storage
+                .get_all(Path::new("split1.split.part-00002"))
+                .await?[..],
+            b"ij"
+        );
+        assert!(!ram_storage.exists(split_path).await?);
+        assert_eq!(multipart_storage.file_num_bytes(split_path).await?, 10);
+        assert_eq!(
+            &multipart_storage.get_slice(split_path, 2..9).await?[..],
+            b"cdefghi"
+        );
+        assert_eq!(
+            &multipart_storage.get_slice(split_path, 4..8).await?[..],
+            b"efgh"
+        );
+        assert_eq!(
+            &multipart_storage.get_all(split_path).await?[..],
+            b"abcdefghij"
+        );
+
+        multipart_storage
+            .put(split_path, PutPayload::from(&b"abcdefgh"[..]))
+            .await?;
+        assert!(
+            !ram_storage
+                .exists(Path::new("split1.split.part-00002"))
+                .await?
+        );
+        assert_eq!(multipart_storage.file_num_bytes(split_path).await?, 8);
+
+        multipart_storage.delete(split_path).await?;
+        assert!(!multipart_storage.exists(split_path).await?);
+        assert!(
+            !ram_storage
+                .exists(Path::new("split1.split.part-00000"))
+                .await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_multipart_file_storage_local_file() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let local_split_path = temp_dir.path().join("split1.split");
+        tokio::fs::write(&local_split_path, b"abcdefghijklm").await?;
+        let ram_storage: Arc<dyn Storage> = Arc::new(RamStorage::default());
+        let multipart_storage = MultipartFileStorage::new(ram_storage.clone(), 5);
+        let split_path = Path::new("split1.split");
+        let progress = UploadProgress::new(13);
+        multipart_storage
+            .put_with_progress(
+                split_path,
+                PutPayload::from(local_split_path.clone()),
+                &progress,
+            )
+            .await?;
+        assert_eq!(progress.num_uploaded_bytes(), 13);
+        assert_eq!(
+            &ram_storage
+                .get_all(Path::new("split1.split.part-00001"))
+                .await?[..],
+            b"fghij"
+        );
+        // Only the split file is left in the directory.
+        assert_eq!(std::fs::read_dir(temp_dir.path())?.count(), 1);
+
+        let local_copy_path = temp_dir.path().join("split1_copy.split");
+        multipart_storage
+            .copy_to_file(split_path, &local_copy_path)
+            .await?;
+        assert_eq!(std::fs::read(&local_copy_path)?, b"abcdefghijklm");
+        Ok(())
+    }
+}
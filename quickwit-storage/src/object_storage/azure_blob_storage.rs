@@ -0,0 +1,843 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use std::{fmt, io};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{stream, StreamExt, TryStreamExt};
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper::header::{HeaderValue, AUTHORIZATION, CONTENT_LENGTH};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use hyper_rustls::HttpsConnector;
+use once_cell::sync::OnceCell;
+use percent_encoding::{
+    percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS, NON_ALPHANUMERIC,
+};
+use quickwit_common::QuickwitUri;
+use regex::Regex;
+use ring::hmac;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+
+use super::file_slice_stream::FileSliceStream;
+use super::http_client::{client_builder, https_connector, S3HttpConfig};
+use super::s3_compatible_storage::split_range_into_chunks;
+use crate::retry::{retry, Retry};
+use crate::{
+    MultiPartPolicy, PutPayload, Storage, StorageError, StorageErrorKind, StorageFactory,
+    StorageResult, UploadProgress,
+};
+
+/// Version of the Blob service REST API targeted by the requests.
+const AZURE_STORAGE_API_VERSION: &str = "2020-10-02";
+
+/// Environment variable holding the name of the storage account.
+const AZURE_STORAGE_ACCOUNT_ENV_KEY: &str = "AZURE_STORAGE_ACCOUNT";
+
+/// Environment variable holding the access key of the storage account.
+const AZURE_STORAGE_ACCESS_KEY_ENV_KEY: &str = "AZURE_STORAGE_ACCESS_KEY";
+
+/// Environment variable overriding the endpoint of the Blob service, e.g. to target the
+/// Azurite emulator.
+const AZURE_STORAGE_ENDPOINT_ENV_KEY: &str = "QUICKWIT_AZURE_STORAGE_ENDPOINT";
+
+/// Standard headers signed with the shared key, in the order of the string to sign.
+const SIGNED_STANDARD_HEADERS: [&str; 11] = [
+    "content-encoding",
+    "content-language",
+    "content-length",
+    "content-md5",
+    "content-type",
+    "date",
+    "if-modified-since",
+    "if-match",
+    "if-none-match",
+    "if-unmodified-since",
+    "range",
+];
+
+/// Characters percent-encoded in the segments of the blob names, i.e. all of them but the
+/// letters, the digits, `-`, `.`, `_` and `~`.
+const BLOB_NAME_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'!')
+    .add(b'"')
+    .add(b'#')
+    .add(b'$')
+    .add(b'%')
+    .add(b'&')
+    .add(b'\'')
+    .add(b'(')
+    .add(b')')
+    .add(b'*')
+    .add(b'+')
+    .add(b',')
+    .add(b'/')
+    .add(b':')
+    .add(b';')
+    .add(b'<')
+    .add(b'=')
+    .add(b'>')
+    .add(b'?')
+    .add(b'@')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+type HttpsClient = hyper::Client<HttpsConnector<HttpConnector>>;
+
+fn create_http_client() -> anyhow::Result<HttpsClient> {
+    let http_config = S3HttpConfig::default();
+    let https_connector = https_connector(&http_config)?;
+    Ok(client_builder(&http_config).build(https_connector))
+}
+
+/// Shared key of a storage account, signing the requests sent on its behalf.
+///
+/// See <https://docs.microsoft.com/en-us/rest/api/storageservices/authorize-with-shared-key>.
+#[derive(Clone)]
+struct SharedKey {
+    account: String,
+    key: hmac::Key,
+}
+
+impl SharedKey {
+    fn new(account: &str, access_key: &str) -> anyhow::Result<SharedKey> {
+        static ACCOUNT_PTN: OnceCell<Regex> = OnceCell::new();
+        let account_ptn = ACCOUNT_PTN.get_or_init(|| Regex::new(r"^[a-z0-9]{3,24}$").unwrap());
+        if !account_ptn.is_match(account) {
+            anyhow::bail!(
+                "Invalid storage account name `{}`: it must be made of 3 to 24 lowercase letters \
+                 and digits.",
+                account
+            );
+        }
+        let key_bytes = base64::decode(access_key)
+            .with_context(|| "The access key of the storage account is not valid base64.")?;
+        Ok(SharedKey {
+            account: account.to_string(),
+            key: hmac::Key::new(hmac::HMAC_SHA256, &key_bytes),
+        })
+    }
+
+    fn sign(&self, request: &mut Request<Body>) {
+        let string_to_sign = string_to_sign(&self.account, request);
+        let signature = base64::encode(hmac::sign(&self.key, string_to_sign.as_bytes()));
+        let authorization = format!("SharedKey {}:{}", self.account, signature);
+        let authorization_value = HeaderValue::from_str(&authorization)
+            .expect("The account name and the signature should be valid header characters.");
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, authorization_value);
+    }
+}
+
+/// Returns the string signed to authorize `request` with the shared key of `account`.
+fn string_to_sign(account: &str, request: &Request<Body>) -> String {
+    let headers = request.headers();
+    let mut string_to_sign = format!("{}\n", request.method());
+    for header_name in SIGNED_STANDARD_HEADERS.iter() {
+        let header_value = headers
+            .get(*header_name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        // Since the version 2015-02-21, a zero content length is signed as an empty string.
+        if !(*header_name == "content-length" && header_value == "0") {
+            string_to_sign.push_str(header_value);
+        }
+        string_to_sign.push('\n');
+    }
+    let mut ms_headers: Vec<(&str, &str)> = headers
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with("x-ms-"))
+        .map(|(name, value)| (name.as_str(), value.to_str().unwrap_or("").trim()))
+        .collect();
+    ms_headers.sort_unstable();
+    for (name, value) in ms_headers {
+        string_to_sign.push_str(&format!("{}:{}\n", name, value));
+    }
+    string_to_sign.push_str(&format!("/{}{}", account, request.uri().path()));
+    let mut query_params: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for query_param in request
+        .uri()
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|query_param| !query_param.is_empty())
+    {
+        let (name, value) = query_param.split_once('=').unwrap_or((query_param, ""));
+        query_params
+            .entry(percent_decode_str(name).decode_utf8_lossy().to_lowercase())
+            .or_default()
+            .push(percent_decode_str(value).decode_utf8_lossy().to_string());
+    }
+    for (name, mut values) in query_params {
+        values.sort_unstable();
+        string_to_sign.push_str(&format!("\n{}:{}", name, values.join(",")));
+    }
+    string_to_sign
+}
+
+/// Returns the id of the block `block_ord` of a blob uploaded in several blocks.
+///
+/// The ids of the blocks of a blob must all have the same length.
+fn block_id(block_ord: usize) -> String {
+    base64::encode(format!("{:06}", block_ord))
+}
+
+/// Multipart policy of the block blobs, made of up to 50,000 blocks of up to 4,000 MiB.
+fn azure_multipart_policy() -> MultiPartPolicy {
+    MultiPartPolicy {
+        target_part_num_bytes: 100 * 1_024 * 1_024, // 100 MiB
+        multipart_threshold_num_bytes: 128 * 1_024 * 1_024, // 128 MiB
+        max_num_parts: 50_000,
+        max_object_num_bytes: 50_000 * 4_000 * 1_024 * 1_024, // 190.7 TiB
+        max_concurrent_upload: 100,
+    }
+}
+
+async fn range_body(payload: &PutPayload, range: Range<u64>) -> io::Result<Body> {
+    match payload {
+        PutPayload::LocalFile(filepath) => {
+            let file = File::open(&filepath).await?;
+            let file_slice_stream = FileSliceStream::try_new(file, range).await?;
+            Ok(Body::wrap_stream(file_slice_stream))
+        }
+        PutPayload::InMemory(data) => Ok(Body::from(
+            data.slice(range.start as usize..range.end as usize),
+        )),
+    }
+}
+
+fn retryable_io_error<E>(error: E) -> Retry<StorageError>
+where anyhow::Error: From<E> {
+    Retry::Retryable(StorageErrorKind::Io.with_error(error))
+}
+
+/// Azure Blob Storage implementation, storing the files as the block blobs of a container.
+pub struct AzureBlobStorage {
+    http_client: HttpsClient,
+    shared_key: SharedKey,
+    endpoint: String,
+    container: String,
+    prefix: PathBuf,
+    multipart_policy: MultiPartPolicy,
+}
+
+impl fmt::Debug for AzureBlobStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "AzureBlobStorage(account={},container={},prefix={:?})",
+            &self.shared_key.account, &self.container, &self.prefix
+        )
+    }
+}
+
+impl AzureBlobStorage {
+    /// Creates a storage of the blobs of `container`, in the storage account `account` whose
+    /// Blob service is reached at `endpoint`. The requests are signed with `access_key`, the
+    /// base64 encoded access key of the account.
+    pub fn new(
+        account: &str,
+        access_key: &str,
+        endpoint: &str,
+        container: &str,
+    ) -> anyhow::Result<AzureBlobStorage> {
+        let shared_key = SharedKey::new(account, access_key)?;
+        let http_client = create_http_client()?;
+        Ok(AzureBlobStorage::new_with_client(
+            http_client,
+            shared_key,
+            endpoint,
+            container,
+        ))
+    }
+
+    fn new_with_client(
+        http_client: HttpsClient,
+        shared_key: SharedKey,
+        endpoint: &str,
+        container: &str,
+    ) -> AzureBlobStorage {
+        AzureBlobStorage {
+            http_client,
+            shared_key,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            container: container.to_string(),
+            prefix: PathBuf::new(),
+            multipart_policy: azure_multipart_policy(),
+        }
+    }
+
+    /// Sets the prefix of the names of the blobs.
+    ///
+    /// This method overrides any existing prefix. (It does NOT
+    /// append the argument to any existing prefix.)
+    pub fn with_prefix(self, prefix: &Path) -> Self {
+        AzureBlobStorage {
+            prefix: prefix.to_path_buf(),
+            ..self
+        }
+    }
+
+    /// Sets the multipart policy.
+    ///
+    /// See `MultiPartPolicy`.
+    pub fn set_policy(&mut self, multipart_policy: MultiPartPolicy) {
+        self.multipart_policy = multipart_policy;
+    }
+}
+
+pub fn parse_azure_uri(uri: &str) -> Option<(String, PathBuf)> {
+    static URI_PTN: OnceCell<Regex> = OnceCell::new();
+    URI_PTN
+        .get_or_init(|| {
+            // azure://container/path/to/object
+            Regex::new(r"^azure://(?P<container>[^/]+)(/(?P<path>.+))?$").unwrap()
+        })
+        .captures(uri)
+        .and_then(|cap| {
+            cap.name("container").map(|container_match| {
+                (
+                    container_match.as_str().to_string(),
+                    cap.name("path").map_or_else(
+                        || PathBuf::from(""),
+                        |path_match| PathBuf::from(path_match.as_str()),
+                    ),
+                )
+            })
+        })
+}
+
+impl AzureBlobStorage {
+    fn uri(&self, relative_path: &Path) -> String {
+        format!(
+            "azure://{}/{}",
+            &self.container,
+            self.blob_name(relative_path)
+        )
+    }
+
+    fn blob_name(&self, relative_path: &Path) -> String {
+        let blob_path = self.prefix.join(relative_path);
+        blob_path.to_string_lossy().to_string()
+    }
+
+    fn blob_url(&self, blob_name: &str, query: &str) -> String {
+        let encoded_blob_name = blob_name
+            .split('/')
+            .map(|segment| utf8_percent_encode(segment, BLOB_NAME_ENCODE_SET).to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut blob_url = format!("{}/{}/{}", self.endpoint, self.container, encoded_blob_name);
+        if !query.is_empty() {
+            blob_url.push('?');
+            blob_url.push_str(query);
+        }
+        blob_url
+    }
+
+    fn signed_request(
+        &self,
+        method: Method,
+        blob_url: &str,
+        headers: &[(&str, String)],
+        body: Body,
+    ) -> StorageResult<Request<Body>> {
+        let mut request_builder = Request::builder()
+            .method(method)
+            .uri(blob_url)
+            .header("x-ms-date", httpdate::fmt_http_date(SystemTime::now()))
+            .header("x-ms-version", AZURE_STORAGE_API_VERSION);
+        for (name, value) in headers {
+            request_builder = request_builder.header(*name, value.as_str());
+        }
+        let mut request = request_builder
+            .body(body)
+            .map_err(|error| StorageErrorKind::InternalError.with_error(error))?;
+        self.shared_key.sign(&mut request);
+        Ok(request)
+    }
+
+    /// Sends `request`, turning the unsuccessful responses into errors. The errors of the
+    /// connections, the throttled requests and the server errors are retryable.
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, Retry<StorageError>> {
+        let response = self
+            .http_client
+            .request(request)
+            .await
+            .map_err(retryable_io_error)?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let error_code = response
+            .headers()
+            .get("x-ms-error-code")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("unknown");
+        let error = anyhow::anyhow!(
+            "Azure Blob Storage returned the status `{}` with the error code `{}`.",
+            status,
+            error_code
+        );
+        let error_kind = match status {
+            StatusCode::NOT_FOUND => StorageErrorKind::DoesNotExist,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => StorageErrorKind::Unauthorized,
+            _ => StorageErrorKind::Service,
+        };
+        let storage_error = error_kind.with_error(error);
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            Err(Retry::Retryable(storage_error))
+        } else {
+            Err(Retry::NotRetryable(storage_error))
+        }
+    }
+
+    async fn put_single_part_single_try(
+        &self,
+        blob_name: &str,
+        payload: &PutPayload,
+        len: u64,
+    ) -> Result<(), Retry<StorageError>> {
+        let body = range_body(payload, 0..len)
+            .await
+            .map_err(|error| Retry::NotRetryable(StorageError::from(error)))?;
+        let headers = [
+            ("x-ms-blob-type", "BlockBlob".to_string()),
+            ("content-length", len.to_string()),
+        ];
+        let request = self
+            .signed_request(Method::PUT, &self.blob_url(blob_name, ""), &headers, body)
+            .map_err(Retry::NotRetryable)?;
+        self.send(request).await?;
+        Ok(())
+    }
+
+    async fn put_block(
+        &self,
+        blob_name: &str,
+        block_id: &str,
+        payload: &PutPayload,
+        range: Range<u64>,
+    ) -> Result<(), Retry<StorageError>> {
+        let len = range.end - range.start;
+        let body = range_body(payload, range)
+            .await
+            .map_err(|error| Retry::NotRetryable(StorageError::from(error)))?;
+        let query = format!(
+            "comp=block&blockid={}",
+            utf8_percent_encode(block_id, NON_ALPHANUMERIC)
+        );
+        let headers = [("content-length", len.to_string())];
+        let request = self
+            .signed_request(
+                Method::PUT,
+                &self.blob_url(blob_name, &query),
+                &headers,
+                body,
+            )
+            .map_err(Retry::NotRetryable)?;
+        self.send(request).await?;
+        Ok(())
+    }
+
+    async fn put_block_list(&self, blob_name: &str, block_ids: &[String]) -> StorageResult<()> {
+        let mut block_list = String::from(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#);
+        for block_id in block_ids {
+            block_list.push_str(&format!("<Latest>{}</Latest>", block_id));
+        }
+        block_list.push_str("</BlockList>");
+        let headers = [("content-length", block_list.len().to_string())];
+        retry(|| async {
+            let request = self
+                .signed_request(
+                    Method::PUT,
+                    &self.blob_url(blob_name, "comp=blocklist"),
+                    &headers,
+                    Body::from(block_list.clone()),
+                )
+                .map_err(Retry::NotRetryable)?;
+            self.send(request).await?;
+            Ok(())
+        })
+        .await
+        .map_err(Retry::into_inner)
+    }
+
+    /// Uploads the parts of the payload as the blocks of the blob, then commits them.
+    ///
+    /// The blocks of a failed upload are never committed, and the service discards them
+    /// after a week.
+    async fn put_multi_part(
+        &self,
+        blob_name: &str,
+        payload: PutPayload,
+        part_len: u64,
+        len: u64,
+        progress: &UploadProgress,
+    ) -> StorageResult<()> {
+        let max_concurrent_upload = self.multipart_policy.max_concurrent_upload();
+        let block_ids: Vec<String> = stream::iter(
+            split_range_into_chunks(len, part_len)
+                .into_iter()
+                .enumerate()
+                .map(|(block_ord, range)| {
+                    let payload = payload.clone();
+                    async move {
+                        let block_id = block_id(block_ord);
+                        let block_len = range.end - range.start;
+                        retry(|| self.put_block(blob_name, &block_id, &payload, range.clone()))
+                            .await
+                            .map_err(Retry::into_inner)?;
+                        progress.record_uploaded_bytes(block_len);
+                        Ok::<_, StorageError>(block_id)
+                    }
+                }),
+        )
+        .buffered(max_concurrent_upload)
+        .try_collect()
+        .await?;
+        self.put_block_list(blob_name, &block_ids).await
+    }
+
+    /// Sends a `GET` request of the blob, or of the slice `range_opt` of the blob.
+    async fn get_blob(
+        &self,
+        path: &Path,
+        range_opt: Option<Range<usize>>,
+    ) -> Result<Response<Body>, Retry<StorageError>> {
+        let blob_name = self.blob_name(path);
+        let headers: Vec<(&str, String)> = range_opt
+            .map(|range| {
+                (
+                    "x-ms-range",
+                    format!("bytes={}-{}", range.start, range.end - 1),
+                )
+            })
+            .into_iter()
+            .collect();
+        let request = self
+            .signed_request(
+                Method::GET,
+                &self.blob_url(&blob_name, ""),
+                &headers,
+                Body::empty(),
+            )
+            .map_err(Retry::NotRetryable)?;
+        self.send(request).await
+    }
+
+    async fn get_to_bytes(
+        &self,
+        path: &Path,
+        range_opt: Option<Range<usize>>,
+    ) -> StorageResult<Bytes> {
+        retry(|| async {
+            let response = self.get_blob(path, range_opt.clone()).await?;
+            hyper::body::to_bytes(response.into_body())
+                .await
+                .map_err(retryable_io_error)
+        })
+        .await
+        .map_err(Retry::into_inner)
+    }
+}
+
+#[async_trait]
+impl Storage for AzureBlobStorage {
+    async fn put(&self, path: &Path, payload: PutPayload) -> StorageResult<()> {
+        self.put_with_progress(path, payload, &UploadProgress::default())
+            .await
+    }
+
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        payload: PutPayload,
+        progress: &UploadProgress,
+    ) -> StorageResult<()> {
+        let blob_name = self.blob_name(path);
+        let len = payload.len().await?;
+        let part_num_bytes = self.multipart_policy.part_num_bytes(len);
+        if part_num_bytes >= len {
+            retry(|| self.put_single_part_single_try(&blob_name, &payload, len))
+                .await
+                .map_err(Retry::into_inner)?;
+            progress.record_upload_completed();
+        } else {
+            self.put_multi_part(&blob_name, payload, part_num_bytes, len, progress)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn copy_to_file(&self, path: &Path, output_path: &Path) -> StorageResult<()> {
+        let response = retry(|| self.get_blob(path, None))
+            .await
+            .map_err(Retry::into_inner)?;
+        let mut body = response.into_body();
+        let mut dest_file = File::create(output_path).await?;
+        while let Some(chunk_res) = body.data().await {
+            let chunk = chunk_res.map_err(|error| StorageErrorKind::Io.with_error(error))?;
+            dest_file.write_all(&chunk).await?;
+        }
+        dest_file.flush().await?;
+        Ok(())
+    }
+
+    async fn get_slice(&self, path: &Path, range: Range<usize>) -> StorageResult<Bytes> {
+        self.get_to_bytes(path, Some(range.clone()))
+            .await
+            .map_err(|err| {
+                err.add_context(format!(
+                    "Failed to fetch slice {:?} for blob: {}",
+                    range,
+                    self.uri(path)
+                ))
+            })
+    }
+
+    async fn get_all(&self, path: &Path) -> StorageResult<Bytes> {
+        self.get_to_bytes(path, None)
+            .await
+            .map_err(|err| err.add_context(format!("Failed to fetch blob: {}", self.uri(path))))
+    }
+
+    async fn delete(&self, path: &Path) -> StorageResult<()> {
+        let blob_name = self.blob_name(path);
+        let delete_res = retry(|| async {
+            let request = self
+                .signed_request(
+                    Method::DELETE,
+                    &self.blob_url(&blob_name, ""),
+                    &[],
+                    Body::empty(),
+                )
+                .map_err(Retry::NotRetryable)?;
+            self.send(request).await
+        })
+        .await
+        .map_err(Retry::into_inner);
+        match delete_res {
+            Ok(_) => Ok(()),
+            Err(error) if error.kind() == StorageErrorKind::DoesNotExist => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn file_num_bytes(&self, path: &Path) -> StorageResult<u64> {
+        let blob_name = self.blob_name(path);
+        let response = retry(|| async {
+            let request = self
+                .signed_request(
+                    Method::HEAD,
+                    &self.blob_url(&blob_name, ""),
+                    &[],
+                    Body::empty(),
+                )
+                .map_err(Retry::NotRetryable)?;
+            self.send(request).await
+        })
+        .await
+        .map_err(Retry::into_inner)
+        .map_err(|err| err.add_context(format!("Missing blob `{}`", self.uri(path))))?;
+        response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .ok_or_else(|| {
+                StorageErrorKind::Service.with_error(anyhow::anyhow!(
+                    "Properties of blob `{}` did not contain a valid content length.",
+                    self.uri(path)
+                ))
+            })
+    }
+
+    fn uri(&self) -> String {
+        format!(
+            "azure://{}/{}",
+            self.container,
+            self.prefix.to_string_lossy()
+        )
+    }
+}
+
+/// Azure Blob Storage URI resolver, for the `azure://<container>/<prefix>` URIs.
+///
+/// The name of the storage account and its access key are read from the
+/// `AZURE_STORAGE_ACCOUNT` and `AZURE_STORAGE_ACCESS_KEY` environment variables. The Blob
+/// service of the account is reached at `https://<account>.blob.core.windows.net`, unless
+/// the `QUICKWIT_AZURE_STORAGE_ENDPOINT` environment variable sets another endpoint, e.g. the
+/// one of the Azurite emulator.
+///
+/// The HTTP client is created on the first storage resolved, and shared by all the storages.
+#[derive(Default)]
+pub struct AzureBlobStorageFactory {
+    http_client: OnceCell<HttpsClient>,
+}
+
+fn env_variable(key: &str) -> StorageResult<String> {
+    std::env::var(key).map_err(|_| {
+        StorageErrorKind::Unauthorized.with_error(anyhow::anyhow!(
+            "The environment variable `{}` must be set to access Azure Blob Storage.",
+            key
+        ))
+    })
+}
+
+impl StorageFactory for AzureBlobStorageFactory {
+    fn protocol(&self) -> String {
+        "azure".to_string()
+    }
+
+    fn resolve(&self, uri: &QuickwitUri) -> StorageResult<Arc<dyn Storage>> {
+        let (container, prefix) = parse_azure_uri(uri.as_str()).ok_or_else(|| {
+            StorageErrorKind::Io.with_error(anyhow::anyhow!("Invalid uri: {}", uri.as_str()))
+        })?;
+        let account = env_variable(AZURE_STORAGE_ACCOUNT_ENV_KEY)?;
+        let access_key = env_variable(AZURE_STORAGE_ACCESS_KEY_ENV_KEY)?;
+        let shared_key = SharedKey::new(&account, &access_key)
+            .map_err(|error| StorageErrorKind::Unauthorized.with_error(error))?;
+        let endpoint = std::env::var(AZURE_STORAGE_ENDPOINT_ENV_KEY)
+            .unwrap_or_else(|_| format!("https://{}.blob.core.windows.net", account));
+        let http_client = self
+            .http_client
+            .get_or_try_init(create_http_client)
+            .map_err(|error| StorageErrorKind::Service.with_error(error))?;
+        let storage = AzureBlobStorage::new_with_client(
+            http_client.clone(),
+            shared_key,
+            &endpoint,
+            &container,
+        )
+        .with_prefix(&prefix);
+        Ok(Arc::new(storage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The access key of the Azurite emulator, documented publicly.
+    const AZURITE_ACCESS_KEY: &str =
+        "Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==";
+
+    #[test]
+    fn test_parse_azure_uri() {
+        assert_eq!(
+            parse_azure_uri("azure://container/path/to/object"),
+            Some(("container".to_string(), PathBuf::from("path/to/object")))
+        );
+        assert_eq!(
+            parse_azure_uri("azure://container/"),
+            Some(("container".to_string(), PathBuf::from("")))
+        );
+        assert_eq!(
+            parse_azure_uri("azure://container"),
+            Some(("container".to_string(), PathBuf::from("")))
+        );
+        assert_eq!(parse_azure_uri("s3://bucket/path/to"), None);
+    }
+
+    #[test]
+    fn test_shared_key_validates_account_and_access_key() {
+        assert!(SharedKey::new("quickwit", AZURITE_ACCESS_KEY).is_ok());
+        assert!(SharedKey::new("Quickwit", AZURITE_ACCESS_KEY).is_err());
+        assert!(SharedKey::new("quickwit", "not base64!").is_err());
+    }
+
+    #[test]
+    fn test_string_to_sign() {
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri("https://quickwit.blob.core.windows.net/indexes/my-index/split%201.split?comp=block&blockid=MDAwMDAx")
+            .header("x-ms-version", AZURE_STORAGE_API_VERSION)
+            .header("x-ms-date", "Wed, 14 Oct 2026 12:00:00 GMT")
+            .header("content-length", "26")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            string_to_sign("quickwit", &request),
+            "PUT\n\n\n26\n\n\n\n\n\n\n\n\nx-ms-date:Wed, 14 Oct 2026 12:00:00 \
+             GMT\nx-ms-version:2020-10-02\n/quickwit/indexes/my-index/split%201.split\nblockid:\
+             MDAwMDAx\ncomp:block"
+        );
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("http://localhost:10000/devstoreaccount1/indexes/my-index/quickwit.json")
+            .header("x-ms-version", AZURE_STORAGE_API_VERSION)
+            .header("x-ms-date", "Wed, 14 Oct 2026 12:00:00 GMT")
+            .header("content-length", "0")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            string_to_sign("devstoreaccount1", &request),
+            "GET\n\n\n\n\n\n\n\n\n\n\n\nx-ms-date:Wed, 14 Oct 2026 12:00:00 \
+             GMT\nx-ms-version:2020-10-02\n/devstoreaccount1/devstoreaccount1/indexes/my-index/\
+             quickwit.json"
+        );
+    }
+
+    #[test]
+    fn test_block_id() {
+        assert_eq!(block_id(1), "MDAwMDAx");
+        assert_eq!(block_id(49_999).len(), block_id(0).len());
+    }
+
+    #[test]
+    fn test_blob_url() -> anyhow::Result<()> {
+        let storage = AzureBlobStorage::new(
+            "quickwit",
+            AZURITE_ACCESS_KEY,
+            "https://quickwit.blob.core.windows.net/",
+            "indexes",
+        )?
+        .with_prefix(Path::new("my-index"));
+        assert_eq!(
+            storage.blob_url(&storage.blob_name(Path::new("split 1.split")), ""),
+            "https://quickwit.blob.core.windows.net/indexes/my-index/split%201.split"
+        );
+        assert_eq!(
+            storage.blob_url(
+                &storage.blob_name(Path::new("quickwit.json")),
+                "comp=blocklist"
+            ),
+            "https://quickwit.blob.core.windows.net/indexes/my-index/quickwit.json?comp=blocklist"
+        );
+        assert_eq!(Storage::uri(&storage), "azure://indexes/my-index");
+        Ok(())
+    }
+}
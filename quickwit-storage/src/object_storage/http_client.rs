@@ -0,0 +1,142 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use hyper::client::HttpConnector;
+use hyper_rustls::HttpsConnector;
+use rusoto_core::request::{DispatchSignedRequest, DispatchSignedRequestFuture};
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::HttpClient;
+
+/// Settings of the HTTP client of the S3 storages.
+///
+/// The storages resolved by a factory share the connection pool of its client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct S3HttpConfig {
+    /// Maximum number of idle connections kept alive per host.
+    pub max_idle_connections_per_host: usize,
+    /// Duration after which the idle connections are closed.
+    pub pool_idle_timeout: Duration,
+    /// Deadline of the establishment of the connections.
+    pub connect_timeout: Duration,
+    /// If set, deadline of the requests, until the headers of their response are received.
+    pub read_timeout_opt: Option<Duration>,
+    /// If true, HTTP/2 is negotiated with the endpoints supporting it. Amazon S3 only
+    /// supports HTTP/1.1, but some S3 compatible object storages support HTTP/2.
+    pub http2: bool,
+}
+
+impl Default for S3HttpConfig {
+    fn default() -> Self {
+        S3HttpConfig {
+            max_idle_connections_per_host: 256,
+            // We experience an issue similar to https://github.com/hyperium/hyper/issues/2312.
+            // It seems like a short idle timeout solves it.
+            pool_idle_timeout: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(5),
+            read_timeout_opt: None,
+            http2: false,
+        }
+    }
+}
+
+/// Request dispatcher of the S3 clients, sharing the connection pool of its HTTP client.
+#[derive(Clone)]
+pub(crate) struct S3RequestDispatcher {
+    http_client: Arc<HttpClient>,
+    read_timeout_opt: Option<Duration>,
+}
+
+/// Returns the connector of the HTTP clients configured by `http_config`, accepting both
+/// `http` and `https` endpoints.
+pub(crate) fn https_connector(
+    http_config: &S3HttpConfig,
+) -> anyhow::Result<HttpsConnector<HttpConnector>> {
+    let mut http_connector = HttpConnector::new();
+    http_connector.enforce_http(false);
+    http_connector.set_connect_timeout(Some(http_config.connect_timeout));
+    let mut tls_config = rustls::ClientConfig::new();
+    tls_config.root_store = rustls_native_certs::load_native_certs()
+        .map_err(|(_, error)| error)
+        .with_context(|| "Failed to load the root certificates.")?;
+    tls_config.alpn_protocols = if http_config.http2 {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    };
+    Ok(HttpsConnector::from((http_connector, tls_config)))
+}
+
+/// Returns the builder of the HTTP clients configured by `http_config`.
+pub(crate) fn client_builder(http_config: &S3HttpConfig) -> hyper::client::Builder {
+    let mut client_builder = hyper::Client::builder();
+    client_builder
+        .pool_max_idle_per_host(http_config.max_idle_connections_per_host)
+        .pool_idle_timeout(http_config.pool_idle_timeout);
+    client_builder
+}
+
+impl S3RequestDispatcher {
+    pub fn new(http_config: &S3HttpConfig) -> anyhow::Result<Self> {
+        let https_connector = https_connector(http_config)?;
+        let client_builder = client_builder(http_config);
+        Ok(S3RequestDispatcher {
+            http_client: Arc::new(HttpClient::from_builder(client_builder, https_connector)),
+            read_timeout_opt: http_config.read_timeout_opt,
+        })
+    }
+}
+
+impl DispatchSignedRequest for S3RequestDispatcher {
+    fn dispatch(
+        &self,
+        request: SignedRequest,
+        timeout_opt: Option<Duration>,
+    ) -> DispatchSignedRequestFuture {
+        self.http_client
+            .dispatch(request, timeout_opt.or(self.read_timeout_opt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_s3_request_dispatcher_shares_http_client() -> anyhow::Result<()> {
+        let request_dispatcher = S3RequestDispatcher::new(&S3HttpConfig {
+            read_timeout_opt: Some(Duration::from_secs(3)),
+            http2: true,
+            ..Default::default()
+        })?;
+        let other_request_dispatcher = request_dispatcher.clone();
+        assert!(Arc::ptr_eq(
+            &request_dispatcher.http_client,
+            &other_request_dispatcher.http_client
+        ));
+        assert_eq!(
+            other_request_dispatcher.read_timeout_opt,
+            Some(Duration::from_secs(3))
+        );
+        Ok(())
+    }
+}
@@ -17,9 +17,15 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+mod azure_blob_storage;
+pub use self::azure_blob_storage::{AzureBlobStorage, AzureBlobStorageFactory};
+
 mod error;
 mod file_slice_stream;
 
+mod http_client;
+pub use self::http_client::S3HttpConfig;
+
 mod s3_compatible_storage;
 pub use self::s3_compatible_storage::S3CompatibleObjectStorage;
 pub use self::s3_compatible_storage_uri_resolver::{
@@ -30,3 +36,6 @@ mod policy;
 pub use crate::object_storage::policy::MultiPartPolicy;
 
 mod s3_compatible_storage_uri_resolver;
+
+mod s3_credentials;
+pub use self::s3_credentials::{S3Credentials, S3CredentialsRule, S3_CREDENTIALS_ENV_KEY};
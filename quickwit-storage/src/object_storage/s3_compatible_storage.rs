@@ -30,7 +30,7 @@ use futures::{stream, StreamExt};
 use once_cell::sync::OnceCell;
 use regex::Regex;
 use rusoto_core::credential::{AutoRefreshingProvider, ChainProvider};
-use rusoto_core::{ByteStream, HttpClient, HttpConfig, Region, RusotoError};
+use rusoto_core::{ByteStream, Region, RusotoError};
 use rusoto_s3::{
     AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedMultipartUpload,
     CompletedPart, CreateMultipartUploadError, CreateMultipartUploadRequest, DeleteObjectRequest,
@@ -43,17 +43,16 @@ use tokio_util::io::ReaderStream;
 use tracing::warn;
 
 use super::error::RusotoErrorWrapper;
+use super::http_client::{S3HttpConfig, S3RequestDispatcher};
+use super::s3_credentials::CachedCredentialsProvider;
 use crate::object_storage::file_slice_stream::FileSliceStream;
 use crate::object_storage::MultiPartPolicy;
 use crate::retry::{retry, IsRetryable, Retry};
-use crate::{PutPayload, Storage, StorageError, StorageErrorKind, StorageResult};
+use crate::{PutPayload, Storage, StorageError, StorageErrorKind, StorageResult, UploadProgress};
 
 /// A credential timeout.
 const CREDENTIAL_TIMEOUT: u64 = 5;
 
-/// An timeout for idle sockets being kept-alive.
-const POOL_IDLE_TIMEOUT: u64 = 10;
-
 /// S3 Compatible object storage implementation.
 pub struct S3CompatibleObjectStorage {
     s3_client: S3Client,
@@ -72,19 +71,24 @@ impl fmt::Debug for S3CompatibleObjectStorage {
     }
 }
 
-fn create_s3_client(region: Region) -> anyhow::Result<S3Client> {
+fn create_s3_client(
+    region: Region,
+    request_dispatcher: S3RequestDispatcher,
+    credentials_provider_opt: Option<CachedCredentialsProvider>,
+) -> anyhow::Result<S3Client> {
+    if let Some(credentials_provider) = credentials_provider_opt {
+        return Ok(S3Client::new_with(
+            request_dispatcher,
+            credentials_provider,
+            region,
+        ));
+    }
     let mut chain_provider = ChainProvider::new();
     chain_provider.set_timeout(Duration::from_secs(CREDENTIAL_TIMEOUT));
     let credentials_provider = AutoRefreshingProvider::new(chain_provider)
         .with_context(|| "Failed to fetch credentials for the object storage.")?;
-    let mut http_config: HttpConfig = HttpConfig::default();
-    // We experience an issue similar to https://github.com/hyperium/hyper/issues/2312.
-    // It seems like the setting below solved it.
-    http_config.pool_idle_timeout(std::time::Duration::from_secs(POOL_IDLE_TIMEOUT));
-    let http_client = HttpClient::new_with_config(http_config)
-        .with_context(|| "failed to create request dispatcher")?;
     Ok(S3Client::new_with(
-        http_client,
+        request_dispatcher,
         credentials_provider,
         region,
     ))
@@ -93,7 +97,20 @@ fn create_s3_client(region: Region) -> anyhow::Result<S3Client> {
 impl S3CompatibleObjectStorage {
     /// Creates an object storage given a region and a bucket name.
     pub fn new(region: Region, bucket: &str) -> anyhow::Result<S3CompatibleObjectStorage> {
-        let s3_client = create_s3_client(region)?;
+        let request_dispatcher = S3RequestDispatcher::new(&S3HttpConfig::default())?;
+        S3CompatibleObjectStorage::new_with_client(region, bucket, request_dispatcher, None)
+    }
+
+    /// Creates an object storage given a region and a bucket name, sending its requests
+    /// through `request_dispatcher`. They are signed with `credentials_provider_opt` instead
+    /// of the default credentials chain if it is set.
+    pub(crate) fn new_with_client(
+        region: Region,
+        bucket: &str,
+        request_dispatcher: S3RequestDispatcher,
+        credentials_provider_opt: Option<CachedCredentialsProvider>,
+    ) -> anyhow::Result<S3CompatibleObjectStorage> {
+        let s3_client = create_s3_client(region, request_dispatcher, credentials_provider_opt)?;
         Ok(S3CompatibleObjectStorage {
             s3_client,
             bucket: bucket.to_string(),
@@ -104,11 +121,29 @@ impl S3CompatibleObjectStorage {
 
     /// Creates an object storage given a region and an uri.
     pub fn from_uri(region: Region, uri: &str) -> crate::StorageResult<S3CompatibleObjectStorage> {
+        let request_dispatcher = S3RequestDispatcher::new(&S3HttpConfig::default())
+            .map_err(|err| crate::StorageErrorKind::Service.with_error(err))?;
+        S3CompatibleObjectStorage::from_uri_with_client(region, uri, request_dispatcher, None)
+    }
+
+    /// Creates an object storage given a region and an uri, see
+    /// [`S3CompatibleObjectStorage::new_with_client`].
+    pub(crate) fn from_uri_with_client(
+        region: Region,
+        uri: &str,
+        request_dispatcher: S3RequestDispatcher,
+        credentials_provider_opt: Option<CachedCredentialsProvider>,
+    ) -> crate::StorageResult<S3CompatibleObjectStorage> {
         let (bucket, path) = parse_uri(uri).ok_or_else(|| {
             crate::StorageErrorKind::Io.with_error(anyhow::anyhow!("Invalid uri: {}", uri))
         })?;
-        let s3_compatible_storage = S3CompatibleObjectStorage::new(region, &bucket)
-            .map_err(|err| crate::StorageErrorKind::Service.with_error(anyhow::anyhow!(err)))?;
+        let s3_compatible_storage = S3CompatibleObjectStorage::new_with_client(
+            region,
+            &bucket,
+            request_dispatcher,
+            credentials_provider_opt,
+        )
+        .map_err(|err| crate::StorageErrorKind::Service.with_error(anyhow::anyhow!(err)))?;
         Ok(s3_compatible_storage.with_prefix(&path))
     }
 
@@ -184,7 +219,7 @@ async fn range_byte_stream(payload: &PutPayload, range: Range<u64>) -> io::Resul
     }
 }
 
-fn split_range_into_chunks(len: u64, chunk_size: u64) -> Vec<Range<u64>> {
+pub(crate) fn split_range_into_chunks(len: u64, chunk_size: u64) -> Vec<Range<u64>> {
     (0..len)
         .step_by(chunk_size as usize)
         .map(move |start| Range {
@@ -355,6 +390,7 @@ impl S3CompatibleObjectStorage {
         payload: PutPayload,
         part_len: u64,
         len: u64,
+        progress: &UploadProgress,
     ) -> StorageResult<()> {
         let upload_id = self
             .create_multipart_upload(key)
@@ -368,9 +404,17 @@ impl S3CompatibleObjectStorage {
             stream::iter(parts.into_iter().map(|part| {
                 let payload = payload.clone();
                 let upload_id = upload_id.clone();
-                retry(move || {
-                    self.upload_part(upload_id.clone(), key, part.clone(), payload.clone())
-                })
+                async move {
+                    let part_len = part.len();
+                    let completed_part_res = retry(|| {
+                        self.upload_part(upload_id.clone(), key, part.clone(), payload.clone())
+                    })
+                    .await;
+                    if completed_part_res.is_ok() {
+                        progress.record_uploaded_bytes(part_len);
+                    }
+                    completed_part_res
+                }
             }))
             .buffered(max_concurrent_upload)
             .collect::<Vec<_>>()
@@ -490,13 +534,24 @@ async fn download_all(byte_stream: &mut ByteStream, output: &mut Vec<u8>) -> io:
 #[async_trait]
 impl Storage for S3CompatibleObjectStorage {
     async fn put(&self, path: &Path, payload: PutPayload) -> StorageResult<()> {
+        self.put_with_progress(path, payload, &UploadProgress::default())
+            .await
+    }
+
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        payload: PutPayload,
+        progress: &UploadProgress,
+    ) -> StorageResult<()> {
         let key = self.key(path);
         let len = payload.len().await?;
         let part_num_bytes = self.multipart_policy.part_num_bytes(len);
         if part_num_bytes >= len {
             self.put_single_part(&key, payload, len).await?;
+            progress.record_upload_completed();
         } else {
-            self.put_multi_part(&key, payload, part_num_bytes, len)
+            self.put_multi_part(&key, payload, part_num_bytes, len, progress)
                 .await?;
         }
         Ok(())
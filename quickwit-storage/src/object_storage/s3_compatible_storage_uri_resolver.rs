@@ -22,10 +22,12 @@ use std::sync::Arc;
 
 use ec2_instance_metadata::{InstanceMetadata, InstanceMetadataClient};
 use once_cell::sync::OnceCell;
-use quickwit_common::{get_quickwit_env, QuickwitEnv};
+use quickwit_common::{get_quickwit_env, QuickwitEnv, QuickwitUri};
 pub use rusoto_core::Region;
 
-use crate::{S3CompatibleObjectStorage, StorageFactory};
+use super::http_client::{S3HttpConfig, S3RequestDispatcher};
+use super::s3_credentials::S3CredentialsProviders;
+use crate::{S3CompatibleObjectStorage, S3CredentialsRule, StorageErrorKind, StorageFactory};
 
 /// The region provider lazily returns a region.
 ///
@@ -95,17 +97,56 @@ impl RegionProvider {
 /// The default implementation uses s3 as a protocol, and detects the region trying to us
 /// sequencially `AWS_DEFAULT_REGION` environment variable, `AWS_REGION` environment variable,
 /// region from ec2 instance metadata and lastly to default value `Region::UsEast1`.
+///
+/// The requests are signed with the credentials of the default credentials chain, or with the
+/// ones of the [`S3CredentialsRule`] matching the URI of the storage. They are sent through
+/// an HTTP client configured by [`S3HttpConfig`], created on the first storage resolved and
+/// shared by all the storages, so they reuse each other's connections.
 pub struct S3CompatibleObjectStorageFactory {
     region_provider: RegionProvider,
     protocol: &'static str,
+    credentials_providers: S3CredentialsProviders,
+    http_config: S3HttpConfig,
+    request_dispatcher: OnceCell<S3RequestDispatcher>,
 }
 
 impl S3CompatibleObjectStorageFactory {
     /// Creates a new S3CompatibleObjetStorageFactory with the given AWS region.
+    ///
+    /// The credentials rules are read from the `QUICKWIT_S3_CREDENTIALS` environment variable.
+    /// If it cannot be parsed, resolving a storage fails.
     pub fn new(region_provider: RegionProvider, protocol: &'static str) -> Self {
         S3CompatibleObjectStorageFactory {
             region_provider,
             protocol,
+            credentials_providers: S3CredentialsProviders::from_env(),
+            http_config: S3HttpConfig::default(),
+            request_dispatcher: OnceCell::new(),
+        }
+    }
+
+    /// Creates a new S3CompatibleObjetStorageFactory with the given AWS region and
+    /// credentials rules.
+    pub fn with_credentials_rules(
+        region_provider: RegionProvider,
+        protocol: &'static str,
+        credentials_rules: Vec<S3CredentialsRule>,
+    ) -> Self {
+        S3CompatibleObjectStorageFactory {
+            region_provider,
+            protocol,
+            credentials_providers: S3CredentialsProviders::new(credentials_rules),
+            http_config: S3HttpConfig::default(),
+            request_dispatcher: OnceCell::new(),
+        }
+    }
+
+    /// Sets the settings of the HTTP client of the storages.
+    pub fn with_http_config(self, http_config: S3HttpConfig) -> Self {
+        S3CompatibleObjectStorageFactory {
+            http_config,
+            request_dispatcher: OnceCell::new(),
+            ..self
         }
     }
 }
@@ -138,8 +179,24 @@ impl StorageFactory for S3CompatibleObjectStorageFactory {
         self.protocol.to_string()
     }
 
-    fn resolve(&self, uri: &str) -> crate::StorageResult<std::sync::Arc<dyn crate::Storage>> {
-        let storage = S3CompatibleObjectStorage::from_uri(self.region_provider.get_region(), uri)?;
+    fn resolve(
+        &self,
+        uri: &QuickwitUri,
+    ) -> crate::StorageResult<std::sync::Arc<dyn crate::Storage>> {
+        let region = self.region_provider.get_region();
+        let credentials_provider_res = self.credentials_providers.provider(uri.as_str(), &region);
+        let credentials_provider_opt = credentials_provider_res
+            .map_err(|error| StorageErrorKind::Unauthorized.with_error(error))?;
+        let request_dispatcher = self
+            .request_dispatcher
+            .get_or_try_init(|| S3RequestDispatcher::new(&self.http_config))
+            .map_err(|error| StorageErrorKind::Service.with_error(error))?;
+        let storage = S3CompatibleObjectStorage::from_uri_with_client(
+            region,
+            uri.as_str(),
+            request_dispatcher.clone(),
+            credentials_provider_opt,
+        )?;
         Ok(Arc::new(storage))
     }
 }
@@ -0,0 +1,367 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use once_cell::sync::OnceCell;
+use rusoto_core::credential::{
+    AutoRefreshingProvider, AwsCredentials, ChainProvider, CredentialsError, ProfileProvider,
+    ProvideAwsCredentials,
+};
+use rusoto_core::{HttpClient, Region};
+use rusoto_sts::{StsAssumeRoleSessionCredentialsProvider, StsClient};
+use tracing::error;
+
+/// Environment variable holding the credentials rules of the S3 storages, separated by `;`.
+pub const S3_CREDENTIALS_ENV_KEY: &str = "QUICKWIT_S3_CREDENTIALS";
+
+/// Name of the sessions opened when assuming a role.
+const ROLE_SESSION_NAME: &str = "quickwit";
+
+/// Timeout of the requests fetching the credentials used to assume a role.
+const CREDENTIAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Credentials used to sign the requests sent to S3.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum S3Credentials {
+    /// Temporary credentials obtained by assuming the role `role_arn` through STS, with the
+    /// credentials of the node.
+    AssumeRole { role_arn: String },
+    /// Credentials of the profile `profile_name` of the AWS credentials file.
+    Profile { profile_name: String },
+}
+
+/// Credentials of the storages whose URI starts with `uri_prefix`, given as
+/// `<uri_prefix>=role:<role_arn>` or `<uri_prefix>=profile:<profile_name>`.
+///
+/// Multi-account setups store the indexes of each account in their own bucket: each rule
+/// targets the bucket, or the prefix of the bucket, holding the indexes of an account.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct S3CredentialsRule {
+    pub uri_prefix: String,
+    pub credentials: S3Credentials,
+}
+
+impl S3CredentialsRule {
+    /// Returns true if `uri` is `uri_prefix` or one of its sub-paths.
+    fn matches(&self, uri: &str) -> bool {
+        let uri_prefix = self.uri_prefix.trim_end_matches('/');
+        match uri.strip_prefix(uri_prefix) {
+            Some(uri_suffix) => uri_suffix.is_empty() || uri_suffix.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+impl FromStr for S3CredentialsRule {
+    type Err = anyhow::Error;
+
+    fn from_str(rule_str: &str) -> anyhow::Result<Self> {
+        let (uri_prefix, credentials_str) = rule_str.split_once('=').with_context(|| {
+            format!(
+                "Invalid S3 credentials `{}`, expected `<uri_prefix>=role:<role_arn>` or \
+                 `<uri_prefix>=profile:<profile_name>`.",
+                rule_str
+            )
+        })?;
+        if !uri_prefix.starts_with("s3") || !uri_prefix.contains("://") {
+            bail!(
+                "Invalid S3 credentials `{}`, `{}` is not an S3 URI.",
+                rule_str,
+                uri_prefix
+            );
+        }
+        let credentials = match credentials_str.split_once(':') {
+            Some(("role", role_arn)) if !role_arn.is_empty() => S3Credentials::AssumeRole {
+                role_arn: role_arn.to_string(),
+            },
+            Some(("profile", profile_name)) if !profile_name.is_empty() => S3Credentials::Profile {
+                profile_name: profile_name.to_string(),
+            },
+            _ => bail!(
+                "Invalid S3 credentials `{}`, expected `role:<role_arn>` or \
+                 `profile:<profile_name>` after the URI prefix.",
+                rule_str
+            ),
+        };
+        Ok(S3CredentialsRule {
+            uri_prefix: uri_prefix.to_string(),
+            credentials,
+        })
+    }
+}
+
+/// Parses the `;`-separated credentials rules of `rules_str`.
+pub(crate) fn parse_s3_credentials_rules(
+    rules_str: &str,
+) -> anyhow::Result<Vec<S3CredentialsRule>> {
+    rules_str
+        .split(';')
+        .map(str::trim)
+        .filter(|rule_str| !rule_str.is_empty())
+        .map(S3CredentialsRule::from_str)
+        .collect()
+}
+
+/// Returns the credentials rules of the `QUICKWIT_S3_CREDENTIALS` environment variable.
+pub(crate) fn s3_credentials_rules_from_env() -> anyhow::Result<Vec<S3CredentialsRule>> {
+    let rules_str = match std::env::var(S3_CREDENTIALS_ENV_KEY) {
+        Ok(rules_str) => rules_str,
+        Err(_) => return Ok(Vec::new()),
+    };
+    parse_s3_credentials_rules(&rules_str)
+        .with_context(|| format!("Failed to parse `{}`.", S3_CREDENTIALS_ENV_KEY))
+}
+
+/// Credentials provider shared by all the storages of a credentials rule.
+///
+/// The credentials are cached, and refreshed once they expire, so the storages resolved for
+/// each request do not fetch them again.
+#[derive(Clone)]
+pub(crate) enum CachedCredentialsProvider {
+    AssumeRole(Arc<AutoRefreshingProvider<StsAssumeRoleSessionCredentialsProvider>>),
+    Profile(Arc<AutoRefreshingProvider<ProfileProvider>>),
+}
+
+impl fmt::Debug for CachedCredentialsProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CachedCredentialsProvider::AssumeRole(_) => write!(f, "AssumeRole"),
+            CachedCredentialsProvider::Profile(_) => write!(f, "Profile"),
+        }
+    }
+}
+
+impl CachedCredentialsProvider {
+    fn new(credentials: &S3Credentials, region: Region) -> anyhow::Result<Self> {
+        let credentials_provider = match credentials {
+            S3Credentials::AssumeRole { role_arn } => {
+                let mut chain_provider = ChainProvider::new();
+                chain_provider.set_timeout(CREDENTIAL_TIMEOUT);
+                let http_client =
+                    HttpClient::new().with_context(|| "Failed to create the STS client.")?;
+                let sts_client = StsClient::new_with(http_client, chain_provider, region);
+                let assume_role_provider = StsAssumeRoleSessionCredentialsProvider::new(
+                    sts_client,
+                    role_arn.clone(),
+                    ROLE_SESSION_NAME.to_string(),
+                    None,
+                    None,
+                    None,
+                    None,
+                );
+                CachedCredentialsProvider::AssumeRole(Arc::new(AutoRefreshingProvider::new(
+                    assume_role_provider,
+                )?))
+            }
+            S3Credentials::Profile { profile_name } => {
+                let mut profile_provider = ProfileProvider::new()?;
+                profile_provider.set_profile(profile_name.clone());
+                CachedCredentialsProvider::Profile(Arc::new(AutoRefreshingProvider::new(
+                    profile_provider,
+                )?))
+            }
+        };
+        Ok(credentials_provider)
+    }
+}
+
+#[async_trait]
+impl ProvideAwsCredentials for CachedCredentialsProvider {
+    async fn credentials(&self) -> Result<AwsCredentials, CredentialsError> {
+        match self {
+            CachedCredentialsProvider::AssumeRole(provider) => provider.credentials().await,
+            CachedCredentialsProvider::Profile(provider) => provider.credentials().await,
+        }
+    }
+}
+
+/// Credentials rules, along with their providers, created on the first storage they apply
+/// to.
+pub(crate) struct S3CredentialsProviders {
+    rules: Vec<(S3CredentialsRule, OnceCell<CachedCredentialsProvider>)>,
+    invalid_rules_error_opt: Option<String>,
+}
+
+impl S3CredentialsProviders {
+    pub fn new(rules: Vec<S3CredentialsRule>) -> Self {
+        S3CredentialsProviders {
+            rules: rules
+                .into_iter()
+                .map(|rule| (rule, OnceCell::new()))
+                .collect(),
+            invalid_rules_error_opt: None,
+        }
+    }
+
+    /// Returns the providers of the rules of the `QUICKWIT_S3_CREDENTIALS` environment
+    /// variable.
+    ///
+    /// If the variable cannot be parsed, no storage is given any provider: signing the
+    /// requests with the default credentials chain instead would let the storages of an
+    /// account be accessed with the identity of another one.
+    pub fn from_env() -> Self {
+        S3CredentialsProviders::from_rules_res(s3_credentials_rules_from_env())
+    }
+
+    fn from_rules_res(rules_res: anyhow::Result<Vec<S3CredentialsRule>>) -> Self {
+        match rules_res {
+            Ok(rules) => S3CredentialsProviders::new(rules),
+            Err(error) => {
+                error!(error = ?error, "Invalid S3 credentials rules, the S3 storages cannot be resolved.");
+                S3CredentialsProviders {
+                    rules: Vec::new(),
+                    invalid_rules_error_opt: Some(format!("{:#}", error)),
+                }
+            }
+        }
+    }
+
+    /// Returns the provider of the rule with the longest URI prefix matching `uri`, or `None`
+    /// if no rule matches, in which case the default credentials chain applies.
+    pub fn provider(
+        &self,
+        uri: &str,
+        region: &Region,
+    ) -> anyhow::Result<Option<CachedCredentialsProvider>> {
+        if let Some(invalid_rules_error) = &self.invalid_rules_error_opt {
+            bail!("{}", invalid_rules_error);
+        }
+        let (rule, provider_cell) = match self
+            .rules
+            .iter()
+            .filter(|(rule, _)| rule.matches(uri))
+            .max_by_key(|(rule, _)| rule.uri_prefix.trim_end_matches('/').len())
+        {
+            Some(rule_and_provider) => rule_and_provider,
+            None => return Ok(None),
+        };
+        let provider = provider_cell
+            .get_or_try_init(|| CachedCredentialsProvider::new(&rule.credentials, region.clone()))
+            .with_context(|| {
+                format!(
+                    "Failed to create the credentials provider of `{}`.",
+                    rule.uri_prefix
+                )
+            })?;
+        Ok(Some(provider.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_s3_credentials_rule_from_str() -> anyhow::Result<()> {
+        assert_eq!(
+            S3CredentialsRule::from_str(
+                "s3://team-a-bucket/indexes=role:arn:aws:iam::123456789012:role/quickwit"
+            )?,
+            S3CredentialsRule {
+                uri_prefix: "s3://team-a-bucket/indexes".to_string(),
+                credentials: S3Credentials::AssumeRole {
+                    role_arn: "arn:aws:iam::123456789012:role/quickwit".to_string()
+                },
+            }
+        );
+        assert_eq!(
+            S3CredentialsRule::from_str("s3://team-b-bucket=profile:team-b")?,
+            S3CredentialsRule {
+                uri_prefix: "s3://team-b-bucket".to_string(),
+                credentials: S3Credentials::Profile {
+                    profile_name: "team-b".to_string()
+                },
+            }
+        );
+        assert!(S3CredentialsRule::from_str("s3://team-b-bucket").is_err());
+        assert!(S3CredentialsRule::from_str("file:///indexes=profile:team-b").is_err());
+        assert!(S3CredentialsRule::from_str("s3://team-b-bucket=key:secret").is_err());
+        assert!(S3CredentialsRule::from_str("s3://team-b-bucket=role:").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_s3_credentials_rules() -> anyhow::Result<()> {
+        let rules = parse_s3_credentials_rules(
+            "s3://team-a-bucket=profile:team-a; s3://team-b-bucket=profile:team-b;",
+        )?;
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[1].uri_prefix, "s3://team-b-bucket");
+        assert!(parse_s3_credentials_rules("")?.is_empty());
+        assert!(parse_s3_credentials_rules("s3://team-a-bucket=profile:team-a;oops").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_s3_credentials_rule_matches() -> anyhow::Result<()> {
+        let rule = S3CredentialsRule::from_str("s3://bucket/team-a/=profile:team-a")?;
+        assert!(rule.matches("s3://bucket/team-a"));
+        assert!(rule.matches("s3://bucket/team-a/my-index"));
+        assert!(!rule.matches("s3://bucket/team-ab/my-index"));
+        assert!(!rule.matches("s3://bucket/team-b/my-index"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_s3_credentials_providers_longest_prefix() -> anyhow::Result<()> {
+        let providers = S3CredentialsProviders::new(parse_s3_credentials_rules(
+            "s3://bucket=profile:bucket;s3://bucket/team-a=role:arn:aws:iam::123456789012:role/a",
+        )?);
+        let region = Region::UsEast1;
+        assert!(providers
+            .provider("s3://other-bucket/index", &region)?
+            .is_none());
+        assert!(matches!(
+            providers.provider("s3://bucket/team-a/index", &region)?,
+            Some(CachedCredentialsProvider::AssumeRole(_))
+        ));
+        let provider = providers.provider("s3://bucket/team-b/index", &region)?;
+        let other_provider = providers.provider("s3://bucket/team-c/index", &region)?;
+        match (provider, other_provider) {
+            (
+                Some(CachedCredentialsProvider::Profile(provider)),
+                Some(CachedCredentialsProvider::Profile(other_provider)),
+            ) => assert!(Arc::ptr_eq(&provider, &other_provider)),
+            _ => panic!("Expected the provider of the `s3://bucket` rule."),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_s3_credentials_providers_fail_closed_on_invalid_rules() -> anyhow::Result<()> {
+        let providers = S3CredentialsProviders::from_rules_res(parse_s3_credentials_rules(
+            "s3://team-a-bucket=profile:team-a;oops",
+        ));
+        let region = Region::UsEast1;
+        // Not even the storages that no rule targets get the default credentials chain.
+        assert!(providers
+            .provider("s3://team-a-bucket/index", &region)
+            .is_err());
+        assert!(providers
+            .provider("s3://other-bucket/index", &region)
+            .is_err());
+        Ok(())
+    }
+}
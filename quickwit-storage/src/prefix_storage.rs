@@ -24,7 +24,7 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use bytes::Bytes;
 
-use crate::Storage;
+use crate::{Storage, UploadProgress};
 
 /// This storage acts as a proxy to another storage that simply modifies each API call
 /// by preceding each path with a given a prefix.
@@ -39,6 +39,17 @@ impl Storage for PrefixStorage {
         self.storage.put(&self.prefix.join(path), payload).await
     }
 
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        payload: crate::PutPayload,
+        progress: &UploadProgress,
+    ) -> crate::StorageResult<()> {
+        self.storage
+            .put_with_progress(&self.prefix.join(path), payload, progress)
+            .await
+    }
+
     async fn copy_to_file(&self, path: &Path, output_path: &Path) -> crate::StorageResult<()> {
         self.storage
             .copy_to_file(&self.prefix.join(path), output_path)
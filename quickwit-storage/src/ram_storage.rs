@@ -25,6 +25,7 @@ use std::{fmt, io};
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use quickwit_common::QuickwitUri;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::RwLock;
@@ -168,21 +169,15 @@ impl StorageFactory for RamStorageFactory {
         "ram".to_string()
     }
 
-    fn resolve(&self, uri: &str) -> crate::StorageResult<Arc<dyn Storage>> {
-        if !uri.starts_with("ram://") {
+    fn resolve(&self, uri: &QuickwitUri) -> crate::StorageResult<Arc<dyn Storage>> {
+        if uri.protocol() != "ram" {
             let err_msg = anyhow::anyhow!(
                 "{:?} is an invalid ram storage uri. Only ram:// is accepted.",
-                uri
+                uri.as_str()
             );
             return Err(StorageErrorKind::DoesNotExist.with_error(err_msg));
         }
-
-        let prefix = uri.split("://").nth(1).ok_or_else(|| {
-            StorageErrorKind::DoesNotExist
-                .with_error(anyhow::anyhow!("Invalid prefix path: {}", uri))
-        })?;
-
-        Ok(add_prefix_to_storage(self.ram_storage.clone(), prefix))
+        Ok(add_prefix_to_storage(self.ram_storage.clone(), uri.path()))
     }
 }
 
@@ -201,14 +196,26 @@ mod tests {
     #[test]
     fn test_ram_storage_factory() {
         let ram_storage_factory = RamStorageFactory::default();
-        let err = ram_storage_factory.resolve("rom://toto").err().unwrap();
+        let err = ram_storage_factory
+            .resolve(&"rom://toto".parse().unwrap())
+            .err()
+            .unwrap();
         assert_eq!(err.kind(), StorageErrorKind::DoesNotExist);
 
-        let data_result = ram_storage_factory.resolve("ram://data").ok().unwrap();
-        let home_result = ram_storage_factory.resolve("ram://home/data").ok().unwrap();
+        let data_result = ram_storage_factory
+            .resolve(&"ram://data".parse().unwrap())
+            .ok()
+            .unwrap();
+        let home_result = ram_storage_factory
+            .resolve(&"ram://home/data".parse().unwrap())
+            .ok()
+            .unwrap();
         assert_ne!(data_result.uri(), home_result.uri());
 
-        let data_result_two = ram_storage_factory.resolve("ram://data").ok().unwrap();
+        let data_result_two = ram_storage_factory
+            .resolve(&"ram://data".parse().unwrap())
+            .ok()
+            .unwrap();
         assert_eq!(data_result.uri(), data_result_two.uri());
     }
 
@@ -24,7 +24,7 @@ use std::path::{Path, PathBuf};
 use async_trait::async_trait;
 use bytes::Bytes;
 
-use crate::{StorageErrorKind, StorageResult};
+use crate::{StorageErrorKind, StorageResult, UploadProgress};
 
 /// Payload argument of a put request.
 #[derive(Clone)]
@@ -83,12 +83,32 @@ impl From<&'static [u8]> for PutPayload {
 /// object storage treat them. This means when directory separators a present
 /// in the storage operation path, the storage implementation should create and remove transparently
 /// these intermediate directories.
+///
+/// Dropping the future of an operation cancels it: implementations must not keep
+/// transferring data in background tasks once the caller gave up on the result.
+/// [`StorageWithTimeout`](crate::StorageWithTimeout) relies on this to enforce
+/// per-operation deadlines.
 #[cfg_attr(any(test, feature = "testsuite"), mockall::automock)]
 #[async_trait]
 pub trait Storage: Send + Sync + 'static {
     /// Saves a file into the storage.
     async fn put(&self, path: &Path, payload: PutPayload) -> StorageResult<()>;
 
+    /// Saves a file into the storage, recording the bytes uploaded so far in `progress`.
+    ///
+    /// By default, the bytes are recorded once the file is saved. Storages uploading
+    /// files in several parts record the parts as they are uploaded.
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        payload: PutPayload,
+        progress: &UploadProgress,
+    ) -> StorageResult<()> {
+        self.put(path, payload).await?;
+        progress.record_upload_completed();
+        Ok(())
+    }
+
     /// Downloads an entire file and writes it into a local file.
     /// `output_path` is expected to be a file path (not a directory path).
     /// TODO Change the API to support multipart download
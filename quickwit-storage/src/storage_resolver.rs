@@ -19,13 +19,19 @@
 
 use std::collections::HashMap;
 use std::error::Error;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use once_cell::sync::OnceCell;
+use quickwit_common::QuickwitUri;
 
+use crate::lazy_storage::LazyStorage;
 use crate::local_file_storage::LocalFileStorageFactory;
 use crate::ram_storage::RamStorageFactory;
-use crate::{RegionProvider, S3CompatibleObjectStorageFactory, Storage, StorageResolverError};
+use crate::{
+    AzureBlobStorageFactory, MirroredStorage, RegionProvider, S3CompatibleObjectStorageFactory,
+    Storage, StorageProgress, StorageResolverError, StorageWithProgress,
+};
 
 /// Quickwit supported storage resolvers.
 pub fn quickwit_storage_uri_resolver() -> &'static StorageUriResolver {
@@ -39,6 +45,7 @@ pub fn quickwit_storage_uri_resolver() -> &'static StorageUriResolver {
                 RegionProvider::Localstack,
                 "s3+localstack",
             ))
+            .register(AzureBlobStorageFactory::default())
             .build()
     })
 }
@@ -49,7 +56,7 @@ pub trait StorageFactory: Send + Sync + 'static {
     /// Returns the protocol this URI resolver is serving.
     fn protocol(&self) -> String;
     /// Given an URI, returns a [`Storage`] object.
-    fn resolve(&self, uri: &str) -> crate::StorageResult<Arc<dyn Storage>>;
+    fn resolve(&self, uri: &QuickwitUri) -> crate::StorageResult<Arc<dyn Storage>>;
 }
 
 /// Resolves an URI by dispatching it to the right [`StorageFactory`]
@@ -57,6 +64,8 @@ pub trait StorageFactory: Send + Sync + 'static {
 #[derive(Clone)]
 pub struct StorageUriResolver {
     per_protocol_resolver: Arc<HashMap<String, Arc<dyn StorageFactory>>>,
+    progress_opt: Option<Arc<StorageProgress>>,
+    lazy: bool,
 }
 
 #[derive(Default)]
@@ -79,6 +88,8 @@ impl StorageUriResolverBuilder {
     pub fn build(self) -> StorageUriResolver {
         StorageUriResolver {
             per_protocol_resolver: Arc::new(self.per_protocol_resolver),
+            progress_opt: None,
+            lazy: false,
         }
     }
 }
@@ -101,28 +112,76 @@ impl StorageUriResolver {
             .build()
     }
 
+    /// Returns a resolver whose storages record the progress of their uploads and deletions
+    /// in `progress`.
+    pub fn with_progress(&self, progress: Arc<StorageProgress>) -> Self {
+        StorageUriResolver {
+            per_protocol_resolver: self.per_protocol_resolver.clone(),
+            progress_opt: Some(progress),
+            lazy: self.lazy,
+        }
+    }
+
+    /// Returns a resolver whose storages are only resolved by their factory on their first
+    /// operation, see [`LazyStorage`].
+    ///
+    /// The URIs are still validated when they are resolved.
+    pub fn lazy(&self) -> Self {
+        StorageUriResolver {
+            per_protocol_resolver: self.per_protocol_resolver.clone(),
+            progress_opt: self.progress_opt.clone(),
+            lazy: true,
+        }
+    }
+
     /// Resolves the given URI.
+    ///
+    /// The URI is normalized first, see [`QuickwitUri`].
     pub fn resolve(&self, uri: &str) -> Result<Arc<dyn Storage>, StorageResolverError> {
-        let protocol = uri
-            .split("://")
-            .next()
-            .ok_or_else(|| StorageResolverError::InvalidUri {
-                message: format!("Protocol not found in storage uri: {}", uri),
-            })?;
-        let resolver = self.per_protocol_resolver.get(protocol).ok_or_else(|| {
-            StorageResolverError::ProtocolUnsupported {
-                protocol: protocol.to_string(),
-            }
-        })?;
-        let storage = resolver.resolve(uri).map_err(|storage_error| {
-            StorageResolverError::FailedToOpenStorage {
-                kind: storage_error.kind(),
-                message: storage_error
-                    .source()
-                    .map(|err| format!("{}", err))
-                    .unwrap_or_else(String::new),
-            }
+        let uri = QuickwitUri::from_str(uri).map_err(|error| StorageResolverError::InvalidUri {
+            message: error.to_string(),
         })?;
+        let resolver = self
+            .per_protocol_resolver
+            .get(uri.protocol())
+            .ok_or_else(|| StorageResolverError::ProtocolUnsupported {
+                protocol: uri.protocol().to_string(),
+            })?;
+        let storage: Arc<dyn Storage> = if self.lazy {
+            Arc::new(LazyStorage::new(resolver.clone(), uri))
+        } else {
+            resolver.resolve(&uri).map_err(|storage_error| {
+                StorageResolverError::FailedToOpenStorage {
+                    kind: storage_error.kind(),
+                    message: storage_error
+                        .source()
+                        .map(|err| format!("{}", err))
+                        .unwrap_or_else(String::new),
+                }
+            })?
+        };
+        if let Some(progress) = self.progress_opt.as_ref() {
+            return Ok(Arc::new(StorageWithProgress::new(
+                storage,
+                progress.clone(),
+            )));
+        }
+        Ok(storage)
+    }
+
+    /// Resolves the storage at `uri`, mirrored to the storage at `mirror_uri_opt` if it is set.
+    ///
+    /// See [`MirroredStorage`].
+    pub fn resolve_with_mirror(
+        &self,
+        uri: &str,
+        mirror_uri_opt: Option<&str>,
+    ) -> Result<Arc<dyn Storage>, StorageResolverError> {
+        let storage = self.resolve(uri)?;
+        if let Some(mirror_uri) = mirror_uri_opt {
+            let mirror_storage = self.resolve(mirror_uri)?;
+            return Ok(Arc::new(MirroredStorage::new(storage, mirror_storage)));
+        }
         Ok(storage)
     }
 }
@@ -166,7 +225,7 @@ mod tests {
             .expect_protocol()
             .returning(|| "protocol".to_string());
         second.expect_resolve().returning(|uri| {
-            assert_eq!(uri, "protocol://mystorage");
+            assert_eq!(uri.as_str(), "protocol://mystorage");
             Ok(Arc::new(
                 RamStorage::builder()
                     .put("hello", b"hello_content_second")
@@ -180,6 +239,63 @@ mod tests {
         let resolved = storage_resolver.resolve("protocol://mystorage")?;
         let data = resolved.get_all(Path::new("hello")).await?;
         assert_eq!(&data[..], b"hello_content_second");
+        // The URI is normalized before being handed over to the factory.
+        storage_resolver.resolve("Protocol://mystorage/")?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_storage_resolver_with_progress() -> anyhow::Result<()> {
+        let progress = Arc::new(StorageProgress::default());
+        let storage_resolver = StorageUriResolver::for_test().with_progress(progress.clone());
+        let resolved = storage_resolver.resolve("ram://indexes/wikipedia")?;
+        resolved
+            .put(Path::new("split1.split"), b"abc".to_vec().into())
+            .await?;
+        assert_eq!(progress.snapshot().num_uploaded_files, 1);
+        assert_eq!(progress.snapshot().num_uploaded_bytes, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_storage_resolver_with_mirror() -> anyhow::Result<()> {
+        let storage_resolver = StorageUriResolver::for_test();
+        let storage = storage_resolver
+            .resolve_with_mirror("ram://indexes/wikipedia", Some("ram://mirror/wikipedia"))?;
+        storage
+            .put(Path::new("split1.split"), b"abc".to_vec().into())
+            .await?;
+        let mirror_storage = storage_resolver.resolve("ram://mirror/wikipedia")?;
+        assert!(mirror_storage.exists(Path::new("split1.split")).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_storage_resolver_lazy() -> anyhow::Result<()> {
+        let mut storage_factory = MockStorageFactory::new();
+        storage_factory
+            .expect_protocol()
+            .returning(|| "protocol".to_string());
+        storage_factory.expect_resolve().times(1).returning(|_uri| {
+            Ok(Arc::new(
+                RamStorage::builder().put("hello", b"hello_content").build(),
+            ))
+        });
+        let storage_resolver = StorageUriResolver::builder()
+            .register(storage_factory)
+            .build()
+            .lazy();
+        assert!(matches!(
+            storage_resolver.resolve("other://mystorage"),
+            Err(crate::StorageResolverError::ProtocolUnsupported { .. })
+        ));
+        let resolved = storage_resolver.resolve("protocol://mystorage")?;
+        assert_eq!(resolved.uri(), "protocol://mystorage");
+        assert_eq!(
+            &resolved.get_all(Path::new("hello")).await?[..],
+            b"hello_content"
+        );
+        assert!(resolved.exists(Path::new("hello")).await?);
         Ok(())
     }
 
@@ -191,4 +307,13 @@ mod tests {
             Err(crate::StorageResolverError::ProtocolUnsupported { protocol }) if protocol == "protocol"
         ));
     }
+
+    #[test]
+    fn test_storage_resolver_invalid_uri() {
+        let storage_resolver = StorageUriResolver::for_test();
+        assert!(matches!(
+            storage_resolver.resolve("ram:indexes/wikipedia"),
+            Err(crate::StorageResolverError::InvalidUri { message }) if message.contains("expected `://`")
+        ));
+    }
 }
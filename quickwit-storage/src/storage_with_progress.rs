@@ -0,0 +1,296 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use crate::{PutPayload, Storage, StorageResult};
+
+/// Number of bytes of a file uploaded so far.
+#[derive(Debug, Default)]
+pub struct UploadProgress {
+    num_bytes: u64,
+    num_uploaded_bytes: AtomicU64,
+}
+
+impl UploadProgress {
+    /// Creates the progress of the upload of a file of `num_bytes` bytes.
+    pub fn new(num_bytes: u64) -> Self {
+        UploadProgress {
+            num_bytes,
+            num_uploaded_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Records that `num_bytes` more bytes were uploaded.
+    pub fn record_uploaded_bytes(&self, num_bytes: u64) {
+        self.num_uploaded_bytes
+            .fetch_add(num_bytes, Ordering::Relaxed);
+    }
+
+    /// Records that the file is entirely uploaded.
+    pub fn record_upload_completed(&self) {
+        self.num_uploaded_bytes
+            .store(self.num_bytes, Ordering::Relaxed);
+    }
+
+    /// Returns the size of the file.
+    pub fn num_bytes(&self) -> u64 {
+        self.num_bytes
+    }
+
+    /// Returns the number of bytes uploaded so far.
+    pub fn num_uploaded_bytes(&self) -> u64 {
+        self.num_uploaded_bytes
+            .load(Ordering::Relaxed)
+            .min(self.num_bytes)
+    }
+}
+
+/// Uploads and deletions of the storages wrapped in a [`StorageWithProgress`].
+///
+/// It is shared between the storages and whoever displays their progress, e.g. the CLI.
+#[derive(Debug, Default)]
+pub struct StorageProgress {
+    ongoing_uploads: Mutex<BTreeMap<PathBuf, Arc<UploadProgress>>>,
+    num_uploaded_files: AtomicU64,
+    num_uploaded_bytes: AtomicU64,
+    num_deleted_files: AtomicU64,
+}
+
+/// Progress of an ongoing upload, as of a [`StorageProgressSnapshot`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OngoingUpload {
+    /// Path of the file in its storage.
+    pub path: PathBuf,
+    /// Size of the file.
+    pub num_bytes: u64,
+    /// Number of bytes uploaded so far.
+    pub num_uploaded_bytes: u64,
+}
+
+/// Point-in-time view of a [`StorageProgress`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StorageProgressSnapshot {
+    /// Uploads not completed yet, ordered by path.
+    pub ongoing_uploads: Vec<OngoingUpload>,
+    /// Number of files uploaded successfully.
+    pub num_uploaded_files: u64,
+    /// Total size of the files uploaded successfully.
+    pub num_uploaded_bytes: u64,
+    /// Number of files deleted successfully.
+    pub num_deleted_files: u64,
+}
+
+impl StorageProgress {
+    /// Returns a point-in-time view of the uploads and deletions.
+    pub fn snapshot(&self) -> StorageProgressSnapshot {
+        let ongoing_uploads = self
+            .ongoing_uploads
+            .lock()
+            .expect("Lock should not be poisoned.")
+            .iter()
+            .map(|(path, upload_progress)| OngoingUpload {
+                path: path.clone(),
+                num_bytes: upload_progress.num_bytes(),
+                num_uploaded_bytes: upload_progress.num_uploaded_bytes(),
+            })
+            .collect();
+        StorageProgressSnapshot {
+            ongoing_uploads,
+            num_uploaded_files: self.num_uploaded_files.load(Ordering::Relaxed),
+            num_uploaded_bytes: self.num_uploaded_bytes.load(Ordering::Relaxed),
+            num_deleted_files: self.num_deleted_files.load(Ordering::Relaxed),
+        }
+    }
+
+    fn start_upload(&self, path: &Path, num_bytes: u64) -> Arc<UploadProgress> {
+        let upload_progress = Arc::new(UploadProgress::new(num_bytes));
+        self.ongoing_uploads
+            .lock()
+            .expect("Lock should not be poisoned.")
+            .insert(path.to_path_buf(), upload_progress.clone());
+        upload_progress
+    }
+
+    fn end_upload(&self, path: &Path, is_success: bool) {
+        let upload_progress_opt = self
+            .ongoing_uploads
+            .lock()
+            .expect("Lock should not be poisoned.")
+            .remove(path);
+        if let (true, Some(upload_progress)) = (is_success, upload_progress_opt) {
+            self.num_uploaded_files.fetch_add(1, Ordering::Relaxed);
+            self.num_uploaded_bytes
+                .fetch_add(upload_progress.num_bytes(), Ordering::Relaxed);
+        }
+    }
+}
+
+/// A storage recording the progress of the uploads and deletions of another storage in a
+/// [`StorageProgress`].
+pub struct StorageWithProgress {
+    storage: Arc<dyn Storage>,
+    progress: Arc<StorageProgress>,
+}
+
+impl StorageWithProgress {
+    /// Wraps `storage` so that its uploads and deletions are recorded in `progress`.
+    pub fn new(storage: Arc<dyn Storage>, progress: Arc<StorageProgress>) -> Self {
+        StorageWithProgress { storage, progress }
+    }
+}
+
+#[async_trait]
+impl Storage for StorageWithProgress {
+    async fn put(&self, path: &Path, payload: PutPayload) -> StorageResult<()> {
+        let num_bytes = payload.len().await?;
+        let upload_progress = self.progress.start_upload(path, num_bytes);
+        let put_res = self
+            .storage
+            .put_with_progress(path, payload, &upload_progress)
+            .await;
+        self.progress.end_upload(path, put_res.is_ok());
+        put_res
+    }
+
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        payload: PutPayload,
+        progress: &UploadProgress,
+    ) -> StorageResult<()> {
+        self.put(path, payload).await?;
+        progress.record_upload_completed();
+        Ok(())
+    }
+
+    async fn copy_to_file(&self, path: &Path, output_path: &Path) -> StorageResult<()> {
+        self.storage.copy_to_file(path, output_path).await
+    }
+
+    async fn get_slice(&self, path: &Path, range: Range<usize>) -> StorageResult<Bytes> {
+        self.storage.get_slice(path, range).await
+    }
+
+    async fn get_all(&self, path: &Path) -> StorageResult<Bytes> {
+        self.storage.get_all(path).await
+    }
+
+    async fn delete(&self, path: &Path) -> StorageResult<()> {
+        self.storage.delete(path).await?;
+        self.progress
+            .num_deleted_files
+            .fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn exists(&self, path: &Path) -> StorageResult<bool> {
+        self.storage.exists(path).await
+    }
+
+    async fn file_num_bytes(&self, path: &Path) -> StorageResult<u64> {
+        self.storage.file_num_bytes(path).await
+    }
+
+    fn uri(&self) -> String {
+        self.storage.uri()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockStorage, RamStorage, StorageErrorKind};
+
+    #[tokio::test]
+    async fn test_storage_with_progress() -> anyhow::Result<()> {
+        let progress = Arc::new(StorageProgress::default());
+        let storage = StorageWithProgress::new(Arc::new(RamStorage::default()), progress.clone());
+        storage
+            .put(Path::new("split1.split"), PutPayload::from(&b"abcdef"[..]))
+            .await?;
+        storage
+            .put(Path::new("split2.split"), PutPayload::from(&b"abc"[..]))
+            .await?;
+        storage.delete(Path::new("split1.split")).await?;
+        assert_eq!(
+            progress.snapshot(),
+            StorageProgressSnapshot {
+                ongoing_uploads: Vec::new(),
+                num_uploaded_files: 2,
+                num_uploaded_bytes: 9,
+                num_deleted_files: 1,
+            }
+        );
+        assert_eq!(
+            &storage.get_all(Path::new("split2.split")).await?[..],
+            b"abc"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_storage_with_progress_failed_operations() -> anyhow::Result<()> {
+        let mut mock_storage = MockStorage::default();
+        mock_storage
+            .expect_put_with_progress()
+            .returning(|_, _, _| Err(StorageErrorKind::Io.with_error(anyhow::anyhow!("foo"))));
+        mock_storage
+            .expect_delete()
+            .returning(|_| Err(StorageErrorKind::Io.with_error(anyhow::anyhow!("foo"))));
+        let progress = Arc::new(StorageProgress::default());
+        let storage = StorageWithProgress::new(Arc::new(mock_storage), progress.clone());
+        assert!(storage
+            .put(Path::new("split1.split"), PutPayload::from(&b"abcdef"[..]))
+            .await
+            .is_err());
+        assert!(storage.delete(Path::new("split1.split")).await.is_err());
+        assert_eq!(progress.snapshot(), StorageProgressSnapshot::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_upload_progress() {
+        let progress = StorageProgress::default();
+        let upload_progress = progress.start_upload(Path::new("split1.split"), 10);
+        upload_progress.record_uploaded_bytes(4);
+        assert_eq!(
+            progress.snapshot().ongoing_uploads,
+            vec![OngoingUpload {
+                path: PathBuf::from("split1.split"),
+                num_bytes: 10,
+                num_uploaded_bytes: 4,
+            }]
+        );
+        upload_progress.record_uploaded_bytes(8);
+        assert_eq!(upload_progress.num_uploaded_bytes(), 10);
+        progress.end_upload(Path::new("split1.split"), true);
+        let snapshot = progress.snapshot();
+        assert!(snapshot.ongoing_uploads.is_empty());
+        assert_eq!(snapshot.num_uploaded_files, 1);
+        assert_eq!(snapshot.num_uploaded_bytes, 10);
+    }
+}
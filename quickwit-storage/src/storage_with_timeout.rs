@@ -0,0 +1,345 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::future::Future;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use quickwit_common::QuickwitUri;
+
+use crate::{PutPayload, Storage, StorageErrorKind, StorageFactory, StorageResult, UploadProgress};
+
+/// Deadlines of the operations of a [`StorageWithTimeout`].
+///
+/// An operation transferring `num_bytes` bytes is given `min_timeout`, plus the time needed to
+/// transfer them at `min_throughput_bytes_per_sec`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StorageTimeoutPolicy {
+    /// Deadline of the operations that do not transfer any data.
+    pub min_timeout: Duration,
+    /// Lowest throughput expected from the storage.
+    pub min_throughput_bytes_per_sec: u64,
+}
+
+impl Default for StorageTimeoutPolicy {
+    fn default() -> Self {
+        StorageTimeoutPolicy {
+            min_timeout: Duration::from_secs(10),
+            min_throughput_bytes_per_sec: 1_000_000,
+        }
+    }
+}
+
+impl StorageTimeoutPolicy {
+    /// Returns the deadline of an operation transferring `num_bytes` bytes.
+    pub fn timeout(&self, num_bytes: u64) -> Duration {
+        let transfer_millis =
+            num_bytes.saturating_mul(1_000) / self.min_throughput_bytes_per_sec.max(1);
+        self.min_timeout + Duration::from_millis(transfer_millis)
+    }
+}
+
+/// Storage failing the operations of the underlying storage that do not complete before
+/// their deadline, with a [`StorageErrorKind::Timeout`] error.
+///
+/// The timed out operation is dropped, which stops its transfer.
+/// `copy_to_file` downloads files of unknown size, and is not given any deadline. `get_all`
+/// fetches the size of the file first, to derive the deadline of its download.
+pub struct StorageWithTimeout {
+    underlying: Arc<dyn Storage>,
+    timeout_policy: StorageTimeoutPolicy,
+}
+
+impl StorageWithTimeout {
+    /// Wraps `underlying` with the deadlines of `timeout_policy`.
+    pub fn new(underlying: Arc<dyn Storage>, timeout_policy: StorageTimeoutPolicy) -> Self {
+        StorageWithTimeout {
+            underlying,
+            timeout_policy,
+        }
+    }
+
+    async fn with_timeout<T, Fut>(
+        &self,
+        operation: &str,
+        path: &Path,
+        num_bytes: u64,
+        future: Fut,
+    ) -> StorageResult<T>
+    where
+        Fut: Future<Output = StorageResult<T>>,
+    {
+        let timeout = self.timeout_policy.timeout(num_bytes);
+        tokio::time::timeout(timeout, future)
+            .await
+            .unwrap_or_else(|_| {
+                Err(StorageErrorKind::Timeout.with_error(anyhow::anyhow!(
+                    "The {} of `{}` on `{}` did not complete within {:?}.",
+                    operation,
+                    path.display(),
+                    self.underlying.uri(),
+                    timeout
+                )))
+            })
+    }
+}
+
+#[async_trait]
+impl Storage for StorageWithTimeout {
+    async fn put(&self, path: &Path, payload: PutPayload) -> StorageResult<()> {
+        let num_bytes = payload.len().await?;
+        self.with_timeout("put", path, num_bytes, self.underlying.put(path, payload))
+            .await
+    }
+
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        payload: PutPayload,
+        progress: &UploadProgress,
+    ) -> StorageResult<()> {
+        let num_bytes = payload.len().await?;
+        self.with_timeout(
+            "put",
+            path,
+            num_bytes,
+            self.underlying.put_with_progress(path, payload, progress),
+        )
+        .await
+    }
+
+    async fn copy_to_file(&self, path: &Path, output_path: &Path) -> StorageResult<()> {
+        self.underlying.copy_to_file(path, output_path).await
+    }
+
+    async fn get_slice(&self, path: &Path, range: Range<usize>) -> StorageResult<Bytes> {
+        let num_bytes = range.len() as u64;
+        self.with_timeout(
+            "get_slice",
+            path,
+            num_bytes,
+            self.underlying.get_slice(path, range.clone()),
+        )
+        .await
+    }
+
+    async fn get_all(&self, path: &Path) -> StorageResult<Bytes> {
+        let num_bytes = self.file_num_bytes(path).await?;
+        self.with_timeout("get_all", path, num_bytes, self.underlying.get_all(path))
+            .await
+    }
+
+    async fn delete(&self, path: &Path) -> StorageResult<()> {
+        self.with_timeout("delete", path, 0, self.underlying.delete(path))
+            .await
+    }
+
+    async fn exists(&self, path: &Path) -> StorageResult<bool> {
+        self.with_timeout("exists", path, 0, self.underlying.exists(path))
+            .await
+    }
+
+    async fn file_num_bytes(&self, path: &Path) -> StorageResult<u64> {
+        self.with_timeout(
+            "file_num_bytes",
+            path,
+            0,
+            self.underlying.file_num_bytes(path),
+        )
+        .await
+    }
+
+    fn uri(&self) -> String {
+        self.underlying.uri()
+    }
+}
+
+/// A StorageFactory that wraps all the storages it produces in a [`StorageWithTimeout`].
+pub struct StorageWithTimeoutFactory {
+    storage_factory: Arc<dyn StorageFactory>,
+    timeout_policy: StorageTimeoutPolicy,
+}
+
+impl StorageWithTimeoutFactory {
+    /// Creates a new StorageFactory applying `timeout_policy` to the storages of
+    /// `storage_factory`.
+    pub fn new(
+        storage_factory: Arc<dyn StorageFactory>,
+        timeout_policy: StorageTimeoutPolicy,
+    ) -> Self {
+        StorageWithTimeoutFactory {
+            storage_factory,
+            timeout_policy,
+        }
+    }
+}
+
+impl StorageFactory for StorageWithTimeoutFactory {
+    fn protocol(&self) -> String {
+        self.storage_factory.protocol()
+    }
+
+    fn resolve(&self, uri: &QuickwitUri) -> StorageResult<Arc<dyn Storage>> {
+        let storage = self.storage_factory.resolve(uri)?;
+        Ok(Arc::new(StorageWithTimeout::new(
+            storage,
+            self.timeout_policy,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::RamStorage;
+
+    #[test]
+    fn test_storage_timeout_policy() {
+        let timeout_policy = StorageTimeoutPolicy {
+            min_timeout: Duration::from_secs(2),
+            min_throughput_bytes_per_sec: 1_000,
+        };
+        assert_eq!(timeout_policy.timeout(0), Duration::from_secs(2));
+        assert_eq!(timeout_policy.timeout(500), Duration::from_millis(2_500));
+    }
+
+    #[tokio::test]
+    async fn test_storage_with_timeout_passes_through() -> anyhow::Result<()> {
+        let storage = StorageWithTimeout::new(
+            Arc::new(RamStorage::default()),
+            StorageTimeoutPolicy::default(),
+        );
+        let path = Path::new("file");
+        storage
+            .put(path, PutPayload::from(b"hello".to_vec()))
+            .await?;
+        assert_eq!(storage.get_slice(path, 1..3).await?, Bytes::from("el"));
+        assert_eq!(storage.file_num_bytes(path).await?, 5);
+        Ok(())
+    }
+
+    /// Storage whose `get_all` operations complete after `get_all_delay_opt`, or never if it is
+    /// `None`.
+    struct StuckStorage {
+        file_num_bytes: u64,
+        get_all_delay_opt: Option<Duration>,
+        num_dropped_operations: Arc<AtomicUsize>,
+    }
+
+    /// Counts the operations of a [`StuckStorage`] that were dropped.
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[async_trait]
+    impl Storage for StuckStorage {
+        async fn put(&self, _path: &Path, _payload: PutPayload) -> StorageResult<()> {
+            unimplemented!()
+        }
+
+        async fn copy_to_file(&self, _path: &Path, _output_path: &Path) -> StorageResult<()> {
+            unimplemented!()
+        }
+
+        async fn get_slice(&self, _path: &Path, _range: Range<usize>) -> StorageResult<Bytes> {
+            unimplemented!()
+        }
+
+        async fn get_all(&self, _path: &Path) -> StorageResult<Bytes> {
+            let _drop_counter = DropCounter(self.num_dropped_operations.clone());
+            match self.get_all_delay_opt {
+                Some(get_all_delay) => tokio::time::sleep(get_all_delay).await,
+                None => futures::future::pending().await,
+            }
+            Ok(Bytes::from(vec![0u8; self.file_num_bytes as usize]))
+        }
+
+        async fn delete(&self, _path: &Path) -> StorageResult<()> {
+            unimplemented!()
+        }
+
+        async fn file_num_bytes(&self, _path: &Path) -> StorageResult<u64> {
+            Ok(self.file_num_bytes)
+        }
+
+        fn uri(&self) -> String {
+            "stuck:///".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_storage_with_timeout_cancels_slow_operations() {
+        let num_dropped_operations = Arc::new(AtomicUsize::new(0));
+        let storage = StorageWithTimeout::new(
+            Arc::new(StuckStorage {
+                file_num_bytes: 0,
+                get_all_delay_opt: None,
+                num_dropped_operations: num_dropped_operations.clone(),
+            }),
+            StorageTimeoutPolicy {
+                min_timeout: Duration::from_millis(10),
+                min_throughput_bytes_per_sec: 1_000,
+            },
+        );
+        let error = storage.get_all(Path::new("file")).await.unwrap_err();
+        assert_eq!(error.kind(), StorageErrorKind::Timeout);
+        assert_eq!(num_dropped_operations.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_storage_with_timeout_get_all_deadline_depends_on_file_size() -> anyhow::Result<()>
+    {
+        let timeout_policy = StorageTimeoutPolicy {
+            min_timeout: Duration::from_millis(10),
+            min_throughput_bytes_per_sec: 1_000,
+        };
+        // Downloading 1,000 bytes is given 1.01s.
+        let storage = StorageWithTimeout::new(
+            Arc::new(StuckStorage {
+                file_num_bytes: 1_000,
+                get_all_delay_opt: Some(Duration::from_millis(50)),
+                num_dropped_operations: Arc::new(AtomicUsize::new(0)),
+            }),
+            timeout_policy,
+        );
+        assert_eq!(storage.get_all(Path::new("file")).await?.len(), 1_000);
+
+        let storage = StorageWithTimeout::new(
+            Arc::new(StuckStorage {
+                file_num_bytes: 0,
+                get_all_delay_opt: Some(Duration::from_millis(50)),
+                num_dropped_operations: Arc::new(AtomicUsize::new(0)),
+            }),
+            timeout_policy,
+        );
+        let error = storage.get_all(Path::new("file")).await.unwrap_err();
+        assert_eq!(error.kind(), StorageErrorKind::Timeout);
+        Ok(())
+    }
+}
@@ -28,7 +28,9 @@ use bytes::Bytes;
 use tokio::sync::Mutex;
 use tracing::{error, warn};
 
-use crate::{LocalFileStorage, PutPayload, Storage, StorageErrorKind, StorageResult};
+use crate::{
+    LocalFileStorage, PutPayload, Storage, StorageErrorKind, StorageResult, UploadProgress,
+};
 
 /// An intermediate folder created at `cache_dir/INTERNAL_CACHE_DIR_NAME`
 /// to hold the local files.
@@ -151,7 +153,19 @@ impl StorageWithUploadCache {
 #[async_trait]
 impl Storage for StorageWithUploadCache {
     async fn put(&self, path: &Path, payload: PutPayload) -> StorageResult<()> {
-        self.remote_storage.put(path, payload.clone()).await?;
+        self.put_with_progress(path, payload, &UploadProgress::default())
+            .await
+    }
+
+    async fn put_with_progress(
+        &self,
+        path: &Path,
+        payload: PutPayload,
+        progress: &UploadProgress,
+    ) -> StorageResult<()> {
+        self.remote_storage
+            .put_with_progress(path, payload.clone(), progress)
+            .await?;
 
         // Ignore if path ends with `CACHE_TEMP_FILE_EXTENSION`.
         if path.to_string_lossy().ends_with(CACHE_TEMP_FILE_EXTENSION) {
@@ -279,8 +293,8 @@ mod tests {
     };
     use crate::tests::storage_test_suite;
     use crate::{
-        create_storage_with_upload_cache, PutPayload, RamStorage, Storage, StorageError,
-        StorageErrorKind,
+        create_storage_with_upload_cache, MockStorage, PutPayload, RamStorage, Storage,
+        StorageError, StorageErrorKind, UploadProgress,
     };
 
     fn list_files(path: PathBuf) -> Vec<PathBuf> {
@@ -467,6 +481,43 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_put_with_progress_is_forwarded_to_remote_storage() -> anyhow::Result<()> {
+        let local_dir = tempdir()?;
+        let mut remote_storage = MockStorage::default();
+        remote_storage
+            .expect_put_with_progress()
+            .times(1)
+            .returning(|_, _, progress| {
+                progress.record_uploaded_bytes(3);
+                Ok(())
+            });
+        let cache_params = CacheParams {
+            max_num_files: 2,
+            max_num_bytes: 10,
+            max_file_size: 10,
+        };
+        let cache = StorageWithUploadCache::create(
+            Arc::new(remote_storage),
+            local_dir.path(),
+            cache_params,
+        )?;
+        let upload_progress = UploadProgress::new(3);
+        cache
+            .put_with_progress(
+                Path::new("a.split"),
+                PutPayload::InMemory(Bytes::from(b"abc".to_vec())),
+                &upload_progress,
+            )
+            .await?;
+        assert_eq!(upload_progress.num_uploaded_bytes(), 3);
+        assert_eq!(
+            cache.inspect().await.get(Path::new("a.split")),
+            Some(&3usize)
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_put_should_not_hit_cache_when_max_num_files_reached() -> anyhow::Result<()> {
         let local_dir = tempdir()?;
@@ -0,0 +1,78 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// This file is an integration test that assumes that the environment makes it possible to
+// connect to the Azurite emulator, holding a `quickwit-integration-tests` container.
+
+use std::path::Path;
+
+use quickwit_common::{get_quickwit_env, QuickwitEnv};
+use quickwit_storage::{AzureBlobStorage, MultiPartPolicy, PutPayload, Storage};
+
+// The account and access key of the Azurite emulator, documented publicly.
+const AZURITE_ACCOUNT: &str = "devstoreaccount1";
+const AZURITE_ACCESS_KEY: &str =
+    "Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==";
+
+fn azurite_storage() -> anyhow::Result<AzureBlobStorage> {
+    let host = if get_quickwit_env() == QuickwitEnv::LOCAL {
+        "localhost"
+    } else {
+        "azurite"
+    };
+    let endpoint = format!("http://{}:10000/{}", host, AZURITE_ACCOUNT);
+    AzureBlobStorage::new(
+        AZURITE_ACCOUNT,
+        AZURITE_ACCESS_KEY,
+        &endpoint,
+        "quickwit-integration-tests",
+    )
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "ci-test"), ignore)]
+async fn test_upload_multiple_block_blob() -> anyhow::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+    let mut object_storage = azurite_storage()?;
+    object_storage.set_policy(MultiPartPolicy {
+        target_part_num_bytes: 5 * 1_024 * 1_024,
+        max_num_parts: 50_000,
+        multipart_threshold_num_bytes: 10_000_000,
+        max_object_num_bytes: 5_000_000_000_000,
+        max_concurrent_upload: 100,
+    });
+    let test_buffer = vec![1u8; 15_000_000];
+    let path = Path::new("test-azure-blob-storage/hello_large.txt");
+    object_storage
+        .put(path, PutPayload::from(test_buffer.clone()))
+        .await?;
+    assert_eq!(object_storage.get_all(path).await?, test_buffer);
+    Ok(())
+}
+
+#[cfg(feature = "testsuite")]
+#[tokio::test]
+#[cfg_attr(not(feature = "ci-test"), ignore)]
+async fn test_suite_on_azure_blob_storage() -> anyhow::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+    let mut object_storage =
+        azurite_storage()?.with_prefix(Path::new("test-azure-blob-storage-suite"));
+    quickwit_storage::storage_test_suite(&mut object_storage).await?;
+    Ok(())
+}
@@ -70,8 +70,26 @@ pub enum TelemetryEvent {
     IndexStart,
     /// Delete command
     Delete,
+    /// Split delete command
+    DeleteSplits,
+    /// Split verify command
+    VerifySplits,
     /// Garbage Collect command
     GarbageCollect,
+    /// Mirror command
+    Mirror,
+    /// Clone command
+    Clone,
+    /// Attach command
+    Attach,
+    /// Companion file command
+    Companion,
+    /// Checkpoint command
+    Checkpoint,
+    /// Describe command
+    Describe,
+    /// Update command
+    Update,
     /// Serve command is called.
     Serve(ServeEvent),
     /// EndCommand (with the return code)
@@ -0,0 +1,274 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use byte_unit::Byte;
+use quickwit_common::QuickwitUri;
+use quickwit_index_config::IndexConfig;
+use quickwit_indexing::actors::IndexerParams;
+use quickwit_indexing::index_data;
+use quickwit_indexing::models::{
+    CommitPolicy, DiskGuardrails, DocSizeLimit, IndexingStatistics, ScratchDirectory,
+};
+use quickwit_indexing::source::{SourceConfig, VecSourceParams};
+use quickwit_metastore::checkpoint::Checkpoint;
+use quickwit_metastore::{IndexMetadata, Metastore, MetastoreUriResolver};
+use quickwit_proto::{SearchRequest, SearchResponse};
+use quickwit_search::single_node_search;
+use quickwit_storage::{Storage, StorageUriResolver};
+
+use crate::localstack::{Localstack, LOCALSTACK_BUCKET};
+
+/// Storage of the metastore and of the indexes of a [`TestHarness`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestStorageKind {
+    /// The metastore and the indexes are held in memory.
+    Ram,
+    /// The metastore and the indexes are stored in the bucket of localstack, see
+    /// [`Localstack`].
+    Localstack,
+}
+
+/// Environment of an integration test: a metastore of its own, the storage of its
+/// indexes, programmatic ingestion, and a searcher embedded in the test process.
+///
+/// The metastore and the indexes of a harness are stored under a root URI unique to it,
+/// so the tests of a process never see each other's indexes.
+pub struct TestHarness {
+    metastore_uri: String,
+    metastore: Arc<dyn Metastore>,
+    storage_uri_resolver: StorageUriResolver,
+    num_ingestions: AtomicUsize,
+    localstack_opt: Option<Arc<Localstack>>,
+}
+
+impl TestHarness {
+    /// Starts a harness storing its metastore and indexes in `storage_kind`, starting
+    /// localstack if needed.
+    pub async fn start(storage_kind: TestStorageKind) -> anyhow::Result<TestHarness> {
+        let harness_id = ulid::Ulid::new().to_string().to_lowercase();
+        let (metastore_uri, localstack_opt) = match storage_kind {
+            TestStorageKind::Ram => (format!("ram://quickwit-test-harness/{}", harness_id), None),
+            TestStorageKind::Localstack => {
+                let localstack = Localstack::shared().await?;
+                let metastore_uri = format!(
+                    "s3+localstack://{}/quickwit-test-harness/{}",
+                    LOCALSTACK_BUCKET, harness_id
+                );
+                (metastore_uri, Some(localstack))
+            }
+        };
+        let metastore = MetastoreUriResolver::default()
+            .resolve(&metastore_uri)
+            .await?;
+        Ok(TestHarness {
+            metastore_uri,
+            metastore,
+            storage_uri_resolver: StorageUriResolver::for_test(),
+            num_ingestions: AtomicUsize::default(),
+            localstack_opt,
+        })
+    }
+
+    /// Stops the harness, and the localstack container it started, if it is the last harness
+    /// using it, see [`Localstack::stop`].
+    pub async fn stop(self) -> anyhow::Result<()> {
+        if let Some(localstack) = self.localstack_opt {
+            localstack.stop().await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the URI of the metastore.
+    pub fn metastore_uri(&self) -> &str {
+        &self.metastore_uri
+    }
+
+    /// Returns the metastore.
+    pub fn metastore(&self) -> Arc<dyn Metastore> {
+        self.metastore.clone()
+    }
+
+    /// Returns the storage URI resolver used to ingest and search the indexes.
+    pub fn storage_uri_resolver(&self) -> StorageUriResolver {
+        self.storage_uri_resolver.clone()
+    }
+
+    /// Returns the URI of the index `index_id`.
+    pub fn index_uri(&self, index_id: &str) -> String {
+        format!("{}/{}", self.metastore_uri, index_id)
+    }
+
+    /// Returns the storage of the index `index_id`.
+    pub fn index_storage(&self, index_id: &str) -> anyhow::Result<Arc<dyn Storage>> {
+        let index_storage = self
+            .storage_uri_resolver
+            .resolve(&self.index_uri(index_id))?;
+        Ok(index_storage)
+    }
+
+    /// Creates the index `index_id`, configured by `index_config`.
+    pub async fn create_index(
+        &self,
+        index_id: &str,
+        index_config: Arc<dyn IndexConfig>,
+    ) -> anyhow::Result<()> {
+        let index_metadata = IndexMetadata {
+            index_id: index_id.to_string(),
+            index_uri: QuickwitUri::from_str(&self.index_uri(index_id))?,
+            index_config,
+            checkpoint: Checkpoint::default(),
+            index_config_history: Vec::new(),
+            mirror_index_uri: None,
+            read_only: false,
+            description: None,
+            labels: Default::default(),
+            create_timestamp: self.metastore.now_timestamp().await?,
+        };
+        self.metastore.create_index(index_metadata).await?;
+        Ok(())
+    }
+
+    /// Ingests the documents into the index `index_id`, and publishes them in a single split
+    /// before returning.
+    ///
+    /// The documents are expected to be `serde_json::Value`.
+    /// They can be created using the `serde_json::json!` macro.
+    pub async fn ingest<I>(&self, index_id: &str, docs: I) -> anyhow::Result<IndexingStatistics>
+    where I: IntoIterator<Item = serde_json::Value> {
+        let docs: Vec<String> = docs.into_iter().map(|doc| doc.to_string()).collect();
+        let num_ingestions = self.num_ingestions.fetch_add(1, Ordering::SeqCst);
+        let source_config = SourceConfig {
+            source_id: format!("{}-test-harness", index_id),
+            source_type: "vec".to_string(),
+            params: serde_json::to_value(VecSourceParams {
+                items: docs,
+                batch_num_docs: 100,
+                partition: format!("ingestion-{}", num_ingestions),
+                start_offset: 0,
+            })?,
+        };
+        let indexer_params = IndexerParams {
+            scratch_directory: ScratchDirectory::try_new_temp()?,
+            heap_size: Byte::from_bytes(100_000_000),
+            commit_policy: CommitPolicy {
+                timeout: Duration::from_secs(3600),
+                num_docs_threshold: 5_000_000,
+                adaptive_opt: None,
+            },
+            disk_guardrails: DiskGuardrails::default(),
+            doc_size_limit: DocSizeLimit::default(),
+            realtime_opt: None,
+        };
+        let statistics = index_data(
+            index_id.to_string(),
+            self.metastore.clone(),
+            indexer_params,
+            source_config,
+            self.storage_uri_resolver.clone(),
+        )
+        .await?;
+        Ok(statistics)
+    }
+
+    /// Searches the index `index_id` for `query`, returning up to `max_hits` hits.
+    pub async fn search(
+        &self,
+        index_id: &str,
+        query: &str,
+        max_hits: u64,
+    ) -> anyhow::Result<SearchResponse> {
+        let search_request = SearchRequest {
+            index_id: index_id.to_string(),
+            query: query.to_string(),
+            max_hits,
+            ..Default::default()
+        };
+        self.search_with_request(&search_request).await
+    }
+
+    /// Runs `search_request` on the searcher embedded in the test process.
+    pub async fn search_with_request(
+        &self,
+        search_request: &SearchRequest,
+    ) -> anyhow::Result<SearchResponse> {
+        let search_response = single_node_search(
+            search_request,
+            &*self.metastore,
+            self.storage_uri_resolver.clone(),
+        )
+        .await?;
+        Ok(search_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickwit_index_config::WikipediaIndexConfig;
+
+    use super::*;
+
+    async fn test_harness_aux(storage_kind: TestStorageKind) -> anyhow::Result<()> {
+        quickwit_common::setup_logging_for_tests();
+        let harness = TestHarness::start(storage_kind).await?;
+        harness
+            .create_index("wikipedia", Arc::new(WikipediaIndexConfig::new()))
+            .await?;
+        let statistics = harness.ingest("wikipedia", vec![
+            serde_json::json!({"title": "Hurricane Fay", "body": "breezy", "url": "http://hurricane-fay"}),
+            serde_json::json!({"title": "Ganimede", "body": "moon", "url": "http://ganimede"}),
+        ]).await?;
+        assert_eq!(statistics.num_uploaded_splits, 1);
+        harness
+            .ingest(
+                "wikipedia",
+                vec![serde_json::json!({"title": "Io", "body": "moon", "url": "http://io"})],
+            )
+            .await?;
+        let splits = harness.metastore().list_all_splits("wikipedia").await?;
+        assert_eq!(splits.len(), 2);
+        let search_response = harness.search("wikipedia", "body:moon", 10).await?;
+        assert_eq!(search_response.num_hits, 2);
+        let other_harness = TestHarness::start(storage_kind).await?;
+        assert_ne!(other_harness.metastore_uri(), harness.metastore_uri());
+        assert!(other_harness
+            .metastore()
+            .index_metadata("wikipedia")
+            .await
+            .is_err());
+        other_harness.stop().await?;
+        harness.stop().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_harness_on_ram_storage() -> anyhow::Result<()> {
+        test_harness_aux(TestStorageKind::Ram).await
+    }
+
+    #[tokio::test]
+    #[cfg_attr(not(feature = "ci-test"), ignore)]
+    async fn test_harness_on_localstack_storage() -> anyhow::Result<()> {
+        test_harness_aux(TestStorageKind::Localstack).await
+    }
+}
@@ -0,0 +1,33 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+#![warn(missing_docs)]
+
+//! `quickwit-test-harness` sets up the environment of the integration tests of the programs
+//! embedding quickwit, without going through the CLI:
+//! - [`TestHarness`] creates a metastore of its own, stored in memory or in localstack, ingests
+//!   documents into its indexes, and searches them with a searcher embedded in the test process.
+//! - [`Localstack`] targets localstack, starting a container if needed, and creates the buckets of
+//!   the tests.
+
+mod harness;
+mod localstack;
+
+pub use self::harness::{TestHarness, TestStorageKind};
+pub use self::localstack::{Localstack, LOCALSTACK_BUCKET};
@@ -0,0 +1,202 @@
+// Copyright (C) 2021 Quickwit, Inc.
+//
+// Quickwit is offered under the AGPL v3.0 and as commercial software.
+// For commercial licensing, contact us at hello@quickwit.io.
+//
+// AGPL:
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use once_cell::sync::Lazy;
+use quickwit_storage::RegionProvider;
+use rusoto_core::credential::StaticProvider;
+use rusoto_core::{HttpClient, Region, RusotoError};
+use rusoto_s3::{CreateBucketError, CreateBucketRequest, S3Client, S3};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, timeout, Instant};
+use tracing::info;
+
+/// Bucket of localstack holding the files of the tests.
+pub const LOCALSTACK_BUCKET: &str = "quickwit-integration-tests";
+
+/// Image of the localstack containers started by [`Localstack::shared`].
+const LOCALSTACK_IMAGE: &str = "localstack/localstack:0.12.17";
+
+/// Deadline of the startup of localstack, until its S3 API accepts requests.
+const LOCALSTACK_STARTUP_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Handle on a localstack instance, mocking Amazon S3 for the `s3+localstack://` URIs.
+///
+/// Localstack is reached at the endpoint of [`RegionProvider::Localstack`], i.e.
+/// `localhost:4566` if the `QUICKWIT_ENV` environment variable is set to `LOCAL`, and
+/// `localstack:4566` otherwise. If nothing listens on `localhost:4566`, a container is started
+/// with `docker`, and removed when the last handle is stopped with [`Localstack::stop`]. A
+/// container whose handles are dropped without being stopped keeps running, and is reused by
+/// the next tests.
+///
+/// The S3 clients, including the ones of the storages, read their credentials from the
+/// environment, e.g. `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`. Localstack accepts
+/// any credentials.
+pub struct Localstack {
+    container_id_opt: Option<String>,
+}
+
+static SHARED_LOCALSTACK: Lazy<Mutex<Weak<Localstack>>> = Lazy::new(|| Mutex::new(Weak::new()));
+
+fn localstack_endpoint() -> String {
+    match RegionProvider::Localstack.get_region() {
+        Region::Custom { endpoint, .. } => endpoint,
+        other_region => panic!(
+            "The localstack region should be a custom region, got `{:?}`.",
+            other_region
+        ),
+    }
+}
+
+async fn is_listening(address: &str) -> bool {
+    matches!(
+        timeout(Duration::from_secs(1), TcpStream::connect(address)).await,
+        Ok(Ok(_))
+    )
+}
+
+async fn start_container() -> anyhow::Result<String> {
+    let output = Command::new("docker")
+        .args(&[
+            "run",
+            "--detach",
+            "--rm",
+            "--publish",
+            "4566:4566",
+            "--env",
+            "SERVICES=s3",
+            LOCALSTACK_IMAGE,
+        ])
+        .output()
+        .await
+        .with_context(|| "Failed to run `docker`, is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to start the localstack container: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+async fn remove_container(container_id: &str) -> anyhow::Result<()> {
+    let output = Command::new("docker")
+        .args(&["rm", "--force", container_id])
+        .output()
+        .await
+        .with_context(|| "Failed to run `docker`, is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to remove the localstack container `{}`: {}",
+            container_id,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+impl Localstack {
+    /// Returns a handle on localstack, shared by the tests of the process, and waits until
+    /// the bucket [`LOCALSTACK_BUCKET`] can be used.
+    pub async fn shared() -> anyhow::Result<Arc<Localstack>> {
+        let mut shared_localstack = SHARED_LOCALSTACK.lock().await;
+        if let Some(localstack) = shared_localstack.upgrade() {
+            return Ok(localstack);
+        }
+        let endpoint = localstack_endpoint();
+        let address = endpoint
+            .trim_start_matches("http://")
+            .trim_start_matches("https://");
+        let container_id_opt = if is_listening(address).await {
+            None
+        } else if !address.starts_with("localhost:") {
+            bail!(
+                "Localstack does not listen on `{}`. Set the `QUICKWIT_ENV` environment variable \
+                 to `LOCAL` to target, or start, localstack on `localhost:4566`.",
+                address
+            );
+        } else {
+            let container_id = start_container().await?;
+            info!(container_id = %container_id, "Started localstack container");
+            Some(container_id)
+        };
+        let localstack = Arc::new(Localstack { container_id_opt });
+        localstack.create_bucket(LOCALSTACK_BUCKET).await?;
+        *shared_localstack = Arc::downgrade(&localstack);
+        Ok(localstack)
+    }
+
+    /// Creates the bucket `bucket` unless it exists, retrying until localstack accepts the
+    /// requests or its startup times out.
+    pub async fn create_bucket(&self, bucket: &str) -> anyhow::Result<()> {
+        let s3_client = S3Client::new_with(
+            HttpClient::new()?,
+            StaticProvider::new_minimal("localstack".to_string(), "localstack".to_string()),
+            RegionProvider::Localstack.get_region(),
+        );
+        let create_bucket_req = CreateBucketRequest {
+            bucket: bucket.to_string(),
+            ..Default::default()
+        };
+        let deadline = Instant::now() + LOCALSTACK_STARTUP_TIMEOUT;
+        loop {
+            match s3_client.create_bucket(create_bucket_req.clone()).await {
+                Ok(_)
+                | Err(RusotoError::Service(CreateBucketError::BucketAlreadyOwnedByYou(_)))
+                | Err(RusotoError::Service(CreateBucketError::BucketAlreadyExists(_))) => {
+                    return Ok(())
+                }
+                Err(error) if Instant::now() >= deadline => {
+                    return Err(anyhow::anyhow!(error)).with_context(|| {
+                        format!("Failed to create the bucket `{}` in localstack.", bucket)
+                    });
+                }
+                Err(_) => sleep(Duration::from_millis(500)).await,
+            }
+        }
+    }
+
+    /// Returns true if the handle started the localstack container, which is removed when the
+    /// last handle is stopped.
+    pub fn owns_container(&self) -> bool {
+        self.container_id_opt.is_some()
+    }
+
+    /// Releases the handle, removing the localstack container if the handle started it and is
+    /// the last one.
+    pub async fn stop(self: Arc<Self>) -> anyhow::Result<()> {
+        // Holding the lock prevents `shared` from handing out the handle in the meantime.
+        let mut shared_localstack = SHARED_LOCALSTACK.lock().await;
+        let localstack = match Arc::try_unwrap(self) {
+            Ok(localstack) => localstack,
+            Err(_) => return Ok(()),
+        };
+        *shared_localstack = Weak::new();
+        if let Some(container_id) = localstack.container_id_opt {
+            remove_container(&container_id).await?;
+            info!(container_id = %container_id, "Removed localstack container");
+        }
+        Ok(())
+    }
+}